@@ -24,6 +24,10 @@ run_clippy() {
 	echo_command cargo clippy -p volo -- --deny warnings
 	echo_command cargo clippy -p volo --no-default-features --features rustls-aws-lc-rs -- --deny warnings
 	echo_command cargo clippy -p volo --no-default-features --features rustls-ring -- --deny warnings
+	# `net::uring` is only built on Linux
+	echo_command cargo clippy -p volo --no-default-features --features io-uring -- --deny warnings
+	echo_command cargo clippy -p volo --no-default-features --features dns -- --deny warnings
+	echo_command cargo clippy -p volo --no-default-features --features chrono -- --deny warnings
 	if [ "${RUN_SHMIPC}" = "yes" ]; then
 		echo_command cargo clippy -p volo --no-default-features --features shmipc -- --deny warnings
 		echo_command cargo clippy -p volo --no-default-features --features tls,shmipc -- --deny warnings
@@ -31,17 +35,30 @@ run_clippy() {
 	echo_command cargo clippy -p volo-thrift --no-default-features -- --deny warnings
 	echo_command cargo clippy -p volo-thrift --no-default-features --features multiplex -- --deny warnings
 	echo_command cargo clippy -p volo-thrift --no-default-features --features unsafe-codec -- --deny warnings
+	echo_command cargo clippy -p volo-thrift --no-default-features --features ttheader-strict -- --deny warnings
+	echo_command cargo clippy -p volo-thrift --no-default-features --features payload-crypto -- --deny warnings
+	echo_command cargo clippy -p volo-thrift --no-default-features --features overload-cpu -- --deny warnings
+	echo_command cargo clippy -p volo-thrift --no-default-features --features datagram -- --deny warnings
+	echo_command cargo clippy -p volo-thrift --no-default-features --features datagram-quic -- --deny warnings
 	echo_command cargo clippy -p volo-grpc --no-default-features -- --deny warnings
 	echo_command cargo clippy -p volo-grpc --no-default-features --features rustls -- --deny warnings
 	echo_command cargo clippy -p volo-grpc --no-default-features --features native-tls -- --deny warnings
 	echo_command cargo clippy -p volo-grpc --no-default-features --features native-tls-vendored -- --deny warnings
 	echo_command cargo clippy -p volo-grpc --no-default-features --features grpc-web -- --deny warnings
+	echo_command cargo clippy -p volo-grpc --no-default-features --features zstd -- --deny warnings
 	echo_command cargo clippy -p volo-http -- --deny warnings
 	echo_command cargo clippy -p volo-http --no-default-features --features client,http1,json -- --deny warnings
 	echo_command cargo clippy -p volo-http --no-default-features --features client,http2,json -- --deny warnings
 	echo_command cargo clippy -p volo-http --no-default-features --features server,http1,query,form,json,multipart,ws -- --deny warnings
 	echo_command cargo clippy -p volo-http --no-default-features --features server,http2,query,form,json,multipart,ws -- --deny warnings
 	echo_command cargo clippy -p volo-http --no-default-features --features full -- --deny warnings
+	echo_command cargo clippy -p volo-http --features http3 -- --deny warnings
+	echo_command cargo clippy -p volo-http --features admin -- --deny warnings
+	echo_command cargo clippy -p volo-http --features session -- --deny warnings
+	echo_command cargo clippy -p volo-http --features gzip -- --deny warnings
+	echo_command cargo clippy -p volo-http --features br -- --deny warnings
+	echo_command cargo clippy -p volo-http --features deflate -- --deny warnings
+	echo_command cargo clippy -p volo-http --features zstd -- --deny warnings
 	echo_command cargo clippy -p volo-build -- --deny warnings
 	echo_command cargo clippy -p volo-cli -- --deny warnings
 	echo_command cargo clippy -p volo-macros -- --deny warnings
@@ -50,14 +67,38 @@ run_clippy() {
 	echo_command cargo clippy --all -- --deny warnings
 }
 
+# The client of volo-http sends requests by `fetch` on `wasm32`, where only the client without the
+# hyper transports is available.
+run_wasm_clippy() {
+	echo_command cargo clippy -p volo --no-default-features --target wasm32-unknown-unknown -- --deny warnings
+	echo_command cargo clippy -p volo-http --no-default-features --features client,query,form,json --target wasm32-unknown-unknown -- --deny warnings
+	echo_command cargo clippy -p volo-http --no-default-features --features client,json,gzip,deflate,br --target wasm32-unknown-unknown -- --deny warnings
+}
+
 run_test() {
 	echo_command cargo test -p volo-thrift
 	echo_command cargo test -p volo-thrift --features shmipc
+	echo_command cargo test -p volo-thrift --features ttheader-strict
+	echo_command cargo test -p volo-thrift --features payload-crypto
+	echo_command cargo test -p volo-thrift --features overload-cpu
+	echo_command cargo test -p volo-thrift --features datagram
+	echo_command cargo test -p volo-thrift --features datagram-quic
 	echo_command cargo test -p volo-grpc --features rustls
+	echo_command cargo test -p volo-grpc --features zstd
 	echo_command cargo test -p volo-http --features client,server,http1,query,form,json,tls,cookie,multipart,ws
 	echo_command cargo test -p volo-http --features client,server,http2,query,form,json,tls,cookie,multipart,ws
 	echo_command cargo test -p volo-http --features full
+	echo_command cargo test -p volo-http --features http3
+	echo_command cargo test -p volo-http --features admin
+	echo_command cargo test -p volo-http --features session
+	echo_command cargo test -p volo-http --features gzip
+	echo_command cargo test -p volo-http --features br
+	echo_command cargo test -p volo-http --features deflate
+	echo_command cargo test -p volo-http --features zstd
 	echo_command cargo test -p volo --features rustls
+	echo_command cargo test -p volo --features io-uring
+	echo_command cargo test -p volo --features dns
+	echo_command cargo test -p volo --features chrono
 	echo_command cargo test -p volo-build
 	echo_command cargo test -p volo-cli
 }
@@ -65,6 +106,7 @@ run_test() {
 main() {
 	local RUN_CLIPPY="yes"
 	local RUN_TEST="yes"
+	local RUN_WASM="no"
 
 	for arg in "$@"; do
 		case "${arg}" in
@@ -80,6 +122,12 @@ main() {
 			RUN_SHMIPC="no"
 			echo "info: shmipc will be ignored"
 			;;
+		--wasm)
+			RUN_WASM="yes"
+			RUN_CLIPPY="no"
+			RUN_TEST="no"
+			echo "info: only clippy checks on wasm32 will be run"
+			;;
 		esac
 	done
 
@@ -89,6 +137,9 @@ main() {
 	if [ "${RUN_TEST}" = "yes" ]; then
 		run_test
 	fi
+	if [ "${RUN_WASM}" = "yes" ]; then
+		run_wasm_clippy
+	fi
 }
 
 main "$@"
@@ -0,0 +1,93 @@
+//! The cache of generated files, which skips the code generation of an entry if none of its inputs
+//! is changed since the last generation.
+//!
+//! pilota parses and generates all the IDL files of an entry in one pass, so the generated file of
+//! the entry is cached as a whole, keyed by the fingerprint of:
+//!
+//! - the version of volo-build and the running executable, i.e., the build script, which covers the
+//!   plugins and any other code of the build script;
+//! - the entry in the config file;
+//! - the paths and contents of the IDL files and their transitive includes.
+
+use std::{
+    fs,
+    hash::Hasher,
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use mur3::Hasher128;
+
+use crate::model::Entry;
+
+pub(crate) struct Fingerprint(Hasher128);
+
+impl Fingerprint {
+    /// Returns `None` if the running executable is unknown, in which case nothing can be cached.
+    pub(crate) fn new(entry: &Entry, idl_files: &[PathBuf]) -> Option<Self> {
+        let exe = std::env::current_exe().ok()?;
+        let metadata = fs::metadata(&exe).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        let mut fingerprint = Self(Hasher128::with_seed(0));
+        fingerprint.write(env!("CARGO_PKG_VERSION").as_bytes());
+        fingerprint.write(exe.as_os_str().as_encoded_bytes());
+        fingerprint.0.write_u64(metadata.len());
+        fingerprint.0.write_u128(modified.as_nanos());
+
+        // the repos are sorted since the order of a map is not stable
+        let mut repos = entry.repos.iter().collect::<Vec<_>>();
+        repos.sort_by_key(|(name, _)| *name);
+        let entry = serde_yaml::to_string(&(
+            &entry.filename,
+            entry.protocol,
+            repos,
+            &entry.services,
+            &entry.common_option,
+        ))
+        .ok()?;
+        fingerprint.write(entry.as_bytes());
+
+        for file in idl_files {
+            fingerprint.write(file.as_os_str().as_encoded_bytes());
+            fingerprint.write(&fs::read(file).ok()?);
+        }
+        Some(fingerprint)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // prefixed by the length, so the concatenated inputs are not ambiguous
+        self.0.write_usize(bytes.len());
+        self.0.write(bytes);
+    }
+
+    fn hex(&self) -> String {
+        let (h1, h2) = self.0.finish128();
+        format!("{h1:016x}{h2:016x}")
+    }
+
+    /// Whether the generated file exists and is generated from the same inputs.
+    pub(crate) fn is_fresh(&self, out_file: &Path) -> bool {
+        out_file.exists()
+            && fs::read_to_string(stamp_path(out_file)).is_ok_and(|stamp| stamp == self.hex())
+    }
+
+    /// Remove the stamp before generating, so a broken generation is never taken as fresh.
+    pub(crate) fn invalidate(out_file: &Path) -> io::Result<()> {
+        match fs::remove_file(stamp_path(out_file)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn save(&self, out_file: &Path) -> io::Result<()> {
+        fs::write(stamp_path(out_file), self.hex())
+    }
+}
+
+fn stamp_path(out_file: &Path) -> PathBuf {
+    let mut path = out_file.as_os_str().to_owned();
+    path.push(".fingerprint");
+    PathBuf::from(path)
+}
@@ -1,15 +1,17 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Ok;
+use anyhow::{Context, Ok};
 use pilota_build::BoxClonePlugin;
 use volo::FastStr;
 
 use crate::{
+    cache::Fingerprint,
+    hooks::PluginRegistry,
     model::{self, Entry},
     util::{
-        DEFAULT_CONFIG_FILE, DEFAULT_DIR, ServiceBuilder, collect_no_service_paths,
-        download_repos_to_target, get_service_builders_from_services, open_config_file,
-        read_config_from_file,
+        DEFAULT_CONFIG_FILE, DEFAULT_DIR, ServiceBuilder, collect_idl_files,
+        collect_no_service_paths, download_repos_to_target, fetch_registry_idls,
+        get_service_builders_from_services, open_config_file, read_config_from_file,
     },
 };
 
@@ -17,6 +19,8 @@ pub struct ConfigBuilder {
     filename: PathBuf,
     plugins: Vec<BoxClonePlugin>,
     out_dir: Option<PathBuf>,
+    incremental: bool,
+    registry: PluginRegistry,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -203,6 +207,16 @@ impl InnerBuilder {
             InnerBuilder::Thrift(inner) => InnerBuilder::Thrift(inner.with_comments(with_comments)),
         }
     }
+
+    /// Only for protobuf, thrift entries are not changed.
+    pub fn json_transcoding(self, json_transcoding: bool) -> Self {
+        match self {
+            InnerBuilder::Protobuf(inner) => {
+                InnerBuilder::Protobuf(inner.json_transcoding(json_transcoding))
+            }
+            thrift @ InnerBuilder::Thrift(_) => thrift,
+        }
+    }
 }
 
 impl ConfigBuilder {
@@ -211,6 +225,8 @@ impl ConfigBuilder {
             filename,
             plugins: Vec::new(),
             out_dir: None,
+            incremental: true,
+            registry: PluginRegistry::default(),
         }
     }
 
@@ -220,6 +236,17 @@ impl ConfigBuilder {
         self
     }
 
+    /// Register the plugin by name, which is enabled by the `plugins` of entries in the config
+    /// file and made by its `config` there, see [`crate::hooks`].
+    pub fn register_plugin<F, P>(mut self, name: impl Into<FastStr>, make: F) -> Self
+    where
+        F: Fn(&serde_yaml::Value) -> anyhow::Result<P> + 'static,
+        P: pilota_build::Plugin + 'static,
+    {
+        self.registry.register(name, make);
+        self
+    }
+
     /// Overrides the output directory used by the underlying code generator.
     /// This also relocates downloaded IDL repos from `${OUT_DIR}/idl` to `<out_dir>/idl`.
     pub fn out_dir<P: AsRef<Path>>(mut self, out_dir: P) -> Self {
@@ -227,6 +254,13 @@ impl ConfigBuilder {
         self
     }
 
+    /// Skip the generation of entries whose IDL files, including the transitive includes, config
+    /// and build script are not changed since the last generation, enabled by default.
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
     fn get_out_dir(&self) -> anyhow::Result<PathBuf> {
         if let Some(out_dir) = &self.out_dir {
             return Ok(out_dir.clone());
@@ -260,10 +294,18 @@ impl ConfigBuilder {
                 for p in self.plugins.iter() {
                     builder = builder.plugin(p.clone());
                 }
+                for p in self
+                    .registry
+                    .make(&entry.common_option.plugins)
+                    .with_context(|| format!("plugins of entry {entry_name}"))?
+                {
+                    builder = builder.plugin(p);
+                }
 
                 // download repos and get the repo paths
                 let target_dir = idl_dir.join(&entry_name);
                 let repo_dir_map = download_repos_to_target(&entry.repos, target_dir)?;
+                fetch_registry_idls(&entry.services, &entry.repos, &repo_dir_map)?;
 
                 // collect per-IDL no_service flags from `codegen_option.config.no_service`
                 let no_service_paths = collect_no_service_paths(&entry.services, &repo_dir_map);
@@ -272,6 +314,23 @@ impl ConfigBuilder {
                 let service_builders =
                     get_service_builders_from_services(&entry.services, &repo_dir_map);
 
+                // rerun only if the idl files are changed, and skip the generation if nothing is
+                // changed since the last one
+                let idl_files = collect_idl_files(&service_builders)?;
+                for file in idl_files.iter() {
+                    println!("cargo:rerun-if-changed={}", file.display());
+                }
+                let out_file = out_dir.join(&entry.filename);
+                let fingerprint = if self.incremental {
+                    Fingerprint::new(&entry, &idl_files)
+                } else {
+                    None
+                };
+                if fingerprint.as_ref().is_some_and(|f| f.is_fresh(&out_file)) {
+                    return Ok(());
+                }
+                Fingerprint::invalidate(&out_file)?;
+
                 // add build options to the builder and build
                 let mut builder = builder.add_services(service_builders);
                 if !no_service_paths.is_empty() {
@@ -285,8 +344,12 @@ impl ConfigBuilder {
                     .with_descriptor(entry.common_option.with_descriptor)
                     .with_field_mask(entry.common_option.with_field_mask)
                     .with_comments(entry.common_option.with_comments)
+                    .json_transcoding(entry.common_option.json_transcoding)
                     .write()?;
 
+                if let Some(fingerprint) = fingerprint {
+                    fingerprint.save(&out_file)?;
+                }
                 Ok(())
             })?;
         Ok(())
@@ -352,6 +415,141 @@ mod tests {
         assert!(!generated.contains("IgnoredRecord"));
     }
 
+    #[test]
+    fn write_skips_unchanged_entries() {
+        let dir = tempdir().unwrap();
+        let idl = dir.path().join("service.thrift");
+        let included = dir.path().join("common.thrift");
+        let config_path = dir.path().join("volo.yml");
+        let out_dir = dir.path().join("out");
+        let out_file = out_dir.join("generated.rs");
+
+        write_thrift(&included, "incremental_common", "CommonRecord");
+        fs::write(
+            &idl,
+            "include \"common.thrift\"\n\nnamespace rs incremental\n\nstruct Record {\n    1: \
+             required common.CommonRecord common,\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &config_path,
+            format!(
+                "entries:\n  sample:\n    filename: generated.rs\n    protocol: thrift\n    \
+                 services:\n      - idl:\n          source: local\n          path: {}\n    \
+                 touch_all: true\n",
+                idl.display()
+            ),
+        )
+        .unwrap();
+        let write = || {
+            ConfigBuilder::new(config_path.clone())
+                .out_dir(&out_dir)
+                .write()
+                .unwrap()
+        };
+
+        write();
+        assert!(
+            fs::read_to_string(&out_file)
+                .unwrap()
+                .contains("CommonRecord")
+        );
+
+        // nothing is changed, so the generated file is kept as is
+        fs::write(&out_file, "// kept").unwrap();
+        write();
+        assert_eq!(fs::read_to_string(&out_file).unwrap(), "// kept");
+
+        // the included file is changed
+        let mut common = fs::read_to_string(&included).unwrap();
+        common.push_str("\nstruct ChangedRecord {}\n");
+        fs::write(&included, common).unwrap();
+        write();
+        assert!(
+            fs::read_to_string(&out_file)
+                .unwrap()
+                .contains("ChangedRecord")
+        );
+    }
+
+    #[derive(Clone)]
+    struct MarkerPlugin(String);
+
+    impl pilota_build::Plugin for MarkerPlugin {
+        fn on_item(
+            &mut self,
+            cx: &pilota_build::Context,
+            def_id: pilota_build::DefId,
+            item: std::sync::Arc<pilota_build::rir::Item>,
+        ) {
+            if let pilota_build::rir::Item::Message(_) = &*item {
+                let name = cx.rust_name(def_id);
+                let marker = &self.0;
+                cx.with_adjust_mut(def_id, |adj| {
+                    adj.add_nested_item(
+                        format!("impl {name} {{ pub const MARKER: &str = \"{marker}\"; }}").into(),
+                    )
+                });
+            }
+            pilota_build::plugin::walk_item(self, cx, def_id, item)
+        }
+    }
+
+    #[test]
+    fn write_runs_plugins_of_entries() {
+        let dir = tempdir().unwrap();
+        let idl = dir.path().join("plugins.thrift");
+        let config_path = dir.path().join("volo.yml");
+        let out_dir = dir.path().join("out");
+
+        fs::write(
+            &idl,
+            "namespace rs plugins_demo\n\nstruct User {\n    1: required string \
+             email,\n}\n\nstruct Other {\n    1: required string email,\n}\n",
+        )
+        .unwrap();
+        let config = |plugins: &str| {
+            fs::write(
+                &config_path,
+                format!(
+                    "entries:\n  sample:\n    filename: generated.rs\n    protocol: thrift\n    \
+                     services:\n      - idl:\n          source: local\n          path: {}\n    \
+                     touch_all: true\n    plugins:\n{plugins}",
+                    idl.display()
+                ),
+            )
+            .unwrap();
+        };
+        let builder = || {
+            ConfigBuilder::new(config_path.clone())
+                .out_dir(&out_dir)
+                .register_plugin("marker", |config| {
+                    Ok(MarkerPlugin(
+                        config["marker"].as_str().unwrap_or_default().to_owned(),
+                    ))
+                })
+        };
+
+        config(
+            "      - name: attrs\n        config:\n          - types: [\"plugins_demo.User\"]\n            attrs: [\"#[derive(sqlx::FromRow)]\"]\n            impls: [\"impl {name} { pub fn is_user() -> bool { true } }\"]\n            fields:\n              email: [\"#[doc = \\\"the email\\\"]\"]\n      - name: marker\n        config:\n          marker: marked\n",
+        );
+        builder().write().unwrap();
+        let generated = fs::read_to_string(out_dir.join("generated.rs")).unwrap();
+        assert_eq!(generated.matches("sqlx::FromRow").count(), 1);
+        assert!(generated.contains("pub fn is_user() -> bool"));
+        assert_eq!(generated.matches("the email").count(), 1);
+        assert_eq!(
+            generated
+                .matches("pub const MARKER: &str = \"marked\"")
+                .count(),
+            2
+        );
+
+        config("      - name: unknown\n");
+        let err = builder().write().unwrap_err();
+        assert!(format!("{err:?}").contains("unknown plugin 'unknown'"));
+    }
+
     #[test]
     fn get_out_dir_prefers_explicit_out_dir() {
         let explicit = tempfile::tempdir()
@@ -387,6 +585,7 @@ impl InitBuilder {
         // download repos and get the repo paths
         let temp_target_dir = tempfile::TempDir::new()?;
         let repo_dir_map = download_repos_to_target(&self.entry.repos, temp_target_dir.as_ref())?;
+        fetch_registry_idls(&self.entry.services, &self.entry.repos, &repo_dir_map)?;
 
         // collect per-IDL no_service flags from `codegen_option.config.no_service`
         let no_service_paths = collect_no_service_paths(&self.entry.services, &repo_dir_map);
@@ -15,7 +15,11 @@ use volo::FastStr;
 
 use crate::util::{get_base_dir, write_file, write_item};
 
-pub struct MkGrpcBackend;
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MkGrpcBackend {
+    /// Generate `{service}_http_router` of services with `google.api.http` annotations.
+    pub json_transcoding: bool,
+}
 
 impl pilota_build::MakeBackend for MkGrpcBackend {
     type Target = VoloGrpcBackend;
@@ -23,6 +27,7 @@ impl pilota_build::MakeBackend for MkGrpcBackend {
     fn make_backend(self, context: Context) -> Self::Target {
         VoloGrpcBackend {
             inner: pilota_build::codegen::pb::ProtobufBackend::new(context),
+            json_transcoding: self.json_transcoding,
         }
     }
 }
@@ -30,6 +35,7 @@ impl pilota_build::MakeBackend for MkGrpcBackend {
 #[derive(Clone)]
 pub struct VoloGrpcBackend {
     inner: pilota_build::codegen::pb::ProtobufBackend,
+    json_transcoding: bool,
 }
 
 impl VoloGrpcBackend {
@@ -172,8 +178,8 @@ impl VoloGrpcBackend {
         streaming: bool,
     ) -> FastStr {
         let resp_stream = format!(
-            r#"let (mut metadata, extensions, message_stream) = resp.into_parts();
-            let mut message_stream = match message_stream {{
+            r#"let (metadata, extensions, message_stream) = resp.into_parts();
+            let message_stream = match message_stream {{
                 {resp_enum_name}::{variant_name}(stream) => stream,
                 #[allow(unreachable_patterns)]
                 _ => return ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
@@ -188,17 +194,7 @@ impl VoloGrpcBackend {
         } else {
             format! {
                 r#"{resp_stream}
-                let message = ::volo_grpc::codegen::StreamExt::try_next(&mut message_stream)
-                    .await
-                    .map_err(|mut status| {{
-                        status.metadata_mut().merge(metadata.clone());
-                        status
-                    }})?
-                    .ok_or_else(|| ::volo_grpc::Status::new(::volo_grpc::Code::Internal, "Missing response message."))?;
-                if let Some(trailers) = message_stream.trailers().await? {{
-                    metadata.merge(trailers);
-                }}
-                ::std::result::Result::Ok(::volo_grpc::Response::from_parts(metadata, extensions, message))"#
+                ::volo_grpc::codegen::unary_response(metadata, extensions, message_stream).await"#
             }
         }.into()
     }
@@ -264,6 +260,200 @@ impl VoloGrpcBackend {
     fn modify_ty_to_arc(&self, ty: pilota_build::ty::CodegenTy) -> pilota_build::ty::CodegenTy {
         pilota_build::middle::ty::CodegenTy::Arc(Arc::new(ty))
     }
+
+    /// Conversions between the well-known types and native types, see `volo_grpc::wkt`.
+    fn codegen_wkt_impl(&self, name: &str, stream: &mut String) {
+        let code = match name {
+            "Timestamp" => {
+                r#"
+                impl ::std::convert::From<::std::time::SystemTime> for Timestamp {
+                    fn from(time: ::std::time::SystemTime) -> Self {
+                        let (seconds, nanos) = ::volo_grpc::wkt::timestamp_from_system_time(time);
+                        Self { seconds, nanos, ..::std::default::Default::default() }
+                    }
+                }
+
+                impl ::std::convert::TryFrom<Timestamp> for ::std::time::SystemTime {
+                    type Error = ::volo_grpc::wkt::WktError;
+
+                    fn try_from(timestamp: Timestamp) -> ::std::result::Result<Self, Self::Error> {
+                        ::volo_grpc::wkt::system_time_from_timestamp(timestamp.seconds, timestamp.nanos)
+                    }
+                }"#
+            }
+            "Duration" => {
+                r#"
+                impl ::std::convert::TryFrom<::std::time::Duration> for Duration {
+                    type Error = ::volo_grpc::wkt::WktError;
+
+                    fn try_from(duration: ::std::time::Duration) -> ::std::result::Result<Self, Self::Error> {
+                        let (seconds, nanos) = ::volo_grpc::wkt::duration_from_std(duration)?;
+                        ::std::result::Result::Ok(Self { seconds, nanos, ..::std::default::Default::default() })
+                    }
+                }
+
+                impl ::std::convert::TryFrom<Duration> for ::std::time::Duration {
+                    type Error = ::volo_grpc::wkt::WktError;
+
+                    fn try_from(duration: Duration) -> ::std::result::Result<Self, Self::Error> {
+                        ::volo_grpc::wkt::std_from_duration(duration.seconds, duration.nanos)
+                    }
+                }"#
+            }
+            "Struct" => {
+                r#"
+                impl ::std::convert::From<::volo_grpc::wkt::Map> for Struct {
+                    fn from(map: ::volo_grpc::wkt::Map) -> Self {
+                        Self {
+                            fields: map.into_iter().map(|(k, v)| (k, v.into())).collect(),
+                            ..::std::default::Default::default()
+                        }
+                    }
+                }
+
+                impl ::std::convert::From<Struct> for ::volo_grpc::wkt::Map {
+                    fn from(s: Struct) -> Self {
+                        s.fields.into_iter().map(|(k, v)| (k, v.into())).collect()
+                    }
+                }"#
+            }
+            "ListValue" => {
+                r#"
+                impl ::std::convert::From<::std::vec::Vec<::volo_grpc::wkt::Value>> for ListValue {
+                    fn from(values: ::std::vec::Vec<::volo_grpc::wkt::Value>) -> Self {
+                        Self {
+                            values: values.into_iter().map(::std::convert::Into::into).collect(),
+                            ..::std::default::Default::default()
+                        }
+                    }
+                }
+
+                impl ::std::convert::From<ListValue> for ::std::vec::Vec<::volo_grpc::wkt::Value> {
+                    fn from(list: ListValue) -> Self {
+                        list.values.into_iter().map(::std::convert::Into::into).collect()
+                    }
+                }"#
+            }
+            "Value" => {
+                r#"
+                impl ::std::convert::From<::volo_grpc::wkt::Value> for Value {
+                    fn from(value: ::volo_grpc::wkt::Value) -> Self {
+                        let kind = match value {
+                            ::volo_grpc::wkt::Value::Null => value::Kind::NullValue(NullValue::NULL_VALUE),
+                            ::volo_grpc::wkt::Value::Bool(b) => value::Kind::BoolValue(b),
+                            ::volo_grpc::wkt::Value::Number(n) => value::Kind::NumberValue(n),
+                            ::volo_grpc::wkt::Value::String(s) => value::Kind::StringValue(s),
+                            ::volo_grpc::wkt::Value::List(l) => value::Kind::ListValue(l.into()),
+                            ::volo_grpc::wkt::Value::Struct(m) => value::Kind::StructValue(m.into()),
+                        };
+                        Self { kind: ::std::option::Option::Some(kind), ..::std::default::Default::default() }
+                    }
+                }
+
+                impl ::std::convert::From<Value> for ::volo_grpc::wkt::Value {
+                    fn from(value: Value) -> Self {
+                        match value.kind {
+                            ::std::option::Option::None | ::std::option::Option::Some(value::Kind::NullValue(_)) => Self::Null,
+                            ::std::option::Option::Some(value::Kind::BoolValue(b)) => Self::Bool(b),
+                            ::std::option::Option::Some(value::Kind::NumberValue(n)) => Self::Number(n),
+                            ::std::option::Option::Some(value::Kind::StringValue(s)) => Self::String(s),
+                            ::std::option::Option::Some(value::Kind::ListValue(l)) => Self::List(l.into()),
+                            ::std::option::Option::Some(value::Kind::StructValue(m)) => Self::Struct(m.into()),
+                        }
+                    }
+                }"#
+            }
+            "Any" => {
+                r#"
+                impl Any {
+                    /// Pack the message with its type URL.
+                    pub fn pack<M: ::volo_grpc::wkt::MessageName + ::pilota::pb::Message>(message: &M) -> Self {
+                        let (type_url, value) = ::volo_grpc::wkt::pack(message);
+                        Self { type_url, value, ..::std::default::Default::default() }
+                    }
+
+                    /// Unpack the message, `None` if the packed message is not `M`.
+                    pub fn unpack<M: ::volo_grpc::wkt::MessageName + ::pilota::pb::Message + ::std::default::Default>(
+                        &self,
+                    ) -> ::std::result::Result<::std::option::Option<M>, ::pilota::pb::DecodeError> {
+                        ::volo_grpc::wkt::unpack(&self.type_url, self.value.clone())
+                    }
+
+                    /// Whether the packed message is `M`.
+                    pub fn is<M: ::volo_grpc::wkt::MessageName>(&self) -> bool {
+                        ::volo_grpc::wkt::type_name_of_url(&self.type_url) == M::FULL_NAME
+                    }
+                }"#
+            }
+            _ => return,
+        };
+        stream.push_str(code);
+    }
+
+    /// Generate `{service}_http_router` for the unary methods with `google.api.http` annotations.
+    fn codegen_http_router(&self, def_id: DefId, s: &rir::Service) -> Option<String> {
+        let file_id = self.cx().node(def_id).unwrap().file_id;
+        let file = self.cx().file(file_id).unwrap();
+        let rules = crate::transcoding::http_rules(&file.descriptor, &s.name);
+
+        let service_name = self.cx().rust_name(def_id);
+        let mut handlers = Vec::new();
+        for method in s.methods.iter() {
+            let Some((_, method_rules)) = rules
+                .iter()
+                .find(|(name, _)| *name == method.name.to_string())
+            else {
+                continue;
+            };
+            let streaming = self
+                .cx()
+                .node_contains_tag::<ClientStreaming>(method.def_id)
+                || self
+                    .cx()
+                    .node_contains_tag::<ServerStreaming>(method.def_id);
+            if streaming
+                || self.is_wrapper_arc(method.args[0].ty.tags_id)
+                || self.is_wrapper_arc(method.ret.tags_id)
+            {
+                println!(
+                    "cargo:warning=json transcoding of `{}.{}` is not supported, skipped",
+                    s.name, method.name
+                );
+                continue;
+            }
+            for rule in method_rules {
+                match crate::transcoding::parse_template(&rule.path) {
+                    Ok(route) => handlers.push(crate::transcoding::Handler {
+                        rule: rule.clone(),
+                        route,
+                        method_name: self.cx().rust_name(method.def_id).to_string(),
+                        service_method: method.name.to_string(),
+                    }),
+                    Err(err) => println!(
+                        "cargo:warning=invalid http rule `{}` of `{}.{}`: {err}, skipped",
+                        rule.path, s.name, method.name
+                    ),
+                }
+            }
+        }
+        if handlers.is_empty() {
+            return None;
+        }
+
+        let file_name = self.cx().file_name(file_id).unwrap();
+        let file_descriptor = format!(
+            "file_descriptor_{}",
+            file_name.replace('.', "_").to_lowercase()
+        );
+        let router_name = format!("{}_http_router", service_name.0.snake_ident());
+        Some(crate::transcoding::codegen_router(
+            &service_name,
+            &router_name,
+            &file_descriptor,
+            &s.name,
+            &handlers,
+        ))
+    }
 }
 
 impl CodegenBackend for VoloGrpcBackend {
@@ -590,6 +780,10 @@ impl CodegenBackend for VoloGrpcBackend {
                     {oneshot_client_name}(self.0.with_opt(opt))
                 }}
 
+                pub async fn warmup(&self, warmup: ::volo::loadbalance::warmup::Warmup) -> ::std::result::Result<::volo::loadbalance::warmup::WarmupReport, ::volo_grpc::Status> {{
+                    self.0.warmup(warmup).await
+                }}
+
                 {client_methods}
             }}
 
@@ -648,6 +842,12 @@ impl CodegenBackend for VoloGrpcBackend {
             }}"#
         };
 
+        let http_router = if self.json_transcoding {
+            self.codegen_http_router(def_id, s)
+        } else {
+            None
+        };
+
         if self.cx().config.split {
             let mut mod_rs_stream = String::new();
             write_item(
@@ -687,6 +887,14 @@ impl CodegenBackend for VoloGrpcBackend {
                 format!("server_{server_name}.rs"),
                 server_impl,
             );
+            if let Some(http_router) = http_router {
+                write_item(
+                    &mut mod_rs_stream,
+                    base_dir,
+                    format!("http_router_{service_name}.rs"),
+                    http_router,
+                );
+            }
 
             let mod_rs_file_path = base_dir.join("mod.rs");
             write_file(&mod_rs_file_path, mod_rs_stream);
@@ -708,6 +916,9 @@ impl CodegenBackend for VoloGrpcBackend {
             {client_impl}
             {server_impl}
             "#});
+            if let Some(http_router) = http_router {
+                stream.push_str(&http_router);
+            }
         }
     }
 
@@ -792,7 +1003,19 @@ impl CodegenBackend for VoloGrpcBackend {
     }
 
     fn codegen_struct_impl(&self, def_id: DefId, stream: &mut String, s: &rir::Message) {
-        self.inner.codegen_struct_impl(def_id, stream, s)
+        self.inner.codegen_struct_impl(def_id, stream, s);
+
+        let name = self.cx().rust_name(def_id);
+        let (package, full_name) = crate::hooks::full_name(self.cx(), def_id);
+        stream.push_str(&format!(
+            r#"
+            impl ::volo_grpc::wkt::MessageName for {name} {{
+                const FULL_NAME: &'static str = "{full_name}";
+            }}"#
+        ));
+        if package == "google.protobuf" && full_name.matches('.').count() == 2 {
+            self.codegen_wkt_impl(&s.name, stream);
+        }
     }
 
     fn cx(&self) -> &Context {
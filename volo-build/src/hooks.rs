@@ -0,0 +1,221 @@
+//! Codegen hooks configured in the config files.
+//!
+//! Plugins are the passes over the generated items, e.g., adding derives to types. Besides the
+//! plugins added to builders for all entries, the named plugins can be registered by
+//! [`PluginRegistry::register`] (or `register_plugin` of builders) and enabled per entry with
+//! their own config:
+//!
+//! ```yaml
+//! entries:
+//!   default:
+//!     plugins:
+//!       - name: attrs
+//!         config:
+//!           - types: ["user.User", "order.*"]
+//!             attrs: ["#[derive(sqlx::FromRow)]"]
+//!             fields:
+//!               email: ["#[sqlx(rename = \"mail\")]"]
+//!       - name: my_plugin
+//!         config: { ... }
+//! ```
+//!
+//! The [`AttrsPlugin`] is registered as `attrs` by default.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{Context as _, bail};
+use itertools::Itertools;
+use pilota_build::{
+    Context, DefId, Plugin,
+    db::RirDatabase,
+    plugin::walk_item,
+    rir::{self, Item},
+};
+use serde::{Deserialize, Serialize};
+use volo::FastStr;
+
+use crate::model::PluginOption;
+
+type MakePlugin = Box<dyn Fn(&serde_yaml::Value) -> anyhow::Result<Box<dyn Plugin>>>;
+
+/// The plugins that can be enabled by name in the config files.
+pub struct PluginRegistry {
+    plugins: HashMap<FastStr, MakePlugin>,
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            plugins: HashMap::new(),
+        };
+        registry.register("attrs", |config| {
+            Ok(AttrsPlugin::new(serde_yaml::from_value(config.clone())?))
+        });
+        registry
+    }
+}
+
+impl PluginRegistry {
+    /// Register the plugin as `name`, which is made by the `config` of the plugin in the config
+    /// file, the plugin with the same name is replaced.
+    pub fn register<F, P>(&mut self, name: impl Into<FastStr>, make: F)
+    where
+        F: Fn(&serde_yaml::Value) -> anyhow::Result<P> + 'static,
+        P: Plugin + 'static,
+    {
+        self.plugins.insert(
+            name.into(),
+            Box::new(move |config| Ok(Box::new(make(config)?))),
+        );
+    }
+
+    /// Make the plugins enabled by the options in order.
+    pub fn make(&self, options: &[PluginOption]) -> anyhow::Result<Vec<Box<dyn Plugin>>> {
+        options
+            .iter()
+            .map(|option| {
+                let Some(make) = self.plugins.get(&option.name) else {
+                    bail!(
+                        "unknown plugin '{}', the registered plugins are: {}",
+                        option.name,
+                        self.plugins.keys().sorted().join(", ")
+                    );
+                };
+                make(&option.config).with_context(|| format!("config of plugin '{}'", option.name))
+            })
+            .collect()
+    }
+}
+
+/// The attributes and impls added to the types matched.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AttrsRule {
+    /// The full names of types in IDL, i.e., `{package or namespace}.{name}`, where `*` matches
+    /// any characters.
+    pub types: Vec<FastStr>,
+    /// The attributes of the types, e.g., `#[derive(sqlx::FromRow)]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attrs: Vec<FastStr>,
+    /// The items after the types, where `{name}` is replaced by the name of the type, e.g.,
+    /// `impl crate::Validate for {name} {}`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub impls: Vec<FastStr>,
+    /// The attributes of fields or enum variants, by their names in IDL.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub fields: HashMap<FastStr, Vec<FastStr>>,
+}
+
+/// Add attributes and impls to the structs, enums and newtypes by [`AttrsRule`]s.
+#[derive(Clone, Debug)]
+pub struct AttrsPlugin {
+    rules: Arc<[AttrsRule]>,
+}
+
+impl AttrsPlugin {
+    pub fn new(rules: Vec<AttrsRule>) -> Self {
+        Self {
+            rules: rules.into(),
+        }
+    }
+}
+
+impl Plugin for AttrsPlugin {
+    fn on_item(&mut self, cx: &Context, def_id: DefId, item: Arc<Item>) {
+        let children = match &*item {
+            Item::Message(s) => s.fields.iter().map(|f| (f.did, &f.name)).collect_vec(),
+            Item::Enum(e) => e.variants.iter().map(|v| (v.did, &v.name)).collect_vec(),
+            Item::NewType(_) => Vec::new(),
+            _ => return walk_item(self, cx, def_id, item),
+        };
+
+        let (_, full_name) = full_name(cx, def_id);
+        for rule in self.rules.iter() {
+            if !rule.types.iter().any(|p| glob_match(p, &full_name)) {
+                continue;
+            }
+            let name = cx.rust_name(def_id);
+            cx.with_adjust_mut(def_id, |adj| {
+                adj.add_attrs(&rule.attrs);
+                for r#impl in rule.impls.iter() {
+                    adj.add_nested_item(r#impl.replace("{name}", &name).into());
+                }
+            });
+            for (did, child) in children.iter() {
+                if let Some(attrs) = rule.fields.get::<str>(child) {
+                    cx.with_adjust_mut(*did, |adj| adj.add_attrs(attrs));
+                }
+            }
+        }
+        walk_item(self, cx, def_id, item)
+    }
+}
+
+/// Returns the package and the full name of the item in IDL, e.g., `demo.Item.Sub`.
+pub(crate) fn full_name(cx: &Context, def_id: DefId) -> (String, String) {
+    let node = cx.node(def_id).unwrap();
+    let package = cx.file(node.file_id).unwrap().package.iter().join(".");
+
+    let mut names = vec![cx.expect_item(def_id).symbol_name().to_string()];
+    let mut parent = node.parent;
+    while let Some(parent_id) = parent {
+        let parent_node = cx.node(parent_id).unwrap();
+        // nested messages are lowered into a mod named after the outer message
+        if let rir::NodeKind::Item(item) = &parent_node.kind {
+            match &**item {
+                Item::Message(m) => names.push(m.name.to_string()),
+                Item::Mod(m) => names.push(m.name.to_string()),
+                _ => {}
+            }
+        }
+        parent = parent_node.parent;
+    }
+    names.reverse();
+
+    let name = names.join(".");
+    let full_name = if package.is_empty() {
+        name
+    } else {
+        format!("{package}.{name}")
+    };
+    (package, full_name)
+}
+
+/// Whether the name is matched by the pattern, where `*` matches any characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect_vec();
+    let Some(last) = parts.pop() else {
+        // no `*` in the pattern
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("demo.User", "demo.User"));
+        assert!(!glob_match("demo.User", "demo.UserInfo"));
+        assert!(glob_match("demo.*", "demo.User"));
+        assert!(glob_match("*", "demo.User"));
+        assert!(glob_match("*.User", "demo.User"));
+        assert!(!glob_match("*.User", "demo.UserInfo"));
+        assert!(glob_match("demo.*Info", "demo.UserInfo"));
+        assert!(glob_match("*o.*r*", "demo.User"));
+        assert!(!glob_match("demo.*.Sub", "demo.User"));
+        assert!(!glob_match("a*a", "a"));
+    }
+}
@@ -12,11 +12,15 @@ use anyhow::anyhow;
 use itertools::Itertools;
 use pilota_build::{IdlService, parser::Parser};
 
+mod cache;
 pub mod config_builder;
 pub mod grpc_backend;
+pub mod hooks;
 pub mod legacy;
 pub mod model;
+pub mod openapi;
 pub mod thrift_backend;
+mod transcoding;
 pub mod util;
 pub mod workspace;
 
@@ -49,13 +53,29 @@ impl Builder<thrift_backend::MkThriftBackend, parser::ThriftParser> {
 impl Builder<grpc_backend::MkGrpcBackend, parser::ProtobufParser> {
     pub fn protobuf() -> Self {
         Builder {
-            pilota_builder: pilota_build::Builder::pb().with_backend(grpc_backend::MkGrpcBackend),
+            pilota_builder: pilota_build::Builder::pb()
+                .with_backend(grpc_backend::MkGrpcBackend::default()),
             out_dir: Default::default(),
             filename: "volo_gen.rs".into(),
             idls: Default::default(),
             config_file_path: "volo.yml".into(),
         }
     }
+
+    /// Generate `{service}_http_router` of services with `google.api.http` annotations, which
+    /// mounts the RESTful routes on a `volo-http` router and transcodes them to the gRPC calls,
+    /// see `volo_grpc::transcoding`.
+    ///
+    /// The descriptors are also generated as they are required by the routers.
+    pub fn json_transcoding(mut self, json_transcoding: bool) -> Self {
+        self.pilota_builder = self
+            .pilota_builder
+            .with_backend(grpc_backend::MkGrpcBackend { json_transcoding });
+        if json_transcoding {
+            self.pilota_builder = self.pilota_builder.with_descriptor(true);
+        }
+        self
+    }
 }
 
 impl<MkB, Parser> Builder<MkB, Parser> {
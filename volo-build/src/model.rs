@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
 
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
@@ -30,6 +33,19 @@ pub struct CommonOption {
     pub with_field_mask: bool,
     #[serde(default, skip_serializing_if = "is_false")]
     pub with_comments: bool,
+    /// Generate JSON transcoding routers of protobuf services, not supported by workspaces.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub json_transcoding: bool,
+    /// The plugins registered by name to run in order, see [`crate::hooks`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginOption>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginOption {
+    pub name: FastStr,
+    #[serde(default, skip_serializing_if = "serde_yaml::Value::is_null")]
+    pub config: serde_yaml::Value,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -60,19 +76,50 @@ pub struct WorkspaceConfig {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Repo {
+    #[serde(default, skip_serializing_if = "RepoKind::is_git")]
+    pub kind: RepoKind,
     pub url: FastStr,
     pub r#ref: FastStr,
     pub lock: FastStr,
+    /// The pinned hashes of the files fetched from a registry, keyed by the paths in the
+    /// registry, e.g., `api/service.thrift: sha256:...`, which are written by `volo repo update`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hashes: BTreeMap<FastStr, FastStr>,
 }
 
 impl Repo {
     pub fn update(&mut self) -> anyhow::Result<()> {
-        let commit_id = get_repo_latest_commit_id(&self.url, &self.r#ref)?;
-        self.lock = commit_id.into();
+        match self.kind {
+            RepoKind::Git => {
+                let commit_id = get_repo_latest_commit_id(&self.url, &self.r#ref)?;
+                self.lock = commit_id.into();
+            }
+            // registries have no way to resolve a ref, the ref itself is pinned
+            RepoKind::Registry => self.lock = self.r#ref.clone(),
+        }
         Ok::<(), anyhow::Error>(())
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepoKind {
+    /// A git repository, which is fetched entirely at the `lock` commit.
+    #[default]
+    #[serde(rename = "git")]
+    Git,
+    /// A registry serving the IDL files at `{url}/{lock}/{path}`, e.g.,
+    /// `https://raw.githubusercontent.com/{owner}/{repo}`, only the IDL files of services and their
+    /// transitive includes are fetched, and each of them must match the hash pinned in `hashes`.
+    #[serde(rename = "registry")]
+    Registry,
+}
+
+impl RepoKind {
+    fn is_git(&self) -> bool {
+        *self == RepoKind::Git
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Service {
     pub idl: Idl,
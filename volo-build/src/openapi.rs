@@ -0,0 +1,1301 @@
+//! Generating `volo-http` scaffolding from OpenAPI 3 documents.
+//!
+//! The generated file contains:
+//!
+//! - types with `serde` of `components.schemas`, parameters, request bodies and responses,
+//! - the `Api` trait with a method of each operation, and `router` registering all operations of an
+//!   implementation into a `Router`,
+//! - `ApiClient`, the client stub calling the operations by a `volo_http::client::Client`.
+//!
+//! Only path and query parameters are generated, and bodies are generated as json or forms, so
+//! the `json`, `query` and `form` features of `volo-http` should be enabled. The generated types
+//! derive `serde::Serialize` and `serde::Deserialize`, and free-form schemas are generated as
+//! `serde_json::Value`.
+//!
+//! ```no_run
+//! volo_build::openapi::OpenApiGenerator::new()
+//!     .client(false)
+//!     .write("openapi.yaml", "src/api.rs")
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt::{self, Write as _},
+    marker::PhantomData,
+    path::Path,
+};
+
+use anyhow::{Context as _, bail};
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use serde::{Deserialize, Deserializer, de};
+
+const ANY_TYPE: &str = "::serde_json::Value";
+
+/// Generator of `volo-http` routes, types and client stubs from an OpenAPI 3 document.
+#[derive(Clone, Debug)]
+pub struct OpenApiGenerator {
+    server: bool,
+    client: bool,
+}
+
+impl Default for OpenApiGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenApiGenerator {
+    /// Create a generator generating both the server and the client.
+    pub fn new() -> Self {
+        Self {
+            server: true,
+            client: true,
+        }
+    }
+
+    /// Set whether to generate the `Api` trait and `router`.
+    pub fn server(mut self, server: bool) -> Self {
+        self.server = server;
+        self
+    }
+
+    /// Set whether to generate `ApiClient`, which requires the `client` feature of `volo-http`.
+    pub fn client(mut self, client: bool) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Generate the code from an OpenAPI document in yaml or json.
+    pub fn generate(&self, document: &str) -> anyhow::Result<String> {
+        let document: Document =
+            serde_yaml::from_str(document).context("failed to parse the OpenAPI document")?;
+        if !document.openapi.starts_with("3.") {
+            bail!(
+                "unsupported OpenAPI version {}, only OpenAPI 3 is supported",
+                document.openapi
+            );
+        }
+        Codegen::new(&document).generate(self)
+    }
+
+    /// Generate the code from the OpenAPI document at `input` and write it to `output`.
+    pub fn write(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> anyhow::Result<()> {
+        let (input, output) = (input.as_ref(), output.as_ref());
+        let document = std::fs::read_to_string(input)
+            .with_context(|| format!("failed to read {}", input.display()))?;
+        let code = self
+            .generate(&document)
+            .with_context(|| format!("failed to generate code from {}", input.display()))?;
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(output, code)
+            .with_context(|| format!("failed to write {}", output.display()))
+    }
+}
+
+/// A map keeping the order of keys in the document, so that the generated code follows it.
+#[derive(Debug)]
+struct Ordered<T>(Vec<(String, T)>);
+
+impl<T> Default for Ordered<T> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Ordered<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> de::Visitor<'de> for Visitor<T> {
+            type Value = Ordered<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Ordered(entries))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor(PhantomData))
+    }
+}
+
+impl<T> Ordered<T> {
+    fn get(&self, key: &str) -> Option<&T> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Document {
+    openapi: String,
+    #[serde(default)]
+    info: Info,
+    #[serde(default)]
+    paths: Ordered<PathItem>,
+    #[serde(default)]
+    components: Components,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Info {
+    title: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Components {
+    schemas: Ordered<Schema>,
+    parameters: Ordered<Parameter>,
+    #[serde(rename = "requestBodies")]
+    request_bodies: Ordered<RequestBody>,
+    responses: Ordered<Response>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PathItem {
+    parameters: Vec<Parameter>,
+    get: Option<Operation>,
+    put: Option<Operation>,
+    post: Option<Operation>,
+    delete: Option<Operation>,
+    options: Option<Operation>,
+    head: Option<Operation>,
+    patch: Option<Operation>,
+    trace: Option<Operation>,
+}
+
+impl PathItem {
+    fn operations(&self) -> impl Iterator<Item = (&'static str, &Operation)> {
+        [
+            ("get", &self.get),
+            ("put", &self.put),
+            ("post", &self.post),
+            ("delete", &self.delete),
+            ("options", &self.options),
+            ("head", &self.head),
+            ("patch", &self.patch),
+            ("trace", &self.trace),
+        ]
+        .into_iter()
+        .filter_map(|(method, op)| op.as_ref().map(|op| (method, op)))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Operation {
+    #[serde(rename = "operationId")]
+    operation_id: Option<String>,
+    summary: Option<String>,
+    description: Option<String>,
+    parameters: Vec<Parameter>,
+    #[serde(rename = "requestBody")]
+    request_body: Option<RequestBody>,
+    responses: Ordered<Response>,
+    deprecated: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Parameter {
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    name: String,
+    #[serde(rename = "in")]
+    location: String,
+    description: Option<String>,
+    required: bool,
+    schema: Option<Schema>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RequestBody {
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    required: bool,
+    content: Ordered<MediaType>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Response {
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    content: Ordered<MediaType>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MediaType {
+    schema: Option<Schema>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Schema {
+    #[serde(rename = "$ref")]
+    reference: Option<String>,
+    #[serde(rename = "type")]
+    ty: Option<SchemaType>,
+    format: Option<String>,
+    description: Option<String>,
+    properties: Ordered<Schema>,
+    required: Vec<String>,
+    items: Option<Box<Schema>>,
+    #[serde(rename = "enum")]
+    enumeration: Vec<serde_yaml::Value>,
+    #[serde(rename = "additionalProperties")]
+    additional_properties: Option<AdditionalProperties>,
+    nullable: bool,
+    #[serde(rename = "allOf")]
+    all_of: Vec<Schema>,
+}
+
+/// `type` of OpenAPI 3.1 can be an array, e.g., `[string, "null"]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SchemaType {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AdditionalProperties {
+    Bool(#[allow(dead_code)] bool),
+    Schema(Box<Schema>),
+}
+
+impl Schema {
+    fn ty(&self) -> Option<&str> {
+        match &self.ty {
+            Some(SchemaType::Single(ty)) => Some(ty),
+            Some(SchemaType::Multiple(tys)) => {
+                tys.iter().map(String::as_str).find(|t| *t != "null")
+            }
+            None if !self.properties.0.is_empty() => Some("object"),
+            None => None,
+        }
+    }
+
+    fn is_nullable(&self) -> bool {
+        self.nullable
+            || matches!(&self.ty, Some(SchemaType::Multiple(tys)) if tys.iter().any(|t| t == "null"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BodyKind {
+    Json,
+    Form,
+}
+
+/// An operation resolved from the document.
+struct Op {
+    method: &'static str,
+    path: String,
+    name: String,
+    docs: Vec<String>,
+    deprecated: bool,
+    path_params: Vec<(String, String)>,
+    query: Option<String>,
+    body: Option<(BodyKind, String)>,
+    response: Option<String>,
+    status: u16,
+}
+
+struct Codegen<'a> {
+    document: &'a Document,
+    /// Names of the generated types.
+    names: HashSet<String>,
+    types: String,
+}
+
+impl<'a> Codegen<'a> {
+    fn new(document: &'a Document) -> Self {
+        Self {
+            document,
+            names: document
+                .components
+                .schemas
+                .0
+                .iter()
+                .map(|(name, _)| name.to_upper_camel_case())
+                .collect(),
+            types: String::new(),
+        }
+    }
+
+    fn generate(mut self, generator: &OpenApiGenerator) -> anyhow::Result<String> {
+        let document = self.document;
+        for (name, schema) in document.components.schemas.0.iter() {
+            self.define(&name.to_upper_camel_case(), schema)
+                .with_context(|| format!("failed to generate schema {name}"))?;
+        }
+        let mut ops = Vec::new();
+        for (path, item) in document.paths.0.iter() {
+            for (method, op) in item.operations() {
+                ops.push(
+                    self.operation(path, method, item, op)
+                        .with_context(|| format!("failed to generate {method} {path}"))?,
+                );
+            }
+        }
+
+        let mut code = String::new();
+        let _ = writeln!(
+            code,
+            "//! Generated by `volo-build` from the OpenAPI document of {} {}, DO NOT EDIT.\n",
+            document.info.title, document.info.version
+        );
+        code.push_str(&self.types);
+        if generator.server {
+            server(&mut code, &ops);
+        }
+        if generator.client {
+            client(&mut code, &ops);
+        }
+        Ok(code)
+    }
+
+    /// Returns a name not used by other types.
+    fn unique_name(&mut self, name: String) -> String {
+        let mut unique = name.clone();
+        let mut i = 1;
+        while !self.names.insert(unique.clone()) {
+            i += 1;
+            unique = format!("{name}{i}");
+        }
+        unique
+    }
+
+    /// Define a named type of a component schema.
+    fn define(&mut self, name: &str, schema: &Schema) -> anyhow::Result<()> {
+        if let [inner] = schema.all_of.as_slice() {
+            return self.define(name, inner);
+        }
+        if schema.reference.is_none() && is_string_enum(schema) {
+            self.define_enum(name, schema);
+        } else if schema.reference.is_none() && !schema.properties.0.is_empty() {
+            self.define_struct(name, schema)?;
+        } else {
+            let ty = self.ty(schema, name)?;
+            push_docs(&mut self.types, "", schema.description.as_deref());
+            let _ = writeln!(self.types, "pub type {name} = {ty};\n");
+        }
+        Ok(())
+    }
+
+    /// Returns the type of a schema, inline structs and enums are defined by the hint name.
+    fn ty(&mut self, schema: &Schema, hint: &str) -> anyhow::Result<String> {
+        if let Some(reference) = &schema.reference {
+            let name = reference
+                .strip_prefix("#/components/schemas/")
+                .with_context(|| format!("unsupported reference {reference}"))?;
+            if self.document.components.schemas.get(name).is_none() {
+                bail!("schema {name} referenced by {reference} does not exist");
+            }
+            return Ok(name.to_upper_camel_case());
+        }
+        if let [inner] = schema.all_of.as_slice() {
+            return self.ty(inner, hint);
+        }
+        let ty = match schema.ty() {
+            Some("string") if is_string_enum(schema) => {
+                let name = self.unique_name(hint.to_owned());
+                self.define_enum(&name, schema);
+                name
+            }
+            Some("string") => "::std::string::String".to_owned(),
+            Some("integer") => match schema.format.as_deref() {
+                Some("int32") => "i32",
+                Some("uint32") => "u32",
+                Some("uint64") => "u64",
+                _ => "i64",
+            }
+            .to_owned(),
+            Some("number") => match schema.format.as_deref() {
+                Some("float") => "f32",
+                _ => "f64",
+            }
+            .to_owned(),
+            Some("boolean") => "bool".to_owned(),
+            Some("array") => {
+                let item = match &schema.items {
+                    Some(items) => self.ty(items, &format!("{hint}Item"))?,
+                    None => ANY_TYPE.to_owned(),
+                };
+                format!("::std::vec::Vec<{item}>")
+            }
+            Some("object") if !schema.properties.0.is_empty() => {
+                let name = self.unique_name(hint.to_owned());
+                self.define_struct(&name, schema)?;
+                name
+            }
+            Some("object") => match &schema.additional_properties {
+                Some(AdditionalProperties::Schema(value)) => format!(
+                    "::std::collections::HashMap<::std::string::String, {}>",
+                    self.ty(value, &format!("{hint}Value"))?
+                ),
+                _ => ANY_TYPE.to_owned(),
+            },
+            _ => ANY_TYPE.to_owned(),
+        };
+        Ok(ty)
+    }
+
+    fn define_struct(&mut self, name: &str, schema: &Schema) -> anyhow::Result<()> {
+        let mut fields = String::new();
+        for (prop, field) in schema.properties.0.iter() {
+            let mut ty = self.ty(field, &format!("{name}{}", prop.to_upper_camel_case()))?;
+            // only the direct recursion needs boxing, collections are already indirect
+            if ty == name {
+                ty = format!("::std::boxed::Box<{ty}>");
+            }
+            let ident = ident(&prop.to_snake_case());
+            push_docs(&mut fields, "    ", field.description.as_deref());
+            push_field(
+                &mut fields,
+                prop,
+                &ident,
+                &ty,
+                schema.required.contains(prop),
+                field.is_nullable(),
+            );
+        }
+        self.push_struct(name, schema.description.as_deref(), &fields);
+        Ok(())
+    }
+
+    fn push_struct(&mut self, name: &str, docs: Option<&str>, fields: &str) {
+        push_docs(&mut self.types, "", docs);
+        let _ = writeln!(
+            self.types,
+            "#[derive(Clone, Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\npub \
+             struct {name} {{\n{fields}}}\n"
+        );
+    }
+
+    fn define_enum(&mut self, name: &str, schema: &Schema) {
+        let mut variants = String::new();
+        let mut idents = HashSet::new();
+        for value in schema.enumeration.iter().filter_map(|v| v.as_str()) {
+            let mut variant = value.to_upper_camel_case();
+            if !variant.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                variant = format!("V{variant}");
+            }
+            while !idents.insert(variant.clone()) {
+                variant.push('_');
+            }
+            let _ = writeln!(variants, "    #[serde(rename = {value:?})]\n    {variant},");
+        }
+        push_docs(&mut self.types, "", schema.description.as_deref());
+        let _ = writeln!(
+            self.types,
+            "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ::serde::Serialize, \
+             ::serde::Deserialize)]\npub enum {name} {{\n{variants}}}\n"
+        );
+    }
+
+    fn parameter<'p>(&self, param: &'p Parameter) -> anyhow::Result<&'p Parameter>
+    where
+        'a: 'p,
+    {
+        match &param.reference {
+            Some(reference) => resolve(
+                reference,
+                "#/components/parameters/",
+                &self.document.components.parameters,
+            ),
+            None => Ok(param),
+        }
+    }
+
+    fn operation(
+        &mut self,
+        path: &str,
+        method: &'static str,
+        item: &PathItem,
+        op: &Operation,
+    ) -> anyhow::Result<Op> {
+        let name = match &op.operation_id {
+            Some(id) => id.to_snake_case(),
+            None => format!("{method}_{path}").to_snake_case(),
+        };
+        let type_name = name.to_upper_camel_case();
+
+        // parameters of the operation override the ones of the path
+        let mut params: Vec<&Parameter> = Vec::new();
+        for param in op.parameters.iter().chain(item.parameters.iter()) {
+            let param = self.parameter(param)?;
+            if !params
+                .iter()
+                .any(|p| p.name == param.name && p.location == param.location)
+            {
+                params.push(param);
+            }
+        }
+
+        let mut path_params = Vec::new();
+        for segment in path.split('/') {
+            let Some(param) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) else {
+                continue;
+            };
+            let ty = params
+                .iter()
+                .find(|p| p.location == "path" && p.name == param)
+                .and_then(|p| p.schema.as_ref())
+                .map(path_param_ty)
+                .unwrap_or("::std::string::String");
+            path_params.push((ident(&param.to_snake_case()), ty.to_owned()));
+        }
+
+        let query_params: Vec<_> = params.iter().filter(|p| p.location == "query").collect();
+        let query = if query_params.is_empty() {
+            None
+        } else {
+            let query = self.unique_name(format!("{type_name}Query"));
+            let mut fields = String::new();
+            for param in query_params {
+                let ty = match &param.schema {
+                    Some(schema) => self.ty(
+                        schema,
+                        &format!("{query}{}", param.name.to_upper_camel_case()),
+                    )?,
+                    None => "::std::string::String".to_owned(),
+                };
+                push_docs(&mut fields, "    ", param.description.as_deref());
+                push_field(
+                    &mut fields,
+                    &param.name,
+                    &ident(&param.name.to_snake_case()),
+                    &ty,
+                    param.required,
+                    false,
+                );
+            }
+            self.push_struct(&query, None, &fields);
+            Some(query)
+        };
+
+        let body = match &op.request_body {
+            Some(body) => {
+                let body = match &body.reference {
+                    Some(reference) => resolve(
+                        reference,
+                        "#/components/requestBodies/",
+                        &self.document.components.request_bodies,
+                    )?,
+                    None => body,
+                };
+                let Some((kind, media)) = body
+                    .content
+                    .0
+                    .iter()
+                    .find_map(|(mime, media)| body_kind(mime).map(|kind| (kind, media)))
+                else {
+                    bail!("only json and form request bodies are supported");
+                };
+                let ty = match &media.schema {
+                    Some(schema) => self.ty(schema, &format!("{type_name}Request"))?,
+                    None => ANY_TYPE.to_owned(),
+                };
+                Some((kind, ty))
+            }
+            None => None,
+        };
+
+        let mut status = 200;
+        let mut response = None;
+        let success = op
+            .responses
+            .0
+            .iter()
+            .filter_map(|(code, resp)| Some((code.parse::<u16>().ok()?, resp)))
+            .filter(|(code, _)| (200..300).contains(code))
+            .min_by_key(|(code, _)| *code);
+        if let Some((code, resp)) = success {
+            let resp = match &resp.reference {
+                Some(reference) => resolve(
+                    reference,
+                    "#/components/responses/",
+                    &self.document.components.responses,
+                )?,
+                None => resp,
+            };
+            status = code;
+            let media = resp
+                .content
+                .0
+                .iter()
+                .find(|(mime, _)| body_kind(mime) == Some(BodyKind::Json));
+            if let Some((_, media)) = media {
+                response = Some(match &media.schema {
+                    Some(schema) => self.ty(schema, &format!("{type_name}Response"))?,
+                    None => ANY_TYPE.to_owned(),
+                });
+            }
+        }
+
+        let docs = [op.summary.as_deref(), op.description.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(ToOwned::to_owned)
+            .collect();
+
+        Ok(Op {
+            method,
+            path: path.to_owned(),
+            name: ident(&name),
+            docs,
+            deprecated: op.deprecated,
+            path_params,
+            query,
+            body,
+            response,
+            status,
+        })
+    }
+}
+
+fn resolve<'a, T>(
+    reference: &str,
+    prefix: &str,
+    components: &'a Ordered<T>,
+) -> anyhow::Result<&'a T> {
+    reference
+        .strip_prefix(prefix)
+        .and_then(|name| components.get(name))
+        .with_context(|| format!("failed to resolve reference {reference}"))
+}
+
+fn is_string_enum(schema: &Schema) -> bool {
+    schema.ty() == Some("string")
+        && !schema.enumeration.is_empty()
+        && schema.enumeration.iter().all(|v| v.is_string())
+}
+
+fn body_kind(mime: &str) -> Option<BodyKind> {
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    if mime == "application/json" || mime.ends_with("+json") {
+        Some(BodyKind::Json)
+    } else if mime == "application/x-www-form-urlencoded" {
+        Some(BodyKind::Form)
+    } else {
+        None
+    }
+}
+
+/// Path parameters are only parsed as the types implementing `FromPathParam`.
+fn path_param_ty(schema: &Schema) -> &'static str {
+    match (schema.ty(), schema.format.as_deref()) {
+        (Some("integer"), Some("int32")) => "i32",
+        (Some("integer"), Some("uint32")) => "u32",
+        (Some("integer"), Some("uint64")) => "u64",
+        (Some("integer"), _) => "i64",
+        (Some("boolean"), _) => "bool",
+        _ => "::std::string::String",
+    }
+}
+
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "unsafe", "use", "where", "while", "yield", "abstract", "become", "box", "do", "final",
+        "macro", "override", "priv", "typeof", "unsized", "virtual",
+    ];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else if matches!(name, "self" | "super" | "crate" | "_") {
+        format!("{name}_")
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+fn push_docs(code: &mut String, indent: &str, docs: Option<&str>) {
+    for line in docs.into_iter().flat_map(str::lines) {
+        let _ = writeln!(code, "{indent}/// {line}");
+    }
+}
+
+fn push_field(
+    code: &mut String,
+    name: &str,
+    ident: &str,
+    ty: &str,
+    required: bool,
+    nullable: bool,
+) {
+    if ident.trim_start_matches("r#") != name {
+        let _ = writeln!(code, "    #[serde(rename = {name:?})]");
+    }
+    if !required {
+        code.push_str(
+            "    #[serde(default, skip_serializing_if = \"::std::option::Option::is_none\")]\n",
+        );
+    }
+    if !required || nullable {
+        let _ = writeln!(code, "    pub {ident}: ::std::option::Option<{ty}>,");
+    } else {
+        let _ = writeln!(code, "    pub {ident}: {ty},");
+    }
+}
+
+fn push_op_docs(code: &mut String, op: &Op) {
+    for (i, doc) in op.docs.iter().enumerate() {
+        if i > 0 {
+            code.push_str("    ///\n");
+        }
+        push_docs(code, "    ", Some(doc));
+    }
+    if op.deprecated {
+        code.push_str("    #[deprecated]\n");
+    }
+}
+
+/// The name of the constant of a success status in `http::StatusCode`.
+fn status_code(status: u16) -> String {
+    let name = match status {
+        200 => "OK",
+        201 => "CREATED",
+        202 => "ACCEPTED",
+        203 => "NON_AUTHORITATIVE_INFORMATION",
+        204 => "NO_CONTENT",
+        205 => "RESET_CONTENT",
+        206 => "PARTIAL_CONTENT",
+        207 => "MULTI_STATUS",
+        208 => "ALREADY_REPORTED",
+        226 => "IM_USED",
+        _ => {
+            return format!(
+                "::volo_http::http::StatusCode::from_u16({status}).expect(\"valid status code\")"
+            );
+        }
+    };
+    format!("::volo_http::http::StatusCode::{name}")
+}
+
+fn server(code: &mut String, ops: &[Op]) {
+    code.push_str(
+        r#"/// The error returned by implementations of [`Api`], which is responded with the status
+/// and the message.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: ::volo_http::http::StatusCode,
+    pub message: ::std::string::String,
+}
+
+impl ApiError {
+    pub fn new(
+        status: ::volo_http::http::StatusCode,
+        message: impl ::std::convert::Into<::std::string::String>,
+    ) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl ::volo_http::server::IntoResponse for ApiError {
+    fn into_response(self) -> ::volo_http::response::Response {
+        ::volo_http::server::IntoResponse::into_response((self.status, self.message))
+    }
+}
+
+/// Operations of the API, serve an implementation by [`router`].
+pub trait Api: ::std::marker::Send + ::std::marker::Sync + 'static {
+"#,
+    );
+    for (i, op) in ops.iter().enumerate() {
+        if i > 0 {
+            code.push('\n');
+        }
+        push_op_docs(code, op);
+        let mut args = String::new();
+        for (name, ty) in op.path_params.iter() {
+            let _ = write!(args, ", {name}: {ty}");
+        }
+        if let Some(query) = &op.query {
+            let _ = write!(args, ", query: {query}");
+        }
+        if let Some((_, body)) = &op.body {
+            let _ = write!(args, ", body: {body}");
+        }
+        let _ = writeln!(
+            code,
+            "    fn {}(&self{args}) -> impl ::std::future::Future<Output = \
+             ::std::result::Result<{}, ApiError>> + ::std::marker::Send;",
+            op.name,
+            op.response.as_deref().unwrap_or("()"),
+        );
+    }
+    code.push_str(
+        "}\n\n/// Create a [`Router`](::volo_http::server::route::Router) serving all operations \
+         of the [`Api`].\npub fn router<A: Api>(api: A) -> ::volo_http::server::route::Router \
+         {\n    let api = ::std::sync::Arc::new(api);\n    \
+         ::volo_http::server::route::Router::new()\n",
+    );
+
+    let mut paths: Vec<&str> = Vec::new();
+    for op in ops {
+        if !paths.contains(&op.path.as_str()) {
+            paths.push(&op.path);
+        }
+    }
+    for path in paths {
+        let _ = write!(code, "        .route(\n            {path:?},\n            ");
+        for (i, op) in ops.iter().filter(|op| op.path == path).enumerate() {
+            if i == 0 {
+                let _ = write!(code, "::volo_http::server::route::{}(", op.method);
+            } else {
+                let _ = write!(code, "\n            .{}(", op.method);
+            }
+            handler(code, op);
+            code.push(')');
+        }
+        code.push_str(",\n        )\n");
+    }
+    code.push_str("}\n\n");
+}
+
+fn handler(code: &mut String, op: &Op) {
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    match op.path_params.as_slice() {
+        [] => {}
+        [(name, ty)] => {
+            params.push(format!(
+                "::volo_http::server::param::PathParams({name}): \
+                 ::volo_http::server::param::PathParams<{ty}>"
+            ));
+            args.push(name.clone());
+        }
+        path_params => {
+            let names = path_params
+                .iter()
+                .map(|(n, _)| n.as_str())
+                .collect::<Vec<_>>();
+            let tys = path_params
+                .iter()
+                .map(|(_, t)| t.as_str())
+                .collect::<Vec<_>>();
+            params.push(format!(
+                "::volo_http::server::param::PathParams(({})): \
+                 ::volo_http::server::param::PathParams<({})>",
+                names.join(", "),
+                tys.join(", ")
+            ));
+            args.extend(names.into_iter().map(ToOwned::to_owned));
+        }
+    }
+    if let Some(query) = &op.query {
+        params.push(format!(
+            "::volo_http::server::extract::Query(query): \
+             ::volo_http::server::extract::Query<{query}>"
+        ));
+        args.push("query".to_owned());
+    }
+    if let Some((kind, body)) = &op.body {
+        let extractor = match kind {
+            BodyKind::Json => "Json",
+            BodyKind::Form => "Form",
+        };
+        params.push(format!(
+            "::volo_http::server::extract::{extractor}(body): \
+             ::volo_http::server::extract::{extractor}<{body}>"
+        ));
+        args.push("body".to_owned());
+    }
+    let status = status_code(op.status);
+    let map = match (&op.response, op.status) {
+        (Some(_), 200) => "::volo_http::server::extract::Json".to_owned(),
+        (Some(_), _) => format!("|resp| ({status}, ::volo_http::server::extract::Json(resp))"),
+        (None, _) => format!("|()| {status}"),
+    };
+    let _ = write!(
+        code,
+        "{{\n                let api = api.clone();\n                move |{}| async move {{ \
+         api.{}({}).await.map({map}) }}\n            }}",
+        params.join(", "),
+        op.name,
+        args.join(", "),
+    );
+}
+
+fn client(code: &mut String, ops: &[Op]) {
+    code.push_str(
+        r#"/// The error returned by [`ApiClient`].
+#[derive(Debug)]
+pub enum ApiClientError {
+    /// Failed to send the request or to receive the response.
+    Client(::volo_http::error::ClientError),
+    /// The server responded with a status other than 2xx.
+    Status {
+        status: ::volo_http::http::StatusCode,
+        body: ::std::string::String,
+    },
+}
+
+impl ::std::fmt::Display for ApiClientError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        match self {
+            Self::Client(err) => ::std::write!(f, "{err}"),
+            Self::Status { status, body } => ::std::write!(f, "unexpected status {status}: {body}"),
+        }
+    }
+}
+
+impl ::std::error::Error for ApiClientError {
+    fn source(&self) -> ::std::option::Option<&(dyn ::std::error::Error + 'static)> {
+        match self {
+            Self::Client(err) => ::std::option::Option::Some(err),
+            Self::Status { .. } => ::std::option::Option::None,
+        }
+    }
+}
+
+impl ::std::convert::From<::volo_http::error::ClientError> for ApiClientError {
+    fn from(err: ::volo_http::error::ClientError) -> Self {
+        Self::Client(err)
+    }
+}
+
+async fn check_status(
+    resp: ::volo_http::response::Response,
+) -> ::std::result::Result<::volo_http::response::Response, ApiClientError> {
+    let status = resp.status();
+    if status.is_success() {
+        return ::std::result::Result::Ok(resp);
+    }
+    let body = ::volo_http::body::BodyConversion::into_string(resp.into_body())
+        .await
+        .unwrap_or_default();
+    ::std::result::Result::Err(ApiClientError::Status { status, body })
+}
+
+fn encode_path(segment: &str) -> ::std::string::String {
+    let mut encoded = ::std::string::String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || ::std::matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&::std::format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// The client of the API.
+#[derive(Clone)]
+pub struct ApiClient {
+    client: ::volo_http::client::Client,
+    base_url: ::std::string::String,
+}
+
+impl ApiClient {
+    /// Create an [`ApiClient`] sending requests to `base_url` by the `client`, e.g.,
+    /// `http://127.0.0.1:8080/v1`, or an empty string for the target of the `client`.
+    pub fn new(
+        client: ::volo_http::client::Client,
+        base_url: impl ::std::convert::Into<::std::string::String>,
+    ) -> Self {
+        let mut base_url = base_url.into();
+        while base_url.ends_with('/') {
+            base_url.pop();
+        }
+        Self { client, base_url }
+    }
+"#,
+    );
+    for op in ops {
+        code.push('\n');
+        push_op_docs(code, op);
+        let mut args = String::new();
+        for (name, ty) in op.path_params.iter() {
+            let _ = write!(args, ", {name}: {ty}");
+        }
+        if let Some(query) = &op.query {
+            let _ = write!(args, ", query: &{query}");
+        }
+        if let Some((_, body)) = &op.body {
+            let _ = write!(args, ", body: &{body}");
+        }
+        let mut uri = String::from("{}");
+        let mut uri_args = String::from("self.base_url");
+        let mut path_params = op.path_params.iter().peekable();
+        for (i, segment) in op.path.split('/').enumerate() {
+            if i > 0 {
+                uri.push('/');
+            }
+            // path parameters are collected from the segments in order
+            match path_params.next_if(|_| segment.starts_with('{') && segment.ends_with('}')) {
+                Some((name, _)) => {
+                    uri.push_str("{}");
+                    let _ = write!(uri_args, ", encode_path(&{name}.to_string())");
+                }
+                None => uri.push_str(segment),
+            }
+        }
+        let mut request = format!(
+            "self\n            .client\n            .{}(::std::format!({uri:?}, {uri_args}))",
+            op.method
+        );
+        if op.query.is_some() {
+            request.push_str("\n            .set_query(query)");
+        }
+        match &op.body {
+            Some((BodyKind::Json, _)) => request.push_str("\n            .json(body)"),
+            Some((BodyKind::Form, _)) => request.push_str("\n            .form(body)"),
+            None => {}
+        }
+        let ret = if op.response.is_some() {
+            "let resp = check_status(resp).await?;\n        \
+             ::volo_http::response::ResponseConversion::into_json(resp)\n            .await\n            \
+             .map_err(|err| ApiClientError::Client(::volo_http::error::client::body_error(err)))"
+        } else {
+            "check_status(resp).await?;\n        ::std::result::Result::Ok(())"
+        };
+        let _ = writeln!(
+            code,
+            "    pub async fn {}(&self{args}) -> ::std::result::Result<{}, ApiClientError> {{\n        \
+             let resp = {request}\n            .send()\n            .await?;\n        {ret}\n    }}",
+            op.name,
+            op.response.as_deref().unwrap_or("()"),
+        );
+    }
+    code.push_str("}\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpenApiGenerator, ident};
+
+    const PETSTORE: &str = r##"
+openapi: 3.0.3
+info:
+  title: Petstore
+  version: 1.0.0
+paths:
+  /pets:
+    get:
+      operationId: listPets
+      summary: List all pets
+      parameters:
+        - name: limit
+          in: query
+          schema:
+            type: integer
+            format: int32
+        - name: X-Request-Id
+          in: header
+          schema:
+            type: string
+      responses:
+        "200":
+          description: pets
+          content:
+            application/json:
+              schema:
+                type: array
+                items:
+                  $ref: "#/components/schemas/Pet"
+    post:
+      operationId: createPet
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Pet"
+      responses:
+        "201":
+          description: created
+          content:
+            application/json:
+              schema:
+                $ref: "#/components/schemas/Pet"
+  /stores/{storeId}/pets/{petId}:
+    parameters:
+      - $ref: "#/components/parameters/StoreId"
+    delete:
+      operationId: deletePet
+      parameters:
+        - name: petId
+          in: path
+          required: true
+          schema:
+            type: string
+      responses:
+        "204":
+          description: deleted
+components:
+  parameters:
+    StoreId:
+      name: storeId
+      in: path
+      required: true
+      schema:
+        type: integer
+  schemas:
+    Pet:
+      type: object
+      required: [id, name]
+      properties:
+        id:
+          type: integer
+        name:
+          type: string
+        kind:
+          type: string
+          enum: [cat, dog, "9lives"]
+        tag:
+          type: object
+          properties:
+            label:
+              type: string
+              nullable: true
+        parent:
+          $ref: "#/components/schemas/Pet"
+        attributes:
+          type: object
+          additionalProperties:
+            type: string
+        extra: {}
+"##;
+
+    #[test]
+    fn test_types() {
+        let code = OpenApiGenerator::new()
+            .server(false)
+            .client(false)
+            .generate(PETSTORE)
+            .unwrap();
+        assert!(code.contains(
+            "pub struct Pet {\n    pub id: i64,\n    pub name: ::std::string::String,\n"
+        ));
+        assert!(code.contains("    pub kind: ::std::option::Option<PetKind>,\n"));
+        assert!(code.contains("pub enum PetKind {\n    #[serde(rename = \"cat\")]\n    Cat,"));
+        assert!(code.contains("#[serde(rename = \"9lives\")]\n    V9lives,"));
+        assert!(code.contains("pub struct PetTag {"));
+        assert!(code.contains("    pub label: ::std::option::Option<::std::string::String>,\n"));
+        assert!(code.contains("pub parent: ::std::option::Option<::std::boxed::Box<Pet>>,"));
+        assert!(code.contains(
+            "pub attributes: \
+             ::std::option::Option<::std::collections::HashMap<::std::string::String, \
+             ::std::string::String>>,"
+        ));
+        assert!(code.contains("pub extra: ::std::option::Option<::serde_json::Value>,"));
+        assert!(code.contains(
+            "pub struct ListPetsQuery {\n    #[serde(default, skip_serializing_if = \
+             \"::std::option::Option::is_none\")]\n    pub limit: ::std::option::Option<i32>,"
+        ));
+        // header parameters are not generated
+        assert!(!code.contains("request_id"));
+        assert!(!code.contains("trait Api"));
+        assert!(!code.contains("ApiClient"));
+    }
+
+    #[test]
+    fn test_server_and_client() {
+        let code = OpenApiGenerator::new().generate(PETSTORE).unwrap();
+        assert!(code.contains(
+            "fn list_pets(&self, query: ListPetsQuery) -> impl ::std::future::Future<Output = \
+             ::std::result::Result<::std::vec::Vec<Pet>, ApiError>>"
+        ));
+        assert!(code.contains("fn create_pet(&self, body: Pet)"));
+        assert!(code.contains(
+            "fn delete_pet(&self, store_id: i64, pet_id: ::std::string::String) -> impl \
+             ::std::future::Future<Output = ::std::result::Result<(), ApiError>>"
+        ));
+        assert!(
+            code.contains(
+                "::volo_http::server::route::get({\n                let api = api.clone();"
+            )
+        );
+        assert!(code.contains("\n            .post({"));
+        assert!(code.contains(
+            "api.create_pet(body).await.map(|resp| (::volo_http::http::StatusCode::CREATED, \
+             ::volo_http::server::extract::Json(resp)))"
+        ));
+        assert!(code.contains(
+            "::volo_http::server::param::PathParams((store_id, pet_id)): \
+             ::volo_http::server::param::PathParams<(i64, ::std::string::String)>"
+        ));
+        assert!(code.contains("\"/stores/{storeId}/pets/{petId}\""));
+        assert!(code.contains("map(|()| ::volo_http::http::StatusCode::NO_CONTENT)"));
+
+        assert!(code.contains(
+            "pub async fn delete_pet(&self, store_id: i64, pet_id: ::std::string::String) -> \
+             ::std::result::Result<(), ApiClientError>"
+        ));
+        assert!(code.contains(
+            ".delete(::std::format!(\"{}/stores/{}/pets/{}\", self.base_url, \
+             encode_path(&store_id.to_string()), encode_path(&pet_id.to_string())))"
+        ));
+        assert!(code.contains(
+            ".get(::std::format!(\"{}/pets\", self.base_url))\n            .set_query(query)"
+        ));
+        assert!(code.contains(".json(body)"));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let err = OpenApiGenerator::new()
+            .generate("swagger: \"2.0\"\nopenapi: \"2.0\"\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("only OpenAPI 3 is supported"));
+
+        let document = r#"
+openapi: 3.1.0
+paths:
+  /upload:
+    post:
+      requestBody:
+        content:
+          application/octet-stream: {}
+      responses: {}
+"#;
+        let err = OpenApiGenerator::new().generate(document).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("only json and form request bodies are supported"),
+            "{err:#}"
+        );
+
+        let document = r##"
+openapi: 3.1.0
+components:
+  schemas:
+    Pet:
+      $ref: "#/components/schemas/Missing"
+"##;
+        assert!(OpenApiGenerator::new().generate(document).is_err());
+    }
+
+    #[test]
+    fn test_ident() {
+        assert_eq!(ident("type"), "r#type");
+        assert_eq!(ident("self"), "self_");
+        assert_eq!(ident("1st"), "_1st");
+        assert_eq!(ident("name"), "name");
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use itertools::Itertools;
@@ -11,6 +13,7 @@ use pilota_build::{
     rir::{self, Method},
     tags::RustWrapperArc,
 };
+use pilota_thrift_parser as thrift_parser;
 use quote::format_ident;
 use volo::FastStr;
 
@@ -379,6 +382,84 @@ impl VoloThriftBackend {
         }
     }
 
+    /// Generates the calls setting the default config of methods by their annotations, e.g.,
+    /// `(vt.timeout = "200ms", vt.retry = "2")`.
+    ///
+    /// Pilota only keeps its own annotations, so the IDL files are parsed again for them.
+    fn codegen_method_configs(&self, def_id: DefId, methods: &[Arc<Method>]) -> String {
+        let mut files = HashMap::new();
+        let mut configs = String::new();
+        for method in methods {
+            let service_def_id = match method.source {
+                rir::MethodSource::Extend(def_id) => def_id,
+                rir::MethodSource::Own => def_id,
+            };
+            let Some(path) = self
+                .cx()
+                .node(service_def_id)
+                .and_then(|node| self.cx().file_paths().get(&node.file_id).cloned())
+            else {
+                continue;
+            };
+            let file = files
+                .entry(path.clone())
+                .or_insert_with(|| parse_thrift_file(&path));
+            let Some(file) = file else {
+                continue;
+            };
+            let service_name = match &*self.cx().expect_item(service_def_id) {
+                rir::Item::Service(s) => s.name.clone(),
+                _ => panic!("expected service"),
+            };
+            let Some(function) = file.items.iter().find_map(|item| match item {
+                thrift_parser::Item::Service(s) if *s.name.0 == *service_name.sym => {
+                    s.functions.iter().find(|f| *f.name.0 == *method.name.sym)
+                }
+                _ => None,
+            }) else {
+                continue;
+            };
+
+            let mut config = String::new();
+            for annotation in function.annotations.iter() {
+                let value = annotation.value.0.as_str();
+                match annotation.key.as_str() {
+                    TIMEOUT_ANNOTATION => {
+                        let timeout = parse_duration(value).unwrap_or_else(|| {
+                            panic!(
+                                "invalid `{TIMEOUT_ANNOTATION}` of method `{service_name}.{}`: \
+                                 {value:?}, expected e.g. \"200ms\"",
+                                method.name
+                            )
+                        });
+                        config.push_str(&format!(
+                            ".rpc_timeout(::std::time::Duration::from_nanos({}))",
+                            timeout.as_nanos()
+                        ));
+                    }
+                    RETRY_ANNOTATION => {
+                        let count = value.parse::<usize>().unwrap_or_else(|_| {
+                            panic!(
+                                "invalid `{RETRY_ANNOTATION}` of method `{service_name}.{}`: \
+                                 {value:?}, expected e.g. \"2\"",
+                                method.name
+                            )
+                        });
+                        config.push_str(&format!(".retry_count({count})"));
+                    }
+                    _ => {}
+                }
+            }
+            if !config.is_empty() {
+                configs.push_str(&format!(
+                    ".method_config(\"{}\", ::volo_thrift::client::MethodConfig::new(){config})",
+                    method.name
+                ));
+            }
+        }
+        configs
+    }
+
     fn method_ty_path(&self, service_name: &Symbol, method: &Method, suffix: &str) -> FastStr {
         match method.source {
             rir::MethodSource::Extend(def_id) => {
@@ -771,6 +852,8 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             }}"#
         };
 
+        let method_configs = self.codegen_method_configs(def_id, &all_methods);
+
         let client_string = format! {
             r#" pub struct {mk_client_name};
 
@@ -793,6 +876,10 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
                     {oneshot_client_name}(self.0.with_opt(opt))
                 }}
 
+                pub async fn warmup(&self, warmup: ::volo::loadbalance::warmup::Warmup) -> ::std::result::Result<::volo::loadbalance::warmup::WarmupReport, ::volo_thrift::ClientError> {{
+                    self.0.warmup(warmup).await
+                }}
+
                 {client_methods}
             }}
 
@@ -815,7 +902,7 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
                     ::volo::loadbalance::LbConfig<::volo::loadbalance::random::WeightedRandomBalance<()>, ::volo::discovery::DummyDiscover>,
                 >
                 {{
-                    ::volo_thrift::client::ClientBuilder::new(service_name, {mk_client_name})
+                    ::volo_thrift::client::ClientBuilder::new(service_name, {mk_client_name}){method_configs}
                 }}
             }}"#
         };
@@ -993,6 +1080,36 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
     }
 }
 
+/// The annotation of methods for the default rpc timeout of calls, e.g., `vt.timeout = "200ms"`.
+const TIMEOUT_ANNOTATION: &str = "vt.timeout";
+
+/// The annotation of methods for the default retry count of calls, e.g., `vt.retry = "2"`.
+const RETRY_ANNOTATION: &str = "vt.retry";
+
+fn parse_thrift_file(path: &Path) -> Option<thrift_parser::File> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let source = thrift_parser::FileSource::new_with_path(path.to_path_buf(), &content).ok()?;
+    thrift_parser::FileParser::new(source).parse().ok()
+}
+
+/// Parses durations like `200ms`, `1.5s` or `1m`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (value, unit) = s.split_at(split);
+    let value = value.parse::<f64>().ok()?;
+    let secs = match unit {
+        "ns" => value / 1e9,
+        "us" => value / 1e6,
+        "ms" => value / 1e3,
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(secs).ok()
+}
+
 fn rust_name(cx: &Context, def_id: DefId) -> FastStr {
     let name = cx.rust_name(def_id);
     if cx.cache.names.contains_key(&def_id) {
@@ -1032,7 +1149,10 @@ mod tests {
         let dir = tempdir().expect("create temp dir");
         let file_path = dir.path().join("test.thrift");
         fs::write(&file_path, thrift_content).expect("write thrift");
+        build_test_context_at(file_path)
+    }
 
+    fn build_test_context_at(file_path: PathBuf) -> Context {
         Builder::<pilota_build::MkThriftBackend, ThriftParser>::build_cx(
             vec![IdlService::from_path(file_path)],
             None,
@@ -1113,4 +1233,48 @@ mod tests {
         );
         assert!(sig.contains(&expected), "signature: {sig}");
     }
+
+    #[test]
+    fn test_codegen_method_configs() {
+        let dir = tempdir().expect("create temp dir");
+        let file_path = dir.path().join("test.thrift");
+        fs::write(
+            &file_path,
+            r#"
+            service ItemService {
+                i32 GetItem(1: i64 id) (vt.timeout = "200ms", vt.retry = "2")
+                i32 ListItems(1: i64 id) (vt.timeout = "1.5s")
+                i32 DeleteItem(1: i64 id)
+            }
+            "#,
+        )
+        .expect("write thrift");
+        let cx = build_test_context_at(file_path);
+
+        let svc_def_id = find_first_service(&cx);
+        let methods = cx.service_methods(svc_def_id);
+        let backend = VoloThriftBackend {
+            inner: ThriftBackend::new(cx.clone()),
+        };
+        let configs = backend.codegen_method_configs(svc_def_id, &methods);
+        assert_eq!(
+            configs,
+            ".method_config(\"GetItem\", \
+             ::volo_thrift::client::MethodConfig::new().\
+             rpc_timeout(::std::time::Duration::from_nanos(200000000)).retry_count(2)).\
+             method_config(\"ListItems\", \
+             ::volo_thrift::client::MethodConfig::new().\
+             rpc_timeout(::std::time::Duration::from_nanos(1500000000)))"
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("200ms"), Some(Duration::from_millis(200)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("200"), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration("1d"), None);
+    }
 }
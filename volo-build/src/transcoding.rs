@@ -0,0 +1,564 @@
+//! Codegen of JSON transcoding routers by the `google.api.http` annotations of methods.
+//!
+//! The annotations are options of methods which are not parsed by `pilota-build`, so they are
+//! decoded from the file descriptors, and the path templates are converted to routes of
+//! `volo-http` at codegen time.
+
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+/// The field number of the `google.api.http` extension of `MethodOptions`.
+const HTTP_EXTENSION: u64 = 72295728;
+
+/// A binding of `google.api.http`, the additional bindings are flattened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HttpRule {
+    pub method: &'static str,
+    pub path: String,
+    pub body: String,
+    pub response_body: String,
+}
+
+/// A route of `volo-http` converted from a path template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Route {
+    pub path: String,
+    pub bindings: Vec<Binding>,
+}
+
+/// A field bound by the path template, whose value is formatted by the pattern whose `{}`s are
+/// replaced by the params.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Binding {
+    pub field: String,
+    pub pattern: String,
+    pub params: Vec<String>,
+}
+
+enum WireValue<'a> {
+    Bytes(&'a [u8]),
+    // varints and fixed values are not used
+    Other,
+}
+
+/// Reader of the fields of an encoded message, returns `None` if the message is invalid.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = self.0.split_first()?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn skip(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(value)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Option<(u64, WireValue<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = (|| {
+            let key = self.varint()?;
+            let value = match key & 0x7 {
+                0 => {
+                    self.varint()?;
+                    WireValue::Other
+                }
+                1 => {
+                    self.skip(8)?;
+                    WireValue::Other
+                }
+                2 => {
+                    let len = self.varint()? as usize;
+                    WireValue::Bytes(self.skip(len)?)
+                }
+                5 => {
+                    self.skip(4)?;
+                    WireValue::Other
+                }
+                _ => return None,
+            };
+            Some((key >> 3, value))
+        })();
+        if field.is_none() {
+            // stop at invalid fields
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+fn bytes_fields(message: &[u8]) -> impl Iterator<Item = (u64, &[u8])> {
+    Fields(message)
+        .map_while(|field| field)
+        .filter_map(|(number, value)| match value {
+            WireValue::Bytes(bytes) => Some((number, bytes)),
+            _ => None,
+        })
+}
+
+fn string_field(message: &[u8], number: u64) -> Option<String> {
+    bytes_fields(message)
+        .filter(|(n, _)| *n == number)
+        .last()
+        .map(|(_, bytes)| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Decode the rules of methods of the service from the encoded `FileDescriptorProto`.
+pub(crate) fn http_rules(descriptor: &[u8], service: &str) -> Vec<(String, Vec<HttpRule>)> {
+    // FileDescriptorProto.service = 6
+    let Some(service) = bytes_fields(descriptor)
+        .filter(|(n, _)| *n == 6)
+        .map(|(_, s)| s)
+        .find(|s| string_field(s, 1).as_deref() == Some(service))
+    else {
+        return Vec::new();
+    };
+
+    // ServiceDescriptorProto.method = 2, MethodDescriptorProto.options = 4
+    bytes_fields(service)
+        .filter(|(n, _)| *n == 2)
+        .filter_map(|(_, method)| {
+            let name = string_field(method, 1)?;
+            let mut rules = Vec::new();
+            for (_, options) in bytes_fields(method).filter(|(n, _)| *n == 4) {
+                for (_, rule) in bytes_fields(options).filter(|(n, _)| *n == HTTP_EXTENSION) {
+                    decode_rule(rule, &mut rules);
+                }
+            }
+            Some((name, rules))
+        })
+        .filter(|(_, rules)| !rules.is_empty())
+        .collect()
+}
+
+fn decode_rule(rule: &[u8], rules: &mut Vec<HttpRule>) {
+    let mut method = None;
+    let mut path = String::new();
+    let mut additional = Vec::new();
+    for (number, value) in bytes_fields(rule) {
+        let method_of = match number {
+            2 => Some("get"),
+            3 => Some("put"),
+            4 => Some("post"),
+            5 => Some("delete"),
+            6 => Some("patch"),
+            // custom methods are not supported by the router
+            8 => {
+                let kind = string_field(value, 1).unwrap_or_default();
+                println!("cargo:warning=custom http method `{kind}` is not supported, skipped");
+                None
+            }
+            11 => {
+                additional.push(value);
+                None
+            }
+            _ => None,
+        };
+        if let Some(m) = method_of {
+            method = Some(m);
+            path = String::from_utf8_lossy(value).into_owned();
+        }
+    }
+    if let Some(method) = method {
+        rules.push(HttpRule {
+            method,
+            path,
+            body: string_field(rule, 7).unwrap_or_default(),
+            response_body: string_field(rule, 12).unwrap_or_default(),
+        });
+    }
+    for rule in additional {
+        decode_rule(rule, rules);
+    }
+}
+
+/// Split the segments by `/` outside of variables.
+fn split_segments(segments: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in segments.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '/' if depth == 0 => {
+                result.push(&segments[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&segments[start..]);
+    result
+}
+
+/// Convert a path template, e.g., `/v1/{name=shelves/*/books/*}`, to a route of `volo-http`, e.g.,
+/// `/v1/shelves/{p0}/books/{p1}` with `name` bound to `shelves/{p0}/books/{p1}`.
+pub(crate) fn parse_template(template: &str) -> Result<Route, String> {
+    let Some(segments) = template.strip_prefix('/') else {
+        return Err("the template should start with `/`".into());
+    };
+
+    let mut path = String::new();
+    let mut bindings = Vec::new();
+    let mut count = 0;
+    let mut catch_all = false;
+    let mut param = |wildcard: &str, path: &mut String| -> Result<String, String> {
+        if catch_all {
+            return Err("`**` should be the last segment".into());
+        }
+        let name = format!("p{count}");
+        count += 1;
+        match wildcard {
+            "*" => write!(path, "/{{{name}}}").unwrap(),
+            _ => {
+                catch_all = true;
+                write!(path, "/{{*{name}}}").unwrap()
+            }
+        }
+        Ok(name)
+    };
+
+    for segment in split_segments(segments) {
+        match segment {
+            "*" | "**" => {
+                param(segment, &mut path)?;
+            }
+            _ if segment.starts_with('{') => {
+                let Some(variable) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}'))
+                else {
+                    return Err("verbs after variables are not supported".into());
+                };
+                let (field, sub) = variable.split_once('=').unwrap_or((variable, "*"));
+                let mut pattern = Vec::new();
+                let mut params = Vec::new();
+                for sub in split_segments(sub) {
+                    match sub {
+                        "*" | "**" => {
+                            params.push(param(sub, &mut path)?);
+                            pattern.push("{}");
+                        }
+                        _ if sub.is_empty() || sub.contains(['{', '}', '*']) => {
+                            return Err(format!("invalid segment `{sub}`"));
+                        }
+                        _ => {
+                            write!(path, "/{sub}").unwrap();
+                            pattern.push(sub);
+                        }
+                    }
+                }
+                bindings.push(Binding {
+                    field: field.to_owned(),
+                    pattern: pattern.join("/"),
+                    params,
+                });
+            }
+            _ if segment.contains(['{', '}', '*']) => {
+                return Err(format!("invalid segment `{segment}`"));
+            }
+            _ => write!(path, "/{segment}").unwrap(),
+        }
+    }
+    if catch_all && !path.ends_with('}') {
+        return Err("`**` should be the last segment".into());
+    }
+    Ok(Route { path, bindings })
+}
+
+/// A handler of a binding to be mounted on the router.
+pub(crate) struct Handler {
+    pub rule: HttpRule,
+    pub route: Route,
+    pub method_name: String,
+    pub service_method: String,
+}
+
+/// Generate `{service}_http_router`, which mounts all handlers on a `volo-http` router.
+pub(crate) fn codegen_router(
+    service_name: &str,
+    router_name: &str,
+    file_descriptor: &str,
+    proto_service: &str,
+    handlers: &[Handler],
+) -> String {
+    // methods of the same path are routed by one `MethodRouter`
+    let mut routes: Vec<(&str, Vec<&Handler>)> = Vec::new();
+    for handler in handlers {
+        match routes
+            .iter_mut()
+            .find(|(path, _)| *path == handler.route.path)
+        {
+            Some((_, handlers)) => {
+                if handlers
+                    .iter()
+                    .any(|h| h.rule.method == handler.rule.method)
+                {
+                    println!(
+                        "cargo:warning=duplicated http rule `{} {}` of `{}`, skipped",
+                        handler.rule.method, handler.rule.path, handler.service_method
+                    );
+                    continue;
+                }
+                handlers.push(handler);
+            }
+            None => routes.push((&handler.route.path, vec![handler])),
+        }
+    }
+
+    let routes = routes
+        .into_iter()
+        .map(|(path, handlers)| {
+            let method_router = handlers
+                .iter()
+                .enumerate()
+                .map(|(i, handler)| {
+                    let handler_code = codegen_handler(service_name, proto_service, handler);
+                    let method = handler.rule.method;
+                    if i == 0 {
+                        format!("::volo_http::server::route::{method}({handler_code})")
+                    } else {
+                        format!(".{method}({handler_code})")
+                    }
+                })
+                .join("");
+            format!(".route(\"{path}\", {method_router})")
+        })
+        .join("\n");
+
+    format!(
+        r#"
+        pub fn {router_name}<S>(inner: S) -> ::volo_http::server::route::Router
+        where
+            S: {service_name} + ::core::marker::Send + ::core::marker::Sync + 'static,
+        {{
+            let inner = ::std::sync::Arc::new(inner);
+            let file = {file_descriptor}();
+            ::volo_http::server::route::Router::new()
+                {routes}
+        }}"#
+    )
+}
+
+fn codegen_handler(service_name: &str, proto_service: &str, handler: &Handler) -> String {
+    let HttpRule {
+        body,
+        response_body,
+        ..
+    } = &handler.rule;
+    let mut transcoder = format!(
+        "::volo_grpc::transcoding::Transcoder::new(file, \"{proto_service}\", \"{}\")",
+        handler.service_method
+    );
+    if !body.is_empty() {
+        write!(transcoder, ".body(\"{body}\")").unwrap();
+    }
+    if !response_body.is_empty() {
+        write!(transcoder, ".response_body(\"{response_body}\")").unwrap();
+    }
+
+    let bindings_len = handler.route.bindings.len();
+    let bindings = handler
+        .route
+        .bindings
+        .iter()
+        .map(|binding| {
+            let params = binding
+                .params
+                .iter()
+                .map(|param| format!(", param(\"{param}\")"))
+                .join("");
+            format!(
+                "(\"{}\", ::std::format!(\"{}\"{params}))",
+                binding.field, binding.pattern
+            )
+        })
+        .join(", ");
+    let method_name = &handler.method_name;
+
+    format!(
+        r#"{{
+            let inner = inner.clone();
+            let transcoder = ::std::sync::Arc::new({transcoder});
+            move |params: ::volo_http::server::param::PathParamsMap, req: ::volo_http::request::Request| async move {{
+                let param = |name: &str| {{
+                    params.get(name).map(|v| v.as_str()).unwrap_or_default().to_owned()
+                }};
+                let bindings: [(&str, ::std::string::String); {bindings_len}] = [{bindings}];
+                let (parts, body) = req.into_parts();
+                let body = match ::volo_http::body::BodyConversion::into_bytes(body).await {{
+                    ::std::result::Result::Ok(body) => body,
+                    ::std::result::Result::Err(err) => {{
+                        return ::volo_grpc::transcoding::error_response(
+                            &::volo_grpc::Status::invalid_argument(err.to_string()),
+                        );
+                    }}
+                }};
+                let req = ::volo_http::http::Request::from_parts(parts, body);
+                transcoder
+                    .call(&bindings, req, |req| {service_name}::{method_name}(&*inner, req))
+                    .await
+            }}
+        }}"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, buf: &mut Vec<u8>) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn field(number: u64, value: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        varint(number << 3 | 2, &mut buf);
+        varint(value.len() as u64, &mut buf);
+        buf.extend_from_slice(value);
+        buf
+    }
+
+    #[test]
+    fn test_http_rules() {
+        let additional = field(2, b"/v1/items:lookup");
+        let rule = [
+            field(2, b"/v1/{name=items/*}"),
+            field(11, &additional),
+            field(12, b"item"),
+        ]
+        .concat();
+        let get = [
+            field(1, b"GetItem"),
+            field(2, b".demo.GetItemRequest"),
+            field(4, &field(HTTP_EXTENSION, &rule)),
+        ]
+        .concat();
+        let create = [
+            field(1, b"CreateItem"),
+            field(
+                4,
+                &field(
+                    HTTP_EXTENSION,
+                    &[field(4, b"/v1/items"), field(7, b"*")].concat(),
+                ),
+            ),
+        ]
+        .concat();
+        let list = field(1, b"ListItems");
+        let service = [
+            field(1, b"Items"),
+            field(2, &get),
+            field(2, &create),
+            field(2, &list),
+        ]
+        .concat();
+        let file = [field(1, b"demo.proto"), field(6, &service)].concat();
+
+        assert!(http_rules(&file, "Other").is_empty());
+        let rules = http_rules(&file, "Items");
+        assert_eq!(
+            rules,
+            vec![
+                (
+                    "GetItem".to_owned(),
+                    vec![
+                        HttpRule {
+                            method: "get",
+                            path: "/v1/{name=items/*}".into(),
+                            body: String::new(),
+                            response_body: "item".into(),
+                        },
+                        HttpRule {
+                            method: "get",
+                            path: "/v1/items:lookup".into(),
+                            body: String::new(),
+                            response_body: String::new(),
+                        },
+                    ]
+                ),
+                (
+                    "CreateItem".to_owned(),
+                    vec![HttpRule {
+                        method: "post",
+                        path: "/v1/items".into(),
+                        body: "*".into(),
+                        response_body: String::new(),
+                    }]
+                ),
+            ]
+        );
+
+        // invalid descriptors
+        assert!(http_rules(&[0xff], "Items").is_empty());
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let binding = |field: &str, pattern: &str, params: &[&str]| Binding {
+            field: field.into(),
+            pattern: pattern.into(),
+            params: params.iter().map(|p| p.to_string()).collect(),
+        };
+
+        assert_eq!(
+            parse_template("/v1/items:lookup").unwrap(),
+            Route {
+                path: "/v1/items:lookup".into(),
+                bindings: vec![],
+            }
+        );
+        assert_eq!(
+            parse_template("/v1/{name}").unwrap(),
+            Route {
+                path: "/v1/{p0}".into(),
+                bindings: vec![binding("name", "{}", &["p0"])],
+            }
+        );
+        assert_eq!(
+            parse_template("/v1/{name=shelves/*/books/*}/*").unwrap(),
+            Route {
+                path: "/v1/shelves/{p0}/books/{p1}/{p2}".into(),
+                bindings: vec![binding("name", "shelves/{}/books/{}", &["p0", "p1"])],
+            }
+        );
+        assert_eq!(
+            parse_template("/v1/{shelf.id}/{book=**}").unwrap(),
+            Route {
+                path: "/v1/{p0}/{*p1}".into(),
+                bindings: vec![
+                    binding("shelf.id", "{}", &["p0"]),
+                    binding("book", "{}", &["p1"]),
+                ],
+            }
+        );
+
+        assert!(parse_template("v1/items").is_err());
+        assert!(parse_template("/v1/{name}:cancel").is_err());
+        assert!(parse_template("/v1/{name=**}/items").is_err());
+        assert!(parse_template("/v1/items*").is_err());
+    }
+}
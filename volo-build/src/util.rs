@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self, File, OpenOptions},
     io::{Read, Seek, Write},
     path::{Path, PathBuf},
@@ -8,12 +8,14 @@ use std::{
 };
 
 use anyhow::{Context, bail};
+use itertools::Itertools;
 use mockall_double::double;
 use pilota_build::{Symbol, middle::context::Mode};
 use serde::de::Error;
+use sha2::{Digest, Sha256};
 use volo::FastStr;
 
-use crate::model::{GitSource, Idl, IdlProtocol, Repo, Service, SingleConfig, Source};
+use crate::model::{GitSource, Idl, IdlProtocol, Repo, RepoKind, Service, SingleConfig, Source};
 
 pub static DEFAULT_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
     std::path::Path::new(
@@ -94,6 +96,17 @@ pub fn download_files_from_git(task: Task) -> anyhow::Result<()> {
 }
 
 pub fn download_repo(repo: &Repo, target_dir: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+    if repo.kind == RepoKind::Registry {
+        // the files are fetched on demand by `fetch_registry_idls`, and the directory is never
+        // changed since the lock is a part of it
+        let dir = target_dir
+            .as_ref()
+            .join(get_registry_path(&repo.url))
+            .join(repo.lock.as_str());
+        ensure_path(&dir)?;
+        return Ok(dir);
+    }
+
     let dir = target_dir.as_ref().join(get_git_path(repo.url.as_str())?);
 
     // check if the repo is already downloaded
@@ -188,6 +201,11 @@ pub fn get_git_path(git: &str) -> anyhow::Result<PathBuf> {
     }
 }
 
+fn get_registry_path(url: &str) -> PathBuf {
+    let url = url.split_once("://").map(|(_, url)| url).unwrap_or(url);
+    PathBuf::from(url.trim_matches('/').replace(':', "_"))
+}
+
 pub fn get_repo_name_by_url(git: &str) -> &str {
     // there may be two type of git here:
     // 1. username@domain:namespace/repo.git
@@ -382,9 +400,11 @@ pub fn init_git_repo(
     let r#ref = r#ref.as_deref().unwrap_or("HEAD");
     let lock = get_repo_latest_commit_id(git, r#ref)?;
     let new_repo = Repo {
+        kind: RepoKind::Git,
         url: FastStr::new(git),
         r#ref: FastStr::new(r#ref),
         lock: lock.into(),
+        hashes: Default::default(),
     };
     Ok((repo_name, new_repo))
 }
@@ -401,6 +421,299 @@ pub fn download_repos_to_target(
     Ok(repo_dir_map)
 }
 
+/// The max time of fetching a file from a registry, so that a stalled registry fails the build
+/// instead of hanging it.
+const REGISTRY_FETCH_TIMEOUT_SECS: u64 = 60;
+
+/// Fetch the IDL files of services from the registry repos, with their transitive includes, the
+/// files already fetched are not fetched again as the revision is pinned.
+///
+/// Each file must match the hash pinned in `hashes` of the repo, which are written by
+/// [`pin_registry_hashes`], otherwise the build fails and the mismatched file is removed.
+pub fn fetch_registry_idls(
+    services: &[Service],
+    repos: &HashMap<FastStr, Repo>,
+    repo_dir_map: &HashMap<FastStr, PathBuf>,
+) -> anyhow::Result<()> {
+    fetch_registry_files(services, repos, repo_dir_map, |_, repo, path, content| {
+        let key = registry_key(path);
+        let hash = content_hash(content);
+        match repo.hashes.get(key.as_str()) {
+            Some(pinned) if *pinned == hash => Ok(()),
+            Some(pinned) => bail!(
+                "hash of {key} from registry {} mismatched, pinned {pinned} but got {hash}",
+                repo.url
+            ),
+            None => bail!(
+                "hash of {key} from registry {} is not pinned, run `volo repo update` or add \
+                 `{key}: {hash}` to `hashes` of the repo",
+                repo.url
+            ),
+        }
+    })
+}
+
+/// Fetch the IDL files of the services from the registry `repo` like [`fetch_registry_idls`], and
+/// pin their hashes in `hashes` of the repo, the previous ones are replaced.
+pub fn pin_registry_hashes(
+    services: &[Service],
+    name: &FastStr,
+    repo: &mut Repo,
+) -> anyhow::Result<()> {
+    if repo.kind != RepoKind::Registry {
+        return Ok(());
+    }
+    let target_dir = tempfile::TempDir::new()?;
+    let dir = download_repo(repo, target_dir.path())?;
+    let repos = HashMap::from([(name.clone(), repo.clone())]);
+    let repo_dir_map = HashMap::from([(name.clone(), dir)]);
+    let mut hashes = BTreeMap::new();
+    fetch_registry_files(services, &repos, &repo_dir_map, |_, _, path, content| {
+        hashes.insert(registry_key(path).into(), content_hash(content).into());
+        Ok(())
+    })?;
+    repo.hashes = hashes;
+    Ok(())
+}
+
+/// Fetch the IDL files of services and their transitive includes from the registry repos, and
+/// check the content of each file by `check`, which is called for the files already fetched too.
+fn fetch_registry_files(
+    services: &[Service],
+    repos: &HashMap<FastStr, Repo>,
+    repo_dir_map: &HashMap<FastStr, PathBuf>,
+    mut check: impl FnMut(&FastStr, &Repo, &Path, &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for service in services {
+        let Source::Git(GitSource { repo: ref name }) = service.idl.source else {
+            continue;
+        };
+        let (Some(repo), Some(dir)) = (repos.get(name), repo_dir_map.get(name)) else {
+            continue;
+        };
+        if repo.kind != RepoKind::Registry {
+            continue;
+        }
+        let mut check = |path: &Path, content: &[u8]| check(name, repo, path, content);
+
+        let path = normalize_path(&strip_slash_prefix(&service.idl.path));
+        if !fetch_registry_file(repo, dir, &path, &mut check)? {
+            bail!(
+                "failed to fetch {} from registry {}",
+                path.display(),
+                repo.url
+            );
+        }
+        walk_idl_files(&dir.join(&path), |file, include| {
+            let file = file.strip_prefix(dir).unwrap_or(file);
+            let protocol = idl_protocol(file).unwrap_or(IdlProtocol::Thrift);
+            for candidate in include_candidates(file, include, &service.idl.includes, protocol) {
+                // the includes out of the registry are not fetched
+                if candidate.starts_with("..") {
+                    continue;
+                }
+                if fetch_registry_file(repo, dir, &candidate, &mut check)? {
+                    return Ok(Some(dir.join(candidate)));
+                }
+            }
+            bail!(
+                "failed to fetch {include} included by {} from registry {}",
+                file.display(),
+                repo.url
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// The path of the file in the registry, which is the key of its hash.
+fn registry_key(path: &Path) -> String {
+    path.iter().map(|s| s.to_string_lossy()).join("/")
+}
+
+fn content_hash(content: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content)))
+}
+
+/// Fetch the file at `{url}/{lock}/{path}` by `curl`, returns false if it's not found.
+///
+/// The content is checked by `check` before the file is kept, and a file fetched before is
+/// checked again and removed if it fails.
+fn fetch_registry_file(
+    repo: &Repo,
+    dir: &Path,
+    path: &Path,
+    check: &mut impl FnMut(&Path, &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<bool> {
+    let dest = dir.join(path);
+    if dest.exists() {
+        let content = fs::read(&dest)?;
+        if let Err(err) = check(path, &content) {
+            let _ = fs::remove_file(&dest);
+            return Err(err);
+        }
+        return Ok(true);
+    }
+    let url = format!(
+        "{}/{}/{}",
+        repo.url.trim_end_matches('/'),
+        repo.lock,
+        registry_key(path)
+    );
+    // download to a temporary file first, so a broken download is never left as the file
+    let tmp = dest.with_extension("download");
+    let status = Command::new("curl")
+        .arg("-fsL")
+        .arg("--max-time")
+        .arg(REGISTRY_FETCH_TIMEOUT_SECS.to_string())
+        .arg("--create-dirs")
+        .arg("-o")
+        .arg(&tmp)
+        .arg(&url)
+        .status()
+        .with_context(|| format!("run curl for {url}"))?;
+    if !status.success() {
+        let _ = fs::remove_file(&tmp);
+        // the exit code of curl for timeouts, which is not a missing file
+        if status.code() == Some(28) {
+            bail!("fetching {url} timed out after {REGISTRY_FETCH_TIMEOUT_SECS}s");
+        }
+        return Ok(false);
+    }
+    let content = fs::read(&tmp)?;
+    if let Err(err) = check(path, &content) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+    fs::rename(&tmp, &dest)?;
+    Ok(true)
+}
+
+pub(crate) fn idl_protocol(path: &Path) -> Option<IdlProtocol> {
+    match path.extension().and_then(|v| v.to_str()) {
+        Some("thrift") => Some(IdlProtocol::Thrift),
+        Some("proto") => Some(IdlProtocol::Protobuf),
+        _ => None,
+    }
+}
+
+/// The files included by the IDL, i.e., `include "..."` of thrift and `import "...";` of
+/// protobuf. The well-known types of protobuf are skipped since they are bundled by the parser.
+pub(crate) fn scan_includes(content: &str, protocol: IdlProtocol) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            let rest = match protocol {
+                IdlProtocol::Thrift => line.strip_prefix("include")?,
+                IdlProtocol::Protobuf => {
+                    let rest = line.strip_prefix("import")?.trim_start();
+                    rest.strip_prefix("public")
+                        .or_else(|| rest.strip_prefix("weak"))
+                        .unwrap_or(rest)
+                }
+            }
+            .trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let rest = &rest[1..];
+            rest.find(quote).map(|end| &rest[..end])
+        })
+        .filter(|include| {
+            protocol == IdlProtocol::Thrift || !include.starts_with("google/protobuf/")
+        })
+        .collect()
+}
+
+/// The paths where the include may be found in order, which are relative to the including file
+/// first for thrift, and relative to the include directories first for protobuf, the same as
+/// the parsers.
+fn include_candidates(
+    file: &Path,
+    include: &str,
+    include_dirs: &[PathBuf],
+    protocol: IdlProtocol,
+) -> Vec<PathBuf> {
+    let relative = file.parent().unwrap_or(Path::new("")).join(include);
+    let mut candidates = include_dirs
+        .iter()
+        .map(|dir| dir.join(include))
+        .collect::<Vec<_>>();
+    match protocol {
+        IdlProtocol::Thrift => candidates.insert(0, relative),
+        IdlProtocol::Protobuf => {
+            candidates.push(PathBuf::from(include));
+            candidates.push(relative);
+        }
+    }
+    candidates.iter().map(|path| normalize_path(path)).collect()
+}
+
+/// Remove the `.` and `..` in the path without accessing the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(std::path::Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Walk the IDL file and its transitive includes, where `resolve` returns the path of an include
+/// by the including file, or `None` to ignore it.
+pub(crate) fn walk_idl_files(
+    path: &Path,
+    mut resolve: impl FnMut(&Path, &str) -> anyhow::Result<Option<PathBuf>>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![normalize_path(path)];
+    while let Some(file) = stack.pop() {
+        if !seen.insert(file.clone()) {
+            continue;
+        }
+        if let Some(protocol) = idl_protocol(&file) {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("read idl file {}", file.display()))?;
+            for include in scan_includes(&content, protocol) {
+                if let Some(included) = resolve(&file, include)? {
+                    stack.push(normalize_path(&included));
+                }
+            }
+        }
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// Collect the IDL files of services and their transitive includes, sorted and deduplicated,
+/// the includes that are not found are ignored and left to the parser to report.
+pub(crate) fn collect_idl_files(services: &[ServiceBuilder]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for service in services {
+        files.extend(walk_idl_files(&service.path, |file, include| {
+            let protocol = idl_protocol(file).unwrap_or(IdlProtocol::Thrift);
+            Ok(
+                include_candidates(file, include, &service.includes, protocol)
+                    .into_iter()
+                    .find(|candidate| candidate.is_file()),
+            )
+        })?);
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
 pub fn get_idl_build_path_and_includes(
     idl: &Idl,
     repo_dir_map: &HashMap<FastStr, PathBuf>,
@@ -520,9 +833,11 @@ pub fn check_and_get_repo_name(
             else {
                 let lock = get_repo_latest_commit_id(git, &r#ref)?.into();
                 let _ = new_repo.insert(Repo {
+                    kind: RepoKind::Git,
                     url: git.clone().into(),
                     r#ref: r#ref.clone(),
                     lock,
+                    hashes: Default::default(),
                 });
             }
             key
@@ -569,9 +884,11 @@ pub fn check_and_get_repo_name(
                 // create a new repo by the git url
                 let lock = get_repo_latest_commit_id(git, &r#ref)?.into();
                 let _ = new_repo.insert(Repo {
+                    kind: RepoKind::Git,
                     url: git.clone().into(),
                     r#ref: r#ref.clone(),
                     lock,
+                    hashes: Default::default(),
                 });
                 name
             }
@@ -821,13 +1138,115 @@ mod tests {
         assert_eq!(paths, vec![selected_idl.path]);
     }
 
+    #[test]
+    fn test_scan_includes() {
+        let thrift = "include \"base.thrift\"\n  include 'common/types.thrift'\ncpp_include \
+                      \"x.h\"\nstruct Includes {}\n";
+        assert_eq!(
+            scan_includes(thrift, IdlProtocol::Thrift),
+            vec!["base.thrift", "common/types.thrift"]
+        );
+
+        let proto = "syntax = \"proto3\";\nimport \"a/b.proto\";\nimport public \
+                     \"c.proto\";\nimport \"google/protobuf/empty.proto\";\nmessage Imports {}\n";
+        assert_eq!(
+            scan_includes(proto, IdlProtocol::Protobuf),
+            vec!["a/b.proto", "c.proto"]
+        );
+    }
+
+    #[test]
+    fn test_fetch_registry_idls() {
+        // a local registry at `{url}/{lock}/{path}`
+        let registry = tempdir().unwrap();
+        let files = [
+            ("v1/api/service.thrift", "include \"../base/base.thrift\"\n"),
+            ("v1/base/base.thrift", "include \"types.thrift\"\n"),
+            ("v1/base/types.thrift", "struct Types {}\n"),
+            ("v1/api/unused.thrift", "struct Unused {}\n"),
+        ];
+        for (path, content) in files {
+            let path = registry.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let repo = Repo {
+            kind: RepoKind::Registry,
+            url: format!("file://{}", registry.path().display()).into(),
+            r#ref: "v1".into(),
+            lock: "v1".into(),
+            hashes: Default::default(),
+        };
+        let name = FastStr::from("registry");
+        let services = vec![create_git_service(
+            &name,
+            Path::new("api/service.thrift"),
+            &[],
+        )];
+        let target = tempdir().unwrap();
+
+        // the hashes must be pinned
+        let mut repos = HashMap::from([(name.clone(), repo)]);
+        let repo_dir_map = download_repos_to_target(&repos, target.path()).unwrap();
+        let err = fetch_registry_idls(&services, &repos, &repo_dir_map).unwrap_err();
+        assert!(err.to_string().contains("is not pinned"));
+        let dir = &repo_dir_map["registry"];
+        assert!(!dir.join("api/service.thrift").exists());
+
+        let repo = repos.get_mut(&name).unwrap();
+        pin_registry_hashes(&services, &name, repo).unwrap();
+        assert_eq!(
+            repo.hashes.keys().collect::<Vec<_>>(),
+            [
+                "api/service.thrift",
+                "base/base.thrift",
+                "base/types.thrift"
+            ]
+        );
+        assert!(repo.hashes["base/types.thrift"].starts_with("sha256:"));
+        fetch_registry_idls(&services, &repos, &repo_dir_map).unwrap();
+
+        assert!(dir.join("api/service.thrift").is_file());
+        assert!(dir.join("base/base.thrift").is_file());
+        assert!(dir.join("base/types.thrift").is_file());
+        assert!(!dir.join("api/unused.thrift").exists());
+
+        // the files fetched before are checked again
+        fs::write(dir.join("base/types.thrift"), "struct Changed {}\n").unwrap();
+        let err = fetch_registry_idls(&services, &repos, &repo_dir_map).unwrap_err();
+        assert!(err.to_string().contains("mismatched"));
+        assert!(!dir.join("base/types.thrift").exists());
+        fetch_registry_idls(&services, &repos, &repo_dir_map).unwrap();
+
+        // the changed files of the registry are rejected
+        fs::write(
+            registry.path().join("v1/api/service.thrift"),
+            "struct Changed {}\n",
+        )
+        .unwrap();
+        fs::remove_file(dir.join("api/service.thrift")).unwrap();
+        assert!(fetch_registry_idls(&services, &repos, &repo_dir_map).is_err());
+        assert!(!dir.join("api/service.thrift").exists());
+
+        // the missing includes are reported
+        let services = vec![create_git_service(
+            &"registry".into(),
+            Path::new("missing.thrift"),
+            &[],
+        )];
+        assert!(fetch_registry_idls(&services, &repos, &repo_dir_map).is_err());
+    }
+
     #[test]
     fn test_check_and_get_repo_name() {
         let mut repos = HashMap::new();
         let repo = Repo {
+            kind: RepoKind::Git,
             url: "https://domain/namespace/repo.git".into(),
             r#ref: "main".into(),
             lock: "123456".into(),
+            hashes: Default::default(),
         };
         repos.insert("test".into(), repo);
         let mut new_repo: Option<Repo> = None;
@@ -914,7 +1333,9 @@ mod tests {
         assert!(name_result.is_ok());
         assert_eq!(name_result.unwrap(), "new");
         assert!(new_repo.is_some());
-        let Repo { url, r#ref, lock } = new_repo.unwrap();
+        let Repo {
+            url, r#ref, lock, ..
+        } = new_repo.unwrap();
         assert_eq!(url, "https://domain/namespace/repo2.git");
         assert_eq!(r#ref, "main");
         assert_eq!(lock, "123456");
@@ -936,7 +1357,9 @@ mod tests {
         assert!(name_result.is_ok());
         assert_eq!(name_result.unwrap(), "repo2");
         assert!(new_repo.is_some());
-        let Repo { url, r#ref, lock } = new_repo.unwrap();
+        let Repo {
+            url, r#ref, lock, ..
+        } = new_repo.unwrap();
         assert_eq!(url, "https://domain/namespace/repo2.git");
         assert_eq!(r#ref, "main");
         assert_eq!(lock, "123456");
@@ -4,12 +4,17 @@ use pilota_build::{IdlService, Plugin};
 use volo::FastStr;
 
 use crate::{
+    hooks::PluginRegistry,
     model::WorkspaceConfig,
-    util::{ServiceBuilder, download_repos_to_target, get_idl_build_path_and_includes},
+    util::{
+        ServiceBuilder, download_repos_to_target, fetch_registry_idls,
+        get_idl_build_path_and_includes,
+    },
 };
 
 pub struct Builder<MkB, P> {
     pilota_builder: pilota_build::Builder<MkB, P>,
+    registry: PluginRegistry,
 }
 
 impl Builder<crate::thrift_backend::MkThriftBackend, crate::parser::ThriftParser> {
@@ -17,6 +22,7 @@ impl Builder<crate::thrift_backend::MkThriftBackend, crate::parser::ThriftParser
         Self {
             pilota_builder: pilota_build::Builder::thrift()
                 .with_backend(crate::thrift_backend::MkThriftBackend),
+            registry: PluginRegistry::default(),
         }
     }
 }
@@ -25,7 +31,8 @@ impl Builder<crate::grpc_backend::MkGrpcBackend, crate::parser::ProtobufParser>
     pub fn protobuf() -> Self {
         Self {
             pilota_builder: pilota_build::Builder::pb()
-                .with_backend(crate::grpc_backend::MkGrpcBackend),
+                .with_backend(crate::grpc_backend::MkGrpcBackend::default()),
+            registry: PluginRegistry::default(),
         }
     }
 }
@@ -70,6 +77,22 @@ where
             eprintln!("failed to download repos");
             std::process::exit(1);
         };
+        if let Err(e) = fetch_registry_idls(&config.services, &config.repos, &repo_dir_map) {
+            eprintln!("failed to fetch idls from registries, err: {e}");
+            std::process::exit(1);
+        }
+
+        match self.registry.make(&config.common_option.plugins) {
+            Ok(plugins) => {
+                for p in plugins {
+                    self = self.plugin(p);
+                }
+            }
+            Err(e) => {
+                eprintln!("failed to make plugins, err: {e:?}");
+                std::process::exit(1);
+            }
+        }
 
         let (idl_services, service_builders): (Vec<_>, Vec<_>) = config
             .services
@@ -134,6 +157,17 @@ where
         self
     }
 
+    /// Register the plugin by name, which is enabled by the `plugins` in `volo.workspace.yml`, see
+    /// [`crate::hooks`].
+    pub fn register_plugin<F, Pl>(mut self, name: impl Into<FastStr>, make: F) -> Self
+    where
+        F: Fn(&serde_yaml::Value) -> anyhow::Result<Pl> + 'static,
+        Pl: Plugin + 'static,
+    {
+        self.registry.register(name, make);
+        self
+    }
+
     pub fn ignore_unused(mut self, ignore_unused: bool) -> Self {
         self.pilota_builder = self.pilota_builder.ignore_unused(ignore_unused);
         self
@@ -0,0 +1,38 @@
+use std::{io, process::Command};
+
+use anyhow::bail;
+use clap::Parser;
+
+use crate::{command::CliCommand, context::Context};
+
+/// The binary of `volo-doctor`, which is a separate crate so that the cli does not depend on the
+/// runtime crates of volo.
+const DOCTOR_BIN: &str = "volo-doctor";
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "diagnose the connectivity to a thrift, grpc or http server by volo-doctor",
+    disable_help_flag = true
+)]
+pub struct Doctor {
+    #[arg(
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        help = "The arguments passed to volo-doctor, see `volo doctor --help`."
+    )]
+    pub args: Vec<String>,
+}
+
+impl CliCommand for Doctor {
+    fn run(&self, _cx: Context) -> anyhow::Result<()> {
+        match Command::new(DOCTOR_BIN).args(&self.args).status() {
+            Ok(status) if status.success() => Ok(()),
+            // the failure has been reported by volo-doctor
+            Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => bail!(
+                "{DOCTOR_BIN} is not found, please install it by `cargo install {DOCTOR_BIN}`"
+            ),
+            Err(err) => bail!("failed to run {DOCTOR_BIN}: {err}"),
+        }
+    }
+}
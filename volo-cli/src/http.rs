@@ -1,11 +1,11 @@
-use std::{fs::create_dir_all, process::Command};
+use std::{fs::create_dir_all, path::PathBuf, process::Command};
 
-use clap::Parser;
-use volo_build::util::git_repo_init;
+use clap::{Parser, value_parser};
+use volo_build::{openapi::OpenApiGenerator, util::git_repo_init};
 
 use crate::{command::CliCommand, context::Context};
 
-define_commands!(Subcommand { Init });
+define_commands!(Subcommand { Init, Idl });
 
 #[derive(Parser, Debug)]
 #[command(about = "manage your http project")]
@@ -68,3 +68,47 @@ impl CliCommand for Init {
         Ok(())
     }
 }
+
+#[derive(Parser, Debug)]
+#[command(about = "generate routes, types and client stubs from an OpenAPI 3 document")]
+pub struct Idl {
+    #[arg(
+        value_parser = value_parser!(PathBuf),
+        help = "Specify the path of the OpenAPI document in yaml or json.\nExample: openapi.yaml"
+    )]
+    pub path: PathBuf,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        help = "Specify the output file, defaults to 'src/api.rs'.",
+        default_value = "src/api.rs"
+    )]
+    pub output: PathBuf,
+
+    #[arg(
+        long = "no-server",
+        help = "Do not generate the `Api` trait and the router."
+    )]
+    pub no_server: bool,
+
+    #[arg(long = "no-client", help = "Do not generate the client stubs.")]
+    pub no_client: bool,
+}
+
+impl CliCommand for Idl {
+    fn run(&self, _: Context) -> anyhow::Result<()> {
+        OpenApiGenerator::new()
+            .server(!self.no_server)
+            .client(!self.no_client)
+            .write(&self.path, &self.output)?;
+
+        let _ = Command::new("rustfmt")
+            .arg("--edition")
+            .arg("2024")
+            .arg(&self.output)
+            .output();
+
+        Ok(())
+    }
+}
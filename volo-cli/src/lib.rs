@@ -6,6 +6,7 @@
 #[macro_use]
 mod command;
 pub mod context;
+mod doctor;
 mod http;
 mod idl;
 mod init;
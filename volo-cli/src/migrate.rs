@@ -6,7 +6,8 @@ use faststr::FastStr;
 use volo_build::{
     legacy::{self, util::open_config_file},
     model::{
-        CodegenOption, CommonOption, Entry, GitSource, Idl, IdlProtocol, Repo, Service, Source,
+        CodegenOption, CommonOption, Entry, GitSource, Idl, IdlProtocol, Repo, RepoKind, Service,
+        Source,
     },
     util::{DEFAULT_CONFIG_FILE, get_repo_name_by_url, git::get_repo_latest_commit_id},
 };
@@ -53,6 +54,8 @@ impl CliCommand for Migrate {
                             with_descriptor: false,
                             with_field_mask: false,
                             with_comments: false,
+                            json_transcoding: false,
+                            plugins: Vec::new(),
                         },
                     };
 
@@ -99,9 +102,11 @@ fn transfer_from_legacy(idls: &[legacy::model::Idl]) -> (HashMap<FastStr, Repo>,
                     .into();
                 let name = FastStr::new(get_repo_name_by_url(repo));
                 let repo = Repo {
+                    kind: RepoKind::Git,
                     url: repo.clone().into(),
                     r#ref,
                     lock,
+                    hashes: Default::default(),
                 };
                 repos.insert(name.clone(), repo);
                 Source::Git(GitSource { repo: name.clone() })
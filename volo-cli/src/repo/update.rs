@@ -1,5 +1,6 @@
 use clap::Parser;
 use faststr::FastStr;
+use volo_build::model::{Repo, Service};
 
 use crate::{command::CliCommand, context::Context};
 
@@ -34,6 +35,7 @@ impl CliCommand for Update {
                     if r.is_err() {
                         eprintln!("update git repo {k} failed");
                     }
+                    pin_hashes(&entry.services, k, v);
                 });
             }
 
@@ -41,11 +43,12 @@ impl CliCommand for Update {
             self.repos
                 .iter()
                 .for_each(|g| match entry.repos.get_mut(&FastStr::new(g)) {
-                    Some(r) => {
-                        let r = r.update();
+                    Some(repo) => {
+                        let r = repo.update();
                         if r.is_err() {
                             eprintln!("update git repo {g} failed");
                         }
+                        pin_hashes(&entry.services, &FastStr::new(g), repo);
                     }
                     None => {
                         eprintln!("git repo {g} not exists in config");
@@ -55,3 +58,10 @@ impl CliCommand for Update {
         })
     }
 }
+
+/// Pin the hashes of the files fetched from the registry repo, which are checked by the builds.
+fn pin_hashes(services: &[Service], name: &FastStr, repo: &mut Repo) {
+    if let Err(e) = volo_build::util::pin_registry_hashes(services, name, repo) {
+        eprintln!("pin the hashes of registry repo {name} failed, err: {e}");
+    }
+}
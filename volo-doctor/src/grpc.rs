@@ -0,0 +1,466 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt, stream};
+use itertools::Itertools;
+use pilota::{
+    FastStr, LinkedBytes,
+    pb::{
+        DecodeError, EncodeLengthContext, Message,
+        encoding::{DecodeContext, WireType, faststr, int32, message, skip_field},
+    },
+};
+use volo::{
+    client::MkClient,
+    net::tls::ClientTlsConfig,
+    service::{BoxCloneService, Service},
+};
+use volo_grpc::{
+    BoxStream, Code, RecvEntryMessage, RecvStream, Request, Response, SendEntryMessage, Status,
+    body::BoxBody,
+    client::ClientBuilder,
+    codec::{compression::CompressionEncoding, decode::Kind, encode::encode},
+    codegen::Frame,
+    context::ClientContext,
+};
+
+use super::report::Step;
+
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+const REFLECTION_PATHS: [&str; 2] = [
+    "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo",
+    "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo",
+];
+
+/// `grpc.health.v1.HealthCheckRequest`
+#[derive(Debug, Default, Clone)]
+struct HealthCheckRequest {
+    service: FastStr,
+}
+
+impl Message for HealthCheckRequest {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        if self.service.is_empty() {
+            0
+        } else {
+            faststr::encoded_len(ctx, 1, &self.service)
+        }
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        if !self.service.is_empty() {
+            faststr::encode(1, &self.service, buf);
+        }
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => faststr::merge(wire_type, &mut self.service, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.health.v1.HealthCheckResponse`
+#[derive(Debug, Default, Clone)]
+struct HealthCheckResponse {
+    status: i32,
+}
+
+impl HealthCheckResponse {
+    fn status_name(&self) -> &'static str {
+        match self.status {
+            0 => "UNKNOWN",
+            1 => "SERVING",
+            2 => "NOT_SERVING",
+            3 => "SERVICE_UNKNOWN",
+            _ => "UNRECOGNIZED",
+        }
+    }
+}
+
+impl Message for HealthCheckResponse {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        if self.status == 0 {
+            0
+        } else {
+            int32::encoded_len(ctx, 1, &self.status)
+        }
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        if self.status != 0 {
+            int32::encode(1, &self.status, buf);
+        }
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => int32::merge(wire_type, &mut self.status, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.reflection.v1.ServerReflectionRequest` with `list_services` only.
+#[derive(Debug, Default, Clone)]
+struct ListServicesRequest {
+    list_services: FastStr,
+}
+
+impl Message for ListServicesRequest {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        // a field of oneof is always encoded
+        faststr::encoded_len(ctx, 7, &self.list_services)
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        faststr::encode(7, &self.list_services, buf);
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            7 => faststr::merge(wire_type, &mut self.list_services, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.reflection.v1.ServerReflectionResponse` with `list_services_response` and
+/// `error_response` only, the other fields are skipped.
+#[derive(Debug, Default, Clone)]
+struct ListServicesResponse {
+    list_services_response: Option<ServiceNames>,
+    error_response: Option<ErrorResponse>,
+}
+
+impl Message for ListServicesResponse {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        self.list_services_response
+            .as_ref()
+            .map_or(0, |m| message::encoded_len(ctx, 6, m))
+            + self
+                .error_response
+                .as_ref()
+                .map_or(0, |m| message::encoded_len(ctx, 7, m))
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        if let Some(m) = &self.list_services_response {
+            message::encode(6, m, buf);
+        }
+        if let Some(m) = &self.error_response {
+            message::encode(7, m, buf);
+        }
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            6 => message::merge(
+                wire_type,
+                self.list_services_response.get_or_insert_default(),
+                buf,
+                ctx,
+            ),
+            7 => message::merge(
+                wire_type,
+                self.error_response.get_or_insert_default(),
+                buf,
+                ctx,
+            ),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.reflection.v1.ListServiceResponse`
+#[derive(Debug, Default, Clone)]
+struct ServiceNames {
+    service: Vec<ServiceName>,
+}
+
+impl Message for ServiceNames {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        message::encoded_len_repeated(ctx, 1, &self.service)
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        message::encode_repeated(1, &self.service, buf);
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => message::merge_repeated(wire_type, &mut self.service, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.reflection.v1.ServiceResponse`
+#[derive(Debug, Default, Clone)]
+struct ServiceName {
+    name: FastStr,
+}
+
+impl Message for ServiceName {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        faststr::encoded_len(ctx, 1, &self.name)
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        faststr::encode(1, &self.name, buf);
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => faststr::merge(wire_type, &mut self.name, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+/// `grpc.reflection.v1.ErrorResponse`
+#[derive(Debug, Default, Clone)]
+struct ErrorResponse {
+    error_code: i32,
+    error_message: FastStr,
+}
+
+impl Message for ErrorResponse {
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        int32::encoded_len(ctx, 1, &self.error_code)
+            + faststr::encoded_len(ctx, 2, &self.error_message)
+    }
+
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        int32::encode(1, &self.error_code, buf);
+        faststr::encode(2, &self.error_message, buf);
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        match tag {
+            1 => int32::merge(wire_type, &mut self.error_code, buf, ctx),
+            2 => faststr::merge(wire_type, &mut self.error_message, buf, ctx),
+            _ => skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+}
+
+enum ProbeRequest {
+    Health(BoxStream<'static, Result<HealthCheckRequest, Status>>),
+    Reflection(BoxStream<'static, Result<ListServicesRequest, Status>>),
+}
+
+impl SendEntryMessage for ProbeRequest {
+    fn into_body(
+        self,
+        compression_encoding: Option<CompressionEncoding>,
+    ) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+        match self {
+            Self::Health(s) => encode(s, compression_encoding),
+            Self::Reflection(s) => encode(s, compression_encoding),
+        }
+    }
+}
+
+enum ProbeResponse {
+    Health(RecvStream<HealthCheckResponse>),
+    Reflection(RecvStream<ListServicesResponse>),
+}
+
+impl RecvEntryMessage for ProbeResponse {
+    fn from_body(
+        method: Option<&str>,
+        body: BoxBody,
+        kind: Kind,
+        compression_encoding: Option<CompressionEncoding>,
+    ) -> Result<Self, Status> {
+        match method {
+            Some(HEALTH_CHECK_PATH) => Ok(Self::Health(RecvStream::new(
+                body,
+                kind,
+                compression_encoding,
+            ))),
+            Some(path) if REFLECTION_PATHS.contains(&path) => Ok(Self::Reflection(
+                RecvStream::new(body, kind, compression_encoding),
+            )),
+            _ => Err(Status::new(Code::Unimplemented, "Method not found.")),
+        }
+    }
+}
+
+struct MkProbeClient;
+
+impl<S> MkClient<volo_grpc::Client<S>> for MkProbeClient {
+    type Target = volo_grpc::Client<S>;
+
+    fn mk_client(&self, service: volo_grpc::Client<S>) -> Self::Target {
+        service
+    }
+}
+
+type ProbeClient = volo_grpc::Client<
+    BoxCloneService<ClientContext, Request<ProbeRequest>, Response<ProbeResponse>, Status>,
+>;
+
+/// A gRPC client of volo for probing the server.
+pub struct Prober {
+    client: ProbeClient,
+}
+
+impl Prober {
+    pub fn new(addr: SocketAddr, tls: Option<ClientTlsConfig>, dur: Duration) -> Self {
+        let mut builder = ClientBuilder::new(MkProbeClient, "volo-doctor")
+            .address(addr)
+            .connect_timeout(dur)
+            .rpc_timeout(Some(dur));
+        if let Some(tls) = tls {
+            builder = builder.tls_config(tls);
+        }
+        Self {
+            client: builder.build(),
+        }
+    }
+
+    /// Check the health by the standard health checking service.
+    ///
+    /// The server speaks gRPC as long as a status is replied, so the health checking service not
+    /// being registered is only a warning.
+    pub async fn health_check(&self, step: &mut Step, service: &str) -> Option<()> {
+        step.detail("path", HEALTH_CHECK_PATH);
+        if !service.is_empty() {
+            step.detail("service", service);
+        }
+
+        let req = HealthCheckRequest {
+            service: FastStr::new(service),
+        };
+        let req = Request::new(ProbeRequest::Health(
+            stream::once(async { Ok(req) }).boxed(),
+        ));
+        let mut cx = self.client.make_cx(HEALTH_CHECK_PATH);
+        let result = async {
+            let resp = self.client.call(&mut cx, req).await?;
+            let ProbeResponse::Health(mut resp) = resp.into_inner() else {
+                return Err(Status::internal("unexpected response"));
+            };
+            resp.try_next()
+                .await?
+                .ok_or_else(|| Status::internal("missing response message"))
+        }
+        .await;
+
+        match result {
+            Ok(resp) => {
+                step.detail("status", resp.status_name());
+                if resp.status != 1 {
+                    step.warn(format!("the service is {}", resp.status_name()));
+                }
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                step.warn("the health checking service is not registered");
+            }
+            Err(status) if status.code() == Code::NotFound => {
+                step.warn(format!("the service is not found: {}", status.message()));
+            }
+            Err(status) => {
+                step.fail(status);
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    /// List the services by the reflection service, `v1` is tried before `v1alpha`.
+    pub async fn list_services(&self, step: &mut Step) -> Option<()> {
+        for path in REFLECTION_PATHS {
+            let req = Request::new(ProbeRequest::Reflection(
+                stream::once(async { Ok(ListServicesRequest::default()) }).boxed(),
+            ));
+            let mut cx = self.client.make_cx(path);
+            let result = async {
+                let resp = self.client.call(&mut cx, req).await?;
+                let ProbeResponse::Reflection(mut resp) = resp.into_inner() else {
+                    return Err(Status::internal("unexpected response"));
+                };
+                resp.try_next()
+                    .await?
+                    .ok_or_else(|| Status::internal("missing response message"))
+            }
+            .await;
+
+            match result {
+                Ok(resp) => {
+                    step.detail("path", path);
+                    if let Some(err) = resp.error_response {
+                        step.warn(format!("error {}: {}", err.error_code, err.error_message));
+                    } else {
+                        let services = resp.list_services_response.unwrap_or_default();
+                        step.detail(
+                            "services",
+                            services.service.iter().map(|s| &s.name).join(", "),
+                        );
+                    }
+                    return Some(());
+                }
+                Err(status) if status.code() == Code::Unimplemented => continue,
+                Err(status) => {
+                    step.detail("path", path);
+                    step.fail(status);
+                    return None;
+                }
+            }
+        }
+        step.warn("the reflection service is not registered");
+        Some(())
+    }
+}
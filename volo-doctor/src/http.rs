@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use http::{Uri, header};
+use volo::net::tls::TlsConnector;
+use volo_http::{body::BodyConversion, client::Client};
+
+use super::report::Step;
+
+/// Send a `GET` request by the HTTP client of volo, which negotiates HTTP/2 by ALPN for https.
+///
+/// The server speaks HTTP as long as a response is received, so a status of client errors is
+/// only a warning.
+pub async fn request(
+    step: &mut Step,
+    uri: Uri,
+    tls: Option<TlsConnector>,
+    dur: Duration,
+) -> Option<()> {
+    step.detail("url", &uri);
+
+    let mut builder = Client::builder();
+    builder
+        .set_connect_timeout(dur)
+        .set_request_timeout(dur)
+        .user_agent(concat!("volo-doctor/", env!("CARGO_PKG_VERSION")));
+    if let Some(tls) = tls {
+        builder.set_tls_config(tls);
+    }
+    let client = match builder.build() {
+        Ok(client) => client,
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    };
+
+    let resp = match client.get(uri).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    };
+    let status = resp.status();
+    step.detail("status", status)
+        .detail("version", format!("{:?}", resp.version()));
+    for (key, name) in [
+        ("server", header::SERVER),
+        ("content_type", header::CONTENT_TYPE),
+    ] {
+        if let Some(value) = resp.headers().get(name) {
+            step.detail(key, String::from_utf8_lossy(value.as_bytes()));
+        }
+    }
+    match resp.into_body().into_bytes().await {
+        Ok(body) => {
+            step.detail("body_size", body.len());
+        }
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    }
+
+    if status.is_server_error() {
+        step.fail(format!("server error: {status}"));
+        return None;
+    }
+    if status.is_client_error() {
+        step.warn(format!("client error: {status}"));
+    }
+    Some(())
+}
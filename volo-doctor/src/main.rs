@@ -0,0 +1,266 @@
+//! `volo-doctor` diagnoses the connectivity to a thrift, grpc or http server step by step, i.e.,
+//! resolving, connecting, TLS handshake and a probe of the protocol by the client of volo.
+//!
+//! It's a separate crate so that `volo-cli` does not depend on the runtime crates, and is run as
+//! `volo doctor` once installed.
+
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use ::http::{Uri, uri::Authority};
+use anyhow::{anyhow, bail};
+use clap::{Parser, ValueEnum, value_parser};
+use volo::net::tls::{ClientTlsConfig, TlsConnector};
+
+use self::report::Report;
+
+mod grpc;
+mod http;
+mod net;
+mod report;
+mod thrift;
+
+const DEFAULT_THRIFT_METHOD: &str = "volo_doctor_probe";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Thrift,
+    Grpc,
+    Http,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Thrift => "thrift",
+            Self::Grpc => "grpc",
+            Self::Http => "http",
+        }
+    }
+
+    fn alpn(self) -> &'static [&'static str] {
+        match self {
+            Self::Thrift => &[],
+            Self::Grpc => &["h2"],
+            Self::Http => &["h2", "http/1.1"],
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "volo-doctor",
+    version,
+    about = "diagnose the connectivity to a thrift, grpc or http server"
+)]
+pub struct Doctor {
+    #[arg(
+        help = "The target to diagnose, in the format of \"host:port\", or an url for \
+                  http.\nExample: 127.0.0.1:8080 / https://example.com/health"
+    )]
+    pub target: String,
+    #[arg(
+        short = 'p',
+        long = "protocol",
+        value_enum,
+        help = "The protocol spoken by the target."
+    )]
+    pub protocol: Protocol,
+    #[arg(
+        long = "tls",
+        help = "Connect to the target by TLS, it's enabled by https urls for http."
+    )]
+    pub tls: bool,
+    #[arg(
+        long = "server-name",
+        help = "The server name for TLS, defaults to the host of the target."
+    )]
+    pub server_name: Option<String>,
+    #[arg(
+        long = "ca",
+        value_parser = value_parser!(PathBuf),
+        help = "Trust the CA certificates in the PEM file besides the default root certificates."
+    )]
+    pub ca: Option<PathBuf>,
+    #[arg(
+        long = "timeout",
+        default_value_t = 3000,
+        help = "The timeout of each step in milliseconds."
+    )]
+    pub timeout: u64,
+    #[arg(
+        long = "service",
+        default_value = "",
+        help = "The service to check health for grpc, defaults to the whole server, or the IDL \
+                service for routing requests of thrift, defaults to the default service."
+    )]
+    pub service: String,
+    #[arg(
+        long = "method",
+        default_value = DEFAULT_THRIFT_METHOD,
+        help = "The method without arguments to call for thrift, defaults to a method not \
+                defined, which is replied by an exception of unknown method."
+    )]
+    pub method: String,
+    #[arg(long = "json", help = "Print the report as json.")]
+    pub json: bool,
+}
+
+fn main() {
+    if let Err(err) = Doctor::parse().run() {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// The target parsed from the arguments.
+struct Target {
+    host: String,
+    port: u16,
+    tls: bool,
+    /// The url to request for http.
+    uri: Option<Uri>,
+}
+
+impl Target {
+    fn parse(target: &str, protocol: Protocol, tls: bool) -> anyhow::Result<Self> {
+        if protocol == Protocol::Http {
+            let uri = if target.contains("://") {
+                Uri::from_str(target)?
+            } else {
+                let scheme = if tls { "https" } else { "http" };
+                Uri::from_str(&format!("{scheme}://{target}/"))?
+            };
+            let tls = match uri.scheme_str() {
+                Some("https") => true,
+                Some("http") => tls,
+                _ => bail!("unsupported scheme of {target}"),
+            };
+            let authority = uri
+                .authority()
+                .ok_or_else(|| anyhow!("missing host of {target}"))?;
+            let port = authority.port_u16().unwrap_or(if tls { 443 } else { 80 });
+            return Ok(Self {
+                host: authority.host().to_owned(),
+                port,
+                tls,
+                uri: Some(uri),
+            });
+        }
+
+        let authority = Authority::from_str(target)?;
+        let port = authority
+            .port_u16()
+            .ok_or_else(|| anyhow!("missing port of {target}"))?;
+        Ok(Self {
+            host: authority.host().to_owned(),
+            port,
+            tls,
+            uri: None,
+        })
+    }
+}
+
+impl Doctor {
+    fn run(&self) -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let report = runtime.block_on(self.diagnose())?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", report.to_text());
+        }
+        if report.failed() {
+            bail!("failed to diagnose {}", self.target);
+        }
+        Ok(())
+    }
+
+    fn tls_connector(&self) -> anyhow::Result<TlsConnector> {
+        let mut builder =
+            TlsConnector::builder().with_alpn_protocols(self.protocol.alpn().iter().copied());
+        if let Some(ca) = &self.ca {
+            builder = builder.add_pem_from_file(ca)?;
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Diagnose the target step by step, each step is skipped if a previous one is failed.
+    ///
+    /// The resolving, connecting and TLS handshake are checked by the network utilities of volo
+    /// first, then the protocol is checked by the real client of volo for the protocol.
+    async fn diagnose(&self) -> anyhow::Result<Report> {
+        let target = Target::parse(&self.target, self.protocol, self.tls)?;
+        let dur = Duration::from_millis(self.timeout);
+        let connector = if target.tls {
+            Some(self.tls_connector()?)
+        } else {
+            None
+        };
+        let server_name = self
+            .server_name
+            .clone()
+            .unwrap_or_else(|| target.host.trim_matches(['[', ']']).to_owned());
+
+        let mut report = Report::new(self.target.clone(), self.protocol.name());
+        let addrs = report
+            .run("resolve", async |step| {
+                net::resolve(step, &target.host, target.port, dur).await
+            })
+            .await;
+        let conn = report
+            .run("connect", async |step| {
+                net::connect(step, addrs?, dur).await
+            })
+            .await;
+        let peer = match &connector {
+            Some(connector) => {
+                report
+                    .run("tls handshake", async |step| {
+                        let (stream, peer) = conn?;
+                        net::handshake(step, connector, &server_name, stream, dur).await?;
+                        Some(peer)
+                    })
+                    .await
+            }
+            None => conn.map(|(_, peer)| peer),
+        };
+        let tls_config = connector
+            .clone()
+            .map(|connector| ClientTlsConfig::new(server_name.clone(), connector));
+
+        match self.protocol {
+            Protocol::Thrift => {
+                report
+                    .run("thrift call", async |step| {
+                        thrift::call(step, peer?, &self.method, &self.service, tls_config, dur)
+                            .await
+                    })
+                    .await;
+            }
+            Protocol::Grpc => {
+                let prober = peer.map(|peer| grpc::Prober::new(peer, tls_config, dur));
+                report
+                    .run("grpc health check", async |step| {
+                        prober.as_ref()?.health_check(step, &self.service).await
+                    })
+                    .await;
+                report
+                    .run("grpc reflection", async |step| {
+                        prober.as_ref()?.list_services(step).await
+                    })
+                    .await;
+            }
+            Protocol::Http => {
+                report
+                    .run("http request", async |step| {
+                        http::request(step, target.uri.clone()?, connector, dur).await
+                    })
+                    .await;
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use itertools::Itertools;
+use tokio::{net::TcpStream, time::timeout};
+use volo::net::{
+    conn::ConnStream,
+    dial::{Config, Dialer, GaiResolver, Resolve},
+    tls::TlsConnector,
+};
+
+use super::report::Step;
+
+/// Resolve the host by the resolver of volo clients.
+pub async fn resolve(
+    step: &mut Step,
+    host: &str,
+    port: u16,
+    dur: Duration,
+) -> Option<Vec<SocketAddr>> {
+    let ip = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse::<IpAddr>();
+    let addrs = match ip {
+        Ok(ip) => {
+            step.detail("resolver", "none (ip address)");
+            vec![SocketAddr::new(ip, port)]
+        }
+        Err(_) => {
+            step.detail("resolver", "system");
+            match timeout(dur, GaiResolver.resolve(host, port)).await {
+                Ok(Ok(addrs)) if !addrs.is_empty() => addrs,
+                Ok(Ok(_)) => {
+                    step.fail(format!("no address of {host}"));
+                    return None;
+                }
+                Ok(Err(err)) => {
+                    step.fail(err);
+                    return None;
+                }
+                Err(_) => {
+                    step.fail(format!("timed out after {dur:?}"));
+                    return None;
+                }
+            }
+        }
+    };
+    step.detail("addresses", addrs.iter().join(", "));
+    Some(addrs)
+}
+
+/// Connect to any of the addresses by the dialer of volo clients, returns the stream and its
+/// peer address.
+pub async fn connect(
+    step: &mut Step,
+    addrs: Vec<SocketAddr>,
+    dur: Duration,
+) -> Option<(TcpStream, SocketAddr)> {
+    let dialer = Dialer::new(Config::default().with_connect_timeout(Some(dur)));
+    let stream = match dialer.dial_addrs(addrs).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    };
+    let peer = match stream.peer_addr() {
+        Ok(peer) => peer,
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    };
+    step.detail("peer", peer);
+    if let Ok(local) = stream.local_addr() {
+        step.detail("local", local);
+    }
+    Some((stream, peer))
+}
+
+/// Do the TLS handshake on the connected stream by the connector of volo clients.
+pub async fn handshake(
+    step: &mut Step,
+    connector: &TlsConnector,
+    server_name: &str,
+    stream: TcpStream,
+    dur: Duration,
+) -> Option<()> {
+    step.detail("server_name", server_name);
+    let stream = match timeout(dur, connector.connect(server_name, stream)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(err)) => {
+            step.fail(err);
+            return None;
+        }
+        Err(_) => {
+            step.fail(format!("timed out after {dur:?}"));
+            return None;
+        }
+    };
+    if let ConnStream::Tls(stream) = &stream {
+        let alpn = stream
+            .negotiated_alpn()
+            .map(|alpn| String::from_utf8_lossy(&alpn).into_owned())
+            .unwrap_or_else(|| "none".to_owned());
+        step.detail("alpn", alpn);
+    }
+    Some(())
+}
@@ -0,0 +1,172 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use serde::{Serialize, Serializer};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// The step is passed, but the result is not as expected, e.g., the health checking service
+    /// is not registered.
+    Warn,
+    Failed,
+    /// The step is not run because a previous step is failed.
+    Skipped,
+}
+
+impl Status {
+    fn label(self) -> colored::ColoredString {
+        match self {
+            Self::Ok => "ok".green(),
+            Self::Warn => "warn".yellow(),
+            Self::Failed => "failed".red(),
+            Self::Skipped => "skipped".dimmed(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Step {
+    pub name: &'static str,
+    pub status: Status,
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+    #[serde(serialize_with = "serialize_details")]
+    pub details: Vec<(&'static str, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Step {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            elapsed: Duration::ZERO,
+            details: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn detail(&mut self, key: &'static str, value: impl ToString) -> &mut Self {
+        self.details.push((key, value.to_string()));
+        self
+    }
+
+    pub fn warn(&mut self, err: impl ToString) -> &mut Self {
+        self.status = Status::Warn;
+        self.error = Some(err.to_string());
+        self
+    }
+
+    pub fn fail(&mut self, err: impl ToString) -> &mut Self {
+        self.status = Status::Failed;
+        self.error = Some(err.to_string());
+        self
+    }
+}
+
+/// The structured result of `volo doctor`, printed as text or JSON.
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub target: String,
+    pub protocol: &'static str,
+    pub steps: Vec<Step>,
+}
+
+impl Report {
+    pub fn new(target: String, protocol: &'static str) -> Self {
+        Self {
+            target,
+            protocol,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.steps.iter().any(|s| s.status == Status::Failed)
+    }
+
+    /// Whether the next step can run, i.e., no step is failed or skipped.
+    pub fn can_continue(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|s| matches!(s.status, Status::Ok | Status::Warn))
+    }
+
+    /// Run and time the step, or skip it if a previous step is failed or skipped.
+    ///
+    /// The output of the step is returned if it is not skipped and not failed.
+    pub async fn run<T>(
+        &mut self,
+        name: &'static str,
+        f: impl AsyncFnOnce(&mut Step) -> Option<T>,
+    ) -> Option<T> {
+        let mut step = Step::new(name);
+        if !self.can_continue() {
+            step.status = Status::Skipped;
+            self.steps.push(step);
+            return None;
+        }
+
+        let start = Instant::now();
+        let output = f(&mut step).await;
+        step.elapsed = start.elapsed();
+        if output.is_none() && step.status == Status::Ok {
+            step.status = Status::Failed;
+        }
+        let failed = step.status == Status::Failed;
+        self.steps.push(step);
+        if failed { None } else { output }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} {} ({})",
+            "target:".bold(),
+            self.target,
+            self.protocol
+        );
+        for step in &self.steps {
+            let _ = write!(out, "  [{}] {}", step.status.label(), step.name.bold());
+            if step.status != Status::Skipped {
+                let _ = write!(out, " {:.2}ms", millis(step.elapsed));
+            }
+            out.push('\n');
+            for (key, value) in &step.details {
+                let _ = writeln!(out, "      {key}: {value}");
+            }
+            if let Some(err) = &step.error {
+                let _ = writeln!(out, "      {}: {err}", "error".red());
+            }
+        }
+        let result = if self.failed() {
+            "failed".red()
+        } else {
+            "passed".green()
+        };
+        let _ = writeln!(out, "{} {result}", "result:".bold());
+        out
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn serialize_millis<S: Serializer>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64((millis(*d) * 1000.0).round() / 1000.0)
+}
+
+fn serialize_details<S: Serializer>(
+    details: &[(&'static str, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(details.iter().map(|(k, v)| (k, v)))
+}
@@ -0,0 +1,159 @@
+use std::{net::SocketAddr, time::Duration};
+
+use pilota::{
+    FastStr,
+    thrift::{
+        ApplicationExceptionKind, TAsyncInputProtocol, TInputProtocol, TLengthProtocol,
+        TMessageIdentifier, TOutputProtocol, TStructIdentifier, TType, ThriftException,
+    },
+};
+use tokio::time::timeout;
+use volo::{
+    client::MkClient,
+    net::{
+        dial,
+        tls::{ClientTlsConfig, TlsMakeTransport},
+    },
+    service::Service,
+};
+use volo_thrift::{ClientError, EntryMessage, client::ClientBuilder, context::ThriftContext};
+
+use super::report::Step;
+
+/// The arguments of the probing call, which is an empty struct, so any method without arguments
+/// can be called.
+#[derive(Clone, Debug)]
+struct ProbeArgs;
+
+/// The result of the probing call, its fields are skipped.
+#[derive(Clone, Debug)]
+struct ProbeResult;
+
+const EMPTY_STRUCT: TStructIdentifier = TStructIdentifier { name: "" };
+
+impl EntryMessage for ProbeArgs {
+    fn encode<T: TOutputProtocol>(&self, protocol: &mut T) -> Result<(), ThriftException> {
+        protocol.write_struct_begin(&EMPTY_STRUCT)?;
+        protocol.write_field_stop()?;
+        protocol.write_struct_end()?;
+        Ok(())
+    }
+
+    fn decode<T: TInputProtocol>(
+        protocol: &mut T,
+        _: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.skip(TType::Struct)?;
+        Ok(Self)
+    }
+
+    async fn decode_async<T: TAsyncInputProtocol>(
+        protocol: &mut T,
+        _: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.skip(TType::Struct).await?;
+        Ok(Self)
+    }
+
+    fn size<T: TLengthProtocol>(&self, protocol: &mut T) -> usize {
+        protocol.struct_begin_len(&EMPTY_STRUCT)
+            + protocol.field_stop_len()
+            + protocol.struct_end_len()
+    }
+}
+
+impl EntryMessage for ProbeResult {
+    fn encode<T: TOutputProtocol>(&self, protocol: &mut T) -> Result<(), ThriftException> {
+        ProbeArgs.encode(protocol)
+    }
+
+    fn decode<T: TInputProtocol>(
+        protocol: &mut T,
+        _: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.skip(TType::Struct)?;
+        Ok(Self)
+    }
+
+    async fn decode_async<T: TAsyncInputProtocol>(
+        protocol: &mut T,
+        _: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.skip(TType::Struct).await?;
+        Ok(Self)
+    }
+
+    fn size<T: TLengthProtocol>(&self, protocol: &mut T) -> usize {
+        ProbeArgs.size(protocol)
+    }
+}
+
+struct MkProbeClient;
+
+impl<S> MkClient<volo_thrift::Client<S>> for MkProbeClient {
+    type Target = volo_thrift::Client<S>;
+
+    fn mk_client(&self, service: volo_thrift::Client<S>) -> Self::Target {
+        service
+    }
+}
+
+/// Call the method by the thrift client of volo with the default codec, i.e., TTHeader and framed
+/// binary protocol.
+///
+/// The server speaks thrift as long as it replies, so an unknown method is not a failure.
+pub async fn call(
+    step: &mut Step,
+    addr: SocketAddr,
+    method: &str,
+    service: &str,
+    tls: Option<ClientTlsConfig>,
+    dur: Duration,
+) -> Option<()> {
+    step.detail("method", method)
+        .detail("codec", "ttheader, framed, binary");
+
+    let builder = ClientBuilder::<_, _, _, ProbeArgs, ProbeResult, _, _, _>::new(
+        "volo-doctor",
+        MkProbeClient,
+    )
+    .address(addr)
+    .connect_timeout(Some(dur));
+    let client = match tls {
+        Some(tls) => builder
+            .make_transport(TlsMakeTransport::new(dial::Config::default(), tls))
+            .build(),
+        None => builder.build(),
+    };
+
+    // the timeout is not set as the rpc timeout, whose error is not distinguishable from the
+    // exceptions replied by the server
+    let mut cx = client.make_cx(method, false);
+    if !service.is_empty() {
+        step.detail("service", service);
+        cx.set_idl_service_name(FastStr::new(service));
+    }
+    let Ok(result) = timeout(dur, client.call(&mut cx, ProbeArgs)).await else {
+        step.fail(format!("timed out after {dur:?}"));
+        return None;
+    };
+    match result {
+        Ok(_) => {
+            step.detail("reply", "success");
+        }
+        Err(ClientError::Application(err))
+            if err.kind() == ApplicationExceptionKind::UNKNOWN_METHOD =>
+        {
+            step.detail("reply", format!("unknown method: {}", err.message()));
+        }
+        Err(err @ (ClientError::Application(_) | ClientError::Biz(_))) => {
+            step.detail("reply", "exception");
+            step.warn(err);
+        }
+        Err(err) => {
+            step.fail(err);
+            return None;
+        }
+    }
+    Some(())
+}
@@ -19,6 +19,10 @@ use volo::{
     discovery::{Change, Discover, Instance},
     loadbalance::error::LoadBalanceError,
     net::Address,
+    util::{
+        time::Timestamp,
+        timing::{self, Phase},
+    },
 };
 
 // Error message constants
@@ -45,7 +49,10 @@ impl DnsResolver {
 
     /// Resolve a host to an IP address.
     pub async fn resolve(&self, host: &str) -> Option<IpAddr> {
-        self.resolver.lookup_ip(host).await.ok()?.into_iter().next()
+        let start = Timestamp::now();
+        let ip = self.resolver.lookup_ip(host).await.ok()?.into_iter().next();
+        timing::record(Phase::Resolve, start, Timestamp::now());
+        ip
     }
 }
 
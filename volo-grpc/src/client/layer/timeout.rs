@@ -9,10 +9,23 @@ use metainfo::METAINFO;
 use motore::{Service, layer::Layer};
 use pin_project::pin_project;
 use tokio::time::{self, Sleep};
+use volo::{
+    context::Context as _,
+    timeout::{CallDeadline, TimeoutError},
+};
 
-use crate::{Request, context::ClientContext, metadata::MetadataValue, status::Status};
+use crate::{
+    Request,
+    context::{ClientContext, Deadline},
+    metadata::MetadataValue,
+    status::Status,
+};
 
 /// Timeout middleware that enforces deadlines from ClientContext.
+///
+/// It applies the rpc timeout, i.e., the total timeout of a call including retries, which is also
+/// limited by the deadline from metainfo, and the request timeout of each attempt is applied by
+/// [`RequestTimeoutLayer`](volo::timeout::RequestTimeoutLayer) inside the load balancer.
 #[derive(Debug, Clone)]
 pub struct Timeout<S> {
     inner: S,
@@ -56,7 +69,12 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let config_timeout = cx.rpc_info.config().rpc_timeout();
 
-        let mi_timeout = METAINFO.with(|m| m.borrow().get::<Duration>().cloned());
+        let mi_timeout = METAINFO.with(|m| {
+            let m = m.borrow();
+            m.get::<Deadline>()
+                .map(Deadline::remaining)
+                .or_else(|| m.get::<Duration>().cloned())
+        });
 
         // get the shorter timeout
         let timeout_duration = match (config_timeout, mi_timeout) {
@@ -66,7 +84,12 @@ where
         };
 
         if let Some(timeout) = timeout_duration {
-            let header_val = duration_to_grpc_timeout(timeout);
+            // the server only handles one attempt
+            let attempt_timeout = match cx.rpc_info.config().request_timeout() {
+                Some(request_timeout) => timeout.min(request_timeout),
+                None => timeout,
+            };
+            let header_val = duration_to_grpc_timeout(attempt_timeout);
             // Convert to gRPC metadata value and add to outgoing request with header
             if let Ok(meta_val) = MetadataValue::from_str(&header_val) {
                 req.metadata_mut()
@@ -76,14 +99,23 @@ where
             }
         }
 
+        let deadline = timeout_duration.map(CallDeadline::new);
+        if let Some(deadline) = deadline {
+            cx.extensions_mut().insert(deadline);
+        }
         let sleep = timeout_duration.map(time::sleep);
         let inner = self.inner.call(cx, req);
 
-        ResponseFuture {
+        let res = ResponseFuture {
             inner,
             sleep: sleep.map(OptionPin::Some).unwrap_or(OptionPin::None),
+            error: deadline.map(|deadline| deadline.error()),
+        }
+        .await;
+        if deadline.is_some() {
+            cx.extensions_mut().remove::<CallDeadline>();
         }
-        .await
+        res
     }
 }
 
@@ -139,6 +171,7 @@ pub struct ResponseFuture<F> {
     inner: F,
     #[pin]
     sleep: OptionPin<Sleep>,
+    error: Option<TimeoutError>,
 }
 
 #[pin_project(project = OptionPinProj)]
@@ -162,7 +195,10 @@ where
 
         if let OptionPinProj::Some(sleep) = this.sleep.project() {
             futures_util::ready!(sleep.poll(cx));
-            let err = Status::deadline_exceeded("timeout");
+            let err = match this.error {
+                Some(err) => Status::from(*err),
+                None => Status::deadline_exceeded("timeout"),
+            };
             return Poll::Ready(Err(err));
         }
 
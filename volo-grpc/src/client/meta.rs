@@ -10,6 +10,7 @@ use crate::{
         DESTINATION_METHOD, DESTINATION_SERVICE, HEADER_TRANS_REMOTE_ADDR, KeyAndValueRef,
         MetadataKey, SOURCE_SERVICE,
     },
+    response::RemoteAddr,
 };
 
 #[derive(Clone)]
@@ -111,6 +112,11 @@ where
             Ok::<(), Status>(())
         });
 
+        // the callee address is updated above if the response comes through a proxy
+        if let Some(addr) = cx.rpc_info.callee().address() {
+            volo_resp.extensions_mut().insert(RemoteAddr(addr));
+        }
+
         Ok(volo_resp)
     }
 }
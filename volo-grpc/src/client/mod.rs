@@ -8,6 +8,9 @@
 mod callopt;
 pub mod dns;
 mod meta;
+#[cfg(feature = "replay")]
+pub mod replay;
+pub mod stream;
 
 use std::{cell::RefCell, marker::PhantomData, sync::Arc, time::Duration};
 
@@ -23,17 +26,27 @@ use volo::{
     client::{MkClient, WithOptService},
     context::{Endpoint, Role, RpcInfo},
     discovery::Discover,
-    loadbalance::{MkLbLayer, random::WeightedRandomBalance},
-    net::Address,
+    loadbalance::{
+        MkLbLayer,
+        random::WeightedRandomBalance,
+        warmup::{Warmup, WarmupHandle, WarmupReport},
+    },
+    net::{
+        Address,
+        memory::MemoryConnector,
+        proxy::{HttpConnectProxy, Proxy, Socks5Proxy},
+    },
+    timeout::{RequestTimeoutLayer, RequestTimeoutService, TimeoutError},
+    util::timing,
 };
 
 use self::{dns::DnsResolver, layer::timeout::TimeoutLayer};
 use crate::{
     Request, Response, Status,
-    codec::compression::CompressionEncoding,
+    codec::{compression::CompressionEncoding, transform::MessageTransform},
     context::{ClientContext, Config},
     layer::loadbalance::LbConfig,
-    transport::ClientTransport,
+    transport::{ClientTransport, Connector, WarmPool, dial_config},
 };
 pub mod layer;
 
@@ -43,12 +56,20 @@ pub struct ClientBuilder<IL, OL, C, LB, T, U> {
     rpc_config: Config,
     callee_name: FastStr,
     caller_name: FastStr,
+    path_prefix: Option<FastStr>,
     // Maybe address use Arc avoid memory alloc.
     target: Option<Address>,
+    proxy: Option<Proxy>,
+    memory: Option<MemoryConnector>,
+    warm_pool: Option<WarmPool>,
+    #[cfg(feature = "replay")]
+    replay: Option<replay::ReplayMode>,
     inner_layer: IL,
     outer_layer: OL,
     mk_client: C,
     mk_lb: LB,
+    // whether domain names are resolved when dialing instead of by the default discover
+    resolve_on_dial: bool,
     _marker: PhantomData<fn(T, U)>,
 
     #[cfg(feature = "__tls")]
@@ -72,11 +93,18 @@ impl<C, T, U>
             rpc_config: Default::default(),
             callee_name: FastStr::new(service_name),
             caller_name: "".into(),
+            path_prefix: None,
             target: None,
+            proxy: None,
+            memory: None,
+            warm_pool: None,
+            #[cfg(feature = "replay")]
+            replay: None,
             inner_layer: Identity::new(),
             outer_layer: Identity::new(),
             mk_client: service_client,
             mk_lb: LbConfig::new(WeightedRandomBalance::new(), DnsResolver::default()),
+            resolve_on_dial: true,
             _marker: PhantomData,
 
             #[cfg(feature = "__tls")]
@@ -95,11 +123,18 @@ impl<IL, OL, C, LB, T, U, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>, T,
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: self.inner_layer,
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb.load_balance(load_balance),
+            resolve_on_dial: self.resolve_on_dial,
             _marker: PhantomData,
 
             #[cfg(feature = "__tls")]
@@ -107,6 +142,14 @@ impl<IL, OL, C, LB, T, U, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>, T,
         }
     }
 
+    /// Sets the service discovery of the client.
+    ///
+    /// By default, connections to callees with domain names are established by resolving them to
+    /// both IPv4 and IPv6 addresses when dialing, and dialing them by the Happy Eyeballs
+    /// algorithm, see [`Dialer`]. After setting the discovery, connections are established to
+    /// the instances picked by the load balancer.
+    ///
+    /// [`Dialer`]: volo::net::dial::Dialer
     pub fn discover<NDISC>(
         self,
         discover: NDISC,
@@ -116,17 +159,35 @@ impl<IL, OL, C, LB, T, U, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>, T,
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: self.inner_layer,
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb.discover(discover),
+            resolve_on_dial: false,
             _marker: PhantomData,
 
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
     }
+
+    /// Sets a [`DrainPolicy`] deciding when connections to instances removed by the discovery
+    /// are drained.
+    ///
+    /// Default is draining immediately.
+    ///
+    /// [`DrainPolicy`]: volo::loadbalance::drain::DrainPolicy
+    pub fn drain_policy<P: volo::loadbalance::drain::DrainPolicy>(mut self, policy: P) -> Self {
+        self.mk_lb = self.mk_lb.drain_policy(policy);
+        self
+    }
 }
 
 impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
@@ -139,6 +200,15 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self.rpc_config.set_rpc_timeout(timeout);
         self
     }
+
+    /// Sets the request timeout for the client, which is the timeout of each attempt of a call,
+    /// so a retry will be made by the load balancer if an attempt is timeout.
+    ///
+    /// There is no request timeout by default, see [`volo::timeout`] for all kinds of timeouts.
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.rpc_config.set_request_timeout(timeout);
+        self
+    }
     /// Sets the `SETTINGS_INITIAL_WINDOW_SIZE` option for HTTP2
     /// stream-level flow control.
     ///
@@ -274,6 +344,97 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self
     }
 
+    /// Sets the SOCKS5 proxy for the client.
+    ///
+    /// All connections will be established through the proxy except hosts in its no proxy list.
+    /// The proxy from environment variable `ALL_PROXY` can be created by [`Socks5Proxy::env`].
+    ///
+    /// Note that only one proxy takes effect, this overrides [`ClientBuilder::http_connect_proxy`].
+    ///
+    /// Default is no proxy.
+    pub fn socks5_proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.proxy = Some(Proxy::Socks5(proxy));
+        self
+    }
+
+    /// Sets the HTTP proxy for the client, the HTTP/2 connection will be established through a
+    /// tunnel created by `CONNECT` method.
+    ///
+    /// All connections will be established through the proxy except hosts in its no proxy list.
+    /// The proxy from environment variable `HTTPS_PROXY` can be created by
+    /// [`HttpConnectProxy::env`].
+    ///
+    /// Note that only one proxy takes effect, this overrides [`ClientBuilder::socks5_proxy`].
+    ///
+    /// Default is no proxy.
+    pub fn http_connect_proxy(mut self, proxy: HttpConnectProxy) -> Self {
+        self.proxy = Some(Proxy::HttpConnect(proxy));
+        self
+    }
+
+    /// Keeps connections to the called instances warm by a background task, which connects to
+    /// instances without a ready connection and replaces aged connections, so that calls shifted
+    /// to them do not pay the connect latency.
+    ///
+    /// Default is not enabled.
+    pub fn warm_pool(mut self, warm_pool: WarmPool) -> Self {
+        self.warm_pool = Some(warm_pool);
+        self
+    }
+
+    /// Connects to the in-memory listener of a server in the same process, so that tests can
+    /// call a real server without binding ports.
+    ///
+    /// All connections are created by the [`MemoryConnector`] regardless of the address of
+    /// callee, and the address defaults to a placeholder if not set. This overrides the proxy and
+    /// the TLS config.
+    ///
+    /// Default is not enabled.
+    pub fn memory_connector(mut self, connector: MemoryConnector) -> Self {
+        if self.target.is_none() {
+            self.target = Some(connector.address());
+        }
+        self.memory = Some(connector);
+        self
+    }
+
+    /// Records all calls of the client to the [`Recorder`], including the metadata, messages and
+    /// trailers, which can be replayed by [`ClientBuilder::replay`] later.
+    ///
+    /// This overrides [`ClientBuilder::replay`].
+    ///
+    /// Default is not enabled.
+    ///
+    /// [`Recorder`]: replay::Recorder
+    #[cfg(feature = "replay")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "replay")))]
+    pub fn record(mut self, recorder: replay::Recorder) -> Self {
+        self.replay = Some(replay::ReplayMode::Record(recorder));
+        self
+    }
+
+    /// Serves all calls of the client with recorded responses of the [`Replayer`] instead of
+    /// calling the server, so that tests can run without servers.
+    ///
+    /// The address of callee defaults to a placeholder if not set, and it is never connected.
+    /// This overrides [`ClientBuilder::record`].
+    ///
+    /// Default is not enabled.
+    ///
+    /// [`Replayer`]: replay::Replayer
+    #[cfg(feature = "replay")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "replay")))]
+    pub fn replay(mut self, replayer: replay::Replayer) -> Self {
+        if self.target.is_none() {
+            self.target = Some(Address::from(std::net::SocketAddr::from((
+                std::net::Ipv4Addr::LOCALHOST,
+                0,
+            ))));
+        }
+        self.replay = Some(replay::ReplayMode::Replay(replayer));
+        self
+    }
+
     /// Sets the caller name for the client.
     ///
     /// Default is the empty string.
@@ -282,6 +443,22 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self
     }
 
+    /// Sets the prefix of the `:path` of requests, e.g., `/twirp` for sending requests to
+    /// `/twirp/{service}/{method}`.
+    ///
+    /// This is useful when the server is behind a gateway that routes by path prefix. The
+    /// method name in the context is not changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix does not start with `/`.
+    ///
+    /// Default is no prefix.
+    pub fn path_prefix(mut self, prefix: impl AsRef<str>) -> Self {
+        self.path_prefix = crate::transport::normalize_path_prefix(prefix.as_ref());
+        self
+    }
+
     /// Sets the send compression encodings for the request, and will self-adaptive with config of
     /// the server.
     ///
@@ -300,17 +477,65 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self
     }
 
+    /// Sets the [`MessageTransform`] applied to the messages, see
+    /// [`transform`](crate::codec::transform) for more details.
+    ///
+    /// Calls fail with `UNIMPLEMENTED` if the server does not support the transform.
+    pub fn message_transform<MT: MessageTransform>(mut self, transform: MT) -> Self {
+        self.rpc_config.message_transforms = Some(vec![Arc::new(transform)]);
+        self
+    }
+
+    /// Sets whether the responses must be transformed if there is a transform, see
+    /// [`transform`](crate::codec::transform) for more details.
+    ///
+    /// Calls fail with `FAILED_PRECONDITION` if it's required and the responses are not
+    /// transformed.
+    ///
+    /// Default is true.
+    pub fn message_transform_required(mut self, required: bool) -> Self {
+        self.rpc_config.message_transform_required = Some(required);
+        self
+    }
+
+    /// Sets the max size of messages received by the client, larger messages are rejected with
+    /// `RESOURCE_EXHAUSTED` before they are buffered.
+    ///
+    /// For compressed messages, the limit applies to the compressed lengths.
+    ///
+    /// Default is no limit.
+    pub fn max_decoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_decoding_message_size = Some(max);
+        self
+    }
+
+    /// Sets the max size of messages sent by the client, larger messages are not sent and the
+    /// call fails with `RESOURCE_EXHAUSTED`.
+    ///
+    /// Default is no limit.
+    pub fn max_encoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_encoding_message_size = Some(max);
+        self
+    }
+
     pub fn mk_load_balance<NLB>(self, mk_load_balance: NLB) -> ClientBuilder<IL, OL, C, NLB, T, U> {
         ClientBuilder {
             http2_config: self.http2_config,
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: self.inner_layer,
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: mk_load_balance,
+            resolve_on_dial: false,
             _marker: PhantomData,
 
             #[cfg(feature = "__tls")]
@@ -350,11 +575,18 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: Stack::new(layer, self.inner_layer),
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             _marker: self._marker,
 
             #[cfg(feature = "__tls")]
@@ -384,11 +616,18 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: Stack::new(self.inner_layer, layer),
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             _marker: self._marker,
 
             #[cfg(feature = "__tls")]
@@ -418,11 +657,18 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: self.inner_layer,
             outer_layer: Stack::new(layer, self.outer_layer),
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             _marker: self._marker,
 
             #[cfg(feature = "__tls")]
@@ -452,11 +698,18 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
             rpc_config: self.rpc_config,
             callee_name: self.callee_name,
             caller_name: self.caller_name,
+            path_prefix: self.path_prefix,
             target: self.target,
+            proxy: self.proxy,
+            memory: self.memory,
+            warm_pool: self.warm_pool,
+            #[cfg(feature = "replay")]
+            replay: self.replay,
             inner_layer: self.inner_layer,
             outer_layer: Stack::new(self.outer_layer, layer),
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             _marker: self._marker,
 
             #[cfg(feature = "__tls")]
@@ -479,27 +732,28 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U>
 where
     C: MkClient<Client<BoxCloneService<ClientContext, Request<T>, Response<U>, Status>>>,
     LB: MkLbLayer,
-    LB::Layer: Layer<IL::Service>,
-    <LB::Layer as Layer<IL::Service>>::Service:
+    LB::Layer: Layer<RequestTimeoutService<IL::Service>>,
+    <LB::Layer as Layer<RequestTimeoutService<IL::Service>>>::Service:
         Service<ClientContext, Request<T>, Response = Response<U>> + 'static + Send + Clone + Sync,
-    <<LB::Layer as Layer<IL::Service>>::Service as Service<ClientContext, Request<T>>>::Error:
-        Into<Status>,
+    <<LB::Layer as Layer<RequestTimeoutService<IL::Service>>>::Service as Service<
+        ClientContext,
+        Request<T>,
+    >>::Error: Into<Status>,
     IL: Layer<MetaService<ClientTransport<U>>>,
     IL::Service:
         Service<ClientContext, Request<T>, Response = Response<U>> + 'static + Send + Clone + Sync,
-    <IL::Service as Service<ClientContext, Request<T>>>::Error: Into<Status>,
-    OL:
-        Layer<
-            BoxCloneService<
+    <IL::Service as Service<ClientContext, Request<T>>>::Error: Into<Status> + From<TimeoutError>,
+    OL: Layer<
+        BoxCloneService<
+            ClientContext,
+            Request<T>,
+            Response<U>,
+            <<LB::Layer as Layer<RequestTimeoutService<IL::Service>>>::Service as Service<
                 ClientContext,
                 Request<T>,
-                Response<U>,
-                <<LB::Layer as Layer<IL::Service>>::Service as Service<
-                    ClientContext,
-                    Request<T>,
-                >>::Error,
-            >,
+            >>::Error,
         >,
+    >,
     OL::Service:
         Service<ClientContext, Request<T>, Response = Response<U>> + 'static + Send + Clone + Sync,
     <OL::Service as Service<ClientContext, Request<T>>>::Error: Send + Into<Status>,
@@ -507,21 +761,46 @@ where
 {
     /// Builds a new [`Client`].
     pub fn build(self) -> C::Target {
-        #[cfg(not(feature = "__tls"))]
-        let transport =
-            MetaService::new(ClientTransport::new(&self.http2_config, &self.rpc_config));
-        #[cfg(feature = "__tls")]
-        let transport = match self.tls_config {
-            Some(tls_config) => MetaService::new(ClientTransport::new_with_tls(
+        let transport = match (self.memory, self.proxy) {
+            (Some(memory), _) => {
+                ClientTransport::with_connector(&self.http2_config, Connector::Memory(memory))
+            }
+            (None, Some(proxy)) => ClientTransport::with_connector(
                 &self.http2_config,
-                &self.rpc_config,
-                tls_config,
-            )),
-            None => MetaService::new(ClientTransport::new(&self.http2_config, &self.rpc_config)),
+                Connector::new_with_proxy(
+                    Some(dial_config(&self.rpc_config)),
+                    proxy,
+                    #[cfg(feature = "__tls")]
+                    self.tls_config,
+                ),
+            ),
+            #[cfg(not(feature = "__tls"))]
+            (None, None) => ClientTransport::new(&self.http2_config, &self.rpc_config)
+                .with_resolve_on_dial(self.resolve_on_dial),
+            #[cfg(feature = "__tls")]
+            (None, None) => match self.tls_config {
+                Some(tls_config) => {
+                    ClientTransport::new_with_tls(&self.http2_config, &self.rpc_config, tls_config)
+                }
+                None => ClientTransport::new(&self.http2_config, &self.rpc_config),
+            }
+            .with_resolve_on_dial(self.resolve_on_dial),
         };
+        let transport = transport.with_warm_pool(self.warm_pool);
+        if let Some(drainer) = self.mk_lb.drainer() {
+            transport.drain_on(&drainer);
+        }
+        let warmup = self.mk_lb.warmup().unwrap_or_default();
+        transport.warmup_on(&warmup);
+        #[cfg(feature = "replay")]
+        let transport = transport.with_replay(self.replay);
+        let transport = MetaService::new(transport.with_path_prefix(self.path_prefix));
 
+        // the request timeout is applied inside the load balancer for each attempt
         let transport = self.outer_layer.layer(BoxCloneService::new(
-            self.mk_lb.make().layer(self.inner_layer.layer(transport)),
+            self.mk_lb
+                .make()
+                .layer(RequestTimeoutLayer.layer(self.inner_layer.layer(transport))),
         ));
 
         let transport = transport.map_err(|err| err.into());
@@ -534,6 +813,7 @@ where
                 caller_name: self.caller_name,
                 rpc_config: self.rpc_config,
                 target: self.target,
+                warmup,
             }),
             transport,
         })
@@ -547,6 +827,7 @@ struct ClientInner {
     caller_name: FastStr,
     rpc_config: Config,
     target: Option<Address>,
+    warmup: WarmupHandle,
 }
 
 /// A client for a gRPC service.
@@ -586,6 +867,20 @@ impl<S> Client<S> {
             inner: self.inner,
         }
     }
+
+    /// Warm up the client by resolving the callee and pre-creating connections to its instances,
+    /// returns when all connections are ready or after the timeout of the [`Warmup`].
+    ///
+    /// One connection is pre-created for each instance since HTTP/2 connections are multiplexed,
+    /// see [`volo::loadbalance::warmup`] for more details.
+    pub async fn warmup(&self, warmup: Warmup) -> Result<WarmupReport, Status> {
+        let rpc_info = self.make_rpc_info("");
+        self.inner
+            .warmup
+            .warmup(rpc_info.callee(), &warmup)
+            .await
+            .map_err(Into::into)
+    }
 }
 
 macro_rules! impl_client {
@@ -641,7 +936,13 @@ macro_rules! impl_client {
 impl_client!((self, &mut cx, req) => async move {
     let has_metainfo = metainfo::METAINFO.try_with(|_| {}).is_ok();
 
-    let mk_call = async { self.transport.call(cx, req).await };
+    let mk_call = async {
+        cx.stats.record_call_start_at();
+        let (res, timestamps) = timing::record_connect(self.transport.call(cx, req)).await;
+        cx.stats.record_connect(timestamps);
+        cx.stats.record_call_end_at();
+        res
+    };
 
     if has_metainfo {
         mk_call.await
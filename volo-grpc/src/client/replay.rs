@@ -0,0 +1,616 @@
+//! Recording and replaying of gRPC calls.
+//!
+//! With [`ClientBuilder::record`], the client transport captures every call as an [`Exchange`],
+//! including the metadata, all encoded messages of the request and the response, and the
+//! trailers, and appends it to a file in JSON Lines. The file can be loaded as a [`Cassette`]
+//! later and served by [`ClientBuilder::replay`] without connecting to any server, which matches
+//! calls by their paths or a custom matcher.
+//!
+//! Messages are recorded as they are sent on the wire, i.e., with the gRPC message prefix and
+//! possibly compressed, and encoded in base64.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use volo_grpc::client::replay::{Cassette, Recorder, Replayer};
+//!
+//! // record calls to the real server
+//! let client = ClientBuilder::new("hello")
+//!     .address(addr)
+//!     .record(Recorder::to_file("calls.jsonl")?)
+//!     .build();
+//!
+//! // replay them later
+//! let client = ClientBuilder::new("hello")
+//!     .replay(Replayer::new(Cassette::load("calls.jsonl")?))
+//!     .build();
+//! ```
+//!
+//! [`ClientBuilder::record`]: super::ClientBuilder::record
+//! [`ClientBuilder::replay`]: super::ClientBuilder::replay
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use futures::StreamExt;
+use http::{
+    HeaderMap, StatusCode,
+    header::{HeaderName, HeaderValue},
+};
+use http_body::{Body as HttpBody, Frame};
+use http_body_util::{BodyExt, BodyStream, StreamBody};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{BoxStream, Status, body::BoxBody};
+
+type RequestBody = StreamBody<BoxStream<'static, Result<Frame<Bytes>, Status>>>;
+
+/// Bytes serialized as a base64 string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Base64Bytes(pub Bytes);
+
+impl Serialize for Base64Bytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64Bytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(s)
+            .map(|b| Self(Bytes::from(b)))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Recorded metadata or trailers, in their original order.
+///
+/// Values of binary metadata are recorded as they are sent on the wire, i.e., encoded in base64.
+pub type RecordedMetadata = Vec<(String, String)>;
+
+fn record_metadata(headers: &HeaderMap) -> RecordedMetadata {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.as_str().to_owned(),
+                String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+fn restore_metadata(metadata: &RecordedMetadata) -> Result<HeaderMap, Status> {
+    let mut headers = HeaderMap::with_capacity(metadata.len());
+    for (k, v) in metadata {
+        let name = HeaderName::try_from(k.as_str())
+            .map_err(|e| Status::internal(format!("invalid recorded metadata key: {e}")))?;
+        let value = HeaderValue::try_from(v.as_str())
+            .map_err(|e| Status::internal(format!("invalid recorded metadata value: {e}")))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// A recorded request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// Path of the request, e.g., `/hello.Greeter/SayHello`.
+    pub path: String,
+    /// Metadata of the request.
+    pub metadata: RecordedMetadata,
+    /// Encoded messages of the request in order.
+    pub messages: Vec<Base64Bytes>,
+}
+
+/// A frame of recorded response body.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedFrame {
+    /// Encoded message or a part of it.
+    Data(Base64Bytes),
+    /// Trailers with the status of the call.
+    Trailers(RecordedMetadata),
+}
+
+/// A recorded response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Metadata of the response, which has the status of the call for trailers-only responses.
+    pub metadata: RecordedMetadata,
+    /// All frames of the response body in order.
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<http::Response<BoxBody>, Status> {
+        let status = StatusCode::from_u16(self.status)
+            .map_err(|e| Status::internal(format!("invalid recorded status code: {e}")))?;
+        let headers = restore_metadata(&self.metadata)?;
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            let frame = match frame {
+                RecordedFrame::Data(data) => Frame::data(data.0.clone()),
+                RecordedFrame::Trailers(trailers) => Frame::trailers(restore_metadata(trailers)?),
+            };
+            frames.push(Ok::<_, Status>(frame));
+        }
+
+        let body = StreamBody::new(futures::stream::iter(frames)).boxed_unsync();
+        let mut resp = http::Response::new(body);
+        *resp.status_mut() = status;
+        *resp.version_mut() = http::Version::HTTP_2;
+        *resp.headers_mut() = headers;
+        Ok(resp)
+    }
+}
+
+/// A recorded call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The recorded request.
+    pub request: RecordedRequest,
+    /// The recorded response.
+    pub response: RecordedResponse,
+}
+
+/// A list of [`Exchange`]s, which is stored as JSON Lines, one [`Exchange`] per line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cassette {
+    exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    /// Creates an empty [`Cassette`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a [`Cassette`] from a file in JSON Lines, empty lines are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut exchanges = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            exchanges.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self { exchanges })
+    }
+
+    /// Saves the [`Cassette`] to a file in JSON Lines, the file is truncated if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for exchange in &self.exchanges {
+            write_exchange(&mut file, exchange)?;
+        }
+        file.flush()
+    }
+
+    /// Appends an [`Exchange`].
+    pub fn push(&mut self, exchange: Exchange) {
+        self.exchanges.push(exchange);
+    }
+
+    /// All [`Exchange`]s in order.
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+}
+
+impl FromIterator<Exchange> for Cassette {
+    fn from_iter<T: IntoIterator<Item = Exchange>>(iter: T) -> Self {
+        Self {
+            exchanges: iter.into_iter().collect(),
+        }
+    }
+}
+
+fn write_exchange<W: Write>(writer: &mut W, exchange: &Exchange) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, exchange)?;
+    writer.write_all(b"\n")
+}
+
+/// Storage of [`Exchange`]s recorded by the client transport.
+///
+/// It is cheap to clone, and all clones share the same storage.
+#[derive(Clone, Default)]
+pub struct Recorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+#[derive(Default)]
+struct RecorderInner {
+    cassette: Cassette,
+    file: Option<File>,
+}
+
+impl Recorder {
+    /// Creates a [`Recorder`] keeping [`Exchange`]s in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a [`Recorder`] that also appends every [`Exchange`] to the file as a line of JSON,
+    /// the file is created if it does not exist.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                cassette: Cassette::new(),
+                file: Some(file),
+            })),
+        })
+    }
+
+    /// Gets a [`Cassette`] of all [`Exchange`]s recorded.
+    pub fn cassette(&self) -> Cassette {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cassette
+            .clone()
+    }
+
+    fn record(&self, exchange: Exchange) {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(file) = inner.file.as_mut() {
+            if let Err(e) = write_exchange(file, &exchange) {
+                tracing::warn!("[VOLO] failed to write recorded exchange: {e}");
+            }
+        }
+        inner.cassette.push(exchange);
+    }
+
+    /// Records messages of the request as they are sent, returns the request and the pending
+    /// [`Exchange`] for its response.
+    pub(crate) fn record_request(
+        &self,
+        req: http::Request<RequestBody>,
+    ) -> (http::Request<RequestBody>, PendingExchange) {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let (parts, body) = req.into_parts();
+        let pending = PendingExchange {
+            recorder: self.clone(),
+            path: parts.uri.path().to_owned(),
+            metadata: record_metadata(&parts.headers),
+            messages: messages.clone(),
+        };
+
+        let stream = BodyStream::new(body).inspect(move |frame| {
+            if let Some(data) = frame.as_ref().ok().and_then(Frame::data_ref) {
+                messages
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(Base64Bytes(data.clone()));
+            }
+        });
+        let req = http::Request::from_parts(parts, StreamBody::new(stream.boxed()));
+        (req, pending)
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Recorder")
+            .field("exchanges", &inner.cassette.exchanges.len())
+            .field("file", &inner.file.is_some())
+            .finish()
+    }
+}
+
+/// An [`Exchange`] waiting for its response.
+pub(crate) struct PendingExchange {
+    recorder: Recorder,
+    path: String,
+    metadata: RecordedMetadata,
+    messages: Arc<Mutex<Vec<Base64Bytes>>>,
+}
+
+impl PendingExchange {
+    /// Records frames of the response body, the [`Exchange`] is recorded when the body ends or
+    /// is dropped.
+    pub(crate) fn record_response(self, resp: http::Response<BoxBody>) -> http::Response<BoxBody> {
+        let status = resp.status().as_u16();
+        let metadata = record_metadata(resp.headers());
+        resp.map(|inner| {
+            RecordBody {
+                inner,
+                frames: Vec::new(),
+                pending: Some((self, status, metadata)),
+            }
+            .boxed_unsync()
+        })
+    }
+}
+
+struct RecordBody {
+    inner: BoxBody,
+    frames: Vec<RecordedFrame>,
+    pending: Option<(PendingExchange, u16, RecordedMetadata)>,
+}
+
+impl RecordBody {
+    fn finish(&mut self) {
+        let Some((pending, status, metadata)) = self.pending.take() else {
+            return;
+        };
+        let messages = std::mem::take(
+            &mut *pending
+                .messages
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        pending.recorder.record(Exchange {
+            request: RecordedRequest {
+                path: pending.path,
+                metadata: pending.metadata,
+                messages,
+            },
+            response: RecordedResponse {
+                status,
+                metadata,
+                frames: std::mem::take(&mut self.frames),
+            },
+        });
+    }
+}
+
+impl HttpBody for RecordBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = std::task::ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    this.frames
+                        .push(RecordedFrame::Data(Base64Bytes(data.clone())));
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    this.frames
+                        .push(RecordedFrame::Trailers(record_metadata(trailers)));
+                }
+            }
+            Some(Err(_)) | None => this.finish(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for RecordBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+type Matcher = Arc<dyn Fn(&http::request::Parts, &RecordedRequest) -> bool + Send + Sync>;
+
+/// Serves calls with responses recorded in a [`Cassette`], see [`ClientBuilder::replay`].
+///
+/// A call is served with the first recorded [`Exchange`] that matches it and has not been served,
+/// and the last matched one is served again if all of them have been served. By default, a call
+/// matches an [`Exchange`] if they have the same path. A call fails with
+/// [`Code::Unimplemented`] if no [`Exchange`] matches it.
+///
+/// It is cheap to clone, and all clones share the state of served [`Exchange`]s.
+///
+/// [`ClientBuilder::replay`]: super::ClientBuilder::replay
+/// [`Code::Unimplemented`]: crate::Code::Unimplemented
+#[derive(Clone)]
+pub struct Replayer {
+    exchanges: Arc<[Exchange]>,
+    served: Arc<Mutex<Vec<bool>>>,
+    matcher: Matcher,
+}
+
+impl Replayer {
+    /// Creates a [`Replayer`] serving the [`Cassette`].
+    pub fn new(cassette: Cassette) -> Self {
+        let len = cassette.exchanges.len();
+        Self {
+            exchanges: cassette.exchanges.into(),
+            served: Arc::new(Mutex::new(vec![false; len])),
+            matcher: Arc::new(|parts, recorded| parts.uri.path() == recorded.path),
+        }
+    }
+
+    /// Sets a custom matcher between calls and recorded requests.
+    ///
+    /// Note that messages of the request are not available for matching.
+    pub fn with_matcher<F>(mut self, matcher: F) -> Self
+    where
+        F: Fn(&http::request::Parts, &RecordedRequest) -> bool + Send + Sync + 'static,
+    {
+        self.matcher = Arc::new(matcher);
+        self
+    }
+
+    fn find(&self, parts: &http::request::Parts) -> Option<&Exchange> {
+        let mut served = self.served.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut last = None;
+        for (idx, exchange) in self.exchanges.iter().enumerate() {
+            if !(self.matcher)(parts, &exchange.request) {
+                continue;
+            }
+            if !served[idx] {
+                served[idx] = true;
+                return Some(exchange);
+            }
+            last = Some(exchange);
+        }
+        last
+    }
+
+    /// Serves the request with a recorded response.
+    pub(crate) fn replay(
+        &self,
+        req: http::Request<RequestBody>,
+    ) -> Result<http::Response<BoxBody>, Status> {
+        let (parts, body) = req.into_parts();
+        // drain messages of the request in background, so that streaming requests are not
+        // blocked by the missing server
+        tokio::spawn(BodyStream::new(body).for_each(|_| async {}));
+
+        match self.find(&parts) {
+            Some(exchange) => exchange.response.to_response(),
+            None => Err(Status::unimplemented(format!(
+                "no recorded exchange matches {}",
+                parts.uri.path()
+            ))),
+        }
+    }
+}
+
+impl fmt::Debug for Replayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replayer")
+            .field("exchanges", &self.exchanges.len())
+            .finish()
+    }
+}
+
+/// How the client transport records or replays calls.
+#[derive(Clone, Debug)]
+pub(crate) enum ReplayMode {
+    Record(Recorder),
+    Replay(Replayer),
+}
+
+#[cfg(test)]
+mod replay_tests {
+    use bytes::Bytes;
+    use http::HeaderMap;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+
+    use super::{Base64Bytes, Cassette, RecordedFrame, Recorder, Replayer};
+    use crate::Status;
+
+    fn request(path: &str, messages: &[&'static str]) -> http::Request<super::RequestBody> {
+        let frames: Vec<_> = messages
+            .iter()
+            .map(|m| Ok::<_, Status>(Frame::data(Bytes::from_static(m.as_bytes()))))
+            .collect();
+        let body = StreamBody::new(futures::StreamExt::boxed(futures::stream::iter(frames)));
+        http::Request::builder()
+            .uri(format!("http://127.0.0.1:8000{path}"))
+            .header("x-id", "1")
+            .body(body)
+            .unwrap()
+    }
+
+    fn response(data: &'static str) -> http::Response<crate::body::BoxBody> {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let frames = vec![
+            Ok::<_, Status>(Frame::data(Bytes::from_static(data.as_bytes()))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        http::Response::new(StreamBody::new(futures::stream::iter(frames)).boxed_unsync())
+    }
+
+    async fn consume(req: http::Request<super::RequestBody>) {
+        req.into_body().collect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let recorder = Recorder::new();
+        for data in ["first", "second"] {
+            let (req, pending) = recorder.record_request(request("/test.Echo/Echo", &["a", "b"]));
+            consume(req).await;
+            let resp = pending.record_response(response(data));
+            resp.into_body().collect().await.unwrap();
+        }
+
+        let cassette = recorder.cassette();
+        assert_eq!(cassette.exchanges().len(), 2);
+        let exchange = &cassette.exchanges()[0];
+        assert_eq!(exchange.request.path, "/test.Echo/Echo");
+        assert_eq!(
+            exchange.request.metadata,
+            vec![("x-id".to_owned(), "1".to_owned())]
+        );
+        assert_eq!(
+            exchange.request.messages,
+            vec![
+                Base64Bytes(Bytes::from_static(b"a")),
+                Base64Bytes(Bytes::from_static(b"b"))
+            ]
+        );
+        assert_eq!(
+            exchange.response.frames,
+            vec![
+                RecordedFrame::Data(Base64Bytes(Bytes::from_static(b"first"))),
+                RecordedFrame::Trailers(vec![("grpc-status".to_owned(), "0".to_owned())]),
+            ]
+        );
+
+        // save and load
+        let path =
+            std::env::temp_dir().join(format!("volo-grpc-replay-{}.jsonl", std::process::id()));
+        cassette.save(&path).unwrap();
+        let loaded = Cassette::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, cassette);
+
+        let replayer = Replayer::new(loaded);
+        // served in order, and the last one is repeated
+        for data in ["first", "second", "second"] {
+            let resp = replayer.replay(request("/test.Echo/Echo", &[])).unwrap();
+            let body = resp.into_body().collect().await.unwrap();
+            assert_eq!(body.trailers().unwrap().get("grpc-status").unwrap(), "0");
+            assert_eq!(body.to_bytes(), data);
+        }
+        // nothing matches
+        let status = replayer
+            .replay(request("/test.Echo/Other", &[]))
+            .unwrap_err();
+        assert_eq!(status.code(), crate::Code::Unimplemented);
+    }
+
+    #[tokio::test]
+    async fn test_record_on_drop() {
+        let recorder = Recorder::new();
+        let (req, pending) = recorder.record_request(request("/test.Echo/Echo", &["a"]));
+        consume(req).await;
+        // the response body is dropped without being consumed
+        drop(pending.record_response(response("first")));
+        let cassette = recorder.cassette();
+        assert_eq!(cassette.exchanges().len(), 1);
+        assert!(cassette.exchanges()[0].response.frames.is_empty());
+
+        let replayer = Replayer::new(cassette)
+            .with_matcher(|parts, _| parts.headers.get("x-id").is_some_and(|id| id == "1"));
+        assert!(replayer.replay(request("/test.Echo/Other", &[])).is_ok());
+    }
+}
@@ -0,0 +1,375 @@
+//! Flow-controlled sender for streaming requests.
+//!
+//! A streaming request is a [`Stream`] polled by the transport, which only takes the next message
+//! when the HTTP/2 stream has flow-control capacity. With an unbounded channel as the request
+//! stream, the application never observes that the peer is slow, and the messages are buffered in
+//! memory. [`sender`] creates a bounded queue as the request stream, so that
+//! [`RequestSender::send`] waits until there is space in the queue, and
+//! [`RequestSender::flush`] waits until all queued messages are taken by the transport.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_grpc::client::stream::{RequestStream, sender};
+//!
+//! # struct Item;
+//! # async fn call<S>(_: S) {}
+//! async fn upload(items: Vec<Item>) {
+//!     let (tx, rx) = sender(16);
+//!     tokio::spawn(async move {
+//!         for item in items {
+//!             // waits for the capacity, and fails if the call is finished
+//!             if tx.send(item).await.is_err() {
+//!                 return;
+//!             }
+//!         }
+//!         let _ = tx.flush().await;
+//!         tx.close_send();
+//!     });
+//!     // pass `rx` as the streaming request, e.g., `client.upload(rx).await`
+//!     call::<RequestStream<Item>>(rx).await;
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::{Pin, pin},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use futures_util::task::AtomicWaker;
+use tokio::sync::Notify;
+
+/// Error returned by [`RequestSender::send`] and [`RequestSender::flush`] when the request stream
+/// is closed, e.g., the call is finished or cancelled, or [`RequestSender::close_send`] has been
+/// called.
+///
+/// The message not sent is returned.
+pub struct SendError<T>(pub T);
+
+impl<T> SendError<T> {
+    /// Consumes the error, returning the message not sent.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request stream is closed")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    // wakes the stream when a message is sent or the sending side is closed
+    rx_waker: AtomicWaker,
+    // notifies the senders when a message is taken or the stream is dropped
+    tx_notify: Notify,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    // `close_send` is called or all senders are dropped
+    send_closed: bool,
+    // the stream is dropped
+    recv_closed: bool,
+}
+
+impl<T> Shared<T> {
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Create a flow-controlled sender of a streaming request, which queues at most `capacity`
+/// messages not taken by the transport.
+///
+/// The [`RequestStream`] can be passed as the streaming request, and messages are sent by the
+/// [`RequestSender`].
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn sender<T>(capacity: usize) -> (RequestSender<T>, RequestStream<T>) {
+    assert!(capacity > 0, "capacity should be greater than zero");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            senders: 1,
+            send_closed: false,
+            recv_closed: false,
+        }),
+        capacity,
+        rx_waker: AtomicWaker::new(),
+        tx_notify: Notify::new(),
+    });
+    (
+        RequestSender {
+            shared: shared.clone(),
+        },
+        RequestStream { shared },
+    )
+}
+
+/// Sender of the streaming request created by [`sender`].
+pub struct RequestSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> RequestSender<T> {
+    /// Send a message to the request stream, waiting until there is space in the queue.
+    ///
+    /// The queue is drained only when the transport has flow-control capacity to send, so this
+    /// applies the backpressure of the peer to the caller.
+    pub async fn send(&self, message: T) -> Result<(), SendError<T>> {
+        let mut message = Some(message);
+        loop {
+            let mut notified = pin!(self.shared.tx_notify.notified());
+            // register before checking the state, so that the notification is not missed
+            notified.as_mut().enable();
+            {
+                let mut state = self.shared.state();
+                if state.send_closed || state.recv_closed {
+                    return Err(SendError(message.take().unwrap()));
+                }
+                if state.queue.len() < self.shared.capacity {
+                    state.queue.push_back(message.take().unwrap());
+                    drop(state);
+                    self.shared.rx_waker.wake();
+                    return Ok(());
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Try to send a message to the request stream without waiting, the message is returned if
+    /// the queue is full or the stream is closed.
+    pub fn try_send(&self, message: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.state();
+        if state.send_closed || state.recv_closed || state.queue.len() >= self.shared.capacity {
+            return Err(SendError(message));
+        }
+        state.queue.push_back(message);
+        drop(state);
+        self.shared.rx_waker.wake();
+        Ok(())
+    }
+
+    /// Wait until all queued messages are taken by the transport.
+    ///
+    /// Returns an error if the stream is dropped before that, e.g., the call is finished or
+    /// cancelled, in which case the queued messages are discarded.
+    pub async fn flush(&self) -> Result<(), SendError<()>> {
+        loop {
+            let mut notified = pin!(self.shared.tx_notify.notified());
+            notified.as_mut().enable();
+            {
+                let state = self.shared.state();
+                if state.recv_closed {
+                    return Err(SendError(()));
+                }
+                if state.queue.is_empty() {
+                    return Ok(());
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Close the sending side of the request stream, the stream ends after the queued messages
+    /// are taken.
+    ///
+    /// Messages sent by any clone of the sender after that are rejected. Dropping all senders also
+    /// closes the sending side.
+    pub fn close_send(&self) {
+        let mut state = self.shared.state();
+        if state.send_closed {
+            return;
+        }
+        state.send_closed = true;
+        drop(state);
+        self.shared.rx_waker.wake();
+        self.shared.tx_notify.notify_waiters();
+    }
+
+    /// Returns whether the request stream is closed, by either [`RequestSender::close_send`] or
+    /// dropping the stream.
+    pub fn is_closed(&self) -> bool {
+        let state = self.shared.state();
+        state.send_closed || state.recv_closed
+    }
+
+    /// Returns the count of messages queued now.
+    pub fn queued(&self) -> usize {
+        self.shared.state().queue.len()
+    }
+
+    /// Returns the max count of queued messages.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+}
+
+impl<T> Clone for RequestSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for RequestSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.send_closed = true;
+            drop(state);
+            self.shared.rx_waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for RequestSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestSender")
+            .field("capacity", &self.shared.capacity)
+            .field("queued", &self.queued())
+            .finish()
+    }
+}
+
+/// Request stream created by [`sender`], it is a [`Stream`] of messages.
+pub struct RequestStream<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Stream for RequestStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.rx_waker.register(cx.waker());
+        let mut state = self.shared.state();
+        if let Some(message) = state.queue.pop_front() {
+            drop(state);
+            self.shared.tx_notify.notify_waiters();
+            return Poll::Ready(Some(message));
+        }
+        if state.send_closed {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.shared.state().queue.len(), None)
+    }
+}
+
+impl<T> Drop for RequestStream<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.recv_closed = true;
+        state.queue.clear();
+        drop(state);
+        self.shared.tx_notify.notify_waiters();
+    }
+}
+
+impl<T> fmt::Debug for RequestStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestStream")
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::sender;
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let (tx, mut rx) = sender(2);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        assert_eq!(tx.queued(), 2);
+        assert_eq!(tx.try_send(3).unwrap_err().into_inner(), 3);
+
+        // the sender waits until a message is taken
+        let send = tokio::spawn(async move {
+            tx.send(3).await.unwrap();
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!send.is_finished());
+
+        assert_eq!(rx.next().await, Some(1));
+        send.await.unwrap();
+        assert_eq!(rx.next().await, Some(2));
+        assert_eq!(rx.next().await, Some(3));
+        // all senders are dropped
+        assert_eq!(rx.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_close_send() {
+        let (tx, mut rx) = sender(4);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        // nothing is queued
+        let (empty_tx, _empty_rx) = sender::<i32>(1);
+        empty_tx.flush().await.unwrap();
+
+        let sender = tx.clone();
+        let flush = tokio::spawn(async move { sender.flush().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!flush.is_finished());
+        assert_eq!(rx.next().await, Some(1));
+        assert!(!flush.is_finished());
+        assert_eq!(rx.next().await, Some(2));
+        flush.await.unwrap().unwrap();
+
+        tx.send(3).await.unwrap();
+        tx.close_send();
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(4).await.unwrap_err().into_inner(), 4);
+        // the stream ends after the queued messages, even though the sender is alive
+        assert_eq!(rx.next().await, Some(3));
+        assert_eq!(rx.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_dropped() {
+        let (tx, rx) = sender(1);
+        tx.send(1).await.unwrap();
+        let sender = tx.clone();
+        let send = tokio::spawn(async move { sender.send(2).await });
+        let sender = tx.clone();
+        let flush = tokio::spawn(async move { sender.flush().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(rx);
+        assert_eq!(send.await.unwrap().unwrap_err().into_inner(), 2);
+        assert!(flush.await.unwrap().is_err());
+        assert!(tx.is_closed());
+    }
+}
@@ -1,6 +1,17 @@
 //! These codes are copied from `tonic/src/codec/compression.rs` and may be modified by us.
 
-use std::io;
+#[cfg(feature = "compress")]
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{Arc, PoisonError, RwLock},
+};
+use std::{
+    fmt,
+    io::{self, Write},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
 
 #[cfg(feature = "compress")]
 use bytes::BufMut;
@@ -8,13 +19,17 @@ use bytes::{Buf, BytesMut};
 #[cfg(feature = "compress")]
 pub use flate2::Compression as Level;
 #[cfg(feature = "gzip")]
-use flate2::bufread::{GzDecoder, GzEncoder};
+use flate2::bufread::GzDecoder;
 #[cfg(feature = "zlib")]
-use flate2::bufread::{ZlibDecoder, ZlibEncoder};
+use flate2::bufread::ZlibDecoder;
+use futures::Stream;
 use http::HeaderValue;
+use pilota::pb::Message;
+use pin_project::pin_project;
+#[cfg(feature = "compress")]
+use volo::net::Address;
 
 use super::BUFFER_SIZE;
-#[cfg(feature = "compress")]
 use crate::Status;
 
 pub const ENCODING_HEADER: &str = "grpc-encoding";
@@ -99,16 +114,7 @@ impl Default for ZstdConfig {
 pub fn compose_encodings(encodings: &[CompressionEncoding]) -> HeaderValue {
     let encodings = encodings
         .iter()
-        .map(|item| match item {
-            // TODO: gzip-6 @https://grpc.github.io/grpc/core/md_doc_compression.html#autotoc_md59
-            #[cfg(feature = "gzip")]
-            CompressionEncoding::Gzip(_) => "gzip",
-            #[cfg(feature = "zlib")]
-            CompressionEncoding::Zlib(_) => "zlib",
-            #[cfg(feature = "zstd")]
-            CompressionEncoding::Zstd(_) => "zstd",
-            CompressionEncoding::Identity => "identity",
-        })
+        .map(CompressionEncoding::as_str)
         .collect::<Vec<&'static str>>();
     // encodings.push("identity");
 
@@ -121,19 +127,40 @@ fn is_enabled(encoding: CompressionEncoding, encodings: &[CompressionEncoding])
 }
 
 impl CompressionEncoding {
-    /// make the compression encoding into a [HeaderValue]
-    pub fn into_header_value(self) -> HeaderValue {
+    /// The name of the compression encoding used in `grpc-encoding` and `grpc-accept-encoding`
+    pub const fn as_str(&self) -> &'static str {
         match self {
+            // TODO: gzip-6 @https://grpc.github.io/grpc/core/md_doc_compression.html#autotoc_md59
             #[cfg(feature = "gzip")]
-            CompressionEncoding::Gzip(_) => HeaderValue::from_static("gzip"),
+            CompressionEncoding::Gzip(_) => "gzip",
             #[cfg(feature = "zlib")]
-            CompressionEncoding::Zlib(_) => HeaderValue::from_static("zlib"),
+            CompressionEncoding::Zlib(_) => "zlib",
             #[cfg(feature = "zstd")]
-            CompressionEncoding::Zstd(_) => HeaderValue::from_static("zstd"),
-            CompressionEncoding::Identity => HeaderValue::from_static("identity"),
+            CompressionEncoding::Zstd(_) => "zstd",
+            CompressionEncoding::Identity => "identity",
         }
     }
 
+    /// Parse the name of a compression encoding, returns [`None`] if it isn't supported.
+    #[cfg(feature = "compress")]
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "gzip")]
+            "gzip" => Some(Self::Gzip(None)),
+            #[cfg(feature = "zlib")]
+            "zlib" => Some(Self::Zlib(None)),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Self::Zstd(None)),
+            "identity" => Some(Self::Identity),
+            _ => None,
+        }
+    }
+
+    /// make the compression encoding into a [HeaderValue]
+    pub fn into_header_value(self) -> HeaderValue {
+        HeaderValue::from_static(self.as_str())
+    }
+
     /// make the compression encodings into a [HeaderValue],and the encodings uses a `,` as
     /// separator
     pub fn into_accept_encoding_header_value(
@@ -270,45 +297,398 @@ impl CompressionEncoding {
     }
 }
 
-/// Compress `len` bytes from `src_buf` into `dest_buf`.
-pub(crate) fn compress(
+/// Maximum number of peers in [`CompressionCache`], the cache is reset when it is full.
+#[cfg(feature = "compress")]
+const MAX_CACHED_PEERS: usize = 4096;
+
+/// Cache of compression encodings accepted by peers.
+///
+/// The accepted encodings of a peer are learned from the `grpc-accept-encoding` header of its
+/// responses, or from the `Unimplemented` error when the peer failed to decompress a request.
+/// The client uses it for skipping the encodings which are not supported by the peer, so that the
+/// peer will not reject the subsequent requests and no compression work is wasted.
+#[cfg(feature = "compress")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CompressionCache {
+    peers: Arc<RwLock<HashMap<Address, Vec<CompressionEncoding>>>>,
+}
+
+#[cfg(feature = "compress")]
+impl CompressionCache {
+    /// Select the encoding with the highest priority in `config` which is accepted by the peer.
+    ///
+    /// If the peer is unknown, the first encoding of `config` is selected.
+    pub fn select(
+        &self,
+        peer: &Address,
+        config: &[CompressionEncoding],
+    ) -> Option<CompressionEncoding> {
+        let peers = self.peers.read().unwrap_or_else(PoisonError::into_inner);
+        match peers.get(peer) {
+            Some(accepted) => config
+                .iter()
+                .find(|encoding| {
+                    **encoding == CompressionEncoding::Identity || accepted.contains(encoding)
+                })
+                .copied(),
+            None => config.first().copied(),
+        }
+    }
+
+    /// Record the encodings accepted by the peer from the `grpc-accept-encoding` header.
+    ///
+    /// Returns `false` if there is no valid header.
+    pub fn record(&self, peer: &Address, headers: &http::HeaderMap) -> bool {
+        let Some(value) = headers
+            .get(ACCEPT_ENCODING_HEADER)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+        let accepted = value
+            .split(',')
+            .filter_map(|name| CompressionEncoding::from_name(name.trim()))
+            .collect::<Vec<_>>();
+
+        // most responses advertise the same encodings, so check it without the write lock first
+        if self
+            .peers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(peer)
+            == Some(&accepted)
+        {
+            return true;
+        }
+        let mut peers = self.peers.write().unwrap_or_else(PoisonError::into_inner);
+        if peers.len() >= MAX_CACHED_PEERS && !peers.contains_key(peer) {
+            peers.clear();
+        }
+        peers.insert(peer.clone(), accepted);
+        true
+    }
+
+    /// Mark the encoding as not accepted by the peer.
+    pub fn reject(&self, peer: &Address, encoding: CompressionEncoding) {
+        let mut peers = self.peers.write().unwrap_or_else(PoisonError::into_inner);
+        if peers.len() >= MAX_CACHED_PEERS && !peers.contains_key(peer) {
+            peers.clear();
+        }
+        peers
+            .entry(peer.clone())
+            .or_insert_with(|| {
+                vec![
+                    #[cfg(feature = "gzip")]
+                    CompressionEncoding::Gzip(None),
+                    #[cfg(feature = "zlib")]
+                    CompressionEncoding::Zlib(None),
+                    #[cfg(feature = "zstd")]
+                    CompressionEncoding::Zstd(None),
+                ]
+            })
+            .retain(|item| *item != encoding);
+    }
+}
+
+/// The size of the first chunk of a compressed message.
+pub(crate) const MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The max size of chunks of a compressed message.
+pub(crate) const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Compressor of a message into chunks, which is fed with the slices of the message one by one.
+///
+/// The message is never copied into one contiguous buffer, and the output is split into chunks
+/// instead of growing one buffer, so that it's never copied by reallocating either. The sizes of
+/// chunks double from `min_chunk_size` up to `max_chunk_size`, so that a small message takes a
+/// small allocation while a large one is split into fewer chunks.
+///
+/// Note that the chunks are only available after [`ChunkCompressor::finish`], since the length of
+/// the whole compressed message is written into the prefix before it.
+///
+/// The slices are written as is for [`CompressionEncoding::Identity`].
+pub(crate) struct ChunkCompressor {
+    inner: ChunkCompressorInner,
+}
+
+enum ChunkCompressorInner {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<ChunkWriter>),
+    #[cfg(feature = "zlib")]
+    Zlib(flate2::write::ZlibEncoder<ChunkWriter>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, ChunkWriter>),
+    Identity(ChunkWriter),
+}
+
+impl ChunkCompressor {
+    /// Create a compressor, leaving `reserved` bytes at the start of the first chunk for the
+    /// prefix of the message.
+    pub(crate) fn new(
+        encoding: CompressionEncoding,
+        reserved: usize,
+        min_chunk_size: usize,
+        max_chunk_size: usize,
+    ) -> Result<Self, io::Error> {
+        let dest = ChunkWriter::new(reserved, min_chunk_size, max_chunk_size);
+        let inner = match encoding {
+            #[cfg(feature = "gzip")]
+            CompressionEncoding::Gzip(config) => {
+                let level = config.unwrap_or_default().level;
+                ChunkCompressorInner::Gzip(flate2::write::GzEncoder::new(dest, level))
+            }
+            #[cfg(feature = "zlib")]
+            CompressionEncoding::Zlib(config) => {
+                let level = config.unwrap_or_default().level;
+                ChunkCompressorInner::Zlib(flate2::write::ZlibEncoder::new(dest, level))
+            }
+            #[cfg(feature = "zstd")]
+            CompressionEncoding::Zstd(config) => {
+                let level = config.unwrap_or_default().level.level();
+                let zstd_level = if level == 0 {
+                    zstd::DEFAULT_COMPRESSION_LEVEL
+                } else {
+                    level as i32
+                };
+                ChunkCompressorInner::Zstd(zstd::Encoder::new(dest, zstd_level)?)
+            }
+            CompressionEncoding::Identity => ChunkCompressorInner::Identity(dest),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Compress the next slice of the message.
+    pub(crate) fn write(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        match &mut self.inner {
+            #[cfg(feature = "gzip")]
+            ChunkCompressorInner::Gzip(encoder) => encoder.write_all(data),
+            #[cfg(feature = "zlib")]
+            ChunkCompressorInner::Zlib(encoder) => encoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            ChunkCompressorInner::Zstd(encoder) => encoder.write_all(data),
+            ChunkCompressorInner::Identity(dest) => dest.write_all(data),
+        }
+    }
+
+    /// Finish the message and get the chunks.
+    #[allow(clippy::infallible_destructuring_match)]
+    pub(crate) fn finish(self) -> Result<Vec<BytesMut>, io::Error> {
+        let dest = match self.inner {
+            #[cfg(feature = "gzip")]
+            ChunkCompressorInner::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zlib")]
+            ChunkCompressorInner::Zlib(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            ChunkCompressorInner::Zstd(encoder) => encoder.finish()?,
+            ChunkCompressorInner::Identity(dest) => dest,
+        };
+        Ok(dest.chunks)
+    }
+}
+
+/// Compress the slices of a message into chunks of at most `chunk_size` bytes.
+#[cfg(test)]
+pub(crate) fn compress_chunks<'a, I>(
     encoding: CompressionEncoding,
-    src_buf: &mut BytesMut,
-    dest_buf: &mut BytesMut,
-) -> Result<(), io::Error> {
-    let len = src_buf.len();
-    let capacity = ((len / BUFFER_SIZE) + 1) * BUFFER_SIZE;
+    src: I,
+    reserved: usize,
+    chunk_size: usize,
+) -> Result<Vec<BytesMut>, io::Error>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut compressor = ChunkCompressor::new(encoding, reserved, chunk_size, chunk_size)?;
+    for slice in src {
+        compressor.write(slice)?;
+    }
+    compressor.finish()
+}
 
-    dest_buf.reserve(capacity);
+/// [`Write`] into chunks with bounded and growing sizes.
+struct ChunkWriter {
+    chunks: Vec<BytesMut>,
+    chunk_size: usize,
+    max_chunk_size: usize,
+}
 
-    match encoding {
-        #[cfg(feature = "gzip")]
-        CompressionEncoding::Gzip(Some(config)) => {
-            let mut gz_encoder = GzEncoder::new(&src_buf[0..len], config.level);
-            io::copy(&mut gz_encoder, &mut dest_buf.writer())?;
+impl ChunkWriter {
+    fn new(reserved: usize, chunk_size: usize, max_chunk_size: usize) -> Self {
+        let mut first = BytesMut::with_capacity(chunk_size.max(reserved));
+        first.resize(reserved, 0);
+        Self {
+            chunks: vec![first],
+            chunk_size,
+            max_chunk_size: max_chunk_size.max(chunk_size),
         }
-        #[cfg(feature = "zlib")]
-        CompressionEncoding::Zlib(Some(config)) => {
-            let mut zlib_encoder = ZlibEncoder::new(&src_buf[0..len], config.level);
-            io::copy(&mut zlib_encoder, &mut dest_buf.writer())?;
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self
+            .chunks
+            .last()
+            .is_none_or(|last| last.len() >= self.chunk_size)
+        {
+            self.chunk_size = (self.chunk_size * 2).min(self.max_chunk_size);
+            self.chunks.push(BytesMut::with_capacity(self.chunk_size));
         }
-        #[cfg(feature = "zstd")]
-        CompressionEncoding::Zstd(Some(config)) => {
-            let level = config.level.level();
-            let zstd_level = if level == 0 {
-                zstd::DEFAULT_COMPRESSION_LEVEL
-            } else {
-                level as i32
-            };
-            let mut zstd_encoder = zstd::Encoder::new(dest_buf.writer(), zstd_level)?;
-            io::copy(&mut &src_buf[0..len], &mut zstd_encoder)?;
-            zstd_encoder.finish()?;
+        let last = self.chunks.last_mut().expect("at least one chunk");
+        let len = buf.len().min(self.chunk_size - last.len());
+        last.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A message to be sent with the flag of whether it may be compressed.
+///
+/// The messages are compressed by the encoding of the stream by default, and the ones wrapped by
+/// [`MaybeCompressed::uncompressed`] are sent without compression, e.g., messages carrying
+/// payloads which are already compressed. The compressed flag of them is unset, so that peers
+/// accept them whatever the `grpc-encoding` of the stream is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaybeCompressed<T> {
+    message: T,
+    compress: bool,
+}
+
+impl<T> MaybeCompressed<T> {
+    /// Wrap a message which is compressed by the encoding of the stream.
+    pub fn new(message: T) -> Self {
+        Self {
+            message,
+            compress: true,
         }
-        _ => {}
-    };
+    }
 
-    src_buf.advance(len);
-    Ok(())
+    /// Wrap a message which is sent without compression.
+    pub fn uncompressed(message: T) -> Self {
+        Self {
+            message,
+            compress: false,
+        }
+    }
+
+    /// Whether the message may be compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compress
+    }
+
+    /// Get the message.
+    pub fn into_inner(self) -> T {
+        self.message
+    }
+}
+
+impl<T> From<T> for MaybeCompressed<T> {
+    fn from(message: T) -> Self {
+        Self::new(message)
+    }
+}
+
+/// Items of outgoing message streams, which are the messages, or the messages wrapped by
+/// [`MaybeCompressed`] with the flags of compression.
+pub trait OutgoingMessage: Send + 'static {
+    /// The message to be encoded.
+    type Message: Message + 'static;
+
+    /// Get the message and whether it may be compressed.
+    fn into_message(self) -> (Self::Message, bool);
+}
+
+impl<T: Message + 'static> OutgoingMessage for T {
+    type Message = T;
+
+    fn into_message(self) -> (T, bool) {
+        (self, true)
+    }
+}
+
+impl<T: Message + 'static> OutgoingMessage for MaybeCompressed<T> {
+    type Message = T;
+
+    fn into_message(self) -> (T, bool) {
+        (self.message, self.compress)
+    }
+}
+
+/// Extension of outgoing message streams for controlling the compression of each message.
+///
+/// # Example
+///
+/// ```
+/// use futures::Stream;
+/// use volo_grpc::{
+///     Status,
+///     codec::compression::{CompressionStreamExt, MaybeCompressed},
+/// };
+///
+/// # struct Chunk { content_type: &'static str }
+/// fn upload(
+///     chunks: impl Stream<Item = Result<Chunk, Status>>,
+/// ) -> impl Stream<Item = Result<MaybeCompressed<Chunk>, Status>> {
+///     // images are already compressed
+///     chunks.skip_compression_if(|chunk: &Chunk| chunk.content_type.starts_with("image/"))
+/// }
+/// ```
+pub trait CompressionStreamExt<T>: Stream<Item = Result<T, Status>> + Sized {
+    /// Wrap the messages by [`MaybeCompressed`], and the ones for which `skip` returns `true`
+    /// are sent without compression.
+    ///
+    /// The flag is carried by each message, so the stream can be adapted further, e.g., merged
+    /// with other streams or buffered, before it's sent.
+    fn skip_compression_if<P>(self, skip: P) -> SkipCompression<Self, P>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        SkipCompression { inner: self, skip }
+    }
+}
+
+impl<S, T> CompressionStreamExt<T> for S where S: Stream<Item = Result<T, Status>> {}
+
+/// Stream returned by [`CompressionStreamExt::skip_compression_if`].
+#[pin_project]
+pub struct SkipCompression<S, P> {
+    #[pin]
+    inner: S,
+    skip: P,
+}
+
+impl<S, T, P> Stream for SkipCompression<S, P>
+where
+    S: Stream<Item = Result<T, Status>>,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<MaybeCompressed<T>, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.inner.poll_next(cx));
+        Poll::Ready(item.map(|item| {
+            item.map(|message| {
+                if (this.skip)(&message) {
+                    MaybeCompressed::uncompressed(message)
+                } else {
+                    MaybeCompressed::new(message)
+                }
+            })
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S, P> fmt::Debug for SkipCompression<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SkipCompression").finish_non_exhaustive()
+    }
 }
 
 /// Decompress `len` bytes from `src_buf` into `dest_buf`.
@@ -316,28 +696,45 @@ pub(crate) fn decompress(
     encoding: CompressionEncoding,
     src_buf: &mut BytesMut,
     dest_buf: &mut BytesMut,
+) -> Result<(), io::Error> {
+    decompress_limited(encoding, src_buf, dest_buf, usize::MAX)
+}
+
+/// Decompress `len` bytes from `src_buf` into `dest_buf`, and stop after more than `max` bytes
+/// are written to `dest_buf`.
+///
+/// The decompressed message exceeds the limit if `dest_buf` is longer than `max` on return, a
+/// small message may be expanded to a huge one, so it must not be decompressed unboundedly.
+pub(crate) fn decompress_limited(
+    encoding: CompressionEncoding,
+    src_buf: &mut BytesMut,
+    dest_buf: &mut BytesMut,
+    max: usize,
 ) -> Result<(), io::Error> {
     let len = src_buf.len();
-    let estimate_decompressed_len = len * 2;
+    let estimate_decompressed_len = len.saturating_mul(2).min(max);
     let capacity = ((estimate_decompressed_len / BUFFER_SIZE) + 1) * BUFFER_SIZE;
 
     dest_buf.reserve(capacity);
 
+    // read one more byte to tell if the limit is exceeded
+    #[cfg(feature = "compress")]
+    let limit = (max as u64).saturating_add(1);
     match encoding {
         #[cfg(feature = "gzip")]
         CompressionEncoding::Gzip(_) => {
-            let mut gz_decoder = GzDecoder::new(&src_buf[0..len]);
-            io::copy(&mut gz_decoder, &mut dest_buf.writer())?;
+            let gz_decoder = GzDecoder::new(&src_buf[0..len]);
+            io::copy(&mut gz_decoder.take(limit), &mut dest_buf.writer())?;
         }
         #[cfg(feature = "zlib")]
         CompressionEncoding::Zlib(_) => {
-            let mut zlib_decoder = ZlibDecoder::new(&src_buf[0..len]);
-            io::copy(&mut zlib_decoder, &mut dest_buf.writer())?;
+            let zlib_decoder = ZlibDecoder::new(&src_buf[0..len]);
+            io::copy(&mut zlib_decoder.take(limit), &mut dest_buf.writer())?;
         }
         #[cfg(feature = "zstd")]
         CompressionEncoding::Zstd(_) => {
-            let mut zstd_decoder = zstd::Decoder::new(&src_buf[0..len])?;
-            io::copy(&mut zstd_decoder, &mut dest_buf.writer())?;
+            let zstd_decoder = zstd::Decoder::new(&src_buf[0..len])?;
+            io::copy(&mut zstd_decoder.take(limit), &mut dest_buf.writer())?;
         }
         _ => {}
     };
@@ -360,16 +757,12 @@ mod tests {
     use crate::codec::compression::ZstdConfig;
     use crate::codec::{
         BUFFER_SIZE,
-        compression::{CompressionEncoding, compress, decompress},
+        compression::{CompressionEncoding, compress_chunks, decompress},
     };
 
     #[test]
     fn test_consistency_for_compression() {
-        let mut src = BytesMut::with_capacity(BUFFER_SIZE);
-        let mut compress_buf = BytesMut::new();
-        let mut de_data = BytesMut::with_capacity(BUFFER_SIZE);
-        let test_data = &b"test compression"[..];
-        src.extend_from_slice(test_data);
+        let test_data = b"test compression ".repeat(BUFFER_SIZE / 8);
 
         let encodings = [
             #[cfg(feature = "gzip")]
@@ -388,10 +781,81 @@ mod tests {
         ];
 
         for encoding in encodings {
-            compress_buf.clear();
-            compress(encoding, &mut src, &mut compress_buf).expect("compress failed:");
-            decompress(encoding, &mut compress_buf, &mut de_data).expect("decompress failed:");
+            // compressed from slices into chunks with a reserved prefix
+            let chunks =
+                compress_chunks(encoding, test_data.chunks(1000), 5, 16).expect("compress failed:");
+            assert!(chunks.len() > 1);
+            assert!(chunks.iter().all(|chunk| chunk.len() <= 16));
+            assert_eq!(&chunks[0][..5], &[0; 5]);
+
+            let mut compress_buf = chunks.concat()[5..].into();
+            let mut de_data = BytesMut::with_capacity(BUFFER_SIZE);
+            if encoding == CompressionEncoding::Identity {
+                de_data = compress_buf;
+            } else {
+                decompress(encoding, &mut compress_buf, &mut de_data).expect("decompress failed:");
+            }
             assert_eq!(test_data, de_data.as_ref());
         }
     }
 }
+
+#[cfg(all(test, feature = "gzip", feature = "zlib"))]
+mod cache_tests {
+    use http::{HeaderMap, HeaderValue};
+    use volo::net::Address;
+
+    use super::{ACCEPT_ENCODING_HEADER, CompressionCache, CompressionEncoding};
+
+    fn peer(port: u16) -> Address {
+        Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    #[test]
+    fn test_compression_cache() {
+        let config = [
+            CompressionEncoding::Gzip(None),
+            CompressionEncoding::Zlib(None),
+        ];
+        let cache = CompressionCache::default();
+
+        // unknown peers use the first encoding
+        assert_eq!(
+            cache.select(&peer(1), &config),
+            Some(CompressionEncoding::Gzip(None))
+        );
+
+        // peers without the header are still unknown
+        assert!(!cache.record(&peer(1), &HeaderMap::new()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_ENCODING_HEADER,
+            HeaderValue::from_static("zlib, unknown"),
+        );
+        assert!(cache.record(&peer(1), &headers));
+        assert_eq!(
+            cache.select(&peer(1), &config),
+            Some(CompressionEncoding::Zlib(None))
+        );
+        assert_eq!(cache.select(&peer(1), &config[..1]), None);
+        assert_eq!(
+            cache.select(&peer(1), &[CompressionEncoding::Identity]),
+            Some(CompressionEncoding::Identity)
+        );
+        // other peers are not affected
+        assert_eq!(
+            cache.select(&peer(2), &config),
+            Some(CompressionEncoding::Gzip(None))
+        );
+
+        // fallback after rejected
+        cache.reject(&peer(2), CompressionEncoding::Gzip(None));
+        assert_eq!(
+            cache.select(&peer(2), &config),
+            Some(CompressionEncoding::Zlib(None))
+        );
+        cache.reject(&peer(2), CompressionEncoding::Zlib(None));
+        assert_eq!(cache.select(&peer(2), &config), None);
+    }
+}
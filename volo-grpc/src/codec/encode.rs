@@ -1,15 +1,17 @@
-use bytes::{BufMut, Bytes};
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use http_body::Frame;
 use linkedbytes::Node;
-use pilota::{LinkedBytes, pb::Message};
+use pilota::LinkedBytes;
 
 use super::{DefaultEncoder, PREFIX_LEN};
 use crate::{
     BoxStream, Status,
     codec::{
         BUFFER_SIZE, Encoder,
-        compression::{CompressionEncoding, compress},
+        compression::{
+            ChunkCompressor, CompressionEncoding, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE, OutgoingMessage,
+        },
     },
 };
 
@@ -19,7 +21,7 @@ pub fn encode<T, S>(
 ) -> BoxStream<'static, Result<Frame<Bytes>, Status>>
 where
     S: Stream<Item = Result<T, Status>> + Send + 'static,
-    T: Message + 'static,
+    T: OutgoingMessage,
 {
     Box::pin(async_stream::stream! {
         futures_util::pin_mut!(source);
@@ -27,58 +29,74 @@ where
         loop {
             match source.next().await {
                 Some(Ok(item)) => {
-                    let mut buf = LinkedBytes::with_capacity(BUFFER_SIZE);
-                    let mut compressed_buf = if compression_encoding.is_some() {
-                        LinkedBytes::with_capacity(BUFFER_SIZE)
-                    } else {
-                        LinkedBytes::new()
-                    };
-
-                    buf.reserve(PREFIX_LEN);
-                    unsafe {
-                        buf.advance_mut(PREFIX_LEN);
-                    }
+                    // messages wrapped by `MaybeCompressed::uncompressed` and the identity
+                    // encoding are sent uncompressed
+                    let (item, compress) = item.into_message();
+                    let compression_encoding = compression_encoding.filter(|encoding| {
+                        compress && *encoding != CompressionEncoding::Identity
+                    });
 
-                    let mut encoder=DefaultEncoder::default();
+                    let mut buf = LinkedBytes::with_capacity(BUFFER_SIZE);
+                    let mut encoder = DefaultEncoder::<T::Message>::default();
 
-                    if let Some(config)=compression_encoding{
-                        encoder.encode(item, &mut compressed_buf)
-                            .map_err(|err| Status::internal(format!("Error encoding: {err}")))?;
-                        compress(config,&mut compressed_buf.concat(), buf.bytes_mut())
-                            .map_err(|err| Status::internal(format!("Error compressing: {err}")))?;
-                    } else {
+                    let Some(config) = compression_encoding else {
+                        buf.reserve(PREFIX_LEN);
+                        unsafe {
+                            buf.advance_mut(PREFIX_LEN);
+                        }
                         encoder.encode(item, &mut buf)
                             .map_err(|err| Status::internal(format!("Error encoding: {err}")))?;
-                    }
 
-                    let len = buf.len() - PREFIX_LEN;
-                    assert!(len <= u32::MAX as usize);
-                    {
+                        let len = buf.len() - PREFIX_LEN;
+                        assert!(len <= u32::MAX as usize);
                         if let Some(node) = buf.get_list_mut(0) {
                             match node {
                                 linkedbytes::Node::BytesMut(bytes_mut) => {
-                                    let mut dest = &mut bytes_mut[..PREFIX_LEN];
-                                    dest.put_u8(compression_encoding.is_some() as u8);
-                                    dest.put_u32(len as u32);
+                                    put_prefix(&mut bytes_mut[..PREFIX_LEN], false, len);
                                 }
                                 _ => unreachable!("reserve_node_idx is not a bytesmut"),
                             };
                         } else {
-                            let mut dest = &mut buf.bytes_mut()[..PREFIX_LEN];
-                            dest.put_u8(compression_encoding.is_some() as u8);
-                            dest.put_u32(len as u32);
+                            put_prefix(&mut buf.bytes_mut()[..PREFIX_LEN], false, len);
                         }
-                    }
 
-                    // send each node in linked bytes as a separate frame
+                        // send each node in linked bytes as a separate frame
+                        for node in buf.into_iter_list() {
+                            let bytes = match node {
+                                Node::Bytes(bytes) => bytes,
+                                Node::BytesMut(bytesmut) => bytesmut.freeze(),
+                                Node::FastStr(faststr) => faststr.into_bytes(),
+                            };
+                            if !bytes.is_empty() {
+                                yield Ok(Frame::data(bytes));
+                            }
+                        }
+                        continue;
+                    };
+
+                    encoder.encode(item, &mut buf)
+                        .map_err(|err| Status::internal(format!("Error encoding: {err}")))?;
+                    // the nodes are compressed one by one without being concatenated, and each
+                    // of them is released once it's compressed
+                    let compress_err = |err| Status::internal(format!("Error compressing: {err}"));
+                    let mut compressor =
+                        ChunkCompressor::new(config, PREFIX_LEN, MIN_CHUNK_SIZE, MAX_CHUNK_SIZE)
+                            .map_err(compress_err)?;
                     for node in buf.into_iter_list() {
-                        let bytes = match node {
-                            Node::Bytes(bytes) => bytes,
-                            Node::BytesMut(bytesmut) => bytesmut.freeze(),
-                            Node::FastStr(faststr) => faststr.into_bytes(),
-                        };
-                        if !bytes.is_empty() {
-                            yield Ok(Frame::data(bytes));
+                        compressor.write(node.as_ref()).map_err(compress_err)?;
+                    }
+                    // the chunks are sent after the length of the whole compressed message is
+                    // known, which must be in the prefix before the message
+                    let mut chunks = compressor.finish().map_err(compress_err)?;
+
+                    let len = chunks.iter().map(BytesMut::len).sum::<usize>() - PREFIX_LEN;
+                    assert!(len <= u32::MAX as usize);
+                    put_prefix(&mut chunks[0][..PREFIX_LEN], true, len);
+
+                    // send each chunk as a separate frame
+                    for chunk in chunks {
+                        if !chunk.is_empty() {
+                            yield Ok(Frame::data(chunk.freeze()));
                         }
                     }
                 },
@@ -89,6 +107,12 @@ where
     })
 }
 
+/// Writes the compressed flag and the length of a message into the prefix.
+fn put_prefix(mut dest: &mut [u8], compressed: bool, len: usize) {
+    dest.put_u8(compressed as u8);
+    dest.put_u32(len as u32);
+}
+
 pub mod tests {
 
     #[derive(Debug, Default, Clone, PartialEq)]
@@ -250,4 +274,117 @@ pub mod tests {
 
         assert!(stream.next().await.is_none());
     }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_encode_skip_compression() {
+        use super::*;
+        use crate::codec::compression::{CompressionStreamExt, GzipConfig};
+
+        let source = futures::stream::iter([
+            Ok(EchoRequest {
+                message: "Volo".into(),
+            }),
+            Ok(EchoRequest {
+                message: "image".into(),
+            }),
+            Ok(EchoRequest {
+                message: "Volo".into(),
+            }),
+        ])
+        .skip_compression_if(|req: &EchoRequest| req.message == "image");
+
+        let compression_encoding = Some(CompressionEncoding::Gzip(Some(GzipConfig::default())));
+        let frames = encode(source, compression_encoding)
+            .map(|frame| frame.unwrap().into_data().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0][0], 1);
+        assert_eq!(&frames[1][..PREFIX_LEN], b"\x00\x00\x00\x00\x07");
+        assert_eq!(&frames[1][PREFIX_LEN..], b"\x0a\x05image");
+        assert_eq!(frames[2][0], 1);
+
+        // the flags are carried by the messages through buffering adapters
+        let source = futures::stream::iter([
+            Ok(EchoRequest {
+                message: "image".into(),
+            }),
+            Ok(EchoRequest {
+                message: "Volo".into(),
+            }),
+        ])
+        .skip_compression_if(|req: &EchoRequest| req.message == "image")
+        .map(futures::future::ready)
+        .buffered(2);
+        let frames = encode(source, compression_encoding)
+            .map(|frame| frame.unwrap().into_data().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(frames[0][0], 0);
+        assert_eq!(frames[1][0], 1);
+
+        // the identity encoding is never compressed
+        let source = futures::stream::iter([Ok(EchoRequest {
+            message: "Volo".into(),
+        })]);
+        let frame = encode(source, Some(CompressionEncoding::Identity))
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            &frame.data_ref().unwrap()[..],
+            b"\x00\x00\x00\x00\x06\x0a\x04Volo"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn test_encode_chunked() {
+        use bytes::BytesMut;
+
+        use super::*;
+        use crate::codec::compression::{ZstdConfig, decompress};
+
+        // incompressible content
+        let mut state = 1u64;
+        let message = (0..MIN_CHUNK_SIZE * 8)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                char::from(b'a' + (state >> 59) as u8)
+            })
+            .collect::<String>();
+        let source = futures::stream::iter([Ok(EchoRequest {
+            message: message.clone().into(),
+        })]);
+
+        let compression_encoding = CompressionEncoding::Zstd(Some(ZstdConfig::default()));
+        let frames = encode(source, Some(compression_encoding))
+            .map(|frame| frame.unwrap().into_data().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        // the sizes of chunks grow
+        assert!(frames.len() > 1);
+        assert_eq!(frames[0].len(), MIN_CHUNK_SIZE);
+        assert_eq!(frames[1].len(), MIN_CHUNK_SIZE * 2);
+        assert!(frames.iter().all(|frame| frame.len() <= MAX_CHUNK_SIZE));
+
+        let data = frames.concat();
+        assert_eq!(data[0], 1);
+        let len = u32::from_be_bytes(data[1..PREFIX_LEN].try_into().unwrap()) as usize;
+        assert_eq!(len, data.len() - PREFIX_LEN);
+
+        let mut compressed_data = BytesMut::from(&data[PREFIX_LEN..]);
+        let mut uncompressed_data_mut = BytesMut::new();
+        decompress(
+            compression_encoding,
+            &mut compressed_data,
+            &mut uncompressed_data_mut,
+        )
+        .unwrap();
+        let decoded: EchoRequest =
+            pilota::pb::Message::decode(uncompressed_data_mut.freeze()).unwrap();
+        assert_eq!(decoded.message, message);
+    }
 }
@@ -0,0 +1,399 @@
+//! Limits of message sizes.
+//!
+//! The limits are checked by the length prefixes of messages in the bodies, so that an oversized
+//! message is rejected with `RESOURCE_EXHAUSTED` before it is buffered. For compressed messages,
+//! the limits apply to the compressed lengths, and the limit of decoding also applies to the
+//! decompressed lengths, which is checked while decompressing.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::StreamExt;
+use http_body::{Body, Frame, SizeHint};
+use volo::memory::{MemoryAccount, MemoryCharge, MemoryLimitExceeded};
+
+use super::{
+    PREFIX_LEN,
+    compression::{CompressionEncoding, decompress_limited},
+    transform::reframe,
+};
+use crate::{
+    BoxStream, Status,
+    body::{BoxBody, boxed},
+};
+
+/// Tracks the length prefixes of messages in a sequence of data frames.
+#[derive(Debug)]
+struct SizeChecker {
+    max: usize,
+    prefix: [u8; PREFIX_LEN],
+    prefix_len: usize,
+    remaining: usize,
+}
+
+impl SizeChecker {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            prefix: [0; PREFIX_LEN],
+            prefix_len: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Returns the length of the first message exceeding the limit in `data`.
+    fn check(&mut self, data: &[u8]) -> Result<(), usize> {
+        let max = self.max;
+        self.scan(data, |len| if len > max { Err(len) } else { Ok(()) })
+    }
+
+    /// Calls `on_message` with the length of each message whose prefix is completed in `data`,
+    /// and stops at the first error.
+    fn scan<E>(
+        &mut self,
+        mut data: &[u8],
+        mut on_message: impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<(), E> {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let n = self.remaining.min(data.len());
+                self.remaining -= n;
+                data.advance(n);
+                continue;
+            }
+            let n = (PREFIX_LEN - self.prefix_len).min(data.len());
+            self.prefix[self.prefix_len..self.prefix_len + n].copy_from_slice(&data[..n]);
+            self.prefix_len += n;
+            data.advance(n);
+            if self.prefix_len == PREFIX_LEN {
+                self.prefix_len = 0;
+                let len = (&self.prefix[1..]).get_u32() as usize;
+                on_message(len)?;
+                self.remaining = len;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps the received `body` to reject messages larger than `max` bytes.
+pub(crate) fn limit_decoding(body: BoxBody, max: usize) -> BoxBody {
+    BoxBody::new(DecodingLimit {
+        inner: body,
+        checker: SizeChecker::new(max),
+        exceeded: false,
+    })
+}
+
+/// Wraps the received `body` to decompress the compressed messages, and reject them if they are
+/// larger than `max` bytes after decompression.
+///
+/// The messages are framed again as uncompressed ones.
+pub(crate) fn limit_decompression(
+    body: BoxBody,
+    encoding: CompressionEncoding,
+    max: usize,
+) -> BoxBody {
+    let stream = http_body_util::BodyStream::new(body);
+    let mut buf = BytesMut::new();
+    boxed(http_body_util::StreamBody::new(reframe(
+        stream,
+        move |flag, data| {
+            if flag == 0 {
+                return Ok((flag, data));
+            }
+            let mut src = BytesMut::from(data);
+            buf.clear();
+            decompress_limited(encoding, &mut src, &mut buf, max)
+                .map_err(|err| Status::internal(format!("Error decompressing: {err}")))?;
+            if buf.len() > max {
+                return Err(Status::resource_exhausted(format!(
+                    "decompressed message length too large: found more than {max} bytes, the \
+                     limit is: {max} bytes"
+                )));
+            }
+            Ok((0, buf.split().freeze()))
+        },
+    )))
+}
+
+/// Wraps the `stream` of frames to be sent to reject messages larger than `max` bytes.
+pub(crate) fn limit_encoding(
+    stream: BoxStream<'static, Result<Frame<Bytes>, Status>>,
+    max: usize,
+) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+    let mut checker = SizeChecker::new(max);
+    Box::pin(
+        stream
+            .map(move |frame| {
+                let data = match &frame {
+                    Ok(frame) => frame.data_ref(),
+                    Err(_) => None,
+                };
+                if let Some(data) = data {
+                    if let Err(len) = checker.check(data) {
+                        return Err(Status::resource_exhausted(format!(
+                            "encoded message length too large: found {len} bytes, the limit is: \
+                             {max} bytes"
+                        )));
+                    }
+                }
+                frame
+            })
+            // stop after the error, which will be sent as the trailers
+            .scan(false, |done, frame| {
+                if *done {
+                    return futures::future::ready(None);
+                }
+                *done = frame.is_err();
+                futures::future::ready(Some(frame))
+            }),
+    )
+}
+
+struct DecodingLimit {
+    inner: BoxBody,
+    checker: SizeChecker,
+    exceeded: bool,
+}
+
+impl Body for DecodingLimit {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.exceeded {
+            return Poll::Ready(None);
+        }
+        let frame = match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            other => return other,
+        };
+        if let Some(data) = frame.data_ref() {
+            let max = self.checker.max;
+            if let Err(len) = self.checker.check(data) {
+                self.exceeded = true;
+                return Poll::Ready(Some(Err(Status::resource_exhausted(format!(
+                    "decoded message length too large: found {len} bytes, the limit is: {max} \
+                     bytes"
+                )))));
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.exceeded || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Wraps the received `body` to charge the messages to the `account`.
+///
+/// A message is charged when its prefix is received, since the decoder buffers it until it's
+/// complete, and released when the next message begins. The call fails with `RESOURCE_EXHAUSTED`
+/// if the limit of the account is exceeded.
+pub(crate) fn account_decoding(body: BoxBody, account: MemoryAccount) -> BoxBody {
+    BoxBody::new(DecodingAccount {
+        inner: body,
+        checker: SizeChecker::new(usize::MAX),
+        account,
+        charge: None,
+        exceeded: false,
+    })
+}
+
+struct DecodingAccount {
+    inner: BoxBody,
+    checker: SizeChecker,
+    account: MemoryAccount,
+    // the charge of the message being received
+    charge: Option<MemoryCharge>,
+    exceeded: bool,
+}
+
+impl Body for DecodingAccount {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.exceeded {
+            return Poll::Ready(None);
+        }
+        let frame = match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            other => return other,
+        };
+        if let Some(data) = frame.data_ref() {
+            let this = &mut *self;
+            let result = this
+                .checker
+                .scan(data, |len| -> Result<(), MemoryLimitExceeded> {
+                    this.charge = None;
+                    this.charge = Some(this.account.charge(len)?);
+                    Ok(())
+                });
+            if let Err(err) = result {
+                self.exceeded = true;
+                return Poll::Ready(Some(Err(Status::resource_exhausted(err.to_string()))));
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.exceeded || self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod limit_tests {
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http_body::Frame;
+    use http_body_util::BodyExt;
+    use volo::memory::MemoryTracker;
+
+    use super::{SizeChecker, account_decoding, limit_decoding, limit_encoding};
+    use crate::{Code, body::BoxBody};
+
+    fn message(len: usize) -> Vec<u8> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+        buf.resize(buf.len() + len, b'v');
+        buf
+    }
+
+    #[test]
+    fn test_size_checker() {
+        let mut checker = SizeChecker::new(4);
+        let mut data = message(4);
+        data.extend(message(0));
+        data.extend(message(3));
+        assert_eq!(checker.check(&data), Ok(()));
+
+        // the prefix and the message are split into frames
+        let data = message(5);
+        let mut checker = SizeChecker::new(4);
+        assert_eq!(checker.check(&data[..2]), Ok(()));
+        assert_eq!(checker.check(&data[2..]), Err(5));
+
+        let data = [message(2), message(2)].concat();
+        let mut checker = SizeChecker::new(2);
+        for chunk in data.chunks(3) {
+            assert_eq!(checker.check(chunk), Ok(()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limit_decoding() {
+        let body = BoxBody::new(
+            http_body_util::Full::new(Bytes::from([message(2), message(8)].concat()))
+                .map_err(|err| match err {}),
+        );
+        let status = limit_decoding(body, 4).collect().await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let body = BoxBody::new(
+            http_body_util::Full::new(Bytes::from(message(4))).map_err(|err| match err {}),
+        );
+        let data = limit_decoding(body, 4).collect().await.unwrap().to_bytes();
+        assert_eq!(data.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_account_decoding() {
+        let body = || {
+            let frames = [message(4), message(8), message(4)]
+                .map(|data| Ok::<_, crate::Status>(Frame::data(Bytes::from(data))));
+            BoxBody::new(http_body_util::StreamBody::new(futures::stream::iter(
+                frames,
+            )))
+        };
+        let tracker = MemoryTracker::new().request_limit(10);
+
+        // only the message being received is charged
+        let account = tracker.account("Echo");
+        let data = account_decoding(body(), account.clone())
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(data.len(), 31);
+        assert_eq!(account.used(), 0);
+        assert_eq!(account.peak(), 8);
+
+        let tracker = MemoryTracker::new().request_limit(6);
+        let status = account_decoding(body(), tracker.account("Echo"))
+            .collect()
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(tracker.stats("Echo").unwrap().rejected, 1);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn test_limit_decompression() {
+        use super::limit_decompression;
+        use crate::codec::compression::{CompressionEncoding, compress_chunks};
+
+        let compressed = |len: usize| {
+            let data = vec![b'v'; len];
+            let chunks =
+                compress_chunks(CompressionEncoding::Gzip(None), [&data[..]], 0, len).unwrap();
+            let data = chunks.concat();
+            let mut buf = vec![1];
+            buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            buf.extend_from_slice(&data);
+            buf
+        };
+        let body = |data: Vec<u8>| {
+            BoxBody::new(http_body_util::Full::new(Bytes::from(data)).map_err(|err| match err {}))
+        };
+
+        // the compressed lengths are small, but the decompressed ones are not
+        let data = [compressed(16), message(2)].concat();
+        let data = limit_decompression(body(data), CompressionEncoding::Gzip(None), 16)
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(&data[..], &[message(16), message(2)].concat()[..]);
+
+        let data = compressed(1024 * 1024);
+        assert!(data.len() < 16 * 1024);
+        let status = limit_decompression(body(data), CompressionEncoding::Gzip(None), 16 * 1024)
+            .collect()
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn test_limit_encoding() {
+        let frames =
+            [message(2), message(8), message(1)].map(|data| Ok(Frame::data(Bytes::from(data))));
+        let mut stream = limit_encoding(Box::pin(futures::stream::iter(frames)), 4);
+        assert!(stream.next().await.unwrap().is_ok());
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -6,6 +6,8 @@
 pub mod compression;
 pub mod decode;
 pub mod encode;
+pub(crate) mod limit;
+pub mod transform;
 
 use std::{io, marker::PhantomData, mem::size_of};
 
@@ -0,0 +1,340 @@
+//! Transforms of encoded messages for end-to-end payload protection.
+//!
+//! A [`MessageTransform`] is applied to the bytes of each message after it is encoded (and
+//! compressed) and before it is framed, and to the bytes of each received message after it is
+//! deframed and before it is decompressed and decoded. It can be used to encrypt or sign messages,
+//! so that proxies between the client and the server can route the calls by headers but can not
+//! read or tamper with the payloads.
+//!
+//! The transform is negotiated by the [`TRANSFORM_HEADER`] metadata. The client sends the name of
+//! its transform and transforms the requests. The server rejects the call with `UNIMPLEMENTED` if
+//! it does not have a transform with the name, otherwise it transforms responses with the same
+//! transform and sends the name back.
+//!
+//! The transform is required by default, since a proxy may strip the metadata, so that the
+//! untransformed messages would be accepted silently. A server with transforms rejects calls
+//! without the metadata, and a client with a transform fails calls if the responses do not have
+//! it, both with `FAILED_PRECONDITION`. It can be disabled with `message_transform_required` of
+//! the builders, e.g., when migrating the clients to the transform.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_grpc::{Status, codec::transform::MessageTransform, codegen::Bytes, server::Server};
+//!
+//! /// A toy transform flipping all bits, use a real cipher instead.
+//! struct Flip;
+//!
+//! impl MessageTransform for Flip {
+//!     fn name(&self) -> &str {
+//!         "flip"
+//!     }
+//!
+//!     fn encode(&self, data: Bytes) -> Result<Bytes, Status> {
+//!         Ok(data.iter().map(|b| !b).collect())
+//!     }
+//!
+//!     fn decode(&self, data: Bytes) -> Result<Bytes, Status> {
+//!         Ok(data.iter().map(|b| !b).collect())
+//!     }
+//! }
+//!
+//! let server = Server::new().message_transform(Flip);
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use http::HeaderMap;
+use http_body::Frame;
+
+use super::PREFIX_LEN;
+use crate::{
+    BoxStream, Status,
+    body::{BoxBody, boxed},
+};
+
+/// The metadata key for negotiating the [`MessageTransform`] by its name.
+pub const TRANSFORM_HEADER: &str = "volo-message-transform";
+
+/// Transform of the bytes of encoded messages, see [the module documentation](self) for more
+/// details.
+pub trait MessageTransform: Send + Sync + 'static {
+    /// The name of the transform, which should be a valid metadata value, e.g.,
+    /// `aes-256-gcm-v1`.
+    ///
+    /// Different versions of keys or algorithms should use different names.
+    fn name(&self) -> &str;
+
+    /// Transforms the bytes of a message to be sent, after it is encoded and compressed.
+    fn encode(&self, data: Bytes) -> Result<Bytes, Status>;
+
+    /// Transforms the bytes of a received message back, before it is decompressed and decoded.
+    ///
+    /// An error, e.g., of a failed verification, fails the call.
+    fn decode(&self, data: Bytes) -> Result<Bytes, Status>;
+}
+
+impl fmt::Debug for dyn MessageTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MessageTransform")
+            .field(&self.name())
+            .finish()
+    }
+}
+
+/// Finds the transform with the name in the headers.
+///
+/// Returns an `UNIMPLEMENTED` status if there is no transform with the name. If the headers do not
+/// contain the name, returns `Ok(None)`, or a `FAILED_PRECONDITION` status if there are
+/// `transforms` and they're `required`.
+#[allow(clippy::result_large_err)]
+pub(crate) fn negotiate(
+    headers: &HeaderMap,
+    transforms: &[Arc<dyn MessageTransform>],
+    required: bool,
+) -> Result<Option<Arc<dyn MessageTransform>>, Status> {
+    let Some(value) = headers.get(TRANSFORM_HEADER) else {
+        if required && !transforms.is_empty() {
+            return Err(Status::failed_precondition(format!(
+                "message transform is required, but `{TRANSFORM_HEADER}` is missing"
+            )));
+        }
+        return Ok(None);
+    };
+    let name = value.to_str().unwrap_or_default();
+    match transforms.iter().find(|t| t.name() == name) {
+        Some(transform) => Ok(Some(transform.clone())),
+        None => Err(Status::unimplemented(format!(
+            "message transform `{name}` is not supported"
+        ))),
+    }
+}
+
+/// Transforms the messages in the `stream` of frames to be sent.
+pub(crate) fn transform_encoding(
+    stream: BoxStream<'static, Result<Frame<Bytes>, Status>>,
+    transform: Arc<dyn MessageTransform>,
+) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+    Box::pin(reframe(stream, move |flag, data| {
+        Ok((flag, transform.encode(data)?))
+    }))
+}
+
+/// Transforms the messages in the received `body` back.
+pub(crate) fn transform_decoding(body: BoxBody, transform: Arc<dyn MessageTransform>) -> BoxBody {
+    let stream = http_body_util::BodyStream::new(body);
+    boxed(http_body_util::StreamBody::new(reframe(
+        stream,
+        move |flag, data| Ok((flag, transform.decode(data)?)),
+    )))
+}
+
+/// Deframes the messages in `stream`, transforms them with their compressed-flags by `f`, and
+/// frames them again.
+///
+/// Trailers are passed through, and the stream ends after the first error.
+pub(super) fn reframe<S, F>(
+    stream: S,
+    mut f: F,
+) -> impl Stream<Item = Result<Frame<Bytes>, Status>> + Send
+where
+    S: Stream<Item = Result<Frame<Bytes>, Status>> + Send + 'static,
+    F: FnMut(u8, Bytes) -> Result<(u8, Bytes), Status> + Send + 'static,
+{
+    async_stream::stream! {
+        futures_util::pin_mut!(stream);
+        let mut buf = BytesMut::new();
+
+        while let Some(frame) = stream.next().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(status) => {
+                    yield Err(status);
+                    return;
+                }
+            };
+            let data = match frame.into_data() {
+                Ok(data) => data,
+                Err(trailers) => {
+                    yield Ok(trailers);
+                    continue;
+                }
+            };
+            buf.put(data);
+
+            while buf.len() >= PREFIX_LEN {
+                let len = (&buf[1..PREFIX_LEN]).get_u32() as usize;
+                if buf.len() < PREFIX_LEN + len {
+                    break;
+                }
+                let flag = buf[0];
+                buf.advance(PREFIX_LEN);
+                let (flag, message) = match f(flag, buf.split_to(len).freeze()) {
+                    Ok(message) => message,
+                    Err(status) => {
+                        yield Err(status);
+                        return;
+                    }
+                };
+                let Ok(len) = u32::try_from(message.len()) else {
+                    yield Err(Status::internal("transformed message is too large"));
+                    return;
+                };
+                let mut prefix = BytesMut::with_capacity(PREFIX_LEN);
+                prefix.put_u8(flag);
+                prefix.put_u32(len);
+                yield Ok(Frame::data(prefix.freeze()));
+                if !message.is_empty() {
+                    yield Ok(Frame::data(message));
+                }
+            }
+        }
+
+        if !buf.is_empty() {
+            yield Err(Status::internal("unexpected EOF transforming messages"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod transform_tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http_body::Frame;
+    use http_body_util::BodyExt;
+
+    use super::{
+        MessageTransform, TRANSFORM_HEADER, negotiate, transform_decoding, transform_encoding,
+    };
+    use crate::{
+        Code, Status,
+        body::{BoxBody, boxed},
+    };
+
+    struct Xor(u8);
+
+    impl MessageTransform for Xor {
+        fn name(&self) -> &str {
+            "xor"
+        }
+
+        fn encode(&self, data: Bytes) -> Result<Bytes, Status> {
+            let mut data: Vec<u8> = data.iter().map(|b| b ^ self.0).collect();
+            // append a checksum as a signature
+            data.push(data.iter().fold(0, |acc, b| acc ^ b));
+            Ok(data.into())
+        }
+
+        fn decode(&self, data: Bytes) -> Result<Bytes, Status> {
+            let (checksum, data) = data
+                .split_last()
+                .ok_or_else(|| Status::data_loss("missing checksum"))?;
+            if data.iter().fold(0, |acc, b| acc ^ b) != *checksum {
+                return Err(Status::data_loss("bad checksum"));
+            }
+            Ok(data.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+
+    fn message(payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_transform() {
+        let transform: Arc<dyn MessageTransform> = Arc::new(Xor(0x5a));
+        let data = [message(b"hello"), message(b""), message(b"volo")].concat();
+        // split the messages into frames at arbitrary positions
+        let frames = data
+            .chunks(3)
+            .map(|chunk| Ok(Frame::data(Bytes::copy_from_slice(chunk))))
+            .collect::<Vec<_>>();
+        let stream = transform_encoding(Box::pin(futures::stream::iter(frames)), transform.clone());
+        let encoded = boxed(http_body_util::StreamBody::new(stream))
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_ne!(&encoded[..], &data[..]);
+        assert_eq!(encoded.len(), data.len() + 3);
+
+        let decoded = transform_decoding(
+            boxed(http_body_util::Full::new(encoded.clone()).map_err(|err| match err {})),
+            transform.clone(),
+        )
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes();
+        assert_eq!(&decoded[..], &data[..]);
+
+        // tampered message
+        let mut tampered = encoded.to_vec();
+        tampered[6] ^= 1;
+        let status = transform_decoding(
+            boxed(http_body_util::Full::new(Bytes::from(tampered)).map_err(|err| match err {})),
+            transform,
+        )
+        .collect()
+        .await
+        .unwrap_err();
+        assert_eq!(status.code(), Code::DataLoss);
+    }
+
+    #[tokio::test]
+    async fn test_trailers_and_eof() {
+        let transform: Arc<dyn MessageTransform> = Arc::new(Xor(1));
+        let frames = vec![
+            Ok(Frame::data(Bytes::from(message(b"a")))),
+            Ok(Frame::trailers(Default::default())),
+        ];
+        let mut stream = transform_encoding(Box::pin(futures::stream::iter(frames)), transform);
+        assert!(stream.next().await.unwrap().unwrap().is_data());
+        assert!(stream.next().await.unwrap().unwrap().is_data());
+        assert!(stream.next().await.unwrap().unwrap().is_trailers());
+        assert!(stream.next().await.is_none());
+
+        let body: BoxBody = boxed(
+            http_body_util::Full::new(Bytes::from_static(b"\x00\x00\x00\x00\x05ab"))
+                .map_err(|err| match err {}),
+        );
+        let status = transform_decoding(body, Arc::new(Xor(1)))
+            .collect()
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let transforms: Vec<Arc<dyn MessageTransform>> = vec![Arc::new(Xor(1))];
+        let mut headers = http::HeaderMap::new();
+        assert!(negotiate(&headers, &transforms, false).unwrap().is_none());
+        assert!(negotiate(&headers, &[], true).unwrap().is_none());
+        // e.g., the metadata is stripped by a proxy
+        assert_eq!(
+            negotiate(&headers, &transforms, true).unwrap_err().code(),
+            Code::FailedPrecondition
+        );
+        headers.insert(TRANSFORM_HEADER, "xor".parse().unwrap());
+        assert_eq!(
+            negotiate(&headers, &transforms, true)
+                .unwrap()
+                .unwrap()
+                .name(),
+            "xor"
+        );
+        headers.insert(TRANSFORM_HEADER, "aes".parse().unwrap());
+        assert_eq!(
+            negotiate(&headers, &transforms, false).unwrap_err().code(),
+            Code::Unimplemented
+        );
+    }
+}
@@ -1,7 +1,113 @@
-//! Re-exports for volo-build.
+//! Re-exports and helpers for the code generated by volo-build.
 
 pub use bytes::Bytes;
 pub use futures;
+use http::Extensions;
 pub use http_body::Frame;
+use pilota::pb::Message;
 pub use tokio::sync::mpsc;
 pub use tokio_stream::{StreamExt, iter, wrappers::ReceiverStream};
+
+use crate::{
+    Code, RecvStream, Response, Status,
+    metadata::MetadataMap,
+    response::{InitialMetadata, Trailers},
+};
+
+/// Receives the only message of a unary response and then its trailers.
+///
+/// The trailers are merged into the metadata of the response, while both the initial metadata and
+/// the trailers are kept in the extensions, see [`Response::initial_metadata`] and
+/// [`Response::trailers`].
+pub async fn unary_response<T>(
+    mut metadata: MetadataMap,
+    mut extensions: Extensions,
+    mut stream: RecvStream<T>,
+) -> Result<Response<T>, Status>
+where
+    T: Message + Default,
+{
+    let message = StreamExt::try_next(&mut stream)
+        .await
+        .map_err(|mut status| {
+            status.metadata_mut().merge(metadata.clone());
+            status
+        })?
+        .ok_or_else(|| Status::new(Code::Internal, "Missing response message."))?;
+    if let Some(trailers) = stream.trailers().await? {
+        extensions.insert(InitialMetadata(metadata.clone()));
+        extensions.insert(Trailers(trailers.clone()));
+        metadata.merge(trailers);
+    }
+    Ok(Response::from_parts(metadata, extensions, message))
+}
+
+#[cfg(all(test, feature = "dynamic"))]
+mod tests {
+    use std::time::Duration;
+
+    use volo::{Service, net::memory::MemoryListener};
+
+    use super::*;
+    use crate::{
+        Request,
+        client::ClientBuilder,
+        context::ServerContext,
+        dynamic::{DynamicClient, DynamicRecv, DynamicSend, MkDynamicClient, RawMessage},
+        server::{Server, ServiceBuilder},
+    };
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<ServerContext, Request<DynamicRecv>> for Echo {
+        type Response = Response<DynamicSend>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            _cx: &mut ServerContext,
+            req: Request<DynamicRecv>,
+        ) -> Result<Self::Response, Self::Error> {
+            let mut resp = Response::new(DynamicSend::from(req.into_inner()));
+            resp.metadata_mut()
+                .insert("x-initial", "initial".parse().unwrap());
+            Ok(resp)
+        }
+    }
+
+    #[tokio::test]
+    async fn unary_call() {
+        let listener = MemoryListener::new();
+        let connector = listener.connector();
+        let addr = connector.address();
+        tokio::spawn(
+            Server::new()
+                .fallback(ServiceBuilder::new(Echo).build())
+                .run(listener),
+        );
+
+        let client: DynamicClient = ClientBuilder::new(MkDynamicClient, "echo")
+            .memory_connector(connector)
+            .build();
+        let mut cx = client.make_cx("/echo.Echo/Unary");
+        let req = Request::new(DynamicSend::once(RawMessage("Volo".into())));
+        let resp = tokio::time::timeout(Duration::from_secs(5), client.call(&mut cx, req))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(resp.remote_addr(), Some(&addr));
+
+        let (metadata, extensions, recv) = resp.into_parts();
+        let resp = unary_response(metadata, extensions, recv.0).await.unwrap();
+        assert_eq!(resp.get_ref().0, "Volo");
+        assert_eq!(resp.initial_metadata().get("x-initial").unwrap(), "initial");
+        assert!(resp.initial_metadata().get("grpc-status").is_none());
+        assert_eq!(resp.trailers().unwrap().get("grpc-status").unwrap(), "0");
+        assert!(resp.trailers().unwrap().get("x-initial").is_none());
+        // the trailers are merged into the metadata
+        assert_eq!(resp.metadata().get("x-initial").unwrap(), "initial");
+        assert_eq!(resp.metadata().get("grpc-status").unwrap(), "0");
+        assert_eq!(resp.remote_addr(), Some(&addr));
+    }
+}
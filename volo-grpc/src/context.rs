@@ -1,29 +1,43 @@
-use std::time::Duration;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use chrono::{DateTime, Local};
 use paste::paste;
 pub use volo::context::*;
-use volo::newtype_impl_context;
-
-use crate::codec::compression::CompressionEncoding;
+use volo::{
+    FastStr,
+    loadbalance::PickInfo,
+    newtype_impl_context,
+    timeout::TimeoutConfig,
+    util::{
+        time::Timestamp,
+        timing::{ConnectTimestamps, Phase, Timing},
+    },
+};
+
+use crate::{
+    codec::{compression::CompressionEncoding, transform::MessageTransform},
+    server::RouteInfo,
+};
 
 macro_rules! stat_impl {
     ($t: ident) => {
         paste! {
             #[inline]
-            pub fn $t(&self) -> Option<DateTime<Local>> {
+            pub fn $t(&self) -> Option<Timestamp> {
                 self.$t
             }
 
             #[doc(hidden)]
             #[inline]
-            pub fn [<set_$t>](&mut self, t: DateTime<Local>) {
+            pub fn [<set_$t>](&mut self, t: Timestamp) {
                 self.$t = Some(t)
             }
 
             #[inline]
             pub fn [<record_ $t>](&mut self) {
-                self.$t = Some(Local::now())
+                self.$t = Some(Timestamp::now())
             }
         }
     };
@@ -31,33 +45,97 @@ macro_rules! stat_impl {
 
 #[derive(Debug, Default, Clone)]
 pub struct ClientStats {
-    make_transport_start_at: Option<DateTime<Local>>,
-    make_transport_end_at: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    call_start_at: Option<Timestamp>,
+    call_end_at: Option<Timestamp>,
+    make_transport_start_at: Option<Timestamp>,
+    make_transport_end_at: Option<Timestamp>,
+
+    // connecting, see `volo::util::timing`
+    resolve_start_at: Option<Timestamp>,
+    resolve_end_at: Option<Timestamp>,
+    connect_start_at: Option<Timestamp>,
+    connect_end_at: Option<Timestamp>,
+    tls_handshake_start_at: Option<Timestamp>,
+    tls_handshake_end_at: Option<Timestamp>,
 }
 
 impl ClientStats {
+    stat_impl!(call_start_at);
+    stat_impl!(call_end_at);
     stat_impl!(make_transport_start_at);
     stat_impl!(make_transport_end_at);
+    stat_impl!(resolve_start_at);
+    stat_impl!(resolve_end_at);
+    stat_impl!(connect_start_at);
+    stat_impl!(connect_end_at);
+    stat_impl!(tls_handshake_start_at);
+    stat_impl!(tls_handshake_end_at);
+
+    /// The request id or trace id of the call, which is set by the users or middlewares.
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
+
+    #[inline]
+    pub(crate) fn record_connect(&mut self, timestamps: ConnectTimestamps) {
+        self.resolve_start_at = timestamps.resolve_start_at;
+        self.resolve_end_at = timestamps.resolve_end_at;
+        self.connect_start_at = timestamps.connect_start_at;
+        self.connect_end_at = timestamps.connect_end_at;
+        self.tls_handshake_start_at = timestamps.tls_handshake_start_at;
+        self.tls_handshake_end_at = timestamps.tls_handshake_end_at;
+    }
 
     #[inline]
     pub fn reset(&mut self) {
+        self.trace_id = None;
+        self.call_start_at = None;
+        self.call_end_at = None;
         self.make_transport_start_at = None;
         self.make_transport_end_at = None;
+        self.resolve_start_at = None;
+        self.resolve_end_at = None;
+        self.connect_start_at = None;
+        self.connect_end_at = None;
+        self.tls_handshake_start_at = None;
+        self.tls_handshake_end_at = None;
     }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct ServerStats {
-    process_start_at: Option<DateTime<Local>>,
-    process_end_at: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    process_start_at: Option<Timestamp>,
+    process_end_at: Option<Timestamp>,
 }
 
 impl ServerStats {
     stat_impl!(process_start_at);
     stat_impl!(process_end_at);
 
+    /// The request id or trace id of the request, which is set by the users or middlewares.
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
+
     #[inline]
     pub fn reset(&mut self) {
+        self.trace_id = None;
         self.process_start_at = None;
         self.process_end_at = None;
     }
@@ -83,6 +161,44 @@ impl ClientContext {
             },
         ))
     }
+
+    /// Get the instance picked by load balancing for the current attempt.
+    ///
+    /// It can be used by retry layers to avoid the failed instances, or by tracing to tag spans
+    /// with the backend identity.
+    #[inline]
+    pub fn pick_info(&self) -> Option<&PickInfo> {
+        self.extensions().get::<PickInfo>()
+    }
+
+    /// Returns the timing breakdown of the call after it returns from the client, see
+    /// [`volo::util::timing`].
+    ///
+    /// The [`Phase::FirstByte`] is the time from the request is sent, i.e., the connection is
+    /// established if any, to the headers of the response are received. The messages of responses
+    /// are decoded while polling the streams, so [`Phase::Decode`] is not recorded.
+    pub fn timing(&self) -> Timing {
+        let stats = &self.stats;
+        let mut timing = Timing::new(stats.call_start_at, stats.call_end_at)
+            .with_trace_id(stats.trace_id.clone());
+        timing.push(Phase::Resolve, stats.resolve_start_at, stats.resolve_end_at);
+        timing.push(Phase::Connect, stats.connect_start_at, stats.connect_end_at);
+        timing.push(
+            Phase::TlsHandshake,
+            stats.tls_handshake_start_at,
+            stats.tls_handshake_end_at,
+        );
+        let sent_at = [
+            stats.make_transport_start_at,
+            stats.connect_end_at,
+            stats.tls_handshake_end_at,
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        timing.push(Phase::FirstByte, sent_at, stats.make_transport_end_at);
+        timing
+    }
 }
 
 impl Default for ClientContext {
@@ -118,6 +234,17 @@ pub struct ServerContext(pub(crate) RpcCx<ServerCxInner, Config>);
 
 newtype_impl_context!(ServerContext, Config, 0);
 
+impl ServerContext {
+    /// Get the service name and method name of the route matched by the router.
+    ///
+    /// It is `None` before routing, e.g., in layers added by `Server::layer`, or if the request is
+    /// handled by the fallback service.
+    #[inline]
+    pub fn route_info(&self) -> Option<&RouteInfo> {
+        self.extensions().get::<RouteInfo>()
+    }
+}
+
 impl Default for ServerContext {
     fn default() -> Self {
         Self(RpcCx::new(
@@ -143,11 +270,27 @@ impl std::ops::DerefMut for ServerContext {
     }
 }
 
+/// The deadline of the request propagated from upstream.
+///
+/// It is inserted into [`metainfo`] by the server with feature `context-propagation`, and the
+/// client takes its remaining time as the upper bound of rpc timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
+
+impl Deadline {
+    /// Remaining time before the deadline, zero if it has been exceeded.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+}
+
 const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Default, Debug, Clone)]
 pub struct Config {
     pub(crate) rpc_timeout: Option<Duration>,
+    /// Amount of time to wait each attempt of a call.
+    pub(crate) request_timeout: Option<Duration>,
     /// Amount of time to wait connecting.
     pub(crate) connect_timeout: Option<Duration>,
     /// Amount of time to wait reading.
@@ -157,14 +300,41 @@ pub struct Config {
 
     pub(crate) accept_compressions: Option<Vec<CompressionEncoding>>,
     pub(crate) send_compressions: Option<Vec<CompressionEncoding>>,
+
+    /// Max size of received messages.
+    pub(crate) max_decoding_message_size: Option<usize>,
+    /// Max size of sent messages.
+    pub(crate) max_encoding_message_size: Option<usize>,
+
+    /// Transforms of encoded messages, the client uses the first one.
+    pub(crate) message_transforms: Option<Vec<Arc<dyn MessageTransform>>>,
+    /// Whether the transform is required, default is true.
+    pub(crate) message_transform_required: Option<bool>,
+}
+
+impl TimeoutConfig for Config {
+    #[inline]
+    fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    #[inline]
+    fn total_timeout(&self) -> Option<Duration> {
+        self.rpc_timeout
+    }
 }
 
 impl Reusable for Config {
     fn clear(&mut self) {
         self.rpc_timeout = None;
+        self.request_timeout = None;
         self.connect_timeout = None;
         self.read_timeout = None;
         self.write_timeout = None;
+        self.max_decoding_message_size = None;
+        self.max_encoding_message_size = None;
+        self.message_transforms = None;
+        self.message_transform_required = None;
         if let Some(v) = self.accept_compressions.as_mut() {
             v.clear();
         }
@@ -179,6 +349,9 @@ impl Config {
         if let Some(t) = other.rpc_timeout {
             self.rpc_timeout = Some(t);
         }
+        if let Some(t) = other.request_timeout {
+            self.request_timeout = Some(t);
+        }
         if let Some(t) = other.connect_timeout {
             self.connect_timeout = Some(t);
         }
@@ -194,6 +367,18 @@ impl Config {
         if let Some(e) = other.send_compressions {
             self.send_compressions = Some(e);
         }
+        if let Some(max) = other.max_decoding_message_size {
+            self.max_decoding_message_size = Some(max);
+        }
+        if let Some(max) = other.max_encoding_message_size {
+            self.max_encoding_message_size = Some(max);
+        }
+        if let Some(t) = other.message_transforms {
+            self.message_transforms = Some(t);
+        }
+        if let Some(required) = other.message_transform_required {
+            self.message_transform_required = Some(required);
+        }
     }
 
     #[inline]
@@ -201,7 +386,7 @@ impl Config {
         self.rpc_timeout
     }
 
-    /// Sets the rpc timeout.
+    /// Sets the rpc timeout, i.e., the total timeout of a call including retries.
     ///
     /// This can be set both by the client builder and the CallOpt.
     #[inline]
@@ -209,6 +394,19 @@ impl Config {
         self.rpc_timeout = rpc_timeout;
     }
 
+    #[inline]
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// Sets the request timeout, i.e., the timeout of each attempt of a call.
+    ///
+    /// This can be set both by the client builder and the CallOpt.
+    #[inline]
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.request_timeout = timeout;
+    }
+
     #[inline]
     pub fn rpc_timeout_or_default(&self) -> Duration {
         self.rpc_timeout.unwrap_or(DEFAULT_RPC_TIMEOUT)
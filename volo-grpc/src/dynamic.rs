@@ -0,0 +1,956 @@
+//! Messages of services that are not compiled into the binary, for proxies and debugging gateways.
+//!
+//! The codec of volo-grpc decodes messages by their generated types, which are unknown to a proxy
+//! forwarding any service. This module provides:
+//!
+//! - [`RawMessage`], the undecoded payload of a message, which can be received and sent as it is;
+//! - [`DynamicRecv`] and [`DynamicSend`], the [`RecvEntryMessage`] and [`SendEntryMessage`] of all
+//!   methods, i.e., the streams of [`RawMessage`]s;
+//! - [`DescriptorPool`], the descriptors of services and messages added at runtime, e.g., from a
+//!   `FileDescriptorSet` or the reflection service of the backend;
+//! - [`DynamicMessage`], a message decoded by its descriptor, whose fields can be read and written
+//!   by reflection, and which can be rendered as protobuf JSON with `google.protobuf.Any` unpacked.
+//!
+//! A proxy forwarding all requests to a backend, and logging the request messages:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use futures::TryStreamExt;
+//! use volo::context::Context;
+//! use volo_grpc::{
+//!     Request, Response, Status,
+//!     client::ClientBuilder,
+//!     context::ServerContext,
+//!     dynamic::{DescriptorPool, DynamicClient, DynamicRecv, DynamicSend, MkDynamicClient},
+//!     server::{Router, ServiceBuilder},
+//! };
+//!
+//! #[derive(Clone)]
+//! struct Proxy {
+//!     client: DynamicClient,
+//!     pool: Arc<DescriptorPool>,
+//! }
+//!
+//! impl volo::Service<ServerContext, Request<DynamicRecv>> for Proxy {
+//!     type Response = Response<DynamicSend>;
+//!     type Error = Status;
+//!
+//!     async fn call(
+//!         &self,
+//!         cx: &mut ServerContext,
+//!         req: Request<DynamicRecv>,
+//!     ) -> Result<Self::Response, Self::Error> {
+//!         let path = cx.rpc_info().method().clone();
+//!         let input = self.pool.method(&path).map(|method| method.input_type());
+//!         let pool = self.pool.clone();
+//!         let req = req.map(|recv| {
+//!             DynamicSend::new(recv.inspect_ok(move |raw| {
+//!                 let Some(input) = &input else { return };
+//!                 if let Ok(json) = raw.to_dynamic(input).and_then(|m| m.to_json(&pool)) {
+//!                     tracing::info!("{path}: {json}");
+//!                 }
+//!             }))
+//!         });
+//!
+//!         let mut backend_cx = self.client.make_cx("");
+//!         backend_cx
+//!             .rpc_info_mut()
+//!             .set_method(cx.rpc_info().method().clone());
+//!         let resp = self.client.call(&mut backend_cx, req).await?;
+//!         Ok(resp.map(DynamicSend::from))
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), Status> {
+//! # let set: &[u8] = &[];
+//! let mut pool = DescriptorPool::new();
+//! pool.add_file_descriptor_set(set)?;
+//! let client = ClientBuilder::new(MkDynamicClient, "backend")
+//!     .address("127.0.0.1:8080".parse::<std::net::SocketAddr>().unwrap())
+//!     .build();
+//! let proxy = Proxy {
+//!     client,
+//!     pool: Arc::new(pool),
+//! };
+//! let router = Router::new().fallback(ServiceBuilder::new(proxy).build());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{Stream, StreamExt, TryStreamExt};
+use http_body::Frame;
+use motore::service::BoxCloneService;
+use pilota::{
+    LinkedBytes,
+    pb::{
+        DecodeError, EncodeLengthContext, Message,
+        descriptor::{FileDescriptorProto, FileDescriptorSet},
+        encoding::{DecodeContext, WireType},
+        reflect::{
+            FieldDescriptor, FileDescriptor, MessageDescriptor, MethodDescriptor, ReflectFieldRef,
+            ReflectValueBox, ReflectValueRef, RuntimeFieldType, RuntimeType, ServiceDescriptor,
+        },
+    },
+};
+use protobuf::{Message as _, MessageDyn};
+use rustc_hash::{FxHashMap, FxHashSet};
+use volo::client::MkClient;
+
+use crate::{
+    BoxStream, Client, RecvEntryMessage, RecvStream, Request, Response, SendEntryMessage, Status,
+    body::BoxBody,
+    codec::{
+        compression::{CompressionEncoding, MaybeCompressed},
+        decode::Kind,
+        encode::encode,
+    },
+    context::ClientContext,
+    wkt::{TYPE_URL_PREFIX, type_name_of_url},
+};
+
+const ANY: &str = "google.protobuf.Any";
+/// The max depth of the nested messages containing `google.protobuf.Any` rendered by
+/// [`DynamicMessage::to_json`].
+pub const MAX_JSON_DEPTH: usize = 100;
+// messages larger than this are inserted into the buffer without being copied
+const ZERO_COPY_THRESHOLD: usize = 4 * 1024;
+// well-known types rendered as JSON values other than objects of their fields, which are embedded
+// in `Any` as the `value` field
+const CUSTOM_JSON_TYPES: &[&str] = &[
+    ANY,
+    "google.protobuf.Timestamp",
+    "google.protobuf.Duration",
+    "google.protobuf.FieldMask",
+    "google.protobuf.Struct",
+    "google.protobuf.Value",
+    "google.protobuf.ListValue",
+    "google.protobuf.Empty",
+    "google.protobuf.DoubleValue",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int64Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Int32Value",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.BoolValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.BytesValue",
+];
+
+/// The encoded payload of a message, which is received and sent without being decoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawMessage(pub Bytes);
+
+impl RawMessage {
+    /// Decode the payload as the message of the descriptor.
+    pub fn to_dynamic(&self, descriptor: &MessageDescriptor) -> Result<DynamicMessage, Status> {
+        DynamicMessage::decode(descriptor, &self.0)
+    }
+}
+
+impl Message for RawMessage {
+    fn encode_raw(&self, buf: &mut LinkedBytes) {
+        if self.0.len() >= ZERO_COPY_THRESHOLD {
+            buf.insert(self.0.clone());
+        } else {
+            buf.put_slice(&self.0);
+        }
+    }
+
+    fn merge_field(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut Bytes,
+        ctx: &mut DecodeContext,
+        _is_root: bool,
+    ) -> Result<(), DecodeError> {
+        // only reached when nested in other messages, whose fields are kept as they are by `merge`
+        pilota::pb::encoding::skip_field(wire_type, tag, buf, ctx)
+    }
+
+    fn encoded_len(&self, ctx: &mut EncodeLengthContext) -> usize {
+        if self.0.len() >= ZERO_COPY_THRESHOLD {
+            ctx.zero_copy_len += self.0.len();
+        }
+        self.0.len()
+    }
+
+    fn merge(&mut self, buf: Bytes) -> Result<(), DecodeError> {
+        if self.0.is_empty() {
+            self.0 = buf;
+        } else {
+            // merging messages is concatenating their encoded fields
+            let mut merged = BytesMut::with_capacity(self.0.len() + buf.len());
+            merged.put_slice(&self.0);
+            merged.put_slice(&buf);
+            self.0 = merged.freeze();
+        }
+        Ok(())
+    }
+}
+
+impl From<Bytes> for RawMessage {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+}
+
+/// The received messages of any method.
+pub struct DynamicRecv(pub RecvStream<RawMessage>);
+
+impl DynamicRecv {
+    /// Get the next message, `None` if the stream is finished.
+    pub async fn message(&mut self) -> Result<Option<RawMessage>, Status> {
+        self.0.try_next().await
+    }
+}
+
+impl Stream for DynamicRecv {
+    type Item = Result<RawMessage, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl fmt::Debug for DynamicRecv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicRecv").field(&self.0).finish()
+    }
+}
+
+impl RecvEntryMessage for DynamicRecv {
+    fn from_body(
+        _method: Option<&str>,
+        body: BoxBody,
+        kind: Kind,
+        compression_encoding: Option<CompressionEncoding>,
+    ) -> Result<Self, Status> {
+        Ok(Self(RecvStream::new(body, kind, compression_encoding)))
+    }
+}
+
+/// The messages to send of any method.
+pub struct DynamicSend(pub BoxStream<'static, Result<MaybeCompressed<RawMessage>, Status>>);
+
+impl DynamicSend {
+    /// Send the messages of the stream.
+    ///
+    /// The messages can be wrapped by [`MaybeCompressed`] to be sent without compression, e.g.,
+    /// by [`CompressionStreamExt::skip_compression_if`].
+    ///
+    /// [`CompressionStreamExt::skip_compression_if`]:
+    /// crate::codec::compression::CompressionStreamExt::skip_compression_if
+    pub fn new<S, M>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<M, Status>> + Send + 'static,
+        M: Into<MaybeCompressed<RawMessage>> + 'static,
+    {
+        Self(stream.map_ok(Into::into).boxed())
+    }
+
+    /// Send a single message, i.e., the request of a unary or server streaming call, or the
+    /// response of a unary or client streaming call.
+    pub fn once(message: RawMessage) -> Self {
+        Self::new(futures::stream::once(async move { Ok(message) }))
+    }
+}
+
+impl From<DynamicRecv> for DynamicSend {
+    /// Forward the received messages.
+    fn from(recv: DynamicRecv) -> Self {
+        Self::new(recv)
+    }
+}
+
+impl From<RawMessage> for DynamicSend {
+    fn from(message: RawMessage) -> Self {
+        Self::once(message)
+    }
+}
+
+impl fmt::Debug for DynamicSend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicSend").finish_non_exhaustive()
+    }
+}
+
+impl SendEntryMessage for DynamicSend {
+    fn into_body(
+        self,
+        compression_encoding: Option<CompressionEncoding>,
+    ) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+        encode(self.0, compression_encoding)
+    }
+}
+
+/// The client calling any method by [`DynamicSend`] and [`DynamicRecv`].
+pub type DynamicClient =
+    Client<BoxCloneService<ClientContext, Request<DynamicSend>, Response<DynamicRecv>, Status>>;
+
+/// Makes the [`DynamicClient`] by [`ClientBuilder`](crate::client::ClientBuilder).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MkDynamicClient;
+
+impl<S> MkClient<Client<S>> for MkDynamicClient {
+    type Target = Client<S>;
+
+    fn mk_client(&self, service: Client<S>) -> Self::Target {
+        service
+    }
+}
+
+/// Descriptors of files, and the services and messages in them, by their full names.
+///
+/// The well-known types of `google.protobuf` are added by default, so files importing them can be
+/// added without them.
+#[derive(Clone)]
+pub struct DescriptorPool {
+    files: FxHashMap<String, FileDescriptor>,
+    messages: FxHashMap<String, MessageDescriptor>,
+    services: FxHashMap<String, ServiceDescriptor>,
+}
+
+impl Default for DescriptorPool {
+    fn default() -> Self {
+        use protobuf::well_known_types::{
+            any, api, duration, empty, field_mask, source_context, struct_, timestamp, type_,
+            wrappers,
+        };
+
+        let mut pool = Self {
+            files: FxHashMap::default(),
+            messages: FxHashMap::default(),
+            services: FxHashMap::default(),
+        };
+        for file in [
+            any::file_descriptor(),
+            api::file_descriptor(),
+            duration::file_descriptor(),
+            empty::file_descriptor(),
+            field_mask::file_descriptor(),
+            source_context::file_descriptor(),
+            struct_::file_descriptor(),
+            timestamp::file_descriptor(),
+            type_::file_descriptor(),
+            wrappers::file_descriptor(),
+        ] {
+            pool.insert(file.clone());
+        }
+        pool
+    }
+}
+
+impl fmt::Debug for DescriptorPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DescriptorPool")
+            .field("files", &self.files.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl DescriptorPool {
+    /// Create a [`DescriptorPool`] with the well-known types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the file and its dependencies, files already in the pool are skipped.
+    ///
+    /// The [`FileDescriptor`] is generated as `file_descriptor_{filename}()` for each proto file.
+    pub fn register(mut self, file: &FileDescriptor) -> Self {
+        self.insert(file.clone());
+        self
+    }
+
+    /// Add the files of the encoded `google.protobuf.FileDescriptorSet`, e.g., generated by
+    /// `protoc --include_imports --descriptor_set_out`.
+    pub fn add_file_descriptor_set(&mut self, bytes: &[u8]) -> Result<(), Status> {
+        let set = FileDescriptorSet::parse_from_bytes(bytes).map_err(|err| {
+            Status::invalid_argument(format!("invalid file descriptor set: {err}"))
+        })?;
+        self.add_files(set.file)
+    }
+
+    /// Add the encoded `google.protobuf.FileDescriptorProto`s, e.g., the `file_descriptor_proto`
+    /// replied by the reflection service.
+    ///
+    /// The files may depend on each other or the files already in the pool, files already in the
+    /// pool are skipped.
+    pub fn add_encoded_files<I, B>(&mut self, files: I) -> Result<(), Status>
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let files = files
+            .into_iter()
+            .map(|bytes| {
+                FileDescriptorProto::parse_from_bytes(bytes.as_ref()).map_err(|err| {
+                    Status::invalid_argument(format!("invalid file descriptor: {err}"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.add_files(files)
+    }
+
+    /// Add the [`FileDescriptorProto`]s, see [`DescriptorPool::add_encoded_files`].
+    pub fn add_files(&mut self, files: Vec<FileDescriptorProto>) -> Result<(), Status> {
+        let mut seen = FxHashSet::default();
+        let files = files
+            .into_iter()
+            .filter(|file| {
+                !self.files.contains_key(file.name()) && seen.insert(file.name().to_owned())
+            })
+            .collect::<Vec<_>>();
+        let deps = self.files.values().cloned().collect::<Vec<_>>();
+        let files = FileDescriptor::new_dynamic_fds(files, &deps)
+            .map_err(|err| Status::invalid_argument(format!("invalid file descriptor: {err}")))?;
+        for file in files {
+            self.insert(file);
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, file: FileDescriptor) {
+        if self.files.contains_key(file.name()) {
+            return;
+        }
+        for dep in file.deps() {
+            self.insert(dep.clone());
+        }
+
+        let mut messages = file.messages().collect::<Vec<_>>();
+        while let Some(message) = messages.pop() {
+            messages.extend(message.nested_messages());
+            self.messages
+                .insert(message.full_name().to_owned(), message);
+        }
+        for service in file.services() {
+            let name = match file.package() {
+                "" => service.proto().name().to_owned(),
+                package => format!("{package}.{}", service.proto().name()),
+            };
+            self.services.insert(name, service);
+        }
+        self.files.insert(file.name().to_owned(), file);
+    }
+
+    /// Get the file by its name, e.g., `google/protobuf/any.proto`.
+    pub fn file(&self, name: &str) -> Option<&FileDescriptor> {
+        self.files.get(name)
+    }
+
+    /// Get the message by its full name, e.g., `google.protobuf.Any`.
+    pub fn message(&self, full_name: &str) -> Option<&MessageDescriptor> {
+        self.messages.get(full_name.trim_start_matches('.'))
+    }
+
+    /// Get the service by its full name, e.g., `grpc.health.v1.Health`.
+    pub fn service(&self, full_name: &str) -> Option<&ServiceDescriptor> {
+        self.services.get(full_name.trim_start_matches('.'))
+    }
+
+    /// All services in the pool by their full names.
+    pub fn services(&self) -> impl Iterator<Item = (&str, &ServiceDescriptor)> {
+        self.services
+            .iter()
+            .map(|(name, service)| (name.as_str(), service))
+    }
+
+    /// Get the method by its path, i.e., `/{package}.{service}/{method}`.
+    pub fn method(&self, path: &str) -> Option<MethodDescriptor> {
+        let (service, method) = path.trim_start_matches('/').split_once('/')?;
+        self.service(service)?
+            .methods()
+            .find(|m| m.proto().name() == method)
+    }
+}
+
+/// A message decoded by its descriptor.
+#[derive(Clone, Debug)]
+pub struct DynamicMessage(Box<dyn MessageDyn>);
+
+impl DynamicMessage {
+    /// Create an empty message of the descriptor.
+    pub fn new(descriptor: &MessageDescriptor) -> Self {
+        Self(descriptor.new_instance())
+    }
+
+    /// Decode the message of the descriptor.
+    pub fn decode(descriptor: &MessageDescriptor, bytes: &[u8]) -> Result<Self, Status> {
+        descriptor.parse_from_bytes(bytes).map(Self).map_err(|err| {
+            Status::invalid_argument(format!(
+                "failed to decode {}: {err}",
+                descriptor.full_name()
+            ))
+        })
+    }
+
+    /// Parse the message of the descriptor from the canonical protobuf JSON.
+    ///
+    /// `google.protobuf.Any` is not supported by the mapping yet.
+    pub fn from_json(descriptor: &MessageDescriptor, json: &str) -> Result<Self, Status> {
+        protobuf_json_mapping::parse_dyn_from_str(descriptor, json)
+            .map(Self)
+            .map_err(|err| {
+                Status::invalid_argument(format!(
+                    "failed to parse {}: {err}",
+                    descriptor.full_name()
+                ))
+            })
+    }
+
+    /// Encode the message.
+    pub fn encode(&self) -> Result<Bytes, Status> {
+        self.0
+            .write_to_bytes_dyn()
+            .map(Bytes::from)
+            .map_err(|err| Status::internal(format!("failed to encode message: {err}")))
+    }
+
+    /// Encode the message as a [`RawMessage`] to send.
+    pub fn to_raw(&self) -> Result<RawMessage, Status> {
+        self.encode().map(RawMessage)
+    }
+
+    pub fn descriptor(&self) -> MessageDescriptor {
+        self.0.descriptor_dyn()
+    }
+
+    /// Get the field by its name, `None` if the field is not found.
+    pub fn get(&self, name: &str) -> Option<ReflectFieldRef<'_>> {
+        let field = self.descriptor().field_by_name(name)?;
+        Some(field.get_reflect(&*self.0))
+    }
+
+    /// Set the singular field by its name.
+    pub fn set(&mut self, name: &str, value: ReflectValueBox) -> Result<(), Status> {
+        let field = self.field(name)?;
+        if !field.is_singular() || field.singular_runtime_type() != value.get_type() {
+            return Err(Status::invalid_argument(format!(
+                "invalid value of {}: {value:?}",
+                field.full_name()
+            )));
+        }
+        field.set_singular_field(&mut *self.0, value);
+        Ok(())
+    }
+
+    /// Get the field by its name for the mutable accessors of [`FieldDescriptor`], e.g.,
+    /// [`FieldDescriptor::mut_repeated`].
+    pub fn field(&self, name: &str) -> Result<FieldDescriptor, Status> {
+        let descriptor = self.descriptor();
+        descriptor.field_by_name(name).ok_or_else(|| {
+            Status::invalid_argument(format!(
+                "field {name} not found in {}",
+                descriptor.full_name()
+            ))
+        })
+    }
+
+    pub fn as_dyn(&self) -> &dyn MessageDyn {
+        &*self.0
+    }
+
+    pub fn as_dyn_mut(&mut self) -> &mut dyn MessageDyn {
+        &mut *self.0
+    }
+
+    pub fn into_inner(self) -> Box<dyn MessageDyn> {
+        self.0
+    }
+
+    /// Pack the message into a `google.protobuf.Any` of the pool.
+    pub fn pack_any(&self, pool: &DescriptorPool) -> Result<Self, Status> {
+        let mut any = Self::new(pool.message(ANY).expect("well-known types are in the pool"));
+        let type_url = format!("{TYPE_URL_PREFIX}{}", self.descriptor().full_name());
+        any.set("type_url", ReflectValueBox::String(type_url))?;
+        any.set("value", ReflectValueBox::Bytes(self.encode()?.into()))?;
+        Ok(any)
+    }
+
+    /// Unpack the message packed in the `google.protobuf.Any` by its descriptor in the pool.
+    pub fn unpack_any(&self, pool: &DescriptorPool) -> Result<Self, Status> {
+        unpack_any(&*self.0, pool)
+    }
+
+    /// Render the message as the canonical protobuf JSON, where the messages packed in
+    /// `google.protobuf.Any` are unpacked by their descriptors in the pool.
+    ///
+    /// Fails with `INVALID_ARGUMENT` if the messages containing `Any` are nested deeper than
+    /// [`MAX_JSON_DEPTH`], since each `Any` may pack another one.
+    pub fn to_json(&self, pool: &DescriptorPool) -> Result<String, Status> {
+        to_json_value(&*self.0, pool, 0).map(|value| value.to_string())
+    }
+}
+
+impl From<Box<dyn MessageDyn>> for DynamicMessage {
+    fn from(message: Box<dyn MessageDyn>) -> Self {
+        Self(message)
+    }
+}
+
+fn unpack_any(any: &dyn MessageDyn, pool: &DescriptorPool) -> Result<DynamicMessage, Status> {
+    let descriptor = any.descriptor_dyn();
+    if descriptor.full_name() != ANY {
+        return Err(Status::invalid_argument(format!(
+            "{} is not {ANY}",
+            descriptor.full_name()
+        )));
+    }
+    let field = |name| {
+        descriptor
+            .field_by_name(name)
+            .expect("fields of google.protobuf.Any")
+            .get_singular_field_or_default(any)
+    };
+    let type_url = field("type_url");
+    let type_url = type_url.to_str().unwrap_or_default();
+    let message = pool
+        .message(type_name_of_url(type_url))
+        .ok_or_else(|| Status::not_found(format!("unknown type of Any: {type_url}")))?;
+    DynamicMessage::decode(message, field("value").to_bytes().unwrap_or_default())
+}
+
+fn to_json_value(
+    message: &dyn MessageDyn,
+    pool: &DescriptorPool,
+    depth: usize,
+) -> Result<serde_json::Value, Status> {
+    if depth > MAX_JSON_DEPTH {
+        return Err(Status::invalid_argument(format!(
+            "messages containing {ANY} are nested deeper than {MAX_JSON_DEPTH}"
+        )));
+    }
+    let descriptor = message.descriptor_dyn();
+    if descriptor.full_name() == ANY {
+        let type_url = descriptor
+            .field_by_name("type_url")
+            .expect("fields of google.protobuf.Any")
+            .get_singular_field_or_default(message)
+            .to_string();
+        let unpacked = unpack_any(message, pool)?;
+        let value = to_json_value(unpacked.as_dyn(), pool, depth + 1)?;
+        let custom = CUSTOM_JSON_TYPES.contains(&unpacked.descriptor().full_name());
+        return Ok(match value {
+            serde_json::Value::Object(mut fields) if !custom => {
+                fields.insert("@type".to_owned(), type_url.into());
+                serde_json::Value::Object(fields)
+            }
+            value => serde_json::json!({ "@type": type_url, "value": value }),
+        });
+    }
+
+    // the fields containing `Any` are rendered separately, since the mapping can't print `Any`
+    let any_fields = descriptor
+        .fields()
+        .filter(|field| field_contains_any(field, &mut FxHashSet::default()))
+        .collect::<Vec<_>>();
+    let print = |message: &dyn MessageDyn| {
+        let json = protobuf_json_mapping::print_to_string(message)
+            .map_err(|err| Status::internal(format!("failed to render message: {err}")))?;
+        serde_json::from_str::<serde_json::Value>(&json)
+            .map_err(|err| Status::internal(format!("failed to render message: {err}")))
+    };
+    if any_fields.is_empty() {
+        return print(message);
+    }
+
+    let mut rest = message.clone_box();
+    for field in any_fields.iter() {
+        field.clear_field(&mut *rest);
+    }
+    let mut value = print(&*rest)?;
+    let Some(fields) = value.as_object_mut() else {
+        return Ok(value);
+    };
+    let message_json = |value: ReflectValueRef<'_>| match value {
+        ReflectValueRef::Message(m) => to_json_value(&*m, pool, depth + 1),
+        value => Ok(serde_json::Value::String(value.to_string())),
+    };
+    for field in any_fields {
+        let json = match field.get_reflect(message) {
+            ReflectFieldRef::Optional(value) => match value.value() {
+                Some(value) => message_json(value)?,
+                None => continue,
+            },
+            ReflectFieldRef::Repeated(values) if !values.is_empty() => values
+                .into_iter()
+                .map(message_json)
+                .collect::<Result<_, _>>()?,
+            ReflectFieldRef::Map(map) if !map.is_empty() => serde_json::Value::Object(
+                (&map)
+                    .into_iter()
+                    .map(|(key, value)| Ok((key.to_string(), message_json(value)?)))
+                    .collect::<Result<_, Status>>()?,
+            ),
+            _ => continue,
+        };
+        fields.insert(field.json_name().to_owned(), json);
+    }
+    Ok(value)
+}
+
+/// Whether `Any` may be in the field, recursively.
+fn field_contains_any(field: &FieldDescriptor, visited: &mut FxHashSet<String>) -> bool {
+    let message = match field.runtime_field_type() {
+        RuntimeFieldType::Singular(RuntimeType::Message(m))
+        | RuntimeFieldType::Repeated(RuntimeType::Message(m))
+        | RuntimeFieldType::Map(_, RuntimeType::Message(m)) => m,
+        _ => return false,
+    };
+    if message.full_name() == ANY {
+        return true;
+    }
+    if !visited.insert(message.full_name().to_owned()) {
+        return false;
+    }
+    message
+        .fields()
+        .any(|field| field_contains_any(&field, visited))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::TryStreamExt;
+    use pilota::pb::{
+        EncodeLengthContext, Message as _,
+        descriptor::{
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+            MethodDescriptorProto, ServiceDescriptorProto,
+            field_descriptor_proto::{Label, Type},
+        },
+        reflect::ReflectValueBox,
+    };
+    use protobuf::Message as _;
+
+    use super::*;
+    use crate::{
+        Code,
+        body::{Body, BoxBody},
+        codec::{decode::Kind, encode::tests::EchoRequest},
+    };
+
+    fn field(name: &str, number: i32, ty: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        let mut field = FieldDescriptorProto::new();
+        field.set_name(name.to_owned());
+        field.set_json_name(name.to_owned());
+        field.set_number(number);
+        field.set_label(Label::LABEL_OPTIONAL);
+        field.set_type(ty);
+        if let Some(type_name) = type_name {
+            field.set_type_name(type_name.to_owned());
+        }
+        field
+    }
+
+    fn file_descriptor_set() -> Vec<u8> {
+        let mut request = DescriptorProto::new();
+        request.set_name("EchoRequest".to_owned());
+        request
+            .field
+            .push(field("message", 1, Type::TYPE_STRING, None));
+
+        let mut detail = field(
+            "detail",
+            2,
+            Type::TYPE_MESSAGE,
+            Some(".google.protobuf.Any"),
+        );
+        detail.set_json_name("detail".to_owned());
+        let mut details = detail.clone();
+        details.set_name("details".to_owned());
+        details.set_json_name("details".to_owned());
+        details.set_number(3);
+        details.set_label(Label::LABEL_REPEATED);
+        let mut response = DescriptorProto::new();
+        response.set_name("EchoResponse".to_owned());
+        response
+            .field
+            .push(field("message", 1, Type::TYPE_STRING, None));
+        response.field.push(detail);
+        response.field.push(details);
+
+        let mut method = MethodDescriptorProto::new();
+        method.set_name("Unary".to_owned());
+        method.set_input_type(".echo.EchoRequest".to_owned());
+        method.set_output_type(".echo.EchoResponse".to_owned());
+        let mut service = ServiceDescriptorProto::new();
+        service.set_name("Echo".to_owned());
+        service.method.push(method);
+
+        let mut file = FileDescriptorProto::new();
+        file.set_name("echo.proto".to_owned());
+        file.set_package("echo".to_owned());
+        file.set_syntax("proto3".to_owned());
+        file.dependency.push("google/protobuf/any.proto".to_owned());
+        file.message_type.push(request);
+        file.message_type.push(response);
+        file.service.push(service);
+
+        let mut set = FileDescriptorSet::new();
+        set.file.push(file);
+        set.write_to_bytes().unwrap()
+    }
+
+    fn echo_request(message: &str) -> Bytes {
+        let request = EchoRequest {
+            message: message.to_owned().into(),
+        };
+        request
+            .encode_to_vec(&mut EncodeLengthContext::default())
+            .into()
+    }
+
+    #[tokio::test]
+    async fn forward_raw_messages() {
+        let large = "a".repeat(ZERO_COPY_THRESHOLD);
+        let messages = vec![
+            Ok(RawMessage(echo_request("Volo"))),
+            Ok(RawMessage(echo_request(&large))),
+        ];
+        let send = DynamicSend::new(futures::stream::iter(messages));
+        let body = BoxBody::new(Body::new(send.into_body(None)));
+
+        // the received messages are forwarded as they are
+        let recv = DynamicRecv::from_body(None, body, Kind::Request, None).unwrap();
+        let body = BoxBody::new(Body::new(DynamicSend::from(recv).into_body(None)));
+        let mut recv = DynamicRecv::from_body(None, body, Kind::Request, None).unwrap();
+
+        let message = recv.message().await.unwrap().unwrap();
+        assert_eq!(EchoRequest::decode(message.0).unwrap().message, "Volo");
+        let message = recv.message().await.unwrap().unwrap();
+        assert_eq!(EchoRequest::decode(message.0).unwrap().message, large);
+        assert!(recv.try_next().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_by_pool() {
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_set(&file_descriptor_set())
+            .unwrap();
+        let method = pool.method("/echo.Echo/Unary").unwrap();
+        assert_eq!(method.input_type().full_name(), "echo.EchoRequest");
+        assert!(pool.method("/echo.Echo/Unknown").is_none());
+        assert!(pool.method("/echo.Unknown/Unary").is_none());
+
+        let message = RawMessage(echo_request("Volo"))
+            .to_dynamic(&method.input_type())
+            .unwrap();
+        let ReflectFieldRef::Optional(value) = message.get("message").unwrap() else {
+            panic!("message is singular");
+        };
+        assert_eq!(value.value().unwrap().to_str(), Some("Volo"));
+        assert!(message.get("unknown").is_none());
+
+        let mut message = DynamicMessage::new(&method.input_type());
+        message
+            .set("message", ReflectValueBox::String("Volo".to_owned()))
+            .unwrap();
+        let status = message.set("message", ReflectValueBox::I32(1)).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let raw = message.to_raw().unwrap();
+        assert_eq!(EchoRequest::decode(raw.0).unwrap().message, "Volo");
+
+        // files already in the pool are skipped
+        let file = pool
+            .file("echo.proto")
+            .unwrap()
+            .proto()
+            .write_to_bytes()
+            .unwrap();
+        pool.add_encoded_files([file]).unwrap();
+        let status = pool.add_encoded_files([b"invalid"]).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn unpack_any() {
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_set(&file_descriptor_set())
+            .unwrap();
+        let request = pool.message("echo.EchoRequest").unwrap();
+        let response = pool.message("echo.EchoResponse").unwrap();
+
+        let mut detail = DynamicMessage::new(request);
+        detail
+            .set("message", ReflectValueBox::String("detail".to_owned()))
+            .unwrap();
+        let any = detail.pack_any(&pool).unwrap();
+        let unpacked = any.unpack_any(&pool).unwrap();
+        assert_eq!(unpacked.descriptor().full_name(), "echo.EchoRequest");
+        assert_eq!(unpacked.encode().unwrap(), detail.encode().unwrap());
+        assert_eq!(
+            detail.unpack_any(&pool).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        let mut message = DynamicMessage::new(response);
+        message
+            .set("message", ReflectValueBox::String("Volo".to_owned()))
+            .unwrap();
+        message
+            .set("detail", ReflectValueBox::Message(any.clone().into_inner()))
+            .unwrap();
+        let details = message.field("details").unwrap();
+        details
+            .mut_repeated(message.as_dyn_mut())
+            .push(ReflectValueBox::Message(any.into_inner()));
+        let json: serde_json::Value =
+            serde_json::from_str(&message.to_json(&pool).unwrap()).unwrap();
+        let detail = serde_json::json!({
+            "@type": "type.googleapis.com/echo.EchoRequest",
+            "message": "detail",
+        });
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "message": "Volo",
+                "detail": detail,
+                "details": [detail],
+            })
+        );
+
+        // the well-known types are embedded as `value`
+        let mut timestamp = DynamicMessage::new(pool.message("google.protobuf.Timestamp").unwrap());
+        timestamp.set("seconds", ReflectValueBox::I64(1)).unwrap();
+        let json = timestamp.pack_any(&pool).unwrap().to_json(&pool).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({
+                "@type": "type.googleapis.com/google.protobuf.Timestamp",
+                "value": "1970-01-01T00:00:01.000000000Z",
+            })
+        );
+
+        // the types not in the pool can't be unpacked
+        let mut any = DynamicMessage::new(pool.message(ANY).unwrap());
+        any.set(
+            "type_url",
+            ReflectValueBox::String("type.googleapis.com/unknown.Unknown".to_owned()),
+        )
+        .unwrap();
+        assert_eq!(any.to_json(&pool).unwrap_err().code(), Code::NotFound);
+
+        // the depth of nested `Any` is limited
+        let mut nested = timestamp.pack_any(&pool).unwrap();
+        for _ in 0..MAX_JSON_DEPTH - 1 {
+            nested = nested.pack_any(&pool).unwrap();
+        }
+        assert!(nested.to_json(&pool).is_ok());
+        nested = nested.pack_any(&pool).unwrap();
+        assert_eq!(
+            nested.to_json(&pool).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+}
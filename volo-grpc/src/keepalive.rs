@@ -0,0 +1,322 @@
+//! Application-level keepalive of long-lived streams.
+//!
+//! HTTP/2 PINGs (see [`Server::http2_keepalive_interval`] and
+//! [`Client::http2_keepalive_interval`]) detect dead connections, but a stream can still be idle
+//! for hours on a healthy connection, e.g., a server-streaming RPC pushing rare events behind a
+//! proxy which closes idle streams, or a server whose handler gets stuck. This module provides
+//! both sides of an application-level keepalive:
+//!
+//! - [`heartbeat`] wraps the response stream of a server, and sends a heartbeat message built by
+//!   the handler whenever no message is sent for the interval. The message type is decided by the
+//!   IDL, e.g., an empty message or a `oneof` variant reserved for heartbeats.
+//! - [`liveness`] wraps the received stream of a client, drops the heartbeat messages and fails the
+//!   stream with an `UNAVAILABLE` [`Status`] when no message, including heartbeats, is received
+//!   within the timeout. The status can be recognized by [`is_liveness_lost`].
+//!
+//! Streams failed by HTTP/2 PING timeouts are also recognized by [`is_liveness_lost`].
+//!
+//! [`Server::http2_keepalive_interval`]: crate::server::Server::http2_keepalive_interval
+//! [`Client::http2_keepalive_interval`]: crate::client::ClientBuilder::http2_keepalive_interval
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use futures::{Stream, StreamExt};
+//! use volo_grpc::{Status, keepalive};
+//!
+//! # #[derive(Default)]
+//! # struct Event { heartbeat: bool }
+//! // server side
+//! fn watch(
+//!     events: impl Stream<Item = Result<Event, Status>>,
+//! ) -> impl Stream<Item = Result<Event, Status>> {
+//!     keepalive::heartbeat(events, Duration::from_secs(15), || Event {
+//!         heartbeat: true,
+//!     })
+//! }
+//!
+//! // client side
+//! async fn consume(events: impl Stream<Item = Result<Event, Status>> + Unpin) {
+//!     let mut events = keepalive::liveness(events, Duration::from_secs(45))
+//!         .skip_heartbeat(|event: &Event| event.heartbeat);
+//!     while let Some(event) = events.next().await {
+//!         match event {
+//!             Ok(event) => { /* ... */ }
+//!             Err(status) if keepalive::is_liveness_lost(&status) => {
+//!                 // reconnect
+//!                 break;
+//!             }
+//!             Err(status) => break,
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use pin_project::pin_project;
+use tokio::time::{Instant, Sleep};
+
+use crate::Status;
+
+/// Wraps a response stream to send a heartbeat message built by `make` whenever no message is
+/// sent for the `interval`.
+///
+/// Heartbeats are only sent when the transport polls the stream, so they never queue up behind a
+/// slow peer.
+pub fn heartbeat<S, T, F>(stream: S, interval: Duration, make: F) -> Heartbeat<S, F>
+where
+    S: Stream<Item = Result<T, Status>>,
+    F: FnMut() -> T,
+{
+    Heartbeat {
+        inner: stream,
+        interval,
+        sleep: Box::pin(tokio::time::sleep(interval)),
+        make,
+    }
+}
+
+/// Stream returned by [`heartbeat`].
+#[pin_project]
+pub struct Heartbeat<S, F> {
+    #[pin]
+    inner: S,
+    interval: Duration,
+    sleep: Pin<Box<Sleep>>,
+    make: F,
+}
+
+impl<S, T, F> Stream for Heartbeat<S, F>
+where
+    S: Stream<Item = Result<T, Status>>,
+    F: FnMut() -> T,
+{
+    type Item = Result<T, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Poll::Ready(item) = this.inner.poll_next(cx) {
+            if item.is_some() {
+                this.sleep.as_mut().reset(Instant::now() + *this.interval);
+            }
+            return Poll::Ready(item);
+        }
+        if this.sleep.as_mut().poll(cx).is_ready() {
+            this.sleep.as_mut().reset(Instant::now() + *this.interval);
+            return Poll::Ready(Some(Ok((this.make)())));
+        }
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.size_hint().0, None)
+    }
+}
+
+impl<S, F> fmt::Debug for Heartbeat<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Wraps a received stream to fail with an `UNAVAILABLE` [`Status`] when no message is received
+/// within the `timeout`.
+///
+/// The timeout should be a few times of the heartbeat interval of the server, so that a delayed
+/// heartbeat does not fail the stream. Heartbeat messages can be dropped by
+/// [`Liveness::skip_heartbeat`].
+pub fn liveness<S, T>(stream: S, timeout: Duration) -> Liveness<S, fn(&T) -> bool>
+where
+    S: Stream<Item = Result<T, Status>>,
+{
+    Liveness {
+        inner: stream,
+        timeout,
+        sleep: Box::pin(tokio::time::sleep(timeout)),
+        is_heartbeat: |_| false,
+        lost: false,
+    }
+}
+
+/// Stream returned by [`liveness`].
+#[pin_project]
+pub struct Liveness<S, P> {
+    #[pin]
+    inner: S,
+    timeout: Duration,
+    sleep: Pin<Box<Sleep>>,
+    is_heartbeat: P,
+    lost: bool,
+}
+
+impl<S, P> Liveness<S, P> {
+    /// Drop the messages for which `is_heartbeat` returns `true`, they still keep the stream
+    /// alive.
+    pub fn skip_heartbeat<T, NP>(self, is_heartbeat: NP) -> Liveness<S, NP>
+    where
+        NP: FnMut(&T) -> bool,
+    {
+        Liveness {
+            inner: self.inner,
+            timeout: self.timeout,
+            sleep: self.sleep,
+            is_heartbeat,
+            lost: self.lost,
+        }
+    }
+}
+
+impl<S, T, P> Stream for Liveness<S, P>
+where
+    S: Stream<Item = Result<T, Status>>,
+    P: FnMut(&T) -> bool,
+{
+    type Item = Result<T, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if *this.lost {
+            return Poll::Ready(None);
+        }
+        loop {
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(message))) => {
+                    this.sleep.as_mut().reset(Instant::now() + *this.timeout);
+                    if (this.is_heartbeat)(&message) {
+                        continue;
+                    }
+                    return Poll::Ready(Some(Ok(message)));
+                }
+                Poll::Ready(item) => return Poll::Ready(item),
+                Poll::Pending => break,
+            }
+        }
+        if this.sleep.as_mut().poll(cx).is_ready() {
+            *this.lost = true;
+            return Poll::Ready(Some(Err(LivenessLost {
+                idle: Some(*this.timeout),
+            }
+            .into())));
+        }
+        Poll::Pending
+    }
+}
+
+impl<S, P> fmt::Debug for Liveness<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Liveness")
+            .field("timeout", &self.timeout)
+            .field("lost", &self.lost)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The source of the [`Status`] failing a stream whose peer is considered dead, see
+/// [`is_liveness_lost`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LivenessLost {
+    /// How long the stream has been idle, `None` for HTTP/2 PING timeouts.
+    pub idle: Option<Duration>,
+}
+
+impl fmt::Display for LivenessLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.idle {
+            Some(idle) => write!(f, "peer is not alive, no message within {idle:?}"),
+            None => f.write_str("peer is not alive, keepalive ping timed out"),
+        }
+    }
+}
+
+impl Error for LivenessLost {}
+
+impl From<LivenessLost> for Status {
+    fn from(err: LivenessLost) -> Self {
+        Status::unavailable(err.to_string()).with_source(err)
+    }
+}
+
+/// Returns whether the [`Status`] is caused by the loss of peer liveness, i.e., [`liveness`] or
+/// HTTP/2 keepalive timed out.
+pub fn is_liveness_lost(status: &Status) -> bool {
+    let mut source = status.source();
+    while let Some(err) = source {
+        if err.is::<LivenessLost>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod keepalive_tests {
+    use std::time::Duration;
+
+    use futures::{StreamExt, stream};
+
+    use super::{heartbeat, is_liveness_lost, liveness};
+    use crate::{Code, Status};
+
+    #[tokio::test]
+    async fn heartbeat_when_idle() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<u32, Status>>();
+        let stream = heartbeat(
+            tokio_stream::wrappers::UnboundedReceiverStream::new(rx),
+            Duration::from_millis(20),
+            || 0,
+        );
+        futures::pin_mut!(stream);
+
+        tx.send(Ok(1)).unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        // no message, a heartbeat is sent
+        assert_eq!(stream.next().await.unwrap().unwrap(), 0);
+        tx.send(Ok(2)).unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), 2);
+        drop(tx);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn liveness_lost() {
+        let server = heartbeat(
+            stream::iter([Ok(1u32)]).chain(stream::pending()),
+            Duration::from_millis(10),
+            || 0,
+        );
+        let client = liveness(server, Duration::from_millis(50)).skip_heartbeat(|n: &u32| *n == 0);
+        let received = tokio::time::timeout(
+            Duration::from_millis(200),
+            client.take(2).collect::<Vec<_>>(),
+        )
+        .await;
+        // heartbeats keep the stream alive and are skipped
+        assert_eq!(received.unwrap_err().to_string(), "deadline has elapsed");
+
+        let client = liveness(
+            stream::iter([Ok(1u32)]).chain(stream::pending()),
+            Duration::from_millis(20),
+        );
+        let received = client.collect::<Vec<_>>().await;
+        assert_eq!(received.len(), 2);
+        assert_eq!(*received[0].as_ref().unwrap(), 1);
+        let status = received[1].as_ref().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(is_liveness_lost(status));
+        assert!(!is_liveness_lost(&Status::unavailable("unavailable")));
+    }
+}
@@ -7,15 +7,23 @@ use volo::{
     Layer,
     context::Context,
     discovery::Discover,
-    loadbalance::{LoadBalance, MkLbLayer, error::LoadBalanceError},
+    event::{self, DiscoveryDiffApplied},
+    loadbalance::{
+        LoadBalance, MkLbLayer,
+        drain::{DrainPolicy, Drainer},
+        error::LoadBalanceError,
+        warmup::WarmupHandle,
+    },
 };
 
 use crate::Request;
 
-#[derive(Clone, Default, Copy)]
+#[derive(Clone, Default)]
 pub struct LoadBalanceLayer<D, LB> {
     discover: D,
     load_balance: LB,
+    drainer: Option<Drainer>,
+    warmup: Option<WarmupHandle>,
 }
 
 impl<D, LB> LoadBalanceLayer<D, LB> {
@@ -23,8 +31,22 @@ impl<D, LB> LoadBalanceLayer<D, LB> {
         LoadBalanceLayer {
             discover,
             load_balance,
+            drainer: None,
+            warmup: None,
         }
     }
+
+    /// Sets the [`Drainer`] notified when the discovery removes instances.
+    pub fn drainer(mut self, drainer: Drainer) -> Self {
+        self.drainer = Some(drainer);
+        self
+    }
+
+    /// Sets the [`WarmupHandle`] resolving instances by the discovery when warming up.
+    pub fn warmup(mut self, warmup: WarmupHandle) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
 }
 
 impl<D, LB, S> Layer<S> for LoadBalanceLayer<D, LB>
@@ -35,12 +57,18 @@ where
     type Service = LoadBalanceService<D, LB, S>;
 
     fn layer(self, inner: S) -> Self::Service {
-        LoadBalanceService::new(self.discover, self.load_balance, inner)
+        LoadBalanceService::with_watchers(
+            self.discover,
+            self.load_balance,
+            inner,
+            self.drainer,
+            self.warmup,
+        )
     }
 }
 #[derive(Clone)]
 pub struct LoadBalanceService<D, LB, S> {
-    discover: D,
+    discover: Arc<D>,
     load_balance: Arc<LB>,
     service: S,
 }
@@ -51,19 +79,72 @@ where
     LB: LoadBalance<D>,
 {
     pub fn new(discover: D, load_balance: LB, service: S) -> Self {
+        Self::with_drainer(discover, load_balance, service, None)
+    }
+
+    /// Create a [`LoadBalanceService`] which also notifies the [`Drainer`] when the discovery
+    /// removes instances.
+    pub fn with_drainer(
+        discover: D,
+        load_balance: LB,
+        service: S,
+        drainer: Option<Drainer>,
+    ) -> Self {
+        Self::with_watchers(discover, load_balance, service, drainer, None)
+    }
+
+    fn with_watchers(
+        discover: D,
+        load_balance: LB,
+        service: S,
+        drainer: Option<Drainer>,
+        warmup: Option<WarmupHandle>,
+    ) -> Self {
         let lb = Arc::new(load_balance);
 
         let service = Self {
-            discover,
+            discover: Arc::new(discover),
             load_balance: lb.clone(),
             service,
         };
 
+        if let Some(warmup) = warmup {
+            let (discover, lb) = (service.discover.clone(), lb.clone());
+            warmup.set_resolver(move |endpoint| {
+                let (discover, lb) = (discover.clone(), lb.clone());
+                Box::pin(async move {
+                    let instances = discover.discover(endpoint).await.map_err(Into::into)?;
+                    // fill the cache of the load balancer
+                    let _ = lb.get_picker(endpoint, &*discover).await;
+                    Ok(instances
+                        .iter()
+                        .map(|instance| instance.address.clone())
+                        .collect())
+                })
+            });
+        }
+
         if let Some(mut channel) = service.discover.watch(None) {
             tokio::spawn(async move {
                 loop {
                     match channel.recv().await {
-                        Ok(recv) => lb.rebalance(recv),
+                        Ok(recv) => {
+                            let emit = event::enabled::<DiscoveryDiffApplied>();
+                            if drainer.is_none() && !emit {
+                                lb.rebalance(recv);
+                                continue;
+                            }
+                            // stop picking the removed instances before draining their
+                            // connections
+                            let change = recv.clone();
+                            lb.rebalance(recv);
+                            if emit {
+                                event::emit(|| DiscoveryDiffApplied::from(&change));
+                            }
+                            if let Some(drainer) = &drainer {
+                                drainer.on_change(&change);
+                            }
+                        }
                         Err(err) => match err {
                             RecvError::Closed => break,
                             _ => warn!("[VOLO] discovering subscription error {:?}", err),
@@ -139,6 +220,8 @@ where
 pub struct LbConfig<L, DISC> {
     load_balance: L,
     discover: DISC,
+    drainer: Drainer,
+    warmup: WarmupHandle,
 }
 
 impl<L, DISC> LbConfig<L, DISC> {
@@ -146,6 +229,8 @@ impl<L, DISC> LbConfig<L, DISC> {
         LbConfig {
             load_balance,
             discover,
+            drainer: Drainer::default(),
+            warmup: WarmupHandle::default(),
         }
     }
 
@@ -153,6 +238,8 @@ impl<L, DISC> LbConfig<L, DISC> {
         LbConfig {
             load_balance,
             discover: self.discover,
+            drainer: self.drainer,
+            warmup: self.warmup,
         }
     }
 
@@ -160,8 +247,19 @@ impl<L, DISC> LbConfig<L, DISC> {
         LbConfig {
             load_balance: self.load_balance,
             discover,
+            drainer: self.drainer,
+            warmup: self.warmup,
         }
     }
+
+    /// Set a [`DrainPolicy`] deciding when connections to instances removed by the discovery are
+    /// drained.
+    ///
+    /// Default is draining immediately.
+    pub fn drain_policy<P: DrainPolicy>(mut self, policy: P) -> Self {
+        self.drainer = Drainer::new(policy);
+        self
+    }
 }
 
 impl<LB, DISC> MkLbLayer for LbConfig<LB, DISC> {
@@ -169,5 +267,15 @@ impl<LB, DISC> MkLbLayer for LbConfig<LB, DISC> {
 
     fn make(self) -> Self::Layer {
         LoadBalanceLayer::new(self.discover, self.load_balance)
+            .drainer(self.drainer)
+            .warmup(self.warmup)
+    }
+
+    fn drainer(&self) -> Option<Drainer> {
+        Some(self.drainer.clone())
+    }
+
+    fn warmup(&self) -> Option<WarmupHandle> {
+        Some(self.warmup.clone())
     }
 }
@@ -11,6 +11,9 @@ pub mod codec;
 #[doc(hidden)]
 pub mod codegen;
 pub mod context;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
+pub mod keepalive;
 pub mod layer;
 pub mod message;
 pub mod metadata;
@@ -19,7 +22,10 @@ pub mod response;
 pub mod server;
 pub mod status;
 pub mod tracing;
+#[cfg(feature = "json-transcoding")]
+pub mod transcoding;
 pub mod transport;
+pub mod wkt;
 
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 pub use client::Client;
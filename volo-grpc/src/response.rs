@@ -3,9 +3,32 @@
 use std::fmt::Debug;
 
 use http::Extensions;
+use volo::net::Address;
 
 use crate::metadata::MetadataMap;
 
+/// Initial metadata of a unary response, i.e., the headers received before the message, which is
+/// inserted into the extensions of the response by the generated client before the trailing
+/// metadata is merged into [`Response::metadata`].
+///
+/// It can be got by [`Response::initial_metadata`].
+#[derive(Debug, Clone)]
+pub struct InitialMetadata(pub MetadataMap);
+
+/// Trailing metadata of a unary response, which is inserted into the extensions of the response
+/// by the generated client.
+///
+/// It can be got by [`Response::trailers`].
+#[derive(Debug, Clone)]
+pub struct Trailers(pub MetadataMap);
+
+/// Address of the server that the response comes from, which is inserted into the extensions of
+/// the response by the client for all transports.
+///
+/// It can be got by [`Response::remote_addr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteAddr(pub Address);
+
 #[derive(Debug)]
 pub struct Response<T> {
     metadata: MetadataMap,
@@ -34,10 +57,37 @@ impl<T> Response<T> {
     }
 
     /// Get a reference to the custom response metadata.
+    ///
+    /// For unary responses received by clients, it contains both the initial metadata and the
+    /// trailing metadata, with values in the trailing metadata taking precedence. They can be got
+    /// separately by [`Response::initial_metadata`] and [`Response::trailers`].
     pub fn metadata(&self) -> &MetadataMap {
         &self.metadata
     }
 
+    /// Get the initial metadata, i.e., the headers of the response without the trailing metadata
+    /// merged into [`Response::metadata`] by the generated unary client.
+    pub fn initial_metadata(&self) -> &MetadataMap {
+        self.extensions
+            .get::<InitialMetadata>()
+            .map_or(&self.metadata, |m| &m.0)
+    }
+
+    /// Get the trailing metadata of a unary response received by the client.
+    ///
+    /// It is `None` for streaming responses, whose trailers can be got from the stream after all
+    /// messages are received, or if the server does not send any trailers.
+    pub fn trailers(&self) -> Option<&MetadataMap> {
+        self.extensions.get::<Trailers>().map(|t| &t.0)
+    }
+
+    /// Get the address of the server that the response comes from, which is resolved by the
+    /// discovery and picked by the load balancer of the client, or told by the proxy in the
+    /// middle.
+    pub fn remote_addr(&self) -> Option<&Address> {
+        self.extensions.get::<RemoteAddr>().map(|a| &a.0)
+    }
+
     /// Get a mutable reference to the response metadata.
     pub fn metadata_mut(&mut self) -> &mut MetadataMap {
         &mut self.metadata
@@ -0,0 +1,236 @@
+//! Filters of accepted connections.
+//!
+//! Rejecting unwanted peers in an interceptor still pays for the TLS handshake and the HTTP/2
+//! setup of each connection, which is costly when the server is under attack. An
+//! [`AcceptFilter`] is called right after a connection is accepted, and once more after the TLS
+//! handshake with the server name requested by the client, so the connection is closed as early
+//! as possible.
+//!
+//! [`IpFilter`] is a built-in filter with CIDR allow and deny lists.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_grpc::server::{Server, accept::IpFilter};
+//!
+//! let filter = IpFilter::new()
+//!     .allow("10.0.0.0/8".parse().unwrap())
+//!     .deny("10.0.1.0/24".parse().unwrap());
+//! let server = Server::new().accept_filter(filter);
+//! ```
+
+use std::{fmt, net::IpAddr, sync::Arc};
+
+use ipnet::IpNet;
+use volo::net::Address;
+
+/// Filter of accepted connections, see [`Server::accept_filter`] for more details.
+///
+/// Any [`Fn(Option<&Address>) -> bool`](Fn) is also an [`AcceptFilter`] that filters connections
+/// by their peer addresses.
+///
+/// [`Server::accept_filter`]: super::Server::accept_filter
+pub trait AcceptFilter: Send + Sync + 'static {
+    /// Called when a connection is accepted, before the TLS handshake and the HTTP/2 setup.
+    ///
+    /// Returns `false` to close the connection.
+    fn on_accept(&self, peer_addr: Option<&Address>) -> bool {
+        let _ = peer_addr;
+        true
+    }
+
+    /// Called after the TLS handshake with the server name requested by the client through SNI,
+    /// which is `None` if the client does not send it or the TLS backend does not provide it.
+    ///
+    /// It is not called if TLS is not enabled. Returns `false` to close the connection.
+    fn on_handshake(&self, peer_addr: Option<&Address>, server_name: Option<&str>) -> bool {
+        let _ = (peer_addr, server_name);
+        true
+    }
+}
+
+impl<F> AcceptFilter for F
+where
+    F: Fn(Option<&Address>) -> bool + Send + Sync + 'static,
+{
+    fn on_accept(&self, peer_addr: Option<&Address>) -> bool {
+        self(peer_addr)
+    }
+}
+
+/// [`AcceptFilter`] with CIDR allow and deny lists of peer addresses.
+///
+/// A peer is rejected if it matches the deny list, or the allow list is not empty and it does
+/// not match the allow list. Connections not from IP addresses, e.g., unix sockets, are always
+/// accepted. IPv4-mapped IPv6 addresses are matched as IPv4 addresses.
+#[derive(Clone, Debug, Default)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    /// Create an [`IpFilter`] accepting all peers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a CIDR block to the allow list.
+    pub fn allow(mut self, net: IpNet) -> Self {
+        self.allow.push(net);
+        self
+    }
+
+    /// Add CIDR blocks to the allow list.
+    pub fn allow_all<I>(mut self, nets: I) -> Self
+    where
+        I: IntoIterator<Item = IpNet>,
+    {
+        self.allow.extend(nets);
+        self
+    }
+
+    /// Add a CIDR block to the deny list.
+    pub fn deny(mut self, net: IpNet) -> Self {
+        self.deny.push(net);
+        self
+    }
+
+    /// Add CIDR blocks to the deny list.
+    pub fn deny_all<I>(mut self, nets: I) -> Self
+    where
+        I: IntoIterator<Item = IpNet>,
+    {
+        self.deny.extend(nets);
+        self
+    }
+
+    /// Returns whether the IP address is accepted by the filter.
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+impl AcceptFilter for IpFilter {
+    fn on_accept(&self, peer_addr: Option<&Address>) -> bool {
+        match peer_addr.and_then(Address::ip_addr) {
+            Some(addr) => self.is_allowed(addr.ip()),
+            None => true,
+        }
+    }
+}
+
+/// All filters set to the server, a connection is accepted only if all of them accept it.
+#[derive(Clone, Default)]
+pub(super) struct AcceptFilters {
+    filters: Vec<Arc<dyn AcceptFilter>>,
+}
+
+impl AcceptFilters {
+    pub(super) fn push<F: AcceptFilter>(&mut self, filter: F) {
+        self.filters.push(Arc::new(filter));
+    }
+
+    pub(super) fn on_accept(&self, peer_addr: Option<&Address>) -> bool {
+        let accepted = self.filters.iter().all(|f| f.on_accept(peer_addr));
+        if !accepted {
+            tracing::debug!("[VOLO] connection from {peer_addr:?} is rejected by accept filter");
+        }
+        accepted
+    }
+
+    #[cfg(feature = "__tls")]
+    pub(super) fn on_handshake(
+        &self,
+        peer_addr: Option<&Address>,
+        server_name: Option<&str>,
+    ) -> bool {
+        let accepted = self
+            .filters
+            .iter()
+            .all(|f| f.on_handshake(peer_addr, server_name));
+        if !accepted {
+            tracing::debug!(
+                "[VOLO] connection from {peer_addr:?} with server name {server_name:?} is \
+                 rejected by accept filter"
+            );
+        }
+        accepted
+    }
+}
+
+impl fmt::Debug for AcceptFilters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcceptFilters")
+            .field("len", &self.filters.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod accept_tests {
+    use std::net::SocketAddr;
+
+    use volo::net::Address;
+
+    use super::{AcceptFilter, AcceptFilters, IpFilter};
+
+    fn addr(s: &str) -> Address {
+        Address::from(s.parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn test_ip_filter() {
+        let filter = IpFilter::new()
+            .allow("10.0.0.0/8".parse().unwrap())
+            .allow("fd00::/8".parse().unwrap())
+            .deny("10.0.1.0/24".parse().unwrap());
+        assert!(filter.on_accept(Some(&addr("10.0.0.1:8000"))));
+        assert!(filter.on_accept(Some(&addr("[fd00::1]:8000"))));
+        // denied even it is in the allow list
+        assert!(!filter.on_accept(Some(&addr("10.0.1.1:8000"))));
+        // not in the allow list
+        assert!(!filter.on_accept(Some(&addr("192.168.0.1:8000"))));
+        // IPv4-mapped IPv6 address
+        assert!(!filter.on_accept(Some(&addr("[::ffff:10.0.1.1]:8000"))));
+        assert!(filter.on_accept(Some(&addr("[::ffff:10.0.0.1]:8000"))));
+        // no peer address
+        assert!(filter.on_accept(None));
+
+        // with only the deny list
+        let filter = IpFilter::new().deny_all(["127.0.0.0/8".parse().unwrap()]);
+        assert!(!filter.on_accept(Some(&addr("127.0.0.1:8000"))));
+        assert!(filter.on_accept(Some(&addr("192.168.0.1:8000"))));
+    }
+
+    #[test]
+    fn test_filters() {
+        struct ServerName;
+
+        impl AcceptFilter for ServerName {
+            fn on_handshake(&self, _: Option<&Address>, server_name: Option<&str>) -> bool {
+                server_name == Some("example.com")
+            }
+        }
+
+        let mut filters = AcceptFilters::default();
+        assert!(filters.on_accept(None));
+        filters.push(IpFilter::new().allow("10.0.0.0/8".parse().unwrap()));
+        filters.push(|peer_addr: Option<&Address>| peer_addr != Some(&addr("10.0.0.2:8000")));
+        filters.push(ServerName);
+        assert!(filters.on_accept(Some(&addr("10.0.0.1:8000"))));
+        assert!(!filters.on_accept(Some(&addr("10.0.0.2:8000"))));
+        assert!(!filters.on_accept(Some(&addr("192.168.0.1:8000"))));
+
+        #[cfg(feature = "__tls")]
+        {
+            let peer_addr = addr("10.0.0.1:8000");
+            assert!(filters.on_handshake(Some(&peer_addr), Some("example.com")));
+            assert!(!filters.on_handshake(Some(&peer_addr), None));
+        }
+    }
+}
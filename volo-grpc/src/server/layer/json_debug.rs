@@ -0,0 +1,541 @@
+//! [`Layer`] for rendering messages of sampled requests as protobuf JSON for debugging.
+//!
+//! [`JsonDebugLayer`] decodes messages of requests and responses with the descriptors embedded in
+//! the generated code, renders them to the canonical protobuf JSON and passes them to a
+//! [`DebugSink`], so that messages in production can be read by humans without writing code for
+//! each service.
+//!
+//! ```no_run
+//! # mod generated {
+//! #     pub fn file_descriptor_echo() -> &'static pilota::pb::reflect::FileDescriptor {
+//! #         unimplemented!()
+//! #     }
+//! # }
+//! # fn main() {
+//! use http::HeaderName;
+//! use volo_grpc::server::layer::json_debug::{DebugRecord, DescriptorRegistry, JsonDebugLayer};
+//!
+//! // descriptors generated by `volo-build`
+//! let registry = DescriptorRegistry::new().register(generated::file_descriptor_echo());
+//! let layer = JsonDebugLayer::new(registry, |record: DebugRecord| {
+//!     tracing::info!(
+//!         "{} {:?}#{}: {}",
+//!         record.method,
+//!         record.direction,
+//!         record.index,
+//!         record.json
+//!     );
+//! })
+//! .sample_ratio(0.001)
+//! .sample_header(HeaderName::from_static("x-debug"));
+//! # }
+//! ```
+//!
+//! The layer should be added by [`Server::layer`], and only messages of methods in the
+//! [`DescriptorRegistry`] are rendered. Compressed messages are not rendered.
+//!
+//! [`Server::layer`]: crate::server::Server::layer
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use faststr::FastStr;
+use http::{HeaderMap, HeaderName};
+use http_body::Frame;
+use http_body_util::BodyExt;
+use motore::{Service, layer::Layer};
+use pilota::pb::reflect::{FileDescriptor, MessageDescriptor};
+use rustc_hash::FxHashMap;
+
+use crate::{Request, Response, Status, body::BoxBody, context::ServerContext};
+
+const DEFAULT_MAX_MESSAGES: usize = 16;
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+// compressed flag and length of a message
+const HEADER_SIZE: usize = 5;
+
+/// Descriptors of methods for decoding their messages.
+#[derive(Clone, Debug, Default)]
+pub struct DescriptorRegistry {
+    // `/{package}.{service}/{method}` -> (input, output)
+    methods: FxHashMap<String, (MessageDescriptor, MessageDescriptor)>,
+}
+
+impl DescriptorRegistry {
+    /// Create an empty [`DescriptorRegistry`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register all methods of services in the file.
+    ///
+    /// The [`FileDescriptor`] is generated as `file_descriptor_{filename}()` for each proto file.
+    pub fn register(mut self, file: &FileDescriptor) -> Self {
+        let package = file.package();
+        for service in file.services() {
+            let service_name = match package {
+                "" => service.proto().name().to_owned(),
+                package => format!("{package}.{}", service.proto().name()),
+            };
+            for method in service.methods() {
+                let path = format!("/{service_name}/{}", method.proto().name());
+                self.methods
+                    .insert(path, (method.input_type(), method.output_type()));
+            }
+        }
+        self
+    }
+
+    fn get(&self, path: &str) -> Option<&(MessageDescriptor, MessageDescriptor)> {
+        self.methods.get(path)
+    }
+}
+
+/// Whether the message is of a request or a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The message is received from the client.
+    Request,
+    /// The message is sent to the client.
+    Response,
+}
+
+/// A message rendered as protobuf JSON.
+#[derive(Clone, Debug)]
+pub struct DebugRecord {
+    /// Path of the method, e.g., `/helloworld.Greeter/SayHello`.
+    pub method: FastStr,
+    /// Whether the message is of a request or a response.
+    pub direction: Direction,
+    /// Index of the message in the stream, it is always `0` for unary calls.
+    pub index: usize,
+    /// The message in protobuf JSON.
+    pub json: String,
+}
+
+/// Receiver of [`DebugRecord`]s.
+///
+/// It is implemented for closures with [`DebugRecord`] as parameter. Note that it is called when
+/// the message is sent or received, so it should not block.
+pub trait DebugSink: Send + Sync + 'static {
+    /// Record a rendered message.
+    fn record(&self, record: DebugRecord);
+}
+
+impl<F> DebugSink for F
+where
+    F: Fn(DebugRecord) + Send + Sync + 'static,
+{
+    fn record(&self, record: DebugRecord) {
+        self(record)
+    }
+}
+
+/// [`Layer`] for rendering messages of sampled requests as protobuf JSON.
+///
+/// See the [module documentation](self) for more details.
+pub struct JsonDebugLayer<K> {
+    registry: Arc<DescriptorRegistry>,
+    sink: Arc<K>,
+    ratio: f64,
+    header: Option<HeaderName>,
+    max_messages: usize,
+    max_message_size: usize,
+}
+
+impl<K> Clone for JsonDebugLayer<K> {
+    fn clone(&self) -> Self {
+        Self {
+            registry: self.registry.clone(),
+            sink: self.sink.clone(),
+            ratio: self.ratio,
+            header: self.header.clone(),
+            max_messages: self.max_messages,
+            max_message_size: self.max_message_size,
+        }
+    }
+}
+
+impl<K: DebugSink> JsonDebugLayer<K> {
+    /// Create a [`JsonDebugLayer`] with methods in the registry, which passes rendered messages
+    /// to the sink.
+    pub fn new(registry: DescriptorRegistry, sink: K) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            sink: Arc::new(sink),
+            ratio: 1.0,
+            header: None,
+            max_messages: DEFAULT_MAX_MESSAGES,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Set the ratio of requests to sample, in `[0, 1]`.
+    ///
+    /// Default is `1.0`, which means all requests are sampled.
+    pub fn sample_ratio(mut self, ratio: f64) -> Self {
+        self.ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Always sample requests with the header, regardless of the ratio.
+    ///
+    /// This is useful for debugging specific requests, e.g., with `.sample_ratio(0.0)`.
+    pub fn sample_header(mut self, header: HeaderName) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// Set the maximum number of messages to render in each direction of a call.
+    ///
+    /// Default is `16`.
+    pub fn max_messages(mut self, max: usize) -> Self {
+        self.max_messages = max;
+        self
+    }
+
+    /// Set the maximum size of a message to render, larger messages are skipped.
+    ///
+    /// Default is 64 KiB.
+    pub fn max_message_size(mut self, max: usize) -> Self {
+        self.max_message_size = max;
+        self
+    }
+
+    fn sampled(&self, headers: &HeaderMap) -> bool {
+        if self
+            .header
+            .as_ref()
+            .is_some_and(|h| headers.contains_key(h))
+        {
+            return true;
+        }
+        self.ratio >= 1.0 || random_unit() < self.ratio
+    }
+}
+
+impl<S, K> Layer<S> for JsonDebugLayer<K> {
+    type Service = JsonDebugService<S, K>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        JsonDebugService { inner, layer: self }
+    }
+}
+
+/// [`Service`] generated by [`JsonDebugLayer`].
+pub struct JsonDebugService<S, K> {
+    inner: S,
+    layer: JsonDebugLayer<K>,
+}
+
+impl<S: Clone, K> Clone for JsonDebugService<S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, K> Service<ServerContext, Request<BoxBody>> for JsonDebugService<S, K>
+where
+    S: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>> + Send + Sync,
+    K: DebugSink,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<BoxBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let method = cx.rpc_info.method().clone();
+        let types = match self.layer.registry.get(&method) {
+            Some(types) if self.layer.sampled(req.metadata().headers()) => types.clone(),
+            _ => return self.inner.call(cx, req).await,
+        };
+        let (input, output) = types;
+        let tap = |descriptor, direction| Tap {
+            method: method.clone(),
+            direction,
+            descriptor,
+            sink: self.layer.sink.clone(),
+            buf: BytesMut::new(),
+            index: 0,
+            skip: 0,
+            max_messages: self.layer.max_messages,
+            max_message_size: self.layer.max_message_size,
+        };
+
+        let req = req.map(|body| tap(input, Direction::Request).wrap(body));
+        let resp = self.inner.call(cx, req).await?;
+        Ok(resp.map(|body| tap(output, Direction::Response).wrap(body)))
+    }
+}
+
+struct Tap<K> {
+    method: FastStr,
+    direction: Direction,
+    descriptor: MessageDescriptor,
+    sink: Arc<K>,
+    buf: BytesMut,
+    index: usize,
+    // bytes of the current message to skip since it is too large
+    skip: usize,
+    max_messages: usize,
+    max_message_size: usize,
+}
+
+impl<K: DebugSink> Tap<K> {
+    fn wrap(self, body: BoxBody) -> BoxBody {
+        TapBody {
+            inner: body,
+            tap: self,
+        }
+        .boxed_unsync()
+    }
+
+    fn done(&self) -> bool {
+        self.index >= self.max_messages
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() && !self.done() {
+            if self.skip > 0 {
+                let n = self.skip.min(data.len());
+                self.skip -= n;
+                data = &data[n..];
+                continue;
+            }
+            if self.buf.len() < HEADER_SIZE {
+                let n = (HEADER_SIZE - self.buf.len()).min(data.len());
+                self.buf.extend_from_slice(&data[..n]);
+                data = &data[n..];
+                continue;
+            }
+            let len = u32::from_be_bytes(self.buf[1..HEADER_SIZE].try_into().unwrap()) as usize;
+            if len > self.max_message_size {
+                tracing::debug!(
+                    "[VOLO] message of {} is too large to render: {len} bytes",
+                    self.method
+                );
+                self.buf.clear();
+                self.skip = len;
+                self.index += 1;
+                continue;
+            }
+            let n = (HEADER_SIZE + len - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.buf.len() == HEADER_SIZE + len {
+                let mut message = self.buf.split().freeze();
+                let compressed = message.get_u8() != 0;
+                message.advance(4);
+                self.render(compressed, message);
+            }
+        }
+    }
+
+    fn render(&mut self, compressed: bool, message: Bytes) {
+        let index = self.index;
+        self.index += 1;
+        if compressed {
+            tracing::debug!(
+                "[VOLO] compressed message of {} is not rendered",
+                self.method
+            );
+            return;
+        }
+        let json = self
+            .descriptor
+            .parse_from_bytes(&message)
+            .map_err(|err| err.to_string())
+            .and_then(|message| {
+                protobuf_json_mapping::print_to_string(&*message).map_err(|err| err.to_string())
+            });
+        match json {
+            Ok(json) => self.sink.record(DebugRecord {
+                method: self.method.clone(),
+                direction: self.direction,
+                index,
+                json,
+            }),
+            Err(err) => tracing::debug!(
+                "[VOLO] failed to render message of {} as json: {err}",
+                self.method
+            ),
+        }
+    }
+}
+
+struct TapBody<K> {
+    inner: BoxBody,
+    tap: Tap<K>,
+}
+
+impl<K: DebugSink> http_body::Body for TapBody<K> {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        if let Some(Ok(Some(data))) = frame.as_ref().map(|f| f.as_ref().map(Frame::data_ref)) {
+            this.tap.feed(data);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Generate a random number in `[0, 1)`.
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod json_debug_tests {
+    use std::sync::{Arc, Mutex};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use http::HeaderName;
+    use http_body_util::{BodyExt, Full};
+    use motore::{Service, layer::Layer, service::service_fn};
+    use pilota::pb::{
+        descriptor::{
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, MethodDescriptorProto,
+            ServiceDescriptorProto,
+            field_descriptor_proto::{Label, Type},
+        },
+        reflect::FileDescriptor,
+    };
+
+    use super::{DebugRecord, DescriptorRegistry, Direction, JsonDebugLayer};
+    use crate::{Request, Response, Status, body::boxed, context::ServerContext};
+
+    fn file() -> FileDescriptor {
+        let field = |name: &str, number, ty| {
+            let mut field = FieldDescriptorProto::new();
+            field.set_name(name.to_owned());
+            field.set_json_name(name.to_owned());
+            field.set_number(number);
+            field.set_label(Label::LABEL_OPTIONAL);
+            field.set_type(ty);
+            field
+        };
+        let mut message = DescriptorProto::new();
+        message.set_name("EchoRequest".to_owned());
+        message.field.push(field("message", 1, Type::TYPE_STRING));
+        message.field.push(field("id", 2, Type::TYPE_INT64));
+
+        let mut method = MethodDescriptorProto::new();
+        method.set_name("Unary".to_owned());
+        method.set_input_type(".echo.EchoRequest".to_owned());
+        method.set_output_type(".echo.EchoRequest".to_owned());
+        let mut service = ServiceDescriptorProto::new();
+        service.set_name("Echo".to_owned());
+        service.method.push(method);
+
+        let mut file = FileDescriptorProto::new();
+        file.set_name("echo.proto".to_owned());
+        file.set_package("echo".to_owned());
+        file.set_syntax("proto3".to_owned());
+        file.message_type.push(message);
+        file.service.push(service);
+        FileDescriptor::new_dynamic(file, &[]).unwrap()
+    }
+
+    // `EchoRequest { message: "hi", id: 5 }` in a gRPC frame
+    fn frame() -> Bytes {
+        let message = [0x0a, 0x02, b'h', b'i', 0x10, 0x05];
+        let mut buf = BytesMut::new();
+        buf.put_u8(0);
+        buf.put_u32(message.len() as u32);
+        buf.put_slice(&message);
+        buf.freeze()
+    }
+
+    async fn call(layer: JsonDebugLayer<impl super::DebugSink>, path: &str, debug: bool) {
+        let echo = service_fn(
+            |_: &mut ServerContext, req: Request<crate::body::BoxBody>| async {
+                let body = req.into_inner().collect().await?.to_bytes();
+                Ok::<_, Status>(Response::new(boxed(Full::new(body))))
+            },
+        );
+        let service = layer.layer(echo);
+
+        let mut cx = ServerContext::default();
+        cx.rpc_info.set_method(path.to_owned().into());
+        let mut req = Request::new(boxed(Full::new(frame())));
+        if debug {
+            req.metadata_mut().insert("x-debug", "1".parse().unwrap());
+        }
+        let resp = service.call(&mut cx, req).await.unwrap();
+        let body = resp.into_inner().collect().await.unwrap().to_bytes();
+        assert_eq!(body, frame());
+    }
+
+    #[tokio::test]
+    async fn render() {
+        let records = Arc::new(Mutex::new(Vec::<DebugRecord>::new()));
+        let sink = {
+            let records = records.clone();
+            move |record: DebugRecord| records.lock().unwrap().push(record)
+        };
+        let layer = JsonDebugLayer::new(DescriptorRegistry::new().register(&file()), sink);
+
+        call(layer.clone(), "/echo.Echo/Unary", false).await;
+        {
+            let records = records.lock().unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].direction, Direction::Request);
+            assert_eq!(records[1].direction, Direction::Response);
+            assert_eq!(records[0].method, "/echo.Echo/Unary");
+            assert_eq!(records[0].json, r#"{"message": "hi", "id": "5"}"#);
+        }
+
+        // unknown methods are not rendered
+        call(layer.clone(), "/echo.Echo/Unknown", false).await;
+        assert_eq!(records.lock().unwrap().len(), 2);
+
+        // too large messages are skipped
+        call(layer.max_message_size(2), "/echo.Echo/Unary", false).await;
+        assert_eq!(records.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sample() {
+        let records = Arc::new(Mutex::new(Vec::<DebugRecord>::new()));
+        let sink = {
+            let records = records.clone();
+            move |record: DebugRecord| records.lock().unwrap().push(record)
+        };
+        let layer = JsonDebugLayer::new(DescriptorRegistry::new().register(&file()), sink)
+            .sample_ratio(0.0)
+            .sample_header(HeaderName::from_static("x-debug"));
+
+        call(layer.clone(), "/echo.Echo/Unary", false).await;
+        assert!(records.lock().unwrap().is_empty());
+        call(layer, "/echo.Echo/Unary", true).await;
+        assert_eq!(records.lock().unwrap().len(), 2);
+    }
+}
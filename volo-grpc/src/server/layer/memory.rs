@@ -0,0 +1,79 @@
+//! [`Layer`] for per-request memory accounting of servers.
+//!
+//! [`MemoryLayer`] creates a [`MemoryAccount`] for each request by the [`MemoryTracker`], charges
+//! the received messages to it and inserts it into the extensions of the context, see
+//! [`volo::memory`] for more details.
+//!
+//! A message is charged from when its length prefix is received until the next message begins,
+//! which is how long the decoder buffers it, and the call fails with `RESOURCE_EXHAUSTED` if a
+//! limit is exceeded. For compressed messages, the compressed lengths are charged.
+//!
+//! ```
+//! use volo::memory::MemoryTracker;
+//! use volo_grpc::server::layer::memory::MemoryLayer;
+//!
+//! let tracker = MemoryTracker::new()
+//!     .request_limit(16 * 1024 * 1024)
+//!     .method_limit("/echo.Echo/Upload", 256 * 1024 * 1024);
+//! let layer = MemoryLayer::new(tracker.clone());
+//! // `Server::new().layer(layer)`, and report `tracker.all_stats()` to metrics
+//! ```
+
+use motore::{Service, layer::Layer};
+use volo::{
+    context::Context,
+    memory::{MemoryAccount, MemoryTracker},
+};
+
+use crate::{Request, body::BoxBody, codec::limit::account_decoding, context::ServerContext};
+
+/// [`Layer`] accounting the memory held by requests, see the [module documentation](self) for
+/// more details.
+#[derive(Clone, Debug)]
+pub struct MemoryLayer {
+    tracker: MemoryTracker,
+}
+
+impl MemoryLayer {
+    /// Create a [`MemoryLayer`] accounting requests by the `tracker`.
+    pub fn new(tracker: MemoryTracker) -> Self {
+        Self { tracker }
+    }
+}
+
+impl<S> Layer<S> for MemoryLayer {
+    type Service = MemoryService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        MemoryService {
+            inner,
+            tracker: self.tracker,
+        }
+    }
+}
+
+/// [`Service`] generated by [`MemoryLayer`].
+#[derive(Clone, Debug)]
+pub struct MemoryService<S> {
+    inner: S,
+    tracker: MemoryTracker,
+}
+
+impl<S> Service<ServerContext, Request<BoxBody>> for MemoryService<S>
+where
+    S: Service<ServerContext, Request<BoxBody>> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<BoxBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let account: MemoryAccount = self.tracker.account(cx.rpc_info.method());
+        cx.extensions_mut().insert(account.clone());
+        let req = req.map(|body| account_decoding(body, account));
+        self.inner.call(cx, req).await
+    }
+}
@@ -1 +1,4 @@
+#[cfg(feature = "json-debug")]
+pub mod json_debug;
+pub mod memory;
 pub mod timeout;
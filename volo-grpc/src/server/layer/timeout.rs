@@ -85,7 +85,7 @@ where
 ///  Ok(Some(duration)) => if parse success.
 ///  Ok(None)           => if no success field.
 ///  Err(&HeaderValue)  => if parse timeout failed or wrong format.
-fn grpc_timeout_to_duration(
+pub(crate) fn grpc_timeout_to_duration(
     headers: &HeaderMap<HeaderValue>,
 ) -> Result<Option<Duration>, &HeaderValue> {
     const SECONDS_HOUR: u64 = 60 * 60;
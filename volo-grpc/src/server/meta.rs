@@ -8,7 +8,7 @@ use volo::{FastStr, Service, context::Context};
 use crate::{
     Request, Response, Status,
     body::BoxBody,
-    context::ServerContext,
+    context::{Config, ServerContext},
     metadata::{
         DESTINATION_SERVICE, HEADER_TRANS_REMOTE_ADDR, KeyAndValueRef, MetadataKey, SOURCE_SERVICE,
     },
@@ -28,6 +28,7 @@ macro_rules! status_to_http {
 pub struct MetaService<S, SP = DefaultProvider> {
     inner: S,
     span_provider: SP,
+    rpc_config: Config,
 }
 
 impl<S, SP> MetaService<S, SP> {
@@ -35,8 +36,15 @@ impl<S, SP> MetaService<S, SP> {
         MetaService {
             inner,
             span_provider,
+            rpc_config: Config::default(),
         }
     }
+
+    /// Sets the server-wide config set to the context of each request.
+    pub(crate) fn rpc_config(mut self, rpc_config: Config) -> Self {
+        self.rpc_config = rpc_config;
+        self
+    }
 }
 
 impl<S, SP> tower::Service<hyper::Request<BoxBody>> for MetaService<S, SP>
@@ -62,8 +70,10 @@ where
     fn call(&mut self, req: hyper::Request<BoxBody>) -> Self::Future {
         let inner = self.inner.clone();
         let span_provider = self.span_provider.clone();
+        let rpc_config = self.rpc_config.clone();
         async move {
             let mut cx = ServerContext::default();
+            cx.rpc_info_mut().set_config(rpc_config);
 
             metainfo::METAINFO
                 .scope(RefCell::new(metainfo::MetaInfo::default()), async move {
@@ -98,6 +108,9 @@ where
                                 .set_service_name(destination_service.into());
                         }
 
+                        #[cfg(feature = "context-propagation")]
+                        super::propagation::extract(metadata, &mut cx, &mut metainfo);
+
                         // persistent and transient
                         let mut vec = Vec::with_capacity(metadata.len());
                         for key_and_value in metadata.iter() {
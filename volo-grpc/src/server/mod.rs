@@ -2,13 +2,18 @@
 //!
 //! This module contains the low level component to build a gRPC server.
 
+pub mod accept;
 mod incoming;
 mod meta;
+#[cfg(feature = "context-propagation")]
+pub mod propagation;
 mod router;
 mod service;
+pub mod stream;
 
 use std::{fmt, io, time::Duration};
 
+use accept::{AcceptFilter, AcceptFilters};
 use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use incoming::IncomingService;
 pub use meta::MetaService;
@@ -22,16 +27,18 @@ use tower::util::BoxCloneService;
 #[cfg(feature = "__tls")]
 use volo::net::tls::ServerTlsConfig;
 use volo::{
+    context::Role,
     net::{conn::Conn, incoming::Incoming},
     spawn,
 };
 
 pub mod layer;
-pub use self::router::Router;
+pub use self::router::{RouteInfo, Router};
 use crate::{
     Request, Response, Status,
     body::BoxBody,
-    context::ServerContext,
+    codec::transform::MessageTransform,
+    context::{Config, ServerContext},
     tracing::{DefaultProvider, SpanProvider},
 };
 
@@ -50,6 +57,8 @@ pub struct Server<IL = Identity, OL = tower::layer::util::Identity, SP = Default
     inner_layer: IL,
     outer_layer: OL,
     http2_config: Http2Config,
+    accept_filters: AcceptFilters,
+    rpc_config: Config,
     router: Router,
     span_provider: SP,
 
@@ -70,6 +79,8 @@ impl Server<Identity, tower::layer::util::Identity, DefaultProvider> {
             inner_layer: Identity::new(),
             outer_layer: tower::layer::util::Identity::new(),
             http2_config: Http2Config::default(),
+            accept_filters: AcceptFilters::default(),
+            rpc_config: Config::default(),
             router: Router::new(),
             span_provider: DefaultProvider,
 
@@ -90,6 +101,69 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
         self
     }
 
+    /// Adds an [`AcceptFilter`] to the server, which can reject connections right after they are
+    /// accepted, and after the TLS handshake by the server name requested by the client.
+    ///
+    /// Rejected connections are closed before the HTTP/2 setup, which is much cheaper than
+    /// rejecting requests by interceptors. If multiple filters are added, a connection is accepted
+    /// only if all of them accept it.
+    ///
+    /// See [`IpFilter`] for a built-in filter with CIDR allow and deny lists.
+    ///
+    /// [`IpFilter`]: accept::IpFilter
+    pub fn accept_filter<F: AcceptFilter>(mut self, filter: F) -> Self {
+        self.accept_filters.push(filter);
+        self
+    }
+
+    /// Sets the max size of messages received by all services, larger messages are rejected with
+    /// `RESOURCE_EXHAUSTED` before they are buffered.
+    ///
+    /// For compressed messages, the limit applies to the compressed lengths. It can be
+    /// overridden for a service by [`ServiceBuilder::max_decoding_message_size`].
+    ///
+    /// Default is no limit.
+    pub fn max_decoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_decoding_message_size = Some(max);
+        self
+    }
+
+    /// Sets the max size of messages sent by all services, larger messages are not sent and the
+    /// call fails with `RESOURCE_EXHAUSTED`.
+    ///
+    /// It can be overridden for a service by [`ServiceBuilder::max_encoding_message_size`].
+    ///
+    /// Default is no limit.
+    pub fn max_encoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_encoding_message_size = Some(max);
+        self
+    }
+
+    /// Adds a [`MessageTransform`] accepted by all services, which is used if the client requests
+    /// it by name, see [`transform`](crate::codec::transform) for more details.
+    ///
+    /// Calls requesting transforms that are not added are rejected with `UNIMPLEMENTED`. It can be
+    /// overridden for a service by [`ServiceBuilder::message_transform`].
+    pub fn message_transform<T: MessageTransform>(mut self, transform: T) -> Self {
+        self.rpc_config
+            .message_transforms
+            .get_or_insert_with(Vec::new)
+            .push(std::sync::Arc::new(transform));
+        self
+    }
+
+    /// Sets whether the calls must be transformed if there are transforms, see
+    /// [`transform`](crate::codec::transform) for more details.
+    ///
+    /// Calls without transforms are rejected with `FAILED_PRECONDITION` if it's required. It can be
+    /// overridden for a service by [`ServiceBuilder::message_transform_required`].
+    ///
+    /// Default is true.
+    pub fn message_transform_required(mut self, required: bool) -> Self {
+        self.rpc_config.message_transform_required = Some(required);
+        self
+    }
+
     /// Sets the `SETTINGS_INITIAL_WINDOW_SIZE` option for HTTP2
     /// stream-level flow control.
     ///
@@ -179,6 +253,24 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
         self
     }
 
+    /// Sets the max age of connections, after which a connection is gracefully shut down by
+    /// sending a GOAWAY frame, so that clients reconnect and spread to new instances.
+    ///
+    /// Default is no limit (`None`).
+    pub fn http2_max_connection_age(mut self, age: impl Into<Option<Duration>>) -> Self {
+        self.http2_config.max_connection_age = age.into();
+        self
+    }
+
+    /// Sets the time to wait for pending requests after a connection reaches the age set by
+    /// [`Server::http2_max_connection_age`], after which the connection is forcibly closed.
+    ///
+    /// Default is waiting until all pending requests are completed (`None`).
+    pub fn http2_max_connection_age_grace(mut self, grace: impl Into<Option<Duration>>) -> Self {
+        self.http2_config.max_connection_age_grace = grace.into();
+        self
+    }
+
     /// Allow this server to accept http1 requests.
     ///
     /// Accepting http1 requests is only useful when developing `grpc-web`
@@ -211,6 +303,8 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
             inner_layer: Stack::new(layer, self.inner_layer),
             outer_layer: self.outer_layer,
             http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
             router: self.router,
             span_provider: self.span_provider,
             #[cfg(feature = "__tls")]
@@ -237,6 +331,8 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
             inner_layer: Stack::new(self.inner_layer, layer),
             outer_layer: self.outer_layer,
             http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
             router: self.router,
             span_provider: self.span_provider,
             #[cfg(feature = "__tls")]
@@ -266,6 +362,8 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
             inner_layer: self.inner_layer,
             outer_layer: tower::layer::util::Stack::new(layer, self.outer_layer),
             http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
             router: self.router,
             span_provider: self.span_provider,
             #[cfg(feature = "__tls")]
@@ -287,6 +385,8 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
             inner_layer: self.inner_layer,
             outer_layer: self.outer_layer,
             http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
             router: self.router.add_service(s),
             span_provider: self.span_provider,
             #[cfg(feature = "__tls")]
@@ -294,12 +394,129 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
         }
     }
 
+    /// Adds a new service to the router with a layer applied only to it, see
+    /// [`Router::add_service_with_layer`].
+    pub fn add_service_with_layer<S, L>(self, s: S, layer: L) -> Self
+    where
+        S: NamedService,
+        L: Layer<S>,
+        L::Service: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            inner_layer: self.inner_layer,
+            outer_layer: self.outer_layer,
+            http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
+            router: self.router.add_service_with_layer(s, layer),
+            span_provider: self.span_provider,
+            #[cfg(feature = "__tls")]
+            tls_config: self.tls_config,
+        }
+    }
+
+    /// Applies a layer only to requests to the exact method `path`, see [`Router::method_layer`].
+    pub fn method_layer<L>(self, path: impl Into<String>, layer: L) -> Self
+    where
+        L: Layer<
+            motore::BoxCloneService<ServerContext, Request<BoxBody>, Response<BoxBody>, Status>,
+        >,
+        L::Service: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            inner_layer: self.inner_layer,
+            outer_layer: self.outer_layer,
+            http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
+            router: self.router.method_layer(path, layer),
+            span_provider: self.span_provider,
+            #[cfg(feature = "__tls")]
+            tls_config: self.tls_config,
+        }
+    }
+
+    /// Sets the service handling requests to unknown paths, see [`Router::fallback`].
+    ///
+    /// By default, such requests are rejected with `UNIMPLEMENTED`.
+    pub fn fallback<S>(self, s: S) -> Self
+    where
+        S: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            inner_layer: self.inner_layer,
+            outer_layer: self.outer_layer,
+            http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
+            router: self.router.fallback(s),
+            span_provider: self.span_provider,
+            #[cfg(feature = "__tls")]
+            tls_config: self.tls_config,
+        }
+    }
+
+    /// Adds a service to the router for the exact `path`, see [`Router::route`].
+    pub fn route<S>(self, path: impl Into<String>, s: S) -> Self
+    where
+        S: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            inner_layer: self.inner_layer,
+            outer_layer: self.outer_layer,
+            http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
+            router: self.router.route(path, s),
+            span_provider: self.span_provider,
+            #[cfg(feature = "__tls")]
+            tls_config: self.tls_config,
+        }
+    }
+
+    /// Sets the prefix of the `:path` to be stripped before routing, e.g., `/twirp`, see
+    /// [`Router::path_prefix`].
+    ///
+    /// Note that the prefix is stripped by the router, so layers added by [`Server::layer`] still
+    /// see the method name with the prefix.
+    pub fn path_prefix(self, prefix: impl AsRef<str>) -> Self {
+        Self {
+            inner_layer: self.inner_layer,
+            outer_layer: self.outer_layer,
+            http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
+            router: self.router.path_prefix(prefix),
+            span_provider: self.span_provider,
+            #[cfg(feature = "__tls")]
+            tls_config: self.tls_config,
+        }
+    }
+
     /// Set a [`SpanProvider`] to the server.
     pub fn span_provider<P: SpanProvider>(self, provider: P) -> Server<IL, OL, P> {
         Server {
             inner_layer: self.inner_layer,
             outer_layer: self.outer_layer,
             http2_config: self.http2_config,
+            accept_filters: self.accept_filters,
+            rpc_config: self.rpc_config,
             router: self.router,
             span_provider: provider,
             #[cfg(feature = "__tls")]
@@ -344,12 +561,10 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
         let mut incoming = incoming.make_incoming().await?;
         tracing::info!("[VOLO] server start at: {:?}", incoming);
 
-        let service = self
-            .outer_layer
-            .layer(BoxCloneService::new(MetaService::new(
-                self.inner_layer.layer(self.router),
-                self.span_provider,
-            )));
+        let service = self.outer_layer.layer(BoxCloneService::new(
+            MetaService::new(self.inner_layer.layer(self.router), self.span_provider)
+                .rpc_config(self.rpc_config),
+        ));
 
         tokio::pin!(signal);
         let (tx, rx) = tokio::sync::watch::channel(());
@@ -369,6 +584,9 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
                         Some(c) => c,
                         None => return Ok(()),
                     };
+                    if !self.accept_filters.on_accept(conn.info.peer_addr.as_ref()) {
+                        continue;
+                    }
                     #[cfg(feature = "__tls")]
                     let conn = {
                         let Conn {
@@ -385,6 +603,16 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
                                         continue;
                                     },
                                 };
+                                let server_name = match &stream {
+                                    volo::net::conn::ConnStream::Tls(tls) => tls.server_name(),
+                                    _ => None,
+                                };
+                                if !self
+                                    .accept_filters
+                                    .on_handshake(info.peer_addr.as_ref(), server_name)
+                                {
+                                    continue;
+                                }
                                 Conn {
                                     stream,
                                     info,
@@ -399,6 +627,9 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
 
                     tracing::trace!("[VOLO] recv a connection from: {:?}", conn.info.peer_addr);
                     let peer_addr = conn.info.peer_addr.clone();
+                    let conn_event = peer_addr
+                        .as_ref()
+                        .and_then(|peer| volo::event::connection("grpc", Role::Server, peer));
 
                     let service = IncomingService::new(service.clone(), peer_addr);
 
@@ -421,7 +652,10 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
                         .max_header_list_size(self.http2_config.max_header_list_size);
 
                     let mut watch = rx.clone();
+                    let max_age = self.http2_config.max_connection_age;
+                    let max_age_grace = self.http2_config.max_connection_age_grace;
                     spawn(async move {
+                        let _conn_event = conn_event;
                         let mut http_conn = std::pin::pin!(server.serve_connection(
                             TokioIo::new(conn),
                             hyper::service::service_fn(move |req| {
@@ -431,6 +665,8 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
                                 }
                             })
                         ));
+                        let mut age = std::pin::pin!(sleep_or_pending(max_age));
+                        let mut grace = std::pin::pin!(sleep_or_pending(None));
                         loop {
                             tokio::select! {
                                 _ = watch.changed() => {
@@ -438,6 +674,18 @@ impl<IL, OL, SP> Server<IL, OL, SP> {
                                     // Graceful shutdown.
                                     http_conn.as_mut().graceful_shutdown();
                                 },
+                                _ = &mut age => {
+                                    tracing::trace!("[VOLO] closing a connection reaching max age");
+                                    http_conn.as_mut().graceful_shutdown();
+                                    age.set(sleep_or_pending(None));
+                                    grace.set(sleep_or_pending(max_age_grace));
+                                },
+                                _ = &mut grace => {
+                                    tracing::debug!(
+                                        "[VOLO] a connection is closed after max age grace"
+                                    );
+                                    break;
+                                },
                                 result = &mut http_conn => {
                                     if let Err(err) = result {
                                         tracing::debug!("[VOLO] connection error: {:?}", err);
@@ -487,11 +735,20 @@ impl<IL, OL, SP> fmt::Debug for Server<IL, OL, SP> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Server")
             .field("http2_config", &self.http2_config)
+            .field("accept_filters", &self.accept_filters)
             .field("router", &self.router)
             .finish()
     }
 }
 
+/// Sleeps for `duration`, or never completes if it is `None`.
+async fn sleep_or_pending(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
 const DEFAULT_KEEPALIVE_TIMEOUT_SECS: Duration = Duration::from_secs(20);
 const DEFAULT_CONN_WINDOW_SIZE: u32 = 1024 * 1024; // 1MB
 const DEFAULT_STREAM_WINDOW_SIZE: u32 = 1024 * 1024; // 1MB
@@ -510,6 +767,8 @@ pub struct Http2Config {
     pub(crate) max_frame_size: Option<u32>,
     pub(crate) max_send_buf_size: usize,
     pub(crate) max_header_list_size: u32,
+    pub(crate) max_connection_age: Option<Duration>,
+    pub(crate) max_connection_age_grace: Option<Duration>,
     pub(crate) accept_http1: bool,
 }
 
@@ -525,6 +784,8 @@ impl Default for Http2Config {
             max_frame_size: None,
             max_send_buf_size: DEFAULT_MAX_SEND_BUF_SIZE,
             max_header_list_size: DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE,
+            max_connection_age: None,
+            max_connection_age_grace: None,
             accept_http1: false,
         }
     }
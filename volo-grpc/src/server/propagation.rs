@@ -0,0 +1,112 @@
+//! Automatic context propagation on request ingress.
+//!
+//! With feature `context-propagation`, the server parses the following standard metadata of
+//! requests into [`metainfo`] and [`ServerContext`] before calling the handler:
+//!
+//! - `baggage` defined by [W3C Baggage](https://www.w3.org/TR/baggage/): each member is set as a
+//!   persistent of [`metainfo`], so it can be read by handlers and will be forwarded by downstream
+//!   clients automatically.
+//! - `grpc-timeout`: the remaining time is set as rpc timeout of [`ServerContext`], and a
+//!   [`Deadline`] is inserted into [`metainfo`], downstream clients will take the remaining time of
+//!   the deadline as the upper bound of their timeout.
+//!
+//! Caller service is always parsed from `source-service` no matter the feature is enabled or not.
+
+use std::time::{Duration, Instant};
+
+use metainfo::{Forward, MetaInfo};
+use volo::context::Context;
+
+use super::layer::timeout::grpc_timeout_to_duration;
+use crate::{
+    context::{Deadline, ServerContext},
+    metadata::MetadataMap,
+};
+
+/// Header name of W3C Baggage.
+pub const BAGGAGE_HEADER: &str = "baggage";
+
+pub(super) fn extract(metadata: &MetadataMap, cx: &mut ServerContext, metainfo: &mut MetaInfo) {
+    if let Some(baggage) = metadata.get(BAGGAGE_HEADER).and_then(|v| v.to_str().ok()) {
+        for (key, value) in parse_baggage(baggage) {
+            metainfo.set_persistent(key, value);
+        }
+    }
+
+    match grpc_timeout_to_duration(metadata.headers()) {
+        Ok(Some(timeout)) => {
+            cx.rpc_info_mut()
+                .config_mut()
+                .set_rpc_timeout(Some(timeout));
+            metainfo.insert(Deadline(Instant::now() + timeout));
+            // for the compatibility of `TimeoutLayer`
+            metainfo.insert::<Duration>(timeout);
+        }
+        Ok(None) => {}
+        Err(_) => tracing::trace!("[VOLO] error parsing grpc-timeout header"),
+    }
+}
+
+/// Parse members of W3C Baggage, properties of members are ignored and invalid members are
+/// skipped.
+pub fn parse_baggage(baggage: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    baggage.split(',').filter_map(|member| {
+        // properties are separated by `;`
+        let kv = member.split(';').next()?;
+        let (key, value) = kv.split_once('=')?;
+        let key = key.trim();
+        if key.is_empty() || !key.bytes().all(is_token_char) {
+            return None;
+        }
+        let value = percent_encoding::percent_decode_str(value.trim())
+            .decode_utf8()
+            .ok()?;
+        Some((key.to_owned(), value.into_owned()))
+    })
+}
+
+// `token` of RFC 7230
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use metainfo::Forward;
+
+    use super::*;
+
+    #[test]
+    fn baggage() {
+        let members: Vec<_> = parse_baggage(
+            "userId=alice, serverNode = DF%2028 ;p=1,bad key=v,isProduction=false,=x",
+        )
+        .collect();
+        assert_eq!(
+            members,
+            [
+                ("userId".to_owned(), "alice".to_owned()),
+                ("serverNode".to_owned(), "DF 28".to_owned()),
+                ("isProduction".to_owned(), "false".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_metadata() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(BAGGAGE_HEADER, "tenant=volo".parse().unwrap());
+        metadata.insert("grpc-timeout", "100m".parse().unwrap());
+        let mut cx = ServerContext::default();
+        let mut metainfo = MetaInfo::default();
+
+        extract(&metadata, &mut cx, &mut metainfo);
+        assert_eq!(metainfo.get_persistent("tenant").unwrap(), "volo");
+        assert_eq!(
+            cx.rpc_info().config().rpc_timeout(),
+            Some(Duration::from_millis(100))
+        );
+        let deadline = metainfo.get::<Deadline>().unwrap();
+        assert!(deadline.remaining() <= Duration::from_millis(100));
+    }
+}
@@ -4,9 +4,9 @@ use std::{
 };
 
 use http_body::Body as HttpBody;
-use motore::{BoxCloneService, Service};
+use motore::{BoxCloneService, Service, layer::Layer};
 use rustc_hash::FxHashMap;
-use volo::Unwrap;
+use volo::{FastStr, Unwrap, context::Context};
 
 use super::NamedService;
 use crate::{Request, Response, Status, body::BoxBody, context::ServerContext};
@@ -26,11 +26,48 @@ impl RouteId {
     }
 }
 
+/// The service name and method name of the route matched by the [`Router`].
+///
+/// It is inserted into the extensions of [`ServerContext`] before calling the matched service,
+/// and can be got by [`ServerContext::route_info`] for tagging metrics without parsing the path
+/// again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    service: FastStr,
+    method: FastStr,
+}
+
+impl RouteInfo {
+    /// Parses the `/{service}/{method}` path, the service name is empty if the path has only one
+    /// segment.
+    fn from_path(path: &FastStr) -> Self {
+        let trimmed = path.strip_prefix('/').unwrap_or(path);
+        let (service, method) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+        Self {
+            service: path.slice_ref(service),
+            method: path.slice_ref(method),
+        }
+    }
+
+    /// The full name of the service, e.g., `helloworld.Greeter`.
+    pub fn service(&self) -> &FastStr {
+        &self.service
+    }
+
+    /// The name of the method, e.g., `SayHello`.
+    pub fn method(&self) -> &FastStr {
+        &self.method
+    }
+}
+
+type RouteService<B> = BoxCloneService<ServerContext, Request<B>, Response<BoxBody>, Status>;
+
 #[derive(Default)]
 pub struct Router<B = BoxBody> {
-    routes:
-        FxHashMap<RouteId, BoxCloneService<ServerContext, Request<B>, Response<BoxBody>, Status>>,
+    routes: FxHashMap<RouteId, RouteService<B>>,
     node: matchit::Router<RouteId>,
+    path_prefix: Option<FastStr>,
+    fallback: Option<RouteService<B>>,
 }
 
 impl<B> Clone for Router<B> {
@@ -38,6 +75,8 @@ impl<B> Clone for Router<B> {
         Self {
             routes: self.routes.clone(),
             node: self.node.clone(),
+            path_prefix: self.path_prefix.clone(),
+            fallback: self.fallback.clone(),
         }
     }
 }
@@ -50,7 +89,49 @@ where
         Self {
             routes: Default::default(),
             node: Default::default(),
+            path_prefix: None,
+            fallback: None,
+        }
+    }
+
+    /// Sets the prefix of the `:path` to be stripped before routing, e.g., `/twirp` for
+    /// serving requests to `/twirp/{service}/{method}`.
+    ///
+    /// Requests without the prefix are still routed as usual. The method name in the context is
+    /// set to the path without the prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix does not start with `/`.
+    pub fn path_prefix(mut self, prefix: impl AsRef<str>) -> Self {
+        self.path_prefix = crate::transport::normalize_path_prefix(prefix.as_ref());
+        self
+    }
+
+    /// Adds a service to the router for the exact `path`.
+    ///
+    /// This is used for non-standard method paths, requests to `path` will be handled by the
+    /// `service` instead of the one registered for the service name by [`Router::add_service`].
+    pub fn route<S>(mut self, path: impl Into<String>, service: S) -> Self
+    where
+        S: Service<ServerContext, Request<B>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        let path = path.into();
+        if !path.starts_with('/') {
+            panic!("[VOLO] Paths must start with a `/`");
         }
+
+        let id = RouteId::next();
+
+        self.set_node(path, id);
+
+        self.routes.insert(id, BoxCloneService::new(service));
+
+        self
     }
 
     pub fn add_service<S>(mut self, service: S) -> Self
@@ -79,6 +160,79 @@ where
         self
     }
 
+    /// Adds a service to the router with a layer applied only to it.
+    ///
+    /// The layered service is registered for the name of `service`, so that several generated
+    /// services can be served by one server with their own middleware.
+    pub fn add_service_with_layer<S, L>(mut self, service: S, layer: L) -> Self
+    where
+        S: NamedService,
+        L: Layer<S>,
+        L::Service: Service<ServerContext, Request<B>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        let path = format!("/{}/{{*rest}}", S::NAME);
+        let id = RouteId::next();
+        self.set_node(path, id);
+        self.routes
+            .insert(id, BoxCloneService::new(layer.layer(service)));
+        self
+    }
+
+    /// Applies a layer only to requests to the exact method `path`, e.g.,
+    /// `/helloworld.Greeter/SayHello`.
+    ///
+    /// The layer wraps the service that `path` is routed to, so it must be called after the
+    /// service is added by [`Router::add_service`] or [`Router::route`], and is outside of the
+    /// layers added by [`Router::add_service_with_layer`]. Calling it more than once for the same
+    /// path stacks the layers, with the last one being the outermost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no service is routed for `path`.
+    pub fn method_layer<L>(mut self, path: impl Into<String>, layer: L) -> Self
+    where
+        L: Layer<RouteService<B>>,
+        L::Service: Service<ServerContext, Request<B>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        let path = path.into();
+        let (id, exact) = match self.node.at(&path) {
+            Ok(match_) => (*match_.value, match_.params.is_empty()),
+            Err(_) => panic!("[VOLO] No service is routed for `{path}`"),
+        };
+        let service = layer.layer(self.routes.get(&id).volo_unwrap().clone());
+        if exact {
+            // replace the exact route in place, since the path can not be inserted twice
+            self.routes.insert(id, BoxCloneService::new(service));
+            self
+        } else {
+            self.route(path, service)
+        }
+    }
+
+    /// Sets the service handling requests to paths that match no routes.
+    ///
+    /// By default, such requests are rejected with `UNIMPLEMENTED`. Note that [`RouteInfo`] is
+    /// not set for the fallback service.
+    pub fn fallback<S>(mut self, service: S) -> Self
+    where
+        S: Service<ServerContext, Request<B>, Response = Response<BoxBody>, Error = Status>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.fallback = Some(BoxCloneService::new(service));
+        self
+    }
+
     #[track_caller]
     fn set_node(&mut self, path: String, id: RouteId) {
         if let Err(err) = self.node.insert(path, id) {
@@ -99,22 +253,213 @@ where
         cx: &mut ServerContext,
         req: Request<B>,
     ) -> Result<Self::Response, Self::Error> {
+        if let Some(prefix) = &self.path_prefix {
+            if let Some(path) = strip_path_prefix(prefix, cx.rpc_info.method()) {
+                let path = FastStr::new(path);
+                cx.rpc_info.set_method(path);
+            }
+        }
         let path = cx.rpc_info.method();
         match self.node.at(path) {
             Ok(match_) => {
                 let id = match_.value;
                 let route = self.routes.get(id).volo_unwrap().clone();
+                let route_info = RouteInfo::from_path(path);
+                cx.extensions_mut().insert(route_info);
                 route.call(cx, req).await
             }
-            Err(err) => Err(Status::unimplemented(err.to_string())),
+            Err(err) => match &self.fallback {
+                Some(fallback) => fallback.clone().call(cx, req).await,
+                None => Err(Status::unimplemented(err.to_string())),
+            },
         }
     }
 }
 
+/// Strips the prefix from the `path`, the remaining path should be started with `/`.
+fn strip_path_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+    path.strip_prefix(prefix)
+        .filter(|path| path.starts_with('/'))
+}
+
 impl<B> fmt::Debug for Router<B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Router")
             .field("routes", &self.routes)
+            .field("path_prefix", &self.path_prefix)
+            .field("fallback", &self.fallback.is_some())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod router_tests {
+    use motore::{
+        Service,
+        layer::{Layer, layer_fn},
+        service::service_fn,
+    };
+    use volo::context::Context;
+
+    use super::{RouteInfo, Router, strip_path_prefix};
+    use crate::{
+        Request, Response, Status,
+        body::{BoxBody, empty_body},
+        context::ServerContext,
+        server::NamedService,
+    };
+
+    #[test]
+    fn test_strip_path_prefix() {
+        assert_eq!(
+            strip_path_prefix("/twirp", "/twirp/hello.Greeter/SayHello"),
+            Some("/hello.Greeter/SayHello")
+        );
+        assert_eq!(
+            strip_path_prefix("/twirp", "/twirpx/hello.Greeter/SayHello"),
+            None
+        );
+        assert_eq!(strip_path_prefix("/twirp", "/hello.Greeter/SayHello"), None);
+    }
+
+    async fn echo_method(
+        cx: &mut ServerContext,
+        _: Request<BoxBody>,
+    ) -> Result<Response<BoxBody>, Status> {
+        Err(Status::ok(cx.rpc_info().method().to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_route_with_prefix() {
+        let router = Router::new()
+            .path_prefix("/twirp/")
+            .route("/hello.Greeter/SayHello", service_fn(echo_method));
+
+        for path in ["/twirp/hello.Greeter/SayHello", "/hello.Greeter/SayHello"] {
+            let mut cx = ServerContext::default();
+            cx.rpc_info.set_method(path.into());
+            let status = router
+                .call(&mut cx, Request::new(empty_body()))
+                .await
+                .unwrap_err();
+            assert_eq!(status.message(), "/hello.Greeter/SayHello");
+        }
+
+        let mut cx = ServerContext::default();
+        cx.rpc_info
+            .set_method("/other/hello.Greeter/SayHello".into());
+        let status = router
+            .call(&mut cx, Request::new(empty_body()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), crate::Code::Unimplemented);
+    }
+
+    #[derive(Clone)]
+    struct Greeter;
+
+    impl NamedService for Greeter {
+        const NAME: &'static str = "hello.Greeter";
+    }
+
+    impl Service<ServerContext, Request<BoxBody>> for Greeter {
+        type Response = Response<BoxBody>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            cx: &mut ServerContext,
+            req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let route_info = cx.route_info().unwrap();
+            let mut message = format!("{}.{}", route_info.service(), route_info.method());
+            if let Some(tag) = req.metadata().get("tag") {
+                message = format!("{message}:{}", tag.to_str().unwrap());
+            }
+            Err(Status::ok(message))
+        }
+    }
+
+    /// Appends the name to the `tag` metadata.
+    #[derive(Clone)]
+    struct Tag<S>(&'static str, S);
+
+    impl<S> Service<ServerContext, Request<BoxBody>> for Tag<S>
+    where
+        S: Service<ServerContext, Request<BoxBody>, Response = Response<BoxBody>, Error = Status>
+            + Sync,
+    {
+        type Response = Response<BoxBody>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            cx: &mut ServerContext,
+            mut req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let tag = match req.metadata().get("tag") {
+                Some(tag) => format!("{}{}", tag.to_str().unwrap(), self.0),
+                None => self.0.to_owned(),
+            };
+            req.metadata_mut().insert("tag", tag.parse().unwrap());
+            self.1.call(cx, req).await
+        }
+    }
+
+    fn tag<S>(name: &'static str) -> impl Layer<S, Service = Tag<S>> + Clone {
+        layer_fn(move |inner| Tag(name, inner))
+    }
+
+    async fn call(router: &Router, path: &str) -> Status {
+        let mut cx = ServerContext::default();
+        cx.rpc_info.set_method(path.to_owned().into());
+        router
+            .call(&mut cx, Request::new(empty_body()))
+            .await
+            .unwrap_err()
+    }
+
+    #[test]
+    fn test_route_info() {
+        let info = RouteInfo::from_path(&"/hello.Greeter/SayHello".into());
+        assert_eq!(info.service(), "hello.Greeter");
+        assert_eq!(info.method(), "SayHello");
+        let info = RouteInfo::from_path(&"/health".into());
+        assert_eq!(info.service(), "");
+        assert_eq!(info.method(), "health");
+    }
+
+    #[tokio::test]
+    async fn test_service_and_method_layers() {
+        let router = Router::new()
+            .add_service_with_layer(Greeter, tag("s"))
+            .method_layer("/hello.Greeter/SayHello", tag("m1"))
+            .method_layer("/hello.Greeter/SayHello", tag("m2"));
+
+        let status = call(&router, "/hello.Greeter/SayHello").await;
+        assert_eq!(status.message(), "hello.Greeter.SayHello:m2m1s");
+        let status = call(&router, "/hello.Greeter/SayHi").await;
+        assert_eq!(status.message(), "hello.Greeter.SayHi:s");
+        let status = call(&router, "/hello.Other/SayHello").await;
+        assert_eq!(status.code(), crate::Code::Unimplemented);
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let router = Router::new()
+            .add_service(Greeter)
+            .fallback(service_fn(echo_method));
+
+        let status = call(&router, "/hello.Greeter/SayHello").await;
+        assert_eq!(status.message(), "hello.Greeter.SayHello");
+        let status = call(&router, "/hello.Other/SayHello").await;
+        assert_eq!(status.code(), crate::Code::Ok);
+        assert_eq!(status.message(), "/hello.Other/SayHello");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_method_layer_without_route() {
+        let _ = Router::<BoxBody>::new().method_layer("/hello.Greeter/SayHello", tag("m"));
+    }
+}
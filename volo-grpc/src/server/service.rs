@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
 
 use motore::{
     layer::{Identity, Layer, Stack},
@@ -12,11 +12,15 @@ use crate::{
     codec::{
         compression::{CompressionEncoding, ENCODING_HEADER},
         decode::Kind,
+        limit::{limit_decoding, limit_decompression, limit_encoding},
+        transform::{self, MessageTransform, TRANSFORM_HEADER},
     },
     context::{Config, ServerContext},
     message::{RecvEntryMessage, SendEntryMessage},
     metadata::MetadataValue,
 };
+#[cfg(feature = "compress")]
+use crate::{codec::compression::ACCEPT_ENCODING_HEADER, metadata::AsciiMetadataValue};
 
 #[derive(Clone)]
 pub struct ServiceBuilder<S, L> {
@@ -54,6 +58,53 @@ impl<S, L> ServiceBuilder<S, L> {
         self
     }
 
+    /// Sets the max size of messages received by the service, larger messages are rejected with
+    /// `RESOURCE_EXHAUSTED` before they are buffered.
+    ///
+    /// This overrides [`Server::max_decoding_message_size`] for the service. Default is no limit.
+    ///
+    /// [`Server::max_decoding_message_size`]: super::Server::max_decoding_message_size
+    pub fn max_decoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_decoding_message_size = Some(max);
+        self
+    }
+
+    /// Sets the max size of messages sent by the service, larger messages are not sent and the
+    /// call fails with `RESOURCE_EXHAUSTED`.
+    ///
+    /// This overrides [`Server::max_encoding_message_size`] for the service. Default is no limit.
+    ///
+    /// [`Server::max_encoding_message_size`]: super::Server::max_encoding_message_size
+    pub fn max_encoding_message_size(mut self, max: usize) -> Self {
+        self.rpc_config.max_encoding_message_size = Some(max);
+        self
+    }
+
+    /// Adds a [`MessageTransform`] accepted by the service, which is used if the client requests
+    /// it by name.
+    ///
+    /// This overrides the transforms set by [`Server::message_transform`] for the service.
+    ///
+    /// [`Server::message_transform`]: super::Server::message_transform
+    pub fn message_transform<T: MessageTransform>(mut self, transform: T) -> Self {
+        self.rpc_config
+            .message_transforms
+            .get_or_insert_with(Vec::new)
+            .push(Arc::new(transform));
+        self
+    }
+
+    /// Sets whether the calls must be transformed if the service has transforms, see
+    /// [`transform`](crate::codec::transform) for more details.
+    ///
+    /// This overrides [`Server::message_transform_required`] for the service.
+    ///
+    /// [`Server::message_transform_required`]: super::Server::message_transform_required
+    pub fn message_transform_required(mut self, required: bool) -> Self {
+        self.rpc_config.message_transform_required = Some(required);
+        self
+    }
+
     pub fn layer<O>(self, layer: O) -> ServiceBuilder<S, Stack<O, L>> {
         ServiceBuilder {
             layer: Stack::new(layer, self.layer),
@@ -109,6 +160,16 @@ impl<S, T, U> CodecService<S, T, U> {
             _marker: PhantomData,
         }
     }
+
+    /// Value of `grpc-accept-encoding` for advertising the accepted compression encodings.
+    #[cfg(feature = "compress")]
+    fn accept_encoding_header_value(&self) -> Option<AsciiMetadataValue> {
+        let encodings = self.rpc_config.accept_compressions.as_ref()?;
+        let value = encodings
+            .first()?
+            .into_accept_encoding_header_value(encodings)?;
+        Some(MetadataValue::unchecked_from_header_value(value))
+    }
 }
 
 impl<S, T, U> Service<ServerContext, Request<BoxBody>> for CodecService<S, T, U>
@@ -141,7 +202,48 @@ where
         let recv_compression = CompressionEncoding::from_encoding_header(
             metadata.headers(),
             &self.rpc_config.accept_compressions,
+        )
+        .map_err(|mut status| {
+            // tell the client what we accept, so that it can fallback to them
+            if let Some(value) = self.accept_encoding_header_value() {
+                status.metadata_mut().insert(ACCEPT_ENCODING_HEADER, value);
+            }
+            status
+        })?;
+
+        let server_config = cx.rpc_info.config();
+        let max_decoding_message_size = self
+            .rpc_config
+            .max_decoding_message_size
+            .or(server_config.max_decoding_message_size);
+        let max_encoding_message_size = self
+            .rpc_config
+            .max_encoding_message_size
+            .or(server_config.max_encoding_message_size);
+        let transform = transform::negotiate(
+            metadata.headers(),
+            self.rpc_config
+                .message_transforms
+                .as_ref()
+                .or(server_config.message_transforms.as_ref())
+                .map_or(&[], Vec::as_slice),
+            self.rpc_config
+                .message_transform_required
+                .or(server_config.message_transform_required)
+                .unwrap_or(true),
         )?;
+        let body = match max_decoding_message_size {
+            Some(max) => limit_decoding(body, max),
+            None => body,
+        };
+        let body = match &transform {
+            Some(transform) => transform::transform_decoding(body, transform.clone()),
+            None => body,
+        };
+        let body = match (max_decoding_message_size, recv_compression) {
+            (Some(max), Some(encoding)) => limit_decompression(body, encoding, max),
+            _ => body,
+        };
 
         let message = T::from_body(
             Some(cx.rpc_info.method().as_str()),
@@ -158,15 +260,37 @@ where
 
         cx.stats.record_process_end_at();
 
-        let mut resp =
-            volo_resp.map(|message| boxed(Body::new(message.into_body(send_compression))));
+        let mut resp = volo_resp.map(|message| {
+            let stream = message.into_body(send_compression);
+            let stream = match &transform {
+                Some(transform) => transform::transform_encoding(stream, transform.clone()),
+                None => stream,
+            };
+            let stream = match max_encoding_message_size {
+                Some(max) => limit_encoding(stream, max),
+                None => stream,
+            };
+            boxed(Body::new(stream))
+        });
 
+        if let Some(transform) = &transform {
+            if let Ok(value) = transform
+                .name()
+                .parse::<crate::metadata::AsciiMetadataValue>()
+            {
+                resp.metadata_mut().insert(TRANSFORM_HEADER, value);
+            }
+        }
         if let Some(encoding) = send_compression {
             resp.metadata_mut().insert(
                 ENCODING_HEADER,
                 MetadataValue::unchecked_from_header_value(encoding.into_header_value()),
             );
         };
+        #[cfg(feature = "compress")]
+        if let Some(value) = self.accept_encoding_header_value() {
+            resp.metadata_mut().insert(ACCEPT_ENCODING_HEADER, value);
+        }
 
         Ok(resp)
     }
@@ -0,0 +1,510 @@
+//! Bounded channel for sending messages of streaming responses.
+//!
+//! Messages of a streaming response are buffered until the client receives them, so a slow
+//! client may balloon the memory of server if the handler keeps producing messages. [`channel`]
+//! bounds the buffered-but-unsent messages of a stream by both the count and the bytes, and
+//! applies the [`OverflowPolicy`] when the bound is exceeded.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_grpc::{
+//!     BoxStream, Response, Status,
+//!     server::stream::{BufferLimits, OverflowPolicy, channel},
+//! };
+//!
+//! # #[derive(Debug, Default, Clone, PartialEq)]
+//! # struct Item;
+//! # impl pilota::pb::Message for Item {
+//! #     fn encoded_len(&self, _: &mut pilota::pb::EncodeLengthContext) -> usize { 0 }
+//! #     fn encode_raw(&self, _: &mut pilota::LinkedBytes) {}
+//! #     fn merge_field(
+//! #         &mut self,
+//! #         _: u32,
+//! #         _: pilota::pb::encoding::WireType,
+//! #         _: &mut pilota::Bytes,
+//! #         _: &mut pilota::pb::encoding::DecodeContext,
+//! #         _: bool,
+//! #     ) -> Result<(), pilota::pb::DecodeError> { Ok(()) }
+//! # }
+//! async fn list() -> Result<Response<BoxStream<'static, Result<Item, Status>>>, Status> {
+//!     let limits = BufferLimits::new()
+//!         .max_messages(64)
+//!         .max_bytes(1024 * 1024)
+//!         .overflow_policy(OverflowPolicy::Abort);
+//!     let (tx, rx) = channel(limits);
+//!     tokio::spawn(async move {
+//!         loop {
+//!             // stop producing if the stream is aborted or the client is gone
+//!             if tx.send(Ok(Item)).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     });
+//!     Ok(Response::new(Box::pin(rx)))
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::{Pin, pin},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use futures_util::task::AtomicWaker;
+use pilota::pb::{EncodeLengthContext, Message};
+use tokio::sync::Notify;
+
+use crate::Status;
+
+const DEFAULT_MAX_MESSAGES: usize = 32;
+const DEFAULT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// What to do when a message sent to the stream exceeds the [`BufferLimits`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the client receives the buffered messages, which applies backpressure to the
+    /// sender.
+    #[default]
+    Wait,
+    /// Abort the stream with `RESOURCE_EXHAUSTED` immediately.
+    Abort,
+    /// Wait for the duration, and abort the stream with `RESOURCE_EXHAUSTED` if the client still
+    /// does not receive the buffered messages.
+    AbortAfter(Duration),
+}
+
+/// Limits of buffered-but-unsent messages of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferLimits {
+    max_messages: usize,
+    max_bytes: usize,
+    policy: OverflowPolicy,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BufferLimits {
+    /// Create a default [`BufferLimits`], which buffers at most 32 messages and 4 MiB, and waits
+    /// when they are exceeded.
+    pub fn new() -> Self {
+        Self {
+            max_messages: DEFAULT_MAX_MESSAGES,
+            max_bytes: DEFAULT_MAX_BYTES,
+            policy: OverflowPolicy::Wait,
+        }
+    }
+
+    /// Sets the max count of buffered messages, it should be greater than zero.
+    ///
+    /// Default is 32.
+    pub fn max_messages(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_messages should be greater than zero");
+        self.max_messages = max;
+        self
+    }
+
+    /// Sets the max encoded bytes of buffered messages.
+    ///
+    /// A message larger than it can still be sent when there is no message buffered.
+    ///
+    /// Default is 4 MiB.
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = max;
+        self
+    }
+
+    /// Sets the [`OverflowPolicy`].
+    ///
+    /// Default is [`OverflowPolicy::Wait`].
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// Error returned by [`StreamSender::send`].
+pub enum SendError<T> {
+    /// The stream is closed, e.g., the client is gone or the stream has been aborted, and the
+    /// message is returned.
+    Closed(Result<T, Status>),
+    /// The stream has been aborted by the [`OverflowPolicy`] because of the message.
+    Aborted,
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed(_) => f.write_str("Closed(..)"),
+            Self::Aborted => f.write_str("Aborted"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Closed(_) => f.write_str("stream is closed"),
+            Self::Aborted => f.write_str("stream is aborted since the buffer limits are exceeded"),
+        }
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    limits: BufferLimits,
+    // wakes the receiver when a message is sent or all senders are dropped
+    rx_waker: AtomicWaker,
+    // notifies the senders when there is space or the receiver is dropped
+    tx_notify: Notify,
+}
+
+struct State<T> {
+    queue: VecDeque<(Result<T, Status>, usize)>,
+    bytes: usize,
+    senders: usize,
+    closed: bool,
+}
+
+impl<T> Shared<T> {
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> State<T> {
+    fn fits(&self, limits: &BufferLimits, len: usize) -> bool {
+        self.queue.is_empty()
+            || (self.queue.len() < limits.max_messages && self.bytes + len <= limits.max_bytes)
+    }
+}
+
+/// Create a bounded channel for a streaming response with the [`BufferLimits`].
+///
+/// The [`StreamReceiver`] can be returned as the response stream, and messages are sent by the
+/// [`StreamSender`].
+pub fn channel<T>(limits: BufferLimits) -> (StreamSender<T>, StreamReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            bytes: 0,
+            senders: 1,
+            closed: false,
+        }),
+        limits,
+        rx_waker: AtomicWaker::new(),
+        tx_notify: Notify::new(),
+    });
+    (
+        StreamSender {
+            shared: shared.clone(),
+        },
+        StreamReceiver { shared },
+    )
+}
+
+/// Sender of the channel created by [`channel`].
+pub struct StreamSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Message> StreamSender<T> {
+    /// Send a message or a [`Status`] to the stream, waiting for the space by the
+    /// [`OverflowPolicy`] if the [`BufferLimits`] are exceeded.
+    ///
+    /// A [`Status`] ends the stream after buffered messages are sent.
+    pub async fn send(&self, item: Result<T, Status>) -> Result<(), SendError<T>> {
+        let len = match &item {
+            Ok(message) => message.encoded_len(&mut EncodeLengthContext::default()),
+            Err(_) => 0,
+        };
+        let deadline = match self.shared.limits.policy {
+            OverflowPolicy::AbortAfter(timeout) => Some(tokio::time::Instant::now() + timeout),
+            _ => None,
+        };
+
+        let mut item = Some(item);
+        loop {
+            let mut notified = pin!(self.shared.tx_notify.notified());
+            // register before checking the state, so that the notification is not missed
+            notified.as_mut().enable();
+            {
+                let mut state = self.shared.state();
+                if state.closed {
+                    return Err(SendError::Closed(item.take().unwrap()));
+                }
+                if state.fits(&self.shared.limits, len) {
+                    state.bytes += len;
+                    state.queue.push_back((item.take().unwrap(), len));
+                    drop(state);
+                    self.shared.rx_waker.wake();
+                    return Ok(());
+                }
+                if self.shared.limits.policy == OverflowPolicy::Abort {
+                    self.abort(state);
+                    return Err(SendError::Aborted);
+                }
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        let state = self.shared.state();
+                        if state.closed {
+                            return Err(SendError::Closed(item.take().unwrap()));
+                        }
+                        if !state.fits(&self.shared.limits, len) {
+                            self.abort(state);
+                            return Err(SendError::Aborted);
+                        }
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    /// Drop the buffered messages, and end the stream with `RESOURCE_EXHAUSTED`.
+    fn abort(&self, mut state: MutexGuard<'_, State<T>>) {
+        tracing::warn!(
+            "[VOLO] aborting the stream since its buffer is exceeded, buffered messages: {}, \
+             bytes: {}",
+            state.queue.len(),
+            state.bytes
+        );
+        state.queue.clear();
+        state.bytes = 0;
+        state.queue.push_back((
+            Err(Status::resource_exhausted(
+                "too many messages are buffered for the stream",
+            )),
+            0,
+        ));
+        state.closed = true;
+        drop(state);
+        self.shared.rx_waker.wake();
+        self.shared.tx_notify.notify_waiters();
+    }
+}
+
+impl<T> StreamSender<T> {
+    /// Returns whether the stream is closed, e.g., the client is gone or the stream has been
+    /// aborted.
+    pub fn is_closed(&self) -> bool {
+        self.shared.state().closed
+    }
+
+    /// Returns the count and the encoded bytes of messages buffered now.
+    pub fn buffered(&self) -> (usize, usize) {
+        let state = self.shared.state();
+        (state.queue.len(), state.bytes)
+    }
+}
+
+impl<T> Clone for StreamSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.rx_waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for StreamSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (messages, bytes) = self.buffered();
+        f.debug_struct("StreamSender")
+            .field("limits", &self.shared.limits)
+            .field("messages", &messages)
+            .field("bytes", &bytes)
+            .finish()
+    }
+}
+
+/// Receiver of the channel created by [`channel`], it is a [`Stream`] of messages.
+pub struct StreamReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Stream for StreamReceiver<T> {
+    type Item = Result<T, Status>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.rx_waker.register(cx.waker());
+        let mut state = self.shared.state();
+        if let Some((item, len)) = state.queue.pop_front() {
+            state.bytes -= len;
+            drop(state);
+            self.shared.tx_notify.notify_waiters();
+            return Poll::Ready(Some(item));
+        }
+        if state.closed || state.senders == 0 {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for StreamReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state();
+        state.closed = true;
+        state.queue.clear();
+        drop(state);
+        self.shared.tx_notify.notify_waiters();
+    }
+}
+
+impl<T> fmt::Debug for StreamReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamReceiver")
+            .field("limits", &self.shared.limits)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::{BufferLimits, OverflowPolicy, SendError, channel};
+    use crate::{Code, codec::encode::tests::EchoRequest};
+
+    fn message(s: &'static str) -> EchoRequest {
+        EchoRequest { message: s.into() }
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let (tx, mut rx) = channel(BufferLimits::new().max_messages(2));
+        tx.send(Ok(message("a"))).await.unwrap();
+        tx.send(Ok(message("b"))).await.unwrap();
+        assert_eq!(tx.buffered().0, 2);
+
+        // the sender waits until a message is received
+        let send = tokio::spawn(async move {
+            tx.send(Ok(message("c"))).await.unwrap();
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!send.is_finished());
+
+        assert_eq!(rx.next().await.unwrap().unwrap(), message("a"));
+        send.await.unwrap();
+        assert_eq!(rx.next().await.unwrap().unwrap(), message("b"));
+        assert_eq!(rx.next().await.unwrap().unwrap(), message("c"));
+        // all senders are dropped
+        assert!(rx.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        // each message is encoded to 3 bytes
+        let (tx, mut rx) = channel(BufferLimits::new().max_bytes(5));
+        // a message larger than the limit can be sent if nothing is buffered
+        let (big_tx, mut big_rx) = channel(BufferLimits::new().max_bytes(1));
+        big_tx.send(Ok(message("a"))).await.unwrap();
+        assert_eq!(big_rx.next().await.unwrap().unwrap(), message("a"));
+
+        tx.send(Ok(message("a"))).await.unwrap();
+        assert_eq!(tx.buffered(), (1, 3));
+        let result =
+            tokio::time::timeout(Duration::from_millis(10), tx.send(Ok(message("b")))).await;
+        assert!(result.is_err());
+        rx.next().await.unwrap().unwrap();
+        tx.send(Ok(message("b"))).await.unwrap();
+        assert_eq!(tx.buffered(), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let (tx, mut rx) = channel(
+            BufferLimits::new()
+                .max_messages(1)
+                .overflow_policy(OverflowPolicy::Abort),
+        );
+        tx.send(Ok(message("a"))).await.unwrap();
+        assert!(matches!(
+            tx.send(Ok(message("b"))).await,
+            Err(SendError::Aborted)
+        ));
+        assert!(tx.is_closed());
+        assert!(matches!(
+            tx.send(Ok(message("c"))).await,
+            Err(SendError::Closed(_))
+        ));
+
+        // buffered messages are dropped
+        let status = rx.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(rx.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_abort_after() {
+        let (tx, mut rx) = channel(
+            BufferLimits::new()
+                .max_messages(1)
+                .overflow_policy(OverflowPolicy::AbortAfter(Duration::from_millis(20))),
+        );
+        tx.send(Ok(message("a"))).await.unwrap();
+        assert!(matches!(
+            tx.send(Ok(message("b"))).await,
+            Err(SendError::Aborted)
+        ));
+        assert_eq!(
+            rx.next().await.unwrap().unwrap_err().code(),
+            Code::ResourceExhausted
+        );
+
+        // the client receives messages in time
+        let (tx, mut rx) = channel(
+            BufferLimits::new()
+                .max_messages(1)
+                .overflow_policy(OverflowPolicy::AbortAfter(Duration::from_millis(100))),
+        );
+        tx.send(Ok(message("a"))).await.unwrap();
+        let recv = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut messages = Vec::new();
+            while let Some(item) = rx.next().await {
+                messages.push(item.unwrap());
+            }
+            messages
+        });
+        tx.send(Ok(message("b"))).await.unwrap();
+        drop(tx);
+        assert_eq!(recv.await.unwrap(), vec![message("a"), message("b")]);
+    }
+
+    #[tokio::test]
+    async fn test_receiver_dropped() {
+        let (tx, rx) = channel(BufferLimits::new().max_messages(1));
+        tx.send(Ok(message("a"))).await.unwrap();
+        let send = tokio::spawn(async move { tx.send(Ok(message("b"))).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(rx);
+        assert!(matches!(send.await.unwrap(), Err(SendError::Closed(_))));
+    }
+}
@@ -8,7 +8,12 @@ use http::header::{HeaderMap, HeaderValue};
 use percent_encoding::{AsciiSet, CONTROLS, percent_decode, percent_encode};
 use tower::BoxError;
 use tracing::{debug, trace, warn};
-use volo::loadbalance::error::{LoadBalanceError, Retryable};
+use volo::{
+    error::{ConnectError, ErrorCategory, ErrorOrigin, RpcError},
+    loadbalance::error::{LoadBalanceError, Retryable},
+    net::Address,
+    timeout::TimeoutError,
+};
 
 use crate::{BASE64_ENGINE, body::BoxBody, metadata::MetadataMap};
 
@@ -53,6 +58,12 @@ pub struct Status {
     metadata: MetadataMap,
     /// Optional underlying error.
     source: Option<Arc<dyn Error + Send + Sync + 'static>>,
+    /// Whether the status is replied by the peer, i.e., parsed from the headers or trailers.
+    origin: ErrorOrigin,
+    /// The address of the peer the call is sent to, which is set by the client.
+    ///
+    /// It's boxed since the `Address` is large and the `Status` is returned in hot paths.
+    peer: Option<Box<Address>>,
 }
 
 /// gRPC status codes used by `Status`.
@@ -176,6 +187,8 @@ impl Status {
             details: Bytes::new(),
             metadata: MetadataMap::new(),
             source: None,
+            origin: ErrorOrigin::Local,
+            peer: None,
         }
     }
 
@@ -352,6 +365,34 @@ impl Status {
 
     // transform between http2 and grpc error code.
     // refer to https://github.com/grpc/grpc/blob/master/doc/statuscodes.md.
+    pub(crate) fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Returns the [`TimeoutError`] telling which timeout fired if the call is timeout on the
+    /// client side.
+    pub fn timeout_error(&self) -> Option<&TimeoutError> {
+        TimeoutError::find(&**self.source.as_ref()?)
+    }
+
+    /// Returns the [`ConnectError`] if the client failed to connect to the peer.
+    pub fn connect_error(&self) -> Option<&ConnectError> {
+        ConnectError::find(&**self.source.as_ref()?)
+    }
+
+    /// Get the address of the peer the call is sent to, which is only known on the client side.
+    pub fn peer(&self) -> Option<&Address> {
+        self.peer
+            .as_deref()
+            .or_else(|| self.connect_error().map(ConnectError::addr))
+    }
+
+    pub(crate) fn with_peer(mut self, peer: Address) -> Self {
+        self.peer = Some(Box::new(peer));
+        self
+    }
+
     pub fn from_h2_error(err: Box<h2::Error>) -> Self {
         let code = Self::code_from_h2(&err);
 
@@ -407,7 +448,10 @@ impl Status {
         // > The service is currently unavailable. This is most likely a transient condition that
         // > can be corrected if retried with a backoff.
         if err.is_timeout() {
-            return Some(Self::unavailable(err.to_string()));
+            return Some(
+                Self::unavailable(err.to_string())
+                    .with_source(crate::keepalive::LivenessLost { idle: None }),
+            );
         }
         if let Some(h2_err) = err.source().and_then(|e| e.downcast_ref::<h2::Error>()) {
             let code = Self::code_from_h2(h2_err);
@@ -466,6 +510,8 @@ impl Status {
                     details,
                     metadata: MetadataMap::from_headers(other_headers),
                     source: None,
+                    origin: ErrorOrigin::Remote,
+                    peer: None,
                 },
                 Err(err) => {
                     warn!("[VOLO] Error deserializing status message header: {}", err);
@@ -475,6 +521,8 @@ impl Status {
                         details,
                         metadata: MetadataMap::from_headers(other_headers),
                         source: None,
+                        origin: ErrorOrigin::Remote,
+                        peer: None,
                     }
                 }
             }
@@ -613,6 +661,8 @@ impl Status {
             details,
             metadata,
             source: None,
+            origin: ErrorOrigin::Local,
+            peer: None,
         }
     }
 
@@ -640,6 +690,8 @@ fn find_status_in_source_chain(err: &(dyn Error + 'static)) -> Option<Status> {
                 details: status.details.clone(),
                 metadata: status.metadata.clone(),
                 source: None,
+                origin: status.origin,
+                peer: status.peer.clone(),
             });
         }
 
@@ -677,6 +729,14 @@ impl fmt::Debug for Status {
 
         builder.field("source", &self.source);
 
+        if self.origin == ErrorOrigin::Remote {
+            builder.field("origin", &self.origin);
+        }
+
+        if let Some(peer) = &self.peer {
+            builder.field("peer", peer);
+        }
+
         builder.finish()
     }
 }
@@ -724,7 +784,7 @@ impl From<std::io::Error> for Status {
             ErrorKind::UnexpectedEof => Code::OutOfRange,
             _ => Code::Unknown,
         };
-        Self::new(code, err.to_string())
+        Self::new(code, err.to_string()).with_source(err)
     }
 }
 
@@ -775,7 +835,41 @@ impl Retryable for Status {
         matches!(
             self.code,
             Code::Internal | Code::Unavailable | Code::Cancelled | Code::ResourceExhausted
-        )
+        ) || self.timeout_error().is_some_and(Retryable::retryable)
+            || self.connect_error().is_some()
+    }
+}
+
+/// The statuses parsed from the headers or trailers are replied by the server, whose categories
+/// are [`ErrorCategory::Biz`] except [`Code::DeadlineExceeded`].
+impl RpcError for Status {
+    fn category(&self) -> ErrorCategory {
+        if self.code == Code::DeadlineExceeded || self.timeout_error().is_some() {
+            return ErrorCategory::Timeout;
+        }
+        if self.connect_error().is_some() {
+            return ErrorCategory::Connect;
+        }
+        match (self.origin, self.code) {
+            (ErrorOrigin::Remote, _) => ErrorCategory::Biz,
+            (_, Code::Unavailable | Code::Cancelled | Code::Aborted) => ErrorCategory::Transport,
+            (_, Code::Internal | Code::DataLoss | Code::Unimplemented) => ErrorCategory::Protocol,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        self.origin
+    }
+
+    fn peer_addr(&self) -> Option<Address> {
+        self.peer().cloned()
+    }
+}
+
+impl From<TimeoutError> for Status {
+    fn from(err: TimeoutError) -> Self {
+        Self::deadline_exceeded(err.to_string()).with_source(err)
     }
 }
 
@@ -1031,4 +1125,65 @@ mod tests {
 
         assert_eq!(status.details(), DETAILS);
     }
+
+    #[test]
+    fn timeout_error() {
+        use std::time::Duration;
+
+        use volo::timeout::TimeoutKind;
+
+        let status = Status::from(TimeoutError::new(
+            TimeoutKind::Request,
+            Duration::from_millis(10),
+        ));
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+        assert_eq!(status.timeout_error().unwrap().kind(), TimeoutKind::Request);
+        assert!(status.retryable());
+
+        let status = Status::from(TimeoutError::new(
+            TimeoutKind::Total,
+            Duration::from_secs(1),
+        ));
+        assert_eq!(status.timeout_error().unwrap().kind(), TimeoutKind::Total);
+        assert!(!status.retryable());
+
+        // connect timeouts are returned as io errors by transports
+        let io_err =
+            TimeoutError::new(TimeoutKind::Connect, Duration::from_secs(1)).into_io_error();
+        let status = Status::from_error(Box::new(Nested(Box::new(io_err))));
+        assert_eq!(status.timeout_error().unwrap().kind(), TimeoutKind::Connect);
+        assert!(status.retryable());
+    }
+
+    #[test]
+    fn rpc_error() {
+        use std::{error::Error as _, io, net::SocketAddr};
+
+        let addr = Address::from(SocketAddr::from(([127, 0, 0, 1], 8888)));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let status = Status::from_error(Box::new(Nested(Box::new(
+            ConnectError::new(addr.clone(), refused).into_io_error(),
+        ))));
+        assert_eq!(status.category(), ErrorCategory::Connect);
+        assert_eq!(status.origin(), ErrorOrigin::Local);
+        assert_eq!(status.peer_addr(), Some(addr.clone()));
+        assert!(status.retryable());
+
+        let status = Status::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(status.category(), ErrorCategory::Transport);
+        assert!(status.source().unwrap().is::<io::Error>());
+
+        let header_map = Status::unavailable("overloaded").to_header_map().unwrap();
+        let status = Status::from_header_map(&header_map)
+            .unwrap()
+            .with_peer(addr.clone());
+        assert_eq!(status.category(), ErrorCategory::Biz);
+        assert_eq!(status.origin(), ErrorOrigin::Remote);
+        assert_eq!(status.peer(), Some(&addr));
+        assert!(status.retryable());
+        // kept when found in the sources
+        let status = Status::from_error(Box::new(Nested(Box::new(status))));
+        assert_eq!(status.origin(), ErrorOrigin::Remote);
+        assert_eq!(status.peer(), Some(&addr));
+    }
 }
@@ -0,0 +1,594 @@
+//! JSON transcoding of unary methods by the `google.api.http` annotations.
+//!
+//! With the `json_transcoding` option of `volo-build`, a `{service}_http_router` is generated for
+//! each service with `google.api.http` annotations, which mounts the RESTful routes on a
+//! `volo-http` router and forwards them to the implementation of the gRPC service:
+//!
+//! ```protobuf
+//! service Items {
+//!   rpc GetItem(GetItemRequest) returns (Item) {
+//!     option (google.api.http) = { get: "/v1/{name=items/*}" };
+//!   }
+//! }
+//! ```
+//!
+//! ```ignore
+//! let router = volo_gen::demo::items_http_router(ItemsImpl);
+//! ```
+//!
+//! The generated handlers use [`Transcoder`] for mapping requests and responses:
+//!
+//! - fields bound by the path template are set from the path,
+//! - the body is parsed as the canonical protobuf JSON of the message or the field specified by
+//!   `body`,
+//! - the other fields are set from the query, if `body` is not `*`,
+//! - the response message, or the field specified by `response_body`, is rendered as the canonical
+//!   protobuf JSON.
+//!
+//! Failed calls are responded by the HTTP status of their codes, see [`http_status`], with a JSON
+//! body of `{"code": .., "message": ..}`. Streaming methods are not transcoded, and the JSON of
+//! `google.protobuf.Any` is not supported by the mapping yet.
+
+use std::{fmt, future::Future};
+
+use bytes::Bytes;
+use http::{HeaderValue, StatusCode, header};
+use percent_encoding::percent_decode_str;
+use pilota::pb::{
+    EncodeLengthContext, Message,
+    reflect::{
+        FieldDescriptor, FileDescriptor, MessageDescriptor, ReflectValueBox, RuntimeFieldType,
+        RuntimeType,
+    },
+};
+use protobuf::MessageDyn;
+
+use crate::{Code, Request, Response, Status, metadata::MetadataMap};
+
+/// Where the request message is read from the body.
+#[derive(Clone)]
+enum RequestBody {
+    None,
+    Message,
+    Field(FieldDescriptor),
+}
+
+/// Mapping between the JSON requests and responses and the messages of a method.
+#[derive(Clone)]
+pub struct Transcoder {
+    input: MessageDescriptor,
+    output: MessageDescriptor,
+    body: RequestBody,
+    response_body: Option<FieldDescriptor>,
+}
+
+impl fmt::Debug for Transcoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transcoder")
+            .field("input", &self.input.full_name())
+            .field("output", &self.output.full_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transcoder {
+    /// Create a [`Transcoder`] of the method of the service in the file, which reads nothing from
+    /// the body.
+    ///
+    /// The [`FileDescriptor`] is generated as `file_descriptor_{filename}()` for each proto file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method is not found.
+    pub fn new(file: &FileDescriptor, service: &str, method: &str) -> Self {
+        let method = file
+            .services()
+            .find(|s| s.proto().name() == service)
+            .and_then(|s| s.methods().find(|m| m.proto().name() == method))
+            .unwrap_or_else(|| panic!("method {service}.{method} not found"));
+        Self {
+            input: method.input_type(),
+            output: method.output_type(),
+            body: RequestBody::None,
+            response_body: None,
+        }
+    }
+
+    /// Set the `body` of the binding, `*` for the whole request message, or the name of a field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the field is not found.
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = match body {
+            "" => RequestBody::None,
+            "*" => RequestBody::Message,
+            name => RequestBody::Field(find_field(&self.input, name)),
+        };
+        self
+    }
+
+    /// Set the `response_body` of the binding, i.e., the name of the field rendered as the
+    /// response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the field is not found.
+    pub fn response_body(mut self, response_body: &str) -> Self {
+        self.response_body = match response_body {
+            "" => None,
+            name => Some(find_field(&self.output, name)),
+        };
+        self
+    }
+
+    /// Decode the request message from the fields bound by the path, the query and the body.
+    ///
+    /// Each binding is the path of a field, e.g., `book.name`, and its value which is
+    /// percent-encoded as in the path.
+    pub fn decode_request<M>(
+        &self,
+        bindings: &[(&str, String)],
+        query: Option<&str>,
+        body: &[u8],
+    ) -> Result<M, Status>
+    where
+        M: Message + Default,
+    {
+        let body = std::str::from_utf8(body)
+            .map_err(|_| Status::invalid_argument("request body is not valid UTF-8"))?
+            .trim();
+        let mut message = match &self.body {
+            RequestBody::None => self.input.new_instance(),
+            RequestBody::Message if body.is_empty() => self.input.new_instance(),
+            RequestBody::Message => protobuf_json_mapping::parse_dyn_from_str(&self.input, body)
+                .map_err(|err| Status::invalid_argument(format!("invalid request body: {err}")))?,
+            RequestBody::Field(field) => {
+                let mut message = self.input.new_instance();
+                if !body.is_empty() {
+                    // parse `{"field": body}` for all kinds of fields
+                    let json = format!("{{\"{}\":{body}}}", field.name());
+                    protobuf_json_mapping::merge_from_str(&mut *message, &json).map_err(|err| {
+                        Status::invalid_argument(format!("invalid request body: {err}"))
+                    })?;
+                }
+                message
+            }
+        };
+
+        for (path, value) in bindings {
+            let value = percent_decode_str(value)
+                .decode_utf8()
+                .map_err(|_| Status::invalid_argument(format!("invalid path param: {path}")))?;
+            if !set_field(&mut *message, path, &value)? {
+                return Err(Status::invalid_argument(format!(
+                    "unknown path param: {path}"
+                )));
+            }
+        }
+
+        if !matches!(self.body, RequestBody::Message) {
+            for (key, value) in query.into_iter().flat_map(parse_query) {
+                let bound = bindings.iter().any(|(path, _)| *path == key)
+                    || matches!(&self.body, RequestBody::Field(field) if is_prefix(field.name(), &key));
+                if bound {
+                    continue;
+                }
+                // unknown params are ignored, e.g., access tokens for proxies
+                set_field(&mut *message, &key, &value)?;
+            }
+        }
+
+        let bytes = message
+            .write_to_bytes_dyn()
+            .map_err(|err| Status::internal(format!("failed to encode request: {err}")))?;
+        M::decode(Bytes::from(bytes))
+            .map_err(|err| Status::internal(format!("failed to decode request: {err}")))
+    }
+
+    /// Render the response message, or the field of `response_body`, as the canonical protobuf
+    /// JSON.
+    pub fn encode_response<M: Message>(&self, message: &M) -> Result<String, Status> {
+        let bytes = message.encode_to_vec(&mut EncodeLengthContext::default());
+        let message = self
+            .output
+            .parse_from_bytes(&bytes)
+            .map_err(|err| Status::internal(format!("failed to decode response: {err}")))?;
+        let json = protobuf_json_mapping::print_to_string(&*message)
+            .map_err(|err| Status::internal(format!("failed to render response: {err}")))?;
+        let Some(field) = &self.response_body else {
+            return Ok(json);
+        };
+
+        let mut value: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|err| Status::internal(format!("failed to render response: {err}")))?;
+        let value = match value.get_mut(field.json_name()) {
+            Some(value) => value.take(),
+            // default values are omitted
+            None => match field.runtime_field_type() {
+                RuntimeFieldType::Singular(RuntimeType::Message(_)) => serde_json::json!({}),
+                RuntimeFieldType::Singular(_) => return Ok(default_json(&*message, field)),
+                RuntimeFieldType::Repeated(_) => serde_json::json!([]),
+                RuntimeFieldType::Map(..) => serde_json::json!({}),
+            },
+        };
+        Ok(value.to_string())
+    }
+
+    /// Call the method by the transcoded request, and transcode the response or the error.
+    ///
+    /// The headers of the request are passed as the metadata, and the metadata of the response are
+    /// returned as the headers.
+    pub async fn call<Req, Resp, F, Fut>(
+        &self,
+        bindings: &[(&str, String)],
+        req: http::Request<Bytes>,
+        f: F,
+    ) -> http::Response<String>
+    where
+        Req: Message + Default,
+        Resp: Message,
+        F: FnOnce(Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Resp>, Status>>,
+    {
+        let (mut parts, body) = req.into_parts();
+        let message = match self.decode_request(bindings, parts.uri.query(), &body) {
+            Ok(message) => message,
+            Err(status) => return error_response(&status),
+        };
+        for name in [
+            header::HOST,
+            header::CONTENT_TYPE,
+            header::CONTENT_LENGTH,
+            header::TRANSFER_ENCODING,
+            header::CONNECTION,
+        ] {
+            parts.headers.remove(name);
+        }
+        let req = Request::from_parts(
+            MetadataMap::from_headers(parts.headers),
+            parts.extensions,
+            message,
+        );
+
+        let (metadata, _, message) = match f(req).await {
+            Ok(resp) => resp.into_parts(),
+            Err(status) => return error_response(&status),
+        };
+        match self.encode_response(&message) {
+            Ok(json) => {
+                let mut resp = http::Response::new(json);
+                *resp.headers_mut() = metadata.into_sanitized_headers();
+                resp.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                );
+                resp
+            }
+            Err(status) => error_response(&status),
+        }
+    }
+}
+
+/// The HTTP status of the gRPC status code, as defined by `google.rpc.Code`.
+pub fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        // 499 Client Closed Request
+        Code::Cancelled => StatusCode::from_u16(499).expect("valid status code"),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// The response of the failed call, i.e., `{"code": .., "message": ..}` with the HTTP status of
+/// the code.
+pub fn error_response(status: &Status) -> http::Response<String> {
+    let body = serde_json::json!({
+        "code": i32::from(status.code()),
+        "message": status.message(),
+    });
+    let mut resp = http::Response::new(body.to_string());
+    *resp.status_mut() = http_status(status.code());
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn find_field(message: &MessageDescriptor, name: &str) -> FieldDescriptor {
+    message
+        .field_by_name(name)
+        .unwrap_or_else(|| panic!("field {name} not found in {}", message.full_name()))
+}
+
+fn is_prefix(field: &str, path: &str) -> bool {
+    path == field
+        || path
+            .strip_prefix(field)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Render the default value of a singular field, which is omitted by `print_to_string`.
+fn default_json(message: &dyn MessageDyn, field: &FieldDescriptor) -> String {
+    match field.get_singular_field_or_default(message).to_box() {
+        ReflectValueBox::String(s) => serde_json::Value::String(s).to_string(),
+        ReflectValueBox::Bytes(_) => "\"\"".to_owned(),
+        ReflectValueBox::Bool(b) => b.to_string(),
+        ReflectValueBox::Enum(e, v) => e
+            .value_by_number(v)
+            .map(|v| serde_json::Value::String(v.name().to_owned()).to_string())
+            .unwrap_or_else(|| v.to_string()),
+        // 64-bit integers are rendered as strings
+        ReflectValueBox::I64(_) | ReflectValueBox::U64(_) => "\"0\"".to_owned(),
+        _ => "0".to_owned(),
+    }
+}
+
+fn parse_query(query: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    query.split('&').filter(|s| !s.is_empty()).map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |s: &str| {
+            percent_decode_str(&s.replace('+', " "))
+                .decode_utf8_lossy()
+                .into_owned()
+        };
+        (decode(key), decode(value))
+    })
+}
+
+/// Set the field of the path, e.g., `book.name`, returns `false` if the field is not found.
+fn set_field(message: &mut dyn MessageDyn, path: &str, value: &str) -> Result<bool, Status> {
+    let descriptor = message.descriptor_dyn();
+    let (name, rest) = match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let Some(field) = descriptor.field_by_name_or_json_name(name) else {
+        return Ok(false);
+    };
+
+    match (field.runtime_field_type(), rest) {
+        (RuntimeFieldType::Singular(RuntimeType::Message(_)), Some(rest)) => {
+            set_field(field.mut_message(message), rest, value)
+        }
+        (RuntimeFieldType::Singular(ty), None) => {
+            let value = parse_value(&field, ty, value)?;
+            field.set_singular_field(message, value);
+            Ok(true)
+        }
+        (RuntimeFieldType::Repeated(ty), None) => {
+            let value = parse_value(&field, ty, value)?;
+            field.mut_repeated(message).push(value);
+            Ok(true)
+        }
+        _ => Err(Status::invalid_argument(format!(
+            "field {path} can't be set by params"
+        ))),
+    }
+}
+
+fn parse_value(
+    field: &FieldDescriptor,
+    ty: RuntimeType,
+    value: &str,
+) -> Result<ReflectValueBox, Status> {
+    fn parse<T: std::str::FromStr>(value: &str) -> Option<T> {
+        value.parse().ok()
+    }
+
+    let parsed = match ty {
+        RuntimeType::I32 => parse(value).map(ReflectValueBox::I32),
+        RuntimeType::I64 => parse(value).map(ReflectValueBox::I64),
+        RuntimeType::U32 => parse(value).map(ReflectValueBox::U32),
+        RuntimeType::U64 => parse(value).map(ReflectValueBox::U64),
+        RuntimeType::F32 => parse(value).map(ReflectValueBox::F32),
+        RuntimeType::F64 => parse(value).map(ReflectValueBox::F64),
+        RuntimeType::Bool => parse(value).map(ReflectValueBox::Bool),
+        RuntimeType::String => Some(ReflectValueBox::String(value.to_owned())),
+        RuntimeType::VecU8 => {
+            use base64::Engine;
+            crate::BASE64_ENGINE
+                .decode(value)
+                .ok()
+                .map(ReflectValueBox::Bytes)
+        }
+        RuntimeType::Enum(e) => e
+            .value_by_name(value)
+            .map(|v| v.value())
+            .or_else(|| parse(value))
+            .map(|v| ReflectValueBox::Enum(e, v)),
+        // well-known types represented by strings, e.g., `google.protobuf.Timestamp`
+        RuntimeType::Message(m) => {
+            let json = serde_json::Value::String(value.to_owned()).to_string();
+            protobuf_json_mapping::parse_dyn_from_str(&m, &json)
+                .ok()
+                .map(ReflectValueBox::Message)
+        }
+    };
+    parsed.ok_or_else(|| {
+        Status::invalid_argument(format!("invalid value of {}: {value}", field.name()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::StatusCode;
+    use pilota::pb::{
+        descriptor::{
+            DescriptorProto, FieldDescriptorProto, FileDescriptorProto, MethodDescriptorProto,
+            ServiceDescriptorProto,
+            field_descriptor_proto::{Label, Type},
+        },
+        reflect::FileDescriptor,
+    };
+
+    use super::{Transcoder, error_response};
+    use crate::{Code, Request, Response, Status, codec::encode::tests::EchoRequest};
+
+    fn file() -> FileDescriptor {
+        let field = |name: &str, number, ty| {
+            let mut field = FieldDescriptorProto::new();
+            field.set_name(name.to_owned());
+            field.set_json_name(name.to_owned());
+            field.set_number(number);
+            field.set_label(Label::LABEL_OPTIONAL);
+            field.set_type(ty);
+            field
+        };
+        let mut message = DescriptorProto::new();
+        message.set_name("EchoRequest".to_owned());
+        message.field.push(field("message", 1, Type::TYPE_STRING));
+        message.field.push(field("id", 2, Type::TYPE_INT64));
+
+        let mut method = MethodDescriptorProto::new();
+        method.set_name("Unary".to_owned());
+        method.set_input_type(".echo.EchoRequest".to_owned());
+        method.set_output_type(".echo.EchoRequest".to_owned());
+        let mut service = ServiceDescriptorProto::new();
+        service.set_name("Echo".to_owned());
+        service.method.push(method);
+
+        let mut file = FileDescriptorProto::new();
+        file.set_name("echo.proto".to_owned());
+        file.set_package("echo".to_owned());
+        file.set_syntax("proto3".to_owned());
+        file.message_type.push(message);
+        file.service.push(service);
+        FileDescriptor::new_dynamic(file, &[]).unwrap()
+    }
+
+    fn message(message: &str) -> EchoRequest {
+        EchoRequest {
+            message: message.to_owned().into(),
+        }
+    }
+
+    #[test]
+    fn decode_request() {
+        let file = file();
+        let transcoder = Transcoder::new(&file, "Echo", "Unary");
+
+        // path params are decoded
+        let req: EchoRequest = transcoder
+            .decode_request(&[("message", "hello%20world".to_owned())], None, b"")
+            .unwrap();
+        assert_eq!(req, message("hello world"));
+
+        // query
+        let req: EchoRequest = transcoder
+            .decode_request(&[], Some("message=a+b&token=x&id=1"), b"")
+            .unwrap();
+        assert_eq!(req, message("a b"));
+        let status = transcoder
+            .decode_request::<EchoRequest>(&[], Some("id=abc"), b"")
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // path params override query
+        let req: EchoRequest = transcoder
+            .decode_request(
+                &[("message", "path".to_owned())],
+                Some("message=query"),
+                b"",
+            )
+            .unwrap();
+        assert_eq!(req, message("path"));
+
+        // the whole body
+        let transcoder = Transcoder::new(&file, "Echo", "Unary").body("*");
+        let req: EchoRequest = transcoder
+            .decode_request(&[], Some("message=query"), br#"{"message": "body"}"#)
+            .unwrap();
+        assert_eq!(req, message("body"));
+        let req: EchoRequest = transcoder.decode_request(&[], None, b"").unwrap();
+        assert_eq!(req, message(""));
+        let status = transcoder
+            .decode_request::<EchoRequest>(&[], None, b"{")
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // a field of the body
+        let transcoder = Transcoder::new(&file, "Echo", "Unary").body("message");
+        let req: EchoRequest = transcoder
+            .decode_request(&[], Some("message=query"), br#""body""#)
+            .unwrap();
+        assert_eq!(req, message("body"));
+    }
+
+    #[test]
+    fn encode_response() {
+        let file = file();
+        let transcoder = Transcoder::new(&file, "Echo", "Unary");
+        assert_eq!(
+            transcoder.encode_response(&message("hi")).unwrap(),
+            r#"{"message": "hi"}"#
+        );
+
+        let transcoder = transcoder.response_body("message");
+        assert_eq!(
+            transcoder.encode_response(&message("hi")).unwrap(),
+            r#""hi""#
+        );
+        // default values are omitted by the canonical JSON
+        assert_eq!(transcoder.encode_response(&message("")).unwrap(), r#""""#);
+    }
+
+    #[tokio::test]
+    async fn call() {
+        let file = file();
+        let transcoder = Transcoder::new(&file, "Echo", "Unary");
+
+        let req = http::Request::builder()
+            .uri("/v1/echo/hi")
+            .header("x-user", "volo")
+            .body(Bytes::new())
+            .unwrap();
+        let resp = transcoder
+            .call(
+                &[("message", "hi".to_owned())],
+                req,
+                |req: Request<EchoRequest>| async move {
+                    assert_eq!(req.metadata().get("x-user").unwrap(), "volo");
+                    let mut resp = Response::new(req.into_inner());
+                    resp.metadata_mut()
+                        .insert("x-server", "volo".parse().unwrap());
+                    Ok(resp)
+                },
+            )
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        assert_eq!(resp.headers()["x-server"], "volo");
+        assert_eq!(resp.body(), r#"{"message": "hi"}"#);
+
+        let req = http::Request::new(Bytes::new());
+        let resp = transcoder
+            .call(&[], req, |_: Request<EchoRequest>| async {
+                Err::<Response<EchoRequest>, _>(Status::not_found("no such item"))
+            })
+            .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.body(), r#"{"code":5,"message":"no such item"}"#);
+    }
+
+    #[test]
+    fn error_status() {
+        let resp = error_response(&Status::new(Code::Cancelled, ""));
+        assert_eq!(resp.status().as_u16(), 499);
+        let resp = error_response(&Status::new(Code::DataLoss, ""));
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
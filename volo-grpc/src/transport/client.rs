@@ -1,4 +1,4 @@
-use std::{io, marker::PhantomData};
+use std::{borrow::Cow, io, marker::PhantomData, sync::Arc};
 
 use bytes::Bytes;
 use http::{
@@ -10,28 +10,59 @@ use http_body_util::StreamBody;
 use hyper_util::rt::{TokioExecutor, TokioTimer};
 use motore::Service;
 use tower::{Service as TowerService, util::ServiceExt};
-use volo::net::Address;
+use volo::{
+    FastStr,
+    context::Endpoint,
+    loadbalance::{drain::Drainer, warmup::WarmupHandle},
+    net::{Address, proxy::ProxyTarget},
+};
 
-use super::connect::Connector;
+use super::{
+    connect::{Connector, TrackedConnector},
+    drain::{GuardedBody, Peers},
+    warm::{self, WarmPool, Warmer},
+};
+#[cfg(feature = "replay")]
+use crate::client::replay::ReplayMode;
+#[cfg(feature = "compress")]
+use crate::codec::compression::CompressionCache;
 use crate::{
     Code, Request, Response, Status,
-    body::boxed,
+    body::{BoxBody, boxed},
     client::Http2Config,
     codec::{
-        compression::{ACCEPT_ENCODING_HEADER, ENCODING_HEADER},
+        compression::{ACCEPT_ENCODING_HEADER, CompressionEncoding, ENCODING_HEADER},
         decode::Kind,
+        limit::{limit_decoding, limit_decompression, limit_encoding},
+        transform::{self, TRANSFORM_HEADER, transform_decoding, transform_encoding},
     },
     context::{ClientContext, Config},
 };
 
+pub(super) type HttpClient = hyper_util::client::legacy::Client<
+    TrackedConnector,
+    StreamBody<crate::BoxStream<'static, Result<Frame<Bytes>, crate::Status>>>,
+>;
+
 /// A simple wrapper of [`hyper_util::client::legacy::Client`] that implements [`Service`]
 /// to make outgoing requests.
-#[allow(clippy::type_complexity)]
 pub struct ClientTransport<U> {
-    http_client: hyper_util::client::legacy::Client<
-        Connector,
-        StreamBody<crate::BoxStream<'static, Result<Frame<Bytes>, crate::Status>>>,
-    >,
+    http_client: HttpClient,
+    // Whether the callee's domain name should be resolved when dialing, by the dialer or the
+    // proxy, instead of connecting to the resolved address
+    resolve_on_dial: bool,
+    // Prefix of the `:path`, e.g., `/twirp`
+    path_prefix: Option<FastStr>,
+    // Compression encodings accepted by the callees
+    #[cfg(feature = "compress")]
+    compressions: CompressionCache,
+    // Connections and calls in flight of each callee, for draining
+    peers: Peers,
+    // Keeping connections to the callees warm
+    warmer: Option<Arc<Warmer>>,
+    // Recording or replaying calls
+    #[cfg(feature = "replay")]
+    replay: Option<ReplayMode>,
     _marker: PhantomData<fn(U)>,
 }
 
@@ -39,6 +70,14 @@ impl<U> Clone for ClientTransport<U> {
     fn clone(&self) -> Self {
         Self {
             http_client: self.http_client.clone(),
+            resolve_on_dial: self.resolve_on_dial,
+            path_prefix: self.path_prefix.clone(),
+            #[cfg(feature = "compress")]
+            compressions: self.compressions.clone(),
+            peers: self.peers.clone(),
+            warmer: self.warmer.clone(),
+            #[cfg(feature = "replay")]
+            replay: self.replay.clone(),
             _marker: self._marker,
         }
     }
@@ -48,29 +87,7 @@ impl<U> ClientTransport<U> {
     /// Creates a new [`ClientTransport`] by setting the underlying connection
     /// with the given config.
     pub fn new(http2_config: &Http2Config, rpc_config: &Config) -> Self {
-        let config = volo::net::dial::Config::new(
-            rpc_config.connect_timeout,
-            rpc_config.read_timeout,
-            rpc_config.write_timeout,
-        );
-        let http_client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
-            .timer(TokioTimer::new())
-            .http2_only(true)
-            .http2_initial_stream_window_size(http2_config.init_stream_window_size)
-            .http2_initial_connection_window_size(http2_config.init_connection_window_size)
-            .http2_max_frame_size(http2_config.max_frame_size)
-            .http2_adaptive_window(http2_config.adaptive_window)
-            .http2_keep_alive_interval(http2_config.http2_keepalive_interval)
-            .http2_keep_alive_timeout(http2_config.http2_keepalive_timeout)
-            .http2_keep_alive_while_idle(http2_config.http2_keepalive_while_idle)
-            .http2_max_concurrent_reset_streams(http2_config.max_concurrent_reset_streams)
-            .http2_max_send_buf_size(http2_config.max_send_buf_size)
-            .build(Connector::new(Some(config)));
-
-        ClientTransport {
-            http_client,
-            _marker: PhantomData,
-        }
+        Self::with_connector(http2_config, Connector::new(Some(dial_config(rpc_config))))
     }
 
     #[cfg(feature = "__tls")]
@@ -80,11 +97,16 @@ impl<U> ClientTransport<U> {
         rpc_config: &Config,
         tls_config: volo::net::tls::ClientTlsConfig,
     ) -> Self {
-        let config = volo::net::dial::Config::new(
-            rpc_config.connect_timeout,
-            rpc_config.read_timeout,
-            rpc_config.write_timeout,
-        );
+        Self::with_connector(
+            http2_config,
+            Connector::new_with_tls(Some(dial_config(rpc_config)), tls_config),
+        )
+    }
+
+    /// Creates a new [`ClientTransport`] with the given [`Connector`].
+    pub(crate) fn with_connector(http2_config: &Http2Config, connector: Connector) -> Self {
+        let resolve_on_dial = connector.remote_dns();
+        let peers = Peers::default();
         let http_client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
             .timer(TokioTimer::new())
             .http2_only(true)
@@ -97,13 +119,111 @@ impl<U> ClientTransport<U> {
             .http2_keep_alive_while_idle(http2_config.http2_keepalive_while_idle)
             .http2_max_concurrent_reset_streams(http2_config.max_concurrent_reset_streams)
             .http2_max_send_buf_size(http2_config.max_send_buf_size)
-            .build(Connector::new_with_tls(Some(config), tls_config));
+            .build(TrackedConnector {
+                connector,
+                peers: peers.clone(),
+            });
 
         ClientTransport {
             http_client,
+            resolve_on_dial,
+            path_prefix: None,
+            #[cfg(feature = "compress")]
+            compressions: CompressionCache::default(),
+            peers,
+            warmer: None,
+            #[cfg(feature = "replay")]
+            replay: None,
             _marker: PhantomData,
         }
     }
+
+    /// Resolves domain names of the callees when dialing, so that both IPv4 and IPv6 addresses
+    /// are dialed by the Happy Eyeballs algorithm, see [`Dialer`].
+    ///
+    /// It is always enabled for the proxy with remote DNS.
+    ///
+    /// [`Dialer`]: volo::net::dial::Dialer
+    pub(crate) fn with_resolve_on_dial(mut self, enable: bool) -> Self {
+        self.resolve_on_dial |= enable;
+        self
+    }
+
+    /// Sets the prefix prepended to the `:path` of all requests, e.g., `/twirp`.
+    ///
+    /// The prefix should start with `/` and should not end with `/`.
+    pub fn with_path_prefix(mut self, prefix: Option<FastStr>) -> Self {
+        self.path_prefix = prefix;
+        self
+    }
+
+    /// Records calls to the recorder or serves calls by the replayer.
+    #[cfg(feature = "replay")]
+    pub(crate) fn with_replay(mut self, replay: Option<ReplayMode>) -> Self {
+        self.replay = replay;
+        self
+    }
+
+    /// Keeps connections to the called instances warm by the [`WarmPool`].
+    ///
+    /// It is ignored if the domain names of callees are resolved when dialing, since the
+    /// connections are not tracked by addresses.
+    pub fn with_warm_pool(mut self, warm_pool: Option<WarmPool>) -> Self {
+        self.warmer = warm_pool.filter(|_| !self.resolve_on_dial).map(Warmer::new);
+        self
+    }
+
+    /// Drains connections to the instances removed by the discovery.
+    ///
+    /// Connections to a removed instance are closed after all calls in flight to it are
+    /// completed.
+    pub fn drain_on(&self, drainer: &Drainer) {
+        self.peers.drain_on(drainer);
+        if let Some(warmer) = &self.warmer {
+            warmer.drain_on(drainer);
+        }
+    }
+
+    /// Pre-creates connections to the instances when warming up the client.
+    ///
+    /// It should be called after [`ClientTransport::with_warm_pool`], so that the connections are
+    /// warmed up by the same request.
+    pub fn warmup_on(&self, handle: &WarmupHandle) {
+        warm::warmup_on(handle, &self.http_client, &self.peers, self.warmer.as_ref());
+    }
+}
+
+/// Normalizes the prefix of `:path` by trimming the trailing `/`, returns [`None`] if the prefix
+/// is empty after that.
+///
+/// # Panics
+///
+/// Panics if the prefix is not empty and does not start with `/`.
+pub(crate) fn normalize_path_prefix(prefix: &str) -> Option<FastStr> {
+    let prefix = prefix.trim_end_matches('/');
+    if prefix.is_empty() {
+        return None;
+    }
+    if !prefix.starts_with('/') {
+        panic!("[VOLO] Path prefix must start with a `/`");
+    }
+    Some(FastStr::new(prefix))
+}
+
+/// Prepends the prefix to the `path`.
+fn prefixed_path<'a>(prefix: Option<&str>, path: &'a str) -> Cow<'a, str> {
+    match prefix {
+        Some(prefix) => Cow::Owned(format!("{prefix}{path}")),
+        None => Cow::Borrowed(path),
+    }
+}
+
+pub(crate) fn dial_config(rpc_config: &Config) -> volo::net::dial::Config {
+    volo::net::dial::Config::new(
+        rpc_config.connect_timeout,
+        rpc_config.read_timeout,
+        rpc_config.write_timeout,
+    )
 }
 
 impl<T, U> Service<ClientContext, Request<T>> for ClientTransport<U>
@@ -129,22 +249,46 @@ where
         })?;
 
         let (metadata, extensions, message) = volo_req.into_parts();
-        let path = cx.rpc_info.method();
+        let path = prefixed_path(self.path_prefix.as_deref(), cx.rpc_info.method());
         let rpc_config = cx.rpc_info.config();
         let accept_compressions = &rpc_config.accept_compressions;
 
-        // select the compression algorithm with the highest priority by user's config
+        // select the compression algorithm with the highest priority by user's config, and skip
+        // the algorithms which are known to be unsupported by the callee
+        #[cfg(not(feature = "compress"))]
         let send_compression = rpc_config
             .send_compressions
             .as_ref()
-            .map(|config| config[0]);
+            .and_then(|config| config.first().copied());
+        #[cfg(feature = "compress")]
+        let send_compression = rpc_config
+            .send_compressions
+            .as_deref()
+            .and_then(|config| self.compressions.select(&target, config));
 
-        let body = http_body_util::StreamBody::new(message.into_body(send_compression));
+        let stream = message.into_body(send_compression);
+        let transform = rpc_config
+            .message_transforms
+            .as_ref()
+            .and_then(|transforms| transforms.first());
+        let stream = match transform {
+            Some(transform) => transform_encoding(stream, transform.clone()),
+            None => stream,
+        };
+        let stream = match rpc_config.max_encoding_message_size {
+            Some(max) => limit_encoding(stream, max),
+            None => stream,
+        };
+        let body = http_body_util::StreamBody::new(stream);
 
         let mut req = http::Request::builder()
             .version(http::Version::HTTP_2)
             .method(http::Method::POST)
-            .uri(build_uri(target.clone(), path))
+            .uri(if self.resolve_on_dial {
+                build_domain_uri(cx.rpc_info.callee(), target.clone(), &path)
+            } else {
+                build_uri(target.clone(), &path)
+            })
             .extension(extensions)
             .body(body)
             .map_err(|err| Status::from_error(err.into()))?;
@@ -154,6 +298,13 @@ where
         req.headers_mut()
             .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
 
+        if let Some(transform) = transform {
+            let value = HeaderValue::from_str(transform.name()).map_err(|err| {
+                Status::internal(format!("invalid message transform name: {err}"))
+            })?;
+            req.headers_mut().insert(TRANSFORM_HEADER, value);
+        }
+
         // insert compression headers
         if let Some(send_compression) = send_compression {
             req.headers_mut()
@@ -171,22 +322,83 @@ where
         }
         cx.stats.record_make_transport_start_at();
 
+        #[cfg(feature = "replay")]
+        let (req, pending) = match &self.replay {
+            Some(ReplayMode::Replay(replayer)) => {
+                let resp = replayer.replay(req)?;
+                cx.stats.record_make_transport_end_at();
+                return self.handle_response(cx, &target, send_compression, resp);
+            }
+            Some(ReplayMode::Record(recorder)) => {
+                let (req, pending) = recorder.record_request(req);
+                (req, Some(pending))
+            }
+            None => (req, None),
+        };
+
+        if let Some(warmer) = &self.warmer {
+            warmer.track(&target, &self.http_client, &self.peers);
+        }
+        // the call is in flight until the response body is dropped
+        let guard = self.peers.enter(&target);
         let resp = http_client
             .ready()
             .await
-            .map_err(|err| Status::from_error(err.into()))?
+            .map_err(|err| Status::from_error(err.into()).with_peer(target.clone()))?
             .call(req)
             .await
-            .map_err(|err| Status::from_error(err.into()))?;
+            .map_err(|err| Status::from_error(err.into()).with_peer(target.clone()))?;
+        let resp = resp.map(|body| boxed(GuardedBody::new(body, guard)));
+        #[cfg(feature = "replay")]
+        let resp = match pending {
+            Some(pending) => pending.record_response(resp),
+            None => resp,
+        };
 
         cx.stats.record_make_transport_end_at();
+        self.handle_response(cx, &target, send_compression, resp)
+    }
+}
 
+impl<U> ClientTransport<U>
+where
+    U: crate::message::RecvEntryMessage + 'static,
+{
+    /// Checks the status in headers and decodes the body of the response.
+    #[cfg_attr(not(feature = "compress"), allow(unused_variables))]
+    fn handle_response(
+        &self,
+        cx: &ClientContext,
+        target: &Address,
+        send_compression: Option<CompressionEncoding>,
+        resp: http::Response<BoxBody>,
+    ) -> Result<Response<U>, Status> {
         let status_code = resp.status();
         let headers = resp.headers();
 
+        #[cfg(feature = "compress")]
+        let recorded = self.compressions.record(target, headers);
+
         if let Some(status) = Status::from_header_map(headers) {
             if status.code() != Code::Ok {
-                return Err(status);
+                // The callee failed to decompress the request without telling us what it accepts,
+                // do not use the encoding for it anymore.
+                #[cfg(feature = "compress")]
+                if let Some(encoding) = send_compression {
+                    if !recorded
+                        && status.code() == Code::Unimplemented
+                        && encoding != CompressionEncoding::Identity
+                        && status.message().contains(encoding.as_str())
+                    {
+                        tracing::debug!(
+                            "[VOLO] callee {target} doesn't support compression `{}`, fallback to \
+                             others",
+                            encoding.as_str()
+                        );
+                        self.compressions.reject(target, encoding);
+                    }
+                }
+                return Err(status.with_peer(target.clone()));
             }
         }
         let path = cx.rpc_info.method();
@@ -196,25 +408,63 @@ where
         let accept_compression = None;
         #[cfg(feature = "compress")]
         let accept_compression =
-            crate::codec::compression::CompressionEncoding::from_encoding_header(
-                headers,
-                &rpc_config.accept_compressions,
-            )?;
+            CompressionEncoding::from_encoding_header(headers, &rpc_config.accept_compressions)?;
+
+        let transform = transform::negotiate(
+            headers,
+            rpc_config.message_transforms.as_deref().unwrap_or_default(),
+            rpc_config.message_transform_required.unwrap_or(true),
+        )
+        .map_err(|status| status.with_peer(target.clone()))?;
 
         let (parts, body) = resp.into_parts();
+        let body = match rpc_config.max_decoding_message_size {
+            Some(max) => limit_decoding(body, max),
+            None => body,
+        };
+        let body = match transform {
+            Some(transform) => transform_decoding(body, transform),
+            None => body,
+        };
+        let body = match (rpc_config.max_decoding_message_size, accept_compression) {
+            (Some(max), Some(encoding)) => limit_decompression(body, encoding, max),
+            _ => body,
+        };
 
         let body = U::from_body(
             Some(path),
-            boxed(body),
+            body,
             Kind::Response(status_code),
             accept_compression,
         )?;
-        let resp = hyper::Response::from_parts(parts, body);
-        Ok(Response::from_http(resp))
+        Ok(Response::from_http(hyper::Response::from_parts(
+            parts, body,
+        )))
     }
 }
 
-fn build_uri(addr: Address, path: &str) -> hyper::Uri {
+/// Build uri with domain name of the callee, the domain name will be resolved when dialing.
+fn build_domain_uri(callee: &Endpoint, addr: Address, path: &str) -> hyper::Uri {
+    let name = callee.service_name_ref();
+    let is_domain = match ProxyTarget::parse(name, 0) {
+        Some(ProxyTarget::Domain(_, port)) => port != 0,
+        _ => false,
+    };
+    if !addr.is_ip() || !is_domain {
+        return build_uri(addr, path);
+    }
+    match hyper::Uri::builder()
+        .scheme(http::uri::Scheme::HTTP)
+        .authority(name)
+        .path_and_query(path)
+        .build()
+    {
+        Ok(uri) => uri,
+        Err(_) => build_uri(addr, path),
+    }
+}
+
+pub(super) fn build_uri(addr: Address, path: &str) -> hyper::Uri {
     match addr {
         Address::Ip(ip) => hyper::Uri::builder()
             .scheme(http::uri::Scheme::HTTP)
@@ -271,6 +521,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_path_prefix() {
+        use super::{normalize_path_prefix, prefixed_path};
+
+        assert_eq!(normalize_path_prefix(""), None);
+        assert_eq!(normalize_path_prefix("/"), None);
+        assert_eq!(normalize_path_prefix("/twirp/").as_deref(), Some("/twirp"));
+
+        let prefix = normalize_path_prefix("/twirp");
+        let path = prefixed_path(prefix.as_deref(), "/hello.Greeter/SayHello");
+        assert_eq!(path, "/twirp/hello.Greeter/SayHello");
+        let addr = "127.0.0.1:8000".parse::<std::net::SocketAddr>().unwrap();
+        assert_eq!(
+            super::build_uri(volo::net::Address::from(addr), &path),
+            "http://127.0.0.1:8000/twirp/hello.Greeter/SayHello"
+                .parse::<hyper::Uri>()
+                .unwrap()
+        );
+        assert_eq!(
+            prefixed_path(None, "/hello.Greeter/SayHello"),
+            "/hello.Greeter/SayHello"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_path_prefix() {
+        super::normalize_path_prefix("twirp");
+    }
+
     fn is_unpin<T: Unpin>() {}
 
     #[test]
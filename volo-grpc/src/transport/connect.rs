@@ -2,31 +2,46 @@
 use std::os::unix::net::SocketAddr as UnixSocketAddr;
 use std::{
     io,
-    net::SocketAddr,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
+use faststr::FastStr;
 use futures_util::future::BoxFuture;
 use hyper::rt::ReadBufCursor;
 use hyper_util::client::legacy::connect::{Connected, Connection};
 use motore::{make::MakeConnection, service::UnaryService};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 #[cfg(feature = "__tls")]
+use volo::net::conn::ConnInfo;
+#[cfg(feature = "__tls")]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
 use volo::net::tls::{ClientTlsConfig, TlsMakeTransport};
-use volo::net::{
-    Address,
-    conn::Conn,
-    dial::{Config, DefaultMakeTransport, MakeTransport},
+use volo::{
+    context::Role,
+    event::ConnectionGuard,
+    net::{
+        Address,
+        conn::{Conn, ConnStream},
+        dial::{Config, DefaultMakeTransport, Dialer, MakeTransport},
+        memory::MemoryConnector,
+        proxy::{Proxy, ProxyTarget},
+    },
 };
 
+use super::drain::{ConnState, Peers};
+
 #[derive(Clone, Debug)]
 pub enum Connector {
     Default(DefaultMakeTransport),
     #[cfg(feature = "__tls")]
     #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
     Tls(TlsMakeTransport),
+    /// Connect through a forward proxy.
+    Proxy(ProxyConnector),
+    /// Connect to an in-memory listener in the same process, the target is ignored.
+    Memory(MemoryConnector),
 }
 
 impl Connector {
@@ -51,6 +66,99 @@ impl Connector {
         }
         Self::Tls(mt)
     }
+
+    /// Creates a [`Connector`] which connects to all targets through the proxy, except which are
+    /// matched by its no proxy list.
+    pub fn new_with_proxy(
+        cfg: Option<Config>,
+        proxy: Proxy,
+        #[cfg(feature = "__tls")] tls_config: Option<ClientTlsConfig>,
+    ) -> Self {
+        Self::Proxy(ProxyConnector {
+            cfg: cfg.unwrap_or_default(),
+            proxy,
+            #[cfg(feature = "__tls")]
+            tls_config,
+        })
+    }
+
+    /// Resolves the `host` and connects to it.
+    async fn dial(&self, host: &str, port: u16) -> io::Result<Conn> {
+        match self {
+            Self::Default(mkt) => mkt.dial(host, port).await,
+            #[cfg(feature = "__tls")]
+            Self::Tls(mkt) => mkt.dial(host, port).await,
+            Self::Proxy(proxy) => {
+                proxy
+                    .connect(ProxyTarget::Domain(FastStr::new(host), port))
+                    .await
+            }
+            Self::Memory(memory) => memory.connect().await,
+        }
+    }
+
+    /// Whether the domain name of target should be sent to the proxy without resolving.
+    pub(crate) fn remote_dns(&self) -> bool {
+        match self {
+            Self::Proxy(proxy) => proxy.proxy.remote_dns(),
+            _ => false,
+        }
+    }
+}
+
+/// A connector that connects to the target through a forward proxy.
+#[derive(Clone, Debug)]
+pub struct ProxyConnector {
+    cfg: Config,
+    proxy: Proxy,
+    #[cfg(feature = "__tls")]
+    tls_config: Option<ClientTlsConfig>,
+}
+
+impl ProxyConnector {
+    async fn connect(&self, target: ProxyTarget) -> io::Result<Conn> {
+        let stream = if self.proxy.bypass(&target) {
+            match &target {
+                ProxyTarget::Ip(addr) => {
+                    let mut mt = DefaultMakeTransport::new();
+                    *mt.config_mut() = self.cfg;
+                    mt.make_connection(Address::Ip(*addr)).await?.stream
+                }
+                ProxyTarget::Domain(host, port) => {
+                    ConnStream::from(Dialer::new(self.cfg).dial(host, *port).await?)
+                }
+            }
+        } else {
+            self.proxy.connect(&self.cfg, &target).await?
+        };
+        if let ConnStream::Tcp(tcp) = &stream {
+            tcp.set_nodelay(true)?;
+        }
+
+        #[cfg(feature = "__tls")]
+        if let Some(tls_config) = &self.tls_config {
+            let ConnStream::Tcp(tcp) = stream else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "TLS over a TLS proxy tunnel is not supported",
+                ));
+            };
+            let stream = tls_config
+                .connector
+                .connect(&tls_config.server_name, tcp)
+                .await?;
+            let peer_addr = match target {
+                ProxyTarget::Ip(addr) => Some(Address::Ip(addr)),
+                ProxyTarget::Domain(..) => None,
+            };
+            return Ok(Conn {
+                stream,
+                info: ConnInfo { peer_addr },
+            });
+        }
+
+        Ok(Conn::from(stream))
+    }
 }
 
 impl Default for Connector {
@@ -68,6 +176,14 @@ impl UnaryService<Address> for Connector {
             Self::Default(mkt) => mkt.make_connection(addr).await,
             #[cfg(feature = "__tls")]
             Self::Tls(mkt) => mkt.make_connection(addr).await,
+            Self::Proxy(proxy) => match addr {
+                Address::Ip(addr) => proxy.connect(ProxyTarget::Ip(addr)).await,
+                _ => Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "only ip address is available for proxy",
+                )),
+            },
+            Self::Memory(memory) => memory.connect().await,
         }
     }
 }
@@ -86,14 +202,30 @@ impl tower::Service<hyper::Uri> for Connector {
     fn call(&mut self, uri: hyper::Uri) -> Self::Future {
         let connector = self.clone();
         Box::pin(async move {
+            if let Self::Memory(memory) = &connector {
+                return Ok(ConnectionWrapper::new(memory.connect().await?));
+            }
             let authority = uri.authority().expect("authority required").as_str();
+            if let (Self::Proxy(proxy), Some("http")) = (&connector, uri.scheme_str()) {
+                let target = ProxyTarget::parse(authority, 80).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "authority must be host:port")
+                })?;
+                return Ok(ConnectionWrapper::new(proxy.connect(target).await?));
+            }
             let target: Address = match uri.scheme_str() {
-                Some("http") => Address::Ip(authority.parse::<SocketAddr>().map_err(|_| {
-                    io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "authority must be valid SocketAddr",
-                    )
-                })?),
+                Some("http") => match ProxyTarget::parse(authority, 80) {
+                    Some(ProxyTarget::Ip(addr)) => Address::Ip(addr),
+                    // the domain name is resolved when dialing
+                    Some(ProxyTarget::Domain(host, port)) => {
+                        return Ok(ConnectionWrapper::new(connector.dial(&host, port).await?));
+                    }
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "authority must be host:port",
+                        ));
+                    }
+                },
                 #[cfg(target_family = "unix")]
                 Some("http+unix") => {
                     use hex::FromHex;
@@ -116,9 +248,41 @@ impl tower::Service<hyper::Uri> for Connector {
                 _ => unimplemented!(),
             };
 
-            Ok(ConnectionWrapper {
-                inner: connector.make_connection(target).await?,
-            })
+            Ok(ConnectionWrapper::new(
+                connector.make_connection(target).await?,
+            ))
+        })
+    }
+}
+
+/// [`Connector`] tracking its connections by [`Peers`], for draining them.
+#[derive(Clone)]
+pub(crate) struct TrackedConnector {
+    pub(crate) connector: Connector,
+    pub(crate) peers: Peers,
+}
+
+impl tower::Service<hyper::Uri> for TrackedConnector {
+    type Response = ConnectionWrapper;
+
+    type Error = io::Error;
+
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        tower::Service::<hyper::Uri>::poll_ready(&mut self.connector, cx)
+    }
+
+    fn call(&mut self, uri: hyper::Uri) -> Self::Future {
+        let fut = tower::Service::<hyper::Uri>::call(&mut self.connector, uri);
+        let peers = self.peers.clone();
+        Box::pin(async move {
+            let mut conn = fut.await?;
+            // connections without peer address, e.g., through a proxy by domain, are not tracked
+            if let Some(addr) = &conn.inner.info.peer_addr {
+                conn.drain = Some(peers.register(addr));
+            }
+            Ok(conn)
         })
     }
 }
@@ -127,6 +291,23 @@ impl tower::Service<hyper::Uri> for Connector {
 pub struct ConnectionWrapper {
     #[pin]
     inner: Conn,
+    drain: Option<Arc<ConnState>>,
+    event: Option<ConnectionGuard>,
+}
+
+impl ConnectionWrapper {
+    fn new(inner: Conn) -> Self {
+        let event = inner
+            .info
+            .peer_addr
+            .as_ref()
+            .and_then(|peer| volo::event::connection("grpc", Role::Client, peer));
+        Self {
+            inner,
+            drain: None,
+            event,
+        }
+    }
 }
 
 impl hyper::rt::Read for ConnectionWrapper {
@@ -135,9 +316,16 @@ impl hyper::rt::Read for ConnectionWrapper {
         cx: &mut Context<'_>,
         mut buf: ReadBufCursor<'_>,
     ) -> Poll<Result<(), std::io::Error>> {
+        let this = self.project();
+        // the connection is drained, tell hyper it is closed by EOF
+        if let Some(drain) = this.drain {
+            if drain.poll_closed(cx) {
+                return Poll::Ready(Ok(()));
+            }
+        }
         let n = unsafe {
             let mut tbuf = tokio::io::ReadBuf::uninit(buf.as_mut());
-            match tokio::io::AsyncRead::poll_read(self.project().inner, cx, &mut tbuf) {
+            match tokio::io::AsyncRead::poll_read(this.inner, cx, &mut tbuf) {
                 Poll::Ready(Ok(())) => tbuf.filled().len(),
                 other => return other,
             }
@@ -215,6 +403,13 @@ impl Connection for ConnectionWrapper {
 #[cfg(test)]
 mod tests {
     use hex::FromHex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use volo::net::{
+        incoming::{Incoming, MakeIncoming},
+        memory::MemoryListener,
+    };
+
+    use super::Connector;
 
     #[test]
     fn test_convert() {
@@ -224,4 +419,38 @@ mod tests {
             "/tmp/rpc.sock"
         );
     }
+
+    #[tokio::test]
+    async fn test_memory_connector() {
+        let listener = MemoryListener::new();
+        let mut connector = Connector::Memory(listener.connector());
+        let mut incoming = listener.make_incoming().await.unwrap();
+
+        // the authority is ignored
+        let mut conn = tower::Service::call(&mut connector, "http://127.0.0.1:1".parse().unwrap())
+            .await
+            .unwrap();
+        let mut server = incoming.accept().await.unwrap().unwrap();
+        conn.write_all(b"PRI").await.unwrap();
+        let mut buf = [0; 3];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"PRI");
+    }
+
+    #[tokio::test]
+    async fn test_dial_domain() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connector = Connector::default();
+
+        // `localhost` may be resolved to `::1` first, the dialer falls back to `127.0.0.1`
+        let uri = format!("http://localhost:{port}").parse().unwrap();
+        let conn = tower::Service::call(&mut connector, uri).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(
+            conn.inner.info.peer_addr,
+            Some(volo::net::Address::Ip(([127, 0, 0, 1], port).into()))
+        );
+        assert!(peer.ip().is_loopback());
+    }
 }
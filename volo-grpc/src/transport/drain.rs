@@ -0,0 +1,336 @@
+//! Draining connections to instances removed by the discovery.
+//!
+//! The connections are pooled by hyper, so they can not be removed from the pool directly.
+//! Instead, the calls in flight and the connections of each peer are tracked, and when a peer is
+//! drained, its connections will see EOF once all calls to it are completed, then hyper drops
+//! them.
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, PoisonError, RwLock, Weak,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_util::task::AtomicWaker;
+use http_body::{Body, Frame, SizeHint};
+use volo::{loadbalance::drain::Drainer, net::Address};
+
+/// Connections and calls in flight of all peers.
+#[derive(Clone, Default)]
+pub(crate) struct Peers {
+    peers: Arc<RwLock<HashMap<Address, Arc<Peer>>>>,
+}
+
+#[derive(Default)]
+struct Peer {
+    in_flight: AtomicUsize,
+    conns: Mutex<Vec<Weak<ConnState>>>,
+    // connections which will be closed once there is no call in flight
+    draining: Mutex<Vec<Arc<ConnState>>>,
+}
+
+/// State shared with a connection, for closing it by draining.
+pub(crate) struct ConnState {
+    closed: AtomicBool,
+    waker: AtomicWaker,
+    created_at: Instant,
+}
+
+impl Default for ConnState {
+    fn default() -> Self {
+        Self {
+            closed: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+            created_at: Instant::now(),
+        }
+    }
+}
+
+impl ConnState {
+    /// Returns whether the connection is closed, and registers the waker to be woken up when it
+    /// is closed.
+    pub(crate) fn poll_closed(&self, cx: &mut Context<'_>) -> bool {
+        self.waker.register(cx.waker());
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+impl Peer {
+    fn is_unused(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire) == 0
+            && self
+                .draining
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty()
+            && self
+                .conns
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .all(|conn| conn.strong_count() == 0)
+    }
+
+    fn close_draining(&self) {
+        let draining =
+            std::mem::take(&mut *self.draining.lock().unwrap_or_else(PoisonError::into_inner));
+        for conn in draining {
+            conn.close();
+        }
+    }
+}
+
+impl Peers {
+    fn get(&self, addr: &Address) -> Option<Arc<Peer>> {
+        self.peers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(addr)
+            .cloned()
+    }
+
+    fn get_or_insert(&self, addr: &Address) -> Arc<Peer> {
+        if let Some(peer) = self.get(addr) {
+            return peer;
+        }
+        self.peers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(addr.clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Tracks a new connection to the peer.
+    pub(crate) fn register(&self, addr: &Address) -> Arc<ConnState> {
+        let state = Arc::new(ConnState::default());
+        {
+            let mut peers = self.peers.write().unwrap_or_else(PoisonError::into_inner);
+            // new connections are rare, so it is a good time to forget peers without any
+            // connection
+            peers.retain(|_, peer| !peer.is_unused());
+            let peer = peers.entry(addr.clone()).or_default();
+            let mut conns = peer.conns.lock().unwrap_or_else(PoisonError::into_inner);
+            conns.retain(|conn| conn.strong_count() > 0);
+            conns.push(Arc::downgrade(&state));
+        }
+        state
+    }
+
+    /// Tracks a call to the peer until the returned guard is dropped.
+    pub(crate) fn enter(&self, addr: &Address) -> CallGuard {
+        let peer = self.get_or_insert(addr);
+        peer.in_flight.fetch_add(1, Ordering::AcqRel);
+        CallGuard { peer }
+    }
+
+    /// Closes the current connections to the peer once there is no call in flight, new
+    /// connections are not affected.
+    pub(crate) fn drain(&self, addr: &Address) {
+        self.drain_if(addr, |_| true);
+    }
+
+    /// Closes the connections to the peer created `max_age` ago once there is no call in flight,
+    /// and returns whether there is any such connection.
+    pub(crate) fn retire(&self, addr: &Address, max_age: Duration) -> bool {
+        self.drain_if(addr, |conn| conn.created_at.elapsed() >= max_age)
+    }
+
+    fn drain_if(&self, addr: &Address, f: impl Fn(&ConnState) -> bool) -> bool {
+        let Some(peer) = self.get(addr) else {
+            return false;
+        };
+        let drained = {
+            let mut conns = peer.conns.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut drained = Vec::new();
+            conns.retain(|conn| match conn.upgrade() {
+                Some(conn) if f(&conn) => {
+                    drained.push(conn);
+                    false
+                }
+                Some(_) => true,
+                None => false,
+            });
+            drained
+        };
+        if drained.is_empty() {
+            return false;
+        }
+        peer.draining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(drained);
+        if peer.in_flight.load(Ordering::Acquire) == 0 {
+            peer.close_draining();
+        }
+        true
+    }
+
+    /// Whether there is any connection to the peer, including which are being drained.
+    pub(crate) fn is_connected(&self, addr: &Address) -> bool {
+        self.get(addr).is_some_and(|peer| {
+            !peer
+                .draining
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty()
+                || peer
+                    .conns
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .iter()
+                    .any(|conn| conn.strong_count() > 0)
+        })
+    }
+
+    /// Drains connections to the instances removed by the discovery.
+    pub(crate) fn drain_on(&self, drainer: &Drainer) {
+        let peers = Arc::downgrade(&self.peers);
+        drainer.subscribe(move |addr| match peers.upgrade() {
+            Some(peers) => {
+                Peers { peers }.drain(addr);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Guard of a call in flight, connections of the drained peer are closed after all guards are
+/// dropped.
+pub(crate) struct CallGuard {
+    peer: Arc<Peer>,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if self.peer.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.peer.close_draining();
+        }
+    }
+}
+
+/// Response body holding the [`CallGuard`], the call is completed when the body is dropped.
+#[pin_project::pin_project]
+pub(crate) struct GuardedBody<B> {
+    #[pin]
+    inner: B,
+    _guard: CallGuard,
+}
+
+impl<B> GuardedBody<B> {
+    pub(crate) fn new(inner: B, guard: CallGuard) -> Self {
+        Self {
+            inner,
+            _guard: guard,
+        }
+    }
+}
+
+impl<B: Body> Body for GuardedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.project().inner.poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod drain_tests {
+    use std::{
+        net::SocketAddr,
+        sync::atomic::Ordering,
+        task::{Context, Waker},
+        time::Duration,
+    };
+
+    use volo::net::Address;
+
+    use super::Peers;
+
+    fn addr(s: &str) -> Address {
+        Address::from(s.parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn test_drain_after_calls() {
+        let peers = Peers::default();
+        let (a, b) = (addr("127.0.0.1:8000"), addr("127.0.0.2:8000"));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let conn_a = peers.register(&a);
+        let conn_b = peers.register(&b);
+        let guard = peers.enter(&a);
+
+        // the call in flight is not affected
+        peers.drain(&a);
+        assert!(!conn_a.poll_closed(&mut cx));
+        // new connections are not affected
+        let new_conn_a = peers.register(&a);
+
+        drop(guard);
+        assert!(conn_a.poll_closed(&mut cx));
+        assert!(!new_conn_a.poll_closed(&mut cx));
+        assert!(!conn_b.poll_closed(&mut cx));
+
+        peers.drain(&b);
+        assert!(conn_b.closed.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn test_retire_aged_conns() {
+        let peers = Peers::default();
+        let a = addr("127.0.0.1:8000");
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(!peers.is_connected(&a));
+
+        let old = peers.register(&a);
+        std::thread::sleep(Duration::from_millis(20));
+        let new = peers.register(&a);
+        let guard = peers.enter(&a);
+        assert!(peers.retire(&a, Duration::from_millis(10)));
+        assert!(!peers.retire(&a, Duration::from_millis(10)));
+        assert!(!old.poll_closed(&mut cx));
+
+        drop(guard);
+        assert!(old.poll_closed(&mut cx));
+        assert!(!new.poll_closed(&mut cx));
+        assert!(peers.is_connected(&a));
+
+        drop((old, new));
+        assert!(!peers.is_connected(&a));
+    }
+
+    #[test]
+    fn test_forget_unused_peers() {
+        let peers = Peers::default();
+        let (a, b) = (addr("127.0.0.1:8000"), addr("127.0.0.2:8000"));
+
+        drop(peers.register(&a));
+        drop(peers.enter(&a));
+        let _conn = peers.register(&b);
+        assert_eq!(peers.peers.read().unwrap().len(), 1);
+    }
+}
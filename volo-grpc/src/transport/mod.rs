@@ -2,5 +2,10 @@
 
 mod client;
 mod connect;
+mod drain;
+pub mod warm;
 
 pub use client::ClientTransport;
+pub(crate) use client::{dial_config, normalize_path_prefix};
+pub(crate) use connect::Connector;
+pub use warm::WarmPool;
@@ -0,0 +1,439 @@
+//! Keeping connections to the called instances warm.
+//!
+//! Connections are created by the first calls to instances, and closed when they are broken or
+//! drained, so calls shifted to instances without connection, e.g., after another instance is
+//! removed, pay the connect latency. With a [`WarmPool`], a background task checks the instances
+//! called by the client periodically, and connects to those without a ready connection before
+//! calls need it. Connections older than the max age are also replaced, so that they can be
+//! balanced again by load balancers between the client and servers.
+//!
+//! HTTP/2 connections are multiplexed, and hyper keeps one connection for each instance, so the
+//! task keeps one ready connection per instance.
+//!
+//! A connection is warmed up by sending a request to [`WarmPool::path`], which defaults to the
+//! standard health checking method. Any response is treated as ready, and the instance is no
+//! longer kept warm if it fails until it is called again. Instances removed by the discovery are
+//! also forgotten.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_grpc::transport::WarmPool;
+//!
+//! let warm_pool = WarmPool::new()
+//!     .interval(Duration::from_secs(5))
+//!     .max_age(Duration::from_secs(600));
+//! ```
+
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, PoisonError, RwLock, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http::{
+    HeaderValue,
+    header::{CONTENT_TYPE, TE},
+};
+use http_body::Frame;
+use volo::{
+    FastStr,
+    loadbalance::{
+        drain::Drainer,
+        warmup::{ConnectFuture, WarmupHandle},
+    },
+    net::Address,
+};
+
+use super::{
+    client::{HttpClient, build_uri},
+    drain::Peers,
+};
+use crate::Status;
+
+/// The default interval of checking connections.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The default timeout of warming up a connection.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The default path of requests warming up connections.
+pub const DEFAULT_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// Config of keeping connections warm, see [the module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct WarmPool {
+    interval: Duration,
+    max_age: Option<Duration>,
+    timeout: Duration,
+    path: FastStr,
+}
+
+impl Default for WarmPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WarmPool {
+    /// Create a [`WarmPool`] with the default config.
+    pub fn new() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            max_age: None,
+            timeout: DEFAULT_TIMEOUT,
+            path: FastStr::from_static_str(DEFAULT_PATH),
+        }
+    }
+
+    /// Set the interval of checking connections.
+    ///
+    /// Default is [`DEFAULT_INTERVAL`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the max age of connections, older connections are closed after the calls in flight on
+    /// them are completed, and replaced by new connections in the next check.
+    ///
+    /// Default is not limited.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set the timeout of warming up a connection, including the time of connecting.
+    ///
+    /// Default is [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the path of requests warming up connections, the request has an empty message.
+    ///
+    /// Default is [`DEFAULT_PATH`].
+    pub fn path(mut self, path: impl Into<FastStr>) -> Self {
+        self.path = path.into();
+        self
+    }
+}
+
+/// Instances kept warm and the state of the background task.
+pub(crate) struct Warmer {
+    config: WarmPool,
+    targets: RwLock<HashSet<Address>>,
+    started: AtomicBool,
+}
+
+impl Warmer {
+    pub(crate) fn new(config: WarmPool) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            targets: RwLock::new(HashSet::new()),
+            started: AtomicBool::new(false),
+        })
+    }
+
+    /// Keeps connections to the `target` warm, the background task is spawned by the first call
+    /// since the client may be built outside of the runtime.
+    pub(crate) fn track(self: &Arc<Self>, target: &Address, client: &HttpClient, peers: &Peers) {
+        if self
+            .targets
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(target)
+        {
+            return;
+        }
+        self.targets
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(target.clone());
+        if !self.started.swap(true, Ordering::AcqRel) {
+            tokio::spawn(maintain(
+                Arc::downgrade(self),
+                self.config.interval,
+                client.clone(),
+                peers.clone(),
+            ));
+        }
+    }
+
+    fn untrack(&self, target: &Address) {
+        self.targets
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(target);
+    }
+
+    /// Forgets the instances removed by the discovery.
+    pub(crate) fn drain_on(self: &Arc<Self>, drainer: &Drainer) {
+        let warmer = Arc::downgrade(self);
+        drainer.subscribe(move |addr| match warmer.upgrade() {
+            Some(warmer) => {
+                warmer.untrack(addr);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Replaces the aged connection to the `target`, and connects to it if there is no
+    /// connection.
+    async fn check(&self, client: &HttpClient, peers: &Peers, target: Address) {
+        if let Some(max_age) = self.config.max_age {
+            // the retired connection is replaced in the next check after hyper drops it,
+            // otherwise the request may be sent on it
+            if peers.retire(&target, max_age) {
+                return;
+            }
+        }
+        if peers.is_connected(&target) {
+            return;
+        }
+        if let Err(status) = warm_up(client, &target, &self.config.path, self.config.timeout).await
+        {
+            tracing::debug!(
+                "[VOLO] failed to warm up the connection to {target}, stop keeping it warm: \
+                 {status}"
+            );
+            self.untrack(&target);
+        }
+    }
+}
+
+/// Warms up the connection to the `target` by sending a request with an empty message to the
+/// `path`, any response is treated as ready.
+pub(super) async fn warm_up(
+    client: &HttpClient,
+    target: &Address,
+    path: &str,
+    timeout: Duration,
+) -> Result<(), Status> {
+    // an empty message
+    let body =
+        futures::stream::once(async { Ok::<_, Status>(Frame::data(Bytes::from_static(&[0; 5]))) });
+    let mut req = http::Request::builder()
+        .version(http::Version::HTTP_2)
+        .method(http::Method::POST)
+        .uri(build_uri(target.clone(), path))
+        .body(http_body_util::StreamBody::new(
+            Box::pin(body) as crate::BoxStream<'static, _>
+        ))
+        .map_err(|err| Status::from_error(err.into()))?;
+    req.headers_mut()
+        .insert(TE, HeaderValue::from_static("trailers"));
+    req.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+
+    match tokio::time::timeout(timeout, client.request(req)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(Status::from_error(err.into())),
+        Err(_) => Err(Status::deadline_exceeded(format!(
+            "timeout after {timeout:?}"
+        ))),
+    }
+}
+
+/// Subscribes to warming up of the client, which pre-creates the connection to every instance by
+/// the same request as the [`WarmPool`], or its default config if there is no [`WarmPool`].
+///
+/// HTTP/2 connections are multiplexed, so only one connection is expected for each instance.
+pub(super) fn warmup_on(
+    handle: &WarmupHandle,
+    client: &HttpClient,
+    peers: &Peers,
+    warmer: Option<&Arc<Warmer>>,
+) {
+    let (client, peers) = (client.clone(), peers.clone());
+    let (path, timeout) = match warmer {
+        Some(warmer) => (warmer.config.path.clone(), warmer.config.timeout),
+        None => (FastStr::from_static_str(DEFAULT_PATH), DEFAULT_TIMEOUT),
+    };
+    handle.subscribe(move |target, _| {
+        let (client, peers, path, target) =
+            (client.clone(), peers.clone(), path.clone(), target.clone());
+        Some(Box::pin(async move {
+            if peers.is_connected(&target) {
+                return (1usize, 1usize);
+            }
+            match warm_up(&client, &target, &path, timeout).await {
+                Ok(()) => (1, 1),
+                Err(status) => {
+                    tracing::debug!(
+                        "[VOLO] failed to warm up the connection to {target}: {status}"
+                    );
+                    (1, 0)
+                }
+            }
+        }) as ConnectFuture)
+    });
+}
+
+/// Checks the connections periodically until the client is dropped.
+async fn maintain(warmer: Weak<Warmer>, interval: Duration, client: HttpClient, peers: Peers) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let Some(warmer) = warmer.upgrade() else {
+            return;
+        };
+        let targets = warmer
+            .targets
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        futures::future::join_all(
+            targets
+                .into_iter()
+                .map(|target| warmer.check(&client, &peers, target)),
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod warm_tests {
+    use std::{
+        convert::Infallible,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use volo::{
+        context::Endpoint,
+        loadbalance::{
+            drain::Drainer,
+            warmup::{Warmup, WarmupHandle},
+        },
+        net::Address,
+    };
+
+    use super::{WarmPool, Warmer, warmup_on};
+    use crate::transport::{
+        Connector, client::HttpClient, connect::TrackedConnector, drain::Peers,
+    };
+
+    fn http_client(peers: &Peers) -> HttpClient {
+        hyper_util::client::legacy::Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build(TrackedConnector {
+                connector: Connector::default(),
+                peers: peers.clone(),
+            })
+    }
+
+    async fn ok(
+        _: http::Request<hyper::body::Incoming>,
+    ) -> Result<http::Response<http_body_util::Empty<Bytes>>, Infallible> {
+        Ok(http::Response::new(http_body_util::Empty::new()))
+    }
+
+    #[tokio::test]
+    async fn test_warm_pool() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = Address::from(listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::AcqRel);
+                tokio::spawn(
+                    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), hyper::service::service_fn(ok)),
+                );
+            }
+        });
+
+        let peers = Peers::default();
+        let warmer = Warmer::new(
+            WarmPool::new()
+                .interval(Duration::from_millis(50))
+                .max_age(Duration::from_millis(300)),
+        );
+        warmer.track(&addr, &http_client(&peers), &peers);
+
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert_eq!(accepted.load(Ordering::Acquire), 1);
+        assert!(peers.is_connected(&addr));
+
+        // the aged connection is replaced
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(accepted.load(Ordering::Acquire) >= 2);
+        assert!(warmer.targets.read().unwrap().contains(&addr));
+
+        // removed instances are forgotten
+        let drainer = Drainer::default();
+        warmer.drain_on(&drainer);
+        drainer.drain(&addr);
+        assert!(!warmer.targets.read().unwrap().contains(&addr));
+    }
+
+    #[tokio::test]
+    async fn test_forget_failed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = Address::from(listener.local_addr().unwrap());
+        drop(listener);
+
+        let peers = Peers::default();
+        let warmer = Warmer::new(WarmPool::new().interval(Duration::from_millis(20)));
+        warmer.track(&addr, &http_client(&peers), &peers);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!warmer.targets.read().unwrap().contains(&addr));
+    }
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = Address::from(listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::AcqRel);
+                tokio::spawn(
+                    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), hyper::service::service_fn(ok)),
+                );
+            }
+        });
+
+        let peers = Peers::default();
+        let handle = WarmupHandle::new();
+        warmup_on(&handle, &http_client(&peers), &peers, None);
+        let mut endpoint = Endpoint::new("test".into());
+        endpoint.set_address(addr.clone());
+
+        let report = handle
+            .warmup(&endpoint, &Warmup::new().connections(4))
+            .await
+            .unwrap();
+        assert!(report.is_complete());
+        // HTTP/2 connections are multiplexed
+        assert_eq!((report.expected, report.ready), (1, 1));
+        assert!(peers.is_connected(&addr));
+
+        // the existing connection is reused
+        let report = handle.warmup(&endpoint, &Warmup::new()).await.unwrap();
+        assert!(report.is_complete());
+        assert_eq!(accepted.load(Ordering::Acquire), 1);
+    }
+}
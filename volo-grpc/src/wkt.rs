@@ -0,0 +1,279 @@
+//! Conversions between well-known types of protobuf and native Rust types.
+//!
+//! The messages of `google.protobuf` used by services are generated by `volo-build` as the other
+//! messages, with the following conversions implemented by the functions of this module:
+//!
+//! | Message                                           | Native type                      |
+//! |---------------------------------------------------|----------------------------------|
+//! | `google.protobuf.Timestamp`                       | [`SystemTime`]                   |
+//! | `google.protobuf.Duration`                        | [`std::time::Duration`]          |
+//! | `google.protobuf.Struct`, `Value` and `ListValue` | [`Map`], [`Value`], `Vec<Value>` |
+//!
+//! and `Any::pack` / `Any::unpack` for packing messages implementing [`MessageName`], e.g.,
+//!
+//! ```ignore
+//! use std::time::SystemTime;
+//!
+//! use volo_gen::google::protobuf::{Any, Timestamp};
+//!
+//! let created = Timestamp::from(SystemTime::now());
+//! let created: SystemTime = created.try_into()?;
+//!
+//! let extra = Any::pack(&item);
+//! let item: Option<Item> = extra.unpack()?;
+//! ```
+//!
+//! The conversions which may fail because of the ranges, e.g., negative durations or timestamps
+//! with invalid nanos, are implemented by [`TryFrom`] with [`WktError`].
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use faststr::FastStr;
+use pilota::pb::{DecodeError, EncodeLengthContext, Message};
+
+const NANOS_PER_SECOND: u32 = 1_000_000_000;
+
+/// The prefix of type URLs of messages packed by [`pack`].
+pub const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Error of converting well-known types to native types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WktError {
+    /// The nanos are not in the range of the message, or their sign is different from seconds.
+    InvalidNanos(i32),
+    /// The value is out of the range of the native type.
+    OutOfRange,
+    /// The duration is negative, which can't be represented by [`std::time::Duration`].
+    NegativeDuration,
+}
+
+impl fmt::Display for WktError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidNanos(nanos) => write!(f, "invalid nanos: {nanos}"),
+            Self::OutOfRange => f.write_str("value out of range"),
+            Self::NegativeDuration => f.write_str("negative duration"),
+        }
+    }
+}
+
+impl Error for WktError {}
+
+/// Split a [`SystemTime`] into seconds and non-negative nanos since the unix epoch, as the fields
+/// of `google.protobuf.Timestamp`.
+///
+/// Times out of the range of `i64` seconds are saturated.
+pub fn timestamp_from_system_time(time: SystemTime) -> (i64, i32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (
+            i64::try_from(after.as_secs()).unwrap_or(i64::MAX),
+            after.subsec_nanos() as i32,
+        ),
+        Err(err) => {
+            let before = err.duration();
+            let seconds = i64::try_from(before.as_secs()).unwrap_or(i64::MAX);
+            match before.subsec_nanos() {
+                0 => (-seconds, 0),
+                nanos => (
+                    (-seconds).saturating_sub(1),
+                    (NANOS_PER_SECOND - nanos) as i32,
+                ),
+            }
+        }
+    }
+}
+
+/// Build a [`SystemTime`] from the fields of `google.protobuf.Timestamp`.
+pub fn system_time_from_timestamp(seconds: i64, nanos: i32) -> Result<SystemTime, WktError> {
+    if !(0..NANOS_PER_SECOND as i32).contains(&nanos) {
+        return Err(WktError::InvalidNanos(nanos));
+    }
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    };
+    time.and_then(|time| time.checked_add(Duration::from_nanos(nanos as u64)))
+        .ok_or(WktError::OutOfRange)
+}
+
+/// Split a [`std::time::Duration`] into the fields of `google.protobuf.Duration`.
+pub fn duration_from_std(duration: Duration) -> Result<(i64, i32), WktError> {
+    let seconds = i64::try_from(duration.as_secs()).map_err(|_| WktError::OutOfRange)?;
+    Ok((seconds, duration.subsec_nanos() as i32))
+}
+
+/// Build a [`std::time::Duration`] from the fields of `google.protobuf.Duration`.
+pub fn std_from_duration(seconds: i64, nanos: i32) -> Result<Duration, WktError> {
+    if nanos.unsigned_abs() >= NANOS_PER_SECOND
+        || (seconds > 0 && nanos < 0)
+        || (seconds < 0 && nanos > 0)
+    {
+        return Err(WktError::InvalidNanos(nanos));
+    }
+    if seconds < 0 || nanos < 0 {
+        return Err(WktError::NegativeDuration);
+    }
+    Ok(Duration::new(seconds as u64, nanos as u32))
+}
+
+/// Fields of `google.protobuf.Struct`.
+pub type Map = BTreeMap<FastStr, Value>;
+
+/// Native representation of `google.protobuf.Value`, i.e., a JSON value.
+///
+/// A `google.protobuf.Value` without kind is converted to [`Value::Null`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Value {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(FastStr),
+    List(Vec<Value>),
+    Struct(Map),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<FastStr> for Value {
+    fn from(value: FastStr) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<&'static str> for Value {
+    fn from(value: &'static str) -> Self {
+        Self::String(FastStr::from_static_str(value))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Self::List(value)
+    }
+}
+
+impl From<Map> for Value {
+    fn from(value: Map) -> Self {
+        Self::Struct(value)
+    }
+}
+
+/// Full name of a message, e.g., `google.protobuf.Timestamp`, which is implemented for all
+/// generated messages.
+pub trait MessageName {
+    /// The full name of the message with its package.
+    const FULL_NAME: &'static str;
+
+    /// The type URL of the message packed in `google.protobuf.Any`.
+    fn type_url() -> String {
+        format!("{TYPE_URL_PREFIX}{}", Self::FULL_NAME)
+    }
+}
+
+/// Returns the full name of the message in the type URL, i.e., the part after the last `/`.
+pub fn type_name_of_url(type_url: &str) -> &str {
+    type_url.rsplit('/').next().unwrap_or(type_url)
+}
+
+/// Encode the message as the fields of `google.protobuf.Any`.
+pub fn pack<M: MessageName + Message>(message: &M) -> (FastStr, Bytes) {
+    let value = message.encode_to_vec(&mut EncodeLengthContext::default());
+    (M::type_url().into(), value.into())
+}
+
+/// Decode the message from the fields of `google.protobuf.Any`, `None` if the packed message is
+/// not `M`.
+pub fn unpack<M: MessageName + Message + Default>(
+    type_url: &str,
+    value: Bytes,
+) -> Result<Option<M>, DecodeError> {
+    if type_name_of_url(type_url) != M::FULL_NAME {
+        return Ok(None);
+    }
+    M::decode(value).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn timestamp() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123);
+        assert_eq!(timestamp_from_system_time(time), (1_700_000_000, 123));
+        assert_eq!(system_time_from_timestamp(1_700_000_000, 123), Ok(time));
+
+        let time = UNIX_EPOCH - Duration::new(1, 250_000_000);
+        assert_eq!(timestamp_from_system_time(time), (-2, 750_000_000));
+        assert_eq!(system_time_from_timestamp(-2, 750_000_000), Ok(time));
+
+        assert_eq!(
+            system_time_from_timestamp(0, -1),
+            Err(WktError::InvalidNanos(-1))
+        );
+        assert_eq!(
+            system_time_from_timestamp(0, 1_000_000_000),
+            Err(WktError::InvalidNanos(1_000_000_000))
+        );
+    }
+
+    #[test]
+    fn duration() {
+        let duration = Duration::new(3, 500);
+        assert_eq!(duration_from_std(duration), Ok((3, 500)));
+        assert_eq!(std_from_duration(3, 500), Ok(duration));
+
+        assert_eq!(
+            duration_from_std(Duration::from_secs(u64::MAX)),
+            Err(WktError::OutOfRange)
+        );
+        assert_eq!(std_from_duration(-1, 0), Err(WktError::NegativeDuration));
+        assert_eq!(std_from_duration(0, -1), Err(WktError::NegativeDuration));
+        assert_eq!(std_from_duration(1, -1), Err(WktError::InvalidNanos(-1)));
+    }
+
+    #[test]
+    fn type_url() {
+        struct Timestamp;
+
+        impl MessageName for Timestamp {
+            const FULL_NAME: &'static str = "google.protobuf.Timestamp";
+        }
+
+        assert_eq!(
+            Timestamp::type_url(),
+            "type.googleapis.com/google.protobuf.Timestamp"
+        );
+        assert_eq!(
+            type_name_of_url("example.com/types/google.protobuf.Timestamp"),
+            "google.protobuf.Timestamp"
+        );
+        assert_eq!(type_name_of_url("demo.Item"), "demo.Item");
+    }
+}
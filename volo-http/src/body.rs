@@ -13,7 +13,8 @@ use std::{
 
 use bytes::Bytes;
 use faststr::FastStr;
-use futures_util::stream::Stream;
+use futures_util::stream::{Stream, TryStreamExt};
+use http::HeaderMap;
 use http_body::{Frame, SizeHint};
 use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
 use hyper::body::Incoming;
@@ -25,7 +26,7 @@ use serde::de::DeserializeOwned;
 use crate::error::BoxError;
 
 // The `futures_util::stream::BoxStream` does not have `Sync`
-type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + Sync + 'a>>;
+pub(crate) type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + Sync + 'a>>;
 
 /// An implementation for [`http_body::Body`].
 #[pin_project]
@@ -83,6 +84,134 @@ impl Body {
         }
     }
 
+    /// Create a body by a [`Stream`] of [`Bytes`].
+    ///
+    /// The length of the body is unknown, so it will be sent with `Transfer-Encoding: chunked`
+    /// in HTTP/1.1, use [`Body::from_bytes_stream_with_length`] if the length is known.
+    pub fn from_bytes_stream<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self::from_stream(stream.map_ok(Frame::data).map_err(Into::into))
+    }
+
+    /// Create a body by a [`Stream`] of [`Bytes`] with the given length.
+    ///
+    /// The length is used as `Content-Length`, and polling the body fails if the stream yields
+    /// more or less data than the length.
+    pub fn from_bytes_stream_with_length<S, E>(stream: S, length: u64) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self::from_body(LengthBody {
+            inner: Self::from_bytes_stream(stream),
+            remaining: length,
+        })
+    }
+
+    /// Append trailers to the body, they are sent after all data of the body.
+    ///
+    /// If the body already has trailers, they are merged.
+    ///
+    /// Note that HTTP/1.1 only sends trailers with `Transfer-Encoding: chunked` when the peer
+    /// accepts them by `TE: trailers`.
+    pub fn with_trailers(self, trailers: HeaderMap) -> Self {
+        Self::from_body(BodyExt::with_trailers(
+            self,
+            std::future::ready(Some(Ok(trailers))),
+        ))
+    }
+
+    /// Append trailers resolved by a future to the body, they are sent after all data of the
+    /// body.
+    ///
+    /// The future is polled after all data of the body is polled, so it can rely on the data,
+    /// e.g., to send a checksum calculated while polling the data.
+    pub fn with_trailers_future<F>(self, trailers: F) -> Self
+    where
+        F: Future<Output = Result<HeaderMap, BoxError>> + Send + Sync + 'static,
+    {
+        Self::from_body(BodyExt::with_trailers(
+            self,
+            async move { Some(trailers.await) },
+        ))
+    }
+
+    /// Override the [`SizeHint`] of the body.
+    ///
+    /// Unlike [`Body::from_bytes_stream_with_length`], the data is not checked with the hint, so
+    /// it should only be used when the hint is known to be accurate, e.g., after mapping frames
+    /// without changing the length.
+    pub fn with_size_hint(self, size_hint: SizeHint) -> Self {
+        Self::from_body(SizeHintBody {
+            inner: self,
+            size_hint,
+        })
+    }
+
+    /// Map each frame of the body by the function.
+    ///
+    /// The length of the body is unknown after mapping, use [`Body::with_size_hint`] if the
+    /// function does not change it.
+    pub fn map_frame<F>(self, f: F) -> Self
+    where
+        F: FnMut(Frame<Bytes>) -> Frame<Bytes> + Send + Sync + 'static,
+    {
+        Self::from_body(MapFrameBody { inner: self, f })
+    }
+
+    /// Map each data frame of the body by the function, trailers are kept as is.
+    ///
+    /// The length of the body is unknown after mapping, use [`Body::with_size_hint`] if the
+    /// function does not change it.
+    pub fn map_data<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(Bytes) -> Bytes + Send + Sync + 'static,
+    {
+        self.map_frame(move |frame| frame.map_data(&mut f))
+    }
+
+    /// Observe the progress of polling the body, the callback is called each time a data frame
+    /// is polled.
+    ///
+    /// It can be used for both requests and responses, e.g., for the progress of receiving a
+    /// request body in a handler. The total length is taken from the [`SizeHint`] of the body.
+    pub fn on_progress<F>(self, callback: F) -> Self
+    where
+        F: FnMut(Progress) + Send + Sync + 'static,
+    {
+        let total = http_body::Body::size_hint(&self).exact();
+        self.on_progress_with_total(total, callback)
+    }
+
+    /// Same as [`Body::on_progress`], but the total length is given, e.g., by `Content-Length`.
+    pub(crate) fn on_progress_with_total<F>(self, total: Option<u64>, callback: F) -> Self
+    where
+        F: FnMut(Progress) + Send + Sync + 'static,
+    {
+        Self::from_body(ProgressBody {
+            inner: self,
+            progress: Progress {
+                transferred: 0,
+                total,
+            },
+            callback: Box::new(callback),
+        })
+    }
+
+    /// Clone the body if it is a complete body, e.g., created from [`Bytes`] or [`String`].
+    #[cfg(feature = "client")]
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        match &self.repr {
+            BodyRepr::Full(full) => Some(Self {
+                repr: BodyRepr::Full(full.clone()),
+            }),
+            _ => None,
+        }
+    }
+
     /// Create a body by another [`http_body::Body`] instance.
     pub fn from_body<B>(body: B) -> Self
     where
@@ -175,6 +304,20 @@ where
         }
     }
 
+    /// Consume a body and convert it into [`Bytes`] and its trailers.
+    fn into_bytes_with_trailers(
+        self,
+    ) -> impl Future<Output = Result<(Bytes, Option<HeaderMap>), BodyConvertError>> + Send {
+        async {
+            let collected = self
+                .collect()
+                .await
+                .map_err(|_| BodyConvertError::BodyCollectionError)?;
+            let trailers = collected.trailers().cloned();
+            Ok((collected.to_bytes(), trailers))
+        }
+    }
+
     /// Consume a body and convert it into [`Vec<u8>`].
     fn into_vec(self) -> impl Future<Output = Result<Vec<u8>, BodyConvertError>> + Send {
         async { Ok(self.into_bytes().await?.into()) }
@@ -237,6 +380,11 @@ where
     }
 
     /// Consume a body and convert it into an instance with [`DeserializeOwned`].
+    ///
+    /// Note that `Content-Type` is unknown to the body, for checking it of a response, use
+    /// [`ResponseConversion::into_json`] instead.
+    ///
+    /// [`ResponseConversion::into_json`]: crate::response::ResponseConversion::into_json
     #[cfg(feature = "json")]
     fn into_json<T>(self) -> impl Future<Output = Result<T, BodyConvertError>> + Send
     where
@@ -249,6 +397,57 @@ where
     }
 }
 
+/// Strictness of checking `Content-Type` of a response by [`ResponseConversion::into_json`].
+///
+/// It can be inserted into extensions of the response before calling
+/// [`ResponseConversion::into_json`].
+///
+/// [`ResponseConversion::into_json`]: crate::response::ResponseConversion::into_json
+#[cfg(feature = "json")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContentTypeCheck {
+    /// `Content-Type` must exist and be a json type.
+    Strict,
+    /// `Content-Type` can be absent, but it must be a json type if it exists.
+    #[default]
+    Lenient,
+    /// Do not check `Content-Type`.
+    Disabled,
+}
+
+#[cfg(feature = "json")]
+impl ContentTypeCheck {
+    /// Check the `Content-Type` in `headers`.
+    pub fn check(self, headers: &HeaderMap) -> Result<(), BodyConvertError> {
+        if self == Self::Disabled {
+            return Ok(());
+        }
+        let Some(value) = headers.get(http::header::CONTENT_TYPE) else {
+            return match self {
+                Self::Strict => Err(BodyConvertError::InvalidContentType(None)),
+                _ => Ok(()),
+            };
+        };
+        let invalid = || BodyConvertError::InvalidContentType(Some(value.clone()));
+        let mime = value
+            .to_str()
+            .ok()
+            .and_then(|s| s.parse::<mime::Mime>().ok())
+            .ok_or_else(invalid)?;
+        let is_json = (mime.type_() == mime::APPLICATION && mime.subtype() == mime::JSON)
+            || mime.suffix() == Some(mime::JSON);
+        if !is_json {
+            return Err(invalid());
+        }
+        if let Some(charset) = mime.get_param(mime::CHARSET) {
+            if charset != mime::UTF_8 && !charset.as_str().eq_ignore_ascii_case("utf8") {
+                return Err(BodyConvertError::UnsupportedCharset(value.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<T> BodyConversion for T
 where
     T: sealed::SealedBody,
@@ -258,6 +457,7 @@ where
 
 /// General error for polling [`http_body::Body`] or converting the [`Bytes`] just polled.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum BodyConvertError {
     /// Failed to collect the body
     BodyCollectionError,
@@ -266,6 +466,34 @@ pub enum BodyConvertError {
     /// Failed to deserialize the json
     #[cfg(feature = "json")]
     JsonDeserializeError(crate::utils::json::Error),
+    /// `Content-Type` of the response is not a json type, or it is absent in
+    /// [`ContentTypeCheck::Strict`] mode.
+    #[cfg(feature = "json")]
+    InvalidContentType(Option<http::HeaderValue>),
+    /// `Content-Type` of the response is a json type, but its charset is not UTF-8.
+    #[cfg(feature = "json")]
+    UnsupportedCharset(http::HeaderValue),
+}
+
+impl BodyConvertError {
+    /// Whether the error is caused by an unexpected `Content-Type` of the response.
+    pub fn is_content_type_error(&self) -> bool {
+        #[cfg(feature = "json")]
+        return matches!(
+            self,
+            Self::InvalidContentType(_) | Self::UnsupportedCharset(_)
+        );
+        #[cfg(not(feature = "json"))]
+        false
+    }
+
+    /// Whether the error is caused by deserializing the json.
+    pub fn is_decode_error(&self) -> bool {
+        #[cfg(feature = "json")]
+        return matches!(self, Self::JsonDeserializeError(_));
+        #[cfg(not(feature = "json"))]
+        false
+    }
 }
 
 impl fmt::Display for BodyConvertError {
@@ -275,6 +503,16 @@ impl fmt::Display for BodyConvertError {
             Self::StringUtf8Error => f.write_str("body is not a valid string"),
             #[cfg(feature = "json")]
             Self::JsonDeserializeError(e) => write!(f, "failed to deserialize body: {e}"),
+            #[cfg(feature = "json")]
+            Self::InvalidContentType(Some(value)) => {
+                write!(f, "response has a non-json content type: {value:?}")
+            }
+            #[cfg(feature = "json")]
+            Self::InvalidContentType(None) => f.write_str("response has no content type"),
+            #[cfg(feature = "json")]
+            Self::UnsupportedCharset(value) => {
+                write!(f, "response has an unsupported charset: {value:?}")
+            }
         }
     }
 }
@@ -335,8 +573,191 @@ impl From<String> for Body {
     }
 }
 
+/// Error of a body whose data does not match its length.
+#[derive(Debug)]
+pub struct LengthMismatch;
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("body length mismatch")
+    }
+}
+
+impl Error for LengthMismatch {}
+
+#[pin_project]
+struct LengthBody {
+    #[pin]
+    inner: Body,
+    remaining: u64,
+}
+
+impl http_body::Body for LengthBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match std::task::ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+            None if *this.remaining == 0 => return Poll::Ready(None),
+            None => return Poll::Ready(Some(Err(Box::new(LengthMismatch)))),
+        };
+        if let Some(data) = frame.data_ref() {
+            let len = data.len() as u64;
+            if len > *this.remaining {
+                return Poll::Ready(Some(Err(Box::new(LengthMismatch))));
+            }
+            *this.remaining -= len;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0 && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+#[pin_project]
+struct SizeHintBody {
+    #[pin]
+    inner: Body,
+    size_hint: SizeHint,
+}
+
+impl http_body::Body for SizeHintBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let res = std::task::ready!(this.inner.poll_frame(cx));
+        if let Some(Ok(frame)) = &res {
+            if let Some(data) = frame.data_ref() {
+                let len = data.len() as u64;
+                let lower = this.size_hint.lower().saturating_sub(len);
+                let upper = this
+                    .size_hint
+                    .upper()
+                    .map(|upper| upper.saturating_sub(len));
+                let mut size_hint = SizeHint::new();
+                size_hint.set_lower(lower);
+                if let Some(upper) = upper {
+                    size_hint.set_upper(upper);
+                }
+                *this.size_hint = size_hint;
+            }
+        }
+        Poll::Ready(res)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.size_hint.clone()
+    }
+}
+
+#[pin_project]
+struct MapFrameBody<F> {
+    #[pin]
+    inner: Body,
+    f: F,
+}
+
+impl<F> http_body::Body for MapFrameBody<F>
+where
+    F: FnMut(Frame<Bytes>) -> Frame<Bytes>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let res = std::task::ready!(this.inner.poll_frame(cx));
+        Poll::Ready(res.map(|frame| frame.map(this.f)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.inner.is_end_stream() {
+            SizeHint::with_exact(0)
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
+/// Progress of polling a body.
+///
+/// See [`Body::on_progress`] for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// Number of bytes that have been polled.
+    pub transferred: u64,
+    /// Total number of bytes if the length of the body is known.
+    pub total: Option<u64>,
+}
+
+#[pin_project]
+struct ProgressBody {
+    #[pin]
+    inner: Body,
+    progress: Progress,
+    callback: Box<dyn FnMut(Progress) + Send + Sync>,
+}
+
+impl http_body::Body for ProgressBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let res = std::task::ready!(this.inner.poll_frame(cx));
+        if let Some(Ok(frame)) = &res {
+            if let Some(data) = frame.data_ref() {
+                this.progress.transferred += data.len() as u64;
+                (this.callback)(*this.progress);
+            }
+        }
+        Poll::Ready(res)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
 struct LinkedBytesBody<I> {
     inner: I,
+    remaining: u64,
 }
 
 impl<I> http_body::Body for LinkedBytesBody<I>
@@ -359,28 +780,25 @@ where
             Node::BytesMut(bytesmut) => bytesmut.freeze(),
             Node::FastStr(faststr) => faststr.into_bytes(),
         };
+        this.remaining = this.remaining.saturating_sub(bytes.len() as u64);
         Poll::Ready(Some(Ok(Frame::data(bytes))))
     }
 
     fn is_end_stream(&self) -> bool {
-        false
+        self.remaining == 0
     }
 
     fn size_hint(&self) -> SizeHint {
-        let (lower, upper) = self.inner.size_hint();
-        let mut size_hint = SizeHint::new();
-        size_hint.set_lower(lower as u64);
-        if let Some(upper) = upper {
-            size_hint.set_upper(upper as u64);
-        }
-        size_hint
+        SizeHint::with_exact(self.remaining)
     }
 }
 
 impl From<LinkedBytes> for Body {
     fn from(value: LinkedBytes) -> Self {
+        let remaining = value.len() as u64;
         Body::from_body(LinkedBytesBody {
             inner: value.into_iter_list(),
+            remaining,
         })
     }
 }
@@ -394,12 +812,124 @@ mod tests {
     use super::Body;
     use crate::body::BodyConversion;
 
+    #[tokio::test]
+    async fn test_with_trailers() {
+        use http::{HeaderMap, HeaderValue};
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        let body = Body::from("Hello, world!").with_trailers(trailers.clone());
+        let (data, received) = body.into_bytes_with_trailers().await.unwrap();
+        assert_eq!(data, "Hello, world!");
+        assert_eq!(received, Some(trailers));
+
+        let (_, received) = Body::from("no trailers")
+            .into_bytes_with_trailers()
+            .await
+            .unwrap();
+        assert_eq!(received, None);
+    }
+
+    #[tokio::test]
+    async fn test_from_bytes_stream() {
+        use futures_util::stream;
+        use http_body::Body as _;
+
+        let chunks = || {
+            stream::iter([
+                Ok::<_, std::io::Error>(Bytes::from_static(b"Hello, ")),
+                Ok(Bytes::from_static(b"world!")),
+            ])
+        };
+        let body = Body::from_bytes_stream(chunks());
+        assert_eq!(body.size_hint().exact(), None);
+        assert_eq!(body.into_string().await.unwrap(), "Hello, world!");
+
+        let body = Body::from_bytes_stream_with_length(chunks(), 13);
+        assert_eq!(body.size_hint().exact(), Some(13));
+        assert_eq!(body.into_string().await.unwrap(), "Hello, world!");
+
+        assert!(
+            Body::from_bytes_stream_with_length(chunks(), 12)
+                .into_bytes()
+                .await
+                .is_err()
+        );
+        assert!(
+            Body::from_bytes_stream_with_length(chunks(), 14)
+                .into_bytes()
+                .await
+                .is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_from_linked_bytes() {
         let mut bytes = LinkedBytes::new();
         bytes.insert(Bytes::from_static(b"Hello, "));
         bytes.insert_faststr(FastStr::new("world!"));
         let body = Body::from(bytes);
+        assert_eq!(http_body::Body::size_hint(&body).exact(), Some(13));
         assert_eq!(body.into_string().await.unwrap(), "Hello, world!");
     }
+
+    #[tokio::test]
+    async fn test_size_hint_and_map() {
+        use futures_util::stream;
+        use http_body::{Body as _, SizeHint};
+        use http_body_util::BodyExt;
+
+        let chunks = stream::iter([
+            Ok::<_, std::io::Error>(Bytes::from_static(b"hello, ")),
+            Ok(Bytes::from_static(b"world!")),
+        ]);
+        let mut body = Body::from_bytes_stream(chunks)
+            .map_data(|data| Bytes::from(data.to_ascii_uppercase()))
+            .with_size_hint(SizeHint::with_exact(13));
+        assert_eq!(body.size_hint().exact(), Some(13));
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "HELLO, ");
+        assert_eq!(body.size_hint().exact(), Some(6));
+        assert_eq!(body.into_string().await.unwrap(), "WORLD!");
+
+        // the length is unknown after mapping
+        let body = Body::from("hello").map_data(|data| data.slice(1..));
+        assert_eq!(body.size_hint().exact(), None);
+        assert_eq!(body.into_string().await.unwrap(), "ello");
+    }
+
+    #[tokio::test]
+    async fn test_trailers_future_and_progress() {
+        use std::sync::{
+            Arc, Mutex,
+            atomic::{AtomicU64, Ordering},
+        };
+
+        use http::{HeaderMap, HeaderValue};
+
+        use super::Progress;
+
+        let polled = Arc::new(AtomicU64::new(0));
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let body = {
+            let observed = polled.clone();
+            let progress = progress.clone();
+            Body::from("Hello, world!")
+                .on_progress(move |p: Progress| {
+                    observed.store(p.transferred, Ordering::Relaxed);
+                    progress.lock().unwrap().push((p.transferred, p.total));
+                })
+                .with_trailers_future(async move {
+                    let mut trailers = HeaderMap::new();
+                    let len = polled.load(Ordering::Relaxed).to_string();
+                    trailers.insert("x-length", HeaderValue::from_str(&len).unwrap());
+                    Ok(trailers)
+                })
+        };
+        let (data, trailers) = body.into_bytes_with_trailers().await.unwrap();
+        assert_eq!(data, "Hello, world!");
+        // the trailers are resolved after all data is polled
+        assert_eq!(trailers.unwrap()["x-length"], "13");
+        assert_eq!(*progress.lock().unwrap(), vec![(13, Some(13))]);
+    }
 }
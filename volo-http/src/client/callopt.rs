@@ -13,11 +13,13 @@ use crate::{context::ClientContext, error::ClientError};
 /// Call options for requests
 #[derive(Debug, Default)]
 pub struct CallOpt {
-    /// Timeout of the whole request
+    /// Timeout of the request
     ///
     /// This timeout includes connect, sending request headers, receiving response headers, but
-    /// without receiving streaming data.
+    /// without receiving streaming data. It is applied to each attempt if the request is retried.
     pub timeout: Option<Duration>,
+    /// Timeout of the whole request including retries
+    pub total_timeout: Option<Duration>,
     /// Additional information of the endpoint.
     ///
     /// Users can use `tags` to store custom data, such as the datacenter name or the region name,
@@ -49,6 +51,17 @@ impl CallOpt {
         self
     }
 
+    /// Set a total timeout including retries for the [`CallOpt`].
+    pub fn set_total_timeout(&mut self, timeout: Duration) {
+        self.total_timeout = Some(timeout);
+    }
+
+    /// Consume current [`CallOpt`] and return a new one with the given total timeout.
+    pub fn with_total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
     /// Check if [`CallOpt`] tags contain entry.
     #[inline]
     pub fn contains<T: 'static>(&self) -> bool {
@@ -118,6 +131,9 @@ impl Apply<ClientContext> for CallOpt {
             if self.timeout.is_some() {
                 config.set_timeout(self.timeout);
             }
+            if self.total_timeout.is_some() {
+                config.set_total_timeout(self.total_timeout);
+            }
         }
         Ok(())
     }
@@ -0,0 +1,69 @@
+use std::{net::TcpListener, time::Duration};
+
+use volo::net::Address;
+
+use crate::{
+    ClientBuilder,
+    body::BodyConversion,
+    client::{dns::DnsResolver, transport::protocol::PeerAddress},
+    server::{
+        Server,
+        route::{Router, get},
+    },
+};
+
+async fn hello() -> &'static str {
+    "hello"
+}
+
+async fn serve() -> u16 {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let router: Router = Router::new().route("/hello", get(hello));
+    tokio::spawn(Server::new(router).run(Address::from(addr)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    addr.port()
+}
+
+#[tokio::test]
+async fn resolve_on_dial() {
+    let port = serve().await;
+
+    // `localhost` may be resolved to `::1` first, the dialer falls back to `127.0.0.1`
+    let client = ClientBuilder::new().build().unwrap();
+    let resp = client
+        .get(format!("http://localhost:{port}/hello"))
+        .send()
+        .await
+        .unwrap()
+        .into_string()
+        .await
+        .unwrap();
+    assert_eq!(resp, "hello");
+    let stats = client.pool_stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(
+        stats[0].address,
+        PeerAddress::Domain("localhost".into(), port)
+    );
+
+    // resolved by the discover
+    let client = ClientBuilder::new()
+        .discover(DnsResolver::default())
+        .build()
+        .unwrap();
+    let resp = client
+        .get(format!("http://127.0.0.1:{port}/hello"))
+        .send()
+        .await
+        .unwrap()
+        .into_string()
+        .await
+        .unwrap();
+    assert_eq!(resp, "hello");
+    let stats = client.pool_stats();
+    assert_eq!(stats.len(), 1);
+    assert!(stats[0].address.as_address().is_some());
+}
@@ -0,0 +1,173 @@
+use std::{
+    net::{SocketAddr, TcpListener, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use http::{HeaderMap, HeaderValue, StatusCode, header, version::Version};
+use volo::net::tls::{ServerTlsConfig, TlsConnector};
+
+use crate::{
+    ClientBuilder,
+    body::{Body, BodyConversion},
+    server::{
+        Server,
+        route::{Router, get},
+        utils::early_hints::EarlyHints,
+    },
+};
+
+fn cert_path(name: &str) -> String {
+    format!("{}/../examples/data/tls/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+async fn echo(body: String) -> String {
+    body
+}
+
+async fn hints(early_hints: EarlyHints) -> Body {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LINK,
+        HeaderValue::from_static("</style.css>; rel=preload"),
+    );
+    early_hints.send(headers);
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+    Body::from("hinted").with_trailers(trailers)
+}
+
+// Start a server listening HTTPS on TCP and HTTP/3 on UDP, returns their addresses.
+async fn serve() -> (SocketAddr, SocketAddr) {
+    let tcp_addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let udp_addr = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let tls_config =
+        ServerTlsConfig::from_pem_file(cert_path("server.pem"), cert_path("server.key")).unwrap();
+    let router: Router = Router::new()
+        .route("/", get(echo).post(echo))
+        .route("/hints", get(hints));
+    let server = Server::new(router).tls_config(tls_config).http3(udp_addr);
+    tokio::spawn(server.run(volo::net::Address::from(tcp_addr)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (tcp_addr, udp_addr)
+}
+
+fn builder() -> ClientBuilder {
+    let tls_connector = TlsConnector::builder()
+        .enable_default_root_certs(false)
+        .add_pem_from_file(cert_path("ca.pem"))
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut builder = ClientBuilder::new();
+    builder.set_tls_config(tls_connector);
+    builder
+}
+
+#[tokio::test]
+async fn http3_prior_knowledge() {
+    let (_, udp_addr) = serve().await;
+    let mut builder = builder();
+    builder.http3_prior_knowledge();
+    let client = builder.build().unwrap();
+
+    let resp = client
+        .post(format!("https://{udp_addr}/"))
+        .body(Body::from("Hello, HTTP/3"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_3);
+    // `Alt-Svc` is only for HTTP/1 and HTTP/2
+    assert!(!resp.headers().contains_key(header::ALT_SVC));
+    assert_eq!(resp.into_string().await.unwrap(), "Hello, HTTP/3");
+}
+
+#[tokio::test]
+async fn http3_alt_svc_upgrade() {
+    let (tcp_addr, udp_addr) = serve().await;
+    let client = builder().build().unwrap();
+    let url = format!("https://{tcp_addr}/");
+
+    let resp = client
+        .post(&url)
+        .body(Body::from("tcp"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_11);
+    assert_eq!(
+        resp.headers().get(header::ALT_SVC).unwrap(),
+        &format!("h3=\":{}\"; ma=86400", udp_addr.port())
+    );
+    assert_eq!(resp.into_string().await.unwrap(), "tcp");
+
+    let resp = client
+        .post(&url)
+        .body(Body::from("quic"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_3);
+    assert_eq!(resp.into_string().await.unwrap(), "quic");
+}
+
+#[tokio::test]
+async fn http3_early_hints_and_trailers() {
+    let (_, udp_addr) = serve().await;
+    let mut builder = builder();
+    builder.http3_prior_knowledge();
+    let client = builder.build().unwrap();
+
+    let interim = Arc::new(Mutex::new(Vec::new()));
+    let cloned = interim.clone();
+    let resp = client
+        .get(format!("https://{udp_addr}/hints"))
+        .on_informational(move |resp| {
+            cloned
+                .lock()
+                .unwrap()
+                .push((resp.status(), resp.headers().get(header::LINK).cloned()));
+        })
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    // hints are not appended to the final response since they have been sent
+    assert!(!resp.headers().contains_key(header::LINK));
+    assert_eq!(
+        *interim.lock().unwrap(),
+        [(
+            StatusCode::EARLY_HINTS,
+            Some(HeaderValue::from_static("</style.css>; rel=preload"))
+        )]
+    );
+
+    let (data, trailers) = resp.into_body().into_bytes_with_trailers().await.unwrap();
+    assert_eq!(data, "hinted");
+    assert_eq!(trailers.unwrap().get("x-checksum").unwrap(), "abc");
+}
+
+#[tokio::test]
+async fn early_hints_fallback() {
+    let (tcp_addr, _) = serve().await;
+    let client = builder().build().unwrap();
+
+    let resp = client
+        .get(format!("https://{tcp_addr}/hints"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_11);
+    assert_eq!(
+        resp.headers().get(header::LINK).unwrap(),
+        "</style.css>; rel=preload"
+    );
+}
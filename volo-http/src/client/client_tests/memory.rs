@@ -0,0 +1,39 @@
+use volo::net::memory::MemoryListener;
+
+use crate::{
+    ClientBuilder,
+    body::{Body, BodyConversion},
+    server::{
+        Server,
+        route::{Router, post},
+    },
+};
+
+async fn echo(body: String) -> String {
+    body
+}
+
+#[tokio::test]
+async fn memory_connector() {
+    let listener = MemoryListener::new();
+    let connector = listener.connector();
+    let router: Router = Router::new().route("/echo", post(echo));
+    tokio::spawn(Server::new(router).run(listener));
+
+    let mut builder = ClientBuilder::new();
+    builder.memory_connector(connector);
+    let client = builder.build().unwrap();
+    for body in ["hello", "world"] {
+        // the address is ignored by the connector
+        let resp = client
+            .post("http://127.0.0.1/echo")
+            .body(Body::from(body))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.into_string().await.unwrap(), body);
+    }
+
+    // only HTTP without TLS is supported
+    assert!(client.get("https://127.0.0.1/echo").send().await.is_err());
+}
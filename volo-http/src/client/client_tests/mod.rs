@@ -7,8 +7,18 @@ use std::collections::HashMap;
 use http::header::{HeaderName, HeaderValue};
 use serde::{Deserialize, Deserializer};
 
+#[cfg(all(feature = "http1", feature = "server"))]
+mod dial;
 #[cfg(feature = "http1")]
 mod http1_only;
+#[cfg(all(feature = "http1", feature = "http3", feature = "server"))]
+mod http3;
+#[cfg(all(feature = "http1", feature = "server"))]
+mod memory;
+#[cfg(all(feature = "http1", feature = "server"))]
+mod proxy;
+#[cfg(all(feature = "http1", feature = "server"))]
+mod timing;
 #[cfg(feature = "__tls")]
 mod tls;
 mod utils;
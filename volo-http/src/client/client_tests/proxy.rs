@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use volo::net::{Address, proxy::Socks5Proxy};
+
+use crate::{
+    ClientBuilder,
+    body::BodyConversion,
+    server::{
+        Server,
+        route::{Router, get},
+    },
+};
+
+async fn hello() -> &'static str {
+    "hello"
+}
+
+/// A SOCKS5 proxy without authentication that connects all targets to `backend`, and checks the
+/// target is the unresolved `domain`.
+async fn socks5_proxy(listener: TcpListener, backend: std::net::SocketAddr, domain: &str) {
+    let (mut stream, _) = listener.accept().await.unwrap();
+
+    let mut buf = [0u8; 2];
+    stream.read_exact(&mut buf).await.unwrap();
+    let mut methods = vec![0u8; buf[1] as usize];
+    stream.read_exact(&mut methods).await.unwrap();
+    stream.write_all(&[5, 0]).await.unwrap();
+
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    // CONNECT with domain name
+    assert_eq!(buf[..4], [5, 1, 0, 3]);
+    let mut name = vec![0u8; buf[4] as usize];
+    stream.read_exact(&mut name).await.unwrap();
+    assert_eq!(name, domain.as_bytes());
+    let _port = stream.read_u16().await.unwrap();
+
+    let mut backend = tokio::net::TcpStream::connect(backend).await.unwrap();
+    stream
+        .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+        .await
+        .unwrap();
+    let _ = tokio::io::copy_bidirectional(&mut stream, &mut backend).await;
+}
+
+#[tokio::test]
+async fn socks5_remote_dns() {
+    let backend_addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let router: Router = Router::new().route("/hello", get(hello));
+    tokio::spawn(Server::new(router).run(Address::from(backend_addr)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    // the domain name can not be resolved locally
+    tokio::spawn(socks5_proxy(proxy, backend_addr, "backend.invalid"));
+
+    let mut builder = ClientBuilder::new();
+    builder.socks5_proxy(Socks5Proxy::from_uri(&format!("socks5h://{proxy_addr}")).unwrap());
+    let client = builder.build().unwrap();
+    let resp = client
+        .get("http://backend.invalid/hello")
+        .send()
+        .await
+        .unwrap()
+        .into_string()
+        .await
+        .unwrap();
+    assert_eq!(resp, "hello");
+}
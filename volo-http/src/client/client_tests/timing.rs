@@ -0,0 +1,52 @@
+use std::{net::TcpListener, time::Duration};
+
+use volo::util::timing::Phase;
+
+use crate::{
+    ClientBuilder,
+    body::BodyConversion,
+    context::ClientContext,
+    server::{
+        Server,
+        route::{Router, get},
+    },
+};
+
+async fn hello() -> &'static str {
+    "hello"
+}
+
+#[tokio::test]
+async fn timing_breakdown() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let router: Router = Router::new().route("/", get(hello));
+    tokio::spawn(Server::new(router).run(volo::net::Address::from(addr)));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client = ClientBuilder::new().build().unwrap();
+    let url = format!("http://{addr}/");
+
+    let mut cx = ClientContext::new();
+    cx.stats.set_trace_id("abc".into());
+    let resp = client.get(&url).send_with_context(&mut cx).await.unwrap();
+    assert_eq!(resp.into_string().await.unwrap(), "hello");
+    let timing = cx.timing();
+    assert_eq!(timing.trace_id().map(AsRef::as_ref), Some("abc"));
+    assert!(timing.total().is_some());
+    assert!(timing.get(Phase::Connect).is_some());
+    assert!(timing.get(Phase::FirstByte).is_some());
+    // resolving is not needed for an ip address
+    assert!(timing.get(Phase::Resolve).is_none());
+    assert!(timing.to_string().starts_with("trace_id: abc, total: "));
+
+    // the connection is reused
+    let mut cx = ClientContext::new();
+    let resp = client.get(&url).send_with_context(&mut cx).await.unwrap();
+    assert_eq!(resp.into_string().await.unwrap(), "hello");
+    let timing = cx.timing();
+    assert!(timing.get(Phase::Connect).is_none());
+    assert!(timing.get(Phase::FirstByte).is_some());
+}
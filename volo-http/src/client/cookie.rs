@@ -2,29 +2,203 @@
 //!
 //! This module provides [`CookieLayer`] for extracting and setting cookies.
 //!
-//! See [`CookieLayer`] for more details.
+//! Cookies are stored in a [`SharedCookieStore`], which can be shared by multiple clients (and
+//! their clones) through [`CookieLayer::shared`], so that all of them keep one coherent session
+//! state.
+//!
+//! See [`CookieLayer`] and [`SharedCookieStore`] for more details.
+
+use std::{fmt, sync::Arc};
 
+use cookie::Cookie;
+use futures::future::BoxFuture;
 use motore::{Service, layer::Layer};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard, broadcast};
+use url::Url;
 
 use crate::{
     context::ClientContext,
-    error::ClientError,
+    error::{BoxError, ClientError},
     request::{Request, RequestPartsExt},
     response::Response,
     utils::cookie::CookieStore,
 };
 
+const CHANGE_CHANNEL_CAPACITY: usize = 64;
+
+type FlushHook =
+    dyn Fn(&cookie_store::CookieStore) -> BoxFuture<'static, Result<(), BoxError>> + Send + Sync;
+
+/// A notification of cookies stored from a response.
+#[derive(Clone, Debug)]
+pub struct CookieChange {
+    /// URL of the request.
+    pub url: Url,
+    /// Cookies from `Set-Cookie` of the response.
+    pub cookies: Vec<Cookie<'static>>,
+}
+
+/// A [`cookie_store::CookieStore`] that can be shared by multiple clients.
+///
+/// All clones of [`SharedCookieStore`] refer to the same store, so it can be set to multiple
+/// clients by [`CookieLayer::shared`].
+///
+/// # Persistence
+///
+/// A flush hook can be set by [`SharedCookieStore::with_flush`], it is called by
+/// [`SharedCookieStore::flush`] or automatically after cookies changed if
+/// [`SharedCookieStore::with_flush_on_change`] is enabled.
+///
+/// The hook takes the store when the read lock is held, so it should serialize the store
+/// synchronously and return a future doing the IO, which is awaited after the lock is released.
+///
+/// # Example
+///
+/// ```rust
+/// use volo_http::{
+///     Client,
+///     client::cookie::{CookieLayer, SharedCookieStore},
+/// };
+///
+/// let store = SharedCookieStore::default().with_flush(|store| {
+///     let mut buf = Vec::new();
+///     let res = cookie_store::serde::json::save(store, &mut buf);
+///     Box::pin(async move {
+///         res?;
+///         tokio::fs::write("cookies.json", buf).await?;
+///         Ok(())
+///     })
+/// });
+///
+/// let client_a: Client = Client::builder()
+///     .layer_inner(CookieLayer::shared(store.clone()))
+///     .build()
+///     .unwrap();
+/// let client_b: Client = Client::builder()
+///     .layer_inner(CookieLayer::shared(store))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct SharedCookieStore {
+    store: Arc<RwLock<CookieStore>>,
+    changes: broadcast::Sender<CookieChange>,
+    flush: Option<Arc<FlushHook>>,
+    flush_on_change: bool,
+}
+
+impl Default for SharedCookieStore {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl fmt::Debug for SharedCookieStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedCookieStore")
+            .field("flush", &self.flush.is_some())
+            .field("flush_on_change", &self.flush_on_change)
+            .finish()
+    }
+}
+
+impl SharedCookieStore {
+    /// Create a new [`SharedCookieStore`] with the given
+    /// [`CookieStore`](cookie_store::CookieStore).
+    pub fn new(cookie_store: cookie_store::CookieStore) -> Self {
+        Self {
+            store: Arc::new(RwLock::new(CookieStore::new(cookie_store))),
+            changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
+            flush: None,
+            flush_on_change: false,
+        }
+    }
+
+    /// Set a hook for flushing the store, e.g., saving it to a file.
+    ///
+    /// Note that the hook is only shared by clones created after calling this method.
+    pub fn with_flush<F>(mut self, flush: F) -> Self
+    where
+        F: Fn(&cookie_store::CookieStore) -> BoxFuture<'static, Result<(), BoxError>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.flush = Some(Arc::new(flush));
+        self
+    }
+
+    /// Set whether to flush the store after cookies are stored from a response.
+    ///
+    /// Errors of the flush hook will be logged and ignored.
+    ///
+    /// Default is `false`.
+    pub fn with_flush_on_change(mut self, enable: bool) -> Self {
+        self.flush_on_change = enable;
+        self
+    }
+
+    /// Subscribe changes of the store.
+    ///
+    /// Only changes from responses are notified, modifications through
+    /// [`SharedCookieStore::write`] are not.
+    pub fn subscribe(&self) -> broadcast::Receiver<CookieChange> {
+        self.changes.subscribe()
+    }
+
+    /// Lock the store for reading.
+    pub async fn read(&self) -> RwLockReadGuard<'_, cookie_store::CookieStore> {
+        RwLockReadGuard::map(self.store.read().await, |store| &**store)
+    }
+
+    /// Lock the store for writing.
+    pub async fn write(&self) -> RwLockMappedWriteGuard<'_, cookie_store::CookieStore> {
+        RwLockWriteGuard::map(self.store.write().await, |store| &mut **store)
+    }
+
+    /// Flush the store by the hook from [`SharedCookieStore::with_flush`].
+    ///
+    /// It does nothing if there is no hook.
+    pub async fn flush(&self) -> Result<(), BoxError> {
+        let Some(flush) = &self.flush else {
+            return Ok(());
+        };
+        let fut = {
+            let store = self.store.read().await;
+            flush(&store)
+        };
+        // the lock has been released before awaiting the hook
+        fut.await
+    }
+
+    async fn store_response(&self, resp: &http::HeaderMap, url: &Url) {
+        let cookies = self.store.write().await.store_response_headers(resp, url);
+        if cookies.is_empty() {
+            return;
+        }
+        // there may be no subscribers
+        let _ = self.changes.send(CookieChange {
+            url: url.clone(),
+            cookies,
+        });
+        if self.flush_on_change {
+            if let Err(err) = self.flush().await {
+                tracing::warn!("[Volo-HTTP] failed to flush cookie store: {err}");
+            }
+        }
+    }
+}
+
 /// [`CookieLayer`] generated [`Service`]
 ///
 /// See [`CookieLayer`] for more details.
 pub struct CookieService<S> {
     inner: S,
-    cookie_store: RwLock<CookieStore>,
+    cookie_store: SharedCookieStore,
 }
 
 impl<S> CookieService<S> {
-    fn new(inner: S, cookie_store: RwLock<CookieStore>) -> Self {
+    fn new(inner: S, cookie_store: SharedCookieStore) -> Self {
         Self {
             inner,
             cookie_store,
@@ -55,6 +229,7 @@ where
             let (mut parts, body) = req.into_parts();
             if parts.headers.get(http::header::COOKIE).is_none() {
                 self.cookie_store
+                    .store
                     .read()
                     .await
                     .add_cookie_header(&mut parts.headers, url);
@@ -65,10 +240,7 @@ where
         let resp = self.inner.call(cx, req).await?;
 
         if let Some(url) = &url {
-            self.cookie_store
-                .write()
-                .await
-                .store_response_headers(resp.headers(), url);
+            self.cookie_store.store_response(resp.headers(), url).await;
         }
 
         Ok(resp)
@@ -79,7 +251,7 @@ where
 ///
 /// See [`CookieLayer::new`] for more details.
 pub struct CookieLayer {
-    cookie_store: RwLock<CookieStore>,
+    cookie_store: SharedCookieStore,
 }
 
 impl CookieLayer {
@@ -105,9 +277,14 @@ impl CookieLayer {
     ///     .unwrap();
     /// ```
     pub fn new(cookie_store: cookie_store::CookieStore) -> Self {
-        Self {
-            cookie_store: RwLock::new(CookieStore::new(cookie_store)),
-        }
+        Self::shared(SharedCookieStore::new(cookie_store))
+    }
+
+    /// Create a new [`CookieLayer`] with the given [`SharedCookieStore`].
+    ///
+    /// All clients using the same [`SharedCookieStore`] share their cookies.
+    pub fn shared(cookie_store: SharedCookieStore) -> Self {
+        Self { cookie_store }
     }
 }
 
@@ -118,3 +295,92 @@ impl<S> Layer<S> for CookieLayer {
         CookieService::new(inner, self.cookie_store)
     }
 }
+
+#[cfg(test)]
+mod cookie_tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use http::header::{self, HeaderValue};
+    use motore::service::service_fn;
+
+    use super::{CookieLayer, SharedCookieStore};
+    use crate::{
+        ClientBuilder, body::Body, client::test_helpers::MockTransport, context::ClientContext,
+        error::ClientError, request::Request, response::Response,
+    };
+
+    fn mock() -> MockTransport {
+        MockTransport::service(service_fn(|_: &mut ClientContext, req: Request| {
+            let mut resp = Response::new(Body::empty());
+            if req.uri().path() == "/login" {
+                resp.headers_mut()
+                    .insert(header::SET_COOKIE, HeaderValue::from_static("session=volo"));
+            } else {
+                // echo cookies by header `x-cookie`
+                if let Some(cookie) = req.headers().get(header::COOKIE) {
+                    resp.headers_mut().insert("x-cookie", cookie.clone());
+                }
+            }
+            async { Ok::<_, ClientError>(resp) }
+        }))
+    }
+
+    #[tokio::test]
+    async fn shared_store() {
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let store = SharedCookieStore::default()
+            .with_flush({
+                let flushed = flushed.clone();
+                move |_| {
+                    flushed.fetch_add(1, Ordering::Relaxed);
+                    Box::pin(async { Ok(()) })
+                }
+            })
+            .with_flush_on_change(true);
+        let mut changes = store.subscribe();
+
+        let client_a = ClientBuilder::new()
+            .layer_inner(CookieLayer::shared(store.clone()))
+            .mock(mock())
+            .unwrap();
+        let client_b = ClientBuilder::new()
+            .layer_inner(CookieLayer::shared(store.clone()))
+            .mock(mock())
+            .unwrap();
+
+        client_a
+            .get("/login")
+            .header(header::HOST, "example.com")
+            .send()
+            .await
+            .unwrap();
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.url.as_str(), "http://example.com/login");
+        assert_eq!(change.cookies[0].name(), "session");
+        assert_eq!(flushed.load(Ordering::Relaxed), 1);
+
+        // the cookie is shared with another client
+        let resp = client_b
+            .get("/")
+            .header(header::HOST, "example.com")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.headers().get("x-cookie").unwrap(), "session=volo");
+        assert_eq!(store.read().await.iter_any().count(), 1);
+
+        store.write().await.clear();
+        store.flush().await.unwrap();
+        assert_eq!(flushed.load(Ordering::Relaxed), 2);
+        let resp = client_b
+            .get("/")
+            .header(header::HOST, "example.com")
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.headers().get("x-cookie").is_none());
+    }
+}
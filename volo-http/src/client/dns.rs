@@ -10,11 +10,17 @@ use std::{
 
 use async_broadcast::Receiver;
 use faststr::FastStr;
+#[cfg(not(target_family = "wasm"))]
 use hickory_resolver::{
     Resolver, TokioResolver,
     config::{LookupIpStrategy, ResolverConfig, ResolverOpts},
     name_server::TokioConnectionProvider,
 };
+#[cfg(not(target_family = "wasm"))]
+use volo::util::{
+    time::Timestamp,
+    timing::{self, Phase},
+};
 use volo::{
     context::Endpoint,
     discovery::{Change, Discover, Instance},
@@ -42,12 +48,23 @@ impl Deref for Port {
     }
 }
 
+/// Marker of the callee whose domain name is resolved when dialing, by the dialer or the SOCKS5
+/// proxy with remote DNS, so that neither [`DnsResolver`] nor load balancing is applied to it.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResolveOnDial;
+
 /// A service discover implementation for DNS.
+///
+/// Domain names are resolved by browsers when sending requests on `wasm32`, where only IP
+/// addresses are parsed.
 #[derive(Clone)]
+#[cfg_attr(target_family = "wasm", derive(Default))]
 pub struct DnsResolver {
+    #[cfg(not(target_family = "wasm"))]
     resolver: TokioResolver,
 }
 
+#[cfg(not(target_family = "wasm"))]
 impl DnsResolver {
     /// Build a new `DnsResolver` through `ResolverConfig` and `ResolverOpts`.
     ///
@@ -63,10 +80,22 @@ impl DnsResolver {
     pub async fn resolve(&self, host: &str) -> Option<IpAddr> {
         // Note that the Resolver will try to parse the host as an IP address first, so we don't
         // need to parse it manually.
-        self.resolver.lookup_ip(host).await.ok()?.into_iter().next()
+        let start = Timestamp::now();
+        let ip = self.resolver.lookup_ip(host).await.ok()?.into_iter().next();
+        timing::record(Phase::Resolve, start, Timestamp::now());
+        ip
+    }
+}
+
+#[cfg(target_family = "wasm")]
+impl DnsResolver {
+    /// Parse a host as an IP address.
+    pub async fn resolve(&self, host: &str) -> Option<IpAddr> {
+        host.parse().ok()
     }
 }
 
+#[cfg(not(target_family = "wasm"))]
 impl Default for DnsResolver {
     fn default() -> Self {
         let (conf, mut opts) = hickory_resolver::system_conf::read_system_conf()
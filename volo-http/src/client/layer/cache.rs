@@ -0,0 +1,820 @@
+//! [`Layer`] for caching responses as a private cache.
+//!
+//! The [`Cache`] layer stores responses of `GET` requests following [RFC 9111], and serves
+//! following requests from the cache while the stored response is fresh, e.g.,
+//!
+//! ```
+//! use volo_http::client::{Client, layer::cache::Cache};
+//!
+//! let client = Client::builder()
+//!     .layer_outer(Cache::new(1024))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! The freshness of a response is computed from `Cache-Control: max-age`, `Expires`, or
+//! heuristically from `Last-Modified`. Once a stored response becomes stale (or it has
+//! `Cache-Control: no-cache`), it is revalidated by a conditional request with `If-None-Match` or
+//! `If-Modified-Since`, and a `304 Not Modified` response refreshes the stored one.
+//!
+//! Responses varying on request headers (by `Vary`) are stored as different variants, and requests
+//! with unsafe methods (e.g., `POST`) invalidate the stored responses of the URI.
+//!
+//! Whether the response is from the cache is inserted into extensions of the response as
+//! [`CacheStatus`].
+//!
+//! Responses are stored in [`MemoryStore`] by default, which is an in-memory LRU store, other
+//! stores can be used by [`Cache::with_store`] with an implementation of [`CacheStore`].
+//!
+//! [RFC 9111]: https://www.rfc-editor.org/rfc/rfc9111
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, stream};
+use http::{
+    HeaderMap, Method, StatusCode, Version,
+    header::{self, HeaderName, HeaderValue},
+};
+use http_body_util::BodyExt;
+use motore::{layer::Layer, service::Service};
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use super::redirect::current_url;
+use crate::{
+    body::Body, context::ClientContext, error::ClientError, request::Request, response::Response,
+};
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Whether the response is served from the cache.
+///
+/// It is inserted into extensions of the response by [`CacheService`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The response is served from the cache without sending the request.
+    Hit,
+    /// The stored response is revalidated by the server and served from the cache.
+    Revalidated,
+    /// The response is from the server.
+    Miss,
+}
+
+/// A stored response.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+    // values of request headers nominated by `Vary`
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    stored_at: Instant,
+    initial_age: Duration,
+    freshness: Duration,
+    no_cache: bool,
+}
+
+impl CachedResponse {
+    fn new(
+        req_headers: &HeaderMap,
+        status: StatusCode,
+        version: Version,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Self {
+        let vary = vary_names(&headers)
+            .map(|name| {
+                let value = req_headers.get(&name).cloned();
+                (name, value)
+            })
+            .collect();
+        let mut resp = Self {
+            status,
+            version,
+            headers,
+            body,
+            vary,
+            stored_at: Instant::now(),
+            initial_age: Duration::ZERO,
+            freshness: Duration::ZERO,
+            no_cache: false,
+        };
+        resp.update_freshness();
+        resp
+    }
+
+    /// The status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The current age of the response.
+    pub fn age(&self) -> Duration {
+        self.initial_age + self.stored_at.elapsed()
+    }
+
+    /// Whether the response can be served without revalidation.
+    pub fn is_fresh(&self) -> bool {
+        !self.no_cache && self.age() < self.freshness
+    }
+
+    fn update_freshness(&mut self) {
+        let cc = CacheControl::parse(&self.headers);
+        self.stored_at = Instant::now();
+        self.initial_age = self
+            .headers
+            .get(header::AGE)
+            .and_then(|age| age.to_str().ok()?.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        self.freshness = freshness(&cc, &self.headers, self.status);
+        self.no_cache = cc.no_cache;
+    }
+
+    fn matches(&self, req_headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| req_headers.get(name) == value.as_ref())
+    }
+
+    fn has_validator(&self) -> bool {
+        self.headers.contains_key(header::ETAG) || self.headers.contains_key(header::LAST_MODIFIED)
+    }
+
+    // Update headers by the `304 Not Modified` response.
+    fn refresh(&self, headers: &HeaderMap) -> Self {
+        let mut refreshed = self.clone();
+        for name in headers.keys() {
+            if name == header::CONTENT_LENGTH {
+                continue;
+            }
+            refreshed.headers.remove(name);
+            for value in headers.get_all(name) {
+                refreshed.headers.append(name, value.clone());
+            }
+        }
+        refreshed.update_freshness();
+        refreshed
+    }
+
+    fn to_response(&self, status: CacheStatus) -> Response {
+        let mut resp = Response::new(Body::from(self.body.clone()));
+        *resp.status_mut() = self.status;
+        *resp.version_mut() = self.version;
+        *resp.headers_mut() = self.headers.clone();
+        resp.headers_mut()
+            .insert(header::AGE, HeaderValue::from(self.age().as_secs()));
+        resp.extensions_mut().insert(status);
+        resp
+    }
+}
+
+/// A store of responses for [`Cache`].
+///
+/// Responses are stored by the URI of requests, and each URI may have multiple variants
+/// nominated by `Vary`.
+pub trait CacheStore: Send + Sync + 'static {
+    /// Get all variants stored for the `key`.
+    fn get(&self, key: &str) -> Vec<Arc<CachedResponse>>;
+
+    /// Replace variants stored for the `key`.
+    fn put(&self, key: &str, variants: Vec<Arc<CachedResponse>>);
+
+    /// Remove all variants stored for the `key`.
+    fn remove(&self, key: &str);
+}
+
+/// An in-memory [`CacheStore`] that evicts the least recently used URI when it is full.
+pub struct MemoryStore {
+    capacity: usize,
+    inner: Mutex<MemoryStoreInner>,
+}
+
+#[derive(Default)]
+struct MemoryStoreInner {
+    entries: HashMap<String, (Vec<Arc<CachedResponse>>, u64)>,
+    // last used tick to key, for finding the least recently used one
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl MemoryStore {
+    /// Create a [`MemoryStore`] storing responses of at most `capacity` URIs.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(MemoryStoreInner::default()),
+        }
+    }
+
+    /// The number of URIs stored.
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl MemoryStoreInner {
+    fn touch(&mut self, key: &str) -> Option<&Vec<Arc<CachedResponse>>> {
+        self.tick += 1;
+        let (variants, tick) = self.entries.get_mut(key)?;
+        self.lru.remove(tick);
+        *tick = self.tick;
+        self.lru.insert(self.tick, key.to_owned());
+        Some(variants)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, tick)) = self.entries.remove(key) {
+            self.lru.remove(&tick);
+        }
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Vec<Arc<CachedResponse>> {
+        self.inner.lock().touch(key).cloned().unwrap_or_default()
+    }
+
+    fn put(&self, key: &str, variants: Vec<Arc<CachedResponse>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        inner.remove(key);
+        if variants.is_empty() {
+            return;
+        }
+        while inner.entries.len() >= self.capacity {
+            let Some((_, lru)) = inner.lru.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&lru);
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.entries.insert(key.to_owned(), (variants, tick));
+        inner.lru.insert(tick, key.to_owned());
+    }
+
+    fn remove(&self, key: &str) {
+        self.inner.lock().remove(key);
+    }
+}
+
+/// [`Layer`] for caching responses.
+///
+/// See the [module documentation](self) for more details.
+pub struct Cache<St = MemoryStore> {
+    store: Arc<St>,
+    max_body_size: usize,
+}
+
+impl<St> Clone for Cache<St> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl Cache {
+    /// Create a [`Cache`] layer with a [`MemoryStore`] storing responses of at most `capacity`
+    /// URIs.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(MemoryStore::new(capacity))
+    }
+}
+
+impl<St> Cache<St> {
+    /// Create a [`Cache`] layer with the given [`CacheStore`].
+    pub fn with_store(store: St) -> Self {
+        Self {
+            store: Arc::new(store),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the maximum size of a response body to be stored.
+    ///
+    /// Default is 1 MiB.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Get a reference to the [`CacheStore`].
+    pub fn store(&self) -> &St {
+        &self.store
+    }
+}
+
+impl<S, St> Layer<S> for Cache<St> {
+    type Service = CacheService<S, St>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CacheService { inner, cache: self }
+    }
+}
+
+/// [`Service`] generated by [`Cache`].
+///
+/// See [`Cache`] and the [module documentation](self) for more details.
+pub struct CacheService<S, St = MemoryStore> {
+    inner: S,
+    cache: Cache<St>,
+}
+
+impl<S, St> Service<ClientContext, Request> for CacheService<S, St>
+where
+    S: Service<ClientContext, Request, Response = Response, Error = ClientError> + Send + Sync,
+    St: CacheStore,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ClientContext,
+        mut req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(mut key) = current_url(cx.target(), req.uri()) else {
+            return self.inner.call(cx, req).await;
+        };
+        key.set_fragment(None);
+        let key = key.as_str();
+
+        if req.method() != Method::GET {
+            let unsafe_method = !matches!(
+                *req.method(),
+                Method::HEAD | Method::OPTIONS | Method::TRACE
+            );
+            let resp = self.inner.call(cx, req).await?;
+            if unsafe_method && !resp.status().is_client_error() && !resp.status().is_server_error()
+            {
+                self.cache.store.remove(key);
+            }
+            return Ok(resp);
+        }
+
+        let req_cc = CacheControl::parse(req.headers());
+        // conditional requests from users are not handled by the cache
+        if req_cc.no_store
+            || req.headers().contains_key(header::IF_NONE_MATCH)
+            || req.headers().contains_key(header::IF_MODIFIED_SINCE)
+        {
+            return self.inner.call(cx, req).await;
+        }
+
+        let req_headers = req.headers().clone();
+        let mut variants = self.cache.store.get(key);
+        let stored = variants
+            .iter()
+            .position(|variant| variant.matches(&req_headers));
+        let mut revalidating = None;
+        if let Some(idx) = stored {
+            let stored = &variants[idx];
+            let acceptable = req_cc.max_age.is_none_or(|max_age| stored.age() <= max_age);
+            if !req_cc.no_cache && acceptable && stored.is_fresh() {
+                return Ok(stored.to_response(CacheStatus::Hit));
+            }
+            if stored.has_validator() {
+                if let Some(etag) = stored.headers.get(header::ETAG) {
+                    req.headers_mut()
+                        .insert(header::IF_NONE_MATCH, etag.clone());
+                }
+                if let Some(last_modified) = stored.headers.get(header::LAST_MODIFIED) {
+                    req.headers_mut()
+                        .insert(header::IF_MODIFIED_SINCE, last_modified.clone());
+                }
+                revalidating = Some(idx);
+            }
+        }
+
+        let resp = self.inner.call(cx, req).await?;
+
+        if let Some(idx) = revalidating {
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                let refreshed = Arc::new(variants[idx].refresh(resp.headers()));
+                variants[idx] = refreshed.clone();
+                self.cache.store.put(key, variants);
+                return Ok(refreshed.to_response(CacheStatus::Revalidated));
+            }
+        }
+
+        if !is_storable(resp.status(), resp.headers()) {
+            if let Some(idx) = stored {
+                variants.remove(idx);
+                self.cache.store.put(key, variants);
+            }
+            return Ok(with_status(resp, CacheStatus::Miss));
+        }
+
+        let (parts, body) = resp.into_parts();
+        let body = match collect_body(body, self.cache.max_body_size).await {
+            Ok(body) => body,
+            Err(body) => {
+                let resp = Response::from_parts(parts, body);
+                return Ok(with_status(resp, CacheStatus::Miss));
+            }
+        };
+        let cached = Arc::new(CachedResponse::new(
+            &req_headers,
+            parts.status,
+            parts.version,
+            parts.headers.clone(),
+            body.clone(),
+        ));
+        let usable = cached.is_fresh() || cached.has_validator();
+        match (stored, usable) {
+            (Some(idx), true) => variants[idx] = cached,
+            (Some(idx), false) => {
+                variants.remove(idx);
+            }
+            (None, true) => variants.push(cached),
+            (None, false) => {}
+        }
+        self.cache.store.put(key, variants);
+
+        let resp = Response::from_parts(parts, Body::from(body));
+        Ok(with_status(resp, CacheStatus::Miss))
+    }
+}
+
+fn with_status(mut resp: Response, status: CacheStatus) -> Response {
+    resp.extensions_mut().insert(status);
+    resp
+}
+
+/// Collect the body if it is not larger than `limit`, or return the body with the same content.
+async fn collect_body(mut body: Body, limit: usize) -> Result<Bytes, Body> {
+    let mut buf = BytesMut::new();
+    let mut chunks = Vec::new();
+    loop {
+        let frame = match body.frame().await {
+            Some(Ok(frame)) => frame,
+            None => return Ok(buf.freeze()),
+            Some(Err(err)) => {
+                // forward the error to the caller
+                let prefix = stream::iter([Ok(buf.freeze())]);
+                let err = stream::iter([Err(err)]);
+                return Err(Body::from_bytes_stream(prefix.chain(err)));
+            }
+        };
+        let Ok(data) = frame.into_data() else {
+            // trailers are not stored
+            continue;
+        };
+        if buf.len() + data.len() > limit {
+            chunks.push(buf.freeze());
+            chunks.push(data);
+            let prefix = stream::iter(chunks.into_iter().map(Ok));
+            return Err(Body::from_bytes_stream(
+                prefix.chain(body.into_data_stream()),
+            ));
+        }
+        buf.extend_from_slice(&data);
+    }
+}
+
+fn is_storable(status: StatusCode, headers: &HeaderMap) -> bool {
+    // status codes that are heuristically cacheable
+    let cacheable = matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    );
+    cacheable
+        && !CacheControl::parse(headers).no_store
+        && !headers
+            .get_all(header::VARY)
+            .iter()
+            .any(|value| value.as_bytes().contains(&b'*'))
+}
+
+fn vary_names(headers: &HeaderMap) -> impl Iterator<Item = HeaderName> + '_ {
+    headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+}
+
+fn freshness(cc: &CacheControl, headers: &HeaderMap, status: StatusCode) -> Duration {
+    if let Some(max_age) = cc.max_age {
+        return max_age;
+    }
+    let date = http_date(headers, header::DATE);
+    if headers.contains_key(header::EXPIRES) {
+        // invalid `Expires` means the response is already expired
+        return match http_date(headers, header::EXPIRES) {
+            Some(expires) => expires
+                .duration_since(date.unwrap_or_else(SystemTime::now))
+                .unwrap_or_default(),
+            None => Duration::ZERO,
+        };
+    }
+    if status != StatusCode::OK {
+        return Duration::ZERO;
+    }
+    match (date, http_date(headers, header::LAST_MODIFIED)) {
+        (Some(date), Some(last_modified)) => {
+            (date.duration_since(last_modified).unwrap_or_default() / 10)
+                .min(MAX_HEURISTIC_FRESHNESS)
+        }
+        _ => Duration::ZERO,
+    }
+}
+
+fn http_date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(SystemTime::from(date))
+}
+
+#[derive(Debug, Default)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<Duration>,
+}
+
+impl CacheControl {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = Self::default();
+        let directives = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            if name.eq_ignore_ascii_case("no-store") {
+                cc.no_store = true;
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                cc.no_cache = true;
+            } else if name.eq_ignore_ascii_case("max-age") {
+                // invalid `max-age` is treated as stale
+                let secs = value.and_then(|value| value.parse().ok()).unwrap_or(0);
+                cc.max_age = Some(Duration::from_secs(secs));
+            }
+        }
+        cc
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use http::{
+        HeaderMap, StatusCode,
+        header::{self, HeaderValue},
+    };
+    use motore::service::service_fn;
+
+    use super::{Cache, CacheStatus, CacheStore, CachedResponse, MemoryStore, freshness};
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        context::ClientContext,
+        error::ClientError,
+        request::Request,
+        response::Response,
+    };
+
+    // Responses with `Cache-Control` from the `cc` query, and the request count as the body.
+    fn mock(count: Arc<AtomicUsize>) -> MockTransport {
+        MockTransport::service(service_fn(move |_: &mut ClientContext, req: Request| {
+            let count = count.clone();
+            async move {
+                let n = count.fetch_add(1, Ordering::Relaxed);
+                if req.headers().get(header::IF_NONE_MATCH)
+                    == Some(&HeaderValue::from_static("\"v1\""))
+                {
+                    let mut resp = Response::new(Body::empty());
+                    *resp.status_mut() = StatusCode::NOT_MODIFIED;
+                    return Ok::<_, ClientError>(resp);
+                }
+                let mut resp = Response::new(Body::from(n.to_string()));
+                let cc = req
+                    .uri()
+                    .query()
+                    .and_then(|query| query.strip_prefix("cc="))
+                    .unwrap_or("no-store");
+                resp.headers_mut()
+                    .insert(header::CACHE_CONTROL, HeaderValue::from_str(cc).unwrap());
+                resp.headers_mut()
+                    .insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+                if req.uri().path() == "/vary" {
+                    resp.headers_mut()
+                        .insert(header::VARY, HeaderValue::from_static("Accept-Language"));
+                }
+                Ok(resp)
+            }
+        }))
+    }
+
+    async fn get(
+        client: &crate::Client,
+        uri: &str,
+        headers: &[(&'static str, &'static str)],
+    ) -> (CacheStatus, String) {
+        let mut builder = client.get(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let resp = builder.send().await.unwrap();
+        let status = *resp.extensions().get::<CacheStatus>().unwrap();
+        (status, resp.into_body().into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn cache_fresh_response() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(Cache::new(16))
+            .mock(mock(count.clone()))
+            .unwrap();
+
+        let uri = "http://example.com/config?cc=max-age=60";
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Miss, "0".into())
+        );
+        assert_eq!(get(&client, uri, &[]).await, (CacheStatus::Hit, "0".into()));
+        // `no-cache` of request forces revalidation
+        assert_eq!(
+            get(&client, uri, &[("cache-control", "no-cache")]).await,
+            (CacheStatus::Revalidated, "0".into())
+        );
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // `no-store` responses are not stored
+        let uri = "http://example.com/config";
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Miss, "2".into())
+        );
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Miss, "3".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_revalidate() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(Cache::new(16))
+            .mock(mock(count.clone()))
+            .unwrap();
+
+        let uri = "http://example.com/config?cc=no-cache";
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Miss, "0".into())
+        );
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Revalidated, "0".into())
+        );
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_vary_and_invalidate() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(Cache::new(16))
+            .mock(mock(count.clone()))
+            .unwrap();
+
+        let uri = "http://example.com/vary?cc=max-age=60";
+        let en = [("accept-language", "en")];
+        let zh = [("accept-language", "zh")];
+        assert_eq!(
+            get(&client, uri, &en).await,
+            (CacheStatus::Miss, "0".into())
+        );
+        assert_eq!(
+            get(&client, uri, &zh).await,
+            (CacheStatus::Miss, "1".into())
+        );
+        assert_eq!(get(&client, uri, &en).await, (CacheStatus::Hit, "0".into()));
+        assert_eq!(get(&client, uri, &zh).await, (CacheStatus::Hit, "1".into()));
+
+        // unsafe methods invalidate the stored responses
+        client.post(uri).send().await.unwrap();
+        assert_eq!(
+            get(&client, uri, &en).await,
+            (CacheStatus::Miss, "3".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_expire() {
+        tokio::time::pause();
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(Cache::new(16))
+            .mock(mock(count.clone()))
+            .unwrap();
+
+        let uri = "http://example.com/config?cc=max-age=10";
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Miss, "0".into())
+        );
+        tokio::time::advance(std::time::Duration::from_secs(5)).await;
+        assert_eq!(get(&client, uri, &[]).await, (CacheStatus::Hit, "0".into()));
+        tokio::time::advance(std::time::Duration::from_secs(5)).await;
+        assert_eq!(
+            get(&client, uri, &[]).await,
+            (CacheStatus::Revalidated, "0".into())
+        );
+        assert_eq!(get(&client, uri, &[]).await, (CacheStatus::Hit, "0".into()));
+    }
+
+    #[test]
+    fn memory_store_lru() {
+        let store = MemoryStore::new(2);
+        let resp = Arc::new(CachedResponse::new(
+            &HeaderMap::new(),
+            StatusCode::OK,
+            http::Version::HTTP_11,
+            HeaderMap::new(),
+            "body".into(),
+        ));
+        store.put("a", vec![resp.clone()]);
+        store.put("b", vec![resp.clone()]);
+        assert_eq!(store.get("a").len(), 1);
+        store.put("c", vec![resp.clone()]);
+        // `b` is the least recently used one
+        assert!(store.get("b").is_empty());
+        assert_eq!(store.get("a").len(), 1);
+        assert_eq!(store.get("c").len(), 1);
+        store.remove("a");
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn heuristic_freshness() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::DATE,
+            HeaderValue::from_static("Wed, 21 Oct 2015 08:00:00 GMT"),
+        );
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:00:00 GMT"),
+        );
+        let cc = super::CacheControl::parse(&headers);
+        assert_eq!(
+            freshness(&cc, &headers, StatusCode::OK),
+            std::time::Duration::from_secs(360)
+        );
+        headers.insert(
+            header::EXPIRES,
+            HeaderValue::from_static("Wed, 21 Oct 2015 08:01:00 GMT"),
+        );
+        assert_eq!(
+            freshness(&cc, &headers, StatusCode::OK),
+            std::time::Duration::from_secs(60)
+        );
+        headers.insert(header::EXPIRES, HeaderValue::from_static("0"));
+        assert_eq!(
+            freshness(&cc, &headers, StatusCode::OK),
+            std::time::Duration::ZERO
+        );
+    }
+}
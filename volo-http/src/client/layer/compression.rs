@@ -0,0 +1,564 @@
+//! [`Layer`] for compressing requests and decompressing responses.
+//!
+//! [`Compression`] inserts `Accept-Encoding` into requests, and decompresses response bodies with
+//! `Content-Encoding` transparently as a stream. It can also compress request bodies whose size
+//! reaches the given threshold by [`Compression::compress_request`].
+//!
+//! Each algorithm is enabled by its own feature:
+//!
+//! - `gzip`: [`Encoding::Gzip`]
+//! - `deflate`: [`Encoding::Deflate`]
+//! - `br`: [`Encoding::Br`]
+//! - `zstd`: [`Encoding::Zstd`]
+//!
+//! # Example
+//!
+//! ```
+//! use volo_http::client::{Client, layer::compression::Compression};
+//!
+//! let client: Client = Client::builder()
+//!     .layer_outer(Compression::new())
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{fmt, io, io::Write};
+
+use bytes::Bytes;
+use futures::stream;
+use http::header::{self, HeaderValue};
+use http_body::{Body as _, Frame};
+use http_body_util::BodyExt;
+use motore::{layer::Layer, service::Service};
+
+use crate::{
+    body::Body,
+    context::ClientContext,
+    error::{
+        BoxError, ClientError,
+        client::{Result, body_error},
+    },
+    request::Request,
+    response::Response,
+};
+
+/// Content coding supported by [`Compression`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `gzip`
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `deflate`, the "zlib" format
+    #[cfg(feature = "deflate")]
+    Deflate,
+    /// `br`, Brotli
+    #[cfg(feature = "br")]
+    Br,
+    /// `zstd`, Zstandard
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    const ALL: &'static [Self] = &[
+        #[cfg(feature = "gzip")]
+        Self::Gzip,
+        #[cfg(feature = "deflate")]
+        Self::Deflate,
+        #[cfg(feature = "br")]
+        Self::Br,
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+    ];
+
+    /// Get the name of the coding used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Self::Deflate => "deflate",
+            #[cfg(feature = "br")]
+            Self::Br => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        Self::ALL
+            .iter()
+            .find(|encoding| value.eq_ignore_ascii_case(encoding.as_str()))
+            .copied()
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "deflate")]
+            Self::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "br")]
+            Self::Br => {
+                let mut buf = Vec::new();
+                brotli::BrotliCompress(&mut &data[..], &mut buf, &Default::default())?;
+                Ok(buf)
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::stream::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// [`Layer`] for compressing requests and decompressing responses.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct Compression {
+    accept: Vec<Encoding>,
+    request: Option<(Encoding, usize)>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Create a [`Compression`] layer accepting all enabled encodings.
+    pub fn new() -> Self {
+        Self {
+            accept: Encoding::ALL.to_vec(),
+            request: None,
+        }
+    }
+
+    /// Set the encodings in `Accept-Encoding`, in order of preference.
+    ///
+    /// If it is empty, `Accept-Encoding` will not be inserted and responses will not be
+    /// decompressed.
+    pub fn accept<I>(mut self, encodings: I) -> Self
+    where
+        I: IntoIterator<Item = Encoding>,
+    {
+        self.accept = encodings.into_iter().collect();
+        self
+    }
+
+    /// Compress request bodies by the `encoding` if their sizes are known and not less than
+    /// `min_size`.
+    ///
+    /// Requests with `Content-Encoding` or a streaming body with unknown size will not be
+    /// compressed.
+    pub fn compress_request(mut self, encoding: Encoding, min_size: usize) -> Self {
+        self.request = Some((encoding, min_size));
+        self
+    }
+}
+
+impl<S> Layer<S> for Compression {
+    type Service = CompressionService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        let accept = if self.accept.is_empty() {
+            None
+        } else {
+            let value = self
+                .accept
+                .iter()
+                .map(Encoding::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(HeaderValue::from_str(&value).expect("encodings are valid header values"))
+        };
+        CompressionService {
+            inner,
+            accept,
+            config: self,
+        }
+    }
+}
+
+/// [`Service`] generated by [`Compression`].
+///
+/// See [`Compression`] for more details.
+pub struct CompressionService<S> {
+    inner: S,
+    accept: Option<HeaderValue>,
+    config: Compression,
+}
+
+impl<S> Service<ClientContext, Request> for CompressionService<S>
+where
+    S: Service<ClientContext, Request, Response = Response, Error = ClientError> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut ClientContext, mut req: Request) -> Result<Self::Response> {
+        if let Some((encoding, min_size)) = self.config.request {
+            req = compress_request(req, encoding, min_size).await?;
+        }
+
+        // Responses are only decompressed if `Accept-Encoding` is inserted by us, or the users
+        // may want to process the encoded body by themselves.
+        let decompress = match &self.accept {
+            Some(accept) if !req.headers().contains_key(header::ACCEPT_ENCODING) => {
+                req.headers_mut()
+                    .insert(header::ACCEPT_ENCODING, accept.clone());
+                true
+            }
+            _ => false,
+        };
+
+        let resp = self.inner.call(cx, req).await?;
+        if !decompress {
+            return Ok(resp);
+        }
+        let Some(encoding) = resp
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .and_then(Encoding::from_header)
+        else {
+            return Ok(resp);
+        };
+        if !self.config.accept.contains(&encoding) {
+            return Ok(resp);
+        }
+
+        let (mut parts, body) = resp.into_parts();
+        parts.headers.remove(header::CONTENT_ENCODING);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = decompress_body(body, encoding).map_err(body_error)?;
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+async fn compress_request(req: Request, encoding: Encoding, min_size: usize) -> Result<Request> {
+    if req.headers().contains_key(header::CONTENT_ENCODING) {
+        return Ok(req);
+    }
+    match req.body().size_hint().exact() {
+        Some(size) if size >= min_size as u64 => {}
+        _ => return Ok(req),
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let data = body.collect().await.map_err(body_error)?.to_bytes();
+    let data = encoding.compress(&data).map_err(body_error)?;
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(data.len()));
+    Ok(Request::from_parts(parts, Body::from(data)))
+}
+
+enum Decoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    #[cfg(feature = "deflate")]
+    Deflate(flate2::write::ZlibDecoder<Vec<u8>>),
+    #[cfg(feature = "br")]
+    Br(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+}
+
+impl Decoder {
+    fn new(encoding: Encoding) -> io::Result<Self> {
+        Ok(match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Self::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => Self::Deflate(flate2::write::ZlibDecoder::new(Vec::new())),
+            #[cfg(feature = "br")]
+            Encoding::Br => Self::Br(Box::new(brotli::DecompressorWriter::new(Vec::new(), 0))),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Self::Zstd(zstd::stream::write::Decoder::new(Vec::new())?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder,
+            #[cfg(feature = "deflate")]
+            Self::Deflate(decoder) => decoder,
+            #[cfg(feature = "br")]
+            Self::Br(decoder) => decoder,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(decoder) => decoder,
+        }
+    }
+
+    fn take_output(&mut self) -> Bytes {
+        let buf = match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.get_mut(),
+            #[cfg(feature = "deflate")]
+            Self::Deflate(decoder) => decoder.get_mut(),
+            #[cfg(feature = "br")]
+            Self::Br(decoder) => decoder.get_mut(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(decoder) => decoder.get_mut(),
+        };
+        Bytes::from(std::mem::take(buf))
+    }
+
+    fn decode(&mut self, data: &[u8]) -> io::Result<Bytes> {
+        let writer = self.writer();
+        writer.write_all(data)?;
+        writer.flush()?;
+        Ok(self.take_output())
+    }
+
+    fn finish(&mut self) -> io::Result<Bytes> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(decoder) => decoder.try_finish()?,
+            #[cfg(feature = "deflate")]
+            Self::Deflate(decoder) => decoder.try_finish()?,
+            #[cfg(feature = "br")]
+            Self::Br(decoder) => decoder.close()?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(decoder) => decoder.flush()?,
+        }
+        Ok(self.take_output())
+    }
+}
+
+struct DecodeState {
+    body: Body,
+    decoder: Decoder,
+    // whether any data has been decoded
+    received: bool,
+    // trailers received before the decoded data is finished
+    trailers: Option<Frame<Bytes>>,
+    done: bool,
+}
+
+impl DecodeState {
+    async fn next(&mut self) -> Option<Result<Frame<Bytes>, BoxError>> {
+        if let Some(trailers) = self.trailers.take() {
+            self.done = true;
+            return Some(Ok(trailers));
+        }
+        if self.done {
+            return None;
+        }
+        loop {
+            let frame = match self.body.frame().await {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                None => {
+                    self.done = true;
+                    return match self.finish() {
+                        Ok(Some(data)) => Some(Ok(Frame::data(data))),
+                        Ok(None) => None,
+                        Err(err) => Some(Err(err.into())),
+                    };
+                }
+            };
+            match frame.into_data() {
+                Ok(data) => {
+                    self.received = true;
+                    match self.decoder.decode(&data) {
+                        Ok(data) if data.is_empty() => continue,
+                        Ok(data) => return Some(Ok(Frame::data(data))),
+                        Err(err) => {
+                            self.done = true;
+                            return Some(Err(err.into()));
+                        }
+                    }
+                }
+                // trailers are the last frame, so the decoder should be finished first
+                Err(trailers) => {
+                    return match self.finish() {
+                        Ok(Some(data)) => {
+                            self.trailers = Some(trailers);
+                            Some(Ok(Frame::data(data)))
+                        }
+                        Ok(None) => {
+                            self.done = true;
+                            Some(Ok(trailers))
+                        }
+                        Err(err) => {
+                            self.done = true;
+                            Some(Err(err.into()))
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<Option<Bytes>> {
+        // the body may be empty, e.g., response of `HEAD`
+        if !self.received {
+            return Ok(None);
+        }
+        let data = self.decoder.finish()?;
+        Ok(Some(data).filter(|data| !data.is_empty()))
+    }
+}
+
+fn decompress_body(body: Body, encoding: Encoding) -> io::Result<Body> {
+    let state = DecodeState {
+        body,
+        decoder: Decoder::new(encoding)?,
+        received: false,
+        trailers: None,
+        done: false,
+    };
+    Ok(Body::from_stream(stream::unfold(
+        state,
+        |mut state| async move {
+            let frame = state.next().await?;
+            Some((frame, state))
+        },
+    )))
+}
+
+#[cfg(test)]
+mod compression_tests {
+    use std::sync::{Arc, Mutex};
+
+    use http::header::{self, HeaderValue};
+    use motore::service::service_fn;
+
+    use super::{Compression, Encoding};
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        context::ClientContext,
+        error::ClientError,
+        request::Request,
+        response::Response,
+    };
+
+    const CONTENT: &str = "Hello, Volo! Hello, Volo! Hello, Volo! Hello, Volo!";
+
+    type Record = Arc<Mutex<Vec<(Option<HeaderValue>, Option<HeaderValue>, Vec<u8>)>>>;
+
+    // respond the compressed `CONTENT` by the first encoding of `Accept-Encoding`
+    fn mock(record: Record) -> MockTransport {
+        MockTransport::service(service_fn(move |_: &mut ClientContext, req: Request| {
+            let record = record.clone();
+            async move {
+                let (parts, body) = req.into_parts();
+                let accept = parts.headers.get(header::ACCEPT_ENCODING).cloned();
+                let body = body.into_vec().await.unwrap();
+                record.lock().unwrap().push((
+                    accept.clone(),
+                    parts.headers.get(header::CONTENT_ENCODING).cloned(),
+                    body,
+                ));
+                let encoding = accept.as_ref().and_then(|accept| {
+                    let first = accept.to_str().unwrap().split(',').next().unwrap();
+                    Encoding::from_header(&HeaderValue::from_str(first).unwrap())
+                });
+                let mut resp = match encoding {
+                    Some(encoding) => {
+                        let mut resp = Response::new(Body::from(
+                            encoding.compress(CONTENT.as_bytes()).unwrap(),
+                        ));
+                        resp.headers_mut().insert(
+                            header::CONTENT_ENCODING,
+                            HeaderValue::from_static(encoding.as_str()),
+                        );
+                        resp
+                    }
+                    None => Response::new(Body::from(CONTENT)),
+                };
+                resp.headers_mut().insert(
+                    "x-encoded",
+                    HeaderValue::from(u16::from(encoding.is_some())),
+                );
+                Ok::<_, ClientError>(resp)
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn decompress_response() {
+        let record = Record::default();
+        for encoding in Encoding::ALL {
+            let client = ClientBuilder::new()
+                .layer_outer(Compression::new().accept([*encoding]))
+                .mock(mock(record.clone()))
+                .unwrap();
+            let resp = client.get("/").send().await.unwrap();
+            assert_eq!(resp.headers().get("x-encoded").unwrap(), "1");
+            assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(resp.into_string().await.unwrap(), CONTENT);
+        }
+
+        // `Accept-Encoding` set by users is not touched
+        let client = ClientBuilder::new()
+            .layer_outer(Compression::new())
+            .mock(mock(record.clone()))
+            .unwrap();
+        let resp = client
+            .get("/")
+            .header(header::ACCEPT_ENCODING, Encoding::ALL[0].as_str())
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_some());
+    }
+
+    #[tokio::test]
+    async fn compress_request() {
+        let record = Record::default();
+        let encoding = Encoding::ALL[0];
+        let client = ClientBuilder::new()
+            .layer_outer(Compression::new().compress_request(encoding, 16))
+            .mock(mock(record.clone()))
+            .unwrap();
+
+        client
+            .post("/")
+            .body(Body::from("short"))
+            .send()
+            .await
+            .unwrap();
+        client
+            .post("/")
+            .body(Body::from(CONTENT))
+            .send()
+            .await
+            .unwrap();
+
+        let record = record.lock().unwrap();
+        assert!(record[0].1.is_none());
+        assert_eq!(record[0].2, b"short");
+        assert_eq!(record[1].1.as_ref().unwrap(), encoding.as_str());
+        assert_eq!(record[1].2, encoding.compress(CONTENT.as_bytes()).unwrap());
+    }
+}
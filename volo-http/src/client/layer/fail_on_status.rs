@@ -155,6 +155,7 @@ impl Error for StatusCodeError {}
 mod fail_on_status_tests {
     use http::status::StatusCode;
     use motore::service::Service;
+    use volo::error::{ErrorCategory, ErrorOrigin, RpcError};
 
     use super::FailOnStatus;
     use crate::{
@@ -191,7 +192,9 @@ mod fail_on_status_tests {
                 .layer_outer_front(FailOnStatus::all())
                 .mock(MockTransport::service(ReturnStatus))
                 .unwrap();
-            client.get("/400").send().await.unwrap_err();
+            let err = client.get("/400").send().await.unwrap_err();
+            assert_eq!(err.category(), ErrorCategory::Biz);
+            assert_eq!(err.origin(), ErrorOrigin::Remote);
             client.get("/500").send().await.unwrap_err();
         }
         {
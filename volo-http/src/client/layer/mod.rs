@@ -2,15 +2,21 @@
 //!
 //! [`Layer`]: motore::layer::Layer
 
+pub mod cache;
+#[cfg(feature = "__compression")]
+pub mod compression;
 mod fail_on_status;
 pub mod header;
 #[cfg(feature = "http1")]
 pub mod http_proxy;
+pub mod redirect;
+pub mod retry;
 mod timeout;
 mod utils;
 
 pub use self::{
     fail_on_status::{FailOnStatus, StatusCodeError},
+    retry::Retry,
     timeout::Timeout,
     utils::TargetLayer,
 };
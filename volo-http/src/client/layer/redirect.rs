@@ -0,0 +1,487 @@
+//! [`Layer`] for following redirections automatically.
+//!
+//! The layer can be enabled by [`ClientBuilder::redirect`] with a [`Policy`], e.g.,
+//!
+//! ```
+//! use volo_http::client::{Client, layer::redirect::Policy};
+//!
+//! let client = Client::builder()
+//!     .redirect(Policy::limited(10))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! For each hop, the [`Target`] of [`ClientContext`] is updated by the `Location` of the response,
+//! so that the following layers and transport will send the request to the new target. The final
+//! URI is inserted into extensions of the response as [`FinalUri`].
+//!
+//! The body of request can only be sent again if it is a complete body (e.g., created from
+//! [`Bytes`], [`String`] or [`Vec<u8>`]), or the redirect response will be returned directly for
+//! `307 Temporary Redirect` and `308 Permanent Redirect`.
+//!
+//! [`ClientBuilder::redirect`]: crate::client::ClientBuilder::redirect
+//! [`Bytes`]: bytes::Bytes
+
+use http::{
+    Extensions, HeaderMap, Method, StatusCode, Uri, Version,
+    header::{self, HeaderName},
+    uri::{PathAndQuery, Scheme},
+};
+use motore::{layer::Layer, service::Service};
+use url::Url;
+use volo::{client::Apply, context::Context};
+
+use super::header::gen_host;
+use crate::{
+    body::Body,
+    client::Target,
+    context::ClientContext,
+    error::{
+        ClientError,
+        client::{Result, too_many_redirects},
+    },
+    request::Request,
+    response::Response,
+};
+
+const DEFAULT_MAX_HOPS: usize = 10;
+
+/// Headers removed from the request when redirecting to another origin.
+const SENSITIVE_HEADERS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::PROXY_AUTHORIZATION,
+    header::WWW_AUTHENTICATE,
+];
+
+/// The policy for following redirections.
+///
+/// The default policy follows at most 10 redirections, allows redirecting to another origin and
+/// strips sensitive headers (`Authorization`, `Cookie`, etc.) when the origin changes.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    max_hops: usize,
+    cross_origin: bool,
+    strip_sensitive_headers: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self::limited(DEFAULT_MAX_HOPS)
+    }
+}
+
+impl Policy {
+    /// Create a [`Policy`] that does not follow any redirection.
+    pub fn none() -> Self {
+        Self::limited(0)
+    }
+
+    /// Create a [`Policy`] that follows at most `max_hops` redirections.
+    ///
+    /// If the limit is reached, a [`TooManyRedirects`] error will be returned.
+    ///
+    /// [`TooManyRedirects`]: crate::error::client::TooManyRedirects
+    pub fn limited(max_hops: usize) -> Self {
+        Self {
+            max_hops,
+            cross_origin: true,
+            strip_sensitive_headers: true,
+        }
+    }
+
+    /// Set whether to follow redirections to another origin (scheme, host and port).
+    ///
+    /// If it is disabled, the redirect response to another origin will be returned directly.
+    ///
+    /// Default is `true`.
+    pub fn allow_cross_origin(mut self, allow: bool) -> Self {
+        self.cross_origin = allow;
+        self
+    }
+
+    /// Set whether to remove sensitive headers (`Authorization`, `Cookie`, `Proxy-Authorization`
+    /// and `WWW-Authenticate`) when redirecting to another origin.
+    ///
+    /// Default is `true`.
+    pub fn strip_sensitive_headers(mut self, strip: bool) -> Self {
+        self.strip_sensitive_headers = strip;
+        self
+    }
+}
+
+impl<S> Layer<S> for Policy {
+    type Service = RedirectService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RedirectService {
+            inner,
+            policy: self,
+        }
+    }
+}
+
+/// The final URI of the request after following redirections.
+///
+/// It is inserted into extensions of the response by [`RedirectService`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalUri(pub Uri);
+
+/// [`Service`] generated by [`Policy`].
+///
+/// See [`Policy`] and the [module documentation](self) for more details.
+pub struct RedirectService<S> {
+    inner: S,
+    policy: Policy,
+}
+
+// The request without its body, saved for sending it again.
+struct SavedRequest {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+    extensions: Extensions,
+    body: Option<Body>,
+}
+
+impl SavedRequest {
+    fn new(req: &Request) -> Self {
+        Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+            extensions: req.extensions().clone(),
+            body: req.body().try_clone(),
+        }
+    }
+}
+
+impl<S, B> Service<ClientContext, Request> for RedirectService<S>
+where
+    S: Service<ClientContext, Request, Response = Response<B>, Error = ClientError> + Send + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut ClientContext, req: Request) -> Result<Self::Response> {
+        if self.policy.max_hops == 0 {
+            return self.inner.call(cx, req).await;
+        }
+
+        let mut url = current_url(cx.target(), req.uri());
+        let mut req = req;
+        let mut hops = 0;
+        loop {
+            let saved = SavedRequest::new(&req);
+            let mut resp = self.inner.call(cx, req).await?;
+
+            let Some(next) = url
+                .as_ref()
+                .and_then(|url| next_url(url, resp.status(), resp.headers()))
+            else {
+                if let Some(uri) = url.and_then(|url| url.as_str().parse().ok()) {
+                    resp.extensions_mut().insert(FinalUri(uri));
+                }
+                return Ok(resp);
+            };
+            let prev = url.take().expect("url has been checked");
+
+            let cross_origin = prev.origin() != next.origin();
+            if cross_origin && !self.policy.cross_origin {
+                tracing::debug!("[Volo-HTTP] redirection to another origin `{next}` is denied");
+                return Ok(resp);
+            }
+            let Some(next_req) = self.redirect_request(saved, resp.status(), &next, cross_origin)
+            else {
+                tracing::debug!(
+                    "[Volo-HTTP] redirection to `{next}` is skipped since the body cannot be sent \
+                     again"
+                );
+                return Ok(resp);
+            };
+
+            hops += 1;
+            if hops > self.policy.max_hops {
+                return Err(too_many_redirects().with_url(resp_uri(&prev)));
+            }
+
+            let target = Target::from_uri(&resp_uri(&next))?;
+            cx.rpc_info_mut().callee_mut().clear();
+            target.apply(cx)?;
+
+            req = next_req;
+            if cross_origin && req.headers().contains_key(header::HOST) {
+                match gen_host(cx.target()) {
+                    Some(host) => {
+                        req.headers_mut().insert(header::HOST, host);
+                    }
+                    None => {
+                        req.headers_mut().remove(header::HOST);
+                    }
+                }
+            }
+            if cx.target().scheme() == Some(&Scheme::HTTPS) {
+                req.extensions_mut().insert(Scheme::HTTPS);
+            } else {
+                req.extensions_mut().remove::<Scheme>();
+            }
+            url = Some(next);
+        }
+    }
+}
+
+impl<S> RedirectService<S> {
+    fn redirect_request(
+        &self,
+        saved: SavedRequest,
+        status: StatusCode,
+        next: &Url,
+        cross_origin: bool,
+    ) -> Option<Request> {
+        let SavedRequest {
+            mut method,
+            uri,
+            version,
+            mut headers,
+            extensions,
+            body,
+        } = saved;
+
+        // `303 See Other` always changes the method to `GET` (except `HEAD`), and `POST` is also
+        // changed to `GET` for `301` and `302` as what browsers do.
+        let rewrite = match status {
+            StatusCode::SEE_OTHER => method != Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => method == Method::POST,
+            _ => false,
+        };
+        let body = if rewrite {
+            method = Method::GET;
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_LENGTH);
+            headers.remove(header::CONTENT_ENCODING);
+            headers.remove(header::TRANSFER_ENCODING);
+            Body::empty()
+        } else {
+            body?
+        };
+
+        if cross_origin && self.policy.strip_sensitive_headers {
+            for name in &SENSITIVE_HEADERS {
+                headers.remove(name);
+            }
+        }
+
+        let next_uri = resp_uri(next);
+        let mut req = Request::new(body);
+        *req.uri_mut() = if uri.scheme().is_some() {
+            // the request was built with a full uri
+            next_uri
+        } else {
+            next_uri
+                .path_and_query()
+                .map(PathAndQuery::to_owned)
+                .unwrap_or_else(|| PathAndQuery::from_static("/"))
+                .into()
+        };
+        *req.method_mut() = method;
+        *req.version_mut() = version;
+        *req.headers_mut() = headers;
+        *req.extensions_mut() = extensions;
+        tracing::trace!("[Volo-HTTP] redirecting to `{next}` with status {status}");
+        Some(req)
+    }
+}
+
+pub(super) fn current_url(target: &Target, uri: &Uri) -> Option<Url> {
+    if uri.scheme().is_some() {
+        return Url::parse(&uri.to_string()).ok();
+    }
+    let Target::Remote(rt) = target else {
+        return None;
+    };
+    let path = uri
+        .path_and_query()
+        .map(PathAndQuery::as_str)
+        .unwrap_or("/");
+    Url::parse(&format!("{rt}{path}")).ok()
+}
+
+fn next_url(url: &Url, status: StatusCode, headers: &HeaderMap) -> Option<Url> {
+    if !matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    ) {
+        return None;
+    }
+    let location = headers.get(header::LOCATION)?.to_str().ok()?;
+    let next = url.join(location).ok()?;
+    if !matches!(next.scheme(), "http" | "https") {
+        return None;
+    }
+    Some(next)
+}
+
+fn resp_uri(url: &Url) -> Uri {
+    // `Url` is always a valid `Uri` except the fragment
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.as_str().parse().expect("url should be a valid uri")
+}
+
+#[cfg(test)]
+mod redirect_tests {
+    use std::sync::{Arc, Mutex};
+
+    use http::{
+        Method, StatusCode,
+        header::{self, HeaderValue},
+    };
+    use motore::service::service_fn;
+
+    use super::{FinalUri, Policy};
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        context::ClientContext,
+        error::{ClientError, client::TooManyRedirects},
+        request::Request,
+        response::Response,
+    };
+
+    type Record = Arc<Mutex<Vec<(String, Method, Option<HeaderValue>, String)>>>;
+
+    fn redirect(status: StatusCode, location: &'static str) -> Response {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = status;
+        resp.headers_mut()
+            .insert(header::LOCATION, HeaderValue::from_static(location));
+        resp
+    }
+
+    fn mock(record: Record) -> MockTransport {
+        MockTransport::service(service_fn(move |cx: &mut ClientContext, req: Request| {
+            let record = record.clone();
+            let target = cx.target().to_string();
+            async move {
+                let (parts, body) = req.into_parts();
+                let path = parts.uri.path().to_owned();
+                let body = body.into_string().await.unwrap();
+                record.lock().unwrap().push((
+                    format!("{target}{path}"),
+                    parts.method,
+                    parts.headers.get(header::AUTHORIZATION).cloned(),
+                    body,
+                ));
+                let resp = match path.as_str() {
+                    "/see-other" => redirect(StatusCode::SEE_OTHER, "/get"),
+                    "/temporary" => redirect(StatusCode::TEMPORARY_REDIRECT, "post"),
+                    "/cross" => redirect(StatusCode::FOUND, "http://other.example.com:8080/get"),
+                    "/loop" => redirect(StatusCode::MOVED_PERMANENTLY, "/loop"),
+                    _ => Response::new(Body::from("ok")),
+                };
+                Ok::<_, ClientError>(resp)
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn follow_redirect() {
+        let record = Record::default();
+        let client = ClientBuilder::new()
+            .redirect(Policy::limited(3))
+            .mock(mock(record.clone()))
+            .unwrap();
+
+        let resp = client
+            .post("http://example.com/see-other")
+            .body(Body::from("body"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.extensions().get::<FinalUri>().unwrap().0,
+            "http://example.com/get"
+        );
+        {
+            let record = std::mem::take(&mut *record.lock().unwrap());
+            assert_eq!(record.len(), 2);
+            assert_eq!(record[1].0, "http://example.com/get");
+            // 303 rewrites method to `GET` without body
+            assert_eq!(record[1].1, Method::GET);
+            assert_eq!(record[1].3, "");
+        }
+
+        client
+            .post("http://example.com/temporary")
+            .body(Body::from("body"))
+            .send()
+            .await
+            .unwrap();
+        {
+            let record = std::mem::take(&mut *record.lock().unwrap());
+            assert_eq!(record.len(), 2);
+            assert_eq!(record[1].0, "http://example.com/post");
+            // 307 keeps the method and body
+            assert_eq!(record[1].1, Method::POST);
+            assert_eq!(record[1].3, "body");
+        }
+
+        client
+            .get("http://example.com/cross")
+            .header(header::AUTHORIZATION, "Bearer token")
+            .send()
+            .await
+            .unwrap();
+        {
+            let record = std::mem::take(&mut *record.lock().unwrap());
+            assert_eq!(record.len(), 2);
+            assert!(record[0].2.is_some());
+            assert_eq!(record[1].0, "http://other.example.com:8080/get");
+            // sensitive headers are removed for another origin
+            assert!(record[1].2.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn redirect_policy() {
+        let record = Record::default();
+        let client = ClientBuilder::new()
+            .redirect(Policy::limited(3))
+            .mock(mock(record.clone()))
+            .unwrap();
+        let err = client
+            .get("http://example.com/loop")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(
+            std::error::Error::source(&err)
+                .unwrap()
+                .is::<TooManyRedirects>()
+        );
+        assert_eq!(record.lock().unwrap().len(), 4);
+
+        let client = ClientBuilder::new()
+            .redirect(Policy::default().allow_cross_origin(false))
+            .mock(mock(record.clone()))
+            .unwrap();
+        let resp = client.get("http://example.com/cross").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FOUND);
+
+        let client = ClientBuilder::new()
+            .redirect(Policy::none())
+            .mock(mock(record.clone()))
+            .unwrap();
+        let resp = client.get("http://example.com/loop").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+    }
+}
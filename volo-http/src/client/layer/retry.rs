@@ -0,0 +1,965 @@
+//! [`Layer`] for retrying failed requests.
+//!
+//! The [`Retry`] layer sends the request again when it fails with a connect error or the response
+//! has a retryable status code (`429 Too Many Requests`, `502 Bad Gateway`,
+//! `503 Service Unavailable` and `504 Gateway Timeout` by default), e.g.,
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_http::client::{
+//!     Client,
+//!     layer::retry::{Retry, RetryBudget},
+//! };
+//!
+//! let client = Client::builder()
+//!     .layer_outer(
+//!         Retry::new(3)
+//!             .backoff(Duration::from_millis(50), Duration::from_secs(2))
+//!             .budget(RetryBudget::new(0.1, 10)),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Since a connect error means the request has not been sent, it is retried for all methods. But
+//! the retryable status codes and the expired request timeout, i.e.,
+//! [`ClientBuilder::set_request_timeout`], only make the request be retried if its method is
+//! idempotent (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and `TRACE`), which can be changed by
+//! [`Retry::retry_non_idempotent`]. A retry is skipped if it cannot start before the total
+//! timeout, i.e., [`ClientBuilder::set_total_timeout`], expires.
+//!
+//! The body of request can only be sent again if it is a complete body (e.g., created from
+//! [`Bytes`], [`String`] or [`Vec<u8>`]), or the request will be sent only once. Streaming bodies
+//! can be sent again by [`Retry::buffer_body`], which wraps them into [`ReplayBody`] for keeping
+//! the sent data up to a limit. If the body exceeds the limit, the request is not retried, and
+//! [`RetryDisabled`] is inserted into extensions of the response for the reason.
+//!
+//! [`Bytes`]: bytes::Bytes
+//! [`ClientBuilder::set_request_timeout`]: crate::client::ClientBuilder::set_request_timeout
+//! [`ClientBuilder::set_total_timeout`]: crate::client::ClientBuilder::set_total_timeout
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use faststr::FastStr;
+use http::{
+    Extensions, HeaderMap, Method, StatusCode, Uri, Version,
+    header::{self, HeaderValue},
+};
+use http_body::{Frame, SizeHint};
+use motore::{layer::Layer, service::Service};
+use parking_lot::Mutex;
+use volo::{
+    context::Context as _,
+    error::{ErrorCategory, RpcError},
+    event::{self, RetryPerformed},
+    loadbalance::error::Retryable,
+    timeout::{CallDeadline, TimeoutKind},
+};
+
+use crate::{
+    body::Body,
+    context::ClientContext,
+    error::{BoxError, ClientError},
+    request::Request,
+    response::Response,
+};
+
+const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(10);
+const DEFAULT_RETRY_STATUSES: [StatusCode; 4] = [
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// [`Layer`] for retrying failed requests.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct Retry {
+    max_retries: usize,
+    connect_errors: bool,
+    statuses: Arc<[StatusCode]>,
+    non_idempotent: bool,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_after: bool,
+    budget: Option<Arc<RetryBudget>>,
+    buffer_limit: Option<usize>,
+}
+
+impl Retry {
+    /// Create a [`Retry`] layer that retries a request at most `max_retries` times.
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            connect_errors: true,
+            statuses: Arc::new(DEFAULT_RETRY_STATUSES),
+            non_idempotent: false,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: true,
+            retry_after: true,
+            budget: None,
+            buffer_limit: None,
+        }
+    }
+
+    /// Set whether to retry the request when it fails with a connect error.
+    ///
+    /// Default is `true`.
+    pub fn retry_connect_errors(mut self, retry: bool) -> Self {
+        self.connect_errors = retry;
+        self
+    }
+
+    /// Set the status codes of responses for retrying.
+    ///
+    /// Default is `429`, `502`, `503` and `504`.
+    pub fn retry_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Set whether to retry requests with non-idempotent methods (e.g., `POST`) on retryable
+    /// status codes.
+    ///
+    /// Default is `false`.
+    pub fn retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.non_idempotent = retry;
+        self
+    }
+
+    /// Set the exponential backoff between retries.
+    ///
+    /// The `n`-th retry waits for `base * 2^(n-1)`, but at most `max`.
+    ///
+    /// Default is `100ms` for `base` and `10s` for `max`.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_delay = base;
+        self.max_delay = max;
+        self
+    }
+
+    /// Set whether to randomize the backoff between zero and the computed delay, which avoids
+    /// clients retrying at the same time.
+    ///
+    /// Default is `true`.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set whether to honor the `Retry-After` header of the response.
+    ///
+    /// If it is enabled, the delay from `Retry-After` is used instead of the backoff, and the
+    /// response is returned directly if the delay is larger than the maximum backoff.
+    ///
+    /// Default is `true`.
+    pub fn honor_retry_after(mut self, honor: bool) -> Self {
+        self.retry_after = honor;
+        self
+    }
+
+    /// Set a [`RetryBudget`] for limiting retries.
+    ///
+    /// Note that the budget is shared by clones of the layer, which means that all clients built
+    /// with the same [`Retry`] share the budget.
+    ///
+    /// Default is no budget.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(Arc::new(budget));
+        self
+    }
+
+    /// Set the limit in bytes for buffering streaming bodies, so that requests with them can be
+    /// retried.
+    ///
+    /// The body is wrapped into [`ReplayBody`], which keeps the data sent until the limit is
+    /// exceeded. After that, the request will not be retried, and [`RetryDisabled::BodyTooLarge`]
+    /// is inserted into extensions of the response if it should be retried.
+    ///
+    /// Default is no buffering, which means requests with streaming bodies are sent only once.
+    pub fn buffer_body(mut self, limit: usize) -> Self {
+        self.buffer_limit = Some(limit);
+        self
+    }
+
+    fn delay(&self, retries: usize) -> Duration {
+        let shift = u32::try_from(retries.saturating_sub(1))
+            .unwrap_or(u32::MAX)
+            .min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << shift)
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(random_unit())
+        } else {
+            delay
+        }
+    }
+}
+
+impl<S> Layer<S> for Retry {
+    type Service = RetryService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RetryService {
+            inner,
+            policy: self,
+        }
+    }
+}
+
+/// A budget for limiting retries to prevent retry storms.
+///
+/// Each request deposits `ratio` token into the budget and each retry withdraws one token, so that
+/// retries are at most about `ratio` of requests. The budget is full of `max_tokens` tokens at the
+/// beginning and it will never exceed that.
+#[derive(Debug)]
+pub struct RetryBudget {
+    // tokens are scaled by `SCALE` for fractional deposits
+    balance: AtomicU64,
+    deposit: u64,
+    max_balance: u64,
+}
+
+impl RetryBudget {
+    const SCALE: u64 = 1000;
+
+    /// Create a [`RetryBudget`] that allows retries of `ratio` of requests with a burst of
+    /// `max_tokens` retries.
+    pub fn new(ratio: f32, max_tokens: usize) -> Self {
+        let max_balance = (max_tokens as u64).saturating_mul(Self::SCALE);
+        Self {
+            balance: AtomicU64::new(max_balance),
+            deposit: (ratio.max(0.0) * Self::SCALE as f32) as u64,
+            max_balance,
+        }
+    }
+
+    fn deposit(&self) {
+        let _ = self
+            .balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |balance| {
+                Some(balance.saturating_add(self.deposit).min(self.max_balance))
+            });
+    }
+
+    fn withdraw(&self) -> bool {
+        self.balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |balance| {
+                balance.checked_sub(Self::SCALE)
+            })
+            .is_ok()
+    }
+}
+
+/// [`Service`] generated by [`Retry`].
+///
+/// See [`Retry`] and the [module documentation](self) for more details.
+pub struct RetryService<S> {
+    inner: S,
+    policy: Retry,
+}
+
+/// Why a failed request is not retried because of its body.
+///
+/// It is inserted into extensions of the response and the [`ClientContext`] by [`RetryService`]
+/// when the request should be retried but its [`ReplayBody`] cannot be sent again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetryDisabled {
+    /// The body exceeds the limit of [`Retry::buffer_body`].
+    BodyTooLarge {
+        /// The limit in bytes.
+        limit: usize,
+    },
+    /// Polling the body fails, so it cannot be sent completely.
+    BodyFailed,
+}
+
+/// A body wrapper that keeps the data polled from the inner body up to a limit, so that it can be
+/// sent again by [`ReplayBody::try_clone`].
+///
+/// Clones share the inner body, a clone yields the kept data first and then polls the inner body
+/// for the rest, which is also kept for later clones. Once the data exceeds the limit, the kept
+/// data is dropped and the body cannot be cloned any more, but the one polling the inner body can
+/// still be sent completely.
+pub struct ReplayBody {
+    shared: Arc<Mutex<ReplayState>>,
+    // count of data frames yielded by this body
+    pos: usize,
+    trailers_sent: bool,
+    size_hint: SizeHint,
+}
+
+struct ReplayState {
+    inner: Body,
+    limit: usize,
+    // data frames polled from the inner body, it is cleared after overflowed
+    frames: Vec<Bytes>,
+    polled: usize,
+    buffered: usize,
+    trailers: Option<HeaderMap>,
+    overflowed: bool,
+    failed: bool,
+    ended: bool,
+}
+
+impl ReplayBody {
+    /// Wrap the body and keep its data up to `limit` bytes.
+    pub fn new(body: Body, limit: usize) -> Self {
+        let size_hint = http_body::Body::size_hint(&body);
+        Self {
+            shared: Arc::new(Mutex::new(ReplayState {
+                inner: body,
+                limit,
+                frames: Vec::new(),
+                polled: 0,
+                buffered: 0,
+                trailers: None,
+                overflowed: false,
+                failed: false,
+                ended: false,
+            })),
+            pos: 0,
+            trailers_sent: false,
+            size_hint,
+        }
+    }
+
+    /// Get the reason if the body cannot be sent again.
+    pub fn disabled(&self) -> Option<RetryDisabled> {
+        let shared = self.shared.lock();
+        if shared.overflowed {
+            Some(RetryDisabled::BodyTooLarge {
+                limit: shared.limit,
+            })
+        } else if shared.failed {
+            Some(RetryDisabled::BodyFailed)
+        } else {
+            None
+        }
+    }
+
+    /// Create a body for sending the data from the beginning, or returns the reason if it cannot
+    /// be sent again.
+    pub fn try_clone(&self) -> Result<Self, RetryDisabled> {
+        match self.disabled() {
+            Some(reason) => Err(reason),
+            None => Ok(Self {
+                shared: self.shared.clone(),
+                pos: 0,
+                trailers_sent: false,
+                size_hint: self.size_hint.clone(),
+            }),
+        }
+    }
+}
+
+impl http_body::Body for ReplayBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let mut shared = this.shared.lock();
+        loop {
+            if this.pos < shared.polled {
+                if shared.overflowed {
+                    return Poll::Ready(Some(Err("replay body is polled after exceeding the \
+                                                 limit"
+                        .into())));
+                }
+                let data = shared.frames[this.pos].clone();
+                this.pos += 1;
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+            if shared.failed {
+                return Poll::Ready(Some(Err("replay body has failed".into())));
+            }
+            if shared.ended {
+                if this.trailers_sent {
+                    return Poll::Ready(None);
+                }
+                this.trailers_sent = true;
+                return Poll::Ready(shared.trailers.clone().map(|t| Ok(Frame::trailers(t))));
+            }
+
+            let frame = match ready!(Pin::new(&mut shared.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => {
+                    shared.failed = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                None => {
+                    shared.ended = true;
+                    continue;
+                }
+            };
+            let frame = match frame.into_data() {
+                Ok(data) => {
+                    let shared = &mut *shared;
+                    shared.polled += 1;
+                    this.pos += 1;
+                    if !shared.overflowed {
+                        shared.buffered += data.len();
+                        if shared.buffered > shared.limit {
+                            shared.overflowed = true;
+                            shared.frames = Vec::new();
+                        } else {
+                            shared.frames.push(data.clone());
+                        }
+                    }
+                    return Poll::Ready(Some(Ok(Frame::data(data))));
+                }
+                Err(frame) => frame,
+            };
+            if let Ok(trailers) = frame.into_trailers() {
+                shared
+                    .trailers
+                    .get_or_insert_with(HeaderMap::new)
+                    .extend(trailers.clone());
+                this.trailers_sent = true;
+                return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+            }
+            // unknown frames are dropped
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let shared = self.shared.lock();
+        shared.ended
+            && self.pos >= shared.polled
+            && (self.trailers_sent || shared.trailers.is_none())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.size_hint.clone()
+    }
+}
+
+// The request without its body, saved for sending it again.
+struct SavedRequest {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+    extensions: Extensions,
+    body: SavedBody,
+}
+
+enum SavedBody {
+    Full(Body),
+    Replay(ReplayBody),
+}
+
+impl SavedRequest {
+    // Save the request if possible, and returns it with the body wrapped if needed.
+    fn new(req: Request, buffer_limit: Option<usize>) -> (Option<Self>, Request) {
+        let (parts, body) = req.into_parts();
+        let (saved, body) = match (body.try_clone(), buffer_limit) {
+            (Some(saved), _) => (SavedBody::Full(saved), body),
+            (None, Some(limit)) => {
+                let replay = ReplayBody::new(body, limit);
+                let saved = SavedBody::Replay(replay.try_clone().expect("body is not polled"));
+                (saved, Body::from_body(replay))
+            }
+            (None, None) => return (None, Request::from_parts(parts, body)),
+        };
+        let saved = Self {
+            method: parts.method.clone(),
+            uri: parts.uri.clone(),
+            version: parts.version,
+            headers: parts.headers.clone(),
+            extensions: parts.extensions.clone(),
+            body: saved,
+        };
+        (Some(saved), Request::from_parts(parts, body))
+    }
+
+    fn to_request(&self) -> Result<Request, RetryDisabled> {
+        let body = match &self.body {
+            SavedBody::Full(body) => body.try_clone().expect("full body can be cloned"),
+            SavedBody::Replay(body) => Body::from_body(body.try_clone()?),
+        };
+        let mut req = Request::new(body);
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+        *req.extensions_mut() = self.extensions.clone();
+        Ok(req)
+    }
+}
+
+impl<S, B> Service<ClientContext, Request> for RetryService<S>
+where
+    S: Service<ClientContext, Request, Response = Response<B>, Error = ClientError> + Send + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ClientContext,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(budget) = &self.policy.budget {
+            budget.deposit();
+        }
+        if self.policy.max_retries == 0 {
+            return self.inner.call(cx, req).await;
+        }
+        let (saved, mut req) = SavedRequest::new(req, self.policy.buffer_limit);
+        let Some(saved) = saved else {
+            return self.inner.call(cx, req).await;
+        };
+        let idempotent = is_idempotent(&saved.method);
+
+        let mut retries = 0;
+        loop {
+            let res = self.inner.call(cx, req).await;
+            let retry_after = match &res {
+                Ok(resp)
+                    if (idempotent || self.policy.non_idempotent)
+                        && self.policy.statuses.contains(&resp.status()) =>
+                {
+                    if self.policy.retry_after {
+                        parse_retry_after(resp.headers())
+                    } else {
+                        None
+                    }
+                }
+                Err(err) if self.policy.connect_errors && is_connect_error(err) => None,
+                // the retryable timeouts except the connect timeout, i.e., the request timeout
+                Err(err)
+                    if (idempotent || self.policy.non_idempotent)
+                        && err.category() == ErrorCategory::Timeout
+                        && err.retryable()
+                        && !is_connect_error(err) =>
+                {
+                    None
+                }
+                _ => return res,
+            };
+
+            if retries >= self.policy.max_retries {
+                return res;
+            }
+            let delay = match retry_after {
+                Some(delay) if delay > self.policy.max_delay => {
+                    tracing::debug!(
+                        "[Volo-HTTP] retry is skipped since `Retry-After` {delay:?} is too long"
+                    );
+                    return res;
+                }
+                Some(delay) => delay,
+                None => self.policy.delay(retries + 1),
+            };
+            if cx
+                .extensions()
+                .get::<CallDeadline>()
+                .is_some_and(|deadline| deadline.remaining() <= delay)
+            {
+                tracing::debug!("[Volo-HTTP] retry is skipped since the total timeout will expire");
+                return res;
+            }
+            if let Some(budget) = &self.policy.budget {
+                if !budget.withdraw() {
+                    tracing::debug!("[Volo-HTTP] retry is skipped since the budget is exhausted");
+                    return res;
+                }
+            }
+            let next = match saved.to_request() {
+                Ok(next) => next,
+                Err(reason) => {
+                    tracing::debug!("[Volo-HTTP] retry is skipped since the body is {reason:?}");
+                    cx.extensions_mut().insert(reason);
+                    let mut res = res;
+                    if let Ok(resp) = &mut res {
+                        resp.extensions_mut().insert(reason);
+                    }
+                    return res;
+                }
+            };
+            let reason = event::enabled::<RetryPerformed>().then(|| match &res {
+                Ok(resp) => resp.status().to_string(),
+                Err(err) => err.to_string(),
+            });
+            drop(res);
+
+            retries += 1;
+            tracing::trace!("[Volo-HTTP] retrying request ({retries}) after {delay:?}");
+            if let Some(reason) = reason {
+                event::emit(|| RetryPerformed {
+                    callee: cx.rpc_info().callee().service_name(),
+                    method: FastStr::new(saved.uri.path()),
+                    attempt: retries + 1,
+                    reason,
+                });
+            }
+            tokio::time::sleep(delay).await;
+            req = next;
+        }
+    }
+}
+
+/// Whether the request has not been sent since failing to connect, including the connect timeout.
+fn is_connect_error(err: &ClientError) -> bool {
+    match err.category() {
+        ErrorCategory::Connect => true,
+        ErrorCategory::Timeout => err
+            .timeout_error()
+            .is_some_and(|err| err.kind() == TimeoutKind::Connect),
+        _ => false,
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Parse `Retry-After` with either seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(header::RETRY_AFTER)
+        .map(HeaderValue::to_str)?
+        .ok()?;
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.signed_duration_since(chrono::Utc::now());
+    // a date in the past means retrying immediately
+    Some(delay.to_std().unwrap_or_default())
+}
+
+/// Generate a random number in `[0, 1)`.
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderValue},
+    };
+    use motore::service::service_fn;
+
+    use super::{Retry, RetryBudget, RetryDisabled, parse_retry_after};
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        context::ClientContext,
+        error::{
+            ClientError,
+            client::{ErrorKind, retry},
+        },
+        request::Request,
+        response::Response,
+    };
+
+    // The first `fails` requests fail with `status`, or a connect error if it is `None`.
+    fn mock(count: Arc<AtomicUsize>, fails: usize, status: Option<StatusCode>) -> MockTransport {
+        MockTransport::service(service_fn(move |_: &mut ClientContext, _: Request| {
+            let count = count.clone();
+            async move {
+                if count.fetch_add(1, Ordering::Relaxed) >= fails {
+                    return Ok(Response::new(Body::from("ok")));
+                }
+                match status {
+                    Some(status) => {
+                        let mut resp = Response::new(Body::empty());
+                        *resp.status_mut() = status;
+                        Ok(resp)
+                    }
+                    None => Err(retry()),
+                }
+            }
+        }))
+    }
+
+    fn retry_layer(max_retries: usize) -> Retry {
+        Retry::new(max_retries).backoff(Duration::from_millis(1), Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn retry_on_failure() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3))
+            .mock(mock(
+                count.clone(),
+                2,
+                Some(StatusCode::SERVICE_UNAVAILABLE),
+            ))
+            .unwrap();
+        let resp = client.get("http://example.com/").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+
+        // connect errors are retried even for `POST`
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3))
+            .mock(mock(count.clone(), 1, None))
+            .unwrap();
+        let resp = client
+            .post("http://example.com/")
+            .body(Body::from("body"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // the last error is returned if all retries fail
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(2))
+            .mock(mock(count.clone(), 10, None))
+            .unwrap();
+        let err = client.get("http://example.com/").send().await.unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Connect);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_policy() {
+        // `POST` is not retried on status codes by default
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3))
+            .mock(mock(count.clone(), 1, Some(StatusCode::BAD_GATEWAY)))
+            .unwrap();
+        let resp = client
+            .post("http://example.com/")
+            .body(Body::from("body"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // status codes not configured are not retried
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3).retry_statuses([StatusCode::TOO_MANY_REQUESTS]))
+            .mock(mock(count.clone(), 1, Some(StatusCode::BAD_GATEWAY)))
+            .unwrap();
+        let resp = client.get("http://example.com/").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // streaming bodies cannot be replayed
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3))
+            .mock(mock(count.clone(), 1, None))
+            .unwrap();
+        let stream = futures::stream::iter([Ok::<_, std::io::Error>(bytes::Bytes::from("body"))]);
+        let err = client
+            .put("http://example.com/")
+            .body(Body::from_bytes_stream(stream))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Connect);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    // The first `fails` requests consume the body and fail with `503`, then the body is echoed.
+    fn echo(count: Arc<AtomicUsize>, fails: usize) -> MockTransport {
+        MockTransport::service(service_fn(move |_: &mut ClientContext, req: Request| {
+            let count = count.clone();
+            async move {
+                let body = req.into_body().into_bytes().await.unwrap();
+                if count.fetch_add(1, Ordering::Relaxed) < fails {
+                    let mut resp = Response::new(Body::empty());
+                    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                    return Ok(resp);
+                }
+                Ok(Response::new(Body::from(body)))
+            }
+        }))
+    }
+
+    fn stream_body() -> Body {
+        let chunks =
+            ["hello", ", ", "world"].map(|s| Ok::<_, std::io::Error>(bytes::Bytes::from(s)));
+        Body::from_bytes_stream(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn replay_body() {
+        // the body is sent again after being consumed
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3).buffer_body(1024))
+            .mock(echo(count.clone(), 2))
+            .unwrap();
+        let resp = client
+            .put("http://example.com/")
+            .body(stream_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+        assert!(resp.extensions().get::<RetryDisabled>().is_none());
+        assert_eq!(
+            resp.into_body().into_string().await.unwrap(),
+            "hello, world"
+        );
+
+        // the body is not polled before a connect error
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3).buffer_body(1024))
+            .mock(mock(count.clone(), 1, None))
+            .unwrap();
+        let resp = client
+            .post("http://example.com/")
+            .body(stream_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // the body exceeding the limit is not retried
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3).buffer_body(8))
+            .mock(echo(count.clone(), 2))
+            .unwrap();
+        let resp = client
+            .put("http://example.com/")
+            .body(stream_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            resp.extensions().get::<RetryDisabled>(),
+            Some(&RetryDisabled::BodyTooLarge { limit: 8 })
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_budget() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3).budget(RetryBudget::new(0.0, 2)))
+            .mock(mock(count.clone(), 10, None))
+            .unwrap();
+        client.get("http://example.com/").send().await.unwrap_err();
+        // only 2 retries are allowed by the budget
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+        client.get("http://example.com/").send().await.unwrap_err();
+        assert_eq!(count.load(Ordering::Relaxed), 4);
+
+        let budget = RetryBudget::new(0.5, 1);
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+        budget.deposit();
+        assert!(!budget.withdraw());
+        budget.deposit();
+        assert!(budget.withdraw());
+    }
+
+    #[test]
+    fn retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(120)));
+        headers.insert(
+            header::RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn long_retry_after() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let service = {
+            let count = count.clone();
+            service_fn(move |_: &mut ClientContext, _: Request| {
+                count.fetch_add(1, Ordering::Relaxed);
+                async {
+                    let mut resp = Response::new(Body::empty());
+                    *resp.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    resp.headers_mut()
+                        .insert(header::RETRY_AFTER, HeaderValue::from_static("3600"));
+                    Ok::<_, ClientError>(resp)
+                }
+            })
+        };
+        let client = ClientBuilder::new()
+            .layer_outer(retry_layer(3))
+            .mock(MockTransport::service(service))
+            .unwrap();
+        let resp = client.get("http://example.com/").send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn backoff() {
+        let retry = Retry::new(10)
+            .backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .jitter(false);
+        assert_eq!(retry.delay(1), Duration::from_millis(100));
+        assert_eq!(retry.delay(2), Duration::from_millis(200));
+        assert_eq!(retry.delay(4), Duration::from_millis(800));
+        assert_eq!(retry.delay(5), Duration::from_secs(1));
+        assert_eq!(retry.delay(100), Duration::from_secs(1));
+
+        let retry = retry.jitter(true);
+        for n in 1..10 {
+            assert!(retry.delay(n) <= Duration::from_secs(1));
+        }
+    }
+}
@@ -1,20 +1,25 @@
 use motore::{layer::Layer, service::Service};
-use volo::context::Context;
+use volo::{context::Context, timeout::CallDeadline};
 
 use crate::{
-    context::client::Config,
+    context::{Deadline, client::Config},
     error::ClientError,
     request::{Request, RequestPartsExt},
 };
 
-/// [`Layer`] for applying timeout from [`Config`].
+/// [`Layer`] for applying the total timeout including retries from [`Config`].
+///
+/// The timeout is also limited by the remaining time of [`Deadline`], which is set by the server
+/// when handling a request with deadline.
 ///
 /// This layer will be applied by default when using [`ClientBuilder::build`], without this layer,
-/// timeout from [`Client`] or [`CallOpt`] will not work.
+/// total timeout from [`Client`] or [`CallOpt`] will not work. The timeout of each attempt, i.e.,
+/// [`Config::timeout`], is applied by [`RequestTimeoutLayer`] inside the load balancer.
 ///
 /// [`Client`]: crate::client::Client
 /// [`ClientBuilder::build`]: crate::client::ClientBuilder::build
 /// [`CallOpt`]: crate::client::CallOpt
+/// [`RequestTimeoutLayer`]: volo::timeout::RequestTimeoutLayer
 #[derive(Clone, Debug, Default)]
 pub struct Timeout;
 
@@ -43,24 +48,141 @@ where
     type Error = S::Error;
 
     async fn call(&self, cx: &mut Cx, req: Request<B>) -> Result<Self::Response, Self::Error> {
-        let timeout = cx.rpc_info().config().timeout().cloned();
-
-        if let Some(duration) = timeout {
-            let url = req.url();
-            let fut = self.inner.call(cx, req);
-            let sleep = tokio::time::sleep(duration);
-
-            tokio::select! {
-                res = fut => res,
-                _ = sleep => {
-                    if let Some(url) = url {
-                        tracing::warn!("[Volo-HTTP] request timeout on `{url}`");
-                    }
-                    Err(crate::error::client::timeout().with_endpoint(cx.rpc_info().callee()))
+        let timeout = cx.rpc_info().config().total_timeout().cloned();
+        // the request should not exceed the deadline of the request being handled
+        let timeout = match (timeout, Deadline::current()) {
+            (Some(timeout), Some(deadline)) => Some(timeout.min(deadline.remaining())),
+            (None, Some(deadline)) => Some(deadline.remaining()),
+            (timeout, None) => timeout,
+        };
+        let Some(duration) = timeout else {
+            return self.inner.call(cx, req).await;
+        };
+
+        // limits the request timeout of each attempt and the retries
+        let deadline = CallDeadline::new(duration);
+        cx.extensions_mut().insert(deadline);
+        // the timers of tokio are not available on `wasm32`, where the `fetch` transport aborts
+        // the request by the deadline
+        if cfg!(target_family = "wasm") {
+            let res = self.inner.call(cx, req).await;
+            cx.extensions_mut().remove::<CallDeadline>();
+            return res;
+        }
+        let url = req.url();
+        let res = tokio::time::timeout(duration, self.inner.call(cx, req)).await;
+        cx.extensions_mut().remove::<CallDeadline>();
+
+        match res {
+            Ok(res) => res,
+            Err(_) => {
+                if let Some(url) = url {
+                    tracing::warn!("[Volo-HTTP] request total timeout on `{url}`");
+                }
+                Err(ClientError::from(deadline.error()).with_endpoint(cx.rpc_info().callee()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod timeout_tests {
+    use std::{
+        cell::RefCell,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use metainfo::{METAINFO, MetaInfo};
+    use motore::service::service_fn;
+    use volo::timeout::{RequestTimeoutLayer, TimeoutKind};
+
+    use super::Timeout;
+    use crate::{
+        ClientBuilder,
+        body::Body,
+        client::{Client, layer::Retry, test_helpers::MockTransport},
+        context::{ClientContext, Deadline},
+        error::ClientError,
+        request::Request,
+        response::Response,
+    };
+
+    #[tokio::test]
+    async fn timeout_with_deadline() {
+        let client = ClientBuilder::new()
+            .layer_outer(Timeout)
+            .mock(MockTransport::service(service_fn(
+                |_: &mut ClientContext, _: Request| async {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Ok::<_, ClientError>(Response::new(Body::empty()))
+                },
+            )))
+            .unwrap();
+        assert!(client.get("http://example.com/").send().await.is_ok());
+
+        let mut metainfo = MetaInfo::default();
+        metainfo.insert(Deadline(Instant::now() + Duration::from_millis(10)));
+        let res = METAINFO
+            .scope(RefCell::new(metainfo), async {
+                client.get("http://example.com/").send().await
+            })
+            .await;
+        assert!(res.is_err());
+    }
+
+    // The first `slow` requests take 100ms.
+    fn slow(count: Arc<AtomicUsize>, slow: usize) -> MockTransport {
+        MockTransport::service(service_fn(move |_: &mut ClientContext, _: Request| {
+            let count = count.clone();
+            async move {
+                if count.fetch_add(1, Ordering::Relaxed) < slow {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
                 }
+                Ok::<_, ClientError>(Response::new(Body::empty()))
             }
-        } else {
-            self.inner.call(cx, req).await
+        }))
+    }
+
+    fn make_client(count: Arc<AtomicUsize>, slow_count: usize, total: Option<Duration>) -> Client {
+        let mut builder = ClientBuilder::new();
+        builder.set_request_timeout(Duration::from_millis(30));
+        if let Some(total) = total {
+            builder.set_total_timeout(total);
         }
+        builder
+            .layer_inner(RequestTimeoutLayer)
+            .layer_outer(Timeout)
+            .layer_outer(Retry::new(10).backoff(Duration::from_millis(1), Duration::from_millis(1)))
+            .mock(slow(count, slow_count))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn request_and_total_timeouts() {
+        // each attempt gets its own request timeout
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = make_client(count.clone(), 1, None);
+        assert!(client.get("http://example.com/").send().await.is_ok());
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // `POST` is not retried on request timeout
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = make_client(count.clone(), 1, None);
+        let err = client.post("http://example.com/").send().await.unwrap_err();
+        assert_eq!(err.timeout_error().unwrap().kind(), TimeoutKind::Request);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // the total timeout covers all attempts, and the second one is limited by it
+        let count = Arc::new(AtomicUsize::new(0));
+        let client = make_client(count.clone(), usize::MAX, Some(Duration::from_millis(50)));
+        let err = client.get("http://example.com/").send().await.unwrap_err();
+        let timeout = err.timeout_error().unwrap();
+        assert_eq!(timeout.kind(), TimeoutKind::Total);
+        assert_eq!(timeout.timeout(), Duration::from_millis(50));
+        assert!(count.load(Ordering::Relaxed) < 10);
     }
 }
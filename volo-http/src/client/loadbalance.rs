@@ -15,10 +15,15 @@ use motore::{layer::Layer, service::Service};
 use volo::{
     context::Context,
     discovery::Discover,
-    loadbalance::{LoadBalance, MkLbLayer, random::WeightedRandomBalance},
+    event::{self, DiscoveryDiffApplied},
+    loadbalance::{
+        LoadBalance, MkLbLayer,
+        drain::{DrainPolicy, Drainer},
+        random::WeightedRandomBalance,
+    },
 };
 
-use super::dns::{DiscoverKey, DnsResolver};
+use super::dns::{DiscoverKey, DnsResolver, ResolveOnDial};
 use crate::{
     context::ClientContext,
     error::{
@@ -38,6 +43,7 @@ pub type DefaultLbService<S> =
 pub struct LbConfig<L, D> {
     load_balance: L,
     discover: D,
+    drainer: Drainer,
 }
 
 impl Default for DefaultLb {
@@ -52,6 +58,7 @@ impl<L, D> LbConfig<L, D> {
         LbConfig {
             load_balance,
             discover,
+            drainer: Drainer::default(),
         }
     }
 
@@ -60,6 +67,7 @@ impl<L, D> LbConfig<L, D> {
         LbConfig {
             load_balance,
             discover: self.discover,
+            drainer: self.drainer,
         }
     }
 
@@ -68,30 +76,46 @@ impl<L, D> LbConfig<L, D> {
         LbConfig {
             load_balance: self.load_balance,
             discover,
+            drainer: self.drainer,
         }
     }
+
+    /// Set a [`DrainPolicy`] deciding when connections to instances removed by the [`Discover`]
+    /// are drained
+    ///
+    /// Default is draining immediately.
+    pub fn drain_policy<P: DrainPolicy>(mut self, policy: P) -> Self {
+        self.drainer = Drainer::new(policy);
+        self
+    }
 }
 
 impl<LB, D> MkLbLayer for LbConfig<LB, D> {
     type Layer = LoadBalanceLayer<LB, D>;
 
     fn make(self) -> Self::Layer {
-        LoadBalanceLayer::new(self.load_balance, self.discover)
+        LoadBalanceLayer::new(self.load_balance, self.discover, self.drainer)
+    }
+
+    fn drainer(&self) -> Option<Drainer> {
+        Some(self.drainer.clone())
     }
 }
 
 /// [`Layer`] for load balance generated by [`LbConfig`]
-#[derive(Clone, Default, Copy)]
+#[derive(Clone)]
 pub struct LoadBalanceLayer<LB, D> {
     load_balance: LB,
     discover: D,
+    drainer: Drainer,
 }
 
 impl<LB, D> LoadBalanceLayer<LB, D> {
-    fn new(load_balance: LB, discover: D) -> Self {
+    fn new(load_balance: LB, discover: D, drainer: Drainer) -> Self {
         LoadBalanceLayer {
             load_balance,
             discover,
+            drainer,
         }
     }
 }
@@ -104,7 +128,7 @@ where
     type Service = LoadBalanceService<LB, D, S>;
 
     fn layer(self, inner: S) -> Self::Service {
-        LoadBalanceService::new(self.load_balance, self.discover, inner)
+        LoadBalanceService::new(self.load_balance, self.discover, inner, self.drainer)
     }
 }
 
@@ -121,7 +145,7 @@ where
     LB: LoadBalance<D>,
     D: Discover,
 {
-    fn new(load_balance: LB, discover: D, service: S) -> Self {
+    fn new(load_balance: LB, discover: D, service: S, drainer: Drainer) -> Self {
         let lb = Arc::new(load_balance);
 
         let service = Self {
@@ -137,7 +161,13 @@ where
         tokio::spawn(async move {
             loop {
                 match channel.recv().await {
-                    Ok(recv) => lb.rebalance(recv),
+                    Ok(recv) => {
+                        // stop picking the removed instances before draining their connections
+                        let change = recv.clone();
+                        lb.rebalance(recv);
+                        drainer.on_change(&change);
+                        event::emit(|| DiscoveryDiffApplied::from(&change));
+                    }
                     Err(err) => match err {
                         RecvError::Closed => break,
                         _ => tracing::warn!("[Volo-HTTP] discovering subscription error: {err}"),
@@ -168,6 +198,10 @@ where
         let callee = cx.rpc_info().callee();
 
         let mut picker = match &callee.address {
+            // the domain name is resolved when dialing
+            None if callee.contains::<ResolveOnDial>() => {
+                return self.service.call(cx, req).await;
+            }
             None => self
                 .load_balance
                 .get_picker(callee, &self.discover)
@@ -21,23 +21,29 @@ use motore::{
     service::{BoxService, Service},
 };
 use paste::paste;
+#[cfg(not(target_family = "wasm"))]
+use volo::net::dial::{DefaultMakeTransport, MakeTransport};
 use volo::{
     client::{MkClient, OneShotService},
     context::Context,
     loadbalance::MkLbLayer,
-    net::dial::{DefaultMakeTransport, MakeTransport},
+    timeout::{RequestTimeoutLayer, RequestTimeoutService},
+    util::timing,
 };
 
+#[cfg(target_family = "wasm")]
+use self::transport::fetch::{ClientConfig, ClientTransport, ClientTransportConfig};
+#[cfg(not(target_family = "wasm"))]
+use self::transport::{
+    pool,
+    protocol::{ClientConfig, ClientTransport, ClientTransportConfig, PoolStats, PoolStatsSource},
+};
 use self::{
     layer::{
         Timeout,
         header::{Host, UserAgent},
     },
     loadbalance::{DefaultLb, LbConfig},
-    transport::{
-        pool,
-        protocol::{ClientConfig, ClientTransport, ClientTransportConfig},
-    },
 };
 use crate::{
     body::Body,
@@ -58,6 +64,8 @@ pub mod cookie;
 pub mod dns;
 pub mod layer;
 pub mod loadbalance;
+#[cfg(feature = "multipart")]
+pub mod multipart;
 mod request_builder;
 pub mod sse;
 pub mod target;
@@ -65,9 +73,15 @@ pub mod target;
 pub mod test_helpers;
 pub mod transport;
 mod utils;
+#[cfg(feature = "ws")]
+pub mod ws;
 
+#[cfg(not(target_family = "wasm"))]
+pub use self::transport::protocol;
 pub use self::{
-    callopt::CallOpt, request_builder::RequestBuilder, target::Target, transport::protocol,
+    callopt::CallOpt,
+    request_builder::{RequestBuilder, UploadProgress},
+    target::Target,
 };
 
 #[doc(hidden)]
@@ -79,9 +93,12 @@ pub mod prelude {
 pub struct ClientBuilder<IL = Identity, OL = Identity, C = DefaultMkClient, LB = DefaultLb> {
     http_config: ClientConfig,
     client_config: ClientTransportConfig,
+    #[cfg(not(target_family = "wasm"))]
     pool_config: pool::Config,
+    #[cfg(not(target_family = "wasm"))]
     connector: DefaultMakeTransport,
     timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
     user_agent: Option<HeaderValue>,
     host_mode: Host,
     headers: HeaderMap,
@@ -89,6 +106,8 @@ pub struct ClientBuilder<IL = Identity, OL = Identity, C = DefaultMkClient, LB =
     outer_layer: OL,
     mk_client: C,
     mk_lb: LB,
+    // whether domain names are resolved when dialing instead of by the default discover
+    resolve_on_dial: bool,
     status: Result<()>,
     #[cfg(feature = "__tls")]
     tls_config: Option<volo::net::tls::TlsConnector>,
@@ -100,9 +119,12 @@ impl ClientBuilder<Identity, Identity, DefaultMkClient, DefaultLb> {
         Self {
             http_config: Default::default(),
             client_config: Default::default(),
+            #[cfg(not(target_family = "wasm"))]
             pool_config: pool::Config::default(),
+            #[cfg(not(target_family = "wasm"))]
             connector: Default::default(),
             timeout: None,
+            total_timeout: None,
             user_agent: None,
             host_mode: Host::Auto,
             headers: Default::default(),
@@ -110,6 +132,7 @@ impl ClientBuilder<Identity, Identity, DefaultMkClient, DefaultLb> {
             outer_layer: Identity::new(),
             mk_client: DefaultMkClient,
             mk_lb: Default::default(),
+            resolve_on_dial: true,
             status: Ok(()),
             #[cfg(feature = "__tls")]
             tls_config: None,
@@ -132,9 +155,12 @@ impl<IL, OL, C, LB, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -142,6 +168,7 @@ impl<IL, OL, C, LB, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>> {
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb.load_balance(load_balance),
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -149,13 +176,22 @@ impl<IL, OL, C, LB, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>> {
     }
 
     /// Set service discover for the client.
+    ///
+    /// By default, domain names are resolved to both IPv4 and IPv6 addresses when dialing, and
+    /// dialed by the Happy Eyeballs algorithm, see [`Dialer`]. After setting the discover, they
+    /// are resolved by the discover and balanced by the load balancer.
+    ///
+    /// [`Dialer`]: volo::net::dial::Dialer
     pub fn discover<NDISC>(self, discover: NDISC) -> ClientBuilder<IL, OL, C, LbConfig<LB, NDISC>> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -163,11 +199,26 @@ impl<IL, OL, C, LB, DISC> ClientBuilder<IL, OL, C, LbConfig<LB, DISC>> {
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb.discover(discover),
+            resolve_on_dial: false,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
     }
+
+    /// Set the [`DrainPolicy`] deciding when pooled connections to instances removed by the
+    /// discover are drained.
+    ///
+    /// Default is draining immediately.
+    ///
+    /// [`DrainPolicy`]: volo::loadbalance::drain::DrainPolicy
+    pub fn drain_policy<P>(mut self, policy: P) -> Self
+    where
+        P: volo::loadbalance::drain::DrainPolicy,
+    {
+        self.mk_lb = self.mk_lb.drain_policy(policy);
+        self
+    }
 }
 
 impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
@@ -177,9 +228,12 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -187,6 +241,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: self.outer_layer,
             mk_client: new_mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -210,9 +265,12 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -220,6 +278,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -246,9 +305,12 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -256,6 +318,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -279,9 +342,12 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -289,6 +355,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: Stack::new(layer, self.outer_layer),
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -315,9 +382,12 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -325,6 +395,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: Stack::new(self.outer_layer, layer),
             mk_client: self.mk_client,
             mk_lb: self.mk_lb,
+            resolve_on_dial: self.resolve_on_dial,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
@@ -332,13 +403,19 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     }
 
     /// Set a new load balance for the client.
+    ///
+    /// Domain names are resolved by the discover of it instead of when dialing, see
+    /// [`ClientBuilder::discover`].
     pub fn mk_load_balance<NLB>(self, mk_load_balance: NLB) -> ClientBuilder<IL, OL, C, NLB> {
         ClientBuilder {
             http_config: self.http_config,
             client_config: self.client_config,
+            #[cfg(not(target_family = "wasm"))]
             pool_config: self.pool_config,
+            #[cfg(not(target_family = "wasm"))]
             connector: self.connector,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             user_agent: self.user_agent,
             host_mode: self.host_mode,
             headers: self.headers,
@@ -346,12 +423,30 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             outer_layer: self.outer_layer,
             mk_client: self.mk_client,
             mk_lb: mk_load_balance,
+            resolve_on_dial: false,
             status: self.status,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
     }
 
+    /// Follow redirections automatically with the given [`Policy`].
+    ///
+    /// The redirect layer is added by [`ClientBuilder::layer_outer_front`], so it is still inside
+    /// the default layers inserted by [`ClientBuilder::build`]. Outer layers added by
+    /// [`ClientBuilder::layer_outer_front`] after this method only see the original request, and
+    /// other layers will be called for each hop.
+    ///
+    /// See [`redirect`](self::layer::redirect) for more details.
+    ///
+    /// [`Policy`]: self::layer::redirect::Policy
+    pub fn redirect(
+        self,
+        policy: self::layer::redirect::Policy,
+    ) -> ClientBuilder<IL, Stack<OL, self::layer::redirect::Policy>, C, LB> {
+        self.layer_outer_front(policy)
+    }
+
     /// Insert a header to the request.
     pub fn header<K, V>(&mut self, key: K, value: V) -> &mut Self
     where
@@ -442,6 +537,33 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         &mut self.http_config.h2
     }
 
+    /// Get configuration of http3 part.
+    ///
+    /// HTTP/3 is experimental, see [`http3`](self::transport::http3) for more details.
+    #[cfg(feature = "http3")]
+    pub fn http3_config(&mut self) -> &mut self::transport::http3::Config {
+        &mut self.http_config.h3
+    }
+
+    /// Send all HTTPS requests by HTTP/3 without waiting for the server to advertise it by the
+    /// `Alt-Svc` header.
+    ///
+    /// HTTP/3 is experimental, see [`http3`](self::transport::http3) for more details.
+    #[cfg(feature = "http3")]
+    pub fn http3_prior_knowledge(&mut self) -> &mut Self {
+        self.http_config.h3.set_prior_knowledge(true);
+        self
+    }
+
+    /// Get a mutable reference of the [`fetch::Config`] for sending requests by the `fetch` of
+    /// browsers.
+    ///
+    /// [`fetch::Config`]: self::transport::fetch::Config
+    #[cfg(target_family = "wasm")]
+    pub fn fetch_config(&mut self) -> &mut self::transport::fetch::Config {
+        &mut self.http_config.fetch
+    }
+
     /// This is unstable now and may be changed in the future.
     #[doc(hidden)]
     pub fn stat_enable(&mut self, enable: bool) -> &mut Self {
@@ -459,11 +581,46 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         self
     }
 
+    /// Set a SOCKS5 proxy for the client.
+    ///
+    /// All connections will be established through the proxy except hosts in its no proxy list,
+    /// and it works for both HTTP and HTTPS. The proxy from environment variable `ALL_PROXY` can
+    /// be created by [`Socks5Proxy::env`].
+    ///
+    /// If the proxy uses remote DNS, e.g., `socks5h://`, domain names of targets are sent to and
+    /// resolved by the proxy, and the discover of client is not used for them.
+    ///
+    /// Default is no proxy.
+    ///
+    /// [`Socks5Proxy`]: volo::net::proxy::Socks5Proxy
+    /// [`Socks5Proxy::env`]: volo::net::proxy::Socks5Proxy::env
+    #[cfg(not(target_family = "wasm"))]
+    pub fn socks5_proxy(&mut self, proxy: volo::net::proxy::Socks5Proxy) -> &mut Self {
+        self.client_config.socks5_proxy = Some(proxy);
+        self
+    }
+
+    /// Connect to the in-memory listener of a server in the same process, so that tests can send
+    /// requests to a real server without binding ports.
+    ///
+    /// All connections are created by the [`MemoryConnector`] regardless of the address of
+    /// target, and only HTTP without TLS is supported. This overrides the SOCKS5 proxy.
+    ///
+    /// Default is not enabled.
+    ///
+    /// [`MemoryConnector`]: volo::net::memory::MemoryConnector
+    #[cfg(not(target_family = "wasm"))]
+    pub fn memory_connector(&mut self, connector: volo::net::memory::MemoryConnector) -> &mut Self {
+        self.client_config.memory = Some(connector);
+        self
+    }
+
     /// Set idle timeout of connection pool.
     ///
     /// If a connection is idle for more than the timeout, the connection will be dropped.
     ///
     /// Default is 20 seconds.
+    #[cfg(not(target_family = "wasm"))]
     pub fn set_pool_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.pool_config.idle_timeout = timeout;
         self
@@ -475,35 +632,84 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     /// refuse to add new idle connections.
     ///
     /// Default is 10240.
+    #[cfg(not(target_family = "wasm"))]
     pub fn set_max_idle_per_host(&mut self, num: usize) -> &mut Self {
         self.pool_config.max_idle_per_host = num;
         self
     }
 
+    /// Set the maximum number of connections per host, including connecting, in-use and idle
+    /// ones.
+    ///
+    /// If connections to a host reach the limit, new requests will wait for an idle connection or
+    /// a closed one, see [`ClientBuilder::set_pool_wait_timeout`].
+    ///
+    /// Default is unlimited.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn set_max_conns_per_host(&mut self, num: usize) -> &mut Self {
+        self.pool_config.max_conns_per_host = Some(num);
+        self
+    }
+
+    /// Set the maximum lifetime of a connection.
+    ///
+    /// A connection will not be reused if it has been connected for more than the lifetime.
+    ///
+    /// Default is unlimited.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn set_pool_max_lifetime(&mut self, lifetime: Duration) -> &mut Self {
+        self.pool_config.max_lifetime = Some(lifetime);
+        self
+    }
+
+    /// Set the maximum time for waiting an available connection when connections to a host reach
+    /// the limit from [`ClientBuilder::set_max_conns_per_host`].
+    ///
+    /// Default is no timeout, the request will wait until it is timeout.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn set_pool_wait_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.pool_config.wait_timeout = Some(timeout);
+        self
+    }
+
     /// Set the maximum idle time for a connection.
+    #[cfg(not(target_family = "wasm"))]
     pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.connector.set_connect_timeout(Some(timeout));
         self
     }
 
     /// Set the maximum idle time for reading data from the connection.
+    #[cfg(not(target_family = "wasm"))]
     pub fn set_read_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.connector.set_read_timeout(Some(timeout));
         self
     }
 
     /// Set the maximum idle time for writing data to the connection.
+    #[cfg(not(target_family = "wasm"))]
     pub fn set_write_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.connector.set_write_timeout(Some(timeout));
         self
     }
 
     /// Set the maximum idle time for the whole request.
+    ///
+    /// It is applied to each attempt if the request is retried, e.g., by [`Retry`], see
+    /// [`ClientBuilder::set_total_timeout`] for limiting all the attempts.
+    ///
+    /// [`Retry`]: crate::client::layer::Retry
     pub fn set_request_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.timeout = Some(timeout);
         self
     }
 
+    /// Set the maximum time for the whole request including retries.
+    pub fn set_total_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
     /// Set default `User-Agent` in request header.
     ///
     /// If there is `User-Agent` given, a default `User-Agent` will be generated by crate name and
@@ -539,13 +745,13 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
 
     /// Build the HTTP client with default configurations.
     ///
-    /// This method will insert some default layers: [`Timeout`], [`UserAgent`] and [`Host`], and
-    /// the final calling sequence will be as follows:
+    /// This method will insert some default layers: [`Timeout`], [`UserAgent`], [`Host`] and
+    /// [`RequestTimeoutLayer`], and the final calling sequence will be as follows:
     ///
     /// - Outer:
-    ///   - [`Timeout`]: Apply timeout from [`ClientBuilder::set_request_timeout`] or
-    ///     [`CallOpt::with_timeout`]. Note that without this layer, timeout from [`Client`] or
-    ///     [`CallOpt`] will not work.
+    ///   - [`Timeout`]: Apply total timeout from [`ClientBuilder::set_total_timeout`] or
+    ///     [`CallOpt::with_total_timeout`]. Note that without this layer, total timeout from
+    ///     [`Client`] or [`CallOpt`] will not work.
     ///   - [`Host`]: Insert `Host` to request headers. [`Host::Auto`] will be applied by default,
     ///     it will insert a `Host` generated from current [`Target`] if there is no `Host` in
     ///     headers.
@@ -553,10 +759,15 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     ///     from [`ClientBuilder::user_agent`] or generates a value based on the current package
     ///     name and version. If `User-Agent` already exists, this layer does nothing.
     ///   - Other outer layers
-    /// - LoadBalance ([`LbConfig`] with [`DnsResolver`] by default)
+    /// - LoadBalance ([`LbConfig`] with [`DnsResolver`] by default, it is skipped for domain names
+    ///   resolved when dialing, see [`ClientBuilder::discover`])
     /// - Inner layers
+    ///   - [`RequestTimeoutLayer`]: Apply timeout of each attempt from
+    ///     [`ClientBuilder::set_request_timeout`] or [`CallOpt::with_timeout`]. Note that without
+    ///     this layer, timeout from [`Client`] or [`CallOpt`] will not work.
     ///   - Other inner layers
-    /// - Transport through network or unix domain socket.
+    /// - Transport through network or unix domain socket, or by the `fetch` of browsers on
+    ///   `wasm32`.
     ///
     /// [`DnsResolver`]: crate::client::dns::DnsResolver
     pub fn build<InnerReqBody, OuterReqBody, RespBody>(mut self) -> Result<C::Target>
@@ -564,9 +775,9 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         IL: Layer<ClientTransport<InnerReqBody>>,
         IL::Service: Send + Sync + 'static,
         LB: MkLbLayer,
-        LB::Layer: Layer<IL::Service>,
-        <LB::Layer as Layer<IL::Service>>::Service: Send + Sync,
-        OL: Layer<<LB::Layer as Layer<IL::Service>>::Service>,
+        LB::Layer: Layer<RequestTimeoutService<IL::Service>>,
+        <LB::Layer as Layer<RequestTimeoutService<IL::Service>>>::Service: Send + Sync,
+        OL: Layer<<LB::Layer as Layer<RequestTimeoutService<IL::Service>>>::Service>,
         OL::Service: Service<
                 ClientContext,
                 Request<OuterReqBody>,
@@ -576,7 +787,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             + Sync
             + 'static,
         C: MkClient<Client<OuterReqBody, RespBody>>,
-        InnerReqBody: Send,
+        InnerReqBody: Send + 'static,
         OuterReqBody: Send + 'static,
         RespBody: Send,
     {
@@ -586,7 +797,8 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             Some(ua) => UserAgent::new(ua),
             None => UserAgent::auto(),
         };
-        self.layer_outer_front(ua_layer)
+        self.layer_inner_front(RequestTimeoutLayer)
+            .layer_outer_front(ua_layer)
             .layer_outer_front(host_layer)
             .layer_outer_front(timeout_layer)
             .build_without_extra_layers()
@@ -617,19 +829,40 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             + Sync
             + 'static,
         C: MkClient<Client<OuterReqBody, RespBody>>,
-        InnerReqBody: Send,
+        InnerReqBody: Send + 'static,
         OuterReqBody: Send + 'static,
         RespBody: Send,
     {
         self.status?;
 
-        let transport = ClientTransport::new(
-            self.http_config,
-            self.client_config,
-            self.pool_config,
-            #[cfg(feature = "__tls")]
-            self.tls_config,
-        );
+        #[cfg(not(target_family = "wasm"))]
+        let remote_dns_proxy = self
+            .client_config
+            .socks5_proxy
+            .clone()
+            .filter(|proxy| proxy.remote_dns());
+        #[cfg(not(target_family = "wasm"))]
+        let transport = {
+            let mut client_config = self.client_config;
+            client_config.dial = self.connector;
+            ClientTransport::new(
+                self.http_config,
+                client_config,
+                self.pool_config,
+                #[cfg(feature = "__tls")]
+                self.tls_config,
+            )
+        };
+        #[cfg(target_family = "wasm")]
+        let transport = ClientTransport::new(self.http_config, self.client_config);
+        #[cfg(not(target_family = "wasm"))]
+        if let Some(drainer) = self.mk_lb.drainer() {
+            transport.drain_on(&drainer);
+        }
+        #[cfg(not(target_family = "wasm"))]
+        let pool_stats = transport.pool_stats_source();
+        let resolve_on_dial = [http::uri::Scheme::HTTP, http::uri::Scheme::HTTPS]
+            .map(|scheme| self.resolve_on_dial && transport.resolves_on_dial(&scheme));
         let service = self
             .outer_layer
             .layer(self.mk_lb.make().layer(self.inner_layer.layer(transport)));
@@ -638,7 +871,13 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         let client_inner = ClientInner {
             service,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             headers: self.headers,
+            #[cfg(not(target_family = "wasm"))]
+            pool_stats: Some(pool_stats),
+            #[cfg(not(target_family = "wasm"))]
+            remote_dns_proxy,
+            resolve_on_dial,
         };
         let client = Client {
             inner: Arc::new(client_inner),
@@ -664,7 +903,14 @@ where
 struct ClientInner<ReqBody, RespBody> {
     service: BoxService<ClientContext, Request<ReqBody>, Response<RespBody>, ClientError>,
     timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
     headers: HeaderMap,
+    #[cfg(not(target_family = "wasm"))]
+    pool_stats: Option<Box<dyn PoolStatsSource>>,
+    #[cfg(not(target_family = "wasm"))]
+    remote_dns_proxy: Option<volo::net::proxy::Socks5Proxy>,
+    // for `http` and `https`
+    resolve_on_dial: [bool; 2],
 }
 
 /// An Client for sending HTTP requests and handling HTTP responses.
@@ -751,6 +997,19 @@ impl<ReqBody, RespBody> Client<ReqBody, RespBody> {
     method_requests!(trace);
     method_requests!(connect);
     method_requests!(patch);
+
+    /// Get statistics of the connection pool for each host.
+    ///
+    /// It returns nothing if the client is not built with a [`ClientTransport`], e.g., a mocked
+    /// client.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        self.inner
+            .pool_stats
+            .as_ref()
+            .map(|pool| pool.pool_stats())
+            .unwrap_or_default()
+    }
 }
 
 impl<ReqBody, RespBody> OneShotService<ClientContext, Request<ReqBody>>
@@ -779,15 +1038,43 @@ where
             if config.timeout().is_none() {
                 config.set_timeout(self.inner.timeout);
             }
+            if config.total_timeout().is_none() {
+                config.set_total_timeout(self.inner.total_timeout);
+            }
         }
 
         // extend headermap
         req.headers_mut().extend(self.inner.headers.clone());
 
+        // leave the domain name to be resolved when dialing, by the dialer or the proxy
+        {
+            let https = cx.target().scheme() == Some(&http::uri::Scheme::HTTPS);
+            let callee = cx.rpc_info_mut().callee_mut();
+            if callee.address.is_none() && callee.contains::<dns::Port>() {
+                #[cfg(not(target_family = "wasm"))]
+                let by_proxy = self
+                    .inner
+                    .remote_dns_proxy
+                    .as_ref()
+                    .is_some_and(|proxy| !proxy.bypass(callee.service_name_ref()));
+                #[cfg(target_family = "wasm")]
+                let by_proxy = false;
+                if by_proxy || self.inner.resolve_on_dial[usize::from(https)] {
+                    callee.insert(dns::ResolveOnDial);
+                }
+            }
+        }
+
         // apply metainfo if it does not exist
         let has_metainfo = METAINFO.try_with(|_| {}).is_ok();
 
-        let fut = self.inner.service.call(cx, req);
+        let fut = async {
+            cx.stats.record_call_start_at();
+            let (res, timestamps) = timing::record_connect(self.inner.service.call(cx, req)).await;
+            cx.stats.record_connect(timestamps);
+            cx.stats.record_call_end_at();
+            res
+        };
 
         if has_metainfo {
             fut.await
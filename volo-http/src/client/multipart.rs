@@ -0,0 +1,408 @@
+//! Multipart implementation for client.
+//!
+//! This module provides [`Form`] for building `multipart/form-data` request bodies, which can be
+//! set by [`RequestBuilder::multipart`].
+//!
+//! Parts from streams or files are not buffered in memory, they are sent as the body is polled.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use volo_http::client::{
+//!     Client,
+//!     multipart::{Form, Part},
+//! };
+//!
+//! # async fn run() {
+//! let client = Client::builder().build().unwrap();
+//! let form = Form::new()
+//!     .text("name", "volo")
+//!     .part(
+//!         "avatar",
+//!         Part::bytes(vec![0u8; 16])
+//!             .file_name("avatar.png")
+//!             .mime(mime::IMAGE_PNG),
+//!     )
+//!     .file("log", "/var/log/app.log")
+//!     .await
+//!     .unwrap();
+//! let resp = client
+//!     .post("http://127.0.0.1:8080/upload")
+//!     .multipart(form)
+//!     .send()
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! [`RequestBuilder::multipart`]: crate::client::RequestBuilder::multipart
+
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    hash::{BuildHasher, Hasher},
+    io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bytes::Bytes;
+use faststr::FastStr;
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http_body::Frame;
+use mime::Mime;
+
+use crate::{body::Body, error::BoxError};
+
+type PartStream = crate::body::BoxStream<'static, Result<Bytes, BoxError>>;
+
+/// A `multipart/form-data` body.
+pub struct Form {
+    boundary: String,
+    parts: Vec<(FastStr, Part)>,
+}
+
+/// A field of [`Form`].
+pub struct Part {
+    body: PartBody,
+    file_name: Option<FastStr>,
+    mime: Option<Mime>,
+    headers: HeaderMap,
+}
+
+enum PartBody {
+    Bytes(Bytes),
+    Stream {
+        stream: PartStream,
+        length: Option<u64>,
+    },
+}
+
+impl Default for Form {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Form {
+    /// Create an empty [`Form`] with a random boundary.
+    pub fn new() -> Self {
+        Self {
+            boundary: gen_boundary(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Get the boundary of the [`Form`].
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Add a text field.
+    pub fn text<N, V>(self, name: N, value: V) -> Self
+    where
+        N: Into<FastStr>,
+        V: Into<FastStr>,
+    {
+        self.part(name, Part::text(value))
+    }
+
+    /// Add a [`Part`].
+    pub fn part<N>(mut self, name: N, part: Part) -> Self
+    where
+        N: Into<FastStr>,
+    {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    /// Add a file part by [`Part::file`].
+    pub async fn file<N, P>(self, name: N, path: P) -> io::Result<Self>
+    where
+        N: Into<FastStr>,
+        P: AsRef<Path>,
+    {
+        Ok(self.part(name, Part::file(path).await?))
+    }
+
+    /// Get the `Content-Type` of the [`Form`] with its boundary.
+    pub fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={}", self.boundary))
+            .expect("boundary is a valid header value")
+    }
+
+    /// Compute the length of the whole body, [`None`] is returned if any part is a stream with
+    /// unknown length.
+    pub fn content_length(&self) -> Option<u64> {
+        let mut length = 0u64;
+        for (name, part) in &self.parts {
+            length += self.part_header(name, part).len() as u64;
+            length += match &part.body {
+                PartBody::Bytes(bytes) => bytes.len() as u64,
+                PartBody::Stream { length, .. } => (*length)?,
+            };
+            // CRLF after the body
+            length += 2;
+        }
+        Some(length + self.boundary.len() as u64 + 6)
+    }
+
+    fn part_header(&self, name: &str, part: &Part) -> Bytes {
+        let mut header = String::with_capacity(64);
+        let _ = write!(
+            header,
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+        if let Some(file_name) = &part.file_name {
+            let _ = write!(header, "; filename=\"{}\"", escape(file_name));
+        }
+        header.push_str("\r\n");
+        if let Some(mime) = &part.mime {
+            let _ = write!(header, "Content-Type: {mime}\r\n");
+        }
+        let mut header = header.into_bytes();
+        for (key, value) in &part.headers {
+            header.extend_from_slice(key.as_str().as_bytes());
+            header.extend_from_slice(b": ");
+            header.extend_from_slice(value.as_bytes());
+            header.extend_from_slice(b"\r\n");
+        }
+        header.extend_from_slice(b"\r\n");
+        Bytes::from(header)
+    }
+
+    /// Convert the [`Form`] into a streaming [`Body`].
+    pub fn into_body(self) -> Body {
+        let headers = self
+            .parts
+            .iter()
+            .map(|(name, part)| self.part_header(name, part))
+            .collect::<Vec<_>>();
+        let tail = Bytes::from(format!("--{}--\r\n", self.boundary));
+
+        let mut streams = Vec::with_capacity(self.parts.len() * 3 + 1);
+        for (header, (_, part)) in headers.into_iter().zip(self.parts) {
+            streams.push(once(header));
+            streams.push(match part.body {
+                PartBody::Bytes(bytes) => once(bytes),
+                PartBody::Stream { stream, .. } => stream,
+            });
+            streams.push(once(Bytes::from_static(b"\r\n")));
+        }
+        streams.push(once(tail));
+
+        Body::from_stream(
+            stream::iter(streams)
+                .flatten()
+                .try_filter(|bytes| futures::future::ready(!bytes.is_empty()))
+                .map_ok(Frame::data),
+        )
+    }
+}
+
+fn once(bytes: Bytes) -> PartStream {
+    Box::pin(stream::once(futures::future::ready(Ok(bytes))))
+}
+
+impl Part {
+    /// Create a [`Part`] from text.
+    pub fn text<V>(value: V) -> Self
+    where
+        V: Into<FastStr>,
+    {
+        Self::new(PartBody::Bytes(value.into().into_bytes()))
+    }
+
+    /// Create a [`Part`] from bytes.
+    pub fn bytes<B>(bytes: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        Self::new(PartBody::Bytes(bytes.into()))
+    }
+
+    /// Create a [`Part`] from a stream with unknown length.
+    ///
+    /// Note that `Content-Length` of the request cannot be computed with such parts, so the
+    /// request will be sent by chunked encoding in HTTP/1.1.
+    pub fn stream<S, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self::new(PartBody::Stream {
+            stream: Box::pin(stream.map_err(Into::into)),
+            length: None,
+        })
+    }
+
+    /// Create a [`Part`] from a stream with the given length.
+    pub fn stream_with_length<S, E>(stream: S, length: u64) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self::new(PartBody::Stream {
+            stream: Box::pin(stream.map_err(Into::into)),
+            length: Some(length),
+        })
+    }
+
+    /// Create a [`Part`] streaming from a file.
+    ///
+    /// The file name is set to the name of the file and the mime type is guessed from its
+    /// extension, they can be overwritten by [`Part::file_name`] and [`Part::mime`].
+    pub async fn file<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        let mut part = Self::stream_with_length(tokio_util::io::ReaderStream::new(file), length);
+        if let Some(file_name) = path.file_name() {
+            part.file_name = Some(FastStr::new(file_name.to_string_lossy()));
+        }
+        part.mime = Some(mime_guess::from_path(path).first_or_octet_stream());
+        Ok(part)
+    }
+
+    fn new(body: PartBody) -> Self {
+        Self {
+            body,
+            file_name: None,
+            mime: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Set the file name of the [`Part`].
+    pub fn file_name<N>(mut self, file_name: N) -> Self
+    where
+        N: Into<FastStr>,
+    {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Set the mime type of the [`Part`].
+    pub fn mime(mut self, mime: Mime) -> Self {
+        self.mime = Some(mime);
+        self
+    }
+
+    /// Insert a custom header of the [`Part`].
+    ///
+    /// `Content-Disposition` and `Content-Type` are generated by the [`Part`] and should not be
+    /// inserted here.
+    pub fn header(mut self, key: HeaderName, value: HeaderValue) -> Self {
+        debug_assert!(key != header::CONTENT_DISPOSITION && key != header::CONTENT_TYPE);
+        self.headers.insert(key, value);
+        self
+    }
+}
+
+/// Escape names in `Content-Disposition` by percent-encoding `"`, CR and LF, which is the same as
+/// browsers.
+fn escape(name: &str) -> Cow<'_, str> {
+    if !name.contains(['"', '\r', '\n']) {
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(
+        name.replace('"', "%22")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A"),
+    )
+}
+
+fn gen_boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let state = std::collections::hash_map::RandomState::new();
+    let mut boundary = String::with_capacity(52);
+    boundary.push_str("------------------------");
+    for i in 0..2 {
+        let mut hasher = state.build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u64(i);
+        let _ = write!(boundary, "{:016x}", hasher.finish());
+    }
+    boundary
+}
+
+#[cfg(test)]
+mod multipart_tests {
+    use super::*;
+    use crate::body::BodyConversion;
+
+    #[tokio::test]
+    async fn encode_form() {
+        let form = Form::new()
+            .text("name", "volo")
+            .part(
+                "file",
+                Part::bytes(&b"hello"[..])
+                    .file_name("a\"b.txt")
+                    .mime(mime::TEXT_PLAIN),
+            )
+            .part(
+                "stream",
+                Part::stream(stream::iter([
+                    Ok::<_, io::Error>(Bytes::from_static(b"foo")),
+                    Ok(Bytes::from_static(b"bar")),
+                ])),
+            );
+        let boundary = form.boundary().to_owned();
+        assert_eq!(
+            form.content_type(),
+            format!("multipart/form-data; boundary={boundary}").as_str()
+        );
+        assert!(form.content_length().is_none());
+
+        let body = form.into_body().into_string().await.unwrap();
+        assert_eq!(
+            body,
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; \
+                 name=\"name\"\r\n\r\nvolo\r\n--{boundary}\r\nContent-Disposition: form-data; \
+                 name=\"file\"; filename=\"a%22b.txt\"\r\nContent-Type: \
+                 text/plain\r\n\r\nhello\r\n--{boundary}\r\nContent-Disposition: form-data; \
+                 name=\"stream\"\r\n\r\nfoobar\r\n--{boundary}--\r\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn content_length() {
+        let form = Form::new().text("a", "1").part(
+            "b",
+            Part::stream_with_length(
+                stream::iter([Ok::<_, io::Error>(Bytes::from_static(b"22"))]),
+                2,
+            ),
+        );
+        let length = form.content_length().unwrap();
+        let body = form.into_body().into_bytes().await.unwrap();
+        assert_eq!(length, body.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn file_part() {
+        let path = std::env::temp_dir().join("volo-http-multipart-test.txt");
+        tokio::fs::write(&path, b"content").await.unwrap();
+        let form = Form::new().file("file", &path).await.unwrap();
+        let length = form.content_length().unwrap();
+        let body = form.into_body().into_string().await.unwrap();
+        assert_eq!(length, body.len() as u64);
+        assert!(body.contains("filename=\"volo-http-multipart-test.txt\""));
+        assert!(body.contains("Content-Type: text/plain\r\n\r\ncontent\r\n"));
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[test]
+    fn boundary() {
+        assert_ne!(Form::new().boundary(), Form::new().boundary());
+    }
+}
@@ -2,9 +2,11 @@
 //!
 //! See [`RequestBuilder`] for more details.
 
-use std::{borrow::Cow, error::Error};
+use std::{borrow::Cow, error::Error, sync::Arc};
 
+use bytes::Bytes;
 use faststr::FastStr;
+use futures::Stream;
 use http::{
     header::{HeaderMap, HeaderName, HeaderValue},
     method::Method,
@@ -74,6 +76,95 @@ impl<S> RequestBuilder<S> {
         self
     }
 
+    /// Set the request body as a [`Stream`] of [`Bytes`].
+    ///
+    /// The length of the body is unknown, so it will be sent with `Transfer-Encoding: chunked`
+    /// in HTTP/1.1, use [`RequestBuilder::body_stream_with_length`] if the length is known.
+    pub fn body_stream<St, E>(self, stream: St) -> Self
+    where
+        St: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        if self.status.is_err() {
+            return self;
+        }
+
+        let (parts, _) = self.request.into_parts();
+        let request = Request::from_parts(parts, Body::from_bytes_stream(stream));
+
+        Self { request, ..self }
+    }
+
+    /// Set the request body as a [`Stream`] of [`Bytes`] with the given length.
+    ///
+    /// The length is sent as `Content-Length`, and the request fails if the stream yields more or
+    /// less data than the length.
+    pub fn body_stream_with_length<St, E>(self, stream: St, length: u64) -> Self
+    where
+        St: Stream<Item = Result<Bytes, E>> + Send + Sync + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        if self.status.is_err() {
+            return self;
+        }
+
+        let (mut parts, _) = self.request.into_parts();
+        parts
+            .headers
+            .insert(http::header::CONTENT_LENGTH, HeaderValue::from(length));
+        let request =
+            Request::from_parts(parts, Body::from_bytes_stream_with_length(stream, length));
+
+        Self { request, ..self }
+    }
+
+    /// Set a callback for observing progress of uploading the request body.
+    ///
+    /// The callback is called each time a chunk of the body is sent, it observes the current
+    /// body, so it should be called after the body is set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use volo_http::{body::Body, client::Client};
+    ///
+    /// # async fn upload(client: Client) {
+    /// let resp = client
+    ///     .post("http://127.0.0.1:8080/upload")
+    ///     .body(Body::from(vec![0u8; 4096]))
+    ///     .on_upload_progress(|progress| {
+    ///         println!("sent {} of {:?} bytes", progress.sent, progress.total);
+    ///     })
+    ///     .send()
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn on_upload_progress<F>(self, callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        if self.status.is_err() {
+            return self;
+        }
+
+        let (parts, body) = self.request.into_parts();
+        let total = parts
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse().ok())
+            .or_else(|| http_body::Body::size_hint(&body).exact());
+        let body = body.on_progress_with_total(total, move |progress| {
+            callback(UploadProgress {
+                sent: progress.transferred,
+                total: progress.total,
+            })
+        });
+        let request = Request::from_parts(parts, body);
+
+        Self { request, ..self }
+    }
+
     /// Set the request body as json from object with [`Serialize`](serde::Serialize).
     #[cfg(feature = "json")]
     pub fn json<T>(mut self, json: &T) -> Self
@@ -102,6 +193,31 @@ impl<S> RequestBuilder<S> {
         self
     }
 
+    /// Set the request body as `multipart/form-data` from [`Form`].
+    ///
+    /// `Content-Length` is set if lengths of all parts are known.
+    ///
+    /// [`Form`]: super::multipart::Form
+    #[cfg(feature = "multipart")]
+    pub fn multipart(self, form: super::multipart::Form) -> Self {
+        if self.status.is_err() {
+            return self;
+        }
+
+        let (mut parts, _) = self.request.into_parts();
+        parts
+            .headers
+            .insert(http::header::CONTENT_TYPE, form.content_type());
+        if let Some(length) = form.content_length() {
+            parts
+                .headers
+                .insert(http::header::CONTENT_LENGTH, HeaderValue::from(length));
+        }
+        let request = Request::from_parts(parts, form.into_body());
+
+        Self { request, ..self }
+    }
+
     /// Set the request body as form from object with [`Serialize`](serde::Serialize).
     #[cfg(feature = "form")]
     pub fn form<T>(mut self, form: &T) -> Self
@@ -327,6 +443,51 @@ impl<S, B> RequestBuilder<S, B> {
         &mut self.target
     }
 
+    /// Set a callback for interim responses with `1xx` status, e.g., `103 Early Hints`.
+    ///
+    /// The callback is called for each interim response received before the final response.
+    ///
+    /// Interim responses are only reported for HTTP/1.1 (with the `http1` feature) and HTTP/3,
+    /// the ones received over HTTP/2 are not reported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use volo_http::client::Client;
+    ///
+    /// # async fn early_hints(client: Client) {
+    /// let resp = client
+    ///     .get("http://127.0.0.1:8080/")
+    ///     .on_informational(|resp| {
+    ///         for link in resp.headers().get_all(http::header::LINK) {
+    ///             println!("preload: {link:?}");
+    ///         }
+    ///     })
+    ///     .send()
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn on_informational<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&http::Response<()>) + Send + Sync + 'static,
+    {
+        let callback = OnInformational(Arc::new(callback));
+        // hyper only reports interim responses of HTTP/1.1
+        #[cfg(feature = "http1")]
+        {
+            let hyper_callback = callback.clone();
+            hyper::ext::on_informational(&mut self.request, move |resp| {
+                let mut interim = http::Response::new(());
+                *interim.status_mut() = resp.status();
+                *interim.version_mut() = resp.version();
+                *interim.headers_mut() = resp.headers().clone();
+                hyper_callback.call(&interim);
+            });
+        }
+        self.request.extensions_mut().insert(callback);
+        self
+    }
+
     /// Set a request body.
     pub fn body<B2>(self, body: B2) -> RequestBuilder<S, B2> {
         let (parts, _) = self.request.into_parts();
@@ -383,7 +544,30 @@ impl<S, B> RequestBuilder<S, B> {
     }
 
     /// Send the request and get the response.
-    pub async fn send<RespBody>(mut self) -> Result<Response<RespBody>>
+    pub async fn send<RespBody>(self) -> Result<Response<RespBody>>
+    where
+        S: OneShotService<
+                ClientContext,
+                Request<B>,
+                Response = Response<RespBody>,
+                Error = ClientError,
+            > + Send
+            + Sync
+            + 'static,
+        B: Send + 'static,
+    {
+        let mut cx = ClientContext::new();
+        self.send_with_context(&mut cx).await
+    }
+
+    /// Send the request with the given [`ClientContext`] and get the response.
+    ///
+    /// The context can be inspected after the call, e.g., getting the timing breakdown of the
+    /// request by [`ClientContext::timing`].
+    pub async fn send_with_context<RespBody>(
+        mut self,
+        cx: &mut ClientContext,
+    ) -> Result<Response<RespBody>>
     where
         S: OneShotService<
                 ClientContext,
@@ -398,9 +582,82 @@ impl<S, B> RequestBuilder<S, B> {
         self.set_version();
         self.status?;
 
-        let mut cx = ClientContext::new();
-        self.target.apply(&mut cx)?;
-        self.inner.call(&mut cx, self.request).await
+        self.target.apply(cx)?;
+        self.inner.call(cx, self.request).await
+    }
+
+    /// Send the request as a WebSocket handshake and upgrade the connection.
+    ///
+    /// The request is always sent by HTTP/1.1 since the upgrade mechanism is not available in
+    /// HTTP/2. See [`ws`](super::ws) for more details.
+    #[cfg(feature = "ws")]
+    pub async fn upgrade<RespBody>(self) -> Result<super::ws::WebSocket>
+    where
+        S: OneShotService<
+                ClientContext,
+                Request<B>,
+                Response = Response<RespBody>,
+                Error = ClientError,
+            > + Send
+            + Sync
+            + 'static,
+        B: Send + 'static,
+    {
+        self.upgrade_with(Default::default()).await
+    }
+
+    /// Send the request as a WebSocket handshake with the [`UpgradeConfig`] and upgrade the
+    /// connection.
+    ///
+    /// [`UpgradeConfig`]: super::ws::UpgradeConfig
+    #[cfg(feature = "ws")]
+    pub async fn upgrade_with<RespBody>(
+        mut self,
+        config: super::ws::UpgradeConfig,
+    ) -> Result<super::ws::WebSocket>
+    where
+        S: OneShotService<
+                ClientContext,
+                Request<B>,
+                Response = Response<RespBody>,
+                Error = ClientError,
+            > + Send
+            + Sync
+            + 'static,
+        B: Send + 'static,
+    {
+        self.version = Some(Version::HTTP_11);
+        self.status?;
+        let key = super::ws::prepare_request(&mut self.request, &config)?;
+        self.status = Ok(());
+        let resp = self.send().await?;
+        super::ws::upgrade(resp, &key, config).await
+    }
+}
+
+/// Progress of uploading a request body.
+///
+/// See [`RequestBuilder::on_upload_progress`] for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UploadProgress {
+    /// Number of bytes that have been sent.
+    pub sent: u64,
+    /// Total number of bytes if the length of the body is known.
+    pub total: Option<u64>,
+}
+
+/// Callback for interim responses set by [`RequestBuilder::on_informational`].
+#[derive(Clone)]
+#[cfg_attr(not(any(feature = "http1", feature = "http3")), allow(dead_code))]
+pub(crate) struct OnInformational(Arc<InformationalCallback>);
+
+type InformationalCallback = dyn Fn(&http::Response<()>) + Send + Sync;
+
+impl OnInformational {
+    #[cfg_attr(not(any(feature = "http1", feature = "http3")), allow(dead_code))]
+    pub(crate) fn call(&self, resp: &http::Response<()>) {
+        (self.0)(resp)
     }
 }
 
@@ -421,3 +678,123 @@ impl<S> Layer<S> for WithOptLayer {
         WithOptService::new(inner, self.opt)
     }
 }
+
+#[cfg(test)]
+mod request_builder_tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use bytes::Bytes;
+    use futures::stream;
+    use http::header;
+    use motore::service::service_fn;
+
+    use super::UploadProgress;
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        context::ClientContext,
+        error::ClientError,
+        request::Request,
+        response::Response,
+    };
+
+    // echo the request body with its `Content-Length`
+    fn echo() -> MockTransport {
+        MockTransport::service(service_fn(|_: &mut ClientContext, req: Request| async {
+            let (parts, body) = req.into_parts();
+            let body = body
+                .into_bytes()
+                .await
+                .map_err(crate::error::client::body_error)?;
+            let mut resp = Response::new(Body::from(body));
+            if let Some(len) = parts.headers.get(header::CONTENT_LENGTH) {
+                resp.headers_mut()
+                    .insert(header::CONTENT_LENGTH, len.clone());
+            }
+            Ok::<_, ClientError>(resp)
+        }))
+    }
+
+    fn chunks() -> impl futures::Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static {
+        stream::iter([
+            Ok(Bytes::from_static(b"Hello, ")),
+            Ok(Bytes::from_static(b"Volo!")),
+        ])
+    }
+
+    #[tokio::test]
+    async fn body_stream() {
+        let client = ClientBuilder::new().mock(echo()).unwrap();
+
+        let resp = client.post("/").body_stream(chunks()).send().await.unwrap();
+        assert!(resp.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(resp.into_string().await.unwrap(), "Hello, Volo!");
+
+        let resp = client
+            .post("/")
+            .body_stream_with_length(chunks(), 12)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.headers().get(header::CONTENT_LENGTH).unwrap(), "12");
+        assert_eq!(resp.into_string().await.unwrap(), "Hello, Volo!");
+
+        // the stream is shorter than the length
+        assert!(
+            client
+                .post("/")
+                .body_stream_with_length(chunks(), 13)
+                .send()
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_progress() {
+        let client = ClientBuilder::new().mock(echo()).unwrap();
+        let record = Arc::new(Mutex::new(Vec::new()));
+
+        client
+            .post("/")
+            .body_stream_with_length(chunks(), 12)
+            .on_upload_progress({
+                let record = record.clone();
+                move |progress| record.lock().unwrap().push(progress)
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            *record.lock().unwrap(),
+            [
+                UploadProgress {
+                    sent: 7,
+                    total: Some(12)
+                },
+                UploadProgress {
+                    sent: 12,
+                    total: Some(12)
+                },
+            ]
+        );
+
+        record.lock().unwrap().clear();
+        client
+            .post("/")
+            .body_stream(chunks())
+            .on_upload_progress({
+                let record = record.clone();
+                move |progress| record.lock().unwrap().push(progress)
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(record.lock().unwrap().last().unwrap().sent, 12);
+        assert_eq!(record.lock().unwrap().last().unwrap().total, None);
+    }
+}
@@ -1,10 +1,13 @@
 //! This module provides [`SseReader`] for consuming SSE streams from a server,
-//! mirroring the server-side [`Sse`] response type in `server::response::sse`.
+//! mirroring the server-side [`Sse`] response type in `server::response::sse`, and
+//! [`EventSource`] for reconnecting automatically with `Last-Event-ID`.
 //!
 //! [`Sse`]: crate::server::response::sse::Sse
-use std::{pin::Pin, time::Duration};
+use std::{future::Future, pin::Pin, time::Duration};
 
 use bytes::Bytes;
+use futures::Stream;
+use http::StatusCode;
 use http_body::Body;
 use http_body_util::BodyExt;
 
@@ -336,6 +339,216 @@ where
     }
 }
 
+impl<B> SseReader<B>
+where
+    B: Body<Data = Bytes> + Unpin + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    /// Convert the reader into a [`Stream`] of [`SseEvent`]s.
+    pub fn into_stream(self) -> impl Stream<Item = Result<SseEvent, BoxError>> + Send {
+        futures::stream::unfold(Some(self), |reader| async move {
+            let mut reader = reader?;
+            match reader.read().await {
+                Ok(Some(event)) => Some((Ok(event), Some(reader))),
+                Ok(None) => None,
+                // stop after an error
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+}
+
+/// Name of the header for resuming an SSE stream.
+pub const LAST_EVENT_ID: &str = "last-event-id";
+
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// An SSE client reconnecting automatically, like [`EventSource`][mdn] of browsers.
+///
+/// [`EventSource`] is created with a function making requests, the function receives the last
+/// event ID which should be sent as [`LAST_EVENT_ID`] header if it exists, so that the server can
+/// resume the stream.
+///
+/// When the stream ends or fails, [`EventSource`] waits for the retry duration, which can be
+/// changed by the server through `retry:` field, and reconnects. The delay is doubled for each
+/// consecutive reconnection without receiving any event, up to the maximum retry delay. If the
+/// server responds a
+/// non-success status or a non-SSE content type, or responds `204 No Content`, the
+/// [`EventSource`] is closed without reconnecting.
+///
+/// # Example
+///
+/// ```no_run
+/// use volo_http::client::{
+///     Client,
+///     sse::{EventSource, LAST_EVENT_ID},
+/// };
+///
+/// # async fn run() {
+/// let client = Client::builder().build().unwrap();
+/// let mut source = EventSource::new(move |last_event_id: Option<String>| {
+///     let mut builder = client.get("http://127.0.0.1:8080/events");
+///     if let Some(id) = last_event_id {
+///         builder = builder.header(LAST_EVENT_ID, id);
+///     }
+///     builder.send()
+/// });
+/// while let Some(event) = source.next().await {
+///     println!("{:?}", event.map(|e| e.data));
+/// }
+/// # }
+/// ```
+///
+/// [mdn]: https://developer.mozilla.org/en-US/docs/Web/API/EventSource
+pub struct EventSource<F, B> {
+    connect: F,
+    reader: Option<SseReader<B>>,
+    last_event_id: String,
+    retry: Duration,
+    max_retry_delay: Duration,
+    max_retries: Option<usize>,
+    // consecutive connections since the last event
+    retries: usize,
+    connected: bool,
+    closed: bool,
+}
+
+impl<F, Fut, B, E> EventSource<F, B>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+    E: Into<BoxError>,
+{
+    /// Create an [`EventSource`] with the function making requests.
+    ///
+    /// The first request is sent when [`EventSource::next`] is called.
+    pub fn new(connect: F) -> Self {
+        Self {
+            connect,
+            reader: None,
+            last_event_id: String::new(),
+            retry: DEFAULT_RETRY,
+            max_retry_delay: DEFAULT_MAX_RETRY_DELAY,
+            max_retries: None,
+            retries: 0,
+            connected: false,
+            closed: false,
+        }
+    }
+
+    /// Set the initial retry duration, default is 3 seconds.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the maximum delay of reconnecting after consecutive failures, default is 60 seconds.
+    ///
+    /// The retry duration set by the server is always respected even if it exceeds the maximum.
+    pub fn with_max_retry_delay(mut self, max_retry_delay: Duration) -> Self {
+        self.max_retry_delay = max_retry_delay;
+        self
+    }
+
+    /// Set the maximum number of consecutive reconnections, the [`EventSource`] will be closed
+    /// after that, default is unlimited.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Returns the last event ID received, empty string means there is no last event ID.
+    pub fn last_event_id(&self) -> &str {
+        &self.last_event_id
+    }
+
+    /// Returns the current retry duration.
+    pub fn retry(&self) -> Duration {
+        self.retry
+    }
+
+    /// Whether the [`EventSource`] is closed and will not reconnect anymore.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Close the [`EventSource`].
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.reader = None;
+    }
+
+    /// Get the next event, reconnecting if the stream ends or fails.
+    ///
+    /// Errors of reconnecting are returned while the [`EventSource`] continues to retry, unless
+    /// the maximum number of retries is reached. [`None`] is returned once the [`EventSource`] is
+    /// closed.
+    pub async fn next(&mut self) -> Option<Result<SseEvent, BoxError>> {
+        loop {
+            if self.closed {
+                return None;
+            }
+            let Some(reader) = &mut self.reader else {
+                if let Err(err) = self.connect().await {
+                    return Some(Err(err));
+                }
+                continue;
+            };
+            match reader.read().await {
+                Ok(Some(event)) => {
+                    self.retries = 0;
+                    self.last_event_id.clone_from(&reader.last_event_id);
+                    if let Some(retry) = event.retry {
+                        self.retry = retry;
+                    }
+                    return Some(Ok(event));
+                }
+                Ok(None) => {
+                    tracing::debug!("[Volo-HTTP] EventSource: stream ended, reconnecting");
+                    self.reader = None;
+                }
+                Err(err) => {
+                    tracing::debug!("[Volo-HTTP] EventSource: stream failed: {err}, reconnecting");
+                    self.reader = None;
+                }
+            }
+        }
+    }
+
+    async fn connect(&mut self) -> Result<(), BoxError> {
+        if self.max_retries.is_some_and(|max| self.retries > max) {
+            self.closed = true;
+            return Err("EventSource: reached the maximum number of retries".into());
+        }
+        if self.connected {
+            tokio::time::sleep(self.retry_delay()).await;
+        }
+        self.connected = true;
+        self.retries += 1;
+
+        let last_event_id = (!self.last_event_id.is_empty()).then(|| self.last_event_id.clone());
+        let resp = (self.connect)(last_event_id).await.map_err(Into::into)?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            self.closed = true;
+            return Err("EventSource: server responded 204 No Content".into());
+        }
+        let mut reader = SseReader::into_sse(resp).inspect_err(|_| self.closed = true)?;
+        reader.last_event_id.clone_from(&self.last_event_id);
+        self.reader = Some(reader);
+        Ok(())
+    }
+
+    /// Delay before the next reconnection, the retry duration with exponential backoff for
+    /// failures since the last event.
+    fn retry_delay(&self) -> Duration {
+        let backoff = self.retry.saturating_mul(1 << self.retries.min(16));
+        backoff.min(self.max_retry_delay).max(self.retry)
+    }
+}
+
 #[cfg(test)]
 mod sse_reader_tests {
     use std::time::Duration;
@@ -594,3 +807,116 @@ mod sse_reader_tests {
         assert_eq!(event.data(), Some("hello"));
     }
 }
+
+#[cfg(test)]
+mod event_source_tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use http::{StatusCode, header};
+    use http_body_util::Full;
+
+    use super::EventSource;
+    use crate::{error::BoxError, response::Response};
+
+    fn sse_response(body: &'static str) -> Response<Full<Bytes>> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, mime::TEXT_EVENT_STREAM.essence_str())
+            .body(Full::new(Bytes::from_static(body.as_bytes())))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reconnect_with_last_event_id() {
+        let mut requests = Vec::new();
+        let mut source = EventSource::new(|last_event_id: Option<String>| {
+            requests.push(last_event_id);
+            let resp = match requests.len() {
+                1 => sse_response("id: 1\ndata: a\n\n"),
+                2 => sse_response("retry: 0\ndata: b\n\n"),
+                _ => Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Full::new(Bytes::new()))
+                    .unwrap(),
+            };
+            async move { Ok::<_, BoxError>(resp) }
+        })
+        .with_retry(Duration::ZERO);
+
+        let event = source.next().await.unwrap().unwrap();
+        assert_eq!(event.data(), Some("a"));
+        assert_eq!(source.last_event_id(), "1");
+
+        let event = source.next().await.unwrap().unwrap();
+        assert_eq!(event.data(), Some("b"));
+        assert_eq!(event.retry(), Some(Duration::ZERO));
+        // events without `id` do not clear the last event id
+        assert_eq!(source.last_event_id(), "1");
+
+        // 204 closes the source
+        assert!(source.next().await.unwrap().is_err());
+        assert!(source.is_closed());
+        assert!(source.next().await.is_none());
+        drop(source);
+
+        assert_eq!(requests, [None, Some("1".to_owned()), Some("1".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn retry_delay() {
+        let mut requests = 0;
+        let mut source = EventSource::new(|_| {
+            requests += 1;
+            let resp = match requests {
+                1 | 3 => Ok(sse_response("data: a\n\n")),
+                _ => Err::<Response<Full<Bytes>>, BoxError>("connection refused".into()),
+            };
+            async move { resp }
+        })
+        .with_retry(Duration::from_millis(50))
+        .with_max_retry_delay(Duration::from_millis(120));
+
+        assert!(source.next().await.unwrap().is_ok());
+        assert_eq!(source.retry_delay(), Duration::from_millis(50));
+
+        // waits for the retry duration even if the last connection received events
+        let start = std::time::Instant::now();
+        assert!(source.next().await.unwrap().is_err());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        // backoff for consecutive failures, up to the maximum
+        assert_eq!(source.retry_delay(), Duration::from_millis(100));
+        source.retries += 1;
+        assert_eq!(source.retry_delay(), Duration::from_millis(120));
+        source.retries -= 1;
+
+        // receiving an event resets the backoff
+        assert!(source.next().await.unwrap().is_ok());
+        assert_eq!(source.retry_delay(), Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn max_retries() {
+        let mut source = EventSource::new(|_| async {
+            Err::<Response<Full<Bytes>>, BoxError>("connection refused".into())
+        })
+        .with_retry(Duration::ZERO)
+        .with_max_retries(1);
+
+        // the first attempt and one retry
+        assert!(source.next().await.unwrap().is_err());
+        assert!(source.next().await.unwrap().is_err());
+        assert!(!source.is_closed());
+        assert!(source.next().await.unwrap().is_err());
+        assert!(source.is_closed());
+        assert!(source.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fatal_content_type() {
+        let mut source = EventSource::new(|_| async {
+            Ok::<_, BoxError>(Response::new(Full::new(Bytes::new())))
+        });
+        assert!(source.next().await.unwrap().is_err());
+        assert!(source.is_closed());
+    }
+}
@@ -18,6 +18,9 @@ use crate::{
     response::Response,
 };
 
+#[cfg(feature = "json")]
+pub mod replay;
+
 /// Default mock service of [`Client`]
 pub type ClientMockService = MockTransport;
 
@@ -81,6 +84,16 @@ impl MockTransport {
             crate::utils::test_helpers::ConvertService::new(service),
         ))
     }
+
+    /// Create a [`MockTransport`] serving responses recorded in the [`Cassette`], see
+    /// [`ReplayTransport`] for more details.
+    ///
+    /// [`Cassette`]: replay::Cassette
+    /// [`ReplayTransport`]: replay::ReplayTransport
+    #[cfg(feature = "json")]
+    pub fn replay(cassette: replay::Cassette) -> Self {
+        Self::service(replay::ReplayTransport::new(cassette))
+    }
 }
 
 impl Service<ClientContext, Request> for MockTransport {
@@ -132,7 +145,11 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         let client_inner = ClientInner {
             service,
             timeout: self.timeout,
+            total_timeout: self.total_timeout,
             headers: self.headers,
+            pool_stats: None,
+            remote_dns_proxy: None,
+            resolve_on_dial: [false; 2],
         };
         let client = Client {
             inner: Arc::new(client_inner),
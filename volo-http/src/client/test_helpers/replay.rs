@@ -0,0 +1,604 @@
+//! Recording and replaying of HTTP exchanges.
+//!
+//! [`RecordLayer`] captures requests and responses of a client as [`Exchange`]s, including
+//! headers, every data frame and trailers of the response, and appends them to a file in JSON
+//! Lines. The file can be loaded as a [`Cassette`] later and served by [`ReplayTransport`], which
+//! matches requests by method and path or a custom matcher, so that tests run without the real
+//! server.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use volo_http::{
+//!     ClientBuilder,
+//!     client::test_helpers::{
+//!         MockTransport,
+//!         replay::{Cassette, RecordLayer, Recorder},
+//!     },
+//! };
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! // record exchanges with the real server
+//! let recorder = Recorder::to_file("exchanges.jsonl")?;
+//! let client = ClientBuilder::new()
+//!     .layer_outer(RecordLayer::new(recorder))
+//!     .build()
+//!     .unwrap();
+//!
+//! // replay them later
+//! let cassette = Cassette::load("exchanges.jsonl")?;
+//! let client = ClientBuilder::new()
+//!     .mock(MockTransport::replay(cassette))
+//!     .unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use bytes::Bytes;
+use http::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    method::Method,
+    status::StatusCode,
+};
+use http_body::Frame;
+use http_body_util::BodyExt;
+use motore::{layer::Layer, service::Service};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    body::{Body, BodyConversion},
+    context::client::ClientContext,
+    error::{
+        BoxError,
+        client::{ClientError, Result, other_error},
+    },
+    request::Request,
+    response::Response,
+};
+
+/// Bytes of a recorded body or header value.
+///
+/// It is recorded as a string if it is valid UTF-8, otherwise as an array of bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RecordedBytes {
+    /// Valid UTF-8 bytes.
+    Text(String),
+    /// Bytes that are not valid UTF-8.
+    Bytes(Vec<u8>),
+}
+
+impl RecordedBytes {
+    /// Get the bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(s) => s.as_bytes(),
+            Self::Bytes(b) => b,
+        }
+    }
+}
+
+impl From<&[u8]> for RecordedBytes {
+    fn from(value: &[u8]) -> Self {
+        match std::str::from_utf8(value) {
+            Ok(s) => Self::Text(s.to_owned()),
+            Err(_) => Self::Bytes(value.to_vec()),
+        }
+    }
+}
+
+/// Recorded headers or trailers, in their original order.
+pub type RecordedHeaders = Vec<(String, RecordedBytes)>;
+
+fn record_headers(headers: &HeaderMap) -> RecordedHeaders {
+    headers
+        .iter()
+        .map(|(k, v)| (k.as_str().to_owned(), RecordedBytes::from(v.as_bytes())))
+        .collect()
+}
+
+fn restore_headers(headers: &RecordedHeaders) -> Result<HeaderMap> {
+    let mut map = HeaderMap::with_capacity(headers.len());
+    for (k, v) in headers {
+        let name = HeaderName::try_from(k.as_str()).map_err(other_error)?;
+        let value = HeaderValue::from_bytes(v.as_bytes()).map_err(other_error)?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// A recorded request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// Method of the request.
+    pub method: String,
+    /// Uri of the request.
+    pub uri: String,
+    /// Headers of the request.
+    pub headers: RecordedHeaders,
+    /// The whole body of the request.
+    pub body: RecordedBytes,
+}
+
+impl RecordedRequest {
+    /// Returns whether the request has the same method, path and query with the recorded one.
+    pub fn matches(&self, parts: &http::request::Parts) -> bool {
+        if self.method != parts.method.as_str() {
+            return false;
+        }
+        let Ok(uri) = self.uri.parse::<http::Uri>() else {
+            return false;
+        };
+        uri.path_and_query().map(|pq| pq.as_str())
+            == parts.uri.path_and_query().map(|pq| pq.as_str())
+    }
+}
+
+/// A frame of recorded response body.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedFrame {
+    /// A data frame.
+    Data(RecordedBytes),
+    /// Trailers of the body.
+    Trailers(RecordedHeaders),
+}
+
+/// A recorded response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// Status code of the response.
+    pub status: u16,
+    /// Headers of the response.
+    pub headers: RecordedHeaders,
+    /// All frames of the response body in order.
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response> {
+        let status = StatusCode::from_u16(self.status).map_err(other_error)?;
+        let headers = restore_headers(&self.headers)?;
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            let frame = match frame {
+                RecordedFrame::Data(data) => Frame::data(Bytes::from(data.as_bytes().to_vec())),
+                RecordedFrame::Trailers(trailers) => Frame::trailers(restore_headers(trailers)?),
+            };
+            frames.push(Ok::<_, BoxError>(frame));
+        }
+
+        let mut resp = Response::new(Body::from_stream(futures::stream::iter(frames)));
+        *resp.status_mut() = status;
+        *resp.headers_mut() = headers;
+        Ok(resp)
+    }
+}
+
+/// A pair of recorded request and response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// The recorded request.
+    pub request: RecordedRequest,
+    /// The recorded response.
+    pub response: RecordedResponse,
+}
+
+/// A list of [`Exchange`]s, which is stored as JSON Lines, one [`Exchange`] per line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cassette {
+    exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    /// Create an empty [`Cassette`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a [`Cassette`] from a file in JSON Lines, empty lines are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut exchanges = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            exchanges.push(sonic_rs::from_str(&line).map_err(io::Error::other)?);
+        }
+        Ok(Self { exchanges })
+    }
+
+    /// Save the [`Cassette`] to a file in JSON Lines, the file is truncated if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for exchange in &self.exchanges {
+            write_exchange(&mut file, exchange)?;
+        }
+        file.flush()
+    }
+
+    /// Append an [`Exchange`].
+    pub fn push(&mut self, exchange: Exchange) {
+        self.exchanges.push(exchange);
+    }
+
+    /// All [`Exchange`]s in order.
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+}
+
+impl FromIterator<Exchange> for Cassette {
+    fn from_iter<T: IntoIterator<Item = Exchange>>(iter: T) -> Self {
+        Self {
+            exchanges: iter.into_iter().collect(),
+        }
+    }
+}
+
+fn write_exchange<W: Write>(writer: &mut W, exchange: &Exchange) -> io::Result<()> {
+    let line = sonic_rs::to_string(exchange).map_err(io::Error::other)?;
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\n")
+}
+
+/// Storage of [`Exchange`]s recorded by [`RecordLayer`].
+///
+/// It is cheap to clone, and all clones share the same storage.
+#[derive(Clone, Default)]
+pub struct Recorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+#[derive(Default)]
+struct RecorderInner {
+    cassette: Cassette,
+    file: Option<File>,
+}
+
+impl Recorder {
+    /// Create a [`Recorder`] keeping [`Exchange`]s in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a [`Recorder`] that also appends every [`Exchange`] to the file as a line of JSON,
+    /// the file is created if it does not exist.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                cassette: Cassette::new(),
+                file: Some(file),
+            })),
+        })
+    }
+
+    /// Get a [`Cassette`] of all [`Exchange`]s recorded.
+    pub fn cassette(&self) -> Cassette {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cassette
+            .clone()
+    }
+
+    fn record(&self, exchange: Exchange) {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(file) = inner.file.as_mut() {
+            if let Err(e) = write_exchange(file, &exchange) {
+                tracing::warn!("[Volo-HTTP] failed to write recorded exchange: {e}");
+            }
+        }
+        inner.cassette.push(exchange);
+    }
+}
+
+/// [`Layer`] for recording requests and responses to a [`Recorder`].
+///
+/// Note that it collects the whole request and response and then records them, so the response
+/// is returned after all of its frames are received.
+#[derive(Clone, Default)]
+pub struct RecordLayer {
+    recorder: Recorder,
+}
+
+impl RecordLayer {
+    /// Create a [`RecordLayer`] with the [`Recorder`].
+    pub fn new(recorder: Recorder) -> Self {
+        Self { recorder }
+    }
+}
+
+impl<S> Layer<S> for RecordLayer {
+    type Service = RecordService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RecordService {
+            inner,
+            recorder: self.recorder,
+        }
+    }
+}
+
+/// [`Service`] generated by [`RecordLayer`].
+///
+/// For more details, see [`RecordLayer`].
+pub struct RecordService<S> {
+    inner: S,
+    recorder: Recorder,
+}
+
+impl<S> Service<ClientContext, Request> for RecordService<S>
+where
+    S: Service<ClientContext, Request, Response = Response, Error = ClientError> + Send + Sync,
+{
+    type Response = Response;
+    type Error = ClientError;
+
+    async fn call(
+        &self,
+        cx: &mut ClientContext,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let (parts, body) = req.into_parts();
+        let bytes = body.into_bytes().await?;
+        let request = RecordedRequest {
+            method: parts.method.to_string(),
+            uri: parts.uri.to_string(),
+            headers: record_headers(&parts.headers),
+            body: RecordedBytes::from(bytes.as_ref()),
+        };
+
+        let resp = self
+            .inner
+            .call(cx, Request::from_parts(parts, Body::from(bytes)))
+            .await?;
+
+        let (parts, mut body) = resp.into_parts();
+        let mut frames = Vec::new();
+        let mut recorded_frames = Vec::new();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(other_error)?;
+            if let Some(data) = frame.data_ref() {
+                recorded_frames.push(RecordedFrame::Data(RecordedBytes::from(data.as_ref())));
+            } else if let Some(trailers) = frame.trailers_ref() {
+                recorded_frames.push(RecordedFrame::Trailers(record_headers(trailers)));
+            }
+            frames.push(Ok::<_, BoxError>(frame));
+        }
+        let response = RecordedResponse {
+            status: parts.status.as_u16(),
+            headers: record_headers(&parts.headers),
+            frames: recorded_frames,
+        };
+        self.recorder.record(Exchange { request, response });
+
+        Ok(Response::from_parts(
+            parts,
+            Body::from_stream(futures::stream::iter(frames)),
+        ))
+    }
+}
+
+type Matcher = Arc<dyn Fn(&http::request::Parts, &RecordedRequest) -> bool + Send + Sync>;
+
+/// Mock transport [`Service`] serving responses recorded in a [`Cassette`].
+///
+/// A request is served with the first recorded [`Exchange`] that matches it and has not been
+/// served, and the last matched one is served again if all of them have been served. By default,
+/// a request matches an [`Exchange`] if they have the same method, path and query, see
+/// [`RecordedRequest::matches`]. It returns an error if no [`Exchange`] matches the request.
+///
+/// It can be used as a [`Service`] directly or through [`MockTransport::replay`].
+///
+/// [`MockTransport::replay`]: super::MockTransport::replay
+#[derive(Clone)]
+pub struct ReplayTransport {
+    exchanges: Arc<[Exchange]>,
+    served: Arc<Mutex<Vec<bool>>>,
+    matcher: Matcher,
+}
+
+impl ReplayTransport {
+    /// Create a [`ReplayTransport`] serving the [`Cassette`].
+    pub fn new(cassette: Cassette) -> Self {
+        let len = cassette.exchanges.len();
+        Self {
+            exchanges: cassette.exchanges.into(),
+            served: Arc::new(Mutex::new(vec![false; len])),
+            matcher: Arc::new(|parts, recorded| recorded.matches(parts)),
+        }
+    }
+
+    /// Set a custom matcher between requests and recorded requests.
+    ///
+    /// Note that the request body is not available for matching.
+    pub fn with_matcher<F>(mut self, matcher: F) -> Self
+    where
+        F: Fn(&http::request::Parts, &RecordedRequest) -> bool + Send + Sync + 'static,
+    {
+        self.matcher = Arc::new(matcher);
+        self
+    }
+
+    fn find(&self, parts: &http::request::Parts) -> Option<&Exchange> {
+        let mut served = self.served.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut last = None;
+        for (idx, exchange) in self.exchanges.iter().enumerate() {
+            if !(self.matcher)(parts, &exchange.request) {
+                continue;
+            }
+            if !served[idx] {
+                served[idx] = true;
+                return Some(exchange);
+            }
+            last = Some(exchange);
+        }
+        last
+    }
+}
+
+impl Service<ClientContext, Request> for ReplayTransport {
+    type Response = Response;
+    type Error = ClientError;
+
+    async fn call(
+        &self,
+        _: &mut ClientContext,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let (parts, _) = req.into_parts();
+        let Some(exchange) = self.find(&parts) else {
+            return Err(other_error(ReplayError {
+                method: parts.method,
+                uri: parts.uri,
+            }));
+        };
+        exchange.response.to_response()
+    }
+}
+
+/// No recorded [`Exchange`] matches the request.
+#[derive(Debug)]
+struct ReplayError {
+    method: Method,
+    uri: http::Uri,
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no recorded exchange matches {} {}",
+            self.method, self.uri
+        )
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[cfg(test)]
+mod replay_tests {
+    use http::{HeaderMap, HeaderValue, StatusCode};
+
+    use super::{Cassette, RecordLayer, RecordedBytes, RecordedFrame, Recorder, ReplayTransport};
+    use crate::{
+        ClientBuilder,
+        body::{Body, BodyConversion},
+        client::test_helpers::MockTransport,
+        response::Response,
+    };
+
+    fn echo_transport() -> MockTransport {
+        MockTransport::service(motore::service::service_fn(
+            |_: &mut crate::context::ClientContext, req: crate::request::Request| async move {
+                let path = req.uri().path().to_owned();
+                let mut trailers = HeaderMap::new();
+                trailers.insert("x-path", HeaderValue::from_str(&path).unwrap());
+                let body = req.into_body().into_bytes().await?;
+                let mut resp = Response::new(Body::from(body).with_trailers(trailers));
+                *resp.status_mut() = StatusCode::CREATED;
+                Ok(resp)
+            },
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let recorder = Recorder::new();
+        let client = ClientBuilder::new()
+            .layer_outer(RecordLayer::new(recorder.clone()))
+            .mock(echo_transport())
+            .unwrap();
+        for body in ["first", "second"] {
+            let resp = client
+                .post("/echo")
+                .body(Body::from(body))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert_eq!(resp.into_body().into_string().await.unwrap(), body);
+        }
+
+        let cassette = recorder.cassette();
+        assert_eq!(cassette.exchanges().len(), 2);
+        let exchange = &cassette.exchanges()[0];
+        assert_eq!(exchange.request.method, "POST");
+        assert_eq!(
+            exchange.request.body,
+            RecordedBytes::Text("first".to_owned())
+        );
+        assert_eq!(
+            exchange.response.frames,
+            vec![
+                RecordedFrame::Data(RecordedBytes::Text("first".to_owned())),
+                RecordedFrame::Trailers(vec![(
+                    "x-path".to_owned(),
+                    RecordedBytes::Text("/echo".to_owned())
+                )]),
+            ]
+        );
+
+        // save and load
+        let path =
+            std::env::temp_dir().join(format!("volo-http-replay-{}.jsonl", std::process::id()));
+        cassette.save(&path).unwrap();
+        let loaded = Cassette::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, cassette);
+
+        let client = ClientBuilder::new()
+            .mock(MockTransport::replay(loaded))
+            .unwrap();
+        // served in order, and the last one is repeated
+        for body in ["first", "second", "second"] {
+            let resp = client.post("/echo").send().await.unwrap();
+            assert_eq!(resp.status(), StatusCode::CREATED);
+            assert_eq!(resp.into_body().into_string().await.unwrap(), body);
+        }
+        // nothing matches
+        assert!(client.get("/echo").send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_matcher() {
+        let recorder = Recorder::new();
+        let client = ClientBuilder::new()
+            .layer_outer(RecordLayer::new(recorder.clone()))
+            .mock(echo_transport())
+            .unwrap();
+        client.get("/a?id=1").send().await.unwrap();
+
+        let transport = ReplayTransport::new(recorder.cassette())
+            .with_matcher(|parts, _| parts.uri.path() == "/b");
+        let client = ClientBuilder::new()
+            .mock(MockTransport::service(transport))
+            .unwrap();
+        assert!(client.get("/a?id=1").send().await.is_err());
+        assert!(client.get("/b").send().await.is_ok());
+    }
+
+    #[test]
+    fn test_recorded_bytes() {
+        assert_eq!(
+            RecordedBytes::from(&b"hello"[..]),
+            RecordedBytes::Text("hello".to_owned())
+        );
+        let bytes = RecordedBytes::from(&[0xff, 0x00][..]);
+        assert_eq!(bytes, RecordedBytes::Bytes(vec![0xff, 0x00]));
+        let json = sonic_rs::to_string(&bytes).unwrap();
+        assert_eq!(json, "[255,0]");
+        assert_eq!(sonic_rs::from_str::<RecordedBytes>(&json).unwrap(), bytes);
+    }
+}
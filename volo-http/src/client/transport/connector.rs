@@ -1,9 +1,15 @@
 use http::uri::Scheme;
 use motore::service::UnaryService;
-use volo::net::{Address, conn::Conn};
+use volo::net::{conn::Conn, memory::MemoryConnector};
 
-use super::{plain::PlainMakeConnection, protocol::ClientTransportConfig};
-use crate::error::{ClientError, client::bad_scheme};
+use super::{
+    plain::PlainMakeConnection,
+    protocol::{ClientTransportConfig, PeerAddress},
+};
+use crate::error::{
+    ClientError,
+    client::{bad_scheme, request_error},
+};
 
 pub struct ConnectorBuilder<'a> {
     mk_conn: HttpMakeConnection,
@@ -13,7 +19,14 @@ pub struct ConnectorBuilder<'a> {
 
 impl<'a> ConnectorBuilder<'a> {
     pub fn new(config: &'a ClientTransportConfig) -> Self {
-        let mk_conn = HttpMakeConnection::Plain(PlainMakeConnection::default());
+        let mut plain = PlainMakeConnection::new(config.dial.clone());
+        if let Some(proxy) = &config.socks5_proxy {
+            plain = plain.with_socks5_proxy(proxy.clone());
+        }
+        let mk_conn = match &config.memory {
+            Some(memory) => HttpMakeConnection::Memory(memory.clone()),
+            None => HttpMakeConnection::Plain(plain),
+        };
         Self { mk_conn, config }
     }
 
@@ -33,6 +46,8 @@ impl<'a> ConnectorBuilder<'a> {
                 HttpMakeConnection::Tls(super::tls::TlsMakeConnection::new(plain, tls_connector))
             }
             HttpMakeConnection::Tls(tls) => HttpMakeConnection::Tls(tls),
+            // TLS is not used for in-memory connections
+            HttpMakeConnection::Memory(memory) => HttpMakeConnection::Memory(memory),
         };
 
         Self { mk_conn, config }
@@ -42,7 +57,7 @@ impl<'a> ConnectorBuilder<'a> {
         let this = self;
 
         #[cfg(feature = "__tls")]
-        let this = if this.config.disable_tls {
+        let this = if this.config.disable_tls || this.config.memory.is_some() {
             this
         } else {
             // If the feature `tls` is enabled and it is not disabled by config, just use a default
@@ -67,19 +82,28 @@ fn default_tls_connector() -> volo::net::tls::TlsConnector {
         .unwrap_or_default()
 }
 
+/// Attach the address of peer to the error if it is resolved.
+pub(super) fn with_peer_address(err: ClientError, address: PeerAddress) -> ClientError {
+    match address {
+        PeerAddress::Address(address) => err.with_address(address),
+        PeerAddress::Domain(..) => err,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(super) struct PeerInfo {
     pub scheme: Scheme,
-    pub address: Address,
-    #[cfg(feature = "__tls")]
+    pub address: PeerAddress,
     pub name: faststr::FastStr,
 }
 
 #[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum HttpMakeConnection {
     Plain(PlainMakeConnection),
     #[cfg(feature = "__tls")]
     Tls(super::tls::TlsMakeConnection),
+    Memory(MemoryConnector),
 }
 
 impl HttpMakeConnection {
@@ -105,6 +129,15 @@ impl UnaryService<PeerInfo> for HttpMakeConnection {
                 // FIXME: tokio-rustls does not support setting alpn for each connection
                 tls.call(req).await
             }
+            Self::Memory(memory) => {
+                if req.scheme != Scheme::HTTP {
+                    return Err(bad_scheme(req.scheme));
+                }
+                memory.connect().await.map_err(|err| {
+                    tracing::warn!("[Volo-HTTP] failed to make in-memory connection, error: {err}");
+                    with_peer_address(request_error(err), req.address)
+                })
+            }
         }
     }
 }
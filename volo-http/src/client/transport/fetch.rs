@@ -0,0 +1,422 @@
+//! Transport by the `fetch` of browsers on `wasm32`
+//!
+//! Requests are sent by the [Fetch API], so connections, TLS, proxies and DNS are all handled by
+//! the browser, and only the [`Config`] of `fetch` can be set for the client. For cross-origin
+//! requests in [`Mode::Cors`], the browser sends the preflight request by `OPTIONS` itself if it
+//! is required, and the response is only returned if the server allows it by CORS headers. Note
+//! that some headers are forbidden to be set by browsers, e.g., `Host` and `User-Agent` of some
+//! browsers, they are ignored silently.
+//!
+//! The futures of JavaScript can not be sent across threads, so the request is sent in a local
+//! task of [`wasm_bindgen_futures`]. The request body is collected before sending, and the
+//! response body is read entirely before returning the response.
+//!
+//! The timers of tokio are not available on `wasm32`, so the request timeout and the total
+//! timeout of the client are applied by aborting the request from this transport.
+//!
+//! [Fetch API]: https://developer.mozilla.org/en-US/docs/Web/API/Fetch_API
+
+use std::{marker::PhantomData, time::Duration};
+
+use bytes::Bytes;
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header,
+    uri::{PathAndQuery, Scheme},
+};
+use http_body_util::BodyExt;
+use js_sys::{Array, Promise, Uint8Array};
+use motore::service::Service;
+use tokio::sync::oneshot;
+use volo::{context::Context, net::Address, timeout::attempt_timeout};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AbortController, AbortSignal, Headers, RequestCredentials, RequestInit, RequestMode, Window,
+    WorkerGlobalScope,
+};
+
+use crate::{
+    body::Body,
+    client::dns::{Port, ResolveOnDial},
+    context::ClientContext,
+    error::{
+        BoxError, ClientError,
+        client::{Result, no_address, request_error},
+    },
+    request::Request,
+    response::Response,
+};
+
+/// Whether cross-origin requests can be sent, see [`Request.mode`] for more details.
+///
+/// [`Request.mode`]: https://developer.mozilla.org/en-US/docs/Web/API/Request/mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Cross-origin requests are sent with CORS, the preflight request is sent before them if
+    /// required.
+    #[default]
+    Cors,
+    /// Cross-origin requests are sent without CORS, but their responses are opaque, which are
+    /// returned as errors by the client.
+    NoCors,
+    /// Cross-origin requests are not allowed.
+    SameOrigin,
+}
+
+impl From<Mode> for RequestMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Cors => Self::Cors,
+            Mode::NoCors => Self::NoCors,
+            Mode::SameOrigin => Self::SameOrigin,
+        }
+    }
+}
+
+/// Whether cookies and HTTP authentication are sent with requests, see [`Request.credentials`]
+/// for more details.
+///
+/// [`Request.credentials`]: https://developer.mozilla.org/en-US/docs/Web/API/Request/credentials
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Credentials {
+    /// Never send credentials.
+    Omit,
+    /// Only send credentials to the same origin.
+    #[default]
+    SameOrigin,
+    /// Always send credentials, even to cross-origin servers, which should allow it by
+    /// `Access-Control-Allow-Credentials`.
+    Include,
+}
+
+impl From<Credentials> for RequestCredentials {
+    fn from(credentials: Credentials) -> Self {
+        match credentials {
+            Credentials::Omit => Self::Omit,
+            Credentials::SameOrigin => Self::SameOrigin,
+            Credentials::Include => Self::Include,
+        }
+    }
+}
+
+/// Configurations of `fetch` for sending requests.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    mode: Mode,
+    credentials: Credentials,
+}
+
+impl Config {
+    /// Sets the [`Mode`] of requests.
+    ///
+    /// Default is [`Mode::Cors`].
+    pub fn set_mode(&mut self, mode: Mode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the [`Credentials`] of requests.
+    ///
+    /// Default is [`Credentials::SameOrigin`].
+    pub fn set_credentials(&mut self, credentials: Credentials) -> &mut Self {
+        self.credentials = credentials;
+        self
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ClientConfig {
+    pub fetch: Config,
+}
+
+#[derive(Clone)]
+pub(crate) struct ClientTransportConfig {
+    pub stat_enable: bool,
+}
+
+impl Default for ClientTransportConfig {
+    fn default() -> Self {
+        Self { stat_enable: true }
+    }
+}
+
+/// Transport service of HTTP Client on `wasm32`.
+///
+/// This service sends a [`Request`] to the [`Address`] or the domain name of callee's
+/// [`Endpoint`] in [`ClientContext`] by `fetch`, and returns the [`Response`] with its whole body.
+///
+/// [`Endpoint`]: volo::context::Endpoint
+/// [`Request`]: http::request::Request
+/// [`Response`]: http::response::Response
+pub struct ClientTransport<B = Body> {
+    config: ClientTransportConfig,
+    fetch: Config,
+    _marker: PhantomData<fn(B)>,
+}
+
+impl<B> ClientTransport<B> {
+    pub(crate) fn new(http_config: ClientConfig, config: ClientTransportConfig) -> Self {
+        Self {
+            config,
+            fetch: http_config.fetch,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Domain names are always resolved by the browser.
+    pub(crate) fn resolves_on_dial(&self, _: &Scheme) -> bool {
+        true
+    }
+}
+
+impl<B> Service<ClientContext, Request<B>> for ClientTransport<B>
+where
+    B: http_body::Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError> + 'static,
+{
+    type Response = Response;
+    type Error = ClientError;
+
+    async fn call(
+        &self,
+        cx: &mut ClientContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let url = full_url(cx, &req)?;
+        let timeout = attempt_timeout(cx);
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await.map_err(request_error)?.to_bytes();
+
+        let stat_enabled = self.config.stat_enable;
+        if stat_enabled {
+            cx.stats.record_transport_start_at();
+        }
+
+        let req = FetchRequest {
+            method: parts.method,
+            url,
+            headers: parts.headers,
+            body,
+            config: self.fetch.clone(),
+        };
+        let (tx, rx) = oneshot::channel();
+        wasm_bindgen_futures::spawn_local(fetch_local(
+            req,
+            timeout.map(|(duration, _)| duration),
+            tx,
+        ));
+        let res = rx
+            .await
+            .unwrap_or_else(|_| Err(FetchError::Js("the fetch task is dropped".to_owned())));
+
+        if stat_enabled {
+            cx.stats.record_transport_end_at();
+        }
+
+        match res {
+            Ok(resp) => Ok(resp.into_response()),
+            Err(FetchError::Js(err)) => Err(request_error(err)),
+            Err(FetchError::Timeout) => match timeout {
+                Some((_, err)) => Err(ClientError::from(err)),
+                None => Err(request_error("the request is aborted")),
+            },
+        }
+    }
+}
+
+// The request is sent to the address picked by the discover, or the domain name left to be
+// resolved by the browser.
+fn full_url<B>(cx: &ClientContext, req: &Request<B>) -> Result<String> {
+    let scheme = cx.target().scheme().cloned().unwrap_or(Scheme::HTTP);
+    let callee = cx.rpc_info().callee();
+    let authority = match callee.address() {
+        Some(Address::Ip(addr)) => addr.to_string(),
+        None if callee.contains::<ResolveOnDial>() => {
+            let port = callee.get::<Port>().ok_or_else(no_address)?;
+            format!("{}:{}", callee.service_name_ref(), port.0)
+        }
+        None => return Err(no_address()),
+    };
+    let path = req.uri().path_and_query().map_or("/", PathAndQuery::as_str);
+    Ok(format!("{scheme}://{authority}{path}"))
+}
+
+struct FetchRequest {
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Bytes,
+    config: Config,
+}
+
+struct FetchResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl FetchResponse {
+    fn into_response(self) -> Response {
+        let mut resp = Response::new(Body::from(self.body));
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers;
+        resp
+    }
+}
+
+enum FetchError {
+    Timeout,
+    Js(String),
+}
+
+impl From<JsValue> for FetchError {
+    fn from(value: JsValue) -> Self {
+        match value.dyn_ref::<js_sys::Error>() {
+            Some(err) => Self::Js(String::from(err.message())),
+            None => Self::Js(format!("{value:?}")),
+        }
+    }
+}
+
+// The request is aborted if it times out or the future of the transport is dropped.
+async fn fetch_local(
+    req: FetchRequest,
+    timeout: Option<Duration>,
+    mut tx: oneshot::Sender<Result<FetchResponse, FetchError>>,
+) {
+    let scope = match Scope::new() {
+        Ok(scope) => scope,
+        Err(err) => {
+            let _ = tx.send(Err(err));
+            return;
+        }
+    };
+    let controller = AbortController::new().ok();
+    let signal = controller.as_ref().map(AbortController::signal);
+    let fetch = fetch(&scope, req, signal.as_ref());
+    let sleep = async {
+        match timeout {
+            Some(timeout) => {
+                let _ = sleep(&scope, timeout).await;
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    let res = tokio::select! {
+        biased;
+        res = fetch => res,
+        _ = sleep => Err(FetchError::Timeout),
+        _ = tx.closed() => Err(FetchError::Timeout),
+    };
+    if let (Some(controller), Err(FetchError::Timeout)) = (&controller, &res) {
+        controller.abort();
+    }
+    let _ = tx.send(res);
+}
+
+async fn fetch(
+    scope: &Scope,
+    req: FetchRequest,
+    signal: Option<&AbortSignal>,
+) -> Result<FetchResponse, FetchError> {
+    let headers = Headers::new()?;
+    for (name, value) in req.headers.iter() {
+        // it is forbidden by browsers and the authority of url is used
+        if name == header::HOST {
+            continue;
+        }
+        let value = value
+            .to_str()
+            .map_err(|_| FetchError::Js(format!("invalid value of header `{name}`")))?;
+        headers.append(name.as_str(), value)?;
+    }
+
+    let init = RequestInit::new();
+    init.set_method(req.method.as_str());
+    init.set_headers(&headers);
+    if !req.body.is_empty() {
+        init.set_body(&Uint8Array::from(req.body.as_ref()));
+    }
+    init.set_mode(req.config.mode.into());
+    init.set_credentials(req.config.credentials.into());
+    init.set_signal(signal);
+    let request = web_sys::Request::new_with_str_and_init(&req.url, &init)?;
+
+    let resp: web_sys::Response = JsFuture::from(scope.fetch(&request)).await?.dyn_into()?;
+    // the response of a cross-origin request without CORS is opaque, whose status is zero
+    let status = StatusCode::from_u16(resp.status())
+        .map_err(|_| FetchError::Js(format!("opaque response from `{}`", req.url)))?;
+    let mut headers = HeaderMap::new();
+    if let Some(entries) = js_sys::try_iter(&resp.headers())? {
+        for entry in entries {
+            let entry: Array = entry?.dyn_into()?;
+            let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string())
+            else {
+                continue;
+            };
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+    }
+    let body = JsFuture::from(resp.array_buffer()?).await?;
+    let body = Uint8Array::new(&body).to_vec();
+
+    Ok(FetchResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn sleep(scope: &Scope, timeout: Duration) -> JsFuture {
+    let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+    let promise = Promise::new(&mut |resolve, _| {
+        let _ = scope.set_timeout(&resolve, millis);
+    });
+    JsFuture::from(promise)
+}
+
+// `fetch` is available in both the window and the workers.
+enum Scope {
+    Window(Window),
+    Worker(WorkerGlobalScope),
+}
+
+impl Scope {
+    fn new() -> Result<Self, FetchError> {
+        let global = js_sys::global();
+        if let Some(window) = global.dyn_ref::<Window>() {
+            return Ok(Self::Window(window.clone()));
+        }
+        match global.dyn_into::<WorkerGlobalScope>() {
+            Ok(worker) => Ok(Self::Worker(worker)),
+            Err(_) => Err(FetchError::Js(
+                "`fetch` is only available in the window or workers".to_owned(),
+            )),
+        }
+    }
+
+    fn fetch(&self, request: &web_sys::Request) -> Promise {
+        match self {
+            Self::Window(window) => window.fetch_with_request(request),
+            Self::Worker(worker) => worker.fetch_with_request(request),
+        }
+    }
+
+    fn set_timeout(&self, handler: &js_sys::Function, millis: i32) -> Result<i32, JsValue> {
+        match self {
+            Self::Window(window) => {
+                window.set_timeout_with_callback_and_timeout_and_arguments_0(handler, millis)
+            }
+            Self::Worker(worker) => {
+                worker.set_timeout_with_callback_and_timeout_and_arguments_0(handler, millis)
+            }
+        }
+    }
+}
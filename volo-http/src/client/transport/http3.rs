@@ -0,0 +1,417 @@
+//! HTTP/3 related utilities
+//!
+//! HTTP/3 support is experimental. Requests are sent over QUIC only when the target is HTTPS and
+//! an IP address, and either prior knowledge is enabled or the server has advertised HTTP/3 by
+//! the `Alt-Svc` header. Limits of the connection pool are not applied to HTTP/3 connections.
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use faststr::FastStr;
+use h3::client::SendRequest;
+use http::{HeaderMap, header, uri::Scheme, version::Version};
+use parking_lot::Mutex;
+use tokio::time::Instant;
+use volo::net::{Address, tls::TlsConnector};
+
+use super::connector::PeerInfo;
+use crate::{
+    body::Body,
+    client::request_builder::OnInformational,
+    error::{
+        BoxError,
+        client::{Result, connect_error, request_error},
+    },
+    request::Request,
+    response::Response,
+    utils::http3::{RecvBody, send_body},
+};
+
+const ALPN_H3: &[u8] = b"h3";
+// The default max age of `Alt-Svc` is 24 hours, see RFC 7838.
+const DEFAULT_ALT_SVC_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Configurations of HTTP3 Client.
+pub struct Config {
+    prior_knowledge: bool,
+    alt_svc: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prior_knowledge: false,
+            alt_svc: true,
+        }
+    }
+}
+
+impl Config {
+    /// Sets whether to send all HTTPS requests by HTTP/3 without waiting for the server to
+    /// advertise it.
+    ///
+    /// Default is `false`.
+    pub fn set_prior_knowledge(&mut self, enabled: bool) -> &mut Self {
+        self.prior_knowledge = enabled;
+        self
+    }
+
+    /// Sets whether to upgrade to HTTP/3 when the server advertises it by the `Alt-Svc` header.
+    ///
+    /// If the QUIC connection cannot be established, the client falls back to HTTP/1 or HTTP/2
+    /// and forgets the advertisement.
+    ///
+    /// Default is `true`.
+    pub fn set_alt_svc(&mut self, enabled: bool) -> &mut Self {
+        self.alt_svc = enabled;
+        self
+    }
+}
+
+type H3Sender = SendRequest<h3_quinn::OpenStreams, Bytes>;
+
+struct H3Connection {
+    conn: quinn::Connection,
+    sender: H3Sender,
+}
+
+/// Where to send a request by HTTP/3.
+pub(super) struct Target {
+    pub addr: SocketAddr,
+    /// The target comes from `Alt-Svc`, and the request can fall back to HTTP/1 or HTTP/2.
+    pub fallback: bool,
+}
+
+pub(super) struct Http3Connector {
+    prior_knowledge: bool,
+    alt_svc: bool,
+    client_config: quinn::ClientConfig,
+    // endpoints for IPv4 and IPv6
+    endpoints: Mutex<[Option<quinn::Endpoint>; 2]>,
+    conns: Mutex<HashMap<(FastStr, SocketAddr), H3Connection>>,
+    alt_svcs: Mutex<HashMap<Address, (u16, Instant)>>,
+}
+
+impl Http3Connector {
+    /// Create an [`Http3Connector`], returns [`None`] if HTTP/3 is disabled or the TLS connector
+    /// is not built by rustls.
+    pub fn new(config: &Config, tls_connector: Option<&TlsConnector>) -> Option<Self> {
+        if !config.prior_knowledge && !config.alt_svc {
+            return None;
+        }
+        let default_connector;
+        let tls_connector = match tls_connector {
+            Some(connector) => connector,
+            None => {
+                default_connector = TlsConnector::default();
+                &default_connector
+            }
+        };
+        let Some(tls_config) = tls_connector.rustls_config() else {
+            tracing::warn!("[Volo-HTTP] HTTP/3 is disabled since the tls connector is not rustls");
+            return None;
+        };
+        let mut tls_config = (**tls_config).clone();
+        tls_config.alpn_protocols = vec![ALPN_H3.to_vec()];
+        let crypto = match quinn::crypto::rustls::QuicClientConfig::try_from(tls_config) {
+            Ok(crypto) => crypto,
+            Err(err) => {
+                tracing::warn!("[Volo-HTTP] HTTP/3 is disabled, error: {err}");
+                return None;
+            }
+        };
+
+        Some(Self {
+            prior_knowledge: config.prior_knowledge,
+            alt_svc: config.alt_svc,
+            client_config: quinn::ClientConfig::new(Arc::new(crypto)),
+            endpoints: Mutex::new([None, None]),
+            conns: Mutex::new(HashMap::new()),
+            alt_svcs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get the [`Target`] if the request should be sent by HTTP/3.
+    pub fn target(&self, peer: &PeerInfo, ver: Version) -> Option<Target> {
+        if peer.scheme != Scheme::HTTPS {
+            return None;
+        }
+        let Some(address @ Address::Ip(addr)) = peer.address.as_address() else {
+            return None;
+        };
+        let addr = *addr;
+        if self.prior_knowledge || ver == Version::HTTP_3 {
+            return Some(Target {
+                addr,
+                fallback: false,
+            });
+        }
+        if !self.alt_svc {
+            return None;
+        }
+        let mut alt_svcs = self.alt_svcs.lock();
+        let (port, expires_at) = *alt_svcs.get(address)?;
+        if Instant::now() >= expires_at {
+            alt_svcs.remove(address);
+            return None;
+        }
+        Some(Target {
+            addr: SocketAddr::new(addr.ip(), port),
+            fallback: true,
+        })
+    }
+
+    /// Record the `Alt-Svc` header from a response of HTTP/1 or HTTP/2.
+    pub fn record_alt_svc(&self, peer: &PeerInfo, headers: &HeaderMap) {
+        if !self.alt_svc || peer.scheme != Scheme::HTTPS {
+            return;
+        }
+        let Some(address) = peer.address.as_address() else {
+            return;
+        };
+        let Some(value) = headers
+            .get(header::ALT_SVC)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return;
+        };
+        match parse_alt_svc(value) {
+            Some(AltSvc::H3 { port, max_age }) if !max_age.is_zero() => {
+                tracing::trace!("[Volo-HTTP] {address:?} advertises h3 on port {port}");
+                self.alt_svcs
+                    .lock()
+                    .insert(address.clone(), (port, Instant::now() + max_age));
+            }
+            Some(_) => self.forget_alt_svc(address),
+            None => {}
+        }
+    }
+
+    pub fn forget_alt_svc(&self, address: &Address) {
+        self.alt_svcs.lock().remove(address);
+    }
+
+    /// Drop connections to the `address` and its `Alt-Svc`, requests in flight are not affected
+    /// and the connections are closed after they are completed.
+    pub fn drain(&self, address: &Address) {
+        let alt_port = self.alt_svcs.lock().remove(address).map(|(port, _)| port);
+        let Address::Ip(addr) = address else {
+            return;
+        };
+        self.conns.lock().retain(|(_, target), _| {
+            target.ip() != addr.ip()
+                || (target.port() != addr.port() && Some(target.port()) != alt_port)
+        });
+    }
+
+    /// Get an HTTP/3 connection to the `addr`, a new one will be established if there is no
+    /// available one.
+    pub async fn connect(&self, name: &FastStr, addr: SocketAddr) -> Result<H3Sender> {
+        let key = (name.clone(), addr);
+        if let Some(conn) = self.conns.lock().get(&key) {
+            if conn.conn.close_reason().is_none() {
+                return Ok(conn.sender.clone());
+            }
+        }
+
+        let endpoint = self.endpoint(addr)?;
+        tracing::debug!("[Volo-HTTP] try to make quic connection, name: {name:?}");
+        let conn = endpoint
+            .connect(addr, name)
+            .map_err(connect_error)?
+            .await
+            .map_err(connect_error)?;
+        let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(conn.clone()))
+            .await
+            .map_err(connect_error)?;
+        tokio::spawn(async move {
+            let err = driver.wait_idle().await;
+            if !err.is_h3_no_error() {
+                tracing::debug!("[Volo-HTTP] http3 connection error: {err}");
+            }
+        });
+
+        self.conns.lock().insert(
+            key,
+            H3Connection {
+                conn,
+                sender: sender.clone(),
+            },
+        );
+        Ok(sender)
+    }
+
+    fn endpoint(&self, addr: SocketAddr) -> Result<quinn::Endpoint> {
+        let mut endpoints = self.endpoints.lock();
+        let endpoint = &mut endpoints[usize::from(addr.is_ipv6())];
+        if let Some(endpoint) = endpoint {
+            return Ok(endpoint.clone());
+        }
+        let bind = if addr.is_ipv6() {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        };
+        let mut new_endpoint = quinn::Endpoint::client(bind).map_err(connect_error)?;
+        new_endpoint.set_default_client_config(self.client_config.clone());
+        *endpoint = Some(new_endpoint.clone());
+        Ok(new_endpoint)
+    }
+}
+
+/// Send the request by the HTTP/3 connection.
+///
+/// The request should have a full uri with scheme and authority.
+pub(super) async fn send_request<B>(mut sender: H3Sender, req: Request<B>) -> Result<Response>
+where
+    B: http_body::Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError> + 'static,
+{
+    let (parts, body) = req.into_parts();
+    let on_informational = parts.extensions.get::<OnInformational>().cloned();
+    let stream = sender
+        .send_request(Request::from_parts(parts, ()))
+        .await
+        .map_err(request_error)?;
+    let (mut send, mut recv) = stream.split();
+    send_body(&mut send, body).await.map_err(request_error)?;
+
+    let mut resp = recv.recv_response().await.map_err(request_error)?;
+    // interim responses are followed by the final response on the same stream
+    while resp.status().is_informational() {
+        if let Some(on_informational) = &on_informational {
+            on_informational.call(&resp);
+        }
+        resp = recv.recv_response().await.map_err(request_error)?;
+    }
+    Ok(resp.map(|()| Body::from_body(RecvBody::new(recv))))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AltSvc {
+    H3 { port: u16, max_age: Duration },
+    Clear,
+}
+
+/// Parse the `Alt-Svc` header and find the first `h3` alternative on the same host.
+fn parse_alt_svc(value: &str) -> Option<AltSvc> {
+    let value = value.trim();
+    if value == "clear" {
+        return Some(AltSvc::Clear);
+    }
+    value.split(',').find_map(|alt| {
+        let mut params = alt.split(';').map(str::trim);
+        let (protocol, authority) = params.next()?.split_once('=')?;
+        if protocol.trim() != "h3" {
+            return None;
+        }
+        let authority = authority.trim().strip_prefix('"')?.strip_suffix('"')?;
+        let (host, port) = authority.rsplit_once(':')?;
+        // alternatives on other hosts are not supported
+        if !host.is_empty() {
+            return None;
+        }
+        let port = port.parse().ok()?;
+        let max_age = params
+            .filter_map(|param| param.strip_prefix("ma="))
+            .find_map(|ma| ma.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_ALT_SVC_MAX_AGE);
+        Some(AltSvc::H3 { port, max_age })
+    })
+}
+
+#[cfg(test)]
+mod http3_tests {
+    use std::time::Duration;
+
+    use http::{HeaderMap, HeaderValue, header, uri::Scheme, version::Version};
+    use volo::net::Address;
+
+    use super::{AltSvc, Config, Http3Connector, parse_alt_svc};
+    use crate::client::transport::{connector::PeerInfo, protocol::PeerAddress};
+
+    #[test]
+    fn parse_alt_svc_header() {
+        assert_eq!(
+            parse_alt_svc(r#"h3=":443"; ma=3600"#),
+            Some(AltSvc::H3 {
+                port: 443,
+                max_age: Duration::from_secs(3600),
+            }),
+        );
+        assert_eq!(
+            parse_alt_svc(r#"h2=":443", h3=":8443""#),
+            Some(AltSvc::H3 {
+                port: 8443,
+                max_age: super::DEFAULT_ALT_SVC_MAX_AGE,
+            }),
+        );
+        assert_eq!(parse_alt_svc("clear"), Some(AltSvc::Clear));
+        // other hosts are not supported
+        assert_eq!(parse_alt_svc(r#"h3="alt.example.com:443""#), None);
+        assert_eq!(parse_alt_svc(r#"h3-29=":443""#), None);
+        assert_eq!(parse_alt_svc(r#"h3=":abc""#), None);
+    }
+
+    fn alt_svc(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ALT_SVC, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[tokio::test]
+    async fn alt_svc_upgrade() {
+        let connector = Http3Connector::new(&Config::default(), None).unwrap();
+        let peer = PeerInfo {
+            scheme: Scheme::HTTPS,
+            address: PeerAddress::Address(Address::Ip("127.0.0.1:443".parse().unwrap())),
+            name: "localhost".into(),
+        };
+        assert!(connector.target(&peer, Version::HTTP_11).is_none());
+        // HTTP/3 can be specified by the request
+        let target = connector.target(&peer, Version::HTTP_3).unwrap();
+        assert!(!target.fallback);
+
+        connector.record_alt_svc(&peer, &alt_svc(r#"h3=":8443"; ma=60"#));
+        let target = connector.target(&peer, Version::HTTP_11).unwrap();
+        assert_eq!(target.addr, "127.0.0.1:8443".parse().unwrap());
+        assert!(target.fallback);
+
+        connector.record_alt_svc(&peer, &alt_svc("clear"));
+        assert!(connector.target(&peer, Version::HTTP_11).is_none());
+
+        // plain HTTP is never upgraded
+        let http_peer = PeerInfo {
+            scheme: Scheme::HTTP,
+            ..peer
+        };
+        connector.record_alt_svc(&http_peer, &alt_svc(r#"h3=":8443""#));
+        assert!(connector.target(&http_peer, Version::HTTP_11).is_none());
+    }
+
+    #[tokio::test]
+    async fn prior_knowledge() {
+        let mut config = Config::default();
+        config.set_prior_knowledge(true);
+        let connector = Http3Connector::new(&config, None).unwrap();
+        let peer = PeerInfo {
+            scheme: Scheme::HTTPS,
+            address: PeerAddress::Address(Address::Ip("127.0.0.1:443".parse().unwrap())),
+            name: "localhost".into(),
+        };
+        let target = connector.target(&peer, Version::HTTP_11).unwrap();
+        assert_eq!(target.addr, "127.0.0.1:443".parse().unwrap());
+        assert!(!target.fallback);
+
+        let mut config = Config::default();
+        config.set_alt_svc(false);
+        assert!(Http3Connector::new(&config, None).is_none());
+    }
+}
@@ -1,12 +1,20 @@
 //! HTTP transport related utilities
 
+#[cfg(not(target_family = "wasm"))]
 mod connector;
+#[cfg(target_family = "wasm")]
+pub mod fetch;
 #[cfg(feature = "http1")]
 pub mod http1;
 #[cfg(feature = "http2")]
 pub mod http2;
+#[cfg(feature = "http3")]
+pub mod http3;
+#[cfg(not(target_family = "wasm"))]
 mod plain;
+#[cfg(not(target_family = "wasm"))]
 pub(crate) mod pool;
+#[cfg(not(target_family = "wasm"))]
 pub mod protocol;
 #[cfg(feature = "__tls")]
 mod tls;
@@ -1,22 +1,46 @@
 use std::error::Error;
 
 use motore::{make::MakeConnection, service::UnaryService};
-use volo::net::{Address, dial::DefaultMakeTransport};
+use volo::net::{
+    Address,
+    conn::Conn,
+    dial::{DefaultMakeTransport, Dialer},
+    proxy::{ProxyTarget, Socks5Proxy},
+};
 
-use super::connector::PeerInfo;
+use super::{
+    connector::{PeerInfo, with_peer_address},
+    protocol::PeerAddress,
+};
 use crate::error::{ClientError, client::request_error};
 
 #[derive(Clone, Debug)]
 pub struct PlainMakeConnection<MkC = DefaultMakeTransport> {
     mk_conn: MkC,
+    // for connecting to the proxy or domain names resolved when dialing
+    dialer: Dialer,
+    socks5_proxy: Option<Socks5Proxy>,
+}
+
+impl PlainMakeConnection<DefaultMakeTransport> {
+    pub fn new(mk_conn: DefaultMakeTransport) -> Self {
+        Self {
+            dialer: mk_conn.dialer().clone(),
+            mk_conn,
+            socks5_proxy: None,
+        }
+    }
 }
 
 impl<MkC> PlainMakeConnection<MkC>
 where
     MkC: MakeConnection<Address>,
 {
-    pub fn new(mk_conn: MkC) -> Self {
-        Self { mk_conn }
+    /// Connect to all targets through the SOCKS5 proxy, except which are matched by its no proxy
+    /// list.
+    pub fn with_socks5_proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.socks5_proxy = Some(proxy);
+        self
     }
 }
 
@@ -26,21 +50,71 @@ impl Default for PlainMakeConnection<DefaultMakeTransport> {
     }
 }
 
+impl<MkC> PlainMakeConnection<MkC> {
+    /// Get the proxy and its target if the peer should be connected through the SOCKS5 proxy.
+    fn proxy_target(&self, peer: &PeerInfo) -> Option<(&Socks5Proxy, ProxyTarget)> {
+        let proxy = self.socks5_proxy.as_ref()?;
+        let addr = match &peer.address {
+            PeerAddress::Domain(name, port) => {
+                if !proxy.remote_dns() || proxy.bypass(name) {
+                    return None;
+                }
+                return Some((proxy, ProxyTarget::Domain(name.clone(), *port)));
+            }
+            PeerAddress::Address(address) => address.ip_addr()?,
+        };
+        if proxy.bypass(&peer.name) || proxy.no_proxy().matches_ip(addr.ip()) {
+            return None;
+        }
+        Some((proxy, ProxyTarget::Ip(*addr)))
+    }
+}
+
 impl<MkC> UnaryService<PeerInfo> for PlainMakeConnection<MkC>
 where
-    MkC: MakeConnection<Address> + Sync,
+    MkC: MakeConnection<Address, Connection = Conn> + Sync,
     MkC::Error: Error + Send + Sync + 'static,
 {
-    type Response = MkC::Connection;
+    type Response = Conn;
     type Error = ClientError;
 
     async fn call(&self, req: PeerInfo) -> Result<Self::Response, Self::Error> {
-        tracing::debug!("[Volo-HTTP] connecting to target: {req:?}");
-        match self.mk_conn.make_connection(req.address.clone()).await {
+        if let Some((proxy, target)) = self.proxy_target(&req) {
+            tracing::debug!(
+                "[Volo-HTTP] connecting to target {target} through socks5 proxy {}",
+                proxy.server()
+            );
+            return match proxy.connect(self.dialer.config(), &target).await {
+                Ok(stream) => {
+                    let _ = stream.set_nodelay(true);
+                    Ok(Conn::from(stream))
+                }
+                Err(err) => {
+                    tracing::warn!("[Volo-HTTP] failed to connect through socks5 proxy: {err}");
+                    Err(with_peer_address(request_error(err), req.address))
+                }
+            };
+        }
+
+        let address = match req.address {
+            PeerAddress::Address(address) => address,
+            PeerAddress::Domain(name, port) => {
+                tracing::debug!("[Volo-HTTP] dialing to target: {name}:{port}");
+                return match self.dialer.dial(&name, port).await {
+                    Ok(stream) => Ok(Conn::from(stream)),
+                    Err(err) => {
+                        tracing::warn!("[Volo-HTTP] failed to dial {name}:{port}, error: {err}");
+                        Err(request_error(err))
+                    }
+                };
+            }
+        };
+        tracing::debug!("[Volo-HTTP] connecting to target: {address:?}");
+        match self.mk_conn.make_connection(address.clone()).await {
             Ok(conn) => Ok(conn),
             Err(err) => {
                 tracing::warn!("[Volo-HTTP] failed to make connection, error: {err}");
-                Err(request_error(err).with_address(req.address))
+                Err(request_error(err).with_address(address))
             }
         }
     }
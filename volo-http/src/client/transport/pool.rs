@@ -9,7 +9,10 @@ use std::{
     hash::Hash,
     ops::{Deref, DerefMut},
     pin::Pin,
-    sync::{Arc, Weak},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
     task::{Context, Poll, ready},
     time::Duration,
 };
@@ -18,7 +21,11 @@ use ahash::{AHashMap, AHashSet};
 use http::Version;
 use parking_lot::Mutex;
 use pin_project::pin_project;
-use tokio::{sync::oneshot, time::Instant};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore, oneshot},
+    time::Instant,
+};
+use volo::{loadbalance::drain::Drainer, net::Address};
 
 pub struct Pool<K: Key, T> {
     // If the pool is disabled, this is None.
@@ -87,6 +94,9 @@ struct PoolInner<K: Eq + Hash, T> {
     // state, waiting to receive a new Request to send on the socket.
     idle: AHashMap<K, Vec<Idle<T>>>,
     max_idle_per_host: usize,
+    // Limits of connections for each host, including connecting and idle ones.
+    hosts: AHashMap<K, Arc<HostState>>,
+    max_conns_per_host: Option<usize>,
     // These are outstanding Checkouts that are waiting for a socket to be
     // able to send a Request one. This is used when "racing" for a new
     // connection.
@@ -97,6 +107,9 @@ struct PoolInner<K: Eq + Hash, T> {
     // them that the Conn could be used instead of waiting for a brand new
     // connection.
     waiters: AHashMap<K, VecDeque<oneshot::Sender<T>>>,
+    // Hosts whose connections are draining, connections to them will not be put back into the
+    // pool.
+    draining: AHashSet<K>,
     // A oneshot channel is used to allow the interval to be notified when
     // the Pool completely drops. That way, the interval can cancel immediately.
     idle_interval_ref: Option<oneshot::Sender<Infallible>>,
@@ -111,6 +124,9 @@ struct WeakOpt<T>(Option<Weak<T>>);
 pub struct Config {
     pub idle_timeout: Duration,
     pub max_idle_per_host: usize,
+    pub max_conns_per_host: Option<usize>,
+    pub max_lifetime: Option<Duration>,
+    pub wait_timeout: Option<Duration>,
 }
 
 impl Default for Config {
@@ -118,10 +134,54 @@ impl Default for Config {
         Self {
             idle_timeout: Duration::from_secs(20),
             max_idle_per_host: 10240,
+            max_conns_per_host: None,
+            max_lifetime: None,
+            wait_timeout: None,
         }
     }
 }
 
+struct HostState {
+    permits: Arc<Semaphore>,
+    max_conns: usize,
+    waiting: AtomicUsize,
+}
+
+impl HostState {
+    fn new(max_conns: Option<usize>) -> Self {
+        let max_conns = max_conns.unwrap_or(Semaphore::MAX_PERMITS);
+        Self {
+            permits: Arc::new(Semaphore::new(max_conns)),
+            max_conns,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    fn conns(&self) -> usize {
+        self.max_conns - self.permits.available_permits()
+    }
+
+    fn is_unused(&self) -> bool {
+        self.conns() == 0 && self.waiting.load(Ordering::Relaxed) == 0
+    }
+}
+
+/// A permit for a connection to a host.
+///
+/// The connection is counted until the permit is dropped.
+pub struct ConnPermit(#[allow(dead_code)] OwnedSemaphorePermit);
+
+/// Statistics of connections to a host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HostStats {
+    /// Number of connections, including connecting, in-use and idle ones.
+    pub connections: usize,
+    /// Number of idle connections.
+    pub idle: usize,
+    /// Number of requests waiting for a connection since the pool is exhausted.
+    pub waiting: usize,
+}
+
 impl<K: Key, T> Pool<K, T> {
     pub fn new(config: Config) -> Pool<K, T> {
         let inner = PoolInner {
@@ -129,7 +189,10 @@ impl<K: Key, T> Pool<K, T> {
             idle: AHashMap::new(),
             idle_interval_ref: None,
             max_idle_per_host: config.max_idle_per_host,
+            hosts: AHashMap::new(),
+            max_conns_per_host: config.max_conns_per_host,
             waiters: AHashMap::new(),
+            draining: AHashSet::new(),
             timeout: config.idle_timeout,
         };
         let inner = Arc::new(Mutex::new(inner));
@@ -137,6 +200,81 @@ impl<K: Key, T> Pool<K, T> {
         Pool { inner }
     }
 
+    fn host(&self, key: &K) -> Arc<HostState> {
+        let mut inner = self.inner.lock();
+        let max_conns = inner.max_conns_per_host;
+        inner
+            .hosts
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(HostState::new(max_conns)))
+            .clone()
+    }
+
+    /// Try to get a permit for making a new connection to the host.
+    ///
+    /// Returns `None` if connections to the host reach the limit.
+    pub fn try_permit(&self, key: &K) -> Option<ConnPermit> {
+        self.host(key)
+            .permits
+            .clone()
+            .try_acquire_owned()
+            .ok()
+            .map(ConnPermit)
+    }
+
+    /// Wait for a permit for making a new connection to the host.
+    pub async fn permit(&self, key: &K) -> ConnPermit {
+        struct Waiting(Arc<HostState>);
+
+        impl Drop for Waiting {
+            fn drop(&mut self) {
+                self.0.waiting.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        let host = self.host(key);
+        host.waiting.fetch_add(1, Ordering::Relaxed);
+        let waiting = Waiting(host);
+        let permit = waiting.0.permits.clone().acquire_owned().await;
+        drop(waiting);
+        // the semaphore is never closed
+        ConnPermit(permit.expect("semaphore of pool closed"))
+    }
+
+    /// Get statistics of all hosts in the pool.
+    pub fn stats(&self) -> Vec<(K, HostStats)> {
+        let inner = self.inner.lock();
+        inner
+            .hosts
+            .iter()
+            .map(|(key, host)| {
+                let stats = HostStats {
+                    connections: host.conns(),
+                    idle: inner.idle.get(key).map(Vec::len).unwrap_or_default(),
+                    waiting: host.waiting.load(Ordering::Relaxed),
+                };
+                (key.clone(), stats)
+            })
+            .collect()
+    }
+
+    /// Subscribe to the [`Drainer`] for draining connections to instances removed by the
+    /// discovery, `matches` checks if a host is the address of the instance.
+    pub fn drain_on<F>(&self, drainer: &Drainer, matches: F)
+    where
+        F: Fn(&K, &Address) -> bool + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let pool = Arc::downgrade(&self.inner);
+        drainer.subscribe(move |addr| {
+            let Some(pool) = pool.upgrade() else {
+                return false;
+            };
+            pool.lock().drain(|key| matches(key, addr));
+            true
+        });
+    }
+
     #[cfg(test)]
     pub(super) fn no_timer(&self) {
         // Prevent an actual interval from being created for this pool...
@@ -200,6 +338,7 @@ impl<K: Key, T: Poolable> Pool<K, T> {
             #[cfg(feature = "http2")]
             Reservation::Shared(to_insert, to_return) => {
                 let mut inner = self.inner.lock();
+                inner.draining.remove(&connecting.key);
                 inner.put(connecting.key.clone(), to_insert, &self.inner);
                 // Do this here instead of Drop for Connecting because we
                 // already have a lock, no need to lock the mutex twice.
@@ -213,6 +352,7 @@ impl<K: Key, T: Poolable> Pool<K, T> {
             }
             #[cfg(feature = "http1")]
             Reservation::Unique(value) => {
+                self.inner.lock().draining.remove(&connecting.key);
                 // Unique reservations must take a reference to the pool
                 // since they hope to reinsert once the reservation is
                 // completed
@@ -304,6 +444,10 @@ impl<'a, T: Poolable + 'a, K: Debug> IdlePopper<'a, K, T> {
 
 impl<K: Key, T: Poolable> PoolInner<K, T> {
     fn put(&mut self, key: K, value: T, __pool_ref: &Arc<Mutex<PoolInner<K, T>>>) {
+        if self.draining.contains(&key) {
+            tracing::trace!("put; dropping draining connection for {:?}", key);
+            return;
+        }
         if value.can_share() && self.idle.contains_key(&key) {
             tracing::trace!("put; existing idle HTTP/2 connection for {:?}", key);
             return;
@@ -400,6 +544,25 @@ impl<K: Key, T: Poolable> PoolInner<K, T> {
     }
 }
 
+impl<K: Key, T> PoolInner<K, T> {
+    /// Drain connections to hosts matched by `matches`, idle connections are dropped, and in-use
+    /// connections will be closed rather than put back into the pool after use.
+    ///
+    /// The host is no longer draining once a new connection is made to it.
+    fn drain<F>(&mut self, matches: F)
+    where
+        F: Fn(&K) -> bool,
+    {
+        self.idle.retain(|key, _| !matches(key));
+        // connections in use are also counted by hosts
+        let draining = self.hosts.keys().filter(|key| matches(key)).cloned();
+        self.draining.extend(draining);
+        if !self.draining.is_empty() {
+            tracing::trace!("draining connections for {:?}", self.draining);
+        }
+    }
+}
+
 impl<K: Eq + Hash, T> PoolInner<K, T> {
     /// Any `FutureResponse`s that were created will have made a `Checkout`,
     /// and possibly inserted into the pool that it is waiting for an idle
@@ -443,6 +606,14 @@ impl<K: Key, T: Poolable> PoolInner<K, T> {
             // returning false evicts this key/val
             !values.is_empty()
         });
+
+        // The permits may be held by connections or waiters out of the pool, so the state could
+        // only be removed when it is unused.
+        self.hosts
+            .retain(|_, host| Arc::strong_count(host) > 1 || !host.is_unused());
+        // all connections to the draining hosts are closed
+        let hosts = &self.hosts;
+        self.draining.retain(|key| hosts.contains_key(key));
     }
 }
 
@@ -802,6 +973,7 @@ mod tests {
         let pool = Pool::new(super::Config {
             idle_timeout: Duration::from_millis(100),
             max_idle_per_host: max_idle,
+            ..Default::default()
         });
         pool.no_timer();
         pool
@@ -898,6 +1070,7 @@ mod tests {
         let pool = Pool::new(super::Config {
             idle_timeout: Duration::from_millis(10),
             max_idle_per_host: usize::MAX,
+            ..Default::default()
         });
 
         let key = host_key("foo");
@@ -965,6 +1138,68 @@ mod tests {
         assert!(pool.locked().waiters.get(&key).is_none());
     }
 
+    #[tokio::test]
+    async fn test_pool_max_conns_per_host() {
+        let pool = Pool::<KeyImpl, Uniq<i32>>::new(super::Config {
+            max_conns_per_host: Some(1),
+            ..Default::default()
+        });
+        pool.no_timer();
+        let key = host_key("foo");
+
+        let permit = pool.try_permit(&key).expect("permit");
+        assert!(pool.try_permit(&key).is_none());
+        // other hosts are not limited
+        assert!(pool.try_permit(&host_key("bar")).is_some());
+
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            let key = key.clone();
+            async move { pool.permit(&key).await }
+        });
+        tokio::task::yield_now().await;
+        let stats = pool.stats();
+        let (_, stats) = stats.iter().find(|(k, _)| k == &key).unwrap();
+        assert_eq!(stats.connections, 1);
+        assert_eq!(stats.idle, 0);
+        assert_eq!(stats.waiting, 1);
+
+        // the waiter gets the permit after the connection is closed
+        drop(permit);
+        let _permit = waiter.await.unwrap();
+        let stats = pool.stats();
+        let (_, stats) = stats.iter().find(|(k, _)| k == &key).unwrap();
+        assert_eq!(stats.connections, 1);
+        assert_eq!(stats.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_drain() {
+        let pool = pool_no_timer();
+        let key = host_key("foo");
+        // connections are counted by permits of the host
+        let _permit = pool.try_permit(&key).expect("permit");
+
+        drop(pool.pooled(c(key.clone()), Uniq(41)));
+        let in_use = pool.pooled(c(key.clone()), Uniq(5));
+        let other = pool.pooled(c(host_key("bar")), Uniq(99));
+        pool.locked().drain(|k| k == &key);
+        assert!(pool.locked().idle.get(&key).is_none());
+
+        // in-use connections of draining hosts are not put back, while others are not affected
+        drop(in_use);
+        drop(other);
+        assert!(pool.locked().idle.get(&key).is_none());
+        assert!(pool.locked().idle.get(&host_key("bar")).is_some());
+
+        // not draining after a new connection is made
+        drop(pool.pooled(c(key.clone()), Uniq(7)));
+        assert_eq!(
+            pool.locked().idle.get(&key).map(|entries| entries.len()),
+            Some(1)
+        );
+    }
+
     #[derive(Debug)]
     struct CanClose {
         #[allow(unused)]
@@ -1,7 +1,8 @@
 //! Protocol related implementations
 
-use std::{error::Error, str::FromStr, sync::LazyLock};
+use std::{error::Error, fmt, str::FromStr, sync::LazyLock, time::Duration};
 
+use faststr::FastStr;
 use futures::{
     FutureExt, TryFutureExt,
     future::{self, Either},
@@ -14,18 +15,27 @@ use http::{
 use hyper::client::conn;
 use hyper_util::rt::TokioIo;
 use motore::{make::MakeConnection, service::Service};
-use volo::{context::Context, net::Address};
+use tokio::time::Instant;
+use volo::{
+    context::{Context, Role},
+    loadbalance::drain::Drainer,
+    net::{Address, dial::DefaultMakeTransport, memory::MemoryConnector, proxy::Socks5Proxy},
+};
 
 use super::{
     connector::{HttpMakeConnection, PeerInfo},
-    pool::{self, Connecting, Pool, Poolable, Pooled, Reservation},
+    pool::{self, ConnPermit, Connecting, Pool, Poolable, Pooled, Reservation},
 };
 use crate::{
     body::Body,
+    client::dns::{Port, ResolveOnDial},
     context::ClientContext,
     error::{
         BoxError, ClientError,
-        client::{Result, connect_error, no_address, request_error, retry, tri},
+        client::{
+            PoolExhausted, Result, connect_error, no_address, pool_exhausted, pool_timeout,
+            request_error, retry, tri,
+        },
     },
     request::Request,
     response::Response,
@@ -39,11 +49,16 @@ pub(crate) struct ClientConfig {
     pub h1: super::http1::Config,
     #[cfg(feature = "http2")]
     pub h2: super::http2::Config,
+    #[cfg(feature = "http3")]
+    pub h3: super::http3::Config,
 }
 
 #[derive(Clone)]
 pub(crate) struct ClientTransportConfig {
     pub stat_enable: bool,
+    pub dial: DefaultMakeTransport,
+    pub socks5_proxy: Option<Socks5Proxy>,
+    pub memory: Option<MemoryConnector>,
     #[cfg(feature = "__tls")]
     #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
     pub disable_tls: bool,
@@ -59,6 +74,9 @@ impl ClientTransportConfig {
     pub fn new() -> Self {
         Self {
             stat_enable: true,
+            dial: DefaultMakeTransport::new(),
+            socks5_proxy: None,
+            memory: None,
             #[cfg(feature = "__tls")]
             disable_tls: false,
         }
@@ -78,12 +96,87 @@ pub struct ClientTransport<B = Body> {
     h1_client: conn::http1::Builder,
     #[cfg(feature = "http2")]
     h2_client: conn::http2::Builder<hyper_util::rt::TokioExecutor>,
+    #[cfg(feature = "http3")]
+    h3_connector: Option<std::sync::Arc<super::http3::Http3Connector>>,
     config: ClientTransportConfig,
     connector: HttpMakeConnection,
     pool: Pool<PoolKey, HttpConnection<B>>,
+    max_lifetime: Option<Duration>,
+    wait_timeout: Option<Duration>,
+}
+
+type PoolKey = (Scheme, PeerAddress);
+
+/// Address of a host connected by [`ClientTransport`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    /// Address resolved by the client.
+    Address(Address),
+    /// Domain name and port of the host, which are resolved when dialing, by the dialer or the
+    /// SOCKS5 proxy with remote DNS.
+    Domain(FastStr, u16),
+}
+
+impl PeerAddress {
+    /// Get the address if it is resolved by the client.
+    pub fn as_address(&self) -> Option<&Address> {
+        match self {
+            Self::Address(address) => Some(address),
+            Self::Domain(..) => None,
+        }
+    }
 }
 
-type PoolKey = (Scheme, Address);
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "{address}"),
+            Self::Domain(name, port) => write!(f, "{name}:{port}"),
+        }
+    }
+}
+
+/// Statistics of connections to a host in the connection pool of [`ClientTransport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoolStats {
+    /// Scheme of the host.
+    pub scheme: Scheme,
+    /// Address of the host.
+    pub address: PeerAddress,
+    /// Number of connections, including connecting, in-use and idle ones.
+    pub connections: usize,
+    /// Number of idle connections.
+    ///
+    /// Note that an HTTP/2 connection is always idle since it can be shared by requests.
+    pub idle: usize,
+    /// Number of requests waiting for a connection since connections to the host reach the
+    /// limit.
+    pub waiting: usize,
+}
+
+/// Source of [`PoolStats`], it is used for erasing type of [`ClientTransport`].
+pub(crate) trait PoolStatsSource: Send + Sync {
+    fn pool_stats(&self) -> Vec<PoolStats>;
+}
+
+impl<B> PoolStatsSource for Pool<PoolKey, HttpConnection<B>>
+where
+    B: Send + 'static,
+{
+    fn pool_stats(&self) -> Vec<PoolStats> {
+        self.stats()
+            .into_iter()
+            .map(|((scheme, address), stats)| PoolStats {
+                scheme,
+                address,
+                connections: stats.connections,
+                idle: stats.idle,
+                waiting: stats.waiting,
+            })
+            .collect()
+    }
+}
 
 impl<B> ClientTransport<B> {
     pub(crate) fn new(
@@ -96,6 +189,13 @@ impl<B> ClientTransport<B> {
         let h1_client = super::http1::client(&http_config.h1);
         #[cfg(feature = "http2")]
         let h2_client = super::http2::client(&http_config.h2);
+        #[cfg(feature = "http3")]
+        let h3_connector = if transport_config.disable_tls {
+            None
+        } else {
+            super::http3::Http3Connector::new(&http_config.h3, tls_connector.as_ref())
+                .map(std::sync::Arc::new)
+        };
 
         let builder = HttpMakeConnection::builder(&transport_config);
         #[cfg(feature = "__tls")]
@@ -110,16 +210,72 @@ impl<B> ClientTransport<B> {
             h1_client,
             #[cfg(feature = "http2")]
             h2_client,
+            #[cfg(feature = "http3")]
+            h3_connector,
             config: transport_config,
             connector,
             pool: Pool::new(pool_config),
+            max_lifetime: pool_config.max_lifetime,
+            wait_timeout: pool_config.wait_timeout,
+        }
+    }
+
+    /// Subscribe to the [`Drainer`] for draining connections to instances removed by the
+    /// discovery.
+    pub(crate) fn drain_on(&self, drainer: &Drainer)
+    where
+        B: Send + 'static,
+    {
+        self.pool.drain_on(drainer, |(_, address): &PoolKey, addr| {
+            address.as_address() == Some(addr)
+        });
+        #[cfg(feature = "http3")]
+        if let Some(h3_connector) = &self.h3_connector {
+            let h3_connector = std::sync::Arc::downgrade(h3_connector);
+            drainer.subscribe(move |addr| {
+                let Some(h3_connector) = h3_connector.upgrade() else {
+                    return false;
+                };
+                h3_connector.drain(addr);
+                true
+            });
         }
     }
 
+    /// Whether domain names of callees with the scheme can be left to be resolved when dialing.
+    ///
+    /// HTTP/3 requires the resolved address for `Alt-Svc`, and a SOCKS5 proxy without remote DNS
+    /// requires the resolved address as the target, so domain names are resolved by the
+    /// [`DnsResolver`] for them.
+    ///
+    /// [`DnsResolver`]: crate::client::dns::DnsResolver
+    pub(crate) fn resolves_on_dial(&self, scheme: &Scheme) -> bool {
+        #[cfg(feature = "http3")]
+        if self.h3_connector.is_some() && scheme == &Scheme::HTTPS {
+            return false;
+        }
+        #[cfg(not(feature = "http3"))]
+        let _ = scheme;
+        self.config.memory.is_none()
+            && self
+                .config
+                .socks5_proxy
+                .as_ref()
+                .is_none_or(|proxy| proxy.remote_dns())
+    }
+
+    pub(crate) fn pool_stats_source(&self) -> Box<dyn PoolStatsSource>
+    where
+        B: Send + 'static,
+    {
+        Box::new(self.pool.clone())
+    }
+
     fn connect_to(
         &self,
         ver: pool::Ver,
         peer: PeerInfo,
+        permit: Option<ConnPermit>,
     ) -> impl Started<Output = Result<Pooled<PoolKey, HttpConnection<B>>>> + Send + 'static
     where
         B: http_body::Body + Unpin + Send + 'static,
@@ -129,6 +285,7 @@ impl<B> ClientTransport<B> {
         let key = (peer.scheme.clone(), peer.address.clone());
         let connector = self.connector.clone();
         let pool = self.pool.clone();
+        let expires_at = self.max_lifetime.map(|lifetime| Instant::now() + lifetime);
         #[cfg(feature = "http1")]
         let h1_client = self.h1_client.clone();
         #[cfg(feature = "http2")]
@@ -139,12 +296,18 @@ impl<B> ClientTransport<B> {
                 Some(lock) => lock,
                 None => return Either::Right(future::err(retry())),
             };
+            let permit = match permit.or_else(|| pool.try_permit(&key)) {
+                Some(permit) => permit,
+                None => return Either::Right(future::err(pool_exhausted())),
+            };
             Either::Left(Box::pin(connect_impl(
                 ver,
                 peer,
                 connector,
                 pool,
                 connecting,
+                permit,
+                expires_at,
                 #[cfg(feature = "http1")]
                 h1_client,
                 #[cfg(feature = "http2")]
@@ -165,8 +328,8 @@ impl<B> ClientTransport<B> {
     {
         let key = (peer.scheme.clone(), peer.address.clone());
 
-        let checkout = self.pool.checkout(key);
-        let connect = self.connect_to(ver.into(), peer);
+        let checkout = self.pool.checkout(key.clone());
+        let connect = self.connect_to(ver.into(), peer.clone(), None);
 
         // Well, `futures::future::select` is more suitable than `tokio::select!` in this case.
         match future::select(checkout, connect).await {
@@ -201,6 +364,30 @@ impl<B> ClientTransport<B> {
                     .is_some_and(<dyn Error>::is::<crate::error::client::Retry>)
                 {
                     checkout.await.map_err(connect_error)
+                } else if err.source().is_some_and(<dyn Error>::is::<PoolExhausted>) {
+                    // Connections reach the limit, wait for an idle connection or a permit for
+                    // making a new connection.
+                    let wait = async {
+                        let permit = Box::pin(self.pool.permit(&key));
+                        match future::select(checkout, permit).await {
+                            Either::Left((Ok(checked_out), _)) => Either::Left(checked_out),
+                            // The checked out connection was closed, continue waiting the permit
+                            Either::Left((Err(_), permit)) => Either::Right(permit.await),
+                            Either::Right((permit, _)) => Either::Right(permit),
+                        }
+                    };
+                    let res = match self.wait_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, wait)
+                            .await
+                            .map_err(|_| pool_timeout())?,
+                        None => wait.await,
+                    };
+                    match res {
+                        Either::Left(checked_out) => Ok(checked_out),
+                        Either::Right(permit) => {
+                            self.connect_to(ver.into(), peer, Some(permit)).await
+                        }
+                    }
                 } else {
                     // Unexpected connect error
                     Err(err)
@@ -210,12 +397,15 @@ impl<B> ClientTransport<B> {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn connect_impl<B>(
     _ver: pool::Ver,
     peer: PeerInfo,
     connector: HttpMakeConnection,
     pool: Pool<PoolKey, HttpConnection<B>>,
     connecting: Connecting<PoolKey, HttpConnection<B>>,
+    permit: ConnPermit,
+    expires_at: Option<Instant>,
     #[cfg(feature = "http1")] h1_client: conn::http1::Builder,
     #[cfg(feature = "http2")] h2_client: conn::http2::Builder<hyper_util::rt::TokioExecutor>,
 ) -> Result<Pooled<PoolKey, HttpConnection<B>>>
@@ -224,6 +414,7 @@ where
     B::Data: Send,
     B::Error: Into<BoxError> + 'static,
 {
+    let address = peer.address.clone();
     let conn = match connector.make_connection(peer).await {
         Ok(conn) => conn,
         Err(err) => {
@@ -237,6 +428,9 @@ where
     #[cfg(not(feature = "http2"))]
     let use_h2 = false;
 
+    let conn_event = address
+        .as_address()
+        .and_then(|address| volo::event::connection("http", Role::Client, address));
     let conn = TokioIo::new(conn);
     if use_h2 {
         #[cfg(feature = "http2")]
@@ -247,10 +441,18 @@ where
                 connecting
             };
             let (mut sender, conn) = tri!(h2_client.handshake(conn).await.map_err(connect_error));
-            tokio::spawn(conn);
+            tokio::spawn(async move {
+                // The connection is counted by the pool until it is closed.
+                let _permit = permit;
+                let _conn_event = conn_event;
+                conn.await
+            });
             // Wait for `conn` to ready up before we declare self sender as usable.
             tri!(sender.ready().await.map_err(connect_error));
-            Ok(pool.pooled(connecting, HttpConnection::H2(sender)))
+            Ok(pool.pooled(
+                connecting,
+                HttpConnection::new(Sender::H2(sender), expires_at),
+            ))
         }
         #[cfg(not(feature = "http2"))]
         Err(crate::error::client::bad_version())
@@ -258,10 +460,19 @@ where
         #[cfg(feature = "http1")]
         {
             let (mut sender, conn) = tri!(h1_client.handshake(conn).await.map_err(connect_error));
-            tokio::spawn(conn);
+            // Upgrades are needed by WebSocket, this does not affect normal requests.
+            tokio::spawn(async move {
+                // The connection is counted by the pool until it is closed.
+                let _permit = permit;
+                let _conn_event = conn_event;
+                conn.with_upgrades().await
+            });
             // Wait for `conn` to ready up before we declare self sender as usable.
             tri!(sender.ready().await.map_err(connect_error));
-            Ok(pool.pooled(connecting, HttpConnection::H1(sender)))
+            Ok(pool.pooled(
+                connecting,
+                HttpConnection::new(Sender::H1(sender), expires_at),
+            ))
         }
         #[cfg(not(feature = "http1"))]
         Err(crate::error::client::bad_version())
@@ -311,13 +522,20 @@ where
         rewrite_uri(cx, &mut req);
 
         let callee = cx.rpc_info().callee();
-        let address = callee.address().ok_or_else(no_address)?;
+        let address = match callee.address() {
+            Some(address) => PeerAddress::Address(address),
+            // the domain name is left to be resolved when dialing
+            None if callee.contains::<ResolveOnDial>() => {
+                let port = callee.get::<Port>().ok_or_else(no_address)?;
+                PeerAddress::Domain(callee.service_name(), port.0)
+            }
+            None => return Err(no_address()),
+        };
 
         let ver = req.version();
         let peer = PeerInfo {
             scheme: cx.target().scheme().cloned().unwrap_or(Scheme::HTTP),
             address,
-            #[cfg(feature = "__tls")]
             name: callee.service_name(),
         };
 
@@ -326,52 +544,125 @@ where
             cx.stats.record_transport_start_at();
         }
 
+        #[cfg(feature = "http3")]
+        if let Some(h3_connector) = &self.h3_connector {
+            if let Some(target) = h3_connector.target(&peer, ver) {
+                let res = match h3_connector.connect(&peer.name, target.addr).await {
+                    Ok(sender) => {
+                        set_full_uri(cx, &mut req);
+                        req.headers_mut().remove(header::HOST);
+                        super::http3::send_request(sender, req).await
+                    }
+                    Err(err) if target.fallback => {
+                        tracing::debug!(
+                            "[Volo-HTTP] failed to make http3 connection, fall back to tcp, \
+                             error: {err}"
+                        );
+                        if let Some(address) = peer.address.as_address() {
+                            h3_connector.forget_alt_svc(address);
+                        }
+                        return self.call_tcp(cx, ver, peer, req).await;
+                    }
+                    Err(err) => Err(err),
+                };
+                if stat_enabled {
+                    cx.stats.record_transport_end_at();
+                }
+                return res;
+            }
+        }
+        self.call_tcp(cx, ver, peer, req).await
+    }
+}
+
+impl<B> ClientTransport<B>
+where
+    B: http_body::Body + Unpin + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    async fn call_tcp(
+        &self,
+        cx: &mut ClientContext,
+        ver: Version,
+        peer: PeerInfo,
+        req: Request<B>,
+    ) -> Result<Response> {
+        #[cfg(feature = "http3")]
+        let h3_peer = peer.clone();
+
         let mut conn = tri!(self.pooled_connect(ver, peer).await);
         let res = conn.send_request(req).await;
 
-        if stat_enabled {
+        if self.config.stat_enable {
             cx.stats.record_transport_end_at();
         }
 
+        #[cfg(feature = "http3")]
+        if let (Some(h3_connector), Ok(resp)) = (&self.h3_connector, &res) {
+            h3_connector.record_alt_svc(&h3_peer, resp.headers());
+        }
+
         res
     }
 }
 
-enum HttpConnection<B> {
+struct HttpConnection<B> {
+    sender: Sender<B>,
+    // The connection will not be reused after it expires.
+    expires_at: Option<Instant>,
+}
+
+enum Sender<B> {
     #[cfg(feature = "http1")]
     H1(conn::http1::SendRequest<B>),
     #[cfg(feature = "http2")]
     H2(conn::http2::SendRequest<B>),
 }
 
+impl<B> HttpConnection<B> {
+    fn new(sender: Sender<B>, expires_at: Option<Instant>) -> Self {
+        Self { sender, expires_at }
+    }
+}
+
 impl<B> Poolable for HttpConnection<B>
 where
     B: Send + 'static,
 {
     fn is_open(&self) -> bool {
-        match &self {
+        if self
+            .expires_at
+            .is_some_and(|expires_at| Instant::now() >= expires_at)
+        {
+            return false;
+        }
+        match &self.sender {
             #[cfg(feature = "http1")]
-            Self::H1(h1) => h1.is_ready(),
+            Sender::H1(h1) => h1.is_ready(),
             #[cfg(feature = "http2")]
-            Self::H2(h2) => h2.is_ready(),
+            Sender::H2(h2) => h2.is_ready(),
         }
     }
 
     fn reserve(self) -> Reservation<Self> {
-        match self {
+        match self.sender {
             #[cfg(feature = "http1")]
-            Self::H1(h1) => Reservation::Unique(Self::H1(h1)),
+            Sender::H1(h1) => Reservation::Unique(Self::new(Sender::H1(h1), self.expires_at)),
             #[cfg(feature = "http2")]
-            Self::H2(h2) => Reservation::Shared(Self::H2(h2.clone()), Self::H2(h2)),
+            Sender::H2(h2) => Reservation::Shared(
+                Self::new(Sender::H2(h2.clone()), self.expires_at),
+                Self::new(Sender::H2(h2), self.expires_at),
+            ),
         }
     }
 
     fn can_share(&self) -> bool {
-        match self {
+        match self.sender {
             #[cfg(feature = "http1")]
-            Self::H1(_) => false,
+            Sender::H1(_) => false,
             #[cfg(feature = "http2")]
-            Self::H2(_) => true,
+            Sender::H2(_) => true,
         }
     }
 }
@@ -383,11 +674,11 @@ where
     B::Error: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
 {
     pub async fn send_request(&mut self, req: Request<B>) -> Result<Response> {
-        let res = match self {
+        let res = match &mut self.sender {
             #[cfg(feature = "http1")]
-            Self::H1(h1) => h1.send_request(req).await,
+            Sender::H1(h1) => h1.send_request(req).await,
             #[cfg(feature = "http2")]
-            Self::H2(h2) => h2.send_request(req).await,
+            Sender::H2(h2) => h2.send_request(req).await,
         };
         match res {
             Ok(resp) => Ok(resp.map(Body::from_incoming)),
@@ -426,6 +717,11 @@ fn rewrite_uri<B>(cx: &ClientContext, req: &mut Request<B>) {
     if req.version() != Version::HTTP_2 {
         return;
     }
+    set_full_uri(cx, req);
+}
+
+// HTTP/3 also requires the full uri, since it has the same pseudo headers as HTTP/2.
+fn set_full_uri<B>(cx: &ClientContext, req: &mut Request<B>) {
     let scheme = cx.target().scheme().cloned().unwrap_or(Scheme::HTTP);
     let authority = gen_authority(req);
     let mut parts = req.uri().to_owned().into_parts();
@@ -436,3 +732,105 @@ fn rewrite_uri<B>(cx: &ClientContext, req: &mut Request<B>) {
     };
     *req.uri_mut() = uri;
 }
+
+#[cfg(test)]
+#[cfg(feature = "http1")]
+mod protocol_tests {
+    use std::{
+        error::Error,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{ClientBuilder, error::client::PoolTimeout};
+
+    // A simple HTTP/1 server responding each request after `delay`, returning the counter of
+    // accepted connections.
+    async fn serve(delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        tokio::time::sleep(delay).await;
+                        let resp = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if stream.write_all(resp).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        (url, accepted)
+    }
+
+    #[tokio::test]
+    async fn max_conns_per_host() {
+        let (url, accepted) = serve(Duration::from_millis(50)).await;
+        let mut builder = ClientBuilder::new();
+        builder.set_max_conns_per_host(1);
+        let client = builder.build().unwrap();
+
+        let reqs = (0..3).map(|_| client.get(&url).send());
+        for resp in futures::future::join_all(reqs).await {
+            assert!(resp.unwrap().status().is_success());
+        }
+        assert_eq!(accepted.load(Ordering::Relaxed), 1);
+
+        let stats = client.pool_stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].connections, 1);
+        assert_eq!(stats[0].idle, 1);
+        assert_eq!(stats[0].waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn pool_wait_timeout() {
+        let (url, _) = serve(Duration::from_millis(200)).await;
+        let mut builder = ClientBuilder::new();
+        builder
+            .set_max_conns_per_host(1)
+            .set_pool_wait_timeout(Duration::from_millis(50));
+        let client = builder.build().unwrap();
+
+        let (first, second) = tokio::join!(client.get(&url).send(), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            client.get(&url).send().await
+        });
+        assert!(first.is_ok());
+        let err = second.unwrap_err();
+        assert!(err.source().is_some_and(|e| e.is::<PoolTimeout>()));
+    }
+
+    #[tokio::test]
+    async fn pool_max_lifetime() {
+        let (url, accepted) = serve(Duration::ZERO).await;
+        let mut builder = ClientBuilder::new();
+        builder.set_pool_max_lifetime(Duration::from_millis(50));
+        let client = builder.build().unwrap();
+
+        client.get(&url).send().await.unwrap();
+        client.get(&url).send().await.unwrap();
+        assert_eq!(accepted.load(Ordering::Relaxed), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        client.get(&url).send().await.unwrap();
+        assert_eq!(accepted.load(Ordering::Relaxed), 2);
+    }
+}
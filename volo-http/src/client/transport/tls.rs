@@ -49,7 +49,7 @@ where
             Ok(stream) => Ok(Conn {
                 stream,
                 info: ConnInfo {
-                    peer_addr: Some(req.address),
+                    peer_addr: req.address.as_address().cloned(),
                 },
             }),
             Err(err) => {
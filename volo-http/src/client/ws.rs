@@ -0,0 +1,411 @@
+//! WebSocket implementation for client.
+//!
+//! A request can be upgraded to a WebSocket connection by [`RequestBuilder::upgrade`], which
+//! sends the handshake request through the connector of the client, so that the TLS and proxy
+//! configurations are shared with normal requests.
+//!
+//! Ping frames from the server are answered automatically when reading or flushing the
+//! [`WebSocket`], and the close handshake is completed by [`WebSocketStream::close`] and reading
+//! the stream until it ends.
+//!
+//! Note that the `permessage-deflate` extension is not supported by the underlying
+//! [`tungstenite`], so it is never offered in the handshake request.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures_util::{sink::SinkExt, stream::StreamExt};
+//! use volo_http::client::{
+//!     Client,
+//!     ws::{Message, WebSocket},
+//! };
+//!
+//! # async fn run() {
+//! let client = Client::builder().build().unwrap();
+//! let mut ws: WebSocket = client
+//!     .get("http://127.0.0.1:8080/ws")
+//!     .upgrade()
+//!     .await
+//!     .unwrap();
+//! ws.send(Message::Text("hello".into())).await.unwrap();
+//! while let Some(Ok(msg)) = ws.next().await {
+//!     println!("{msg:?}");
+//! }
+//! # }
+//! ```
+//!
+//! [`RequestBuilder::upgrade`]: crate::client::RequestBuilder::upgrade
+
+use std::{
+    error::Error,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use faststr::FastStr;
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    status::StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use tokio_tungstenite::WebSocketStream;
+pub use tungstenite::{Message, protocol::WebSocketConfig};
+use tungstenite::{
+    handshake::{client::generate_key, derive_accept_key},
+    protocol,
+};
+
+use crate::{
+    error::client::{Result, request_error},
+    request::Request,
+    response::Response,
+};
+
+const HEADERVALUE_UPGRADE: HeaderValue = HeaderValue::from_static("upgrade");
+const HEADERVALUE_WEBSOCKET: HeaderValue = HeaderValue::from_static("websocket");
+const HEADERVALUE_VERSION: HeaderValue = HeaderValue::from_static("13");
+
+/// Configurations for upgrading a request to a WebSocket connection.
+#[derive(Clone, Debug, Default)]
+pub struct UpgradeConfig {
+    protocols: Vec<FastStr>,
+    config: WebSocketConfig,
+}
+
+impl UpgradeConfig {
+    /// Create a default [`UpgradeConfig`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set protocols for [`Sec-WebSocket-Protocol`][mdn].
+    ///
+    /// The server should pick one of them, and the picked protocol can be got by
+    /// [`WebSocket::protocol`].
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Sec-WebSocket-Protocol
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<FastStr>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the [`WebSocketConfig`] for the connection, e.g., size limits of messages and frames.
+    pub fn websocket_config(mut self, config: WebSocketConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+/// WebSocket connection upgraded from a client request.
+pub struct WebSocket {
+    inner: WebSocketStream<TokioIo<hyper::upgrade::Upgraded>>,
+    protocol: Option<HeaderValue>,
+    headers: HeaderMap,
+}
+
+impl WebSocket {
+    /// Get protocol picked by the server from [`UpgradeConfig::protocols`].
+    pub fn protocol(&self) -> Option<&str> {
+        simdutf8::basic::from_utf8(self.protocol.as_ref()?.as_bytes()).ok()
+    }
+
+    /// Get headers of the handshake response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Consume the [`WebSocket`] and get the inner [`WebSocketStream`].
+    pub fn into_inner(self) -> WebSocketStream<TokioIo<hyper::upgrade::Upgraded>> {
+        self.inner
+    }
+}
+
+impl Deref for WebSocket {
+    type Target = WebSocketStream<TokioIo<hyper::upgrade::Upgraded>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for WebSocket {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// Error of upgrading a request to a WebSocket connection.
+#[derive(Debug)]
+pub enum WebSocketUpgradeError {
+    /// The server does not response `101 Switching Protocols`.
+    InvalidStatus(StatusCode),
+    /// The `Connection` header of response does not include `upgrade`.
+    InvalidConnectionHeader,
+    /// The `Upgrade` header of response is not `websocket`.
+    InvalidUpgradeHeader,
+    /// The `Sec-WebSocket-Accept` header of response is missing or mismatched.
+    InvalidAcceptKey,
+    /// The server picks a protocol which is not requested.
+    InvalidProtocol(HeaderValue),
+    /// Error from [`hyper`] when waiting for the upgraded connection.
+    Upgrade(hyper::Error),
+}
+
+impl fmt::Display for WebSocketUpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidStatus(status) => {
+                write!(
+                    f,
+                    "server responded {status} rather than switching protocols"
+                )
+            }
+            Self::InvalidConnectionHeader => {
+                f.write_str("header `Connection` does not include `upgrade`")
+            }
+            Self::InvalidUpgradeHeader => f.write_str("header `Upgrade` is not `websocket`"),
+            Self::InvalidAcceptKey => f.write_str("header `Sec-WebSocket-Accept` is invalid"),
+            Self::InvalidProtocol(protocol) => {
+                write!(f, "server picked an unrequested protocol: {protocol:?}")
+            }
+            Self::Upgrade(err) => write!(f, "failed to upgrade: {err}"),
+        }
+    }
+}
+
+impl Error for WebSocketUpgradeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Upgrade(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Insert headers of the handshake into the request and return the `Sec-WebSocket-Key`.
+pub(super) fn prepare_request<B>(req: &mut Request<B>, config: &UpgradeConfig) -> Result<String> {
+    let key = generate_key();
+    let headers = req.headers_mut();
+    headers.insert(header::CONNECTION, HEADERVALUE_UPGRADE);
+    headers.insert(header::UPGRADE, HEADERVALUE_WEBSOCKET);
+    headers.insert(header::SEC_WEBSOCKET_VERSION, HEADERVALUE_VERSION);
+    headers.insert(
+        header::SEC_WEBSOCKET_KEY,
+        HeaderValue::from_str(&key).map_err(request_error)?,
+    );
+    if !config.protocols.is_empty() {
+        let protocols = config.protocols.join(", ");
+        headers.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_str(&protocols).map_err(request_error)?,
+        );
+    }
+    Ok(key)
+}
+
+/// Validate the handshake response and wait for the upgraded connection.
+pub(super) async fn upgrade<B>(
+    mut resp: Response<B>,
+    key: &str,
+    config: UpgradeConfig,
+) -> Result<WebSocket> {
+    if let Err(err) = check_response(resp.status(), resp.headers(), key, &config) {
+        return Err(request_error(err));
+    }
+    let upgraded = hyper::upgrade::on(&mut resp)
+        .await
+        .map_err(|err| request_error(WebSocketUpgradeError::Upgrade(err)))?;
+    let inner = WebSocketStream::from_raw_socket(
+        TokioIo::new(upgraded),
+        protocol::Role::Client,
+        Some(config.config),
+    )
+    .await;
+    let (parts, _) = resp.into_parts();
+    Ok(WebSocket {
+        inner,
+        protocol: parts.headers.get(header::SEC_WEBSOCKET_PROTOCOL).cloned(),
+        headers: parts.headers,
+    })
+}
+
+fn check_response(
+    status: StatusCode,
+    headers: &HeaderMap,
+    key: &str,
+    config: &UpgradeConfig,
+) -> Result<(), WebSocketUpgradeError> {
+    if status != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(WebSocketUpgradeError::InvalidStatus(status));
+    }
+    let connection = headers
+        .get(header::CONNECTION)
+        .and_then(|v| simdutf8::basic::from_utf8(v.as_bytes()).ok())
+        .unwrap_or_default();
+    if !connection
+        .split(',')
+        .any(|v| v.trim().eq_ignore_ascii_case("upgrade"))
+    {
+        return Err(WebSocketUpgradeError::InvalidConnectionHeader);
+    }
+    if !headers
+        .get(header::UPGRADE)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"websocket"))
+    {
+        return Err(WebSocketUpgradeError::InvalidUpgradeHeader);
+    }
+    if headers
+        .get(header::SEC_WEBSOCKET_ACCEPT)
+        .is_none_or(|v| v.as_bytes() != derive_accept_key(key.as_bytes()).as_bytes())
+    {
+        return Err(WebSocketUpgradeError::InvalidAcceptKey);
+    }
+    if let Some(protocol) = headers.get(header::SEC_WEBSOCKET_PROTOCOL) {
+        if !config
+            .protocols
+            .iter()
+            .any(|p| p.as_bytes() == protocol.as_bytes())
+        {
+            return Err(WebSocketUpgradeError::InvalidProtocol(protocol.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod ws_tests {
+    use super::*;
+
+    fn switching_headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(header::UPGRADE, HEADERVALUE_WEBSOCKET);
+        headers.insert(
+            header::SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_str(&derive_accept_key(key.as_bytes())).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn prepare() {
+        let mut req = Request::new(());
+        let config = UpgradeConfig::new().protocols(["chat", "graphql-ws"]);
+        let key = prepare_request(&mut req, &config).unwrap();
+        let headers = req.headers();
+        assert_eq!(headers[header::SEC_WEBSOCKET_KEY], key.as_str());
+        assert_eq!(headers[header::SEC_WEBSOCKET_VERSION], "13");
+        assert_eq!(headers[header::SEC_WEBSOCKET_PROTOCOL], "chat, graphql-ws");
+    }
+
+    #[test]
+    fn check() {
+        let key = generate_key();
+        let config = UpgradeConfig::new().protocols(["chat"]);
+        let headers = switching_headers(&key);
+        assert!(check_response(StatusCode::SWITCHING_PROTOCOLS, &headers, &key, &config).is_ok());
+
+        assert!(matches!(
+            check_response(StatusCode::OK, &headers, &key, &config),
+            Err(WebSocketUpgradeError::InvalidStatus(StatusCode::OK))
+        ));
+        assert!(matches!(
+            check_response(
+                StatusCode::SWITCHING_PROTOCOLS,
+                &headers,
+                &generate_key(),
+                &config
+            ),
+            Err(WebSocketUpgradeError::InvalidAcceptKey)
+        ));
+
+        let mut headers = switching_headers(&key);
+        headers.insert(header::SEC_WEBSOCKET_PROTOCOL, "soap".parse().unwrap());
+        assert!(matches!(
+            check_response(StatusCode::SWITCHING_PROTOCOLS, &headers, &key, &config),
+            Err(WebSocketUpgradeError::InvalidProtocol(_))
+        ));
+
+        let mut headers = switching_headers(&key);
+        headers.remove(header::UPGRADE);
+        assert!(matches!(
+            check_response(StatusCode::SWITCHING_PROTOCOLS, &headers, &key, &config),
+            Err(WebSocketUpgradeError::InvalidUpgradeHeader)
+        ));
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn echo() {
+        use std::net::SocketAddr;
+
+        use futures_util::{sink::SinkExt, stream::StreamExt};
+        use volo::net::Address;
+
+        use crate::{
+            Server,
+            client::Client,
+            server::{
+                route::{Router, get},
+                utils::ws::{WebSocket as ServerWebSocket, WebSocketUpgrade},
+            },
+        };
+
+        async fn echo(mut socket: ServerWebSocket) {
+            while let Some(Ok(msg)) = socket.next().await {
+                if msg.is_ping() || msg.is_pong() {
+                    continue;
+                }
+                if socket.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        async fn handler(ws: WebSocketUpgrade) -> Response {
+            ws.protocols(["chat"]).on_upgrade(echo)
+        }
+
+        let addr: SocketAddr = "127.0.0.1:25232".parse().unwrap();
+        let app: Router = Router::new().route("/ws", get(handler));
+        tokio::spawn(Server::new(app).run(Address::Ip(addr)));
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+
+        let client = Client::builder().build().unwrap();
+        let mut ws = client
+            .get(format!("http://{addr}/ws"))
+            .upgrade_with(UpgradeConfig::new().protocols(["chat"]))
+            .await
+            .unwrap();
+        assert_eq!(ws.protocol(), Some("chat"));
+
+        let input = Message::Text("foobar".into());
+        ws.send(input.clone()).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), input);
+
+        ws.send(Message::Ping("foobar".into())).await.unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Pong("foobar".into())
+        );
+
+        ws.close(None).await.unwrap();
+        while let Some(Ok(msg)) = ws.next().await {
+            assert!(msg.is_close());
+        }
+
+        // the server does not support websocket on this path
+        let Err(err) = client.get(format!("http://{addr}/")).upgrade().await else {
+            panic!("upgrade should fail");
+        };
+        assert!(
+            err.source()
+                .and_then(|e| e.downcast_ref::<WebSocketUpgradeError>())
+                .is_some()
+        );
+    }
+}
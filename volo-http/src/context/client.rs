@@ -2,10 +2,15 @@
 
 use std::time::Duration;
 
-use chrono::{DateTime, Local};
 use volo::{
+    FastStr,
     context::{Reusable, Role, RpcCx, RpcInfo},
     newtype_impl_context,
+    timeout::TimeoutConfig,
+    util::{
+        time::Timestamp,
+        timing::{ConnectTimestamps, Phase, Timing},
+    },
 };
 
 use crate::{
@@ -28,6 +33,38 @@ impl ClientContext {
             },
         ))
     }
+
+    /// Get the timing breakdown of the request after it returns from the client, see
+    /// [`volo::util::timing`].
+    ///
+    /// The [`Phase::FirstByte`] is the time from the request is sent, i.e., the connection is
+    /// established if any, to the headers of the response are received. The body of response is
+    /// read after the client returns, so [`Phase::Read`] and [`Phase::Decode`] are not recorded.
+    ///
+    /// The transport timestamps are only recorded if
+    /// [`ClientBuilder::stat_enable`](crate::client::ClientBuilder::stat_enable) is true.
+    pub fn timing(&self) -> Timing {
+        let stats = &self.stats;
+        let mut timing = Timing::new(stats.call_start_at, stats.call_end_at)
+            .with_trace_id(stats.trace_id.clone());
+        timing.push(Phase::Resolve, stats.resolve_start_at, stats.resolve_end_at);
+        timing.push(Phase::Connect, stats.connect_start_at, stats.connect_end_at);
+        timing.push(
+            Phase::TlsHandshake,
+            stats.tls_handshake_start_at,
+            stats.tls_handshake_end_at,
+        );
+        let sent_at = [
+            stats.transport_start_at,
+            stats.connect_end_at,
+            stats.tls_handshake_end_at,
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        timing.push(Phase::FirstByte, sent_at, stats.transport_end_at);
+        timing
+    }
 }
 
 impl Default for ClientContext {
@@ -68,20 +105,63 @@ impl ClientCxInner {
 /// This is unstable now and may be changed in the future.
 #[derive(Debug, Default, Clone)]
 pub struct ClientStats {
-    transport_start_at: Option<DateTime<Local>>,
-    transport_end_at: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    call_start_at: Option<Timestamp>,
+    call_end_at: Option<Timestamp>,
+    transport_start_at: Option<Timestamp>,
+    transport_end_at: Option<Timestamp>,
+
+    // connecting, see `volo::util::timing`
+    resolve_start_at: Option<Timestamp>,
+    resolve_end_at: Option<Timestamp>,
+    connect_start_at: Option<Timestamp>,
+    connect_end_at: Option<Timestamp>,
+    tls_handshake_start_at: Option<Timestamp>,
+    tls_handshake_end_at: Option<Timestamp>,
 }
 
 impl ClientStats {
+    stat_impl!(call_start_at);
+    stat_impl!(call_end_at);
     stat_impl!(transport_start_at);
     stat_impl!(transport_end_at);
+    stat_impl!(resolve_start_at);
+    stat_impl!(resolve_end_at);
+    stat_impl!(connect_start_at);
+    stat_impl!(connect_end_at);
+    stat_impl!(tls_handshake_start_at);
+    stat_impl!(tls_handshake_end_at);
+
+    /// Get the request id or trace id of the request, which is set by the users or middlewares
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    /// Set the request id or trace id of the request
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
+
+    pub(crate) fn record_connect(&mut self, timestamps: ConnectTimestamps) {
+        self.resolve_start_at = timestamps.resolve_start_at;
+        self.resolve_end_at = timestamps.resolve_end_at;
+        self.connect_start_at = timestamps.connect_start_at;
+        self.connect_end_at = timestamps.connect_end_at;
+        self.tls_handshake_start_at = timestamps.tls_handshake_start_at;
+        self.tls_handshake_end_at = timestamps.tls_handshake_end_at;
+    }
 }
 
 /// Configuration of the request
 #[derive(Clone, Debug, Default)]
 pub struct Config {
-    /// Timeout of the current request
+    /// Timeout of the current request, which is applied to each attempt if the request is retried
     pub timeout: Option<Duration>,
+    /// Timeout of the whole request including retries
+    pub total_timeout: Option<Duration>,
 }
 
 impl Config {
@@ -102,10 +182,35 @@ impl Config {
     pub fn set_timeout(&mut self, timeout: Option<Duration>) {
         self.timeout = timeout;
     }
+
+    /// Get current total timeout of the request
+    #[inline]
+    pub fn total_timeout(&self) -> Option<&Duration> {
+        self.total_timeout.as_ref()
+    }
+
+    /// Set total timeout to the request
+    #[inline]
+    pub fn set_total_timeout(&mut self, timeout: Option<Duration>) {
+        self.total_timeout = timeout;
+    }
+}
+
+impl TimeoutConfig for Config {
+    #[inline]
+    fn request_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    #[inline]
+    fn total_timeout(&self) -> Option<Duration> {
+        self.total_timeout
+    }
 }
 
 impl Reusable for Config {
     fn clear(&mut self) {
         self.timeout = None;
+        self.total_timeout = None;
     }
 }
@@ -14,3 +14,29 @@ pub use self::server::ServerContext;
 
 #[cfg(all(feature = "client", feature = "server"))]
 pub mod stat;
+
+/// The deadline of the current request.
+///
+/// It is set by [`DeadlineLayer`] of the server into [`METAINFO`], and the client will limit the
+/// timeout of requests by the remaining time before the deadline, so that the budget of a request
+/// is propagated to requests sent when handling it.
+///
+/// [`DeadlineLayer`]: crate::server::layer::DeadlineLayer
+/// [`METAINFO`]: metainfo::METAINFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline(pub std::time::Instant);
+
+impl Deadline {
+    /// Get the deadline of the current request from [`METAINFO`](metainfo::METAINFO).
+    pub fn current() -> Option<Self> {
+        metainfo::METAINFO
+            .try_with(|metainfo| metainfo.borrow().get::<Self>().copied())
+            .ok()
+            .flatten()
+    }
+
+    /// The remaining time before the deadline, it is zero if the deadline has been exceeded.
+    pub fn remaining(&self) -> std::time::Duration {
+        self.0.saturating_duration_since(std::time::Instant::now())
+    }
+}
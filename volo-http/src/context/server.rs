@@ -1,10 +1,13 @@
 //! Context and its utilities of server
 
-use chrono::{DateTime, Local};
+use std::time::{Duration, Instant};
+
 use volo::{
+    FastStr,
     context::{Context, Reusable, Role, RpcCx, RpcInfo},
     net::Address,
     newtype_impl_context,
+    util::time::Timestamp,
 };
 
 use crate::{
@@ -48,24 +51,48 @@ pub struct ServerCxInner {
 
     /// Statistics of the request
     pub stats: ServerStats,
+
+    /// Deadline of the request
+    ///
+    /// See [`DeadlineLayer`] for more details.
+    ///
+    /// [`DeadlineLayer`]: crate::server::layer::DeadlineLayer
+    pub deadline: Option<Instant>,
 }
 
 impl ServerCxInner {
     impl_getter!(params, PathParamsVec);
     impl_getter!(stats, ServerStats);
+
+    /// Get the deadline of the request.
+    #[inline]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Get the remaining time before the deadline of the request.
+    ///
+    /// It is zero if the deadline has been exceeded, or [`None`] if there is no deadline.
+    #[inline]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
 }
 
 /// Statistics of server
 #[derive(Debug, Default, Clone)]
 pub struct ServerStats {
-    read_header_start: Option<DateTime<Local>>,
-    read_header_finish: Option<DateTime<Local>>,
-    read_body_start: Option<DateTime<Local>>,
-    read_body_finish: Option<DateTime<Local>>,
-    handle_start: Option<DateTime<Local>>,
-    handle_finish: Option<DateTime<Local>>,
-    write_start: Option<DateTime<Local>>,
-    write_finish: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    read_header_start: Option<Timestamp>,
+    read_header_finish: Option<Timestamp>,
+    read_body_start: Option<Timestamp>,
+    read_body_finish: Option<Timestamp>,
+    handle_start: Option<Timestamp>,
+    handle_finish: Option<Timestamp>,
+    write_start: Option<Timestamp>,
+    write_finish: Option<Timestamp>,
 }
 
 impl ServerStats {
@@ -77,6 +104,18 @@ impl ServerStats {
     stat_impl!(handle_finish);
     stat_impl!(write_start);
     stat_impl!(write_finish);
+
+    /// Get the request id or trace id of the request, which is set by the users or middlewares
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    /// Set the request id or trace id of the request
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
 }
 
 /// Configuration of the request
@@ -1,16 +1,16 @@
 //! HTTP request and response statistics shared across client and server contexts.
 
-use chrono::{DateTime, Local};
 use http::{method::Method, status::StatusCode, uri::Uri};
+use volo::util::time::Timestamp;
 
 /// Shared HTTP statistics captured for every request on both client and server sides
 #[derive(Debug, Default, Clone)]
 pub struct CommonStats {
     /// The time at which request processing began
-    pub process_start_time: DateTime<Local>,
+    pub process_start_time: Option<Timestamp>,
 
     /// The time at which request processing completed
-    pub process_end_time: DateTime<Local>,
+    pub process_end_time: Option<Timestamp>,
 
     /// The HTTP method of the request (e.g. `GET`, `POST`)
     pub method: Method,
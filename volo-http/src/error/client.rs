@@ -1,17 +1,24 @@
 //! Generic error types for client
 
-use std::{error::Error, fmt, net::SocketAddr};
+use std::{error::Error, fmt, net::SocketAddr, time::Duration};
 
 use http::uri::Uri;
 use paste::paste;
-use volo::{context::Endpoint, net::Address};
+use volo::{
+    context::Endpoint,
+    error::{ConnectError, ErrorCategory, ErrorOrigin, RpcError, Sources},
+    loadbalance::error::Retryable,
+    net::Address,
+    timeout::{TimeoutError, TimeoutKind},
+};
 
 use super::BoxError;
-use crate::body::BodyConvertError;
+use crate::{body::BodyConvertError, client::layer::StatusCodeError};
 
 /// [`Result`](std::result::Result) with [`ClientError`] as its error by default.
 pub type Result<T, E = ClientError> = std::result::Result<T, E>;
 
+#[cfg(not(target_family = "wasm"))]
 macro_rules! tri {
     ($result:expr) => {
         match $result {
@@ -29,6 +36,7 @@ macro_rules! tri {
         }
     };
 }
+#[cfg(not(target_family = "wasm"))]
 pub(crate) use tri;
 
 /// Generic client error
@@ -133,6 +141,16 @@ impl ClientError {
     pub fn addr(&self) -> Option<&SocketAddr> {
         self.addr.as_ref()
     }
+
+    /// Get a reference to the [`TimeoutError`] telling which timeout fired if the request is
+    /// timeout
+    pub fn timeout_error(&self) -> Option<&TimeoutError> {
+        TimeoutError::find(self.source.as_deref()?)
+    }
+
+    fn status_code_error(&self) -> Option<&StatusCodeError> {
+        Sources::new(self.source.as_deref()?).find_map(|err| err.downcast_ref())
+    }
 }
 
 impl fmt::Display for ClientError {
@@ -236,6 +254,58 @@ where
     ClientError::new(ErrorKind::Other, Some(error))
 }
 
+impl Retryable for ClientError {
+    /// Connect errors and the connect or request timeouts are retryable, note that the
+    /// [`Retry`](crate::client::layer::Retry) layer also considers whether the request is
+    /// idempotent.
+    fn retryable(&self) -> bool {
+        match self.timeout_error() {
+            Some(err) => err.retryable(),
+            None => self.kind == ErrorKind::Connect,
+        }
+    }
+}
+
+/// Only the [`StatusCodeError`] returned by the
+/// [`FailOnStatus`](crate::client::layer::FailOnStatus) layer is taken as replied by the server,
+/// whose category is [`ErrorCategory::Biz`].
+impl RpcError for ClientError {
+    fn category(&self) -> ErrorCategory {
+        if self.timeout_error().is_some() {
+            return ErrorCategory::Timeout;
+        }
+        if self.status_code_error().is_some() {
+            return ErrorCategory::Biz;
+        }
+        match self.kind {
+            ErrorKind::Connect => ErrorCategory::Connect,
+            ErrorKind::Request => ErrorCategory::Transport,
+            ErrorKind::Body => ErrorCategory::Protocol,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        match self.status_code_error() {
+            Some(_) => ErrorOrigin::Remote,
+            None => ErrorOrigin::Local,
+        }
+    }
+
+    fn peer_addr(&self) -> Option<Address> {
+        match self.addr {
+            Some(addr) => Some(Address::Ip(addr)),
+            None => Some(ConnectError::find(self.source.as_deref()?)?.addr().clone()),
+        }
+    }
+}
+
+impl From<TimeoutError> for ClientError {
+    fn from(value: TimeoutError) -> Self {
+        ClientError::new(ErrorKind::Request, Some(value))
+    }
+}
+
 impl From<BodyConvertError> for ClientError {
     fn from(value: BodyConvertError) -> Self {
         ClientError::new(ErrorKind::Body, Some(BoxError::from(value)))
@@ -308,23 +378,45 @@ macro_rules! simple_error {
 
 simple_error!(Builder => NoAddress => "missing target address");
 simple_error!(Builder => BadScheme(::http::uri::Scheme) => "bad scheme");
-#[cfg(not(all(feature = "http1", feature = "http2")))]
+#[cfg(all(
+    not(target_family = "wasm"),
+    not(all(feature = "http1", feature = "http2"))
+))]
 simple_error!(Builder => BadVersion => "bad http protocol version");
 simple_error!(Builder => BadHostName(::faststr::FastStr) => "bad host name");
 simple_error!(Builder => SchemeUnavailable => "scheme is unavailable in current target");
 simple_error!(Builder => PortUnavailable => "port is unavailable in current target");
+#[cfg(not(target_family = "wasm"))]
 simple_error!(Connect => Retry => "retry");
-simple_error!(Request => Timeout => "request timeout");
+#[cfg(not(target_family = "wasm"))]
+simple_error!(Connect => PoolExhausted => "connection pool exhausted");
+#[cfg(not(target_family = "wasm"))]
+simple_error!(Connect => PoolTimeout => "timeout waiting for an available connection");
+simple_error!(Request => TooManyRedirects => "too many redirects");
 simple_error!(LoadBalance => NoAvailableEndpoint => "no available endpoint");
 
+/// Request error "request timeout"
+#[deprecated(note = "use `volo::timeout::TimeoutError`, which tells which timeout fired")]
+pub type Timeout = TimeoutError;
+
+/// Create a [`ClientError`] of the request timeout, whose duration is unknown.
+#[deprecated(note = "use `ClientError::from(TimeoutError)`, which tells which timeout fired")]
+pub fn timeout() -> ClientError {
+    ClientError::from(TimeoutError::new(TimeoutKind::Request, Duration::ZERO))
+}
+
 #[cfg(test)]
 mod client_error_tests {
-    use std::error::Error;
+    use std::{error::Error, time::Duration};
+
+    use volo::timeout::{TimeoutError, TimeoutKind};
 
     use crate::error::client::{
-        BadHostName, BadScheme, NoAddress, NoAvailableEndpoint, Timeout, bad_host_name, bad_scheme,
-        no_address, no_available_endpoint, timeout,
+        BadHostName, BadScheme, ClientError, ErrorKind, NoAddress, NoAvailableEndpoint,
+        bad_host_name, bad_scheme, no_address, no_available_endpoint,
     };
+    #[allow(deprecated)]
+    use crate::error::client::{Timeout, timeout};
 
     #[test]
     fn types_downcast() {
@@ -341,7 +433,10 @@ mod client_error_tests {
                 .unwrap()
                 .is::<BadHostName>()
         );
-        assert!(timeout().source().unwrap().is::<Timeout>());
+        #[allow(deprecated)]
+        {
+            assert!(timeout().source().unwrap().is::<Timeout>());
+        }
         assert!(
             no_available_endpoint()
                 .source()
@@ -349,4 +444,52 @@ mod client_error_tests {
                 .is::<NoAvailableEndpoint>()
         );
     }
+
+    #[test]
+    fn timeout_error() {
+        let err = ClientError::from(TimeoutError::new(
+            TimeoutKind::Request,
+            Duration::from_secs(1),
+        ));
+        assert_eq!(err.kind(), &ErrorKind::Request);
+        assert!(err.source().unwrap().is::<TimeoutError>());
+        assert_eq!(err.timeout_error().unwrap().kind(), TimeoutKind::Request);
+
+        // connect timeouts are returned as io errors by the connector
+        let io_err =
+            TimeoutError::new(TimeoutKind::Connect, Duration::from_secs(1)).into_io_error();
+        let err = super::connect_error(io_err);
+        assert_eq!(err.timeout_error().unwrap().kind(), TimeoutKind::Connect);
+    }
+
+    #[test]
+    fn rpc_error() {
+        use std::{io, net::SocketAddr};
+
+        use volo::{
+            error::{ConnectError, ErrorCategory, ErrorOrigin, RpcError},
+            loadbalance::error::Retryable,
+            net::Address,
+        };
+
+        let addr = Address::from(SocketAddr::from(([127, 0, 0, 1], 8888)));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let err = super::connect_error(ConnectError::new(addr.clone(), refused).into_io_error());
+        assert_eq!(err.category(), ErrorCategory::Connect);
+        assert_eq!(err.origin(), ErrorOrigin::Local);
+        assert_eq!(err.peer_addr(), Some(addr));
+        assert!(err.retryable());
+
+        let err = ClientError::from(TimeoutError::new(
+            TimeoutKind::Total,
+            Duration::from_secs(1),
+        ));
+        assert_eq!(err.category(), ErrorCategory::Timeout);
+        assert!(!err.retryable());
+
+        let err = no_address();
+        assert_eq!(err.category(), ErrorCategory::Other);
+        assert_eq!(err.peer_addr(), None);
+        assert!(!err.retryable());
+    }
 }
@@ -32,5 +32,25 @@ pub mod prelude {
 #[doc(hidden)]
 pub use self::prelude::*;
 
-#[cfg(not(any(feature = "http1", feature = "http2")))]
+#[cfg(all(
+    not(target_family = "wasm"),
+    not(any(feature = "http1", feature = "http2"))
+))]
 compile_error!("At least one of features \"http1\" and \"http2\" needs to be enabled!");
+
+// Requests are sent by the `fetch` of browsers on `wasm32`, see `client::transport::fetch`.
+#[cfg(all(
+    target_family = "wasm",
+    any(
+        feature = "server",
+        feature = "http1",
+        feature = "http2",
+        feature = "http3",
+        feature = "__tls",
+        feature = "ws"
+    )
+))]
+compile_error!(
+    "Only the client is available on `wasm32`, features \"server\", \"http1\", \"http2\", \
+     \"http3\", \"tls\" and \"ws\" need to be disabled!"
+);
@@ -1,8 +1,143 @@
 //! Response types for client and server.
 
+#[cfg(feature = "json")]
+use std::future::Future;
+
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+
 use crate::body::Body;
+#[cfg(feature = "json")]
+use crate::body::{BodyConversion, BodyConvertError, ContentTypeCheck};
 
 /// [`Response`] with [`Body`] as default body
 ///
 /// [`Response`]: http::response::Response
 pub type Response<B = Body> = http::response::Response<B>;
+
+/// Conversions of [`Response`] that depend on its headers, which are unknown to
+/// [`BodyConversion`].
+#[cfg(feature = "json")]
+pub trait ResponseConversion {
+    /// Consume a response and convert its body into an instance with [`DeserializeOwned`].
+    ///
+    /// `Content-Type` of the response is checked before deserializing, so that an html error page
+    /// from a gateway will not be reported as a confusing decode error. The strictness is the
+    /// [`ContentTypeCheck`] in extensions of the response, or [`ContentTypeCheck::Lenient`] by
+    /// default.
+    ///
+    /// The following media types are considered as json:
+    ///
+    /// - `application/json`
+    /// - `application/problem+json` ([RFC 9457](https://datatracker.ietf.org/doc/html/rfc9457))
+    /// - any vendored type with `+json` suffix, e.g., `application/vnd.github+json`
+    ///
+    /// Json must be encoded in UTF-8, so a `charset` parameter other than `utf-8` is rejected.
+    ///
+    /// Note that [`BodyConversion::into_json`] is also applicable to a response, use
+    /// `ResponseConversion::into_json(resp)` if both traits are imported.
+    fn into_json<T>(self) -> impl Future<Output = Result<T, BodyConvertError>> + Send
+    where
+        T: DeserializeOwned;
+}
+
+#[cfg(feature = "json")]
+impl<B> ResponseConversion for Response<B>
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+{
+    fn into_json<T>(self) -> impl Future<Output = Result<T, BodyConvertError>> + Send
+    where
+        T: DeserializeOwned,
+    {
+        let check = self.extensions().get().copied().unwrap_or_default();
+        let checked = ContentTypeCheck::check(check, self.headers());
+        let body = self.into_body();
+        async move {
+            checked?;
+            BodyConversion::into_json(body).await
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod response_tests {
+    use http::header;
+    use serde::Deserialize;
+
+    use super::{Response, ResponseConversion};
+    use crate::body::{Body, BodyConversion, BodyConvertError, ContentTypeCheck};
+
+    // `BodyConversion::into_json` is also applicable to responses
+    async fn into_json(resp: Response) -> Result<Msg, BodyConvertError> {
+        ResponseConversion::into_json(resp).await
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Eq)]
+    struct Msg {
+        msg: String,
+    }
+
+    fn resp(content_type: Option<&'static str>, body: &'static str) -> Response {
+        let mut builder = Response::builder();
+        if let Some(content_type) = content_type {
+            builder = builder.header(header::CONTENT_TYPE, content_type);
+        }
+        builder.body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_types() {
+        for ty in [
+            "application/json",
+            "application/json; charset=utf-8",
+            "application/json;charset=UTF8",
+            "application/problem+json",
+            "application/vnd.github+json",
+        ] {
+            let msg = into_json(resp(Some(ty), r#"{"msg":"hello"}"#))
+                .await
+                .unwrap();
+            assert_eq!(msg.msg, "hello");
+        }
+    }
+
+    #[tokio::test]
+    async fn content_type_errors() {
+        let err = into_json(resp(Some("text/html"), "<html></html>"))
+            .await
+            .unwrap_err();
+        assert!(err.is_content_type_error());
+        assert!(!err.is_decode_error());
+
+        let err = into_json(resp(Some("application/json; charset=gbk"), r#"{"msg":""}"#))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BodyConvertError::UnsupportedCharset(_)));
+
+        // lenient mode allows missing content type
+        assert!(into_json(resp(None, r#"{"msg":""}"#)).await.is_ok());
+        let mut strict = resp(None, r#"{"msg":""}"#);
+        strict.extensions_mut().insert(ContentTypeCheck::Strict);
+        let err = into_json(strict).await.unwrap_err();
+        assert!(matches!(err, BodyConvertError::InvalidContentType(None)));
+
+        let mut disabled = resp(Some("text/plain"), r#"{"msg":""}"#);
+        disabled.extensions_mut().insert(ContentTypeCheck::Disabled);
+        assert!(into_json(disabled).await.is_ok());
+
+        // the body alone does not know its content type
+        let body = resp(Some("text/plain"), r#"{"msg":""}"#).into_body();
+        assert!(body.into_json::<Msg>().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn decode_error() {
+        let err = into_json(resp(Some("application/json"), "{"))
+            .await
+            .unwrap_err();
+        assert!(err.is_decode_error());
+        assert!(!err.is_content_type_error());
+    }
+}
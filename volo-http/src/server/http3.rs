@@ -0,0 +1,245 @@
+//! HTTP/3 server implementation
+//!
+//! HTTP/3 support is experimental, see [`Server::http3`] for more details.
+//!
+//! [`Server::http3`]: super::Server::http3
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue, header};
+use motore::{BoxError, service::Service};
+use parking_lot::RwLock;
+use scopeguard::defer;
+use tokio::sync::Notify;
+use volo::{
+    context::Role,
+    net::{Address, tls::ServerTlsConfig},
+};
+
+use super::{
+    HyperService, IntoResponse, limit::Limits, span_provider::SpanProvider,
+    utils::early_hints::EarlyHints,
+};
+use crate::{
+    body::Body,
+    context::{ServerContext, server::Config},
+    request::Request,
+    response::Response,
+    utils::http3::{RecvBody, send_body},
+};
+
+const ALPN_H3: &[u8] = b"h3";
+
+/// Create a QUIC endpoint listening on the UDP `addr` by the rustls config of `tls_config`.
+pub(super) fn make_endpoint(
+    addr: SocketAddr,
+    tls_config: Option<&ServerTlsConfig>,
+) -> Result<quinn::Endpoint, BoxError> {
+    let tls_config = tls_config
+        .and_then(|config| config.acceptor.rustls_config())
+        .ok_or("HTTP/3 requires a tls config built by rustls")?;
+    let mut tls_config = (**tls_config).clone();
+    tls_config.alpn_protocols = vec![ALPN_H3.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls_config)?;
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    Ok(quinn::Endpoint::server(config, addr)?)
+}
+
+/// Value of the `Alt-Svc` header for advertising HTTP/3 on the `port`.
+pub(super) fn alt_svc(port: u16) -> HeaderValue {
+    HeaderValue::try_from(format!("h3=\":{port}\"; ma=86400"))
+        .expect("`Alt-Svc` should be a valid header value")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn serve<S, SP>(
+    endpoint: quinn::Endpoint,
+    service: S,
+    config: Config,
+    exit_flag: Arc<RwLock<bool>>,
+    conn_cnt: Arc<AtomicUsize>,
+    exit_notify: Arc<Notify>,
+    span_provider: SP,
+    limits: Limits,
+) where
+    S: Service<ServerContext, Request> + Clone + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    S::Error: IntoResponse,
+    SP: SpanProvider + Clone + Send + Sync + 'static,
+{
+    loop {
+        if *exit_flag.read() {
+            break;
+        }
+
+        let Some(incoming) = endpoint.accept().await else {
+            break;
+        };
+        let peer = Address::from(incoming.remote_address());
+        tracing::trace!("accept quic connection from: {peer:?}");
+
+        let service = HyperService {
+            inner: service.clone(),
+            peer,
+            config: config.clone(),
+            span_provider: span_provider.clone(),
+            limits: limits.clone(),
+            inflight: limits.inflight(),
+            keep_alive: None,
+            alt_svc: None,
+        };
+
+        tokio::spawn(serve_conn(
+            incoming,
+            service,
+            conn_cnt.clone(),
+            exit_notify.clone(),
+        ));
+    }
+}
+
+async fn serve_conn<S, SP>(
+    incoming: quinn::Incoming,
+    service: HyperService<S, SP>,
+    conn_cnt: Arc<AtomicUsize>,
+    exit_notify: Arc<Notify>,
+) where
+    S: Service<ServerContext, Request> + Clone + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    S::Error: IntoResponse,
+    SP: SpanProvider + Clone + Send + Sync + 'static,
+{
+    conn_cnt.fetch_add(1, Ordering::Relaxed);
+    defer! {
+        conn_cnt.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    let conn = match incoming.await {
+        Ok(conn) => conn,
+        Err(err) => {
+            tracing::trace!("[Volo-HTTP] quic handshake error: {err:?}");
+            return;
+        }
+    };
+    let mut h3_conn =
+        match h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn)).await {
+            Ok(h3_conn) => h3_conn,
+            Err(err) => {
+                tracing::debug!("[Volo-HTTP] http3 connection error: {err:?}");
+                return;
+            }
+        };
+    let _conn_event = volo::event::connection("http", Role::Server, &service.peer);
+
+    let notified = exit_notify.notified();
+    tokio::pin!(notified);
+    let mut shutting_down = false;
+
+    loop {
+        let res = tokio::select! {
+            _ = &mut notified, if !shutting_down => None,
+            res = h3_conn.accept() => Some(res),
+        };
+        let Some(res) = res else {
+            tracing::trace!("[Volo-HTTP] closing a pending http3 connection");
+            // Graceful shutdown, requests in flight are still served.
+            shutting_down = true;
+            if let Err(err) = h3_conn.shutdown(0).await {
+                tracing::debug!("[Volo-HTTP] http3 connection error: {err:?}");
+                break;
+            }
+            continue;
+        };
+        match res {
+            Ok(Some(resolver)) => {
+                tokio::spawn(serve_request(resolver, service.clone()));
+            }
+            // The connection is closed gracefully.
+            Ok(None) => break,
+            Err(err) => {
+                if !err.is_h3_no_error() {
+                    tracing::debug!("[Volo-HTTP] http3 connection error: {err:?}");
+                }
+                break;
+            }
+        }
+    }
+}
+
+async fn serve_request<S, SP>(
+    resolver: h3::server::RequestResolver<h3_quinn::Connection, Bytes>,
+    service: HyperService<S, SP>,
+) where
+    S: Service<ServerContext, Request> + Clone + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    S::Error: IntoResponse,
+    SP: SpanProvider + Clone + Send + Sync + 'static,
+{
+    let (req, stream) = match resolver.resolve_request().await {
+        Ok(req) => req,
+        Err(err) => {
+            tracing::debug!("[Volo-HTTP] failed to receive http3 request: {err:?}");
+            return;
+        }
+    };
+    let (mut send, recv) = stream.split();
+
+    let mut req = req.map(|()| Body::from_body(RecvBody::new(recv)));
+    // HTTP/3 uses `:authority` instead of `Host`, but extractors and routers depend on `Host`.
+    if !req.headers().contains_key(header::HOST) {
+        if let Some(host) = req
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        {
+            req.headers_mut().insert(header::HOST, host);
+        }
+    }
+
+    let (early_hints, mut hints) = EarlyHints::interim();
+    req.extensions_mut().insert(early_hints);
+
+    let resp = service.serve(req);
+    tokio::pin!(resp);
+    // send hints as interim responses until the final response is ready
+    let resp: Response = loop {
+        tokio::select! {
+            resp = &mut resp => break resp,
+            Some(headers) = hints.recv() => send_early_hints(&mut send, headers).await,
+        }
+    };
+    // hints sent right before the handler returns
+    while let Ok(headers) = hints.try_recv() {
+        send_early_hints(&mut send, headers).await;
+    }
+    let (parts, body) = resp.into_parts();
+    if let Err(err) = send
+        .send_response(http::Response::from_parts(parts, ()))
+        .await
+    {
+        tracing::debug!("[Volo-HTTP] failed to send http3 response: {err:?}");
+        return;
+    }
+    if let Err(err) = send_body(&mut send, body).await {
+        tracing::debug!("[Volo-HTTP] failed to send http3 response body: {err:?}");
+    }
+}
+
+async fn send_early_hints<S>(send: &mut h3::server::RequestStream<S, Bytes>, headers: HeaderMap)
+where
+    S: h3::quic::SendStream<Bytes>,
+{
+    let mut interim = http::Response::new(());
+    *interim.status_mut() = http::StatusCode::EARLY_HINTS;
+    *interim.headers_mut() = headers;
+    if let Err(err) = send.send_response(interim).await {
+        tracing::debug!("[Volo-HTTP] failed to send http3 early hints: {err:?}");
+    }
+}
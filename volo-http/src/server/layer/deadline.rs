@@ -0,0 +1,254 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use http::{StatusCode, header::HeaderName};
+use metainfo::{METAINFO, MetaInfo};
+use motore::{Service, layer::Layer};
+
+use crate::{
+    context::{Deadline, ServerContext},
+    request::Request,
+    response::Response,
+    server::IntoResponse,
+};
+
+const DEFAULT_DEADLINE_HEADER: &str = "x-request-timeout-ms";
+
+/// [`Layer`] for enforcing the deadline of requests from the header.
+///
+/// The header (`x-request-timeout-ms` by default) is parsed as the timeout of the request in
+/// milliseconds, and the deadline is set to [`ServerContext`] (see [`ServerCxInner::deadline`]).
+/// If the request is not completed before the deadline, it will be cancelled and a response with
+/// `504 Gateway Timeout` will be returned.
+///
+/// The deadline is also inserted into [`METAINFO`] as [`Deadline`], so that requests sent by the
+/// client of volo-http when handling the request will not exceed the remaining budget.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use volo_http::server::{
+///     layer::DeadlineLayer,
+///     route::{Router, get},
+/// };
+///
+/// async fn index() -> &'static str {
+///     "Hello, World"
+/// }
+///
+/// let router: Router = Router::new().route("/", get(index)).layer(
+///     DeadlineLayer::new()
+///         .default_timeout(Duration::from_secs(3))
+///         .max_timeout(Duration::from_secs(10)),
+/// );
+/// ```
+///
+/// [`ServerCxInner::deadline`]: crate::context::server::ServerCxInner::deadline
+#[derive(Clone, Debug)]
+pub struct DeadlineLayer {
+    header: HeaderName,
+    default_timeout: Option<Duration>,
+    max_timeout: Option<Duration>,
+}
+
+impl Default for DeadlineLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeadlineLayer {
+    /// Create a new [`DeadlineLayer`] parsing the `x-request-timeout-ms` header.
+    pub fn new() -> Self {
+        Self {
+            header: HeaderName::from_static(DEFAULT_DEADLINE_HEADER),
+            default_timeout: None,
+            max_timeout: None,
+        }
+    }
+
+    /// Set the header for parsing, its value should be the timeout in milliseconds.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the timeout for requests without the header or with an invalid value.
+    ///
+    /// Default is no timeout.
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum timeout, timeout from the header larger than it will be limited to it.
+    ///
+    /// Default is no limit.
+    pub fn max_timeout(mut self, timeout: Duration) -> Self {
+        self.max_timeout = Some(timeout);
+        self
+    }
+
+    fn timeout<B>(&self, req: &Request<B>) -> Option<Duration> {
+        let timeout = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok()?.trim().parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .or(self.default_timeout)?;
+        match self.max_timeout {
+            Some(max) => Some(timeout.min(max)),
+            None => Some(timeout),
+        }
+    }
+}
+
+impl<S> Layer<S> for DeadlineLayer
+where
+    S: Send + Sync + 'static,
+{
+    type Service = DeadlineService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        DeadlineService {
+            inner,
+            config: self,
+        }
+    }
+}
+
+/// [`DeadlineLayer`] generated [`Service`]
+///
+/// See [`DeadlineLayer`] for more details.
+#[derive(Clone)]
+pub struct DeadlineService<S> {
+    inner: S,
+    config: DeadlineLayer,
+}
+
+impl<S, B> Service<ServerContext, Request<B>> for DeadlineService<S>
+where
+    S: Service<ServerContext, Request<B>> + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    S::Error: IntoResponse,
+    B: Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(timeout) = self.config.timeout(&req) else {
+            return self
+                .inner
+                .call(cx, req)
+                .await
+                .map(IntoResponse::into_response);
+        };
+        let deadline = Instant::now() + timeout;
+        cx.deadline = Some(deadline);
+
+        let fut = async {
+            let _ = METAINFO.try_with(|metainfo| metainfo.borrow_mut().insert(Deadline(deadline)));
+            tokio::time::timeout(timeout, self.inner.call(cx, req)).await
+        };
+        let res = if METAINFO.try_with(|_| {}).is_ok() {
+            fut.await
+        } else {
+            METAINFO.scope(RefCell::new(MetaInfo::default()), fut).await
+        };
+
+        match res {
+            Ok(resp) => resp.map(IntoResponse::into_response),
+            Err(_) => {
+                tracing::debug!("[Volo-HTTP] request exceeded its deadline of {timeout:?}");
+                Ok(StatusCode::GATEWAY_TIMEOUT.into_response())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod deadline_tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use http::{Method, StatusCode};
+    use motore::{Service, layer::Layer, service::service_fn};
+
+    use super::DeadlineLayer;
+    use crate::{
+        body::BodyConversion,
+        context::{Deadline, ServerContext},
+        request::Request,
+        server::test_helpers::empty_cx,
+        utils::test_helpers::simple_req,
+    };
+
+    fn req(timeout: Option<&str>) -> Request<&'static str> {
+        let mut req = simple_req(Method::GET, "/", "");
+        if let Some(timeout) = timeout {
+            req.headers_mut()
+                .insert("x-request-timeout-ms", timeout.parse().unwrap());
+        }
+        req
+    }
+
+    async fn handler(cx: &mut ServerContext, _: Request<&str>) -> Result<String, Infallible> {
+        assert_eq!(Deadline::current().map(|d| d.0), cx.deadline());
+        let remaining = cx.remaining().map(|d| d.as_millis());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(format!("{remaining:?}"))
+    }
+
+    #[tokio::test]
+    async fn test_deadline_layer() {
+        let layer = DeadlineLayer::new().max_timeout(Duration::from_secs(1));
+        let service = layer.layer(service_fn(handler));
+
+        // no deadline
+        let resp = service.call(&mut empty_cx(), req(None)).await.unwrap();
+        assert_eq!(resp.into_body().into_string().await.unwrap(), "None");
+
+        // enough budget
+        let resp = service
+            .call(&mut empty_cx(), req(Some("800")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // limited by the max timeout
+        let resp = service
+            .call(&mut empty_cx(), req(Some("60000")))
+            .await
+            .unwrap();
+        let remaining = resp.into_body().into_string().await.unwrap();
+        let remaining = remaining
+            .trim_start_matches("Some(")
+            .trim_end_matches(')')
+            .parse::<u128>()
+            .unwrap();
+        assert!(remaining <= 1000);
+
+        // exceeded the deadline
+        let mut cx = empty_cx();
+        let resp = service.call(&mut cx, req(Some("10"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(cx.deadline().is_some());
+
+        // invalid values fallback to the default timeout
+        let layer = DeadlineLayer::new().default_timeout(Duration::from_millis(10));
+        let service = layer.layer(service_fn(handler));
+        let resp = service
+            .call(&mut empty_cx(), req(Some("abc")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
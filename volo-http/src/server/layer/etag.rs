@@ -0,0 +1,263 @@
+use http::{Method, StatusCode, header};
+use http_body::Body as _;
+use motore::{Service, layer::Layer};
+
+use crate::{
+    body::{Body, BodyConversion},
+    context::ServerContext,
+    request::Request,
+    response::Response,
+    server::IntoResponse,
+};
+
+/// The default max size of responses with generated ETags.
+pub const DEFAULT_MAX_SIZE: usize = 1024 * 1024;
+
+/// [`Layer`] for generating strong ETags of buffered responses and answering `If-None-Match`
+///
+/// The ETag is a hash of the body, so it's only generated for successful responses of `GET`
+/// requests whose sizes are known and not larger than the max size, i.e., responses built from
+/// bytes, strings or JSON, and streaming responses are never buffered. Responses which already
+/// have ETags are not changed.
+///
+/// If the `If-None-Match` of the request matches the ETag, the response is replaced by a
+/// `304 Not Modified` response without body, which keeps the headers of the original response.
+///
+/// The body is hashed as it is seen by this layer, so it should be applied outside of the
+/// compression layers, then the tag of each encoding is different as required by strong ETags.
+///
+/// See [`ETagLayer::new`] for more details.
+#[derive(Clone, Debug)]
+pub struct ETagLayer {
+    max_size: usize,
+}
+
+impl Default for ETagLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ETagLayer {
+    /// Create a new [`ETagLayer`] with the default max size [`DEFAULT_MAX_SIZE`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use volo_http::server::{
+    ///     layer::ETagLayer,
+    ///     route::{Router, get},
+    /// };
+    ///
+    /// async fn index() -> &'static str {
+    ///     "Hello, World"
+    /// }
+    ///
+    /// let router: Router = Router::new()
+    ///     .route("/", get(index))
+    ///     .layer(ETagLayer::new().max_size(64 * 1024));
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Set the max size of responses with generated ETags, larger responses are passed through.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl<S> Layer<S> for ETagLayer {
+    type Service = ETagService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ETagService {
+            service: inner,
+            max_size: self.max_size,
+        }
+    }
+}
+
+/// [`ETagLayer`] generated [`Service`]
+///
+/// See [`ETagLayer`] for more details.
+#[derive(Clone, Debug)]
+pub struct ETagService<S> {
+    service: S,
+    max_size: usize,
+}
+
+impl<S, B> Service<ServerContext, Request<B>> for ETagService<S>
+where
+    S: Service<ServerContext, Request<B>> + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    B: Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        if req.method() != Method::GET {
+            return Ok(self.service.call(cx, req).await?.into_response());
+        }
+        let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+        let resp = self.service.call(cx, req).await?.into_response();
+        if resp.status() != StatusCode::OK || resp.headers().contains_key(header::ETAG) {
+            return Ok(resp);
+        }
+        match resp.body().size_hint().exact() {
+            Some(size) if size <= self.max_size as u64 => {}
+            _ => return Ok(resp),
+        }
+
+        let (mut parts, body) = resp.into_parts();
+        let bytes = match body.into_bytes().await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                tracing::debug!("[Volo-HTTP] ETagLayer: failed to buffer the response: {err}");
+                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+        };
+        let etag = etag(&bytes);
+        let not_modified = if_none_match
+            .as_ref()
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| etag_matches(value, &etag));
+        // the tag only contains hex digits, `"` and `-`
+        parts.headers.insert(
+            header::ETAG,
+            header::HeaderValue::from_str(&etag).expect("valid etag"),
+        );
+        if not_modified {
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            return Ok(Response::from_parts(parts, Body::empty()));
+        }
+        Ok(Response::from_parts(parts, Body::from(bytes)))
+    }
+}
+
+/// Generates a strong ETag by the FNV-1a hash and the length of the body, which is stable
+/// across processes, so that instances serving the same content generate the same tag.
+fn etag(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{hash:016x}-{:x}\"", bytes.len())
+}
+
+/// Check `If-None-Match` by the weak comparison.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').any(|tag| {
+        let tag = tag.trim();
+        tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::{Method, StatusCode, header};
+    use motore::{Service, layer::Layer};
+
+    use crate::{
+        body::{Body, BodyConversion},
+        response::Response,
+        server::{
+            layer::ETagLayer,
+            route::{Route, any},
+            test_helpers::empty_cx,
+        },
+        utils::test_helpers::simple_req,
+    };
+
+    async fn handler() -> &'static str {
+        "Hello, World"
+    }
+
+    #[tokio::test]
+    async fn test_etag() {
+        let service = ETagLayer::new().layer(Route::<_, Infallible>::new(any(handler)));
+        let mut cx = empty_cx();
+
+        let resp = service
+            .call(&mut cx, simple_req(Method::GET, "/", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers()[header::ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with('"'));
+        assert_eq!(
+            resp.into_body().into_string().await.unwrap(),
+            "Hello, World"
+        );
+
+        // matched
+        let mut req = simple_req(Method::GET, "/", Body::empty());
+        req.headers_mut().insert(
+            header::IF_NONE_MATCH,
+            format!("\"other\", W/{}", etag.to_str().unwrap())
+                .parse()
+                .unwrap(),
+        );
+        let resp = service.call(&mut cx, req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+        assert!(!resp.headers().contains_key(header::CONTENT_LENGTH));
+        assert!(resp.into_body().into_bytes().await.unwrap().is_empty());
+
+        // not matched
+        let mut req = simple_req(Method::GET, "/", Body::empty());
+        req.headers_mut()
+            .insert(header::IF_NONE_MATCH, "\"other\"".parse().unwrap());
+        let resp = service.call(&mut cx, req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // other methods
+        let resp = service
+            .call(&mut cx, simple_req(Method::POST, "/", Body::empty()))
+            .await
+            .unwrap();
+        assert!(!resp.headers().contains_key(header::ETAG));
+    }
+
+    #[tokio::test]
+    async fn test_etag_skipped() {
+        let mut cx = empty_cx();
+
+        // larger than the max size
+        let service = ETagLayer::new()
+            .max_size(4)
+            .layer(Route::<_, Infallible>::new(any(handler)));
+        let resp = service
+            .call(&mut cx, simple_req(Method::GET, "/", Body::empty()))
+            .await
+            .unwrap();
+        assert!(!resp.headers().contains_key(header::ETAG));
+
+        // streaming responses
+        async fn stream() -> Response {
+            Response::new(Body::from_bytes_stream(futures::stream::iter([Ok::<
+                _,
+                std::io::Error,
+            >(
+                bytes::Bytes::from_static(b"Hello"),
+            )])))
+        }
+        let service = ETagLayer::new().layer(Route::<_, Infallible>::new(any(stream)));
+        let resp = service
+            .call(&mut cx, simple_req(Method::GET, "/", Body::empty()))
+            .await
+            .unwrap();
+        assert!(!resp.headers().contains_key(header::ETAG));
+        assert_eq!(resp.into_body().into_string().await.unwrap(), "Hello");
+    }
+}
@@ -0,0 +1,169 @@
+use faststr::FastStr;
+use http::{StatusCode, header};
+use http_body::Body;
+use motore::{Service, layer::Layer};
+use volo::{
+    context::Context,
+    memory::{MemoryAccount, MemoryTracker},
+};
+
+use crate::{context::ServerContext, request::Request, response::Response, server::IntoResponse};
+
+/// [`Layer`] for accounting the memory held by requests
+///
+/// The layer creates a [`MemoryAccount`] for each request by the [`MemoryTracker`], charges the
+/// size of the request body until the request is done, and inserts the account into the
+/// extensions of the context, so that handlers can charge their own buffers as well, see
+/// [`volo::memory`] for more details.
+///
+/// The size of the body is decided by the `Content-Length` or the size hint of the body, so
+/// streaming bodies without lengths are only charged by the handlers. Requests exceeding the
+/// limits are rejected with `413 Payload Too Large`.
+///
+/// Requests are accounted by their paths, or the endpoint name set by [`MemoryLayer::endpoint`],
+/// which is preferred for routes with path parameters.
+///
+/// See [`MemoryLayer::new`] for more details.
+#[derive(Clone, Debug)]
+pub struct MemoryLayer {
+    tracker: MemoryTracker,
+    endpoint: Option<FastStr>,
+}
+
+impl MemoryLayer {
+    /// Create a new [`MemoryLayer`] accounting requests by the `tracker`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use volo::memory::MemoryTracker;
+    /// use volo_http::server::{
+    ///     layer::MemoryLayer,
+    ///     route::{Router, post},
+    /// };
+    ///
+    /// async fn upload(body: bytes::Bytes) -> String {
+    ///     format!("{} bytes", body.len())
+    /// }
+    ///
+    /// let tracker = MemoryTracker::new().method_limit("upload", 256 * 1024 * 1024);
+    /// let router: Router = Router::new()
+    ///     .route("/upload/{name}", post(upload))
+    ///     .route_layer(MemoryLayer::new(tracker.clone()).endpoint("upload"));
+    /// // report `tracker.all_stats()` to metrics
+    /// ```
+    pub fn new(tracker: MemoryTracker) -> Self {
+        Self {
+            tracker,
+            endpoint: None,
+        }
+    }
+
+    /// Set the name of the endpoint for accounting requests instead of their paths.
+    pub fn endpoint(mut self, endpoint: impl Into<FastStr>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+}
+
+impl<S> Layer<S> for MemoryLayer {
+    type Service = MemoryService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        MemoryService {
+            service: inner,
+            tracker: self.tracker,
+            endpoint: self.endpoint,
+        }
+    }
+}
+
+/// [`MemoryLayer`] generated [`Service`]
+///
+/// See [`MemoryLayer`] for more details.
+#[derive(Clone, Debug)]
+pub struct MemoryService<S> {
+    service: S,
+    tracker: MemoryTracker,
+    endpoint: Option<FastStr>,
+}
+
+impl<S, B> Service<ServerContext, Request<B>> for MemoryService<S>
+where
+    S: Service<ServerContext, Request<B>> + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    B: Body + Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let account = match &self.endpoint {
+            Some(endpoint) => self.tracker.account(endpoint),
+            None => self.tracker.account(req.uri().path()),
+        };
+        let size = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or_else(|| req.body().size_hint().lower());
+        match account.charge(usize::try_from(size).unwrap_or(usize::MAX)) {
+            Ok(charge) => charge.retain(),
+            Err(err) => {
+                tracing::debug!("[Volo-HTTP] MemoryLayer: request is rejected: {err}");
+                return Ok(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+            }
+        }
+        cx.extensions_mut().insert(account);
+        let resp = self.service.call(cx, req).await?.into_response();
+        cx.extensions_mut().remove::<MemoryAccount>();
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{Method, StatusCode};
+    use motore::{Service, layer::Layer};
+    use volo::memory::MemoryTracker;
+
+    use crate::{
+        server::{
+            layer::MemoryLayer,
+            route::{Route, any},
+            test_helpers::empty_cx,
+        },
+        utils::test_helpers::simple_req,
+    };
+
+    async fn handler() -> &'static str {
+        "Hello, World"
+    }
+
+    #[tokio::test]
+    async fn test_memory_layer() {
+        let tracker = MemoryTracker::new().request_limit(8);
+        let route: Route<_> = Route::new(any(handler));
+        let service = MemoryLayer::new(tracker.clone()).layer(route);
+        let mut cx = empty_cx();
+
+        let req = simple_req(Method::POST, "/upload", "111111111".to_string());
+        let res = service.call(&mut cx, req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = simple_req(Method::POST, "/upload", "1111".to_string());
+        let res = service.call(&mut cx, req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let stats = tracker.stats("/upload").unwrap();
+        assert_eq!(stats.requests, 2);
+        assert_eq!(stats.rejected, 1);
+        assert_eq!(stats.max_peak, 4);
+        assert_eq!(stats.in_flight, 0);
+    }
+}
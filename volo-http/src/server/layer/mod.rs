@@ -1,9 +1,15 @@
 //! Collections of some useful `Layer`s.
 
 mod body_limit;
+mod deadline;
+mod etag;
 mod filter;
+mod memory;
 mod timeout;
 
 pub use body_limit::BodyLimitLayer;
+pub use deadline::DeadlineLayer;
+pub use etag::{ETagLayer, ETagService};
 pub use filter::FilterLayer;
+pub use memory::{MemoryLayer, MemoryService};
 pub use timeout::TimeoutLayer;
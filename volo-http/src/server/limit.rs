@@ -0,0 +1,476 @@
+//! Server-level protections applied when serving requests of connections.
+//!
+//! See [`Server::max_body_size`], [`Server::request_timeout`],
+//! [`Server::max_inflight_requests`], [`Server::keep_alive_timeout`] and
+//! [`Server::max_requests_per_connection`] for more details.
+//!
+//! [`Server::max_body_size`]: super::Server::max_body_size
+//! [`Server::request_timeout`]: super::Server::request_timeout
+//! [`Server::max_inflight_requests`]: super::Server::max_inflight_requests
+//! [`Server::keep_alive_timeout`]: super::Server::keep_alive_timeout
+//! [`Server::max_requests_per_connection`]: super::Server::max_requests_per_connection
+
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http::{HeaderValue, StatusCode, Version, header};
+use http_body::{Frame, SizeHint};
+use http_body_util::BodyExt;
+use parking_lot::Mutex;
+use tokio::{
+    sync::{Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError},
+    time::Instant,
+};
+
+use crate::{
+    body::Body, error::BoxError, request::Request, response::Response, server::IntoResponse,
+};
+
+const DEFAULT_DRAIN_LIMIT: usize = 64 * 1024;
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub(super) struct Limits {
+    pub(super) max_body_size: Option<usize>,
+    pub(super) drain_limit: usize,
+    pub(super) request_timeout: Option<Duration>,
+    pub(super) max_inflight: Option<usize>,
+    pub(super) keep_alive_timeout: Option<Duration>,
+    pub(super) max_requests_per_conn: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body_size: None,
+            drain_limit: DEFAULT_DRAIN_LIMIT,
+            request_timeout: None,
+            max_inflight: None,
+            keep_alive_timeout: None,
+            max_requests_per_conn: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Create the in-flight limiter for a new connection.
+    pub(super) fn inflight(&self) -> Option<Arc<Semaphore>> {
+        self.max_inflight.map(|max| Arc::new(Semaphore::new(max)))
+    }
+
+    /// Create the keep-alive tracker for a new connection.
+    pub(super) fn keep_alive(&self) -> Option<Arc<KeepAlive>> {
+        if self.keep_alive_timeout.is_none() && self.max_requests_per_conn.is_none() {
+            return None;
+        }
+        Some(Arc::new(KeepAlive {
+            timeout: self.keep_alive_timeout,
+            max_requests: self.max_requests_per_conn,
+            requests: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            http2: AtomicBool::new(false),
+            idle_since: Mutex::new(Instant::now()),
+            notify: Notify::new(),
+        }))
+    }
+}
+
+/// Keep-alive state of an HTTP/1 connection.
+///
+/// HTTP/2 connections are detected by their first requests and not tracked, since requests are
+/// multiplexed and the idle connections are managed by HTTP/2 pings.
+pub(super) struct KeepAlive {
+    timeout: Option<Duration>,
+    max_requests: Option<usize>,
+    requests: AtomicUsize,
+    active: AtomicUsize,
+    http2: AtomicBool,
+    idle_since: Mutex<Instant>,
+    notify: Notify,
+}
+
+impl KeepAlive {
+    /// Start serving a request of the connection, returns the guard finishing it when dropped,
+    /// and whether the connection should be closed after the response.
+    pub(super) fn enter(
+        this: Option<&Arc<Self>>,
+        version: Version,
+    ) -> (Option<KeepAliveGuard>, bool) {
+        let Some(this) = this else {
+            return (None, false);
+        };
+        if version >= Version::HTTP_2 {
+            this.http2.store(true, Ordering::Relaxed);
+            // wake up the idle timer to stop it
+            this.notify.notify_one();
+            return (None, false);
+        }
+        let requests = this.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        this.active.fetch_add(1, Ordering::AcqRel);
+        this.notify.notify_one();
+        let last = this.max_requests.is_some_and(|max| requests >= max);
+        if last {
+            tracing::trace!("[Volo-HTTP] connection reached the max requests, closing it");
+        }
+        (Some(KeepAliveGuard(this.clone())), last)
+    }
+
+    /// Returns when the connection has been idle for the keep-alive timeout, and never returns
+    /// if there is no timeout or it is an HTTP/2 connection.
+    pub(super) async fn idle_timeout(this: Option<&Self>) {
+        let Some((this, timeout)) = this.and_then(|this| Some((this, this.timeout?))) else {
+            return std::future::pending().await;
+        };
+        loop {
+            if this.http2.load(Ordering::Relaxed) {
+                return std::future::pending().await;
+            }
+            if this.active.load(Ordering::Acquire) > 0 {
+                this.notify.notified().await;
+                continue;
+            }
+            let deadline = *this.idle_since.lock() + timeout;
+            if deadline <= Instant::now() {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = this.notify.notified() => {}
+            }
+        }
+    }
+}
+
+/// Marks the request as finished when dropped, and the connection becomes idle if there are no
+/// other requests.
+pub(super) struct KeepAliveGuard(Arc<KeepAlive>);
+
+impl Drop for KeepAliveGuard {
+    fn drop(&mut self) {
+        *self.0.idle_since.lock() = Instant::now();
+        if self.0.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.notify.notify_one();
+        }
+    }
+}
+
+/// Close the HTTP/1 connection after the response.
+pub(super) fn close_after(resp: &mut Response) {
+    resp.headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+}
+
+/// Acquire a permit of the connection, or returns `503 Service Unavailable` if there are too many
+/// in-flight requests.
+pub(super) fn acquire(
+    inflight: Option<&Arc<Semaphore>>,
+) -> Result<Option<OwnedSemaphorePermit>, StatusCode> {
+    let Some(inflight) = inflight else {
+        return Ok(None);
+    };
+    match inflight.clone().try_acquire_owned() {
+        Ok(permit) => Ok(Some(permit)),
+        Err(TryAcquireError::NoPermits) => {
+            tracing::debug!("[Volo-HTTP] too many in-flight requests of the connection");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        Err(TryAcquireError::Closed) => Err(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+/// Flag set when the body of a request exceeds the limit.
+#[derive(Clone, Default)]
+pub(super) struct Exceeded(Arc<AtomicBool>);
+
+impl Exceeded {
+    pub(super) fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Check the body of the request with the limit.
+///
+/// If `Content-Length` exceeds the limit, the body is drained and `413 Payload Too Large` is
+/// returned, otherwise the body is wrapped for checking the data received.
+pub(super) async fn limit_body(
+    req: Request,
+    limits: &Limits,
+) -> Result<(Request, Exceeded), Response> {
+    let exceeded = Exceeded::default();
+    let Some(limit) = limits.max_body_size else {
+        return Ok((req, exceeded));
+    };
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > limit as u64) {
+        let version = req.version();
+        let drained =
+            tokio::time::timeout(DRAIN_TIMEOUT, drain(req.into_body(), limits.drain_limit))
+                .await
+                .unwrap_or(false);
+        return Err(payload_too_large(version, drained));
+    }
+    let req = req.map(|body| {
+        Body::from_body(LimitedBody {
+            inner: body,
+            remaining: limit,
+            exceeded: exceeded.clone(),
+        })
+    });
+    Ok((req, exceeded))
+}
+
+/// Read and drop the body at most `limit` bytes, returns if the body is fully drained.
+async fn drain(mut body: Body, limit: usize) -> bool {
+    let mut drained = 0;
+    while drained <= limit {
+        match body.frame().await {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    drained += data.len();
+                }
+            }
+            Some(Err(_)) => return false,
+            None => return true,
+        }
+    }
+    false
+}
+
+/// The connection of HTTP/1 should be closed if the rest of the body is not drained, since the
+/// next request cannot be read.
+pub(super) fn payload_too_large(version: Version, drained: bool) -> Response {
+    let mut resp = StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    if !drained && version <= Version::HTTP_11 {
+        resp.headers_mut()
+            .insert(header::CONNECTION, HeaderValue::from_static("close"));
+    }
+    resp
+}
+
+/// Returns `408 Request Timeout` when serving the request takes longer than the timeout.
+pub(super) fn request_timeout() -> Response {
+    tracing::debug!("[Volo-HTTP] serving request timed out");
+    let mut resp = StatusCode::REQUEST_TIMEOUT.into_response();
+    resp.headers_mut()
+        .insert(header::CONNECTION, HeaderValue::from_static("close"));
+    resp
+}
+
+struct LimitedBody {
+    inner: Body,
+    remaining: usize,
+    exceeded: Exceeded,
+}
+
+impl http_body::Body for LimitedBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+        if let Some(Ok(data)) = frame.as_ref().map(|f| f.as_ref().map(Frame::data_ref)) {
+            let len = data.map_or(0, Bytes::len);
+            if len > this.remaining {
+                this.exceeded.0.store(true, Ordering::Relaxed);
+                return Poll::Ready(Some(Err("request body exceeds the limit".into())));
+            }
+            this.remaining -= len;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod limit_tests {
+    use std::{convert::Infallible, sync::Arc, time::Duration};
+
+    use futures::future::join;
+    use http::{Method, StatusCode, header};
+    use tokio::sync::Notify;
+
+    use super::{KeepAlive, Limits};
+    use crate::{
+        body::Body,
+        context::server::Config,
+        request::Request,
+        server::{
+            HyperService,
+            route::{Route, any},
+            span_provider::DefaultProvider,
+        },
+    };
+
+    fn service(limits: Limits) -> HyperService<Arc<Route>, DefaultProvider> {
+        let route = Route::new(any(|body: String| async move { body }));
+        HyperService {
+            inner: Arc::new(route),
+            peer: "127.0.0.1:8080"
+                .parse::<std::net::SocketAddr>()
+                .unwrap()
+                .into(),
+            config: Config::default(),
+            span_provider: DefaultProvider,
+            inflight: limits.inflight(),
+            keep_alive: limits.keep_alive(),
+            limits,
+            #[cfg(feature = "http3")]
+            alt_svc: None,
+        }
+    }
+
+    fn req(body: Body, content_length: Option<usize>) -> Request {
+        let mut req = http::Request::builder().method(Method::POST).uri("/");
+        if let Some(len) = content_length {
+            req = req.header(header::CONTENT_LENGTH, len);
+        }
+        req.body(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn max_body_size() {
+        let service = service(Limits {
+            max_body_size: Some(4),
+            ..Default::default()
+        });
+
+        let resp = service.serve(req(Body::from("1234"), Some(4))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // rejected by `Content-Length` and drained
+        let resp = service.serve(req(Body::from("12345"), Some(5))).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!resp.headers().contains_key(header::CONNECTION));
+
+        // rejected when the streaming body exceeds the limit
+        let chunks = ["12", "34", "56"].map(|s| Ok::<_, Infallible>(bytes::Bytes::from(s)));
+        let body = Body::from_bytes_stream(futures::stream::iter(chunks));
+        let resp = service.serve(req(body, None)).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(resp.headers()[header::CONNECTION], "close");
+
+        // too large to drain
+        let service = self::service(Limits {
+            max_body_size: Some(4),
+            drain_limit: 8,
+            ..Default::default()
+        });
+        let resp = service.serve(req(Body::from("1234567890"), Some(10))).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(resp.headers()[header::CONNECTION], "close");
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        let service = service(Limits {
+            request_timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        });
+        // the body never ends
+        let body =
+            Body::from_bytes_stream(futures::stream::pending::<Result<bytes::Bytes, Infallible>>());
+        let resp = service.serve(req(body, None)).await;
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn max_inflight_requests() {
+        let service = service(Limits {
+            max_inflight: Some(1),
+            ..Default::default()
+        });
+        let notify = Arc::new(Notify::new());
+        let body = {
+            let notify = notify.clone();
+            Body::from_bytes_stream(futures::stream::once(async move {
+                notify.notified().await;
+                Ok::<_, Infallible>(bytes::Bytes::from("1"))
+            }))
+        };
+        let first = service.serve(req(body, None));
+        let second = async {
+            let resp = service.serve(req(Body::from("2"), None)).await;
+            notify.notify_one();
+            resp
+        };
+        let (first, second) = join(first, second).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // the permit is released
+        let resp = service.serve(req(Body::from("3"), None)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn max_requests_per_connection() {
+        let service = service(Limits {
+            max_requests_per_conn: Some(2),
+            ..Default::default()
+        });
+        let resp = service.serve(req(Body::from("1"), None)).await;
+        assert!(!resp.headers().contains_key(header::CONNECTION));
+        let resp = service.serve(req(Body::from("2"), None)).await;
+        assert_eq!(resp.headers()[header::CONNECTION], "close");
+
+        // HTTP/2 connections are not limited
+        let service = self::service(Limits {
+            max_requests_per_conn: Some(1),
+            ..Default::default()
+        });
+        let mut req = req(Body::from("1"), None);
+        *req.version_mut() = http::Version::HTTP_2;
+        let resp = service.serve(req).await;
+        assert!(!resp.headers().contains_key(header::CONNECTION));
+    }
+
+    #[tokio::test]
+    async fn keep_alive_timeout() {
+        let timeout = Duration::from_millis(50);
+        let keep_alive = Limits {
+            keep_alive_timeout: Some(timeout),
+            ..Default::default()
+        }
+        .keep_alive();
+        let idle = || KeepAlive::idle_timeout(keep_alive.as_deref());
+
+        // idle without any request
+        tokio::time::timeout(timeout * 2, idle()).await.unwrap();
+
+        // not idle while serving a request
+        let (guard, close) = KeepAlive::enter(keep_alive.as_ref(), http::Version::HTTP_11);
+        assert!(!close);
+        assert!(tokio::time::timeout(timeout * 2, idle()).await.is_err());
+        drop(guard);
+        let start = tokio::time::Instant::now();
+        tokio::time::timeout(timeout * 2, idle()).await.unwrap();
+        assert!(start.elapsed() >= timeout);
+
+        // HTTP/2 connections are never idle
+        KeepAlive::enter(keep_alive.as_ref(), http::Version::HTTP_2);
+        assert!(tokio::time::timeout(timeout * 2, idle()).await.is_err());
+    }
+}
@@ -31,11 +31,15 @@ use tracing::Instrument;
 #[cfg(feature = "__tls")]
 use volo::net::{conn::ConnStream, tls::ServerTlsConfig};
 use volo::{
-    context::Context,
+    context::{Context, Role},
+    event::ConnectionGuard,
     net::{Address, MakeIncoming, conn::Conn, incoming::Incoming},
 };
 
-use self::span_provider::{DefaultProvider, SpanProvider};
+use self::{
+    span_provider::{DefaultProvider, SpanProvider},
+    utils::early_hints::EarlyHints,
+};
 use crate::{
     body::Body,
     context::{ServerContext, server::Config},
@@ -45,7 +49,10 @@ use crate::{
 
 pub mod extract;
 mod handler;
+#[cfg(feature = "http3")]
+mod http3;
 pub mod layer;
+mod limit;
 pub mod middleware;
 pub mod panic_handler;
 pub mod param;
@@ -102,8 +109,11 @@ pub struct Server<S, L = Identity, SP = DefaultProvider> {
     config: Config,
     shutdown_hooks: Vec<Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>>,
     span_provider: SP,
+    limits: self::limit::Limits,
     #[cfg(feature = "__tls")]
     tls_config: Option<ServerTlsConfig>,
+    #[cfg(feature = "http3")]
+    http3_addr: Option<std::net::SocketAddr>,
 }
 
 impl<S> Server<S, Identity, DefaultProvider> {
@@ -116,8 +126,11 @@ impl<S> Server<S, Identity, DefaultProvider> {
             config: Config::default(),
             shutdown_hooks: Vec::new(),
             span_provider: DefaultProvider,
+            limits: Default::default(),
             #[cfg(feature = "__tls")]
             tls_config: None,
+            #[cfg(feature = "http3")]
+            http3_addr: None,
         }
     }
 }
@@ -134,6 +147,19 @@ impl<S, L, SP> Server<S, L, SP> {
         self
     }
 
+    /// Accept HTTP/3 (QUIC) connections on the UDP `addr` alongside the TCP listener.
+    ///
+    /// HTTP/3 requires TLS, so the [`tls_config`](Self::tls_config) must be set and built by
+    /// rustls. Responses of HTTP/1 and HTTP/2 will carry the `Alt-Svc` header for advertising
+    /// HTTP/3, so that clients can upgrade to it.
+    ///
+    /// This is experimental and may be changed in the future.
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, addr: std::net::SocketAddr) -> Self {
+        self.http3_addr = Some(addr);
+        self
+    }
+
     /// Register shutdown hook.
     ///
     /// Hook functions will be called just before volo's own gracefull existing code starts,
@@ -165,8 +191,11 @@ impl<S, L, SP> Server<S, L, SP> {
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
             span_provider: self.span_provider,
+            limits: self.limits,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
+            #[cfg(feature = "http3")]
+            http3_addr: self.http3_addr,
         }
     }
 
@@ -189,8 +218,11 @@ impl<S, L, SP> Server<S, L, SP> {
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
             span_provider: self.span_provider,
+            limits: self.limits,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
+            #[cfg(feature = "http3")]
+            http3_addr: self.http3_addr,
         }
     }
 
@@ -208,11 +240,107 @@ impl<S, L, SP> Server<S, L, SP> {
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
             span_provider,
+            limits: self.limits,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
+            #[cfg(feature = "http3")]
+            http3_addr: self.http3_addr,
         }
     }
 
+    /// Set the maximum size of request bodies.
+    ///
+    /// Requests with `Content-Length` larger than the limit are rejected with
+    /// `413 Payload Too Large` before calling the service, and their bodies are drained (at most
+    /// 64 KiB by default, see [`Server::body_drain_limit`]) so that the HTTP/1 connection can be
+    /// reused, or the connection is closed.
+    ///
+    /// For bodies without `Content-Length`, reading the body fails once the limit is exceeded, and
+    /// the response is replaced with `413 Payload Too Large`.
+    ///
+    /// Default is no limit.
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.limits.max_body_size = Some(limit);
+        self
+    }
+
+    /// Set the maximum size of a rejected body to drain before responding, see
+    /// [`Server::max_body_size`].
+    ///
+    /// Default is 64 KiB.
+    pub fn body_drain_limit(mut self, limit: usize) -> Self {
+        self.limits.drain_limit = limit;
+        self
+    }
+
+    /// Set the timeout for serving a request, including reading the body and calling the service.
+    ///
+    /// If the timeout is reached, `408 Request Timeout` is returned and the HTTP/1 connection is
+    /// closed, which protects the server from clients sending bodies slowly. For timeouts of
+    /// specific routes, use [`TimeoutLayer`](layer::TimeoutLayer) instead.
+    ///
+    /// For clients sending headers slowly, use [`Http1Config::set_header_read_timeout`].
+    ///
+    /// Default is no timeout.
+    ///
+    /// [`Http1Config::set_header_read_timeout`]: protocol::Http1Config::set_header_read_timeout
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.limits.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of in-flight requests of each connection.
+    ///
+    /// More requests of the connection are rejected with `503 Service Unavailable` until some of
+    /// them finish. This is useful for HTTP/2 and HTTP/3 connections where requests are served
+    /// concurrently, while HTTP/1 connections serve one request at a time.
+    ///
+    /// Default is no limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_inflight_requests(mut self, max: usize) -> Self {
+        assert!(max > 0, "`max_inflight_requests` should be greater than 0");
+        self.limits.max_inflight = Some(max);
+        self
+    }
+
+    /// Set the timeout for HTTP/1 connections to be kept alive without requests.
+    ///
+    /// Connections idle for longer than it are closed gracefully, including connections that
+    /// have not sent any request yet. Together with [`Http1Config::set_header_read_timeout`]
+    /// and [`Server::request_timeout`], it protects the server from clients holding connections
+    /// without making progress (a.k.a. slowloris). HTTP/2 connections are not affected.
+    ///
+    /// Default is no timeout.
+    ///
+    /// [`Http1Config::set_header_read_timeout`]: protocol::Http1Config::set_header_read_timeout
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.limits.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of requests served by each HTTP/1 connection.
+    ///
+    /// The response of the last request has `Connection: close` and the connection is closed
+    /// after it, so clients reconnect and are rebalanced by load balancers in front of the
+    /// server. HTTP/2 connections are not affected.
+    ///
+    /// Default is no limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        assert!(
+            max > 0,
+            "`max_requests_per_connection` should be greater than 0"
+        );
+        self.limits.max_requests_per_conn = Some(max);
+        self
+    }
+
     /// This is unstable now and may be changed in the future.
     #[doc(hidden)]
     pub fn config(&self) -> &Config {
@@ -274,8 +402,11 @@ impl<S, L, SP> Server<S, L, SP> {
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
             span_provider: self.span_provider,
+            limits: self.limits,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
+            #[cfg(feature = "http3")]
+            http3_addr: self.http3_addr,
         }
     }
 
@@ -289,8 +420,11 @@ impl<S, L, SP> Server<S, L, SP> {
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
             span_provider: self.span_provider,
+            limits: self.limits,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
+            #[cfg(feature = "http3")]
+            http3_addr: self.http3_addr,
         }
     }
 
@@ -308,6 +442,11 @@ impl<S, L, SP> Server<S, L, SP> {
     {
         let server = Arc::new(self.server);
         let service = Arc::new(self.layer.layer(self.service));
+        #[cfg(feature = "http3")]
+        let http3_endpoint = match self.http3_addr {
+            Some(addr) => Some(self::http3::make_endpoint(addr, self.tls_config.as_ref())?),
+            None => None,
+        };
         let incoming = mk_incoming.make_incoming().await?;
         tracing::info!("[Volo-HTTP] server start at: {:?}", incoming);
 
@@ -318,6 +457,26 @@ impl<S, L, SP> Server<S, L, SP> {
         // notifier for stopping all inflight connections
         let exit_notify = Arc::new(Notify::const_new());
 
+        #[cfg(feature = "http3")]
+        let (http3_handler, alt_svc) = match http3_endpoint {
+            Some(endpoint) => {
+                let addr = endpoint.local_addr()?;
+                tracing::info!("[Volo-HTTP] http3 server start at: {addr:?}");
+                let handler = tokio::spawn(self::http3::serve(
+                    endpoint,
+                    service.clone(),
+                    self.config.clone(),
+                    exit_flag.clone(),
+                    conn_cnt.clone(),
+                    exit_notify.clone(),
+                    self.span_provider.clone(),
+                    self.limits.clone(),
+                ));
+                (Some(handler), Some(self::http3::alt_svc(addr.port())))
+            }
+            None => (None, None),
+        };
+
         let handler = tokio::spawn(serve(
             server,
             incoming,
@@ -327,8 +486,11 @@ impl<S, L, SP> Server<S, L, SP> {
             conn_cnt.clone(),
             exit_notify.clone(),
             self.span_provider,
+            self.limits,
             #[cfg(feature = "__tls")]
             self.tls_config,
+            #[cfg(feature = "http3")]
+            alt_svc,
         ));
 
         #[cfg(target_family = "unix")]
@@ -368,6 +530,11 @@ impl<S, L, SP> Server<S, L, SP> {
         // received signal, graceful shutdown now
         tracing::info!("[Volo-HTTP] received signal, gracefully exiting now");
         *exit_flag.write() = true;
+        // The QUIC endpoint will not accept new connections.
+        #[cfg(feature = "http3")]
+        if let Some(http3_handler) = http3_handler {
+            http3_handler.abort();
+        }
 
         // Now we won't accept new connections.
         // And we want to send crrst reply to the peers in the short future.
@@ -402,7 +569,9 @@ async fn serve<I, S, SP>(
     conn_cnt: Arc<AtomicUsize>,
     exit_notify: Arc<Notify>,
     span_provider: SP,
+    limits: self::limit::Limits,
     #[cfg(feature = "__tls")] tls_config: Option<ServerTlsConfig>,
+    #[cfg(feature = "http3")] alt_svc: Option<http::HeaderValue>,
 ) where
     I: Incoming,
     S: Service<ServerContext, Request> + Clone + Unpin + Send + Sync + 'static,
@@ -448,17 +617,26 @@ async fn serve<I, S, SP>(
             }
         };
 
+        let conn_event = volo::event::connection("http", Role::Server, &peer);
         let hyper_service = HyperService {
             inner: service.clone(),
             peer,
             config: config.clone(),
             span_provider: span_provider.clone(),
+            limits: limits.clone(),
+            inflight: limits.inflight(),
+            keep_alive: limits.keep_alive(),
+            #[cfg(feature = "http3")]
+            alt_svc: alt_svc.clone(),
         };
 
+        let keep_alive = hyper_service.keep_alive.clone();
         tokio::spawn(serve_conn(
             server.clone(),
             conn,
             hyper_service,
+            keep_alive,
+            conn_event,
             conn_cnt.clone(),
             exit_notify.clone(),
         ));
@@ -469,6 +647,8 @@ async fn serve_conn<S>(
     server: Arc<auto::Builder<TokioExecutor>>,
     conn: Conn,
     service: S,
+    keep_alive: Option<Arc<self::limit::KeepAlive>>,
+    _conn_event: Option<ConnectionGuard>,
     conn_cnt: Arc<AtomicUsize>,
     exit_notify: Arc<Notify>,
 ) where
@@ -484,10 +664,21 @@ async fn serve_conn<S>(
     let notified = exit_notify.notified();
     tokio::pin!(notified);
 
+    let idle = self::limit::KeepAlive::idle_timeout(keep_alive.as_deref());
+    tokio::pin!(idle);
+
     let http_conn = server.serve_connection_with_upgrades(TokioIo::new(conn), service);
     futures::pin_mut!(http_conn);
 
     tokio::select! {
+        _ = &mut idle => {
+            tracing::trace!("[Volo-HTTP] closing an idle connection");
+            http_conn.as_mut().graceful_shutdown();
+            let result = http_conn.as_mut().await;
+            if let Err(err) = result {
+                tracing::debug!("[Volo-HTTP] connection error: {:?}", err);
+            }
+        }
         _ = &mut notified => {
             tracing::trace!("[Volo-HTTP] closing a pending connection");
             // Graceful shutdown.
@@ -512,6 +703,75 @@ struct HyperService<S, SP> {
     peer: Address,
     config: Config,
     span_provider: SP,
+    limits: self::limit::Limits,
+    // limiter of in-flight requests of the connection
+    inflight: Option<Arc<tokio::sync::Semaphore>>,
+    // keep-alive state of the HTTP/1 connection
+    keep_alive: Option<Arc<self::limit::KeepAlive>>,
+    // `Alt-Svc` header for advertising HTTP/3
+    #[cfg(feature = "http3")]
+    alt_svc: Option<http::HeaderValue>,
+}
+
+impl<S, SP> HyperService<S, SP>
+where
+    S: Service<ServerContext, Request> + Clone + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    S::Error: IntoResponse,
+    SP: SpanProvider + Clone + Send + Sync + 'static,
+{
+    fn serve(&self, mut req: Request) -> impl Future<Output = Response> + Send + 'static {
+        let service = self.clone();
+        // the HTTP/3 server sets its own early hints for sending interim responses
+        let early_hints = match req.extensions().get::<EarlyHints>() {
+            Some(early_hints) => early_hints.clone(),
+            None => {
+                let early_hints = EarlyHints::fallback();
+                req.extensions_mut().insert(early_hints.clone());
+                early_hints
+            }
+        };
+        let permit = self::limit::acquire(self.inflight.as_ref());
+        let (keep_alive, close) =
+            self::limit::KeepAlive::enter(self.keep_alive.as_ref(), req.version());
+        METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
+            let _keep_alive = keep_alive;
+            let _permit = match permit {
+                Ok(permit) => permit,
+                Err(status) => return status.into_response(),
+            };
+            let version = req.version();
+            let (req, exceeded) = match self::limit::limit_body(req, &service.limits).await {
+                Ok(res) => res,
+                Err(resp) => return resp,
+            };
+            let mut cx = ServerContext::new(service.peer);
+            cx.rpc_info_mut().set_config(service.config);
+            let span = service.span_provider.on_serve(&cx);
+            let fut = service.inner.call(&mut cx, req).instrument(span);
+            let resp = match service.limits.request_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, fut).await.ok(),
+                None => Some(fut.await),
+            };
+            service.span_provider.leave_serve(&cx);
+            let mut resp: http::Response<Body> = match resp {
+                _ if exceeded.get() => self::limit::payload_too_large(version, false),
+                Some(resp) => resp.into_response(),
+                None => self::limit::request_timeout(),
+            };
+            early_hints.append_to(resp.headers_mut());
+            if close {
+                self::limit::close_after(&mut resp);
+            }
+            #[cfg(feature = "http3")]
+            if let Some(alt_svc) = service.alt_svc {
+                resp.headers_mut()
+                    .entry(http::header::ALT_SVC)
+                    .or_insert(alt_svc);
+            }
+            resp
+        })
+    }
 }
 
 type HyperRequest = http::request::Request<hyper::body::Incoming>;
@@ -528,21 +788,7 @@ where
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn call(&self, req: HyperRequest) -> Self::Future {
-        let service = self.clone();
-        Box::pin(
-            METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
-                let mut cx = ServerContext::new(service.peer);
-                cx.rpc_info_mut().set_config(service.config);
-                let span = service.span_provider.on_serve(&cx);
-                let resp: http::Response<Body> = service
-                    .inner
-                    .call(&mut cx, req.map(Body::from_incoming))
-                    .instrument(span)
-                    .await
-                    .into_response();
-                service.span_provider.leave_serve(&cx);
-                Ok(resp)
-            }),
-        )
+        let resp = self.serve(req.map(Body::from_incoming));
+        Box::pin(async move { Ok(resp.await) })
     }
 }
@@ -160,17 +160,54 @@ impl FromContext for PathParamsMap {
     }
 }
 
-trait FromPathParam: Sized {
-    fn from_path_param(param: &str) -> Result<Self, PathParamsRejection>;
+/// Types that can be parsed from a path param, used by [`PathParams`].
+///
+/// It is implemented for primitive types, [`String`] and [`FastStr`], and [`Parsed`] for all
+/// types implementing [`FromStr`], e.g., `Uuid`. Custom types can implement it for their own
+/// parsing and validation.
+///
+/// # Examples
+///
+/// ```
+/// use volo_http::{
+///     error::BoxError,
+///     server::{
+///         param::{FromPathParam, PathParams},
+///         route::{Router, get},
+///     },
+/// };
+///
+/// struct Slug(String);
+///
+/// impl FromPathParam for Slug {
+///     fn from_path_param(param: &str) -> Result<Self, BoxError> {
+///         if param.is_empty()
+///             || !param
+///                 .bytes()
+///                 .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+///         {
+///             return Err("slug should only contain alphanumeric characters and dashes".into());
+///         }
+///         Ok(Slug(param.to_owned()))
+///     }
+/// }
+///
+/// async fn post(PathParams((slug, page)): PathParams<(Slug, u32)>) -> String {
+///     format!("post: {}, page: {page}", slug.0)
+/// }
+///
+/// let router: Router = Router::new().route("/posts/{slug}/{page}", get(post));
+/// ```
+pub trait FromPathParam: Sized {
+    /// Parse the value from a path param.
+    fn from_path_param(param: &str) -> Result<Self, BoxError>;
 }
 
 macro_rules! impl_from_path_param {
     ($ty:ty) => {
         impl FromPathParam for $ty {
-            fn from_path_param(param: &str) -> Result<Self, PathParamsRejection> {
-                FromStr::from_str(param)
-                    .map_err(Into::into)
-                    .map_err(PathParamsRejection::ParseError)
+            fn from_path_param(param: &str) -> Result<Self, BoxError> {
+                FromStr::from_str(param).map_err(Into::into)
             }
         }
     };
@@ -191,6 +228,41 @@ impl_from_path_param!(char);
 impl_from_path_param!(String);
 impl_from_path_param!(FastStr);
 
+/// A path param parsed by [`FromStr`].
+///
+/// It can be used in [`PathParams`] for types that do not implement [`FromPathParam`], e.g.,
+/// `PathParams<(Parsed<Uuid>, u64)>`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Parsed<T>(pub T);
+
+impl<T> FromPathParam for Parsed<T>
+where
+    T: FromStr,
+    T::Err: Into<BoxError>,
+{
+    fn from_path_param(param: &str) -> Result<Self, BoxError> {
+        T::from_str(param).map(Parsed).map_err(Into::into)
+    }
+}
+
+impl<T> Deref for Parsed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn parse_param<T: FromPathParam>(
+    param: Option<&(FastStr, FastStr)>,
+) -> Result<T, PathParamsRejection> {
+    let (name, value) = param.ok_or(PathParamsRejection::LengthMismatch)?;
+    T::from_path_param(value).map_err(|source| PathParamsRejection::ParseError {
+        name: name.clone(),
+        source,
+    })
+}
+
 /// Extractor for params from request uri
 ///
 /// # Examples
@@ -217,14 +289,7 @@ where
     type Rejection = PathParamsRejection;
 
     async fn from_context(cx: &mut ServerContext, _: &mut Parts) -> Result<Self, Self::Rejection> {
-        let mut param_iter = cx.params().iter();
-        let t = T::from_path_param(
-            param_iter
-                .next()
-                .ok_or(PathParamsRejection::LengthMismatch)?
-                .1
-                .as_str(),
-        )?;
+        let t = parse_param(cx.params().iter().next())?;
         Ok(PathParams(t))
     }
 }
@@ -248,9 +313,7 @@ macro_rules! impl_path_params_extractor {
             ) -> Result<Self, Self::Rejection> {
                 let mut param_iter = cx.params().iter();
                 $(
-                    let $ty = $ty::from_path_param(
-                        param_iter.next().ok_or(PathParamsRejection::LengthMismatch)?.1.as_str(),
-                    )?;
+                    let $ty = parse_param::<$ty>(param_iter.next())?;
                 )+
                 Ok(PathParams(($($ty,)+)))
             }
@@ -265,8 +328,13 @@ all_the_tuples!(impl_path_params_extractor);
 pub enum PathParamsRejection {
     /// The number of params does not match the number of idents in [`PathParams`]
     LengthMismatch,
-    /// Error when parsing a string to the specified type
-    ParseError(BoxError),
+    /// Error when parsing a param to the specified type
+    ParseError {
+        /// Name of the param
+        name: FastStr,
+        /// Error of parsing
+        source: BoxError,
+    },
 }
 
 impl fmt::Display for PathParamsRejection {
@@ -276,7 +344,9 @@ impl fmt::Display for PathParamsRejection {
                 f,
                 "the number of path params does not match number of types in `PathParams`"
             ),
-            Self::ParseError(e) => write!(f, "path param parse error: {e}"),
+            Self::ParseError { name, source } => {
+                write!(f, "failed to parse path param `{name}`: {source}")
+            }
         }
     }
 }
@@ -285,13 +355,13 @@ impl Error for PathParamsRejection {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             Self::LengthMismatch => None,
-            Self::ParseError(e) => Some(e.as_ref()),
+            Self::ParseError { source, .. } => Some(source.as_ref()),
         }
     }
 }
 
 impl IntoResponse for PathParamsRejection {
     fn into_response(self) -> Response {
-        StatusCode::BAD_REQUEST.into_response()
+        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
     }
 }
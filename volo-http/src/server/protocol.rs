@@ -4,7 +4,12 @@
 //!
 //! And in most cases, users do not need to pay attention to this mod.
 
+#[cfg(feature = "http1")]
+use std::time::Duration;
+
 use hyper_util::rt::TokioExecutor;
+#[cfg(feature = "http1")]
+use hyper_util::rt::TokioTimer;
 
 /// Configuration of the http1 part of the [`Server`].
 ///
@@ -50,6 +55,16 @@ impl Http1Config<'_> {
         self
     }
 
+    /// Set whether HTTP/1 connections should aggregate flushes of pipelined responses.
+    ///
+    /// This is experimental and may be useful for clients pipelining requests.
+    ///
+    /// Default is false.
+    pub fn set_pipeline_flush(&mut self, enabled: bool) -> &mut Self {
+        self.inner.pipeline_flush(enabled);
+        self
+    }
+
     /// Set whether HTTP/1 connections will write header names as title case at
     /// the socket level.
     ///
@@ -87,6 +102,34 @@ impl Http1Config<'_> {
         self.inner.max_headers(max_headers);
         self
     }
+
+    /// Set the timeout for reading headers of a request.
+    ///
+    /// If a client does not send all headers within the timeout, the connection is closed, which
+    /// protects the server from clients sending headers slowly (a.k.a. slowloris). Pass `None` to
+    /// disable it.
+    ///
+    /// Default is no timeout.
+    pub fn set_header_read_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.inner.timer(TokioTimer::new());
+        self.inner.header_read_timeout(timeout);
+        self
+    }
+
+    /// Set the maximum size of the read buffer, which limits the size of headers of a request.
+    ///
+    /// If headers of a request are larger than it, the server responds to the client with
+    /// "431 Request Header Fields Too Large".
+    ///
+    /// Default is about 400 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is less than 8192.
+    pub fn set_max_buf_size(&mut self, max: usize) -> &mut Self {
+        self.inner.max_buf_size(max);
+        self
+    }
 }
 
 /// Configuration of the http2 part of the [`Server`].
@@ -193,6 +193,41 @@ where
             fallback,
         }
     }
+
+    /// Add a new inner layer to all routes in this method router, but not to its fallback,
+    /// i.e., requests with unmatched methods are not handled by the layer.
+    ///
+    /// It is useful for layers which should only apply to the handlers, e.g., authentication
+    /// should not turn `405 Method Not Allowed` into `401 Unauthorized`.
+    ///
+    /// The layer's `Service` should be `Clone + Send + Sync + 'static`.
+    pub fn route_layer<L>(self, l: L) -> Self
+    where
+        L: Layer<Route<B, E>> + Clone + Send + Sync + 'static,
+        L::Service: Service<ServerContext, Request<B>, Error = E> + Send + Sync + 'static,
+        <L::Service as Service<ServerContext, Request<B>>>::Response: IntoResponse,
+    {
+        let layer_fn = move |route: Route<B, E>| {
+            Route::new(
+                l.clone()
+                    .layer(route)
+                    .map_response(IntoResponse::into_response),
+            )
+        };
+
+        MethodRouter {
+            options: self.options.map(layer_fn.clone()),
+            get: self.get.map(layer_fn.clone()),
+            post: self.post.map(layer_fn.clone()),
+            put: self.put.map(layer_fn.clone()),
+            delete: self.delete.map(layer_fn.clone()),
+            head: self.head.map(layer_fn.clone()),
+            trace: self.trace.map(layer_fn.clone()),
+            connect: self.connect.map(layer_fn.clone()),
+            patch: self.patch.map(layer_fn),
+            fallback: self.fallback,
+        }
+    }
 }
 
 macro_rules! for_all_methods {
@@ -459,4 +494,25 @@ mod method_router_tests {
         .await;
         test_all_method(any(teapot), |_| true).await;
     }
+
+    #[tokio::test]
+    async fn method_route_layer() {
+        use crate::server::layer::FilterLayer;
+
+        async fn reject(_: Method) -> Result<(), StatusCode> {
+            Err(StatusCode::UNAUTHORIZED)
+        }
+
+        let router: MethodRouter<Option<Body>> =
+            get(always_ok).route_layer(FilterLayer::new(reject));
+        assert_eq!(
+            router.call_route(Method::GET, None).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        // the fallback is not affected
+        assert_eq!(
+            router.call_route(Method::POST, None).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
 }
@@ -402,6 +402,61 @@ where
             is_default_fallback: self.is_default_fallback,
         }
     }
+
+    /// Add a new inner layer to all routes in router, but not to the fallback, i.e., requests
+    /// with unmatched paths are not handled by the layer.
+    ///
+    /// Like [`Router::layer`], only routes added before calling this are affected, so it can be
+    /// used for attaching middlewares to a subset of routes, e.g., authentication for some routes
+    /// while others are public.
+    ///
+    /// The layer's `Service` should be `Send + Sync + 'static`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use http::StatusCode;
+    /// use volo_http::{
+    ///     context::ServerContext,
+    ///     request::Request,
+    ///     response::Response,
+    ///     server::{
+    ///         IntoResponse,
+    ///         middleware::{self, Next},
+    ///         route::{Router, get},
+    ///     },
+    /// };
+    ///
+    /// async fn auth(cx: &mut ServerContext, req: Request, next: Next) -> Response {
+    ///     if !req.headers().contains_key(http::header::AUTHORIZATION) {
+    ///         return StatusCode::UNAUTHORIZED.into_response();
+    ///     }
+    ///     next.run(cx, req).await.into_response()
+    /// }
+    ///
+    /// let router: Router = Router::new()
+    ///     .route("/admin", get(|| async { "admin" }))
+    ///     .route_layer(middleware::from_fn(auth))
+    ///     // public routes are added after the layer
+    ///     .route("/", get(|| async { "index" }));
+    /// ```
+    pub fn route_layer<L>(self, l: L) -> Self
+    where
+        L: Layer<Route<B, E>> + Clone + Send + Sync + 'static,
+        L::Service: Service<ServerContext, Request<B>, Error = E> + Send + Sync + 'static,
+        <L::Service as Service<ServerContext, Request<B>>>::Response: IntoResponse,
+    {
+        let routes = self
+            .routes
+            .into_iter()
+            .map(|(id, route)| {
+                let route = route.layer(l.clone());
+                (id, route)
+            })
+            .collect();
+
+        Router { routes, ..self }
+    }
 }
 
 impl<B, E> Service<ServerContext, Request<B>> for Router<B, E>
@@ -814,4 +869,74 @@ mod router_tests {
                 .is_err()
         );
     }
+
+    #[tokio::test]
+    async fn route_layer() {
+        use crate::{
+            context::ServerContext,
+            request::Request,
+            response::Response,
+            server::{
+                IntoResponse,
+                middleware::{Next, from_fn},
+            },
+        };
+
+        async fn auth(
+            cx: &mut ServerContext,
+            req: Request<Option<Body>>,
+            next: Next<Option<Body>>,
+        ) -> Response {
+            if !req.headers().contains_key(http::header::AUTHORIZATION) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            next.run(cx, req).await.into_response()
+        }
+        async fn status(
+            server: &TestServer<Router<Option<Body>>, Option<Body>>,
+            uri: &str,
+        ) -> StatusCode {
+            server.call_route(Method::GET, uri, None).await.status()
+        }
+
+        let router: Router<Option<Body>> = Router::new()
+            .route("/admin", any(always_ok))
+            .route_layer(from_fn(auth))
+            .route("/", any(always_ok));
+        let server = Server::new(router).into_test_server();
+
+        assert_eq!(status(&server, "/admin").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(&server, "/").await, StatusCode::OK);
+        // the fallback is not affected
+        assert_eq!(status(&server, "/404").await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn typed_path_params() {
+        use crate::server::param::{Parsed, PathParams};
+
+        async fn params(
+            PathParams((id, page)): PathParams<(Parsed<std::net::Ipv4Addr>, u32)>,
+        ) -> String {
+            format!("{} {page}", *id)
+        }
+        async fn get_res(
+            server: &TestServer<Router<Option<Body>>, Option<Body>>,
+            uri: &str,
+        ) -> (StatusCode, String) {
+            let resp = server.call_route(Method::GET, uri, None).await;
+            (resp.status(), resp.into_string().await.unwrap())
+        }
+
+        let router: Router<Option<Body>> = Router::new().route("/{ip}/{page}", any(params));
+        let server = Server::new(router).into_test_server();
+
+        assert_eq!(
+            get_res(&server, "/127.0.0.1/2").await,
+            (StatusCode::OK, "127.0.0.1 2".to_owned())
+        );
+        let (status, body) = get_res(&server, "/127.0.0.1/two").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.starts_with("failed to parse path param `page`"));
+    }
 }
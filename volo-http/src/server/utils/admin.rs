@@ -0,0 +1,872 @@
+//! Admin server for introspecting a running process.
+//!
+//! [`Admin`] is a small [`Router`] which can be mounted by any volo process, e.g., served on a
+//! separate port by [`Admin::serve`] or nested into an existing router by [`Admin::into_router`].
+//! It exposes the following endpoints in JSON:
+//!
+//! - `GET /config`: the configs registered by [`Admin::config`].
+//! - `GET /connections`: the open connections by protocol, role and peer, and the connection pools
+//!   registered by [`Admin::pool`].
+//! - `GET /discovery`: the latest instances of each callee from the discoveries.
+//! - `GET /breakers`: the states of circuit breakers.
+//! - `GET /runtime`: the metrics of the tokio runtime serving the admin.
+//! - `GET /log_level` and `PUT /log_level`: the filter of logs, which can be changed by the
+//!   directives of [`EnvFilter`] in the body if [`Admin::log_filter`] is set.
+//! - `GET /in_flight`: the requests in flight through the [`InFlightLayer`] of the admin, the
+//!   slowest first.
+//!
+//! The connections, discovery snapshots and breaker states are collected from the events of
+//! [`volo::event`] since the [`Admin`] is created, so connections established before it are not
+//! counted, and only discoveries which watch changes are shown. They are collected until the
+//! [`Admin`] and all its clones are dropped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use serde::Serialize;
+//! use volo_http::server::{
+//!     Server,
+//!     route::{Router, get},
+//!     utils::admin::Admin,
+//! };
+//!
+//! #[derive(Serialize)]
+//! struct AppConfig {
+//!     port: u16,
+//! }
+//!
+//! async fn index() -> &'static str {
+//!     "hello"
+//! }
+//!
+//! # async fn run() {
+//! let admin = Admin::new().config("app", || AppConfig { port: 8080 });
+//! let app: Router = Router::new()
+//!     .route("/", get(index))
+//!     .layer(admin.in_flight_layer());
+//!
+//! let addr = "127.0.0.1:9090".parse::<std::net::SocketAddr>().unwrap();
+//! tokio::spawn(admin.serve(volo::net::Address::from(addr)));
+//! # }
+//! ```
+//!
+//! The admin exposes internals of the process and allows changing the log level, it should only be
+//! served on a port not reachable from untrusted networks.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use faststr::FastStr;
+use http::StatusCode;
+use motore::{layer::Layer, service::Service};
+use parking_lot::Mutex;
+use serde::Serialize;
+use sonic_rs::OwnedLazyValue;
+use tracing_subscriber::{EnvFilter, reload};
+use volo::{
+    context::{Context, Role},
+    event::{
+        self, BreakerState, BreakerStateChanged, ConnectionClosed, ConnectionEstablished,
+        DiscoveryDiffApplied, Subscription,
+    },
+    net::{Address, incoming::MakeIncoming},
+};
+
+use crate::{
+    server::{
+        Server,
+        extract::Json,
+        route::{Router, get},
+    },
+    utils::Extension,
+};
+
+type Provider = Box<dyn Fn() -> Result<OwnedLazyValue, sonic_rs::Error> + Send + Sync>;
+
+/// The admin server, see the [module docs](self) for more details.
+///
+/// It is cheap to clone, and all clones share the same states.
+#[derive(Clone)]
+pub struct Admin {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    configs: Mutex<Vec<(FastStr, Provider)>>,
+    pools: Mutex<Vec<(FastStr, Provider)>>,
+    log_filter: Mutex<Option<Box<dyn LogFilter>>>,
+    in_flight: InFlight,
+
+    connections: Mutex<HashMap<ConnectionKey, usize>>,
+    discovery: Mutex<BTreeMap<FastStr, DiscoverySnapshot>>,
+    breakers: Mutex<BTreeMap<FastStr, (BreakerState, Instant)>>,
+    subscriptions: Mutex<Vec<Subscription>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ConnectionKey {
+    protocol: &'static str,
+    role: &'static str,
+    peer: String,
+}
+
+impl ConnectionKey {
+    fn new(protocol: &'static str, role: Role, peer: &Address) -> Self {
+        Self {
+            protocol,
+            role: role_str(role),
+            peer: peer.to_string(),
+        }
+    }
+}
+
+struct DiscoverySnapshot {
+    instances: Vec<InstanceSnapshot>,
+    updated_at: Instant,
+}
+
+#[derive(Clone, Serialize)]
+struct InstanceSnapshot {
+    address: String,
+    weight: u32,
+    tags: BTreeMap<String, String>,
+}
+
+impl Default for Admin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Admin {
+    /// Create an [`Admin`] and start collecting the events of connections, discoveries and
+    /// circuit breakers.
+    pub fn new() -> Self {
+        let inner = Arc::new(Inner {
+            configs: Default::default(),
+            pools: Default::default(),
+            log_filter: Default::default(),
+            in_flight: InFlight::default(),
+            connections: Default::default(),
+            discovery: Default::default(),
+            breakers: Default::default(),
+            subscriptions: Default::default(),
+        });
+        let subscriptions = vec![
+            subscribe(&inner, |inner, e: &ConnectionEstablished| {
+                *inner
+                    .connections
+                    .lock()
+                    .entry(ConnectionKey::new(e.protocol, e.role, &e.peer))
+                    .or_default() += 1;
+            }),
+            subscribe(&inner, |inner, e: &ConnectionClosed| {
+                let key = ConnectionKey::new(e.protocol, e.role, &e.peer);
+                let mut connections = inner.connections.lock();
+                // the connection may be established before the admin is created
+                if let Some(count) = connections.get_mut(&key) {
+                    *count -= 1;
+                    if *count == 0 {
+                        connections.remove(&key);
+                    }
+                }
+            }),
+            subscribe(&inner, |inner, e: &DiscoveryDiffApplied| {
+                let snapshot = DiscoverySnapshot {
+                    instances: e
+                        .all
+                        .iter()
+                        .map(|instance| InstanceSnapshot {
+                            address: instance.address.to_string(),
+                            weight: instance.weight,
+                            tags: instance
+                                .tags
+                                .iter()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect(),
+                        })
+                        .collect(),
+                    updated_at: Instant::now(),
+                };
+                inner
+                    .discovery
+                    .lock()
+                    .insert(e.key.clone().unwrap_or_default(), snapshot);
+            }),
+            subscribe(&inner, |inner, e: &BreakerStateChanged| {
+                inner
+                    .breakers
+                    .lock()
+                    .insert(e.name.clone(), (e.to, Instant::now()));
+            }),
+        ];
+        *inner.subscriptions.lock() = subscriptions;
+        Self { inner }
+    }
+
+    /// Register a config returned by `f` to be shown by `GET /config` as `name`.
+    pub fn config<N, F, T>(self, name: N, f: F) -> Self
+    where
+        N: Into<FastStr>,
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Serialize,
+    {
+        self.inner
+            .configs
+            .lock()
+            .push((name.into(), Box::new(move || sonic_rs::to_lazyvalue(&f()))));
+        self
+    }
+
+    /// Register the states of a connection pool returned by `f` to be shown by
+    /// `GET /connections` as `name`.
+    pub fn pool<N, F, T>(self, name: N, f: F) -> Self
+    where
+        N: Into<FastStr>,
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Serialize,
+    {
+        self.inner
+            .pools
+            .lock()
+            .push((name.into(), Box::new(move || sonic_rs::to_lazyvalue(&f()))));
+        self
+    }
+
+    /// Register the connection pool of a [`Client`](crate::client::Client) to be shown by
+    /// `GET /connections` as `name`, see [`Client::pool_stats`](crate::client::Client::pool_stats).
+    #[cfg(feature = "client")]
+    pub fn client_pool<N, ReqBody, RespBody>(
+        self,
+        name: N,
+        client: &crate::client::Client<ReqBody, RespBody>,
+    ) -> Self
+    where
+        N: Into<FastStr>,
+        ReqBody: 'static,
+        RespBody: 'static,
+    {
+        #[derive(Serialize)]
+        struct HostStats {
+            scheme: String,
+            address: String,
+            connections: usize,
+            idle: usize,
+            waiting: usize,
+        }
+
+        let client = client.clone();
+        self.pool(name, move || {
+            client
+                .pool_stats()
+                .into_iter()
+                .map(|stats| HostStats {
+                    scheme: stats.scheme.to_string(),
+                    address: stats.address.to_string(),
+                    connections: stats.connections,
+                    idle: stats.idle,
+                    waiting: stats.waiting,
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+
+    /// Set the reload handle of the [`EnvFilter`] of logs for `GET /log_level` and
+    /// `PUT /log_level`.
+    ///
+    /// The filter should be added to the subscriber by [`reload::Layer`], e.g.,
+    ///
+    /// ```ignore
+    /// let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+    /// tracing_subscriber::registry().with(filter).with(fmt::layer()).init();
+    /// let admin = Admin::new().log_filter(handle);
+    /// ```
+    pub fn log_filter<S>(self, handle: reload::Handle<EnvFilter, S>) -> Self
+    where
+        S: 'static,
+    {
+        *self.inner.log_filter.lock() = Some(Box::new(handle));
+        self
+    }
+
+    /// Returns the [`InFlight`] requests tracked by [`Admin::in_flight_layer`].
+    pub fn in_flight(&self) -> &InFlight {
+        &self.inner.in_flight
+    }
+
+    /// Returns an [`InFlightLayer`] for tracking the requests of servers or clients, which are
+    /// shown by `GET /in_flight`.
+    pub fn in_flight_layer(&self) -> InFlightLayer {
+        InFlightLayer {
+            in_flight: self.inner.in_flight.clone(),
+        }
+    }
+
+    /// Convert the admin into a [`Router`], which can be nested into other routers.
+    pub fn into_router(self) -> Router {
+        Router::new()
+            .route("/config", get(config))
+            .route("/connections", get(connections))
+            .route("/discovery", get(discovery))
+            .route("/breakers", get(breakers))
+            .route("/runtime", get(runtime))
+            .route("/log_level", get(log_level).put(set_log_level))
+            .route("/in_flight", get(in_flight))
+            .layer(Extension(self))
+    }
+
+    /// Serve the admin on `mk_incoming`, e.g., an address.
+    pub async fn serve<MI>(self, mk_incoming: MI) -> Result<(), crate::error::BoxError>
+    where
+        MI: MakeIncoming,
+    {
+        Server::new(self.into_router()).run(mk_incoming).await
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        for subscription in self.subscriptions.get_mut().drain(..) {
+            subscription.unsubscribe();
+        }
+    }
+}
+
+fn subscribe<E, F>(inner: &Arc<Inner>, f: F) -> Subscription
+where
+    E: event::Event,
+    F: Fn(&Inner, &E) + Send + Sync + 'static,
+{
+    let inner = Arc::downgrade(inner);
+    event::subscribe(move |e: &E| {
+        if let Some(inner) = Weak::upgrade(&inner) {
+            f(&inner, e);
+        }
+    })
+}
+
+fn role_str(role: Role) -> &'static str {
+    match role {
+        Role::Client => "client",
+        Role::Server => "server",
+    }
+}
+
+fn breaker_state_str(state: BreakerState) -> &'static str {
+    match state {
+        BreakerState::Closed => "closed",
+        BreakerState::Open => "open",
+        BreakerState::HalfOpen => "half_open",
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+type JsonResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+fn provide(
+    providers: &Mutex<Vec<(FastStr, Provider)>>,
+) -> JsonResult<BTreeMap<FastStr, OwnedLazyValue>> {
+    providers
+        .lock()
+        .iter()
+        .map(|(name, f)| match f() {
+            Ok(value) => Ok((name.clone(), value)),
+            Err(err) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to serialize `{name}`: {err}"),
+            )),
+        })
+        .collect::<Result<_, _>>()
+        .map(Json)
+}
+
+async fn config(
+    Extension(admin): Extension<Admin>,
+) -> JsonResult<BTreeMap<FastStr, OwnedLazyValue>> {
+    provide(&admin.inner.configs)
+}
+
+#[derive(Serialize)]
+struct Connections {
+    open: Vec<OpenConnections>,
+    pools: BTreeMap<FastStr, OwnedLazyValue>,
+}
+
+#[derive(Serialize)]
+struct OpenConnections {
+    protocol: &'static str,
+    role: &'static str,
+    peer: String,
+    count: usize,
+}
+
+async fn connections(Extension(admin): Extension<Admin>) -> JsonResult<Connections> {
+    let mut open = admin
+        .inner
+        .connections
+        .lock()
+        .iter()
+        .map(|(key, count)| (key.clone(), *count))
+        .collect::<Vec<_>>();
+    open.sort();
+    let Json(pools) = provide(&admin.inner.pools)?;
+    Ok(Json(Connections {
+        open: open
+            .into_iter()
+            .map(|(key, count)| OpenConnections {
+                protocol: key.protocol,
+                role: key.role,
+                peer: key.peer,
+                count,
+            })
+            .collect(),
+        pools,
+    }))
+}
+
+#[derive(Serialize)]
+struct Discovery {
+    instances: Vec<InstanceSnapshot>,
+    updated_ms_ago: u64,
+}
+
+async fn discovery(Extension(admin): Extension<Admin>) -> Json<BTreeMap<FastStr, Discovery>> {
+    let snapshots = admin
+        .inner
+        .discovery
+        .lock()
+        .iter()
+        .map(|(key, snapshot)| {
+            (
+                key.clone(),
+                Discovery {
+                    instances: snapshot.instances.clone(),
+                    updated_ms_ago: millis(snapshot.updated_at.elapsed()),
+                },
+            )
+        })
+        .collect();
+    Json(snapshots)
+}
+
+#[derive(Serialize)]
+struct Breaker {
+    state: &'static str,
+    changed_ms_ago: u64,
+}
+
+async fn breakers(Extension(admin): Extension<Admin>) -> Json<BTreeMap<FastStr, Breaker>> {
+    let breakers = admin
+        .inner
+        .breakers
+        .lock()
+        .iter()
+        .map(|(name, (state, since))| {
+            (
+                name.clone(),
+                Breaker {
+                    state: breaker_state_str(*state),
+                    changed_ms_ago: millis(since.elapsed()),
+                },
+            )
+        })
+        .collect();
+    Json(breakers)
+}
+
+#[derive(Serialize)]
+struct RuntimeMetrics {
+    workers: usize,
+    alive_tasks: usize,
+    global_queue_depth: usize,
+    worker_busy_ms: Vec<u64>,
+    worker_park_count: Vec<u64>,
+}
+
+async fn runtime() -> Json<RuntimeMetrics> {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    let workers = metrics.num_workers();
+    Json(RuntimeMetrics {
+        workers,
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+        worker_busy_ms: (0..workers)
+            .map(|worker| millis(metrics.worker_total_busy_duration(worker)))
+            .collect(),
+        worker_park_count: (0..workers)
+            .map(|worker| metrics.worker_park_count(worker))
+            .collect(),
+    })
+}
+
+trait LogFilter: Send + Sync {
+    fn current(&self) -> Result<String, String>;
+    fn reload(&self, directives: &str) -> Result<(), String>;
+}
+
+impl<S: 'static> LogFilter for reload::Handle<EnvFilter, S> {
+    fn current(&self) -> Result<String, String> {
+        self.with_current(ToString::to_string)
+            .map_err(|err| err.to_string())
+    }
+
+    fn reload(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directives).map_err(|err| err.to_string())?;
+        reload::Handle::reload(self, filter).map_err(|err| err.to_string())
+    }
+}
+
+const NO_LOG_FILTER: &str = "log filter is not set by `Admin::log_filter`";
+
+async fn log_level(Extension(admin): Extension<Admin>) -> (StatusCode, String) {
+    match admin.inner.log_filter.lock().as_ref() {
+        Some(filter) => match filter.current() {
+            Ok(current) => (StatusCode::OK, current),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err),
+        },
+        None => (StatusCode::NOT_FOUND, NO_LOG_FILTER.to_owned()),
+    }
+}
+
+async fn set_log_level(
+    Extension(admin): Extension<Admin>,
+    directives: String,
+) -> (StatusCode, String) {
+    match admin.inner.log_filter.lock().as_ref() {
+        Some(filter) => match filter.reload(directives.trim()) {
+            Ok(()) => {
+                tracing::info!(
+                    "[Volo-HTTP] log filter is changed to `{}`",
+                    directives.trim()
+                );
+                (StatusCode::OK, directives.trim().to_owned())
+            }
+            Err(err) => (StatusCode::BAD_REQUEST, err),
+        },
+        None => (StatusCode::NOT_FOUND, NO_LOG_FILTER.to_owned()),
+    }
+}
+
+async fn in_flight(Extension(admin): Extension<Admin>) -> Json<Vec<InFlightRequest>> {
+    Json(admin.inner.in_flight.requests())
+}
+
+/// Requests in flight through the [`InFlightLayer`]s created from it.
+///
+/// It is cheap to clone, and all clones share the same requests.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight {
+    inner: Arc<InFlightInner>,
+}
+
+#[derive(Debug, Default)]
+struct InFlightInner {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, InFlightEntry>>,
+}
+
+#[derive(Debug)]
+struct InFlightEntry {
+    role: Role,
+    method: FastStr,
+    caller: FastStr,
+    callee: FastStr,
+    peer: Option<Address>,
+    start: Instant,
+}
+
+/// The summary of a request in flight, see [`InFlight::requests`].
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct InFlightRequest {
+    /// Whether the request is sent by a client or received by a server.
+    pub role: &'static str,
+    /// The method of the request, or the path for http requests.
+    pub method: FastStr,
+    /// The service name of the caller.
+    pub caller: FastStr,
+    /// The service name of the callee.
+    pub callee: FastStr,
+    /// The address of the other side if known, i.e., the caller for servers and the callee for
+    /// clients.
+    pub peer: Option<String>,
+    /// How long the request has been in flight in milliseconds.
+    pub elapsed_ms: u64,
+}
+
+impl InFlight {
+    /// Create an empty [`InFlight`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an [`InFlightLayer`] tracking requests into this.
+    pub fn layer(&self) -> InFlightLayer {
+        InFlightLayer {
+            in_flight: self.clone(),
+        }
+    }
+
+    /// Returns the number of requests in flight.
+    pub fn len(&self) -> usize {
+        self.inner.requests.lock().len()
+    }
+
+    /// Returns whether there is no request in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the summaries of requests in flight, the slowest first.
+    pub fn requests(&self) -> Vec<InFlightRequest> {
+        let mut requests = self
+            .inner
+            .requests
+            .lock()
+            .values()
+            .map(|entry| InFlightRequest {
+                role: role_str(entry.role),
+                method: entry.method.clone(),
+                caller: entry.caller.clone(),
+                callee: entry.callee.clone(),
+                peer: entry.peer.as_ref().map(ToString::to_string),
+                elapsed_ms: millis(entry.start.elapsed()),
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by_key(|request| Reverse(request.elapsed_ms));
+        requests
+    }
+
+    fn enter<Cx: Context>(&self, cx: &Cx) -> InFlightGuard {
+        let rpc_info = cx.rpc_info();
+        let role = rpc_info.role();
+        let peer = match role {
+            Role::Client => rpc_info.callee().address(),
+            Role::Server => rpc_info.caller().address(),
+        };
+        let entry = InFlightEntry {
+            role,
+            method: rpc_info.method().clone(),
+            caller: rpc_info.caller().service_name(),
+            callee: rpc_info.callee().service_name(),
+            peer,
+            start: Instant::now(),
+        };
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.inner.requests.lock().insert(id, entry);
+        InFlightGuard {
+            in_flight: self.inner.clone(),
+            id,
+        }
+    }
+}
+
+/// Removes the request from [`InFlight`] when the call returns or is cancelled.
+struct InFlightGuard {
+    in_flight: Arc<InFlightInner>,
+    id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.requests.lock().remove(&self.id);
+    }
+}
+
+/// [`Layer`] for tracking requests in an [`InFlight`].
+///
+/// It works with the services of any volo contexts, e.g., servers or clients of volo-thrift,
+/// volo-grpc and volo-http.
+#[derive(Clone, Debug)]
+pub struct InFlightLayer {
+    in_flight: InFlight,
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            in_flight: self.in_flight,
+        }
+    }
+}
+
+/// The [`Service`] generated by [`InFlightLayer`].
+#[derive(Clone, Debug)]
+pub struct InFlightService<S> {
+    inner: S,
+    in_flight: InFlight,
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for InFlightService<S>
+where
+    Cx: Context + Send,
+    Req: Send,
+    S: Service<Cx, Req> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let _guard = self.in_flight.enter(cx);
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod admin_tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use faststr::FastStr;
+    use http::{Method, StatusCode};
+    use motore::{Service, layer::Layer, service::service_fn};
+    use serde::Serialize;
+    use tokio::sync::oneshot;
+    use tracing_subscriber::{EnvFilter, reload};
+    use volo::{
+        context::{Context, Role},
+        discovery::{Change, Instance},
+        event::{self, BreakerState, BreakerStateChanged, DiscoveryDiffApplied},
+        net::Address,
+    };
+
+    use super::Admin;
+    use crate::{
+        body::{Body, BodyConversion},
+        context::ServerContext,
+        server::{route::Router, test_helpers::empty_cx},
+    };
+
+    async fn call(router: &Router, method: Method, uri: &str, body: &str) -> (StatusCode, String) {
+        let req = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let resp = router.call(&mut empty_cx(), req).await.unwrap();
+        let status = resp.status();
+        (status, resp.into_body().into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints() {
+        #[derive(Serialize)]
+        struct AppConfig {
+            port: u16,
+            host: &'static str,
+        }
+
+        let admin = Admin::new().config("app", || AppConfig {
+            port: 8080,
+            host: "localhost",
+        });
+        let router = admin.clone().into_router();
+
+        let (status, body) = call(&router, Method::GET, "/config", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"app":{"port":8080,"host":"localhost"}}"#);
+
+        let peer = Address::from("127.0.0.1:8888".parse::<SocketAddr>().unwrap());
+        let guard = event::connection("admin-test", Role::Client, &peer);
+        let (_, body) = call(&router, Method::GET, "/connections", "").await;
+        assert!(body.contains(
+            r#"{"protocol":"admin-test","role":"client","peer":"127.0.0.1:8888","count":1}"#
+        ));
+        drop(guard);
+        let (_, body) = call(&router, Method::GET, "/connections", "").await;
+        assert!(!body.contains("admin-test"));
+
+        event::emit(|| BreakerStateChanged {
+            name: "admin-test".into(),
+            from: BreakerState::Closed,
+            to: BreakerState::Open,
+        });
+        let (_, body) = call(&router, Method::GET, "/breakers", "").await;
+        assert!(body.contains(r#""admin-test":{"state":"open","#));
+
+        let instance = Arc::new(Instance {
+            address: peer.clone(),
+            weight: 10,
+            tags: Default::default(),
+        });
+        let change = Change {
+            key: FastStr::from_static_str("admin-test"),
+            all: vec![instance.clone()],
+            added: vec![instance],
+            updated: Vec::new(),
+            removed: Vec::new(),
+        };
+        event::emit(|| DiscoveryDiffApplied::from(&change));
+        let (_, body) = call(&router, Method::GET, "/discovery", "").await;
+        assert!(body.contains(
+            r#""admin-test":{"instances":[{"address":"127.0.0.1:8888","weight":10,"tags":{}}],"#
+        ));
+
+        let (status, body) = call(&router, Method::GET, "/runtime", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#""workers":"#));
+    }
+
+    #[tokio::test]
+    async fn log_level() {
+        let admin = Admin::new();
+        let router = admin.clone().into_router();
+        let (status, _) = call(&router, Method::GET, "/log_level", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // the filter can be reloaded as long as the layer is alive
+        let (_layer, handle) = reload::Layer::<_, ()>::new(EnvFilter::new("info"));
+        let router = admin.log_filter(handle).into_router();
+        assert_eq!(
+            call(&router, Method::GET, "/log_level", "").await,
+            (StatusCode::OK, "info".to_owned())
+        );
+        let (status, _) = call(&router, Method::PUT, "/log_level", "volo=debug").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            call(&router, Method::GET, "/log_level", "").await,
+            (StatusCode::OK, "volo=debug".to_owned())
+        );
+        let (status, _) = call(&router, Method::PUT, "/log_level", "volo=loud").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn in_flight() {
+        let admin = Admin::new();
+        let (tx, rx) = oneshot::channel::<()>();
+        let svc = admin.in_flight_layer().layer(service_fn(
+            |_: &mut ServerContext, rx: oneshot::Receiver<()>| async move {
+                let _ = rx.await;
+                Ok::<_, ()>(())
+            },
+        ));
+        let handle = tokio::spawn(async move {
+            let mut cx = empty_cx();
+            cx.rpc_info_mut().set_method("/slow".into());
+            svc.call(&mut cx, rx).await
+        });
+        while admin.in_flight().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let (_, body) = call(&admin.clone().into_router(), Method::GET, "/in_flight", "").await;
+        assert!(body.starts_with(r#"[{"role":"server","method":"/slow","#));
+
+        tx.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        assert!(admin.in_flight().is_empty());
+    }
+}
@@ -0,0 +1,455 @@
+//! Rooms and broadcasting for WebSocket connections.
+//!
+//! [`Broadcast`] manages rooms of subscribers, each WebSocket connection holds a [`Subscription`]
+//! with a bounded queue, joins or leaves rooms by it, and forwards messages from the queue to the
+//! socket. When a client cannot keep up with the messages, the [`SlowClientPolicy`] decides what
+//! to do with the queue.
+//!
+//! # Example
+//!
+//! ```
+//! use faststr::FastStr;
+//! use futures_util::{sink::SinkExt, stream::StreamExt};
+//! use volo_http::{
+//!     response::Response,
+//!     server::{
+//!         route::{Router, get},
+//!         utils::{
+//!             broadcast::Broadcast,
+//!             ws::{Message, WebSocket, WebSocketUpgrade},
+//!         },
+//!     },
+//!     utils::Extension,
+//! };
+//!
+//! async fn chat(mut socket: WebSocket, broadcast: Broadcast) {
+//!     let mut sub = broadcast.subscribe();
+//!     sub.join("lobby");
+//!     loop {
+//!         tokio::select! {
+//!             msg = socket.next() => match msg {
+//!                 Some(Ok(msg @ Message::Text(_))) => {
+//!                     broadcast.send("lobby", msg);
+//!                 }
+//!                 Some(Ok(_)) => {}
+//!                 _ => break,
+//!             },
+//!             msg = sub.recv() => match msg {
+//!                 Some(msg) => {
+//!                     if socket.send(msg).await.is_err() {
+//!                         break;
+//!                     }
+//!                 }
+//!                 // disconnected by `SlowClientPolicy::Disconnect`
+//!                 None => break,
+//!             },
+//!         }
+//!     }
+//! }
+//!
+//! async fn handler(Extension(broadcast): Extension<Broadcast>, ws: WebSocketUpgrade) -> Response {
+//!     ws.on_upgrade(move |socket| chat(socket, broadcast))
+//! }
+//!
+//! let app: Router = Router::new()
+//!     .route("/chat", get(handler))
+//!     .layer(Extension(Broadcast::<FastStr>::new()));
+//! ```
+
+use std::{
+    collections::VecDeque,
+    hash::Hash,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use ahash::{AHashMap, AHashSet};
+use faststr::FastStr;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::ws::Message;
+
+const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+/// What to do when the queue of a subscriber is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlowClientPolicy {
+    /// Drop the oldest message in the queue to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+    /// Close the subscription, [`Subscription::recv`] will return [`None`] after that, and the
+    /// connection should be closed.
+    Disconnect,
+}
+
+/// Rooms of WebSocket subscribers.
+///
+/// [`Broadcast`] is cheap to clone and all clones share the same rooms, so it can be shared by
+/// handlers through [`Extension`](crate::utils::Extension).
+///
+/// `K` is the type of room names.
+pub struct Broadcast<K = FastStr> {
+    inner: Arc<Inner<K>>,
+}
+
+struct Inner<K> {
+    capacity: usize,
+    policy: SlowClientPolicy,
+    next_id: AtomicU64,
+    subscribers: Mutex<AHashMap<u64, Arc<Queue>>>,
+    rooms: Mutex<AHashMap<K, AHashMap<u64, Arc<Queue>>>>,
+}
+
+impl<K> Clone for Broadcast<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> Default for Broadcast<K> {
+    fn default() -> Self {
+        Self::with_config(DEFAULT_QUEUE_CAPACITY, SlowClientPolicy::default())
+    }
+}
+
+impl<K> Broadcast<K> {
+    /// Create a [`Broadcast`] with queue capacity of 64 and [`SlowClientPolicy::DropOldest`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a [`Broadcast`] with the given queue capacity of each subscriber and the policy for
+    /// slow subscribers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_config(capacity: usize, policy: SlowClientPolicy) -> Self {
+        assert!(capacity > 0, "capacity of queue must be positive");
+        Self {
+            inner: Arc::new(Inner {
+                capacity,
+                policy,
+                next_id: AtomicU64::new(0),
+                subscribers: Mutex::new(AHashMap::new()),
+                rooms: Mutex::new(AHashMap::new()),
+            }),
+        }
+    }
+
+    /// Create a [`Subscription`] for a connection, it is not in any room until
+    /// [`Subscription::join`] is called.
+    pub fn subscribe(&self) -> Subscription<K>
+    where
+        K: Hash + Eq + Clone,
+    {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let queue = Arc::new(Queue::new(self.inner.capacity));
+        self.inner.subscribers.lock().insert(id, queue.clone());
+        Subscription {
+            broadcast: self.clone(),
+            id,
+            queue,
+            rooms: AHashSet::new(),
+        }
+    }
+
+    /// Send a message to all subscribers in the room, and return the number of subscribers that
+    /// received it.
+    pub fn send<Q>(&self, room: Q, msg: Message) -> usize
+    where
+        K: Hash + Eq + From<Q>,
+    {
+        let room = K::from(room);
+        let queues = match self.inner.rooms.lock().get(&room) {
+            Some(members) => members.values().cloned().collect::<Vec<_>>(),
+            None => return 0,
+        };
+        self.fan_out(queues, msg)
+    }
+
+    /// Send a message to all subscribers no matter which room they are in, and return the number
+    /// of subscribers that received it.
+    pub fn send_all(&self, msg: Message) -> usize {
+        let queues = self
+            .inner
+            .subscribers
+            .lock()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.fan_out(queues, msg)
+    }
+
+    fn fan_out(&self, queues: Vec<Arc<Queue>>, msg: Message) -> usize {
+        queues
+            .iter()
+            .filter(|queue| queue.push(msg.clone(), self.inner.policy))
+            .count()
+    }
+
+    /// Get the number of subscribers in the room.
+    pub fn room_size<Q>(&self, room: Q) -> usize
+    where
+        K: Hash + Eq + From<Q>,
+    {
+        self.inner
+            .rooms
+            .lock()
+            .get(&K::from(room))
+            .map_or(0, |members| members.len())
+    }
+
+    /// Get the number of all subscribers.
+    pub fn subscribers(&self) -> usize {
+        self.inner.subscribers.lock().len()
+    }
+}
+
+/// A subscriber of [`Broadcast`], it leaves all rooms when dropped.
+pub struct Subscription<K = FastStr>
+where
+    K: Hash + Eq + Clone,
+{
+    broadcast: Broadcast<K>,
+    id: u64,
+    queue: Arc<Queue>,
+    rooms: AHashSet<K>,
+}
+
+impl<K> Subscription<K>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Join a room, messages sent to the room will be received by [`Subscription::recv`].
+    pub fn join<Q>(&mut self, room: Q)
+    where
+        K: From<Q>,
+    {
+        let room = K::from(room);
+        if !self.rooms.insert(room.clone()) {
+            return;
+        }
+        self.broadcast
+            .inner
+            .rooms
+            .lock()
+            .entry(room)
+            .or_default()
+            .insert(self.id, self.queue.clone());
+    }
+
+    /// Leave a room.
+    pub fn leave<Q>(&mut self, room: Q)
+    where
+        K: From<Q>,
+    {
+        let room = K::from(room);
+        if self.rooms.remove(&room) {
+            remove_member(&mut self.broadcast.inner.rooms.lock(), &room, self.id);
+        }
+    }
+
+    /// Get the rooms joined.
+    pub fn rooms(&self) -> impl Iterator<Item = &K> {
+        self.rooms.iter()
+    }
+
+    /// Receive the next message.
+    ///
+    /// [`None`] is returned if the subscription is closed by [`SlowClientPolicy::Disconnect`].
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let notified = self.queue.notify.notified();
+            {
+                let mut state = self.queue.state.lock();
+                if let Some(msg) = state.messages.pop_front() {
+                    return Some(msg);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Get the number of messages dropped because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the subscription is closed by [`SlowClientPolicy::Disconnect`].
+    pub fn is_closed(&self) -> bool {
+        self.queue.state.lock().closed
+    }
+}
+
+impl<K> Drop for Subscription<K>
+where
+    K: Hash + Eq + Clone,
+{
+    fn drop(&mut self) {
+        let inner = &self.broadcast.inner;
+        inner.subscribers.lock().remove(&self.id);
+        let mut rooms = inner.rooms.lock();
+        for room in self.rooms.iter() {
+            remove_member(&mut rooms, room, self.id);
+        }
+    }
+}
+
+fn remove_member<K>(rooms: &mut AHashMap<K, AHashMap<u64, Arc<Queue>>>, room: &K, id: u64)
+where
+    K: Hash + Eq,
+{
+    if let Some(members) = rooms.get_mut(room) {
+        members.remove(&id);
+        if members.is_empty() {
+            rooms.remove(room);
+        }
+    }
+}
+
+struct Queue {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    notify: Notify,
+    dropped: AtomicUsize,
+}
+
+struct QueueState {
+    messages: VecDeque<Message>,
+    closed: bool,
+}
+
+impl Queue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                closed: false,
+            }),
+            notify: Notify::new(),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Push a message into the queue, return `false` if the message is not queued.
+    fn push(&self, msg: Message, policy: SlowClientPolicy) -> bool {
+        let mut state = self.state.lock();
+        if state.closed {
+            return false;
+        }
+        let queued = if state.messages.len() < self.capacity {
+            state.messages.push_back(msg);
+            true
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            match policy {
+                SlowClientPolicy::DropOldest => {
+                    state.messages.pop_front();
+                    state.messages.push_back(msg);
+                    true
+                }
+                SlowClientPolicy::DropNewest => false,
+                SlowClientPolicy::Disconnect => {
+                    tracing::debug!("[Volo-HTTP] broadcast: disconnect a slow subscriber");
+                    state.closed = true;
+                    state.messages.clear();
+                    false
+                }
+            }
+        };
+        drop(state);
+        self.notify.notify_one();
+        queued
+    }
+}
+
+#[cfg(test)]
+mod broadcast_tests {
+    use super::*;
+
+    fn text(s: &'static str) -> Message {
+        Message::Text(s.into())
+    }
+
+    #[tokio::test]
+    async fn rooms() {
+        let broadcast = Broadcast::<FastStr>::new();
+        let mut a = broadcast.subscribe();
+        let mut b = broadcast.subscribe();
+        a.join("lobby");
+        a.join("game");
+        b.join("lobby");
+        assert_eq!(broadcast.room_size("lobby"), 2);
+        assert_eq!(broadcast.subscribers(), 2);
+
+        assert_eq!(broadcast.send("lobby", text("hello")), 2);
+        assert_eq!(broadcast.send("game", text("start")), 1);
+        assert_eq!(broadcast.send("nobody", text("?")), 0);
+        assert_eq!(a.recv().await, Some(text("hello")));
+        assert_eq!(a.recv().await, Some(text("start")));
+        assert_eq!(b.recv().await, Some(text("hello")));
+
+        b.leave("lobby");
+        assert_eq!(broadcast.room_size("lobby"), 1);
+        assert_eq!(broadcast.send_all(text("all")), 2);
+        assert_eq!(b.recv().await, Some(text("all")));
+
+        drop(a);
+        assert_eq!(broadcast.room_size("lobby"), 0);
+        assert_eq!(broadcast.room_size("game"), 0);
+        assert_eq!(broadcast.subscribers(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_client() {
+        let broadcast = Broadcast::<FastStr>::with_config(2, SlowClientPolicy::DropOldest);
+        let mut sub = broadcast.subscribe();
+        sub.join("room");
+        for msg in ["1", "2", "3"] {
+            broadcast.send("room", text(msg));
+        }
+        assert_eq!(sub.dropped(), 1);
+        assert_eq!(sub.recv().await, Some(text("2")));
+        assert_eq!(sub.recv().await, Some(text("3")));
+
+        let broadcast = Broadcast::<FastStr>::with_config(2, SlowClientPolicy::DropNewest);
+        let mut sub = broadcast.subscribe();
+        sub.join("room");
+        for msg in ["1", "2", "3"] {
+            broadcast.send("room", text(msg));
+        }
+        assert_eq!(sub.recv().await, Some(text("1")));
+        assert_eq!(sub.recv().await, Some(text("2")));
+
+        let broadcast = Broadcast::<FastStr>::with_config(1, SlowClientPolicy::Disconnect);
+        let mut sub = broadcast.subscribe();
+        sub.join("room");
+        assert_eq!(broadcast.send("room", text("1")), 1);
+        assert_eq!(broadcast.send("room", text("2")), 0);
+        assert!(sub.is_closed());
+        assert_eq!(sub.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn recv_wakes_up() {
+        let broadcast = Broadcast::<FastStr>::new();
+        let mut sub = broadcast.subscribe();
+        sub.join("room");
+        let task = tokio::spawn(async move { sub.recv().await });
+        tokio::task::yield_now().await;
+        broadcast.send("room", text("wake"));
+        assert_eq!(task.await.unwrap(), Some(text("wake")));
+    }
+}
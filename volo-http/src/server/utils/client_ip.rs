@@ -52,6 +52,7 @@ where
 pub struct ClientIpConfig {
     remote_ip_headers: Vec<HeaderName>,
     trusted_cidrs: Vec<IpNet>,
+    trusted_proxies: Option<Vec<IpNet>>,
 }
 
 impl Default for ClientIpConfig {
@@ -68,6 +69,7 @@ impl Default for ClientIpConfig {
                     0,
                 )),
             ],
+            trusted_proxies: None,
         }
     }
 }
@@ -111,7 +113,7 @@ impl ClientIpConfig {
 
         Ok(Self {
             remote_ip_headers,
-            trusted_cidrs: self.trusted_cidrs,
+            ..self
         })
     }
 
@@ -132,12 +134,73 @@ impl ClientIpConfig {
         H: IntoIterator<Item = IpNet>,
     {
         Self {
-            remote_ip_headers: self.remote_ip_headers,
             trusted_cidrs: cidrs.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Get Real Client IP by skipping the trusted proxies in the headers.
+    ///
+    /// If the caller is not a trusted proxy, the caller ip is the client ip and the headers are
+    /// ignored, otherwise addresses in the headers are checked from the nearest one (the last
+    /// one), and the first address that is not a trusted proxy is the client ip. This prevents
+    /// clients from spoofing their ip by sending the headers.
+    ///
+    /// Once set, [`with_trusted_cidrs`](ClientIpConfig::with_trusted_cidrs) is not used.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use volo_http::server::utils::client_ip::ClientIpConfig;
+    ///
+    /// let client_ip_config = ClientIpConfig::new()
+    ///     .with_remote_ip_headers(vec!["forwarded", "x-forwarded-for"])
+    ///     .unwrap()
+    ///     .with_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()]);
+    /// ```
+    pub fn with_trusted_proxies<H>(self, cidrs: H) -> Self
+    where
+        H: IntoIterator<Item = IpNet>,
+    {
+        Self {
+            trusted_proxies: Some(cidrs.into_iter().collect()),
+            ..self
         }
     }
 }
 
+/// Parse addresses in the `for` parameters of the `Forwarded` header ([RFC 7239]), e.g.,
+/// `for=192.0.2.60;proto=http, for="[2001:db8:cafe::17]:4711"`.
+///
+/// Obfuscated identifiers like `unknown` and `_hidden` are returned as [`None`].
+///
+/// [RFC 7239]: https://datatracker.ietf.org/doc/html/rfc7239
+fn parse_forwarded(value: &str) -> impl Iterator<Item = Option<IpAddr>> + '_ {
+    value.split(',').filter_map(|element| {
+        let node = element.split(';').find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            key.eq_ignore_ascii_case("for").then_some(value)
+        })?;
+        let node = node.trim_matches('"');
+        let ip = match node.strip_prefix('[') {
+            Some(v6) => v6.split_once(']').and_then(|(ip, _)| ip.parse().ok()),
+            None => node.split(':').next().and_then(|ip| ip.parse().ok()),
+        };
+        Some(ip)
+    })
+}
+
+fn parse_header(name: &HeaderName, value: &str) -> Vec<Option<IpAddr>> {
+    if name == http::header::FORWARDED {
+        parse_forwarded(value).collect()
+    } else {
+        value
+            .split(',')
+            .map(|ip| IpAddr::from_str(ip.trim()).ok())
+            .collect()
+    }
+}
+
 /// Return original client IP Address
 ///
 /// If you want to get client IP by retrieving specific headers, you can use
@@ -147,6 +210,14 @@ impl ClientIpConfig {
 /// If you want to get client IP that is trusted with specific cidrs, you can use
 /// [`with_trusted_cidrs`](ClientIpConfig::with_trusted_cidrs) to set the cidrs.
 ///
+/// If the server is behind proxies, you can use
+/// [`with_trusted_proxies`](ClientIpConfig::with_trusted_proxies) to skip the proxies in the
+/// headers.
+///
+/// The `Forwarded` header is parsed as [RFC 7239] if it is in the remote ip headers.
+///
+/// [RFC 7239]: https://datatracker.ietf.org/doc/html/rfc7239
+///
 /// # Example
 ///
 /// ## Default config
@@ -222,6 +293,10 @@ impl<S> ClientIpService<S> {
             None => return ClientIp(None),
         };
 
+        if let Some(trusted_proxies) = &self.config.trusted_proxies {
+            return self.get_proxied_ip(trusted_proxies, remote_ip, headers);
+        }
+
         if let Some(remote_ip) = &remote_ip {
             if !self
                 .config
@@ -238,22 +313,62 @@ impl<S> ClientIpService<S> {
             else {
                 continue;
             };
-            for remote_ip in remote_ips.split(',').map(str::trim) {
-                if let Ok(remote_ip_addr) = IpAddr::from_str(remote_ip) {
-                    if self
-                        .config
-                        .trusted_cidrs
-                        .iter()
-                        .any(|cidr| cidr.contains(&remote_ip_addr))
-                    {
-                        return ClientIp(Some(remote_ip_addr));
-                    }
+            for remote_ip_addr in parse_header(remote_ip_header, remote_ips)
+                .into_iter()
+                .flatten()
+            {
+                if self
+                    .config
+                    .trusted_cidrs
+                    .iter()
+                    .any(|cidr| cidr.contains(&remote_ip_addr))
+                {
+                    return ClientIp(Some(remote_ip_addr));
                 }
             }
         }
 
         ClientIp(remote_ip)
     }
+
+    fn get_proxied_ip(
+        &self,
+        trusted_proxies: &[IpNet],
+        remote_ip: Option<IpAddr>,
+        headers: &HeaderMap,
+    ) -> ClientIp {
+        let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|cidr| cidr.contains(ip));
+        // unix sockets are always from local proxies
+        if remote_ip.as_ref().is_some_and(|ip| !is_trusted(ip)) {
+            return ClientIp(remote_ip);
+        }
+
+        for remote_ip_header in self.config.remote_ip_headers.iter() {
+            let values = headers
+                .get_all(remote_ip_header)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| parse_header(remote_ip_header, v))
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                continue;
+            }
+            let mut client_ip = remote_ip;
+            for ip in values.into_iter().rev() {
+                // obfuscated or invalid addresses cannot be trusted
+                let Some(ip) = ip else {
+                    break;
+                };
+                client_ip = Some(ip);
+                if !is_trusted(&ip) {
+                    break;
+                }
+            }
+            return ClientIp(client_ip);
+        }
+
+        ClientIp(remote_ip)
+    }
 }
 
 impl<S, B> Service<ServerContext, Request<B>> for ClientIpService<S>
@@ -336,4 +451,77 @@ mod client_ip_tests {
         let resp = service.call(&mut cx, req).await.unwrap();
         assert_eq!("10.0.0.1", resp.into_string().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_trusted_proxies() {
+        async fn handler(ClientIp(client_ip): ClientIp) -> String {
+            client_ip.unwrap().to_string()
+        }
+
+        let route: Route<&str> = Route::new(get(handler));
+        let service = ClientIpLayer::new()
+            .with_config(
+                ClientIpConfig::default()
+                    .with_remote_ip_headers(vec!["forwarded", "x-forwarded-for"])
+                    .unwrap()
+                    .with_trusted_proxies(vec!["10.0.0.0/8".parse().unwrap()]),
+            )
+            .layer(route);
+
+        let call = |peer: &'static str, header: &'static str, value: &'static str| {
+            let service = &service;
+            async move {
+                let mut cx = ServerContext::new(Address::from(SocketAddr::from_str(peer).unwrap()));
+                let mut req = simple_req(Method::GET, "/", "");
+                req.headers_mut()
+                    .insert(header, HeaderValue::from_static(value));
+                let resp = service.call(&mut cx, req).await.unwrap();
+                resp.into_string().await.unwrap()
+            }
+        };
+
+        // the first untrusted address from the nearest one
+        assert_eq!(
+            "1.1.1.1",
+            call(
+                "10.0.0.1:8080",
+                "x-forwarded-for",
+                "2.2.2.2, 1.1.1.1, 10.0.0.2"
+            )
+            .await
+        );
+        assert_eq!(
+            "2001:db8:cafe::17",
+            call(
+                "10.0.0.1:8080",
+                "forwarded",
+                "for=192.0.2.60;proto=http, for=\"[2001:db8:cafe::17]:4711\";by=10.0.0.1",
+            )
+            .await
+        );
+        assert_eq!(
+            "192.0.2.43",
+            call("10.0.0.1:8080", "forwarded", "For=192.0.2.43:47011").await
+        );
+        // all addresses are trusted proxies
+        assert_eq!(
+            "10.0.0.3",
+            call("10.0.0.1:8080", "x-forwarded-for", "10.0.0.3, 10.0.0.2").await
+        );
+        // obfuscated address stops the chain
+        assert_eq!(
+            "10.0.0.2",
+            call(
+                "10.0.0.1:8080",
+                "forwarded",
+                "for=1.1.1.1, for=_hidden, for=10.0.0.2"
+            )
+            .await
+        );
+        // headers from untrusted callers are ignored
+        assert_eq!(
+            "1.1.1.1",
+            call("1.1.1.1:8080", "x-forwarded-for", "2.2.2.2").await
+        );
+    }
 }
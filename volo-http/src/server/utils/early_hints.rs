@@ -0,0 +1,166 @@
+//! Utilities for sending `103 Early Hints`
+//!
+//! See [`EarlyHints`] for more details.
+
+use std::{convert::Infallible, sync::Arc};
+
+use http::{HeaderMap, HeaderValue, header, request::Parts};
+use parking_lot::Mutex;
+#[cfg(feature = "http3")]
+use tokio::sync::mpsc;
+
+use crate::{context::ServerContext, server::extract::FromContext};
+
+/// Extractor for sending `103 Early Hints` interim responses before the final response.
+///
+/// Early hints let the client preload resources by the `Link` headers while the server is still
+/// preparing the final response.
+///
+/// Only the HTTP/3 server sends real interim responses, since hyper does not support sending
+/// them in HTTP/1.1 and HTTP/2. For these protocols, the `Link` headers of hints are appended to
+/// the final response instead, and other headers of hints are ignored.
+///
+/// # Example
+///
+/// ```
+/// use http::{HeaderMap, HeaderValue, header};
+/// use volo_http::server::{
+///     route::{Router, get},
+///     utils::early_hints::EarlyHints,
+/// };
+///
+/// async fn index(hints: EarlyHints) -> &'static str {
+///     let mut headers = HeaderMap::new();
+///     headers.insert(
+///         header::LINK,
+///         HeaderValue::from_static("</style.css>; rel=preload; as=style"),
+///     );
+///     hints.send(headers);
+///
+///     // render the page...
+///     "<html>...</html>"
+/// }
+///
+/// let router: Router = Router::new().route("/", get(index));
+/// ```
+#[derive(Clone, Debug)]
+pub struct EarlyHints {
+    repr: Repr,
+}
+
+#[derive(Clone, Debug)]
+enum Repr {
+    /// Hints are sent as interim responses by the server.
+    #[cfg(feature = "http3")]
+    Interim(mpsc::UnboundedSender<HeaderMap>),
+    /// `Link` headers of hints are appended to the final response.
+    Fallback(Arc<Mutex<Vec<HeaderValue>>>),
+}
+
+impl EarlyHints {
+    /// Create an [`EarlyHints`] whose hints are received by the returned receiver.
+    #[cfg(feature = "http3")]
+    pub(crate) fn interim() -> (Self, mpsc::UnboundedReceiver<HeaderMap>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                repr: Repr::Interim(tx),
+            },
+            rx,
+        )
+    }
+
+    /// Create an [`EarlyHints`] whose `Link` headers are appended to the final response by
+    /// [`EarlyHints::append_to`].
+    pub(crate) fn fallback() -> Self {
+        Self {
+            repr: Repr::Fallback(Default::default()),
+        }
+    }
+
+    /// Send a `103 Early Hints` with the headers.
+    ///
+    /// It can be called multiple times, and hints sent after the final response are ignored.
+    pub fn send(&self, headers: HeaderMap) {
+        match &self.repr {
+            #[cfg(feature = "http3")]
+            Repr::Interim(tx) => {
+                let _ = tx.send(headers);
+            }
+            Repr::Fallback(links) => links
+                .lock()
+                .extend(headers.get_all(header::LINK).iter().cloned()),
+        }
+    }
+
+    /// Append `Link` headers of the hints to the final response.
+    pub(crate) fn append_to(&self, headers: &mut HeaderMap) {
+        match &self.repr {
+            #[cfg(feature = "http3")]
+            Repr::Interim(_) => {}
+            Repr::Fallback(links) => {
+                for link in links.lock().drain(..) {
+                    headers.append(header::LINK, link);
+                }
+            }
+        }
+    }
+}
+
+impl FromContext for EarlyHints {
+    type Rejection = Infallible;
+
+    async fn from_context(
+        _: &mut ServerContext,
+        parts: &mut Parts,
+    ) -> Result<Self, Self::Rejection> {
+        // hints are dropped if the request is not from the server, e.g., in tests
+        Ok(parts
+            .extensions
+            .get::<EarlyHints>()
+            .cloned()
+            .unwrap_or_else(Self::fallback))
+    }
+}
+
+#[cfg(test)]
+mod early_hints_tests {
+    use http::{HeaderMap, HeaderValue, header};
+
+    use super::EarlyHints;
+
+    fn hints(link: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LINK, HeaderValue::from_static(link));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers
+    }
+
+    #[test]
+    fn fallback() {
+        let early_hints = EarlyHints::fallback();
+        early_hints.send(hints("</a.css>; rel=preload"));
+        early_hints.send(hints("</b.js>; rel=preload"));
+
+        let mut headers = HeaderMap::new();
+        early_hints.append_to(&mut headers);
+        assert_eq!(
+            headers.get_all(header::LINK).iter().collect::<Vec<_>>(),
+            ["</a.css>; rel=preload", "</b.js>; rel=preload"]
+        );
+        assert!(!headers.contains_key(header::CACHE_CONTROL));
+    }
+
+    #[cfg(feature = "http3")]
+    #[tokio::test]
+    async fn interim() {
+        let (early_hints, mut rx) = EarlyHints::interim();
+        early_hints.send(hints("</a.css>; rel=preload"));
+        assert_eq!(rx.recv().await.unwrap(), hints("</a.css>; rel=preload"));
+
+        // nothing is appended to the final response
+        let mut headers = HeaderMap::new();
+        early_hints.append_to(&mut headers);
+        assert!(headers.is_empty());
+    }
+}
@@ -56,21 +56,30 @@ impl IntoResponse for FileResponse {
         let file = tokio::fs::File::from_std(self.file);
         Response::builder()
             .header(header::CONTENT_TYPE, self.content_type)
-            .body(Body::from_body(FileBody {
-                reader: ReaderStream::with_capacity(file, BUF_SIZE),
-                size: self.size,
-            }))
+            .body(Body::from_body(FileBody::new(file, self.size)))
             .unwrap()
     }
 }
 
 #[pin_project]
-struct FileBody<R> {
+pub(super) struct FileBody<R> {
     #[pin]
     reader: ReaderStream<R>,
     size: u64,
 }
 
+impl<R> FileBody<R>
+where
+    R: AsyncRead,
+{
+    pub(super) fn new(reader: R, size: u64) -> Self {
+        Self {
+            reader: ReaderStream::with_capacity(reader, BUF_SIZE),
+            size,
+        }
+    }
+}
+
 impl<R> http_body::Body for FileBody<R>
 where
     R: AsyncRead,
@@ -2,12 +2,22 @@
 
 mod file_response;
 mod serve_dir;
+mod serve_file;
 
 pub use file_response::FileResponse;
 pub use serve_dir::ServeDir;
+pub use serve_file::ServeFile;
 
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "ws")]
+pub mod broadcast;
 pub mod client_ip;
+pub mod early_hints;
 #[cfg(feature = "multipart")]
 pub mod multipart;
+#[cfg(feature = "session")]
+pub mod session;
+pub mod upload;
 #[cfg(feature = "ws")]
 pub mod ws;
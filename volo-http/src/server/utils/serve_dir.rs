@@ -18,6 +18,26 @@
 //!
 //! The `"."` means `ServeDir` will serve the CWD (current working directory) and then you can
 //! access any file in the directory.
+//!
+//! Files are served with the supports of conditional requests, range requests and precompressed
+//! siblings, see [`ServeFile`] for more details.
+//!
+//! For a SPA (single-page application), the index page can be served for all paths which do not
+//! exist:
+//!
+//! ```
+//! use volo_http::server::{route::Router, utils::ServeDir};
+//!
+//! let router: Router = Router::new().nest_service(
+//!     "/app/",
+//!     ServeDir::new(".")
+//!         .precompressed_br()
+//!         .precompressed_gzip()
+//!         .fallback("index.html"),
+//! );
+//! ```
+//!
+//! [`ServeFile`]: super::ServeFile
 
 use std::{
     fs,
@@ -25,16 +45,21 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use http::{header::HeaderValue, status::StatusCode};
+use http::{header::HeaderValue, request::Parts, status::StatusCode};
 use motore::service::Service;
 
-use super::FileResponse;
+use super::serve_file::{Precompressed, serve_file};
 use crate::{context::ServerContext, request::Request, response::Response, server::IntoResponse};
 
+const DEFAULT_INDEX_FILE: &str = "index.html";
+
 /// [`ServeDir`] is a service for sending files from a given directory.
 pub struct ServeDir<E, F> {
     path: PathBuf,
     mime_getter: F,
+    index_file: Option<PathBuf>,
+    fallback: Option<PathBuf>,
+    precompressed: Precompressed,
     _marker: PhantomData<fn(E)>,
 }
 
@@ -54,6 +79,9 @@ impl<E> ServeDir<E, fn(&Path) -> HeaderValue> {
         Self {
             path,
             mime_getter: guess_mime,
+            index_file: Some(PathBuf::from(DEFAULT_INDEX_FILE)),
+            fallback: None,
+            precompressed: Precompressed::default(),
             _marker: PhantomData,
         }
     }
@@ -69,11 +97,52 @@ impl<E> ServeDir<E, fn(&Path) -> HeaderValue> {
         ServeDir {
             path: self.path,
             mime_getter,
+            index_file: self.index_file,
+            fallback: self.fallback,
+            precompressed: self.precompressed,
             _marker: self._marker,
         }
     }
 }
 
+impl<E, F> ServeDir<E, F> {
+    /// Set the file to serve when a directory is requested, or [`None`] for responding
+    /// `404 Not Found`.
+    ///
+    /// Default is `index.html`.
+    pub fn index_file<P>(mut self, index_file: Option<P>) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.index_file = index_file.map(|p| p.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set a file relative to the serving directory, it will be served for all requested files
+    /// which do not exist, e.g., the index page of a SPA (single-page application).
+    pub fn fallback<P>(mut self, fallback: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.fallback = Some(fallback.as_ref().to_path_buf());
+        self
+    }
+
+    /// Serve the `.gz` sibling of the requested file, e.g., `index.html.gz`, if it exists and the
+    /// client accepts `gzip`.
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.precompressed.gzip = true;
+        self
+    }
+
+    /// Serve the `.br` sibling of the requested file, e.g., `index.html.br`, if it exists and the
+    /// client accepts `br`.
+    pub fn precompressed_br(mut self) -> Self {
+        self.precompressed.br = true;
+        self
+    }
+}
+
 impl<B, E, F> Service<ServerContext, Request<B>> for ServeDir<E, F>
 where
     B: Send,
@@ -87,16 +156,19 @@ where
         _: &mut ServerContext,
         req: Request<B>,
     ) -> Result<Self::Response, Self::Error> {
+        let (parts, _) = req.into_parts();
         // Get relative path from uri
-        let path = req.uri().path();
+        let path = parts.uri.path();
         let path = path.strip_prefix('/').unwrap_or(path);
 
         tracing::trace!("[Volo-HTTP] ServeDir: path: {path}");
 
         // Join to the serving directory and canonicalize it
-        let path = self.path.join(path);
-        let Ok(path) = fs::canonicalize(path) else {
-            return Ok(StatusCode::NOT_FOUND.into_response());
+        let Some(path) = self.resolve(Path::new(path)).await else {
+            return Ok(match &self.fallback {
+                Some(fallback) => self.serve(&self.path.join(fallback), &parts).await,
+                None => StatusCode::NOT_FOUND.into_response(),
+            });
         };
 
         // Reject file which is out of the serving directory
@@ -105,17 +177,42 @@ where
             return Ok(StatusCode::FORBIDDEN.into_response());
         }
 
-        // Check metadata and permission
-        if !path.is_file() {
-            return Ok(StatusCode::NOT_FOUND.into_response());
+        Ok(self.serve(&path, &parts).await)
+    }
+}
+
+impl<E, F> ServeDir<E, F>
+where
+    F: Fn(&Path) -> HeaderValue,
+{
+    /// Returns the canonicalized path of the requested file, or the index file if a directory is
+    /// requested, or [`None`] if the file does not exist.
+    async fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let path = tokio::fs::canonicalize(self.path.join(path)).await.ok()?;
+        if !tokio::fs::metadata(&path).await.ok()?.is_dir() {
+            return Some(path);
         }
+        let index = tokio::fs::canonicalize(path.join(self.index_file.as_ref()?))
+            .await
+            .ok()?;
+        tokio::fs::metadata(&index)
+            .await
+            .ok()?
+            .is_file()
+            .then_some(index)
+    }
 
+    async fn serve(&self, path: &Path, parts: &Parts) -> Response {
         // Get mime and return it!
-        let content_type = (self.mime_getter)(&path);
-        let Ok(resp) = FileResponse::new(path, content_type) else {
-            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
-        };
-        Ok(resp.into_response())
+        let content_type = (self.mime_getter)(path);
+        serve_file(
+            path,
+            content_type,
+            self.precompressed,
+            &parts.method,
+            &parts.headers,
+        )
+        .await
     }
 }
 
@@ -132,7 +229,7 @@ mod serve_dir_tests {
 
     use super::ServeDir;
     use crate::{
-        body::Body,
+        body::{Body, BodyConversion},
         server::{Router, Server},
     };
 
@@ -175,4 +272,45 @@ mod serve_dir_tests {
             StatusCode::FORBIDDEN
         );
     }
+
+    #[tokio::test]
+    async fn index_and_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("index.html"), "index").unwrap();
+        std::fs::write(dir.path().join("docs/index.html"), "docs").unwrap();
+
+        let router: Router<Option<Body>> = Router::new()
+            .nest_service("/static/", ServeDir::new(dir.path()).fallback("index.html"));
+        let server = Server::new(router).into_test_server();
+        let get = async |uri| {
+            let resp = server.call_route(Method::GET, uri, None).await;
+            (resp.status(), resp.into_body().into_string().await.unwrap())
+        };
+        assert_eq!(
+            get("/static/docs/").await,
+            (StatusCode::OK, "docs".to_owned())
+        );
+        assert_eq!(
+            get("/static/app/").await,
+            (StatusCode::OK, "index".to_owned())
+        );
+        assert_eq!(
+            get("/static/app/settings").await,
+            (StatusCode::OK, "index".to_owned())
+        );
+
+        let router: Router<Option<Body>> = Router::new().nest_service(
+            "/static/",
+            ServeDir::new(dir.path()).index_file(None::<&str>),
+        );
+        let server = Server::new(router).into_test_server();
+        assert_eq!(
+            server
+                .call_route(Method::GET, "/static/docs/", None)
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+    }
 }
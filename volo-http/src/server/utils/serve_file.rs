@@ -0,0 +1,576 @@
+//! Service for serving a single file.
+//!
+//! This module includes [`ServeFile`], and the file serving used by both [`ServeFile`] and
+//! [`ServeDir`], including:
+//!
+//! - Conditional requests by `If-None-Match` and `If-Modified-Since`
+//! - Range requests by `Range` and `If-Range`, only a single range is supported
+//! - Precompressed `.br` and `.gz` siblings selected by `Accept-Encoding`
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use std::{
+    ffi::OsString,
+    io::{self, SeekFrom},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    method::Method,
+    status::StatusCode,
+};
+use motore::service::Service;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use super::file_response::FileBody;
+use crate::{
+    body::Body, context::ServerContext, request::Request, response::Response, server::IntoResponse,
+};
+
+/// [`ServeFile`] is a service for sending a single file.
+///
+/// # Examples
+///
+/// ```
+/// use volo_http::server::{
+///     route::{Router, get_service},
+///     utils::ServeFile,
+/// };
+///
+/// let router: Router = Router::new().route(
+///     "/favicon.ico",
+///     get_service(ServeFile::new("static/favicon.ico")),
+/// );
+/// ```
+pub struct ServeFile<E> {
+    path: PathBuf,
+    content_type: HeaderValue,
+    precompressed: Precompressed,
+    _marker: PhantomData<fn(E)>,
+}
+
+impl<E> ServeFile<E> {
+    /// Create a new [`ServeFile`] service with the given path.
+    ///
+    /// The `Content-Type` is guessed through the file extension name.
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let content_type = super::serve_dir::guess_mime(&path);
+        Self {
+            path,
+            content_type,
+            precompressed: Precompressed::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the `Content-Type` of the file.
+    pub fn content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Serve the `.gz` sibling of the file, e.g., `index.html.gz`, if it exists and the client
+    /// accepts `gzip`.
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.precompressed.gzip = true;
+        self
+    }
+
+    /// Serve the `.br` sibling of the file, e.g., `index.html.br`, if it exists and the client
+    /// accepts `br`.
+    pub fn precompressed_br(mut self) -> Self {
+        self.precompressed.br = true;
+        self
+    }
+}
+
+impl<B, E> Service<ServerContext, Request<B>> for ServeFile<E>
+where
+    B: Send,
+{
+    type Response = Response;
+    type Error = E;
+
+    async fn call(
+        &self,
+        _: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(serve_file(
+            &self.path,
+            self.content_type.clone(),
+            self.precompressed,
+            req.method(),
+            req.headers(),
+        )
+        .await)
+    }
+}
+
+/// Precompressed siblings enabled for serving.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Precompressed {
+    pub(super) gzip: bool,
+    pub(super) br: bool,
+}
+
+impl Precompressed {
+    fn is_enabled(&self) -> bool {
+        self.gzip || self.br
+    }
+
+    /// Returns the enabled encodings accepted by the client, with their file extension names, in
+    /// the order of preference.
+    fn accepted(&self, headers: &HeaderMap) -> Vec<(&'static str, &'static str)> {
+        let mut candidates = Vec::new();
+        if self.br {
+            candidates.push(("br", "br"));
+        }
+        if self.gzip {
+            candidates.push(("gzip", "gz"));
+        }
+        let mut accepted = candidates
+            .into_iter()
+            .filter_map(|(encoding, ext)| {
+                let q = accept_quality(headers, encoding);
+                (q > 0.0).then_some((q, encoding, ext))
+            })
+            .collect::<Vec<_>>();
+        // the sort is stable, so `br` is preferred if qualities are equal
+        accepted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        accepted
+            .into_iter()
+            .map(|(_, encoding, ext)| (encoding, ext))
+            .collect()
+    }
+}
+
+/// Returns the quality of the encoding in `Accept-Encoding`, `0` means not acceptable.
+fn accept_quality(headers: &HeaderMap, encoding: &str) -> f32 {
+    let mut wildcard = None;
+    for value in headers.get_all(header::ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let q = params
+                .find_map(|param| {
+                    let (key, value) = param.split_once('=')?;
+                    (key.trim() == "q").then(|| value.trim().parse::<f32>().ok())?
+                })
+                .unwrap_or(1.0);
+            if name.eq_ignore_ascii_case(encoding) {
+                return q;
+            }
+            if name == "*" {
+                wildcard = Some(q);
+            }
+        }
+    }
+    wildcard.unwrap_or(0.0)
+}
+
+/// Serve the file at the path for the request, all errors are converted to responses.
+pub(super) async fn serve_file(
+    path: &Path,
+    content_type: HeaderValue,
+    precompressed: Precompressed,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        let mut resp = StatusCode::METHOD_NOT_ALLOWED.into_response();
+        resp.headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return resp;
+    }
+
+    let (file, encoding) = match open(path, precompressed, headers).await {
+        Ok(Some(opened)) => opened,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::debug!(
+                "[Volo-HTTP] ServeFile: failed to open {}: {err}",
+                path.display()
+            );
+            return match err.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+            .into_response();
+        }
+    };
+    let Ok(metadata) = file.metadata().await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let size = metadata.len();
+    let modified = metadata.modified().ok();
+    let etag = modified.map(|modified| etag(modified, size));
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(etag) = &etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    if let Some(modified) = modified {
+        builder = builder.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+    if precompressed.is_enabled() {
+        builder = builder.header(header::VARY, "accept-encoding");
+    }
+    if let Some(encoding) = encoding {
+        builder = builder.header(header::CONTENT_ENCODING, encoding);
+    }
+
+    if is_not_modified(headers, etag.as_deref(), modified) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let range = if if_range_matches(headers, etag.as_deref(), modified) {
+        headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_range(value, size))
+    } else {
+        None
+    };
+    let (start, len) = match range {
+        None => (0, size),
+        Some(Ok((start, end))) => {
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+            (start, end - start + 1)
+        }
+        Some(Err(())) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    builder = builder.header(header::CONTENT_LENGTH, len);
+
+    if method == Method::HEAD {
+        return builder.body(Body::empty()).unwrap();
+    }
+    let mut file = file;
+    if start > 0 && file.seek(SeekFrom::Start(start)).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    builder
+        .body(Body::from_body(FileBody::new(file.take(len), len)))
+        .unwrap()
+}
+
+/// Open the precompressed sibling accepted by the client, or the file itself.
+///
+/// Returns `None` if the path is not a file.
+async fn open(
+    path: &Path,
+    precompressed: Precompressed,
+    headers: &HeaderMap,
+) -> io::Result<Option<(File, Option<&'static str>)>> {
+    for (encoding, ext) in precompressed.accepted(headers) {
+        let mut sibling = OsString::from(path.as_os_str());
+        sibling.push(".");
+        sibling.push(ext);
+        if let Ok(file) = File::open(&sibling).await {
+            if file
+                .metadata()
+                .await
+                .is_ok_and(|metadata| metadata.is_file())
+            {
+                return Ok(Some((file, Some(encoding))));
+            }
+        }
+    }
+    let file = File::open(path).await?;
+    if !file.metadata().await?.is_file() {
+        return Ok(None);
+    }
+    Ok(Some((file, None)))
+}
+
+fn etag(modified: SystemTime, size: u64) -> String {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("\"{modified:x}-{size:x}\"")
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Check `If-None-Match` and `If-Modified-Since`, the latter is ignored if the former exists.
+fn is_not_modified(headers: &HeaderMap, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        let Some(etag) = etag else {
+            return false;
+        };
+        // weak comparison
+        return if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+        });
+    }
+    if let Some(since) = header_str(headers, header::IF_MODIFIED_SINCE) {
+        let (Some(modified), Ok(since)) = (modified, httpdate::parse_http_date(since)) else {
+            return false;
+        };
+        // the precision of http date is seconds
+        return match modified.duration_since(since) {
+            Ok(elapsed) => elapsed.as_secs() == 0,
+            Err(_) => true,
+        };
+    }
+    false
+}
+
+/// Check `If-Range`, the range is ignored if the file has been changed.
+fn if_range_matches(headers: &HeaderMap, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = header_str(headers, header::IF_RANGE) else {
+        return true;
+    };
+    // strong comparison, so weak tags are never matched
+    if if_range.starts_with('"') {
+        return etag == Some(if_range);
+    }
+    match (modified, httpdate::parse_http_date(if_range)) {
+        (Some(modified), Ok(date)) => {
+            httpdate::fmt_http_date(modified) == httpdate::fmt_http_date(date)
+        }
+        _ => false,
+    }
+}
+
+/// Parse the `Range` with the file size.
+///
+/// Returns `None` if the range should be ignored, e.g., it is invalid or there are multiple
+/// ranges, `Some(Err(()))` if the range is not satisfiable, or the start and the inclusive end of
+/// the range.
+fn parse_range(range: &str, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 || size == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((size.saturating_sub(suffix), size - 1)));
+    }
+    let start = start.parse::<u64>().ok()?;
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        end.parse::<u64>().ok()?
+    };
+    if end < start {
+        return None;
+    }
+    if start >= size {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.min(size - 1))))
+}
+
+#[cfg(test)]
+mod serve_file_tests {
+    use std::{io::Write, path::Path};
+
+    use http::{
+        StatusCode,
+        header::{self, HeaderMap, HeaderValue},
+        method::Method,
+    };
+
+    use super::{Precompressed, parse_range, serve_file};
+    use crate::body::BodyConversion;
+
+    #[test]
+    fn range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some(Ok((0, 9))));
+        assert_eq!(parse_range("bytes=90-", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=90-200", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=-10", 100), Some(Ok((90, 99))));
+        assert_eq!(parse_range("bytes=-200", 100), Some(Ok((0, 99))));
+        assert_eq!(parse_range("bytes=100-", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 100), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), None);
+        assert_eq!(parse_range("bytes=9-0", 100), None);
+        assert_eq!(parse_range("items=0-9", 100), None);
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    async fn get(
+        path: &Path,
+        precompressed: Precompressed,
+        headers: &HeaderMap,
+    ) -> http::Response<Vec<u8>> {
+        let resp = serve_file(
+            path,
+            HeaderValue::from_static("text/plain"),
+            precompressed,
+            &Method::GET,
+            headers,
+        )
+        .await;
+        let (parts, body) = resp.into_parts();
+        let body = body.into_vec().await.unwrap();
+        http::Response::from_parts(parts, body)
+    }
+
+    #[tokio::test]
+    async fn conditional_and_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hello.txt");
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(b"Hello, World!")
+            .unwrap();
+        let none = Precompressed::default();
+
+        let resp = get(&path, none, &HeaderMap::new()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.body(), b"Hello, World!");
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_owned();
+        let modified = resp.headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let resp = get(&path, none, &headers(&[(header::IF_NONE_MATCH, &etag)])).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert!(resp.body().is_empty());
+        let resp = get(
+            &path,
+            none,
+            &headers(&[(header::IF_MODIFIED_SINCE, &modified)]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        // `If-None-Match` takes precedence
+        let resp = get(
+            &path,
+            none,
+            &headers(&[
+                (header::IF_NONE_MATCH, "\"other\""),
+                (header::IF_MODIFIED_SINCE, &modified),
+            ]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = get(&path, none, &headers(&[(header::RANGE, "bytes=7-")])).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes 7-12/13");
+        assert_eq!(resp.body(), b"World!");
+        let resp = get(&path, none, &headers(&[(header::RANGE, "bytes=20-")])).await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers()[header::CONTENT_RANGE], "bytes */13");
+        // the file has been changed
+        let resp = get(
+            &path,
+            none,
+            &headers(&[
+                (header::RANGE, "bytes=0-4"),
+                (header::IF_RANGE, "\"other\""),
+            ]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = get(
+            &path,
+            none,
+            &headers(&[(header::RANGE, "bytes=0-4"), (header::IF_RANGE, &etag)]),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.body(), b"Hello");
+    }
+
+    #[tokio::test]
+    async fn precompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        std::fs::write(&path, b"plain").unwrap();
+        std::fs::write(dir.path().join("app.js.gz"), b"gzip").unwrap();
+        std::fs::write(dir.path().join("app.js.br"), b"br").unwrap();
+        let both = Precompressed {
+            gzip: true,
+            br: true,
+        };
+
+        let resp = get(
+            &path,
+            both,
+            &headers(&[(header::ACCEPT_ENCODING, "gzip, br")]),
+        )
+        .await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+        assert_eq!(resp.body(), b"br");
+
+        let resp = get(
+            &path,
+            both,
+            &headers(&[(header::ACCEPT_ENCODING, "br;q=0.5, gzip")]),
+        )
+        .await;
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.body(), b"gzip");
+
+        let resp = get(
+            &path,
+            both,
+            &headers(&[(header::ACCEPT_ENCODING, "identity")]),
+        )
+        .await;
+        assert!(!resp.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(resp.body(), b"plain");
+
+        // not enabled
+        let gzip = Precompressed {
+            gzip: true,
+            br: false,
+        };
+        let resp = get(&path, gzip, &headers(&[(header::ACCEPT_ENCODING, "br")])).await;
+        assert_eq!(resp.body(), b"plain");
+
+        // the sibling does not exist
+        std::fs::remove_file(dir.path().join("app.js.gz")).unwrap();
+        let resp = get(&path, gzip, &headers(&[(header::ACCEPT_ENCODING, "*")])).await;
+        assert_eq!(resp.body(), b"plain");
+    }
+}
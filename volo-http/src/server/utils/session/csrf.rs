@@ -0,0 +1,176 @@
+//! CSRF protection by tokens of sessions.
+
+use http::{HeaderName, Method, StatusCode};
+use motore::{Service, layer::Layer};
+
+use super::Session;
+use crate::{context::ServerContext, request::Request, response::Response, server::IntoResponse};
+
+const DEFAULT_HEADER_NAME: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// [`Layer`] for rejecting unsafe requests without the CSRF token of the session.
+///
+/// Requests with methods other than `GET`, `HEAD`, `OPTIONS` and `TRACE` should have a header
+/// with the token from [`Session::csrf_token`], otherwise `403 Forbidden` is returned.
+///
+/// Only the header is checked since the body is not read by the layer, so the token should be
+/// sent by scripts, e.g., `fetch` with the token from a `<meta>` tag. For HTML forms submitted
+/// without scripts, check the form field by [`Session::verify_csrf_token`] in the handler instead.
+///
+/// It requires the [`Session`], so it should be applied inside the [`SessionLayer`].
+///
+/// # Example
+///
+/// ```
+/// use volo_http::server::{
+///     route::{Router, get, post},
+///     utils::session::{CsrfLayer, Key, MemoryStore, Session, SessionLayer},
+/// };
+///
+/// async fn page(session: Session) -> String {
+///     format!(
+///         "<meta name=\"csrf-token\" content=\"{}\"><script>const token = \
+///          document.querySelector('meta[name=\"csrf-token\"]').content;fetch('/submit', {{ \
+///          method: 'POST', headers: {{ 'x-csrf-token': token }} }});</script>",
+///         session.csrf_token()
+///     )
+/// }
+///
+/// let router: Router = Router::new()
+///     .route("/", get(page))
+///     .route("/submit", post(|| async { "submitted" }))
+///     .layer(CsrfLayer::new())
+///     .layer(SessionLayer::new(MemoryStore::new(), Key::generate()));
+/// ```
+///
+/// [`SessionLayer`]: super::SessionLayer
+#[derive(Clone, Debug)]
+pub struct CsrfLayer {
+    header_name: HeaderName,
+}
+
+impl Default for CsrfLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsrfLayer {
+    /// Create a new [`CsrfLayer`].
+    pub fn new() -> Self {
+        Self {
+            header_name: DEFAULT_HEADER_NAME,
+        }
+    }
+
+    /// Set the name of the header for the CSRF token.
+    ///
+    /// Default is `x-csrf-token`.
+    pub fn header_name(mut self, header_name: HeaderName) -> Self {
+        self.header_name = header_name;
+        self
+    }
+}
+
+impl<S> Layer<S> for CsrfLayer {
+    type Service = CsrfService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CsrfService {
+            inner,
+            header_name: self.header_name,
+        }
+    }
+}
+
+/// [`Service`] generated by [`CsrfLayer`].
+///
+/// See [`CsrfLayer`] for more details.
+#[derive(Clone, Debug)]
+pub struct CsrfService<S> {
+    inner: S,
+    header_name: HeaderName,
+}
+
+impl<S, B> Service<ServerContext, Request<B>> for CsrfService<S>
+where
+    S: Service<ServerContext, Request<B>> + Send + Sync,
+    S::Response: IntoResponse,
+    B: Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let safe = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+        );
+        if !safe {
+            let verified = match (
+                req.extensions().get::<Session>(),
+                req.headers()
+                    .get(&self.header_name)
+                    .and_then(|value| value.to_str().ok()),
+            ) {
+                (Some(session), Some(token)) => session.verify_csrf_token(token),
+                (None, _) => {
+                    tracing::warn!(
+                        "[Volo-HTTP] CsrfService: session is not found, is `SessionLayer` applied?"
+                    );
+                    false
+                }
+                _ => false,
+            };
+            if !verified {
+                return Ok(StatusCode::FORBIDDEN.into_response());
+            }
+        }
+        Ok(self.inner.call(cx, req).await?.into_response())
+    }
+}
+
+#[cfg(test)]
+mod csrf_tests {
+    use http::{Method, StatusCode};
+    use motore::{Service, layer::Layer};
+
+    use super::CsrfLayer;
+    use crate::{
+        body::Body,
+        server::{
+            route::{Route, any},
+            test_helpers::empty_cx,
+            utils::session::Session,
+        },
+    };
+
+    #[tokio::test]
+    async fn csrf() {
+        let route: Route<Option<Body>> = Route::new(any(|| async { "ok" }));
+        let service = CsrfLayer::new().layer(route);
+        let session = Session::default();
+        let token = session.csrf_token();
+
+        let call = async |method: Method, token: Option<&str>| {
+            let mut req = http::Request::builder().method(method).uri("/");
+            if let Some(token) = token {
+                req = req.header("x-csrf-token", token);
+            }
+            let mut req = req.body(None).unwrap();
+            req.extensions_mut().insert(session.clone());
+            service.call(&mut empty_cx(), req).await.unwrap().status()
+        };
+        assert_eq!(call(Method::GET, None).await, StatusCode::OK);
+        assert_eq!(call(Method::POST, None).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            call(Method::POST, Some("invalid")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(call(Method::POST, Some(&token)).await, StatusCode::OK);
+    }
+}
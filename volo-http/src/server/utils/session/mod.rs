@@ -0,0 +1,633 @@
+//! Session management with signed or encrypted cookies.
+//!
+//! [`SessionLayer`] loads the [`Session`] of each request from a [`SessionStore`] by the session
+//! cookie, and saves it after the handler returns if it is changed.
+//!
+//! The session cookie is protected by a [`Key`], it is encrypted and authenticated by AES-GCM by
+//! default, or only signed by HMAC if [`SessionLayer::signed`] is used.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_http::server::{
+//!     route::{Router, get},
+//!     utils::session::{Key, MemoryStore, Session, SessionLayer},
+//! };
+//!
+//! async fn counter(session: Session) -> String {
+//!     let count = session.get::<u64>("count").unwrap_or_default() + 1;
+//!     session.insert("count", &count).unwrap();
+//!     format!("visited {count} times")
+//! }
+//!
+//! let router: Router = Router::new()
+//!     .route("/", get(counter))
+//!     .layer(SessionLayer::new(MemoryStore::new(), Key::generate()));
+//! ```
+
+mod csrf;
+mod store;
+
+use std::{error::Error, fmt, sync::Arc, time::Duration};
+
+use cookie::{Cookie, CookieJar};
+pub use cookie::{Key, SameSite};
+use http::{HeaderValue, header, request::Parts};
+use motore::{Service, layer::Layer};
+use parking_lot::Mutex;
+use serde::{Serialize, de::DeserializeOwned};
+
+pub use self::{
+    csrf::{CsrfLayer, CsrfService},
+    store::{CookieStore, MemoryStore, RedisClient, RedisStore, SessionData, SessionStore},
+};
+use crate::{
+    context::ServerContext,
+    request::Request,
+    response::Response,
+    server::{IntoResponse, extract::FromContext},
+};
+
+const DEFAULT_COOKIE_NAME: &str = "session";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const CSRF_TOKEN_KEY: &str = "_csrf";
+
+/// Session of the request, it can be extracted from a handler when [`SessionLayer`] is applied.
+///
+/// Values are serialized as JSON, and the session is saved after the handler returns if it is
+/// changed.
+///
+/// It is cheap to clone, and all clones share the same session.
+#[derive(Clone, Default)]
+pub struct Session {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    data: SessionData,
+    changed: bool,
+    renew: bool,
+    purged: bool,
+}
+
+impl Session {
+    fn new(data: SessionData) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                data,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Get the value of the key, or [`None`] if the key does not exist or its value can not be
+    /// deserialized.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let inner = self.inner.lock();
+        let value = inner.data.get(key)?;
+        sonic_rs::from_str(value).ok()
+    }
+
+    /// Insert a value for the key.
+    pub fn insert<K, T>(&self, key: K, value: &T) -> Result<(), sonic_rs::Error>
+    where
+        K: Into<String>,
+        T: Serialize + ?Sized,
+    {
+        let value = sonic_rs::to_string(value)?;
+        let mut inner = self.inner.lock();
+        inner.data.insert(key.into(), value);
+        inner.changed = true;
+        Ok(())
+    }
+
+    /// Remove the key, and returns whether the key exists.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut inner = self.inner.lock();
+        let removed = inner.data.remove(key).is_some();
+        inner.changed |= removed;
+        removed
+    }
+
+    /// Returns whether the key exists.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.lock().data.contains_key(key)
+    }
+
+    /// Returns whether the session is empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().data.is_empty()
+    }
+
+    /// Remove all values of the session.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        if !inner.data.is_empty() {
+            inner.data.clear();
+            inner.changed = true;
+        }
+    }
+
+    /// Save the session with a new id and remove the old one from the store, which should be
+    /// called after the privilege is changed, e.g., login, for preventing session fixation.
+    pub fn renew(&self) {
+        self.inner.lock().renew = true;
+    }
+
+    /// Remove all values of the session, delete it from the store and the session cookie, e.g.,
+    /// logout.
+    pub fn purge(&self) {
+        let mut inner = self.inner.lock();
+        inner.data.clear();
+        inner.purged = true;
+    }
+
+    /// Returns the CSRF token of the session, a new one is generated if it does not exist.
+    ///
+    /// The token should be sent with unsafe requests by the client, and be checked by
+    /// [`Session::verify_csrf_token`] or [`CsrfLayer`].
+    pub fn csrf_token(&self) -> String {
+        if let Some(token) = self.get::<String>(CSRF_TOKEN_KEY) {
+            return token;
+        }
+        let token = store::random_id();
+        self.insert(CSRF_TOKEN_KEY, &token)
+            .expect("serializing a string should not fail");
+        token
+    }
+
+    /// Returns whether the token is the CSRF token of the session.
+    pub fn verify_csrf_token(&self, token: &str) -> bool {
+        self.get::<String>(CSRF_TOKEN_KEY)
+            .is_some_and(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock();
+        // values may be sensitive
+        f.debug_struct("Session")
+            .field("keys", &inner.data.keys().collect::<Vec<_>>())
+            .field("changed", &inner.changed)
+            .finish()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejection of extracting [`Session`] when [`SessionLayer`] is not applied.
+#[derive(Debug)]
+pub struct MissingSessionLayer;
+
+impl fmt::Display for MissingSessionLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("session is not found, is `SessionLayer` applied?")
+    }
+}
+
+impl Error for MissingSessionLayer {}
+
+impl IntoResponse for MissingSessionLayer {
+    fn into_response(self) -> Response {
+        http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+}
+
+impl FromContext for Session {
+    type Rejection = MissingSessionLayer;
+
+    async fn from_context(
+        _: &mut ServerContext,
+        parts: &mut Parts,
+    ) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Session>()
+            .cloned()
+            .ok_or(MissingSessionLayer)
+    }
+}
+
+/// How the session cookie is protected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Protection {
+    Signed,
+    Private,
+}
+
+/// [`Layer`] for loading and saving the [`Session`].
+///
+/// See [`Session`] and the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct SessionLayer<St> {
+    store: Arc<St>,
+    config: SessionConfig,
+}
+
+#[derive(Clone)]
+struct SessionConfig {
+    key: Key,
+    protection: Protection,
+    cookie_name: String,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: SameSite,
+    ttl: Duration,
+    rolling: bool,
+}
+
+impl<St> SessionLayer<St> {
+    /// Create a new [`SessionLayer`] with the [`SessionStore`] and the [`Key`] for protecting
+    /// the session cookie.
+    pub fn new(store: St, key: Key) -> Self {
+        Self {
+            store: Arc::new(store),
+            config: SessionConfig {
+                key,
+                protection: Protection::Private,
+                cookie_name: DEFAULT_COOKIE_NAME.to_owned(),
+                path: "/".to_owned(),
+                domain: None,
+                secure: true,
+                http_only: true,
+                same_site: SameSite::Lax,
+                ttl: DEFAULT_TTL,
+                rolling: false,
+            },
+        }
+    }
+
+    /// Only sign the session cookie by HMAC rather than encrypting it, the value of cookie is
+    /// visible to the client but can not be tampered.
+    ///
+    /// It should not be used with [`CookieStore`] if the session contains secrets.
+    pub fn signed(mut self) -> Self {
+        self.config.protection = Protection::Signed;
+        self
+    }
+
+    /// Set the name of the session cookie.
+    ///
+    /// Default is `session`.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.config.cookie_name = name.into();
+        self
+    }
+
+    /// Set the `Path` of the session cookie.
+    ///
+    /// Default is `/`.
+    pub fn cookie_path(mut self, path: impl Into<String>) -> Self {
+        self.config.path = path.into();
+        self
+    }
+
+    /// Set the `Domain` of the session cookie.
+    ///
+    /// Default is not set.
+    pub fn cookie_domain(mut self, domain: impl Into<String>) -> Self {
+        self.config.domain = Some(domain.into());
+        self
+    }
+
+    /// Set whether the session cookie is `Secure`, which is only sent by HTTPS.
+    ///
+    /// Default is `true`.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.config.secure = secure;
+        self
+    }
+
+    /// Set whether the session cookie is `HttpOnly`, which can not be accessed by scripts.
+    ///
+    /// Default is `true`.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.config.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` of the session cookie.
+    ///
+    /// Default is [`SameSite::Lax`].
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.config.same_site = same_site;
+        self
+    }
+
+    /// Set how long the session lives since it is saved.
+    ///
+    /// Default is 1 day.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = ttl;
+        self
+    }
+
+    /// Set whether the expiration of the session is extended by each request, otherwise it is
+    /// only extended when the session is changed.
+    ///
+    /// Default is `false`.
+    pub fn rolling(mut self, rolling: bool) -> Self {
+        self.config.rolling = rolling;
+        self
+    }
+}
+
+impl<S, St> Layer<S> for SessionLayer<St> {
+    type Service = SessionService<S, St>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SessionService {
+            inner,
+            store: self.store,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+/// [`Service`] generated by [`SessionLayer`].
+///
+/// See [`SessionLayer`] for more details.
+#[derive(Clone)]
+pub struct SessionService<S, St> {
+    inner: S,
+    store: Arc<St>,
+    config: Arc<SessionConfig>,
+}
+
+impl<S, St> SessionService<S, St>
+where
+    St: SessionStore,
+{
+    fn session_token(&self, parts: &Parts) -> Option<String> {
+        let jar = crate::utils::cookie::CookieJar::from_header(&parts.headers);
+        let name = self.config.cookie_name.as_str();
+        let cookie = match self.config.protection {
+            Protection::Private => jar.private(&self.config.key).get(name),
+            Protection::Signed => jar.signed(&self.config.key).get(name),
+        }?;
+        Some(cookie.value().to_owned())
+    }
+
+    async fn load(&self, token: Option<&str>) -> Option<SessionData> {
+        match self.store.load(token?).await {
+            Ok(data) => data,
+            Err(err) => {
+                tracing::warn!("[Volo-HTTP] SessionService: failed to load session: {err}");
+                None
+            }
+        }
+    }
+
+    fn base_cookie(&self, value: String) -> Cookie<'static> {
+        let config = &self.config;
+        let mut cookie = Cookie::build((config.cookie_name.clone(), value))
+            .path(config.path.clone())
+            .secure(config.secure)
+            .http_only(config.http_only)
+            .same_site(config.same_site)
+            .build();
+        if let Some(domain) = &config.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+
+    fn set_cookie(&self, resp: &mut Response, token: String) {
+        let mut cookie = self.base_cookie(token);
+        cookie.set_max_age(
+            cookie::time::Duration::try_from(self.config.ttl)
+                .unwrap_or(cookie::time::Duration::MAX),
+        );
+        let mut jar = CookieJar::new();
+        match self.config.protection {
+            Protection::Private => jar.private_mut(&self.config.key).add(cookie),
+            Protection::Signed => jar.signed_mut(&self.config.key).add(cookie),
+        }
+        for cookie in jar.delta() {
+            append_set_cookie(resp, cookie);
+        }
+    }
+
+    fn remove_cookie(&self, resp: &mut Response) {
+        let mut cookie = self.base_cookie(String::new());
+        cookie.make_removal();
+        append_set_cookie(resp, &cookie);
+    }
+
+    /// Save or delete the session after the handler returns.
+    async fn commit(
+        &self,
+        session: Session,
+        token: Option<String>,
+        loaded: bool,
+        resp: &mut Response,
+    ) {
+        let (data, changed, renew, purged) = {
+            let mut inner = session.inner.lock();
+            (
+                std::mem::take(&mut inner.data),
+                inner.changed,
+                inner.renew,
+                inner.purged,
+            )
+        };
+
+        // an empty session is not worth saving
+        if purged || (data.is_empty() && (changed || renew)) {
+            if let Some(token) = &token {
+                if let Err(err) = self.store.delete(token).await {
+                    tracing::warn!("[Volo-HTTP] SessionService: failed to delete session: {err}");
+                }
+            }
+            if token.is_some() {
+                self.remove_cookie(resp);
+            }
+            return;
+        }
+        if !(changed || renew || (self.config.rolling && loaded)) {
+            return;
+        }
+
+        let mut token = token.filter(|_| loaded);
+        if renew {
+            if let Some(token) = token.take() {
+                if let Err(err) = self.store.delete(&token).await {
+                    tracing::warn!("[Volo-HTTP] SessionService: failed to delete session: {err}");
+                }
+            }
+        }
+        match self
+            .store
+            .save(token.as_deref(), &data, self.config.ttl)
+            .await
+        {
+            Ok(token) => self.set_cookie(resp, token),
+            Err(err) => {
+                tracing::warn!("[Volo-HTTP] SessionService: failed to save session: {err}");
+            }
+        }
+    }
+}
+
+fn append_set_cookie(resp: &mut Response, cookie: &Cookie<'_>) {
+    match HeaderValue::from_str(&cookie.encoded().to_string()) {
+        Ok(value) => {
+            resp.headers_mut().append(header::SET_COOKIE, value);
+        }
+        Err(err) => {
+            tracing::warn!("[Volo-HTTP] SessionService: invalid session cookie: {err}");
+        }
+    }
+}
+
+impl<S, St, B> Service<ServerContext, Request<B>> for SessionService<S, St>
+where
+    S: Service<ServerContext, Request<B>> + Send + Sync,
+    S::Response: IntoResponse,
+    St: SessionStore,
+    B: Send,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: Request<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+        let token = self.session_token(&parts);
+        let data = self.load(token.as_deref()).await;
+        let loaded = data.is_some();
+        let session = Session::new(data.unwrap_or_default());
+        parts.extensions.insert(session.clone());
+
+        let mut resp = self
+            .inner
+            .call(cx, Request::from_parts(parts, body))
+            .await?
+            .into_response();
+        self.commit(session, token, loaded, &mut resp).await;
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use http::{HeaderValue, Method, StatusCode, header};
+    use motore::Service;
+
+    use super::{Key, MemoryStore, Session, SessionLayer};
+    use crate::{
+        body::{Body, BodyConversion},
+        response::Response,
+        server::{
+            route::{Router, get},
+            test_helpers::empty_cx,
+        },
+    };
+
+    async fn counter(session: Session) -> String {
+        let count = session.get::<u64>("count").unwrap_or_default() + 1;
+        session.insert("count", &count).unwrap();
+        count.to_string()
+    }
+
+    async fn logout(session: Session) {
+        session.purge();
+    }
+
+    async fn read(session: Session) -> String {
+        session.get::<u64>("count").unwrap_or_default().to_string()
+    }
+
+    fn router(layer: SessionLayer<MemoryStore>) -> Router<Option<Body>> {
+        Router::new()
+            .route("/", get(counter))
+            .route("/read", get(read))
+            .route("/logout", get(logout))
+            .layer(layer)
+    }
+
+    fn set_cookie(resp: &Response) -> Option<String> {
+        let value = resp.headers().get(header::SET_COOKIE)?.to_str().unwrap();
+        Some(value.split(';').next().unwrap().to_owned())
+    }
+
+    async fn call(
+        router: &Router<Option<Body>>,
+        uri: &str,
+        cookie: Option<&str>,
+    ) -> (Option<String>, String) {
+        let mut req = http::Request::builder().method(Method::GET).uri(uri);
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+        }
+        let resp = router
+            .call(&mut empty_cx(), req.body(None).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let cookie = set_cookie(&resp);
+        (cookie, resp.into_body().into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn session_lifecycle() {
+        let server = router(SessionLayer::new(MemoryStore::new(), Key::generate()));
+
+        let (cookie, body) = call(&server, "/", None).await;
+        assert_eq!(body, "1");
+        let cookie = cookie.unwrap();
+        let (new_cookie, body) = call(&server, "/", Some(&cookie)).await;
+        assert_eq!(body, "2");
+        // the session id is not changed
+        assert!(new_cookie.is_some());
+
+        // unchanged session is not saved
+        let (new_cookie, body) = call(&server, "/read", Some(&cookie)).await;
+        assert_eq!(body, "2");
+        assert!(new_cookie.is_none());
+
+        // tampered cookie is rejected
+        let (_, body) = call(&server, "/read", Some(&format!("{cookie}x"))).await;
+        assert_eq!(body, "0");
+
+        let (removal, _) = call(&server, "/logout", Some(&cookie)).await;
+        assert_eq!(removal.unwrap(), "session=");
+        let (_, body) = call(&server, "/read", Some(&cookie)).await;
+        assert_eq!(body, "0");
+    }
+
+    #[tokio::test]
+    async fn rolling() {
+        let server = router(
+            SessionLayer::new(MemoryStore::new(), Key::generate())
+                .signed()
+                .rolling(true),
+        );
+
+        let (cookie, _) = call(&server, "/", None).await;
+        let cookie = cookie.unwrap();
+        // the expiration is extended even if the session is not changed
+        let (new_cookie, body) = call(&server, "/read", Some(&cookie)).await;
+        assert_eq!(body, "1");
+        assert_eq!(new_cookie.unwrap(), cookie);
+    }
+
+    #[test]
+    fn csrf_token() {
+        let session = Session::default();
+        let token = session.csrf_token();
+        assert_eq!(session.csrf_token(), token);
+        assert!(session.verify_csrf_token(&token));
+        assert!(!session.verify_csrf_token("invalid"));
+        assert!(!Session::default().verify_csrf_token(""));
+    }
+}
@@ -0,0 +1,397 @@
+//! Stores of sessions.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use motore::BoxError;
+use parking_lot::Mutex;
+use rand::RngCore;
+
+/// Data of a session, whose values are serialized as JSON.
+pub type SessionData = BTreeMap<String, String>;
+
+/// Store of sessions used by [`SessionLayer`].
+///
+/// The token of session is the value of the session cookie, e.g., the session id, or the session
+/// data itself for [`CookieStore`]. It is protected by the [`SessionLayer`], so stores need not
+/// sign or encrypt it.
+///
+/// [`SessionLayer`]: super::SessionLayer
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load the session by the token, or [`None`] if it does not exist or has expired.
+    fn load(
+        &self,
+        token: &str,
+    ) -> impl Future<Output = Result<Option<SessionData>, BoxError>> + Send;
+
+    /// Save the session, and returns the token for the session cookie.
+    ///
+    /// The `token` is [`None`] for a new session, or a session should be saved with a new id.
+    fn save(
+        &self,
+        token: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<String, BoxError>> + Send;
+
+    /// Delete the session by the token.
+    fn delete(&self, token: &str) -> impl Future<Output = Result<(), BoxError>> + Send;
+}
+
+/// Generate a random id with 256 bits.
+pub(super) fn random_id() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Interval of removing expired sessions from [`MemoryStore`].
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// [`SessionStore`] keeping sessions in memory, which are lost after the server restarts and not
+/// shared between servers.
+///
+/// Expired sessions are removed when they are loaded, and swept periodically when saving
+/// sessions.
+///
+/// It is cheap to clone, and all clones share the same sessions.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+#[derive(Debug)]
+struct Sessions {
+    // sessions with their expiration, `None` means the ttl overflows and it never expires
+    map: HashMap<String, (SessionData, Option<Instant>)>,
+    last_sweep: Instant,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+}
+
+fn is_alive(expires_at: Option<Instant>, now: Instant) -> bool {
+    expires_at.is_none_or(|expires_at| expires_at > now)
+}
+
+impl MemoryStore {
+    /// Create an empty [`MemoryStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    async fn load(&self, token: &str) -> Result<Option<SessionData>, BoxError> {
+        let mut sessions = self.sessions.lock();
+        match sessions.map.get(token) {
+            Some((data, expires_at)) if is_alive(*expires_at, Instant::now()) => {
+                Ok(Some(data.clone()))
+            }
+            Some(_) => {
+                sessions.map.remove(token);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        token: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> Result<String, BoxError> {
+        let now = Instant::now();
+        let token = token.map_or_else(random_id, ToOwned::to_owned);
+        let mut sessions = self.sessions.lock();
+        if now.duration_since(sessions.last_sweep) >= SWEEP_INTERVAL {
+            sessions
+                .map
+                .retain(|_, (_, expires_at)| is_alive(*expires_at, now));
+            sessions.last_sweep = now;
+        }
+        sessions
+            .map
+            .insert(token.clone(), (data.clone(), now.checked_add(ttl)));
+        Ok(token)
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), BoxError> {
+        self.sessions.lock().map.remove(token);
+        Ok(())
+    }
+}
+
+/// [`SessionStore`] keeping sessions in the session cookie itself, so nothing is stored in the
+/// server.
+///
+/// The session cookie is encrypted by [`SessionLayer`] by default, and its size is limited by
+/// browsers, usually 4 KiB.
+///
+/// Since the session is not stored in the server, deleting a session only removes the cookie from
+/// the client, and a copy of the cookie is still valid until it expires.
+///
+/// [`SessionLayer`]: super::SessionLayer
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CookieStore;
+
+impl CookieStore {
+    /// Create a [`CookieStore`].
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl SessionStore for CookieStore {
+    async fn load(&self, token: &str) -> Result<Option<SessionData>, BoxError> {
+        // the token is `{expiration in unix seconds}.{data in json}`
+        let Some((expires_at, data)) = token.split_once('.') else {
+            return Err("invalid session cookie".into());
+        };
+        let expires_at: u64 = expires_at.parse()?;
+        if expires_at <= unix_now().as_secs() {
+            return Ok(None);
+        }
+        Ok(Some(sonic_rs::from_str(data)?))
+    }
+
+    async fn save(
+        &self,
+        _: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> Result<String, BoxError> {
+        let expires_at = unix_now().saturating_add(ttl).as_secs();
+        Ok(format!("{expires_at}.{}", sonic_rs::to_string(data)?))
+    }
+
+    async fn delete(&self, _: &str) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Client of Redis or other key-value databases used by [`RedisStore`].
+///
+/// It is implemented by users with their favorite client.
+pub trait RedisClient: Send + Sync + 'static {
+    /// Get the value of the key, e.g., `GET key`.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<String>, BoxError>> + Send;
+
+    /// Set the value of the key with the expiration, e.g., `SET key value PX ttl`.
+    fn set_ex(
+        &self,
+        key: &str,
+        value: String,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), BoxError>> + Send;
+
+    /// Delete the key, e.g., `DEL key`.
+    fn del(&self, key: &str) -> impl Future<Output = Result<(), BoxError>> + Send;
+}
+
+/// [`SessionStore`] keeping sessions in Redis by a [`RedisClient`], which can be shared between
+/// servers.
+#[derive(Clone, Debug)]
+pub struct RedisStore<C> {
+    client: C,
+    prefix: String,
+}
+
+impl<C> RedisStore<C> {
+    /// Create a [`RedisStore`] with the [`RedisClient`].
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            prefix: "session:".to_owned(),
+        }
+    }
+
+    /// Set the prefix of keys.
+    ///
+    /// Default is `session:`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn key(&self, token: &str) -> String {
+        format!("{}{token}", self.prefix)
+    }
+}
+
+impl<C: RedisClient> SessionStore for RedisStore<C> {
+    async fn load(&self, token: &str) -> Result<Option<SessionData>, BoxError> {
+        match self.client.get(&self.key(token)).await? {
+            Some(data) => Ok(Some(sonic_rs::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        token: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> Result<String, BoxError> {
+        let token = token.map_or_else(random_id, ToOwned::to_owned);
+        self.client
+            .set_ex(&self.key(&token), sonic_rs::to_string(data)?, ttl)
+            .await?;
+        Ok(token)
+    }
+
+    async fn delete(&self, token: &str) -> Result<(), BoxError> {
+        self.client.del(&self.key(token)).await
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use motore::BoxError;
+    use parking_lot::Mutex;
+
+    use super::{
+        CookieStore, MemoryStore, RedisClient, RedisStore, SWEEP_INTERVAL, SessionData,
+        SessionStore,
+    };
+
+    fn data() -> SessionData {
+        SessionData::from([("user".to_owned(), "\"volo\"".to_owned())])
+    }
+
+    async fn check_store<St: SessionStore>(store: St) {
+        let token = store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.load(&token).await.unwrap(), Some(data()));
+
+        let expired = store
+            .save(None, &data(), Duration::from_millis(1))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(store.load(&expired).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let store = MemoryStore::new();
+        check_store(store.clone()).await;
+
+        let token = store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .save(Some(&token), &SessionData::new(), Duration::from_secs(60))
+                .await
+                .unwrap(),
+            token
+        );
+        assert_eq!(store.load(&token).await.unwrap(), Some(SessionData::new()));
+        store.delete(&token).await.unwrap();
+        assert_eq!(store.load(&token).await.unwrap(), None);
+
+        // ttl overflowing `Instant`
+        let token = store.save(None, &data(), Duration::MAX).await.unwrap();
+        assert_eq!(store.load(&token).await.unwrap(), Some(data()));
+    }
+
+    #[tokio::test]
+    async fn memory_store_sweep() {
+        let store = MemoryStore::new();
+        store
+            .save(None, &data(), Duration::from_millis(1))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // expired sessions are kept until the next sweep
+        store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.sessions.lock().map.len(), 2);
+
+        store.sessions.lock().last_sweep -= SWEEP_INTERVAL;
+        store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.sessions.lock().map.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn cookie_store() {
+        // the expiration is in seconds
+        let store = CookieStore::new();
+        let token = store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.load(&token).await.unwrap(), Some(data()));
+        let expired = store.save(None, &data(), Duration::ZERO).await.unwrap();
+        assert_eq!(store.load(&expired).await.unwrap(), None);
+        let token = store.save(None, &data(), Duration::MAX).await.unwrap();
+        assert_eq!(store.load(&token).await.unwrap(), Some(data()));
+        assert!(store.load("invalid").await.is_err());
+    }
+
+    #[derive(Default)]
+    struct MockRedis {
+        kv: Arc<Mutex<HashMap<String, (String, tokio::time::Instant)>>>,
+    }
+
+    impl RedisClient for MockRedis {
+        async fn get(&self, key: &str) -> Result<Option<String>, BoxError> {
+            Ok(self
+                .kv
+                .lock()
+                .get(key)
+                .filter(|(_, expires_at)| *expires_at > tokio::time::Instant::now())
+                .map(|(value, _)| value.clone()))
+        }
+
+        async fn set_ex(&self, key: &str, value: String, ttl: Duration) -> Result<(), BoxError> {
+            self.kv
+                .lock()
+                .insert(key.to_owned(), (value, tokio::time::Instant::now() + ttl));
+            Ok(())
+        }
+
+        async fn del(&self, key: &str) -> Result<(), BoxError> {
+            self.kv.lock().remove(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn redis_store() {
+        let redis = MockRedis::default();
+        let kv = redis.kv.clone();
+        let store = RedisStore::new(redis).prefix("sid:");
+        check_store(store).await;
+        assert!(kv.lock().keys().all(|key| key.starts_with("sid:")));
+    }
+}
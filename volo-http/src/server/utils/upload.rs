@@ -0,0 +1,680 @@
+//! Utilities for resumable uploads.
+//!
+//! See [`ResumableUpload`] for more details.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+
+use bytes::{Bytes, BytesMut};
+use http::{
+    HeaderMap, HeaderValue, Method, StatusCode,
+    header::{self, HeaderName},
+};
+use http_body_util::BodyExt;
+use motore::BoxError;
+use parking_lot::Mutex;
+use tokio::io::AsyncWriteExt;
+
+use crate::{body::Body, request::Request, response::Response, server::IntoResponse};
+
+/// HTTP header `Upload-Offset`, the number of bytes received of an upload.
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+
+/// Store of uploads used by [`ResumableUpload`], which tracks the received bytes of uploads.
+pub trait UploadStore: Send + Sync + 'static {
+    /// Get the number of bytes received of the upload, or `0` if it does not exist.
+    fn offset(&self, id: &str) -> impl Future<Output = Result<u64, BoxError>> + Send;
+
+    /// Append the `data` to the upload at the `offset`, and returns the new offset.
+    ///
+    /// The store should return `Ok(None)` without appending if the `offset` is not the number of
+    /// bytes received, or the upload is being appended by another request.
+    fn append(
+        &self,
+        id: &str,
+        offset: u64,
+        data: Bytes,
+    ) -> impl Future<Output = Result<Option<u64>, BoxError>> + Send;
+
+    /// Remove the upload, e.g., after it is completed and processed.
+    fn remove(&self, id: &str) -> impl Future<Output = Result<(), BoxError>> + Send;
+}
+
+/// [`UploadStore`] keeping uploads in memory, which is only suitable for tests or small files.
+///
+/// It is cheap to clone, and all clones share the same uploads.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUploadStore {
+    uploads: Arc<Mutex<HashMap<String, BytesMut>>>,
+}
+
+impl MemoryUploadStore {
+    /// Create an empty [`MemoryUploadStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the received bytes of the upload out of the store.
+    pub fn take(&self, id: &str) -> Option<Bytes> {
+        self.uploads.lock().remove(id).map(BytesMut::freeze)
+    }
+}
+
+impl UploadStore for MemoryUploadStore {
+    async fn offset(&self, id: &str) -> Result<u64, BoxError> {
+        Ok(self
+            .uploads
+            .lock()
+            .get(id)
+            .map(|buf| buf.len() as u64)
+            .unwrap_or_default())
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> Result<Option<u64>, BoxError> {
+        let mut uploads = self.uploads.lock();
+        let buf = uploads.entry(id.to_owned()).or_default();
+        if buf.len() as u64 != offset {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&data);
+        Ok(Some(buf.len() as u64))
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), BoxError> {
+        self.uploads.lock().remove(id);
+        Ok(())
+    }
+}
+
+/// [`UploadStore`] writing uploads to files in a directory, named by the upload ids.
+///
+/// Ids with characters other than ASCII alphanumerics, `-` and `_` are rejected, so that they
+/// can not escape the directory.
+///
+/// It is cheap to clone, and all clones share the same uploads.
+#[derive(Clone, Debug)]
+pub struct FileUploadStore {
+    dir: Arc<PathBuf>,
+    appending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl FileUploadStore {
+    /// Create a [`FileUploadStore`] writing uploads in the directory, which should exist.
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: Arc::new(dir.as_ref().to_path_buf()),
+            appending: Default::default(),
+        }
+    }
+
+    /// Get the path of the file of the upload.
+    pub fn path(&self, id: &str) -> Result<PathBuf, BoxError> {
+        if id.is_empty()
+            || !id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(format!("invalid upload id: {id:?}").into());
+        }
+        Ok(self.dir.join(id))
+    }
+}
+
+impl UploadStore for FileUploadStore {
+    async fn offset(&self, id: &str) -> Result<u64, BoxError> {
+        match tokio::fs::metadata(self.path(id)?).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn append(&self, id: &str, offset: u64, data: Bytes) -> Result<Option<u64>, BoxError> {
+        let path = self.path(id)?;
+        if !self.appending.lock().insert(id.to_owned()) {
+            return Ok(None);
+        }
+        scopeguard::defer! {
+            self.appending.lock().remove(id);
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        if file.metadata().await?.len() != offset {
+            return Ok(None);
+        }
+        file.write_all(&data).await?;
+        file.flush().await?;
+        Ok(Some(offset + data.len() as u64))
+    }
+
+    async fn remove(&self, id: &str) -> Result<(), BoxError> {
+        match tokio::fs::remove_file(self.path(id)?).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+type CompleteFuture = Pin<Box<dyn Future<Output = Result<(), BoxError>> + Send>>;
+type OnComplete = Arc<dyn Fn(String, u64) -> CompleteFuture + Send + Sync>;
+
+/// Helper of resumable uploads by `Content-Range`, which tracks offsets of uploads by an
+/// [`UploadStore`].
+///
+/// An upload is identified by an id, e.g., a path parameter, and sent by one or more `PUT`
+/// requests:
+///
+/// - A request with `Content-Range: bytes {start}-{end}/{total}` appends its body at `start`, and
+///   the `total` can be `*` if it is unknown yet. A request without `Content-Range` sends the whole
+///   upload.
+/// - A request with `Content-Range: bytes */{total}` and an empty body, or a `HEAD` request,
+///   queries the received bytes.
+///
+/// Responses tell the received bytes by the header [`UPLOAD_OFFSET`] and `Range:
+/// bytes=0-{offset - 1}`, so an interrupted client can resume from it. The status is `308` if
+/// the upload is incomplete, `200` for `HEAD` requests or completed uploads, and `409 Conflict`
+/// if the `start` is not the received bytes, e.g., the upload is being sent by another request.
+///
+/// When all bytes of the upload are received, the callback set by
+/// [`ResumableUpload::on_complete`] is called before the response.
+///
+/// # Example
+///
+/// ```
+/// use volo_http::{
+///     request::Request,
+///     server::{
+///         param::PathParams,
+///         route::{Router, any},
+///         utils::upload::{MemoryUploadStore, ResumableUpload},
+///     },
+/// };
+///
+/// let store = MemoryUploadStore::new();
+/// let uploads = ResumableUpload::new(store.clone()).on_complete(move |id, size| {
+///     let store = store.clone();
+///     async move {
+///         let data = store.take(&id).unwrap_or_default();
+///         // process `size` bytes of `data`...
+///         Ok(())
+///     }
+/// });
+///
+/// let router: Router = Router::new().route(
+///     "/upload/{id}",
+///     any(move |PathParams(id): PathParams<String>, req: Request| {
+///         let uploads = uploads.clone();
+///         async move { uploads.handle(&id, req).await }
+///     }),
+/// );
+/// ```
+pub struct ResumableUpload<S> {
+    store: Arc<S>,
+    max_size: Option<u64>,
+    on_complete: Option<OnComplete>,
+}
+
+impl<S> Clone for ResumableUpload<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            max_size: self.max_size,
+            on_complete: self.on_complete.clone(),
+        }
+    }
+}
+
+impl<S> fmt::Debug for ResumableUpload<S>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumableUpload")
+            .field("store", &self.store)
+            .field("max_size", &self.max_size)
+            .finish()
+    }
+}
+
+impl<S> ResumableUpload<S>
+where
+    S: UploadStore,
+{
+    /// Create a [`ResumableUpload`] with the [`UploadStore`].
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            max_size: None,
+            on_complete: None,
+        }
+    }
+
+    /// Set the max size of uploads, larger uploads are rejected with `413 Payload Too Large`.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Set the callback called with the id and the size of an upload when it is completed.
+    ///
+    /// If the callback fails, the response is `500 Internal Server Error`, and the client may
+    /// query the upload and complete it again.
+    pub fn on_complete<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(String, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        self.on_complete = Some(Arc::new(move |id, size| Box::pin(f(id, size))));
+        self
+    }
+
+    /// Get the [`UploadStore`].
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Handle the request of the upload, all errors are converted to responses.
+    pub async fn handle(&self, id: &str, req: Request) -> Response {
+        let method = req.method().clone();
+        if method != Method::PUT && method != Method::HEAD {
+            let mut resp = StatusCode::METHOD_NOT_ALLOWED.into_response();
+            resp.headers_mut()
+                .insert(header::ALLOW, HeaderValue::from_static("PUT, HEAD"));
+            return resp;
+        }
+
+        let offset = match self.store.offset(id).await {
+            Ok(offset) => offset,
+            Err(err) => return store_error(id, err),
+        };
+        if method == Method::HEAD {
+            return offset_response(StatusCode::OK, offset);
+        }
+
+        let range = match parse_content_range(req.headers()) {
+            Ok(range) => range,
+            Err(status) => return status.into_response(),
+        };
+        let total = match range {
+            ContentRange::Query { total } => {
+                let status = if total == Some(offset) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::PERMANENT_REDIRECT
+                };
+                return offset_response(status, offset);
+            }
+            ContentRange::Bytes { start, end, total } => {
+                if total.is_some_and(|total| end >= total) {
+                    return StatusCode::RANGE_NOT_SATISFIABLE.into_response();
+                }
+                if start != offset {
+                    return offset_response(StatusCode::CONFLICT, offset);
+                }
+                total
+            }
+            ContentRange::None => {
+                if offset != 0 {
+                    return offset_response(StatusCode::CONFLICT, offset);
+                }
+                None
+            }
+        };
+        // the end (exclusive) of this request
+        let end = match range {
+            ContentRange::Bytes { end, .. } => match end.checked_add(1) {
+                Some(end) => Some(end),
+                None => return StatusCode::RANGE_NOT_SATISFIABLE.into_response(),
+            },
+            _ => None,
+        };
+        if let Some(max_size) = self.max_size {
+            if total.or(end).is_some_and(|size| size > max_size) {
+                return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+            }
+        }
+
+        let offset = match self.receive(id, offset, end, req.into_body()).await {
+            Ok(offset) => offset,
+            Err(resp) => return resp,
+        };
+
+        let completed = match range {
+            ContentRange::None => true,
+            _ => total == Some(offset),
+        };
+        if !completed {
+            return offset_response(StatusCode::PERMANENT_REDIRECT, offset);
+        }
+        if let Some(on_complete) = &self.on_complete {
+            if let Err(err) = on_complete(id.to_owned(), offset).await {
+                tracing::warn!("[Volo-HTTP] ResumableUpload: failed to complete {id}: {err}");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+        offset_response(StatusCode::OK, offset)
+    }
+
+    /// Append the body to the upload, and returns the new offset.
+    ///
+    /// The body may be shorter than the range, e.g., the client is interrupted, and the received
+    /// bytes are kept for resuming.
+    async fn receive(
+        &self,
+        id: &str,
+        mut offset: u64,
+        end: Option<u64>,
+        mut body: Body,
+    ) -> Result<u64, Response> {
+        while let Some(frame) = body.frame().await {
+            let data = match frame {
+                Ok(frame) => match frame.into_data() {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                },
+                Err(err) => {
+                    tracing::debug!("[Volo-HTTP] ResumableUpload: failed to receive {id}: {err}");
+                    break;
+                }
+            };
+            if data.is_empty() {
+                continue;
+            }
+            let Some(new_offset) = offset.checked_add(data.len() as u64) else {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+            };
+            if end.is_some_and(|end| new_offset > end) {
+                return Err(StatusCode::BAD_REQUEST.into_response());
+            }
+            if self.max_size.is_some_and(|max_size| new_offset > max_size) {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE.into_response());
+            }
+            offset = match self.store.append(id, offset, data).await {
+                Ok(Some(offset)) => offset,
+                Ok(None) => {
+                    let offset = self.store.offset(id).await.unwrap_or(offset);
+                    return Err(offset_response(StatusCode::CONFLICT, offset));
+                }
+                Err(err) => return Err(store_error(id, err)),
+            };
+        }
+        Ok(offset)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContentRange {
+    /// No `Content-Range`, the body is the whole upload.
+    None,
+    /// `Content-Range: bytes */{total}`
+    Query { total: Option<u64> },
+    /// `Content-Range: bytes {start}-{end}/{total}`, the `end` is inclusive.
+    Bytes {
+        start: u64,
+        end: u64,
+        total: Option<u64>,
+    },
+}
+
+fn parse_content_range(headers: &HeaderMap) -> Result<ContentRange, StatusCode> {
+    let Some(value) = headers.get(header::CONTENT_RANGE) else {
+        return Ok(ContentRange::None);
+    };
+    let value = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let (range, total) = value
+        .trim()
+        .strip_prefix("bytes ")
+        .and_then(|value| value.split_once('/'))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().map_err(|_| StatusCode::BAD_REQUEST)?),
+    };
+    if range.trim() == "*" {
+        return Ok(ContentRange::Query { total });
+    }
+    let (start, end) = range.split_once('-').ok_or(StatusCode::BAD_REQUEST)?;
+    let start = start.trim().parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let end = end.trim().parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    if start > end {
+        return Err(StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+    Ok(ContentRange::Bytes { start, end, total })
+}
+
+fn offset_response(status: StatusCode, offset: u64) -> Response {
+    let mut resp = status.into_response();
+    let headers = resp.headers_mut();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+    if offset > 0 {
+        if let Ok(range) = HeaderValue::try_from(format!("bytes=0-{}", offset - 1)) {
+            headers.insert(header::RANGE, range);
+        }
+    }
+    resp
+}
+
+fn store_error(id: &str, err: BoxError) -> Response {
+    tracing::warn!("[Volo-HTTP] ResumableUpload: failed to access {id}: {err}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+#[cfg(test)]
+mod upload_tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    use bytes::Bytes;
+    use http::{HeaderMap, Method, StatusCode, header};
+
+    use super::{
+        ContentRange, FileUploadStore, MemoryUploadStore, ResumableUpload, UPLOAD_OFFSET,
+        UploadStore, parse_content_range,
+    };
+    use crate::{body::Body, request::Request, response::Response};
+
+    fn put(range: Option<&str>, data: &'static [u8]) -> Request {
+        let mut builder = Request::builder().method(Method::PUT).uri("/upload/id");
+        if let Some(range) = range {
+            builder = builder.header(header::CONTENT_RANGE, range);
+        }
+        builder.body(Body::from(Bytes::from_static(data))).unwrap()
+    }
+
+    fn offset(resp: &Response) -> u64 {
+        resp.headers()
+            .get(UPLOAD_OFFSET)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_range() {
+        let parse = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_RANGE, value.parse().unwrap());
+            parse_content_range(&headers)
+        };
+        assert_eq!(
+            parse_content_range(&HeaderMap::new()),
+            Ok(ContentRange::None)
+        );
+        assert_eq!(
+            parse("bytes 0-9/100"),
+            Ok(ContentRange::Bytes {
+                start: 0,
+                end: 9,
+                total: Some(100)
+            })
+        );
+        assert_eq!(
+            parse("bytes 10-19/*"),
+            Ok(ContentRange::Bytes {
+                start: 10,
+                end: 19,
+                total: None
+            })
+        );
+        assert_eq!(
+            parse("bytes */100"),
+            Ok(ContentRange::Query { total: Some(100) })
+        );
+        assert_eq!(
+            parse("bytes 9-0/100"),
+            Err(StatusCode::RANGE_NOT_SATISFIABLE)
+        );
+        assert_eq!(parse("bytes 0-9"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(parse("items 0-9/100"), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn resume_upload() {
+        let store = MemoryUploadStore::new();
+        let completed = Arc::new(AtomicU64::new(0));
+        let uploads = ResumableUpload::new(store.clone()).on_complete({
+            let completed = completed.clone();
+            move |_, size| {
+                completed.store(size, Ordering::Relaxed);
+                async { Ok(()) }
+            }
+        });
+
+        let resp = uploads
+            .handle("id", put(Some("bytes 0-4/11"), b"hello"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(offset(&resp), 5);
+        assert_eq!(resp.headers().get(header::RANGE).unwrap(), "bytes=0-4");
+
+        // the body is shorter than the range, e.g., the client is interrupted
+        let resp = uploads
+            .handle("id", put(Some("bytes 5-10/11"), b" wo"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(offset(&resp), 8);
+
+        // query the offset
+        let resp = uploads.handle("id", put(Some("bytes */11"), b"")).await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(offset(&resp), 8);
+        let req = Request::builder()
+            .method(Method::HEAD)
+            .body(Body::empty())
+            .unwrap();
+        let resp = uploads.handle("id", req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(offset(&resp), 8);
+
+        // conflict
+        let resp = uploads
+            .handle("id", put(Some("bytes 5-10/11"), b" world"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(offset(&resp), 8);
+        assert_eq!(completed.load(Ordering::Relaxed), 0);
+
+        let resp = uploads
+            .handle("id", put(Some("bytes 8-10/11"), b"rld"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(offset(&resp), 11);
+        assert_eq!(completed.load(Ordering::Relaxed), 11);
+        assert_eq!(
+            store.take("id").unwrap(),
+            Bytes::from_static(b"hello world")
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_upload() {
+        let uploads = ResumableUpload::new(MemoryUploadStore::new()).max_size(8);
+
+        let resp = uploads.handle("id", put(Some("bytes 0-9/10"), b"")).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let resp = uploads.handle("id", put(None, b"volo-http")).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let resp = uploads.handle("id", put(Some("bytes 0-4/4"), b"")).await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        // the end of the range overflows
+        let resp = uploads
+            .handle("id", put(Some("bytes 0-18446744073709551615/*"), b""))
+            .await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        // the body is longer than the range
+        let resp = uploads
+            .handle("id", put(Some("bytes 0-1/*"), b"volo"))
+            .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = uploads.handle("id", put(None, b"volo")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(offset(&resp), 4);
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .body(Body::empty())
+            .unwrap();
+        let resp = uploads.handle("id", req).await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn file_store() {
+        let dir = std::env::temp_dir().join(format!("volo-http-upload-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let store = FileUploadStore::new(&dir);
+
+        assert!(store.path("../id").is_err());
+        assert_eq!(store.offset("id").await.unwrap(), 0);
+        assert_eq!(
+            store
+                .append("id", 0, Bytes::from_static(b"volo"))
+                .await
+                .unwrap(),
+            Some(4)
+        );
+        assert_eq!(
+            store
+                .append("id", 0, Bytes::from_static(b"volo"))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            store
+                .append("id", 4, Bytes::from_static(b"-http"))
+                .await
+                .unwrap(),
+            Some(9)
+        );
+        assert_eq!(store.offset("id").await.unwrap(), 9);
+        assert_eq!(
+            tokio::fs::read(store.path("id").unwrap()).await.unwrap(),
+            b"volo-http"
+        );
+        store.remove("id").await.unwrap();
+        assert_eq!(store.offset("id").await.unwrap(), 0);
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
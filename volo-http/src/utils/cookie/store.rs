@@ -22,17 +22,27 @@ impl CookieStore {
         }
     }
 
-    pub fn store_response_headers(&mut self, headers: &HeaderMap, request_url: &url::Url) {
-        let mut set_cookie_headers = headers.get_all(header::SET_COOKIE).iter().peekable();
-
-        if set_cookie_headers.peek().is_some() {
-            let cookie_iter = set_cookie_headers.filter_map(|val| {
+    /// Store cookies from `Set-Cookie` of the response headers, the parsed cookies are returned.
+    pub fn store_response_headers(
+        &mut self,
+        headers: &HeaderMap,
+        request_url: &url::Url,
+    ) -> Vec<Cookie<'static>> {
+        let cookies = headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|val| {
                 std::str::from_utf8(val.as_bytes())
                     .ok()
                     .and_then(|val| Cookie::parse(val).map(|c| c.into_owned()).ok())
-            });
-            self.inner.store_response_cookies(cookie_iter, request_url);
+            })
+            .collect::<Vec<_>>();
+
+        if !cookies.is_empty() {
+            self.inner
+                .store_response_cookies(cookies.iter().cloned(), request_url);
         }
+        cookies
     }
 
     /// Get [`HeaderValue`] from the cookie store
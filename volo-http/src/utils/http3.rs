@@ -0,0 +1,167 @@
+//! Body utilities shared by client and server of HTTP/3
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use bytes::{Buf, Bytes};
+use h3::error::StreamError;
+use http::HeaderMap;
+use http_body::{Body, Frame};
+use http_body_util::BodyExt;
+
+use crate::error::BoxError;
+
+/// Receiving half of an HTTP/3 request stream, it is implemented by streams of both client and
+/// server.
+pub(crate) trait RecvStream {
+    fn poll_recv_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Bytes>, StreamError>>;
+    fn poll_recv_trailers(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, StreamError>>;
+}
+
+/// Sending half of an HTTP/3 request stream, it is implemented by streams of both client and
+/// server.
+pub(crate) trait SendStream {
+    async fn send_data(&mut self, data: Bytes) -> Result<(), StreamError>;
+    async fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), StreamError>;
+    async fn finish(&mut self) -> Result<(), StreamError>;
+}
+
+macro_rules! impl_stream {
+    ($stream:ident) => {
+        impl<S> RecvStream for ::h3::$stream::RequestStream<S, Bytes>
+        where
+            S: ::h3::quic::RecvStream,
+        {
+            fn poll_recv_data(
+                &mut self,
+                cx: &mut Context<'_>,
+            ) -> Poll<Result<Option<Bytes>, StreamError>> {
+                let data = ready!(self.poll_recv_data(cx))?;
+                Poll::Ready(Ok(data.map(|mut data| data.copy_to_bytes(data.remaining()))))
+            }
+
+            fn poll_recv_trailers(
+                &mut self,
+                cx: &mut Context<'_>,
+            ) -> Poll<Result<Option<HeaderMap>, StreamError>> {
+                self.poll_recv_trailers(cx)
+            }
+        }
+
+        impl<S> SendStream for ::h3::$stream::RequestStream<S, Bytes>
+        where
+            S: ::h3::quic::SendStream<Bytes>,
+        {
+            async fn send_data(&mut self, data: Bytes) -> Result<(), StreamError> {
+                self.send_data(data).await
+            }
+
+            async fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), StreamError> {
+                self.send_trailers(trailers).await
+            }
+
+            async fn finish(&mut self) -> Result<(), StreamError> {
+                self.finish().await
+            }
+        }
+    };
+}
+
+#[cfg(feature = "client")]
+impl_stream!(client);
+#[cfg(feature = "server")]
+impl_stream!(server);
+
+/// [`Body`] received from an HTTP/3 request stream.
+pub(crate) struct RecvBody<S> {
+    stream: S,
+    data_done: bool,
+    end: bool,
+}
+
+impl<S> RecvBody<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            data_done: false,
+            end: false,
+        }
+    }
+}
+
+impl<S> Body for RecvBody<S>
+where
+    S: RecvStream + Unpin,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.end {
+            return Poll::Ready(None);
+        }
+        if !this.data_done {
+            match ready!(this.stream.poll_recv_data(cx)) {
+                Ok(Some(data)) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                Ok(None) => this.data_done = true,
+                Err(err) => {
+                    this.end = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+        }
+        let res = ready!(this.stream.poll_recv_trailers(cx));
+        this.end = true;
+        match res {
+            Ok(Some(trailers)) => Poll::Ready(Some(Ok(Frame::trailers(trailers)))),
+            Ok(None) => Poll::Ready(None),
+            Err(err) => Poll::Ready(Some(Err(err.into()))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.end
+    }
+}
+
+/// Send all frames of the body to the HTTP/3 request stream and finish it.
+pub(crate) async fn send_body<S, B>(stream: &mut S, body: B) -> Result<(), BoxError>
+where
+    S: SendStream,
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    let mut body = std::pin::pin!(body);
+    loop {
+        // the error of body may not be `Send`, so it should be converted before awaiting
+        let frame = match body.frame().await {
+            Some(frame) => frame.map_err(Into::into)?,
+            None => break,
+        };
+        match frame.into_data() {
+            Ok(mut data) => {
+                stream
+                    .send_data(data.copy_to_bytes(data.remaining()))
+                    .await?;
+            }
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    // `send_trailers` also finishes the stream
+                    stream.send_trailers(trailers).await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    stream.finish().await?;
+    Ok(())
+}
@@ -98,23 +98,23 @@ pub(crate) use impl_getter;
 macro_rules! stat_impl {
     ($t: ident) => {
         paste::paste! {
-            #[doc = "Get the recorded [`DateTime`] of \"" $t "\""]
+            #[doc = "Get the recorded [`Timestamp`] of \"" $t "\""]
             #[inline]
-            pub fn $t(&self) -> Option<DateTime<Local>> {
+            pub fn $t(&self) -> Option<Timestamp> {
                 self.$t
             }
 
-            #[doc = "Set a [`DateTime`] of \"" $t "\""]
+            #[doc = "Set a [`Timestamp`] of \"" $t "\""]
             #[doc(hidden)]
             #[inline]
-            pub fn [<set_$t>](&mut self, t: DateTime<Local>) {
+            pub fn [<set_$t>](&mut self, t: Timestamp) {
                 self.$t = Some(t)
             }
 
-            #[doc = "Record the current [`DateTime`] of \"" $t "\""]
+            #[doc = "Record the current [`Timestamp`] of \"" $t "\""]
             #[inline]
             pub fn [<record_ $t>](&mut self) {
-                self.$t = Some(Local::now())
+                self.$t = Some(Timestamp::now())
             }
         }
     };
@@ -4,9 +4,11 @@ pub mod consts;
 #[cfg(feature = "cookie")]
 pub mod cookie;
 mod extension;
+#[cfg(feature = "http3")]
+pub(crate) mod http3;
 #[cfg(feature = "json")]
 pub(crate) mod json;
-#[cfg(feature = "client")]
+#[cfg(all(feature = "client", not(target_family = "wasm")))]
 pub(crate) mod lazy;
 pub(crate) mod macros;
 #[cfg(test)]
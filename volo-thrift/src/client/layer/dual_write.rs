@@ -0,0 +1,456 @@
+//! Sends requests to both the old and the new backend for migrations.
+//!
+//! [`DualWriteLayer`] sends each request to the original client and to a new [`Client`], which
+//! may use another protocol or codec, or call a rewritten service. The response of the original
+//! client is returned, and the response of the new client is compared with it by a
+//! [`Comparator`] asynchronously, so the original requests are not slowed down. Mismatches are
+//! counted in [`DualWriteStats`] and reported to the hook set by [`DualWriteLayer::on_mismatch`].
+//!
+//! Unlike [`MirrorLayer`], all requests are sent to the new client, so it should only be used
+//! for idempotent methods or backends sharing no state.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use volo_thrift::client::layer::dual_write::DualWriteLayer;
+//!
+//! let new = ItemServiceClientBuilder::new("item-v2")
+//!     .address(new_addr)
+//!     .build();
+//! let dual_write = DualWriteLayer::new(new.0).on_mismatch(|mismatch| {
+//!     tracing::warn!("{} mismatched: {:?} != {:?}", mismatch.method, mismatch.old, mismatch.new);
+//! });
+//! let stats = dual_write.stats();
+//!
+//! let client = ItemServiceClientBuilder::new("item")
+//!     .layer_outer(dual_write)
+//!     .build();
+//! ```
+//!
+//! [`MirrorLayer`]: super::mirror::MirrorLayer
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use motore::{layer::Layer, service::Service};
+use pilota::thrift::TMessageType;
+use volo::context::Context;
+
+use crate::{ClientError, client::Client, context::ClientContext};
+
+const DEFAULT_MAX_IN_FLIGHT: usize = 1024;
+
+/// Compares the results of the old and the new client.
+pub trait Comparator<Resp>: Send + Sync + 'static {
+    /// Returns whether the results match.
+    fn compare(&self, old: &Result<Resp, ClientError>, new: &Result<Resp, ClientError>) -> bool;
+}
+
+impl<Resp, F> Comparator<Resp> for F
+where
+    F: Fn(&Result<Resp, ClientError>, &Result<Resp, ClientError>) -> bool + Send + Sync + 'static,
+{
+    fn compare(&self, old: &Result<Resp, ClientError>, new: &Result<Resp, ClientError>) -> bool {
+        self(old, new)
+    }
+}
+
+/// The default [`Comparator`], successful responses match if they are equal, and errors always
+/// match each other since they usually contain addresses of the callees.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EqComparator;
+
+impl<Resp: PartialEq> Comparator<Resp> for EqComparator {
+    fn compare(&self, old: &Result<Resp, ClientError>, new: &Result<Resp, ClientError>) -> bool {
+        match (old, new) {
+            (Ok(old), Ok(new)) => old == new,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A mismatch of the results reported to the hook.
+#[derive(Debug)]
+pub struct Mismatch<'a, Resp> {
+    /// The method of the request.
+    pub method: &'a str,
+    /// The result of the original client.
+    pub old: &'a Result<Resp, ClientError>,
+    /// The result of the new client.
+    pub new: &'a Result<Resp, ClientError>,
+}
+
+/// Statistics of dual written requests.
+///
+/// It is cheap to clone, and all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct DualWriteStats {
+    inner: Arc<DualWriteStatsInner>,
+}
+
+#[derive(Default)]
+struct DualWriteStatsInner {
+    compared: AtomicU64,
+    mismatched: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl DualWriteStats {
+    /// The number of requests whose results are compared.
+    pub fn compared(&self) -> u64 {
+        self.inner.compared.load(Ordering::Relaxed)
+    }
+
+    /// The number of compared requests whose results mismatch.
+    pub fn mismatched(&self) -> u64 {
+        self.inner.mismatched.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests not sent to the new client since too many requests to it were in
+    /// flight.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for DualWriteStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DualWriteStats")
+            .field("compared", &self.compared())
+            .field("mismatched", &self.mismatched())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+type MismatchHook<Resp> = Box<dyn Fn(&Mismatch<'_, Resp>) + Send + Sync>;
+
+/// [`Layer`] for sending requests to both the original client and a new [`Client`].
+///
+/// See the [module documentation](self) for more details.
+pub struct DualWriteLayer<S, Resp, C = EqComparator> {
+    inner: Arc<DualWrite<S, Resp, C>>,
+}
+
+impl<S, Resp, C> Clone for DualWriteLayer<S, Resp, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct DualWrite<S, Resp, C> {
+    new: Client<S>,
+    comparator: C,
+    on_mismatch: Option<MismatchHook<Resp>>,
+    max_in_flight: usize,
+    in_flight: AtomicUsize,
+    stats: DualWriteStats,
+}
+
+impl<S, Resp> DualWriteLayer<S, Resp> {
+    /// Create a [`DualWriteLayer`] sending requests to the `new` client too, and comparing the
+    /// results by [`EqComparator`].
+    pub fn new(new: Client<S>) -> Self {
+        Self::with_comparator(new, EqComparator)
+    }
+}
+
+impl<S, Resp, C> DualWriteLayer<S, Resp, C> {
+    /// Create a [`DualWriteLayer`] sending requests to the `new` client too, and comparing the
+    /// results by the `comparator`.
+    pub fn with_comparator(new: Client<S>, comparator: C) -> Self {
+        Self {
+            inner: Arc::new(DualWrite {
+                new,
+                comparator,
+                on_mismatch: None,
+                max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+                in_flight: AtomicUsize::new(0),
+                stats: DualWriteStats::default(),
+            }),
+        }
+    }
+
+    /// Set the hook called with each mismatch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layer has been cloned.
+    pub fn on_mismatch<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Mismatch<'_, Resp>) + Send + Sync + 'static,
+    {
+        Arc::get_mut(&mut self.inner)
+            .expect("`DualWriteLayer` should not be cloned before configured")
+            .on_mismatch = Some(Box::new(f));
+        self
+    }
+
+    /// Set the maximum number of requests to the new client in flight, requests exceeding the
+    /// limit are only sent to the original client.
+    ///
+    /// Default is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layer has been cloned.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("`DualWriteLayer` should not be cloned before configured")
+            .max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get the [`DualWriteStats`] of the layer.
+    pub fn stats(&self) -> DualWriteStats {
+        self.inner.stats.clone()
+    }
+}
+
+impl<S, Resp, C> DualWrite<S, Resp, C>
+where
+    C: Comparator<Resp>,
+{
+    fn compare(
+        &self,
+        method: &str,
+        old: &Result<Resp, ClientError>,
+        new: &Result<Resp, ClientError>,
+    ) {
+        self.stats.inner.compared.fetch_add(1, Ordering::Relaxed);
+        if self.comparator.compare(old, new) {
+            return;
+        }
+        self.stats.inner.mismatched.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!("[VOLO] results of {method} mismatch between the old and the new client");
+        if let Some(on_mismatch) = &self.on_mismatch {
+            on_mismatch(&Mismatch { method, old, new });
+        }
+    }
+}
+
+impl<S, Resp, C, Inner> Layer<Inner> for DualWriteLayer<S, Resp, C> {
+    type Service = DualWriteService<Inner, S, Resp, C>;
+
+    fn layer(self, inner: Inner) -> Self::Service {
+        DualWriteService {
+            inner,
+            dual_write: self.inner,
+        }
+    }
+}
+
+/// [`Service`] generated by [`DualWriteLayer`].
+pub struct DualWriteService<Inner, S, Resp, C> {
+    inner: Inner,
+    dual_write: Arc<DualWrite<S, Resp, C>>,
+}
+
+impl<Inner: Clone, S, Resp, C> Clone for DualWriteService<Inner, S, Resp, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            dual_write: self.dual_write.clone(),
+        }
+    }
+}
+
+impl<Inner, S, Resp, C, Req> Service<ClientContext, Req> for DualWriteService<Inner, S, Resp, C>
+where
+    Inner: Service<ClientContext, Req, Response = Resp, Error = ClientError> + Send + Sync,
+    Req: Clone + Send + 'static,
+    Resp: Clone + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+    C: Comparator<Resp>,
+    Client<S>: Service<ClientContext, Req, Response = Resp, Error = ClientError>,
+{
+    type Response = Resp;
+    type Error = ClientError;
+
+    async fn call(&self, cx: &mut ClientContext, req: Req) -> Result<Self::Response, Self::Error> {
+        let tx = self.spawn_new(cx, req.clone());
+        let result = self.inner.call(cx, req).await;
+        if let Some(tx) = tx {
+            let _ = tx.send(result.clone());
+        }
+        result
+    }
+}
+
+impl<Inner, S, Resp, C> DualWriteService<Inner, S, Resp, C>
+where
+    S: Clone + Send + Sync + 'static,
+    Resp: Send + Sync + 'static,
+    C: Comparator<Resp>,
+{
+    /// Sends the request to the new client, and returns the sender of the result of the original
+    /// client for comparing.
+    fn spawn_new<Req>(
+        &self,
+        cx: &ClientContext,
+        req: Req,
+    ) -> Option<tokio::sync::oneshot::Sender<Result<Resp, ClientError>>>
+    where
+        Req: Send + 'static,
+        Client<S>: Service<ClientContext, Req, Response = Resp, Error = ClientError>,
+    {
+        let dual_write = &self.dual_write;
+        if dual_write.in_flight.fetch_add(1, Ordering::AcqRel) >= dual_write.max_in_flight {
+            dual_write.in_flight.fetch_sub(1, Ordering::AcqRel);
+            dual_write
+                .stats
+                .inner
+                .dropped
+                .fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        let oneway = cx.message_type == TMessageType::OneWay;
+        let mut new_cx = dual_write.new.make_cx(cx.rpc_info().method(), oneway);
+        let dual_write = dual_write.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            let new = dual_write.new.call(&mut new_cx, req).await;
+            // the original call may be cancelled
+            if let Ok(old) = rx.await {
+                dual_write.compare(new_cx.rpc_info().method(), &old, &new);
+            }
+            dual_write.in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+        Some(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, atomic::AtomicI32};
+
+    use motore::service::service_fn;
+    use pilota::FastStr;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{
+        ApplicationException, ApplicationExceptionKind,
+        client::{Client, ClientInner},
+        context::Config,
+    };
+
+    fn client<S>(transport: S) -> Client<S> {
+        Client {
+            transport,
+            inner: Arc::new(ClientInner {
+                callee_name: FastStr::from_static_str("new"),
+                caller_name: FastStr::from_static_str("test"),
+                config: Config::default(),
+                address: None,
+                seq_id: AtomicI32::new(0),
+                warmup: Default::default(),
+                methods: Default::default(),
+            }),
+        }
+    }
+
+    fn cx() -> ClientContext {
+        client(()).make_cx("Echo", false)
+    }
+
+    async fn echo(_: &mut ClientContext, req: u32) -> Result<Option<u32>, ClientError> {
+        Ok(Some(req))
+    }
+
+    async fn wait_idle<S, Resp, C>(layer: &DualWriteLayer<S, Resp, C>) {
+        while layer.inner.in_flight.load(Ordering::Acquire) != 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn dual_write_compare() {
+        // the new backend differs for multiples of 3, and fails for multiples of 5
+        let new = client(service_fn(|_: &mut ClientContext, req: u32| async move {
+            if req % 5 == 0 {
+                Err(ApplicationException::new(ApplicationExceptionKind::UNKNOWN, "five").into())
+            } else if req % 3 == 0 {
+                Ok(Some(req + 1))
+            } else {
+                Ok(Some(req))
+            }
+        }));
+        let mismatches = Arc::new(Mutex::new(Vec::new()));
+        let recorded = mismatches.clone();
+        let layer = DualWriteLayer::new(new).on_mismatch(move |m: &Mismatch<'_, Option<u32>>| {
+            assert_eq!(m.method, "Echo");
+            recorded.lock().unwrap().push(*m.old.as_ref().unwrap());
+        });
+        let stats = layer.stats();
+        let svc = layer.clone().layer(service_fn(echo));
+
+        for i in 1..=10 {
+            assert_eq!(svc.call(&mut cx(), i).await.unwrap(), Some(i));
+        }
+        wait_idle(&layer).await;
+        assert_eq!(stats.compared(), 10);
+        // 3, 5, 6, 9, 10
+        assert_eq!(stats.mismatched(), 5);
+        let mut mismatches = mismatches.lock().unwrap().clone();
+        mismatches.sort();
+        assert_eq!(
+            mismatches,
+            vec![Some(3), Some(5), Some(6), Some(9), Some(10)]
+        );
+    }
+
+    #[tokio::test]
+    async fn dual_write_custom_comparator() {
+        let new = client(service_fn(|_: &mut ClientContext, req: u32| async move {
+            Ok::<_, ClientError>(Some(req * 2))
+        }));
+        let layer = DualWriteLayer::with_comparator(
+            new,
+            |old: &Result<Option<u32>, ClientError>, new: &Result<Option<u32>, ClientError>| matches!((old, new), (Ok(Some(a)), Ok(Some(b))) if a * 2 == *b),
+        );
+        let stats = layer.stats();
+        let svc = layer.clone().layer(service_fn(echo));
+        for i in 0..4 {
+            svc.call(&mut cx(), i).await.unwrap();
+        }
+        wait_idle(&layer).await;
+        assert_eq!(stats.compared(), 4);
+        assert_eq!(stats.mismatched(), 0);
+    }
+
+    #[tokio::test]
+    async fn dual_write_max_in_flight() {
+        let notify = Arc::new(Notify::new());
+        let new = {
+            let notify = notify.clone();
+            client(service_fn(move |_: &mut ClientContext, req: u32| {
+                let notify = notify.clone();
+                async move {
+                    notify.notified().await;
+                    Ok::<_, ClientError>(Some(req))
+                }
+            }))
+        };
+        let layer = DualWriteLayer::new(new).with_max_in_flight(2);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(echo));
+
+        for i in 0..5 {
+            // the original requests are not blocked by the new client
+            assert_eq!(svc.call(&mut cx(), i).await.unwrap(), Some(i));
+        }
+        assert_eq!(stats.dropped(), 3);
+        notify.notify_waiters();
+    }
+}
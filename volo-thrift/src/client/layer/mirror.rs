@@ -0,0 +1,351 @@
+//! Mirrors a sampled fraction of requests to a shadow cluster for dark launch.
+//!
+//! [`MirrorLayer`] sends a copy of sampled requests to a shadow [`Client`] asynchronously, so that
+//! a new version of the backend can be validated with production-shaped traffic. Responses from
+//! the shadow client are discarded and errors are only counted in [`MirrorStats`], the original
+//! requests are never affected.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use volo_thrift::client::layer::mirror::MirrorLayer;
+//!
+//! let shadow = ItemServiceClientBuilder::new("item-shadow")
+//!     .address(shadow_addr)
+//!     .build();
+//! let mirror = MirrorLayer::new(shadow.0, 0.1).with_max_in_flight(128);
+//! let stats = mirror.stats();
+//!
+//! let client = ItemServiceClientBuilder::new("item")
+//!     .layer_outer(mirror)
+//!     .build();
+//! ```
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use motore::{layer::Layer, service::Service};
+use pilota::thrift::TMessageType;
+use volo::context::Context;
+
+use crate::{ClientError, client::Client, context::ClientContext};
+
+const DEFAULT_MAX_IN_FLIGHT: usize = 1024;
+const SAMPLE_SCALE: u64 = 1 << 32;
+
+/// Statistics of mirrored requests.
+///
+/// It is cheap to clone, and all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct MirrorStats {
+    inner: Arc<MirrorStatsInner>,
+}
+
+#[derive(Default)]
+struct MirrorStatsInner {
+    mirrored: AtomicU64,
+    errors: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl MirrorStats {
+    /// The number of requests sent to the shadow client.
+    pub fn mirrored(&self) -> u64 {
+        self.inner.mirrored.load(Ordering::Relaxed)
+    }
+
+    /// The number of mirrored requests failed with an error.
+    pub fn errors(&self) -> u64 {
+        self.inner.errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of sampled requests dropped since too many mirrored requests were in flight.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for MirrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirrorStats")
+            .field("mirrored", &self.mirrored())
+            .field("errors", &self.errors())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// [`Layer`] for mirroring requests to a shadow [`Client`].
+///
+/// See the [module documentation](self) for more details.
+pub struct MirrorLayer<S> {
+    inner: Arc<Mirror<S>>,
+}
+
+impl<S> Clone for MirrorLayer<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct Mirror<S> {
+    shadow: Client<S>,
+    // sampling ratio scaled by `SAMPLE_SCALE`
+    ratio: u64,
+    max_in_flight: usize,
+    count: AtomicU64,
+    in_flight: Arc<AtomicUsize>,
+    stats: MirrorStats,
+}
+
+impl<S> MirrorLayer<S> {
+    /// Create a [`MirrorLayer`] that mirrors `ratio` (from `0.0` to `1.0`) of requests to the
+    /// `shadow` client.
+    ///
+    /// Requests are sampled evenly, e.g., one of every ten requests is mirrored for `0.1`.
+    pub fn new(shadow: Client<S>, ratio: f64) -> Self {
+        Self {
+            inner: Arc::new(Mirror {
+                shadow,
+                ratio: (ratio.clamp(0.0, 1.0) * SAMPLE_SCALE as f64).round() as u64,
+                max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+                count: AtomicU64::new(0),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+                stats: MirrorStats::default(),
+            }),
+        }
+    }
+
+    /// Set the maximum number of mirrored requests in flight, sampled requests exceeding the limit
+    /// are dropped.
+    ///
+    /// Default is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layer has been cloned.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("`MirrorLayer` should not be cloned before configured")
+            .max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get the [`MirrorStats`] of the layer.
+    pub fn stats(&self) -> MirrorStats {
+        self.inner.stats.clone()
+    }
+}
+
+impl<S> Mirror<S> {
+    fn sample(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed);
+        let (n, ratio, scale) = (
+            u128::from(n),
+            u128::from(self.ratio),
+            u128::from(SAMPLE_SCALE),
+        );
+        // mirror the request when the accumulated ratio crosses an integer
+        (n + 1) * ratio / scale != n * ratio / scale
+    }
+}
+
+impl<S, Inner> Layer<Inner> for MirrorLayer<S> {
+    type Service = MirrorService<Inner, S>;
+
+    fn layer(self, inner: Inner) -> Self::Service {
+        MirrorService {
+            inner,
+            mirror: self.inner,
+        }
+    }
+}
+
+/// [`Service`] generated by [`MirrorLayer`].
+pub struct MirrorService<Inner, S> {
+    inner: Inner,
+    mirror: Arc<Mirror<S>>,
+}
+
+impl<Inner: Clone, S> Clone for MirrorService<Inner, S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mirror: self.mirror.clone(),
+        }
+    }
+}
+
+impl<Inner, S, Req> Service<ClientContext, Req> for MirrorService<Inner, S>
+where
+    Inner: Service<ClientContext, Req> + Send + Sync,
+    Req: Clone + Send + 'static,
+    S: Clone + Send + Sync + 'static,
+    Client<S>: Service<ClientContext, Req, Error = ClientError>,
+{
+    type Response = Inner::Response;
+    type Error = Inner::Error;
+
+    async fn call(&self, cx: &mut ClientContext, req: Req) -> Result<Self::Response, Self::Error> {
+        if self.mirror.sample() {
+            self.spawn_mirror(cx, req.clone());
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<Inner, S> MirrorService<Inner, S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn spawn_mirror<Req>(&self, cx: &ClientContext, req: Req)
+    where
+        Req: Send + 'static,
+        Client<S>: Service<ClientContext, Req, Error = ClientError>,
+    {
+        let mirror = &self.mirror;
+        if mirror.in_flight.fetch_add(1, Ordering::AcqRel) >= mirror.max_in_flight {
+            mirror.in_flight.fetch_sub(1, Ordering::AcqRel);
+            mirror.stats.inner.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let oneway = cx.message_type == TMessageType::OneWay;
+        let mut shadow_cx = mirror.shadow.make_cx(cx.rpc_info().method(), oneway);
+        let shadow = mirror.shadow.clone();
+        let in_flight = mirror.in_flight.clone();
+        let stats = mirror.stats.clone();
+        stats.inner.mirrored.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            if let Err(err) = shadow.call(&mut shadow_cx, req).await {
+                stats.inner.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!(
+                    "[VOLO] mirrored request to {} failed: {err}",
+                    shadow_cx.rpc_info().callee().service_name()
+                );
+            }
+            in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicI32;
+
+    use motore::service::service_fn;
+    use pilota::FastStr;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{
+        ApplicationException, ApplicationExceptionKind,
+        client::{Client, ClientInner},
+        context::Config,
+    };
+
+    fn client<S>(transport: S) -> Client<S> {
+        Client {
+            transport,
+            inner: Arc::new(ClientInner {
+                callee_name: FastStr::from_static_str("shadow"),
+                caller_name: FastStr::from_static_str("test"),
+                config: Config::default(),
+                address: None,
+                seq_id: AtomicI32::new(0),
+                warmup: Default::default(),
+                methods: Default::default(),
+            }),
+        }
+    }
+
+    fn cx() -> ClientContext {
+        client(()).make_cx("Echo", false)
+    }
+
+    async fn echo(_: &mut ClientContext, req: u32) -> Result<Option<u32>, ClientError> {
+        Ok(Some(req))
+    }
+
+    #[tokio::test]
+    async fn mirror_sampled_requests() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let shadow = client(service_fn(move |cx: &mut ClientContext, req: u32| {
+            let tx = tx.clone();
+            let method = cx.rpc_info().method().clone();
+            async move {
+                tx.send((method, req)).unwrap();
+                if req % 2 == 0 {
+                    Ok(Some(req))
+                } else {
+                    Err(ApplicationException::new(ApplicationExceptionKind::UNKNOWN, "odd").into())
+                }
+            }
+        }));
+        let layer = MirrorLayer::new(shadow, 0.5);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(echo));
+
+        for i in 0..10 {
+            assert_eq!(svc.call(&mut cx(), i).await.unwrap(), Some(i));
+        }
+        let mut mirrored = Vec::new();
+        for _ in 0..5 {
+            mirrored.push(rx.recv().await.unwrap());
+        }
+        assert!(mirrored.iter().all(|(method, _)| method == "Echo"));
+        while svc.mirror.in_flight.load(Ordering::Acquire) != 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(stats.mirrored(), 5);
+        assert_eq!(
+            stats.errors(),
+            mirrored.iter().filter(|(_, req)| req % 2 == 1).count() as u64
+        );
+        assert_eq!(stats.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn mirror_ratio() {
+        let none = MirrorLayer::new(client(()), 0.0);
+        assert!((0..100).all(|_| !none.inner.sample()));
+        let all = MirrorLayer::new(client(()), 1.0);
+        assert!((0..100).all(|_| all.inner.sample()));
+        let tenth = MirrorLayer::new(client(()), 0.1);
+        assert_eq!((0..100).filter(|_| tenth.inner.sample()).count(), 10);
+    }
+
+    #[tokio::test]
+    async fn mirror_max_in_flight() {
+        let notify = Arc::new(Notify::new());
+        let shadow = {
+            let notify = notify.clone();
+            client(service_fn(move |_: &mut ClientContext, req: u32| {
+                let notify = notify.clone();
+                async move {
+                    notify.notified().await;
+                    Ok::<_, ClientError>(Some(req))
+                }
+            }))
+        };
+        let layer = MirrorLayer::new(shadow, 1.0).with_max_in_flight(2);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(echo));
+
+        for i in 0..5 {
+            // the original requests are not blocked by the shadow client
+            assert_eq!(svc.call(&mut cx(), i).await.unwrap(), Some(i));
+        }
+        assert_eq!(stats.mirrored(), 2);
+        assert_eq!(stats.dropped(), 3);
+        notify.notify_waiters();
+    }
+}
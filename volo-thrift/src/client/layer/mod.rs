@@ -1 +1,3 @@
+pub mod dual_write;
+pub mod mirror;
 pub mod timeout;
@@ -1,10 +1,17 @@
-//! Applies a timeout to request
+//! Applies the rpc timeout, i.e., the total timeout of a call including retries
 //! if the inner service's call does not complete within specified timeout, the response will be
 //! aborted.
 use motore::{layer::Layer, service::Service};
 use tracing::warn;
+use volo::{
+    context::Context,
+    timeout::{CallDeadline, TimeoutKind},
+};
 
-use crate::context::ClientContext;
+use crate::{
+    context::ClientContext,
+    error::{RPC_TIMEOUT_MSG, volo_exception},
+};
 
 #[derive(Clone)]
 pub struct Timeout<S> {
@@ -24,24 +31,30 @@ where
         match cx.rpc_info.config().rpc_timeout() {
             Some(duration) => {
                 let start = std::time::Instant::now();
-                match tokio::time::timeout(duration, self.inner.call(cx, req)).await {
-                    Ok(r) => r,
-                    Err(_) => {
-                        let msg = format!(
-                            "[VOLO] thrift rpc call timeout, rpcinfo: {:?}, elpased: {:?}, \
-                             timeout config: {:?}",
-                            cx.rpc_info,
-                            start.elapsed(),
-                            duration
-                        );
-                        warn!(msg);
-                        Err(crate::ApplicationException::new(
-                            crate::ApplicationExceptionKind::INTERNAL_ERROR,
-                            msg,
-                        )
-                        .into())
-                    }
+                // limits the request timeout of each attempt
+                let deadline = CallDeadline::new(duration);
+                cx.extensions_mut().insert(deadline);
+                let res = tokio::time::timeout(duration, self.inner.call(cx, req)).await;
+                cx.extensions_mut().remove::<CallDeadline>();
+                match res {
+                    // the attempt cut by the deadline is also reported as the rpc timeout
+                    Ok(Err(e))
+                        if e.timeout_error()
+                            .is_some_and(|t| t.kind() == TimeoutKind::Total) => {}
+                    Ok(r) => return r,
+                    Err(_) => {}
                 }
+                let err = volo_exception(
+                    crate::ApplicationExceptionKind::INTERNAL_ERROR,
+                    format_args!(
+                        "{RPC_TIMEOUT_MSG}, rpcinfo: {:?}, elpased: {:?}, timeout config: {:?}",
+                        cx.rpc_info,
+                        start.elapsed(),
+                        duration
+                    ),
+                );
+                warn!("{}", err.message());
+                Err(err.into())
             }
             None => self.inner.call(cx, req).await,
         }
@@ -65,3 +78,68 @@ impl<S> Layer<S> for TimeoutLayer {
         Timeout { inner }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use motore::{
+        layer::Layer,
+        service::{Service, service_fn},
+    };
+    use pilota::thrift::TMessageType;
+    use volo::{
+        FastStr,
+        context::{Endpoint, Role, RpcInfo},
+        error::{ErrorCategory, RpcError},
+        loadbalance::error::Retryable,
+        timeout::{RequestTimeoutLayer, TimeoutKind},
+    };
+
+    use super::TimeoutLayer;
+    use crate::{
+        ApplicationExceptionKind, ClientError,
+        context::{ClientContext, Config},
+    };
+
+    async fn sleep(_: &mut ClientContext, dur: Duration) -> Result<(), ClientError> {
+        tokio::time::sleep(dur).await;
+        Ok(())
+    }
+
+    fn make_cx(rpc_timeout: Duration, request_timeout: Duration) -> ClientContext {
+        let mut config = Config::new();
+        config.set_rpc_timeout(Some(rpc_timeout));
+        config.set_request_timeout(Some(request_timeout));
+        let ri = RpcInfo::new(
+            Role::Client,
+            FastStr::from_static_str("test"),
+            Endpoint::new(FastStr::from_static_str("caller")),
+            Endpoint::new(FastStr::from_static_str("callee")),
+            config,
+        );
+        ClientContext::new(0, ri, TMessageType::Call)
+    }
+
+    #[tokio::test]
+    async fn timeout_kinds() {
+        let svc = TimeoutLayer::new().layer(RequestTimeoutLayer.layer(service_fn(sleep)));
+
+        let mut cx = make_cx(Duration::from_secs(1), Duration::from_millis(10));
+        let err = svc.call(&mut cx, Duration::from_secs(2)).await.unwrap_err();
+        let timeout = err.timeout_error().unwrap();
+        assert_eq!(timeout.kind(), TimeoutKind::Request);
+        assert!(err.retryable());
+
+        let mut cx = make_cx(Duration::from_millis(10), Duration::from_secs(1));
+        let err = svc.call(&mut cx, Duration::from_secs(2)).await.unwrap_err();
+        match &err {
+            ClientError::Application(e) => {
+                assert_eq!(e.kind(), ApplicationExceptionKind::INTERNAL_ERROR)
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(err.category(), ErrorCategory::Timeout);
+        assert!(!err.retryable());
+    }
+}
@@ -0,0 +1,116 @@
+//! Default config of methods.
+//!
+//! The timeout and retry policy of a method usually belong to the method rather than the client,
+//! e.g., a slow batch query needs a longer timeout than the others, and only idempotent methods
+//! should be retried. They can be declared by annotations of methods in IDL:
+//!
+//! ```thrift
+//! service ItemService {
+//!     Item GetItem(1: GetItemRequest req) (vt.timeout = "200ms", vt.retry = "2")
+//! }
+//! ```
+//!
+//! and `volo-build` generates the client builder with the [`MethodConfig`] of each annotated
+//! method. They can also be set by [`ClientBuilder::method_config`][super::ClientBuilder].
+//!
+//! A [`MethodConfig`] overrides the config of the client for calls of the method, and is also
+//! overridden by the [`CallOpt`][super::CallOpt] of each call.
+
+use std::time::Duration;
+
+/// The annotation key of the rpc timeout of a method, e.g., `vt.timeout = "200ms"`.
+pub const TIMEOUT_ANNOTATION: &str = "vt.timeout";
+
+/// The annotation key of the retry count of a method, e.g., `vt.retry = "2"`.
+pub const RETRY_ANNOTATION: &str = "vt.retry";
+
+/// Default config of calls of a method, see the [module documentation](self) for more details.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodConfig {
+    rpc_timeout: Option<Duration>,
+    retry_count: Option<usize>,
+}
+
+impl MethodConfig {
+    /// Creates a [`MethodConfig`] using the config of the client.
+    pub const fn new() -> Self {
+        Self {
+            rpc_timeout: None,
+            retry_count: None,
+        }
+    }
+
+    /// Sets the rpc timeout of calls of the method.
+    pub const fn rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the retry count of calls of the method, `0` for not retrying.
+    pub const fn retry_count(mut self, count: usize) -> Self {
+        self.retry_count = Some(count);
+        self
+    }
+
+    /// Returns the rpc timeout, `None` for using the timeout of the client.
+    pub fn get_rpc_timeout(&self) -> Option<Duration> {
+        self.rpc_timeout
+    }
+
+    /// Returns the retry count, `None` for using the retry count of the client.
+    pub fn get_retry_count(&self) -> Option<usize> {
+        self.retry_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, atomic::AtomicI32},
+        time::Duration,
+    };
+
+    use ahash::AHashMap;
+    use volo::{FastStr, context::Context, loadbalance::RetryCount};
+
+    use super::{super::ClientInner, MethodConfig};
+    use crate::{client::Client, context::Config};
+
+    #[test]
+    fn method_config() {
+        let mut config = Config::default();
+        config.set_rpc_timeout(Some(Duration::from_secs(1)));
+        let client = Client {
+            transport: (),
+            inner: Arc::new(ClientInner {
+                callee_name: FastStr::from_static_str("test"),
+                caller_name: FastStr::from_static_str("test"),
+                config,
+                address: None,
+                seq_id: AtomicI32::new(0),
+                warmup: Default::default(),
+                methods: AHashMap::from([(
+                    FastStr::from_static_str("GetItem"),
+                    MethodConfig::new()
+                        .rpc_timeout(Duration::from_millis(200))
+                        .retry_count(2),
+                )]),
+            }),
+        };
+
+        let cx = client.make_cx("GetItem", false);
+        assert_eq!(
+            cx.rpc_info().config().rpc_timeout(),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(cx.extensions().get::<RetryCount>(), Some(&RetryCount(2)));
+
+        // other methods use the config of the client
+        let cx = client.make_cx("ListItems", false);
+        assert_eq!(
+            cx.rpc_info().config().rpc_timeout(),
+            Some(Duration::from_secs(1))
+        );
+        assert!(cx.extensions().get::<RetryCount>().is_none());
+    }
+}
@@ -11,6 +11,7 @@ use std::{
     sync::{Arc, atomic::AtomicI32},
 };
 
+use ahash::AHashMap;
 use motore::{
     layer::{Identity, Layer, Stack},
     service::{BoxCloneService, Service},
@@ -24,18 +25,29 @@ use volo::{
     client::WithOptService,
     context::{Context, Endpoint, Role, RpcInfo},
     discovery::{Discover, DummyDiscover},
-    loadbalance::{LbConfig, MkLbLayer, random::WeightedRandomBalance},
+    loadbalance::{
+        LbConfig, MkLbLayer, RetryCount,
+        drain::DrainPolicy,
+        random::WeightedRandomBalance,
+        warmup::{Warmup, WarmupHandle, WarmupReport},
+    },
     net::{
         Address,
-        dial::{DefaultMakeTransport, MakeTransport},
+        dial::{DefaultMakeTransport, MakeTransport, TcpKeepalive},
+        memory::MemoryConnector,
     },
+    timeout::attempt_timeout,
+    util::timing,
 };
 
 use crate::{
     ClientError, EntryMessage, ThriftMessage,
     codec::{
         DefaultMakeCodec, MakeCodec,
-        default::{framed::MakeFramedCodec, thrift::MakeThriftCodec, ttheader::MakeTTHeaderCodec},
+        default::{
+            MakeZeroCopyCodec, framed::MakeFramedCodec, thrift::MakeThriftCodec,
+            ttheader::MakeTTHeaderCodec,
+        },
     },
     context::{CLIENT_CONTEXT_CACHE, ClientContext, Config},
     transport::{pingpong, pool},
@@ -43,6 +55,8 @@ use crate::{
 
 mod callopt;
 pub use callopt::CallOpt;
+pub mod method;
+pub use method::MethodConfig;
 
 use self::layer::timeout::TimeoutLayer;
 
@@ -60,6 +74,7 @@ pub struct ClientBuilder<IL, OL, MkClient, Req, Resp, MkT, MkC, LB> {
     make_codec: MkC,
     mk_client: MkClient,
     mk_lb: LB,
+    methods: AHashMap<FastStr, MethodConfig>,
     _marker: PhantomData<(*const Req, *const Resp)>,
 
     disable_timeout_layer: bool,
@@ -94,6 +109,7 @@ impl<C, Req, Resp>
             make_transport: DefaultMakeTransport::default(),
             make_codec: DefaultMakeCodec::default(),
             mk_lb: LbConfig::new(WeightedRandomBalance::new(), DummyDiscover {}),
+            methods: AHashMap::new(),
             _marker: PhantomData,
 
             disable_timeout_layer: false,
@@ -126,6 +142,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
             make_codec: self.make_codec,
             mk_lb: self.mk_lb.load_balance(load_balance),
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -152,6 +169,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
             make_codec: self.make_codec,
             mk_lb: self.mk_lb.discover(discover),
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -165,10 +183,20 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
         self.mk_lb = self.mk_lb.retry_count(count);
         self
     }
+
+    /// Sets the [`DrainPolicy`] deciding when pooled connections to instances removed by the
+    /// discovery are drained.
+    ///
+    /// Default is draining immediately.
+    pub fn drain_policy<P: DrainPolicy>(mut self, policy: P) -> Self {
+        self.mk_lb = self.mk_lb.drain_policy(policy);
+        self
+    }
 }
 
 impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB> {
-    /// Sets the rpc timeout for the client.
+    /// Sets the rpc timeout for the client, which is the total timeout of a call including
+    /// retries.
     ///
     /// The default value is 1 second.
     ///
@@ -178,6 +206,23 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         self
     }
 
+    /// Sets the request timeout for the client, which is the timeout of each attempt of a call,
+    /// so a retry will be made by the load balancer if an attempt is timeout.
+    ///
+    /// There is no request timeout by default, see [`volo::timeout`] for all kinds of timeouts.
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.set_request_timeout(timeout);
+        self
+    }
+
+    /// Sets the default config of calls of the method, overriding the config of the client.
+    ///
+    /// It's also generated from the annotations of the method in IDL, see [`MethodConfig`].
+    pub fn method_config(mut self, method: impl AsRef<str>, config: MethodConfig) -> Self {
+        self.methods.insert(FastStr::new(method), config);
+        self
+    }
+
     /// Sets the config for connection pool.
     pub fn pool_config(mut self, config: pool::Config) -> Self {
         self.pool = Some(config);
@@ -202,7 +247,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         self
     }
 
-    /// Disable the default timeout layer.
+    /// Disable the default timeout layer of the rpc timeout.
     #[doc(hidden)]
     pub fn disable_timeout_layer(mut self) -> Self {
         self.disable_timeout_layer = true;
@@ -236,6 +281,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: mk_load_balance,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -244,6 +290,21 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         }
     }
 
+    /// Set the [`MakeZeroCopyCodec`] of the default codec, which is
+    /// `TTHeader<Framed<Binary>>` by default.
+    ///
+    /// Components of the default codec can be wrapped or replaced by custom ones implementing
+    /// [`MakeZeroCopyCodec`], e.g., a [`MakeTransformCodec`] between TTHeader and the payload codec
+    /// for encrypting payloads, and the peer must use the same codec stack.
+    ///
+    /// [`MakeTransformCodec`]: crate::codec::default::transform::MakeTransformCodec
+    pub fn zero_copy_codec<MkZC: MakeZeroCopyCodec>(
+        self,
+        make_codec: MkZC,
+    ) -> ClientBuilder<IL, OL, C, Req, Resp, MkT, DefaultMakeCodec<MkZC>, LB> {
+        self.make_codec(DefaultMakeCodec::new(make_codec))
+    }
+
     /// Set the codec to use for the client.
     ///
     /// This should not be used by most users, Volo has already provided a default encoder.
@@ -270,6 +331,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -298,6 +360,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -306,6 +369,21 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         }
     }
 
+    /// Connects to the in-memory listener of a server in the same process, so that tests can
+    /// call a real server without binding ports.
+    ///
+    /// All connections are created by the [`MemoryConnector`] regardless of the address of
+    /// callee, and the address defaults to a placeholder if not set.
+    pub fn memory_transport(
+        mut self,
+        connector: MemoryConnector,
+    ) -> ClientBuilder<IL, OL, C, Req, Resp, MemoryConnector, MkC, LB> {
+        if self.address.is_none() {
+            self.address = Some(connector.address());
+        }
+        self.make_transport(connector)
+    }
+
     /// Sets the target address.
     ///
     /// If the address is set, the call will be sent to the address directly.
@@ -347,6 +425,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -386,6 +465,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -425,6 +505,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -464,6 +545,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -490,6 +572,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
 
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
 
@@ -526,6 +609,7 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
             ),
             make_codec: self.make_codec,
             mk_lb: self.mk_lb,
+            methods: self.methods,
             disable_timeout_layer: self.disable_timeout_layer,
             enable_biz_error: self.enable_biz_error,
             #[cfg(feature = "multiplex")]
@@ -569,7 +653,25 @@ where
 
     async fn call(&self, cx: &mut ClientContext, req: Req) -> Result<Self::Response, Self::Error> {
         let msg = ThriftMessage::mk_client_msg(cx, req);
-        let resp = self.inner.call(cx, msg).await;
+        // the request timeout is applied here, i.e., inside the load balancer, for each attempt
+        let resp = match attempt_timeout(cx) {
+            Some((duration, err)) if duration.is_zero() => Err(err.into()),
+            Some((duration, err)) => {
+                match tokio::time::timeout(duration, self.inner.call(cx, msg)).await {
+                    Ok(resp) => resp,
+                    Err(_) => {
+                        tracing::warn!(
+                            "[VOLO] {err} for method `{}` to {:?}",
+                            cx.rpc_info.method(),
+                            cx.rpc_info.callee().address(),
+                        );
+                        Err(err.into())
+                    }
+                }
+            }
+            None => self.inner.call(cx, msg).await,
+        }
+        .map_err(|err| err.with_peer_addr(cx.rpc_info.callee().address.as_ref()));
         if self.read_biz_error {
             if let Some(biz_err) = cx.common_stats.biz_error() {
                 return Err(biz_err.clone().into());
@@ -584,6 +686,22 @@ where
     }
 }
 
+impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB>
+where
+    MkT: MakeTransport,
+{
+    /// Sets the TCP keepalive of connections, so that connections broken silently, e.g., by NAT or
+    /// load balancers, are detected by the kernel.
+    ///
+    /// It is applied to the current transport, so it should be called after
+    /// [`make_transport`](Self::make_transport). See also
+    /// [`Heartbeat`](crate::transport::Heartbeat) for application-level heartbeats.
+    pub fn tcp_keepalive(mut self, keepalive: Option<TcpKeepalive>) -> Self {
+        self.make_transport.set_tcp_keepalive(keepalive);
+        self
+    }
+}
+
 impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB>
 where
     C: volo::client::MkClient<
@@ -627,34 +745,105 @@ where
         if let Some(timeout) = self.config.read_write_timeout() {
             self.make_transport.set_write_timeout(Some(timeout));
         }
+        let drainer = self.mk_lb.drainer();
+        let affinity = self.mk_lb.affinity();
+        let warmup = self.mk_lb.warmup().unwrap_or_default();
         let msg_svc = MessageService {
             #[cfg(not(feature = "multiplex"))]
-            inner: pingpong::Client::new(self.make_transport, self.pool, self.make_codec),
+            inner: {
+                let client = pingpong::Client::new(
+                    self.make_transport.clone(),
+                    self.pool.clone(),
+                    self.make_codec.clone(),
+                );
+                if let Some(drainer) = &drainer {
+                    client.drain_on(drainer);
+                }
+                if let Some(affinity) = &affinity {
+                    client.prewarm_on(affinity);
+                }
+                client.warmup_on(&warmup);
+                client
+            },
             #[cfg(feature = "multiplex")]
             inner: if !self.multiplex {
-                motore::utils::Either::A(pingpong::Client::new(
-                    self.make_transport,
-                    self.pool,
-                    self.make_codec,
-                ))
+                let client = pingpong::Client::new(
+                    self.make_transport.clone(),
+                    self.pool.clone(),
+                    self.make_codec.clone(),
+                );
+                if let Some(drainer) = &drainer {
+                    client.drain_on(drainer);
+                }
+                if let Some(affinity) = &affinity {
+                    client.prewarm_on(affinity);
+                }
+                client.warmup_on(&warmup);
+                motore::utils::Either::A(client)
             } else {
-                motore::utils::Either::B(crate::transport::multiplex::Client::new(
-                    self.make_transport,
-                    self.pool,
-                    self.make_codec,
-                ))
+                let client = crate::transport::multiplex::Client::new(
+                    self.make_transport.clone(),
+                    self.pool.clone(),
+                    self.make_codec.clone(),
+                );
+                if let Some(drainer) = &drainer {
+                    client.drain_on(drainer);
+                }
+                if let Some(affinity) = &affinity {
+                    client.prewarm_on(affinity);
+                }
+                client.warmup_on(&warmup);
+                motore::utils::Either::B(client)
             },
             read_biz_error: self.enable_biz_error,
         };
 
+        self.build_with(msg_svc, warmup)
+    }
+}
+
+impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB>
+where
+    LB: MkLbLayer,
+{
+    /// Build volo client with the innermost service sending messages.
+    fn build_with<MS>(self, msg_svc: MS, warmup: WarmupHandle) -> C::Target
+    where
+        C: volo::client::MkClient<
+                Client<
+                    BoxCloneService<
+                        ClientContext,
+                        Req,
+                        Option<Resp>,
+                        <OL::Service as Service<ClientContext, Req>>::Error,
+                    >,
+                >,
+            >,
+        LB::Layer: Layer<IL::Service>,
+        <LB::Layer as Layer<IL::Service>>::Service: Service<ClientContext, Req, Response = Option<Resp>, Error = ClientError>
+            + 'static
+            + Send
+            + Clone
+            + Sync,
+        Req: EntryMessage + Send + 'static + Sync + Clone,
+        Resp: EntryMessage + Send + 'static,
+        IL: Layer<MS>,
+        IL::Service:
+            Service<ClientContext, Req, Response = Option<Resp>> + Sync + Clone + Send + 'static,
+        <IL::Service as Service<ClientContext, Req>>::Error: Send + Into<ClientError>,
+        OL: Layer<BoxCloneService<ClientContext, Req, Option<Resp>, ClientError>>,
+        OL::Service:
+            Service<ClientContext, Req, Response = Option<Resp>> + 'static + Send + Clone + Sync,
+        <OL::Service as Service<ClientContext, Req>>::Error: Send + Sync + Into<ClientError>,
+    {
+        let lb_svc = self.mk_lb.make().layer(self.inner_layer.layer(msg_svc));
         let transport = if !self.disable_timeout_layer {
-            BoxCloneService::new(self.outer_layer.layer(BoxCloneService::new(
-                TimeoutLayer::new().layer(self.mk_lb.make().layer(self.inner_layer.layer(msg_svc))),
-            )))
+            BoxCloneService::new(
+                self.outer_layer
+                    .layer(BoxCloneService::new(TimeoutLayer::new().layer(lb_svc))),
+            )
         } else {
-            BoxCloneService::new(self.outer_layer.layer(BoxCloneService::new(
-                self.mk_lb.make().layer(self.inner_layer.layer(msg_svc)),
-            )))
+            BoxCloneService::new(self.outer_layer.layer(BoxCloneService::new(lb_svc)))
         };
 
         self.mk_client.mk_client(Client {
@@ -664,12 +853,62 @@ where
                 address: self.address,
                 caller_name: self.caller_name,
                 seq_id: AtomicI32::new(0),
+                warmup,
+                methods: self.methods,
             }),
             transport,
         })
     }
 }
 
+#[cfg(feature = "datagram")]
+impl<IL, OL, C, Req, Resp, MkT, MkZC, LB>
+    ClientBuilder<IL, OL, C, Req, Resp, MkT, DefaultMakeCodec<MkZC>, LB>
+where
+    C: volo::client::MkClient<
+            Client<
+                BoxCloneService<
+                    ClientContext,
+                    Req,
+                    Option<Resp>,
+                    <OL::Service as Service<ClientContext, Req>>::Error,
+                >,
+            >,
+        >,
+    LB: MkLbLayer,
+    LB::Layer: Layer<IL::Service>,
+    <LB::Layer as Layer<IL::Service>>::Service: Service<ClientContext, Req, Response = Option<Resp>, Error = ClientError>
+        + 'static
+        + Send
+        + Clone
+        + Sync,
+    Req: EntryMessage + Send + 'static + Sync + Clone,
+    Resp: EntryMessage + Send + 'static,
+    IL: Layer<crate::transport::datagram::Client<Resp, MkZC>>,
+    IL::Service:
+        Service<ClientContext, Req, Response = Option<Resp>> + Sync + Clone + Send + 'static,
+    <IL::Service as Service<ClientContext, Req>>::Error: Send + Into<ClientError>,
+    MkZC: MakeZeroCopyCodec + Sync,
+    OL: Layer<BoxCloneService<ClientContext, Req, Option<Resp>, ClientError>>,
+    OL::Service:
+        Service<ClientContext, Req, Response = Option<Resp>> + 'static + Send + Clone + Sync,
+    <OL::Service as Service<ClientContext, Req>>::Error: Send + Sync + Into<ClientError>,
+{
+    /// Build volo client sending requests by the [datagram
+    /// transport](crate::transport::datagram), which only supports oneway methods.
+    ///
+    /// The transport, the connection pool and the connect and read/write timeouts of the builder
+    /// are not used, and the codec must be length-prefixed, e.g., the default one.
+    pub fn build_datagram(self, config: crate::transport::datagram::Config) -> C::Target {
+        let warmup = self.mk_lb.warmup().unwrap_or_default();
+        let msg_svc = crate::transport::datagram::Client::new(
+            self.make_codec.make_zero_copy_codec().clone(),
+            config,
+        );
+        self.build_with(msg_svc, warmup)
+    }
+}
+
 /// A client for a Thrift service.
 ///
 /// `Client` is designed to "clone and use", so it's cheap to clone it.
@@ -689,11 +928,14 @@ struct ClientInner {
     config: Config,
     address: Option<Address>,
     seq_id: AtomicI32,
+    warmup: WarmupHandle,
+    methods: AHashMap<FastStr, MethodConfig>,
 }
 
 impl<S> Client<S> {
     pub fn make_cx(&self, method: &str, oneway: bool) -> ClientContext {
-        CLIENT_CONTEXT_CACHE.with(|cache| {
+        let method_config = self.inner.methods.get(method);
+        let mut cx = CLIENT_CONTEXT_CACHE.with(|cache| {
             let mut cache = cache.borrow_mut();
             cache
                 .pop()
@@ -737,7 +979,18 @@ impl<S> Client<S> {
                         },
                     )
                 })
-        })
+        });
+        if let Some(method_config) = method_config {
+            if let Some(timeout) = method_config.get_rpc_timeout() {
+                cx.rpc_info_mut()
+                    .config_mut()
+                    .set_rpc_timeout(Some(timeout));
+            }
+            if let Some(count) = method_config.get_retry_count() {
+                cx.extensions_mut().insert(RetryCount(count));
+            }
+        }
+        cx
     }
 
     fn make_rpc_info(&self, method: &str) -> RpcInfo<Config> {
@@ -757,6 +1010,20 @@ impl<S> Client<S> {
             inner: self.inner,
         }
     }
+
+    /// Warm up the client by resolving the callee and pre-creating connections to its instances,
+    /// returns when all connections are ready or after the timeout of the [`Warmup`].
+    ///
+    /// It should be called before serving traffic, see [`volo::loadbalance::warmup`] for more
+    /// details.
+    pub async fn warmup(&self, warmup: Warmup) -> Result<WarmupReport, ClientError> {
+        let rpc_info = self.make_rpc_info("");
+        self.inner
+            .warmup
+            .warmup(rpc_info.callee(), &warmup)
+            .await
+            .map_err(Into::into)
+    }
 }
 
 macro_rules! impl_client {
@@ -815,7 +1082,13 @@ impl_client!((self, &mut cx, req) => async move {
 
     let has_metainfo = metainfo::METAINFO.try_with(|_| {}).is_ok();
 
-    let mk_call = async { self.transport.call(cx, req).await };
+    let mk_call = async {
+        cx.stats.record_call_start_at();
+        let (res, timestamps) = timing::record_connect(self.transport.call(cx, req)).await;
+        cx.stats.record_connect(timestamps);
+        cx.stats.record_call_end_at();
+        res
+    };
 
     if has_metainfo {
         mk_call.await
@@ -0,0 +1,445 @@
+//! Encrypting or signing payloads inside TTHeader frames.
+//!
+//! When TLS is terminated by a proxy between the client and the server, the payloads are exposed
+//! to the proxy. [`PayloadCrypto`] is a [`PayloadTransform`] protecting the payloads end to end,
+//! while the TTHeader headers are still readable by proxies for routing:
+//!
+//! - [`Algorithm::Aes256Gcm`] and [`Algorithm::ChaCha20Poly1305`] encrypt and authenticate
+//!   payloads, the payload is sent as `nonce || ciphertext || tag`.
+//! - [`Algorithm::HmacSha256`] only signs payloads, the payload is sent as `payload || tag`.
+//!
+//! Keys are provided by a [`KeyProvider`], and the id of the key protecting a payload is carried
+//! by the [`HEADER_PAYLOAD_KEY_ID`] header, so that keys can be rotated by adding the new key to
+//! peers before using it. The id is also authenticated with the payload.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_thrift::codec::default::{
+//!     crypto::{Algorithm, Key, PayloadCrypto, StaticKeys},
+//!     framed::MakeFramedCodec,
+//!     thrift::MakeThriftCodec,
+//!     transform::MakeTransformCodec,
+//!     ttheader::MakeTTHeaderCodec,
+//! };
+//!
+//! // load the secret from your secret manager instead
+//! let key = Key::new(Algorithm::Aes256Gcm, &[0x42; 32]).unwrap();
+//! let crypto = PayloadCrypto::new(StaticKeys::new("2024-01", key));
+//!
+//! // TTHeader<Crypto<Framed<Thrift>>>
+//! let codec = MakeTTHeaderCodec::new(MakeTransformCodec::new(
+//!     MakeFramedCodec::new(MakeThriftCodec::default()),
+//!     crypto,
+//! ));
+//! ```
+//!
+//! [`HEADER_PAYLOAD_KEY_ID`]: super::ttheader::HEADER_PAYLOAD_KEY_ID
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use pilota::{
+    FastStr,
+    thrift::{ProtocolExceptionKind, ThriftException, new_protocol_exception},
+};
+use ring::{
+    aead::{self, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::{transform::PayloadTransform, ttheader::PayloadKeyId};
+use crate::context::ThriftContext;
+
+/// Algorithms protecting payloads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// AES-256 in GCM mode, which encrypts and authenticates payloads with a 32-byte key.
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, which encrypts and authenticates payloads with a 32-byte key.
+    ChaCha20Poly1305,
+    /// HMAC-SHA256, which only signs payloads, the key should be at least 32 bytes.
+    HmacSha256,
+}
+
+/// A key protecting payloads.
+pub struct Key {
+    algorithm: Algorithm,
+    inner: KeyInner,
+}
+
+enum KeyInner {
+    Aead(Box<LessSafeKey>),
+    Hmac(hmac::Key),
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the secret
+        f.debug_struct("Key")
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Key {
+    /// Create a [`Key`] of the `algorithm` with the `secret`.
+    ///
+    /// Returns an error if the length of the secret is invalid for the algorithm.
+    pub fn new(algorithm: Algorithm, secret: &[u8]) -> Result<Self, ThriftException> {
+        let aead = match algorithm {
+            Algorithm::Aes256Gcm => &aead::AES_256_GCM,
+            Algorithm::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+            Algorithm::HmacSha256 => {
+                return Ok(Self {
+                    algorithm,
+                    inner: KeyInner::Hmac(hmac::Key::new(hmac::HMAC_SHA256, secret)),
+                });
+            }
+        };
+        let key = UnboundKey::new(aead, secret).map_err(|_| {
+            new_protocol_exception(
+                ProtocolExceptionKind::InvalidData,
+                format!(
+                    "invalid key length {} for {algorithm:?}, expected {}",
+                    secret.len(),
+                    aead.key_len()
+                ),
+            )
+        })?;
+        Ok(Self {
+            algorithm,
+            inner: KeyInner::Aead(Box::new(LessSafeKey::new(key))),
+        })
+    }
+
+    /// The algorithm of the key.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Returns the length of the protected payload of `len` bytes.
+    fn protected_len(&self, len: usize) -> usize {
+        match &self.inner {
+            KeyInner::Aead(key) => NONCE_LEN + len + key.algorithm().tag_len(),
+            KeyInner::Hmac(_) => len + hmac::HMAC_SHA256.digest_algorithm().output_len(),
+        }
+    }
+
+    fn protect(
+        &self,
+        rng: &SystemRandom,
+        id: &[u8],
+        payload: Bytes,
+    ) -> Result<Bytes, ThriftException> {
+        let mut buf = BytesMut::with_capacity(self.protected_len(payload.len()));
+        match &self.inner {
+            KeyInner::Aead(key) => {
+                let mut nonce = [0; NONCE_LEN];
+                rng.fill(&mut nonce).map_err(|_| failed("generate nonce"))?;
+                buf.put_slice(&nonce);
+                buf.put_slice(&payload);
+                let tag = key
+                    .seal_in_place_separate_tag(
+                        Nonce::assume_unique_for_key(nonce),
+                        aead::Aad::from(id),
+                        &mut buf[NONCE_LEN..],
+                    )
+                    .map_err(|_| failed("encrypt payload"))?;
+                buf.put_slice(tag.as_ref());
+            }
+            KeyInner::Hmac(key) => {
+                buf.put_slice(&payload);
+                buf.put_slice(hmac_sign(key, id, &payload).as_ref());
+            }
+        }
+        Ok(buf.freeze())
+    }
+
+    fn unprotect(&self, id: &[u8], mut payload: Bytes) -> Result<Bytes, ThriftException> {
+        match &self.inner {
+            KeyInner::Aead(key) => {
+                if payload.len() < NONCE_LEN + key.algorithm().tag_len() {
+                    return Err(failed("decrypt payload: too short"));
+                }
+                let nonce = payload.split_to(NONCE_LEN);
+                let nonce = Nonce::try_assume_unique_for_key(&nonce)
+                    .map_err(|_| failed("decrypt payload"))?;
+                let mut buf = BytesMut::from(payload);
+                let len = key
+                    .open_in_place(nonce, aead::Aad::from(id), &mut buf)
+                    .map_err(|_| failed("decrypt payload"))?
+                    .len();
+                buf.truncate(len);
+                Ok(buf.freeze())
+            }
+            KeyInner::Hmac(key) => {
+                let tag_len = hmac::HMAC_SHA256.digest_algorithm().output_len();
+                if payload.len() < tag_len {
+                    return Err(failed("verify payload: too short"));
+                }
+                let tag = payload.split_off(payload.len() - tag_len);
+                let mut msg = Vec::with_capacity(id.len() + 1 + payload.len());
+                msg.extend_from_slice(id);
+                msg.push(0);
+                msg.extend_from_slice(&payload);
+                hmac::verify(key, &msg, &tag).map_err(|_| failed("verify payload"))?;
+                Ok(payload)
+            }
+        }
+    }
+}
+
+/// Signs the id of the key and the payload, separated by a zero byte.
+fn hmac_sign(key: &hmac::Key, id: &[u8], payload: &[u8]) -> hmac::Tag {
+    let mut cx = hmac::Context::with_key(key);
+    cx.update(id);
+    cx.update(&[0]);
+    cx.update(payload);
+    cx.sign()
+}
+
+fn failed(action: &str) -> ThriftException {
+    new_protocol_exception(
+        ProtocolExceptionKind::InvalidData,
+        format!("failed to {action}"),
+    )
+}
+
+/// Provider of keys used by [`PayloadCrypto`].
+pub trait KeyProvider: Send + Sync + 'static {
+    /// Returns the id and the key protecting payloads to be sent.
+    ///
+    /// The id should be a valid header value, and should not be reused by different keys.
+    fn current(&self) -> (FastStr, Arc<Key>);
+
+    /// Returns the key of the id carried by a received payload, or [`None`] if the key is
+    /// unknown, e.g., it is revoked.
+    fn get(&self, id: &str) -> Option<Arc<Key>>;
+}
+
+/// A [`KeyProvider`] with fixed keys.
+///
+/// Keys added by [`StaticKeys::with_key`] are only used for received payloads, so that peers can
+/// switch to a new key after all of them accept it.
+#[derive(Debug)]
+pub struct StaticKeys {
+    current: (FastStr, Arc<Key>),
+    keys: HashMap<FastStr, Arc<Key>>,
+}
+
+impl StaticKeys {
+    /// Create a [`StaticKeys`] protecting payloads to be sent by the `key` with the `id`.
+    pub fn new(id: impl Into<FastStr>, key: Key) -> Self {
+        let (id, key) = (id.into(), Arc::new(key));
+        Self {
+            keys: HashMap::from([(id.clone(), key.clone())]),
+            current: (id, key),
+        }
+    }
+
+    /// Add a key only used for received payloads.
+    pub fn with_key(mut self, id: impl Into<FastStr>, key: Key) -> Self {
+        self.keys.insert(id.into(), Arc::new(key));
+        self
+    }
+}
+
+impl KeyProvider for StaticKeys {
+    fn current(&self) -> (FastStr, Arc<Key>) {
+        self.current.clone()
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<Key>> {
+        self.keys.get(id).cloned()
+    }
+}
+
+/// [`PayloadTransform`] encrypting or signing payloads by keys of the [`KeyProvider`], see
+/// [the module documentation](self) for more details.
+pub struct PayloadCrypto<P> {
+    provider: Arc<P>,
+    rng: SystemRandom,
+}
+
+impl<P> Clone for PayloadCrypto<P> {
+    fn clone(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+impl<P: KeyProvider> PayloadCrypto<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    fn key_of<Cx: ThriftContext>(&self, cx: &Cx) -> Result<(FastStr, Arc<Key>), ThriftException> {
+        let Some(PayloadKeyId(id)) = cx.extensions().get::<PayloadKeyId>() else {
+            return Err(new_protocol_exception(
+                ProtocolExceptionKind::InvalidData,
+                "missing payload key id, the payload must be framed by TTHeader",
+            ));
+        };
+        match self.provider.get(id) {
+            Some(key) => Ok((id.clone(), key)),
+            None => Err(new_protocol_exception(
+                ProtocolExceptionKind::InvalidData,
+                format!("unknown payload key id `{id}`"),
+            )),
+        }
+    }
+}
+
+impl<P: KeyProvider> PayloadTransform for PayloadCrypto<P> {
+    fn encoded_len<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        len: usize,
+    ) -> Result<usize, ThriftException> {
+        // the key is chosen here since the id is sent in headers, which are encoded before the
+        // payload
+        let (id, key) = self.provider.current();
+        cx.extensions_mut().insert(PayloadKeyId(id));
+        Ok(key.protected_len(len))
+    }
+
+    fn encode<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        payload: Bytes,
+    ) -> Result<Bytes, ThriftException> {
+        let (id, key) = self.key_of(cx)?;
+        key.protect(&self.rng, id.as_bytes(), payload)
+    }
+
+    fn decode<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        payload: Bytes,
+    ) -> Result<Bytes, ThriftException> {
+        let (id, key) = self.key_of(cx)?;
+        key.unprotect(id.as_bytes(), payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bytes::Bytes;
+    use linkedbytes::LinkedBytes;
+    use metainfo::MetaInfo;
+    use volo::context::{Context, Role, RpcInfo};
+
+    use super::{Algorithm, Key, PayloadCrypto, StaticKeys};
+    use crate::{
+        ThriftMessage,
+        codec::default::{
+            MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder,
+            framed::MakeFramedCodec,
+            thrift::MakeThriftCodec,
+            transform::MakeTransformCodec,
+            ttheader::{MakeTTHeaderCodec, PayloadKeyId},
+        },
+        context::{ClientContext, ServerContext},
+        protocol::TMessageType,
+        transport::heartbeat::HeartbeatMessage,
+    };
+
+    fn encode(crypto: &PayloadCrypto<StaticKeys>) -> Bytes {
+        let make_codec = MakeTTHeaderCodec::new(MakeTransformCodec::new(
+            MakeFramedCodec::new(MakeThriftCodec::default()),
+            crypto.clone(),
+        ));
+        let (mut encoder, _) = make_codec.make_codec();
+        let mut linked_bytes = LinkedBytes::new();
+        metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            let mut cx =
+                ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+            cx.rpc_info_mut().set_method("ping".into());
+            let msg = ThriftMessage::mk_client_msg(&cx, HeartbeatMessage);
+            let (size, _) = encoder.size(&mut cx, &msg).unwrap();
+            encoder.encode(&mut cx, &mut linked_bytes, msg).unwrap();
+            assert_eq!(linked_bytes.len(), size);
+        });
+        linked_bytes.concat().freeze()
+    }
+
+    fn decode(
+        crypto: &PayloadCrypto<StaticKeys>,
+        mut bytes: Bytes,
+    ) -> Result<Option<PayloadKeyId>, pilota::thrift::ThriftException> {
+        let make_codec = MakeTTHeaderCodec::new(MakeTransformCodec::new(
+            MakeFramedCodec::new(MakeThriftCodec::default()),
+            crypto.clone(),
+        ));
+        let (_, mut decoder) = make_codec.make_codec();
+        metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            let mut cx = ServerContext::default();
+            decoder.decode::<HeartbeatMessage, _>(&mut cx, &mut bytes)?;
+            Ok(cx.extensions().get::<PayloadKeyId>().cloned())
+        })
+    }
+
+    #[test]
+    fn payload_crypto() {
+        for algorithm in [
+            Algorithm::Aes256Gcm,
+            Algorithm::ChaCha20Poly1305,
+            Algorithm::HmacSha256,
+        ] {
+            let crypto = PayloadCrypto::new(StaticKeys::new(
+                "k1",
+                Key::new(algorithm, &[1; 32]).unwrap(),
+            ));
+            let data = encode(&crypto);
+            assert_eq!(
+                decode(&crypto, data.clone()).unwrap(),
+                Some(PayloadKeyId("k1".into()))
+            );
+
+            // tampered payload
+            let mut tampered = data.to_vec();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(decode(&crypto, tampered.into()).is_err());
+
+            // unknown key
+            let other = PayloadCrypto::new(StaticKeys::new(
+                "k2",
+                Key::new(algorithm, &[2; 32]).unwrap(),
+            ));
+            assert!(decode(&other, data).is_err());
+        }
+    }
+
+    #[test]
+    fn key_rotation() {
+        let old = Key::new(Algorithm::Aes256Gcm, &[1; 32]).unwrap();
+        let data = encode(&PayloadCrypto::new(StaticKeys::new("old", old)));
+
+        let crypto = PayloadCrypto::new(
+            StaticKeys::new("new", Key::new(Algorithm::Aes256Gcm, &[2; 32]).unwrap())
+                .with_key("old", Key::new(Algorithm::Aes256Gcm, &[1; 32]).unwrap()),
+        );
+        assert_eq!(
+            decode(&crypto, data).unwrap(),
+            Some(PayloadKeyId("old".into()))
+        );
+
+        // same secret with another id
+        let data = encode(&PayloadCrypto::new(StaticKeys::new(
+            "new",
+            Key::new(Algorithm::Aes256Gcm, &[1; 32]).unwrap(),
+        )));
+        assert!(decode(&crypto, data).is_err());
+
+        assert!(Key::new(Algorithm::ChaCha20Poly1305, &[1; 16]).is_err());
+    }
+}
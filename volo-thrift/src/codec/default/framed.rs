@@ -1,11 +1,13 @@
+use std::sync::Arc;
+
 use bytes::{Buf, Bytes, BytesMut};
 use linkedbytes::LinkedBytes;
 use pilota::thrift::{ProtocolException, ThriftException, rw_ext::WriteExt};
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt};
 use tracing::trace;
-use volo::{context::Role, util::buf_reader::BufReader};
+use volo::{context::Role, memory::MemoryTracker, util::buf_reader::BufReader};
 
-use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder};
+use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder, pool::BufferPool};
 use crate::{EntryMessage, ThriftMessage, context::ThriftContext};
 
 /// Default limit according to thrift spec.
@@ -18,6 +20,8 @@ pub const DEFAULT_MAX_FRAME_SIZE: i32 = 16 * 1024 * 1024; // 16MB
 pub struct MakeFramedCodec<Inner: MakeZeroCopyCodec> {
     inner: Inner,
     max_frame_size: i32,
+    buffer_pool: Option<Arc<BufferPool>>,
+    memory_tracker: Option<MemoryTracker>,
 }
 
 impl<Inner: MakeZeroCopyCodec> MakeFramedCodec<Inner> {
@@ -26,6 +30,8 @@ impl<Inner: MakeZeroCopyCodec> MakeFramedCodec<Inner> {
         Self {
             inner,
             max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            buffer_pool: None,
+            memory_tracker: None,
         }
     }
 
@@ -34,6 +40,25 @@ impl<Inner: MakeZeroCopyCodec> MakeFramedCodec<Inner> {
         self.max_frame_size = max_frame_size;
         self
     }
+
+    /// Take the buffers of frames read by the decoders from the [`BufferPool`], which can be
+    /// shared by codecs of all connections, default is `None`.
+    #[inline]
+    pub fn with_buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Charge the frames read by the decoders to the [`MemoryTracker`] before they are read,
+    /// default is `None`.
+    ///
+    /// The charge is inserted into the extensions of the context, and moved to the account of
+    /// the request by [`MemoryLayer`](crate::server::memory::MemoryLayer) with the same tracker.
+    #[inline]
+    pub fn with_memory_tracker(mut self, tracker: MemoryTracker) -> Self {
+        self.memory_tracker = Some(tracker);
+        self
+    }
 }
 
 impl<Inner: MakeZeroCopyCodec> MakeZeroCopyCodec for MakeFramedCodec<Inner> {
@@ -46,7 +71,12 @@ impl<Inner: MakeZeroCopyCodec> MakeZeroCopyCodec for MakeFramedCodec<Inner> {
         let (encoder, decoder) = self.inner.make_codec();
         (
             FramedEncoder::new(encoder, self.max_frame_size),
-            FramedDecoder::new(decoder, self.max_frame_size),
+            FramedDecoder {
+                inner: decoder,
+                max_frame_size: self.max_frame_size,
+                buffer_pool: self.buffer_pool.clone(),
+                memory_tracker: self.memory_tracker.clone(),
+            },
         )
     }
 }
@@ -58,6 +88,8 @@ pub struct HasFramed;
 pub struct FramedDecoder<D: ZeroCopyDecoder> {
     inner: D,
     max_frame_size: i32,
+    buffer_pool: Option<Arc<BufferPool>>,
+    memory_tracker: Option<MemoryTracker>,
 }
 
 impl<D: ZeroCopyDecoder> FramedDecoder<D> {
@@ -66,8 +98,24 @@ impl<D: ZeroCopyDecoder> FramedDecoder<D> {
         Self {
             inner,
             max_frame_size,
+            buffer_pool: None,
+            memory_tracker: None,
         }
     }
+
+    /// See [`MakeFramedCodec::with_buffer_pool`].
+    #[inline]
+    pub fn with_buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// See [`MakeFramedCodec::with_memory_tracker`].
+    #[inline]
+    pub fn with_memory_tracker(mut self, tracker: MemoryTracker) -> Self {
+        self.memory_tracker = Some(tracker);
+        self
+    }
 }
 
 /// 4-bytes length + 2-byte protocol id
@@ -118,8 +166,14 @@ where
 
                 reader.consume(4);
                 check_framed_size(size, self.max_frame_size)?;
+                if let Some(tracker) = &self.memory_tracker {
+                    charge_frame(cx, tracker, size as usize + 4)?;
+                }
 
-                let mut buffer = BytesMut::with_capacity(size as usize);
+                let mut buffer = match &self.buffer_pool {
+                    Some(pool) => pool.get(size as usize),
+                    None => BytesMut::with_capacity(size as usize),
+                };
 
                 unsafe {
                     buffer.set_len(size as usize);
@@ -131,7 +185,15 @@ where
                 // set has framed flag
                 cx.extensions_mut().insert(HasFramed);
                 // decode inner
-                self.inner.decode(cx, &mut buffer)
+                match &self.buffer_pool {
+                    Some(pool) => {
+                        let result = self.inner.decode(cx, &mut buffer.clone());
+                        // return the buffer if the message does not reference it
+                        pool.put_bytes(buffer);
+                        result
+                    }
+                    None => self.inner.decode(cx, &mut buffer),
+                }
             } else {
                 // no Framed, just forward to inner decoder
                 self.inner.decode_async(cx, reader).await
@@ -142,6 +204,23 @@ where
     }
 }
 
+/// Charge the frame of `size` by [`MemoryTracker::charge_pending`] before it is read, and insert
+/// the charge into the extensions of the context.
+pub(crate) fn charge_frame<Cx: ThriftContext>(
+    cx: &mut Cx,
+    tracker: &MemoryTracker,
+    size: usize,
+) -> Result<(), ProtocolException> {
+    let charge = tracker.charge_pending(size).map_err(|err| {
+        ProtocolException::new(
+            pilota::thrift::ProtocolExceptionKind::SizeLimit,
+            format!("frame of {size} bytes is rejected: {err}"),
+        )
+    })?;
+    cx.extensions_mut().insert(charge);
+    Ok(())
+}
+
 /// Detect protocol according to
 /// <https://github.com/apache/thrift/blob/master/doc/specs/thrift-rpc.md#compatibility>
 #[inline]
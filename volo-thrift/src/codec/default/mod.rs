@@ -27,7 +27,7 @@
 //! [Kitex]: https://github.com/cloudwego/kitex
 //! [TTHeader]: https://www.cloudwego.io/docs/kitex/reference/transport_protocol_ttheader/
 //! [Framed]: https://github.com/apache/thrift/blob/master/doc/specs/thrift-rpc.md#framed-vs-unframed-transport
-use std::future::Future;
+use std::{future::Future, io::IoSlice};
 
 use bytes::Bytes;
 use linkedbytes::LinkedBytes;
@@ -39,9 +39,14 @@ use self::{framed::MakeFramedCodec, thrift::MakeThriftCodec, ttheader::MakeTTHea
 use super::{Decoder, Encoder, MakeCodec};
 use crate::{EntryMessage, ThriftMessage, context::ThriftContext};
 
+#[cfg(feature = "payload-crypto")]
+pub mod crypto;
 pub mod framed;
+pub mod pool;
 pub mod thrift;
+pub mod transform;
 pub mod ttheader;
+pub mod unknown;
 
 /// Trait for encoding a [`ThriftMessage`] in place.
 ///
@@ -115,21 +120,27 @@ pub struct DefaultEncoder<E, W> {
     encoder: E,
     writer: W,
     linked_bytes: LinkedBytes,
+    // messages encoded by `encode_buffered`, and the reusable buffers
+    pending: Vec<LinkedBytes>,
+    free: Vec<LinkedBytes>,
 }
 
-impl<E: ZeroCopyEncoder, W: AsyncWrite + AsyncExt + Unpin + Send + Sync + 'static> Encoder
-    for DefaultEncoder<E, W>
-{
-    #[inline]
-    async fn encode<Req: Send + EntryMessage, Cx: ThriftContext>(
-        &mut self,
+// buffers kept for `encode_buffered` after flushing
+const MAX_FREE_BUFFERS: usize = 64;
+
+impl<E: ZeroCopyEncoder, W> DefaultEncoder<E, W> {
+    /// Encode the message into `linked_bytes`, returns the real size and the malloc size for
+    /// logging errors.
+    fn encode_into<Req: Send + EntryMessage, Cx: ThriftContext>(
+        encoder: &mut E,
         cx: &mut Cx,
+        linked_bytes: &mut LinkedBytes,
         msg: ThriftMessage<Req>,
-    ) -> Result<(), ThriftException> {
+    ) -> Result<(usize, usize), ThriftException> {
         cx.stats_mut().record_encode_start_at();
 
         // first, we need to get the size of the message
-        let (real_size, malloc_size) = self.encoder.size(cx, &msg)?;
+        let (real_size, malloc_size) = encoder.size(cx, &msg)?;
         tracing::trace!(
             "[VOLO] codec encode message real size: {}, malloc size: {}",
             real_size,
@@ -137,33 +148,53 @@ impl<E: ZeroCopyEncoder, W: AsyncWrite + AsyncExt + Unpin + Send + Sync + 'stati
         );
         cx.stats_mut().set_write_size(real_size);
 
-        self.linked_bytes.reset();
+        linked_bytes.reset();
         // then we reserve the size of the message in the linked bytes
-        self.linked_bytes.reserve(malloc_size);
+        linked_bytes.reserve(malloc_size);
         // after that, we encode the message into the linked bytes
-        let mut write_result: Result<(), ThriftException> = self
-            .encoder
-            .encode(cx, &mut self.linked_bytes, msg)
-            .inspect_err(|_| {
-                // record the error time
-                cx.stats_mut().record_encode_end_at();
-            });
-        if write_result.is_ok() {
-            cx.stats_mut().record_encode_end_at();
-            // encode end is also write start
-            cx.stats_mut().record_write_start_at();
+        let result = encoder.encode(cx, linked_bytes, msg);
+        cx.stats_mut().record_encode_end_at();
+        result.map(|_| (real_size, malloc_size))
+    }
+}
 
-            write_result = self
-                .linked_bytes
-                .write_all_vectored(&mut self.writer)
-                .await
-                .map_err(Into::into);
+impl<E: ZeroCopyEncoder, W: AsyncWrite + AsyncExt + Unpin + Send + Sync + 'static> Encoder
+    for DefaultEncoder<E, W>
+{
+    #[inline]
+    async fn encode<Req: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        msg: ThriftMessage<Req>,
+    ) -> Result<(), ThriftException> {
+        // keep the order of messages
+        if !self.pending.is_empty() {
+            Encoder::flush(self).await?;
         }
+
+        let (real_size, malloc_size) =
+            match Self::encode_into(&mut self.encoder, cx, &mut self.linked_bytes, msg) {
+                Ok(size) => size,
+                Err(mut e) => {
+                    // put write end here so we can also record the time of encode error
+                    cx.stats_mut().record_write_end_at();
+                    e.append_msg(&format!(", cx: {:?}", cx.rpc_info()));
+                    tracing::warn!("[VOLO] thrift codec encode message error: {}", e);
+                    return Err(e);
+                }
+            };
+        // encode end is also write start
+        cx.stats_mut().record_write_start_at();
+
+        let mut write_result: Result<(), ThriftException> = self
+            .linked_bytes
+            .write_all_vectored(&mut self.writer)
+            .await
+            .map_err(Into::into);
         if write_result.is_ok() {
             write_result = self.writer.flush().await.map_err(Into::into);
         }
 
-        // put write end here so we can also record the time of encode error
         cx.stats_mut().record_write_end_at();
 
         match write_result {
@@ -180,7 +211,70 @@ impl<E: ZeroCopyEncoder, W: AsyncWrite + AsyncExt + Unpin + Send + Sync + 'stati
                 Err(e)
             }
         }
-        // write_result
+    }
+
+    async fn encode_buffered<Req: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        msg: ThriftMessage<Req>,
+    ) -> Result<(), ThriftException> {
+        let mut linked_bytes = self.free.pop().unwrap_or_default();
+        let result = Self::encode_into(&mut self.encoder, cx, &mut linked_bytes, msg);
+        // the message is written by `flush`, so the write time only covers buffering it
+        cx.stats_mut().record_write_start_at();
+        cx.stats_mut().record_write_end_at();
+        match result {
+            Ok(_) => {
+                self.pending.push(linked_bytes);
+                Ok(())
+            }
+            Err(e) => {
+                self.free.push(linked_bytes);
+                Err(e)
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), ThriftException> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut slices = self
+            .pending
+            .iter()
+            .flat_map(LinkedBytes::io_slice)
+            .collect::<Vec<_>>();
+        let mut bufs = &mut slices[..];
+        let mut result = Ok(());
+        while !bufs.is_empty() {
+            match self.writer.write_vectored(bufs).await {
+                Ok(0) => {
+                    result = Err(std::io::Error::from(std::io::ErrorKind::WriteZero));
+                    break;
+                }
+                Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        drop(slices);
+        if result.is_ok() {
+            result = self.writer.flush().await;
+        }
+
+        for mut linked_bytes in self.pending.drain(..) {
+            if self.free.len() < MAX_FREE_BUFFERS {
+                linked_bytes.reset();
+                self.free.push(linked_bytes);
+            }
+        }
+        result.map_err(|e| {
+            let e = ThriftException::from(e);
+            tracing::warn!("[VOLO] thrift codec flush buffered messages error: {}", e);
+            e
+        })
     }
 
     async fn is_closed(&self) -> bool {
@@ -304,6 +398,11 @@ impl<MkZC: MakeZeroCopyCodec> DefaultMakeCodec<MkZC> {
             make_zero_copy_codec,
         }
     }
+
+    #[cfg(feature = "datagram")]
+    pub(crate) fn make_zero_copy_codec(&self) -> &MkZC {
+        &self.make_zero_copy_codec
+    }
 }
 
 impl Default for DefaultMakeCodec<MakeTTHeaderCodec<MakeFramedCodec<MakeThriftCodec>>> {
@@ -332,6 +431,8 @@ where
                 encoder,
                 writer,
                 linked_bytes: LinkedBytes::new(),
+                pending: Vec::new(),
+                free: Vec::new(),
             },
             DefaultDecoder {
                 decoder,
@@ -475,6 +576,99 @@ mod tests {
         assert!(err.to_string().contains("unexpected eof"));
     }
 
+    #[derive(Default)]
+    struct MockWriter {
+        data: Vec<u8>,
+        writes: usize,
+    }
+
+    impl tokio::io::AsyncWrite for MockWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.poll_write_vectored(_cx, &[io::IoSlice::new(buf)])
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            this.writes += 1;
+            let len = bufs.iter().map(|buf| buf.len()).sum();
+            bufs.iter().for_each(|buf| this.data.extend_from_slice(buf));
+            Poll::Ready(Ok(len))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            true
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl volo::net::ext::AsyncExt for MockWriter {
+        async fn ready(&self, _interest: tokio::io::Interest) -> io::Result<tokio::io::Ready> {
+            Ok(tokio::io::Ready::WRITABLE)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encode_buffered() {
+        use volo::context::Context as _;
+
+        let mut cx = crate::context::ServerContext::default();
+        cx.rpc_info_mut().set_method("ping".into());
+        let msg = |body: &'static [u8]| ThriftMessage::mk_server_resp(&cx, Ok(Bytes::from(body)));
+        let messages = [b"a".as_slice(), b"bc", b"def"];
+
+        let mk_encoder = || DefaultEncoder {
+            encoder: thrift::MakeThriftCodec::default().make_codec().0,
+            writer: MockWriter::default(),
+            linked_bytes: LinkedBytes::new(),
+            pending: Vec::new(),
+            free: Vec::new(),
+        };
+        let mut cx = crate::context::ServerContext::default();
+
+        // each message is written by a syscall
+        let mut encoder = mk_encoder();
+        for body in messages {
+            encoder.encode(&mut cx, msg(body)).await.unwrap();
+        }
+        assert_eq!(encoder.writer.writes, 3);
+
+        // buffered messages are written together
+        let mut batched = mk_encoder();
+        for body in messages {
+            batched.encode_buffered(&mut cx, msg(body)).await.unwrap();
+        }
+        assert_eq!(batched.writer.writes, 0);
+        batched.flush().await.unwrap();
+        assert_eq!(batched.writer.writes, 1);
+        assert_eq!(batched.writer.data, encoder.writer.data);
+        assert_eq!(batched.free.len(), 3);
+
+        // buffered messages are written before the unbuffered one
+        let written = batched.writer.data.len();
+        batched.encode_buffered(&mut cx, msg(b"a")).await.unwrap();
+        batched.encode(&mut cx, msg(b"bc")).await.unwrap();
+        assert_eq!(batched.writer.writes, 3);
+        assert_eq!(
+            batched.writer.data[written..],
+            encoder.writer.data[..batched.writer.data.len() - written]
+        );
+    }
+
     #[cfg(feature = "shmipc")]
     struct ShmipcTestEnv {
         path: std::path::PathBuf,
@@ -0,0 +1,452 @@
+//! Buffer pool for the frames read by decoders.
+//!
+//! [`FramedDecoder`] and [`TTHeaderDecoder`] allocate a [`BytesMut`] for every frame they read,
+//! which puts pressure on the allocator at high throughput. With a [`BufferPool`] set by
+//! [`MakeFramedCodec::with_buffer_pool`] or [`MakeTTHeaderCodec::with_buffer_pool`], the buffers
+//! are taken from and returned to the pool instead.
+//!
+//! The pool is sharded, and each thread uses its own shard, so the lock is almost never
+//! contended. Buffers are grouped into size classes of powers of two, and each class of a shard
+//! keeps at most [`BufferPoolConfig::high_water_mark`] idle buffers. Every
+//! [`BufferPoolConfig::shrink_interval`] operations on a shard, buffers that have stayed idle
+//! during the whole interval are released, so the pool shrinks after a burst of traffic.
+//!
+//! Note that a buffer is only returned to the pool when the decoded message holds no reference to
+//! it, e.g., it will not be recycled if a `Bytes` field of the message is decoded without copying.
+//!
+//! [`FramedDecoder`]: super::framed::FramedDecoder
+//! [`TTHeaderDecoder`]: super::ttheader::TTHeaderDecoder
+//! [`MakeFramedCodec::with_buffer_pool`]: super::framed::MakeFramedCodec::with_buffer_pool
+//! [`MakeTTHeaderCodec::with_buffer_pool`]: super::ttheader::MakeTTHeaderCodec::with_buffer_pool
+
+use std::{
+    cell::Cell,
+    fmt,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+
+const DEFAULT_MIN_CLASS_SIZE: usize = 4 * 1024;
+const DEFAULT_MAX_CLASS_SIZE: usize = 4 * 1024 * 1024;
+const DEFAULT_HIGH_WATER_MARK: usize = 64;
+const DEFAULT_SHRINK_INTERVAL: usize = 4096;
+
+/// Config of [`BufferPool`].
+#[derive(Clone, Debug)]
+pub struct BufferPoolConfig {
+    min_class_size: usize,
+    max_class_size: usize,
+    high_water_mark: usize,
+    shrink_interval: usize,
+    shards: usize,
+}
+
+impl Default for BufferPoolConfig {
+    fn default() -> Self {
+        Self {
+            min_class_size: DEFAULT_MIN_CLASS_SIZE,
+            max_class_size: DEFAULT_MAX_CLASS_SIZE,
+            high_water_mark: DEFAULT_HIGH_WATER_MARK,
+            shrink_interval: DEFAULT_SHRINK_INTERVAL,
+            shards: std::thread::available_parallelism().map_or(1, usize::from),
+        }
+    }
+}
+
+impl BufferPoolConfig {
+    /// Create a [`BufferPoolConfig`] with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size of the smallest class, it is rounded up to a power of two.
+    ///
+    /// Default is 4 KiB.
+    pub fn min_class_size(mut self, size: usize) -> Self {
+        self.min_class_size = size.max(1).next_power_of_two();
+        self
+    }
+
+    /// Set the size of the largest class, it is rounded up to a power of two. Buffers larger than
+    /// it are allocated directly and never pooled.
+    ///
+    /// Default is 4 MiB.
+    pub fn max_class_size(mut self, size: usize) -> Self {
+        self.max_class_size = size.max(1).next_power_of_two();
+        self
+    }
+
+    /// Set the max count of idle buffers kept by each class of a shard, buffers returned when
+    /// there are already so many idle buffers are dropped.
+    ///
+    /// Default is 64.
+    pub fn high_water_mark(mut self, count: usize) -> Self {
+        self.high_water_mark = count;
+        self
+    }
+
+    /// Set the count of operations on a shard between two shrinks, buffers that have stayed idle
+    /// during the interval are released when shrinking.
+    ///
+    /// Default is 4096.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn shrink_interval(mut self, interval: usize) -> Self {
+        assert!(interval > 0, "shrink interval must be greater than zero");
+        self.shrink_interval = interval;
+        self
+    }
+
+    /// Set the count of shards.
+    ///
+    /// Default is the available parallelism.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "shards must be greater than zero");
+        self.shards = shards;
+        self
+    }
+}
+
+/// Sharded pool of [`BytesMut`] used by decoders.
+///
+/// See the [module docs](self) for details.
+pub struct BufferPool {
+    shards: Box<[Mutex<Shard>]>,
+    config: BufferPoolConfig,
+    stats: BufferPoolStats,
+}
+
+struct Shard {
+    classes: Vec<Class>,
+    ops: usize,
+}
+
+struct Class {
+    free: Vec<BytesMut>,
+    // min count of idle buffers since the last shrink
+    low_water: usize,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(BufferPoolConfig::default())
+    }
+}
+
+impl BufferPool {
+    /// Create a [`BufferPool`] with the config.
+    pub fn new(mut config: BufferPoolConfig) -> Self {
+        config.max_class_size = config.max_class_size.max(config.min_class_size);
+        let classes = (config.max_class_size / config.min_class_size).trailing_zeros() as usize + 1;
+        let shards = (0..config.shards)
+            .map(|_| {
+                Mutex::new(Shard {
+                    classes: (0..classes)
+                        .map(|_| Class {
+                            free: Vec::new(),
+                            low_water: 0,
+                        })
+                        .collect(),
+                    ops: 0,
+                })
+            })
+            .collect();
+        Self {
+            shards,
+            config,
+            stats: BufferPoolStats::default(),
+        }
+    }
+
+    /// Get an empty buffer with capacity of at least `size`.
+    pub fn get(&self, size: usize) -> BytesMut {
+        let Some(class) = self.class_of(size) else {
+            self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            return BytesMut::with_capacity(size);
+        };
+        let buf = {
+            let mut shard = self.shard().lock();
+            let buf = {
+                let class = &mut shard.classes[class];
+                let buf = class.free.pop();
+                class.low_water = class.low_water.min(class.free.len());
+                buf
+            };
+            self.tick(&mut shard);
+            buf
+        };
+        match buf {
+            Some(buf) => {
+                self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(self.config.min_class_size << class)
+            }
+        }
+    }
+
+    /// Return a buffer to the pool.
+    ///
+    /// The buffer is dropped if its capacity does not fit any class, or the class already has
+    /// enough idle buffers.
+    pub fn put(&self, mut buf: BytesMut) {
+        let capacity = buf.capacity();
+        if capacity < self.config.min_class_size || capacity > self.config.max_class_size {
+            self.stats.drops.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // the largest class whose size is not greater than the capacity
+        let class = (capacity / self.config.min_class_size).ilog2() as usize;
+        buf.clear();
+
+        let mut shard = self.shard().lock();
+        let pooled = {
+            let class = &mut shard.classes[class];
+            if class.free.len() < self.config.high_water_mark {
+                class.free.push(buf);
+                true
+            } else {
+                false
+            }
+        };
+        self.tick(&mut shard);
+        drop(shard);
+        if pooled {
+            self.stats.returns.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Return the buffer of a frozen [`BytesMut`] to the pool if it's not referenced by others.
+    pub fn put_bytes(&self, bytes: Bytes) {
+        match bytes.try_into_mut() {
+            Ok(buf) => self.put(buf),
+            Err(_) => {
+                self.stats.drops.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Get the stats of the pool.
+    pub fn stats(&self) -> &BufferPoolStats {
+        &self.stats
+    }
+
+    /// Returns the count and the total capacity of idle buffers in the pool.
+    pub fn idle(&self) -> (usize, usize) {
+        self.shards.iter().fold((0, 0), |(count, bytes), shard| {
+            let shard = shard.lock();
+            shard
+                .classes
+                .iter()
+                .fold((count, bytes), |(count, bytes), class| {
+                    (
+                        count + class.free.len(),
+                        bytes + class.free.iter().map(BytesMut::capacity).sum::<usize>(),
+                    )
+                })
+        })
+    }
+
+    fn class_of(&self, size: usize) -> Option<usize> {
+        if size > self.config.max_class_size {
+            return None;
+        }
+        let size = size.max(self.config.min_class_size).next_power_of_two();
+        Some((size / self.config.min_class_size).trailing_zeros() as usize)
+    }
+
+    fn shard(&self) -> &Mutex<Shard> {
+        thread_local! {
+            static SHARD_INDEX: Cell<Option<usize>> = const { Cell::new(None) };
+        }
+        static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+        let index = SHARD_INDEX.with(|index| match index.get() {
+            Some(i) => i,
+            None => {
+                let i = NEXT_INDEX.fetch_add(1, Ordering::Relaxed);
+                index.set(Some(i));
+                i
+            }
+        });
+        &self.shards[index % self.shards.len()]
+    }
+
+    /// Count an operation on the shard, and release the buffers that stay idle during the
+    /// interval.
+    fn tick(&self, shard: &mut Shard) {
+        shard.ops += 1;
+        if shard.ops < self.config.shrink_interval {
+            return;
+        }
+        shard.ops = 0;
+        for class in shard.classes.iter_mut() {
+            if class.low_water > 0 {
+                let len = class.free.len() - class.low_water;
+                class.free.truncate(len);
+                self.stats
+                    .shrinks
+                    .fetch_add(class.low_water as u64, Ordering::Relaxed);
+            }
+            class.low_water = class.free.len();
+        }
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("config", &self.config)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+/// Stats of a [`BufferPool`].
+#[derive(Debug, Default)]
+pub struct BufferPoolStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returns: AtomicU64,
+    drops: AtomicU64,
+    shrinks: AtomicU64,
+}
+
+impl BufferPoolStats {
+    /// Number of buffers got from the pool.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of buffers allocated since there is no idle buffer or the size is too large.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of buffers returned to the pool.
+    pub fn returns(&self) -> u64 {
+        self.returns.load(Ordering::Relaxed)
+    }
+
+    /// Number of buffers dropped instead of being returned, because they are still referenced,
+    /// their capacities do not fit any class, or the high water mark is reached.
+    pub fn drops(&self) -> u64 {
+        self.drops.load(Ordering::Relaxed)
+    }
+
+    /// Number of idle buffers released by shrinking.
+    pub fn shrinks(&self) -> u64 {
+        self.shrinks.load(Ordering::Relaxed)
+    }
+
+    /// Ratio of hits in all gets.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits() + self.misses() {
+            0 => 0.0,
+            total => self.hits() as f64 / total as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use bytes::BufMut;
+
+    use super::{BufferPool, BufferPoolConfig};
+
+    fn pool() -> BufferPool {
+        BufferPool::new(
+            BufferPoolConfig::new()
+                .min_class_size(1024)
+                .max_class_size(8 * 1024)
+                .high_water_mark(2)
+                .shards(1),
+        )
+    }
+
+    #[test]
+    fn test_size_classes() {
+        let pool = pool();
+        let buf = pool.get(100);
+        assert_eq!(buf.capacity(), 1024);
+        let buf = pool.get(1025);
+        assert_eq!(buf.capacity(), 2048);
+        // too large to pool
+        let buf = pool.get(10 * 1024);
+        assert!(buf.capacity() >= 10 * 1024);
+        assert_eq!(pool.stats().misses(), 3);
+
+        pool.put(buf);
+        pool.put(bytes::BytesMut::with_capacity(100));
+        assert_eq!(pool.stats().drops(), 2);
+        assert_eq!(pool.idle(), (0, 0));
+    }
+
+    #[test]
+    fn test_reuse() {
+        let pool = pool();
+        let mut buf = pool.get(2000);
+        buf.put_slice(b"hello");
+        pool.put(buf);
+        assert_eq!(pool.idle(), (1, 2048));
+
+        let buf = pool.get(1500);
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), 2048);
+        assert_eq!(pool.stats().hits(), 1);
+        assert_eq!(pool.stats().returns(), 1);
+
+        // frozen buffers are returned only if not referenced
+        let mut buf = buf;
+        buf.put_slice(b"hello");
+        let bytes = buf.freeze();
+        let referenced = bytes.slice(1..3);
+        pool.put_bytes(bytes.clone());
+        assert_eq!(pool.stats().drops(), 1);
+        drop(referenced);
+        pool.put_bytes(bytes);
+        assert_eq!(pool.idle(), (1, 2048));
+    }
+
+    #[test]
+    fn test_high_water_mark_and_shrink() {
+        let pool = BufferPool::new(
+            BufferPoolConfig::new()
+                .min_class_size(1024)
+                .max_class_size(1024)
+                .high_water_mark(2)
+                .shrink_interval(4)
+                .shards(1),
+        );
+        for _ in 0..3 {
+            pool.put(bytes::BytesMut::with_capacity(1024));
+        }
+        // the third one exceeds the high water mark
+        assert_eq!(pool.idle().0, 2);
+        assert_eq!(pool.stats().drops(), 1);
+
+        // at the 4th operation, the low water mark is reset to 1
+        let buf = pool.get(1024);
+        assert_eq!(pool.idle().0, 1);
+        // only one buffer is used during the next interval, so one is idle for the whole interval
+        pool.put(buf);
+        let buf = pool.get(1024);
+        pool.put(buf);
+        let buf = pool.get(1024);
+        pool.put(buf);
+        assert_eq!(pool.stats().shrinks(), 1);
+        assert_eq!(pool.idle().0, 1);
+    }
+}
@@ -1,4 +1,4 @@
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use linkedbytes::LinkedBytes;
 use pilota::thrift::{
     ProtocolException, ProtocolExceptionKind, TAsyncBinaryProtocol, TAsyncCompactProtocol,
@@ -9,13 +9,14 @@ use pilota::thrift::{
 use tokio::io::AsyncRead;
 use volo::util::buf_reader::BufReader;
 
-use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder};
+use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder, unknown::UnknownFields};
 use crate::{EntryMessage, ThriftMessage, context::ThriftContext};
 
 /// [`MakeThriftCodec`] implements [`MakeZeroCopyCodec`] to create [`ThriftCodec`].
 #[derive(Debug, Clone, Copy)]
 pub struct MakeThriftCodec {
     protocol: Protocol,
+    passthrough: bool,
 }
 
 impl MakeThriftCodec {
@@ -23,6 +24,7 @@ impl MakeThriftCodec {
     pub fn new() -> Self {
         Self {
             protocol: Protocol::Binary,
+            passthrough: false,
         }
     }
 
@@ -46,6 +48,16 @@ impl MakeThriftCodec {
         self.protocol = protocol;
         self
     }
+
+    /// Capture the fields of structs that are not recognized by the generated code and re-emit
+    /// them when the request or response is forwarded, default is `false`.
+    ///
+    /// This is useful for proxies built on volo-thrift, so that fields added by newer versions of
+    /// the IDL will not be dropped silently. See [`unknown`](super::unknown) for details.
+    pub fn with_passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
 }
 
 impl Default for MakeThriftCodec {
@@ -62,8 +74,9 @@ impl MakeZeroCopyCodec for MakeThriftCodec {
 
     #[inline]
     fn make_codec(&self) -> (Self::Encoder, Self::Decoder) {
-        let codec = ThriftCodec::new(self.protocol);
-        (codec, codec)
+        let mut codec = ThriftCodec::new(self.protocol);
+        codec.passthrough = self.passthrough;
+        (codec.clone(), codec)
     }
 }
 
@@ -83,9 +96,12 @@ pub struct ProtocolApacheCompact;
 /// <https://github.com/apache/thrift/blob/master/doc/specs/thrift-rpc.md#compatibility>
 pub const HEADER_DETECT_LENGTH: usize = 1;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct ThriftCodec {
     protocol: Protocol,
+    passthrough: bool,
+    // the message with unknown fields, computed by `size` and written by `encode`
+    encoded: Option<Bytes>,
 }
 
 impl ThriftCodec {
@@ -93,7 +109,46 @@ impl ThriftCodec {
     /// protocol.
     #[inline]
     pub fn new(protocol: Protocol) -> Self {
-        Self { protocol }
+        Self {
+            protocol,
+            passthrough: false,
+            encoded: None,
+        }
+    }
+
+    /// Capture the unknown fields of the binary message decoded from `original`.
+    fn capture_unknown<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        cx: &Cx,
+        original: &[u8],
+        msg: &ThriftMessage<Msg>,
+    ) -> Result<(), ThriftException> {
+        if msg.data.is_err() {
+            return Ok(());
+        }
+        let mut linked_bytes = LinkedBytes::new();
+        msg.encode(&mut TBinaryProtocol::new(&mut linked_bytes, true))?;
+        let encoded = linked_bytes.concat();
+        let fields = UnknownFields::capture(msg.meta.method.clone(), original, &encoded)?;
+        fields.store(cx.rpc_info().role());
+        Ok(())
+    }
+
+    /// Encode the binary message with the unknown fields to forward, if there are any.
+    fn encode_unknown<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        cx: &Cx,
+        msg: &ThriftMessage<Msg>,
+    ) -> Result<Option<Bytes>, ThriftException> {
+        if msg.data.is_err() {
+            return Ok(None);
+        }
+        let Some(fields) = UnknownFields::to_forward(&msg.meta.method, cx.rpc_info().role()) else {
+            return Ok(None);
+        };
+        let mut linked_bytes = LinkedBytes::new();
+        msg.encode(&mut TBinaryProtocol::new(&mut linked_bytes, true))?;
+        let mut dst = BytesMut::new();
+        fields.emit(&linked_bytes.concat(), &mut dst)?;
+        Ok(Some(dst.freeze()))
     }
 }
 
@@ -125,12 +180,13 @@ impl ZeroCopyDecoder for ThriftCodec {
         // TODO: do we need to check the response protocol at client side?
         match protocol {
             Protocol::Binary => {
+                let original = self.passthrough.then(|| bytes.clone());
                 #[cfg(feature = "unsafe-codec")]
                 let mut p = unsafe {
                     pilota::thrift::binary_unsafe::TBinaryUnsafeInputProtocol::new(bytes)
                 };
                 #[cfg(not(feature = "unsafe-codec"))]
-                let mut p = TBinaryProtocol::new(bytes, true);
+                let mut p = TBinaryProtocol::new(&mut *bytes, true);
                 let msg = ThriftMessage::<Msg>::decode(&mut p, cx)?;
                 #[cfg(feature = "unsafe-codec")]
                 {
@@ -139,6 +195,10 @@ impl ZeroCopyDecoder for ThriftCodec {
                     let index = p.index();
                     p.buf().advance(index);
                 }
+                if let Some(original) = original {
+                    let consumed = original.len() - bytes.len();
+                    Self::capture_unknown(cx, &original[..consumed], &msg)?;
+                }
                 cx.extensions_mut().insert(ProtocolBinary);
                 Ok(Some(msg))
             }
@@ -239,6 +299,16 @@ impl ZeroCopyEncoder for ThriftCodec {
         }
         match protocol {
             Protocol::Binary => {
+                if self.passthrough {
+                    let encoded = match self.encoded.take() {
+                        Some(encoded) => Some(encoded),
+                        None => Self::encode_unknown(cx, &msg)?,
+                    };
+                    if let Some(encoded) = encoded {
+                        linked_bytes.bytes_mut().put_slice(&encoded);
+                        return Ok(());
+                    }
+                }
                 #[cfg(feature = "unsafe-codec")]
                 let buf = unsafe {
                     let l = linked_bytes.bytes_mut().len();
@@ -297,6 +367,12 @@ impl ZeroCopyEncoder for ThriftCodec {
         }
         match protocol {
             Protocol::Binary => {
+                if self.passthrough {
+                    self.encoded = Self::encode_unknown(cx, msg)?;
+                    if let Some(encoded) = &self.encoded {
+                        return Ok((encoded.len(), encoded.len()));
+                    }
+                }
                 let mut p = TBinaryProtocol::new((), true);
                 let real_size = msg.size(&mut p);
                 let malloc_size = real_size - p.zero_copy_len();
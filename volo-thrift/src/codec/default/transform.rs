@@ -0,0 +1,327 @@
+//! Transforms of encoded payloads, e.g., for encrypting or signing them.
+//!
+//! [`MakeTransformCodec`] wraps a [`MakeZeroCopyCodec`] and transforms the bytes encoded by it
+//! with a [`PayloadTransform`], and transforms received bytes back before decoding them. It
+//! should be inserted between TTHeader and the payload codec, so that headers are still readable
+//! by proxies:
+//!
+//! ```
+//! use volo_thrift::{
+//!     Bytes, ThriftException,
+//!     codec::default::{
+//!         framed::MakeFramedCodec,
+//!         thrift::MakeThriftCodec,
+//!         transform::{MakeTransformCodec, PayloadTransform},
+//!         ttheader::MakeTTHeaderCodec,
+//!     },
+//!     context::ThriftContext,
+//! };
+//!
+//! /// A toy transform flipping all bits, use a real cipher instead.
+//! #[derive(Clone)]
+//! struct Flip;
+//!
+//! impl PayloadTransform for Flip {
+//!     fn encoded_len<Cx: ThriftContext>(
+//!         &self,
+//!         _cx: &mut Cx,
+//!         len: usize,
+//!     ) -> Result<usize, ThriftException> {
+//!         Ok(len)
+//!     }
+//!
+//!     fn encode<Cx: ThriftContext>(
+//!         &self,
+//!         _cx: &mut Cx,
+//!         payload: Bytes,
+//!     ) -> Result<Bytes, ThriftException> {
+//!         Ok(payload.iter().map(|b| !b).collect())
+//!     }
+//!
+//!     fn decode<Cx: ThriftContext>(
+//!         &self,
+//!         _cx: &mut Cx,
+//!         payload: Bytes,
+//!     ) -> Result<Bytes, ThriftException> {
+//!         Ok(payload.iter().map(|b| !b).collect())
+//!     }
+//! }
+//!
+//! // TTHeader<Transform<Framed<Thrift>>>
+//! let codec = MakeTTHeaderCodec::new(MakeTransformCodec::new(
+//!     MakeFramedCodec::new(MakeThriftCodec::default()),
+//!     Flip,
+//! ));
+//! ```
+//!
+//! The codec can be set by `ClientBuilder::zero_copy_codec` and `Server::zero_copy_codec`.
+//!
+//! Since transformed payloads can not be detected as thrift messages, the transform codec must
+//! be wrapped by a codec framing messages by itself, such as TTHeader, and the peer must use the
+//! same codec stack.
+
+use bytes::Bytes;
+use linkedbytes::LinkedBytes;
+use pilota::thrift::{ProtocolExceptionKind, ThriftException, new_protocol_exception};
+use tokio::io::AsyncRead;
+use volo::util::buf_reader::BufReader;
+
+use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder};
+use crate::{EntryMessage, ThriftMessage, context::ThriftContext};
+
+/// Payloads not shorter than it are inserted into the [`LinkedBytes`] without copying.
+const ZERO_COPY_THRESHOLD: usize = 4 * 1024;
+
+/// Transform of encoded payloads used by [`MakeTransformCodec`].
+pub trait PayloadTransform: Clone + Send + Sync + 'static {
+    /// Returns the exact length of the transformed payload of `len` bytes.
+    ///
+    /// Outer codecs write the length in their headers before encoding the payload, so the
+    /// length must be known in advance, e.g., `len + NONCE_LEN + TAG_LEN` for AEAD ciphers.
+    ///
+    /// It is called before [`encode`](Self::encode) and before outer codecs encode their
+    /// headers, so it can also set the headers to be sent in the `cx`, e.g., the id of the key.
+    fn encoded_len<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        len: usize,
+    ) -> Result<usize, ThriftException>;
+
+    /// Transforms the encoded payload to be sent.
+    fn encode<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        payload: Bytes,
+    ) -> Result<Bytes, ThriftException>;
+
+    /// Transforms the received payload back before it is decoded.
+    fn decode<Cx: ThriftContext>(
+        &self,
+        cx: &mut Cx,
+        payload: Bytes,
+    ) -> Result<Bytes, ThriftException>;
+}
+
+/// [`MakeTransformCodec`] implements [`MakeZeroCopyCodec`] to create [`TransformEncoder`] and
+/// [`TransformDecoder`].
+#[derive(Clone)]
+pub struct MakeTransformCodec<Inner: MakeZeroCopyCodec, T> {
+    inner: Inner,
+    transform: T,
+}
+
+impl<Inner: MakeZeroCopyCodec, T: PayloadTransform> MakeTransformCodec<Inner, T> {
+    #[inline]
+    pub fn new(inner: Inner, transform: T) -> Self {
+        Self { inner, transform }
+    }
+}
+
+impl<Inner: MakeZeroCopyCodec, T: PayloadTransform> MakeZeroCopyCodec
+    for MakeTransformCodec<Inner, T>
+{
+    type Encoder = TransformEncoder<Inner::Encoder, T>;
+
+    type Decoder = TransformDecoder<Inner::Decoder, T>;
+
+    #[inline]
+    fn make_codec(&self) -> (Self::Encoder, Self::Decoder) {
+        let (encoder, decoder) = self.inner.make_codec();
+        (
+            TransformEncoder {
+                inner: encoder,
+                transform: self.transform.clone(),
+                inner_size: (0, 0),
+                size: 0,
+            },
+            TransformDecoder {
+                inner: decoder,
+                transform: self.transform.clone(),
+            },
+        )
+    }
+}
+
+pub struct TransformEncoder<E: ZeroCopyEncoder, T> {
+    inner: E,
+    transform: T,
+    inner_size: (usize, usize), // cache inner size
+    size: usize,                // cache transformed size
+}
+
+impl<E, T> ZeroCopyEncoder for TransformEncoder<E, T>
+where
+    E: ZeroCopyEncoder,
+    T: PayloadTransform,
+{
+    fn encode<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        linked_bytes: &mut LinkedBytes,
+        msg: ThriftMessage<Msg>,
+    ) -> Result<(), ThriftException> {
+        let (_, malloc_size) = self.inner_size;
+        let mut buf = LinkedBytes::with_capacity(malloc_size);
+        self.inner.encode(cx, &mut buf, msg)?;
+        let payload = buf.into_bytes_mut().freeze();
+
+        let payload = self.transform.encode(cx, payload)?;
+        let expected = self.size;
+        if payload.len() != expected {
+            return Err(new_protocol_exception(
+                ProtocolExceptionKind::InvalidData,
+                format!(
+                    "transformed payload length mismatch: expected {expected}, got {}",
+                    payload.len()
+                ),
+            ));
+        }
+        if payload.len() >= ZERO_COPY_THRESHOLD {
+            linked_bytes.insert(payload);
+        } else {
+            linked_bytes.bytes_mut().extend_from_slice(&payload);
+        }
+        Ok(())
+    }
+
+    fn size<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        msg: &ThriftMessage<Msg>,
+    ) -> Result<(usize, usize), ThriftException> {
+        self.inner_size = self.inner.size(cx, msg)?;
+        self.size = self.transform.encoded_len(cx, self.inner_size.0)?;
+        Ok((self.size, self.size))
+    }
+}
+
+pub struct TransformDecoder<D: ZeroCopyDecoder, T> {
+    inner: D,
+    transform: T,
+}
+
+impl<D, T> ZeroCopyDecoder for TransformDecoder<D, T>
+where
+    D: ZeroCopyDecoder,
+    T: PayloadTransform,
+{
+    fn decode<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        bytes: &mut Bytes,
+    ) -> Result<Option<ThriftMessage<Msg>>, ThriftException> {
+        let payload = std::mem::take(bytes);
+        let mut payload = self.transform.decode(cx, payload)?;
+        self.inner.decode(cx, &mut payload)
+    }
+
+    async fn decode_async<
+        Msg: Send + EntryMessage,
+        Cx: ThriftContext,
+        R: AsyncRead + Unpin + Send + Sync,
+    >(
+        &mut self,
+        _cx: &mut Cx,
+        _reader: &mut BufReader<R>,
+    ) -> Result<Option<ThriftMessage<Msg>>, ThriftException> {
+        Err(new_protocol_exception(
+            ProtocolExceptionKind::NotImplemented,
+            "transformed payloads must be framed by an outer codec such as TTHeader",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bytes::Bytes;
+    use linkedbytes::LinkedBytes;
+    use metainfo::MetaInfo;
+    use pilota::thrift::ThriftException;
+    use volo::context::{Context, Role, RpcInfo};
+
+    use super::{MakeTransformCodec, PayloadTransform};
+    use crate::{
+        ThriftMessage,
+        codec::default::{
+            MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder, framed::MakeFramedCodec,
+            thrift::MakeThriftCodec, ttheader::MakeTTHeaderCodec,
+        },
+        context::{ClientContext, ServerContext, ThriftContext},
+        protocol::TMessageType,
+        transport::heartbeat::HeartbeatMessage,
+    };
+
+    /// Appends the xor of all bytes as a checksum.
+    #[derive(Clone)]
+    struct Checksum;
+
+    impl PayloadTransform for Checksum {
+        fn encoded_len<Cx: ThriftContext>(
+            &self,
+            _cx: &mut Cx,
+            len: usize,
+        ) -> Result<usize, ThriftException> {
+            Ok(len + 1)
+        }
+
+        fn encode<Cx: ThriftContext>(
+            &self,
+            _cx: &mut Cx,
+            payload: Bytes,
+        ) -> Result<Bytes, ThriftException> {
+            let mut data = payload.to_vec();
+            data.push(payload.iter().fold(0, |acc, b| acc ^ b));
+            Ok(data.into())
+        }
+
+        fn decode<Cx: ThriftContext>(
+            &self,
+            _cx: &mut Cx,
+            mut payload: Bytes,
+        ) -> Result<Bytes, ThriftException> {
+            let data = payload.split_to(payload.len().saturating_sub(1));
+            if payload.first() != Some(&data.iter().fold(0, |acc, b| acc ^ b)) {
+                return Err(ThriftException::from(std::io::Error::other("bad checksum")));
+            }
+            Ok(data)
+        }
+    }
+
+    #[test]
+    fn transform_codec() {
+        let make_codec = MakeTTHeaderCodec::new(MakeTransformCodec::new(
+            MakeFramedCodec::new(MakeThriftCodec::default()),
+            Checksum,
+        ));
+        let (mut encoder, _) = make_codec.make_codec();
+
+        let mut linked_bytes = LinkedBytes::new();
+        metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            let mut cx =
+                ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+            cx.rpc_info_mut().set_method("ping".into());
+            let msg = ThriftMessage::mk_client_msg(&cx, HeartbeatMessage);
+            let (size, _) = encoder.size(&mut cx, &msg).unwrap();
+            encoder.encode(&mut cx, &mut linked_bytes, msg).unwrap();
+            assert_eq!(linked_bytes.len(), size);
+        });
+        let data = linked_bytes.concat().freeze();
+
+        let decode = |mut bytes: Bytes| {
+            let (_, mut decoder) = make_codec.make_codec();
+            metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+                let mut cx = ServerContext::default();
+                decoder.decode::<HeartbeatMessage, _>(&mut cx, &mut bytes)
+            })
+        };
+        let msg = decode(data.clone()).unwrap().unwrap();
+        assert_eq!(msg.meta.msg_type, TMessageType::Call);
+
+        // tampered payload
+        let mut tampered = data.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decode(tampered.into()).is_err());
+    }
+}
@@ -5,7 +5,7 @@
 
 #![allow(clippy::mutable_key_type)]
 
-use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use linkedbytes::LinkedBytes;
@@ -16,25 +16,91 @@ use pilota::thrift::{
 };
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt};
 use tracing::{trace, warn};
-use volo::{FastStr, context::Role, util::buf_reader::BufReader};
+use volo::{
+    FastStr,
+    context::{Endpoint, Role},
+    loadbalance::tag::RouteTags,
+    memory::MemoryTracker,
+    util::buf_reader::BufReader,
+};
 
-use super::MakeZeroCopyCodec;
+use super::{MakeZeroCopyCodec, framed::charge_frame, pool::BufferPool};
 use crate::{
     BizError, EntryMessage, ThriftMessage,
     codec::default::{ZeroCopyDecoder, ZeroCopyEncoder},
     context::ThriftContext,
 };
 
+#[cfg(feature = "ttheader-strict")]
+pub mod conformance;
+#[cfg(feature = "ttheader-strict")]
+pub mod strict;
+
 /// [`MakeTTHeaderCodec`] implements [`MakeZeroCopyCodec`] to create [`TTHeaderEncoder`] and
 /// [`TTHeaderDecoder`].
 #[derive(Clone)]
 pub struct MakeTTHeaderCodec<Inner: MakeZeroCopyCodec> {
     inner: Inner,
+    passthrough: bool,
+    #[cfg(feature = "ttheader-strict")]
+    strict: bool,
+    buffer_pool: Option<Arc<BufferPool>>,
+    memory_tracker: Option<MemoryTracker>,
 }
 
 impl<Inner: MakeZeroCopyCodec> MakeTTHeaderCodec<Inner> {
     pub fn new(inner: Inner) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            passthrough: false,
+            #[cfg(feature = "ttheader-strict")]
+            strict: false,
+            buffer_pool: None,
+            memory_tracker: None,
+        }
+    }
+
+    /// Take the buffers of frames read by the decoders from the [`BufferPool`], which can be
+    /// shared by codecs of all connections, default is `None`.
+    pub fn with_buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Charge the frames read by the decoders to the [`MemoryTracker`] before they are read,
+    /// default is `None`.
+    ///
+    /// The charge is inserted into the extensions of the context, and moved to the account of
+    /// the request by [`MemoryLayer`](crate::server::memory::MemoryLayer) with the same tracker.
+    pub fn with_memory_tracker(mut self, tracker: MemoryTracker) -> Self {
+        self.memory_tracker = Some(tracker);
+        self
+    }
+
+    /// Capture the key-values of TTHeader that are not recognized by volo and re-emit them when
+    /// the request or response is forwarded, default is `false`.
+    ///
+    /// This is useful for proxies built on volo-thrift, so that headers added by newer peers will
+    /// not be dropped silently. Headers decoded from a request are written into the requests sent
+    /// by clients in the same task, and headers decoded from a response of a client are written
+    /// into the response of the server. See [`UnknownHeaders`] for details.
+    ///
+    /// Note that this only works on the headers, unknown fields of structs are preserved by
+    /// [`MakeThriftCodec::with_passthrough`](super::thrift::MakeThriftCodec::with_passthrough).
+    pub fn with_passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Validate the framing of TTHeader strictly before decoding, default is `false`.
+    ///
+    /// Malformed frames from peers, e.g., lengths overflowing the frame, non-UTF-8 strings,
+    /// unknown info ids or transforms, are rejected with a [`ProtocolException`] instead of being
+    /// misread. See [`strict`] for the rules.
+    #[cfg(feature = "ttheader-strict")]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 }
 
@@ -45,7 +111,17 @@ impl<Inner: MakeZeroCopyCodec> MakeZeroCopyCodec for MakeTTHeaderCodec<Inner> {
 
     fn make_codec(&self) -> (Self::Encoder, Self::Decoder) {
         let (encoder, decoder) = self.inner.make_codec();
-        (TTHeaderEncoder::new(encoder), TTHeaderDecoder::new(decoder))
+        let mut encoder = TTHeaderEncoder::new(encoder);
+        encoder.passthrough = self.passthrough;
+        let mut decoder = TTHeaderDecoder::new(decoder);
+        decoder.passthrough = self.passthrough;
+        decoder.buffer_pool = self.buffer_pool.clone();
+        decoder.memory_tracker = self.memory_tracker.clone();
+        #[cfg(feature = "ttheader-strict")]
+        {
+            decoder.strict = self.strict;
+        }
+        (encoder, decoder)
     }
 }
 
@@ -54,14 +130,130 @@ pub struct HasTTHeader;
 
 struct BizErrorExtra(FastStr);
 
+/// Key-values of TTHeader that are not recognized by volo.
+///
+/// With [`MakeTTHeaderCodec::with_passthrough`] enabled, they are captured during decoding and
+/// stored in [`metainfo`]:
+///
+/// - headers of a request received by the server are read by [`UnknownHeaders::upstream`], and will
+///   be written into requests sent by clients.
+/// - headers of a response received by a client are read by [`UnknownHeaders::downstream`], and
+///   will be written into the response sent by the server.
+///
+/// Key-values with prefixes of [`metainfo`] are not included since they are always forwarded
+/// according to their kinds.
+///
+/// Note that the response of a multiplex client is decoded in the background, so headers of it
+/// cannot be captured.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownHeaders {
+    /// String key-values.
+    pub headers: Vec<(FastStr, FastStr)>,
+    /// Int key-values whose keys are not defined in [`IntMetaKey`].
+    pub int_headers: Vec<(u16, FastStr)>,
+}
+
+struct UpstreamUnknownHeaders(UnknownHeaders);
+
+struct DownstreamUnknownHeaders(UnknownHeaders);
+
+impl UnknownHeaders {
+    /// Get the unknown headers of the request received by the server in the current task.
+    pub fn upstream() -> Option<Self> {
+        metainfo::METAINFO
+            .try_with(|mi| {
+                mi.borrow()
+                    .get::<UpstreamUnknownHeaders>()
+                    .map(|h| h.0.clone())
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Get the unknown headers of the response received by the client in the current task.
+    pub fn downstream() -> Option<Self> {
+        metainfo::METAINFO
+            .try_with(|mi| {
+                mi.borrow()
+                    .get::<DownstreamUnknownHeaders>()
+                    .map(|h| h.0.clone())
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Whether there is no unknown header.
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.int_headers.is_empty()
+    }
+
+    /// The headers should be re-emitted when encoding a message of `role`.
+    fn to_forward(metainfo: &metainfo::MetaInfo, role: Role) -> Option<&Self> {
+        match role {
+            Role::Client => metainfo.get::<UpstreamUnknownHeaders>().map(|h| &h.0),
+            Role::Server => metainfo.get::<DownstreamUnknownHeaders>().map(|h| &h.0),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct TTHeaderDecoder<D: ZeroCopyDecoder> {
     inner: D,
+    passthrough: bool,
+    #[cfg(feature = "ttheader-strict")]
+    strict: bool,
+    buffer_pool: Option<Arc<BufferPool>>,
+    memory_tracker: Option<MemoryTracker>,
 }
 
 impl<D: ZeroCopyDecoder> TTHeaderDecoder<D> {
     pub fn new(inner: D) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            passthrough: false,
+            #[cfg(feature = "ttheader-strict")]
+            strict: false,
+            buffer_pool: None,
+            memory_tracker: None,
+        }
+    }
+
+    /// See [`MakeTTHeaderCodec::with_buffer_pool`].
+    pub fn with_buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// See [`MakeTTHeaderCodec::with_memory_tracker`].
+    pub fn with_memory_tracker(mut self, tracker: MemoryTracker) -> Self {
+        self.memory_tracker = Some(tracker);
+        self
+    }
+
+    /// Decode the frame of TTHeader read by `decode_async`, the length has been consumed.
+    fn decode_frame<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        buffer: &mut Bytes,
+    ) -> Result<Option<ThriftMessage<Msg>>, ThriftException> {
+        #[cfg(feature = "ttheader-strict")]
+        if self.strict {
+            strict::parse(buffer.len() as u32, buffer)?;
+        }
+
+        // decode ttheader
+        decode(cx, buffer, self.passthrough)?;
+        // set has ttheader flag
+        cx.extensions_mut().insert(HasTTHeader);
+        // decode inner
+        self.inner.decode(cx, buffer)
+    }
+
+    /// See [`MakeTTHeaderCodec::with_strict`].
+    #[cfg(feature = "ttheader-strict")]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 }
 
@@ -85,9 +277,14 @@ where
         }
 
         if is_ttheader(&bytes[..HEADER_DETECT_LENGTH]) {
+            #[cfg(feature = "ttheader-strict")]
+            if self.strict {
+                let size = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+                strict::parse(size, &bytes[4..])?;
+            }
             let _size = bytes.get_u32() as usize;
             // decode ttheader
-            decode(cx, bytes)?;
+            decode(cx, bytes, self.passthrough)?;
             // set has ttheader flag
             cx.extensions_mut().insert(HasTTHeader);
         }
@@ -112,7 +309,13 @@ where
                 cx.stats_mut().set_read_size(size + 4);
 
                 reader.consume(4);
-                let mut buffer = BytesMut::with_capacity(size);
+                if let Some(tracker) = &self.memory_tracker {
+                    charge_frame(cx, tracker, size + 4)?;
+                }
+                let mut buffer = match &self.buffer_pool {
+                    Some(pool) => pool.get(size),
+                    None => BytesMut::with_capacity(size),
+                };
                 unsafe {
                     buffer.set_len(size);
                 }
@@ -120,14 +323,19 @@ where
 
                 cx.stats_mut().record_read_end_at();
 
-                let mut buffer = buffer.freeze();
-
-                // decode ttheader
-                decode(cx, &mut buffer)?;
-                // set has ttheader flag
-                cx.extensions_mut().insert(HasTTHeader);
-                // decode inner
-                self.inner.decode(cx, &mut buffer)
+                let buffer = buffer.freeze();
+                match self.buffer_pool.clone() {
+                    Some(pool) => {
+                        let result = self.decode_frame(cx, &mut buffer.clone());
+                        // return the buffer if the message does not reference it
+                        pool.put_bytes(buffer);
+                        result
+                    }
+                    None => {
+                        let mut buffer = buffer;
+                        self.decode_frame(cx, &mut buffer)
+                    }
+                }
             } else {
                 // no TTHeader, just forward to inner decoder
                 self.inner.decode_async(cx, reader).await
@@ -147,6 +355,7 @@ pub fn is_ttheader(buf: &[u8]) -> bool {
 pub struct TTHeaderEncoder<E: ZeroCopyEncoder> {
     inner: E,
     inner_size: usize, // used to cache the size
+    passthrough: bool,
 }
 
 impl<E: ZeroCopyEncoder> TTHeaderEncoder<E> {
@@ -154,6 +363,7 @@ impl<E: ZeroCopyEncoder> TTHeaderEncoder<E> {
         Self {
             inner,
             inner_size: 0,
+            passthrough: false,
         }
     }
 }
@@ -172,7 +382,7 @@ where
         // only encode ttheader if role is client or server has detected ttheader in decode
         if cx.rpc_info().role() == Role::Client || cx.extensions().contains::<HasTTHeader>() {
             // encode ttheader first
-            encode(cx, dst, self.inner_size)?;
+            encode(cx, dst, self.inner_size, self.passthrough)?;
         }
         self.inner.encode(cx, linked_bytes, msg)
     }
@@ -186,7 +396,7 @@ where
         self.inner_size = real_size;
         // only calc ttheader size if role is client or server has detected ttheader in decode
         if cx.rpc_info().role() == Role::Client || cx.extensions().contains::<HasTTHeader>() {
-            let size = encode_size(cx)?;
+            let size = encode_size(cx, self.passthrough)?;
             Ok((real_size + size, malloc_size + size))
         } else {
             Ok((real_size, malloc_size))
@@ -215,6 +425,28 @@ pub(crate) const TT_HEADER_BIZ_EXTRA_KEY: &str = "biz-extra";
 /// IDL service name header key for multi-service routing.
 pub const HEADER_IDL_SERVICE_NAME: &str = "isn";
 
+/// Header key of the [`PayloadKeyId`].
+pub const HEADER_PAYLOAD_KEY_ID: &str = "payload-key-id";
+
+/// Prefix of header keys for [`RouteTags`], e.g., `route-tag-env` for the environment.
+///
+/// Route tags of a request received by the server are stored into [`metainfo`], so they are
+/// written into requests sent by clients in the same task and used for selecting instances by
+/// [`TagRouteBalance`].
+///
+/// [`TagRouteBalance`]: volo::loadbalance::tag::TagRouteBalance
+pub const HEADER_ROUTE_TAG_PREFIX: &str = "route-tag-";
+
+/// Id of the key protecting the payload, e.g., by a [`PayloadTransform`] encrypting it, which is
+/// carried by the [`HEADER_PAYLOAD_KEY_ID`] header.
+///
+/// It is sent if it is in the extensions of the context when encoding, and it is inserted into
+/// the extensions when decoding a message with the header.
+///
+/// [`PayloadTransform`]: super::transform::PayloadTransform
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayloadKeyId(pub FastStr);
+
 #[derive(TryFromPrimitive, Clone, Copy, Default)]
 #[repr(u8)]
 pub enum ProtocolId {
@@ -281,6 +513,7 @@ pub(crate) fn encode<Cx: ThriftContext>(
     cx: &mut Cx,
     dst: &mut BytesMut,
     size: usize,
+    passthrough: bool,
 ) -> Result<(), ThriftException> {
     metainfo::METAINFO.with(|metainfo| {
         let metainfo = metainfo.borrow_mut();
@@ -318,21 +551,34 @@ pub(crate) fn encode<Cx: ThriftContext>(
         // }
 
         let role = cx.rpc_info().role();
+        let unknown = if passthrough {
+            UnknownHeaders::to_forward(&metainfo, role)
+        } else {
+            None
+        };
+        let route_tags = match role {
+            Role::Client => route_tags_to_send(cx.rpc_info().callee(), &metainfo),
+            Role::Server => None,
+        };
 
         // Write string KV start.
 
-        let has_string_kv = match role {
-            Role::Client => {
-                metainfo.get_all_persistents().is_some()
-                    || metainfo.get_all_transients().is_some()
-                    || cx.idl_service_name().is_some()
-            }
-            Role::Server => {
-                metainfo.get_all_backward_transients().is_some()
-                    || cx.encode_conn_reset()
-                    || cx.stats().biz_error().is_some()
-            }
-        };
+        let payload_key_id = cx.extensions().get::<PayloadKeyId>();
+        let has_string_kv = unknown.is_some_and(|u| !u.headers.is_empty())
+            || route_tags.is_some()
+            || payload_key_id.is_some()
+            || match role {
+                Role::Client => {
+                    metainfo.get_all_persistents().is_some()
+                        || metainfo.get_all_transients().is_some()
+                        || cx.idl_service_name().is_some()
+                }
+                Role::Server => {
+                    metainfo.get_all_backward_transients().is_some()
+                        || cx.encode_conn_reset()
+                        || cx.stats().biz_error().is_some()
+                }
+            };
 
         if has_string_kv {
             dst.put_u8(info::INFO_KEY_VALUE);
@@ -375,6 +621,17 @@ pub(crate) fn encode<Cx: ThriftContext>(
                         dst.put_slice(isn.as_bytes());
                         string_kv_len += 1;
                     }
+                    if let Some(tags) = route_tags {
+                        for (key, value) in tags.iter() {
+                            let key_len = HEADER_ROUTE_TAG_PREFIX.len() + key.len();
+                            dst.put_u16(key_len as u16);
+                            dst.put_slice(HEADER_ROUTE_TAG_PREFIX.as_bytes());
+                            dst.put_slice(key.as_bytes());
+                            dst.put_u16(value.len() as u16);
+                            dst.put_slice(value.as_bytes());
+                            string_kv_len += 1;
+                        }
+                    }
                 }
                 Role::Server => {
                     if let Some(at) = metainfo.get_all_backward_transients() {
@@ -431,6 +688,24 @@ pub(crate) fn encode<Cx: ThriftContext>(
                 }
             }
 
+            if let Some(PayloadKeyId(id)) = payload_key_id {
+                dst.put_u16(HEADER_PAYLOAD_KEY_ID.len() as u16);
+                dst.put_slice(HEADER_PAYLOAD_KEY_ID.as_bytes());
+                dst.put_u16(id.len() as u16);
+                dst.put_slice(id.as_bytes());
+                string_kv_len += 1;
+            }
+
+            if let Some(unknown) = unknown {
+                for (key, value) in &unknown.headers {
+                    dst.put_u16(key.len() as u16);
+                    dst.put_slice(key.as_bytes());
+                    dst.put_u16(value.len() as u16);
+                    dst.put_slice(value.as_bytes());
+                    string_kv_len += 1;
+                }
+            }
+
             let mut buf = &mut dst[string_kv_index..string_kv_index + 2];
             buf.put_u16(string_kv_len);
         }
@@ -510,6 +785,15 @@ pub(crate) fn encode<Cx: ThriftContext>(
             }
         };
 
+        if let Some(unknown) = unknown {
+            for (key, value) in &unknown.int_headers {
+                dst.put_u16(*key);
+                dst.put_u16(value.len() as u16);
+                dst.put_slice(value.as_bytes());
+                int_kv_len += 1;
+            }
+        }
+
         // fill int kv length
         let mut buf = &mut dst[int_kv_index..int_kv_index + 2];
         buf.put_u16(int_kv_len);
@@ -559,7 +843,10 @@ pub(crate) fn encode<Cx: ThriftContext>(
 }
 
 // this must be with sync to the encode impl
-pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, ThriftException> {
+pub(crate) fn encode_size<Cx: ThriftContext>(
+    cx: &mut Cx,
+    passthrough: bool,
+) -> Result<usize, ThriftException> {
     let thrift_cx = cx;
     Ok(metainfo::METAINFO.with(|metainfo| {
         let metainfo = metainfo.borrow_mut();
@@ -587,19 +874,32 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
         // }
 
         let role = thrift_cx.rpc_info().role();
+        let unknown = if passthrough {
+            UnknownHeaders::to_forward(&metainfo, role)
+        } else {
+            None
+        };
+        let route_tags = match role {
+            Role::Client => route_tags_to_send(thrift_cx.rpc_info().callee(), &metainfo),
+            Role::Server => None,
+        };
 
         // Write string KV start.
 
-        let has_string_kv = match role {
-            Role::Client => {
-                metainfo.get_all_persistents().is_some()
-                    || metainfo.get_all_transients().is_some()
-                    || thrift_cx.idl_service_name().is_some()
-            }
-            Role::Server => {
-                metainfo.get_all_backward_transients().is_some() || thrift_cx.encode_conn_reset()
-            }
-        };
+        let has_string_kv = unknown.is_some_and(|u| !u.headers.is_empty())
+            || route_tags.is_some()
+            || thrift_cx.extensions().contains::<PayloadKeyId>()
+            || match role {
+                Role::Client => {
+                    metainfo.get_all_persistents().is_some()
+                        || metainfo.get_all_transients().is_some()
+                        || thrift_cx.idl_service_name().is_some()
+                }
+                Role::Server => {
+                    metainfo.get_all_backward_transients().is_some()
+                        || thrift_cx.encode_conn_reset()
+                }
+            };
 
         if has_string_kv {
             // info key value
@@ -634,6 +934,14 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
                         len += 2; // value length
                         len += isn.len();
                     }
+                    if let Some(tags) = route_tags {
+                        for (key, value) in tags.iter() {
+                            len += 2;
+                            len += HEADER_ROUTE_TAG_PREFIX.len() + key.len();
+                            len += 2;
+                            len += value.len();
+                        }
+                    }
                 }
                 Role::Server => {
                     if let Some(at) = metainfo.get_all_backward_transients() {
@@ -676,6 +984,22 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
                     }
                 }
             }
+
+            if let Some(PayloadKeyId(id)) = thrift_cx.extensions().get::<PayloadKeyId>() {
+                len += 2;
+                len += HEADER_PAYLOAD_KEY_ID.len();
+                len += 2;
+                len += id.len();
+            }
+
+            if let Some(unknown) = unknown {
+                for (key, value) in &unknown.headers {
+                    len += 2;
+                    len += key.len();
+                    len += 2;
+                    len += value.len();
+                }
+            }
         }
 
         // int KV start
@@ -741,6 +1065,14 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
             }
         };
 
+        if let Some(unknown) = unknown {
+            for (_, value) in &unknown.int_headers {
+                len += 2;
+                len += 2;
+                len += value.len();
+            }
+        }
+
         // write padding
         let overflow = (len - 14) % 4;
         let padding = (4 - overflow) % 4;
@@ -749,9 +1081,20 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
     }))
 }
 
+/// Convert the key or value of a header sent by the peer, which must be valid UTF-8.
+fn header_str(bytes: Bytes) -> Result<FastStr, ThriftException> {
+    FastStr::from_bytes(bytes).map_err(|err| {
+        new_protocol_exception(
+            ProtocolExceptionKind::InvalidData,
+            format!("invalid utf-8 in ttheader key or value: {err}"),
+        )
+    })
+}
+
 pub(crate) fn decode<Cx: ThriftContext>(
     cx: &mut Cx,
     src: &mut Bytes,
+    passthrough: bool,
 ) -> Result<(), ThriftException> {
     metainfo::METAINFO.with(|metainfo| {
             let metainfo = &mut *metainfo.borrow_mut();
@@ -781,6 +1124,7 @@ pub(crate) fn decode<Cx: ThriftContext>(
             #[allow(clippy::mutable_key_type)]
             let mut headers = HashMap::new();
             let mut int_headers = HashMap::new();
+            let mut unknown = UnknownHeaders::default();
             let mut _padding_num = 0usize;
 
             let mut remaining_header_size = (header_size as usize) * 4 - 2 /* protocol_id and transform_ids_num */ - transform_ids_num as usize;
@@ -808,10 +1152,7 @@ pub(crate) fn decode<Cx: ThriftContext>(
                             remaining_header_size -= value_len as usize;
                             let value = src.split_to(value_len as usize);
 
-                            headers.insert(
-                                unsafe { FastStr::from_bytes_unchecked(key) },
-                                unsafe { FastStr::from_bytes_unchecked(value) }
-                            );
+                            headers.insert(header_str(key)?, header_str(value)?);
                         }
                     }
                     info::INFO_INT_KEY_VALUE => {
@@ -829,14 +1170,14 @@ pub(crate) fn decode<Cx: ThriftContext>(
                                 Ok(k) => k,
                                 Err(e) => {
                                     tracing::debug!("[VOLO] unknown int header key: {}, value: {:?}, error: {}", key, value, e);
+                                    if passthrough {
+                                        unknown.int_headers.push((key, header_str(value)?));
+                                    }
                                     continue;
                                 },
                             };
 
-                            int_headers.insert(
-                                key,
-                                unsafe { FastStr::from_bytes_unchecked(value) }
-                            );
+                            int_headers.insert(key, header_str(value)?);
                         }
                     }
 
@@ -855,6 +1196,10 @@ pub(crate) fn decode<Cx: ThriftContext>(
                 }
             }
 
+            if let Some(id) = headers.remove(HEADER_PAYLOAD_KEY_ID) {
+                cx.extensions_mut().insert(PayloadKeyId(id));
+            }
+
             let role = cx.rpc_info().role();
             match role {
                 Role::Client => {
@@ -880,8 +1225,13 @@ pub(crate) fn decode<Cx: ThriftContext>(
                     for (k, v) in headers.into_iter() {
                         if k.starts_with(metainfo::RPC_PREFIX_BACKWARD) {
                             metainfo.strip_rpc_prefix_and_set_backward_downstream(k, v);
+                        } else if passthrough && !is_reserved_header(&k) {
+                            unknown.headers.push((k, v));
                         }
                     }
+                    if passthrough {
+                        metainfo.insert(DownstreamUnknownHeaders(unknown));
+                    }
                 }
                 Role::Server => {
                     // Extract IDL service name (ISN) for multi-service routing
@@ -924,19 +1274,59 @@ pub(crate) fn decode<Cx: ThriftContext>(
 
                     // Search for forward metainfo.
                     // We are not supposed to use headers, so we can use into_iter to avoid clone.
+                    let mut route_tags = RouteTags::new();
                     for (k, v) in headers.into_iter() {
                         if k.starts_with(metainfo::RPC_PREFIX_PERSISTENT) {
                             metainfo.strip_rpc_prefix_and_set_persistent(k, v);
                         } else if k.starts_with(metainfo::RPC_PREFIX_TRANSIENT) {
                             metainfo.strip_rpc_prefix_and_set_upstream(k, v);
+                        } else if let Some(key) = k.strip_prefix(HEADER_ROUTE_TAG_PREFIX) {
+                            route_tags.insert(k.slice_ref(key), v);
+                        } else if passthrough && !is_reserved_header(&k) {
+                            unknown.headers.push((k, v));
                         }
                     }
+                    if !route_tags.is_empty() {
+                        metainfo.insert(route_tags);
+                    }
+                    if passthrough {
+                        metainfo.insert(UpstreamUnknownHeaders(unknown));
+                    }
                 }
             }
             Ok(())
         })
 }
 
+/// Route tags written into requests sent by clients, which are set to the callee or received by
+/// the server in the current task, see [`RouteTags::current`].
+fn route_tags_to_send<'a>(
+    callee: &'a Endpoint,
+    metainfo: &'a metainfo::MetaInfo,
+) -> Option<&'a RouteTags> {
+    callee
+        .get::<RouteTags>()
+        .or_else(|| metainfo.get::<RouteTags>())
+        .filter(|tags| !tags.is_empty())
+}
+
+/// Headers which are generated by volo itself for each message, so they must not be forwarded.
+fn is_reserved_header(key: &str) -> bool {
+    matches!(
+        key,
+        HEADER_TRANS_REMOTE_ADDR
+            | HEADER_CONNECTION_READY_TO_RESET
+            | HEADER_IDL_SERVICE_NAME
+            | HEADER_PAYLOAD_KEY_ID
+            | TT_HEADER_BIZ_STATUS_KEY
+            | TT_HEADER_BIZ_MESSAGE_KEY
+            | TT_HEADER_BIZ_EXTRA_KEY
+    ) || key.starts_with(HEADER_ROUTE_TAG_PREFIX)
+        || key.starts_with(metainfo::RPC_PREFIX_PERSISTENT)
+        || key.starts_with(metainfo::RPC_PREFIX_TRANSIENT)
+        || key.starts_with(metainfo::RPC_PREFIX_BACKWARD)
+}
+
 fn set_biz_error_header<Cx: ThriftContext>(
     thrift_cx: &mut Cx,
     headers: &mut HashMap<FastStr, FastStr>,
@@ -992,4 +1382,134 @@ mod tests {
     fn test_idl_service_name_constant() {
         assert_eq!(HEADER_IDL_SERVICE_NAME, "isn");
     }
+
+    #[tokio::test]
+    async fn test_passthrough_unknown_headers() {
+        use std::cell::RefCell;
+
+        use metainfo::MetaInfo;
+        use pilota::thrift::TMessageType;
+        use volo::context::RpcInfo;
+
+        use crate::context::{ClientContext, ServerContext};
+
+        let unknown = UnknownHeaders {
+            headers: vec![(
+                FastStr::from_static_str("x-new-header"),
+                FastStr::from_static_str("value"),
+            )],
+            int_headers: vec![(100, FastStr::from_static_str("1"))],
+        };
+
+        // encode a request with unknown headers from upstream
+        let mut dst = BytesMut::new();
+        let mut mi = MetaInfo::default();
+        mi.insert(UpstreamUnknownHeaders(unknown.clone()));
+        metainfo::METAINFO
+            .scope(RefCell::new(mi), async {
+                let mut cx =
+                    ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+                let size = encode_size(&mut cx, true).unwrap();
+                encode(&mut cx, &mut dst, 0, true).unwrap();
+                assert_eq!(size, dst.len());
+            })
+            .await;
+
+        let decode_with = |passthrough| {
+            let mut src = dst.clone().freeze();
+            src.advance(4);
+            metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+                let mut cx = ServerContext::default();
+                decode(&mut cx, &mut src, passthrough).unwrap();
+                UnknownHeaders::upstream()
+            })
+        };
+        assert_eq!(decode_with(true), Some(unknown));
+        assert_eq!(decode_with(false), None);
+    }
+
+    #[tokio::test]
+    async fn test_route_tags() {
+        use std::cell::RefCell;
+
+        use metainfo::MetaInfo;
+        use pilota::thrift::TMessageType;
+        use volo::{
+            context::{Context, RpcInfo},
+            loadbalance::tag::{ENV_TAG, RouteTags, SET_TAG},
+        };
+
+        use crate::context::{ClientContext, ServerContext};
+
+        let encode_with = |mi: MetaInfo, callee_tags: Option<RouteTags>| {
+            let mut dst = BytesMut::new();
+            metainfo::METAINFO.sync_scope(RefCell::new(mi), || {
+                let mut cx =
+                    ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+                if let Some(tags) = callee_tags {
+                    cx.rpc_info_mut().callee_mut().insert(tags);
+                }
+                let size = encode_size(&mut cx, false).unwrap();
+                encode(&mut cx, &mut dst, 0, false).unwrap();
+                assert_eq!(size, dst.len());
+            });
+            dst
+        };
+        let decode_tags = |dst: BytesMut| {
+            let mut src = dst.freeze();
+            src.advance(4);
+            metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+                let mut cx = ServerContext::default();
+                decode(&mut cx, &mut src, true).unwrap();
+                // route tags are not unknown headers
+                assert_eq!(UnknownHeaders::upstream(), Some(UnknownHeaders::default()));
+                metainfo::METAINFO.with(|mi| mi.borrow().get::<RouteTags>().cloned())
+            })
+        };
+
+        // route tags received from upstream are forwarded
+        let upstream = RouteTags::new().with(ENV_TAG, "lane-1");
+        let mut mi = MetaInfo::default();
+        mi.insert(upstream.clone());
+        assert_eq!(decode_tags(encode_with(mi, None)), Some(upstream));
+
+        // route tags of the callee take precedence
+        let callee = RouteTags::new().with(ENV_TAG, "lane-2").with(SET_TAG, "a");
+        let mut mi = MetaInfo::default();
+        mi.insert(RouteTags::new().with(ENV_TAG, "lane-1"));
+        assert_eq!(
+            decode_tags(encode_with(mi, Some(callee.clone()))),
+            Some(callee)
+        );
+
+        assert_eq!(decode_tags(encode_with(MetaInfo::default(), None)), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_tracker() {
+        use volo::memory::MemoryTracker;
+
+        use crate::{
+            DummyMessage,
+            codec::default::{framed::MakeFramedCodec, thrift::MakeThriftCodec},
+            context::ServerContext,
+        };
+
+        let tracker = MemoryTracker::new().request_limit(100);
+        let (_, mut decoder) = MakeTTHeaderCodec::new(MakeFramedCodec::new(MakeThriftCodec::new()))
+            .with_memory_tracker(tracker.clone())
+            .make_codec();
+
+        // rejected before the frame is read
+        let mut frame = 1000u32.to_be_bytes().to_vec();
+        frame.extend_from_slice(&[0x10, 0x00, 0x00, 0x00]);
+        let mut reader = BufReader::new(frame.as_slice());
+        let mut cx = ServerContext::default();
+        let result = decoder
+            .decode_async::<DummyMessage, _, _>(&mut cx, &mut reader)
+            .await;
+        assert!(matches!(result, Err(ThriftException::Protocol(_))));
+        assert_eq!(tracker.pending_stats().rejected, 1);
+        assert!(!cx.extensions.contains::<volo::memory::MemoryCharge>());
+    }
 }
@@ -0,0 +1,445 @@
+//! Byte-level test vectors of TTHeader for cross-language compatibility.
+//!
+//! Each [`TestVector`] is a frame in the layout written by a TTHeader implementation, e.g.,
+//! Kitex, with the header fields it is expected to be decoded to, or the error if it is
+//! malformed. [`Corpus::verify`] checks them against the strict parser and the decoder of volo,
+//! so changes of the codec which break other peers are caught by tests.
+//!
+//! The built-in vectors are in `volo-thrift/fixtures/ttheader`, and more vectors can be added by
+//! [`Corpus::push`] or [`Corpus::load_dir`].
+//!
+//! # Fixture format
+//!
+//! A fixture is a text file, lines starting with `#` are fields of the vector, and other lines
+//! are the frame in hex, including the 4-byte length:
+//!
+//! ```text
+//! # name: kitex-request-isn
+//! # peer: kitex
+//! # direction: request
+//! # seq_id: 3
+//! # protocol_id: 0
+//! # header: isn=EchoService
+//! # payload_len: 0
+//! 00 00 00 1a 10 00 00 00 00 00 00 03 00 04 00 00
+//! 01 00 01 00 03 69 73 6e 00 0b 45 63 68 6f 53 65
+//! ```
+//!
+//! The fields are:
+//!
+//! - `name`, `peer`: for reporting failures.
+//! - `direction`: `request` or `response`, for decoding by the server or client.
+//! - `seq_id`, `protocol_id`, `flags`, `payload_len`: expected fields, `flags` defaults to `0`.
+//! - `header`, `int_header`: expected key-values as `key=value` in the order of the frame, and
+//!   values can contain bytes as `\xNN`.
+//! - `valid`: `false` for malformed frames, and `error` is a part of the expected error message.
+//!
+//! # Example
+//!
+//! ```
+//! use volo_thrift::codec::default::ttheader::conformance::Corpus;
+//!
+//! let corpus = Corpus::builtin();
+//! if let Err(failures) = corpus.verify() {
+//!     panic!("{failures:#?}");
+//! }
+//! ```
+
+use std::{cell::RefCell, fmt, fs, io, path::Path};
+
+use bytes::{Buf, Bytes};
+use metainfo::MetaInfo;
+use pilota::thrift::TMessageType;
+use volo::{
+    context::{Role, RpcInfo},
+    net::Address,
+};
+
+use super::strict::{self, RawHeader};
+use crate::context::{ClientContext, ServerContext};
+
+const BUILTIN: &[(&str, &str)] = &[
+    (
+        "kitex_request.hex",
+        include_str!("../../../../fixtures/ttheader/kitex_request.hex"),
+    ),
+    (
+        "kitex_request_isn.hex",
+        include_str!("../../../../fixtures/ttheader/kitex_request_isn.hex"),
+    ),
+    (
+        "kitex_response.hex",
+        include_str!("../../../../fixtures/ttheader/kitex_response.hex"),
+    ),
+    (
+        "java_request_compact_acl.hex",
+        include_str!("../../../../fixtures/ttheader/java_request_compact_acl.hex"),
+    ),
+    (
+        "invalid_magic.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_magic.hex"),
+    ),
+    (
+        "invalid_header_size.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_header_size.hex"),
+    ),
+    (
+        "invalid_info_id.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_info_id.hex"),
+    ),
+    (
+        "invalid_transform.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_transform.hex"),
+    ),
+    (
+        "invalid_truncated_kv.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_truncated_kv.hex"),
+    ),
+    (
+        "invalid_utf8.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_utf8.hex"),
+    ),
+    (
+        "invalid_padding.hex",
+        include_str!("../../../../fixtures/ttheader/invalid_padding.hex"),
+    ),
+];
+
+/// Who decodes the frame of a [`TestVector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The frame is a request decoded by the server.
+    Request,
+    /// The frame is a response decoded by the client.
+    Response,
+}
+
+/// What a [`TestVector`] is expected to be decoded to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expectation {
+    /// The frame is valid and decoded to the header.
+    Valid(RawHeader),
+    /// The frame is malformed, and the error message contains the string.
+    Invalid(String),
+}
+
+/// A frame of TTHeader with its expected decoding result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    /// Name of the vector.
+    pub name: String,
+    /// The implementation whose layout the frame follows, e.g., `kitex`.
+    pub peer: String,
+    /// Who decodes the frame.
+    pub direction: Direction,
+    /// The frame including the 4-byte length.
+    pub frame: Bytes,
+    /// The expected decoding result.
+    pub expected: Expectation,
+}
+
+/// Error of parsing a fixture.
+#[derive(Debug)]
+pub struct FixtureError {
+    name: String,
+    message: String,
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fixture `{}`: {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+fn unescape(value: &str) -> Result<String, String> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'\\' && tail.first() == Some(&b'x') && tail.len() >= 3 {
+            let hex = std::str::from_utf8(&tail[1..3]).map_err(|e| e.to_string())?;
+            out.push(u8::from_str_radix(hex, 16).map_err(|e| format!("bad escape: {e}"))?);
+            rest = &tail[3..];
+        } else {
+            out.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(out).map_err(|e| e.to_string())
+}
+
+impl TestVector {
+    /// Parse a vector from a fixture, the `name` is used for errors and if the fixture does not
+    /// have a name.
+    pub fn parse(name: &str, fixture: &str) -> Result<Self, FixtureError> {
+        let err = |message: String| FixtureError {
+            name: name.to_owned(),
+            message,
+        };
+        let mut vector_name = None;
+        let mut peer = String::new();
+        let mut direction = Direction::Request;
+        let mut header = RawHeader::default();
+        let mut payload_len = None;
+        let mut valid = true;
+        let mut error = String::new();
+        let mut hex = String::new();
+
+        for line in fixture.lines().map(str::trim) {
+            let Some(field) = line.strip_prefix('#') else {
+                hex.push_str(line);
+                continue;
+            };
+            let Some((key, value)) = field.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let parse_int = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| err(format!("invalid `{}`: {e}", key.trim())))
+            };
+            match key.trim() {
+                "name" => vector_name = Some(value.to_owned()),
+                "peer" => peer = value.to_owned(),
+                "direction" => {
+                    direction = match value {
+                        "request" => Direction::Request,
+                        "response" => Direction::Response,
+                        _ => return Err(err(format!("unknown direction `{value}`"))),
+                    }
+                }
+                "seq_id" => header.seq_id = parse_int(value)?,
+                "protocol_id" => header.protocol_id = parse_int(value)? as u8,
+                "flags" => header.flags = parse_int(value)? as u16,
+                "payload_len" => payload_len = Some(parse_int(value)? as usize),
+                "header" | "int_header" => {
+                    let (k, v) = value
+                        .split_once('=')
+                        .ok_or_else(|| err(format!("invalid key-value `{value}`")))?;
+                    let v = unescape(v).map_err(err)?;
+                    if key.trim() == "header" {
+                        header.headers.push((unescape(k).map_err(err)?, v));
+                    } else {
+                        header.int_headers.push((parse_int(k)? as u16, v));
+                    }
+                }
+                "valid" => valid = value != "false",
+                "error" => error = value.to_owned(),
+                other => return Err(err(format!("unknown field `{other}`"))),
+            }
+        }
+
+        let hex = hex.split_whitespace().collect::<String>();
+        if hex.len() % 2 != 0 {
+            return Err(err("odd number of hex digits".to_owned()));
+        }
+        let frame = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| err(format!("invalid hex: {e}")))?;
+
+        let expected = if valid {
+            header.payload_len =
+                payload_len.ok_or_else(|| err("`payload_len` is required".to_owned()))?;
+            Expectation::Valid(header)
+        } else {
+            Expectation::Invalid(error)
+        };
+        Ok(Self {
+            name: vector_name.unwrap_or_else(|| name.to_owned()),
+            peer,
+            direction,
+            frame: Bytes::from(frame),
+            expected,
+        })
+    }
+
+    /// Verify the vector, and returns the reason if it fails.
+    pub fn verify(&self) -> Result<(), String> {
+        let parsed = strict::parse_frame(&self.frame);
+        match (&self.expected, parsed) {
+            (Expectation::Valid(expected), Ok(header)) => {
+                if *expected != header {
+                    return Err(format!("expected {expected:?}, but decoded {header:?}"));
+                }
+                self.decode()
+            }
+            (Expectation::Valid(_), Err(err)) => Err(format!("unexpected error: {err}")),
+            (Expectation::Invalid(expected), Ok(header)) => Err(format!(
+                "expected error containing `{expected}`, but decoded {header:?}"
+            )),
+            (Expectation::Invalid(expected), Err(err)) => {
+                if err.to_string().contains(expected.as_str()) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "expected error containing `{expected}`, but got: {err}"
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Decode the valid frame as the codec does.
+    fn decode(&self) -> Result<(), String> {
+        let mut src = self.frame.clone();
+        src.advance(4);
+        let result =
+            metainfo::METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+                match self.direction {
+                    Direction::Request => {
+                        let mut cx = ServerContext::default();
+                        super::decode(&mut cx, &mut src, false)
+                    }
+                    Direction::Response => {
+                        let mut rpc_info = RpcInfo::with_role(Role::Client);
+                        rpc_info.callee_mut().set_address(Address::from(
+                            std::net::SocketAddr::from(([127, 0, 0, 1], 8888)),
+                        ));
+                        let mut cx = ClientContext::new(0, rpc_info, TMessageType::Call);
+                        super::decode(&mut cx, &mut src, false)
+                    }
+                }
+            });
+        result.map_err(|err| format!("decoder error: {err}"))
+    }
+}
+
+/// A failed [`TestVector`] reported by [`Corpus::verify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// Name of the vector.
+    pub name: String,
+    /// The peer of the vector.
+    pub peer: String,
+    /// Why it fails.
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.peer, self.name, self.reason)
+    }
+}
+
+/// A set of [`TestVector`]s.
+#[derive(Clone, Debug, Default)]
+pub struct Corpus {
+    vectors: Vec<TestVector>,
+}
+
+impl Corpus {
+    /// Create an empty [`Corpus`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a [`Corpus`] with the built-in vectors.
+    pub fn builtin() -> Self {
+        let vectors = BUILTIN
+            .iter()
+            .map(|(name, fixture)| {
+                TestVector::parse(name, fixture).expect("built-in fixtures should be valid")
+            })
+            .collect();
+        Self { vectors }
+    }
+
+    /// Add a vector.
+    pub fn push(&mut self, vector: TestVector) -> &mut Self {
+        self.vectors.push(vector);
+        self
+    }
+
+    /// Add all vectors of fixtures with the extension `hex` in the directory.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<&mut Self> {
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "hex"));
+        paths.sort();
+        for path in paths {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let fixture = fs::read_to_string(&path)?;
+            let vector = TestVector::parse(&name, &fixture)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.vectors.push(vector);
+        }
+        Ok(self)
+    }
+
+    /// Returns all vectors.
+    pub fn vectors(&self) -> &[TestVector] {
+        &self.vectors
+    }
+
+    /// Verify all vectors, and returns the failures if any.
+    pub fn verify(&self) -> Result<(), Vec<Failure>> {
+        let failures = self
+            .vectors
+            .iter()
+            .filter_map(|vector| {
+                vector.verify().err().map(|reason| Failure {
+                    name: vector.name.clone(),
+                    peer: vector.peer.clone(),
+                    reason,
+                })
+            })
+            .collect::<Vec<_>>();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use pilota::thrift::ThriftException;
+
+    use super::{Corpus, Expectation, TestVector};
+    use crate::{
+        codec::default::{ZeroCopyDecoder, thrift::ThriftCodec, ttheader::TTHeaderDecoder},
+        context::ServerContext,
+    };
+
+    #[test]
+    fn test_builtin() {
+        let corpus = Corpus::builtin();
+        assert!(corpus.vectors().len() >= 10);
+        if let Err(failures) = corpus.verify() {
+            panic!("{failures:#?}");
+        }
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/ttheader");
+        let mut corpus = Corpus::new();
+        corpus.load_dir(dir).unwrap();
+        assert_eq!(corpus.vectors().len(), Corpus::builtin().vectors().len());
+    }
+
+    #[test]
+    fn test_strict_decoder() {
+        let fixture = include_str!("../../../../fixtures/ttheader/invalid_utf8.hex");
+        let mut frame = TestVector::parse("invalid_utf8", fixture).unwrap().frame;
+        let mut decoder = TTHeaderDecoder::new(ThriftCodec::default()).with_strict(true);
+        let mut cx = ServerContext::default();
+        let res = decoder.decode::<bytes::Bytes, _>(&mut cx, &mut frame);
+        assert!(matches!(res, Err(ThriftException::Protocol(_))));
+    }
+
+    #[test]
+    fn test_wrong_expectation() {
+        let fixture = include_str!("../../../../fixtures/ttheader/kitex_request_isn.hex")
+            .replace("isn=EchoService", "isn=OtherService");
+        let vector = TestVector::parse("wrong", &fixture).unwrap();
+        assert!(matches!(vector.expected, Expectation::Valid(_)));
+        assert!(vector.verify().unwrap_err().contains("OtherService"));
+    }
+}
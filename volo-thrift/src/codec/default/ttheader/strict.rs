@@ -0,0 +1,199 @@
+//! Strict validation of TTHeader framing for interop with other implementations.
+//!
+//! The default decoder trusts the peer, e.g., the lengths are not checked against the frame and
+//! strings are not checked as UTF-8. The strict mode parses the frame with all the checks before
+//! decoding, so malformed frames from peers are rejected with a [`ProtocolException`] rather than
+//! being misread.
+//!
+//! The rules are the ones followed by Kitex and other TTHeader implementations, and they are
+//! tested with the frames in [`conformance`](super::conformance).
+//!
+//! [`ProtocolException`]: pilota::thrift::ProtocolException
+
+use pilota::thrift::{ProtocolExceptionKind, ThriftException, new_protocol_exception};
+
+use super::{ProtocolId, TT_HEADER_MAGIC, info};
+
+/// Fields of a TTHeader parsed in strict mode.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawHeader {
+    /// Flags of the header.
+    pub flags: u16,
+    /// Sequence id of the message.
+    pub seq_id: u32,
+    /// Protocol id of the payload, see [`ProtocolId`].
+    pub protocol_id: u8,
+    /// String key-values in the order of the frame.
+    pub headers: Vec<(String, String)>,
+    /// Int key-values in the order of the frame.
+    pub int_headers: Vec<(u16, String)>,
+    /// Length of the payload following the header.
+    pub payload_len: usize,
+}
+
+fn invalid(msg: impl Into<String>) -> ThriftException {
+    new_protocol_exception(
+        ProtocolExceptionKind::InvalidData,
+        format!("strict ttheader: {}", msg.into()),
+    )
+}
+
+/// Cursor of the header, all reads are checked against the remaining bytes.
+struct Cursor<'a> {
+    buf: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ThriftException> {
+        if self.buf.len() < n {
+            return Err(invalid(format!(
+                "{} is truncated, {n} bytes needed but {} remaining",
+                self.what,
+                self.buf.len()
+            )));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ThriftException> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ThriftException> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ThriftException> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, ThriftException> {
+        let len = self.u16()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|err| invalid(format!("string in {} is not utf-8: {err}", self.what)))
+    }
+}
+
+/// Parse a frame with the 4-byte length in strict mode.
+pub fn parse_frame(frame: &[u8]) -> Result<RawHeader, ThriftException> {
+    let mut cursor = Cursor {
+        buf: frame,
+        what: "frame",
+    };
+    let size = cursor.u32()?;
+    parse(size, cursor.buf)
+}
+
+/// Parse the frame following the 4-byte length in strict mode, and the `size` is the value of
+/// the length.
+///
+/// Bytes after `size` are not checked, since they belong to the next frame.
+pub fn parse(size: u32, buf: &[u8]) -> Result<RawHeader, ThriftException> {
+    let size = size as usize;
+    if buf.len() < size {
+        return Err(invalid(format!(
+            "frame length is {size} but only {} bytes are received",
+            buf.len()
+        )));
+    }
+    let mut frame = Cursor {
+        buf: &buf[..size],
+        what: "fixed header",
+    };
+
+    let magic = frame.u16()?;
+    if magic != TT_HEADER_MAGIC {
+        return Err(invalid(format!("bad magic {magic:#06x}")));
+    }
+    let flags = frame.u16()?;
+    let seq_id = frame.u32()?;
+    let header_size = frame.u16()? as usize * 4;
+    let header = frame.take(header_size).map_err(|_| {
+        invalid(format!(
+            "header size {header_size} overflows the frame length {size}"
+        ))
+    })?;
+    let payload_len = frame.buf.len();
+
+    let mut header = Cursor {
+        buf: header,
+        what: "header",
+    };
+    let protocol_id = header.u8()?;
+    if ProtocolId::try_from(protocol_id).is_err() {
+        return Err(new_protocol_exception(
+            ProtocolExceptionKind::BadVersion,
+            format!("strict ttheader: unknown protocol id {protocol_id}"),
+        ));
+    }
+    let transforms = header.u8()?;
+    if transforms > 0 {
+        // the payload can not be decoded without applying them
+        return Err(invalid(format!(
+            "{transforms} transforms are not supported"
+        )));
+    }
+
+    let mut raw = RawHeader {
+        flags,
+        seq_id,
+        protocol_id,
+        payload_len,
+        ..Default::default()
+    };
+    while !header.buf.is_empty() {
+        match header.u8()? {
+            info::INFO_PADDING => {
+                // padding is only allowed at the end
+                if let Some(pos) = header.buf.iter().position(|b| *b != info::INFO_PADDING) {
+                    return Err(invalid(format!(
+                        "non-zero byte {:#04x} after padding",
+                        header.buf[pos]
+                    )));
+                }
+                break;
+            }
+            info::INFO_KEY_VALUE => {
+                header.what = "string key-values";
+                for _ in 0..header.u16()? {
+                    let key = header.string()?;
+                    let value = header.string()?;
+                    raw.headers.push((key, value));
+                }
+            }
+            info::INFO_INT_KEY_VALUE => {
+                header.what = "int key-values";
+                for _ in 0..header.u16()? {
+                    let key = header.u16()?;
+                    let value = header.string()?;
+                    raw.int_headers.push((key, value));
+                }
+            }
+            info::ACL_TOKEN_KEY_VALUE => {
+                header.what = "acl token";
+                header.string()?;
+            }
+            info_id => return Err(invalid(format!("unexpected info id {info_id:#04x}"))),
+        }
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod strict_tests {
+    use super::parse_frame;
+
+    #[test]
+    fn test_truncated() {
+        // the frame length is larger than the frame
+        let frame = [0, 0, 0, 20, 0x10, 0, 0, 0];
+        assert!(parse_frame(&frame).is_err());
+        assert!(parse_frame(&[0, 0]).is_err());
+    }
+}
@@ -0,0 +1,457 @@
+//! Passthrough of unknown fields of structs.
+//!
+//! Fields added by newer versions of the IDL are skipped when decoding into the structs generated
+//! by older versions, so intermediary services such as proxies drop them silently when
+//! forwarding the messages.
+//!
+//! With [`MakeThriftCodec::with_passthrough`] enabled, fields of a decoded message that are not
+//! recognized by the generated structs are captured and stored in [`metainfo`]:
+//!
+//! - fields of a request received by the server are read by [`UnknownFields::upstream`], and will
+//!   be written into the request of the same method sent by clients.
+//! - fields of a response received by a client are read by [`UnknownFields::downstream`], and will
+//!   be written into the response of the same method sent by the server.
+//!
+//! Fields of nested structs are captured as well, except structs in lists, sets and maps.
+//!
+//! Note that this only works with the binary protocol and framed transports (`Framed` or
+//! `TTHeader`), and it costs an extra encoding of each decoded message for detecting the unknown
+//! fields. Structs generated with `keep_unknown_fields` in the codegen config preserve their
+//! unknown fields by themselves, so they are never captured twice.
+//!
+//! [`MakeThriftCodec::with_passthrough`]: super::thrift::MakeThriftCodec::with_passthrough
+
+use bytes::{BufMut, Bytes, BytesMut};
+use pilota::thrift::{ProtocolExceptionKind, ThriftException, new_protocol_exception};
+use volo::{FastStr, context::Role};
+
+// thrift types of the binary protocol
+const STOP: u8 = 0;
+const BOOL: u8 = 2;
+const I8: u8 = 3;
+const DOUBLE: u8 = 4;
+const I16: u8 = 6;
+const I32: u8 = 8;
+const I64: u8 = 10;
+const BINARY: u8 = 11;
+const STRUCT: u8 = 12;
+const MAP: u8 = 13;
+const SET: u8 = 14;
+const LIST: u8 = 15;
+const UUID: u8 = 16;
+
+/// Max depth of nested values, deeper messages are rejected.
+const MAX_DEPTH: usize = 64;
+
+/// Fields of a message that are not recognized by the generated structs.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnknownFields {
+    method: FastStr,
+    fields: Vec<UnknownField>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct UnknownField {
+    // ids of the fields from the outermost struct to the struct containing the field
+    path: Vec<i16>,
+    // the whole encoded field, including the type and the id
+    bytes: Bytes,
+}
+
+struct UpstreamUnknownFields(UnknownFields);
+
+struct DownstreamUnknownFields(UnknownFields);
+
+impl UnknownFields {
+    /// Get the unknown fields of the request received by the server in the current task.
+    pub fn upstream() -> Option<Self> {
+        metainfo::METAINFO
+            .try_with(|mi| {
+                mi.borrow()
+                    .get::<UpstreamUnknownFields>()
+                    .map(|f| f.0.clone())
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Get the unknown fields of the response received by the client in the current task.
+    pub fn downstream() -> Option<Self> {
+        metainfo::METAINFO
+            .try_with(|mi| {
+                mi.borrow()
+                    .get::<DownstreamUnknownFields>()
+                    .map(|f| f.0.clone())
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Method of the message containing the fields.
+    pub fn method(&self) -> &FastStr {
+        &self.method
+    }
+
+    /// Number of the unknown fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether there is no unknown field.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Detect the unknown fields by comparing the `original` message with the message
+    /// `encoded` again from the decoded structs.
+    pub(crate) fn capture(
+        method: FastStr,
+        original: &[u8],
+        encoded: &[u8],
+    ) -> Result<Self, ThriftException> {
+        let mut orig = Reader::new(original);
+        orig.skip_message_begin()?;
+        let mut enc = Reader::new(encoded);
+        enc.skip_message_begin()?;
+
+        let mut fields = Vec::new();
+        diff(
+            original,
+            orig.pos,
+            encoded,
+            enc.pos,
+            &mut Vec::new(),
+            &mut fields,
+        )?;
+        Ok(Self { method, fields })
+    }
+
+    /// Store the fields decoded from a message of `role` in the current task.
+    pub(crate) fn store(self, role: Role) {
+        let _ = metainfo::METAINFO.try_with(|mi| {
+            let mut mi = mi.borrow_mut();
+            match role {
+                Role::Server => mi.insert(UpstreamUnknownFields(self)),
+                Role::Client => mi.insert(DownstreamUnknownFields(self)),
+            }
+        });
+    }
+
+    /// The fields should be re-emitted when encoding a message of `method` and `role`.
+    pub(crate) fn to_forward(method: &str, role: Role) -> Option<Self> {
+        let fields = match role {
+            Role::Client => Self::upstream(),
+            Role::Server => Self::downstream(),
+        }?;
+        (!fields.is_empty() && fields.method == method).then_some(fields)
+    }
+
+    /// Write the `encoded` message into `dst` with the unknown fields inserted at the end of
+    /// their structs, fields whose structs are missing in the message are dropped.
+    pub(crate) fn emit(&self, encoded: &[u8], dst: &mut BytesMut) -> Result<(), ThriftException> {
+        let mut r = Reader::new(encoded);
+        r.skip_message_begin()?;
+
+        let mut inserts = Vec::with_capacity(self.fields.len());
+        for field in self.fields.iter() {
+            if let Some(stop) = find_stop(encoded, r.pos, &field.path)? {
+                inserts.push((stop, &field.bytes));
+            }
+        }
+        // the sort is stable, so fields of the same struct keep their order
+        inserts.sort_by_key(|(offset, _)| *offset);
+
+        dst.reserve(encoded.len() + inserts.iter().map(|(_, b)| b.len()).sum::<usize>());
+        let mut last = 0;
+        for (offset, bytes) in inserts {
+            dst.put_slice(&encoded[last..offset]);
+            dst.put_slice(bytes);
+            last = offset;
+        }
+        dst.put_slice(&encoded[last..]);
+        Ok(())
+    }
+}
+
+struct Field {
+    id: i16,
+    ttype: u8,
+    // offsets of the field header, the value and the end of the field
+    start: usize,
+    value: usize,
+    end: usize,
+}
+
+/// Read the fields of the struct starting at `start`, returns the fields and the offset of STOP.
+fn read_fields(buf: &[u8], start: usize) -> Result<(Vec<Field>, usize), ThriftException> {
+    let mut r = Reader { buf, pos: start };
+    let mut fields = Vec::new();
+    loop {
+        let field_start = r.pos;
+        let ttype = r.u8()?;
+        if ttype == STOP {
+            return Ok((fields, field_start));
+        }
+        let id = r.i16()?;
+        let value = r.pos;
+        r.skip(ttype, 0)?;
+        fields.push(Field {
+            id,
+            ttype,
+            start: field_start,
+            value,
+            end: r.pos,
+        });
+    }
+}
+
+fn diff(
+    original: &[u8],
+    orig_start: usize,
+    encoded: &[u8],
+    enc_start: usize,
+    path: &mut Vec<i16>,
+    out: &mut Vec<UnknownField>,
+) -> Result<(), ThriftException> {
+    let (orig_fields, _) = read_fields(original, orig_start)?;
+    let (enc_fields, _) = read_fields(encoded, enc_start)?;
+    for field in orig_fields {
+        match enc_fields
+            .iter()
+            .find(|f| f.id == field.id && f.ttype == field.ttype)
+        {
+            None => out.push(UnknownField {
+                path: path.clone(),
+                // copy the field, so that the buffer of the whole frame can be reused
+                bytes: Bytes::copy_from_slice(&original[field.start..field.end]),
+            }),
+            Some(f) if field.ttype == STRUCT && path.len() < MAX_DEPTH => {
+                path.push(field.id);
+                diff(original, field.value, encoded, f.value, path, out)?;
+                path.pop();
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Find the offset of STOP of the struct at `path` in the struct starting at `start`.
+fn find_stop(buf: &[u8], start: usize, path: &[i16]) -> Result<Option<usize>, ThriftException> {
+    let (fields, stop) = read_fields(buf, start)?;
+    let Some((id, rest)) = path.split_first() else {
+        return Ok(Some(stop));
+    };
+    match fields.iter().find(|f| f.id == *id && f.ttype == STRUCT) {
+        Some(f) => find_stop(buf, f.value, rest),
+        None => Ok(None),
+    }
+}
+
+fn invalid_data(msg: &'static str) -> ThriftException {
+    new_protocol_exception(ProtocolExceptionKind::InvalidData, msg)
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn advance(&mut self, n: usize) -> Result<&'a [u8], ThriftException> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| {
+                invalid_data("unexpected end of message when detecting unknown fields")
+            })?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ThriftException> {
+        Ok(self.advance(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, ThriftException> {
+        let b = self.advance(2)?;
+        Ok(i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, ThriftException> {
+        let b = self.advance(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn len(&mut self) -> Result<usize, ThriftException> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative length of thrift value"))
+    }
+
+    /// Skip the message header of both strict and non-strict binary protocol.
+    fn skip_message_begin(&mut self) -> Result<(), ThriftException> {
+        let first = self.i32()?;
+        if first < 0 {
+            // version and type, name, seq id
+            let len = self.len()?;
+            self.advance(len)?;
+            self.advance(4)?;
+        } else {
+            // name, type, seq id
+            self.advance(first as usize)?;
+            self.advance(5)?;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, ttype: u8, depth: usize) -> Result<(), ThriftException> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("thrift value is nested too deeply"));
+        }
+        match ttype {
+            BOOL | I8 => {
+                self.advance(1)?;
+            }
+            I16 => {
+                self.advance(2)?;
+            }
+            I32 => {
+                self.advance(4)?;
+            }
+            DOUBLE | I64 => {
+                self.advance(8)?;
+            }
+            UUID => {
+                self.advance(16)?;
+            }
+            BINARY => {
+                let len = self.len()?;
+                self.advance(len)?;
+            }
+            STRUCT => loop {
+                let ttype = self.u8()?;
+                if ttype == STOP {
+                    break;
+                }
+                self.advance(2)?;
+                self.skip(ttype, depth + 1)?;
+            },
+            MAP => {
+                let ktype = self.u8()?;
+                let vtype = self.u8()?;
+                for _ in 0..self.len()? {
+                    self.skip(ktype, depth + 1)?;
+                    self.skip(vtype, depth + 1)?;
+                }
+            }
+            SET | LIST => {
+                let etype = self.u8()?;
+                for _ in 0..self.len()? {
+                    self.skip(etype, depth + 1)?;
+                }
+            }
+            _ => return Err(invalid_data("unknown thrift type")),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(body: &[u8]) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        // strict binary protocol, call
+        buf.put_i32(0x8001_0000u32 as i32 | 1);
+        buf.put_i32(4);
+        buf.put_slice(b"test");
+        buf.put_i32(1);
+        buf.put_slice(body);
+        buf.to_vec()
+    }
+
+    fn field(ttype: u8, id: i16, value: &[u8]) -> Vec<u8> {
+        let mut buf = vec![ttype];
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(value);
+        buf
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut buf = (s.len() as i32).to_be_bytes().to_vec();
+        buf.extend_from_slice(s.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn capture_and_emit() {
+        let known = field(I32, 1, &7i32.to_be_bytes());
+        let new_inner = field(BINARY, 9, &string("new"));
+        let new_outer = field(I64, 7, &42i64.to_be_bytes());
+
+        // args { 1: i32, 2: { 1: i32, 9: string }, 7: i64 }
+        let inner = [known.clone(), new_inner.clone(), vec![STOP]].concat();
+        let original = message(
+            &[
+                known.clone(),
+                field(STRUCT, 2, &inner),
+                new_outer.clone(),
+                vec![STOP],
+            ]
+            .concat(),
+        );
+        // args { 1: i32, 2: { 1: i32 } } known by the older IDL
+        let inner = [known.clone(), vec![STOP]].concat();
+        let encoded = message(&[known.clone(), field(STRUCT, 2, &inner), vec![STOP]].concat());
+
+        let fields = UnknownFields::capture("test".into(), &original, &encoded).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields.fields[0].path, [2]);
+        assert_eq!(fields.fields[0].bytes[..], new_inner[..]);
+        assert!(fields.fields[1].path.is_empty());
+        assert_eq!(fields.fields[1].bytes[..], new_outer[..]);
+
+        let mut dst = BytesMut::new();
+        fields.emit(&encoded, &mut dst).unwrap();
+        assert_eq!(dst[..], original[..]);
+
+        // the nested struct is unset in the forwarded message
+        let forwarded = message(&[known.clone(), vec![STOP]].concat());
+        let mut dst = BytesMut::new();
+        fields.emit(&forwarded, &mut dst).unwrap();
+        assert_eq!(
+            dst[..],
+            message(&[known, new_outer, vec![STOP]].concat())[..]
+        );
+    }
+
+    #[test]
+    fn nothing_unknown() {
+        let body = [
+            field(
+                LIST,
+                1,
+                &[[I32].as_slice(), &1i32.to_be_bytes(), &3i32.to_be_bytes()].concat(),
+            ),
+            vec![STOP],
+        ]
+        .concat();
+        let msg = message(&body);
+        let fields = UnknownFields::capture("test".into(), &msg, &msg).unwrap();
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let msg = message(&field(BINARY, 1, &string("truncated")));
+        assert!(UnknownFields::capture("test".into(), &msg[..msg.len() - 2], &msg).is_err());
+    }
+}
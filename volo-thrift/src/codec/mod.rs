@@ -40,6 +40,23 @@ pub trait Encoder: Send + Sync + 'static {
         msg: ThriftMessage<Req>,
     ) -> impl Future<Output = Result<(), ThriftException>> + Send;
 
+    /// Encode a [`ThriftMessage`] without writing it, buffered messages are written together by
+    /// [`Encoder::flush`], which saves syscalls for small messages.
+    ///
+    /// The default implementation writes the message immediately as [`Encoder::encode`].
+    fn encode_buffered<Req: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        msg: ThriftMessage<Req>,
+    ) -> impl Future<Output = Result<(), ThriftException>> + Send {
+        self.encode(cx, msg)
+    }
+
+    /// Write and flush all the messages buffered by [`Encoder::encode_buffered`].
+    fn flush(&mut self) -> impl Future<Output = Result<(), ThriftException>> + Send {
+        async { Ok(()) }
+    }
+
     fn is_closed(&self) -> impl Future<Output = bool> + Send {
         async { false }
     }
@@ -1,36 +1,40 @@
 use std::time::Duration;
 
-use chrono::{DateTime, Local};
 use paste::paste;
 use pilota::thrift::TMessageIdentifier;
 use volo::{
     FastStr,
     context::{Context, Reusable, Role, RpcCx, RpcInfo},
     newtype_impl_context,
+    timeout::TimeoutConfig,
+    util::{
+        time::Timestamp,
+        timing::{ConnectTimestamps, Phase, Timing},
+    },
 };
 
-use crate::{BizError, client::CallOpt, protocol::TMessageType};
+use crate::{BizError, client::CallOpt, protocol::TMessageType, transport::pool::Acquisition};
 
 macro_rules! stat_impl {
     ($t: ident) => {
         paste! {
             /// This is unstable now and may be changed in the future.
             #[inline]
-            pub fn $t(&self) -> Option<DateTime<Local>> {
+            pub fn $t(&self) -> Option<Timestamp> {
                 self.$t
             }
 
             /// This is unstable now and may be changed in the future.
             #[doc(hidden)]
             #[inline]
-            pub fn [<set_$t>](&mut self, t: DateTime<Local>) {
+            pub fn [<set_$t>](&mut self, t: Timestamp) {
                 self.$t = Some(t)
             }
 
             /// This is unstable now and may be changed in the future.
             #[inline]
             pub fn [<record_ $t>](&mut self) {
-                self.$t = Some(Local::now())
+                self.$t = Some(Timestamp::now())
             }
         }
     };
@@ -62,15 +66,15 @@ impl ServerTransportInfo {
 #[derive(Debug, Default, Clone)]
 pub struct CommonStats {
     // if there's a length-prefixed transport, we can get the read time
-    read_start_at: Option<DateTime<Local>>,
-    read_end_at: Option<DateTime<Local>>,
+    read_start_at: Option<Timestamp>,
+    read_end_at: Option<Timestamp>,
 
-    decode_start_at: Option<DateTime<Local>>,
-    decode_end_at: Option<DateTime<Local>>,
-    encode_start_at: Option<DateTime<Local>>,
-    encode_end_at: Option<DateTime<Local>>,
-    write_start_at: Option<DateTime<Local>>,
-    write_end_at: Option<DateTime<Local>>,
+    decode_start_at: Option<Timestamp>,
+    decode_end_at: Option<Timestamp>,
+    encode_start_at: Option<Timestamp>,
+    encode_end_at: Option<Timestamp>,
+    write_start_at: Option<Timestamp>,
+    write_end_at: Option<Timestamp>,
 
     // size
     read_size: Option<usize>, /* only applicable to length-prefixed transport such as TTHeader
@@ -132,16 +136,30 @@ impl CommonStats {
 /// This is unstable now and may be changed in the future.
 #[derive(Debug, Default, Clone)]
 pub struct ServerStats {
-    process_start_at: Option<DateTime<Local>>,
-    process_end_at: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    process_start_at: Option<Timestamp>,
+    process_end_at: Option<Timestamp>,
 }
 
 impl ServerStats {
     stat_impl!(process_start_at);
     stat_impl!(process_end_at);
 
+    /// The request id or trace id of the request, which is set by the users or middlewares.
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
+
     #[inline]
     pub fn reset(&mut self) {
+        self.trace_id = None;
         self.process_start_at = None;
         self.process_end_at = None;
     }
@@ -150,18 +168,117 @@ impl ServerStats {
 /// This is unstable now and may be changed in the future.
 #[derive(Debug, Default, Clone)]
 pub struct ClientStats {
-    make_transport_start_at: Option<DateTime<Local>>,
-    make_transport_end_at: Option<DateTime<Local>>,
+    trace_id: Option<FastStr>,
+
+    call_start_at: Option<Timestamp>,
+    call_end_at: Option<Timestamp>,
+    make_transport_start_at: Option<Timestamp>,
+    make_transport_end_at: Option<Timestamp>,
+
+    // connecting, see `volo::util::timing`
+    resolve_start_at: Option<Timestamp>,
+    resolve_end_at: Option<Timestamp>,
+    connect_start_at: Option<Timestamp>,
+    connect_end_at: Option<Timestamp>,
+    tls_handshake_start_at: Option<Timestamp>,
+    tls_handshake_end_at: Option<Timestamp>,
+
+    // connection pool
+    pool_wait: Option<Duration>,
+    connect_cost: Option<Duration>,
+    conn_reused: Option<bool>,
 }
 
 impl ClientStats {
+    stat_impl!(call_start_at);
+    stat_impl!(call_end_at);
     stat_impl!(make_transport_start_at);
     stat_impl!(make_transport_end_at);
+    stat_impl!(resolve_start_at);
+    stat_impl!(resolve_end_at);
+    stat_impl!(connect_start_at);
+    stat_impl!(connect_end_at);
+    stat_impl!(tls_handshake_start_at);
+    stat_impl!(tls_handshake_end_at);
+
+    /// The request id or trace id of the call, which is set by the users or middlewares.
+    #[inline]
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    #[inline]
+    pub fn set_trace_id(&mut self, trace_id: FastStr) {
+        self.trace_id = Some(trace_id)
+    }
+
+    /// Time spent waiting for a connection from the pool, the time establishing a new connection
+    /// is excluded.
+    #[inline]
+    pub fn pool_wait(&self) -> Option<Duration> {
+        self.pool_wait
+    }
+
+    #[inline]
+    pub fn set_pool_wait(&mut self, wait: Duration) {
+        self.pool_wait = Some(wait)
+    }
+
+    /// Time spent establishing a new connection, [`None`] if the connection is reused.
+    #[inline]
+    pub fn connect_cost(&self) -> Option<Duration> {
+        self.connect_cost
+    }
+
+    #[inline]
+    pub fn set_connect_cost(&mut self, cost: Duration) {
+        self.connect_cost = Some(cost)
+    }
+
+    /// Whether the call used a connection reused from the pool or a fresh one.
+    #[inline]
+    pub fn conn_reused(&self) -> Option<bool> {
+        self.conn_reused
+    }
+
+    #[inline]
+    pub fn set_conn_reused(&mut self, reused: bool) {
+        self.conn_reused = Some(reused)
+    }
+
+    #[inline]
+    pub(crate) fn record_acquisition(&mut self, acquisition: Acquisition) {
+        self.pool_wait = Some(acquisition.wait);
+        self.connect_cost = acquisition.connect;
+        self.conn_reused = Some(acquisition.reused);
+    }
+
+    #[inline]
+    pub(crate) fn record_connect(&mut self, timestamps: ConnectTimestamps) {
+        self.resolve_start_at = timestamps.resolve_start_at;
+        self.resolve_end_at = timestamps.resolve_end_at;
+        self.connect_start_at = timestamps.connect_start_at;
+        self.connect_end_at = timestamps.connect_end_at;
+        self.tls_handshake_start_at = timestamps.tls_handshake_start_at;
+        self.tls_handshake_end_at = timestamps.tls_handshake_end_at;
+    }
 
     #[inline]
     pub fn reset(&mut self) {
+        self.trace_id = None;
+        self.call_start_at = None;
+        self.call_end_at = None;
         self.make_transport_start_at = None;
         self.make_transport_end_at = None;
+        self.resolve_start_at = None;
+        self.resolve_end_at = None;
+        self.connect_start_at = None;
+        self.connect_end_at = None;
+        self.tls_handshake_start_at = None;
+        self.tls_handshake_end_at = None;
+        self.pool_wait = None;
+        self.connect_cost = None;
+        self.conn_reused = None;
     }
 }
 
@@ -239,6 +356,32 @@ impl ClientContext {
         // self.0 is RpcCx, this reset will clear rpcinfo and extension
         self.0.reset(self.0.inner.clone());
     }
+
+    /// Returns the timing breakdown of the call after it returns from the client, see
+    /// [`volo::util::timing`].
+    ///
+    /// The [`Phase::FirstByte`] is the time from the request is written to the first byte of the
+    /// response is read, and [`Phase::Decode`] overlaps with [`Phase::Read`] since the response is
+    /// decoded while reading.
+    pub fn timing(&self) -> Timing {
+        let stats = &self.stats;
+        let common = &self.common_stats;
+        let mut timing = Timing::new(stats.call_start_at, stats.call_end_at)
+            .with_trace_id(stats.trace_id.clone());
+        timing.push(Phase::Resolve, stats.resolve_start_at, stats.resolve_end_at);
+        timing.push(Phase::Connect, stats.connect_start_at, stats.connect_end_at);
+        timing.push(
+            Phase::TlsHandshake,
+            stats.tls_handshake_start_at,
+            stats.tls_handshake_end_at,
+        );
+        timing.push(Phase::Encode, common.encode_start_at, common.encode_end_at);
+        timing.push(Phase::Write, common.write_start_at, common.write_end_at);
+        timing.push(Phase::FirstByte, common.write_end_at, common.read_start_at);
+        timing.push(Phase::Read, common.read_start_at, common.read_end_at);
+        timing.push(Phase::Decode, common.decode_start_at, common.decode_end_at);
+        timing
+    }
 }
 
 impl std::ops::Deref for ClientContext {
@@ -421,6 +564,7 @@ const DEFAULT_READ_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Config {
     rpc_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
     connect_timeout: Option<Duration>,
     read_write_timeout: Option<Duration>,
 }
@@ -430,6 +574,7 @@ impl Config {
     pub fn new() -> Self {
         Self {
             rpc_timeout: None,
+            request_timeout: None,
             connect_timeout: None,
             read_write_timeout: None,
         }
@@ -440,7 +585,7 @@ impl Config {
         self.rpc_timeout
     }
 
-    /// Sets the rpc timeout.
+    /// Sets the rpc timeout, i.e., the total timeout of a call including retries.
     ///
     /// This can be set both by the client builder and the CallOpt.
     #[inline]
@@ -453,6 +598,19 @@ impl Config {
         self.rpc_timeout.unwrap_or(DEFAULT_RPC_TIMEOUT)
     }
 
+    #[inline]
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    /// Sets the request timeout, i.e., the timeout of each attempt of a call.
+    ///
+    /// This can be set both by the client builder and the CallOpt.
+    #[inline]
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.request_timeout = timeout;
+    }
+
     #[inline]
     pub fn connect_timeout(&self) -> Option<Duration> {
         self.connect_timeout
@@ -491,6 +649,9 @@ impl Config {
         if let Some(t) = other.rpc_timeout {
             self.rpc_timeout = Some(t);
         }
+        if let Some(t) = other.request_timeout {
+            self.request_timeout = Some(t);
+        }
         if let Some(t) = other.connect_timeout {
             self.connect_timeout = Some(t);
         }
@@ -500,9 +661,22 @@ impl Config {
     }
 }
 
+impl TimeoutConfig for Config {
+    #[inline]
+    fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+    }
+
+    #[inline]
+    fn total_timeout(&self) -> Option<Duration> {
+        self.rpc_timeout
+    }
+}
+
 impl Reusable for Config {
     fn clear(&mut self) {
         self.rpc_timeout = None;
+        self.request_timeout = None;
         self.connect_timeout = None;
         self.read_write_timeout = None;
     }
@@ -5,7 +5,33 @@ pub use pilota::thrift::{
     ThriftException, TransportException, new_application_exception, new_protocol_exception,
 };
 use pilota::{AHashMap, FastStr};
-use volo::loadbalance::error::{LoadBalanceError, Retryable};
+use volo::{
+    error::{ConnectError, ErrorCategory, ErrorOrigin, PeerError, RpcError},
+    loadbalance::error::{LoadBalanceError, Retryable},
+    net::Address,
+    timeout::TimeoutError,
+};
+
+/// The prefix of the messages of the application exceptions produced by volo itself rather than
+/// the business logic, e.g., by the load balancer, the rpc timeout, the transports and the overload
+/// control and peer quota of servers, which tells them apart, see [`volo_exception`].
+const VOLO_EXCEPTION_PREFIX: &str = "[VOLO] ";
+
+/// The message of the exception of the rpc timeout after the [`VOLO_EXCEPTION_PREFIX`].
+pub(crate) const RPC_TIMEOUT_MSG: &str = "thrift rpc call timeout";
+
+/// Create an [`ApplicationException`] produced by volo itself, whose origin is taken as
+/// [`ErrorOrigin::Local`] by the clients.
+pub(crate) fn volo_exception(
+    kind: ApplicationExceptionKind,
+    msg: impl Display,
+) -> ApplicationException {
+    ApplicationException::new(kind, format!("{VOLO_EXCEPTION_PREFIX}{msg}"))
+}
+
+fn is_volo_exception(e: &ApplicationException) -> bool {
+    e.message().starts_with(VOLO_EXCEPTION_PREFIX)
+}
 
 pub type ServerResult<T> = Result<T, ServerError>;
 pub type ClientResult<T> = Result<T, ClientError>;
@@ -108,23 +134,115 @@ impl ClientError {
             ClientError::Biz(e) => e.append_msg(msg),
         }
     }
+
+    /// Returns the [`TimeoutError`] telling which timeout fired if the call is timeout.
+    ///
+    /// Note that it's lost after the error is cloned.
+    pub fn timeout_error(&self) -> Option<&TimeoutError> {
+        match self {
+            ClientError::Transport(e) => TimeoutError::find(e.io_error()),
+            _ => None,
+        }
+    }
+
+    /// Record the address of the peer in the transport error of a call, see [`PeerError`].
+    pub(crate) fn with_peer_addr(self, addr: Option<&Address>) -> Self {
+        match (self, addr) {
+            (ClientError::Transport(e), Some(addr))
+                if ConnectError::find(e.io_error()).is_none()
+                    && PeerError::find(e.io_error()).is_none() =>
+            {
+                // the io error cannot be taken out, so only the `TimeoutError` inside is kept
+                let source = match TimeoutError::find(e.io_error()) {
+                    Some(timeout) => timeout.into_io_error(),
+                    None => io::Error::new(e.kind(), e.message().to_string()),
+                };
+                ClientError::Transport(TransportException::from(
+                    PeerError::new(addr.clone(), source).into_io_error(),
+                ))
+            }
+            (e, _) => e,
+        }
+    }
 }
 
 impl Retryable for ClientError {
     fn retryable(&self) -> bool {
         if let Self::Transport(_) = self {
-            return true;
+            return self.timeout_error().is_none_or(Retryable::retryable);
         }
         false
     }
 }
 
+/// The application exceptions and biz errors are taken as replied by the server, except the
+/// application exceptions produced by volo itself, which are tagged by the prefix `[VOLO] ` of
+/// their messages, e.g., no available instances of the load balancer, the rpc timeout, and the
+/// requests shed by the overload control or rejected by the peer quota of volo servers.
+impl RpcError for ClientError {
+    fn category(&self) -> ErrorCategory {
+        if self.timeout_error().is_some() {
+            return ErrorCategory::Timeout;
+        }
+        match self {
+            ClientError::Application(e) => match e.kind() {
+                ApplicationExceptionKind::UNKNOWN_METHOD
+                | ApplicationExceptionKind::INVALID_MESSAGE_TYPE
+                | ApplicationExceptionKind::WRONG_METHOD_NAME
+                | ApplicationExceptionKind::BAD_SEQUENCE_ID
+                | ApplicationExceptionKind::MISSING_RESULT
+                | ApplicationExceptionKind::PROTOCOL_ERROR
+                | ApplicationExceptionKind::INVALID_TRANSFORM
+                | ApplicationExceptionKind::INVALID_PROTOCOL
+                | ApplicationExceptionKind::UNSUPPORTED_CLIENT_TYPE => ErrorCategory::Protocol,
+                _ if !is_volo_exception(e) => ErrorCategory::Biz,
+                _ if e.message()[VOLO_EXCEPTION_PREFIX.len()..].starts_with(RPC_TIMEOUT_MSG) => {
+                    ErrorCategory::Timeout
+                }
+                _ => ErrorCategory::Other,
+            },
+            ClientError::Transport(e) => match ConnectError::find(e.io_error()) {
+                Some(_) => ErrorCategory::Connect,
+                None => ErrorCategory::Transport,
+            },
+            ClientError::Protocol(_) => ErrorCategory::Protocol,
+            ClientError::Biz(_) => ErrorCategory::Biz,
+        }
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        match self {
+            ClientError::Application(e) if !is_volo_exception(e) => ErrorOrigin::Remote,
+            ClientError::Biz(_) => ErrorOrigin::Remote,
+            _ => ErrorOrigin::Local,
+        }
+    }
+
+    /// The address is only recorded in transport errors, i.e., failing to connect or the errors of
+    /// calls after connected, which is lost after the error is cloned.
+    fn peer_addr(&self) -> Option<Address> {
+        match self {
+            ClientError::Transport(e) => match ConnectError::find(e.io_error()) {
+                Some(err) => Some(err.addr().clone()),
+                None => PeerError::find(e.io_error()).map(|e| e.addr().clone()),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<TimeoutError> for ClientError {
+    fn from(err: TimeoutError) -> Self {
+        ClientError::Transport(TransportException::from(err.into_io_error()))
+    }
+}
+
 impl From<LoadBalanceError> for ClientError {
     // TODO: use specified error code
     fn from(err: LoadBalanceError) -> Self {
-        ClientError::Application(ApplicationException::new(
+        ClientError::Application(volo_exception(
             ApplicationExceptionKind::INTERNAL_ERROR,
-            err.to_string(),
+            err,
         ))
     }
 }
@@ -234,3 +352,76 @@ impl<T: Default, E> Default for MaybeException<T, E> {
         MaybeException::Ok(Default::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::SocketAddr, time::Duration};
+
+    use volo::timeout::TimeoutKind;
+
+    use super::*;
+
+    #[test]
+    fn rpc_error() {
+        let addr = Address::from(SocketAddr::from(([127, 0, 0, 1], 8888)));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        let err = ClientError::from(ConnectError::new(addr.clone(), refused).into_io_error());
+        assert_eq!(err.category(), ErrorCategory::Connect);
+        assert_eq!(err.origin(), ErrorOrigin::Local);
+        assert_eq!(err.peer_addr(), Some(addr.clone()));
+        assert!(err.retryable());
+
+        let err = ClientError::from(TimeoutError::new(
+            TimeoutKind::Total,
+            Duration::from_secs(1),
+        ));
+        assert_eq!(err.category(), ErrorCategory::Timeout);
+        assert!(!err.retryable());
+
+        let err = ClientError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(err.category(), ErrorCategory::Transport);
+        assert_eq!(err.peer_addr(), None);
+
+        let err = ClientError::from(ApplicationException::new(
+            ApplicationExceptionKind::UNKNOWN_METHOD,
+            "unknown method",
+        ));
+        assert_eq!(err.category(), ErrorCategory::Protocol);
+        assert_eq!(err.origin(), ErrorOrigin::Remote);
+
+        let err = ClientError::from(BizError::new(1, "biz".into()));
+        assert_eq!(err.category(), ErrorCategory::Biz);
+        assert_eq!(err.origin(), ErrorOrigin::Remote);
+        assert!(!err.retryable());
+
+        let err = ClientError::from(LoadBalanceError::Retry);
+        assert_eq!(err.category(), ErrorCategory::Other);
+        assert_eq!(err.origin(), ErrorOrigin::Local);
+
+        let err = ClientError::from(volo_exception(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            RPC_TIMEOUT_MSG,
+        ));
+        assert_eq!(err.category(), ErrorCategory::Timeout);
+        assert_eq!(err.origin(), ErrorOrigin::Local);
+        assert!(!err.retryable());
+
+        let err = ClientError::from(TimeoutError::new(
+            TimeoutKind::Request,
+            Duration::from_secs(1),
+        ))
+        .with_peer_addr(Some(&addr));
+        assert_eq!(err.category(), ErrorCategory::Timeout);
+        assert_eq!(err.peer_addr(), Some(addr.clone()));
+        assert_eq!(
+            err.to_string(),
+            "transport exception: timed out: request timeout of 1s expired"
+        );
+        assert!(err.retryable());
+
+        let err = ClientError::from(io::Error::from(io::ErrorKind::ConnectionReset))
+            .with_peer_addr(Some(&addr));
+        assert_eq!(err.category(), ErrorCategory::Transport);
+        assert_eq!(err.peer_addr(), Some(addr));
+    }
+}
@@ -0,0 +1,188 @@
+//! Per-request memory accounting of servers.
+//!
+//! [`MemoryLayer`] creates a [`MemoryAccount`] for each request by the [`MemoryTracker`], charges
+//! the received frame to it and inserts it into the extensions of the context, see
+//! [`volo::memory`] for more details.
+//!
+//! The frame is read before the method is known, so the codecs built with
+//! [`MakeTTHeaderCodec::with_memory_tracker`] or [`MakeFramedCodec::with_memory_tracker`] charge
+//! it by [`MemoryTracker::charge_pending`] as soon as its length is read, which rejects frames
+//! exceeding the largest request limit before they are buffered, and the layer moves the charge to
+//! the account of the request. With other codecs, the size of the frame is charged after it is
+//! decoded. The frame is referenced by the decoded request, so it is charged until the request is
+//! done. Requests of transports without length prefixes, i.e., neither framed nor TTHeader, are
+//! not charged, but the handlers can still charge their own buffers to the account.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::memory::MemoryTracker;
+//! use volo_thrift::{
+//!     codec::default::{
+//!         DefaultMakeCodec, framed::MakeFramedCodec, thrift::MakeThriftCodec,
+//!         ttheader::MakeTTHeaderCodec,
+//!     },
+//!     server::memory::MemoryLayer,
+//! };
+//!
+//! let tracker = MemoryTracker::new()
+//!     .request_limit(16 * 1024 * 1024)
+//!     .method_limit("Upload", 256 * 1024 * 1024);
+//! let codec = DefaultMakeCodec::new(
+//!     MakeTTHeaderCodec::new(MakeFramedCodec::new(MakeThriftCodec::new()))
+//!         .with_memory_tracker(tracker.clone()),
+//! );
+//! let layer = MemoryLayer::new(tracker.clone());
+//! // `Server::new(service).make_codec(codec).layer_front(layer)`, and report
+//! // `tracker.all_stats()` to metrics
+//! ```
+//!
+//! [`MakeTTHeaderCodec::with_memory_tracker`]: crate::codec::default::ttheader::MakeTTHeaderCodec::with_memory_tracker
+//! [`MakeFramedCodec::with_memory_tracker`]: crate::codec::default::framed::MakeFramedCodec::with_memory_tracker
+
+use motore::{layer::Layer, service::Service};
+use volo::{
+    context::Context,
+    memory::{MemoryAccount, MemoryCharge, MemoryTracker},
+};
+
+use crate::{ApplicationException, ApplicationExceptionKind, ServerError, context::ServerContext};
+
+/// [`Layer`] accounting the memory held by requests, see the [module documentation](self) for
+/// more details.
+#[derive(Clone, Debug)]
+pub struct MemoryLayer {
+    tracker: MemoryTracker,
+}
+
+impl MemoryLayer {
+    /// Create a [`MemoryLayer`] accounting requests by the `tracker`.
+    pub fn new(tracker: MemoryTracker) -> Self {
+        Self { tracker }
+    }
+}
+
+impl<S> Layer<S> for MemoryLayer {
+    type Service = MemoryService<S>;
+
+    #[inline]
+    fn layer(self, inner: S) -> Self::Service {
+        MemoryService {
+            inner,
+            tracker: self.tracker,
+        }
+    }
+}
+
+/// [`Service`] generated by [`MemoryLayer`].
+#[derive(Clone, Debug)]
+pub struct MemoryService<S> {
+    inner: S,
+    tracker: MemoryTracker,
+}
+
+impl<S, Req> Service<ServerContext, Req> for MemoryService<S>
+where
+    S: Service<ServerContext, Req> + Send + Sync,
+    S::Error: Into<ServerError>,
+    Req: Send,
+{
+    type Response = S::Response;
+    type Error = ServerError;
+
+    async fn call(&self, cx: &mut ServerContext, req: Req) -> Result<Self::Response, Self::Error> {
+        let account = self.tracker.account(cx.rpc_info().method().as_str());
+        let received = match cx.extensions_mut().remove::<MemoryCharge>() {
+            // charged by the decoder
+            Some(charge) => Some(account.adopt(charge)),
+            None => cx.common_stats.read_size().map(|size| account.charge(size)),
+        };
+        if let Some(received) = received {
+            match received {
+                Ok(charge) => charge.retain(),
+                Err(err) => {
+                    let msg = format!(
+                        "[VOLO] request of {} is rejected: {err}",
+                        cx.rpc_info().method()
+                    );
+                    tracing::debug!("{msg}");
+                    return Err(ApplicationException::new(
+                        ApplicationExceptionKind::INTERNAL_ERROR,
+                        msg,
+                    )
+                    .into());
+                }
+            }
+        }
+        cx.extensions_mut().insert(account);
+        let resp = self.inner.call(cx, req).await.map_err(Into::into);
+        // the context may be reused by the next request
+        cx.extensions_mut().remove::<MemoryAccount>();
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::{layer::Layer, service::Service};
+    use volo::{
+        FastStr,
+        context::Context,
+        memory::{MemoryAccount, MemoryTracker},
+    };
+
+    use super::MemoryLayer;
+    use crate::{ServerError, context::ServerContext};
+
+    struct Handler;
+
+    impl Service<ServerContext, usize> for Handler {
+        type Response = usize;
+        type Error = ServerError;
+
+        async fn call(&self, cx: &mut ServerContext, buffer: usize) -> Result<usize, ServerError> {
+            let account = cx.extensions().get::<MemoryAccount>().unwrap();
+            let _buffer = account.charge(buffer).map_err(|err| {
+                crate::ApplicationException::new(
+                    crate::ApplicationExceptionKind::INTERNAL_ERROR,
+                    err.to_string(),
+                )
+            })?;
+            Ok(account.used())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_layer() {
+        let tracker = MemoryTracker::new().request_limit(100);
+        let service = MemoryLayer::new(tracker.clone()).layer(Handler);
+        let cx = |read_size: usize| {
+            let mut cx = ServerContext::default();
+            cx.rpc_info_mut()
+                .set_method(FastStr::from_static_str("GetItem"));
+            cx.common_stats.set_read_size(read_size);
+            cx
+        };
+
+        let mut ok = cx(40);
+        assert_eq!(service.call(&mut ok, 20).await.unwrap(), 60);
+        assert!(ok.extensions().get::<MemoryAccount>().is_none());
+        // exceeded by the frame
+        assert!(service.call(&mut cx(200), 0).await.is_err());
+        // exceeded by the handler
+        assert!(service.call(&mut cx(40), 80).await.is_err());
+
+        // charged by the decoder
+        let mut pending = cx(40);
+        let charge = tracker.charge_pending(50).unwrap();
+        pending.extensions_mut().insert(charge);
+        assert_eq!(service.call(&mut pending, 20).await.unwrap(), 70);
+        assert_eq!(tracker.pending_stats().in_flight, 0);
+
+        let stats = tracker.stats("GetItem").unwrap();
+        assert_eq!(stats.requests, 4);
+        assert_eq!(stats.rejected, 2);
+        assert_eq!(stats.max_peak, 70);
+        assert_eq!(stats.in_flight, 0);
+    }
+}
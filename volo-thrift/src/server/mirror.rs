@@ -0,0 +1,551 @@
+//! Dumps or mirrors a sampled fraction of incoming requests for shadow testing.
+//!
+//! [`MirrorLayer`] copies sampled requests into [`MirrorRecord`]s and hands them to a
+//! [`MirrorSink`] in background tasks, so that the primary response path is never affected: the
+//! request is served as usual, failures of the sink are only counted in [`MirrorStats`], and
+//! records are dropped if too many of them are in flight.
+//!
+//! A record has the decoded metadata of the request and the raw message re-encoded with the
+//! Binary protocol, which can be replayed by any thrift client. Sinks provided are:
+//!
+//! - [`tokio::sync::mpsc::Sender`], for consuming records in the application;
+//! - [`FileSink`], appending messages to a file in the Framed transport;
+//! - [`ShadowSink`], sending messages to a shadow backend in the Framed transport and discarding
+//!   its responses.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use volo_thrift::server::mirror::{FileSink, MirrorLayer, ShadowSink};
+//!
+//! // mirror 10% of requests to the new version of the service
+//! let shadow = MirrorLayer::new(ShadowSink::new("10.0.0.1:8080".parse().unwrap()), 0.1);
+//! let stats = shadow.stats();
+//!
+//! // dump all requests to a file
+//! let dump = MirrorLayer::new(FileSink::create("/tmp/requests.bin").unwrap(), 1.0);
+//! ```
+//!
+//! The layers can be added to the server by `Server::layer`.
+
+use std::{
+    fmt,
+    future::Future,
+    io::{self, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use ahash::AHashMap;
+use bytes::{BufMut, Bytes, BytesMut};
+use metainfo::{Forward, METAINFO};
+use motore::{BoxError, layer::Layer, service::Service};
+use pilota::{
+    FastStr,
+    thrift::{TMessageIdentifier, TMessageType, TOutputProtocol, binary::TBinaryProtocol},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use volo::{context::Context, net::Address};
+
+use crate::{EntryMessage, context::ServerContext};
+
+const DEFAULT_MAX_IN_FLIGHT: usize = 1024;
+const SAMPLE_SCALE: u64 = 1 << 32;
+
+/// The default timeout of sending a message to the shadow backend and receiving its response.
+pub const DEFAULT_SHADOW_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A copy of an incoming request.
+#[derive(Clone, Debug)]
+pub struct MirrorRecord {
+    /// The method called.
+    pub method: FastStr,
+    /// The sequence id of the request.
+    pub seq_id: i32,
+    /// The type of the message, `Call` or `OneWay`.
+    pub message_type: TMessageType,
+    /// The service name of the caller, empty if unknown.
+    pub caller: FastStr,
+    /// The address of the caller.
+    pub peer: Option<Address>,
+    /// The IDL service name for routing, from TTHeader.
+    pub idl_service_name: Option<FastStr>,
+    /// Persistent metainfo of the request.
+    pub persistents: AHashMap<FastStr, FastStr>,
+    /// Transient metainfo of the request.
+    pub transients: AHashMap<FastStr, FastStr>,
+    /// When the request is received.
+    pub received_at: SystemTime,
+    /// The message encoded with the Binary protocol, including the message header.
+    pub payload: Bytes,
+}
+
+impl MirrorRecord {
+    fn new<Req: EntryMessage>(cx: &ServerContext, req: &Req) -> Result<Self, BoxError> {
+        let method = cx.rpc_info().method().clone();
+        let seq_id = cx.seq_id.unwrap_or(0);
+        let message_type = cx.req_msg_type.unwrap_or(TMessageType::Call);
+
+        let mut buf = BytesMut::new();
+        let mut protocol = TBinaryProtocol::new(&mut buf, true);
+        protocol.write_message_begin(&TMessageIdentifier::new(
+            method.clone(),
+            message_type,
+            seq_id,
+        ))?;
+        req.encode(&mut protocol)?;
+        protocol.write_message_end()?;
+
+        let (persistents, transients) = METAINFO
+            .try_with(|mi| {
+                let mi = mi.borrow();
+                (
+                    mi.get_all_persistents().cloned().unwrap_or_default(),
+                    mi.get_all_transients().cloned().unwrap_or_default(),
+                )
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            method,
+            seq_id,
+            message_type,
+            caller: cx.rpc_info().caller().service_name(),
+            peer: cx.rpc_info().caller().address(),
+            idl_service_name: cx.idl_service_name.clone(),
+            persistents,
+            transients,
+            received_at: SystemTime::now(),
+            payload: buf.freeze(),
+        })
+    }
+
+    /// The payload in the Framed transport, i.e., prefixed by its length in 4 bytes.
+    fn framed(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(4 + self.payload.len());
+        buf.put_u32(self.payload.len() as u32);
+        buf.put_slice(&self.payload);
+        buf.freeze()
+    }
+}
+
+/// Destination of [`MirrorRecord`]s, called in background tasks.
+pub trait MirrorSink: Send + Sync + 'static {
+    /// Consume a record, errors are counted in [`MirrorStats::errors`].
+    fn send(&self, record: MirrorRecord) -> impl Future<Output = Result<(), BoxError>> + Send;
+}
+
+impl MirrorSink for tokio::sync::mpsc::Sender<MirrorRecord> {
+    async fn send(&self, record: MirrorRecord) -> Result<(), BoxError> {
+        // do not wait for the consumer, the record is dropped if the channel is full
+        Ok(self.try_send(record)?)
+    }
+}
+
+/// [`MirrorSink`] appending messages to a file in the Framed transport.
+///
+/// Only the payloads are written, other fields of the records are discarded.
+#[derive(Clone)]
+pub struct FileSink {
+    file: Arc<parking_lot::Mutex<io::BufWriter<std::fs::File>>>,
+}
+
+impl FileSink {
+    /// Open the file at `path` for appending, creating it if it does not exist.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self {
+            file: Arc::new(parking_lot::Mutex::new(io::BufWriter::new(file))),
+        })
+    }
+}
+
+impl MirrorSink for FileSink {
+    async fn send(&self, record: MirrorRecord) -> Result<(), BoxError> {
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut file = file.lock();
+            file.write_all(&record.framed())?;
+            file.flush()
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+/// [`MirrorSink`] sending messages to a shadow backend in the Framed transport.
+///
+/// Messages are sent one by one on a single connection, and responses are read and discarded.
+/// The connection is closed on errors and reconnected by the next record, and records are dropped
+/// by the [`MirrorLayer`] if the backend can not keep up with them.
+pub struct ShadowSink {
+    addr: SocketAddr,
+    timeout: Duration,
+    conn: tokio::sync::Mutex<Option<TcpStream>>,
+}
+
+impl ShadowSink {
+    /// Create a [`ShadowSink`] sending messages to the backend at `addr`.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            timeout: DEFAULT_SHADOW_TIMEOUT,
+            conn: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Set the timeout of connecting, sending a message and receiving its response.
+    ///
+    /// Default is [`DEFAULT_SHADOW_TIMEOUT`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn roundtrip(
+        &self,
+        conn: &mut Option<TcpStream>,
+        record: &MirrorRecord,
+    ) -> io::Result<()> {
+        let stream = match conn {
+            Some(stream) => stream,
+            None => conn.insert(TcpStream::connect(self.addr).await?),
+        };
+        stream.write_all(&record.framed()).await?;
+        if record.message_type == TMessageType::OneWay {
+            return Ok(());
+        }
+        let len = stream.read_u32().await?;
+        let mut resp = stream.take(u64::from(len));
+        let read = tokio::io::copy(&mut resp, &mut tokio::io::sink()).await?;
+        if read != u64::from(len) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl MirrorSink for ShadowSink {
+    async fn send(&self, record: MirrorRecord) -> Result<(), BoxError> {
+        let mut conn = self.conn.lock().await;
+        let res = match tokio::time::timeout(self.timeout, self.roundtrip(&mut conn, &record)).await
+        {
+            Ok(res) => res,
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        };
+        if res.is_err() {
+            // the connection may be in the middle of a message
+            *conn = None;
+        }
+        Ok(res?)
+    }
+}
+
+/// Statistics of mirrored requests.
+///
+/// It is cheap to clone, and all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct MirrorStats {
+    inner: Arc<MirrorStatsInner>,
+}
+
+#[derive(Default)]
+struct MirrorStatsInner {
+    mirrored: AtomicU64,
+    errors: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl MirrorStats {
+    /// The number of records sent to the sink.
+    pub fn mirrored(&self) -> u64 {
+        self.inner.mirrored.load(Ordering::Relaxed)
+    }
+
+    /// The number of records failed to be encoded or consumed by the sink.
+    pub fn errors(&self) -> u64 {
+        self.inner.errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of sampled requests dropped since too many records were in flight.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for MirrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirrorStats")
+            .field("mirrored", &self.mirrored())
+            .field("errors", &self.errors())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// [`Layer`] for mirroring incoming requests to a [`MirrorSink`].
+///
+/// See the [module documentation](self) for more details.
+pub struct MirrorLayer<K> {
+    inner: Arc<Mirror<K>>,
+}
+
+impl<K> Clone for MirrorLayer<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct Mirror<K> {
+    sink: Arc<K>,
+    // sampling ratio scaled by `SAMPLE_SCALE`
+    ratio: u64,
+    max_in_flight: usize,
+    count: AtomicU64,
+    in_flight: Arc<AtomicUsize>,
+    stats: MirrorStats,
+}
+
+impl<K> MirrorLayer<K> {
+    /// Create a [`MirrorLayer`] that mirrors `ratio` (from `0.0` to `1.0`) of requests to the
+    /// `sink`.
+    ///
+    /// Requests are sampled evenly, e.g., one of every ten requests is mirrored for `0.1`.
+    pub fn new(sink: K, ratio: f64) -> Self {
+        Self {
+            inner: Arc::new(Mirror {
+                sink: Arc::new(sink),
+                ratio: (ratio.clamp(0.0, 1.0) * SAMPLE_SCALE as f64).round() as u64,
+                max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+                count: AtomicU64::new(0),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+                stats: MirrorStats::default(),
+            }),
+        }
+    }
+
+    /// Set the maximum number of records in flight, sampled requests exceeding the limit are
+    /// dropped.
+    ///
+    /// Default is 1024.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layer has been cloned.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("`MirrorLayer` should not be cloned before configured")
+            .max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Get the [`MirrorStats`] of the layer.
+    pub fn stats(&self) -> MirrorStats {
+        self.inner.stats.clone()
+    }
+}
+
+impl<K> Mirror<K> {
+    fn sample(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed);
+        let (n, ratio, scale) = (
+            u128::from(n),
+            u128::from(self.ratio),
+            u128::from(SAMPLE_SCALE),
+        );
+        // mirror the request when the accumulated ratio crosses an integer
+        (n + 1) * ratio / scale != n * ratio / scale
+    }
+}
+
+impl<K, S> Layer<S> for MirrorLayer<K> {
+    type Service = MirrorService<S, K>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        MirrorService {
+            inner,
+            mirror: self.inner,
+        }
+    }
+}
+
+/// [`Service`] generated by [`MirrorLayer`].
+pub struct MirrorService<S, K> {
+    inner: S,
+    mirror: Arc<Mirror<K>>,
+}
+
+impl<S: Clone, K> Clone for MirrorService<S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mirror: self.mirror.clone(),
+        }
+    }
+}
+
+impl<S, K, Req> Service<ServerContext, Req> for MirrorService<S, K>
+where
+    S: Service<ServerContext, Req> + Send + Sync,
+    K: MirrorSink,
+    Req: EntryMessage + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut ServerContext, req: Req) -> Result<Self::Response, Self::Error> {
+        if self.mirror.sample() {
+            self.spawn_mirror(cx, &req);
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, K: MirrorSink> MirrorService<S, K> {
+    fn spawn_mirror<Req: EntryMessage>(&self, cx: &ServerContext, req: &Req) {
+        let mirror = &self.mirror;
+        if mirror.in_flight.fetch_add(1, Ordering::AcqRel) >= mirror.max_in_flight {
+            mirror.in_flight.fetch_sub(1, Ordering::AcqRel);
+            mirror.stats.inner.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let record = match MirrorRecord::new(cx, req) {
+            Ok(record) => record,
+            Err(err) => {
+                mirror.in_flight.fetch_sub(1, Ordering::AcqRel);
+                mirror.stats.inner.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[VOLO] failed to encode the mirrored request: {err}");
+                return;
+            }
+        };
+
+        let sink = mirror.sink.clone();
+        let in_flight = mirror.in_flight.clone();
+        let stats = mirror.stats.clone();
+        stats.inner.mirrored.fetch_add(1, Ordering::Relaxed);
+
+        tokio::spawn(async move {
+            let method = record.method.clone();
+            if let Err(err) = sink.send(record).await {
+                stats.inner.errors.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[VOLO] failed to mirror the request of {method}: {err}");
+            }
+            in_flight.fetch_sub(1, Ordering::AcqRel);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::service::service_fn;
+    use pilota::thrift::{TInputProtocol, binary::TBinaryProtocol};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{ServerError, transport::heartbeat::HeartbeatMessage};
+
+    fn cx(seq_id: i32) -> ServerContext {
+        let mut cx = ServerContext::default();
+        cx.rpc_info_mut()
+            .set_method(FastStr::from_static_str("ping"));
+        cx.seq_id = Some(seq_id);
+        cx.req_msg_type = Some(TMessageType::Call);
+        cx
+    }
+
+    async fn ok(_: &mut ServerContext, _: HeartbeatMessage) -> Result<(), ServerError> {
+        Ok(())
+    }
+
+    async fn wait(stats: &MirrorStats, in_flight: &AtomicUsize, mirrored: u64) {
+        while stats.mirrored() < mirrored || in_flight.load(Ordering::Acquire) != 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn mirror_to_channel() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let layer = MirrorLayer::new(tx, 0.5);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(ok));
+
+        for i in 0..10 {
+            svc.call(&mut cx(i), HeartbeatMessage).await.unwrap();
+        }
+        wait(&stats, &svc.mirror.in_flight, 5).await;
+        assert_eq!(stats.errors(), 0);
+        assert_eq!(stats.dropped(), 0);
+
+        let record = rx.recv().await.unwrap();
+        assert_eq!(record.method, "ping");
+        assert_eq!(record.message_type, TMessageType::Call);
+        let mut payload = record.payload.clone();
+        let mut protocol = TBinaryProtocol::new(&mut payload, true);
+        let ident = protocol.read_message_begin().unwrap();
+        assert_eq!(ident.name, "ping");
+        assert_eq!(ident.sequence_number, record.seq_id);
+    }
+
+    #[tokio::test]
+    async fn mirror_to_shadow() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            loop {
+                let Ok(len) = stream.read_u32().await else {
+                    return;
+                };
+                let mut buf = vec![0; len as usize];
+                stream.read_exact(&mut buf).await.unwrap();
+                tx.send(buf).unwrap();
+                // any response is discarded
+                stream.write_u32(2).await.unwrap();
+                stream.write_all(b"ok").await.unwrap();
+            }
+        });
+
+        let layer = MirrorLayer::new(ShadowSink::new(addr), 1.0);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(ok));
+        for i in 0..3 {
+            svc.call(&mut cx(i), HeartbeatMessage).await.unwrap();
+            wait(&stats, &svc.mirror.in_flight, i as u64 + 1).await;
+        }
+        assert_eq!(stats.errors(), 0);
+        for _ in 0..3 {
+            let msg = rx.recv().await.unwrap();
+            let mut payload = Bytes::from(msg);
+            let ident = TBinaryProtocol::new(&mut payload, true)
+                .read_message_begin()
+                .unwrap();
+            assert_eq!(ident.name, "ping");
+        }
+    }
+
+    #[tokio::test]
+    async fn mirror_max_in_flight() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let layer = MirrorLayer::new(tx, 1.0).with_max_in_flight(0);
+        let stats = layer.stats();
+        let svc = layer.layer(service_fn(ok));
+        svc.call(&mut cx(1), HeartbeatMessage).await.unwrap();
+        assert_eq!(stats.mirrored(), 0);
+        assert_eq!(stats.dropped(), 1);
+    }
+}
@@ -19,6 +19,7 @@ use tracing::{info, trace};
 #[cfg(feature = "shmipc")]
 use volo::net::shmipc_fallback::ShmipcAddressWithFallback;
 use volo::{
+    context::Role,
     net::{
         Address,
         conn::{OwnedReadHalf, OwnedWriteHalf},
@@ -31,7 +32,10 @@ use crate::{
     EntryMessage,
     codec::{
         DefaultMakeCodec, MakeCodec,
-        default::{framed::MakeFramedCodec, thrift::MakeThriftCodec, ttheader::MakeTTHeaderCodec},
+        default::{
+            MakeZeroCopyCodec, framed::MakeFramedCodec, thrift::MakeThriftCodec,
+            ttheader::MakeTTHeaderCodec,
+        },
     },
     context::ServerContext,
     server::layer::biz_error::BizErrorLayer,
@@ -39,9 +43,15 @@ use crate::{
 };
 
 mod layer;
+pub mod memory;
+pub mod mirror;
+pub mod overload;
 pub mod panic_handler;
+pub mod peer_quota;
 pub mod router;
+pub mod worker_pool;
 
+use peer_quota::{ConnectionGuard, PeerQuota};
 pub use router::{NamedService, Router};
 
 /// This is unstable now and may be changed in the future.
@@ -55,8 +65,11 @@ pub struct Server<S, L, Req, MkC, SP> {
     stat_tracer: Vec<TraceFn>,
     #[cfg(feature = "multiplex")]
     multiplex: bool,
+    #[cfg(feature = "multiplex")]
+    write_batch: Option<crate::transport::multiplex::WriteBatch>,
     span_provider: SP,
     shutdown_hooks: Vec<Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>>,
+    peer_quota: Option<PeerQuota>,
     _marker: PhantomData<Req>,
 }
 
@@ -80,8 +93,11 @@ impl<S, Req>
             stat_tracer: Vec::new(),
             #[cfg(feature = "multiplex")]
             multiplex: false,
+            #[cfg(feature = "multiplex")]
+            write_batch: None,
             span_provider: DefaultProvider {},
             shutdown_hooks: Vec::new(),
+            peer_quota: None,
             _marker: PhantomData,
         }
     }
@@ -125,8 +141,11 @@ impl
             stat_tracer: Vec::new(),
             #[cfg(feature = "multiplex")]
             multiplex: false,
+            #[cfg(feature = "multiplex")]
+            write_batch: None,
             span_provider: DefaultProvider {},
             shutdown_hooks: Vec::new(),
+            peer_quota: None,
             _marker: PhantomData,
         }
     }
@@ -164,8 +183,11 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             stat_tracer: self.stat_tracer,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
+            #[cfg(feature = "multiplex")]
+            write_batch: self.write_batch,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            peer_quota: self.peer_quota,
             _marker: PhantomData,
         }
     }
@@ -189,8 +211,33 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             stat_tracer: self.stat_tracer,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
+            #[cfg(feature = "multiplex")]
+            write_batch: self.write_batch,
+            span_provider: self.span_provider,
+            shutdown_hooks: self.shutdown_hooks,
+            peer_quota: self.peer_quota,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the [`PeerQuota`] to limit connections and request rates per peer IP.
+    ///
+    /// Connections exceeding the quota are closed right after they are accepted, and the request
+    /// rate limiting layer is added as the front layer, so that rejected requests do not reach
+    /// other layers.
+    pub fn peer_quota(self, quota: PeerQuota) -> Server<S, Stack<L, PeerQuota>, Req, MkC, SP> {
+        Server {
+            layer: Stack::new(self.layer, quota.clone()),
+            service: self.service,
+            make_codec: self.make_codec,
+            stat_tracer: self.stat_tracer,
+            #[cfg(feature = "multiplex")]
+            multiplex: self.multiplex,
+            #[cfg(feature = "multiplex")]
+            write_batch: self.write_batch,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            peer_quota: Some(quota),
             _marker: PhantomData,
         }
     }
@@ -202,6 +249,21 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         self
     }
 
+    /// Set the [`MakeZeroCopyCodec`] of the default codec, which is
+    /// `TTHeader<Framed<Binary>>` by default.
+    ///
+    /// Components of the default codec can be wrapped or replaced by custom ones implementing
+    /// [`MakeZeroCopyCodec`], e.g., a [`MakeTransformCodec`] between TTHeader and the payload codec
+    /// for encrypting payloads, and the peer must use the same codec stack.
+    ///
+    /// [`MakeTransformCodec`]: crate::codec::default::transform::MakeTransformCodec
+    pub fn zero_copy_codec<MkZC: MakeZeroCopyCodec>(
+        self,
+        make_codec: MkZC,
+    ) -> Server<S, L, Req, DefaultMakeCodec<MkZC>, SP> {
+        self.make_codec(DefaultMakeCodec::new(make_codec))
+    }
+
     /// Set the codec to use for the server.
     ///
     /// This should not be used by most users, Volo has already provided a default encoder.
@@ -218,8 +280,11 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             stat_tracer: self.stat_tracer,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
+            #[cfg(feature = "multiplex")]
+            write_batch: self.write_batch,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            peer_quota: self.peer_quota,
             _marker: PhantomData,
         }
     }
@@ -274,6 +339,14 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                     Ok(Some(conn)) => {
                         let peer_addr = conn.info.peer_addr;
                         trace!("[VOLO] accept connection from: {:?}", peer_addr);
+                        let guard = match &self.peer_quota {
+                            Some(quota) => match quota.acquire_connection(peer_addr.as_ref()) {
+                                Some(guard) => Some(guard),
+                                // close the connection
+                                None => continue,
+                            },
+                            None => None,
+                        };
                         let (rh, wh) = conn.stream.into_split();
 
                         #[cfg(feature = "multiplex")]
@@ -284,19 +357,42 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                                 let _ = rh.shmipc_helper().close().await;
                                 continue;
                             }
-                            tokio::spawn(handle_conn_multiplex(
-                                rh,
-                                wh,
-                                service.clone(),
-                                self.make_codec.clone(),
-                                stat_tracer.clone(),
-                                exit_notify_inner.clone(),
-                                exit_mark_inner.clone(),
-                                conn_cnt.clone(),
-                                peer_addr,
+                            tokio::spawn(with_guard(
+                                guard,
+                                handle_conn_multiplex(
+                                    rh,
+                                    wh,
+                                    service.clone(),
+                                    self.make_codec.clone(),
+                                    stat_tracer.clone(),
+                                    exit_notify_inner.clone(),
+                                    exit_mark_inner.clone(),
+                                    conn_cnt.clone(),
+                                    peer_addr,
+                                    self.write_batch.clone(),
+                                ),
                             ));
                         } else {
-                            tokio::spawn(handle_conn(
+                            tokio::spawn(with_guard(
+                                guard,
+                                handle_conn(
+                                    rh,
+                                    wh,
+                                    service.clone(),
+                                    self.make_codec.clone(),
+                                    stat_tracer.clone(),
+                                    exit_notify_inner.clone(),
+                                    exit_mark_inner.clone(),
+                                    conn_cnt.clone(),
+                                    peer_addr,
+                                    self.span_provider.clone(),
+                                ),
+                            ));
+                        }
+                        #[cfg(not(feature = "multiplex"))]
+                        tokio::spawn(with_guard(
+                            guard,
+                            handle_conn(
                                 rh,
                                 wh,
                                 service.clone(),
@@ -307,20 +403,7 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                                 conn_cnt.clone(),
                                 peer_addr,
                                 self.span_provider.clone(),
-                            ));
-                        }
-                        #[cfg(not(feature = "multiplex"))]
-                        tokio::spawn(handle_conn(
-                            rh,
-                            wh,
-                            service.clone(),
-                            self.make_codec.clone(),
-                            stat_tracer.clone(),
-                            exit_notify_inner.clone(),
-                            exit_mark_inner.clone(),
-                            conn_cnt.clone(),
-                            peer_addr,
-                            self.span_provider.clone(),
+                            ),
                         ));
                     }
                     // no more incoming connections
@@ -454,12 +537,25 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             make_codec: self.make_codec,
             stat_tracer: self.stat_tracer,
             multiplex,
+            write_batch: self.write_batch,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            peer_quota: self.peer_quota,
             _marker: PhantomData,
         }
     }
 
+    #[cfg(feature = "multiplex")]
+    /// Coalesce responses pending on a connection into batches, and write each batch in one
+    /// vectored write to save syscalls under high QPS with small responses.
+    ///
+    /// It only takes effect in multiplex mode, since responses of ping-pong connections are
+    /// written one by one.
+    pub fn write_batch(mut self, batch: crate::transport::multiplex::WriteBatch) -> Self {
+        self.write_batch = Some(batch);
+        self
+    }
+
     pub fn span_provider<P: SpanProvider>(self, provider: P) -> Server<S, L, Req, MkC, P> {
         Server {
             layer: self.layer,
@@ -468,13 +564,157 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             stat_tracer: self.stat_tracer,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
+            #[cfg(feature = "multiplex")]
+            write_batch: self.write_batch,
             span_provider: provider,
             shutdown_hooks: self.shutdown_hooks,
+            peer_quota: self.peer_quota,
             _marker: PhantomData,
         }
     }
 }
 
+#[cfg(feature = "datagram")]
+impl<S, L, Req, MkZC, SP> Server<S, L, Req, DefaultMakeCodec<MkZC>, SP>
+where
+    MkZC: MakeZeroCopyCodec + Sync,
+{
+    /// Run the server receiving requests of oneway methods from UDP datagrams on the `addr`,
+    /// which are sent by clients built by
+    /// [`ClientBuilder::build_datagram`](crate::client::ClientBuilder::build_datagram).
+    ///
+    /// Requests of other methods are dropped since they cannot be replied. Datagrams are handled
+    /// concurrently, and ones arriving when too many are being handled are dropped by the kernel
+    /// after the receive buffer of the socket is full. See [`crate::transport::datagram`] for
+    /// more details.
+    pub async fn run_datagram(self, addr: std::net::SocketAddr) -> Result<(), BoxError>
+    where
+        L: Layer<BoxService<ServerContext, Req, S::Response, crate::ServerError>>,
+        L::Service: Service<ServerContext, Req, Response = S::Response, Error = crate::ServerError>
+            + Send
+            + 'static
+            + Sync,
+        S: Service<ServerContext, Req, Error = crate::ServerError> + Send + 'static + Sync,
+        S::Response: EntryMessage + Send + 'static + Sync,
+        Req: EntryMessage + Send + 'static,
+        SP: SpanProvider,
+    {
+        let listener = crate::transport::datagram::server::Listener::udp(addr).await?;
+        self.serve_datagram(listener).await
+    }
+
+    /// Run the server receiving requests of oneway methods from QUIC datagrams on the UDP `addr`
+    /// with the TLS config, see [`Server::run_datagram`] for more details.
+    ///
+    /// The TLS config must be built by rustls.
+    #[cfg(feature = "datagram-quic")]
+    pub async fn run_quic_datagram(
+        self,
+        addr: std::net::SocketAddr,
+        tls_config: volo::net::tls::ServerTlsConfig,
+    ) -> Result<(), BoxError>
+    where
+        L: Layer<BoxService<ServerContext, Req, S::Response, crate::ServerError>>,
+        L::Service: Service<ServerContext, Req, Response = S::Response, Error = crate::ServerError>
+            + Send
+            + 'static
+            + Sync,
+        S: Service<ServerContext, Req, Error = crate::ServerError> + Send + 'static + Sync,
+        S::Response: EntryMessage + Send + 'static + Sync,
+        Req: EntryMessage + Send + 'static,
+        SP: SpanProvider,
+    {
+        let listener = crate::transport::datagram::server::Listener::quic(addr, &tls_config)?;
+        self.serve_datagram(listener).await
+    }
+
+    async fn serve_datagram(
+        self,
+        listener: crate::transport::datagram::server::Listener,
+    ) -> Result<(), BoxError>
+    where
+        L: Layer<BoxService<ServerContext, Req, S::Response, crate::ServerError>>,
+        L::Service: Service<ServerContext, Req, Response = S::Response, Error = crate::ServerError>
+            + Send
+            + 'static
+            + Sync,
+        S: Service<ServerContext, Req, Error = crate::ServerError> + Send + 'static + Sync,
+        S::Response: EntryMessage + Send + 'static + Sync,
+        Req: EntryMessage + Send + 'static,
+        SP: SpanProvider,
+    {
+        use crate::transport::datagram::server::{self, Handler, MAX_CONCURRENT_DATAGRAMS};
+
+        let service = Arc::new(
+            self.layer
+                .layer(BoxService::new(BizErrorLayer::new().layer(self.service))),
+        );
+        let permits = Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_DATAGRAMS));
+        let handler = Handler {
+            service,
+            make_codec: self.make_codec.make_zero_copy_codec().clone(),
+            stat_tracer: Arc::from(self.stat_tracer),
+            span_provider: self.span_provider,
+            permits: permits.clone(),
+        };
+        info!(
+            "[VOLO] datagram server start at: {:?}",
+            listener.local_addr()?
+        );
+        let mut recv_loop = tokio::spawn(server::serve(listener, handler));
+
+        tokio::select! {
+            res = shutdown_signal() => res?,
+            res = &mut recv_loop => res?,
+        }
+        recv_loop.abort();
+
+        if !self.shutdown_hooks.is_empty() {
+            info!("[VOLO] call shutdown hooks");
+
+            for hook in self.shutdown_hooks {
+                (hook)().await;
+            }
+        }
+
+        // received signal, wait for the datagrams being handled
+        info!("[VOLO] received signal, gracefully exiting now");
+        let _ = tokio::time::timeout(
+            Duration::from_secs(30),
+            permits.acquire_many(MAX_CONCURRENT_DATAGRAMS as u32),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+/// Wait for the signals of shutting down gracefully.
+#[cfg(feature = "datagram")]
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(target_family = "unix")]
+    {
+        let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+        let mut sighup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        let mut sigterm =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigint.recv() => {}
+            _ = sighup.recv() => {}
+            _ = sigterm.recv() => {}
+        }
+        Ok(())
+    }
+
+    #[cfg(target_family = "windows")]
+    tokio::signal::ctrl_c().await
+}
+
+/// Holds the guard of the connection until it is closed.
+async fn with_guard<F: std::future::Future>(guard: Option<ConnectionGuard>, f: F) -> F::Output {
+    let _guard = guard;
+    f.await
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn handle_conn<R, W, Req, Svc, Resp, MkC, SP>(
     rh: R,
@@ -502,6 +742,9 @@ async fn handle_conn<R, W, Req, Svc, Resp, MkC, SP>(
     defer! {
         conn_cnt.fetch_sub(1, Ordering::Relaxed);
     }
+    let _conn_event = peer_addr
+        .as_ref()
+        .and_then(|peer| volo::event::connection("thrift", Role::Server, peer));
 
     let (encoder, decoder) = make_codec.make_codec(rh, wh);
 
@@ -537,6 +780,7 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
     exit_mark: Arc<std::sync::atomic::AtomicBool>,
     conn_cnt: Arc<std::sync::atomic::AtomicUsize>,
     peer_addr: Option<Address>,
+    write_batch: Option<crate::transport::multiplex::WriteBatch>,
 ) where
     R: AsyncRead + Unpin + Send + Sync + 'static,
     W: AsyncWrite + Unpin + Send + Sync + 'static,
@@ -550,6 +794,9 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
     defer! {
         conn_cnt.fetch_sub(1, Ordering::Relaxed);
     }
+    let _conn_event = peer_addr
+        .as_ref()
+        .and_then(|peer| volo::event::connection("thrift", Role::Server, peer));
     let (encoder, decoder) = make_codec.make_codec(rh, wh);
 
     info!(
@@ -564,6 +811,7 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
         service,
         stat_tracer,
         peer_addr,
+        write_batch,
     )
     .await;
 }
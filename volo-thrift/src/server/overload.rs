@@ -0,0 +1,677 @@
+//! Priority-aware overload control.
+//!
+//! When a server is overloaded, serving all requests slowly is usually worse than rejecting some
+//! of them quickly, and some requests are more important than others, e.g., requests of paying
+//! users or health checks. [`OverloadLayer`] classifies requests into [`Priority`] tiers, and
+//! sheds requests of low priorities first when the load reported by [`OverloadSignal`]s exceeds
+//! their [`ShedThreshold`]s.
+//!
+//! A request is classified by, in order:
+//!
+//! 1. the method, see [`OverloadLayer::method`];
+//! 2. the caller service name, see [`OverloadLayer::caller`];
+//! 3. the metainfo [`PRIORITY_KEY`] sent by the caller, persistent or transient, only if it's
+//!    trusted by [`OverloadLayer::trust_caller_priority`];
+//! 4. the default priority, see [`OverloadLayer::default_priority`].
+//!
+//! The rules of the server come first, and the priorities claimed by callers are ignored by
+//! default, since any caller could claim `critical` to avoid being shed otherwise.
+//!
+//! The load is the maximum of all signals, where `1.0` means saturated. [`QueueDelay`] is
+//! measured from the delay between decoding requests and serving them, and `CpuUsage` is sampled
+//! from the system with the `overload-cpu` feature. Custom signals can be added by implementing
+//! [`OverloadSignal`] or by closures returning the load.
+//!
+//! In each tier, requests are shed evenly at a rate rising linearly from `0` to `1` when the load
+//! rises from the start to the end of its threshold. The current shed rate and counters of each
+//! tier are recorded in [`OverloadStats`] for reporting to metrics.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_thrift::server::overload::{OverloadLayer, Priority, QueueDelay, ShedThreshold};
+//!
+//! let layer = OverloadLayer::new()
+//!     .signal(QueueDelay::new(Duration::from_millis(20)))
+//!     .method("HealthCheck", Priority::Critical)
+//!     .method("Export", Priority::Low)
+//!     .caller("batch-job", Priority::Low)
+//!     .threshold(Priority::Low, ShedThreshold::new(0.5, 0.8));
+//! let stats = layer.stats();
+//! ```
+//!
+//! The layer can be added to the server by `Server::layer`.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use metainfo::{Forward, METAINFO};
+use motore::{layer::Layer, service::Service};
+use pilota::FastStr;
+use volo::context::Context;
+
+use crate::{ApplicationExceptionKind, ServerError, context::ServerContext, error::volo_exception};
+
+/// The metainfo key of the priority of a request.
+///
+/// The value is one of `critical`, `high`, `normal` and `low`, case-insensitive.
+pub const PRIORITY_KEY: &str = "priority";
+
+const SHED_SCALE: u64 = 1 << 32;
+
+/// Priority tiers of requests, from the most important to the least.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    /// Never shed by default, e.g., health checks.
+    Critical,
+    High,
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// All tiers, from the most important to the least.
+    pub const ALL: [Priority; 4] = [
+        Priority::Critical,
+        Priority::High,
+        Priority::Normal,
+        Priority::Low,
+    ];
+
+    /// The name of the tier, which is also the value of [`PRIORITY_KEY`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Critical => "critical",
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+
+    /// Parse the value of [`PRIORITY_KEY`].
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str().eq_ignore_ascii_case(value.trim()))
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn default_threshold(self) -> ShedThreshold {
+        match self {
+            Priority::Critical => ShedThreshold::never(),
+            Priority::High => ShedThreshold::new(0.9, 1.0),
+            Priority::Normal => ShedThreshold::new(0.8, 0.95),
+            Priority::Low => ShedThreshold::new(0.7, 0.9),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The range of load where requests of a tier are shed.
+///
+/// Requests are shed at a rate rising linearly from `0` at `start` to `1` at `full`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShedThreshold {
+    start: f64,
+    full: f64,
+}
+
+impl ShedThreshold {
+    /// Create a [`ShedThreshold`] shedding requests from the load `start` and shedding all
+    /// requests at the load `full`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `full` is less than `start`.
+    pub fn new(start: f64, full: f64) -> Self {
+        assert!(full >= start, "`full` should not be less than `start`");
+        Self { start, full }
+    }
+
+    /// Never shed requests.
+    pub fn never() -> Self {
+        Self {
+            start: f64::INFINITY,
+            full: f64::INFINITY,
+        }
+    }
+
+    fn shed_rate(&self, load: f64) -> f64 {
+        if load < self.start {
+            0.0
+        } else if load >= self.full {
+            1.0
+        } else {
+            (load - self.start) / (self.full - self.start)
+        }
+    }
+}
+
+/// Signal of the load of the server.
+pub trait OverloadSignal: Send + Sync + 'static {
+    /// The current load, where `1.0` means saturated.
+    fn load(&self) -> f64;
+
+    /// Observe the queue delay of a request, i.e., the time between decoding the request and
+    /// serving it, which is called for every request including shed ones.
+    fn observe(&self, _queue_delay: Duration) {}
+}
+
+impl<F> OverloadSignal for F
+where
+    F: Fn() -> f64 + Send + Sync + 'static,
+{
+    fn load(&self) -> f64 {
+        self()
+    }
+}
+
+/// [`OverloadSignal`] of the queue delay of requests.
+///
+/// The load is the moving average of queue delays divided by the target delay, so it exceeds
+/// `1.0` when requests wait longer than the target on average.
+pub struct QueueDelay {
+    target: Duration,
+    // moving average in seconds, stored as bits of `f64`
+    average: AtomicU64,
+}
+
+impl QueueDelay {
+    // weight of the latest delay in the moving average
+    const ALPHA: f64 = 0.1;
+
+    /// Create a [`QueueDelay`] with the target delay.
+    pub fn new(target: Duration) -> Self {
+        Self {
+            target,
+            average: AtomicU64::new(0.0f64.to_bits()),
+        }
+    }
+}
+
+impl OverloadSignal for QueueDelay {
+    fn load(&self) -> f64 {
+        f64::from_bits(self.average.load(Ordering::Relaxed)) / self.target.as_secs_f64()
+    }
+
+    fn observe(&self, queue_delay: Duration) {
+        // races between requests only lose some samples
+        let average = f64::from_bits(self.average.load(Ordering::Relaxed));
+        let average = average + (queue_delay.as_secs_f64() - average) * Self::ALPHA;
+        self.average.store(average.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// [`OverloadSignal`] of the CPU usage of the system, from `0.0` to `1.0`.
+///
+/// The usage is sampled by a background thread, which exits when the signal is dropped.
+#[cfg(feature = "overload-cpu")]
+pub struct CpuUsage {
+    usage: Arc<AtomicU64>,
+}
+
+#[cfg(feature = "overload-cpu")]
+impl CpuUsage {
+    /// The default interval of sampling the usage.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Create a [`CpuUsage`] sampling the usage every [`CpuUsage::DEFAULT_INTERVAL`].
+    pub fn new() -> Self {
+        Self::with_interval(Self::DEFAULT_INTERVAL)
+    }
+
+    /// Create a [`CpuUsage`] sampling the usage every `interval`, which is at least
+    /// [`sysinfo::MINIMUM_CPU_UPDATE_INTERVAL`].
+    pub fn with_interval(interval: Duration) -> Self {
+        let interval = interval.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        let usage = Arc::new(AtomicU64::new(0.0f64.to_bits()));
+        let weak = Arc::downgrade(&usage);
+        std::thread::Builder::new()
+            .name("volo-cpu-usage".to_owned())
+            .spawn(move || {
+                let mut system = sysinfo::System::new();
+                system.refresh_cpu_usage();
+                loop {
+                    std::thread::sleep(interval);
+                    let Some(usage) = weak.upgrade() else {
+                        return;
+                    };
+                    system.refresh_cpu_usage();
+                    let value = f64::from(system.global_cpu_usage()) / 100.0;
+                    usage.store(value.to_bits(), Ordering::Relaxed);
+                }
+            })
+            .expect("failed to spawn the thread sampling cpu usage");
+        Self { usage }
+    }
+}
+
+#[cfg(feature = "overload-cpu")]
+impl Default for CpuUsage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "overload-cpu")]
+impl OverloadSignal for CpuUsage {
+    fn load(&self) -> f64 {
+        f64::from_bits(self.usage.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Default)]
+struct TierCounters {
+    admitted: AtomicU64,
+    shed: AtomicU64,
+    // shed rate stored as bits of `f64`
+    shed_rate: AtomicU64,
+    // accumulated shed rate scaled by `SHED_SCALE`
+    acc: AtomicU64,
+}
+
+impl TierCounters {
+    /// Whether to shed the request, requests are shed evenly at the rate.
+    fn shed(&self, rate: f64) -> bool {
+        self.shed_rate.store(rate.to_bits(), Ordering::Relaxed);
+        if rate <= 0.0 {
+            return false;
+        }
+        let inc = (rate.min(1.0) * SHED_SCALE as f64) as u64;
+        let prev = self.acc.fetch_add(inc, Ordering::Relaxed);
+        prev.wrapping_add(inc) / SHED_SCALE != prev / SHED_SCALE
+    }
+}
+
+/// Statistics of a tier, see [`OverloadStats::tier`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TierStats {
+    /// The number of requests admitted.
+    pub admitted: u64,
+    /// The number of requests shed.
+    pub shed: u64,
+    /// The shed rate of the latest request, from `0.0` to `1.0`.
+    pub shed_rate: f64,
+}
+
+/// Statistics of the overload control.
+///
+/// It is cheap to clone, and all clones share the same counters.
+#[derive(Clone, Default)]
+pub struct OverloadStats {
+    inner: Arc<OverloadStatsInner>,
+}
+
+#[derive(Default)]
+struct OverloadStatsInner {
+    tiers: [TierCounters; 4],
+    // load of the latest request, stored as bits of `f64`
+    load: AtomicU64,
+}
+
+impl OverloadStats {
+    /// The statistics of the tier.
+    pub fn tier(&self, priority: Priority) -> TierStats {
+        let tier = &self.inner.tiers[priority.index()];
+        TierStats {
+            admitted: tier.admitted.load(Ordering::Relaxed),
+            shed: tier.shed.load(Ordering::Relaxed),
+            shed_rate: f64::from_bits(tier.shed_rate.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// The load of the latest request.
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.inner.load.load(Ordering::Relaxed))
+    }
+}
+
+impl fmt::Debug for OverloadStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("OverloadStats");
+        s.field("load", &self.load());
+        for priority in Priority::ALL {
+            s.field(priority.as_str(), &self.tier(priority));
+        }
+        s.finish()
+    }
+}
+
+struct Overload {
+    signals: Vec<Arc<dyn OverloadSignal>>,
+    methods: AHashMap<FastStr, Priority>,
+    callers: AHashMap<FastStr, Priority>,
+    default_priority: Priority,
+    trust_caller_priority: bool,
+    thresholds: [ShedThreshold; 4],
+    stats: OverloadStats,
+}
+
+impl Overload {
+    fn classify(&self, cx: &ServerContext) -> Priority {
+        self.methods
+            .get(cx.rpc_info().method())
+            .copied()
+            .or_else(|| {
+                self.callers
+                    .get(cx.rpc_info().caller().service_name_ref())
+                    .copied()
+            })
+            .or_else(|| self.caller_priority())
+            .unwrap_or(self.default_priority)
+    }
+
+    /// The priority claimed by the caller in the metainfo, if it's trusted.
+    fn caller_priority(&self) -> Option<Priority> {
+        if !self.trust_caller_priority {
+            return None;
+        }
+        METAINFO
+            .try_with(|mi| {
+                let mi = mi.borrow();
+                mi.get_persistent(PRIORITY_KEY)
+                    .or_else(|| mi.get_transient(PRIORITY_KEY))
+                    .and_then(|value| Priority::parse(&value))
+            })
+            .ok()
+            .flatten()
+    }
+
+    fn load(&self) -> f64 {
+        self.signals
+            .iter()
+            .map(|signal| signal.load())
+            .fold(0.0, f64::max)
+    }
+
+    /// Returns the priority of the request if it should be shed.
+    fn check(&self, cx: &ServerContext) -> Option<Priority> {
+        if let Some(decoded) = cx.common_stats.decode_end_at() {
+            let delay = decoded.elapsed();
+            self.signals.iter().for_each(|signal| signal.observe(delay));
+        }
+        let priority = self.classify(cx);
+        let load = self.load();
+        let stats = &self.stats.inner;
+        stats.load.store(load.to_bits(), Ordering::Relaxed);
+        let tier = &stats.tiers[priority.index()];
+        if tier.shed(self.thresholds[priority.index()].shed_rate(load)) {
+            tier.shed.fetch_add(1, Ordering::Relaxed);
+            Some(priority)
+        } else {
+            tier.admitted.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// A [`Layer`] shedding requests of low priorities first when the server is overloaded.
+///
+/// See the [module level documentation](self) for more details.
+pub struct OverloadLayer {
+    inner: Overload,
+}
+
+impl Default for OverloadLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OverloadLayer {
+    /// Create an [`OverloadLayer`] without signals, which never sheds requests until signals are
+    /// added by [`OverloadLayer::signal`].
+    pub fn new() -> Self {
+        Self {
+            inner: Overload {
+                signals: Vec::new(),
+                methods: AHashMap::new(),
+                callers: AHashMap::new(),
+                default_priority: Priority::Normal,
+                trust_caller_priority: false,
+                thresholds: Priority::ALL.map(Priority::default_threshold),
+                stats: OverloadStats::default(),
+            },
+        }
+    }
+
+    /// Add a signal of the load.
+    pub fn signal(mut self, signal: impl OverloadSignal) -> Self {
+        self.inner.signals.push(Arc::new(signal));
+        self
+    }
+
+    /// Set the priority of requests of the method.
+    pub fn method(mut self, method: impl Into<FastStr>, priority: Priority) -> Self {
+        self.inner.methods.insert(method.into(), priority);
+        self
+    }
+
+    /// Set the priority of requests from the caller service.
+    pub fn caller(mut self, caller: impl Into<FastStr>, priority: Priority) -> Self {
+        self.inner.callers.insert(caller.into(), priority);
+        self
+    }
+
+    /// Set whether to classify requests by the metainfo [`PRIORITY_KEY`] sent by callers, which
+    /// is used only if the request is not classified by the method or caller.
+    ///
+    /// Enable it only if all callers are trusted, e.g., the priority is set by a gateway and can
+    /// not be set by the end users.
+    ///
+    /// Default is false.
+    pub fn trust_caller_priority(mut self, trust: bool) -> Self {
+        self.inner.trust_caller_priority = trust;
+        self
+    }
+
+    /// Set the priority of requests not classified by the method, caller or metainfo.
+    ///
+    /// Default is [`Priority::Normal`].
+    pub fn default_priority(mut self, priority: Priority) -> Self {
+        self.inner.default_priority = priority;
+        self
+    }
+
+    /// Set the threshold of the tier.
+    ///
+    /// Defaults are:
+    ///
+    /// | Priority | Start | Full |
+    /// |----------|-------|------|
+    /// | Critical | never | never |
+    /// | High     | 0.9   | 1.0  |
+    /// | Normal   | 0.8   | 0.95 |
+    /// | Low      | 0.7   | 0.9  |
+    pub fn threshold(mut self, priority: Priority, threshold: ShedThreshold) -> Self {
+        self.inner.thresholds[priority.index()] = threshold;
+        self
+    }
+
+    /// Get the [`OverloadStats`] of the layer.
+    pub fn stats(&self) -> OverloadStats {
+        self.inner.stats.clone()
+    }
+}
+
+impl<S> Layer<S> for OverloadLayer {
+    type Service = OverloadService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        OverloadService {
+            inner,
+            overload: Arc::new(self.inner),
+        }
+    }
+}
+
+/// [`Service`] generated by [`OverloadLayer`].
+#[derive(Clone)]
+pub struct OverloadService<S> {
+    inner: S,
+    overload: Arc<Overload>,
+}
+
+impl<S, Req> Service<ServerContext, Req> for OverloadService<S>
+where
+    S: Service<ServerContext, Req, Error = ServerError> + Send + Sync,
+    Req: Send,
+{
+    type Response = S::Response;
+    type Error = ServerError;
+
+    async fn call(&self, cx: &mut ServerContext, req: Req) -> Result<Self::Response, Self::Error> {
+        if let Some(priority) = self.overload.check(cx) {
+            // shed requests are not logged at a higher level, which adds to the load
+            tracing::debug!(
+                "[VOLO] request of method `{}` with priority `{priority}` is shed by overload \
+                 control",
+                cx.rpc_info().method()
+            );
+            return Err(volo_exception(
+                ApplicationExceptionKind::INTERNAL_ERROR,
+                format_args!("server is overloaded, request of priority `{priority}` is shed"),
+            )
+            .into());
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, sync::atomic::AtomicU64};
+
+    use metainfo::MetaInfo;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Noop;
+
+    impl Service<ServerContext, ()> for Noop {
+        type Response = ();
+        type Error = ServerError;
+
+        async fn call(&self, _: &mut ServerContext, _: ()) -> Result<(), ServerError> {
+            Ok(())
+        }
+    }
+
+    fn cx(method: &'static str) -> ServerContext {
+        let mut cx = ServerContext::default();
+        cx.rpc_info_mut()
+            .set_method(FastStr::from_static_str(method));
+        cx
+    }
+
+    fn load_signal() -> (Arc<AtomicU64>, impl OverloadSignal) {
+        let load = Arc::new(AtomicU64::new(0.0f64.to_bits()));
+        let signal = {
+            let load = load.clone();
+            move || f64::from_bits(load.load(Ordering::Relaxed))
+        };
+        (load, signal)
+    }
+
+    #[test]
+    fn shed_rate() {
+        let threshold = ShedThreshold::new(0.5, 1.0);
+        assert_eq!(threshold.shed_rate(0.2), 0.0);
+        assert_eq!(threshold.shed_rate(0.75), 0.5);
+        assert_eq!(threshold.shed_rate(1.5), 1.0);
+        assert_eq!(ShedThreshold::never().shed_rate(f64::MAX), 0.0);
+
+        let tier = TierCounters::default();
+        assert_eq!((0..100).filter(|_| tier.shed(0.25)).count(), 25);
+        assert_eq!((0..100).filter(|_| tier.shed(1.0)).count(), 100);
+        assert_eq!((0..100).filter(|_| tier.shed(0.0)).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn shed_low_priority_first() {
+        let (load, signal) = load_signal();
+        let layer = OverloadLayer::new()
+            .signal(signal)
+            .method("Health", Priority::Critical)
+            .caller("batch", Priority::Low);
+        let stats = layer.stats();
+        let svc = layer.layer(Noop);
+
+        let mut batch = cx("Echo");
+        batch
+            .rpc_info_mut()
+            .caller_mut()
+            .set_service_name(FastStr::from_static_str("batch"));
+
+        assert!(svc.call(&mut batch, ()).await.is_ok());
+
+        // low priority requests are shed, normal ones are not
+        load.store(0.9f64.to_bits(), Ordering::Relaxed);
+        assert!(svc.call(&mut batch, ()).await.is_err());
+        assert!(svc.call(&mut cx("Echo"), ()).await.is_ok());
+
+        // critical requests are never shed
+        load.store(10.0f64.to_bits(), Ordering::Relaxed);
+        assert!(svc.call(&mut cx("Echo"), ()).await.is_err());
+        assert!(svc.call(&mut cx("Health"), ()).await.is_ok());
+
+        let low = stats.tier(Priority::Low);
+        assert_eq!((low.admitted, low.shed, low.shed_rate), (1, 1, 1.0));
+        let normal = stats.tier(Priority::Normal);
+        assert_eq!((normal.admitted, normal.shed), (1, 1));
+        assert_eq!(stats.tier(Priority::Critical).shed, 0);
+        assert_eq!(stats.load(), 10.0);
+    }
+
+    #[tokio::test]
+    async fn classify_by_metainfo() {
+        let classify = |overload: Overload, method: &'static str| {
+            METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
+                METAINFO.with(|mi| mi.borrow_mut().set_persistent(PRIORITY_KEY, "Critical"));
+                overload.classify(&cx(method))
+            })
+        };
+        let layer = || OverloadLayer::new().method("Export", Priority::Low);
+
+        // the metainfo is ignored by default
+        assert_eq!(classify(layer().inner, "Echo").await, Priority::Normal);
+
+        // the rules of the server come first
+        let overload = layer().trust_caller_priority(true).inner;
+        assert_eq!(classify(overload, "Export").await, Priority::Low);
+        let overload = layer().trust_caller_priority(true).inner;
+        assert_eq!(classify(overload, "Echo").await, Priority::Critical);
+
+        let overload = layer().trust_caller_priority(true).inner;
+        assert_eq!(overload.classify(&cx("Export")), Priority::Low);
+        assert_eq!(overload.classify(&cx("Echo")), Priority::Normal);
+    }
+
+    #[test]
+    fn queue_delay() {
+        let signal = QueueDelay::new(Duration::from_millis(10));
+        assert_eq!(signal.load(), 0.0);
+        for _ in 0..100 {
+            signal.observe(Duration::from_millis(20));
+        }
+        assert!((signal.load() - 2.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,659 @@
+//! Per-peer connection quotas and request rate limits.
+//!
+//! A misbehaving caller, e.g., one leaking connections or retrying in a tight loop, can exhaust
+//! the resources of the server and affect all other callers. [`PeerQuota`] tracks connections and
+//! request rates per peer IP, rejects connections and requests exceeding the quotas, and counts
+//! the rejections so that they can be reported to metrics.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_thrift::server::peer_quota::{PeerQuota, PeerQuotaConfig};
+//!
+//! let quota = PeerQuota::new(
+//!     PeerQuotaConfig::new()
+//!         .with_max_connections(64)
+//!         .with_max_requests_per_second(1000)
+//!         .with_max_peers(10000)
+//!         .with_idle_timeout(Duration::from_secs(300)),
+//! );
+//! ```
+//!
+//! The quota can be set to the server by `Server::peer_quota`, and the counters can be read from
+//! a clone of it by [`PeerQuota::stats`] and [`PeerQuota::peer`].
+//!
+//! # Eviction
+//!
+//! At most [`PeerQuotaConfig::with_max_peers`] peers are tracked. The table is split into
+//! [`PeerQuotaConfig::with_shards`] shards by the hashes of IPs, each of which tracks its part of
+//! the peers behind its own lock, so that the connections and requests of different peers rarely
+//! contend.
+//!
+//! When a new peer comes and its shard is full, peers without connections that have been idle for
+//! the idle timeout are evicted, and then the least recently seen peers without connections, until
+//! 1/8 of the shard is evicted. Evicting in batches makes the cost of scanning the shard amortized
+//! over the following new peers. If all peers of the shard have connections, the new peer is not
+//! tracked and not limited, which is counted by [`PeerQuotaStats::untracked`].
+
+use std::{
+    fmt,
+    net::IpAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use motore::{layer::Layer, service::Service};
+use parking_lot::Mutex;
+use volo::net::Address;
+
+use crate::{ApplicationExceptionKind, ServerError, context::ServerContext, error::volo_exception};
+
+const DEFAULT_MAX_PEERS: usize = 65536;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Configuration of [`PeerQuota`].
+#[derive(Clone, Copy, Debug)]
+pub struct PeerQuotaConfig {
+    max_connections: Option<usize>,
+    max_requests_per_second: Option<u32>,
+    max_peers: usize,
+    idle_timeout: Duration,
+    shards: usize,
+}
+
+impl Default for PeerQuotaConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeerQuotaConfig {
+    /// Create a config without any quota, which only tracks the peers.
+    pub fn new() -> Self {
+        Self {
+            max_connections: None,
+            max_requests_per_second: None,
+            max_peers: DEFAULT_MAX_PEERS,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            shards: std::thread::available_parallelism().map_or(1, usize::from) * 4,
+        }
+    }
+
+    /// Set the maximum number of concurrent connections of a peer.
+    ///
+    /// Connections exceeding the limit will be closed right after they are accepted.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Set the maximum number of requests per second of a peer, with bursts up to the same
+    /// number.
+    ///
+    /// Requests exceeding the limit will be rejected with an `ApplicationException`.
+    pub fn with_max_requests_per_second(mut self, max: u32) -> Self {
+        self.max_requests_per_second = Some(max);
+        self
+    }
+
+    /// Set the maximum number of tracked peers, default is 65536.
+    pub fn with_max_peers(mut self, max: usize) -> Self {
+        self.max_peers = max;
+        self
+    }
+
+    /// Set the time after which a peer without connections can be evicted, default is 60s.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Set the count of shards of the table, which is at most the maximum number of tracked
+    /// peers.
+    ///
+    /// Default is 4 times the available parallelism.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        assert!(shards > 0, "shards must be greater than zero");
+        self.shards = shards;
+        self
+    }
+}
+
+/// Counters of all peers.
+#[derive(Debug, Default)]
+pub struct PeerQuotaStats {
+    accepted_connections: AtomicU64,
+    rejected_connections: AtomicU64,
+    rejected_requests: AtomicU64,
+    evictions: AtomicU64,
+    untracked: AtomicU64,
+}
+
+impl PeerQuotaStats {
+    /// Number of connections from IP addresses accepted.
+    pub fn accepted_connections(&self) -> u64 {
+        self.accepted_connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections rejected by the connection quota.
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests rejected by the rate limit.
+    pub fn rejected_requests(&self) -> u64 {
+        self.rejected_requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of peers evicted from the table.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections accepted without tracking because the table is full.
+    pub fn untracked(&self) -> u64 {
+        self.untracked.load(Ordering::Relaxed)
+    }
+}
+
+/// Snapshot of the counters of a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerSnapshot {
+    /// Number of current connections.
+    pub connections: usize,
+    /// Number of requests, including the rejected ones.
+    pub requests: u64,
+    /// Number of connections rejected by the connection quota.
+    pub rejected_connections: u64,
+    /// Number of requests rejected by the rate limit.
+    pub rejected_requests: u64,
+}
+
+struct Peer {
+    connections: usize,
+    tokens: f64,
+    last_refill: Instant,
+    last_seen: Instant,
+    requests: u64,
+    rejected_connections: u64,
+    rejected_requests: u64,
+}
+
+impl Peer {
+    fn new(config: &PeerQuotaConfig, now: Instant) -> Self {
+        Self {
+            connections: 0,
+            tokens: config.max_requests_per_second.unwrap_or(0) as f64,
+            last_refill: now,
+            last_seen: now,
+            requests: 0,
+            rejected_connections: 0,
+            rejected_requests: 0,
+        }
+    }
+
+    /// Takes a token from the bucket, returns `false` if there is no token.
+    fn take_token(&mut self, rate: u32, now: Instant) -> bool {
+        let rate = rate as f64;
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct Shard {
+    peers: AHashMap<IpAddr, Peer>,
+    capacity: usize,
+}
+
+impl Shard {
+    /// Gets the peer from the shard, or inserts it if there is room after eviction.
+    fn peer(&mut self, inner: &Inner, ip: IpAddr, now: Instant) -> Option<&mut Peer> {
+        if !self.peers.contains_key(&ip) && self.peers.len() >= self.capacity {
+            self.evict(inner, now);
+            if self.peers.len() >= self.capacity {
+                return None;
+            }
+        }
+        Some(
+            self.peers
+                .entry(ip)
+                .or_insert_with(|| Peer::new(&inner.config, now)),
+        )
+    }
+
+    /// Evicts the idle peers, and then the least recently seen peers without connections until
+    /// 1/8 of the shard is evicted.
+    fn evict(&mut self, inner: &Inner, now: Instant) {
+        let before = self.peers.len();
+        let idle_timeout = inner.config.idle_timeout;
+        self.peers.retain(|_, peer| {
+            peer.connections > 0 || now.saturating_duration_since(peer.last_seen) < idle_timeout
+        });
+        let batch = (self.capacity / 8).max(1);
+        let evicted = before - self.peers.len();
+        if evicted < batch {
+            let mut idle = self
+                .peers
+                .iter()
+                .filter(|(_, peer)| peer.connections == 0)
+                .map(|(ip, peer)| (peer.last_seen, *ip))
+                .collect::<Vec<_>>();
+            let n = (batch - evicted).min(idle.len());
+            if n > 0 && n < idle.len() {
+                idle.select_nth_unstable(n - 1);
+            }
+            for (_, ip) in &idle[..n] {
+                self.peers.remove(ip);
+            }
+        }
+        inner
+            .stats
+            .evictions
+            .fetch_add((before - self.peers.len()) as u64, Ordering::Relaxed);
+    }
+}
+
+struct Inner {
+    config: PeerQuotaConfig,
+    shards: Box<[Mutex<Shard>]>,
+    hasher: ahash::RandomState,
+    stats: PeerQuotaStats,
+}
+
+impl Inner {
+    fn shard(&self, ip: &IpAddr) -> &Mutex<Shard> {
+        let index = self.hasher.hash_one(ip) as usize;
+        &self.shards[index % self.shards.len()]
+    }
+}
+
+/// Tracker of connections and request rates per peer IP, see the
+/// [module level documentation](self) for more details.
+///
+/// It is also a [`Layer`] for limiting the request rates, which is added by `Server::peer_quota`
+/// automatically.
+#[derive(Clone)]
+pub struct PeerQuota {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for PeerQuota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerQuota")
+            .field("config", &self.inner.config)
+            .field("stats", &self.inner.stats)
+            .finish()
+    }
+}
+
+impl PeerQuota {
+    /// Create a [`PeerQuota`] with the config.
+    pub fn new(config: PeerQuotaConfig) -> Self {
+        let count = config.shards.min(config.max_peers).max(1);
+        // split the capacity exactly, so that at most `max_peers` peers are tracked
+        let shards = (0..count)
+            .map(|i| {
+                Mutex::new(Shard {
+                    peers: AHashMap::new(),
+                    capacity: config.max_peers / count + usize::from(i < config.max_peers % count),
+                })
+            })
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                shards,
+                hasher: ahash::RandomState::new(),
+                stats: PeerQuotaStats::default(),
+            }),
+        }
+    }
+
+    /// Get the counters of all peers.
+    pub fn stats(&self) -> &PeerQuotaStats {
+        &self.inner.stats
+    }
+
+    /// Get the snapshot of the counters of a peer, `None` if it is not tracked.
+    pub fn peer(&self, ip: IpAddr) -> Option<PeerSnapshot> {
+        let ip = ip.to_canonical();
+        let shard = self.inner.shard(&ip).lock();
+        shard.peers.get(&ip).map(|peer| PeerSnapshot {
+            connections: peer.connections,
+            requests: peer.requests,
+            rejected_connections: peer.rejected_connections,
+            rejected_requests: peer.rejected_requests,
+        })
+    }
+
+    /// Number of tracked peers.
+    pub fn len(&self) -> usize {
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| shard.lock().peers.len())
+            .sum()
+    }
+
+    /// Returns `true` if no peer is tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Acquires a connection of the peer, returns `None` if the connection should be rejected.
+    ///
+    /// Connections not from IP addresses are always accepted without tracking.
+    pub(crate) fn acquire_connection(&self, addr: Option<&Address>) -> Option<ConnectionGuard> {
+        let Some(ip) = addr
+            .and_then(Address::ip_addr)
+            .map(|a| a.ip().to_canonical())
+        else {
+            return Some(ConnectionGuard { quota: None });
+        };
+        let inner = &self.inner;
+        let now = Instant::now();
+        let mut shard = inner.shard(&ip).lock();
+        let Some(peer) = shard.peer(inner, ip, now) else {
+            inner.stats.untracked.fetch_add(1, Ordering::Relaxed);
+            inner
+                .stats
+                .accepted_connections
+                .fetch_add(1, Ordering::Relaxed);
+            return Some(ConnectionGuard { quota: None });
+        };
+        peer.last_seen = now;
+        if inner
+            .config
+            .max_connections
+            .is_some_and(|max| peer.connections >= max)
+        {
+            peer.rejected_connections += 1;
+            inner
+                .stats
+                .rejected_connections
+                .fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("[VOLO] connection from {ip} is rejected by peer quota");
+            return None;
+        }
+        peer.connections += 1;
+        inner
+            .stats
+            .accepted_connections
+            .fetch_add(1, Ordering::Relaxed);
+        Some(ConnectionGuard {
+            quota: Some((self.clone(), ip)),
+        })
+    }
+
+    /// Checks the request rate of the peer, returns `false` if the request should be rejected.
+    fn check_request(&self, addr: Option<&Address>) -> bool {
+        let Some(ip) = addr
+            .and_then(Address::ip_addr)
+            .map(|a| a.ip().to_canonical())
+        else {
+            return true;
+        };
+        let inner = &self.inner;
+        let now = Instant::now();
+        let mut shard = inner.shard(&ip).lock();
+        let Some(peer) = shard.peer(inner, ip, now) else {
+            return true;
+        };
+        peer.last_seen = now;
+        peer.requests += 1;
+        match inner.config.max_requests_per_second {
+            Some(rate) if !peer.take_token(rate, now) => {
+                peer.rejected_requests += 1;
+                inner
+                    .stats
+                    .rejected_requests
+                    .fetch_add(1, Ordering::Relaxed);
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Releases the connection of the peer when dropped.
+pub(crate) struct ConnectionGuard {
+    quota: Option<(PeerQuota, IpAddr)>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let Some((quota, ip)) = &self.quota else {
+            return;
+        };
+        let mut shard = quota.inner.shard(ip).lock();
+        if let Some(peer) = shard.peers.get_mut(ip) {
+            peer.connections = peer.connections.saturating_sub(1);
+            peer.last_seen = Instant::now();
+        }
+    }
+}
+
+impl<S> Layer<S> for PeerQuota {
+    type Service = PeerQuotaService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        PeerQuotaService { inner, quota: self }
+    }
+}
+
+/// [`Service`] generated by [`PeerQuota`].
+#[derive(Clone)]
+pub struct PeerQuotaService<S> {
+    inner: S,
+    quota: PeerQuota,
+}
+
+impl<S, Req> Service<ServerContext, Req> for PeerQuotaService<S>
+where
+    S: Service<ServerContext, Req, Error = ServerError> + Send + Sync,
+    Req: Send,
+{
+    type Response = S::Response;
+    type Error = ServerError;
+
+    async fn call(&self, cx: &mut ServerContext, req: Req) -> Result<Self::Response, Self::Error> {
+        let addr = cx.rpc_info.caller().address();
+        if !self.quota.check_request(addr.as_ref()) {
+            let err = volo_exception(
+                ApplicationExceptionKind::INTERNAL_ERROR,
+                format_args!("request from {addr:?} is rejected by peer quota"),
+            );
+            tracing::debug!("{}", err.message());
+            return Err(err.into());
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use volo::context::Context;
+
+    use super::*;
+
+    fn addr(s: &str) -> Address {
+        Address::from(s.parse::<SocketAddr>().unwrap())
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn connection_quota() {
+        let quota = PeerQuota::new(PeerQuotaConfig::new().with_max_connections(2));
+        let a = addr("10.0.0.1:8000");
+        let g1 = quota.acquire_connection(Some(&a)).unwrap();
+        let _g2 = quota
+            .acquire_connection(Some(&addr("10.0.0.1:8001")))
+            .unwrap();
+        assert!(quota.acquire_connection(Some(&a)).is_none());
+        // other peers are not affected
+        let _g3 = quota
+            .acquire_connection(Some(&addr("10.0.0.2:8000")))
+            .unwrap();
+        // IPv4-mapped IPv6 addresses are the same peer
+        assert!(
+            quota
+                .acquire_connection(Some(&addr("[::ffff:10.0.0.1]:8000")))
+                .is_none()
+        );
+        // non-IP peers are not tracked
+        assert!(quota.acquire_connection(None).is_some());
+
+        drop(g1);
+        assert!(quota.acquire_connection(Some(&a)).is_some());
+
+        let snapshot = quota.peer(ip("10.0.0.1")).unwrap();
+        assert_eq!(snapshot.connections, 1);
+        assert_eq!(snapshot.rejected_connections, 2);
+        assert_eq!(quota.stats().rejected_connections(), 2);
+        assert_eq!(quota.stats().accepted_connections(), 4);
+    }
+
+    #[test]
+    fn eviction() {
+        let quota = PeerQuota::new(
+            PeerQuotaConfig::new()
+                .with_max_peers(2)
+                .with_max_connections(1)
+                .with_shards(1),
+        );
+        let _g1 = quota
+            .acquire_connection(Some(&addr("10.0.0.1:8000")))
+            .unwrap();
+        drop(quota.acquire_connection(Some(&addr("10.0.0.2:8000"))));
+        // the idle peer is evicted
+        let g3 = quota
+            .acquire_connection(Some(&addr("10.0.0.3:8000")))
+            .unwrap();
+        assert_eq!(quota.len(), 2);
+        assert!(quota.peer(ip("10.0.0.2")).is_none());
+        assert_eq!(quota.stats().evictions(), 1);
+
+        // all peers have connections, the new one is not tracked
+        let _g4 = quota
+            .acquire_connection(Some(&addr("10.0.0.4:8000")))
+            .unwrap();
+        let _g5 = quota
+            .acquire_connection(Some(&addr("10.0.0.4:8001")))
+            .unwrap();
+        assert!(quota.peer(ip("10.0.0.4")).is_none());
+        assert_eq!(quota.stats().untracked(), 2);
+        drop(g3);
+        assert_eq!(quota.peer(ip("10.0.0.3")).unwrap().connections, 0);
+    }
+
+    #[test]
+    fn batch_eviction() {
+        let quota = PeerQuota::new(PeerQuotaConfig::new().with_max_peers(16).with_shards(1));
+        let _g = quota
+            .acquire_connection(Some(&addr("10.0.0.0:8000")))
+            .unwrap();
+        for i in 1..16 {
+            drop(quota.acquire_connection(Some(&addr(&format!("10.0.0.{i}:8000")))));
+        }
+        assert_eq!(quota.len(), 16);
+        // order the peers by the last seen time
+        let now = Instant::now();
+        for (ip, peer) in quota.inner.shards[0].lock().peers.iter_mut() {
+            let IpAddr::V4(v4) = ip else { unreachable!() };
+            peer.last_seen = now - Duration::from_secs(16 - u64::from(v4.octets()[3]));
+        }
+
+        // the 2 least recently seen peers without connections are evicted at once
+        drop(quota.acquire_connection(Some(&addr("10.0.1.0:8000"))));
+        assert_eq!(quota.stats().evictions(), 2);
+        assert_eq!(quota.len(), 15);
+        assert!(quota.peer(ip("10.0.0.0")).is_some());
+        assert!(quota.peer(ip("10.0.0.1")).is_none());
+        assert!(quota.peer(ip("10.0.0.2")).is_none());
+        assert!(quota.peer(ip("10.0.0.3")).is_some());
+        // there is room for the next new peer without eviction
+        drop(quota.acquire_connection(Some(&addr("10.0.1.1:8000"))));
+        assert_eq!(quota.stats().evictions(), 2);
+    }
+
+    #[test]
+    fn shards() {
+        let quota = PeerQuota::new(PeerQuotaConfig::new().with_max_peers(10).with_shards(4));
+        let capacity = quota
+            .inner
+            .shards
+            .iter()
+            .map(|shard| shard.lock().capacity)
+            .collect::<Vec<_>>();
+        assert_eq!(capacity, [3, 3, 2, 2]);
+        for i in 0..64 {
+            drop(quota.acquire_connection(Some(&addr(&format!("10.0.0.{i}:8000")))));
+        }
+        assert!(quota.len() <= 10);
+    }
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<ServerContext, ()> for Echo {
+        type Response = ();
+        type Error = ServerError;
+
+        async fn call(&self, _: &mut ServerContext, _: ()) -> Result<(), ServerError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn request_rate() {
+        let quota = PeerQuota::new(PeerQuotaConfig::new().with_max_requests_per_second(2));
+        let svc = quota.clone().layer(Echo);
+        let cx = |s: &str| {
+            let mut cx = ServerContext::default();
+            cx.rpc_info_mut().caller_mut().set_address(addr(s));
+            cx
+        };
+
+        assert!(svc.call(&mut cx("10.0.0.1:8000"), ()).await.is_ok());
+        assert!(svc.call(&mut cx("10.0.0.1:8000"), ()).await.is_ok());
+        assert!(matches!(
+            svc.call(&mut cx("10.0.0.1:8000"), ()).await,
+            Err(ServerError::Application(_))
+        ));
+        assert!(svc.call(&mut cx("10.0.0.2:8000"), ()).await.is_ok());
+        assert!(svc.call(&mut ServerContext::default(), ()).await.is_ok());
+
+        let snapshot = quota.peer(ip("10.0.0.1")).unwrap();
+        assert_eq!(snapshot.requests, 3);
+        assert_eq!(snapshot.rejected_requests, 1);
+        assert_eq!(quota.stats().rejected_requests(), 1);
+
+        // refilled after a while
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(svc.call(&mut cx("10.0.0.1:8000"), ()).await.is_ok());
+    }
+}
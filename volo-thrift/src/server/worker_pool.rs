@@ -0,0 +1,356 @@
+//! Worker pool isolation for groups of methods.
+//!
+//! Methods sharing the same server also share the same set of resources by default, so that a
+//! burst of heavy requests (e.g., analytical queries) may starve latency-critical methods.
+//!
+//! [`WorkerPoolLayer`] routes configured groups of methods onto dedicated bounded worker pools.
+//! Each pool has its own concurrency limit and its own waiting queue, when the queue of a pool is
+//! full, requests of the pool will be rejected immediately without affecting other pools.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_thrift::server::worker_pool::{WorkerPoolConfig, WorkerPoolLayer};
+//!
+//! let layer = WorkerPoolLayer::new()
+//!     .group(
+//!         "analytics",
+//!         ["Query", "Report"],
+//!         WorkerPoolConfig::new(8)
+//!             .with_max_pending(64)
+//!             .with_queue_timeout(Duration::from_millis(500)),
+//!     )
+//!     .default_group(WorkerPoolConfig::new(1024));
+//! ```
+//!
+//! The layer can be added to the server by `Server::layer`.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use motore::{layer::Layer, service::Service};
+use pilota::FastStr;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::{ApplicationException, ApplicationExceptionKind, ServerError, context::ServerContext};
+
+/// Configuration of a worker pool.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerPoolConfig {
+    max_concurrency: usize,
+    max_pending: usize,
+    queue_timeout: Option<Duration>,
+}
+
+impl WorkerPoolConfig {
+    /// Create a config with maximum number of requests processed concurrently in the pool.
+    ///
+    /// By default, the waiting queue is unbounded and there is no timeout for waiting.
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            max_concurrency,
+            max_pending: usize::MAX,
+            queue_timeout: None,
+        }
+    }
+
+    /// Set the maximum number of requests waiting for the pool.
+    ///
+    /// Requests exceeding the limit will be rejected immediately.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Set the maximum time a request can wait in the queue of the pool.
+    pub fn with_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = Some(timeout);
+        self
+    }
+}
+
+/// Counts a request waiting for the pool until it is dropped.
+struct PendingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// A bounded worker pool, requests will be processed only if there is an idle worker.
+pub struct WorkerPool {
+    name: FastStr,
+    config: WorkerPoolConfig,
+    workers: Semaphore,
+    pending: AtomicUsize,
+}
+
+impl fmt::Debug for WorkerPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerPool")
+            .field("name", &self.name)
+            .field("config", &self.config)
+            .field("in_flight", &self.in_flight())
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl WorkerPool {
+    fn new(name: FastStr, config: WorkerPoolConfig) -> Self {
+        Self {
+            name,
+            config,
+            workers: Semaphore::new(config.max_concurrency.min(Semaphore::MAX_PERMITS)),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Name of the pool.
+    pub fn name(&self) -> &FastStr {
+        &self.name
+    }
+
+    /// Number of requests being processed in the pool.
+    pub fn in_flight(&self) -> usize {
+        self.config
+            .max_concurrency
+            .min(Semaphore::MAX_PERMITS)
+            .saturating_sub(self.workers.available_permits())
+    }
+
+    /// Number of requests waiting for an idle worker.
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>, ServerError> {
+        // fast path
+        if let Ok(permit) = self.workers.try_acquire() {
+            return Ok(permit);
+        }
+
+        if self.pending.fetch_add(1, Ordering::AcqRel) >= self.config.max_pending {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+            return Err(self.overloaded("queue is full"));
+        }
+        // decrease the counter even if the future is cancelled while waiting
+        let _pending = PendingGuard(&self.pending);
+        let res = match self.config.queue_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.workers.acquire())
+                .await
+                .map_err(|_| self.overloaded("queue timeout")),
+            None => Ok(self.workers.acquire().await),
+        };
+
+        // The semaphore is never closed
+        res.map(|permit| permit.expect("worker pool semaphore closed"))
+    }
+
+    fn overloaded(&self, reason: &str) -> ServerError {
+        let msg = format!("[VOLO] worker pool `{}` is overloaded: {reason}", self.name);
+        tracing::warn!("{msg}");
+        ApplicationException::new(ApplicationExceptionKind::INTERNAL_ERROR, msg).into()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Pools {
+    groups: Vec<Arc<WorkerPool>>,
+    methods: AHashMap<FastStr, Arc<WorkerPool>>,
+    default: Option<Arc<WorkerPool>>,
+}
+
+impl Pools {
+    fn get(&self, method: &str) -> Option<&Arc<WorkerPool>> {
+        self.methods.get(method).or(self.default.as_ref())
+    }
+}
+
+/// A [`Layer`] for isolating groups of methods onto dedicated worker pools.
+///
+/// Methods not belonging to any group will be processed in the default group if it is set by
+/// [`WorkerPoolLayer::default_group`], or without any limit.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Clone, Default)]
+pub struct WorkerPoolLayer {
+    pools: Pools,
+}
+
+impl WorkerPoolLayer {
+    /// Create an empty [`WorkerPoolLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a group of methods with a dedicated worker pool.
+    ///
+    /// If a method has been added to another group, it will be moved to the new group.
+    pub fn group<N, I, M>(mut self, name: N, methods: I, config: WorkerPoolConfig) -> Self
+    where
+        N: Into<FastStr>,
+        I: IntoIterator<Item = M>,
+        M: Into<FastStr>,
+    {
+        let pool = Arc::new(WorkerPool::new(name.into(), config));
+        for method in methods {
+            self.pools.methods.insert(method.into(), pool.clone());
+        }
+        self.pools.groups.push(pool);
+        self
+    }
+
+    /// Set the worker pool for methods not belonging to any group.
+    pub fn default_group(mut self, config: WorkerPoolConfig) -> Self {
+        self.pools.default = Some(Arc::new(WorkerPool::new(
+            FastStr::from_static_str("default"),
+            config,
+        )));
+        self
+    }
+
+    /// Get all worker pools, including the default one, this is useful for reporting metrics.
+    pub fn pools(&self) -> impl Iterator<Item = &Arc<WorkerPool>> {
+        self.pools.groups.iter().chain(self.pools.default.iter())
+    }
+}
+
+impl<S> Layer<S> for WorkerPoolLayer {
+    type Service = WorkerPoolService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        WorkerPoolService {
+            inner,
+            pools: Arc::new(self.pools),
+        }
+    }
+}
+
+/// [`Service`] generated by [`WorkerPoolLayer`].
+#[derive(Clone)]
+pub struct WorkerPoolService<S> {
+    inner: S,
+    pools: Arc<Pools>,
+}
+
+impl<S, Req> Service<ServerContext, Req> for WorkerPoolService<S>
+where
+    S: Service<ServerContext, Req, Error = ServerError> + Send + Sync,
+    Req: Send,
+{
+    type Response = S::Response;
+    type Error = ServerError;
+
+    async fn call(&self, cx: &mut ServerContext, req: Req) -> Result<Self::Response, Self::Error> {
+        let Some(pool) = self.pools.get(cx.rpc_info.method()) else {
+            return self.inner.call(cx, req).await;
+        };
+        let _permit = pool.acquire().await?;
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::oneshot;
+    use volo::context::Context;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct BlockingService {
+        rx: Arc<parking_lot::Mutex<Option<oneshot::Receiver<()>>>>,
+    }
+
+    impl Service<ServerContext, ()> for BlockingService {
+        type Response = ();
+        type Error = ServerError;
+
+        async fn call(&self, cx: &mut ServerContext, _: ()) -> Result<(), ServerError> {
+            if cx.rpc_info().method() != "Heavy" {
+                return Ok(());
+            }
+            let rx = self.rx.lock().take();
+            if let Some(rx) = rx {
+                let _ = rx.await;
+            }
+            Ok(())
+        }
+    }
+
+    fn cx(method: &'static str) -> ServerContext {
+        let mut cx = ServerContext::default();
+        cx.rpc_info_mut()
+            .set_method(FastStr::from_static_str(method));
+        cx
+    }
+
+    #[tokio::test]
+    async fn isolation() {
+        let (tx, rx) = oneshot::channel();
+        let layer = WorkerPoolLayer::new()
+            .group(
+                "heavy",
+                ["Heavy"],
+                WorkerPoolConfig::new(1).with_max_pending(0),
+            )
+            .default_group(WorkerPoolConfig::new(1));
+        let svc = Arc::new(layer.layer(BlockingService {
+            rx: Arc::new(parking_lot::Mutex::new(Some(rx))),
+        }));
+
+        // occupy the only worker of `heavy`
+        let svc2 = svc.clone();
+        let task = tokio::spawn(async move { svc2.call(&mut cx("Heavy"), ()).await });
+        tokio::task::yield_now().await;
+        while svc.pools.groups[0].in_flight() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // `heavy` is full and its queue is disabled
+        assert!(matches!(
+            svc.call(&mut cx("Heavy"), ()).await,
+            Err(ServerError::Application(_))
+        ));
+        // other methods are not affected
+        assert!(svc.call(&mut cx("Light"), ()).await.is_ok());
+
+        tx.send(()).unwrap();
+        assert!(task.await.unwrap().is_ok());
+        assert_eq!(svc.pools.groups[0].in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn queue_timeout() {
+        let pool = WorkerPool::new(
+            FastStr::from_static_str("test"),
+            WorkerPoolConfig::new(1).with_queue_timeout(Duration::from_millis(10)),
+        );
+        let permit = pool.acquire().await.unwrap();
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.pending(), 0);
+        drop(permit);
+        assert!(pool.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiting() {
+        let pool = WorkerPool::new(FastStr::from_static_str("test"), WorkerPoolConfig::new(1));
+        let _permit = pool.acquire().await.unwrap();
+        // the request is cancelled, e.g., by timeout, while waiting for a worker
+        let res = tokio::time::timeout(Duration::from_millis(10), pool.acquire()).await;
+        assert!(res.is_err());
+        assert_eq!(pool.pending(), 0);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    io,
+    marker::PhantomData,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use bytes::{Bytes, BytesMut};
+use linkedbytes::LinkedBytes;
+use motore::service::Service;
+use parking_lot::RwLock;
+use tokio::{
+    net::UdpSocket,
+    sync::mpsc::{self, error::TrySendError},
+    time::Instant,
+};
+use volo::net::Address;
+
+use super::Config;
+use crate::{
+    ClientError, EntryMessage, ThriftMessage,
+    codec::default::{MakeZeroCopyCodec, ZeroCopyEncoder},
+    context::ClientContext,
+    protocol::TMessageType,
+};
+
+// frames of an address are sent by a socket kept until it's idle for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The client of the datagram transport, which encodes the requests of oneway methods into frames
+/// and queues them to be batched into datagrams.
+///
+/// See the [module docs](super) for more details.
+pub struct Client<Resp, MkZC> {
+    make_codec: MkZC,
+    queues: Arc<Queues>,
+    _marker: PhantomData<fn() -> Resp>,
+}
+
+impl<Resp, MkZC: Clone> Clone for Client<Resp, MkZC> {
+    fn clone(&self) -> Self {
+        Self {
+            make_codec: self.make_codec.clone(),
+            queues: self.queues.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Resp, MkZC: MakeZeroCopyCodec> Client<Resp, MkZC> {
+    pub fn new(make_codec: MkZC, config: Config) -> Self {
+        Self {
+            make_codec,
+            queues: Arc::new(Queues {
+                config,
+                next_id: AtomicU64::new(0),
+                queues: RwLock::new(AHashMap::new()),
+            }),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp, MkZC> Service<ClientContext, Req> for Client<Resp, MkZC>
+where
+    Req: EntryMessage + Send + 'static,
+    Resp: Send + 'static,
+    MkZC: MakeZeroCopyCodec + Sync,
+{
+    type Response = Option<Resp>;
+
+    type Error = ClientError;
+
+    async fn call(&self, cx: &mut ClientContext, req: Req) -> Result<Self::Response, Self::Error> {
+        if cx.message_type != TMessageType::OneWay {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "the datagram transport only supports oneway methods, method: {}",
+                    cx.rpc_info.method()
+                ),
+            )
+            .into());
+        }
+        let target = match cx.rpc_info.callee().address() {
+            Some(Address::Ip(addr)) => addr,
+            target => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("an ip address is required for datagrams, target: {target:?}"),
+                )
+                .into());
+            }
+        };
+
+        let msg = ThriftMessage::mk_client_msg(cx, req);
+        let (mut encoder, _) = self.make_codec.make_codec();
+        cx.common_stats.record_encode_start_at();
+        let (real_size, malloc_size) = encoder.size(cx, &msg)?;
+        cx.common_stats.set_write_size(real_size);
+        let max_size = self.queues.config.max_datagram_size;
+        if real_size > max_size {
+            self.queues.config.stats.record_dropped(1);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame of {real_size} bytes exceeds the max datagram size {max_size}"),
+            )
+            .into());
+        }
+        let mut linked_bytes = LinkedBytes::with_capacity(malloc_size);
+        encoder.encode(cx, &mut linked_bytes, msg)?;
+        cx.common_stats.record_encode_end_at();
+
+        cx.common_stats.record_write_start_at();
+        self.queues.send(target, linked_bytes.concat().freeze())?;
+        cx.common_stats.record_write_end_at();
+        Ok(None)
+    }
+}
+
+struct Queue {
+    id: u64,
+    tx: mpsc::Sender<Bytes>,
+}
+
+/// Queues of frames to each address, each of them is consumed by a task batching its frames into
+/// datagrams.
+struct Queues {
+    config: Config,
+    next_id: AtomicU64,
+    queues: RwLock<AHashMap<SocketAddr, Queue>>,
+}
+
+impl Queues {
+    fn send(self: &Arc<Self>, target: SocketAddr, frame: Bytes) -> io::Result<()> {
+        if let Some(queue) = self.queues.read().get(&target) {
+            return self.try_send(target, &queue.tx, frame);
+        }
+        let mut queues = self.queues.write();
+        let queue = queues.entry(target).or_insert_with(|| {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let (tx, rx) = mpsc::channel(self.config.queue_size);
+            tokio::spawn(batch(Arc::downgrade(self), id, target, rx));
+            Queue { id, tx }
+        });
+        self.try_send(target, &queue.tx, frame)
+    }
+
+    fn try_send(
+        &self,
+        target: SocketAddr,
+        tx: &mpsc::Sender<Bytes>,
+        frame: Bytes,
+    ) -> io::Result<()> {
+        tx.try_send(frame).map_err(|err| {
+            self.config.stats.record_dropped(1);
+            match err {
+                TrySendError::Full(_) => io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("the queue of datagrams to {target} is full"),
+                ),
+                TrySendError::Closed(_) => io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    format!("the queue of datagrams to {target} is closed"),
+                ),
+            }
+        })
+    }
+
+    /// Remove the queue, so that no more frames are sent to it.
+    fn remove(&self, target: SocketAddr, id: u64) {
+        let mut queues = self.queues.write();
+        if queues.get(&target).is_some_and(|queue| queue.id == id) {
+            queues.remove(&target);
+        }
+    }
+}
+
+enum Socket {
+    Udp(UdpSocket),
+    #[cfg(feature = "datagram-quic")]
+    Quic(quinn::Connection),
+}
+
+impl Socket {
+    async fn connect(target: SocketAddr, config: &Config) -> io::Result<Self> {
+        #[cfg(feature = "datagram-quic")]
+        if let Some(connector) = &config.quic {
+            return connector.connect(target).await.map(Self::Quic);
+        }
+        #[cfg(not(feature = "datagram-quic"))]
+        let _ = config;
+
+        let bind = if target.is_ipv6() {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(target).await?;
+        Ok(Self::Udp(socket))
+    }
+
+    fn max_datagram_size(&self, max: usize) -> usize {
+        match self {
+            Self::Udp(_) => max,
+            #[cfg(feature = "datagram-quic")]
+            Self::Quic(conn) => conn.max_datagram_size().map_or(max, |size| size.min(max)),
+        }
+    }
+
+    async fn send(&self, datagram: Bytes) -> io::Result<()> {
+        match self {
+            Self::Udp(socket) => socket.send(&datagram).await.map(|_| ()),
+            #[cfg(feature = "datagram-quic")]
+            Self::Quic(conn) => conn.send_datagram(datagram).map_err(io::Error::other),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Udp(_) => false,
+            #[cfg(feature = "datagram-quic")]
+            Self::Quic(conn) => conn.close_reason().is_some(),
+        }
+    }
+}
+
+/// A datagram being filled with frames.
+struct Batch<'a> {
+    socket: &'a Socket,
+    target: SocketAddr,
+    config: &'a Config,
+    buf: BytesMut,
+    frames: usize,
+}
+
+impl Batch<'_> {
+    async fn push(&mut self, frame: Bytes) {
+        let max_size = self.socket.max_datagram_size(self.config.max_datagram_size);
+        if !self.buf.is_empty() && self.buf.len() + frame.len() > max_size {
+            self.flush().await;
+        }
+        self.buf.extend_from_slice(&frame);
+        self.frames += 1;
+    }
+
+    async fn flush(&mut self) {
+        if self.frames == 0 {
+            return;
+        }
+        let frames = std::mem::take(&mut self.frames);
+        match self.socket.send(self.buf.split().freeze()).await {
+            Ok(()) => self.config.stats.record_sent(frames),
+            Err(e) => {
+                tracing::debug!(
+                    "[VOLO] failed to send datagram of {frames} frames to {}: {e}",
+                    self.target
+                );
+                self.config.stats.record_dropped(frames);
+            }
+        }
+    }
+}
+
+/// Batch the frames of the queue into datagrams until the client is dropped, the queue is idle or
+/// the QUIC connection is closed.
+async fn batch(queues: Weak<Queues>, id: u64, target: SocketAddr, mut rx: mpsc::Receiver<Bytes>) {
+    let Some(config) = queues.upgrade().map(|queues| queues.config.clone()) else {
+        return;
+    };
+    let socket = match Socket::connect(target, &config).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::warn!("[VOLO] failed to make datagram socket to {target}: {e}");
+            close(&queues, id, target, &mut rx);
+            let mut dropped = 0;
+            while rx.try_recv().is_ok() {
+                dropped += 1;
+            }
+            config.stats.record_dropped(dropped);
+            return;
+        }
+    };
+    let mut batch = Batch {
+        socket: &socket,
+        target,
+        config: &config,
+        buf: BytesMut::with_capacity(config.max_datagram_size),
+        frames: 0,
+    };
+
+    loop {
+        let frame = match tokio::time::timeout(IDLE_TIMEOUT, rx.recv()).await {
+            Ok(Some(frame)) => frame,
+            // the client is dropped
+            Ok(None) => break,
+            Err(_) => {
+                // send the frames queued before the queue is removed
+                close(&queues, id, target, &mut rx);
+                while let Ok(frame) = rx.try_recv() {
+                    batch.push(frame).await;
+                }
+                break;
+            }
+        };
+        batch.push(frame).await;
+        if config.flush_delay.is_zero() {
+            while let Ok(frame) = rx.try_recv() {
+                batch.push(frame).await;
+            }
+        } else {
+            let deadline = Instant::now() + config.flush_delay;
+            while let Ok(Some(frame)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+                batch.push(frame).await;
+            }
+        }
+        batch.flush().await;
+
+        if socket.is_closed() {
+            tracing::debug!("[VOLO] datagram connection to {target} is closed");
+            close(&queues, id, target, &mut rx);
+            let mut dropped = 0;
+            while rx.try_recv().is_ok() {
+                dropped += 1;
+            }
+            config.stats.record_dropped(dropped);
+            return;
+        }
+    }
+    batch.flush().await;
+}
+
+/// Remove the queue for calls to make a new one, and close it to receive the frames left.
+fn close(queues: &Weak<Queues>, id: u64, target: SocketAddr, rx: &mut mpsc::Receiver<Bytes>) {
+    if let Some(queues) = queues.upgrade() {
+        queues.remove(target, id);
+    }
+    rx.close();
+}
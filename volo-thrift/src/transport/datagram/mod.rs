@@ -0,0 +1,347 @@
+//! Datagram transport for oneway methods, e.g., for ingesting high-volume telemetry or metrics.
+//!
+//! Requests of oneway methods are not replied, so they don't need the ordering or the state of
+//! connections. The datagram transport sends them by UDP, or by unreliable QUIC datagrams
+//! (RFC 9221) with the `datagram-quic` feature, so a lost or slow datagram never blocks the
+//! following ones:
+//!
+//! - Frames to the same address are batched into datagrams of at most
+//!   [`Config::max_datagram_size`], waiting at most [`Config::flush_delay`] for more frames.
+//! - The delivery is best effort. A call returns after its frame is queued, and frames are lost
+//!   silently if the datagrams are dropped by the network or the server. The frames failing to be
+//!   queued or sent are counted by [`DatagramStats`].
+//! - Only oneway methods are supported, calls of other methods fail without being sent.
+//!
+//! Each datagram carries complete frames only, so the codec must be length-prefixed, i.e.,
+//! TTHeader or Framed, as the default codec `TTHeader<Framed<Binary>>` is.
+//!
+//! ```ignore
+//! use volo_thrift::transport::datagram;
+//!
+//! let client = TelemetryServiceClientBuilder::new("telemetry")
+//!     .address(addr)
+//!     .build_datagram(datagram::Config::new().flush_delay(Duration::from_millis(1)));
+//! client.report(metrics).await?;
+//!
+//! Server::new(TelemetryServiceServer::new(Handler))
+//!     .run_datagram(addr)
+//!     .await?;
+//! ```
+//!
+//! See [`ClientBuilder::build_datagram`] and [`Server::run_datagram`] for more details.
+//!
+//! [`ClientBuilder::build_datagram`]: crate::client::ClientBuilder::build_datagram
+//! [`Server::run_datagram`]: crate::server::Server::run_datagram
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes};
+
+mod client;
+#[cfg(feature = "datagram-quic")]
+mod quic;
+pub(crate) mod server;
+
+pub use client::Client;
+
+// fits into a datagram of the Ethernet MTU of 1500 bytes over IPv6 without fragmentation
+const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1452;
+// the max payload size of a UDP datagram over IPv4
+const MAX_DATAGRAM_SIZE: usize = 65507;
+const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+/// Config of the clients using the datagram transport.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub(crate) max_datagram_size: usize,
+    pub(crate) flush_delay: Duration,
+    pub(crate) queue_size: usize,
+    #[cfg(feature = "datagram-quic")]
+    pub(crate) quic: Option<Arc<quic::Connector>>,
+    pub(crate) stats: Arc<DatagramStats>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            flush_delay: Duration::ZERO,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            #[cfg(feature = "datagram-quic")]
+            quic: None,
+            stats: Default::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Create a [`Config`] with default config.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum size of a datagram, and calls whose frames are larger fail.
+    ///
+    /// Default is `1452`, which avoids fragmentation in most networks. For QUIC, datagrams are
+    /// further limited by the size allowed by the connection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero or larger than `65507`.
+    pub fn max_datagram_size(mut self, size: usize) -> Self {
+        assert!(
+            size > 0 && size <= MAX_DATAGRAM_SIZE,
+            "max datagram size must be in 1..={MAX_DATAGRAM_SIZE}"
+        );
+        self.max_datagram_size = size;
+        self
+    }
+
+    /// Set the time to wait for more frames before sending a datagram.
+    ///
+    /// Default is zero, which means only the frames already queued are batched, so the latency is
+    /// not increased. A small delay (e.g., a millisecond) makes datagrams fuller at the cost of
+    /// latency.
+    pub fn flush_delay(mut self, delay: Duration) -> Self {
+        self.flush_delay = delay;
+        self
+    }
+
+    /// Set the maximum number of frames queued for each address, and calls fail when the queue
+    /// is full.
+    ///
+    /// Default is `1024`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn queue_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "queue size must be greater than zero");
+        self.queue_size = size;
+        self
+    }
+
+    /// Send frames by unreliable QUIC datagrams (RFC 9221) with the TLS config instead of UDP,
+    /// and the server should be run by
+    /// [`Server::run_quic_datagram`](crate::server::Server::run_quic_datagram).
+    ///
+    /// Returns an error if the TLS connector is not built by rustls.
+    #[cfg(feature = "datagram-quic")]
+    pub fn quic(mut self, tls_config: volo::net::tls::ClientTlsConfig) -> std::io::Result<Self> {
+        self.quic = Some(Arc::new(quic::Connector::new(tls_config)?));
+        Ok(self)
+    }
+
+    /// Get the stats of datagrams, which are shared by all clients built with the config.
+    pub fn stats(&self) -> Arc<DatagramStats> {
+        self.stats.clone()
+    }
+}
+
+/// Stats of datagrams sent by clients using the datagram transport.
+#[derive(Debug, Default)]
+pub struct DatagramStats {
+    datagrams: AtomicU64,
+    frames: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl DatagramStats {
+    pub(crate) fn record_sent(&self, frames: usize) {
+        self.datagrams.fetch_add(1, Ordering::Relaxed);
+        self.frames.fetch_add(frames as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped(&self, frames: usize) {
+        self.dropped.fetch_add(frames as u64, Ordering::Relaxed);
+    }
+
+    /// Number of datagrams sent.
+    pub fn datagrams(&self) -> u64 {
+        self.datagrams.load(Ordering::Relaxed)
+    }
+
+    /// Number of frames sent in all datagrams.
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Number of frames failed to be queued or sent.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Average number of frames in a datagram.
+    pub fn avg_frames(&self) -> f64 {
+        match self.datagrams() {
+            0 => 0.0,
+            datagrams => self.frames() as f64 / datagrams as f64,
+        }
+    }
+}
+
+/// Split the next frame from a datagram by its 4-byte length prefix, returns [`None`] if the rest
+/// of the datagram is not a complete frame.
+pub(crate) fn split_frame(datagram: &mut Bytes) -> Option<Bytes> {
+    if datagram.remaining() < 4 {
+        return None;
+    }
+    let size = u32::from_be_bytes(datagram[..4].try_into().unwrap()) as usize;
+    if datagram.remaining() - 4 < size {
+        return None;
+    }
+    Some(datagram.split_to(size + 4))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use motore::service::Service;
+    use tokio::sync::mpsc;
+    use volo::{FastStr, client::MkClient};
+
+    use super::*;
+    use crate::{ServerError, client::ClientBuilder, context::ServerContext, server::Server};
+
+    struct Collect(mpsc::UnboundedSender<(FastStr, Bytes)>);
+
+    impl Service<ServerContext, Bytes> for Collect {
+        type Response = Bytes;
+        type Error = ServerError;
+
+        async fn call(&self, cx: &mut ServerContext, req: Bytes) -> Result<Bytes, ServerError> {
+            let _ = self.0.send((cx.rpc_info.method().clone(), req));
+            Ok(Bytes::new())
+        }
+    }
+
+    struct MkRaw;
+
+    impl<S> MkClient<crate::Client<S>> for MkRaw {
+        type Target = crate::Client<S>;
+
+        fn mk_client(&self, service: crate::Client<S>) -> Self::Target {
+            service
+        }
+    }
+
+    fn free_addr() -> std::net::SocketAddr {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    async fn report_metrics<S>(
+        client: &crate::Client<S>,
+        rx: &mut mpsc::UnboundedReceiver<(FastStr, Bytes)>,
+    ) where
+        S: Service<
+                crate::context::ClientContext,
+                Bytes,
+                Response = Option<Bytes>,
+                Error = crate::ClientError,
+            > + Send
+            + Sync
+            + 'static,
+    {
+        for i in 0..3 {
+            let mut cx = client.make_cx("Report", true);
+            let resp = client
+                .call(&mut cx, Bytes::from(format!("metric-{i}")))
+                .await;
+            assert!(resp.unwrap().is_none());
+        }
+        // only oneway methods are supported
+        let mut cx = client.make_cx("Query", false);
+        assert!(client.call(&mut cx, Bytes::new()).await.is_err());
+
+        for i in 0..3 {
+            let (method, req) = rx.recv().await.unwrap();
+            assert_eq!(method, "Report");
+            assert_eq!(req, format!("metric-{i}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn oneway_by_udp() {
+        let addr = free_addr();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(Server::new(Collect(tx)).run_datagram(addr));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let config = Config::new().flush_delay(Duration::from_millis(50));
+        let stats = config.stats();
+        let client = ClientBuilder::<_, _, _, Bytes, Bytes, _, _, _>::new("telemetry", MkRaw)
+            .address(addr)
+            .build_datagram(config);
+        report_metrics(&client, &mut rx).await;
+
+        // all frames are batched into a datagram
+        assert_eq!(stats.datagrams(), 1);
+        assert_eq!(stats.frames(), 3);
+        assert_eq!(stats.dropped(), 0);
+
+        let mut cx = client.make_cx("Report", true);
+        let large = Bytes::from(vec![0; DEFAULT_MAX_DATAGRAM_SIZE]);
+        assert!(client.call(&mut cx, large).await.is_err());
+        assert_eq!(stats.dropped(), 1);
+    }
+
+    #[cfg(feature = "datagram-quic")]
+    #[tokio::test]
+    async fn oneway_by_quic() {
+        use volo::net::tls::{ClientTlsConfig, ServerTlsConfig, TlsConnector};
+
+        let cert_path =
+            |name: &str| format!("{}/../examples/data/tls/{name}", env!("CARGO_MANIFEST_DIR"));
+        let addr = free_addr();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let tls_config =
+            ServerTlsConfig::from_pem_file(cert_path("server.pem"), cert_path("server.key"))
+                .unwrap();
+        tokio::spawn(Server::new(Collect(tx)).run_quic_datagram(addr, tls_config));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let connector = TlsConnector::builder()
+            .enable_default_root_certs(false)
+            .add_pem_from_file(cert_path("ca.pem"))
+            .unwrap()
+            .build()
+            .unwrap();
+        let config = Config::new()
+            .quic(ClientTlsConfig::new("localhost", connector))
+            .unwrap();
+        let stats = config.stats();
+        let client = ClientBuilder::<_, _, _, Bytes, Bytes, _, _, _>::new("telemetry", MkRaw)
+            .address(addr)
+            .build_datagram(config);
+        report_metrics(&client, &mut rx).await;
+        assert_eq!(stats.frames(), 3);
+    }
+
+    #[test]
+    fn split_frames() {
+        let mut buf = BytesMut::new();
+        buf.put_u32(3);
+        buf.put_slice(b"abc");
+        buf.put_u32(0);
+        // truncated
+        buf.put_u32(4);
+        buf.put_slice(b"de");
+        let mut datagram = buf.freeze();
+
+        assert_eq!(split_frame(&mut datagram).unwrap(), &b"\0\0\0\x03abc"[..]);
+        assert_eq!(split_frame(&mut datagram).unwrap(), &b"\0\0\0\0"[..]);
+        assert_eq!(split_frame(&mut datagram), None);
+        assert_eq!(datagram.len(), 6);
+    }
+}
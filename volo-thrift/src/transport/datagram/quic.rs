@@ -0,0 +1,99 @@
+//! QUIC datagrams of the datagram transport.
+
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use volo::net::tls::{ClientTlsConfig, ServerTlsConfig};
+
+const ALPN: &[u8] = b"thrift-datagram";
+
+/// Connector making QUIC connections for sending datagrams.
+pub(crate) struct Connector {
+    client_config: quinn::ClientConfig,
+    server_name: String,
+    // endpoints for IPv4 and IPv6
+    endpoints: Mutex<[Option<quinn::Endpoint>; 2]>,
+}
+
+impl fmt::Debug for Connector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connector")
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+impl Connector {
+    pub(crate) fn new(tls_config: ClientTlsConfig) -> io::Result<Self> {
+        let rustls_config = tls_config.connector.rustls_config().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "QUIC requires a tls connector built by rustls",
+            )
+        })?;
+        let mut rustls_config = (**rustls_config).clone();
+        rustls_config.alpn_protocols = vec![ALPN.to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(rustls_config)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            client_config: quinn::ClientConfig::new(Arc::new(crypto)),
+            server_name: tls_config.server_name,
+            endpoints: Mutex::new([None, None]),
+        })
+    }
+
+    /// Make a QUIC connection to the `addr` supporting datagrams.
+    pub(crate) async fn connect(&self, addr: SocketAddr) -> io::Result<quinn::Connection> {
+        let conn = self
+            .endpoint(addr)?
+            .connect_with(self.client_config.clone(), addr, &self.server_name)
+            .map_err(io::Error::other)?
+            .await?;
+        if conn.max_datagram_size().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{addr} does not support QUIC datagrams"),
+            ));
+        }
+        Ok(conn)
+    }
+
+    fn endpoint(&self, addr: SocketAddr) -> io::Result<quinn::Endpoint> {
+        let mut endpoints = self.endpoints.lock();
+        let endpoint = &mut endpoints[usize::from(addr.is_ipv6())];
+        if let Some(endpoint) = endpoint {
+            return Ok(endpoint.clone());
+        }
+        let bind = if addr.is_ipv6() {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        };
+        let new_endpoint = quinn::Endpoint::client(bind)?;
+        *endpoint = Some(new_endpoint.clone());
+        Ok(new_endpoint)
+    }
+}
+
+/// Create a QUIC endpoint listening on the UDP `addr` by the rustls config of `tls_config`.
+pub(crate) fn make_endpoint(
+    addr: SocketAddr,
+    tls_config: &ServerTlsConfig,
+) -> io::Result<quinn::Endpoint> {
+    let rustls_config = tls_config.acceptor.rustls_config().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "QUIC requires a tls config built by rustls",
+        )
+    })?;
+    let mut rustls_config = (**rustls_config).clone();
+    rustls_config.alpn_protocols = vec![ALPN.to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(rustls_config)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    quinn::Endpoint::server(config, addr)
+}
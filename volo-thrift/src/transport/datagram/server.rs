@@ -0,0 +1,225 @@
+use std::{cell::RefCell, io, net::SocketAddr, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use metainfo::MetaInfo;
+use motore::service::Service;
+use tokio::{net::UdpSocket, sync::Semaphore};
+use tracing::Instrument;
+use volo::net::Address;
+
+use super::{MAX_DATAGRAM_SIZE, split_frame};
+use crate::{
+    EntryMessage, ServerError, ThriftMessage,
+    codec::default::{MakeZeroCopyCodec, ZeroCopyDecoder},
+    context::ServerContext,
+    protocol::TMessageType,
+    server::TraceFn,
+    tracing::SpanProvider,
+};
+
+// datagrams handled concurrently, and the kernel drops datagrams arriving when it's exceeded and
+// the receive buffer of the socket is full
+pub(crate) const MAX_CONCURRENT_DATAGRAMS: usize = 1024;
+
+/// Where the server receives datagrams from.
+pub(crate) enum Listener {
+    Udp(UdpSocket),
+    #[cfg(feature = "datagram-quic")]
+    Quic(quinn::Endpoint),
+}
+
+impl Listener {
+    pub(crate) async fn udp(addr: SocketAddr) -> io::Result<Self> {
+        UdpSocket::bind(addr).await.map(Self::Udp)
+    }
+
+    #[cfg(feature = "datagram-quic")]
+    pub(crate) fn quic(
+        addr: SocketAddr,
+        tls_config: &volo::net::tls::ServerTlsConfig,
+    ) -> io::Result<Self> {
+        super::quic::make_endpoint(addr, tls_config).map(Self::Quic)
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Self::Udp(socket) => socket.local_addr(),
+            #[cfg(feature = "datagram-quic")]
+            Self::Quic(endpoint) => endpoint.local_addr(),
+        }
+    }
+}
+
+/// Handles the frames of each datagram by the service.
+pub(crate) struct Handler<Svc, MkZC, SP> {
+    pub(crate) service: Arc<Svc>,
+    pub(crate) make_codec: MkZC,
+    pub(crate) stat_tracer: Arc<[TraceFn]>,
+    pub(crate) span_provider: SP,
+    pub(crate) permits: Arc<Semaphore>,
+}
+
+impl<Svc, MkZC: Clone, SP: Clone> Clone for Handler<Svc, MkZC, SP> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            make_codec: self.make_codec.clone(),
+            stat_tracer: self.stat_tracer.clone(),
+            span_provider: self.span_provider.clone(),
+            permits: self.permits.clone(),
+        }
+    }
+}
+
+/// Receive datagrams from the listener and handle them until the listener is closed.
+pub(crate) async fn serve<Svc, Req, MkZC, SP>(listener: Listener, handler: Handler<Svc, MkZC, SP>)
+where
+    Svc: Service<ServerContext, Req> + Send + Sync + 'static,
+    Svc::Error: Into<ServerError>,
+    Req: EntryMessage + Send + 'static,
+    MkZC: MakeZeroCopyCodec + Sync,
+    SP: SpanProvider,
+{
+    match listener {
+        Listener::Udp(socket) => {
+            let mut buf = BytesMut::new();
+            loop {
+                buf.reserve(MAX_DATAGRAM_SIZE);
+                match socket.recv_buf_from(&mut buf).await {
+                    Ok((_, peer)) => {
+                        let datagram = buf.split().freeze();
+                        handler.spawn(datagram, Address::from(peer)).await;
+                    }
+                    // e.g., ICMP errors of the datagrams sent by the socket on some platforms
+                    Err(e) => {
+                        buf.clear();
+                        tracing::debug!("[VOLO] datagram server receive error: {e}");
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "datagram-quic")]
+        Listener::Quic(endpoint) => {
+            while let Some(incoming) = endpoint.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let peer = Address::from(incoming.remote_address());
+                    let conn = match incoming.await {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            tracing::trace!("[VOLO] quic handshake error: {e}, peer: {peer}");
+                            return;
+                        }
+                    };
+                    let _conn_event =
+                        volo::event::connection("thrift", volo::context::Role::Server, &peer);
+                    while let Ok(datagram) = conn.read_datagram().await {
+                        handler.spawn(datagram, peer.clone()).await;
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl<Svc, MkZC, SP> Handler<Svc, MkZC, SP> {
+    async fn spawn<Req>(&self, datagram: Bytes, peer: Address)
+    where
+        Svc: Service<ServerContext, Req> + Send + Sync + 'static,
+        Svc::Error: Into<ServerError>,
+        Req: EntryMessage + Send + 'static,
+        MkZC: MakeZeroCopyCodec + Sync,
+        SP: SpanProvider,
+    {
+        let Ok(permit) = self.permits.clone().acquire_owned().await else {
+            return;
+        };
+        let service = self.service.clone();
+        let (_, decoder) = self.make_codec.make_codec();
+        let stat_tracer = self.stat_tracer.clone();
+        let span_provider = self.span_provider.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            metainfo::METAINFO
+                .scope(
+                    RefCell::new(MetaInfo::default()),
+                    handle_datagram(
+                        datagram,
+                        peer,
+                        &*service,
+                        decoder,
+                        &stat_tracer,
+                        &span_provider,
+                    ),
+                )
+                .await;
+        });
+    }
+}
+
+async fn handle_datagram<Svc, Req, D, SP>(
+    mut datagram: Bytes,
+    peer: Address,
+    service: &Svc,
+    mut decoder: D,
+    stat_tracer: &[TraceFn],
+    span_provider: &SP,
+) where
+    Svc: Service<ServerContext, Req>,
+    Svc::Error: Into<ServerError>,
+    Req: EntryMessage,
+    D: ZeroCopyDecoder,
+    SP: SpanProvider,
+{
+    while !datagram.is_empty() {
+        let Some(mut frame) = split_frame(&mut datagram) else {
+            tracing::warn!(
+                "[VOLO] datagram server drops an incomplete frame of {} bytes, peer_addr: {peer}",
+                datagram.len()
+            );
+            break;
+        };
+        let mut cx = ServerContext::default();
+        cx.rpc_info.caller_mut().set_address(peer.clone());
+        cx.common_stats.set_read_size(frame.len());
+
+        let req = match decoder.decode::<Req, _>(&mut cx, &mut frame) {
+            Ok(Some(ThriftMessage { data: Ok(req), .. })) => req,
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::warn!(
+                    "[VOLO] datagram server decode error: {e:?}, cx: {cx:?}, peer_addr: {peer}"
+                );
+                continue;
+            }
+        };
+        if cx.req_msg_type != Some(TMessageType::OneWay) {
+            tracing::warn!(
+                "[VOLO] datagram server drops a request of non-oneway method `{}`, peer_addr: \
+                 {peer}",
+                cx.rpc_info.method()
+            );
+            continue;
+        }
+
+        let span = span_provider.on_serve(&cx);
+        async {
+            cx.stats.record_process_start_at();
+            if let Err(e) = service.call(&mut cx, req).await {
+                tracing::debug!(
+                    "[VOLO] datagram server handle error: {:?}, cx: {cx:?}, peer_addr: {peer}",
+                    e.into()
+                );
+            }
+            cx.stats.record_process_end_at();
+        }
+        .instrument(span)
+        .await;
+        stat_tracer.iter().for_each(|f| f(&cx));
+        span_provider.leave_serve(&cx);
+
+        metainfo::METAINFO.with(|mi| {
+            mi.borrow_mut().clear();
+        });
+    }
+}
@@ -0,0 +1,234 @@
+//! Application-level heartbeats of pooled connections.
+//!
+//! Connections idle in the pool may be broken silently, e.g., by NAT or load balancers dropping
+//! idle flows, and a request sent on such a connection will wait until the timeout. With a
+//! [`Heartbeat`] set by [`Config::heartbeat`], a connection idle for a while is probed by calling
+//! a no-op method before it is reused, and it is dropped if the probe fails, then another
+//! connection is used.
+//!
+//! The probe is a call to the method with empty arguments, so it works with all protocols of the
+//! client. The method should be handled by the server, e.g., a `void ping()` method in the IDL,
+//! but any response is treated as alive, including an `UNKNOWN_METHOD` exception.
+//!
+//! Only ping-pong connections are probed, since multiplex connections are shared and their idle
+//! time is not tracked.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo_thrift::transport::{Config, Heartbeat};
+//!
+//! let config = Config::default().heartbeat(
+//!     Heartbeat::new("ping")
+//!         .idle(Duration::from_secs(10))
+//!         .timeout(Duration::from_millis(200)),
+//! );
+//! ```
+//!
+//! [`Config::heartbeat`]: super::Config::heartbeat
+
+use pilota::thrift::{TAsyncInputProtocol, TStructIdentifier, TType, ThriftException};
+use tokio::time::Duration;
+use volo::{
+    FastStr,
+    context::{Endpoint, Role, RpcInfo},
+};
+
+use crate::{
+    EntryMessage, ThriftMessage,
+    codec::{Decoder, Encoder},
+    context::ClientContext,
+    protocol::{
+        TInputProtocol, TLengthProtocol, TMessageIdentifier, TMessageType, TOutputProtocol,
+    },
+    transport::pingpong::thrift_transport::ThriftTransport,
+};
+
+/// The default idle time of connections before they are probed.
+pub const DEFAULT_IDLE: Duration = Duration::from_secs(30);
+
+/// The default timeout of probes.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Config of heartbeats, see [the module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    method: FastStr,
+    idle: Duration,
+    timeout: Duration,
+}
+
+impl Heartbeat {
+    /// Create a [`Heartbeat`] probing connections by calling the `method`.
+    pub fn new(method: impl Into<FastStr>) -> Self {
+        Self {
+            method: method.into(),
+            idle: DEFAULT_IDLE,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the idle time of connections before they are probed.
+    ///
+    /// Default is [`DEFAULT_IDLE`].
+    pub fn idle(mut self, idle: Duration) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    /// Set the timeout of probes, the connection is dropped if there is no response in time.
+    ///
+    /// Default is [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Whether a connection idle for `idle` should be probed before it is reused.
+    pub(crate) fn should_probe(&self, idle: Duration) -> bool {
+        idle >= self.idle
+    }
+
+    /// Probe the `transport` by the heartbeat call, and returns if it is alive.
+    ///
+    /// The `cx` is the context of the request to be sent, whose endpoints and config are used by
+    /// the heartbeat call.
+    pub(crate) async fn probe<E, D>(
+        &self,
+        cx: &ClientContext,
+        transport: &mut ThriftTransport<E, D>,
+    ) -> bool
+    where
+        E: Encoder,
+        D: Decoder,
+    {
+        let caller = Endpoint::new(cx.rpc_info.caller().service_name());
+        let mut callee = Endpoint::new(cx.rpc_info.callee().service_name());
+        callee.address = cx.rpc_info.callee().address();
+        let rpc_info = RpcInfo::new(
+            Role::Client,
+            self.method.clone(),
+            caller,
+            callee,
+            *cx.rpc_info.config(),
+        );
+        let mut hb_cx = ClientContext::new(cx.seq_id, rpc_info, TMessageType::Call);
+        hb_cx.idl_service_name = cx.idl_service_name.clone();
+        let msg = ThriftMessage::mk_client_msg(&hb_cx, HeartbeatMessage);
+
+        let resp = tokio::time::timeout(
+            self.timeout,
+            transport.send::<_, HeartbeatMessage>(&mut hb_cx, msg, false),
+        )
+        .await;
+        match resp {
+            Ok(Ok(Some(_))) => true,
+            Ok(Ok(None)) => {
+                tracing::debug!("[VOLO] heartbeat failed: connection closed by the server");
+                false
+            }
+            Ok(Err(e)) => {
+                tracing::debug!("[VOLO] heartbeat failed: {e}");
+                false
+            }
+            Err(_) => {
+                tracing::debug!("[VOLO] heartbeat timeout after {:?}", self.timeout);
+                false
+            }
+        }
+    }
+}
+
+/// Arguments and result of the heartbeat call, which are empty structs.
+///
+/// Fields of the result are skipped, so the method can return anything.
+pub(crate) struct HeartbeatMessage;
+
+const HEARTBEAT_STRUCT: TStructIdentifier = TStructIdentifier { name: "Heartbeat" };
+
+impl EntryMessage for HeartbeatMessage {
+    fn encode<T: TOutputProtocol>(&self, protocol: &mut T) -> Result<(), ThriftException> {
+        protocol.write_struct_begin(&HEARTBEAT_STRUCT)?;
+        protocol.write_field_stop()?;
+        protocol.write_struct_end()
+    }
+
+    // fields are skipped one by one like the generated code, since the unsafe protocol can only
+    // skip a value right after its field header
+    fn decode<T: TInputProtocol>(
+        protocol: &mut T,
+        _msg_ident: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.read_struct_begin()?;
+        loop {
+            let field = protocol.read_field_begin()?;
+            if field.field_type == TType::Stop {
+                break;
+            }
+            protocol.skip(field.field_type)?;
+            protocol.read_field_end()?;
+        }
+        protocol.read_struct_end()?;
+        Ok(Self)
+    }
+
+    async fn decode_async<T: TAsyncInputProtocol>(
+        protocol: &mut T,
+        _msg_ident: &TMessageIdentifier,
+    ) -> Result<Self, ThriftException> {
+        protocol.read_struct_begin().await?;
+        loop {
+            let field = protocol.read_field_begin().await?;
+            if field.field_type == TType::Stop {
+                break;
+            }
+            protocol.skip(field.field_type).await?;
+            protocol.read_field_end().await?;
+        }
+        protocol.read_struct_end().await?;
+        Ok(Self)
+    }
+
+    fn size<T: TLengthProtocol>(&self, protocol: &mut T) -> usize {
+        protocol.struct_begin_len(&HEARTBEAT_STRUCT)
+            + protocol.field_stop_len()
+            + protocol.struct_end_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pilota::thrift::{TMessageIdentifier, TMessageType, binary::TBinaryProtocol};
+
+    use super::{Heartbeat, HeartbeatMessage};
+    use crate::EntryMessage;
+
+    #[test]
+    fn heartbeat_message() {
+        let mut buf = bytes::BytesMut::new();
+        HeartbeatMessage
+            .encode(&mut TBinaryProtocol::new(&mut buf, true))
+            .unwrap();
+        assert_eq!(&buf[..], &[0]);
+        assert_eq!(
+            HeartbeatMessage.size(&mut TBinaryProtocol::new((), true)),
+            buf.len()
+        );
+
+        let ident = TMessageIdentifier::new("ping".into(), TMessageType::Reply, 1);
+        // the result of `void ping()`
+        let mut buf = bytes::Bytes::from_static(&[0]);
+        HeartbeatMessage::decode(&mut TBinaryProtocol::new(&mut buf, true), &ident).unwrap();
+        assert!(buf.is_empty());
+        // the result of a method returning a string
+        let mut buf = bytes::Bytes::from_static(&[11, 0, 0, 0, 0, 0, 2, b'o', b'k', 0]);
+        HeartbeatMessage::decode(&mut TBinaryProtocol::new(&mut buf, true), &ident).unwrap();
+        assert!(buf.is_empty());
+
+        let heartbeat = Heartbeat::new("ping");
+        assert!(!heartbeat.should_probe(std::time::Duration::from_secs(1)));
+        assert!(heartbeat.should_probe(super::DEFAULT_IDLE));
+    }
+}
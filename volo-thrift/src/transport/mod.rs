@@ -1,8 +1,12 @@
+#[cfg(feature = "datagram")]
+pub mod datagram;
+pub mod heartbeat;
 pub(crate) mod incoming;
 #[cfg(feature = "multiplex")]
 pub mod multiplex;
 pub mod pingpong;
 pub mod pool;
+pub use heartbeat::Heartbeat;
 use pilota::thrift::ThriftException;
 pub use pool::Config;
 
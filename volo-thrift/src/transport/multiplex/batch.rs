@@ -0,0 +1,110 @@
+//! Write batching of responses for the multiplex server.
+//!
+//! Under high QPS with small responses, writing each response costs a syscall. With
+//! [`WriteBatch`], the responses pending on a connection are encoded into the buffer of the
+//! encoder and written together in one vectored write.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+const DEFAULT_MAX_MESSAGES: usize = 64;
+
+/// Config of write batching for the multiplex server.
+///
+/// See [`Server::write_batch`](crate::server::Server::write_batch) for more details.
+#[derive(Clone, Debug)]
+pub struct WriteBatch {
+    pub(crate) max_messages: usize,
+    pub(crate) flush_delay: Duration,
+    pub(crate) stats: Arc<WriteBatchStats>,
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        Self {
+            max_messages: DEFAULT_MAX_MESSAGES,
+            flush_delay: Duration::ZERO,
+            stats: Default::default(),
+        }
+    }
+}
+
+impl WriteBatch {
+    /// Create a [`WriteBatch`] with default config.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of responses in a batch.
+    ///
+    /// Default is `64`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn max_messages(mut self, max: usize) -> Self {
+        assert!(max > 0, "max messages of a batch must be greater than zero");
+        self.max_messages = max;
+        self
+    }
+
+    /// Set the time to wait for more responses before writing a batch.
+    ///
+    /// Default is zero, which means only the responses already pending are batched, so the
+    /// latency is not increased. A small delay (e.g., tens of microseconds) makes batches larger
+    /// at the cost of latency.
+    pub fn flush_delay(mut self, delay: Duration) -> Self {
+        self.flush_delay = delay;
+        self
+    }
+
+    /// Get the stats of batches, which are shared by all connections of the server.
+    pub fn stats(&self) -> Arc<WriteBatchStats> {
+        self.stats.clone()
+    }
+}
+
+/// Stats of batches written by the multiplex server.
+#[derive(Debug, Default)]
+pub struct WriteBatchStats {
+    batches: AtomicU64,
+    messages: AtomicU64,
+    max_batch_size: AtomicU64,
+}
+
+impl WriteBatchStats {
+    pub(crate) fn record(&self, size: usize) {
+        let size = size as u64;
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        self.messages.fetch_add(size, Ordering::Relaxed);
+        self.max_batch_size.fetch_max(size, Ordering::Relaxed);
+    }
+
+    /// Number of batches written.
+    pub fn batches(&self) -> u64 {
+        self.batches.load(Ordering::Relaxed)
+    }
+
+    /// Number of responses written in all batches.
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Number of responses in the largest batch.
+    pub fn max_batch_size(&self) -> u64 {
+        self.max_batch_size.load(Ordering::Relaxed)
+    }
+
+    /// Average number of responses in a batch.
+    pub fn avg_batch_size(&self) -> f64 {
+        match self.batches() {
+            0 => 0.0,
+            batches => self.messages() as f64 / batches as f64,
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use std::{io, marker::PhantomData};
 
 use motore::service::{Service, UnaryService};
-use volo::net::{Address, dial::MakeTransport};
+use volo::{
+    loadbalance::{affinity::Affinity, drain::Drainer, warmup::WarmupHandle},
+    net::{Address, dial::MakeTransport},
+};
 
 use crate::{
     ClientError, EntryMessage, ThriftMessage,
@@ -118,6 +121,24 @@ where
             _marker: PhantomData,
         }
     }
+
+    /// Subscribe to the [`Drainer`] for draining connections to instances removed by the
+    /// discovery.
+    pub fn drain_on(&self, drainer: &Drainer) {
+        self.make_transport.drain_on(drainer);
+    }
+
+    /// Subscribe to the [`Affinity`] for pre-creating connections to instances taking over
+    /// requests after changes of the discovery.
+    pub fn prewarm_on(&self, affinity: &Affinity) {
+        self.make_transport.prewarm_on(affinity, Ver::Multiplex);
+    }
+
+    /// Subscribe to the [`WarmupHandle`] for pre-creating connections when the client is warmed
+    /// up.
+    pub fn warmup_on(&self, warmup: &WarmupHandle) {
+        self.make_transport.warmup_on(warmup, Ver::Multiplex);
+    }
 }
 
 impl<Req, Resp, MkT, MkC> Service<ClientContext, ThriftMessage<Req>> for Client<Resp, MkT, MkC>
@@ -145,6 +166,7 @@ where
         cx.stats.record_make_transport_start_at();
         let transport = self.make_transport.call((target, Ver::Multiplex)).await?;
         cx.stats.record_make_transport_end_at();
+        cx.stats.record_acquisition(transport.acquisition());
         let resp = transport.send(cx, req, oneway).await;
         if let Ok(None) = resp {
             if !oneway {
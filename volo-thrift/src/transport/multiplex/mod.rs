@@ -1,6 +1,8 @@
+mod batch;
 mod client;
 mod server;
 mod thrift_transport;
 
+pub use batch::{WriteBatch, WriteBatchStats};
 pub use client::Client;
 pub use server::serve;
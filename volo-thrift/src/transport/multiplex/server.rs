@@ -10,6 +10,7 @@ use tokio::sync::{futures::Notified, mpsc};
 use tracing::*;
 use volo::{context::Context, net::Address, volo_unreachable};
 
+use super::WriteBatch;
 use crate::{
     DummyMessage, EntryMessage, ServerError, ThriftMessage,
     codec::{Decoder, Encoder},
@@ -21,6 +22,9 @@ use crate::{
 
 const CHANNEL_SIZE: usize = 1024;
 
+type PendingResponse<Resp> = (MetaInfo, ServerContext, ThriftMessage<Resp>);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn serve<Svc, Req, Resp, E, D>(
     mut encoder: E,
     mut decoder: D,
@@ -29,6 +33,7 @@ pub async fn serve<Svc, Req, Resp, E, D>(
     service: Svc,
     stat_tracer: Arc<[crate::server::TraceFn]>,
     peer_addr: Option<Address>,
+    write_batch: Option<WriteBatch>,
 ) where
     Svc: Service<ServerContext, Req, Response = Resp> + Send + Clone + 'static + Sync,
     Svc::Error: Into<ServerError> + Send,
@@ -40,7 +45,7 @@ pub async fn serve<Svc, Req, Resp, E, D>(
     tokio::pin!(notified);
 
     // mpsc channel used to send responses to the loop
-    let (send_tx, mut send_rx) = mpsc::channel(CHANNEL_SIZE);
+    let (send_tx, mut send_rx) = mpsc::channel::<PendingResponse<Resp>>(CHANNEL_SIZE);
     let (error_send_tx, mut error_send_rx) =
         mpsc::channel::<(ServerContext, ThriftMessage<DummyMessage>)>(1);
 
@@ -53,8 +58,22 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                         tokio::select! {
                             // receives a response, we need to send it back to client
                             msg = send_rx.recv() => {
-                                match msg {
-                                    Some((mi, mut cx, msg)) => {
+                                match (msg, &write_batch) {
+                                    (Some(resp), Some(batch)) => {
+                                        if !encode_batch(
+                                            &mut encoder,
+                                            &mut send_rx,
+                                            resp,
+                                            batch,
+                                            &stat_tracer,
+                                            &peer_addr,
+                                        )
+                                        .await
+                                        {
+                                            return;
+                                        }
+                                    }
+                                    (Some((mi, mut cx, msg)), None) => {
                                         if let Err(e) = metainfo::METAINFO
                                             .scope(
                                                 RefCell::new(mi),
@@ -78,7 +97,7 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                                             return;
                                         }
                                     }
-                                    None => {
+                                    (None, _) => {
                                         // log it
                                         trace!(
                                             "[VOLO] server send channel closed, peer_addr: {:?}",
@@ -228,3 +247,76 @@ pub async fn serve<Svc, Req, Resp, E, D>(
         })
         .await;
 }
+
+/// Encode the response and the ones pending after it, and write them in one batch.
+///
+/// Returns `false` if the connection should be closed.
+async fn encode_batch<Resp, E>(
+    encoder: &mut E,
+    send_rx: &mut mpsc::Receiver<PendingResponse<Resp>>,
+    first: PendingResponse<Resp>,
+    batch: &WriteBatch,
+    stat_tracer: &[crate::server::TraceFn],
+    peer_addr: &Option<Address>,
+) -> bool
+where
+    Resp: EntryMessage + 'static,
+    E: Encoder,
+{
+    let deadline = tokio::time::Instant::now() + batch.flush_delay;
+    let mut cxs = Vec::new();
+    let mut next = Some(first);
+    let mut conn_reset = false;
+    while let Some((mi, mut cx, msg)) = next.take() {
+        if let Err(e) = metainfo::METAINFO
+            .scope(
+                RefCell::new(mi),
+                encoder.encode_buffered::<Resp, ServerContext>(&mut cx, msg),
+            )
+            .await
+        {
+            stat_tracer.iter().for_each(|f| f(&cx));
+            if should_log(&e) {
+                error!(
+                    "[VOLO] server send response error: {:?}, cx: {:?}, peer_addr: {:?}",
+                    e, cx, peer_addr
+                );
+            }
+            // write the encoded ones before closing the connection
+            let _ = encoder.flush().await;
+            cxs.iter()
+                .for_each(|cx| stat_tracer.iter().for_each(|f| f(cx)));
+            return false;
+        }
+        conn_reset |= cx.encode_conn_reset();
+        cxs.push(cx);
+        if conn_reset || cxs.len() >= batch.max_messages {
+            break;
+        }
+        // the pending response is returned immediately even if the deadline has passed
+        next = tokio::time::timeout_at(deadline, send_rx.recv())
+            .await
+            .ok()
+            .flatten();
+    }
+
+    let result = encoder.flush().await;
+    batch.stats.record(cxs.len());
+    trace!(
+        "[VOLO] server wrote a batch of {} responses, peer_addr: {:?}",
+        cxs.len(),
+        peer_addr
+    );
+    cxs.iter()
+        .for_each(|cx| stat_tracer.iter().for_each(|f| f(cx)));
+    if let Err(e) = result {
+        if should_log(&e) {
+            error!(
+                "[VOLO] server send response error: {:?}, peer_addr: {:?}",
+                e, peer_addr
+            );
+        }
+        return false;
+    }
+    !conn_reset
+}
@@ -7,7 +7,7 @@ use std::{
 };
 
 use metainfo::MetaInfo;
-use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use pilota::thrift::ApplicationExceptionKind;
 use pin_project::pin_project;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
@@ -22,6 +22,7 @@ use crate::{
     ClientError, EntryMessage, ThriftMessage,
     codec::{Decoder, Encoder, MakeCodec},
     context::{ClientContext, ThriftContext},
+    error::volo_exception,
     transport::{
         pool::{Poolable, Reservation},
         should_log,
@@ -108,7 +109,9 @@ where
         let inner_read_error = read_error.clone();
         let read_closed = Arc::new(AtomicBool::new(false));
         let inner_read_closed = read_closed.clone();
+        let conn_event = volo::event::connection("thrift", Role::Client, &target);
         tokio::spawn(async move {
+            let _conn_event = conn_event;
             metainfo::METAINFO
                 .scope(RefCell::new(Default::default()), async move {
                     loop {
@@ -135,14 +138,10 @@ where
                             let mut tx_map = inner_tx_map.lock().await;
                             inner_read_error.store(true, std::sync::atomic::Ordering::Relaxed);
                             for (_, tx) in tx_map.drain() {
-                                let _ = tx.send(Err(ClientError::Application(
-                                    ApplicationException::new(
-                                        ApplicationExceptionKind::UNKNOWN,
-                                        format!(
-                                            "multiplex connection error: {e}, target: {target}"
-                                        ),
-                                    ),
-                                )));
+                                let _ = tx.send(Err(ClientError::Application(volo_exception(
+                                    ApplicationExceptionKind::UNKNOWN,
+                                    format!("multiplex connection error: {e}, target: {target}"),
+                                ))));
                             }
                             return;
                         }
@@ -205,15 +204,15 @@ where
     ) -> Result<Option<ThriftMessage<Resp>>, ClientError> {
         // check error and closed
         if self.read_error.load(std::sync::atomic::Ordering::Relaxed) {
-            return Err(ClientError::Application(ApplicationException::new(
+            return Err(ClientError::Application(volo_exception(
                 ApplicationExceptionKind::UNKNOWN,
-                "multiplex connection error".to_string(),
+                "multiplex connection error",
             )));
         }
         if self.read_closed.load(std::sync::atomic::Ordering::Relaxed) {
-            return Err(ClientError::Application(ApplicationException::new(
+            return Err(ClientError::Application(volo_exception(
                 ApplicationExceptionKind::UNKNOWN,
-                "multiplex connection closed".to_string(),
+                "multiplex connection closed",
             )));
         }
         let (tx, rx) = oneshot::channel();
@@ -230,9 +229,9 @@ where
             // not be reused
             self.write_error
                 .store(true, std::sync::atomic::Ordering::Relaxed);
-            return Err(ClientError::Application(ApplicationException::new(
+            return Err(ClientError::Application(volo_exception(
                 ApplicationExceptionKind::UNKNOWN,
-                "multiplex connection is dirty".to_string(),
+                "multiplex connection is dirty",
             )));
         }
         self.dirty.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -283,9 +282,9 @@ where
             },
             Err(e) => {
                 tracing::error!("[VOLO] multiplex connection oneshot recv error: {e}");
-                Err(ClientError::Application(ApplicationException::new(
+                Err(ClientError::Application(volo_exception(
                     ApplicationExceptionKind::UNKNOWN,
-                    format!("multiplex connection oneshot recv error: {e}"),
+                    format_args!("multiplex connection oneshot recv error: {e}"),
                 )))
             }
         }
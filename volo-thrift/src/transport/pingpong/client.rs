@@ -2,7 +2,10 @@ use std::{io, marker::PhantomData};
 
 use motore::service::{Service, UnaryService};
 use pilota::thrift::TransportException;
-use volo::net::{Address, dial::MakeTransport};
+use volo::{
+    loadbalance::{affinity::Affinity, drain::Drainer, warmup::WarmupHandle},
+    net::{Address, dial::MakeTransport},
+};
 
 use crate::{
     EntryMessage, ThriftMessage,
@@ -10,6 +13,7 @@ use crate::{
     context::ClientContext,
     protocol::TMessageType,
     transport::{
+        heartbeat::Heartbeat,
         pingpong::thrift_transport::ThriftTransport,
         pool::{Config, PooledMakeTransport, Ver},
     },
@@ -51,8 +55,10 @@ where
     #[inline]
     async fn call(&self, target: Address) -> Result<Self::Response, Self::Error> {
         let make_transport = self.make_transport.clone();
-        let (rh, wh) = make_transport.make_transport(target).await?;
-        Ok(ThriftTransport::new(rh, wh, self.make_codec.clone()))
+        let (rh, wh) = make_transport.make_transport(target.clone()).await?;
+        let mut transport = ThriftTransport::new(rh, wh, self.make_codec.clone());
+        transport.track_connection(&target);
+        Ok(transport)
     }
 }
 
@@ -63,6 +69,7 @@ where
 {
     #[allow(clippy::type_complexity)]
     make_transport: PooledMakeTransport<MakeClientTransport<MkT, MkC>, Address>,
+    heartbeat: Option<Heartbeat>,
     _marker: PhantomData<Resp>,
 }
 
@@ -74,6 +81,7 @@ where
     fn clone(&self) -> Self {
         Self {
             make_transport: self.make_transport.clone(),
+            heartbeat: self.heartbeat.clone(),
             _marker: self._marker,
         }
     }
@@ -85,13 +93,33 @@ where
     MkC: MakeCodec<MkT::ReadHalf, MkT::WriteHalf> + Sync,
 {
     pub fn new(make_transport: MkT, pool_cfg: Option<Config>, make_codec: MkC) -> Self {
+        let heartbeat = pool_cfg.as_ref().and_then(|cfg| cfg.heartbeat.clone());
         let make_transport = MakeClientTransport::new(make_transport, make_codec);
         let make_transport = PooledMakeTransport::new(make_transport, pool_cfg);
         Client {
             make_transport,
+            heartbeat,
             _marker: PhantomData,
         }
     }
+
+    /// Subscribe to the [`Drainer`] for draining connections to instances removed by the
+    /// discovery.
+    pub fn drain_on(&self, drainer: &Drainer) {
+        self.make_transport.drain_on(drainer);
+    }
+
+    /// Subscribe to the [`Affinity`] for pre-creating connections to instances taking over
+    /// requests after changes of the discovery.
+    pub fn prewarm_on(&self, affinity: &Affinity) {
+        self.make_transport.prewarm_on(affinity, Ver::PingPong);
+    }
+
+    /// Subscribe to the [`WarmupHandle`] for pre-creating connections when the client is warmed
+    /// up.
+    pub fn warmup_on(&self, warmup: &WarmupHandle) {
+        self.make_transport.warmup_on(warmup, Ver::PingPong);
+    }
 }
 
 impl<Req, Resp, MkT, MkC> Service<ClientContext, ThriftMessage<Req>> for Client<Resp, MkT, MkC>
@@ -120,8 +148,24 @@ where
         })?;
         let oneway = cx.message_type == TMessageType::OneWay;
         cx.stats.record_make_transport_start_at();
-        let mut transport = self.make_transport.call((target, Ver::PingPong)).await?;
+        let mut transport = loop {
+            let mut transport = self
+                .make_transport
+                .call((target.clone(), Ver::PingPong))
+                .await?;
+            let Some(heartbeat) = &self.heartbeat else {
+                break transport;
+            };
+            if !transport.acquisition().reused
+                || !heartbeat.should_probe(transport.idle())
+                || heartbeat.probe(cx, &mut transport).await
+            {
+                break transport;
+            }
+            // the broken connection is dropped, try the next one
+        };
         cx.stats.record_make_transport_end_at();
+        cx.stats.record_acquisition(transport.acquisition());
         let resp = transport.send(cx, req, oneway).await;
         if let Ok(None) = resp {
             if !oneway {
@@ -1,6 +1,6 @@
 mod client;
 mod server;
-mod thrift_transport;
+pub(crate) mod thrift_transport;
 
 pub use client::Client;
 pub use server::serve;
@@ -1,13 +1,18 @@
 use std::sync::{LazyLock, atomic::AtomicUsize};
 
-use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use pilota::thrift::ApplicationExceptionKind;
 use pin_project::pin_project;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::{Duration, Instant},
+};
+use volo::context::Role;
 
 use crate::{
     ClientError, EntryMessage, ThriftMessage,
     codec::{Decoder, Encoder, MakeCodec},
     context::{ClientContext, ThriftContext},
+    error::volo_exception,
     transport::{pool::Poolable, should_log},
 };
 
@@ -17,6 +22,9 @@ static TRANSPORT_ID_COUNTER: LazyLock<AtomicUsize> = LazyLock::new(|| AtomicUsiz
 pub struct ThriftTransport<E: Encoder, D: Decoder> {
     write_half: WriteHalf<E>,
     read_half: ReadHalf<D>,
+    /// When the last request finished, or the transport was made.
+    idle_since: Instant,
+    conn_event: Option<volo::event::ConnectionGuard>,
     #[cfg(feature = "shmipc")]
     shmipc_helper: volo::net::shmipc::ShmipcHelper,
 }
@@ -50,11 +58,18 @@ where
                 id,
                 reusable: true,
             },
+            idle_since: Instant::now(),
+            conn_event: None,
             #[cfg(feature = "shmipc")]
             shmipc_helper,
         }
     }
 
+    /// Emit the connection events of the transport to the `target`.
+    pub fn track_connection(&mut self, target: &volo::net::Address) {
+        self.conn_event = volo::event::connection("thrift", Role::Client, target);
+    }
+
     #[allow(dead_code)]
     pub fn split(self) -> (ReadHalf<D>, WriteHalf<E>) {
         (self.read_half, self.write_half)
@@ -72,11 +87,19 @@ where
         msg: ThriftMessage<Req>,
         oneway: bool,
     ) -> Result<Option<ThriftMessage<Resp>>, ClientError> {
-        self.write_half.send(cx, msg).await?;
-        if oneway {
-            return Ok(None);
-        }
-        self.read_half.try_next(cx).await
+        let resp = self.write_half.send(cx, msg).await;
+        let resp = match resp {
+            Ok(()) if oneway => Ok(None),
+            Ok(()) => self.read_half.try_next(cx).await,
+            Err(e) => Err(e),
+        };
+        self.idle_since = Instant::now();
+        resp
+    }
+
+    /// How long the transport has been idle since the last request.
+    pub fn idle(&self) -> Duration {
+        Instant::now().saturating_duration_since(self.idle_since)
     }
 
     #[cfg(feature = "shmipc")]
@@ -117,9 +140,9 @@ where
                     cx.seq_id,
                     cx,
                 );
-                return Err(ClientError::Application(ApplicationException::new(
+                return Err(ClientError::Application(volo_exception(
                     ApplicationExceptionKind::BAD_SEQUENCE_ID,
-                    format!("seq_id not match, cx: {cx:?}"),
+                    format_args!("seq_id not match, cx: {cx:?}"),
                 )));
             }
         };
@@ -1,6 +1,10 @@
 //! MakeTransport with pool
 
 use motore::service::UnaryService;
+use volo::{
+    loadbalance::{affinity::Affinity, drain::Drainer, warmup::WarmupHandle},
+    net::Address,
+};
 
 use super::{Key, Pool, Poolable, Pooled, Ver};
 
@@ -42,6 +46,35 @@ where
     }
 }
 
+impl<MT> PooledMakeTransport<MT, Address>
+where
+    MT: UnaryService<Address>,
+    MT::Response: Poolable + Send + 'static,
+{
+    /// Subscribe to the [`Drainer`] for draining pooled connections.
+    pub fn drain_on(&self, drainer: &Drainer) {
+        self.pool.drain_on(drainer);
+    }
+
+    /// Subscribe to the [`Affinity`] for pre-creating pooled connections.
+    pub fn prewarm_on(&self, affinity: &Affinity, ver: Ver)
+    where
+        MT: Clone + Send + Sync + 'static,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        self.pool.prewarm_on(affinity, ver, self.inner.clone());
+    }
+
+    /// Subscribe to the [`WarmupHandle`] for pre-creating pooled connections when warming up.
+    pub fn warmup_on(&self, warmup: &WarmupHandle, ver: Ver)
+    where
+        MT: Clone + Send + Sync + 'static,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        self.pool.warmup_on(warmup, ver, self.inner.clone());
+    }
+}
+
 impl<MT, K: Key> UnaryService<(K, Ver)> for PooledMakeTransport<MT, K>
 where
     MT: UnaryService<K> + Send + Clone + 'static + Sync,
@@ -29,7 +29,13 @@ use tokio::{
     sync::oneshot,
     time::{Duration, Instant, Interval, interval},
 };
-use volo::Unwrap;
+use volo::{
+    Unwrap,
+    loadbalance::{affinity::Affinity, drain::Drainer, warmup::WarmupHandle},
+    net::Address,
+};
+
+use super::heartbeat::Heartbeat;
 
 pub trait Key: Eq + Hash + Clone + Debug + Unpin + Send + 'static {}
 
@@ -104,6 +110,7 @@ impl<K: Key, T: Poolable> Clone for Pool<K, T> {
 pub struct Config {
     max_idle_per_key: usize,
     timeout: Duration,
+    pub(crate) heartbeat: Option<Heartbeat>,
 }
 
 impl Default for Config {
@@ -111,6 +118,7 @@ impl Default for Config {
         Config {
             max_idle_per_key: 10240,
             timeout: Duration::from_secs(15),
+            heartbeat: None,
         }
     }
 }
@@ -120,6 +128,7 @@ impl Config {
         Config {
             max_idle_per_key,
             timeout,
+            heartbeat: None,
         }
     }
 
@@ -132,6 +141,12 @@ impl Config {
         self.timeout = timeout;
         self
     }
+
+    /// Probe idle connections by the [`Heartbeat`] before reusing them.
+    pub fn heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
 }
 
 // This is because `Weak::new()` *allocates* space for `T`, even if it
@@ -177,6 +192,7 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
             connecting: HashSet::new(),
             idle: HashMap::new(),
             waiters: HashMap::new(),
+            draining: HashSet::new(),
             timeout: cfg.timeout,
             max_idle_per_key: cfg.max_idle_per_key,
             _pool_drop_rx: rx,
@@ -235,6 +251,28 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
         ver: Ver,
         mt: MT,
     ) -> Result<Pooled<K, T>, crate::ClientError>
+    where
+        T: Poolable + Send + 'static,
+        MT: UnaryService<K, Response = T> + Send + 'static + Sync,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        let start = Instant::now();
+        let mut pooled = self.acquire(key, ver, mt).await?;
+        let acquisition = &mut pooled.acquisition;
+        // only the connection made by this call has the connect cost
+        acquisition.reused = acquisition.connect.is_none();
+        acquisition.wait = start
+            .elapsed()
+            .saturating_sub(acquisition.connect.unwrap_or_default());
+        Ok(pooled)
+    }
+
+    async fn acquire<MT>(
+        &self,
+        key: K,
+        ver: Ver,
+        mt: MT,
+    ) -> Result<Pooled<K, T>, crate::ClientError>
     where
         T: Poolable + Send + 'static,
         MT: UnaryService<K, Response = T> + Send + 'static + Sync,
@@ -327,6 +365,7 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
         };
 
         // 3. select waiter and mc return future
+        let start = Instant::now();
         let checkout = self.checkout(key.clone(), (rx, waiter_token));
         let connector = {
             let key = key.clone();
@@ -340,7 +379,9 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
                                     "[VOLO] make_transport finished for {:?}",
                                     &connecting.key
                                 );
-                                Ok(this.pooled(connecting, t))
+                                let mut pooled = this.pooled(connecting, t);
+                                pooled.acquisition.connect = Some(start.elapsed());
+                                Ok(pooled)
                             }
                             Err(e) => Err(e),
                         },
@@ -383,11 +424,78 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
         }
     }
 
+    /// Drain connections of the key, idle connections are dropped, and in-use connections will be
+    /// closed rather than put back into the pool after use.
+    ///
+    /// The key is no longer draining once a new connection is made for it.
+    pub fn drain(&self, key: &K) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.drain(key);
+        }
+    }
+
+    /// Pre-create connections of the key until there are `connections` idle ones, returns the
+    /// count of idle connections of the key ready after pre-creating.
+    ///
+    /// Multiplex connections are shared, so at most one is created. Nothing is created if the key
+    /// is draining.
+    pub async fn prewarm<MT>(&self, key: K, ver: Ver, connections: usize, mt: MT) -> usize
+    where
+        MT: UnaryService<K, Response = T> + Send + 'static + Sync,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        let (idle, missing) = {
+            let inner = self.inner.lock().volo_unwrap();
+            if inner.draining.contains(&key) {
+                return 0;
+            }
+            let idle = inner.idle.get(&key).map_or(0, VecDeque::len);
+            let missing = match ver {
+                Ver::Multiplex => usize::from(idle == 0 && !inner.connecting.contains(&key)),
+                Ver::PingPong => connections.min(inner.max_idle_per_key).saturating_sub(idle),
+            };
+            (idle, missing)
+        };
+        if missing == 0 {
+            return match ver {
+                Ver::Multiplex => idle.min(1),
+                Ver::PingPong => idle,
+            };
+        }
+        tracing::debug!("[VOLO] pre-creating {missing} connections for {:?}", key);
+        if ver == Ver::Multiplex {
+            // the connection is kept in the pool after the returned one is dropped
+            return match self.get(key.clone(), ver, mt).await {
+                Ok(_) => 1,
+                Err(e) => {
+                    tracing::debug!("[VOLO] pre-creating connection error: {e:?}, key: {key:?}");
+                    0
+                }
+            };
+        }
+        let results = future::join_all((0..missing).map(|_| mt.call(key.clone()))).await;
+        let mut ready = idle;
+        for result in results {
+            match result {
+                Ok(t) => {
+                    self.inner.lock().volo_unwrap().put(key.clone(), t);
+                    ready += 1;
+                }
+                Err(e) => {
+                    let e = e.into();
+                    tracing::debug!("[VOLO] pre-creating connection error: {e:?}, key: {key:?}");
+                }
+            }
+        }
+        ready
+    }
+
     fn pooled(&self, mut connecting: Connecting<K, T>, value: T) -> Pooled<K, T> {
         let (value, pool_ref) = {
             match value.reserve() {
                 Reservation::Shared(to_insert, to_return) => {
                     let mut inner = self.inner.lock().unwrap();
+                    inner.draining.remove(&connecting.key);
                     inner.put(connecting.key.clone(), to_insert);
                     inner.connected(&connecting.key);
                     connecting.pool = WeakOpt::none();
@@ -396,6 +504,9 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
                     (to_return, None)
                 }
                 Reservation::Unique(value) => {
+                    if let Ok(mut inner) = self.inner.lock() {
+                        inner.draining.remove(&connecting.key);
+                    }
                     // Unique reservations must take a reference to the pool
                     // since they hope to reinsert once the reservation is
                     // completed
@@ -423,6 +534,64 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
     }
 }
 
+impl<T: Poolable + Send + 'static> Pool<Address, T> {
+    /// Subscribe to the [`Drainer`] for draining connections to instances removed by the
+    /// discovery.
+    pub fn drain_on(&self, drainer: &Drainer) {
+        let pool = Arc::downgrade(&self.inner);
+        drainer.subscribe(move |addr| {
+            let Some(pool) = pool.upgrade() else {
+                return false;
+            };
+            if let Ok(mut inner) = pool.lock() {
+                inner.drain(addr);
+            }
+            true
+        });
+    }
+
+    /// Subscribe to the [`Affinity`] for pre-creating connections to instances taking over
+    /// requests, the connections are made by `mt`.
+    pub fn prewarm_on<MT>(&self, affinity: &Affinity, ver: Ver, mt: MT)
+    where
+        MT: UnaryService<Address, Response = T> + Clone + Send + 'static + Sync,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        let pool = Arc::downgrade(&self.inner);
+        affinity.subscribe(move |addr, connections| {
+            let Some(inner) = pool.upgrade() else {
+                return false;
+            };
+            let pool = Pool { inner };
+            let (addr, mt) = (addr.clone(), mt.clone());
+            tokio::spawn(async move { pool.prewarm(addr, ver, connections, mt).await });
+            true
+        });
+    }
+
+    /// Subscribe to the [`WarmupHandle`] for pre-creating connections when the client is warmed
+    /// up, the connections are made by `mt`.
+    pub fn warmup_on<MT>(&self, warmup: &WarmupHandle, ver: Ver, mt: MT)
+    where
+        MT: UnaryService<Address, Response = T> + Clone + Send + 'static + Sync,
+        MT::Error: Into<crate::ClientError> + Send,
+    {
+        let pool = Arc::downgrade(&self.inner);
+        warmup.subscribe(move |addr, connections| {
+            let inner = pool.upgrade()?;
+            let expected = match ver {
+                Ver::Multiplex => 1,
+                Ver::PingPong => connections.min(inner.lock().volo_unwrap().max_idle_per_key),
+            };
+            let pool = Pool { inner };
+            let (addr, mt) = (addr.clone(), mt.clone());
+            Some(Box::pin(async move {
+                (expected, pool.prewarm(addr, ver, connections, mt).await)
+            }))
+        });
+    }
+}
+
 pub struct Connecting<K: Key, T: Poolable> {
     key: K,
     pool: WeakOpt<Mutex<Inner<K, T>>>,
@@ -490,6 +659,17 @@ struct Idle<T> {
     idle_at: Instant,
 }
 
+/// How a [`Pooled`] connection is acquired from the [`Pool`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Acquisition {
+    /// Whether the connection is an idle one reused from the pool rather than a fresh one.
+    pub reused: bool,
+    /// Time spent waiting for the connection, the time establishing a new connection is excluded.
+    pub wait: Duration,
+    /// Time spent establishing a new connection, only available for fresh connections.
+    pub connect: Option<Duration>,
+}
+
 #[pin_project]
 pub struct Pooled<K: Key, T: Poolable> {
     key: Option<K>,
@@ -497,6 +677,7 @@ pub struct Pooled<K: Key, T: Poolable> {
     t: Option<T>,
     // shared transport no need pool ref
     pool: WeakOpt<Mutex<Inner<K, T>>>,
+    acquisition: Acquisition,
 }
 
 impl<K: Key, T: Poolable> Pooled<K, T> {
@@ -505,9 +686,15 @@ impl<K: Key, T: Poolable> Pooled<K, T> {
             key: Some(key),
             t: Some(t),
             pool,
+            acquisition: Acquisition::default(),
         }
     }
 
+    /// Get how the connection is acquired from the pool.
+    pub fn acquisition(&self) -> Acquisition {
+        self.acquisition
+    }
+
     pub(crate) async fn reuse(mut self) {
         let inner = self.t.take().volo_unwrap();
         if !inner.reusable().await {
@@ -596,6 +783,8 @@ struct Inner<K: Key, T: Poolable> {
     idle: HashMap<K, VecDeque<Idle<T>>>,
     // waiters wait for idle transport
     waiters: HashMap<K, WaiterList<T>>,
+    // keys whose connections are draining, connections of them will not be put back
+    draining: HashSet<K>,
     // idle timeout and check interval
     timeout: Duration,
     // idle count per key
@@ -629,7 +818,20 @@ impl<K: Key, T: Poolable> Inner<K, T> {
 }
 
 impl<K: Key, T: Poolable> Inner<K, T> {
+    fn drain(&mut self, key: &K) {
+        tracing::trace!("[VOLO] draining connections for {:?}", key);
+        self.idle.remove(key);
+        self.draining.insert(key.clone());
+    }
+
     fn put(&mut self, key: K, t: T) {
+        if self.draining.contains(&key) {
+            tracing::trace!(
+                "[VOLO] [pool put]: dropping draining connection for {:?}",
+                key
+            );
+            return;
+        }
         // check the wait queue
         let mut value = Some(t);
         if let Some(waiters) = self.waiters.get_mut(&key) {
@@ -738,3 +940,88 @@ impl<K: Key, T: Poolable> Future for IdleTask<K, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod pool_tests {
+    use motore::service::UnaryService;
+    use tokio::time::Duration;
+
+    use super::{Pool, Poolable, Ver};
+
+    struct Conn;
+
+    impl Poolable for Conn {
+        async fn reusable(&self) -> bool {
+            true
+        }
+    }
+
+    struct MakeConn;
+
+    impl UnaryService<u32> for MakeConn {
+        type Response = Conn;
+        type Error = crate::ClientError;
+
+        async fn call(&self, _: u32) -> Result<Self::Response, Self::Error> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(Conn)
+        }
+    }
+
+    #[tokio::test]
+    async fn acquisition() {
+        let pool = Pool::<u32, Conn>::new(None);
+
+        let conn = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        let acquisition = conn.acquisition();
+        assert!(!acquisition.reused);
+        assert!(acquisition.connect.unwrap() >= Duration::from_millis(10));
+        conn.reuse().await;
+
+        let conn = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        let acquisition = conn.acquisition();
+        assert!(acquisition.reused);
+        assert!(acquisition.connect.is_none());
+    }
+
+    #[tokio::test]
+    async fn drain() {
+        let pool = Pool::<u32, Conn>::new(None);
+
+        let idle = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        let in_use = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        idle.reuse().await;
+        pool.drain(&1);
+
+        // the in-use connection is not put back after draining
+        in_use.reuse().await;
+        let conn = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        assert!(!conn.acquisition().reused);
+
+        // a new connection is pooled as usual
+        conn.reuse().await;
+        let conn = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        assert!(conn.acquisition().reused);
+    }
+
+    #[tokio::test]
+    async fn prewarm() {
+        let pool = Pool::<u32, Conn>::new(None);
+
+        assert_eq!(pool.prewarm(1, Ver::PingPong, 2, MakeConn).await, 2);
+        let first = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        let second = pool.get(1, Ver::PingPong, MakeConn).await.unwrap();
+        assert!(first.acquisition().reused);
+        assert!(second.acquisition().reused);
+
+        // only the missing connections are created
+        first.reuse().await;
+        assert_eq!(pool.prewarm(1, Ver::PingPong, 2, MakeConn).await, 2);
+        assert_eq!(pool.inner.lock().unwrap().idle[&1].len(), 2);
+
+        // draining keys are not pre-created
+        pool.drain(&2);
+        assert_eq!(pool.prewarm(2, Ver::PingPong, 2, MakeConn).await, 0);
+        assert!(!pool.inner.lock().unwrap().idle.contains_key(&2));
+    }
+}
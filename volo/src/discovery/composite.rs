@@ -0,0 +1,481 @@
+//! Combinators of [`Discover`]s.
+//!
+//! - [`Fallback`] uses the secondary [`Discover`] when the primary one fails, e.g., falling back
+//!   from the registry to a static seed list during outages of the control plane.
+//! - [`Merge`] returns instances of both [`Discover`]s, e.g., from two registries during a
+//!   migration.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::discovery::{StaticDiscover, composite::Fallback};
+//!
+//! # let registry = StaticDiscover::from(vec!["127.0.0.1:8000".parse().unwrap()]);
+//! let seeds = StaticDiscover::from(vec!["10.0.0.1:8000".parse().unwrap()]);
+//! let discover = Fallback::new(registry, seeds);
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_broadcast::Receiver;
+use futures::{StreamExt, stream::BoxStream};
+
+use super::{Change, Discover, Instance, diff_address};
+use crate::{context::Endpoint, loadbalance::error::LoadBalanceError};
+
+const CHANNEL_CAPACITY: usize = 64;
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A [`Discover`] falling back to the secondary [`Discover`] when the primary one fails.
+///
+/// When the primary [`Discover`] returns an error (or no instances, see
+/// [`Fallback::fallback_on_empty`]), it is considered unhealthy and skipped for the retry
+/// interval, so that a control plane in outage is not requested for each discovery.
+///
+/// Changes are only watched from the primary [`Discover`], and changes without any instances are
+/// ignored if [`Fallback::fallback_on_empty`] is enabled, so that instances from the secondary
+/// one are not cleared by the primary one in outage.
+#[derive(Clone)]
+pub struct Fallback<P, S> {
+    primary: P,
+    secondary: S,
+    fallback_on_empty: bool,
+    retry_interval: Duration,
+    unhealthy_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl<P, S> Fallback<P, S> {
+    /// Create a new [`Fallback`] with the primary and secondary [`Discover`]s.
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self {
+            primary,
+            secondary,
+            fallback_on_empty: true,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            unhealthy_until: Default::default(),
+        }
+    }
+
+    /// Whether to fall back when the primary [`Discover`] returns no instances.
+    ///
+    /// Default is `true`.
+    pub fn fallback_on_empty(mut self, enable: bool) -> Self {
+        self.fallback_on_empty = enable;
+        self
+    }
+
+    /// Set the interval for retrying the primary [`Discover`] after it fails.
+    ///
+    /// Default is 10 seconds.
+    pub fn with_retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Whether the primary [`Discover`] is being used.
+    pub fn is_primary_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| until <= Instant::now())
+    }
+
+    fn set_primary_healthy(&self, healthy: bool) {
+        let until = (!healthy).then(|| Instant::now() + self.retry_interval);
+        *self.unhealthy_until.lock().unwrap() = until;
+    }
+}
+
+impl<P, S> Discover for Fallback<P, S>
+where
+    P: Discover,
+    S: Discover,
+{
+    type Key = P::Key;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        if self.is_primary_healthy() {
+            match self.primary.discover(endpoint).await {
+                Ok(instances) if !(self.fallback_on_empty && instances.is_empty()) => {
+                    self.set_primary_healthy(true);
+                    return Ok(instances);
+                }
+                Ok(_) => {
+                    tracing::warn!(
+                        "[VOLO] primary discover returns no instances of {}, fallback to the \
+                         secondary",
+                        endpoint.service_name
+                    );
+                }
+                Err(err) => {
+                    let err: LoadBalanceError = err.into();
+                    tracing::warn!(
+                        "[VOLO] primary discover of {} failed, fallback to the secondary: {err}",
+                        endpoint.service_name
+                    );
+                }
+            }
+            self.set_primary_healthy(false);
+        }
+        self.secondary.discover(endpoint).await.map_err(Into::into)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        self.primary.key(endpoint)
+    }
+
+    fn watch(&self, keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        let inner = self.primary.watch(keys)?;
+        if !self.fallback_on_empty {
+            return Some(inner);
+        }
+        let (mut tx, rx) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        tx.set_overflow(true);
+        let unhealthy_until = self.unhealthy_until.clone();
+
+        tokio::spawn(async move {
+            let mut inner = inner.filter(|change| std::future::ready(!change.all.is_empty()));
+            while let Some(change) = inner.next().await {
+                // the primary one is recovered
+                *unhealthy_until.lock().unwrap() = None;
+                // all receivers are dropped
+                if tx.broadcast(change).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Some(rx)
+    }
+}
+
+/// A [`Discover`] returning instances of both [`Discover`]s.
+///
+/// Instances are deduplicated by the address, and the ones of the first [`Discover`] are kept.
+/// If one of the [`Discover`]s fails, instances of the other one are returned.
+#[derive(Clone)]
+pub struct Merge<A, B>
+where
+    A: Discover,
+{
+    a: A,
+    b: B,
+    cache: MergeCache<A::Key>,
+}
+
+// last instances of both for merging changes
+type MergeCache<K> = Arc<Mutex<HashMap<K, [Vec<Arc<Instance>>; 2]>>>;
+
+impl<A, B> Merge<A, B>
+where
+    A: Discover,
+    B: Discover<Key = A::Key>,
+{
+    /// Create a new [`Merge`] of the two [`Discover`]s.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            cache: Default::default(),
+        }
+    }
+}
+
+fn merge(a: &[Arc<Instance>], b: &[Arc<Instance>]) -> Vec<Arc<Instance>> {
+    let mut seen = HashSet::with_capacity(a.len() + b.len());
+    a.iter()
+        .chain(b)
+        .filter(|instance| seen.insert(instance.address.clone()))
+        .cloned()
+        .collect()
+}
+
+impl<A, B> Discover for Merge<A, B>
+where
+    A: Discover,
+    B: Discover<Key = A::Key>,
+{
+    type Key = A::Key;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        // errors are converted before awaiting the other one, since they may not be `Send`
+        let a = async {
+            self.a
+                .discover(endpoint)
+                .await
+                .map_err(Into::<LoadBalanceError>::into)
+        };
+        let b = async {
+            self.b
+                .discover(endpoint)
+                .await
+                .map_err(Into::<LoadBalanceError>::into)
+        };
+        let (a, b) = match futures::future::join(a, b).await {
+            (Ok(a), Ok(b)) => (a, b),
+            (Ok(instances), Err(err)) | (Err(err), Ok(instances)) => {
+                tracing::warn!(
+                    "[VOLO] merged discover of {} failed: {err}",
+                    endpoint.service_name
+                );
+                return Ok(instances);
+            }
+            (Err(err), Err(_)) => return Err(err),
+        };
+        let instances = merge(&a, &b);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(self.a.key(endpoint), [a, b]);
+        Ok(instances)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        self.a.key(endpoint)
+    }
+
+    fn watch(&self, keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        fn changes<K>(
+            rx: Option<Receiver<Change<K>>>,
+            idx: usize,
+        ) -> BoxStream<'static, (usize, Change<K>)>
+        where
+            K: Clone + Send + Sync + 'static,
+        {
+            match rx {
+                Some(rx) => rx.map(move |change| (idx, change)).boxed(),
+                None => futures::stream::empty().boxed(),
+            }
+        }
+
+        let (a, b) = (self.a.watch(keys), self.b.watch(keys));
+        if a.is_none() && b.is_none() {
+            return None;
+        }
+        let mut inner = futures::stream::select(changes(a, 0), changes(b, 1));
+        let (mut tx, rx) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        tx.set_overflow(true);
+        let cache = self.cache.clone();
+
+        tokio::spawn(async move {
+            while let Some((idx, change)) = inner.next().await {
+                let (prev, next) = {
+                    let mut cache = cache.lock().unwrap();
+                    let lists = cache.entry(change.key.clone()).or_default();
+                    let prev = merge(&lists[0], &lists[1]);
+                    lists[idx] = change.all;
+                    (prev, merge(&lists[0], &lists[1]))
+                };
+                let (mut merged, changed) = diff_address(change.key, prev, next);
+                if !changed && change.updated.is_empty() {
+                    continue;
+                }
+                merged.updated = change.updated;
+                // all receivers are dropped
+                if tx.broadcast(merged).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Some(rx)
+    }
+}
+
+#[cfg(test)]
+mod composite_tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use async_broadcast::{Receiver, Sender};
+
+    use super::{Fallback, Merge};
+    use crate::{
+        context::Endpoint,
+        discovery::{Change, Discover, Instance, StaticDiscover},
+        loadbalance::error::LoadBalanceError,
+        net::Address,
+    };
+
+    fn instance(addr: &str) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: Address::Ip(addr.parse().unwrap()),
+            weight: 1,
+            tags: Default::default(),
+        })
+    }
+
+    /// A [`Discover`] counting calls, which fails if there are no instances.
+    #[derive(Clone, Default)]
+    struct MockDiscover {
+        instances: Vec<Arc<Instance>>,
+        fail: bool,
+        calls: Arc<AtomicUsize>,
+        tx: Option<Sender<Change<()>>>,
+    }
+
+    impl Discover for MockDiscover {
+        type Key = ();
+        type Error = LoadBalanceError;
+
+        async fn discover<'s>(
+            &'s self,
+            _: &'s Endpoint,
+        ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if self.fail {
+                return Err(LoadBalanceError::Discover("registry is down".into()));
+            }
+            Ok(self.instances.clone())
+        }
+
+        fn key(&self, _: &Endpoint) -> Self::Key {}
+
+        fn watch(&self, _: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+            self.tx.as_ref().map(Sender::new_receiver)
+        }
+    }
+
+    fn change(all: Vec<Arc<Instance>>) -> Change<()> {
+        Change {
+            key: (),
+            all,
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        let empty = Endpoint::new("".into());
+        let seeds = StaticDiscover::new(vec![instance("10.0.0.1:8000")]);
+        let primary = MockDiscover {
+            fail: true,
+            ..Default::default()
+        };
+        let discover = Fallback::new(primary.clone(), seeds.clone());
+
+        assert_eq!(
+            discover.discover(&empty).await.unwrap(),
+            vec![instance("10.0.0.1:8000")]
+        );
+        assert!(!discover.is_primary_healthy());
+        // the primary one is skipped in the retry interval
+        discover.discover(&empty).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::Relaxed), 1);
+
+        // it is retried after the interval
+        let discover =
+            Fallback::new(primary.clone(), seeds.clone()).with_retry_interval(Duration::ZERO);
+        discover.discover(&empty).await.unwrap();
+        discover.discover(&empty).await.unwrap();
+        assert_eq!(primary.calls.load(Ordering::Relaxed), 3);
+
+        // empty instances
+        let primary = MockDiscover::default();
+        let discover = Fallback::new(primary.clone(), seeds.clone());
+        assert_eq!(discover.discover(&empty).await.unwrap().len(), 1);
+        let discover = Fallback::new(primary, seeds).fallback_on_empty(false);
+        assert!(discover.discover(&empty).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fallback_watch() {
+        let empty = Endpoint::new("".into());
+        let (tx, _rx) = async_broadcast::broadcast(4);
+        let primary = MockDiscover {
+            fail: true,
+            tx: Some(tx.clone()),
+            ..Default::default()
+        };
+        let discover = Fallback::new(
+            primary,
+            StaticDiscover::new(vec![instance("10.0.0.1:8000")]),
+        );
+        discover.discover(&empty).await.unwrap();
+        assert!(!discover.is_primary_healthy());
+
+        let mut rx = discover.watch(None).unwrap();
+        // changes without instances are ignored
+        tx.broadcast(change(Vec::new())).await.unwrap();
+        tx.broadcast(change(vec![instance("10.0.0.2:8000")]))
+            .await
+            .unwrap();
+        let change = rx.recv().await.unwrap();
+        assert_eq!(change.all, vec![instance("10.0.0.2:8000")]);
+        assert!(discover.is_primary_healthy());
+    }
+
+    #[tokio::test]
+    async fn merge() {
+        let empty = Endpoint::new("".into());
+        let a = StaticDiscover::new(vec![instance("10.0.0.1:8000"), instance("10.0.0.2:8000")]);
+        let b = StaticDiscover::new(vec![instance("10.0.0.2:8000"), instance("10.0.0.3:8000")]);
+        let discover = Merge::new(a.clone(), b);
+        assert_eq!(
+            discover.discover(&empty).await.unwrap(),
+            vec![
+                instance("10.0.0.1:8000"),
+                instance("10.0.0.2:8000"),
+                instance("10.0.0.3:8000")
+            ]
+        );
+
+        // the failed one is ignored
+        let failed = MockDiscover {
+            fail: true,
+            ..Default::default()
+        };
+        let discover = Merge::new(failed.clone(), a);
+        assert_eq!(discover.discover(&empty).await.unwrap().len(), 2);
+        let discover = Merge::new(failed.clone(), failed);
+        assert!(discover.discover(&empty).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn merge_watch() {
+        let empty = Endpoint::new("".into());
+        let (tx, _rx) = async_broadcast::broadcast(4);
+        let a = MockDiscover {
+            instances: vec![instance("10.0.0.1:8000")],
+            tx: Some(tx.clone()),
+            ..Default::default()
+        };
+        let b = MockDiscover {
+            instances: vec![instance("10.0.0.2:8000")],
+            ..Default::default()
+        };
+        let discover = Merge::new(a, b);
+        discover.discover(&empty).await.unwrap();
+
+        let mut rx = discover.watch(None).unwrap();
+        tx.broadcast(change(vec![instance("10.0.0.3:8000")]))
+            .await
+            .unwrap();
+        let change = rx.recv().await.unwrap();
+        assert_eq!(
+            change.all,
+            vec![instance("10.0.0.3:8000"), instance("10.0.0.2:8000")]
+        );
+        assert_eq!(change.added, vec![instance("10.0.0.3:8000")]);
+        assert_eq!(change.removed, vec![instance("10.0.0.1:8000")]);
+    }
+}
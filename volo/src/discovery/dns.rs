@@ -0,0 +1,344 @@
+//! DNS based service discovery.
+//!
+//! [`DnsDiscover`] resolves the service name of [`Endpoint`] by DNS and caches the result, the
+//! cached result is refreshed in background by the TTL of records, and changes are pushed to the
+//! load balancer through [`Discover::watch`].
+//!
+//! It is useful for headless Kubernetes services, whose domain names are resolved to addresses of
+//! all pods and the addresses change as pods are rescheduled.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender};
+use dashmap::{DashMap, mapref::entry::Entry};
+use faststr::FastStr;
+use hickory_resolver::{Resolver, TokioResolver, name_server::TokioConnectionProvider};
+
+use super::{Change, Discover, Instance, diff_address};
+use crate::{context::Endpoint, loadbalance::error::LoadBalanceError, net::Address};
+
+const DEFAULT_PORT: u16 = 80;
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A [`Discover`] resolving service names by DNS with caching and background refreshing.
+///
+/// The service name of [`Endpoint`] should be `host:port`, or an SRV name like
+/// `_grpc._tcp.my-svc.my-ns.svc.cluster.local` if [`DnsDiscover::with_srv`] is enabled, in which
+/// case ports and weights are read from the SRV records.
+///
+/// Records are refreshed when their TTL expires, and the interval is clamped by
+/// [`DnsDiscover::with_refresh_interval`]. If a refresh fails, the stale result is kept and used
+/// until the next successful refresh.
+#[derive(Clone)]
+pub struct DnsDiscover {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    resolver: TokioResolver,
+    srv: bool,
+    default_port: u16,
+    min_refresh_interval: Duration,
+    max_refresh_interval: Duration,
+    cache: DashMap<FastStr, Vec<Arc<Instance>>>,
+    tx: Sender<Change<FastStr>>,
+    // keep the channel open even if there is no subscriber
+    rx: InactiveReceiver<Change<FastStr>>,
+}
+
+/// Result of a resolution, including the time when it expires.
+struct Resolved {
+    instances: Vec<Arc<Instance>>,
+    valid_until: Instant,
+}
+
+impl DnsDiscover {
+    /// Create a [`DnsDiscover`] with the given resolver.
+    pub fn new(resolver: TokioResolver) -> Self {
+        let (mut tx, rx) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        // the load balancer only cares about the latest result
+        tx.set_overflow(true);
+        Self {
+            inner: Arc::new(Inner {
+                resolver,
+                srv: false,
+                default_port: DEFAULT_PORT,
+                min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+                max_refresh_interval: DEFAULT_MAX_REFRESH_INTERVAL,
+                cache: DashMap::new(),
+                tx,
+                rx: rx.deactivate(),
+            }),
+        }
+    }
+
+    /// Create a [`DnsDiscover`] with the system configuration, e.g., `/etc/resolv.conf`.
+    pub fn system() -> Result<Self, LoadBalanceError> {
+        let resolver = Resolver::builder(TokioConnectionProvider::default())
+            .map_err(|e| LoadBalanceError::Discover(Box::new(e)))?
+            .build();
+        Ok(Self::new(resolver))
+    }
+
+    /// Resolve service names as SRV records.
+    ///
+    /// Only records with the lowest priority are used, since others are for fallback.
+    pub fn with_srv(mut self, srv: bool) -> Self {
+        self.inner_mut().srv = srv;
+        self
+    }
+
+    /// Set the port for service names without port, default is 80.
+    pub fn with_default_port(mut self, port: u16) -> Self {
+        self.inner_mut().default_port = port;
+        self
+    }
+
+    /// Set the minimum and maximum interval of refreshing, default is 5s and 60s.
+    pub fn with_refresh_interval(mut self, min: Duration, max: Duration) -> Self {
+        let inner = self.inner_mut();
+        inner.min_refresh_interval = min;
+        inner.max_refresh_interval = max.max(min);
+        self
+    }
+
+    fn inner_mut(&mut self) -> &mut Inner {
+        Arc::get_mut(&mut self.inner).expect("DnsDiscover is configured after being cloned")
+    }
+}
+
+impl Inner {
+    async fn resolve(&self, name: &str) -> Result<Resolved, LoadBalanceError> {
+        if self.srv {
+            self.resolve_srv(name).await
+        } else {
+            let (host, port) = crate::net::proxy::split_host_port(name, self.default_port)
+                .ok_or_else(|| LoadBalanceError::Discover(format!("bad host: {name}").into()))?;
+            let host = host
+                .strip_prefix('[')
+                .and_then(|h| h.strip_suffix(']'))
+                .unwrap_or(host);
+            let lookup = self
+                .resolver
+                .lookup_ip(host)
+                .await
+                .map_err(|e| LoadBalanceError::Discover(Box::new(e)))?;
+            Ok(Resolved {
+                instances: lookup.iter().map(|ip| instance(ip, port, 1)).collect(),
+                valid_until: lookup.valid_until(),
+            })
+        }
+    }
+
+    async fn resolve_srv(&self, name: &str) -> Result<Resolved, LoadBalanceError> {
+        let lookup = self
+            .resolver
+            .srv_lookup(name)
+            .await
+            .map_err(|e| LoadBalanceError::Discover(Box::new(e)))?;
+        let mut valid_until = lookup.as_lookup().valid_until();
+        let priority = lookup.iter().map(|srv| srv.priority()).min();
+        let records = lookup
+            .iter()
+            .filter(|srv| Some(srv.priority()) == priority)
+            .map(|srv| {
+                (
+                    srv.target().to_string(),
+                    srv.port(),
+                    u32::from(srv.weight()).max(1),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let lookups = futures::future::join_all(
+            records
+                .iter()
+                .map(|(target, ..)| self.resolver.lookup_ip(target.as_str())),
+        )
+        .await;
+        let mut instances = Vec::new();
+        for ((target, port, weight), res) in records.iter().zip(lookups) {
+            match res {
+                Ok(lookup) => {
+                    valid_until = valid_until.min(lookup.valid_until());
+                    instances.extend(lookup.iter().map(|ip| instance(ip, *port, *weight)));
+                }
+                Err(e) => tracing::warn!("[VOLO] DnsDiscover: failed to resolve {target}: {e}"),
+            }
+        }
+        Ok(Resolved {
+            instances,
+            valid_until,
+        })
+    }
+
+    fn refresh_delay(&self, valid_until: Instant) -> Duration {
+        valid_until
+            .saturating_duration_since(Instant::now())
+            .clamp(self.min_refresh_interval, self.max_refresh_interval)
+    }
+
+    /// Update the cache and notify the load balancer if the result changes.
+    fn update(&self, key: &FastStr, next: Vec<Arc<Instance>>) {
+        let Some(mut prev) = self.cache.get_mut(key) else {
+            return;
+        };
+        let (change, changed) = diff_address(key.clone(), prev.clone(), next.clone());
+        if !changed {
+            return;
+        }
+        *prev = next;
+        drop(prev);
+        tracing::debug!(
+            "[VOLO] DnsDiscover: {key} changed, added: {}, removed: {}",
+            change.added.len(),
+            change.removed.len()
+        );
+        let _ = self.tx.try_broadcast(change);
+    }
+}
+
+fn instance(ip: IpAddr, port: u16, weight: u32) -> Arc<Instance> {
+    Arc::new(Instance {
+        address: Address::Ip(SocketAddr::new(ip, port)),
+        weight,
+        tags: Default::default(),
+    })
+}
+
+async fn refresh(inner: Weak<Inner>, key: FastStr, mut delay: Duration) {
+    loop {
+        tokio::time::sleep(delay).await;
+        // stop refreshing if the discover has been dropped
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        match inner.resolve(&key).await {
+            Ok(resolved) => {
+                delay = inner.refresh_delay(resolved.valid_until);
+                inner.update(&key, resolved.instances);
+            }
+            Err(e) => {
+                tracing::warn!("[VOLO] DnsDiscover: failed to refresh {key}: {e}");
+                delay = inner.min_refresh_interval;
+            }
+        }
+    }
+}
+
+impl Discover for DnsDiscover {
+    type Key = FastStr;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        if endpoint.address().is_some() {
+            return Ok(Vec::new());
+        }
+        let key = endpoint.service_name();
+        if key.is_empty() {
+            return Err(LoadBalanceError::Discover("missing service name".into()));
+        }
+        if let Some(instances) = self.inner.cache.get(&key) {
+            return Ok(instances.clone());
+        }
+
+        let resolved = self.inner.resolve(&key).await?;
+        match self.inner.cache.entry(key.clone()) {
+            // resolved by another request concurrently
+            Entry::Occupied(entry) => Ok(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(resolved.instances.clone());
+                tokio::spawn(refresh(
+                    Arc::downgrade(&self.inner),
+                    key,
+                    self.inner.refresh_delay(resolved.valid_until),
+                ));
+                Ok(resolved.instances)
+            }
+        }
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        endpoint.service_name()
+    }
+
+    fn watch(&self, _keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        Some(self.inner.rx.activate_cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discover() -> DnsDiscover {
+        let resolver =
+            Resolver::builder_with_config(Default::default(), TokioConnectionProvider::default())
+                .build();
+        DnsDiscover::new(resolver)
+    }
+
+    #[tokio::test]
+    async fn discover_ip_literal() {
+        let discover = discover().with_default_port(8080);
+        let endpoint = Endpoint::new("127.0.0.1".into());
+        let instances = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(
+            instances[0].address,
+            Address::Ip("127.0.0.1:8080".parse().unwrap())
+        );
+        assert!(discover.inner.cache.contains_key("127.0.0.1"));
+
+        let endpoint = Endpoint::new("[::1]:9090".into());
+        let instances = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(
+            instances[0].address,
+            Address::Ip("[::1]:9090".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn update_notifies_changes() {
+        let discover = discover();
+        let mut rx = discover.watch(None).unwrap();
+        let key = FastStr::from_static_str("svc:80");
+        let a = instance("10.0.0.1".parse().unwrap(), 80, 1);
+        let b = instance("10.0.0.2".parse().unwrap(), 80, 1);
+        discover.inner.cache.insert(key.clone(), vec![a.clone()]);
+
+        // not changed
+        discover.inner.update(&key, vec![a.clone()]);
+        assert!(rx.try_recv().is_err());
+
+        discover.inner.update(&key, vec![a.clone(), b.clone()]);
+        let change = rx.try_recv().unwrap();
+        assert_eq!(change.key, key);
+        assert_eq!(change.added, vec![b]);
+        assert!(change.removed.is_empty());
+        assert_eq!(discover.inner.cache.get(&key).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn refresh_delay() {
+        let discover =
+            discover().with_refresh_interval(Duration::from_secs(1), Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(discover.inner.refresh_delay(now), Duration::from_secs(1));
+        assert_eq!(
+            discover
+                .inner
+                .refresh_delay(now + Duration::from_secs(3600)),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -15,7 +15,15 @@ use std::{
 
 use async_broadcast::Receiver;
 
-use crate::{context::Endpoint, loadbalance::error::LoadBalanceError, net::Address};
+use crate::{
+    context::Endpoint,
+    loadbalance::{HEALTHY_TAG, REGION_TAG, ZONE_TAG, error::LoadBalanceError},
+    net::Address,
+};
+
+pub mod composite;
+#[cfg(feature = "dns")]
+pub mod dns;
 
 /// [`Instance`] contains information of an instance from the target service.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -25,6 +33,30 @@ pub struct Instance {
     pub tags: HashMap<Cow<'static, str>, Cow<'static, str>>,
 }
 
+impl Instance {
+    /// The zone of the instance from its tag [`ZONE_TAG`].
+    pub fn zone(&self) -> Option<&str> {
+        self.tags.get(ZONE_TAG).map(AsRef::as_ref)
+    }
+
+    /// The region of the instance from its tag [`REGION_TAG`].
+    pub fn region(&self) -> Option<&str> {
+        self.tags.get(REGION_TAG).map(AsRef::as_ref)
+    }
+
+    /// Whether the instance is healthy, i.e., its weight is not zero and its tag [`HEALTHY_TAG`]
+    /// is not `false`.
+    ///
+    /// Registries often keep unhealthy instances with such metadata instead of removing them.
+    pub fn is_healthy(&self) -> bool {
+        self.weight > 0
+            && self
+                .tags
+                .get(HEALTHY_TAG)
+                .is_none_or(|healthy| !healthy.eq_ignore_ascii_case("false"))
+    }
+}
+
 /// [`Discover`] is the most basic trait for Discover.
 pub trait Discover: Send + Sync + 'static {
     /// `Key` identifies a group of instances, such as the cluster name.
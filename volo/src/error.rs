@@ -0,0 +1,253 @@
+//! The error taxonomy shared by the clients of volo-thrift, volo-grpc and volo-http.
+//!
+//! The errors of clients, i.e., `volo_thrift::ClientError`, `volo_grpc::Status` and
+//! `volo_http::error::ClientError`, implement [`RpcError`], which tells the [`ErrorCategory`], the
+//! [`ErrorOrigin`], whether it's worth retrying and the address of the peer, so the generic
+//! middlewares, e.g., retries, circuit breakers and metrics, can act on errors without downcasting
+//! them to the types of each protocol.
+//!
+//! The underlying errors are kept as the sources, which can be walked by [`Sources`], e.g., the
+//! [`TimeoutError`](crate::timeout::TimeoutError) of a timeout, the [`ConnectError`] of failing
+//! to connect to a peer or the [`PeerError`] of a call to a peer.
+
+use std::{error::Error, fmt, io};
+
+use crate::{loadbalance::error::Retryable, net::Address};
+
+/// The category of an error of a call.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Failed to connect to the peer.
+    Connect,
+    /// Any kind of timeout fired, see [`TimeoutKind`](crate::timeout::TimeoutKind).
+    Timeout,
+    /// The connection is broken after it was established, e.g., reset by the peer.
+    Transport,
+    /// Failed to encode or decode messages, or the peer violated the protocol.
+    Protocol,
+    /// The error returned by the business logic of the peer.
+    Biz,
+    /// Other errors, e.g., building requests or no available instances from the load balancer.
+    Other,
+}
+
+impl ErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Timeout => "timeout",
+            Self::Transport => "transport",
+            Self::Protocol => "protocol",
+            Self::Biz => "biz",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where an error comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorOrigin {
+    /// The error occurs in the local process, e.g., connecting, timeouts or decoding.
+    Local,
+    /// The error is replied by the peer.
+    Remote,
+}
+
+impl ErrorOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Remote => "remote",
+        }
+    }
+}
+
+impl fmt::Display for ErrorOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The common interface of the errors of clients, see the [module docs](self) for more details.
+///
+/// The [`Retryable`] hint tells whether the error is transient, e.g., connect errors and the
+/// timeouts of attempts, which doesn't consider whether the request is idempotent.
+pub trait RpcError: Error + Retryable + Send + Sync + 'static {
+    /// Returns the category of the error.
+    fn category(&self) -> ErrorCategory;
+
+    /// Returns whether the error is replied by the peer or occurs locally.
+    fn origin(&self) -> ErrorOrigin;
+
+    /// Returns the address of the peer the error occurs with if known.
+    fn peer_addr(&self) -> Option<Address>;
+
+    /// Returns the iterator over the error and all its sources.
+    fn sources(&self) -> Sources<'_>
+    where
+        Self: Sized,
+    {
+        Sources::new(self)
+    }
+}
+
+/// Iterator over an error and its sources, including the errors inside [`io::Error`]s, which are
+/// skipped by [`io::Error::source`].
+#[derive(Clone, Debug)]
+pub struct Sources<'a> {
+    next: Option<&'a (dyn Error + 'static)>,
+}
+
+impl<'a> Sources<'a> {
+    /// Create an iterator starting from the `err` itself.
+    pub fn new(err: &'a (dyn Error + 'static)) -> Self {
+        Self { next: Some(err) }
+    }
+}
+
+impl<'a> Iterator for Sources<'a> {
+    type Item = &'a (dyn Error + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let err = self.next?;
+        self.next = match err.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            Some(inner) => Some(inner),
+            None => err.source(),
+        };
+        Some(err)
+    }
+}
+
+/// The error of failing to connect to the peer, which is returned inside an [`io::Error`] of the
+/// same kind by the transports of volo.
+#[derive(Debug)]
+pub struct ConnectError {
+    addr: Address,
+    source: io::Error,
+}
+
+impl ConnectError {
+    pub fn new(addr: Address, source: io::Error) -> Self {
+        Self { addr, source }
+    }
+
+    /// The address of the peer failed to connect to.
+    pub fn addr(&self) -> &Address {
+        &self.addr
+    }
+
+    /// Convert into an [`io::Error`] of the same kind as the source with the error inside, which
+    /// can be found again by [`ConnectError::find`].
+    pub fn into_io_error(self) -> io::Error {
+        io::Error::new(self.source.kind(), self)
+    }
+
+    /// Find the [`ConnectError`] in the error or its sources, see [`Sources`].
+    pub fn find<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a ConnectError> {
+        Sources::new(err).find_map(|err| err.downcast_ref::<ConnectError>())
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to connect to {}: {}", self.addr, self.source)
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// The error of a call to the peer after connected, e.g., the connection is broken or the attempt
+/// is timeout, which is returned inside an [`io::Error`] of the same kind by the clients to record
+/// the address of the peer.
+///
+/// It's displayed as the source, so the messages of errors are not changed.
+#[derive(Debug)]
+pub struct PeerError {
+    addr: Address,
+    source: io::Error,
+}
+
+impl PeerError {
+    pub fn new(addr: Address, source: io::Error) -> Self {
+        Self { addr, source }
+    }
+
+    /// The address of the peer the call is sent to.
+    pub fn addr(&self) -> &Address {
+        &self.addr
+    }
+
+    /// Convert into an [`io::Error`] of the same kind as the source with the error inside, which
+    /// can be found again by [`PeerError::find`].
+    pub fn into_io_error(self) -> io::Error {
+        io::Error::new(self.source.kind(), self)
+    }
+
+    /// Find the [`PeerError`] in the error or its sources, see [`Sources`].
+    pub fn find<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a PeerError> {
+        Sources::new(err).find_map(|err| err.downcast_ref::<PeerError>())
+    }
+}
+
+impl fmt::Display for PeerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+impl Error for PeerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use super::*;
+    use crate::timeout::{TimeoutError, TimeoutKind};
+
+    #[test]
+    fn connect_error_sources() {
+        let addr = Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], 8888)));
+        let timeout = TimeoutError::new(TimeoutKind::Connect, Duration::from_secs(1));
+        let err = ConnectError::new(addr.clone(), timeout.into_io_error()).into_io_error();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            err.to_string(),
+            "failed to connect to 127.0.0.1:8888: connect timeout of 1s expired"
+        );
+
+        assert_eq!(ConnectError::find(&err).unwrap().addr(), &addr);
+        assert_eq!(TimeoutError::find(&err), Some(&timeout));
+        // io error, connect error, io error, timeout error
+        assert_eq!(Sources::new(&err).count(), 4);
+
+        assert!(ConnectError::find(&io::Error::from(io::ErrorKind::ConnectionRefused)).is_none());
+    }
+
+    #[test]
+    fn peer_error_sources() {
+        let addr = Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], 8888)));
+        let timeout = TimeoutError::new(TimeoutKind::Request, Duration::from_secs(1));
+        let err = PeerError::new(addr.clone(), timeout.into_io_error()).into_io_error();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "request timeout of 1s expired");
+
+        assert_eq!(PeerError::find(&err).unwrap().addr(), &addr);
+        assert_eq!(TimeoutError::find(&err), Some(&timeout));
+        assert!(ConnectError::find(&err).is_none());
+    }
+}
@@ -0,0 +1,351 @@
+//! Structured events of framework internals.
+//!
+//! Volo emits events when something notable happens inside the framework, such as connections
+//! being established or closed, requests being retried and discovery changes being applied.
+//! Handlers can be subscribed for each type of events by [`subscribe`], which is useful for
+//! custom telemetry without parsing logs.
+//!
+//! Handlers are called synchronously where the events happen, so they should be cheap and never
+//! block, e.g., by updating counters or sending the events to a channel. Events are not built at
+//! all if there is no handler of their types.
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! use volo::event::{self, ConnectionEstablished};
+//!
+//! static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+//!
+//! let subscription = event::subscribe(|e: &ConnectionEstablished| {
+//!     CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+//!     tracing::info!("{} connection to {}", e.protocol, e.peer);
+//! });
+//! // ...
+//! subscription.unsubscribe();
+//! ```
+//!
+//! Custom components, e.g., circuit breakers, can also emit events of the types defined here or
+//! their own types by [`emit`].
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwap;
+use faststr::FastStr;
+
+use crate::{
+    context::Role,
+    discovery::{Change, Instance},
+    net::Address,
+};
+
+/// Types of events which can be subscribed.
+pub trait Event: Any + Send + Sync + fmt::Debug {}
+
+/// A connection is established.
+#[derive(Clone, Debug)]
+pub struct ConnectionEstablished {
+    /// The protocol of the connection, e.g., `thrift`, `grpc` or `http`.
+    pub protocol: &'static str,
+    /// Whether the connection is made by a client or accepted by a server.
+    pub role: Role,
+    /// The address of the other side.
+    pub peer: Address,
+}
+
+impl Event for ConnectionEstablished {}
+
+/// A connection is closed.
+#[derive(Clone, Debug)]
+pub struct ConnectionClosed {
+    /// The protocol of the connection, e.g., `thrift`, `grpc` or `http`.
+    pub protocol: &'static str,
+    /// Whether the connection is made by a client or accepted by a server.
+    pub role: Role,
+    /// The address of the other side.
+    pub peer: Address,
+    /// How long the connection has been open.
+    pub lifetime: Duration,
+}
+
+impl Event for ConnectionClosed {}
+
+/// A request is sent again after previous attempts failed.
+#[derive(Clone, Debug)]
+pub struct RetryPerformed {
+    /// The service name of the callee.
+    pub callee: FastStr,
+    /// The method of the request, or the path for http requests.
+    pub method: FastStr,
+    /// The number of the attempt, starting from `2` for the first retry.
+    pub attempt: usize,
+    /// The reason of the retry, i.e., the error of the previous attempt.
+    pub reason: String,
+}
+
+impl Event for RetryPerformed {}
+
+/// States of circuit breakers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// The state of a circuit breaker is changed.
+///
+/// It is emitted by circuit breakers by [`emit`].
+#[derive(Clone, Debug)]
+pub struct BreakerStateChanged {
+    /// The name of the breaker, e.g., the callee service or instance it protects.
+    pub name: FastStr,
+    pub from: BreakerState,
+    pub to: BreakerState,
+}
+
+impl Event for BreakerStateChanged {}
+
+/// A change from the discovery is applied to the load balancer.
+#[derive(Clone, Debug)]
+pub struct DiscoveryDiffApplied {
+    /// The key of the change if it is a string, e.g., the service name of the callee.
+    pub key: Option<FastStr>,
+    /// The number of instances after the change.
+    pub total: usize,
+    /// All instances after the change.
+    pub all: Vec<Arc<Instance>>,
+    pub added: Vec<Arc<Instance>>,
+    pub updated: Vec<Arc<Instance>>,
+    pub removed: Vec<Arc<Instance>>,
+}
+
+impl Event for DiscoveryDiffApplied {}
+
+impl<K: 'static> From<&Change<K>> for DiscoveryDiffApplied {
+    fn from(change: &Change<K>) -> Self {
+        let key: &dyn Any = &change.key;
+        let key = if let Some(key) = key.downcast_ref::<FastStr>() {
+            Some(key.clone())
+        } else if let Some(key) = key.downcast_ref::<String>() {
+            Some(FastStr::new(key))
+        } else {
+            key.downcast_ref::<&'static str>()
+                .map(|key| FastStr::from_static_str(key))
+        };
+        Self {
+            key,
+            total: change.all.len(),
+            all: change.all.clone(),
+            added: change.added.clone(),
+            updated: change.updated.clone(),
+            removed: change.removed.clone(),
+        }
+    }
+}
+
+/// Emits [`ConnectionClosed`] when dropped, see [`connection`].
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    protocol: &'static str,
+    role: Role,
+    peer: Address,
+    since: Instant,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        emit(|| ConnectionClosed {
+            protocol: self.protocol,
+            role: self.role,
+            peer: self.peer.clone(),
+            lifetime: self.since.elapsed(),
+        });
+    }
+}
+
+/// Emit [`ConnectionEstablished`] for a new connection, and returns a guard emitting
+/// [`ConnectionClosed`] when dropped, which should be kept as long as the connection.
+///
+/// Returns `None` if neither of them is subscribed, so connections established before
+/// subscribing do not emit [`ConnectionClosed`].
+pub fn connection(protocol: &'static str, role: Role, peer: &Address) -> Option<ConnectionGuard> {
+    if !enabled::<ConnectionEstablished>() && !enabled::<ConnectionClosed>() {
+        return None;
+    }
+    emit(|| ConnectionEstablished {
+        protocol,
+        role,
+        peer: peer.clone(),
+    });
+    Some(ConnectionGuard {
+        protocol,
+        role,
+        peer: peer.clone(),
+        since: Instant::now(),
+    })
+}
+
+type Handler = Arc<dyn Fn(&dyn Any) + Send + Sync>;
+type Handlers = HashMap<TypeId, Vec<(u64, Handler)>>;
+
+/// Handlers by the types of events, replaced as a whole when subscribing or unsubscribing so that
+/// emitting never locks.
+static HANDLERS: LazyLock<ArcSwap<Handlers>> = LazyLock::new(Default::default);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A subscribed handler, see [`subscribe`].
+///
+/// The handler is kept subscribed when it is dropped, use [`Subscription::unsubscribe`] to remove
+/// the handler.
+#[derive(Debug)]
+pub struct Subscription {
+    type_id: TypeId,
+    id: u64,
+}
+
+impl Subscription {
+    /// Remove the handler.
+    pub fn unsubscribe(self) {
+        HANDLERS.rcu(|handlers| {
+            let mut handlers = HashMap::clone(handlers);
+            if let Some(list) = handlers.get_mut(&self.type_id) {
+                list.retain(|(id, _)| *id != self.id);
+                if list.is_empty() {
+                    handlers.remove(&self.type_id);
+                }
+            }
+            handlers
+        });
+    }
+}
+
+/// Subscribe a handler to events of type `E`.
+pub fn subscribe<E, F>(handler: F) -> Subscription
+where
+    E: Event,
+    F: Fn(&E) + Send + Sync + 'static,
+{
+    let type_id = TypeId::of::<E>();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let handler: Handler = Arc::new(move |event: &dyn Any| {
+        if let Some(event) = event.downcast_ref::<E>() {
+            handler(event);
+        }
+    });
+    HANDLERS.rcu(|handlers| {
+        let mut handlers = HashMap::clone(handlers);
+        handlers
+            .entry(type_id)
+            .or_default()
+            .push((id, handler.clone()));
+        handlers
+    });
+    Subscription { type_id, id }
+}
+
+/// Whether there is any handler of events of type `E`, for skipping the work of preparing the
+/// events.
+pub fn enabled<E: Event>() -> bool {
+    HANDLERS.load().contains_key(&TypeId::of::<E>())
+}
+
+/// Emit an event built by `f` to the handlers of its type, `f` is not called if there is no
+/// handler.
+pub fn emit<E, F>(f: F)
+where
+    E: Event,
+    F: FnOnce() -> E,
+{
+    let handlers = HANDLERS.load();
+    let Some(list) = handlers.get(&TypeId::of::<E>()) else {
+        return;
+    };
+    let event = f();
+    for (_, handler) in list {
+        handler(&event);
+    }
+}
+
+#[cfg(test)]
+mod event_tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use super::{
+        BreakerState, BreakerStateChanged, ConnectionClosed, ConnectionEstablished, RetryPerformed,
+        connection, emit, subscribe,
+    };
+    use crate::{context::Role, net::Address};
+
+    #[test]
+    fn subscribe_and_emit() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscription = {
+            let count = count.clone();
+            subscribe(move |e: &BreakerStateChanged| {
+                assert_eq!(e.to, BreakerState::Open);
+                count.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        let changed = || BreakerStateChanged {
+            name: "test".into(),
+            from: BreakerState::Closed,
+            to: BreakerState::Open,
+        };
+        emit(changed);
+        emit(changed);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // events without handlers are not built
+        emit(|| -> RetryPerformed { unreachable!() });
+
+        subscription.unsubscribe();
+        emit(changed);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn connection_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let established = {
+            let events = events.clone();
+            subscribe(move |e: &ConnectionEstablished| {
+                if e.protocol == "test" {
+                    events.lock().unwrap().push("established");
+                }
+            })
+        };
+        let closed = {
+            let events = events.clone();
+            subscribe(move |e: &ConnectionClosed| {
+                if e.protocol == "test" {
+                    events.lock().unwrap().push("closed");
+                }
+            })
+        };
+        let peer = Address::from("127.0.0.1:8080".parse::<SocketAddr>().unwrap());
+        let guard = connection("test", Role::Client, &peer);
+        assert!(guard.is_some());
+        drop(guard);
+        established.unsubscribe();
+        closed.unsubscribe();
+        assert_eq!(*events.lock().unwrap(), ["established", "closed"]);
+    }
+}
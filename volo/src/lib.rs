@@ -10,8 +10,12 @@ pub use tokio::main;
 pub mod catch_panic;
 pub mod context;
 pub mod discovery;
+pub mod error;
+pub mod event;
 pub mod loadbalance;
+pub mod memory;
 pub mod net;
+pub mod timeout;
 pub mod util;
 pub use hack::Unwrap;
 #[cfg(target_family = "unix")]
@@ -0,0 +1,149 @@
+//! Pre-creating connections to instances taking over requests under consistent hashing.
+//!
+//! With [`ConsistentHashBalance`], requests of a hash always go to the same instance, so the
+//! pooled connections to it are reused. When the discovery changes the ring, e.g., after scaling,
+//! the hashes of removed instances move to their successors on the ring and some hashes move to
+//! the added instances, then the first calls to them pay the connect latency. [`Affinity`]
+//! receives the instances taking over requests from the load balance layer, which are returned by
+//! [`LoadBalance::takeover`], and notifies the client transports subscribed to it to pre-create
+//! connections to them.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::{
+//!     discovery::StaticDiscover,
+//!     loadbalance::{
+//!         LbConfig,
+//!         affinity::Affinity,
+//!         consistent_hash::{ConsistentHashBalance, ConsistentHashOption},
+//!     },
+//! };
+//!
+//! let discover = StaticDiscover::from(vec!["127.0.0.1:8000".parse().unwrap()]);
+//! let lb = LbConfig::new(
+//!     ConsistentHashBalance::<()>::new(ConsistentHashOption::default()),
+//!     discover,
+//! )
+//! .affinity(Affinity::new(4));
+//! ```
+//!
+//! [`ConsistentHashBalance`]: super::consistent_hash::ConsistentHashBalance
+//! [`LoadBalance::takeover`]: super::LoadBalance::takeover
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::net::Address;
+
+type Hook = Box<dyn Fn(&Address, usize) -> bool + Send + Sync>;
+
+/// [`Affinity`] notifies client transports to pre-create connections to instances taking over
+/// requests after changes of the discovery.
+///
+/// It is cheap to clone, and all clones share the same subscribers.
+#[derive(Clone)]
+pub struct Affinity {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    connections: usize,
+    hooks: Mutex<Vec<Hook>>,
+}
+
+impl Default for Affinity {
+    /// Create an [`Affinity`] pre-creating one connection for each instance.
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Affinity {
+    /// Create an [`Affinity`] pre-creating `connections` connections for each instance taking over
+    /// requests.
+    ///
+    /// Multiplex transports always keep one connection for an instance.
+    pub fn new(connections: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                connections,
+                hooks: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// The count of connections pre-created for each instance.
+    pub fn connections(&self) -> usize {
+        self.inner.connections
+    }
+
+    /// Subscribe to the pre-creating with a hook, it is called with the address of instance and
+    /// the count of connections that should be ready for it.
+    ///
+    /// The hook should return `false` if the subscriber is gone, e.g., the connection pool has
+    /// been dropped, and then it will be removed.
+    pub fn subscribe<F>(&self, hook: F)
+    where
+        F: Fn(&Address, usize) -> bool + Send + Sync + 'static,
+    {
+        self.inner
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(hook));
+    }
+
+    /// Pre-create connections to the address.
+    pub fn warm(&self, address: &Address) {
+        if self.inner.connections == 0 {
+            return;
+        }
+        tracing::debug!("[VOLO] pre-creating connections to {address}");
+        self.inner
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|hook| hook(address, self.inner.connections));
+    }
+}
+
+impl fmt::Debug for Affinity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Affinity")
+            .field("connections", &self.inner.connections)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod affinity_tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use super::Affinity;
+    use crate::net::Address;
+
+    #[test]
+    fn test_warm() {
+        let affinity = Affinity::new(2);
+        let warmed = Arc::new(Mutex::new(Vec::new()));
+        let cloned = warmed.clone();
+        affinity.subscribe(move |addr, connections| {
+            cloned.lock().unwrap().push((addr.clone(), connections));
+            true
+        });
+        // the subscriber is gone
+        affinity.subscribe(|_, _| false);
+
+        let addr = Address::from("127.0.0.1:8000".parse::<SocketAddr>().unwrap());
+        affinity.warm(&addr);
+        affinity.warm(&addr);
+        assert_eq!(*warmed.lock().unwrap(), vec![(addr.clone(), 2), (addr, 2)]);
+        assert_eq!(affinity.inner.hooks.lock().unwrap().len(), 1);
+    }
+}
@@ -54,10 +54,10 @@ impl Default for ConsistentHashOption {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// RealNode is a wrapper of Instance
-struct RealNode(Instance);
+struct RealNode(Arc<Instance>);
 
-impl From<Instance> for RealNode {
-    fn from(instance: Instance) -> Self {
+impl From<Arc<Instance>> for RealNode {
+    fn from(instance: Arc<Instance>) -> Self {
         RealNode(instance)
     }
 }
@@ -200,7 +200,7 @@ where
         };
         let mut virtual_nodes = Vec::with_capacity(sum_of_nodes);
         for instance in instances {
-            let real_node = Arc::new(RealNode::from((*instance).clone()));
+            let real_node = Arc::new(RealNode::from(instance.clone()));
             real_nodes.push(real_node.clone());
             let mut weight = 1;
             if self.option.weighted {
@@ -290,6 +290,53 @@ where
             entry.replace_entry(Arc::new(self.build_weighted_instances(changes.all)));
         }
     }
+
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        self.router
+            .get(&discover.key(endpoint))?
+            .real_nodes
+            .iter()
+            .find(|node| &node.0.address == address)
+            .map(|node| node.0.clone())
+    }
+
+    fn takeover(&self, changes: &Change<<D as Discover>::Key>) -> Vec<Address> {
+        // the ring is built only after the endpoint has been called
+        let Some(ring) = self.router.get(&changes.key).map(|ring| ring.clone()) else {
+            return Vec::new();
+        };
+        if ring.virtual_nodes.is_empty() {
+            return Vec::new();
+        }
+        let mut seen = HashSet::new();
+        let mut addrs = Vec::new();
+        for instance in &changes.added {
+            // instances with zero weight have no virtual nodes
+            if (!self.option.weighted || instance.weight > 0) && seen.insert(&instance.address) {
+                addrs.push(instance.address.clone());
+            }
+        }
+        if !changes.removed.is_empty() {
+            // hashes of a removed virtual node move to its successor on the new ring
+            let removed = self.build_weighted_instances(changes.removed.clone());
+            for node in &removed.virtual_nodes {
+                let mut index = ring.virtual_nodes.partition_point(|vn| vn.hash < node.hash);
+                if index == ring.virtual_nodes.len() {
+                    index = 0;
+                }
+                let addr = &ring.virtual_nodes[index].real_node.0.address;
+                if seen.insert(addr) {
+                    addrs.push(addr.clone());
+                }
+            }
+        }
+        addrs
+    }
 }
 
 #[cfg(test)]
@@ -307,7 +354,7 @@ mod tests {
     use super::{ConsistentHashBalance, ConsistentHashOption, LoadBalance};
     use crate::{
         context::Endpoint,
-        discovery::{Instance, StaticDiscover},
+        discovery::{Change, Instance, StaticDiscover},
         loadbalance::RequestHash,
         net::Address,
     };
@@ -526,4 +573,51 @@ mod tests {
             assert!(virtual_nodes.contains(&node));
         }
     }
+
+    #[tokio::test]
+    async fn test_consistent_hash_takeover() {
+        test_with_meta_info(consistent_hash_takeover_tests).await;
+    }
+
+    async fn consistent_hash_takeover_tests() {
+        let empty = empty_endpoint();
+        let mut instances = (0..5)
+            .map(|i| new_instance(format!("127.0.0.{i}:8000"), 10))
+            .collect::<Vec<_>>();
+        let discovery = StaticDiscover::new(instances.clone());
+        let lb = ConsistentHashBalance::new(ConsistentHashOption::new(1, 10, true));
+
+        let hashes = (0..1000).map(|_| rand::random::<u64>()).collect::<Vec<_>>();
+        let mut before = Vec::new();
+        for hash in &hashes {
+            set_request_hash(*hash);
+            let mut picker = lb.get_picker(&empty, &discovery).await.unwrap();
+            before.push(picker.next().unwrap());
+        }
+
+        let removed = instances.remove(0);
+        let added = new_instance("127.0.0.9:8000".to_string(), 10);
+        instances.push(added.clone());
+        let change = Change {
+            key: (),
+            all: instances,
+            added: vec![added.clone()],
+            updated: Vec::new(),
+            removed: vec![removed.clone()],
+        };
+        LoadBalance::<StaticDiscover>::rebalance(&lb, change.clone());
+        let takeover = LoadBalance::<StaticDiscover>::takeover(&lb, &change);
+        assert!(takeover.contains(&added.address));
+        assert!(!takeover.contains(&removed.address));
+
+        // all hashes moved to other instances are taken over by the returned instances
+        for (hash, before) in hashes.iter().zip(before) {
+            set_request_hash(*hash);
+            let mut picker = lb.get_picker(&empty, &discovery).await.unwrap();
+            let after = picker.next().unwrap();
+            if after != before {
+                assert!(takeover.contains(&after));
+            }
+        }
+    }
 }
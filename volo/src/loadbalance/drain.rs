@@ -0,0 +1,308 @@
+//! Draining connections to instances removed by service discovery.
+//!
+//! When the discovery removes an instance, the load balancer stops picking it after
+//! [`LoadBalance::rebalance`], but the client transports may still keep connections to it in their
+//! pools. [`Drainer`] receives the removals from the load balance layer and notifies the client
+//! transports subscribed to it, then they drop the idle connections of the instance, and close
+//! the in-use ones after the in-flight calls are completed rather than putting them back into
+//! the pool.
+//!
+//! When the connections are drained is decided by a [`DrainPolicy`], e.g., waiting for a while
+//! since some discoveries may remove an instance temporarily and add it back soon.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use volo::{
+//!     discovery::StaticDiscover,
+//!     loadbalance::{LbConfig, random::WeightedRandomBalance},
+//! };
+//!
+//! let discover = StaticDiscover::from(vec!["127.0.0.1:8000".parse().unwrap()]);
+//! let lb = LbConfig::new(WeightedRandomBalance::with_discover(&discover), discover)
+//!     .drain_policy(Duration::from_secs(5));
+//! ```
+//!
+//! [`LoadBalance::rebalance`]: super::LoadBalance::rebalance
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    discovery::{Change, Instance},
+    net::Address,
+};
+
+/// [`DrainPolicy`] decides when connections to a removed instance are drained.
+pub trait DrainPolicy: Send + Sync + 'static {
+    /// Returns how long to wait before draining connections to the removed instance, or [`None`]
+    /// if the connections should not be drained, and they will be closed by the idle timeout of
+    /// pools.
+    ///
+    /// If the instance is added back by the discovery before the delay elapses, the draining is
+    /// cancelled.
+    fn delay(&self, instance: &Instance) -> Option<Duration>;
+}
+
+/// Drain after a fixed delay.
+impl DrainPolicy for Duration {
+    fn delay(&self, _: &Instance) -> Option<Duration> {
+        Some(*self)
+    }
+}
+
+impl<F> DrainPolicy for F
+where
+    F: Fn(&Instance) -> Option<Duration> + Send + Sync + 'static,
+{
+    fn delay(&self, instance: &Instance) -> Option<Duration> {
+        self(instance)
+    }
+}
+
+type Hook = Box<dyn Fn(&Address) -> bool + Send + Sync>;
+
+/// [`Drainer`] notifies client transports to drain connections to instances removed by the
+/// discovery.
+///
+/// It is cheap to clone, and all clones share the same subscribers.
+#[derive(Clone)]
+pub struct Drainer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    policy: Box<dyn DrainPolicy>,
+    hooks: Mutex<Vec<Hook>>,
+    // removed instances waiting for the delay of policy, with the id of each drain
+    pending: Mutex<HashMap<Address, u64>>,
+    next_id: AtomicU64,
+}
+
+impl Default for Drainer {
+    /// Create a [`Drainer`] draining connections immediately when instances are removed.
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+impl Drainer {
+    /// Create a [`Drainer`] with the [`DrainPolicy`].
+    pub fn new<P: DrainPolicy>(policy: P) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                policy: Box::new(policy),
+                hooks: Mutex::new(Vec::new()),
+                pending: Mutex::new(HashMap::new()),
+                next_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Subscribe to the draining with a hook, it is called with the address of instance whose
+    /// connections should be drained.
+    ///
+    /// The hook should return `false` if the subscriber is gone, e.g., the connection pool has
+    /// been dropped, and then it will be removed.
+    pub fn subscribe<F>(&self, hook: F)
+    where
+        F: Fn(&Address) -> bool + Send + Sync + 'static,
+    {
+        self.inner
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(hook));
+    }
+
+    /// Drain connections to the address immediately.
+    pub fn drain(&self, address: &Address) {
+        tracing::debug!("[VOLO] draining connections to {address}");
+        self.inner
+            .hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|hook| hook(address));
+    }
+
+    /// Handle a [`Change`] from the discovery.
+    ///
+    /// Connections to the removed instances will be drained by the [`DrainPolicy`], and the
+    /// pending draining of added instances will be cancelled.
+    pub fn on_change<K>(&self, change: &Change<K>) {
+        if !change.added.is_empty() {
+            let mut pending = self.pending();
+            for instance in &change.added {
+                pending.remove(&instance.address);
+            }
+        }
+        for instance in &change.removed {
+            // the address may still be used by another instance
+            if change
+                .all
+                .iter()
+                .any(|item| item.address == instance.address)
+            {
+                continue;
+            }
+            let Some(delay) = self.inner.policy.delay(instance) else {
+                continue;
+            };
+            if delay.is_zero() {
+                self.pending().remove(&instance.address);
+                self.drain(&instance.address);
+                continue;
+            }
+
+            let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+            self.pending().insert(instance.address.clone(), id);
+            let drainer = self.clone();
+            let address = instance.address.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let still_pending = {
+                    let mut pending = drainer.pending();
+                    if pending.get(&address) == Some(&id) {
+                        pending.remove(&address);
+                        true
+                    } else {
+                        false
+                    }
+                };
+                if still_pending {
+                    drainer.drain(&address);
+                }
+            });
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<Address, u64>> {
+        self.inner
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for Drainer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drainer")
+            .field("pending", &*self.pending())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod drain_tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::Drainer;
+    use crate::{
+        discovery::{Change, Instance},
+        net::Address,
+    };
+
+    fn instance(addr: &str) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: Address::from(addr.parse::<SocketAddr>().unwrap()),
+            weight: 1,
+            tags: Default::default(),
+        })
+    }
+
+    fn change(
+        all: &[Arc<Instance>],
+        added: &[Arc<Instance>],
+        removed: &[Arc<Instance>],
+    ) -> Change<()> {
+        Change {
+            key: (),
+            all: all.to_vec(),
+            added: added.to_vec(),
+            updated: Vec::new(),
+            removed: removed.to_vec(),
+        }
+    }
+
+    fn subscribe(drainer: &Drainer) -> Arc<Mutex<Vec<Address>>> {
+        let drained = Arc::new(Mutex::new(Vec::new()));
+        let cloned = drained.clone();
+        drainer.subscribe(move |addr| {
+            cloned.lock().unwrap().push(addr.clone());
+            true
+        });
+        drained
+    }
+
+    #[tokio::test]
+    async fn test_drain_immediately() {
+        let drainer = Drainer::default();
+        let drained = subscribe(&drainer);
+        let (a, b) = (instance("127.0.0.1:8000"), instance("127.0.0.2:8000"));
+
+        drainer.on_change(&change(
+            std::slice::from_ref(&b),
+            &[],
+            std::slice::from_ref(&a),
+        ));
+        assert_eq!(*drained.lock().unwrap(), vec![a.address.clone()]);
+
+        // subscribers which are gone are removed
+        drainer.subscribe(|_| false);
+        drainer.drain(&b.address);
+        drainer.drain(&b.address);
+        assert_eq!(drained.lock().unwrap().len(), 3);
+        assert_eq!(drainer.inner.hooks.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_drain_delay() {
+        let drainer = Drainer::new(Duration::from_millis(50));
+        let drained = subscribe(&drainer);
+        let (a, b) = (instance("127.0.0.1:8000"), instance("127.0.0.2:8000"));
+
+        drainer.on_change(&change(&[], &[], &[a.clone(), b.clone()]));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(drained.lock().unwrap().is_empty());
+
+        // added back before the delay elapses
+        drainer.on_change(&change(
+            std::slice::from_ref(&a),
+            std::slice::from_ref(&a),
+            &[],
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(*drained.lock().unwrap(), vec![b.address.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_drain_policy() {
+        let drainer = Drainer::new(|instance: &Instance| {
+            if instance.weight > 1 {
+                None
+            } else {
+                Some(Duration::ZERO)
+            }
+        });
+        let drained = subscribe(&drainer);
+        let a = instance("127.0.0.1:8000");
+        let mut b = (*instance("127.0.0.2:8000")).clone();
+        b.weight = 10;
+
+        drainer.on_change(&change(&[], &[], &[a.clone(), Arc::new(b)]));
+        assert_eq!(*drained.lock().unwrap(), vec![a.address.clone()]);
+    }
+}
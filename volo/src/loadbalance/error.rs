@@ -9,6 +9,8 @@ pub enum LoadBalanceError {
     Discover(#[from] BoxError),
     #[error("missing 'request_hash' for consistent hash load balancer")]
     MissRequestHash,
+    #[error("no instance matches route tags `{0}`")]
+    NoMatchedInstance(super::tag::RouteTags),
 }
 
 pub trait Retryable {
@@ -4,12 +4,25 @@ use async_broadcast::RecvError;
 use motore::Service;
 use tracing::warn;
 
-use super::error::{LoadBalanceError, Retryable};
-use crate::{Layer, context::Context, discovery::Discover, loadbalance::LoadBalance};
+use super::{
+    PickInfo, RetryCount, ZONE_TAG,
+    affinity::Affinity,
+    drain::Drainer,
+    error::{LoadBalanceError, Retryable},
+    warmup::WarmupHandle,
+};
+use crate::{
+    Layer,
+    context::Context,
+    discovery::Discover,
+    error::RpcError,
+    event::{self, DiscoveryDiffApplied, RetryPerformed},
+    loadbalance::LoadBalance,
+};
 
 #[derive(Clone)]
 pub struct LoadBalanceService<D, LB, S> {
-    discover: D,
+    discover: Arc<D>,
     load_balance: Arc<LB>,
     service: S,
     retry: usize,
@@ -21,20 +34,82 @@ where
     LB: LoadBalance<D>,
 {
     pub fn new(discover: D, load_balance: LB, service: S, retry: usize) -> Self {
+        Self::with_drainer(discover, load_balance, service, retry, None)
+    }
+
+    /// Create a [`LoadBalanceService`] which also notifies the [`Drainer`] when the discovery
+    /// removes instances.
+    pub fn with_drainer(
+        discover: D,
+        load_balance: LB,
+        service: S,
+        retry: usize,
+        drainer: Option<Drainer>,
+    ) -> Self {
+        Self::with_watchers(discover, load_balance, service, retry, drainer, None, None)
+    }
+
+    fn with_watchers(
+        discover: D,
+        load_balance: LB,
+        service: S,
+        retry: usize,
+        drainer: Option<Drainer>,
+        affinity: Option<Affinity>,
+        warmup: Option<WarmupHandle>,
+    ) -> Self {
         let lb = Arc::new(load_balance);
 
         let service = Self {
-            discover,
+            discover: Arc::new(discover),
             load_balance: lb.clone(),
             service,
             retry,
         };
 
+        if let Some(warmup) = warmup {
+            let (discover, lb) = (service.discover.clone(), lb.clone());
+            warmup.set_resolver(move |endpoint| {
+                let (discover, lb) = (discover.clone(), lb.clone());
+                Box::pin(async move {
+                    let instances = discover.discover(endpoint).await.map_err(Into::into)?;
+                    // fill the cache of the load balancer, it may fail without the information
+                    // of requests, e.g., the request hash
+                    let _ = lb.get_picker(endpoint, &*discover).await;
+                    Ok(instances
+                        .iter()
+                        .map(|instance| instance.address.clone())
+                        .collect())
+                })
+            });
+        }
+
         if let Some(mut channel) = service.discover.watch(None) {
             tokio::spawn(async move {
                 loop {
                     match channel.recv().await {
-                        Ok(recv) => lb.rebalance(recv),
+                        Ok(recv) => {
+                            let emit = event::enabled::<DiscoveryDiffApplied>();
+                            if drainer.is_none() && affinity.is_none() && !emit {
+                                lb.rebalance(recv);
+                                continue;
+                            }
+                            // stop picking the removed instances before draining their
+                            // connections
+                            let change = recv.clone();
+                            lb.rebalance(recv);
+                            if emit {
+                                event::emit(|| DiscoveryDiffApplied::from(&change));
+                            }
+                            if let Some(drainer) = &drainer {
+                                drainer.on_change(&change);
+                            }
+                            if let Some(affinity) = &affinity {
+                                for addr in lb.takeover(&change) {
+                                    affinity.warm(&addr);
+                                }
+                            }
+                        }
                         Err(err) => match err {
                             RecvError::Closed => break,
                             _ => warn!("[VOLO] discovering subscription error: {:?}", err),
@@ -54,7 +129,7 @@ where
     LB: LoadBalance<D>,
     S: Service<Cx, Req> + 'static + Send + Sync,
     LoadBalanceError: Into<S::Error>,
-    S::Error: Debug + Retryable,
+    S::Error: RpcError,
     Req: Clone + Send + Sync + 'static,
 {
     type Response = S::Response;
@@ -62,21 +137,60 @@ where
     type Error = S::Error;
 
     async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        // instances failed in previous calls with the same context, e.g., retried by outer layers
+        let previous = cx.extensions_mut().remove::<PickInfo>();
         let callee = cx.rpc_info().callee();
+        let picked_by_lb = match (&callee.address, &previous) {
+            (None, _) => true,
+            // the address was set by the previous call rather than by users
+            (Some(addr), Some(info)) => addr == &info.address,
+            _ => false,
+        };
+        if !picked_by_lb {
+            return self.service.call(cx, req).await;
+        }
+        let picker = self
+            .load_balance
+            .get_picker(callee, &self.discover)
+            .await
+            .map_err(|err| err.into())?;
 
-        let picker = match &callee.address {
-            None => self
-                .load_balance
-                .get_picker(callee, &self.discover)
-                .await
-                .map_err(|err| err.into())?,
-            _ => {
-                return self.service.call(cx, req).await;
-            }
+        let (mut attempt, mut failed) = match previous {
+            Some(info) => (info.attempt, info.failed),
+            None => (0, Vec::new()),
         };
+        let skipped = failed.clone();
+        let retry = cx
+            .extensions()
+            .get::<RetryCount>()
+            .map_or(self.retry, |count| count.0);
         let mut call_count = 0;
-        for (addr, _) in picker.zip(0..self.retry + 1) {
+        // the error of the previous attempt, only formatted if retries are subscribed
+        let mut reason = None;
+        for addr in picker
+            .filter(|addr| !skipped.contains(addr))
+            .take(retry + 1)
+        {
             call_count += 1;
+            attempt += 1;
+            if let Some(reason) = reason.take() {
+                event::emit(|| RetryPerformed {
+                    callee: cx.rpc_info().callee().service_name(),
+                    method: cx.rpc_info().method().clone(),
+                    attempt,
+                    reason,
+                });
+            }
+            let instance =
+                self.load_balance
+                    .instance(cx.rpc_info().callee(), &self.discover, &addr);
+            cx.extensions_mut().insert(PickInfo {
+                address: addr.clone(),
+                weight: instance.as_ref().map(|instance| instance.weight),
+                zone: instance.and_then(|instance| instance.tags.get(ZONE_TAG).cloned()),
+                attempt,
+                failed: failed.clone(),
+            });
             cx.rpc_info_mut().callee_mut().address = Some(addr.clone());
 
             match self.service.call(cx, req.clone()).await {
@@ -84,10 +198,22 @@ where
                     return Ok(resp);
                 }
                 Err(err) => {
-                    warn!("[VOLO] call rpcinfo: {:?}, error: {:?}", cx.rpc_info(), err);
+                    failed.push(addr);
+                    if let Some(info) = cx.extensions_mut().get_mut::<PickInfo>() {
+                        info.failed.clone_from(&failed);
+                    }
+                    warn!(
+                        "[VOLO] call rpcinfo: {:?}, {} error: {:?}",
+                        cx.rpc_info(),
+                        err.category(),
+                        err
+                    );
                     if !err.retryable() {
                         return Err(err);
                     }
+                    if event::enabled::<RetryPerformed>() {
+                        reason = Some(format!("{} error: {err}", err.category()));
+                    }
                 }
             }
         }
@@ -112,11 +238,14 @@ where
     }
 }
 
-#[derive(Clone, Default, Copy)]
+#[derive(Clone, Default)]
 pub struct LoadBalanceLayer<D, LB> {
     discover: D,
     load_balance: LB,
     retry_count: usize,
+    drainer: Option<Drainer>,
+    affinity: Option<Affinity>,
+    warmup: Option<WarmupHandle>,
 }
 
 impl<D, LB> LoadBalanceLayer<D, LB> {
@@ -125,8 +254,30 @@ impl<D, LB> LoadBalanceLayer<D, LB> {
             discover,
             load_balance,
             retry_count,
+            drainer: None,
+            affinity: None,
+            warmup: None,
         }
     }
+
+    /// Sets the [`Drainer`] notified when the discovery removes instances.
+    pub fn drainer(mut self, drainer: Drainer) -> Self {
+        self.drainer = Some(drainer);
+        self
+    }
+
+    /// Sets the [`Affinity`] notified of instances taking over requests after the discovery
+    /// changes.
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    /// Sets the [`WarmupHandle`] resolving instances by the discovery when warming up.
+    pub fn warmup(mut self, warmup: WarmupHandle) -> Self {
+        self.warmup = Some(warmup);
+        self
+    }
 }
 
 impl<D, LB, S> Layer<S> for LoadBalanceLayer<D, LB>
@@ -137,18 +288,43 @@ where
     type Service = LoadBalanceService<D, LB, S>;
 
     fn layer(self, inner: S) -> Self::Service {
-        LoadBalanceService::new(self.discover, self.load_balance, inner, self.retry_count)
+        LoadBalanceService::with_watchers(
+            self.discover,
+            self.load_balance,
+            inner,
+            self.retry_count,
+            self.drainer,
+            self.affinity,
+            self.warmup,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::Infallible;
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        fmt,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
 
-    use motore::service::service_fn;
+    use motore::{Service, service::service_fn};
 
     use super::LoadBalanceService;
-    use crate::{discovery::StaticDiscover, loadbalance::random::WeightedRandomBalance};
+    use crate::{
+        context::{Context, Reusable, Role, RpcCx, RpcInfo},
+        discovery::{Instance, StaticDiscover},
+        error::{ErrorCategory, ErrorOrigin, RpcError},
+        event::{self, RetryPerformed},
+        loadbalance::{
+            PickInfo, RetryCount, ZONE_TAG,
+            error::{LoadBalanceError, Retryable},
+            random::WeightedRandomBalance,
+        },
+        net::Address,
+    };
 
     #[derive(Debug)]
     struct MotoreContext;
@@ -166,4 +342,159 @@ mod tests {
 
         LoadBalanceService::new(discover, lb, service, 1);
     }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("test error")
+        }
+    }
+
+    impl std::error::Error for TestError {}
+
+    impl Retryable for TestError {
+        fn retryable(&self) -> bool {
+            true
+        }
+    }
+
+    impl RpcError for TestError {
+        fn category(&self) -> ErrorCategory {
+            ErrorCategory::Transport
+        }
+
+        fn origin(&self) -> ErrorOrigin {
+            ErrorOrigin::Local
+        }
+
+        fn peer_addr(&self) -> Option<Address> {
+            None
+        }
+    }
+
+    impl From<LoadBalanceError> for TestError {
+        fn from(_: LoadBalanceError) -> Self {
+            TestError
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct TestConfig;
+
+    impl Reusable for TestConfig {
+        fn clear(&mut self) {}
+    }
+
+    type TestContext = RpcCx<(), TestConfig>;
+
+    fn instance(addr: &str, zone: &'static str) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: Address::from(addr.parse::<SocketAddr>().unwrap()),
+            weight: 10,
+            tags: HashMap::from([(ZONE_TAG.into(), zone.into())]),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_pick_info() {
+        let discover = StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", "zone-a"),
+            instance("127.0.0.2:8000", "zone-b"),
+        ]);
+        let lb = WeightedRandomBalance::with_discover(&discover);
+        let first = Arc::new(Mutex::new(None));
+        let service = {
+            let first = first.clone();
+            service_fn(move |cx: &mut TestContext, _: ()| {
+                let info = cx.extensions().get::<PickInfo>().unwrap().clone();
+                let mut first = first.lock().unwrap();
+                let res = match &*first {
+                    // the first picked instance always fails
+                    None => {
+                        *first = Some(info.address.clone());
+                        Err(TestError)
+                    }
+                    Some(addr) if addr == &info.address => Err(TestError),
+                    _ => Ok(info),
+                };
+                async move { res }
+            })
+        };
+        let service = LoadBalanceService::new(discover, lb, service, 0);
+
+        let mut cx = TestContext::new(RpcInfo::with_role(Role::Client), ());
+        assert!(service.call(&mut cx, ()).await.is_err());
+        let failed = first.lock().unwrap().clone().unwrap();
+        let info = cx.extensions().get::<PickInfo>().unwrap();
+        assert_eq!(info.attempt, 1);
+        assert!(info.has_failed(&failed));
+
+        // retry with the same context skips the failed instance
+        let info = service.call(&mut cx, ()).await.unwrap();
+        assert_ne!(info.address, failed);
+        assert_eq!(info.attempt, 2);
+        assert_eq!(info.weight, Some(10));
+        assert!(info.zone.is_some());
+        assert!(info.has_failed(&failed));
+    }
+
+    #[tokio::test]
+    async fn test_retry_event() {
+        let discover = StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", "zone-a"),
+            instance("127.0.0.2:8000", "zone-b"),
+        ]);
+        let lb = WeightedRandomBalance::with_discover(&discover);
+        let service = service_fn(|cx: &mut TestContext, _: ()| {
+            let attempt = cx.extensions().get::<PickInfo>().unwrap().attempt;
+            async move { if attempt == 1 { Err(TestError) } else { Ok(()) } }
+        });
+        let service = LoadBalanceService::new(discover, lb, service, 1);
+
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let subscription = {
+            let attempts = attempts.clone();
+            event::subscribe(move |e: &RetryPerformed| {
+                // events of other tests are ignored
+                if e.callee == "retry-event" {
+                    attempts.lock().unwrap().push((e.attempt, e.reason.clone()));
+                }
+            })
+        };
+        let mut cx = TestContext::new(RpcInfo::with_role(Role::Client), ());
+        cx.rpc_info_mut()
+            .callee_mut()
+            .set_service_name("retry-event".into());
+        service.call(&mut cx, ()).await.unwrap();
+        subscription.unsubscribe();
+        assert_eq!(
+            *attempts.lock().unwrap(),
+            [(2, "transport error: test error".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_count() {
+        let discover = StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", "zone-a"),
+            instance("127.0.0.2:8000", "zone-b"),
+        ]);
+        let lb = WeightedRandomBalance::with_discover(&discover);
+        let service = service_fn(|cx: &mut TestContext, _: ()| {
+            let attempt = cx.extensions().get::<PickInfo>().unwrap().attempt;
+            async move { if attempt == 1 { Err(TestError) } else { Ok(()) } }
+        });
+        let service = LoadBalanceService::new(discover, lb, service, 0);
+
+        let mut cx = TestContext::new(RpcInfo::with_role(Role::Client), ());
+        assert!(service.call(&mut cx, ()).await.is_err());
+
+        // the retry count of the call overrides the one of the layer
+        let mut cx = TestContext::new(RpcInfo::with_role(Role::Client), ());
+        cx.extensions_mut().insert(RetryCount(1));
+        service.call(&mut cx, ()).await.unwrap();
+        assert_eq!(cx.extensions().get::<PickInfo>().unwrap().attempt, 2);
+    }
 }
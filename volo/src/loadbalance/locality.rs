@@ -0,0 +1,429 @@
+//! Locality-aware load balancing, preferring instances in the same zone and region.
+//!
+//! [`LocalityBalance`] groups instances into tiers by their [`Instance::zone`] and
+//! [`Instance::region`]: instances in the local zone, instances in other zones of the local
+//! region, and all the others. Requests are sent to the local zone as long as it's healthy enough,
+//! and spill over to the next tiers in proportion to its unhealthy instances.
+//!
+//! The health of a tier is the ratio of its instances which are [`Instance::is_healthy`]. With an
+//! overprovisioning factor `f` (default `1.4`), a tier with health `h` receives `min(1, h * f)` of
+//! the remaining traffic, so the local zone keeps all requests until more than `1 - 1 / f` (about
+//! 28%) of its instances are unhealthy. For example, when half of the local instances are
+//! unhealthy, 70% of the requests stay in the local zone and 30% spill over to the local region.
+//!
+//! Within a tier, healthy instances are picked by weighted random. Unhealthy instances are only
+//! picked when there is no healthy instance at all, or by retries after all healthy ones.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::{
+//!     discovery::StaticDiscover,
+//!     loadbalance::{LbConfig, locality::LocalityBalance},
+//! };
+//!
+//! let discover = StaticDiscover::from(vec!["127.0.0.1:8000".parse().unwrap()]);
+//! let lb = LocalityBalance::with_discover(&discover)
+//!     .zone("us-east-1a")
+//!     .region("us-east-1");
+//! let lb = LbConfig::new(lb, discover);
+//! ```
+
+use std::{hash::Hash, sync::Arc};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use faststr::FastStr;
+use rand::Rng;
+
+use super::{LoadBalance, error::LoadBalanceError};
+use crate::{
+    context::Endpoint,
+    discovery::{Change, Discover, Instance},
+    net::Address,
+};
+
+/// The default overprovisioning factor of [`LocalityBalance`].
+pub const DEFAULT_OVERPROVISIONING: f64 = 1.4;
+
+#[derive(Clone, Debug, Default)]
+struct Locality {
+    zone: Option<FastStr>,
+    region: Option<FastStr>,
+    overprovisioning: f64,
+}
+
+impl Locality {
+    // 0 for the local zone, 1 for the local region and 2 for others
+    fn tier(&self, instance: &Instance) -> usize {
+        let same_region = match (&self.region, instance.region()) {
+            (Some(local), Some(region)) => local == region,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let same_zone = match (&self.zone, instance.zone()) {
+            (Some(local), Some(zone)) => local == zone,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        match (same_region, same_zone) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, _) => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tier {
+    // healthy instances with prefix sums of their weights
+    healthy: Vec<Arc<Instance>>,
+    prefix_sum_of_weights: Vec<u64>,
+    // ratio of requests sent to the tier
+    load: f64,
+}
+
+impl Tier {
+    fn pick(&self) -> Option<&Arc<Instance>> {
+        let total = *self.prefix_sum_of_weights.last()?;
+        let weight = rand::rng().random_range(0..total) + 1;
+        let index = self
+            .prefix_sum_of_weights
+            .binary_search(&weight)
+            .unwrap_or_else(|index| index);
+        self.healthy.get(index)
+    }
+}
+
+#[derive(Debug)]
+struct LocalityInstances {
+    tiers: [Tier; 3],
+    // all instances ordered by tiers, healthy ones first in each tier
+    ordered: Vec<Arc<Instance>>,
+}
+
+impl LocalityInstances {
+    fn new(instances: Vec<Arc<Instance>>, locality: &Locality) -> Self {
+        let mut tiers: [Tier; 3] = Default::default();
+        let mut totals = [0usize; 3];
+        let mut ordered = instances;
+        ordered.sort_by_key(|instance| (locality.tier(instance), !instance.is_healthy()));
+
+        for instance in ordered.iter() {
+            let idx = locality.tier(instance);
+            totals[idx] += 1;
+            if instance.is_healthy() {
+                let tier = &mut tiers[idx];
+                let sum = tier.prefix_sum_of_weights.last().copied().unwrap_or(0);
+                tier.prefix_sum_of_weights
+                    .push(sum + u64::from(instance.weight));
+                tier.healthy.push(instance.clone());
+            }
+        }
+
+        let mut remaining = 1.0f64;
+        for (tier, total) in tiers.iter_mut().zip(totals) {
+            if total == 0 {
+                continue;
+            }
+            let health = tier.healthy.len() as f64 / total as f64;
+            tier.load = remaining.min((health * locality.overprovisioning).min(1.0));
+            remaining -= tier.load;
+        }
+        // scale up if all tiers are not healthy enough to take all requests
+        let assigned = 1.0 - remaining;
+        if remaining > 0.0 && assigned > 0.0 {
+            for tier in tiers.iter_mut() {
+                tier.load /= assigned;
+            }
+        }
+
+        Self { tiers, ordered }
+    }
+
+    fn pick(&self) -> Option<&Arc<Instance>> {
+        let mut point = rand::rng().random::<f64>();
+        for tier in self.tiers.iter().filter(|tier| tier.load > 0.0) {
+            if point < tier.load {
+                return tier.pick();
+            }
+            point -= tier.load;
+        }
+        // floating point errors, or no healthy instance
+        match self.tiers.iter().rev().find(|tier| tier.load > 0.0) {
+            Some(tier) => tier.pick(),
+            None => self.ordered.first(),
+        }
+    }
+}
+
+/// Iterator of instances picked by [`LocalityBalance`].
+///
+/// The first instance is picked by locality and weights, and the following ones for retries are
+/// the other instances ordered by locality with healthy ones first.
+#[derive(Debug)]
+pub struct LocalityPicker {
+    shared_instances: Arc<LocalityInstances>,
+    first: Option<Address>,
+    offset: usize,
+}
+
+impl Iterator for LocalityPicker {
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(first) = &self.first else {
+            let first = self.shared_instances.pick()?.address.clone();
+            self.first = Some(first.clone());
+            return Some(first);
+        };
+        let instances = &self.shared_instances.ordered;
+        while let Some(instance) = instances.get(self.offset) {
+            self.offset += 1;
+            if &instance.address != first {
+                return Some(instance.address.clone());
+            }
+        }
+        None
+    }
+}
+
+/// [`LoadBalance`] preferring instances in the local zone and region, spilling over to other
+/// zones when the local zone is unhealthy.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Debug)]
+pub struct LocalityBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    locality: Locality,
+    router: DashMap<K, Arc<LocalityInstances>>,
+}
+
+impl<K> LocalityBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    /// Create a [`LocalityBalance`] without local zone and region, which balances among all
+    /// instances by their health and weights until [`LocalityBalance::zone`] or
+    /// [`LocalityBalance::region`] is set.
+    pub fn new() -> Self {
+        Self {
+            locality: Locality {
+                overprovisioning: DEFAULT_OVERPROVISIONING,
+                ..Default::default()
+            },
+            router: DashMap::new(),
+        }
+    }
+
+    /// Create a [`LocalityBalance`] for the [`Discover`], which is only used for inferring the
+    /// type of keys.
+    pub fn with_discover<D>(_: &D) -> Self
+    where
+        D: Discover<Key = K>,
+    {
+        Self::new()
+    }
+
+    /// Set the local zone, which is compared with [`Instance::zone`].
+    pub fn zone(mut self, zone: impl Into<FastStr>) -> Self {
+        self.locality.zone = Some(zone.into());
+        self
+    }
+
+    /// Set the local region, which is compared with [`Instance::region`].
+    pub fn region(mut self, region: impl Into<FastStr>) -> Self {
+        self.locality.region = Some(region.into());
+        self
+    }
+
+    /// Set the overprovisioning factor, a larger factor keeps more requests in the local zone
+    /// when some of its instances are unhealthy.
+    ///
+    /// Default is [`DEFAULT_OVERPROVISIONING`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the factor is less than `1.0`.
+    pub fn overprovisioning(mut self, factor: f64) -> Self {
+        assert!(
+            factor >= 1.0,
+            "overprovisioning factor should be at least 1.0"
+        );
+        self.locality.overprovisioning = factor;
+        self
+    }
+}
+
+impl<K> Default for LocalityBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> LoadBalance<D> for LocalityBalance<D::Key>
+where
+    D: Discover,
+{
+    type InstanceIter = LocalityPicker;
+
+    async fn get_picker<'future>(
+        &'future self,
+        endpoint: &'future Endpoint,
+        discover: &'future D,
+    ) -> Result<Self::InstanceIter, LoadBalanceError> {
+        let key = discover.key(endpoint);
+        let instances = if let Some(instances) = self.router.get(&key) {
+            instances.clone()
+        } else {
+            let instances = Arc::new(LocalityInstances::new(
+                discover
+                    .discover(endpoint)
+                    .await
+                    .map_err(|err| err.into())?,
+                &self.locality,
+            ));
+            self.router.insert(key, Arc::clone(&instances));
+            instances
+        };
+        Ok(LocalityPicker {
+            shared_instances: instances,
+            first: None,
+            offset: 0,
+        })
+    }
+
+    fn rebalance(&self, changes: Change<D::Key>) {
+        if let Entry::Occupied(entry) = self.router.entry(changes.key.clone()) {
+            entry.replace_entry(Arc::new(LocalityInstances::new(
+                changes.all,
+                &self.locality,
+            )));
+        }
+    }
+
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        self.router
+            .get(&discover.key(endpoint))?
+            .ordered
+            .iter()
+            .find(|instance| &instance.address == address)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod locality_tests {
+    use std::{borrow::Cow, collections::HashMap, net::SocketAddr, sync::Arc};
+
+    use super::LocalityBalance;
+    use crate::{
+        context::Endpoint,
+        discovery::{Instance, StaticDiscover},
+        loadbalance::{HEALTHY_TAG, LoadBalance, REGION_TAG, ZONE_TAG},
+        net::Address,
+    };
+
+    fn instance(port: u16, zone: &'static str, healthy: bool) -> Arc<Instance> {
+        let mut tags = HashMap::from([
+            (Cow::Borrowed(REGION_TAG), Cow::Borrowed("region-1")),
+            (Cow::Borrowed(ZONE_TAG), Cow::Borrowed(zone)),
+        ]);
+        if !healthy {
+            tags.insert(Cow::Borrowed(HEALTHY_TAG), Cow::Borrowed("false"));
+        }
+        Arc::new(Instance {
+            address: Address::from(SocketAddr::from(([127, 0, 0, 1], port))),
+            weight: 10,
+            tags,
+        })
+    }
+
+    async fn local_ratio(lb: &LocalityBalance<()>, discover: &StaticDiscover) -> f64 {
+        let endpoint = Endpoint::new("".into());
+        let mut local = 0;
+        for _ in 0..10000 {
+            let mut picker = lb.get_picker(&endpoint, discover).await.unwrap();
+            let addr = picker.next().unwrap();
+            let instance = lb.instance(&endpoint, discover, &addr).unwrap();
+            assert!(instance.is_healthy());
+            if instance.zone() == Some("zone-a") {
+                local += 1;
+            }
+        }
+        local as f64 / 10000.0
+    }
+
+    #[tokio::test]
+    async fn prefer_local_zone() {
+        let discover = StaticDiscover::new(vec![
+            instance(8000, "zone-a", true),
+            instance(8001, "zone-a", true),
+            instance(8002, "zone-b", true),
+            instance(8003, "zone-b", true),
+        ]);
+        let lb = LocalityBalance::with_discover(&discover)
+            .zone("zone-a")
+            .region("region-1");
+        assert_eq!(local_ratio(&lb, &discover).await, 1.0);
+
+        // retries go to the other local instance first
+        let endpoint = Endpoint::new("".into());
+        let picker = lb.get_picker(&endpoint, &discover).await.unwrap();
+        let zones = picker
+            .map(|addr| {
+                lb.instance(&endpoint, &discover, &addr)
+                    .unwrap()
+                    .zone()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(zones, ["zone-a", "zone-a", "zone-b", "zone-b"]);
+    }
+
+    #[tokio::test]
+    async fn spill_over() {
+        // half of the local instances are unhealthy, 0.5 * 1.4 of requests stay local
+        let discover = StaticDiscover::new(vec![
+            instance(8000, "zone-a", true),
+            instance(8001, "zone-a", false),
+            instance(8002, "zone-b", true),
+            instance(8003, "zone-b", true),
+        ]);
+        let lb = LocalityBalance::with_discover(&discover)
+            .zone("zone-a")
+            .region("region-1");
+        let ratio = local_ratio(&lb, &discover).await;
+        assert!((ratio - 0.7).abs() < 0.03, "local ratio: {ratio}");
+
+        // all local instances are unhealthy
+        let discover = StaticDiscover::new(vec![
+            instance(8000, "zone-a", false),
+            instance(8002, "zone-b", true),
+        ]);
+        let lb = LocalityBalance::with_discover(&discover).zone("zone-a");
+        assert_eq!(local_ratio(&lb, &discover).await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn all_unhealthy() {
+        let discover = StaticDiscover::new(vec![
+            instance(8000, "zone-a", false),
+            instance(8001, "zone-b", false),
+        ]);
+        let lb = LocalityBalance::with_discover(&discover).zone("zone-a");
+        let endpoint = Endpoint::new("".into());
+        let picker = lb.get_picker(&endpoint, &discover).await.unwrap();
+        assert_eq!(picker.count(), 2);
+    }
+}
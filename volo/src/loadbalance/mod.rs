@@ -1,17 +1,71 @@
+pub mod affinity;
 pub mod consistent_hash;
+pub mod drain;
 pub mod error;
 mod layer;
+pub mod locality;
 pub mod random;
-
-use std::future::Future;
-
-use self::{error::LoadBalanceError, layer::LoadBalanceLayer};
+pub mod round_robin;
+pub mod subset;
+pub mod tag;
+pub mod warmup;
+
+use std::{borrow::Cow, future::Future, sync::Arc};
+
+use self::{
+    affinity::Affinity,
+    drain::{DrainPolicy, Drainer},
+    error::LoadBalanceError,
+    layer::LoadBalanceLayer,
+    warmup::WarmupHandle,
+};
 use crate::{
     context::Endpoint,
-    discovery::{Change, Discover},
+    discovery::{Change, Discover, Instance},
     net::Address,
 };
 
+/// The tag key of [`Instance`] for its zone.
+pub const ZONE_TAG: &str = "zone";
+
+/// The tag key of [`Instance`] for its region.
+pub const REGION_TAG: &str = "region";
+
+/// The tag key of [`Instance`] for its health, the instance is unhealthy if it's `false`.
+pub const HEALTHY_TAG: &str = "healthy";
+
+/// Information of the instance picked by load balancing for the current call.
+///
+/// It is inserted into extensions of the context before each attempt, so that the inner services
+/// and tracing can know which backend is called. When the call fails, the address is recorded into
+/// [`PickInfo::failed`], and a retry (e.g., by an outer retry layer with the same context) will
+/// skip the failed instances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickInfo {
+    /// Address of the picked instance.
+    pub address: Address,
+    /// Weight of the picked instance, [`None`] if the load balancer cannot provide it.
+    pub weight: Option<u32>,
+    /// Zone of the picked instance from its tag [`ZONE_TAG`].
+    pub zone: Option<Cow<'static, str>>,
+    /// Number of the attempt, starts from 1.
+    pub attempt: usize,
+    /// Addresses of instances failed in previous attempts.
+    pub failed: Vec<Address>,
+}
+
+/// The retry count of a call, overriding [`LbConfig::retry_count`] when it is inserted into
+/// extensions of the context, e.g., by the default config of the called method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryCount(pub usize);
+
+impl PickInfo {
+    /// Returns `true` if the address has failed in previous attempts.
+    pub fn has_failed(&self, address: &Address) -> bool {
+        self.failed.contains(address)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Ord, Eq, Hash)]
 pub struct RequestHash(pub u64);
 
@@ -32,18 +86,61 @@ where
     ) -> impl Future<Output = Result<Self::InstanceIter, LoadBalanceError>> + Send;
     /// `rebalance` is the callback method be used in service discovering subscription.
     fn rebalance(&self, changes: Change<D::Key>);
+
+    /// `instance` returns the cached [`Instance`] of the address picked for the endpoint, which is
+    /// used for filling [`PickInfo`].
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        let _ = (endpoint, discover, address);
+        None
+    }
+
+    /// `takeover` returns the addresses of instances taking over requests after `rebalance` with
+    /// the changes, e.g., the added instances and the successors of removed ones on a hash ring.
+    ///
+    /// It's called after `rebalance` when an [`Affinity`] is set, for pre-creating connections to
+    /// them.
+    fn takeover(&self, changes: &Change<D::Key>) -> Vec<Address> {
+        let _ = changes;
+        Vec::new()
+    }
 }
 
 pub trait MkLbLayer {
     type Layer;
 
     fn make(self) -> Self::Layer;
+
+    /// Returns the [`Drainer`] used by the layer, client transports should subscribe to it for
+    /// draining connections to instances removed by the discovery.
+    fn drainer(&self) -> Option<Drainer> {
+        None
+    }
+
+    /// Returns the [`Affinity`] used by the layer, client transports should subscribe to it for
+    /// pre-creating connections to instances taking over requests.
+    fn affinity(&self) -> Option<Affinity> {
+        None
+    }
+
+    /// Returns the [`WarmupHandle`] used by the layer, client transports should subscribe to it
+    /// for pre-creating connections when warming up.
+    fn warmup(&self) -> Option<WarmupHandle> {
+        None
+    }
 }
 
 pub struct LbConfig<L, DISC> {
     load_balance: L,
     discover: DISC,
     retry_count: usize,
+    drainer: Drainer,
+    affinity: Option<Affinity>,
+    warmup: WarmupHandle,
 }
 
 impl<L, DISC> LbConfig<L, DISC> {
@@ -52,6 +149,9 @@ impl<L, DISC> LbConfig<L, DISC> {
             load_balance,
             discover,
             retry_count: 0,
+            drainer: Drainer::default(),
+            affinity: None,
+            warmup: WarmupHandle::default(),
         }
     }
 
@@ -60,6 +160,9 @@ impl<L, DISC> LbConfig<L, DISC> {
             load_balance,
             discover: self.discover,
             retry_count: self.retry_count,
+            drainer: self.drainer,
+            affinity: self.affinity,
+            warmup: self.warmup,
         }
     }
 
@@ -68,6 +171,9 @@ impl<L, DISC> LbConfig<L, DISC> {
             load_balance: self.load_balance,
             discover,
             retry_count: self.retry_count,
+            drainer: self.drainer,
+            affinity: self.affinity,
+            warmup: self.warmup,
         }
     }
 
@@ -76,6 +182,24 @@ impl<L, DISC> LbConfig<L, DISC> {
         self.retry_count = count;
         self
     }
+
+    /// Sets the [`DrainPolicy`] deciding when connections to instances removed by the discovery
+    /// are drained.
+    ///
+    /// Default is draining immediately, see [`drain`] for more details.
+    pub fn drain_policy<P: DrainPolicy>(mut self, policy: P) -> Self {
+        self.drainer = Drainer::new(policy);
+        self
+    }
+
+    /// Sets the [`Affinity`] for pre-creating connections to instances taking over requests
+    /// after changes of the discovery, which is useful with consistent hashing.
+    ///
+    /// Default is `None`, see [`affinity`] for more details.
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
 }
 
 pub struct CustomLayer<L>(pub L);
@@ -84,7 +208,25 @@ impl<LB, DISC> MkLbLayer for LbConfig<LB, DISC> {
     type Layer = LoadBalanceLayer<DISC, LB>;
 
     fn make(self) -> Self::Layer {
-        LoadBalanceLayer::new(self.discover, self.load_balance, self.retry_count)
+        let layer = LoadBalanceLayer::new(self.discover, self.load_balance, self.retry_count)
+            .drainer(self.drainer)
+            .warmup(self.warmup);
+        match self.affinity {
+            Some(affinity) => layer.affinity(affinity),
+            None => layer,
+        }
+    }
+
+    fn drainer(&self) -> Option<Drainer> {
+        Some(self.drainer.clone())
+    }
+
+    fn affinity(&self) -> Option<Affinity> {
+        self.affinity.clone()
+    }
+
+    fn warmup(&self) -> Option<WarmupHandle> {
+        Some(self.warmup.clone())
     }
 }
 
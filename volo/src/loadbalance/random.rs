@@ -167,6 +167,20 @@ where
             entry.replace_entry(Arc::new(WeightedInstances::from(changes.all)));
         }
     }
+
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        self.router
+            .get(&discover.key(endpoint))?
+            .instances
+            .iter()
+            .find(|instance| &instance.address == address)
+            .cloned()
+    }
 }
 
 #[cfg(test)]
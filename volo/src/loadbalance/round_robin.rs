@@ -0,0 +1,271 @@
+//! Weighted round robin load balancing.
+//!
+//! [`WeightedRoundRobinBalance`] uses the smooth weighted round robin algorithm of nginx: every
+//! pick increases the current weight of each instance by its weight, picks the instance with the
+//! largest current weight and decreases it by the total weight. An instance with weight `w` of a
+//! total weight `W` is picked exactly `w` times in every `W` picks, and the picks are interleaved
+//! instead of bursting to the same instance, e.g., weights `5, 1, 1` yield `a a b a c a a`.
+//!
+//! Instances with zero weight are never picked first, but still used for retries.
+
+use std::{
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+
+use super::{LoadBalance, error::LoadBalanceError};
+use crate::{
+    context::Endpoint,
+    discovery::{Change, Discover, Instance},
+    net::Address,
+};
+
+#[derive(Debug)]
+struct RoundRobinInstances {
+    instances: Vec<Arc<Instance>>,
+    total_weight: i64,
+    current_weights: Mutex<Vec<i64>>,
+}
+
+impl RoundRobinInstances {
+    fn pick(&self) -> Option<usize> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut current_weights = self
+            .current_weights
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut picked = None;
+        let mut max = i64::MIN;
+        for (idx, (instance, current)) in self
+            .instances
+            .iter()
+            .zip(current_weights.iter_mut())
+            .enumerate()
+        {
+            if instance.weight == 0 {
+                continue;
+            }
+            *current += instance.weight as i64;
+            if *current > max {
+                max = *current;
+                picked = Some(idx);
+            }
+        }
+        let picked = picked?;
+        current_weights[picked] -= self.total_weight;
+        Some(picked)
+    }
+}
+
+impl From<Vec<Arc<Instance>>> for RoundRobinInstances {
+    fn from(instances: Vec<Arc<Instance>>) -> Self {
+        let total_weight = instances
+            .iter()
+            .map(|instance| instance.weight as i64)
+            .sum();
+        let current_weights = Mutex::new(vec![0; instances.len()]);
+        Self {
+            instances,
+            total_weight,
+            current_weights,
+        }
+    }
+}
+
+/// Iterator of instances picked by [`WeightedRoundRobinBalance`].
+///
+/// The first instance is picked by weighted round robin, and the following ones for retries are
+/// the next instances in order.
+#[derive(Debug)]
+pub struct RoundRobinPicker {
+    shared_instances: Arc<RoundRobinInstances>,
+    last_offset: Option<usize>,
+    iter_times: usize,
+}
+
+impl Iterator for RoundRobinPicker {
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instances = &self.shared_instances.instances;
+        if instances.is_empty() {
+            return None;
+        }
+        self.iter_times += 1;
+        match &mut self.last_offset {
+            None => {
+                let offset = self.shared_instances.pick()?;
+                self.last_offset = Some(offset);
+                Some(instances[offset].address.clone())
+            }
+            Some(last_offset) => {
+                if self.iter_times > instances.len() {
+                    return None;
+                }
+                *last_offset = (*last_offset + 1) % instances.len();
+                Some(instances[*last_offset].address.clone())
+            }
+        }
+    }
+}
+
+/// [`LoadBalance`] picking instances by smooth weighted round robin.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Debug)]
+pub struct WeightedRoundRobinBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    router: DashMap<K, Arc<RoundRobinInstances>>,
+}
+
+impl<K> WeightedRoundRobinBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    pub fn with_discover<D>(_: &D) -> Self
+    where
+        D: Discover<Key = K>,
+    {
+        Self::new()
+    }
+
+    pub fn new() -> Self {
+        Self {
+            router: DashMap::new(),
+        }
+    }
+}
+
+impl<K> Default for WeightedRoundRobinBalance<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> LoadBalance<D> for WeightedRoundRobinBalance<D::Key>
+where
+    D: Discover,
+{
+    type InstanceIter = RoundRobinPicker;
+
+    async fn get_picker<'future>(
+        &'future self,
+        endpoint: &'future Endpoint,
+        discover: &'future D,
+    ) -> Result<Self::InstanceIter, LoadBalanceError> {
+        let key = discover.key(endpoint);
+        let instances = if let Some(instances) = self.router.get(&key) {
+            instances.clone()
+        } else {
+            let instances = Arc::new(RoundRobinInstances::from(
+                discover
+                    .discover(endpoint)
+                    .await
+                    .map_err(|err| err.into())?,
+            ));
+            self.router.insert(key, Arc::clone(&instances));
+            instances
+        };
+        Ok(RoundRobinPicker {
+            shared_instances: instances,
+            last_offset: None,
+            iter_times: 0,
+        })
+    }
+
+    fn rebalance(&self, changes: Change<D::Key>) {
+        if let Entry::Occupied(entry) = self.router.entry(changes.key.clone()) {
+            entry.replace_entry(Arc::new(RoundRobinInstances::from(changes.all)));
+        }
+    }
+
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        self.router
+            .get(&discover.key(endpoint))?
+            .instances
+            .iter()
+            .find(|instance| &instance.address == address)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod round_robin_tests {
+    use std::collections::HashMap;
+
+    use super::{LoadBalance, WeightedRoundRobinBalance};
+    use crate::{
+        context::Endpoint,
+        discovery::{StaticDiscover, WeightedStaticDiscover},
+    };
+
+    #[tokio::test]
+    async fn smooth_weighted_round_robin() {
+        let empty = Endpoint::new("".into());
+        let discover = WeightedStaticDiscover::from(vec![
+            ("127.0.0.1:8000".parse().unwrap(), 5),
+            ("127.0.0.2:8000".parse().unwrap(), 1),
+            ("127.0.0.3:8000".parse().unwrap(), 1),
+        ]);
+        let lb = WeightedRoundRobinBalance::with_discover(&discover);
+
+        let mut picked = Vec::new();
+        for _ in 0..7 {
+            let mut picker = lb.get_picker(&empty, &discover).await.unwrap();
+            picked.push(picker.next().unwrap().to_string());
+        }
+        assert_eq!(
+            picked,
+            [
+                "127.0.0.1:8000",
+                "127.0.0.1:8000",
+                "127.0.0.2:8000",
+                "127.0.0.1:8000",
+                "127.0.0.3:8000",
+                "127.0.0.1:8000",
+                "127.0.0.1:8000",
+            ]
+        );
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..700 {
+            let mut picker = lb.get_picker(&empty, &discover).await.unwrap();
+            *counts
+                .entry(picker.next().unwrap().to_string())
+                .or_default() += 1;
+        }
+        assert_eq!(counts["127.0.0.1:8000"], 500);
+        assert_eq!(counts["127.0.0.2:8000"], 100);
+    }
+
+    #[tokio::test]
+    async fn retry_instances() {
+        let empty = Endpoint::new("".into());
+        let discover = StaticDiscover::from(vec![
+            "127.0.0.1:8000".parse().unwrap(),
+            "127.0.0.2:8000".parse().unwrap(),
+            "127.0.0.3:8000".parse().unwrap(),
+        ]);
+        let lb = WeightedRoundRobinBalance::with_discover(&discover);
+        let picker = lb.get_picker(&empty, &discover).await.unwrap();
+        let mut all = picker.collect::<Vec<_>>();
+        assert_eq!(all.len(), 3);
+        all.sort_by_key(|addr| addr.to_string());
+        all.dedup();
+        assert_eq!(all.len(), 3);
+    }
+}
@@ -0,0 +1,307 @@
+//! Deterministic subsetting of instances before load balancing.
+//!
+//! When a service has a huge number of instances (e.g., 10k+), a client balancing over all of
+//! them keeps too many connections. [`SubsetDiscover`] wraps a [`Discover`] and only returns a
+//! subset of instances to the load balancer, so that each client connects to at most `size`
+//! instances.
+//!
+//! The subset is selected by weighted rendezvous hashing of the client id and addresses of
+//! instances, which means that:
+//!
+//! - The subset of a client is stable, and it changes minimally when instances are added or
+//!   removed.
+//! - Different clients select different subsets, so the load is still spread over all instances,
+//!   and instances with higher weights are more likely to be selected.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::{
+//!     discovery::StaticDiscover,
+//!     loadbalance::{LbConfig, random::WeightedRandomBalance, subset::SubsetDiscover},
+//! };
+//!
+//! let discover = StaticDiscover::from(vec![
+//!     "127.0.0.1:8000".parse().unwrap(),
+//!     "127.0.0.2:8000".parse().unwrap(),
+//!     "127.0.0.3:8000".parse().unwrap(),
+//! ]);
+//! let discover = SubsetDiscover::new(discover, 2, "client-1");
+//! let lb = LbConfig::new(WeightedRandomBalance::with_discover(&discover), discover);
+//! ```
+
+use std::{collections::HashSet, sync::Arc};
+
+use async_broadcast::{Receiver, RecvError};
+
+use crate::{
+    context::Endpoint,
+    discovery::{Change, Discover, Instance, diff_address},
+};
+
+const CHANNEL_CAPACITY: usize = 64;
+
+/// A [`Discover`] returning a deterministic subset of instances from the inner [`Discover`].
+///
+/// See the [module documentation](self) for more details.
+#[derive(Clone)]
+pub struct SubsetDiscover<D> {
+    inner: D,
+    size: usize,
+    seed: u64,
+}
+
+impl<D> SubsetDiscover<D> {
+    /// Create a new [`SubsetDiscover`] selecting at most `size` instances for the client.
+    ///
+    /// The `client_id` decides which instances are selected, it should be unique for each client
+    /// (e.g., the hostname or pod name) so that instances are evenly covered by all clients, and
+    /// be stable across restarts so that connections are not reshuffled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn new(inner: D, size: usize, client_id: impl AsRef<[u8]>) -> Self {
+        assert!(size > 0, "size of subset must be greater than zero");
+        Self {
+            inner,
+            size,
+            seed: mur3::murmurhash3_x64_128(client_id.as_ref(), 0).0,
+        }
+    }
+
+    /// Get the size of the subset.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get a reference to the inner [`Discover`].
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+}
+
+impl<D> Discover for SubsetDiscover<D>
+where
+    D: Discover,
+{
+    type Key = D::Key;
+    type Error = D::Error;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        let instances = self.inner.discover(endpoint).await?;
+        Ok(subset(instances, self.size, self.seed))
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        self.inner.key(endpoint)
+    }
+
+    fn watch(&self, keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        let mut inner = self.inner.watch(keys)?;
+        let (mut tx, rx) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        tx.set_overflow(true);
+        let (size, seed) = (self.size, self.seed);
+
+        tokio::spawn(async move {
+            loop {
+                match inner.recv().await {
+                    Ok(change) => {
+                        let Some(change) = subset_change(change, size, seed) else {
+                            continue;
+                        };
+                        // all receivers are dropped
+                        if tx.broadcast(change).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Overflowed(n)) => {
+                        tracing::warn!("[VOLO] subset discover missed {n} changes");
+                    }
+                }
+            }
+        });
+
+        Some(rx)
+    }
+}
+
+/// Select at most `size` instances by weighted rendezvous hashing with the `seed`.
+fn subset(mut instances: Vec<Arc<Instance>>, size: usize, seed: u64) -> Vec<Arc<Instance>> {
+    if instances.len() <= size {
+        return instances;
+    }
+    let mut scored = instances
+        .drain(..)
+        .map(|instance| (score(&instance, seed), instance))
+        .collect::<Vec<_>>();
+    // the highest scores win, ties are broken by address for determinism
+    scored.sort_unstable_by(|(a, ia), (b, ib)| {
+        b.total_cmp(a)
+            .then_with(|| ia.address.to_string().cmp(&ib.address.to_string()))
+    });
+    scored.truncate(size);
+    scored.into_iter().map(|(_, instance)| instance).collect()
+}
+
+fn score(instance: &Instance, seed: u64) -> f64 {
+    if instance.weight == 0 {
+        return f64::NEG_INFINITY;
+    }
+    let mut buf = seed.to_le_bytes().to_vec();
+    buf.extend_from_slice(instance.address.to_string().as_bytes());
+    let hash = mur3::murmurhash3_x64_128(&buf, 0).0;
+    // map the hash into (0, 1)
+    let unit = ((hash >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+    -f64::from(instance.weight) / unit.ln()
+}
+
+/// Convert a [`Change`] of all instances into a [`Change`] of the subset.
+///
+/// Returns [`None`] if the subset is not changed.
+fn subset_change<K>(change: Change<K>, size: usize, seed: u64) -> Option<Change<K>>
+where
+    K: std::hash::Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    // rebuild the previous instances from the change
+    let added = change
+        .added
+        .iter()
+        .map(|instance| &instance.address)
+        .collect::<HashSet<_>>();
+    let prev = change
+        .all
+        .iter()
+        .filter(|instance| !added.contains(&instance.address))
+        .chain(change.removed.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let prev = subset(prev, size, seed);
+    let next = subset(change.all, size, seed);
+    let selected = next
+        .iter()
+        .map(|instance| instance.address.clone())
+        .collect::<HashSet<_>>();
+    let updated = change
+        .updated
+        .into_iter()
+        .filter(|instance| selected.contains(&instance.address))
+        .collect::<Vec<_>>();
+
+    let (mut subset_change, changed) = diff_address(change.key, prev, next);
+    if !changed && updated.is_empty() {
+        return None;
+    }
+    subset_change.updated = updated;
+    Some(subset_change)
+}
+
+#[cfg(test)]
+mod subset_tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::{SubsetDiscover, subset, subset_change};
+    use crate::{
+        context::Endpoint,
+        discovery::{Change, Discover, Instance, StaticDiscover},
+        net::Address,
+    };
+
+    fn instances(n: usize) -> Vec<Arc<Instance>> {
+        (0..n)
+            .map(|i| {
+                Arc::new(Instance {
+                    address: Address::Ip(
+                        format!("10.0.{}.{}:8000", i / 256, i % 256)
+                            .parse()
+                            .unwrap(),
+                    ),
+                    weight: 1,
+                    tags: Default::default(),
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deterministic_subset() {
+        let all = instances(100);
+        let discover = StaticDiscover::new(all.clone());
+        let empty = Endpoint::new("".into());
+
+        let a = SubsetDiscover::new(discover.clone(), 10, "client-a");
+        let picked = a.discover(&empty).await.unwrap();
+        assert_eq!(picked.len(), 10);
+        // the same client always selects the same subset
+        let again = SubsetDiscover::new(discover.clone(), 10, "client-a");
+        assert_eq!(again.discover(&empty).await.unwrap(), picked);
+        // different clients select different subsets
+        let b = SubsetDiscover::new(discover, 10, "client-b");
+        assert_ne!(b.discover(&empty).await.unwrap(), picked);
+
+        // the order of instances does not matter
+        let mut reversed = all.clone();
+        reversed.reverse();
+        assert_eq!(subset(reversed, 10, a.seed), picked);
+
+        // small sets are returned as is
+        assert_eq!(subset(all[..5].to_vec(), 10, a.seed).len(), 5);
+    }
+
+    #[test]
+    fn subset_spread() {
+        let all = instances(1000);
+        let mut counts = HashMap::new();
+        for i in 0..1000 {
+            let seed = mur3::murmurhash3_x64_128(format!("client-{i}").as_bytes(), 0).0;
+            for instance in subset(all.clone(), 10, seed) {
+                *counts.entry(instance.address.clone()).or_insert(0) += 1;
+            }
+        }
+        // each instance is selected by 10 clients on average
+        assert!(counts.len() > 990);
+        assert!(counts.values().all(|count| *count < 30));
+    }
+
+    #[test]
+    fn minimal_change() {
+        let all = instances(100);
+        let seed = 42;
+        let prev = subset(all.clone(), 10, seed);
+
+        // removing an unselected instance does not change the subset
+        let unselected = all
+            .iter()
+            .find(|instance| !prev.contains(instance))
+            .unwrap()
+            .clone();
+        let change = Change {
+            key: (),
+            all: all.iter().filter(|i| **i != unselected).cloned().collect(),
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: vec![unselected],
+        };
+        assert!(subset_change(change, 10, seed).is_none());
+
+        // removing a selected instance only replaces it
+        let selected = prev[0].clone();
+        let change = Change {
+            key: (),
+            all: all.iter().filter(|i| **i != selected).cloned().collect(),
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: vec![selected.clone()],
+        };
+        let change = subset_change(change, 10, seed).unwrap();
+        assert_eq!(change.all.len(), 10);
+        assert_eq!(change.removed, vec![selected]);
+        assert_eq!(change.added.len(), 1);
+        assert!(change.all.iter().filter(|i| prev.contains(i)).count() == 9);
+    }
+}
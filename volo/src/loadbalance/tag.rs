@@ -0,0 +1,488 @@
+//! Routing requests to instances by tags, e.g., for staging environments and set-based
+//! deployments.
+//!
+//! A request carries [`RouteTags`], e.g., `env=staging-1`, and [`TagRouteBalance`] restricts the
+//! instances picked by the inner [`LoadBalance`] to those whose [`Instance::tags`] have the same
+//! values. When no instance matches, the [`TagFallback`] decides whether to fail the call or to
+//! fall back to the instances without these tags (the base environment) or to all instances.
+//!
+//! The [`RouteTags`] of a call are taken from the callee [`Endpoint`] first, which can be set per
+//! call, and then from the [`metainfo`] of the current task, so a server can store the tags of
+//! the received request into [`metainfo`] and they are used by all calls in the request, which
+//! forms a swimlane across services.
+//!
+//! # Example
+//!
+//! ```
+//! use volo::{
+//!     discovery::StaticDiscover,
+//!     loadbalance::{
+//!         LbConfig,
+//!         random::WeightedRandomBalance,
+//!         tag::{TagFallback, TagRouteBalance},
+//!     },
+//! };
+//!
+//! let discover = StaticDiscover::from(vec!["127.0.0.1:8000".parse().unwrap()]);
+//! let lb =
+//!     TagRouteBalance::with_discover(WeightedRandomBalance::with_discover(&discover), &discover)
+//!         .fallback(TagFallback::Untagged);
+//! let lb = LbConfig::new(lb, discover);
+//! ```
+
+use std::{collections::HashMap, fmt, hash::Hash, sync::Arc};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+use faststr::FastStr;
+
+use super::{LoadBalance, error::LoadBalanceError};
+use crate::{
+    context::Endpoint,
+    discovery::{Change, Discover, Instance},
+    net::Address,
+};
+
+/// The tag key of [`Instance`] and [`RouteTags`] for its environment, e.g., a staging lane.
+pub const ENV_TAG: &str = "env";
+
+/// The tag key of [`Instance`] and [`RouteTags`] for its set (cell) in set-based deployments.
+pub const SET_TAG: &str = "set";
+
+/// Tags of a request for selecting instances, every tag must equal the tag of the same key of an
+/// instance.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RouteTags {
+    // sorted by keys, so that equal tags have the same hash
+    tags: Vec<(FastStr, FastStr)>,
+}
+
+impl RouteTags {
+    /// Create empty [`RouteTags`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tag, see [`RouteTags::insert`].
+    pub fn with(mut self, key: impl Into<FastStr>, value: impl Into<FastStr>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Insert a tag, the value of the key is replaced if it exists.
+    pub fn insert(&mut self, key: impl Into<FastStr>, value: impl Into<FastStr>) {
+        let (key, value) = (key.into(), value.into());
+        match self.tags.binary_search_by(|(k, _)| k.cmp(&key)) {
+            Ok(idx) => self.tags[idx].1 = value,
+            Err(idx) => self.tags.insert(idx, (key, value)),
+        }
+    }
+
+    /// Get the value of a tag.
+    pub fn get(&self, key: &str) -> Option<&FastStr> {
+        self.tags
+            .binary_search_by(|(k, _)| k.as_str().cmp(key))
+            .ok()
+            .map(|idx| &self.tags[idx].1)
+    }
+
+    /// Iterate all tags ordered by keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&FastStr, &FastStr)> {
+        self.tags.iter().map(|(k, v)| (k, v))
+    }
+
+    /// The count of tags.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Whether there is no tag.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Returns whether all tags equal the tags of the instance.
+    pub fn matches(&self, instance: &Instance) -> bool {
+        self.tags.iter().all(|(k, v)| {
+            instance
+                .tags
+                .get(k.as_str())
+                .is_some_and(|tag| tag.as_ref() == v.as_str())
+        })
+    }
+
+    /// Returns whether the instance has none of the tag keys.
+    pub fn is_untagged(&self, instance: &Instance) -> bool {
+        self.tags
+            .iter()
+            .all(|(k, _)| !instance.tags.contains_key(k.as_str()))
+    }
+
+    /// Get the [`RouteTags`] of a call to the callee, which are taken from the callee first and
+    /// then from [`metainfo`] of the current task.
+    pub fn current(callee: &Endpoint) -> Option<Self> {
+        if let Some(tags) = callee.get::<Self>() {
+            return Some(tags.clone());
+        }
+        metainfo::METAINFO
+            .try_with(|mi| mi.borrow().get::<Self>().cloned())
+            .ok()
+            .flatten()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for RouteTags
+where
+    K: Into<FastStr>,
+    V: Into<FastStr>,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut tags = Self::new();
+        for (k, v) in iter {
+            tags.insert(k, v);
+        }
+        tags
+    }
+}
+
+impl fmt::Display for RouteTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (k, v)) in self.tags.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{k}={v}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What [`TagRouteBalance`] does when no instance matches the [`RouteTags`] of a call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TagFallback {
+    /// Fail the call with [`LoadBalanceError::NoMatchedInstance`].
+    Fail,
+    /// Select from instances without any of the tag keys, e.g., the base environment.
+    #[default]
+    Untagged,
+    /// Select from all instances.
+    All,
+}
+
+/// [`LoadBalance`] restricting instances picked by the inner [`LoadBalance`] to those matching
+/// the [`RouteTags`] of calls.
+///
+/// Calls without [`RouteTags`] are balanced among all instances. See the [module
+/// documentation](self) for more details.
+pub struct TagRouteBalance<LB, K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    inner: LB,
+    fallback: TagFallback,
+    router: DashMap<K, Arc<TagIndex>>,
+}
+
+impl<LB, K> TagRouteBalance<LB, K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    /// Create a [`TagRouteBalance`] with the inner [`LoadBalance`].
+    pub fn new(inner: LB) -> Self {
+        Self {
+            inner,
+            fallback: TagFallback::default(),
+            router: DashMap::new(),
+        }
+    }
+
+    /// Create a [`TagRouteBalance`] with the inner [`LoadBalance`] for the [`Discover`], which
+    /// is only used for inferring the type of keys.
+    pub fn with_discover<D>(inner: LB, _: &D) -> Self
+    where
+        D: Discover<Key = K>,
+    {
+        Self::new(inner)
+    }
+
+    /// Set the [`TagFallback`] when no instance matches.
+    ///
+    /// Default is [`TagFallback::Untagged`].
+    pub fn fallback(mut self, fallback: TagFallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Get a reference to the inner [`LoadBalance`].
+    pub fn inner(&self) -> &LB {
+        &self.inner
+    }
+}
+
+impl<LB, K> fmt::Debug for TagRouteBalance<LB, K>
+where
+    LB: fmt::Debug,
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TagRouteBalance")
+            .field("inner", &self.inner)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+/// Instances of a discover key indexed by addresses.
+struct TagIndex {
+    instances: HashMap<Address, Arc<Instance>>,
+    // whether any instance matches the tags
+    matched: DashMap<RouteTags, bool>,
+}
+
+impl TagIndex {
+    fn new(instances: Vec<Arc<Instance>>) -> Self {
+        Self {
+            instances: instances
+                .into_iter()
+                .map(|instance| (instance.address.clone(), instance))
+                .collect(),
+            matched: DashMap::new(),
+        }
+    }
+
+    fn has_matched(&self, tags: &RouteTags) -> bool {
+        if let Some(matched) = self.matched.get(tags) {
+            return *matched;
+        }
+        let matched = self
+            .instances
+            .values()
+            .any(|instance| tags.matches(instance));
+        self.matched.insert(tags.clone(), matched);
+        matched
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Matched,
+    Untagged,
+}
+
+/// Iterator of instances picked by [`TagRouteBalance`].
+pub struct TagPicker<I> {
+    inner: I,
+    filter: Option<(Arc<TagIndex>, RouteTags, Filter)>,
+}
+
+impl<I> Iterator for TagPicker<I>
+where
+    I: Iterator<Item = Address>,
+{
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some((index, tags, filter)) = &self.filter else {
+            return self.inner.next();
+        };
+        self.inner.by_ref().find(|addr| {
+            index
+                .instances
+                .get(addr)
+                .is_some_and(|instance| match filter {
+                    Filter::Matched => tags.matches(instance),
+                    Filter::Untagged => tags.is_untagged(instance),
+                })
+        })
+    }
+}
+
+impl<D, LB> LoadBalance<D> for TagRouteBalance<LB, D::Key>
+where
+    D: Discover,
+    LB: LoadBalance<D>,
+{
+    type InstanceIter = TagPicker<LB::InstanceIter>;
+
+    async fn get_picker<'future>(
+        &'future self,
+        endpoint: &'future Endpoint,
+        discover: &'future D,
+    ) -> Result<Self::InstanceIter, LoadBalanceError> {
+        let inner = self.inner.get_picker(endpoint, discover).await?;
+        let Some(tags) = RouteTags::current(endpoint).filter(|tags| !tags.is_empty()) else {
+            return Ok(TagPicker {
+                inner,
+                filter: None,
+            });
+        };
+
+        let key = discover.key(endpoint);
+        let index = match self.router.get(&key) {
+            Some(index) => index.clone(),
+            None => {
+                let index = Arc::new(TagIndex::new(
+                    discover
+                        .discover(endpoint)
+                        .await
+                        .map_err(|err| err.into())?,
+                ));
+                self.router.insert(key, index.clone());
+                index
+            }
+        };
+
+        let filter = if index.has_matched(&tags) {
+            Filter::Matched
+        } else {
+            tracing::debug!(
+                "[VOLO] no instance matches route tags {tags}, fallback to {:?}",
+                self.fallback
+            );
+            match self.fallback {
+                TagFallback::Fail => return Err(LoadBalanceError::NoMatchedInstance(tags)),
+                TagFallback::Untagged => Filter::Untagged,
+                TagFallback::All => {
+                    return Ok(TagPicker {
+                        inner,
+                        filter: None,
+                    });
+                }
+            }
+        };
+        Ok(TagPicker {
+            inner,
+            filter: Some((index, tags, filter)),
+        })
+    }
+
+    fn rebalance(&self, changes: Change<D::Key>) {
+        if let Entry::Occupied(entry) = self.router.entry(changes.key.clone()) {
+            entry.replace_entry(Arc::new(TagIndex::new(changes.all.clone())));
+        }
+        self.inner.rebalance(changes);
+    }
+
+    fn instance(
+        &self,
+        endpoint: &Endpoint,
+        discover: &D,
+        address: &Address,
+    ) -> Option<Arc<Instance>> {
+        self.inner.instance(endpoint, discover, address)
+    }
+
+    fn takeover(&self, changes: &Change<D::Key>) -> Vec<Address> {
+        self.inner.takeover(changes)
+    }
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+    use super::{ENV_TAG, RouteTags, TagFallback, TagRouteBalance};
+    use crate::{
+        context::Endpoint,
+        discovery::{Instance, StaticDiscover},
+        loadbalance::{LoadBalance, error::LoadBalanceError, random::WeightedRandomBalance},
+        net::Address,
+    };
+
+    fn instance(addr: &str, env: Option<&'static str>) -> Arc<Instance> {
+        let mut tags = HashMap::new();
+        if let Some(env) = env {
+            tags.insert(Cow::Borrowed(ENV_TAG), Cow::Borrowed(env));
+        }
+        Arc::new(Instance {
+            address: Address::from(addr.parse::<std::net::SocketAddr>().unwrap()),
+            weight: 10,
+            tags,
+        })
+    }
+
+    fn discover() -> StaticDiscover {
+        StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", None),
+            instance("127.0.0.2:8000", None),
+            instance("127.0.0.3:8000", Some("lane-1")),
+        ])
+    }
+
+    async fn pick(
+        lb: &TagRouteBalance<WeightedRandomBalance<()>, ()>,
+        tags: Option<RouteTags>,
+    ) -> Result<Vec<String>, LoadBalanceError> {
+        let discover = discover();
+        let mut endpoint = Endpoint::new("test".into());
+        if let Some(tags) = tags {
+            endpoint.insert(tags);
+        }
+        let mut picked = lb
+            .get_picker(&endpoint, &discover)
+            .await?
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>();
+        picked.sort();
+        Ok(picked)
+    }
+
+    #[test]
+    fn test_route_tags() {
+        let tags = RouteTags::new()
+            .with("set", "a")
+            .with(ENV_TAG, "lane-1")
+            .with("set", "b");
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags.get("set").unwrap(), "b");
+        assert_eq!(tags.to_string(), "env=lane-1,set=b");
+        assert_eq!(
+            tags,
+            [(ENV_TAG, "lane-1"), ("set", "b")].into_iter().collect()
+        );
+
+        let lane = instance("127.0.0.1:8000", Some("lane-1"));
+        let base = instance("127.0.0.1:8000", None);
+        let tags = RouteTags::new().with(ENV_TAG, "lane-1");
+        assert!(tags.matches(&lane));
+        assert!(!tags.matches(&base));
+        assert!(tags.is_untagged(&base));
+        assert!(!tags.is_untagged(&lane));
+    }
+
+    #[tokio::test]
+    async fn test_tag_route() {
+        let lb = TagRouteBalance::new(WeightedRandomBalance::new());
+        // without tags
+        assert_eq!(pick(&lb, None).await.unwrap().len(), 3);
+        // matched
+        let lane = RouteTags::new().with(ENV_TAG, "lane-1");
+        assert_eq!(pick(&lb, Some(lane)).await.unwrap(), vec!["127.0.0.3:8000"]);
+        // fallback to untagged instances
+        let other = RouteTags::new().with(ENV_TAG, "lane-2");
+        assert_eq!(
+            pick(&lb, Some(other.clone())).await.unwrap(),
+            vec!["127.0.0.1:8000", "127.0.0.2:8000"]
+        );
+
+        let lb = TagRouteBalance::new(WeightedRandomBalance::new()).fallback(TagFallback::All);
+        assert_eq!(pick(&lb, Some(other.clone())).await.unwrap().len(), 3);
+
+        let lb = TagRouteBalance::new(WeightedRandomBalance::new()).fallback(TagFallback::Fail);
+        assert!(matches!(
+            pick(&lb, Some(other)).await,
+            Err(LoadBalanceError::NoMatchedInstance(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tags_from_metainfo() {
+        let lb = TagRouteBalance::new(WeightedRandomBalance::new());
+        let mut mi = metainfo::MetaInfo::new();
+        mi.insert(RouteTags::new().with(ENV_TAG, "lane-1"));
+        let picked = metainfo::METAINFO
+            .scope(std::cell::RefCell::new(mi), pick(&lb, None))
+            .await
+            .unwrap();
+        assert_eq!(picked, vec!["127.0.0.3:8000"]);
+    }
+}
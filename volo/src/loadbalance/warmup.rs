@@ -0,0 +1,354 @@
+//! Warming up clients before serving traffic.
+//!
+//! A newly built client has resolved nothing and connected to nothing, so the first requests after
+//! a deploy pay for the discovery, the connecting and the TLS handshakes. Warming up a client
+//! resolves the callee by the discovery of the load balance layer, which also fills the cache of
+//! the load balancer, and pre-creates [`Warmup::connections`] connections to every instance,
+//! which completes TLS handshakes and primes the session cache of TLS for resumption as well.
+//!
+//! [`WarmupHandle`] connects the pieces: the load balance layer sets the resolver, and client
+//! transports subscribe to it for pre-creating connections. Clients of `volo-thrift` and
+//! `volo-grpc` expose it as `Client::warmup`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//!
+//! use volo::loadbalance::warmup::Warmup;
+//!
+//! let report = client
+//!     .warmup(Warmup::new().connections(4).timeout(Duration::from_secs(5)))
+//!     .await?;
+//! if !report.is_complete() {
+//!     tracing::warn!("client is not fully warmed up: {report:?}");
+//! }
+//! ```
+
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use futures::future::{self, BoxFuture};
+
+use super::error::LoadBalanceError;
+use crate::{context::Endpoint, net::Address};
+
+/// The default timeout of warming up.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Options of warming up a client.
+#[derive(Clone, Debug)]
+pub struct Warmup {
+    connections: usize,
+    timeout: Duration,
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Warmup {
+    /// Create [`Warmup`] pre-creating one connection for each instance.
+    pub fn new() -> Self {
+        Self {
+            connections: 1,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the count of connections pre-created for each instance, `0` for only resolving the
+    /// callee.
+    ///
+    /// Multiplex transports always keep one connection for an instance.
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// Set the timeout of warming up, including the discovery and connecting.
+    ///
+    /// Default is [`DEFAULT_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// What warming up has done.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WarmupReport {
+    /// The count of instances resolved.
+    pub instances: usize,
+    /// The count of connections expected to be ready, i.e., the connections per instance of each
+    /// transport times the count of instances, only counted for the completed instances if
+    /// timed out.
+    pub expected: usize,
+    /// The count of connections ready, including the existing ones.
+    pub ready: usize,
+    /// Whether warming up timed out, connections still being created are kept creating.
+    pub timed_out: bool,
+}
+
+impl WarmupReport {
+    /// Whether all expected connections are ready.
+    pub fn is_complete(&self) -> bool {
+        !self.timed_out && self.ready >= self.expected
+    }
+}
+
+type Resolver = Arc<
+    dyn for<'a> Fn(&'a Endpoint) -> BoxFuture<'a, Result<Vec<Address>, LoadBalanceError>>
+        + Send
+        + Sync,
+>;
+
+type Connector = Arc<dyn Fn(&Address, usize) -> Option<ConnectFuture> + Send + Sync>;
+
+/// Future returned by subscribers of [`WarmupHandle`] with the count of connections expected and
+/// ready, see [`WarmupHandle::subscribe`].
+pub type ConnectFuture = BoxFuture<'static, (usize, usize)>;
+
+/// The handle connecting the discovery and the client transports when warming up.
+///
+/// It is cheap to clone, and all clones share the same resolver and subscribers.
+#[derive(Clone, Default)]
+pub struct WarmupHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    resolver: Mutex<Option<Resolver>>,
+    connectors: Mutex<Vec<Connector>>,
+}
+
+impl WarmupHandle {
+    /// Create a [`WarmupHandle`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the resolver of the addresses of instances of an endpoint, which is set by the load
+    /// balance layer.
+    pub fn set_resolver<F>(&self, resolver: F)
+    where
+        F: for<'a> Fn(&'a Endpoint) -> BoxFuture<'a, Result<Vec<Address>, LoadBalanceError>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        *self
+            .inner
+            .resolver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(resolver));
+    }
+
+    /// Subscribe to warming up with a connector, it is called with the address of an instance
+    /// and the count of connections that should be ready for it.
+    ///
+    /// The connector should return `None` if the subscriber is gone, e.g., the connection pool
+    /// has been dropped, and then it will be removed. Otherwise, it returns a future of the count
+    /// of connections expected and ready, the expected count may be less than the requested
+    /// count, e.g., multiplex transports only keep one connection.
+    pub fn subscribe<F>(&self, connector: F)
+    where
+        F: Fn(&Address, usize) -> Option<ConnectFuture> + Send + Sync + 'static,
+    {
+        self.inner
+            .connectors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::new(connector));
+    }
+
+    /// Resolve the addresses of the instances of the endpoint.
+    ///
+    /// The address of the endpoint is used if it's set, and nothing is resolved if there is no
+    /// resolver.
+    pub async fn resolve(&self, endpoint: &Endpoint) -> Result<Vec<Address>, LoadBalanceError> {
+        if let Some(address) = &endpoint.address {
+            return Ok(vec![address.clone()]);
+        }
+        let resolver = self
+            .inner
+            .resolver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        match resolver {
+            Some(resolver) => resolver(endpoint).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Warm up the clients subscribed for the endpoint, returns when all connections are ready
+    /// or after the timeout.
+    ///
+    /// Fails only if the discovery fails.
+    pub async fn warmup(
+        &self,
+        endpoint: &Endpoint,
+        warmup: &Warmup,
+    ) -> Result<WarmupReport, LoadBalanceError> {
+        let expected = Arc::new(AtomicUsize::new(0));
+        let ready = Arc::new(AtomicUsize::new(0));
+        let resolved = Arc::new(AtomicUsize::new(0));
+
+        let run = async {
+            let addresses = self.resolve(endpoint).await?;
+            resolved.store(addresses.len(), Ordering::Relaxed);
+            if warmup.connections == 0 {
+                return Ok(());
+            }
+            let connectors = {
+                let mut connectors = self
+                    .inner
+                    .connectors
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                // the subscribers gone are removed when they return `None`
+                let mut futures = Vec::new();
+                connectors.retain(|connector| {
+                    let mut alive = true;
+                    for address in addresses.iter() {
+                        match connector(address, warmup.connections) {
+                            Some(future) => futures.push(future),
+                            None => alive = false,
+                        }
+                    }
+                    alive
+                });
+                futures
+            };
+            tracing::debug!(
+                "[VOLO] warming up {} instances of {}",
+                addresses.len(),
+                endpoint.service_name
+            );
+            // spawned for keeping the connecting after timed out
+            future::join_all(connectors.into_iter().map(|future| {
+                let (expected, ready) = (expected.clone(), ready.clone());
+                tokio::spawn(async move {
+                    let (e, r) = future.await;
+                    expected.fetch_add(e, Ordering::Relaxed);
+                    ready.fetch_add(r, Ordering::Relaxed);
+                })
+            }))
+            .await;
+            Ok(())
+        };
+
+        let timed_out = match tokio::time::timeout(warmup.timeout, run).await {
+            Ok(Ok(())) => false,
+            Ok(Err(err)) => return Err(err),
+            Err(_) => true,
+        };
+        let report = WarmupReport {
+            instances: resolved.load(Ordering::Relaxed),
+            expected: expected.load(Ordering::Relaxed),
+            ready: ready.load(Ordering::Relaxed),
+            timed_out,
+        };
+        if !report.is_complete() {
+            tracing::info!(
+                "[VOLO] warming up of {} is not complete: {report:?}",
+                endpoint.service_name
+            );
+        }
+        Ok(report)
+    }
+}
+
+impl fmt::Debug for WarmupHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WarmupHandle").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod warmup_tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use futures::FutureExt;
+
+    use super::{Warmup, WarmupHandle, WarmupReport};
+    use crate::{context::Endpoint, net::Address};
+
+    fn addr(port: u16) -> Address {
+        Address::from(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let handle = WarmupHandle::new();
+        handle.set_resolver(|_| async { Ok(vec![addr(8000), addr(8001)]) }.boxed());
+        handle.subscribe(|addr, connections| {
+            let ready = if addr.to_string().ends_with("8000") {
+                connections
+            } else {
+                1
+            };
+            Some(async move { (connections, ready) }.boxed())
+        });
+        // the subscriber is gone
+        handle.subscribe(|_, _| None);
+
+        let endpoint = Endpoint::new("test".into());
+        let report = handle
+            .warmup(&endpoint, &Warmup::new().connections(2))
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            WarmupReport {
+                instances: 2,
+                expected: 4,
+                ready: 3,
+                timed_out: false,
+            }
+        );
+        assert!(!report.is_complete());
+        assert_eq!(handle.inner.connectors.lock().unwrap().len(), 1);
+
+        // a fixed address is not resolved
+        let mut endpoint = Endpoint::new("test".into());
+        endpoint.set_address(addr(9000));
+        let report = handle.warmup(&endpoint, &Warmup::new()).await.unwrap();
+        assert_eq!(report.instances, 1);
+        assert!(report.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_warmup_timeout() {
+        let handle = WarmupHandle::new();
+        handle.set_resolver(|_| async { Ok(vec![addr(8000)]) }.boxed());
+        handle.subscribe(|_, connections| {
+            Some(
+                async move {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    (connections, connections)
+                }
+                .boxed(),
+            )
+        });
+        let report = handle
+            .warmup(
+                &Endpoint::new("test".into()),
+                &Warmup::new().timeout(Duration::from_millis(20)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.instances, 1);
+        assert!(report.timed_out);
+        assert!(!report.is_complete());
+    }
+}
@@ -0,0 +1,575 @@
+//! Per-request memory accounting.
+//!
+//! Decode buffers and body buffers are the largest allocations of most servers, and they are
+//! sized by the peers, so a few endpoints receiving large payloads can hold most of the memory of
+//! a process. [`MemoryTracker`] attributes these buffers to the requests holding them, bounds the
+//! memory of each request and the memory held by all in-flight requests of a method, and keeps
+//! statistics of each method for finding the memory-hungry endpoints.
+//!
+//! The server layers of `volo-thrift`, `volo-grpc` and `volo-http` create a [`MemoryAccount`] for
+//! each request, charge the received payloads to it, and insert it into the extensions of the
+//! context, so that handlers can charge their own buffers as well:
+//!
+//! ```
+//! use volo::memory::{MemoryAccount, MemoryTracker};
+//!
+//! let tracker = MemoryTracker::new()
+//!     .request_limit(16 * 1024 * 1024)
+//!     .method_limit("Upload", 256 * 1024 * 1024);
+//!
+//! // by the server layer
+//! let account = tracker.account("Upload");
+//! let received = account.charge(1024).unwrap();
+//!
+//! // by the handler, e.g., `cx.extensions().get::<MemoryAccount>()`
+//! let mut buffer = account.charge(4096).unwrap();
+//! buffer.try_grow(4096).unwrap();
+//! assert_eq!(account.used(), 1024 + 8192);
+//!
+//! // released when the charges are dropped
+//! drop(buffer);
+//! assert_eq!(account.used(), 1024);
+//! drop((received, account));
+//!
+//! let stats = tracker.stats("Upload").unwrap();
+//! assert_eq!(stats.requests, 1);
+//! assert_eq!(stats.max_peak, 1024 + 8192);
+//! ```
+//!
+//! Charges are released when they are dropped, or when the account is dropped if they are
+//! retained by [`MemoryCharge::retain`]. The account is dropped when the request is done and all
+//! its clones, e.g., the ones held by request bodies, are dropped.
+//!
+//! Decoders reading a whole frame before the method is known charge it by
+//! [`MemoryTracker::charge_pending`] when the length is read, and the charge is moved to the
+//! account of the request by [`MemoryAccount::adopt`] once the request is decoded.
+
+use std::{
+    error::Error,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use dashmap::DashMap;
+use faststr::FastStr;
+
+/// The max count of methods tracked separately, the requests of other methods are tracked
+/// together as [`OTHER_METHODS`], which bounds the memory of the tracker itself when the method
+/// names come from peers.
+const MAX_METHODS: usize = 4096;
+
+/// The name of the statistics of methods which are not tracked separately.
+pub const OTHER_METHODS: &str = "<other>";
+
+/// Tracker of the memory held by requests, see the [module documentation](self) for more
+/// details.
+///
+/// It is cheap to clone, and all clones share the same statistics.
+#[derive(Clone, Default)]
+pub struct MemoryTracker {
+    request_limit: Option<usize>,
+    limits: Arc<DashMap<FastStr, MethodLimit>>,
+    methods: Arc<DashMap<FastStr, Arc<MethodMemory>>>,
+    // bytes received before the methods are known
+    pending: Arc<MethodMemory>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct MethodLimit {
+    request: Option<usize>,
+    total: Option<usize>,
+}
+
+#[derive(Default)]
+struct MethodMemory {
+    limit: MethodLimit,
+    in_flight: AtomicUsize,
+    requests: AtomicU64,
+    rejected: AtomicU64,
+    max_peak: AtomicUsize,
+    total_peak: AtomicU64,
+}
+
+impl MemoryTracker {
+    /// Create a [`MemoryTracker`] without limits, which only keeps statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the max bytes held by each request.
+    pub fn request_limit(mut self, limit: usize) -> Self {
+        self.request_limit = Some(limit);
+        self
+    }
+
+    /// Set the max bytes held by each request of the method, which overrides
+    /// [`MemoryTracker::request_limit`].
+    pub fn method_request_limit(self, method: impl Into<FastStr>, limit: usize) -> Self {
+        self.update_limit(method.into(), |l| l.request = Some(limit));
+        self
+    }
+
+    /// Set the max bytes held by all in-flight requests of the method.
+    pub fn method_limit(self, method: impl Into<FastStr>, limit: usize) -> Self {
+        self.update_limit(method.into(), |l| l.total = Some(limit));
+        self
+    }
+
+    fn update_limit(&self, method: FastStr, f: impl FnOnce(&mut MethodLimit)) {
+        f(&mut self.limits.entry(method.clone()).or_default());
+        // the limit is read when the method is tracked
+        self.methods.remove(&method);
+    }
+
+    /// Create a [`MemoryAccount`] for a request of the method.
+    pub fn account(&self, method: &str) -> MemoryAccount {
+        let memory = match self.methods.get(method) {
+            Some(memory) => memory.clone(),
+            None => self.track(method),
+        };
+        let limit = memory.limit.request.or(self.request_limit);
+        MemoryAccount::new(memory, limit)
+    }
+
+    /// Charge `bytes` received before the method of the request is known, e.g., a frame whose
+    /// length has been read by the decoder, which should be moved to the account of the request
+    /// by [`MemoryAccount::adopt`].
+    ///
+    /// Since the request may be of any method, the largest request limit applies, and the limits
+    /// of the methods apply when the charge is adopted.
+    pub fn charge_pending(&self, bytes: usize) -> Result<MemoryCharge, MemoryLimitExceeded> {
+        let limit = self.request_limit.map(|limit| {
+            self.limits
+                .iter()
+                .filter_map(|entry| entry.request)
+                .fold(limit, usize::max)
+        });
+        MemoryAccount::new(self.pending.clone(), limit).charge(bytes)
+    }
+
+    fn track(&self, method: &str) -> Arc<MethodMemory> {
+        let method = if self.methods.len() >= MAX_METHODS && !self.limits.contains_key(method) {
+            OTHER_METHODS
+        } else {
+            method
+        };
+        let limit = self
+            .limits
+            .get(method)
+            .map(|limit| *limit)
+            .unwrap_or_default();
+        self.methods
+            .entry(FastStr::new(method))
+            .or_insert_with(|| {
+                Arc::new(MethodMemory {
+                    limit,
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+
+    /// Returns the statistics of the method, `None` if no request of it has been tracked.
+    pub fn stats(&self, method: &str) -> Option<MemoryStats> {
+        self.methods.get(method).map(|memory| memory.stats())
+    }
+
+    /// Returns the statistics of all methods which have been tracked.
+    pub fn all_stats(&self) -> Vec<(FastStr, MemoryStats)> {
+        self.methods
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().stats()))
+            .collect()
+    }
+
+    /// Returns the statistics of the charges by [`MemoryTracker::charge_pending`], each of which
+    /// is counted as a request.
+    pub fn pending_stats(&self) -> MemoryStats {
+        self.pending.stats()
+    }
+}
+
+impl fmt::Debug for MemoryTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTracker")
+            .field("request_limit", &self.request_limit)
+            .field("methods", &self.methods.len())
+            .finish()
+    }
+}
+
+impl MethodMemory {
+    fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            max_peak: self.max_peak.load(Ordering::Relaxed),
+            total_peak: self.total_peak.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Statistics of the memory held by requests of a method.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The count of requests done.
+    pub requests: u64,
+    /// The count of requests which have been rejected by the limits.
+    pub rejected: u64,
+    /// The bytes held by the in-flight requests.
+    pub in_flight: usize,
+    /// The max of the peak bytes held by a request.
+    pub max_peak: usize,
+    /// The sum of the peak bytes held by the requests done.
+    pub total_peak: u64,
+}
+
+impl MemoryStats {
+    /// Returns the average of the peak bytes held by a request.
+    pub fn avg_peak(&self) -> u64 {
+        self.total_peak.checked_div(self.requests).unwrap_or(0)
+    }
+}
+
+/// The memory held by a request, see the [module documentation](self) for more details.
+///
+/// It is cheap to clone, and all clones share the same account.
+#[derive(Clone)]
+pub struct MemoryAccount {
+    inner: Arc<AccountInner>,
+}
+
+struct AccountInner {
+    method: Arc<MethodMemory>,
+    limit: Option<usize>,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    rejected: AtomicBool,
+}
+
+impl MemoryAccount {
+    fn new(method: Arc<MethodMemory>, limit: Option<usize>) -> Self {
+        Self {
+            inner: Arc::new(AccountInner {
+                method,
+                limit,
+                used: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                rejected: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Charge `bytes` to the request, returns the [`MemoryCharge`] releasing them when dropped.
+    ///
+    /// Fails if the limit of the request or the method is exceeded, and nothing is charged.
+    pub fn charge(&self, bytes: usize) -> Result<MemoryCharge, MemoryLimitExceeded> {
+        self.reserve(bytes)?;
+        Ok(MemoryCharge {
+            account: self.clone(),
+            bytes,
+        })
+    }
+
+    /// Move the bytes of `charge`, e.g., by [`MemoryTracker::charge_pending`], to the request.
+    ///
+    /// Fails if the limit of the request or the method is exceeded, and the bytes are released.
+    pub fn adopt(&self, charge: MemoryCharge) -> Result<MemoryCharge, MemoryLimitExceeded> {
+        // charged before released, so that the bytes are always accounted
+        self.charge(charge.bytes)
+    }
+
+    fn reserve(&self, bytes: usize) -> Result<(), MemoryLimitExceeded> {
+        let inner = &*self.inner;
+        let used = inner.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(limit) = inner.limit {
+            if used > limit {
+                inner.used.fetch_sub(bytes, Ordering::Relaxed);
+                return Err(self.reject(LimitKind::Request, limit, bytes));
+            }
+        }
+        let in_flight = inner.method.in_flight.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(limit) = inner.method.limit.total {
+            if in_flight > limit {
+                inner.method.in_flight.fetch_sub(bytes, Ordering::Relaxed);
+                inner.used.fetch_sub(bytes, Ordering::Relaxed);
+                return Err(self.reject(LimitKind::Method, limit, bytes));
+            }
+        }
+        inner.peak.fetch_max(used, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn release(&self, bytes: usize) {
+        self.inner.used.fetch_sub(bytes, Ordering::Relaxed);
+        self.inner
+            .method
+            .in_flight
+            .fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn reject(&self, kind: LimitKind, limit: usize, requested: usize) -> MemoryLimitExceeded {
+        if !self.inner.rejected.swap(true, Ordering::Relaxed) {
+            self.inner.method.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        MemoryLimitExceeded {
+            kind,
+            limit,
+            requested,
+            used: self.used(),
+        }
+    }
+
+    /// Returns the bytes held by the request.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the peak bytes held by the request.
+    pub fn peak(&self) -> usize {
+        self.inner.peak.load(Ordering::Relaxed)
+    }
+
+    /// Returns the max bytes held by the request, `None` for no limit.
+    pub fn limit(&self) -> Option<usize> {
+        self.inner.limit
+    }
+}
+
+impl Drop for AccountInner {
+    fn drop(&mut self) {
+        let used = *self.used.get_mut();
+        self.method.in_flight.fetch_sub(used, Ordering::Relaxed);
+        let peak = *self.peak.get_mut();
+        self.method.requests.fetch_add(1, Ordering::Relaxed);
+        self.method.max_peak.fetch_max(peak, Ordering::Relaxed);
+        self.method
+            .total_peak
+            .fetch_add(peak as u64, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Debug for MemoryAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryAccount")
+            .field("used", &self.used())
+            .field("peak", &self.peak())
+            .field("limit", &self.inner.limit)
+            .finish()
+    }
+}
+
+/// Bytes charged to a [`MemoryAccount`], which are released when it is dropped.
+#[must_use = "the bytes are released immediately if the charge is dropped"]
+pub struct MemoryCharge {
+    account: MemoryAccount,
+    bytes: usize,
+}
+
+impl MemoryCharge {
+    /// Returns the bytes charged.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Charge `bytes` more, e.g., when the buffer grows.
+    ///
+    /// Fails if the limit of the request or the method is exceeded, and nothing is charged.
+    pub fn try_grow(&mut self, bytes: usize) -> Result<(), MemoryLimitExceeded> {
+        self.account.reserve(bytes)?;
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    /// Release `bytes` of the charge, e.g., when the buffer shrinks.
+    pub fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.account.release(bytes);
+        self.bytes -= bytes;
+    }
+
+    /// Keep the bytes charged until the account is dropped, i.e., the request is done, e.g., when
+    /// the buffer is handed over to the handler.
+    pub fn retain(mut self) {
+        self.bytes = 0;
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            self.account.release(self.bytes);
+        }
+    }
+}
+
+impl fmt::Debug for MemoryCharge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryCharge")
+            .field("bytes", &self.bytes)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LimitKind {
+    Request,
+    Method,
+}
+
+/// Error returned when a charge exceeds the limit of the request or the method.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryLimitExceeded {
+    kind: LimitKind,
+    limit: usize,
+    requested: usize,
+    used: usize,
+}
+
+impl MemoryLimitExceeded {
+    /// Returns whether the limit of all in-flight requests of the method is exceeded rather than
+    /// the limit of the request.
+    pub fn is_method_limit(&self) -> bool {
+        self.kind == LimitKind::Method
+    }
+
+    /// Returns the limit exceeded.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the bytes requested by the charge.
+    pub fn requested(&self) -> usize {
+        self.requested
+    }
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = match self.kind {
+            LimitKind::Request => "the request",
+            LimitKind::Method => "in-flight requests of the method",
+        };
+        write!(
+            f,
+            "memory limit of {scope} exceeded: requested {} bytes with {} bytes used by the \
+             request, the limit is {} bytes",
+            self.requested, self.used, self.limit
+        )
+    }
+}
+
+impl Error for MemoryLimitExceeded {}
+
+#[cfg(test)]
+mod memory_tests {
+    use super::{MAX_METHODS, MemoryStats, MemoryTracker, OTHER_METHODS};
+
+    #[test]
+    fn request_limit() {
+        let tracker = MemoryTracker::new()
+            .request_limit(100)
+            .method_request_limit("Large", 1000);
+
+        let account = tracker.account("Small");
+        let mut charge = account.charge(60).unwrap();
+        let err = account.charge(60).unwrap_err();
+        assert!(!err.is_method_limit());
+        assert_eq!(err.limit(), 100);
+        assert_eq!(account.used(), 60);
+        assert!(charge.try_grow(60).is_err());
+        charge.shrink(20);
+        charge.try_grow(60).unwrap();
+        assert_eq!(account.used(), 100);
+        drop(charge);
+        assert_eq!(account.used(), 0);
+        assert_eq!(account.peak(), 100);
+
+        // overridden by the method
+        let large = tracker.account("Large");
+        assert_eq!(large.limit(), Some(1000));
+        large.charge(500).unwrap().retain();
+        assert_eq!(large.used(), 500);
+        assert_eq!(tracker.stats("Large").unwrap().in_flight, 500);
+        drop(large);
+
+        drop(account);
+        assert_eq!(
+            tracker.stats("Small").unwrap(),
+            MemoryStats {
+                requests: 1,
+                rejected: 1,
+                in_flight: 0,
+                max_peak: 100,
+                total_peak: 100,
+            }
+        );
+        assert_eq!(tracker.stats("Large").unwrap().in_flight, 0);
+    }
+
+    #[test]
+    fn method_limit() {
+        let tracker = MemoryTracker::new().method_limit("Upload", 100);
+        let first = tracker.account("Upload");
+        let second = tracker.account("Upload");
+        let charge = first.charge(80).unwrap();
+        let err = second.charge(40).unwrap_err();
+        assert!(err.is_method_limit());
+        assert_eq!(second.used(), 0);
+        drop(charge);
+        let _charge = second.charge(40).unwrap();
+        assert_eq!(tracker.stats("Upload").unwrap().in_flight, 40);
+
+        // other methods are not limited
+        let other = tracker.account("Download");
+        let _charge = other.charge(1000).unwrap();
+    }
+
+    #[test]
+    fn max_methods() {
+        let tracker = MemoryTracker::new().method_limit("Upload", 100);
+        for i in 0..MAX_METHODS + 10 {
+            drop(tracker.account(&format!("method{i}")));
+        }
+        assert_eq!(tracker.all_stats().len(), MAX_METHODS + 1);
+        assert_eq!(tracker.stats(OTHER_METHODS).unwrap().requests, 10);
+        // methods with limits are always tracked
+        assert!(tracker.account("Upload").charge(1000).is_err());
+    }
+
+    #[test]
+    fn pending() {
+        let tracker = MemoryTracker::new()
+            .request_limit(100)
+            .method_request_limit("Large", 1000)
+            .method_limit("Large", 600);
+
+        // limited by the largest request limit
+        assert!(tracker.charge_pending(1001).is_err());
+        let pending = tracker.charge_pending(500).unwrap();
+        assert_eq!(tracker.pending_stats().in_flight, 500);
+
+        let large = tracker.account("Large");
+        let charge = large.adopt(pending).unwrap();
+        assert_eq!(large.used(), 500);
+        assert_eq!(tracker.pending_stats().in_flight, 0);
+        assert_eq!(tracker.pending_stats().requests, 2);
+        assert_eq!(tracker.pending_stats().rejected, 1);
+        drop(charge);
+
+        // limited by the method once adopted
+        let small = tracker.account("Small");
+        assert!(small.adopt(tracker.charge_pending(200).unwrap()).is_err());
+        let _charge = large.charge(300).unwrap();
+        assert!(large.adopt(tracker.charge_pending(400).unwrap()).is_err());
+        assert_eq!(tracker.pending_stats().in_flight, 0);
+
+        // no limit if any method is not limited
+        let tracker = MemoryTracker::new().method_request_limit("Large", 1000);
+        assert!(tracker.charge_pending(2000).is_ok());
+    }
+}
@@ -8,7 +8,7 @@ use pin_project::pin_project;
 #[cfg(target_family = "unix")]
 use tokio::net::{UnixStream, unix};
 use tokio::{
-    io::{AsyncRead, AsyncWrite, ReadBuf},
+    io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf, ReadHalf, WriteHalf},
     net::{TcpStream, tcp},
 };
 
@@ -28,6 +28,9 @@ pub enum ConnStream {
     Tls(#[pin] super::tls::TlsStream),
     #[cfg(feature = "shmipc")]
     Shmipc(#[pin] super::shmipc::Stream),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(#[pin] super::uring::UringStream),
+    Memory(#[pin] DuplexStream),
 }
 
 impl ConnStream {
@@ -50,6 +53,15 @@ impl ConnStream {
         matches!(self, Self::Shmipc(_))
     }
 
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn is_uring(&self) -> bool {
+        matches!(self, Self::Uring(_))
+    }
+
+    pub fn is_memory(&self) -> bool {
+        matches!(self, Self::Memory(_))
+    }
+
     pub fn into_tcp(self) -> Option<TcpStream> {
         match self {
             Self::Tcp(stream) => Some(stream),
@@ -59,6 +71,9 @@ impl ConnStream {
             Self::Tls(_) => None,
             #[cfg(feature = "shmipc")]
             Self::Shmipc(_) => None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(_) => None,
+            Self::Memory(_) => None,
         }
     }
 
@@ -85,6 +100,21 @@ impl ConnStream {
             _ => None,
         }
     }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn into_uring(self) -> Option<super::uring::UringStream> {
+        match self {
+            Self::Uring(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    pub fn into_memory(self) -> Option<DuplexStream> {
+        match self {
+            Self::Memory(stream) => Some(stream),
+            _ => None,
+        }
+    }
 }
 
 #[pin_project(project = OwnedWriteHalfProj)]
@@ -96,6 +126,9 @@ pub enum OwnedWriteHalf {
     Tls(#[pin] super::tls::OwnedWriteHalf),
     #[cfg(feature = "shmipc")]
     Shmipc(#[pin] super::shmipc::WriteHalf),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(#[pin] super::uring::WriteHalf),
+    Memory(#[pin] WriteHalf<DuplexStream>),
 }
 
 impl AsyncWrite for OwnedWriteHalf {
@@ -113,6 +146,9 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Tls(half) => half.poll_write(cx, buf),
             #[cfg(feature = "shmipc")]
             OwnedWriteHalfProj::Shmipc(half) => half.poll_write(cx, buf),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedWriteHalfProj::Uring(half) => half.poll_write(cx, buf),
+            OwnedWriteHalfProj::Memory(half) => half.poll_write(cx, buf),
         }
     }
 
@@ -126,6 +162,9 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Tls(half) => half.poll_flush(cx),
             #[cfg(feature = "shmipc")]
             OwnedWriteHalfProj::Shmipc(half) => half.poll_flush(cx),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedWriteHalfProj::Uring(half) => half.poll_flush(cx),
+            OwnedWriteHalfProj::Memory(half) => half.poll_flush(cx),
         }
     }
 
@@ -139,6 +178,9 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Tls(half) => half.poll_shutdown(cx),
             #[cfg(feature = "shmipc")]
             OwnedWriteHalfProj::Shmipc(half) => half.poll_shutdown(cx),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedWriteHalfProj::Uring(half) => half.poll_shutdown(cx),
+            OwnedWriteHalfProj::Memory(half) => half.poll_shutdown(cx),
         }
     }
 
@@ -156,6 +198,9 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Tls(half) => half.poll_write_vectored(cx, bufs),
             #[cfg(feature = "shmipc")]
             OwnedWriteHalfProj::Shmipc(half) => half.poll_write_vectored(cx, bufs),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedWriteHalfProj::Uring(half) => half.poll_write_vectored(cx, bufs),
+            OwnedWriteHalfProj::Memory(half) => half.poll_write_vectored(cx, bufs),
         }
     }
 
@@ -169,6 +214,9 @@ impl AsyncWrite for OwnedWriteHalf {
             Self::Tls(half) => half.is_write_vectored(),
             #[cfg(feature = "shmipc")]
             Self::Shmipc(half) => half.is_write_vectored(),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(half) => half.is_write_vectored(),
+            Self::Memory(half) => half.is_write_vectored(),
         }
     }
 }
@@ -182,6 +230,9 @@ pub enum OwnedReadHalf {
     Tls(#[pin] super::tls::OwnedReadHalf),
     #[cfg(feature = "shmipc")]
     Shmipc(#[pin] super::shmipc::ReadHalf),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(#[pin] super::uring::ReadHalf),
+    Memory(#[pin] ReadHalf<DuplexStream>),
 }
 
 impl OwnedReadHalf {
@@ -209,6 +260,9 @@ impl AsyncRead for OwnedReadHalf {
             OwnedReadHalfProj::Tls(half) => half.poll_read(cx, buf),
             #[cfg(feature = "shmipc")]
             OwnedReadHalfProj::Shmipc(half) => half.poll_read(cx, buf),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedReadHalfProj::Uring(half) => half.poll_read(cx, buf),
+            OwnedReadHalfProj::Memory(half) => half.poll_read(cx, buf),
         }
     }
 }
@@ -235,6 +289,15 @@ impl ConnStream {
                 let (rh, wh) = stream.into_split();
                 (OwnedReadHalf::Shmipc(rh), OwnedWriteHalf::Shmipc(wh))
             }
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(stream) => {
+                let (rh, wh) = stream.into_split();
+                (OwnedReadHalf::Uring(rh), OwnedWriteHalf::Uring(wh))
+            }
+            Self::Memory(stream) => {
+                let (rh, wh) = tokio::io::split(stream);
+                (OwnedReadHalf::Memory(rh), OwnedWriteHalf::Memory(wh))
+            }
         }
     }
 
@@ -282,6 +345,14 @@ impl From<super::shmipc::Stream> for ConnStream {
     }
 }
 
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl From<super::uring::UringStream> for ConnStream {
+    #[inline]
+    fn from(value: super::uring::UringStream) -> Self {
+        Self::Uring(value)
+    }
+}
+
 impl AsyncRead for ConnStream {
     #[inline]
     fn poll_read(
@@ -297,6 +368,9 @@ impl AsyncRead for ConnStream {
             IoStreamProj::Tls(s) => s.poll_read(cx, buf),
             #[cfg(feature = "shmipc")]
             IoStreamProj::Shmipc(s) => s.poll_read(cx, buf),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            IoStreamProj::Uring(s) => s.poll_read(cx, buf),
+            IoStreamProj::Memory(s) => s.poll_read(cx, buf),
         }
     }
 }
@@ -316,6 +390,9 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Tls(s) => s.poll_write(cx, buf),
             #[cfg(feature = "shmipc")]
             IoStreamProj::Shmipc(s) => s.poll_write(cx, buf),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            IoStreamProj::Uring(s) => s.poll_write(cx, buf),
+            IoStreamProj::Memory(s) => s.poll_write(cx, buf),
         }
     }
 
@@ -329,6 +406,9 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Tls(s) => s.poll_flush(cx),
             #[cfg(feature = "shmipc")]
             IoStreamProj::Shmipc(s) => s.poll_flush(cx),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            IoStreamProj::Uring(s) => s.poll_flush(cx),
+            IoStreamProj::Memory(s) => s.poll_flush(cx),
         }
     }
 
@@ -342,6 +422,9 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Tls(s) => s.poll_shutdown(cx),
             #[cfg(feature = "shmipc")]
             IoStreamProj::Shmipc(s) => s.poll_shutdown(cx),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            IoStreamProj::Uring(s) => s.poll_shutdown(cx),
+            IoStreamProj::Memory(s) => s.poll_shutdown(cx),
         }
     }
 
@@ -359,6 +442,9 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Tls(s) => s.poll_write_vectored(cx, bufs),
             #[cfg(feature = "shmipc")]
             IoStreamProj::Shmipc(s) => s.poll_write_vectored(cx, bufs),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            IoStreamProj::Uring(s) => s.poll_write_vectored(cx, bufs),
+            IoStreamProj::Memory(s) => s.poll_write_vectored(cx, bufs),
         }
     }
 
@@ -372,6 +458,9 @@ impl AsyncWrite for ConnStream {
             Self::Tls(s) => s.is_write_vectored(),
             #[cfg(feature = "shmipc")]
             Self::Shmipc(s) => s.is_write_vectored(),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(s) => s.is_write_vectored(),
+            Self::Memory(s) => s.is_write_vectored(),
         }
     }
 }
@@ -387,6 +476,9 @@ impl ConnStream {
             Self::Tls(s) => s.peer_addr().map(Address::from).ok(),
             #[cfg(feature = "shmipc")]
             Self::Shmipc(s) => Some(Address::from(s.peer_addr())),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(s) => s.peer_addr().map(Address::from).ok(),
+            Self::Memory(_) => Some(super::memory::PLACEHOLDER_ADDRESS),
         }
     }
 }
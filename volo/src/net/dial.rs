@@ -1,5 +1,11 @@
-use std::{future::Future, io, net::SocketAddr};
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
+use futures::stream::{FuturesUnordered, StreamExt};
 use motore::{make::MakeConnection, service::UnaryService};
 use socket2::{Domain, Protocol, Socket, Type};
 #[cfg(target_family = "unix")]
@@ -14,6 +20,14 @@ use super::{
     Address,
     conn::{Conn, OwnedReadHalf, OwnedWriteHalf},
 };
+use crate::{
+    error::ConnectError,
+    timeout::{TimeoutError, TimeoutKind},
+    util::{
+        time::Timestamp,
+        timing::{self, Phase},
+    },
+};
 
 /// [`MakeTransport`] creates an [`AsyncRead`] and an [`AsyncWrite`] for the given [`Address`].
 pub trait MakeTransport: Clone + Send + Sync + 'static {
@@ -27,11 +41,17 @@ pub trait MakeTransport: Clone + Send + Sync + 'static {
     fn set_connect_timeout(&mut self, timeout: Option<Duration>);
     fn set_read_timeout(&mut self, timeout: Option<Duration>);
     fn set_write_timeout(&mut self, timeout: Option<Duration>);
+    /// Set the TCP keepalive of connections, transports not based on TCP can ignore it.
+    fn set_tcp_keepalive(&mut self, _keepalive: Option<TcpKeepalive>) {}
 }
 
+/// The default [`MakeTransport`] connecting by TCP or unix domain socket.
+///
+/// TCP connections are established by the [`Dialer`], so that host names are resolved to both
+/// IPv4 and IPv6 addresses and dialed by the Happy Eyeballs algorithm.
 #[derive(Default, Debug, Clone)]
 pub struct DefaultMakeTransport {
-    cfg: Config,
+    dialer: Dialer,
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -39,6 +59,14 @@ pub struct Config {
     pub connect_timeout: Option<Duration>,
     pub read_timeout: Option<Duration>,
     pub write_timeout: Option<Duration>,
+    /// Enable TCP Fast Open for outgoing connections, data of the first write will be sent in
+    /// the SYN packet if a cookie of the server is cached.
+    ///
+    /// Only supported on Linux 4.11+, it will be ignored on other platforms.
+    pub tcp_fast_open: bool,
+    /// Enable TCP keepalive for outgoing connections, so that connections broken silently, e.g.,
+    /// by NAT or load balancers, are detected by the kernel.
+    pub tcp_keepalive: Option<TcpKeepalive>,
 }
 
 impl Config {
@@ -51,6 +79,8 @@ impl Config {
             connect_timeout,
             read_timeout,
             write_timeout,
+            tcp_fast_open: false,
+            tcp_keepalive: None,
         }
     }
 
@@ -68,6 +98,52 @@ impl Config {
         self.write_timeout = timeout;
         self
     }
+
+    pub fn with_tcp_fast_open(mut self, enable: bool) -> Self {
+        self.tcp_fast_open = enable;
+        self
+    }
+
+    pub fn with_tcp_keepalive(mut self, keepalive: Option<TcpKeepalive>) -> Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
+}
+
+/// Options of TCP keepalive, see [`Config::tcp_keepalive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// The idle time before the first probe is sent.
+    pub time: Duration,
+    /// The interval between probes, the default of the system is used if not set.
+    ///
+    /// Only supported on Linux, Android, FreeBSD, NetBSD, macOS, iOS and Windows.
+    pub interval: Option<Duration>,
+    /// The number of unacknowledged probes before the connection is dropped, the default of the
+    /// system is used if not set.
+    ///
+    /// Only supported on Linux, Android, FreeBSD, NetBSD, macOS, iOS and Windows.
+    pub retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    pub fn new(time: Duration) -> Self {
+        Self {
+            time,
+            interval: None,
+            retries: None,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
 }
 
 impl DefaultMakeTransport {
@@ -76,11 +152,23 @@ impl DefaultMakeTransport {
     }
 
     pub const fn config(&self) -> &Config {
-        &self.cfg
+        self.dialer.config()
     }
 
     pub const fn config_mut(&mut self) -> &mut Config {
-        &mut self.cfg
+        self.dialer.config_mut()
+    }
+
+    pub const fn dialer(&self) -> &Dialer {
+        &self.dialer
+    }
+
+    /// Resolve the `host` and connect to it by the [`Dialer`], the `host` can also be an ip
+    /// address.
+    pub async fn dial(&self, host: &str, port: u16) -> io::Result<Conn> {
+        let stream = self.dialer.dial(host, port).await?;
+        stream.set_nodelay(true)?;
+        Ok(Conn::from(stream))
     }
 }
 
@@ -95,15 +183,19 @@ impl MakeTransport for DefaultMakeTransport {
     }
 
     fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
-        self.cfg = self.cfg.with_connect_timeout(timeout);
+        self.config_mut().connect_timeout = timeout;
     }
 
     fn set_read_timeout(&mut self, timeout: Option<Duration>) {
-        self.cfg = self.cfg.with_read_timeout(timeout);
+        self.config_mut().read_timeout = timeout;
     }
 
     fn set_write_timeout(&mut self, timeout: Option<Duration>) {
-        self.cfg = self.cfg.with_write_timeout(timeout);
+        self.config_mut().write_timeout = timeout;
+    }
+
+    fn set_tcp_keepalive(&mut self, keepalive: Option<TcpKeepalive>) {
+        self.config_mut().tcp_keepalive = keepalive;
     }
 }
 
@@ -116,6 +208,12 @@ pub(super) async fn make_tcp_connection(
     socket.set_nonblocking(true)?;
     socket.set_read_timeout(cfg.read_timeout)?;
     socket.set_write_timeout(cfg.write_timeout)?;
+    if cfg.tcp_fast_open {
+        set_tcp_fast_open_connect(&socket)?;
+    }
+    if let Some(keepalive) = &cfg.tcp_keepalive {
+        set_tcp_keepalive(&socket, keepalive)?;
+    }
 
     #[cfg(unix)]
     let socket = unsafe {
@@ -128,40 +226,393 @@ pub(super) async fn make_tcp_connection(
         TcpSocket::from_raw_socket(socket.into_raw_socket())
     };
 
+    let start = Timestamp::now();
     let connect = socket.connect(addr);
 
-    if let Some(conn_timeout) = cfg.connect_timeout {
-        timeout(conn_timeout, connect).await?
+    let stream = if let Some(conn_timeout) = cfg.connect_timeout {
+        timeout(conn_timeout, connect)
+            .await
+            .map_err(|_| TimeoutError::new(TimeoutKind::Connect, conn_timeout).into_io_error())?
     } else {
         connect.await
+    }?;
+    timing::record(Phase::Connect, start, Timestamp::now());
+    Ok(stream)
+}
+
+/// The default delay before starting the next connection attempt, which is recommended by
+/// [RFC 8305](https://datatracker.ietf.org/doc/html/rfc8305#section-5).
+pub const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// [`Resolve`] resolves a host name to socket addresses for [`Dialer`].
+///
+/// Both IPv4 and IPv6 addresses should be returned, and the order of addresses is respected as
+/// the preference, see [RFC 6724](https://datatracker.ietf.org/doc/html/rfc6724). A custom
+/// resolver, e.g., `hickory-resolver` with caching, can be plugged by implementing this trait.
+pub trait Resolve: Send + Sync + 'static {
+    fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> impl Future<Output = io::Result<Vec<SocketAddr>>> + Send;
+}
+
+impl<R> Resolve for Arc<R>
+where
+    R: Resolve,
+{
+    fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> impl Future<Output = io::Result<Vec<SocketAddr>>> + Send {
+        (**self).resolve(host, port)
+    }
+}
+
+/// [`Resolve`] by `getaddrinfo` of the system, which queries `A` and `AAAA` records and sorts
+/// them by the system policy.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GaiResolver;
+
+impl Resolve for GaiResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+/// [`Dialer`] establishes TCP connections to a host name by the Happy Eyeballs algorithm defined
+/// in [RFC 8305](https://datatracker.ietf.org/doc/html/rfc8305).
+///
+/// All resolved addresses are interleaved by address family, and connection attempts are raced
+/// with a delay between starting each of them, the first established connection wins and the
+/// others will be cancelled. If an attempt fails, the next one starts immediately.
+///
+/// The `connect_timeout` in [`Config`] is applied to each attempt.
+#[derive(Debug, Clone)]
+pub struct Dialer<R = GaiResolver> {
+    cfg: Config,
+    resolver: R,
+    attempt_delay: Duration,
+}
+
+impl Default for Dialer {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl Dialer {
+    /// Create a [`Dialer`] using the system resolver.
+    pub fn new(cfg: Config) -> Self {
+        Self {
+            cfg,
+            resolver: GaiResolver,
+            attempt_delay: DEFAULT_ATTEMPT_DELAY,
+        }
     }
 }
 
+impl<R> Dialer<R> {
+    /// Set the resolver for resolving host names.
+    pub fn with_resolver<R2>(self, resolver: R2) -> Dialer<R2> {
+        Dialer {
+            cfg: self.cfg,
+            resolver,
+            attempt_delay: self.attempt_delay,
+        }
+    }
+
+    /// Set the delay before starting the next connection attempt.
+    ///
+    /// Default is [`DEFAULT_ATTEMPT_DELAY`].
+    pub fn with_attempt_delay(mut self, delay: Duration) -> Self {
+        self.attempt_delay = delay;
+        self
+    }
+
+    pub const fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    pub const fn config_mut(&mut self) -> &mut Config {
+        &mut self.cfg
+    }
+
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Connect to any of the `addrs` by racing connection attempts.
+    pub async fn dial_addrs(&self, addrs: Vec<SocketAddr>) -> io::Result<TcpStream> {
+        let mut pending = interleave_addrs(addrs).into_iter().peekable();
+        let mut attempts = FuturesUnordered::new();
+        let mut last_err = None;
+        if let Some(addr) = pending.next() {
+            attempts.push(make_tcp_connection(&self.cfg, addr));
+        }
+
+        loop {
+            let res = if pending.peek().is_some() {
+                match timeout(self.attempt_delay, attempts.next()).await {
+                    Ok(res) => res,
+                    Err(_) => {
+                        // The previous attempts are too slow, start the next one concurrently.
+                        if let Some(addr) = pending.next() {
+                            attempts.push(make_tcp_connection(&self.cfg, addr));
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                attempts.next().await
+            };
+            match res {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(err)) => {
+                    tracing::debug!("[VOLO] dial attempt failed: {err}");
+                    last_err = Some(err);
+                    if let Some(addr) = pending.next() {
+                        attempts.push(make_tcp_connection(&self.cfg, addr));
+                    }
+                }
+                None => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::AddrNotAvailable, "no address to dial")
+                    }));
+                }
+            }
+        }
+    }
+}
+
+impl<R> Dialer<R>
+where
+    R: Resolve,
+{
+    /// Resolve the `host` and connect to it, the `host` can also be an ip address.
+    pub async fn dial(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let ip = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host)
+            .parse::<IpAddr>();
+        if let Ok(ip) = ip {
+            return make_tcp_connection(&self.cfg, SocketAddr::new(ip, port)).await;
+        }
+
+        let start = Timestamp::now();
+        let addrs = self.resolver.resolve(host, port).await?;
+        timing::record(Phase::Resolve, start, Timestamp::now());
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("failed to resolve {host}"),
+            ));
+        }
+        self.dial_addrs(addrs).await
+    }
+}
+
+/// Interleave addresses by address family, the family of the first address is preferred.
+///
+/// See [RFC 8305 Section 4](https://datatracker.ietf.org/doc/html/rfc8305#section-4).
+fn interleave_addrs(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let prefer_v6 = first.is_ipv6();
+    let (preferred, others): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == prefer_v6);
+
+    let mut res = Vec::with_capacity(preferred.len() + others.len());
+    let mut preferred = preferred.into_iter();
+    let mut others = others.into_iter();
+    loop {
+        match (preferred.next(), others.next()) {
+            (None, None) => break,
+            (a, b) => res.extend(a.into_iter().chain(b)),
+        }
+    }
+    res
+}
+
+fn set_tcp_keepalive(socket: &Socket, keepalive: &TcpKeepalive) -> io::Result<()> {
+    #[allow(unused_mut)]
+    let mut params = socket2::TcpKeepalive::new().with_time(keepalive.time);
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "windows",
+    ))]
+    {
+        if let Some(interval) = keepalive.interval {
+            params = params.with_interval(interval);
+        }
+        if let Some(retries) = keepalive.retries {
+            params = params.with_retries(retries);
+        }
+    }
+    socket.set_tcp_keepalive(&params)
+}
+
+#[cfg(target_os = "linux")]
+fn set_tcp_fast_open_connect(socket: &Socket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let enable: libc::c_int = 1;
+    // SAFETY: the fd is valid and the option value is a `c_int` as required by the kernel.
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN_CONNECT,
+            &enable as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_tcp_fast_open_connect(_: &Socket) -> io::Result<()> {
+    tracing::debug!("[VOLO] TCP Fast Open is not supported on this platform, ignored");
+    Ok(())
+}
+
 impl UnaryService<Address> for DefaultMakeTransport {
     type Response = Conn;
     type Error = io::Error;
 
+    /// The errors are returned with the [`ConnectError`] inside, which carries the address.
     async fn call(&self, addr: Address) -> Result<Self::Response, Self::Error> {
-        match addr {
-            Address::Ip(addr) => {
-                let stream = make_tcp_connection(&self.cfg, addr).await?;
-                stream.set_nodelay(true)?;
-                Ok(Conn::from(stream))
-            }
+        let res = match &addr {
+            Address::Ip(addr) => self
+                .dialer
+                .dial_addrs(vec![*addr])
+                .await
+                .and_then(|stream| {
+                    stream.set_nodelay(true)?;
+                    Ok(Conn::from(stream))
+                }),
             #[cfg(target_family = "unix")]
-            Address::Unix(addr) => UnixStream::connect(addr.as_pathname().ok_or_else(|| {
-                io::Error::new(
+            Address::Unix(addr) => match addr.as_pathname() {
+                Some(path) => {
+                    let start = Timestamp::now();
+                    let stream = UnixStream::connect(path).await;
+                    timing::record(Phase::Connect, start, Timestamp::now());
+                    stream.map(Conn::from)
+                }
+                None => Err(io::Error::new(
                     io::ErrorKind::AddrNotAvailable,
                     "cannot connect to unnamed socket",
-                )
-            })?)
-            .await
-            .map(Conn::from),
+                )),
+            },
             #[cfg(feature = "shmipc")]
             Address::Shmipc(addr) => super::shmipc::addr::ShmipcMakeTransport
-                .call(addr)
+                .call(addr.clone())
                 .await
                 .map(Conn::from),
+        };
+        res.map_err(|err| ConnectError::new(addr, err).into_io_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn interleave() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "1.1.1.1:1", "2.2.2.2:1"]
+            .into_iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let res: Vec<String> = interleave_addrs(addrs)
+            .into_iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(
+            res,
+            ["[::1]:1", "1.1.1.1:1", "[::2]:1", "2.2.2.2:1", "[::3]:1"]
+        );
+    }
+
+    struct StaticResolver(Vec<SocketAddr>);
+
+    impl Resolve for StaticResolver {
+        async fn resolve(&self, _: &str, _: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(self.0.clone())
         }
     }
+
+    #[tokio::test]
+    async fn dial_fallback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // bind and drop for getting an unused port
+        let refused = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let dialer = Dialer::default()
+            .with_resolver(StaticResolver(vec![refused, addr]))
+            .with_attempt_delay(Duration::from_secs(10));
+        let stream = dialer.dial("volo.test", addr.port()).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        // tfo falls back to a normal handshake without the cookie
+        let stream = Dialer::new(Config::default().with_tcp_fast_open(true))
+            .dial_addrs(vec![addr])
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        let dialer = dialer.with_resolver(StaticResolver(vec![refused]));
+        assert!(dialer.dial("volo.test", 0).await.is_err());
+        let dialer = dialer.with_resolver(StaticResolver(Vec::new()));
+        assert!(dialer.dial("volo.test", 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tcp_keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let keepalive = TcpKeepalive::new(Duration::from_secs(30))
+            .with_interval(Duration::from_secs(5))
+            .with_retries(3);
+        let stream = Dialer::new(Config::default().with_tcp_keepalive(Some(keepalive)))
+            .dial_addrs(vec![addr])
+            .await
+            .unwrap();
+        let socket = socket2::SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                socket.tcp_keepalive_time().unwrap(),
+                Duration::from_secs(30)
+            );
+            assert_eq!(
+                socket.tcp_keepalive_interval().unwrap(),
+                Duration::from_secs(5)
+            );
+            assert_eq!(socket.tcp_keepalive_retries().unwrap(), 3);
+        }
+
+        let stream = Dialer::default().dial_addrs(vec![addr]).await.unwrap();
+        assert!(!socket2::SockRef::from(&stream).keepalive().unwrap());
+    }
 }
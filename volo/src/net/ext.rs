@@ -34,6 +34,11 @@ impl AsyncExt for Conn {
                 io::ErrorKind::Unsupported,
                 "AsyncExt is not supported for ShmIPC connection",
             )),
+            // in-memory and io_uring connections are always ready, and closing is observed by
+            // reading
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ConnStream::Uring(_) => Ok(Ready::READABLE | Ready::WRITABLE),
+            ConnStream::Memory(_) => Ok(Ready::READABLE | Ready::WRITABLE),
         }
     }
 
@@ -62,6 +67,11 @@ impl AsyncExt for OwnedReadHalf {
                 io::ErrorKind::Unsupported,
                 "AsyncExt is not supported for ShmIPC connection",
             )),
+            // in-memory and io_uring connections are always ready, and closing is observed by
+            // reading
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedReadHalf::Uring(_) => Ok(Ready::READABLE | Ready::WRITABLE),
+            OwnedReadHalf::Memory(_) => Ok(Ready::READABLE | Ready::WRITABLE),
         }
     }
 
@@ -90,6 +100,11 @@ impl AsyncExt for OwnedWriteHalf {
                 io::ErrorKind::Unsupported,
                 "AsyncExt is not supported for ShmIPC connection",
             )),
+            // in-memory and io_uring connections are always ready, and closing is observed by
+            // reading
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            OwnedWriteHalf::Uring(_) => Ok(Ready::READABLE | Ready::WRITABLE),
+            OwnedWriteHalf::Memory(_) => Ok(Ready::READABLE | Ready::WRITABLE),
         }
     }
 
@@ -3,6 +3,7 @@ use std::{
     future::Future,
     io,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures::Stream;
@@ -75,6 +76,72 @@ pub trait MakeIncoming {
     fn make_incoming(self) -> impl Future<Output = io::Result<Self::Incoming>> + Send;
 }
 
+/// Options of TCP listeners.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ListenConfig {
+    /// Enable TCP Fast Open with the maximum length of pending SYNs carrying data.
+    ///
+    /// Only supported on Linux, it will be ignored on other platforms.
+    pub tcp_fast_open: Option<u32>,
+    /// Only wake up the acceptor when data arrives, connections without any data in the timeout
+    /// will be accepted (or dropped) by the kernel without waking up.
+    ///
+    /// Only supported on Linux, it will be ignored on other platforms.
+    pub defer_accept: Option<Duration>,
+}
+
+impl ListenConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tcp_fast_open(mut self, queue_len: Option<u32>) -> Self {
+        self.tcp_fast_open = queue_len;
+        self
+    }
+
+    pub fn with_defer_accept(mut self, timeout: Option<Duration>) -> Self {
+        self.defer_accept = timeout;
+        self
+    }
+}
+
+/// [`MakeIncoming`] for an [`Address`] with options of [`ListenConfig`].
+///
+/// The config only takes effect for [`Address::Ip`].
+#[derive(Debug, Clone)]
+pub struct AddressWithConfig {
+    pub addr: Address,
+    pub config: ListenConfig,
+}
+
+impl AddressWithConfig {
+    pub fn new(addr: Address, config: ListenConfig) -> Self {
+        Self { addr, config }
+    }
+}
+
+impl MakeIncoming for AddressWithConfig {
+    type Incoming = DefaultIncoming;
+
+    #[cfg(target_family = "unix")]
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        match self.addr {
+            Address::Ip(addr) => {
+                let listener =
+                    unix_helper::create_tcp_listener_with_max_backlog(addr, &self.config).await;
+                TcpListener::from_std(listener?).map(DefaultIncoming::from)
+            }
+            addr => addr.make_incoming().await,
+        }
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        self.addr.make_incoming().await
+    }
+}
+
 #[cfg(target_family = "unix")]
 impl MakeIncoming for Address {
     type Incoming = DefaultIncoming;
@@ -82,7 +149,11 @@ impl MakeIncoming for Address {
     async fn make_incoming(self) -> io::Result<Self::Incoming> {
         match self {
             Address::Ip(addr) => {
-                let listener = unix_helper::create_tcp_listener_with_max_backlog(addr).await;
+                let listener = unix_helper::create_tcp_listener_with_max_backlog(
+                    addr,
+                    &ListenConfig::default(),
+                )
+                .await;
                 TcpListener::from_std(listener?).map(DefaultIncoming::from)
             }
             Address::Unix(addr) => {
@@ -133,7 +204,7 @@ impl Stream for DefaultIncoming {
 }
 
 #[cfg(target_family = "unix")]
-mod unix_helper {
+pub(super) mod unix_helper {
 
     #[cfg(target_os = "linux")]
     use std::{
@@ -151,6 +222,7 @@ mod unix_helper {
 
     use socket2::{Domain, Protocol, Socket, Type};
 
+    use super::ListenConfig;
     use crate::hotrestart::DEFAULT_HOT_RESTART;
 
     /// Returns major and minor kernel version numbers, parsed from
@@ -261,6 +333,7 @@ mod unix_helper {
 
     pub async fn create_tcp_listener_with_max_backlog(
         addr: SocketAddr,
+        config: &ListenConfig,
     ) -> std::io::Result<TcpListener> {
         if let Ok(Some(raw_fd)) = DEFAULT_HOT_RESTART
             .dup_parent_listener_sock(addr.to_string())
@@ -284,6 +357,7 @@ mod unix_helper {
         socket.set_cloexec(true)?;
 
         socket.bind(&socket2::SockAddr::from(addr))?;
+        set_listen_options(&socket, config)?;
 
         #[cfg(target_os = "linux")]
         let backlog = max_listener_backlog();
@@ -295,6 +369,55 @@ mod unix_helper {
         Ok(socket.into())
     }
 
+    #[cfg(target_os = "linux")]
+    fn set_listen_options(socket: &Socket, config: &ListenConfig) -> std::io::Result<()> {
+        fn set_tcp_opt(socket: &Socket, opt: libc::c_int, val: libc::c_int) -> std::io::Result<()> {
+            // SAFETY: the fd is valid and the option value is a `c_int` as required by the kernel.
+            let ret = unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::IPPROTO_TCP,
+                    opt,
+                    &val as *const libc::c_int as *const libc::c_void,
+                    std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if ret != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        if let Some(queue_len) = config.tcp_fast_open {
+            set_tcp_opt(
+                socket,
+                libc::TCP_FASTOPEN,
+                queue_len.min(libc::c_int::MAX as u32) as libc::c_int,
+            )?;
+        }
+        if let Some(timeout) = config.defer_accept {
+            // the option is in seconds, round up for sub-second timeouts
+            let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            set_tcp_opt(
+                socket,
+                libc::TCP_DEFER_ACCEPT,
+                secs.min(libc::c_int::MAX as u64) as libc::c_int,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_listen_options(_: &Socket, config: &ListenConfig) -> std::io::Result<()> {
+        if config.tcp_fast_open.is_some() || config.defer_accept.is_some() {
+            tracing::debug!(
+                "[VOLO] TCP Fast Open and deferred accept are not supported on this platform, \
+                 ignored"
+            );
+        }
+        Ok(())
+    }
+
     pub async fn create_unix_listener_with_max_backlog<P: AsRef<Path>>(
         path: P,
     ) -> std::io::Result<UnixListener> {
@@ -337,3 +460,35 @@ mod unix_helper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn listen_with_config() {
+        let config = ListenConfig::new()
+            .with_tcp_fast_open(Some(128))
+            .with_defer_accept(Some(Duration::from_millis(500)));
+        let mut incoming =
+            AddressWithConfig::new(Address::Ip("127.0.0.1:0".parse().unwrap()), config)
+                .make_incoming()
+                .await
+                .unwrap();
+        let DefaultIncoming::Tcp(listener) = &incoming else {
+            panic!("unexpected incoming: {incoming:?}");
+        };
+        let addr = listener.as_ref().local_addr().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        // with deferred accept, the connection is accepted after data arrives
+        tokio::io::AsyncWriteExt::write_all(&mut stream, b"ping")
+            .await
+            .unwrap();
+        let conn = incoming.accept().await.unwrap().unwrap();
+        assert_eq!(
+            conn.info.peer_addr,
+            Some(Address::Ip(stream.local_addr().unwrap()))
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! In-memory transport for testing clients against servers in the same process.
+//!
+//! A [`MemoryListener`] accepts connections created by its [`MemoryConnector`]s, every connection
+//! is a pair of [`DuplexStream`]s, so no port is bound and no socket is created. The listener can
+//! be passed to servers as a [`MakeIncoming`], and the connector can be used by clients as a
+//! [`MakeTransport`] or [`MakeConnection`], which ignores the address to connect to.
+//!
+//! # Example
+//!
+//! ```
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use volo::net::{incoming::Incoming, memory::MemoryListener};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> std::io::Result<()> {
+//! let mut listener = MemoryListener::new();
+//! let connector = listener.connector();
+//!
+//! let mut client = connector.connect().await?;
+//! client.write_all(b"ping").await?;
+//!
+//! let mut server = listener.accept().await?.unwrap();
+//! let mut buf = [0; 4];
+//! server.read_exact(&mut buf).await?;
+//! assert_eq!(&buf, b"ping");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`MakeConnection`]: motore::make::MakeConnection
+
+use std::{
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use motore::service::UnaryService;
+use tokio::{
+    io::DuplexStream,
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+};
+
+use super::{
+    Address,
+    conn::{Conn, ConnStream, OwnedReadHalf, OwnedWriteHalf},
+    dial::MakeTransport,
+    incoming::{Incoming, MakeIncoming},
+};
+
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Placeholder address of both sides of in-memory connections.
+pub(crate) const PLACEHOLDER_ADDRESS: Address =
+    Address::Ip(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0));
+
+/// Listener of the in-memory transport, which accepts connections created by its
+/// [`MemoryConnector`]s.
+///
+/// The listener is closed when all of its connectors are dropped, and then
+/// [`Incoming::accept`] returns `None`, which stops the server.
+pub struct MemoryListener {
+    rx: UnboundedReceiver<DuplexStream>,
+    tx: UnboundedSender<DuplexStream>,
+    buffer_size: usize,
+}
+
+impl Default for MemoryListener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryListener {
+    /// Create a [`MemoryListener`].
+    pub fn new() -> Self {
+        let (tx, rx) = unbounded_channel();
+        Self {
+            rx,
+            tx,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Set the max bytes buffered in each direction of connections, writing to a connection waits
+    /// if the buffer is full.
+    ///
+    /// Default is 64KiB. It only takes effect on connectors created after this call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn with_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "buffer size must be greater than zero");
+        self.buffer_size = size;
+        self
+    }
+
+    /// Create a [`MemoryConnector`] of the listener.
+    ///
+    /// Note that the listener holds no connector itself, so the connector should be created
+    /// before the listener is passed to a server.
+    pub fn connector(&self) -> MemoryConnector {
+        MemoryConnector {
+            tx: self.tx.clone(),
+            buffer_size: self.buffer_size,
+        }
+    }
+}
+
+impl MakeIncoming for MemoryListener {
+    type Incoming = MemoryIncoming;
+
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        // drop the sender of the listener, so that `accept` returns `None` after all connectors
+        // are dropped
+        Ok(MemoryIncoming { rx: self.rx })
+    }
+}
+
+impl Incoming for MemoryListener {
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        // the listener holds a sender, so it never returns `None` here
+        Ok(self.rx.recv().await.map(accepted))
+    }
+}
+
+impl fmt::Debug for MemoryListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryListener")
+            .field("buffer_size", &self.buffer_size)
+            .finish()
+    }
+}
+
+/// [`Incoming`] made from [`MemoryListener`] for servers.
+pub struct MemoryIncoming {
+    rx: UnboundedReceiver<DuplexStream>,
+}
+
+impl Incoming for MemoryIncoming {
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        Ok(self.rx.recv().await.map(accepted))
+    }
+}
+
+impl fmt::Debug for MemoryIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryIncoming").finish()
+    }
+}
+
+fn accepted(stream: DuplexStream) -> Conn {
+    tracing::trace!("[VOLO] recv an in-memory connection");
+    Conn::from(ConnStream::Memory(stream))
+}
+
+/// Connector of the in-memory transport, which creates connections to its [`MemoryListener`].
+///
+/// It is cheap to clone, and the address to connect to is always ignored.
+#[derive(Clone)]
+pub struct MemoryConnector {
+    tx: UnboundedSender<DuplexStream>,
+    buffer_size: usize,
+}
+
+impl MemoryConnector {
+    /// Create a connection to the listener.
+    ///
+    /// Returns [`io::ErrorKind::ConnectionRefused`] if the listener has been dropped, e.g., the
+    /// server is stopped.
+    pub async fn connect(&self) -> io::Result<Conn> {
+        let (client, server) = tokio::io::duplex(self.buffer_size);
+        self.tx.send(server).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "in-memory listener is closed",
+            )
+        })?;
+        Ok(Conn::from(ConnStream::Memory(client)))
+    }
+
+    /// Returns whether the listener has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// A placeholder address for clients requiring an address of the callee, e.g., for the load
+    /// balance, it is always `127.0.0.1:0`.
+    ///
+    /// It is also the peer address of both sides of in-memory connections.
+    pub fn address(&self) -> Address {
+        PLACEHOLDER_ADDRESS
+    }
+}
+
+impl UnaryService<Address> for MemoryConnector {
+    type Response = Conn;
+    type Error = io::Error;
+
+    async fn call(&self, _: Address) -> Result<Self::Response, Self::Error> {
+        self.connect().await
+    }
+}
+
+impl MakeTransport for MemoryConnector {
+    type ReadHalf = OwnedReadHalf;
+    type WriteHalf = OwnedWriteHalf;
+
+    async fn make_transport(&self, _: Address) -> io::Result<(Self::ReadHalf, Self::WriteHalf)> {
+        Ok(self.connect().await?.stream.into_split())
+    }
+
+    // connecting to and reading from memory never time out
+    fn set_connect_timeout(&mut self, _: Option<Duration>) {}
+
+    fn set_read_timeout(&mut self, _: Option<Duration>) {}
+
+    fn set_write_timeout(&mut self, _: Option<Duration>) {}
+}
+
+impl fmt::Debug for MemoryConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryConnector")
+            .field("buffer_size", &self.buffer_size)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::MemoryListener;
+    use crate::net::{
+        dial::MakeTransport,
+        incoming::{Incoming, MakeIncoming},
+    };
+
+    #[tokio::test]
+    async fn test_connect_and_accept() {
+        let listener = MemoryListener::new().with_buffer_size(16);
+        let connector = listener.connector();
+        let mut incoming = listener.make_incoming().await.unwrap();
+
+        let (mut rh, mut wh) = connector.make_transport(connector.address()).await.unwrap();
+        let mut server = incoming.accept().await.unwrap().unwrap();
+        assert!(server.stream.is_memory());
+        assert_eq!(server.info.peer_addr, Some(connector.address()));
+
+        wh.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        server.write_all(b"world").await.unwrap();
+        rh.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+
+        // the server is stopped after all connectors are dropped
+        drop(connector);
+        assert!(incoming.accept().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_listener_dropped() {
+        let listener = MemoryListener::new();
+        let connector = listener.connector();
+        drop(listener);
+        assert!(connector.is_closed());
+        assert_eq!(
+            connector.connect().await.err().unwrap().kind(),
+            std::io::ErrorKind::ConnectionRefused
+        );
+    }
+}
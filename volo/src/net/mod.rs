@@ -1,7 +1,18 @@
+// The networking of the OS is not available on `wasm32`, where only the `Address` is kept.
+#[cfg(not(target_family = "wasm"))]
 pub mod conn;
+#[cfg(not(target_family = "wasm"))]
 pub mod dial;
+#[cfg(not(target_family = "wasm"))]
 pub mod ext;
+#[cfg(not(target_family = "wasm"))]
 pub mod incoming;
+#[cfg(not(target_family = "wasm"))]
+pub mod memory;
+#[cfg(not(target_family = "wasm"))]
+pub mod proxy;
+#[cfg(not(target_family = "wasm"))]
+pub mod proxy_protocol;
 #[cfg(feature = "shmipc")]
 pub mod shmipc;
 #[cfg(feature = "shmipc")]
@@ -9,7 +20,11 @@ pub mod shmipc_fallback;
 #[cfg(feature = "__tls")]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
 pub mod tls;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "io-uring")))]
+pub mod uring;
 
+#[cfg(not(target_family = "wasm"))]
 mod probe;
 
 #[cfg(target_os = "linux")]
@@ -22,7 +37,8 @@ use std::{
     net::{Ipv6Addr, SocketAddr},
 };
 
-pub use incoming::{DefaultIncoming, MakeIncoming};
+#[cfg(not(target_family = "wasm"))]
+pub use incoming::{AddressWithConfig, DefaultIncoming, ListenConfig, MakeIncoming};
 #[cfg(target_family = "unix")]
 use tokio::net::unix::SocketAddr as TokioUnixSocketAddr;
 
@@ -160,11 +176,17 @@ impl Address {
     }
 }
 
+#[cfg(not(target_family = "wasm"))]
 fn should_favor_ipv6() -> bool {
     let probed = probe::probe();
     !probed.ipv4 || probed.ipv4_mapped_ipv6
 }
 
+#[cfg(target_family = "wasm")]
+fn should_favor_ipv6() -> bool {
+    false
+}
+
 impl fmt::Display for Address {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
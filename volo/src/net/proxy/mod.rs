@@ -0,0 +1,340 @@
+//! Forward proxy support for client connections.
+//!
+//! This module provides the shared parts of dialing through a forward proxy, the protocol
+//! specific implementations live in the submodules, e.g., [`socks5`] and [`http_connect`].
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+};
+
+use faststr::FastStr;
+use tokio::net::TcpStream;
+
+use super::{
+    conn::ConnStream,
+    dial::{Config, Dialer, make_tcp_connection},
+};
+
+pub mod http_connect;
+pub mod socks5;
+
+pub use self::{http_connect::HttpConnectProxy, socks5::Socks5Proxy};
+
+/// A forward proxy for establishing connections.
+#[derive(Clone, Debug)]
+pub enum Proxy {
+    /// SOCKS5 proxy
+    Socks5(Socks5Proxy),
+    /// HTTP proxy with `CONNECT` method
+    HttpConnect(HttpConnectProxy),
+}
+
+impl Proxy {
+    /// Get address of the proxy server.
+    pub fn server(&self) -> &ProxyTarget {
+        match self {
+            Self::Socks5(proxy) => proxy.server(),
+            Self::HttpConnect(proxy) => proxy.server(),
+        }
+    }
+
+    /// Whether the domain name of destination should be sent to the proxy server.
+    pub fn remote_dns(&self) -> bool {
+        match self {
+            Self::Socks5(proxy) => proxy.remote_dns(),
+            Self::HttpConnect(_) => true,
+        }
+    }
+
+    /// Get the list of hosts that should be connected directly.
+    pub fn no_proxy(&self) -> &NoProxy {
+        match self {
+            Self::Socks5(proxy) => proxy.no_proxy(),
+            Self::HttpConnect(proxy) => proxy.no_proxy(),
+        }
+    }
+
+    /// Check if the `target` should be connected directly without the proxy.
+    pub fn bypass(&self, target: &ProxyTarget) -> bool {
+        match target {
+            ProxyTarget::Ip(addr) => self.no_proxy().matches_ip(addr.ip()),
+            ProxyTarget::Domain(host, _) => self.no_proxy().matches(host),
+        }
+    }
+
+    /// Connect to the `target` through the proxy.
+    pub async fn connect(&self, cfg: &Config, target: &ProxyTarget) -> io::Result<ConnStream> {
+        match self {
+            Self::Socks5(proxy) => proxy.connect(cfg, target).await.map(ConnStream::from),
+            Self::HttpConnect(proxy) => proxy.connect(cfg, target).await,
+        }
+    }
+}
+
+impl From<Socks5Proxy> for Proxy {
+    fn from(value: Socks5Proxy) -> Self {
+        Self::Socks5(value)
+    }
+}
+
+impl From<HttpConnectProxy> for Proxy {
+    fn from(value: HttpConnectProxy) -> Self {
+        Self::HttpConnect(value)
+    }
+}
+
+/// The destination that should be connected by a proxy server.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProxyTarget {
+    /// A resolved socket address.
+    Ip(SocketAddr),
+    /// A domain name and port, the name will be resolved by the proxy server.
+    Domain(FastStr, u16),
+}
+
+impl ProxyTarget {
+    /// Parse a `host:port` pair, the host can be an IPv4 address, a bracketed IPv6 address or a
+    /// domain name.
+    pub fn parse(s: &str, default_port: u16) -> Option<Self> {
+        let (host, port) = split_host_port(s, default_port)?;
+        Some(Self::from_host_port(host, port))
+    }
+
+    /// Create a [`ProxyTarget`] by host and port, the host will be considered as a domain name if
+    /// it is not an ip address.
+    pub fn from_host_port(host: &str, port: u16) -> Self {
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+        match host.parse::<IpAddr>() {
+            Ok(ip) => Self::Ip(SocketAddr::new(ip, port)),
+            Err(_) => Self::Domain(FastStr::new(host), port),
+        }
+    }
+
+    /// Get the port of the target.
+    pub fn port(&self) -> u16 {
+        match self {
+            Self::Ip(addr) => addr.port(),
+            Self::Domain(_, port) => *port,
+        }
+    }
+}
+
+impl From<SocketAddr> for ProxyTarget {
+    fn from(value: SocketAddr) -> Self {
+        Self::Ip(value)
+    }
+}
+
+impl fmt::Display for ProxyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(addr) => write!(f, "{addr}"),
+            Self::Domain(host, port) => write!(f, "{host}:{port}"),
+        }
+    }
+}
+
+/// Connect to the proxy `server`, a domain name will be dialed by [`Dialer`].
+pub(crate) async fn connect_server(cfg: &Config, server: &ProxyTarget) -> io::Result<TcpStream> {
+    match server {
+        ProxyTarget::Ip(addr) => make_tcp_connection(cfg, *addr).await,
+        ProxyTarget::Domain(host, port) => Dialer::new(*cfg).dial(host, *port).await,
+    }
+}
+
+/// Split `host:port`, the `default_port` will be used if there is no port.
+pub(crate) fn split_host_port(s: &str, default_port: u16) -> Option<(&str, u16)> {
+    if s.is_empty() {
+        return None;
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        // `[::1]:8080` or `[::1]`
+        let end = rest.find(']')?;
+        let host = &s[..end + 2];
+        let port = match &rest[end + 1..] {
+            "" => default_port,
+            port => port.strip_prefix(':')?.parse().ok()?,
+        };
+        return Some((host, port));
+    }
+    match s.rsplit_once(':') {
+        // A bare IPv6 address without brackets
+        Some((host, _)) if host.contains(':') => Some((s, default_port)),
+        Some((host, port)) => Some((host, port.parse().ok()?)),
+        None => Some((s, default_port)),
+    }
+}
+
+/// A list of hosts that should be connected directly without any proxy.
+///
+/// The list is usually read from environment variable `NO_PROXY` or `no_proxy`, and each entry
+/// is separated by comma. An entry can be:
+///
+/// - `*`: matches all hosts
+/// - an ip address, e.g., `127.0.0.1` or `::1`
+/// - a CIDR block, e.g., `10.0.0.0/8`
+/// - a domain name, e.g., `example.com` or `.example.com`, both of them match `example.com` and all
+///   its subdomains
+#[derive(Clone, Debug, Default)]
+pub struct NoProxy {
+    all: bool,
+    ips: Vec<(IpAddr, u8)>,
+    domains: Vec<FastStr>,
+}
+
+impl NoProxy {
+    /// Parse a [`NoProxy`] from a comma separated list.
+    pub fn new(list: &str) -> Self {
+        let mut no_proxy = Self::default();
+        for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if entry == "*" {
+                no_proxy.all = true;
+                continue;
+            }
+            if let Some(cidr) = parse_cidr(entry) {
+                no_proxy.ips.push(cidr);
+                continue;
+            }
+            let domain = entry.trim_start_matches("*.").trim_start_matches('.');
+            no_proxy
+                .domains
+                .push(FastStr::new(domain.to_ascii_lowercase()));
+        }
+        no_proxy
+    }
+
+    /// Read [`NoProxy`] from environment variable `NO_PROXY` or `no_proxy`.
+    pub fn env() -> Self {
+        std::env::var("NO_PROXY")
+            .or_else(|_| std::env::var("no_proxy"))
+            .map(|list| Self::new(&list))
+            .unwrap_or_default()
+    }
+
+    /// Check if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.ips.is_empty() && self.domains.is_empty()
+    }
+
+    /// Check if the `host` should be connected directly.
+    pub fn matches(&self, host: &str) -> bool {
+        if self.all {
+            return true;
+        }
+        let host = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host);
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self.matches_ip(ip);
+        }
+        let host = host.trim_end_matches('.');
+        self.domains.iter().any(|domain| {
+            host.len() >= domain.len()
+                && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain)
+                && (host.len() == domain.len()
+                    || host.as_bytes()[host.len() - domain.len() - 1] == b'.')
+        })
+    }
+
+    /// Check if the `ip` should be connected directly.
+    pub fn matches_ip(&self, ip: IpAddr) -> bool {
+        if self.all {
+            return true;
+        }
+        self.ips
+            .iter()
+            .any(|(net, prefix)| ip_in_net(ip, *net, *prefix))
+    }
+}
+
+fn parse_cidr(entry: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = match entry.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix.parse::<u8>().ok()?)),
+        None => (entry, None),
+    };
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(ip)
+        .parse::<IpAddr>()
+        .ok()?;
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    if prefix > max {
+        return None;
+    }
+    Some((ip, prefix))
+}
+
+fn ip_in_net(ip: IpAddr, net: IpAddr, prefix: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_host_port_test() {
+        assert_eq!(
+            split_host_port("127.0.0.1:1080", 0),
+            Some(("127.0.0.1", 1080))
+        );
+        assert_eq!(
+            split_host_port("localhost", 1080),
+            Some(("localhost", 1080))
+        );
+        assert_eq!(split_host_port("[::1]:8080", 0), Some(("[::1]", 8080)));
+        assert_eq!(split_host_port("[::1]", 1080), Some(("[::1]", 1080)));
+        assert_eq!(split_host_port("::1", 1080), Some(("::1", 1080)));
+        assert_eq!(split_host_port("localhost:abc", 0), None);
+        assert_eq!(split_host_port("", 0), None);
+    }
+
+    #[test]
+    fn proxy_target_test() {
+        assert_eq!(
+            ProxyTarget::parse("[::1]:80", 0),
+            Some(ProxyTarget::Ip("[::1]:80".parse().unwrap()))
+        );
+        assert_eq!(
+            ProxyTarget::parse("example.com", 443),
+            Some(ProxyTarget::Domain(
+                FastStr::from_static_str("example.com"),
+                443
+            ))
+        );
+    }
+
+    #[test]
+    fn no_proxy_test() {
+        let no_proxy = NoProxy::new("localhost, .example.com,10.0.0.0/8, ::1,*.internal");
+        assert!(no_proxy.matches("localhost"));
+        assert!(no_proxy.matches("example.com"));
+        assert!(no_proxy.matches("API.Example.com"));
+        assert!(!no_proxy.matches("badexample.com"));
+        assert!(no_proxy.matches("svc.internal"));
+        assert!(no_proxy.matches("10.1.2.3"));
+        assert!(!no_proxy.matches("11.1.2.3"));
+        assert!(no_proxy.matches("[::1]"));
+        assert!(!no_proxy.matches("volo.rs"));
+
+        assert!(NoProxy::new("*").matches("volo.rs"));
+        assert!(NoProxy::new("").is_empty());
+    }
+}
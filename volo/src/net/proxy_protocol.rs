@@ -0,0 +1,389 @@
+//! PROXY protocol (v1 and v2) support for accepted connections.
+//!
+//! Load balancers like HAProxy or AWS NLB can send the address of the original client in a PROXY
+//! protocol header before the data of the connection. [`ProxyProtocol`] reads the header of each
+//! accepted connection and replaces [`ConnInfo::peer_addr`] with the source address in it, so
+//! servers see the real client rather than the load balancer.
+//!
+//! ```no_run
+//! use volo::net::{Address, proxy_protocol::ProxyProtocol};
+//!
+//! let addr: Address = "[::]:8080".parse::<std::net::SocketAddr>().unwrap().into();
+//! // pass it to `run` of servers as the `MakeIncoming`
+//! let incoming = ProxyProtocol::new(addr);
+//! ```
+//!
+//! The header is required on all connections of the listener, connections without a valid header
+//! are closed. So it should only be enabled for listeners behind proxies sending the header.
+//!
+//! [`ConnInfo::peer_addr`]: super::conn::ConnInfo::peer_addr
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{io::AsyncReadExt, sync::mpsc, task::JoinHandle};
+
+use super::{
+    Address,
+    conn::Conn,
+    incoming::{Incoming, MakeIncoming},
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+// connections with parsed headers waiting to be accepted
+const BACKLOG: usize = 128;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+// `PROXY UNKNOWN\r\n`
+const V1_MIN_LEN: usize = 15;
+const V1_MAX_LEN: usize = 107;
+
+/// [`MakeIncoming`] that reads the PROXY protocol header of accepted connections.
+///
+/// See the [module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct ProxyProtocol<MI> {
+    inner: MI,
+    timeout: Duration,
+}
+
+impl<MI> ProxyProtocol<MI> {
+    /// Read the PROXY protocol header of connections accepted by the inner [`MakeIncoming`].
+    pub fn new(inner: MI) -> Self {
+        Self {
+            inner,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Set the timeout for reading the header, connections timed out are closed.
+    ///
+    /// Default is 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<MI> MakeIncoming for ProxyProtocol<MI>
+where
+    MI: MakeIncoming + Send,
+{
+    type Incoming = ProxyProtocolIncoming;
+
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        let inner = self.inner.make_incoming().await?;
+        Ok(ProxyProtocolIncoming::new(inner, self.timeout))
+    }
+}
+
+/// [`Incoming`] generated by [`ProxyProtocol`].
+///
+/// Headers are read in background tasks, so slow connections do not block accepting others.
+#[derive(Debug)]
+pub struct ProxyProtocolIncoming {
+    rx: mpsc::Receiver<io::Result<Conn>>,
+    handle: JoinHandle<()>,
+}
+
+impl ProxyProtocolIncoming {
+    fn new<I: Incoming>(inner: I, timeout: Duration) -> Self {
+        let (tx, rx) = mpsc::channel(BACKLOG);
+        let handle = tokio::spawn(accept_loop(inner, timeout, tx));
+        Self { rx, handle }
+    }
+}
+
+impl Drop for ProxyProtocolIncoming {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl Incoming for ProxyProtocolIncoming {
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        self.rx.recv().await.transpose()
+    }
+}
+
+async fn accept_loop<I: Incoming>(
+    mut incoming: I,
+    timeout: Duration,
+    tx: mpsc::Sender<io::Result<Conn>>,
+) {
+    loop {
+        let mut conn = match incoming.accept().await {
+            Ok(Some(conn)) => conn,
+            Ok(None) => return,
+            Err(err) => {
+                if tx.send(Err(err)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(timeout, read_header(&mut conn)).await {
+                Ok(Ok(source)) => {
+                    if let Some(source) = source {
+                        tracing::trace!(
+                            "[VOLO] proxy protocol source of {:?}: {source}",
+                            conn.info.peer_addr
+                        );
+                        conn.info.peer_addr = Some(Address::Ip(source));
+                    }
+                    let _ = tx.send(Ok(conn)).await;
+                }
+                Ok(Err(err)) => {
+                    tracing::debug!(
+                        "[VOLO] failed to read proxy protocol header from {:?}: {err}",
+                        conn.info.peer_addr
+                    );
+                }
+                Err(_) => {
+                    tracing::debug!(
+                        "[VOLO] reading proxy protocol header from {:?} timed out",
+                        conn.info.peer_addr
+                    );
+                }
+            }
+        });
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid proxy protocol header: {msg}"),
+    )
+}
+
+/// Read the header without consuming any data after it, returns the source address if the
+/// connection is proxied.
+async fn read_header<S>(stream: &mut S) -> io::Result<Option<SocketAddr>>
+where
+    S: tokio::io::AsyncRead + Unpin,
+{
+    let mut buf = [0u8; V1_MAX_LEN];
+    stream.read_exact(&mut buf[..V1_MIN_LEN]).await?;
+
+    if buf[..V2_SIGNATURE.len()] == V2_SIGNATURE {
+        stream
+            .read_exact(&mut buf[V1_MIN_LEN..V2_HEADER_LEN])
+            .await?;
+        let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+        let mut addrs = vec![0; len];
+        stream.read_exact(&mut addrs).await?;
+        return parse_v2(&buf[..V2_HEADER_LEN], &addrs);
+    }
+
+    if !buf.starts_with(b"PROXY ") {
+        return Err(invalid("missing header"));
+    }
+    // the line is short, so read it byte by byte rather than buffering data after it
+    let mut len = V1_MIN_LEN;
+    while !buf[..len].ends_with(b"\r\n") {
+        if len == V1_MAX_LEN {
+            return Err(invalid("v1 header is too long"));
+        }
+        stream.read_exact(&mut buf[len..len + 1]).await?;
+        len += 1;
+    }
+    parse_v1(&buf[..len])
+}
+
+/// Parse a v1 header line, e.g., `PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n`.
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.strip_suffix("\r\n"))
+        .ok_or_else(|| invalid("v1 header is not a line"))?;
+    let mut parts = line.split(' ').skip(1);
+    let family = parts.next();
+    if family == Some("UNKNOWN") {
+        return Ok(None);
+    }
+    let (Some(src), Some(_dst), Some(src_port), Some(_dst_port), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(invalid("wrong number of fields in v1 header"));
+    };
+    let src = src
+        .parse::<IpAddr>()
+        .map_err(|_| invalid("bad source address in v1 header"))?;
+    let port = src_port
+        .parse::<u16>()
+        .map_err(|_| invalid("bad source port in v1 header"))?;
+    match (family, src) {
+        (Some("TCP4"), IpAddr::V4(_)) | (Some("TCP6"), IpAddr::V6(_)) => {
+            Ok(Some(SocketAddr::new(src, port)))
+        }
+        _ => Err(invalid("bad protocol in v1 header")),
+    }
+}
+
+/// Parse a v2 header with the addresses following it.
+fn parse_v2(header: &[u8], addrs: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0f;
+    if version != 2 {
+        return Err(invalid("bad version in v2 header"));
+    }
+    match command {
+        // health checks of the proxy itself
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(invalid("bad command in v2 header")),
+    }
+    // TLVs after the addresses are ignored
+    match header[13] >> 4 {
+        // AF_INET
+        0x1 => {
+            let addrs: &[u8; 12] = addrs
+                .get(..12)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| invalid("truncated ipv4 addresses in v2 header"))?;
+            let ip = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let port = u16::from_be_bytes([addrs[8], addrs[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            let addrs: &[u8; 36] = addrs
+                .get(..36)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| invalid("truncated ipv6 addresses in v2 header"))?;
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&addrs[..16]);
+            let port = u16::from_be_bytes([addrs[32], addrs[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // AF_UNSPEC and AF_UNIX, the address of the connection is kept
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod proxy_protocol_tests {
+    use std::net::SocketAddr;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{ProxyProtocol, V2_SIGNATURE, read_header};
+    use crate::net::{Address, DefaultIncoming, MakeIncoming, incoming::Incoming};
+
+    fn v2(command: u8, family: u8, addrs: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(addrs.len() as u16).to_be_bytes());
+        header.extend_from_slice(addrs);
+        header
+    }
+
+    async fn read(mut data: &[u8]) -> std::io::Result<Option<SocketAddr>> {
+        let source = read_header(&mut data).await;
+        // data after the header is not consumed
+        assert!(source.is_err() || data == b"ping");
+        source
+    }
+
+    #[tokio::test]
+    async fn test_v1() {
+        let source = read(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nping").await;
+        assert_eq!(source.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        let source = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\nping").await;
+        assert_eq!(
+            source.unwrap(),
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+        assert_eq!(read(b"PROXY UNKNOWN\r\nping").await.unwrap(), None);
+
+        assert!(
+            read(b"PROXY TCP4 2001:db8::1 192.0.2.2 56324 443\r\n")
+                .await
+                .is_err()
+        );
+        assert!(
+            read(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324\r\n")
+                .await
+                .is_err()
+        );
+        assert!(read(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let mut data = v2(
+            0x1,
+            0x11,
+            &[192, 0, 2, 1, 192, 0, 2, 2, 0xdc, 0x04, 0x01, 0xbb],
+        );
+        data.extend_from_slice(b"ping");
+        let source = read(&data).await;
+        assert_eq!(source.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+
+        let mut addrs = [0u8; 36];
+        addrs[0] = 0x20;
+        addrs[1] = 0x01;
+        addrs[15] = 1;
+        addrs[32..34].copy_from_slice(&56324u16.to_be_bytes());
+        let mut data = v2(0x1, 0x21, &addrs);
+        data.extend_from_slice(b"ping");
+        let source = read(&data).await;
+        assert_eq!(source.unwrap(), Some("[2001::1]:56324".parse().unwrap()));
+
+        // LOCAL command
+        let mut data = v2(0x0, 0x00, &[]);
+        data.extend_from_slice(b"ping");
+        assert_eq!(read(&data).await.unwrap(), None);
+
+        // truncated addresses
+        let data = v2(0x1, 0x11, &[192, 0, 2, 1]);
+        assert!(read(&data).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_incoming() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut incoming = ProxyProtocol::new(DefaultIncoming::from(listener))
+            .make_incoming()
+            .await
+            .unwrap();
+
+        // connections without the header are closed
+        let mut bad = TcpStream::connect(addr).await.unwrap();
+        bad.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\nping")
+            .await
+            .unwrap();
+        let mut conn = incoming.accept().await.unwrap().unwrap();
+        assert_eq!(
+            conn.info.peer_addr,
+            Some(Address::Ip("192.0.2.1:56324".parse().unwrap()))
+        );
+        let mut buf = [0u8; 4];
+        conn.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        let mut buf = Vec::new();
+        // closed with unread data, so it may be reset
+        assert!(!matches!(bad.read_to_end(&mut buf).await, Ok(n) if n > 0));
+    }
+}
@@ -3,7 +3,7 @@ use std::io;
 use super::{
     Address, DefaultIncoming, MakeIncoming,
     conn::{Conn, OwnedReadHalf, OwnedWriteHalf},
-    dial::{DefaultMakeTransport, MakeTransport},
+    dial::{DefaultMakeTransport, MakeTransport, TcpKeepalive},
     incoming::Incoming,
 };
 
@@ -119,4 +119,8 @@ impl MakeTransport for ShmipcMakeTransportWithFallback {
         self.default_mkt.set_write_timeout(timeout);
         self.shmipc_mkt.set_write_timeout(timeout);
     }
+
+    fn set_tcp_keepalive(&mut self, keepalive: Option<TcpKeepalive>) {
+        self.default_mkt.set_tcp_keepalive(keepalive);
+    }
 }
@@ -15,10 +15,16 @@ use tokio::{
     net::TcpStream,
 };
 
-use super::dial::{Config, MakeTransport};
-use crate::net::{
-    Address,
-    conn::{self, Conn, ConnStream},
+use super::dial::{Config, Dialer, MakeTransport, TcpKeepalive};
+use crate::{
+    net::{
+        Address,
+        conn::{self, Conn, ConnStream},
+    },
+    util::{
+        time::Timestamp,
+        timing::{self, Phase},
+    },
 };
 
 #[cfg(feature = "native-tls")]
@@ -94,6 +100,21 @@ impl TlsStream {
             Self::NativeTls(stream) => stream.get_ref().negotiated_alpn().ok().flatten(),
         }
     }
+
+    /// Returns the server name requested by the client through SNI.
+    ///
+    /// It is only available for streams accepted by rustls, and `None` for native-tls.
+    pub fn server_name(&self) -> Option<&str> {
+        match self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(stream) => match stream.as_ref() {
+                tokio_rustls::TlsStream::Server(stream) => stream.get_ref().1.server_name(),
+                tokio_rustls::TlsStream::Client(_) => None,
+            },
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(_) => None,
+        }
+    }
 }
 
 #[cfg(feature = "rustls")]
@@ -322,7 +343,8 @@ impl TlsConnector {
         server_name: &str,
         tcp_stream: TcpStream,
     ) -> io::Result<ConnStream> {
-        match self {
+        let start = Timestamp::now();
+        let stream = match self {
             #[cfg(feature = "rustls")]
             Self::Rustls(connector) => connector
                 .connect(server_name, tcp_stream)
@@ -333,7 +355,9 @@ impl TlsConnector {
                 .connect(server_name, tcp_stream)
                 .await
                 .map(ConnStream::from),
-        }
+        }?;
+        timing::record(Phase::TlsHandshake, start, Timestamp::now());
+        Ok(stream)
     }
 }
 
@@ -511,6 +535,31 @@ impl TlsMakeTransport {
     pub fn new(cfg: Config, tls_config: ClientTlsConfig) -> Self {
         Self { cfg, tls_config }
     }
+
+    /// Resolve the `host` and connect to it by the [`Dialer`], the `host` can also be an ip
+    /// address.
+    pub async fn dial(&self, host: &str, port: u16) -> io::Result<Conn> {
+        let tcp = Dialer::new(self.cfg).dial(host, port).await?;
+        self.handshake(tcp).await
+    }
+
+    async fn handshake(&self, tcp: TcpStream) -> io::Result<Conn> {
+        let start = Timestamp::now();
+        let conn = match &self.tls_config.connector {
+            #[cfg(feature = "rustls")]
+            TlsConnector::Rustls(connector) => connector
+                .connect(&self.tls_config.server_name, tcp)
+                .await
+                .map(Conn::from),
+            #[cfg(feature = "native-tls")]
+            TlsConnector::NativeTls(connector) => connector
+                .connect(&self.tls_config.server_name, tcp)
+                .await
+                .map(Conn::from),
+        }?;
+        timing::record(Phase::TlsHandshake, start, Timestamp::now());
+        Ok(conn)
+    }
 }
 
 impl UnaryService<Address> for TlsMakeTransport {
@@ -520,20 +569,8 @@ impl UnaryService<Address> for TlsMakeTransport {
     async fn call(&self, addr: Address) -> std::result::Result<Self::Response, Self::Error> {
         match addr {
             Address::Ip(addr) => {
-                let tcp = super::dial::make_tcp_connection(&self.cfg, addr).await?;
-
-                match &self.tls_config.connector {
-                    #[cfg(feature = "rustls")]
-                    TlsConnector::Rustls(connector) => connector
-                        .connect(&self.tls_config.server_name, tcp)
-                        .await
-                        .map(Conn::from),
-                    #[cfg(feature = "native-tls")]
-                    TlsConnector::NativeTls(connector) => connector
-                        .connect(&self.tls_config.server_name, tcp)
-                        .await
-                        .map(Conn::from),
-                }
+                let tcp = Dialer::new(self.cfg).dial_addrs(vec![addr]).await?;
+                self.handshake(tcp).await
             }
             #[cfg(target_family = "unix")]
             Address::Unix(_) => Err(io::Error::new(
@@ -570,4 +607,8 @@ impl MakeTransport for TlsMakeTransport {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) {
         self.cfg = self.cfg.with_write_timeout(timeout);
     }
+
+    fn set_tcp_keepalive(&mut self, keepalive: Option<TcpKeepalive>) {
+        self.cfg = self.cfg.with_tcp_keepalive(keepalive);
+    }
 }
@@ -155,3 +155,27 @@ impl From<TlsAcceptor> for super::TlsAcceptor {
         Self::Rustls(RustlsAcceptor(acceptor))
     }
 }
+
+impl super::TlsConnector {
+    /// Get the rustls [`ClientConfig`] of the connector, returns [`None`] if the connector is not
+    /// built by rustls.
+    pub fn rustls_config(&self) -> Option<&Arc<ClientConfig>> {
+        match self {
+            Self::Rustls(connector) => Some(connector.0.config()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+impl super::TlsAcceptor {
+    /// Get the rustls [`ServerConfig`] of the acceptor, returns [`None`] if the acceptor is not
+    /// built by rustls.
+    pub fn rustls_config(&self) -> Option<&Arc<ServerConfig>> {
+        match self {
+            Self::Rustls(acceptor) => Some(acceptor.0.config()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,697 @@
+//! TCP servers accepting, reading and writing connections through `io_uring`.
+//!
+//! [`UringAddress`] is a [`MakeIncoming`] like [`Address`], but the listener accepts connections
+//! by `io_uring`, and reads and writes of the accepted connections are submitted to `io_uring`
+//! instead of being driven by the epoll reactor of tokio. The connections are still
+//! [`ConnStream`]s implementing [`AsyncRead`] and [`AsyncWrite`], so servers and services work
+//! with them without any change.
+//!
+//! This is the first cut of the support, so there are some limitations:
+//!
+//! - Only servers are supported, clients still connect by tokio.
+//! - All operations of the process are submitted to one ring, whose completions are reaped by a
+//!   dedicated thread named `volo-uring`.
+//! - Data is copied between the buffers of the caller and the buffers owned by the operations,
+//!   since the kernel may access the buffers until the operations complete.
+//! - Readiness of [`AsyncExt::ready`] is not supported, and connections are always ready.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use volo::net::{MakeIncoming, incoming::Incoming, uring::UringAddress};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let mut incoming = UringAddress::new("127.0.0.1:8080".parse().unwrap())
+//!     .make_incoming()
+//!     .await?;
+//! while let Some(conn) = incoming.accept().await? {
+//!     println!("accepted connection from {:?}", conn.info.peer_addr);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Address`]: super::Address
+//! [`AsyncExt::ready`]: super::ext::AsyncExt::ready
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt, io,
+    net::SocketAddr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    pin::Pin,
+    sync::{
+        Arc, Mutex, Once, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Waker, ready},
+};
+
+use io_uring::{IoUring, opcode, squeue, types::Fd};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{
+    Address,
+    conn::{Conn, ConnInfo, ConnStream},
+    incoming::{Incoming, ListenConfig, MakeIncoming, unix_helper},
+};
+
+/// Entries of the submission queue.
+const RING_ENTRIES: u32 = 4096;
+/// Size of the buffer for each read operation.
+const READ_BUF_SIZE: usize = 64 * 1024;
+/// Maximum size of data submitted by each write operation.
+const MAX_WRITE_SIZE: usize = 1024 * 1024;
+/// User data of cancelling operations, whose completions are ignored.
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+type Resources = Box<dyn Any + Send>;
+
+enum Lifecycle {
+    Submitted(Option<Waker>),
+    Completed(i32),
+    // the handle has been dropped, the operation is removed once it completes
+    Cancelled,
+}
+
+struct OpState {
+    lifecycle: Lifecycle,
+    // buffers and fds used by the kernel, which must be alive until the operation completes
+    resources: Resources,
+}
+
+struct Driver {
+    ring: IoUring,
+    // guards the submission queue, which is shared by all threads submitting operations
+    submission: Mutex<()>,
+    ops: Mutex<HashMap<u64, OpState>>,
+    next_id: AtomicU64,
+}
+
+static DRIVER: OnceLock<io::Result<Driver>> = OnceLock::new();
+static START: Once = Once::new();
+
+impl Driver {
+    /// Get the driver of the process, the ring is created and the thread reaping completions is
+    /// started at the first call.
+    fn get() -> io::Result<&'static Driver> {
+        let driver = match DRIVER.get_or_init(|| {
+            IoUring::new(RING_ENTRIES).map(|ring| Driver {
+                ring,
+                submission: Mutex::new(()),
+                ops: Mutex::new(HashMap::new()),
+                next_id: AtomicU64::new(0),
+            })
+        }) {
+            Ok(driver) => driver,
+            Err(err) => return Err(io::Error::new(err.kind(), err.to_string())),
+        };
+        START.call_once(|| {
+            std::thread::Builder::new()
+                .name("volo-uring".to_owned())
+                .spawn(|| driver.run())
+                .expect("failed to spawn the thread of io_uring");
+        });
+        Ok(driver)
+    }
+
+    fn run(&self) {
+        loop {
+            if let Err(err) = self.ring.submitter().submit_and_wait(1) {
+                if !matches!(err.raw_os_error(), Some(libc::EINTR | libc::EBUSY)) {
+                    tracing::error!("[VOLO] io_uring failed to wait for completions: {err}");
+                }
+            }
+
+            let mut wakers = Vec::new();
+            {
+                let mut ops = self.ops.lock().unwrap();
+                // SAFETY: completions are only consumed by this thread.
+                for cqe in unsafe { self.ring.completion_shared() } {
+                    let id = cqe.user_data();
+                    if id == CANCEL_USER_DATA {
+                        continue;
+                    }
+                    let Some(op) = ops.get_mut(&id) else {
+                        continue;
+                    };
+                    match &mut op.lifecycle {
+                        Lifecycle::Submitted(waker) => {
+                            wakers.extend(waker.take());
+                            op.lifecycle = Lifecycle::Completed(cqe.result());
+                        }
+                        Lifecycle::Cancelled => {
+                            ops.remove(&id);
+                        }
+                        Lifecycle::Completed(_) => {}
+                    }
+                }
+            }
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+        let _guard = self.submission.lock().unwrap();
+        // SAFETY: the submission queue is guarded by the lock.
+        let mut sq = unsafe { self.ring.submission_shared() };
+        // SAFETY: resources of the entry are kept alive in `ops` until it completes.
+        while unsafe { sq.push(entry) }.is_err() {
+            // the queue is full, let the kernel consume the entries
+            sq.sync();
+            self.ring.submit()?;
+            sq.sync();
+        }
+        sq.sync();
+        drop(sq);
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn submit<T: Send + 'static>(
+        &'static self,
+        entry: squeue::Entry,
+        resources: T,
+    ) -> io::Result<Op<T>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.ops.lock().unwrap().insert(
+            id,
+            OpState {
+                lifecycle: Lifecycle::Submitted(None),
+                resources: Box::new(resources),
+            },
+        );
+        if let Err(err) = self.push(&entry.user_data(id)) {
+            self.ops.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        Ok(Op {
+            driver: self,
+            id,
+            done: false,
+            _marker: std::marker::PhantomData,
+        })
+    }
+}
+
+/// Handle of an operation submitted to the ring, the operation is cancelled if the handle is
+/// dropped before it completes.
+struct Op<T> {
+    driver: &'static Driver,
+    id: u64,
+    done: bool,
+    _marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> Op<T> {
+    /// Poll the result of the operation, and take back the resources.
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<(i32, T)> {
+        let mut ops = self.driver.ops.lock().unwrap();
+        let op = ops.get_mut(&self.id).expect("io_uring operation not found");
+        match &mut op.lifecycle {
+            Lifecycle::Submitted(waker) => {
+                match waker {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    _ => *waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+            Lifecycle::Completed(res) => {
+                let res = *res;
+                let op = ops.remove(&self.id).expect("io_uring operation not found");
+                self.done = true;
+                let resources = op
+                    .resources
+                    .downcast::<T>()
+                    .expect("unexpected resources of io_uring operation");
+                Poll::Ready((res, *resources))
+            }
+            Lifecycle::Cancelled => unreachable!("polling a cancelled io_uring operation"),
+        }
+    }
+}
+
+impl<T> Drop for Op<T> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut ops = self.driver.ops.lock().unwrap();
+        let Some(op) = ops.get_mut(&self.id) else {
+            return;
+        };
+        if matches!(op.lifecycle, Lifecycle::Completed(_)) {
+            ops.remove(&self.id);
+            return;
+        }
+        op.lifecycle = Lifecycle::Cancelled;
+        drop(ops);
+        let entry = opcode::AsyncCancel::new(self.id)
+            .build()
+            .user_data(CANCEL_USER_DATA);
+        if let Err(err) = self.driver.push(&entry) {
+            tracing::warn!("[VOLO] io_uring failed to cancel operation: {err}");
+        }
+    }
+}
+
+fn result(res: i32) -> io::Result<usize> {
+    if res < 0 {
+        Err(io::Error::from_raw_os_error(-res))
+    } else {
+        Ok(res as usize)
+    }
+}
+
+/// [`MakeIncoming`] for a TCP address whose connections are handled by `io_uring`.
+///
+/// See the [module level documentation](self) for more details.
+#[derive(Debug, Clone)]
+pub struct UringAddress {
+    pub addr: SocketAddr,
+    pub config: ListenConfig,
+}
+
+impl UringAddress {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            config: ListenConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ListenConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl MakeIncoming for UringAddress {
+    type Incoming = UringIncoming;
+
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        let driver = Driver::get()?;
+        let listener =
+            unix_helper::create_tcp_listener_with_max_backlog(self.addr, &self.config).await?;
+        Ok(UringIncoming {
+            driver,
+            listener: Arc::new(OwnedFd::from(listener)),
+            accepting: None,
+        })
+    }
+}
+
+struct AcceptAddr {
+    _listener: Arc<OwnedFd>,
+    storage: socket2::SockAddrStorage,
+    len: libc::socklen_t,
+}
+
+/// [`Incoming`] accepting connections by `io_uring`, created by [`UringAddress`].
+pub struct UringIncoming {
+    driver: &'static Driver,
+    listener: Arc<OwnedFd>,
+    // the accept operation is kept across cancelled calls of `accept`
+    accepting: Option<Op<Box<AcceptAddr>>>,
+}
+
+impl fmt::Debug for UringIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringIncoming")
+            .field("listener", &self.listener)
+            .finish()
+    }
+}
+
+impl UringIncoming {
+    /// Returns the local address of the listener.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        socket2::SockRef::from(&*self.listener)
+            .local_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("listener is not bound to an ip address"))
+    }
+
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Conn>> {
+        let op = match &mut self.accepting {
+            Some(op) => op,
+            None => {
+                let storage = socket2::SockAddrStorage::zeroed();
+                let mut addr = Box::new(AcceptAddr {
+                    _listener: self.listener.clone(),
+                    len: storage.size_of(),
+                    storage,
+                });
+                let entry = opcode::Accept::new(
+                    Fd(self.listener.as_raw_fd()),
+                    // SAFETY: the storage is large enough for any socket address.
+                    unsafe { addr.storage.view_as::<libc::sockaddr>() },
+                    &mut addr.len,
+                )
+                .flags(libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK)
+                .build();
+                self.accepting.insert(self.driver.submit(entry, addr)?)
+            }
+        };
+        let (res, addr) = ready!(op.poll(cx));
+        self.accepting = None;
+
+        // SAFETY: the fd is just accepted and owned by nobody else.
+        let fd = unsafe { OwnedFd::from_raw_fd(result(res)? as i32) };
+        let _ = socket2::SockRef::from(&fd).set_tcp_nodelay(true);
+        // SAFETY: the address is written by the kernel with its length.
+        let peer_addr = unsafe { socket2::SockAddr::new(addr.storage, addr.len) }
+            .as_socket()
+            .map(Address::from);
+        let stream = UringStream::new(self.driver, fd);
+        Poll::Ready(Ok(Conn::new(
+            ConnStream::Uring(stream),
+            ConnInfo { peer_addr },
+        )))
+    }
+}
+
+impl Incoming for UringIncoming {
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        let conn = std::future::poll_fn(|cx| self.poll_accept(cx)).await?;
+        tracing::trace!("[VOLO] recv a connection from: {:?}", conn.info.peer_addr);
+        Ok(Some(conn))
+    }
+}
+
+/// A TCP connection read and written by `io_uring`.
+pub struct UringStream {
+    read: ReadHalf,
+    write: WriteHalf,
+}
+
+impl fmt::Debug for UringStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringStream")
+            .field("fd", &self.read.fd)
+            .finish()
+    }
+}
+
+impl UringStream {
+    fn new(driver: &'static Driver, fd: OwnedFd) -> Self {
+        let fd = Arc::new(fd);
+        Self {
+            read: ReadHalf {
+                driver,
+                fd: fd.clone(),
+                buf: Vec::new(),
+                pos: 0,
+                reading: None,
+            },
+            write: WriteHalf {
+                driver,
+                fd,
+                spare: Vec::new(),
+                writing: None,
+            },
+        }
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        socket2::SockRef::from(&*self.read.fd)
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("peer is not an ip address"))
+    }
+
+    pub fn into_split(self) -> (ReadHalf, WriteHalf) {
+        (self.read, self.write)
+    }
+}
+
+impl AsyncRead for UringStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.read).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UringStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.write).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.write).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.write.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.write).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.write).poll_shutdown(cx)
+    }
+}
+
+/// The read half of [`UringStream`].
+pub struct ReadHalf {
+    driver: &'static Driver,
+    fd: Arc<OwnedFd>,
+    // data read but not consumed yet
+    buf: Vec<u8>,
+    pos: usize,
+    reading: Option<Op<(Arc<OwnedFd>, Vec<u8>)>>,
+}
+
+impl AsyncRead for ReadHalf {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.pos == this.buf.len() {
+            let op = match &mut this.reading {
+                Some(op) => op,
+                None => {
+                    let mut data = std::mem::take(&mut this.buf);
+                    // the buffer is empty until the read completes, which may be polled again
+                    this.pos = 0;
+                    data.resize(READ_BUF_SIZE, 0);
+                    let entry = opcode::Recv::new(
+                        Fd(this.fd.as_raw_fd()),
+                        data.as_mut_ptr(),
+                        data.len() as u32,
+                    )
+                    .build();
+                    this.reading
+                        .insert(this.driver.submit(entry, (this.fd.clone(), data))?)
+                }
+            };
+            let (res, (_, mut data)) = ready!(op.poll(cx));
+            this.reading = None;
+            let n = result(res)?;
+            data.truncate(n);
+            this.buf = data;
+            this.pos = 0;
+        }
+        let n = buf.remaining().min(this.buf.len() - this.pos);
+        buf.put_slice(&this.buf[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+struct WriteBuf {
+    _fd: Arc<OwnedFd>,
+    data: Vec<u8>,
+    offset: usize,
+}
+
+/// The write half of [`UringStream`].
+///
+/// Data is written in the background once it is accepted by `poll_write`, and errors of writing
+/// are returned by the following calls.
+pub struct WriteHalf {
+    driver: &'static Driver,
+    fd: Arc<OwnedFd>,
+    spare: Vec<u8>,
+    writing: Option<Op<WriteBuf>>,
+}
+
+impl WriteHalf {
+    fn send(&mut self, buf: WriteBuf) -> io::Result<()> {
+        let data = &buf.data[buf.offset..];
+        let entry =
+            opcode::Send::new(Fd(self.fd.as_raw_fd()), data.as_ptr(), data.len() as u32).build();
+        self.writing = Some(self.driver.submit(entry, buf)?);
+        Ok(())
+    }
+
+    /// Wait until all data submitted has been written.
+    fn poll_written(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(op) = &mut self.writing {
+            let (res, mut buf) = ready!(op.poll(cx));
+            self.writing = None;
+            buf.offset += result(res)?;
+            if buf.offset < buf.data.len() {
+                self.send(buf)?;
+            } else {
+                self.spare = buf.data;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_write(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let mut data = std::mem::take(&mut self.spare);
+        data.clear();
+        for buf in bufs {
+            let n = buf.len().min(MAX_WRITE_SIZE - data.len());
+            data.extend_from_slice(&buf[..n]);
+            if data.len() == MAX_WRITE_SIZE {
+                break;
+            }
+        }
+        let n = data.len();
+        if n > 0 {
+            self.send(WriteBuf {
+                _fd: self.fd.clone(),
+                data,
+                offset: 0,
+            })?;
+        }
+        Ok(n)
+    }
+}
+
+impl AsyncWrite for WriteHalf {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[io::IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_written(cx))?;
+        Poll::Ready(self.start_write(bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_written(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_written(cx))?;
+        Poll::Ready(socket2::SockRef::from(&*self.fd).shutdown(std::net::Shutdown::Write))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn echo() {
+        let mut incoming = UringAddress::new("127.0.0.1:0".parse().unwrap())
+            .make_incoming()
+            .await
+            .unwrap();
+        let addr = incoming.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            // larger than the buffers of a single read and write
+            let data = (0..4 * MAX_WRITE_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+            stream.write_all(&data).await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut echoed = Vec::new();
+            stream.read_to_end(&mut echoed).await.unwrap();
+            assert_eq!(echoed, data);
+            stream.local_addr().unwrap()
+        });
+
+        let conn = incoming.accept().await.unwrap().unwrap();
+        assert!(matches!(conn.stream, ConnStream::Uring(_)));
+        let peer_addr = conn.info.peer_addr.clone();
+        let (mut rh, mut wh) = conn.stream.into_split();
+        tokio::io::copy(&mut rh, &mut wh).await.unwrap();
+        wh.shutdown().await.unwrap();
+
+        let client_addr = client.await.unwrap();
+        assert_eq!(peer_addr, Some(Address::Ip(client_addr)));
+    }
+
+    #[tokio::test]
+    async fn pending_read() {
+        let mut incoming = UringAddress::new("127.0.0.1:0".parse().unwrap())
+            .make_incoming()
+            .await
+            .unwrap();
+        let addr = incoming.local_addr().unwrap();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut conn = incoming.accept().await.unwrap().unwrap();
+
+        let mut buf = [0; 8];
+        stream.write_all(b"a").await.unwrap();
+        assert_eq!(conn.stream.read(&mut buf).await.unwrap(), 1);
+
+        // the read is pending after all data is consumed, and is kept after the call is cancelled
+        let res = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            conn.stream.read(&mut buf),
+        )
+        .await;
+        assert!(res.is_err());
+
+        stream.write_all(b"b").await.unwrap();
+        assert_eq!(conn.stream.read(&mut buf).await.unwrap(), 1);
+        assert_eq!(&buf[..1], b"b");
+    }
+
+    #[tokio::test]
+    async fn cancel_accept() {
+        let mut incoming = UringAddress::new("127.0.0.1:0".parse().unwrap())
+            .make_incoming()
+            .await
+            .unwrap();
+        let addr = incoming.local_addr().unwrap();
+
+        // the accept operation is kept after the call is cancelled
+        let res =
+            tokio::time::timeout(std::time::Duration::from_millis(10), incoming.accept()).await;
+        assert!(res.is_err());
+
+        let _stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        assert!(incoming.accept().await.unwrap().is_some());
+        drop(incoming);
+    }
+}
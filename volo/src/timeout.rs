@@ -0,0 +1,332 @@
+//! The timeout model shared by the clients of volo-thrift, volo-grpc and volo-http.
+//!
+//! A call is bounded by three kinds of timeouts:
+//!
+//! - [`TimeoutKind::Connect`]: establishing a connection, which is set on the transport, e.g.,
+//!   [`dial::Config::connect_timeout`](crate::net::dial::Config::connect_timeout).
+//! - [`TimeoutKind::Request`]: each attempt of the call, i.e., a retry gets a new one.
+//! - [`TimeoutKind::Total`]: the whole call including all retries, which starts a [`CallDeadline`]
+//!   in the context extensions.
+//!
+//! The request timeout is enforced by [`RequestTimeoutLayer`], which is placed inside the retries
+//! by the clients, and the total timeout is enforced by the timeout layer of each client. The
+//! future of the call is dropped on expiry, which cancels the request and closes or resets the
+//! connection it was using, and the error returned carries a [`TimeoutError`] telling which
+//! timeout fired.
+
+use std::{error::Error, fmt, io, time::Duration};
+
+use motore::{layer::Layer, service::Service};
+#[cfg(not(target_family = "wasm"))]
+use tokio::time::Instant;
+#[cfg(target_family = "wasm")]
+use web_time::Instant;
+
+use crate::{context::Context, error::Sources, loadbalance::error::Retryable};
+
+/// The kind of a timeout, see the [module docs](self) for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeoutKind {
+    /// Timeout of establishing a connection.
+    Connect,
+    /// Timeout of each attempt of a call.
+    Request,
+    /// Timeout of the whole call including retries.
+    Total,
+}
+
+impl TimeoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Request => "request",
+            Self::Total => "total",
+        }
+    }
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The request and total timeouts of the config of client contexts.
+pub trait TimeoutConfig {
+    /// The timeout of each attempt of a call.
+    fn request_timeout(&self) -> Option<Duration>;
+
+    /// The timeout of the whole call including retries.
+    fn total_timeout(&self) -> Option<Duration>;
+}
+
+/// The error of a call whose timeout expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeoutError {
+    kind: TimeoutKind,
+    timeout: Duration,
+}
+
+impl TimeoutError {
+    pub fn new(kind: TimeoutKind, timeout: Duration) -> Self {
+        Self { kind, timeout }
+    }
+
+    /// Which timeout fired.
+    pub fn kind(&self) -> TimeoutKind {
+        self.kind
+    }
+
+    /// The duration of the timeout fired.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Convert into an [`io::Error`] of [`io::ErrorKind::TimedOut`] with the error inside, which
+    /// can be found again by [`TimeoutError::find`].
+    pub fn into_io_error(self) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, self)
+    }
+
+    /// Find the [`TimeoutError`] in the error or its sources, see [`Sources`].
+    pub fn find<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a TimeoutError> {
+        Sources::new(err).find_map(|err| err.downcast_ref::<TimeoutError>())
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timeout of {:?} expired", self.kind, self.timeout)
+    }
+}
+
+impl Error for TimeoutError {}
+
+impl Retryable for TimeoutError {
+    /// A connect or request timeout only fails the attempt, while the call can never succeed
+    /// after the total timeout.
+    fn retryable(&self) -> bool {
+        self.kind != TimeoutKind::Total
+    }
+}
+
+/// The deadline of the whole call, which is inserted into the context extensions by the timeout
+/// layers of clients when a total timeout is set, so that each attempt does not exceed it.
+#[derive(Clone, Copy, Debug)]
+pub struct CallDeadline {
+    deadline: Instant,
+    timeout: Duration,
+}
+
+impl CallDeadline {
+    /// Create a deadline after the `timeout` from now.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + timeout,
+            timeout,
+        }
+    }
+
+    /// Returns the instant of the deadline.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Returns the total timeout this deadline is created with.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Returns the remaining time before the deadline, or zero if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns the [`TimeoutError`] when the deadline has passed.
+    pub fn error(&self) -> TimeoutError {
+        TimeoutError::new(TimeoutKind::Total, self.timeout)
+    }
+}
+
+/// Returns the time allowed for the next attempt of the call and the timeout that will fire
+/// first, limited by both the request timeout and the [`CallDeadline`] in the context.
+pub fn attempt_timeout<Cx>(cx: &Cx) -> Option<(Duration, TimeoutError)>
+where
+    Cx: Context,
+    Cx::Config: TimeoutConfig,
+{
+    let request = cx.rpc_info().config().request_timeout();
+    let deadline = cx.extensions().get::<CallDeadline>();
+    match (request, deadline) {
+        (Some(request), Some(deadline)) => {
+            let remaining = deadline.remaining();
+            if remaining < request {
+                Some((remaining, deadline.error()))
+            } else {
+                Some((request, TimeoutError::new(TimeoutKind::Request, request)))
+            }
+        }
+        (Some(request), None) => Some((request, TimeoutError::new(TimeoutKind::Request, request))),
+        (None, Some(deadline)) => Some((deadline.remaining(), deadline.error())),
+        (None, None) => None,
+    }
+}
+
+/// [`Layer`] for applying the request timeout of each attempt, see [`attempt_timeout`].
+///
+/// The clients place it inside the load balancer, so each retry gets a new request timeout.
+///
+/// The timers of tokio are not available on `wasm32`, where the timeout is left to the transport,
+/// e.g., the `fetch` transport of volo-http aborts the request by [`attempt_timeout`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestTimeoutLayer;
+
+impl<S> Layer<S> for RequestTimeoutLayer {
+    type Service = RequestTimeoutService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RequestTimeoutService { inner }
+    }
+}
+
+/// The [`Service`] generated by [`RequestTimeoutLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestTimeoutService<S> {
+    inner: S,
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for RequestTimeoutService<S>
+where
+    Cx: Context + Send,
+    Cx::Config: TimeoutConfig,
+    Req: Send,
+    S: Service<Cx, Req> + Send + Sync,
+    S::Error: From<TimeoutError>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call(&self, cx: &mut Cx, req: Req) -> Result<Self::Response, Self::Error> {
+        let Some((duration, err)) = attempt_timeout(cx) else {
+            return self.inner.call(cx, req).await;
+        };
+        if duration.is_zero() {
+            return Err(err.into());
+        }
+        if cfg!(target_family = "wasm") {
+            return self.inner.call(cx, req).await;
+        }
+        match tokio::time::timeout(duration, self.inner.call(cx, req)).await {
+            Ok(res) => res,
+            Err(_) => {
+                tracing::warn!(
+                    "[VOLO] {err} for method `{}` to {:?}",
+                    cx.rpc_info().method(),
+                    cx.rpc_info().callee().address(),
+                );
+                Err(err.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, io, time::Duration};
+
+    use motore::service::service_fn;
+
+    use super::*;
+    use crate::context::{Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config {
+        request: Option<Duration>,
+    }
+
+    impl Reusable for Config {
+        fn clear(&mut self) {
+            self.request = None;
+        }
+    }
+
+    impl TimeoutConfig for Config {
+        fn request_timeout(&self) -> Option<Duration> {
+            self.request
+        }
+
+        fn total_timeout(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    #[derive(Debug)]
+    struct TestError(TimeoutError);
+
+    impl From<TimeoutError> for TestError {
+        fn from(err: TimeoutError) -> Self {
+            Self(err)
+        }
+    }
+
+    fn make_cx(request: Option<Duration>) -> RpcCx<(), Config> {
+        let mut info = RpcInfo::<Config>::with_role(Role::Client);
+        info.config_mut().request = request;
+        RpcCx::new(info, ())
+    }
+
+    async fn sleep(_: &mut RpcCx<(), Config>, dur: Duration) -> Result<(), TestError> {
+        tokio::time::sleep(dur).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_and_total_timeouts() {
+        let svc = RequestTimeoutLayer.layer(service_fn(sleep));
+
+        let mut cx = make_cx(Some(Duration::from_millis(20)));
+        assert!(svc.call(&mut cx, Duration::ZERO).await.is_ok());
+        let err = svc.call(&mut cx, Duration::from_secs(1)).await.unwrap_err();
+        assert_eq!(err.0.kind(), TimeoutKind::Request);
+        assert_eq!(err.0.timeout(), Duration::from_millis(20));
+
+        // the attempt is limited by the deadline of the whole call
+        cx.extensions_mut()
+            .insert(CallDeadline::new(Duration::from_millis(10)));
+        let err = svc.call(&mut cx, Duration::from_secs(1)).await.unwrap_err();
+        assert_eq!(err.0.kind(), TimeoutKind::Total);
+        // no more attempts after the deadline
+        let err = svc.call(&mut cx, Duration::ZERO).await.unwrap_err();
+        assert_eq!(err.0.kind(), TimeoutKind::Total);
+        assert!(!err.0.retryable());
+
+        let mut cx = make_cx(None);
+        assert!(svc.call(&mut cx, Duration::from_millis(10)).await.is_ok());
+    }
+
+    #[test]
+    fn find_timeout_error() {
+        let err = TimeoutError::new(TimeoutKind::Connect, Duration::from_secs(1));
+        let io_err = err.into_io_error();
+        assert_eq!(io_err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(TimeoutError::find(&io_err), Some(&err));
+
+        #[derive(Debug)]
+        struct Wrapper(io::Error);
+        impl std::fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "wrapped: {}", self.0)
+            }
+        }
+        impl Error for Wrapper {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+        assert_eq!(TimeoutError::find(&Wrapper(io_err)), Some(&err));
+        assert_eq!(
+            TimeoutError::find(&io::Error::from(io::ErrorKind::TimedOut)),
+            None
+        );
+    }
+}
@@ -10,6 +10,7 @@ use std::{
 use pin_project::pin_project;
 use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, ReadBuf};
 
+#[cfg(not(target_family = "wasm"))]
 use crate::net::ext::AsyncExt;
 
 // used by `BufReader` and `BufWriter`
@@ -225,6 +226,7 @@ impl<R: AsyncRead> AsyncBufRead for BufReader<R> {
     }
 }
 
+#[cfg(not(target_family = "wasm"))]
 impl<R: AsyncExt + Send + Sync> AsyncExt for BufReader<R> {
     async fn ready(&self, interest: tokio::io::Interest) -> io::Result<tokio::io::Ready> {
         self.inner.ready(interest).await
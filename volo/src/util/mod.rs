@@ -1,4 +1,6 @@
 pub mod buf_reader;
+pub mod time;
+pub mod timing;
 
 // used internally.
 #[doc(hidden)]
@@ -0,0 +1,107 @@
+//! Timestamps recorded by statistics of contexts.
+//!
+//! See [`Timestamp`] for more details.
+
+#[cfg(not(target_family = "wasm"))]
+use std::time::{Instant, SystemTime};
+use std::{sync::OnceLock, time::Duration};
+
+// the clocks of `std` panic on `wasm32-unknown-unknown`
+#[cfg(target_family = "wasm")]
+use web_time::{Instant, SystemTime};
+
+/// A point in time recorded by statistics, e.g., when a request starts to be processed.
+///
+/// It is measured by the monotonic clock, so recording it is cheap and durations between
+/// timestamps are never negative. The wall-clock time is only calculated when it is required by
+/// [`Timestamp::system_time`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(Instant);
+
+impl Timestamp {
+    /// Returns a [`Timestamp`] of now.
+    #[inline]
+    pub fn now() -> Self {
+        Self(Instant::now())
+    }
+
+    /// Returns the [`Instant`] of the timestamp.
+    #[inline]
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Returns the amount of time elapsed from an earlier timestamp, or zero if the `earlier` is
+    /// later than this one.
+    #[inline]
+    pub fn duration_since(&self, earlier: Timestamp) -> Duration {
+        self.0.saturating_duration_since(earlier.0)
+    }
+
+    /// Returns the amount of time elapsed since this timestamp.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+
+    /// Returns the wall-clock time of the timestamp.
+    ///
+    /// It is calculated from a pair of [`Instant`] and [`SystemTime`] captured once in the
+    /// process, so adjustments of the system clock after that are not reflected.
+    pub fn system_time(&self) -> SystemTime {
+        static ANCHOR: OnceLock<(Instant, SystemTime)> = OnceLock::new();
+        let (instant, system_time) = *ANCHOR.get_or_init(|| (Instant::now(), SystemTime::now()));
+        if self.0 >= instant {
+            system_time + (self.0 - instant)
+        } else {
+            system_time - (instant - self.0)
+        }
+    }
+
+    /// Returns the wall-clock time of the timestamp in the local timezone.
+    #[cfg(feature = "chrono")]
+    pub fn to_local(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::DateTime::from(self.system_time())
+    }
+}
+
+impl From<Instant> for Timestamp {
+    fn from(instant: Instant) -> Self {
+        Self(instant)
+    }
+}
+
+#[cfg(test)]
+mod time_tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::Timestamp;
+
+    #[test]
+    fn test_duration() {
+        let start = Timestamp::now();
+        let end = Timestamp::from(start.instant() + Duration::from_millis(10));
+        assert_eq!(end.duration_since(start), Duration::from_millis(10));
+        assert_eq!(start.duration_since(end), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_system_time() {
+        let before = SystemTime::now();
+        let now = Timestamp::now();
+        let after = SystemTime::now();
+        // `SystemTime` may be adjusted, so allow a small error
+        let tolerance = Duration::from_secs(1);
+        assert!(now.system_time() + tolerance >= before);
+        assert!(now.system_time() <= after + tolerance);
+
+        let later = Timestamp::from(now.instant() + Duration::from_secs(60));
+        assert_eq!(
+            later
+                .system_time()
+                .duration_since(now.system_time())
+                .unwrap(),
+            Duration::from_secs(60)
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Timing breakdowns of calls, e.g., for logging slow requests.
+//!
+//! The statistics of client contexts record the timestamps of the phases of a call, and the
+//! `timing()` of the client contexts of volo-thrift, volo-grpc and volo-http returns a [`Timing`]
+//! of them after the call:
+//!
+//! ```ignore
+//! let res = client.call(&mut cx, req).await;
+//! let timing = cx.timing();
+//! if timing.total() > Some(Duration::from_millis(100)) {
+//!     tracing::warn!("slow call of method `{}`: {timing}", cx.rpc_info().method());
+//! }
+//! ```
+//!
+//! The phases of establishing a connection, i.e., [`Phase::Resolve`], [`Phase::Connect`] and
+//! [`Phase::TlsHandshake`], are recorded by the transports of volo by [`record`] into the scope of
+//! [`record_connect`], which is entered by the clients for each call. So they are only set to the
+//! statistics when the call returns from the client, and are missing if the connection is reused
+//! or established in the background.
+
+use std::{cell::Cell, fmt, future::Future, time::Duration};
+
+use faststr::FastStr;
+
+use super::time::Timestamp;
+
+/// A phase of a call.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Resolving the domain name of the callee.
+    Resolve,
+    /// Establishing a connection to the callee.
+    Connect,
+    /// The TLS handshake of a new connection.
+    TlsHandshake,
+    /// Encoding the request.
+    Encode,
+    /// Writing the request to the connection.
+    Write,
+    /// Waiting for the first byte of the response after the request is sent.
+    FirstByte,
+    /// Reading the response from the connection.
+    Read,
+    /// Decoding the response.
+    Decode,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Resolve => "resolve",
+            Self::Connect => "connect",
+            Self::TlsHandshake => "tls_handshake",
+            Self::Encode => "encode",
+            Self::Write => "write",
+            Self::FirstByte => "first_byte",
+            Self::Read => "read",
+            Self::Decode => "decode",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The start and end of a [`Phase`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhaseTiming {
+    phase: Phase,
+    start: Timestamp,
+    end: Timestamp,
+}
+
+impl PhaseTiming {
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn start(&self) -> Timestamp {
+        self.start
+    }
+
+    pub fn end(&self) -> Timestamp {
+        self.end
+    }
+
+    /// Returns the time spent in the phase.
+    pub fn cost(&self) -> Duration {
+        self.end.duration_since(self.start)
+    }
+}
+
+/// The timing breakdown of a call, see the [module docs](self) for more details.
+///
+/// It is displayed as `key: value` pairs for logging, e.g.,
+/// `trace_id: abc, total: 12ms, connect: 3ms, write: 1ms, first_byte: 7ms`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timing {
+    trace_id: Option<FastStr>,
+    start: Option<Timestamp>,
+    end: Option<Timestamp>,
+    phases: Vec<PhaseTiming>,
+}
+
+impl Timing {
+    /// Create a [`Timing`] of the call from `start` to `end`.
+    pub fn new(start: Option<Timestamp>, end: Option<Timestamp>) -> Self {
+        Self {
+            trace_id: None,
+            start,
+            end,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Set the trace id of the call.
+    pub fn with_trace_id(mut self, trace_id: Option<FastStr>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// Add a phase, which is skipped if any of the timestamps is not recorded.
+    pub fn push(&mut self, phase: Phase, start: Option<Timestamp>, end: Option<Timestamp>) {
+        if let (Some(start), Some(end)) = (start, end) {
+            self.phases.push(PhaseTiming { phase, start, end });
+        }
+    }
+
+    /// Returns the trace id of the call.
+    pub fn trace_id(&self) -> Option<&FastStr> {
+        self.trace_id.as_ref()
+    }
+
+    /// Returns when the call started.
+    pub fn start(&self) -> Option<Timestamp> {
+        self.start
+    }
+
+    /// Returns the time spent in the whole call.
+    pub fn total(&self) -> Option<Duration> {
+        Some(self.end?.duration_since(self.start?))
+    }
+
+    /// Returns the phases recorded in order.
+    pub fn phases(&self) -> &[PhaseTiming] {
+        &self.phases
+    }
+
+    /// Returns the time spent in the phase if recorded.
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|p| p.phase == phase)
+            .map(PhaseTiming::cost)
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        if let Some(trace_id) = &self.trace_id {
+            write!(f, "trace_id: {trace_id}")?;
+            sep = ", ";
+        }
+        if let Some(total) = self.total() {
+            write!(f, "{sep}total: {total:?}")?;
+            sep = ", ";
+        }
+        for phase in self.phases.iter() {
+            write!(f, "{sep}{}: {:?}", phase.phase, phase.cost())?;
+            sep = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// The timestamps of establishing a connection, see [`record_connect`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectTimestamps {
+    pub resolve_start_at: Option<Timestamp>,
+    pub resolve_end_at: Option<Timestamp>,
+    pub connect_start_at: Option<Timestamp>,
+    pub connect_end_at: Option<Timestamp>,
+    pub tls_handshake_start_at: Option<Timestamp>,
+    pub tls_handshake_end_at: Option<Timestamp>,
+}
+
+tokio::task_local! {
+    static CONNECT_TIMESTAMPS: Cell<ConnectTimestamps>;
+}
+
+/// Run the future of a call, and returns the [`ConnectTimestamps`] recorded by [`record`] inside
+/// it, where the latest one of each phase is kept.
+pub async fn record_connect<F>(fut: F) -> (F::Output, ConnectTimestamps)
+where
+    F: Future,
+{
+    CONNECT_TIMESTAMPS
+        .scope(Cell::new(ConnectTimestamps::default()), async {
+            let output = fut.await;
+            (output, CONNECT_TIMESTAMPS.with(Cell::get))
+        })
+        .await
+}
+
+/// Record a phase of establishing a connection into the scope of [`record_connect`] if any.
+///
+/// Only [`Phase::Resolve`], [`Phase::Connect`] and [`Phase::TlsHandshake`] are recorded.
+pub fn record(phase: Phase, start: Timestamp, end: Timestamp) {
+    let _ = CONNECT_TIMESTAMPS.try_with(|cell| {
+        let mut timestamps = cell.get();
+        let (start_at, end_at) = match phase {
+            Phase::Resolve => (
+                &mut timestamps.resolve_start_at,
+                &mut timestamps.resolve_end_at,
+            ),
+            Phase::Connect => (
+                &mut timestamps.connect_start_at,
+                &mut timestamps.connect_end_at,
+            ),
+            Phase::TlsHandshake => (
+                &mut timestamps.tls_handshake_start_at,
+                &mut timestamps.tls_handshake_end_at,
+            ),
+            _ => return,
+        };
+        *start_at = Some(start);
+        *end_at = Some(end);
+        cell.set(timestamps);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn record_in_scope() {
+        let start = Timestamp::now();
+        let end = Timestamp::from(start.instant() + Duration::from_millis(3));
+
+        // no-op outside the scope
+        record(Phase::Connect, start, end);
+
+        let ((), timestamps) = record_connect(async {
+            record(Phase::Resolve, start, start);
+            record(Phase::Connect, start, end);
+            // not a phase of connecting
+            record(Phase::Decode, start, end);
+        })
+        .await;
+        assert_eq!(
+            timestamps,
+            ConnectTimestamps {
+                resolve_start_at: Some(start),
+                resolve_end_at: Some(start),
+                connect_start_at: Some(start),
+                connect_end_at: Some(end),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn timing_breakdown() {
+        let start = Timestamp::now();
+        let at = |ms| Some(Timestamp::from(start.instant() + Duration::from_millis(ms)));
+
+        let mut timing = Timing::new(Some(start), at(10)).with_trace_id(Some("abc".into()));
+        timing.push(Phase::Connect, Some(start), at(3));
+        timing.push(Phase::TlsHandshake, None, at(3));
+        timing.push(Phase::FirstByte, at(3), at(10));
+
+        assert_eq!(timing.total(), Some(Duration::from_millis(10)));
+        assert_eq!(timing.phases().len(), 2);
+        assert_eq!(timing.get(Phase::Connect), Some(Duration::from_millis(3)));
+        assert_eq!(timing.get(Phase::TlsHandshake), None);
+        assert_eq!(
+            timing.to_string(),
+            "trace_id: abc, total: 10ms, connect: 3ms, first_byte: 7ms"
+        );
+        assert_eq!(Timing::default().to_string(), "");
+    }
+}